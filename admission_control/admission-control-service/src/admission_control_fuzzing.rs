@@ -6,12 +6,20 @@ use crate::{
     admission_control_service::SubmitTransactionRequest,
     mocks::local_mock_mempool::LocalMockMempool,
 };
-use proptest;
+use admission_control_proto::SubmitTransactionResponse;
+use crypto::ed25519::compat::keypair_strategy;
+use disk_monitor::DiskMonitor;
+use proptest::{prelude::*, sample::select};
 use proptest_helpers::ValueGenerator;
 use prost::Message;
-use std::sync::Arc;
+use std::{convert::TryFrom, sync::Arc};
 use storage_service::mocks::mock_storage_client::MockStorageReadClient;
-use types::transaction::SignedTransaction;
+use trusted_ledger::TrustedLedger;
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::SignedTransaction,
+};
 use vm_validator::mocks::mock_vm_validator::MockVMValidator;
 
 #[test]
@@ -21,17 +29,25 @@ fn test_fuzzer() {
     fuzzer(&data);
 }
 
-/// generate_corpus produces an arbitrary SubmitTransactionRequest for admission control
+#[test]
+fn test_fuzzer_mock_senders() {
+    let mut gen = ValueGenerator::new();
+    for sender in mock_senders() {
+        let signed_txn = gen.generate(signed_txn_strategy(Just(sender)));
+        fuzzer(&encode_request(signed_txn));
+    }
+}
+
+/// generate_corpus produces an arbitrary SubmitTransactionRequest for admission control. Half of
+/// the transactions are sent by the accounts the mock VM validator and Mempool have a status for,
+/// so that each of these statuses is mapped to a response
 pub fn generate_corpus(gen: &mut ValueGenerator) -> Vec<u8> {
     // use proptest to generate a SignedTransaction
-    let signed_txn = gen.generate(proptest::arbitrary::any::<SignedTransaction>());
-    // wrap it in a SubmitTransactionRequest
-    let mut req = SubmitTransactionRequest::default();
-    req.signed_txn = Some(signed_txn.into());
-
-    let mut bytes = bytes::BytesMut::with_capacity(req.encoded_len());
-    req.encode(&mut bytes).unwrap();
-    bytes.to_vec()
+    let signed_txn = gen.generate(prop_oneof![
+        any::<SignedTransaction>(),
+        signed_txn_strategy(select(mock_senders())),
+    ]);
+    encode_request(signed_txn)
 }
 
 /// fuzzer takes a serialized SubmitTransactionRequest an process it with an admission control
@@ -54,6 +70,8 @@ pub fn fuzzer(data: &[u8]) {
         Arc::new(MockStorageReadClient),
         Arc::new(MockVMValidator),
         false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
     );
 
     // process the request
@@ -61,4 +79,41 @@ pub fn fuzzer(data: &[u8]) {
     if cfg!(test) && res.is_err() {
         panic!();
     }
+
+    // every response of the service must map to a status the clients understand
+    if let Ok(response) = res {
+        SubmitTransactionResponse::try_from(response)
+            .expect("admission control response should map to a status");
+    }
+}
+
+/// Senders which the mock VM validator, from `[0; ADDRESS_LENGTH]` to `[6; ADDRESS_LENGTH]`, and
+/// `LocalMockMempool`, from `[100; ADDRESS_LENGTH]` to `[104; ADDRESS_LENGTH]`, answer with a
+/// status of their own
+fn mock_senders() -> Vec<AccountAddress> {
+    (0..=6)
+        .chain(100..=104)
+        .map(|byte| AccountAddress::new([byte; ADDRESS_LENGTH]))
+        .collect()
+}
+
+/// Transactions of `sender` with a valid signature, which the mocks only tell apart by sender
+fn signed_txn_strategy(
+    sender: impl Strategy<Value = AccountAddress>,
+) -> impl Strategy<Value = SignedTransaction> {
+    (sender, any::<u64>(), keypair_strategy()).prop_map(
+        |(sender, sequence_number, (private_key, public_key))| {
+            get_test_signed_txn(sender, sequence_number, private_key, public_key, None)
+        },
+    )
+}
+
+/// Serializes a SubmitTransactionRequest of `signed_txn`
+fn encode_request(signed_txn: SignedTransaction) -> Vec<u8> {
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(signed_txn.into());
+
+    let mut bytes = bytes::BytesMut::with_capacity(req.encoded_len());
+    req.encode(&mut bytes).unwrap();
+    bytes.to_vec()
 }
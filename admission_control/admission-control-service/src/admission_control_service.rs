@@ -5,34 +5,79 @@
 //! from external clients (such as wallets) and performs necessary processing before sending them to
 //! next step.
 
-use crate::OP_COUNTERS;
+use crate::{
+    async_submission::{AsyncSubmitter, DEFAULT_TICKET_CAPACITY, DEFAULT_TICKET_TTL},
+    load_shedder::{InFlight, LoadShedder},
+    pre_validation::{self, PreValidator},
+    quota::{Quota, QuotaExceeded, QuotaGuard},
+    rate_limiter::RateLimiter,
+    submission_cache::{
+        SubmissionCache, DEFAULT_RESPONSE_TTL, DEFAULT_SUBMISSION_CACHE_CAPACITY,
+        DEFAULT_SUBMISSION_CACHE_TTL,
+    },
+    upstream::UpstreamForwarder,
+    OP_COUNTERS,
+};
 use admission_control_proto::{
     proto::admission_control::{
-        submit_transaction_response::Status, AdmissionControl, SubmitTransactionRequest,
-        SubmitTransactionResponse,
+        submit_transaction_async_response, submit_transaction_response::Status,
+        subscribe_transaction_events_request::Filter, AdmissionControl, ComponentHealth,
+        GetAccountStateRequest, GetAccountStateResponse, GetGasPriceEstimateRequest,
+        GetGasPriceEstimateResponse, GetSubmissionResultRequest, GetSubmissionResultResponse,
+        GetTransactionStatusByHashRequest, GetTransactionStatusByHashResponse,
+        GetTransactionStatusRequest, GetTransactionStatusResponse, HealthCheckRequest,
+        HealthCheckResponse, SubmissionResultStatus, SubmitTransactionAsyncResponse,
+        SubmitTransactionRequest, SubmitTransactionResponse, SubmitTransactionsBatchRequest,
+        SubmitTransactionsBatchResponse, SubscribeTransactionEventsRequest, TransactionEvent,
+        TransactionEventType, TransactionStatusCode,
     },
     AdmissionControlStatus,
 };
+use config::config::{
+    IdentityQuotaConfig, LoadSheddingConfig, PreValidationConfig, RateLimitConfig,
+};
+use crypto::{ed25519::Ed25519Signature, hash::CryptoHash, HashValue};
+use disk_monitor::DiskMonitor;
 use failure::prelude::*;
-use futures::future::Future;
-use futures03::executor::block_on;
-use grpc_helpers::provide_grpc_response;
+use futures::{
+    future::{self, Future},
+    stream, Sink, Stream,
+};
+use futures03::{executor::block_on, TryFutureExt};
+use grpc_helpers::{default_reply_error_logger, provide_grpc_response};
+use grpcio::{RpcStatus, RpcStatusCode, WriteFlags};
 use logger::prelude::*;
-use mempool::proto::{
-    mempool::{AddTransactionWithValidationRequest, HealthCheckRequest},
-    mempool_client::MempoolClientTrait,
+use mempool::{
+    proto::{
+        mempool::{
+            self as mempool_proto, AddTransactionWithValidationRequest,
+            AddTransactionsWithValidationRequest, MempoolEvent, MempoolEventType,
+            SubscribeEventsRequest,
+        },
+        mempool_client::MempoolClientTrait,
+    },
+    MAX_ADD_TRANSACTIONS_BATCH_SIZE,
 };
 use mempool_shared_proto::proto::mempool_status::{
     MempoolAddTransactionStatus,
     MempoolAddTransactionStatusCode::{self, MempoolIsFull},
+    MempoolTransactionStatusCode,
 };
 use metrics::counters::SVC_COUNTERS;
+use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use storage_client::StorageRead;
+use trusted_ledger::TrustedLedger;
 use types::{
+    account_address::AccountAddress,
+    get_with_proof::{RequestItem, ResponseItem},
     proto::types::{UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse},
-    transaction::SignedTransaction,
+    transaction::{SignedTransaction, Version},
+    vm_error::{StatusCode, VMStatus},
 };
 use vm_validator::vm_validator::{get_account_state, TransactionValidation};
 
@@ -45,8 +90,16 @@ mod admission_control_service_test;
 /// fuzzing module for admission control
 pub mod fuzzing;
 
+/// Time each component of the node is given to respond to a health check.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum number of transactions in a batch submitted at once.
+pub const MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE: usize = 1000;
+
+/// Number of the peers the networks of the node are connected to.
+pub type ConnectedPeers = Arc<dyn Fn() -> i64 + Send + Sync>;
+
 /// Struct implementing trait (service handle) AdmissionControlService.
-#[derive(Clone)]
 pub struct AdmissionControlService<M, V> {
     /// gRPC client connecting Mempool.
     mempool_client: Option<Arc<M>>,
@@ -57,9 +110,88 @@ pub struct AdmissionControlService<M, V> {
     /// Flag indicating whether we need to check mempool before validation, drop txn if check
     /// fails.
     need_to_check_mempool_before_validation: bool,
+    /// Latest ledger info verified by the node, which responses must not be older than.
+    trusted_ledger: TrustedLedger,
+    /// Disk usage monitor of the node. Submissions are rejected while it is in protective mode.
+    disk_monitor: DiskMonitor,
+    /// Reads are refused while the ledger of the node lags more versions than this behind the
+    /// ledger its peers advertise.
+    max_sync_lag: Option<u64>,
+    /// Transactions recently submitted to this node, to track them by hash, and the responses to
+    /// their submission, returned again to the identical resubmissions.
+    submissions: SubmissionCache,
+    /// Stateless checks of the transactions, run before their VM validation.
+    pre_validator: PreValidator,
+    /// Limits the rate of the submissions of each sender account, if set.
+    sender_rate_limiter: Option<RateLimiter<AccountAddress>>,
+    /// Limits the rate of the submissions of each client IP address, if set.
+    ip_rate_limiter: Option<RateLimiter<IpAddr>>,
+    /// Validates the transactions submitted asynchronously and adds them to Mempool, if enabled.
+    async_submitter: Option<AsyncSubmitter>,
+    /// Sheds the submissions of the senders without priority while the node is overloaded, if set.
+    load_shedder: Option<LoadShedder>,
+    /// Quota of the identity of the clients served, if they are authenticated.
+    quota: Option<Quota>,
+    /// Tells the connectivity of the node to the network for its health check, if set.
+    connected_peers: Option<ConnectedPeers>,
+    /// Components whose health check is still running, e.g. because they hang.
+    health_checks_in_flight: Arc<Mutex<HashSet<&'static str>>>,
+    /// Forwards the transactions submitted to a node without Mempool to its upstream peers, if
+    /// set.
+    upstream: Option<UpstreamForwarder>,
 }
 
-impl<M: 'static, V> AdmissionControlService<M, V>
+// Cannot derive `Clone`, which would require `M: Clone` and `V: Clone`.
+impl<M, V> Clone for AdmissionControlService<M, V> {
+    fn clone(&self) -> Self {
+        Self {
+            mempool_client: self.mempool_client.clone(),
+            storage_read_client: Arc::clone(&self.storage_read_client),
+            vm_validator: Arc::clone(&self.vm_validator),
+            need_to_check_mempool_before_validation: self.need_to_check_mempool_before_validation,
+            trusted_ledger: self.trusted_ledger.clone(),
+            disk_monitor: self.disk_monitor.clone(),
+            max_sync_lag: self.max_sync_lag,
+            submissions: self.submissions.clone(),
+            pre_validator: self.pre_validator.clone(),
+            sender_rate_limiter: self.sender_rate_limiter.clone(),
+            ip_rate_limiter: self.ip_rate_limiter.clone(),
+            async_submitter: self.async_submitter.clone(),
+            load_shedder: self.load_shedder.clone(),
+            quota: self.quota.clone(),
+            connected_peers: self.connected_peers.clone(),
+            health_checks_in_flight: Arc::clone(&self.health_checks_in_flight),
+            upstream: self.upstream.clone(),
+        }
+    }
+}
+
+/// Stream of the events of the transactions a client subscribed to.
+pub type TransactionEventStream = Box<dyn Stream<Item = TransactionEvent, Error = Error> + Send>;
+
+/// Error of the reads refused because the ledger of the node lags too far behind to answer them,
+/// e.g. while state sync catches up with the network. Reported to clients with an `UNAVAILABLE`
+/// status, so that they retry later or on another node instead of acting on stale data.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "Node behind: latest ledger info at version {} lags {} versions behind the network",
+    version, sync_lag
+)]
+pub struct NodeBehind {
+    /// Version of the latest ledger info of the node.
+    pub version: Version,
+    /// Number of versions the node lags behind the highest version advertised by its peers.
+    pub sync_lag: u64,
+}
+
+/// Error ending a stream of transaction events once Mempool stops sending the events, e.g.
+/// because the subscriber fell too far behind. Reported to clients with an `ABORTED` status, so
+/// that they look up the status of their transactions before subscribing again.
+#[derive(Debug, Fail)]
+#[fail(display = "Transaction events were lost, the subscription has to be renewed")]
+pub struct EventsLost;
+
+impl<M: 'static, V: 'static> AdmissionControlService<M, V>
 where
     M: MempoolClientTrait,
     V: TransactionValidation,
@@ -70,20 +202,438 @@ where
         storage_read_client: Arc<dyn StorageRead>,
         vm_validator: Arc<V>,
         need_to_check_mempool_before_validation: bool,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
     ) -> Self {
         AdmissionControlService {
             mempool_client,
             storage_read_client,
             vm_validator,
             need_to_check_mempool_before_validation,
+            trusted_ledger,
+            disk_monitor,
+            max_sync_lag: None,
+            submissions: SubmissionCache::new(
+                DEFAULT_SUBMISSION_CACHE_CAPACITY,
+                DEFAULT_SUBMISSION_CACHE_TTL,
+                DEFAULT_RESPONSE_TTL,
+            ),
+            pre_validator: PreValidator::default(),
+            sender_rate_limiter: None,
+            ip_rate_limiter: None,
+            async_submitter: None,
+            load_shedder: None,
+            quota: None,
+            connected_peers: None,
+            health_checks_in_flight: Arc::new(Mutex::new(HashSet::new())),
+            upstream: None,
         }
     }
 
+    /// Refuses reads with a [`NodeBehind`] error while the ledger of the node lags more than
+    /// `max_sync_lag` versions behind the ledger its peers advertise. How old the ledger is
+    /// doesn't matter, so that the reads are still served while the chain is idle.
+    pub fn with_max_sync_lag(mut self, max_sync_lag: u64) -> Self {
+        self.max_sync_lag = Some(max_sync_lag);
+        self
+    }
+
+    /// Remembers at most `capacity` of the transactions submitted to the node for `ttl` after
+    /// their submission, to track them by hash, and answers the identical resubmissions within
+    /// `response_ttl` with the response to the submission.
+    pub fn with_submission_cache(
+        mut self,
+        capacity: usize,
+        ttl: Duration,
+        response_ttl: Duration,
+    ) -> Self {
+        self.submissions = SubmissionCache::new(capacity, ttl, response_ttl);
+        self
+    }
+
+    /// Rejects the transactions failing the checks enabled in `config` before their VM validation.
+    pub fn with_pre_validation(mut self, config: &PreValidationConfig) -> Self {
+        self.pre_validator = PreValidator::new(config.clone());
+        self
+    }
+
+    /// Refuses the transactions of the senders which submit more than `config` allows with a
+    /// `RateLimited` status.
+    pub fn with_sender_rate_limit(mut self, config: &RateLimitConfig) -> Self {
+        self.sender_rate_limiter = Some(RateLimiter::new(config));
+        self
+    }
+
+    /// Refuses the transactions of the clients which submit more than `config` allows from the
+    /// same IP address with a `RateLimited` status.
+    pub fn with_ip_rate_limit(mut self, config: &RateLimitConfig) -> Self {
+        self.ip_rate_limiter = Some(RateLimiter::new(config));
+        self
+    }
+
+    /// Refuses the transactions of the senders without priority with an `Overloaded` status while
+    /// more transactions than `config` allows are in flight.
+    pub fn with_load_shedding(mut self, config: &LoadSheddingConfig) -> Self {
+        self.load_shedder = Some(LoadShedder::new(config));
+        self
+    }
+
+    /// Serves the clients authenticated as `identity`, whose requests of any kind are refused with a
+    /// `RESOURCE_EXHAUSTED` status beyond `quota`.
+    pub fn with_identity_quota(mut self, identity: &str, quota: &IdentityQuotaConfig) -> Self {
+        self.quota = Some(Quota::new(identity.to_string(), quota));
+        self
+    }
+
+    /// Includes the network in the health check, which is healthy while `connected_peers` tells
+    /// that the node is connected to at least one peer.
+    pub fn with_network_health(mut self, connected_peers: ConnectedPeers) -> Self {
+        self.connected_peers = Some(connected_peers);
+        self
+    }
+
+    /// Forwards the transactions submitted to the node to its upstream peers with `upstream`, once
+    /// they pass the checks which don't need the VM. Only used by the nodes without Mempool.
+    pub fn with_upstream(mut self, upstream: UpstreamForwarder) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// Accepts asynchronous submissions, whose transactions are validated and added to Mempool by
+    /// `workers` threads. At most `queue_size` of them wait for a worker, after which submissions
+    /// are rejected until the workers catch up.
+    pub fn with_async_submission(mut self, workers: usize, queue_size: usize) -> Self {
+        self.async_submitter = Some(AsyncSubmitter::new(
+            workers,
+            queue_size,
+            DEFAULT_TICKET_CAPACITY,
+            DEFAULT_TICKET_TTL,
+        ));
+        self
+    }
+
     /// Validate transaction signature, then via VM, and add it to Mempool if it passes VM check.
-    pub(crate) fn submit_transaction_inner(
+    pub fn submit_transaction_inner(
+        &self,
+        req: SubmitTransactionRequest,
+    ) -> Result<SubmitTransactionResponse> {
+        self.submit_transaction_from(req, None)
+    }
+
+    /// Same as [`submit_transaction_inner`], for a transaction submitted by a client at the
+    /// `client` IP address, if known, whose submissions are rate limited.
+    ///
+    /// [`submit_transaction_inner`]: AdmissionControlService::submit_transaction_inner
+    pub fn submit_transaction_from(
         &self,
         req: SubmitTransactionRequest,
+        client: Option<IpAddr>,
     ) -> Result<SubmitTransactionResponse> {
+        let _timer = OP_COUNTERS.timer("submit_txn.e2e_time_s");
+        let response = if let Some(response) = self.check_accepting_txns()? {
+            response
+        } else {
+            match self.check_txn(&req, client) {
+                Ok(signed_txn) => match self.admit_txns(&[&signed_txn]) {
+                    Ok(_in_flight) => self.validate_and_add_txn(&req, &signed_txn)?,
+                    Err(response) => response,
+                },
+                Err(response) => response,
+            }
+        };
+        count_response_status(&response);
+        Ok(response)
+    }
+
+    /// Submits the transaction like [`submit_transaction_from`] does if the node has a Mempool,
+    /// or else forwards it to the upstream peers of the node once it passes the checks which
+    /// don't need the VM. Fails if the node neither has a Mempool nor upstream peers.
+    ///
+    /// [`submit_transaction_from`]: AdmissionControlService::submit_transaction_from
+    pub fn submit_or_forward_transaction(
+        &self,
+        req: SubmitTransactionRequest,
+        client: Option<IpAddr>,
+    ) -> Result<SubmitTransactionResponse> {
+        let upstream = match (&self.mempool_client, &self.upstream) {
+            (Some(_), _) => return self.submit_transaction_from(req, client),
+            (None, Some(upstream)) => upstream,
+            (None, None) => bail!("Node doesn't accept write requests"),
+        };
+        let _timer = OP_COUNTERS.timer("submit_txn.forward_time_s");
+        let response = match self.check_txn(&req, client) {
+            Ok(signed_txn) => {
+                let response = upstream.forward(req)?;
+                self.submissions.record(&signed_txn, &response);
+                response
+            }
+            Err(response) => response,
+        };
+        count_response_status(&response);
+        Ok(response)
+    }
+
+    /// Answers with a ticket once the signature and the basic checks of the transaction pass,
+    /// leaving its VM validation and its addition to Mempool to the workers of the asynchronous
+    /// submissions. The result of the submission is polled for with
+    /// [`get_submission_result_inner`]. Transactions failing the checks, and the ones submitted
+    /// while the workers are overloaded, are answered right away.
+    ///
+    /// [`get_submission_result_inner`]: AdmissionControlService::get_submission_result_inner
+    pub fn submit_transaction_async_inner(
+        &self,
+        req: SubmitTransactionRequest,
+        client: Option<IpAddr>,
+    ) -> Result<SubmitTransactionAsyncResponse> {
+        let async_submitter = self
+            .async_submitter
+            .as_ref()
+            .ok_or_else(|| format_err!("Asynchronous submission is not enabled"))?;
+        let submitted_at = Instant::now();
+        let mut response = SubmitTransactionAsyncResponse::default();
+        let immediate = |txn_response| {
+            count_response_status(&txn_response);
+            Some(submit_transaction_async_response::Result::Response(
+                txn_response,
+            ))
+        };
+        if let Some(txn_response) = self.check_accepting_txns()? {
+            response.result = immediate(txn_response);
+            return Ok(response);
+        }
+        let signed_txn = match self.check_txn(&req, client) {
+            Ok(signed_txn) => signed_txn,
+            Err(txn_response) => {
+                response.result = immediate(txn_response);
+                return Ok(response);
+            }
+        };
+        // the transactions waiting for a worker are in flight already
+        let in_flight = match self.admit_txns(&[&signed_txn]) {
+            Ok(in_flight) => in_flight,
+            Err(txn_response) => {
+                response.result = immediate(txn_response);
+                return Ok(response);
+            }
+        };
+
+        let ticket = signed_txn.hash();
+        let service = self.clone();
+        let is_queued = async_submitter.submit(ticket, move || {
+            let _in_flight = in_flight;
+            let result = service.validate_and_add_txn(&req, &signed_txn);
+            // the time spent waiting for a worker included
+            OP_COUNTERS.observe_duration("submit_txn_async.e2e_time_s", submitted_at.elapsed());
+            let response = result.map_err(|e| e.to_string())?;
+            count_response_status(&response);
+            Ok(response)
+        });
+        if !is_queued {
+            debug!("Asynchronous submission queue is full");
+            OP_COUNTERS.inc_by("submit_txn_async.rejected.queue_full", 1);
+            let mut txn_response = SubmitTransactionResponse::default();
+            txn_response.status = Some(Status::AcStatus(
+                AdmissionControlStatus::Rejected(
+                    "Node is overloaded with asynchronous submissions".to_string(),
+                )
+                .into(),
+            ));
+            response.result = immediate(txn_response);
+            return Ok(response);
+        }
+        OP_COUNTERS.inc_by("submit_txn_async.queued", 1);
+        response.result = Some(submit_transaction_async_response::Result::Ticket(
+            ticket.to_vec(),
+        ));
+        Ok(response)
+    }
+
+    /// Tells whether the asynchronous submission of a ticket is still pending, and the response to
+    /// it once it is done.
+    pub fn get_submission_result_inner(
+        &self,
+        req: GetSubmissionResultRequest,
+    ) -> Result<GetSubmissionResultResponse> {
+        let async_submitter = self
+            .async_submitter
+            .as_ref()
+            .ok_or_else(|| format_err!("Asynchronous submission is not enabled"))?;
+        let ticket = HashValue::from_slice(&req.ticket)?;
+        let mut response = GetSubmissionResultResponse::default();
+        match async_submitter.result(&ticket) {
+            None => response.set_status(SubmissionResultStatus::SubmissionUnknown),
+            Some(None) => response.set_status(SubmissionResultStatus::SubmissionPending),
+            Some(Some(Ok(txn_response))) => {
+                response.set_status(SubmissionResultStatus::SubmissionDone);
+                response.response = Some(txn_response);
+            }
+            Some(Some(Err(error))) => {
+                response.set_status(SubmissionResultStatus::SubmissionFailed);
+                response.error = error;
+            }
+        }
+        Ok(response)
+    }
+
+    /// Validates each transaction of the batch like [`submit_transaction_inner`] does, then adds
+    /// all the valid ones to Mempool in a single request. The load shedder admits the batch as a
+    /// whole, with priority only if all its transactions have it.
+    ///
+    /// [`submit_transaction_inner`]: AdmissionControlService::submit_transaction_inner
+    pub fn submit_transactions_batch_inner(
+        &self,
+        req: SubmitTransactionsBatchRequest,
+    ) -> Result<SubmitTransactionsBatchResponse> {
+        self.submit_transactions_batch_from(req, None)
+    }
+
+    /// Same as [`submit_transactions_batch_inner`], for a batch submitted by a client at the
+    /// `client` IP address, if known. Each transaction of the batch counts against the rate limits.
+    /// Fails if the batch has more than `MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE` transactions.
+    ///
+    /// [`submit_transactions_batch_inner`]: AdmissionControlService::submit_transactions_batch_inner
+    pub fn submit_transactions_batch_from(
+        &self,
+        req: SubmitTransactionsBatchRequest,
+        client: Option<IpAddr>,
+    ) -> Result<SubmitTransactionsBatchResponse> {
+        ensure!(
+            req.transactions.len() <= MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE,
+            "Batch of {} transactions is over the limit of {} transactions",
+            req.transactions.len(),
+            MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE
+        );
+        let _timer = OP_COUNTERS.timer("submit_txns_batch.e2e_time_s");
+        OP_COUNTERS.observe("submit_txns_batch.size", req.transactions.len() as f64);
+        let mut response = SubmitTransactionsBatchResponse::default();
+        if let Some(txn_response) = self.check_accepting_txns()? {
+            response.responses = vec![txn_response; req.transactions.len()];
+            response.responses.iter().for_each(count_response_status);
+            return Ok(response);
+        }
+
+        let mut responses = Vec::with_capacity(req.transactions.len());
+        // index in `responses` of each transaction passing the checks
+        let mut checked_txns = vec![];
+        for txn_req in &req.transactions {
+            match self.check_txn(txn_req, client) {
+                Ok(signed_txn) => {
+                    checked_txns.push((responses.len(), txn_req, signed_txn));
+                    responses.push(SubmitTransactionResponse::default());
+                }
+                Err(txn_response) => responses.push(txn_response),
+            }
+        }
+        // the batch is in flight until it is added to Mempool
+        let _in_flight = if checked_txns.is_empty() {
+            None
+        } else {
+            let signed_txns: Vec<_> = checked_txns.iter().map(|(_, _, txn)| txn).collect();
+            match self.admit_txns(&signed_txns) {
+                Ok(in_flight) => in_flight,
+                Err(txn_response) => {
+                    for (index, _, _) in checked_txns.drain(..) {
+                        responses[index] = txn_response.clone();
+                    }
+                    None
+                }
+            }
+        };
+
+        // index in `responses` of each transaction sent to Mempool
+        let mut mempool_txns = vec![];
+        for (index, txn_req, signed_txn) in checked_txns {
+            match self.validate_txn_with_vm(txn_req, &signed_txn) {
+                Ok(Ok(add_transaction_request)) => {
+                    mempool_txns.push((index, signed_txn, add_transaction_request));
+                }
+                Ok(Err(txn_response)) => responses[index] = txn_response,
+                // the other transactions of the batch are still submitted
+                Err(e) => {
+                    debug!("txn failed to be validated: {:?}, txn: {:?}", e, signed_txn);
+                    let mut txn_response = SubmitTransactionResponse::default();
+                    txn_response.status = Some(Status::AcStatus(
+                        AdmissionControlStatus::InternalError(e.to_string()).into(),
+                    ));
+                    responses[index] = txn_response;
+                }
+            }
+        }
+
+        // Mempool takes batches of a limited size
+        for mempool_batch in mempool_txns.chunks(MAX_ADD_TRANSACTIONS_BATCH_SIZE) {
+            let mempool_client = self
+                .mempool_client
+                .as_ref()
+                .ok_or_else(|| format_err!("Mempool is not initialized"))?;
+            let mut mempool_request = AddTransactionsWithValidationRequest::default();
+            mempool_request.transactions = mempool_batch
+                .iter()
+                .map(|(_, _, add_transaction_request)| add_transaction_request.clone())
+                .collect();
+            let mempool_response = {
+                let _timer = OP_COUNTERS.timer("submit_txns_batch.mempool_insert_time_s");
+                mempool_client.add_transactions_with_validation(&mempool_request)?
+            };
+            ensure!(
+                mempool_response.statuses.len() == mempool_batch.len(),
+                "Mempool returned {} statuses for {} transactions",
+                mempool_response.statuses.len(),
+                mempool_batch.len(),
+            );
+            for ((index, signed_txn, add_transaction_request), status) in
+                mempool_batch.iter().zip(mempool_response.statuses)
+            {
+                let txn_response =
+                    Self::mempool_status_to_response(Some(status), add_transaction_request);
+                self.submissions.record(signed_txn, &txn_response);
+                responses[*index] = txn_response;
+            }
+        }
+        responses.iter().for_each(count_response_status);
+        response.responses = responses;
+        Ok(response)
+    }
+
+    /// Takes a slot of the quota of the identity of the clients served, if any, for a request which
+    /// holds it until the guard is dropped. Fails with the status of the refused requests.
+    fn acquire_quota(&self) -> std::result::Result<Option<QuotaGuard>, RpcStatus> {
+        self.try_acquire_quota()
+            .map_err(|e| RpcStatus::new(RpcStatusCode::RESOURCE_EXHAUSTED, Some(e.to_string())))
+    }
+
+    /// Same as `acquire_quota`, for the front ends which don't answer with gRPC statuses.
+    pub(crate) fn try_acquire_quota(
+        &self,
+    ) -> std::result::Result<Option<QuotaGuard>, QuotaExceeded> {
+        let quota = match &self.quota {
+            Some(quota) => quota,
+            None => return Ok(None),
+        };
+        quota.try_acquire(Instant::now()).map(Some).map_err(|e| {
+            debug!("Request refused: {}", e);
+            OP_COUNTERS.inc_by(&format!("quota.{}.exceeded", quota.identity()), 1);
+            e
+        })
+    }
+
+    /// Response to the submissions refused before their transactions are even validated, because
+    /// the node does not accept transactions at the moment.
+    fn check_accepting_txns(&self) -> Result<Option<SubmitTransactionResponse>> {
+        // The transaction could not be committed anyway, as the node stops writing to storage.
+        if self.disk_monitor.is_protective() {
+            debug!("Node is low on disk space");
+            OP_COUNTERS.inc_by("submit_txn.rejected.disk_full", 1);
+            let mut response = SubmitTransactionResponse::default();
+            response.status = Some(Status::AcStatus(
+                AdmissionControlStatus::Rejected(
+                    "Node is low on disk space and does not accept transactions".to_string(),
+                )
+                .into(),
+            ));
+            return Ok(Some(response));
+        }
+
         // Drop requests first if mempool is full (validator is lagging behind) so not to consume
         // unnecessary resources.
         if !self.can_send_txn_to_mempool()? {
@@ -94,9 +644,98 @@ where
             status.set_code(MempoolIsFull);
             status.message = "Mempool is full".to_string();
             response.status = Some(Status::MempoolStatus(status));
-            return Ok(response);
+            return Ok(Some(response));
+        }
+        Ok(None)
+    }
+
+    /// Checks the signature of the transaction. Returns the response to its submission if it is
+    /// invalid.
+    fn check_signature(
+        &self,
+        signed_txn: &SignedTransaction,
+    ) -> std::result::Result<(), SubmitTransactionResponse> {
+        let signature_check = {
+            let _timer = OP_COUNTERS.timer("submit_txn.signature_check_time_s");
+            signed_txn.clone().check_signature()
+        };
+        if let Err(e) = signature_check {
+            debug!("txn failed signature check: {}, txn: {:?}", e, signed_txn);
+            OP_COUNTERS.inc_by("submit_txn.rejected.invalid_signature", 1);
+            let mut response = SubmitTransactionResponse::default();
+            response.status = Some(Status::AcStatus(
+                AdmissionControlStatus::Rejected("Invalid signature".to_string()).into(),
+            ));
+            self.submissions.record(signed_txn, &response);
+            return Err(response);
+        }
+        Ok(())
+    }
+
+    /// Admits the transactions past the load shedder, if any, as a single unit until the returned
+    /// slot is dropped. They must have passed `check_txn`, their signature check included.
+    /// Returns the response to their submission if they are shed.
+    fn admit_txns(
+        &self,
+        signed_txns: &[&SignedTransaction],
+    ) -> std::result::Result<Option<InFlight>, SubmitTransactionResponse> {
+        let load_shedder = match &self.load_shedder {
+            Some(load_shedder) => load_shedder,
+            None => return Ok(None),
+        };
+        // A transaction with a valid signature by the key its sender address derives from comes
+        // from this sender: anyone can claim the address of a priority sender otherwise. The
+        // senders who rotated their key get no priority.
+        let is_priority = signed_txns.iter().all(|signed_txn| {
+            load_shedder.is_priority_sender(&signed_txn.sender())
+                && AccountAddress::from_public_key(&signed_txn.public_key()) == signed_txn.sender()
+        });
+        let result = load_shedder.try_admit(is_priority);
+        OP_COUNTERS.set("load_shedding.in_flight", load_shedder.in_flight());
+        match result {
+            Ok(in_flight) => Ok(Some(in_flight)),
+            Err(retry_after) => {
+                debug!(
+                    "Node overloaded, shedding {} txns, retry after {:?}",
+                    signed_txns.len(),
+                    retry_after
+                );
+                OP_COUNTERS.inc_by("submit_txn.rejected.overloaded", signed_txns.len());
+                let mut response = SubmitTransactionResponse::default();
+                response.status = Some(Status::AcStatus(
+                    AdmissionControlStatus::Overloaded(retry_after).into(),
+                ));
+                Err(response)
+            }
         }
+    }
 
+    /// Validates the transaction via VM, then adds it to Mempool if it is valid. Returns the
+    /// response to its submission.
+    fn validate_and_add_txn(
+        &self,
+        req: &SubmitTransactionRequest,
+        signed_txn: &SignedTransaction,
+    ) -> Result<SubmitTransactionResponse> {
+        match self.validate_txn_with_vm(req, signed_txn)? {
+            Ok(add_transaction_request) => {
+                let response = self.add_txn_to_mempool(add_transaction_request)?;
+                self.submissions.record(signed_txn, &response);
+                Ok(response)
+            }
+            Err(response) => Ok(response),
+        }
+    }
+
+    /// Runs the checks of the transaction which don't need the VM: its deserialization, the rate
+    /// limits, its signature, the duplicate submissions and the pre-validation. Returns the
+    /// transaction if it passes them, or the response to its submission otherwise.
+    fn check_txn(
+        &self,
+        req: &SubmitTransactionRequest,
+        client: Option<IpAddr>,
+    ) -> std::result::Result<SignedTransaction, SubmitTransactionResponse> {
+        let _timer = OP_COUNTERS.timer("submit_txn.checks_time_s");
         let signed_txn_proto = req.signed_txn.clone().unwrap_or_else(Default::default);
 
         let signed_txn = match SignedTransaction::try_from(signed_txn_proto.clone()) {
@@ -111,11 +750,58 @@ where
                     AdmissionControlStatus::Rejected("submit txn rejected".to_string()).into(),
                 ));
                 OP_COUNTERS.inc_by("submit_txn.rejected.invalid_txn", 1);
-                return Ok(response);
+                return Err(response);
             }
         };
 
+        if let Some(response) = self.check_ip_rate_limit(client) {
+            return Err(response);
+        }
+        // Anyone can put the address of another sender in a transaction: the sender only gets
+        // charged for the transactions it signed.
+        self.check_signature(&signed_txn)?;
+        if let Some(response) = self.check_sender_rate_limit(signed_txn.sender()) {
+            return Err(response);
+        }
+
+        // Clients retrying a submission get the same response again, without validating the
+        // transaction twice.
+        if let Some(response) = self.submissions.get_response(&signed_txn.hash()) {
+            debug!("Duplicate submission of txn: {:?}", signed_txn);
+            OP_COUNTERS.inc_by("submit_txn.duplicate", 1);
+            return Err(response);
+        }
+
+        if let Err(e) = self.pre_validator.check(&req.chain_id, &signed_txn) {
+            debug!("txn failed pre-validation: {}, txn: {:?}", e, signed_txn);
+            OP_COUNTERS.inc_by(
+                &format!(
+                    "submit_txn.rejected.pre_validation.{}",
+                    pre_validation::check_name(&e)
+                ),
+                1,
+            );
+            let mut response = SubmitTransactionResponse::default();
+            response.status = Some(Status::AcStatus(
+                AdmissionControlStatus::Rejected(e.to_string()).into(),
+            ));
+            self.submissions.record(&signed_txn, &response);
+            return Err(response);
+        }
+        Ok(signed_txn)
+    }
+
+    /// Validates the transaction via VM. Returns the request adding it to Mempool if it is valid,
+    /// or the response to its submission otherwise.
+    fn validate_txn_with_vm(
+        &self,
+        req: &SubmitTransactionRequest,
+        signed_txn: &SignedTransaction,
+    ) -> Result<std::result::Result<AddTransactionWithValidationRequest, SubmitTransactionResponse>>
+    {
         let gas_cost = signed_txn.max_gas_amount();
+        // the VM checks the signature of the transaction as well
+        let vm_timer = OP_COUNTERS.timer("submit_txn.vm_validation_time_s");
         let validation_status = self
             .vm_validator
             .validate_transaction(signed_txn.clone())
@@ -123,10 +809,11 @@ where
             .map_err(|e| {
                 security_log(SecurityEvent::InvalidTransactionAC)
                     .error(&e)
-                    .data(&signed_txn)
+                    .data(signed_txn)
                     .log();
                 e
             })?;
+        drop(vm_timer);
         if let Some(validation_status) = validation_status {
             let mut response = SubmitTransactionResponse::default();
             OP_COUNTERS.inc_by("submit_txn.vm_validation.failure", 1);
@@ -135,7 +822,8 @@ where
                 validation_status, signed_txn
             );
             response.status = Some(Status::VmStatus(validation_status.into()));
-            return Ok(response);
+            self.submissions.record(signed_txn, &response);
+            return Ok(Err(response));
         }
         let sender = signed_txn.sender();
         let account_state = block_on(get_account_state(self.storage_read_client.clone(), sender));
@@ -147,13 +835,50 @@ where
             add_transaction_request.account_balance = balance;
             add_transaction_request.latest_sequence_number = sequence_number;
         }
+        Ok(Ok(add_transaction_request))
+    }
+
+    /// Response to the submissions refused because `client` submits too often.
+    fn check_ip_rate_limit(&self, client: Option<IpAddr>) -> Option<SubmitTransactionResponse> {
+        let (ip_rate_limiter, client) = match (&self.ip_rate_limiter, client) {
+            (Some(ip_rate_limiter), Some(client)) => (ip_rate_limiter, client),
+            _ => return None,
+        };
+        let retry_after = ip_rate_limiter.try_acquire(client, Instant::now()).err()?;
+        OP_COUNTERS.inc_by("submit_txn.rejected.rate_limited.ip", 1);
+        debug!(
+            "Rate limited submission from {}, retry after {:?}",
+            client, retry_after
+        );
+        Some(Self::rate_limited_response(retry_after))
+    }
+
+    /// Response to the submissions refused because `sender` submits too often. The signature of
+    /// the transaction must have been checked, so that nobody drains the bucket of others.
+    fn check_sender_rate_limit(&self, sender: AccountAddress) -> Option<SubmitTransactionResponse> {
+        let sender_rate_limiter = self.sender_rate_limiter.as_ref()?;
+        let retry_after = sender_rate_limiter
+            .try_acquire(sender, Instant::now())
+            .err()?;
+        OP_COUNTERS.inc_by("submit_txn.rejected.rate_limited.sender", 1);
+        debug!(
+            "Rate limited submission of {}, retry after {:?}",
+            sender, retry_after
+        );
+        Some(Self::rate_limited_response(retry_after))
+    }
 
-        self.add_txn_to_mempool(add_transaction_request)
+    fn rate_limited_response(retry_after: Duration) -> SubmitTransactionResponse {
+        let mut response = SubmitTransactionResponse::default();
+        response.status = Some(Status::AcStatus(
+            AdmissionControlStatus::RateLimited(retry_after).into(),
+        ));
+        response
     }
 
     fn can_send_txn_to_mempool(&self) -> Result<bool> {
         if self.need_to_check_mempool_before_validation {
-            let req = HealthCheckRequest::default();
+            let req = mempool_proto::HealthCheckRequest::default();
             let is_mempool_healthy = match &self.mempool_client {
                 Some(client) => client.health_check(&req)?.is_healthy,
                 None => false,
@@ -170,37 +895,323 @@ where
     ) -> Result<SubmitTransactionResponse> {
         match &self.mempool_client {
             Some(mempool_client) => {
-                let mempool_result =
-                    mempool_client.add_transaction_with_validation(&add_transaction_request)?;
+                let mempool_result = {
+                    let _timer = OP_COUNTERS.timer("submit_txn.mempool_insert_time_s");
+                    mempool_client.add_transaction_with_validation(&add_transaction_request)?
+                };
 
                 debug!("[GRPC] Done with transaction submission request");
-                let mut response = SubmitTransactionResponse::default();
-                if let Some(status) = mempool_result.status {
-                    if status.code() == MempoolAddTransactionStatusCode::Valid {
-                        OP_COUNTERS.inc_by("submit_txn.txn_accepted", 1);
-                        response.status =
-                            Some(Status::AcStatus(AdmissionControlStatus::Accepted.into()));
-                    } else {
-                        debug!(
-                            "txn failed in mempool, status: {:?}, txn: {:?}",
-                            status, add_transaction_request.signed_txn
-                        );
-                        OP_COUNTERS.inc_by("submit_txn.mempool.failure", 1);
-                        response.status = Some(Status::MempoolStatus(status));
-                    }
-                }
+                Ok(Self::mempool_status_to_response(
+                    mempool_result.status,
+                    &add_transaction_request,
+                ))
+            }
+            None => Err(format_err!("Mempool is not initialized")),
+        }
+    }
+
+    /// Response to the submission of a transaction, given the status of its addition to Mempool
+    fn mempool_status_to_response(
+        status: Option<MempoolAddTransactionStatus>,
+        add_transaction_request: &AddTransactionWithValidationRequest,
+    ) -> SubmitTransactionResponse {
+        let mut response = SubmitTransactionResponse::default();
+        if let Some(status) = status {
+            // A replacement of a pending transaction is accepted like any other one.
+            if status.code() == MempoolAddTransactionStatusCode::Valid
+                || status.code() == MempoolAddTransactionStatusCode::Replaced
+            {
+                OP_COUNTERS.inc_by("submit_txn.txn_accepted", 1);
+                response.status = Some(Status::AcStatus(AdmissionControlStatus::Accepted.into()));
+            } else {
+                debug!(
+                    "txn failed in mempool, status: {:?}, txn: {:?}",
+                    status, add_transaction_request.signed_txn
+                );
+                OP_COUNTERS.inc_by("submit_txn.mempool.failure", 1);
+                response.status = Some(Status::MempoolStatus(status));
+            }
+        }
+        response
+    }
+
+    /// Asks Mempool for its estimate of the gas unit price needed to get into the next block.
+    pub fn get_gas_price_estimate_inner(
+        &self,
+        _req: GetGasPriceEstimateRequest,
+    ) -> Result<GetGasPriceEstimateResponse> {
+        match &self.mempool_client {
+            Some(mempool_client) => {
+                let mempool_response = mempool_client
+                    .get_gas_price_estimate(&mempool_proto::GetGasPriceEstimateRequest::default())?;
+                let mut response = GetGasPriceEstimateResponse::default();
+                response.estimate = mempool_response.estimate;
                 Ok(response)
             }
             None => Err(format_err!("Mempool is not initialized")),
         }
     }
 
+    /// Asks Mempool whether a transaction is still waiting in it, or why it was removed.
+    pub fn get_transaction_status_inner(
+        &self,
+        req: GetTransactionStatusRequest,
+    ) -> Result<GetTransactionStatusResponse> {
+        match &self.mempool_client {
+            Some(mempool_client) => {
+                let mut mempool_request = mempool_proto::GetTransactionStatusRequest::default();
+                mempool_request.sender = req.sender;
+                mempool_request.sequence_number = req.sequence_number;
+                let mempool_response = mempool_client.get_transaction_status(&mempool_request)?;
+                let mut response = GetTransactionStatusResponse::default();
+                response.set_status(mempool_response.status());
+                Ok(response)
+            }
+            None => Err(format_err!("Mempool is not initialized")),
+        }
+    }
+
+    /// Tracks a transaction submitted to the node by its hash. Mempool tells whether it is still
+    /// pending, then Storage whether it was committed, and otherwise Mempool why it was removed.
+    pub fn get_transaction_status_by_hash_inner(
+        &self,
+        req: GetTransactionStatusByHashRequest,
+    ) -> Result<GetTransactionStatusByHashResponse> {
+        let hash = HashValue::from_slice(&req.hash)?;
+        let mut response = GetTransactionStatusByHashResponse::default();
+        let submission = match self.submissions.get(&hash) {
+            Some(submission) => submission,
+            None => {
+                response.set_status(TransactionStatusCode::TxnUnknown);
+                return Ok(response);
+            }
+        };
+        if let Some(rejection) = submission.rejection {
+            response.set_status(TransactionStatusCode::TxnRejected);
+            response.rejection = Some(rejection);
+            return Ok(response);
+        }
+
+        let mut mempool_request = GetTransactionStatusRequest::default();
+        mempool_request.sender = submission.sender.to_vec();
+        mempool_request.sequence_number = submission.sequence_number;
+        let mempool_status = self.get_transaction_status_inner(mempool_request)?.status();
+        if mempool_status == MempoolTransactionStatusCode::Pending {
+            response.set_status(TransactionStatusCode::TxnPending);
+            return Ok(response);
+        }
+
+        let request_item = RequestItem::GetAccountTransactionBySequenceNumber {
+            account: submission.sender,
+            sequence_number: submission.sequence_number,
+            fetch_events: false,
+        };
+        let (response_items, _, _, _) = self
+            .storage_read_client
+            .update_to_latest_ledger(0, vec![request_item])?;
+        if let Some(ResponseItem::GetAccountTransactionBySequenceNumber {
+            signed_transaction_with_proof: Some(txn_with_proof),
+            ..
+        }) = response_items.into_iter().next()
+        {
+            if txn_with_proof.signed_transaction.hash() == hash {
+                let major_status = txn_with_proof.proof.transaction_info().major_status();
+                response.set_status(TransactionStatusCode::TxnCommitted);
+                response.version = txn_with_proof.version;
+                response.vm_status = Some(VMStatus::new(major_status).into());
+            } else {
+                response.set_status(TransactionStatusCode::TxnDiscarded);
+            }
+            return Ok(response);
+        }
+
+        response.set_status(match mempool_status {
+            MempoolTransactionStatusCode::Rejected => TransactionStatusCode::TxnRejected,
+            MempoolTransactionStatusCode::Expired => TransactionStatusCode::TxnExpired,
+            MempoolTransactionStatusCode::Evicted => TransactionStatusCode::TxnEvicted,
+            MempoolTransactionStatusCode::Replaced => TransactionStatusCode::TxnReplaced,
+            MempoolTransactionStatusCode::Pending => TransactionStatusCode::TxnPending,
+            // Mempool saw a commit Storage doesn't know about, nothing can be told for sure
+            MempoolTransactionStatusCode::Committed | MempoolTransactionStatusCode::Unknown => {
+                TransactionStatusCode::TxnUnknown
+            }
+        });
+        Ok(response)
+    }
+
+    /// Checks that each component of the node AC depends on is alive: Mempool if it says it is
+    /// healthy, Storage if it answers a read of its latest ledger info, and the network if the
+    /// node is connected to peers. The node is healthy if all of them are. The VM validator runs
+    /// in-process over Storage, so it isn't checked on its own.
+    ///
+    /// Mempool and Storage are given `HEALTH_CHECK_TIMEOUT` to respond, and are unhealthy until
+    /// the check of a previous probe which timed out completes.
+    pub fn health_check_inner(&self, _req: HealthCheckRequest) -> Result<HealthCheckResponse> {
+        let mut components = vec![];
+        if let Some(mempool_client) = &self.mempool_client {
+            let mempool_client = Arc::clone(mempool_client);
+            components.push(check_component("mempool", || {
+                self.run_health_check("mempool", move || {
+                    let mempool_response = mempool_client
+                        .health_check(&mempool_proto::HealthCheckRequest::default())?;
+                    ensure!(mempool_response.is_healthy, "Mempool is unhealthy");
+                    Ok(String::new())
+                })
+            }));
+        }
+        let storage_read_client = Arc::clone(&self.storage_read_client);
+        components.push(check_component("storage", || {
+            self.run_health_check("storage", move || {
+                let (_, ledger_info_with_sigs, _, _) =
+                    storage_read_client.update_to_latest_ledger(0, vec![])?;
+                Ok(format!(
+                    "Latest version {}",
+                    ledger_info_with_sigs.ledger_info().version()
+                ))
+            })
+        }));
+        if let Some(connected_peers) = &self.connected_peers {
+            components.push(check_component("network", || {
+                let peers = connected_peers();
+                ensure!(peers > 0, "No connected peers");
+                Ok(format!("{} connected peers", peers))
+            }));
+        }
+
+        let mut response = HealthCheckResponse::default();
+        response.is_healthy = components.iter().all(|component| component.is_healthy);
+        if !response.is_healthy {
+            OP_COUNTERS.inc_by("health_check.unhealthy", 1);
+        }
+        response.components = components;
+        Ok(response)
+    }
+
+    /// Runs the health `check` of the component `name` on a thread of its own, waiting at most
+    /// `HEALTH_CHECK_TIMEOUT` for it. A check which times out keeps running, and the component is
+    /// reported unhealthy without being checked again until it completes, so that a hung
+    /// component doesn't pile up threads.
+    fn run_health_check<F>(&self, name: &'static str, check: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<String> + Send + 'static,
+    {
+        let in_flight = Arc::clone(&self.health_checks_in_flight);
+        ensure!(
+            in_flight
+                .lock()
+                .expect("[health check] acquire lock")
+                .insert(name),
+            "The check of a previous probe is still running"
+        );
+        let (result_tx, result_rx) = mpsc::channel();
+        let spawned = thread::Builder::new()
+            .name(format!("health-check-{}", name))
+            .spawn({
+                let in_flight = Arc::clone(&in_flight);
+                move || {
+                    let result = check();
+                    in_flight
+                        .lock()
+                        .expect("[health check] acquire lock")
+                        .remove(name);
+                    // the probe may have timed out already
+                    let _ = result_tx.send(result);
+                }
+            });
+        if let Err(e) = spawned {
+            in_flight
+                .lock()
+                .expect("[health check] acquire lock")
+                .remove(name);
+            bail!("Failed to spawn the health check: {}", e);
+        }
+        result_rx
+            .recv_timeout(HEALTH_CHECK_TIMEOUT)
+            .unwrap_or_else(|_| bail!("No response within {:?}", HEALTH_CHECK_TIMEOUT))
+    }
+
+    /// Subscribes to the events of the transactions selected by the filter of `req`, as Mempool
+    /// reports them. The version of the committed transactions is read from Storage.
+    pub fn subscribe_transaction_events_inner(
+        &self,
+        req: SubscribeTransactionEventsRequest,
+    ) -> Result<TransactionEventStream> {
+        let mempool_client = self
+            .mempool_client
+            .as_ref()
+            .ok_or_else(|| format_err!("Mempool is not initialized"))?;
+        let (sender, sequence_number) = match req.filter {
+            Some(Filter::Sender(sender)) => (AccountAddress::try_from(&sender[..])?, None),
+            Some(Filter::Hash(hash)) => {
+                let hash = HashValue::from_slice(&hash)?;
+                let submission = self.submissions.get(&hash).ok_or_else(|| {
+                    format_err!(
+                        "Transaction {:x} was not submitted to this node recently",
+                        hash
+                    )
+                })?;
+                (submission.sender, Some(submission.sequence_number))
+            }
+            None => bail!("No transactions selected"),
+        };
+        let mut subscribe_req = SubscribeEventsRequest::default();
+        subscribe_req.sender = sender.to_vec();
+        subscribe_req.sequence_number = sequence_number;
+        let storage_read_client = Arc::clone(&self.storage_read_client);
+        // Mempool only ends the stream when the subscriber falls too far behind or Mempool goes
+        // away, either way the events since then are lost
+        let events = mempool_client
+            .subscribe_events(&subscribe_req)?
+            .map_err(Error::from)
+            .chain(stream::once(Err(EventsLost.into())))
+            .and_then(move |event| to_transaction_event(&*storage_read_client, event));
+        Ok(Box::new(events))
+    }
+
     /// Pass the UpdateToLatestLedgerRequest to Storage for read query.
-    fn update_to_latest_ledger_inner(
+    pub fn update_to_latest_ledger_inner(
         &self,
         req: UpdateToLatestLedgerRequest,
     ) -> Result<UpdateToLatestLedgerResponse> {
         let rust_req = types::get_with_proof::UpdateToLatestLedgerRequest::try_from(req)?;
+        let rust_resp =
+            self.read_latest_ledger(rust_req.client_known_version, rust_req.requested_items)?;
+        Ok(rust_resp.into())
+    }
+
+    /// Reads the state of an account along with the proof of it, as an `UpdateToLatestLedger`
+    /// request for the account state would.
+    pub fn get_account_state_inner(
+        &self,
+        req: GetAccountStateRequest,
+    ) -> Result<GetAccountStateResponse> {
+        let address = AccountAddress::try_from(&req.address[..])?;
+        let mut rust_resp = self.read_latest_ledger(
+            req.client_known_version,
+            vec![RequestItem::GetAccountState { address }],
+        )?;
+        let account_state_with_proof = match rust_resp.response_items.pop() {
+            Some(ResponseItem::GetAccountState {
+                account_state_with_proof,
+            }) => account_state_with_proof,
+            item => bail!("Storage responded with {:?} to an account state read", item),
+        };
+        Ok(admission_control_proto::GetAccountStateResponse {
+            account_state_with_proof,
+            ledger_info_with_sigs: rust_resp.ledger_info_with_sigs,
+            validator_change_events: rust_resp.validator_change_events,
+            ledger_consistency_proof: rust_resp.ledger_consistency_proof,
+        }
+        .into())
+    }
+
+    /// Reads `requested_items` from storage as of its latest ledger info, unless it is older than
+    /// the trusted ledger info, or the node lags more than `max_sync_lag` behind the network.
+    fn read_latest_ledger(
+        &self,
+        client_known_version: Version,
+        requested_items: Vec<RequestItem>,
+    ) -> Result<types::get_with_proof::UpdateToLatestLedgerResponse<Ed25519Signature>> {
         let (
             response_items,
             ledger_info_with_sigs,
@@ -208,18 +1219,39 @@ where
             ledger_consistency_proof,
         ) = self
             .storage_read_client
-            .update_to_latest_ledger(rust_req.client_known_version, rust_req.requested_items)?;
-        let rust_resp = types::get_with_proof::UpdateToLatestLedgerResponse::new(
+            .update_to_latest_ledger(client_known_version, requested_items)?;
+        if let Some(trusted_version) = self.trusted_ledger.version() {
+            let version = ledger_info_with_sigs.ledger_info().version();
+            if version < trusted_version {
+                OP_COUNTERS.inc_by("update_to_latest_ledger.stale_response", 1);
+                bail!(
+                    "Storage responded as of version {}, older than the latest trusted version {}",
+                    version,
+                    trusted_version,
+                );
+            }
+        }
+        if let Some(max_sync_lag) = self.max_sync_lag {
+            let version = ledger_info_with_sigs.ledger_info().version();
+            let sync_lag = self
+                .trusted_ledger
+                .network_version()
+                .saturating_sub(version);
+            if sync_lag > max_sync_lag {
+                OP_COUNTERS.inc_by("update_to_latest_ledger.node_behind", 1);
+                return Err(NodeBehind { version, sync_lag }.into());
+            }
+        }
+        Ok(types::get_with_proof::UpdateToLatestLedgerResponse::new(
             response_items,
             ledger_info_with_sigs,
             validator_change_events,
             ledger_consistency_proof,
-        );
-        Ok(rust_resp.into())
+        ))
     }
 }
 
-impl<M: 'static, V> AdmissionControl for AdmissionControlService<M, V>
+impl<M: 'static, V: 'static> AdmissionControl for AdmissionControlService<M, V>
 where
     M: MempoolClientTrait,
     V: TransactionValidation,
@@ -235,13 +1267,84 @@ where
     ) {
         debug!("[GRPC] AdmissionControl::submit_transaction");
         let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.submit_or_forward_transaction(req, parse_peer_ip(&ctx.peer()));
+        provide_grpc_response(resp, ctx, sink);
+    }
+
+    /// Submit several transactions at once. Each transaction is validated like the ones submitted
+    /// on their own, and the valid ones are added to Mempool in a single request.
+    fn submit_transactions_batch(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: SubmitTransactionsBatchRequest,
+        sink: ::grpcio::UnarySink<SubmitTransactionsBatchResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::submit_transactions_batch");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        if req.transactions.len() > MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE {
+            let status = RpcStatus::new(
+                RpcStatusCode::INVALID_ARGUMENT,
+                Some(format!(
+                    "Batch of {} transactions is over the limit of {} transactions",
+                    req.transactions.len(),
+                    MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE
+                )),
+            );
+            return fail_call(ctx, sink, status);
+        }
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
         let resp = match self.mempool_client {
             None => Err(format_err!("Node doesn't accept write requests")),
-            Some(_) => self.submit_transaction_inner(req),
+            Some(_) => self.submit_transactions_batch_from(req, parse_peer_ip(&ctx.peer())),
         };
         provide_grpc_response(resp, ctx, sink);
     }
 
+    /// Submit a transaction without waiting for its VM validation and its addition to Mempool.
+    /// Answers with a ticket to poll for the result of the submission with.
+    fn submit_transaction_async(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: SubmitTransactionRequest,
+        sink: ::grpcio::UnarySink<SubmitTransactionAsyncResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::submit_transaction_async");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = match self.mempool_client {
+            None => Err(format_err!("Node doesn't accept write requests")),
+            Some(_) => self.submit_transaction_async_inner(req, parse_peer_ip(&ctx.peer())),
+        };
+        provide_grpc_response(resp, ctx, sink);
+    }
+
+    /// Polls for the result of an asynchronous submission by its ticket.
+    fn get_submission_result(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetSubmissionResultRequest,
+        sink: ::grpcio::UnarySink<GetSubmissionResultResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::get_submission_result");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.get_submission_result_inner(req);
+        provide_grpc_response(resp, ctx, sink);
+    }
+
     /// This API is used to update the client to the latest ledger version and optionally also
     /// request 1..n other pieces of data.  This allows for batch queries.  All queries return
     /// proofs that a client should check to validate the data.
@@ -256,7 +1359,293 @@ where
     ) {
         debug!("[GRPC] AdmissionControl::update_to_latest_ledger");
         let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
         let resp = self.update_to_latest_ledger_inner(req);
+        provide_read_response(resp, ctx, sink);
+    }
+
+    /// Reads the state of an account along with the proof of it.
+    fn get_account_state(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetAccountStateRequest,
+        sink: ::grpcio::UnarySink<GetAccountStateResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::get_account_state");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.get_account_state_inner(req);
+        provide_read_response(resp, ctx, sink);
+    }
+
+    /// Estimates the gas unit price needed to get a transaction into the next block, from the
+    /// transactions waiting in Mempool and the recently committed ones.
+    fn get_gas_price_estimate(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetGasPriceEstimateRequest,
+        sink: ::grpcio::UnarySink<GetGasPriceEstimateResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::get_gas_price_estimate");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.get_gas_price_estimate_inner(req);
+        provide_grpc_response(resp, ctx, sink);
+    }
+
+    /// Tells whether a transaction is still waiting in Mempool, or why Mempool recently removed
+    /// it.
+    fn get_transaction_status(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetTransactionStatusRequest,
+        sink: ::grpcio::UnarySink<GetTransactionStatusResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::get_transaction_status");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.get_transaction_status_inner(req);
+        provide_grpc_response(resp, ctx, sink);
+    }
+
+    /// Tracks a transaction submitted to the node by its hash, from its submission to its commit
+    /// or its removal from Mempool.
+    fn get_transaction_status_by_hash(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetTransactionStatusByHashRequest,
+        sink: ::grpcio::UnarySink<GetTransactionStatusByHashResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::get_transaction_status_by_hash");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let _quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => return fail_call(ctx, sink, status),
+        };
+        let resp = self.get_transaction_status_by_hash_inner(req);
         provide_grpc_response(resp, ctx, sink);
     }
+
+    /// Checks the health of each component of the node AC depends on.
+    fn health_check(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: HealthCheckRequest,
+        sink: ::grpcio::UnarySink<HealthCheckResponse>,
+    ) {
+        debug!("[GRPC] AdmissionControl::health_check");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        // load balancer probes don't count against the quota
+        let resp = self.health_check_inner(req);
+        provide_grpc_response(resp, ctx, sink);
+    }
+
+    /// Streams the events of the transactions of an account, or of a transaction submitted to the
+    /// node, until the client goes away or falls too far behind.
+    fn subscribe_transaction_events(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: SubscribeTransactionEventsRequest,
+        sink: ::grpcio::ServerStreamingSink<TransactionEvent>,
+    ) {
+        debug!("[GRPC] AdmissionControl::subscribe_transaction_events");
+        let quota = match self.acquire_quota() {
+            Ok(quota) => quota,
+            Err(status) => {
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger));
+                return;
+            }
+        };
+        match self.subscribe_transaction_events_inner(req) {
+            Ok(events) => {
+                let events = events
+                    .map(move |event| {
+                        // the subscription holds its slot of the quota until it ends
+                        let _quota = &quota;
+                        (event, WriteFlags::default())
+                    })
+                    .map_err(|e| {
+                        let code = if e.downcast_ref::<EventsLost>().is_some() {
+                            RpcStatusCode::ABORTED
+                        } else {
+                            RpcStatusCode::INTERNAL
+                        };
+                        grpcio::Error::RpcFailure(RpcStatus::new(code, Some(e.to_string())))
+                    });
+                // the stream ends when the subscriber goes away, as sending it the next event fails
+                ctx.spawn(
+                    sink.send_all(events)
+                        .map(|_| ())
+                        .map_err(default_reply_error_logger),
+                );
+            }
+            Err(e) => {
+                // Mempool refuses the subscriptions beyond its maximum number of subscribers
+                let code = match e.downcast_ref::<grpcio::Error>() {
+                    Some(grpcio::Error::RpcFailure(status)) => status.status,
+                    _ => RpcStatusCode::INVALID_ARGUMENT,
+                };
+                let status = RpcStatus::new(code, Some(e.to_string()));
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger));
+            }
+        }
+    }
+}
+
+/// Same as `provide_grpc_response`, except that the reads refused with [`NodeBehind`] fail with an
+/// `UNAVAILABLE` status.
+fn provide_read_response<T: std::fmt::Debug>(
+    resp: Result<T>,
+    ctx: grpcio::RpcContext<'_>,
+    sink: grpcio::UnarySink<T>,
+) {
+    if let Some(node_behind) = resp
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<NodeBehind>())
+    {
+        let status = RpcStatus::new(RpcStatusCode::UNAVAILABLE, Some(node_behind.to_string()));
+        return fail_call(ctx, sink, status);
+    }
+    provide_grpc_response(resp, ctx, sink);
+}
+
+/// Fails a call with `status`.
+fn fail_call<T>(ctx: grpcio::RpcContext<'_>, sink: grpcio::UnarySink<T>, status: RpcStatus) {
+    ctx.spawn(sink.fail(status).map_err(default_reply_error_logger));
+    SVC_COUNTERS.resp(&ctx, false);
+}
+
+/// Runs the check of a component, which tells details of its status or fails with the reason it
+/// is unhealthy, and times it.
+fn check_component<F>(name: &str, check: F) -> ComponentHealth
+where
+    F: FnOnce() -> Result<String>,
+{
+    let start = Instant::now();
+    let result = check();
+    let latency = start.elapsed();
+    OP_COUNTERS.observe_duration(&format!("health_check.{}_time_s", name), latency);
+    let mut component = ComponentHealth::default();
+    component.name = name.to_string();
+    component.latency_us = latency.as_micros() as u64;
+    match result {
+        Ok(message) => {
+            component.is_healthy = true;
+            component.message = message;
+        }
+        Err(e) => {
+            warn!("[health check] {} is unhealthy: {}", name, e);
+            component.message = e.to_string();
+        }
+    }
+    component
+}
+
+/// Counts the responses to the submissions by status, e.g. `submit_txn.status.ac.Accepted`,
+/// `submit_txn.status.vm.INVALID_SIGNATURE` or, for Move aborts, `submit_txn.status.vm.ABORTED.7`
+/// where 7 is the category of the abort code.
+fn count_response_status(response: &SubmitTransactionResponse) {
+    let status = match &response.status {
+        Some(Status::AcStatus(status)) => format!("ac.{:?}", status.code()),
+        Some(Status::VmStatus(status)) if status.has_abort_code => {
+            format!("vm.ABORTED.{}", status.abort_category)
+        }
+        Some(Status::VmStatus(status)) => format!(
+            "vm.{:?}",
+            StatusCode::from_u64_or_unknown(status.major_status)
+        ),
+        Some(Status::MempoolStatus(status)) => format!("mempool.{:?}", status.code()),
+        None => "unknown".to_string(),
+    };
+    OP_COUNTERS.inc_by(&format!("submit_txn.status.{}", status), 1);
+}
+
+/// IP address of a gRPC peer, given as e.g. `ipv4:127.0.0.1:40000` or `ipv6:[::1]:40000`.
+fn parse_peer_ip(peer: &str) -> Option<IpAddr> {
+    let address = peer.splitn(2, ':').nth(1)?;
+    address
+        .parse::<SocketAddr>()
+        .ok()
+        .map(|address| address.ip())
+}
+
+/// Converts an event of Mempool to the event of the transaction sent to clients, along with the
+/// version of the transaction if it was committed, which is read from Storage asynchronously.
+fn to_transaction_event(
+    storage_read_client: &dyn StorageRead,
+    event: MempoolEvent,
+) -> Box<dyn Future<Item = TransactionEvent, Error = Error> + Send> {
+    let mut txn_event = TransactionEvent::default();
+    txn_event.set_event_type(match event.event_type() {
+        MempoolEventType::TxnAdded => TransactionEventType::EventAccepted,
+        MempoolEventType::TxnBroadcast => TransactionEventType::EventBroadcast,
+        MempoolEventType::TxnRemovedCommitted => TransactionEventType::EventCommitted,
+        MempoolEventType::TxnRejected => TransactionEventType::EventRejected,
+        MempoolEventType::TxnExpired => TransactionEventType::EventExpired,
+        MempoolEventType::TxnEvicted => TransactionEventType::EventEvicted,
+        MempoolEventType::TxnReplaced => TransactionEventType::EventReplaced,
+    });
+    let version = if txn_event.event_type() == TransactionEventType::EventCommitted {
+        Some(get_committed_version(storage_read_client, &event))
+    } else {
+        None
+    };
+    txn_event.sender = event.sender;
+    txn_event.sequence_number = event.sequence_number;
+    match version {
+        Some(version) => Box::new(version.then(move |version| {
+            match version {
+                Ok(Some(version)) => txn_event.version = version,
+                Ok(None) => (),
+                Err(e) => warn!(
+                    "Failed to read the version of a committed transaction: {}",
+                    e
+                ),
+            }
+            Ok::<_, Error>(txn_event)
+        })),
+        None => Box::new(future::ok(txn_event)),
+    }
+}
+
+/// Version of the committed transaction of the sender and sequence number of `event`, if Storage
+/// has it.
+fn get_committed_version(
+    storage_read_client: &dyn StorageRead,
+    event: &MempoolEvent,
+) -> Box<dyn Future<Item = Option<Version>, Error = Error> + Send> {
+    let account = match AccountAddress::try_from(&event.sender[..]) {
+        Ok(account) => account,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let request_item = RequestItem::GetAccountTransactionBySequenceNumber {
+        account,
+        sequence_number: event.sequence_number,
+        fetch_events: false,
+    };
+    let response = storage_read_client
+        .update_to_latest_ledger_async(0, vec![request_item])
+        .compat();
+    Box::new(response.and_then(
+        |(response_items, _, _, _)| match response_items.into_iter().next() {
+            Some(ResponseItem::GetAccountTransactionBySequenceNumber {
+                signed_transaction_with_proof,
+                ..
+            }) => Ok(signed_transaction_with_proof.map(|txn| txn.version)),
+            _ => bail!("Unexpected response from Storage"),
+        },
+    ))
 }
@@ -0,0 +1,129 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Asynchronous submission of transactions: Admission Control answers the submissions with a
+//! ticket right after their basic checks, and leaves their VM validation and their addition to
+//! Mempool to a pool of workers. Clients poll for the response to their submission with their
+//! ticket, which is the hash of their transaction, so that identical resubmissions share the same
+//! ticket.
+//!
+//! The submissions waiting for a worker are queued, up to `queue_size` of them, after which new
+//! submissions are refused until the workers catch up. The responses are remembered for
+//! `ttl` after the submission, for at most `capacity` tickets at a time.
+
+use admission_control_proto::proto::admission_control::SubmitTransactionResponse;
+use crypto::HashValue;
+use logger::prelude::*;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use ttl_cache::TtlCache;
+
+#[cfg(test)]
+#[path = "unit_tests/async_submission_test.rs"]
+mod async_submission_test;
+
+/// Default number of tickets remembered at a time.
+pub const DEFAULT_TICKET_CAPACITY: usize = 100_000;
+/// Default time the responses are remembered for after the submission.
+pub const DEFAULT_TICKET_TTL: Duration = Duration::from_secs(600);
+
+/// Computes the response to a submission, or fails with the error of the node.
+type Job = Box<dyn FnOnce() -> Result<SubmitTransactionResponse, String> + Send>;
+
+/// Result of an asynchronous submission, if it is done: the response to it, or the error which
+/// prevented the node from processing it.
+pub(crate) type SubmissionResult = Option<Result<SubmitTransactionResponse, String>>;
+
+/// Queue of the asynchronous submissions and their results. Clones share the same queue and
+/// workers, which exit once all the clones are dropped.
+#[derive(Clone)]
+pub(crate) struct AsyncSubmitter {
+    jobs: SyncSender<(HashValue, Job)>,
+    results: Arc<Mutex<TtlCache<HashValue, SubmissionResult>>>,
+    ttl: Duration,
+}
+
+impl AsyncSubmitter {
+    /// Starts `workers` threads processing the submissions, at most `queue_size` of which are
+    /// waiting for them.
+    pub(crate) fn new(workers: usize, queue_size: usize, capacity: usize, ttl: Duration) -> Self {
+        let (jobs, receiver) = mpsc::sync_channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let results = Arc::new(Mutex::new(TtlCache::new(capacity)));
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let results = Arc::clone(&results);
+            thread::Builder::new()
+                .name(format!("ac-async-submission-{}", i))
+                .spawn(move || Self::work(&receiver, &results, ttl))
+                .expect("[async submission] failed to spawn worker");
+        }
+        Self { jobs, results, ttl }
+    }
+
+    fn work(
+        receiver: &Mutex<Receiver<(HashValue, Job)>>,
+        results: &Mutex<TtlCache<HashValue, SubmissionResult>>,
+        ttl: Duration,
+    ) {
+        loop {
+            // the lock is released as soon as a job is received
+            let job = receiver
+                .lock()
+                .expect("[async submission] acquire receiver lock")
+                .recv();
+            let (ticket, job) = match job {
+                Ok(job) => job,
+                // all the submitters are gone
+                Err(_) => return,
+            };
+            let result = job();
+            results
+                .lock()
+                .expect("[async submission] acquire lock")
+                .insert(ticket, Some(result), ttl);
+        }
+    }
+
+    /// Queues `job` computing the response to the submission of `ticket`, unless the same ticket
+    /// was submitted already. Returns false if the queue is full.
+    pub(crate) fn submit<F>(&self, ticket: HashValue, job: F) -> bool
+    where
+        F: FnOnce() -> Result<SubmitTransactionResponse, String> + Send + 'static,
+    {
+        let mut results = self
+            .results
+            .lock()
+            .expect("[async submission] acquire lock");
+        if results.contains_key(&ticket) {
+            return true;
+        }
+        match self.jobs.try_send((ticket, Box::new(job))) {
+            Ok(()) => {
+                results.insert(ticket, None, self.ttl);
+                true
+            }
+            Err(TrySendError::Full(_)) => false,
+            Err(TrySendError::Disconnected(_)) => {
+                error!("[async submission] no worker to process the submissions");
+                false
+            }
+        }
+    }
+
+    /// Result of the submission of `ticket`: `None` if the ticket is unknown, `Some(None)` while
+    /// the submission is pending.
+    pub(crate) fn result(&self, ticket: &HashValue) -> Option<SubmissionResult> {
+        self.results
+            .lock()
+            .expect("[async submission] acquire lock")
+            .get(ticket)
+            .cloned()
+    }
+}
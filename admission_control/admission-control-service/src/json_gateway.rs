@@ -0,0 +1,378 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP+JSON front end of Admission Control, for web wallets and tools which can't speak gRPC.
+//!
+//! The gateway maps its endpoints onto the handlers of [`AdmissionControlService`]:
+//! - `POST /v1/transactions` submits a transaction, given as `{"signed_txn": "<hex>"}` where the
+//!   hex string encodes the LCS serialization of the signed transaction, and answers with the
+//!   status of the submission.
+//! - `GET /v1/transactions/<sender>/<sequence_number>/status` tells whether the transaction of
+//!   the hex-encoded `sender` with `sequence_number` is waiting in Mempool, or why Mempool
+//!   recently removed it.
+//! - `GET /v1/health` tells the health of each component of the node, with a `200 OK` status if
+//!   all of them are healthy and `503 Service Unavailable` otherwise, for load balancer probes.
+//!
+//! Malformed requests are answered with `400 Bad Request`, bodies larger than
+//! [`MAX_REQUEST_BODY_BYTES`] with `413 Payload Too Large`, requests beyond the quota of the
+//! service with `429 Too Many Requests`, and failures of the node with
+//! `500 Internal Server Error`, all with an `{"error": "<message>"}` body.
+
+use crate::admission_control_service::AdmissionControlService;
+use admission_control_proto::{
+    proto::admission_control::{
+        GetTransactionStatusRequest, HealthCheckRequest, SubmitTransactionRequest,
+    },
+    AdmissionControlStatus, SubmitTransactionResponse,
+};
+use failure::prelude::*;
+use futures::{
+    future::{self, Either},
+    Future, Stream,
+};
+use futures_cpupool::{Builder as CpuPoolBuilder, CpuPool};
+use hyper::{
+    rt,
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use logger::prelude::*;
+use mempool::proto::mempool_client::MempoolClientTrait;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::Arc,
+};
+use types::account_address::AccountAddress;
+use vm_validator::vm_validator::TransactionValidation;
+
+#[cfg(test)]
+#[path = "unit_tests/json_gateway_test.rs"]
+mod json_gateway_test;
+
+/// Size of the largest request body read, well above the size of the largest transactions, so
+/// that clients can't exhaust the memory of the node.
+pub const MAX_REQUEST_BODY_BYTES: usize = 64 * 1024;
+
+/// Body of the transaction submissions.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JsonSubmitTransactionRequest {
+    /// Hex encoded LCS serialization of the signed transaction.
+    pub signed_txn: String,
+}
+
+/// Status of a transaction submission.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JsonSubmitTransactionResponse {
+    /// One of `accepted`, `blacklisted`, `rejected`, `rate_limited`, `overloaded`,
+    /// `mempool_error` or `vm_error`.
+    pub status: String,
+    /// Mempool status code or VM major status of the errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Details of the status, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Status of a transaction in Mempool.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JsonTransactionStatusResponse {
+    /// One of the Mempool transaction status codes, e.g. `Pending` or `Expired`.
+    pub status: String,
+}
+
+/// Health of the node.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JsonHealthResponse {
+    /// Whether all the components are healthy.
+    pub is_healthy: bool,
+    /// Health of each component.
+    pub components: Vec<JsonComponentHealth>,
+}
+
+/// Health of a component of the node.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JsonComponentHealth {
+    /// One of `mempool`, `storage`, `vm_validator` or `network`.
+    pub name: String,
+    /// Whether the component is healthy.
+    pub is_healthy: bool,
+    /// How long the check of the component took, in microseconds.
+    pub latency_us: u64,
+    /// Why the component is unhealthy, or details of its status, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonError {
+    error: String,
+}
+
+enum BodyError {
+    TooLarge,
+    Hyper(hyper::Error),
+}
+
+/// HTTP+JSON gateway to an Admission Control service. Cloning it is cheap and all clones share
+/// the same service.
+pub struct JsonGateway<M, V> {
+    service: Arc<AdmissionControlService<M, V>>,
+    // The handlers block on the VM, Mempool and storage, so they run on this pool rather than on
+    // the threads of the HTTP server.
+    pool: CpuPool,
+}
+
+// Cannot derive `Clone`, which would require `V: Clone`.
+impl<M, V> Clone for JsonGateway<M, V> {
+    fn clone(&self) -> Self {
+        Self {
+            service: Arc::clone(&self.service),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<M: 'static, V: 'static> JsonGateway<M, V>
+where
+    M: MempoolClientTrait,
+    V: TransactionValidation,
+{
+    /// Creates a gateway serving the requests with `service`.
+    pub fn new(service: AdmissionControlService<M, V>) -> Self {
+        Self {
+            service: Arc::new(service),
+            pool: CpuPoolBuilder::new().name_prefix("ac-json-").create(),
+        }
+    }
+
+    /// Serves HTTP requests on `to_addr` until the process exits.
+    pub fn run<T: ToSocketAddrs>(self, to_addr: T) {
+        let addr: SocketAddr = to_addr
+            .to_socket_addrs()
+            .unwrap_or_else(|_| panic!("Failed to parse address"))
+            .next()
+            .unwrap();
+
+        rt::run(rt::lazy(move || {
+            match Server::try_bind(&addr) {
+                Ok(srv) => {
+                    let srv = srv
+                        .serve(make_service_fn(move |conn: &AddrStream| {
+                            let gateway = self.clone();
+                            let client = conn.remote_addr().ip();
+                            service_fn(move |req: Request<Body>| gateway.serve(req, client))
+                        }))
+                        .map_err(|e| error!("JSON gateway error: {}", e));
+                    info!("AC JSON gateway listening on http://{}", addr);
+                    rt::spawn(srv);
+                }
+                Err(e) => error!("AC JSON gateway bind error: {}", e),
+            };
+
+            Ok(())
+        }));
+    }
+
+    fn serve(
+        &self,
+        req: Request<Body>,
+        client: IpAddr,
+    ) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+        let gateway = self.clone();
+        let (parts, body) = req.into_parts();
+        body.map_err(BodyError::Hyper)
+            .fold(Vec::new(), |mut body, chunk| {
+                if body.len() + chunk.len() > MAX_REQUEST_BODY_BYTES {
+                    return Err(BodyError::TooLarge);
+                }
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            })
+            .then(move |body| match body {
+                Ok(body) => Either::A(gateway.pool.clone().spawn_fn(move || {
+                    Ok::<_, hyper::Error>(gateway.handle(
+                        &parts.method,
+                        parts.uri.path(),
+                        &body,
+                        Some(client),
+                    ))
+                })),
+                Err(BodyError::TooLarge) => Either::B(future::ok(error_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Request body larger than {} bytes", MAX_REQUEST_BODY_BYTES),
+                ))),
+                Err(BodyError::Hyper(e)) => Either::B(future::err(e)),
+            })
+    }
+
+    /// Answers the request for `path` with `body`, sent by `client` if its address is known.
+    pub fn handle(
+        &self,
+        method: &Method,
+        path: &str,
+        body: &[u8],
+        client: Option<IpAddr>,
+    ) -> Response<Body> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        // load balancer probes don't count against the quota
+        let _quota = if segments != ["v1", "health"] {
+            match self.service.try_acquire_quota() {
+                Ok(quota) => quota,
+                Err(e) => return error_response(StatusCode::TOO_MANY_REQUESTS, e.to_string()),
+            }
+        } else {
+            None
+        };
+        match (method, segments.as_slice()) {
+            (&Method::POST, ["v1", "transactions"]) => self.submit_transaction(body, client),
+            (&Method::GET, ["v1", "transactions", sender, sequence_number, "status"]) => {
+                self.get_transaction_status(sender, sequence_number)
+            }
+            (&Method::GET, ["v1", "health"]) => self.health_check(),
+            _ => error_response(StatusCode::NOT_FOUND, format!("No route for {}", path)),
+        }
+    }
+
+    fn submit_transaction(&self, body: &[u8], client: Option<IpAddr>) -> Response<Body> {
+        let req = match parse_submit_transaction_request(body) {
+            Ok(req) => req,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        match self
+            .service
+            .submit_or_forward_transaction(req, client)
+            .and_then(SubmitTransactionResponse::try_from)
+        {
+            Ok(response) => json_response(StatusCode::OK, &to_json_submit_response(response)),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+
+    fn get_transaction_status(&self, sender: &str, sequence_number: &str) -> Response<Body> {
+        let req = match parse_transaction_status_request(sender, sequence_number) {
+            Ok(req) => req,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        match self.service.get_transaction_status_inner(req) {
+            Ok(response) => json_response(
+                StatusCode::OK,
+                &JsonTransactionStatusResponse {
+                    status: format!("{:?}", response.status()),
+                },
+            ),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+
+    fn health_check(&self) -> Response<Body> {
+        match self
+            .service
+            .health_check_inner(HealthCheckRequest::default())
+        {
+            Ok(response) => {
+                let status = if response.is_healthy {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                };
+                let components = response
+                    .components
+                    .into_iter()
+                    .map(|component| JsonComponentHealth {
+                        name: component.name,
+                        is_healthy: component.is_healthy,
+                        latency_us: component.latency_us,
+                        message: Some(component.message).filter(|message| !message.is_empty()),
+                    })
+                    .collect();
+                json_response(
+                    status,
+                    &JsonHealthResponse {
+                        is_healthy: response.is_healthy,
+                        components,
+                    },
+                )
+            }
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+}
+
+fn parse_submit_transaction_request(body: &[u8]) -> Result<SubmitTransactionRequest> {
+    let json_req: JsonSubmitTransactionRequest = serde_json::from_slice(body)?;
+    let mut signed_txn = types::proto::types::SignedTransaction::default();
+    signed_txn.signed_txn = hex::decode(&json_req.signed_txn)?;
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(signed_txn);
+    Ok(req)
+}
+
+fn parse_transaction_status_request(
+    sender: &str,
+    sequence_number: &str,
+) -> Result<GetTransactionStatusRequest> {
+    let sender = AccountAddress::try_from(hex::decode(sender)?.as_slice())?;
+    let mut req = GetTransactionStatusRequest::default();
+    req.sender = sender.to_vec();
+    req.sequence_number = sequence_number.parse()?;
+    Ok(req)
+}
+
+fn to_json_submit_response(response: SubmitTransactionResponse) -> JsonSubmitTransactionResponse {
+    let (status, code, message) = if let Some(ac_status) = response.ac_status {
+        match ac_status {
+            AdmissionControlStatus::Accepted => ("accepted", None, None),
+            AdmissionControlStatus::Blacklisted(message) => ("blacklisted", None, Some(message)),
+            AdmissionControlStatus::Rejected(message) => ("rejected", None, Some(message)),
+            AdmissionControlStatus::RateLimited(retry_after) => (
+                "rate_limited",
+                None,
+                Some(format!("retry after {} ms", retry_after.as_millis())),
+            ),
+            AdmissionControlStatus::Overloaded(retry_after) => (
+                "overloaded",
+                None,
+                Some(format!("retry after {} ms", retry_after.as_millis())),
+            ),
+            AdmissionControlStatus::InternalError(message) => {
+                ("internal_error", None, Some(message))
+            }
+        }
+    } else if let Some(mempool_error) = response.mempool_error {
+        (
+            "mempool_error",
+            Some(format!("{:?}", mempool_error.code)),
+            Some(mempool_error.message),
+        )
+    } else if let Some(vm_error) = response.vm_error {
+        (
+            "vm_error",
+            Some(format!("{:?}", vm_error.major_status)),
+            vm_error.message,
+        )
+    } else {
+        ("unknown", None, None)
+    };
+    JsonSubmitTransactionResponse {
+        status: status.to_string(),
+        code,
+        message: message.filter(|message| !message.is_empty()),
+    }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let body = serde_json::to_vec(body).expect("Unable to serialize JSON response");
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("Unable to build JSON response")
+}
+
+fn error_response(status: StatusCode, error: String) -> Response<Body> {
+    json_response(status, &JsonError { error })
+}
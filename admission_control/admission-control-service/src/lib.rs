@@ -9,12 +9,28 @@
 //! AC serves two types of request from clients:
 //! 1. SubmitTransaction, to submit transaction to associated validator.
 //! 2. UpdateToLatestLedger, to query storage, e.g. account state, transaction log, and proofs.
+//!
+//! Clients can also track the transactions they submitted by hash with GetTransactionStatusByHash.
+//! High throughput clients can submit transactions asynchronously with SubmitTransactionAsync, and
+//! poll for the result of their submissions with GetSubmissionResult.
+//!
+//! Transactions can also be submitted and followed over HTTP+JSON through the [`json_gateway`].
 
 /// AC gRPC service.
 pub mod admission_control_service;
+mod async_submission;
+/// AC HTTP+JSON gateway.
+pub mod json_gateway;
+mod load_shedder;
 #[cfg(any(test, feature = "fuzzing"))]
 /// Useful Mocks
 pub mod mocks;
+mod pre_validation;
+mod quota;
+mod rate_limiter;
+mod submission_cache;
+/// Submission of transactions over the network.
+pub mod upstream;
 use lazy_static::lazy_static;
 use metrics::OpMetrics;
 
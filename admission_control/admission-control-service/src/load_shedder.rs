@@ -0,0 +1,89 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Load shedding of the transaction submissions, so that a flood of submissions is refused early
+//! instead of queueing up VM validations for everyone.
+//!
+//! The shedder counts the transactions in flight, from the end of their stateless checks to their
+//! addition to Mempool, the ones submitted asynchronously and waiting for a worker included. Past
+//! `max_in_flight`, only the transactions of the priority senders are admitted, up to
+//! `max_in_flight_priority`, and the other ones are refused with a retriable `Overloaded` status.
+//! A transaction only counts as one of a priority sender once it is proven to come from it, see
+//! `AdmissionControlService::admit_txns`. A batch of transactions is admitted as a single unit.
+
+use config::config::LoadSheddingConfig;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use types::account_address::AccountAddress;
+
+#[cfg(test)]
+#[path = "unit_tests/load_shedder_test.rs"]
+mod load_shedder_test;
+
+/// Counts the transactions in flight. Clones share the same count.
+#[derive(Clone)]
+pub(crate) struct LoadShedder {
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+    max_in_flight_priority: usize,
+    retry_after: Duration,
+    priority_senders: Arc<HashSet<AccountAddress>>,
+}
+
+impl LoadShedder {
+    pub(crate) fn new(config: &LoadSheddingConfig) -> Self {
+        Self {
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: config.max_in_flight,
+            max_in_flight_priority: config.max_in_flight_priority.max(config.max_in_flight),
+            retry_after: Duration::from_millis(config.retry_after_ms),
+            priority_senders: Arc::new(config.get_priority_senders()),
+        }
+    }
+
+    /// Whether the transactions of `sender` are admitted past `max_in_flight`.
+    pub(crate) fn is_priority_sender(&self, sender: &AccountAddress) -> bool {
+        self.priority_senders.contains(sender)
+    }
+
+    /// Admits a transaction, or a batch of them, which stays in flight until the guard is dropped.
+    /// Returns how long the client is to wait before retrying if it is shed.
+    pub(crate) fn try_admit(&self, is_priority: bool) -> Result<InFlight, Duration> {
+        let max_in_flight = if is_priority {
+            self.max_in_flight_priority
+        } else {
+            self.max_in_flight
+        };
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        // the guard gives the slot back on every path
+        let guard = InFlight {
+            in_flight: Arc::clone(&self.in_flight),
+        };
+        if in_flight >= max_in_flight {
+            return Err(self.retry_after);
+        }
+        Ok(guard)
+    }
+
+    /// Number of the transactions in flight.
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Transaction admitted by the load shedder, in flight until dropped.
+pub(crate) struct InFlight {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -4,12 +4,18 @@
 use mempool::proto::{
     mempool::{
         AddTransactionWithValidationRequest, AddTransactionWithValidationResponse,
-        HealthCheckRequest, HealthCheckResponse,
+        AddTransactionsWithValidationRequest, AddTransactionsWithValidationResponse,
+        GetGasPriceEstimateRequest, GetGasPriceEstimateResponse, GetTransactionStatusRequest,
+        GetTransactionStatusResponse, HealthCheckRequest, HealthCheckResponse, MempoolEvent,
+        MempoolEventType, SubscribeEventsRequest,
     },
-    mempool_client::MempoolClientTrait,
+    mempool_client::{MempoolClientTrait, MempoolEventStream},
 };
-use mempool_shared_proto::proto::mempool_status::{
-    MempoolAddTransactionStatus, MempoolAddTransactionStatusCode,
+use mempool_shared_proto::{
+    proto::mempool_status::{
+        MempoolAddTransactionStatus, MempoolAddTransactionStatusCode, MempoolTransactionStatusCode,
+    },
+    GasPriceEstimate, GasPricePercentiles,
 };
 use std::convert::TryFrom;
 use std::time::SystemTime;
@@ -60,6 +66,18 @@ impl MempoolClientTrait for LocalMockMempool {
         resp.status = Some(status);
         Ok(resp)
     }
+
+    fn add_transactions_with_validation(
+        &self,
+        req: &AddTransactionsWithValidationRequest,
+    ) -> ::grpcio::Result<AddTransactionsWithValidationResponse> {
+        let mut resp = AddTransactionsWithValidationResponse::default();
+        for txn_req in &req.transactions {
+            let status = self.add_transaction_with_validation(txn_req)?.status;
+            resp.statuses.push(status.unwrap_or_default());
+        }
+        Ok(resp)
+    }
     fn health_check(&self, _req: &HealthCheckRequest) -> ::grpcio::Result<HealthCheckResponse> {
         let mut ret = HealthCheckResponse::default();
         let duration_ms = SystemTime::now()
@@ -69,4 +87,66 @@ impl MempoolClientTrait for LocalMockMempool {
         ret.is_healthy = duration_ms > 500 || duration_ms < 300;
         Ok(ret)
     }
+
+    fn get_gas_price_estimate(
+        &self,
+        _req: &GetGasPriceEstimateRequest,
+    ) -> ::grpcio::Result<GetGasPriceEstimateResponse> {
+        let mut ret = GetGasPriceEstimateResponse::default();
+        let percentiles = GasPricePercentiles {
+            p50: 1,
+            p90: 5,
+            p99: 10,
+            count: 100,
+        };
+        let estimate = GasPriceEstimate {
+            mempool: percentiles,
+            committed: percentiles,
+            next_block_gas_price: 6,
+        };
+        ret.estimate = Some(estimate.into());
+        Ok(ret)
+    }
+
+    fn get_transaction_status(
+        &self,
+        req: &GetTransactionStatusRequest,
+    ) -> ::grpcio::Result<GetTransactionStatusResponse> {
+        let mut ret = GetTransactionStatusResponse::default();
+        let pending_add = [103_u8; ADDRESS_LENGTH];
+        let expired_add = [105_u8; ADDRESS_LENGTH];
+        if req.sender[..] == pending_add {
+            ret.set_status(MempoolTransactionStatusCode::Pending);
+        } else if req.sender[..] == expired_add {
+            ret.set_status(MempoolTransactionStatusCode::Expired);
+        }
+        Ok(ret)
+    }
+
+    fn subscribe_events(
+        &self,
+        req: &SubscribeEventsRequest,
+    ) -> ::grpcio::Result<MempoolEventStream> {
+        let event = |sender: u8, sequence_number: u64, event_type: MempoolEventType| {
+            let mut event = MempoolEvent::default();
+            event.set_event_type(event_type);
+            event.sender = vec![sender; ADDRESS_LENGTH];
+            event.sequence_number = sequence_number;
+            event
+        };
+        let events = vec![
+            event(103, 0, MempoolEventType::TxnAdded),
+            event(103, 0, MempoolEventType::TxnBroadcast),
+            event(105, 0, MempoolEventType::TxnAdded),
+            event(103, 0, MempoolEventType::TxnRemovedCommitted),
+            event(105, 0, MempoolEventType::TxnExpired),
+            event(103, 1, MempoolEventType::TxnAdded),
+        ];
+        let (sender, sequence_number) = (req.sender.clone(), req.sequence_number);
+        let events = events.into_iter().filter(move |event| {
+            event.sender == sender
+                && sequence_number.map_or(true, |seq| seq == event.sequence_number)
+        });
+        Ok(Box::new(futures::stream::iter_ok(events)))
+    }
 }
@@ -0,0 +1,92 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stateless checks of the transactions submitted to Admission Control, run before their VM
+//! validation so that the obviously bad transactions are rejected without paying for it. The
+//! checks only look at the submission itself, never at the state of the ledger, and each of them
+//! is enabled by its own setting of the [`PreValidationConfig`].
+//!
+//! The checks of the transactions are the stateless validations of Mempool, so that both enforce
+//! the same policies the same way. The chain the client submits to is not part of the signed
+//! transaction, so it only catches the clients submitting to the wrong chain by mistake.
+
+use config::config::PreValidationConfig;
+use crypto::HashValue;
+use failure::prelude::*;
+use mempool::{
+    MaxGasUnitPrice, MaxTransactionSize, ScriptAllowList, ScriptDenyList, StatelessValidationError,
+    StatelessValidator,
+};
+use types::transaction::SignedTransaction;
+
+#[cfg(test)]
+#[path = "unit_tests/pre_validation_test.rs"]
+mod pre_validation_test;
+
+/// Why a submission failed the pre-validation, besides the stateless validations of Mempool.
+#[derive(Debug, Fail, PartialEq)]
+pub(crate) enum PreValidationError {
+    #[fail(
+        display = "Transaction submitted to chain {:?}, this node is on chain {:?}",
+        chain_id, expected
+    )]
+    WrongChain { chain_id: String, expected: String },
+}
+
+/// Name of the check which failed with `error`, for the counters.
+pub(crate) fn check_name(error: &Error) -> &'static str {
+    if let Some(error) = error.downcast_ref::<StatelessValidationError>() {
+        return error.check_name();
+    }
+    match error.downcast_ref::<PreValidationError>() {
+        Some(PreValidationError::WrongChain { .. }) => "wrong_chain",
+        None => "other",
+    }
+}
+
+/// Runs the enabled checks of its config.
+#[derive(Clone, Default)]
+pub(crate) struct PreValidator {
+    chain_id: Option<String>,
+    validator: StatelessValidator,
+}
+
+impl PreValidator {
+    pub(crate) fn new(config: PreValidationConfig) -> Self {
+        let mut validator = StatelessValidator::new();
+        if let Some(max) = config.max_transaction_size_bytes {
+            validator = validator.with(MaxTransactionSize(max as usize));
+        }
+        if let Some(max) = config.max_gas_unit_price {
+            validator = validator.with(MaxGasUnitPrice(max));
+        }
+        // denied scripts are rejected even if they are allowed
+        if !config.denied_scripts.is_empty() {
+            let hashes = config.denied_scripts.into_iter().map(HashValue::new);
+            validator = validator.with(ScriptDenyList(hashes.collect()));
+        }
+        if !config.allowed_scripts.is_empty() {
+            let hashes = config.allowed_scripts.into_iter().map(HashValue::new);
+            validator = validator.with(ScriptAllowList(hashes.collect()));
+        }
+        Self {
+            chain_id: config.chain_id,
+            validator,
+        }
+    }
+
+    /// Checks `txn`, submitted to the chain `chain_id`, failing with the first check it does not
+    /// pass.
+    pub(crate) fn check(&self, chain_id: &str, txn: &SignedTransaction) -> Result<()> {
+        if let Some(expected) = &self.chain_id {
+            if chain_id != expected {
+                return Err(PreValidationError::WrongChain {
+                    chain_id: chain_id.to_string(),
+                    expected: expected.clone(),
+                }
+                .into());
+            }
+        }
+        self.validator.validate(txn)
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Quota of the requests of a named identity of clients authenticated with mutual TLS, shared by
+//! all the clients of the identity. The quota limits the rate of the requests of any kind, with a
+//! token bucket like the rate limits of the submissions, and the number of the requests processed
+//! at once. A request holds its slot of the quota until its [`QuotaGuard`] is dropped.
+
+use crate::rate_limiter::RateLimiter;
+use config::config::IdentityQuotaConfig;
+use failure::prelude::*;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+#[path = "unit_tests/quota_test.rs"]
+mod quota_test;
+
+/// Why a request exceeds the quota of its identity.
+#[derive(Debug, Fail, PartialEq)]
+pub(crate) enum QuotaExceeded {
+    #[fail(display = "Quota of {} exceeded, retry after {:?}", _0, _1)]
+    RateLimited(String, Duration),
+    #[fail(
+        display = "Quota of {} exceeded, {} requests in flight already",
+        _0, _1
+    )]
+    TooManyInFlight(String, usize),
+}
+
+/// Quota of an identity. Clones share the same quota.
+#[derive(Clone)]
+pub(crate) struct Quota {
+    identity: String,
+    rate_limiter: Option<RateLimiter<()>>,
+    max_in_flight: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Quota {
+    pub(crate) fn new(identity: String, config: &IdentityQuotaConfig) -> Self {
+        Self {
+            identity,
+            rate_limiter: config.rate_limit.as_ref().map(RateLimiter::new),
+            max_in_flight: config.max_in_flight,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Takes a slot of the quota for a request received at `now`, which it holds until the guard
+    /// is dropped.
+    pub(crate) fn try_acquire(
+        &self,
+        now: Instant,
+    ) -> std::result::Result<QuotaGuard, QuotaExceeded> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        // the guard gives the slot back on every path
+        let guard = QuotaGuard {
+            in_flight: Arc::clone(&self.in_flight),
+        };
+        if let Some(max_in_flight) = self.max_in_flight {
+            if in_flight >= max_in_flight {
+                return Err(QuotaExceeded::TooManyInFlight(
+                    self.identity.clone(),
+                    in_flight,
+                ));
+            }
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.try_acquire((), now).map_err(|retry_after| {
+                QuotaExceeded::RateLimited(self.identity.clone(), retry_after)
+            })?;
+        }
+        Ok(guard)
+    }
+}
+
+/// Slot of the quota of an identity held by a request in flight.
+pub(crate) struct QuotaGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for QuotaGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token bucket rate limiting of the transaction submissions, so that a single client can't
+//! monopolize the VM validation capacity of Admission Control.
+//!
+//! Each key, e.g. a sender account or a client IP address, gets its own bucket holding up to
+//! `burst` tokens and refilled with `requests_per_sec` tokens per second. Each submission takes a
+//! token, and is refused while the bucket of its key is empty, telling the client how long to wait
+//! for the next token. Only the buckets of the `MAX_BUCKETS` most recently active keys are kept
+//! in memory: the keys whose bucket is forgotten get a full one again.
+
+use config::config::RateLimitConfig;
+use lru_cache::LruCache;
+use std::{
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+#[path = "unit_tests/rate_limiter_test.rs"]
+mod rate_limiter_test;
+
+/// Number of buckets kept in memory
+pub(crate) const MAX_BUCKETS: usize = 10_000;
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Rate limiter with a token bucket per key. Clones share the same buckets.
+#[derive(Clone)]
+pub(crate) struct RateLimiter<K> {
+    buckets: Arc<Mutex<LruCache<K, TokenBucket>>>,
+    requests_per_sec: f64,
+    burst: f64,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub(crate) fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(LruCache::new(MAX_BUCKETS))),
+            // buckets which are never refilled would lock their keys out for good
+            requests_per_sec: config.requests_per_sec.max(1) as f64,
+            burst: config.burst.max(1) as f64,
+        }
+    }
+
+    /// Takes a token from the bucket of `key` as of `now`. Returns how long to wait for the next
+    /// token if the bucket is empty.
+    pub(crate) fn try_acquire(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().expect("[rate limiter] acquire lock");
+        // taken out and put back, which makes it the most recently used one
+        let mut bucket = buckets.remove(&key).unwrap_or_else(|| TokenBucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+        self.refill(&mut bucket, now);
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.requests_per_sec,
+            ))
+        };
+        buckets.insert(key, bucket);
+        result
+    }
+
+    fn refill(&self, bucket: &mut TokenBucket, now: Instant) {
+        if now > bucket.last_refill {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.requests_per_sec).min(self.burst);
+            bucket.last_refill = now;
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Remembers the transactions recently submitted to this node by their hash, along with the
+//! response to their submission.
+//!
+//! Clients track the transactions by hash, while Mempool and Storage only know them by sender and
+//! sequence number. The transactions rejected at submission are remembered along with the response
+//! to their submission, as nothing else on the node knows about them. The resubmission of a
+//! transaction which was accepted doesn't change what is remembered about it, even if it is
+//! rejected, e.g. as underpriced because the transaction is already pending in Mempool.
+//!
+//! Clients retrying a submission within `response_ttl` get the same response again, without the
+//! transaction going through the VM validation and Mempool a second time. Only the responses
+//! which a resubmission would get again are returned: the transactions refused because Mempool or
+//! the node is full, or because their sender submits too often, could be accepted on a retry.
+//!
+//! At most `capacity` transactions are remembered at a time, the least recently used one being
+//! dropped first, and a transaction is forgotten `ttl` after its submission.
+
+use admission_control_proto::proto::admission_control::{
+    submit_transaction_response::Status, AdmissionControlStatusCode, SubmitTransactionResponse,
+};
+use crypto::{hash::CryptoHash, HashValue};
+use mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use ttl_cache::TtlCache;
+use types::{account_address::AccountAddress, transaction::SignedTransaction};
+
+/// Default number of transactions remembered at a time.
+pub const DEFAULT_SUBMISSION_CACHE_CAPACITY: usize = 100_000;
+/// Default time the transactions are remembered for after their submission.
+pub const DEFAULT_SUBMISSION_CACHE_TTL: Duration = Duration::from_secs(600);
+/// Default time the responses are returned again to the resubmissions for.
+pub const DEFAULT_RESPONSE_TTL: Duration = Duration::from_secs(10);
+
+/// A transaction submitted to the node.
+#[derive(Clone, Debug)]
+pub(crate) struct Submission {
+    pub sender: AccountAddress,
+    pub sequence_number: u64,
+    /// Response to the submission, if the transaction was rejected.
+    pub rejection: Option<SubmitTransactionResponse>,
+}
+
+struct Entry {
+    submission: Submission,
+    response: SubmitTransactionResponse,
+    responded_at: Instant,
+}
+
+/// Transactions recently submitted to the node, by hash. Clones share the same transactions.
+#[derive(Clone)]
+pub(crate) struct SubmissionCache {
+    entries: Arc<Mutex<TtlCache<HashValue, Entry>>>,
+    ttl: Duration,
+    response_ttl: Duration,
+}
+
+impl SubmissionCache {
+    pub(crate) fn new(capacity: usize, ttl: Duration, response_ttl: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(TtlCache::new(capacity))),
+            ttl,
+            response_ttl,
+        }
+    }
+
+    /// Remembers the submission of `signed_txn` and the `response` to it, unless `signed_txn` was
+    /// accepted before and `response` rejects it.
+    pub(crate) fn record(
+        &self,
+        signed_txn: &SignedTransaction,
+        response: &SubmitTransactionResponse,
+    ) {
+        let hash = signed_txn.hash();
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("[submission cache] acquire lock");
+        let is_accepted = is_accepted(response);
+        if !is_accepted {
+            if let Some(entry) = entries.get(&hash) {
+                if entry.submission.rejection.is_none() {
+                    return;
+                }
+            }
+        }
+        let entry = Entry {
+            submission: Submission {
+                sender: signed_txn.sender(),
+                sequence_number: signed_txn.sequence_number(),
+                rejection: if is_accepted {
+                    None
+                } else {
+                    Some(response.clone())
+                },
+            },
+            response: response.clone(),
+            responded_at: Instant::now(),
+        };
+        entries.insert(hash, entry, self.ttl);
+    }
+
+    pub(crate) fn get(&self, hash: &HashValue) -> Option<Submission> {
+        self.entries
+            .lock()
+            .expect("[submission cache] acquire lock")
+            .get(hash)
+            .map(|entry| entry.submission.clone())
+    }
+
+    /// Response to return again to a resubmission of the transaction with `hash`, if any.
+    pub(crate) fn get_response(&self, hash: &HashValue) -> Option<SubmitTransactionResponse> {
+        self.entries
+            .lock()
+            .expect("[submission cache] acquire lock")
+            .get(hash)
+            .filter(|entry| {
+                entry.responded_at.elapsed() < self.response_ttl && is_final(&entry.response)
+            })
+            .map(|entry| entry.response.clone())
+    }
+}
+
+fn is_accepted(response: &SubmitTransactionResponse) -> bool {
+    match &response.status {
+        Some(Status::AcStatus(status)) => status.code() == AdmissionControlStatusCode::Accepted,
+        _ => false,
+    }
+}
+
+/// Whether a resubmission of the transaction would get the same `response`.
+fn is_final(response: &SubmitTransactionResponse) -> bool {
+    match &response.status {
+        Some(Status::AcStatus(status)) => match status.code() {
+            AdmissionControlStatusCode::RateLimited
+            | AdmissionControlStatusCode::Overloaded
+            | AdmissionControlStatusCode::InternalError => false,
+            _ => true,
+        },
+        Some(Status::MempoolStatus(status)) => {
+            status.code() != MempoolAddTransactionStatusCode::MempoolIsFull
+        }
+        Some(Status::VmStatus(_)) => true,
+        None => false,
+    }
+}
@@ -3,22 +3,49 @@
 
 use crate::{
     admission_control_service::{
-        AdmissionControlService, SubmitTransactionRequest,
+        parse_peer_ip, submit_transaction_async_response, AdmissionControlService, EventsLost,
+        Filter, GetAccountStateRequest, GetGasPriceEstimateRequest, GetSubmissionResultRequest,
+        GetTransactionStatusByHashRequest, GetTransactionStatusRequest, HealthCheckRequest,
+        NodeBehind, SubmissionResultStatus, SubmitTransactionRequest,
         SubmitTransactionResponse as ProtoSubmitTransactionResponse,
+        SubmitTransactionsBatchRequest, SubscribeTransactionEventsRequest, TransactionEventType,
+        TransactionStatusCode, MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE,
     },
     mocks::local_mock_mempool::LocalMockMempool,
+    OP_COUNTERS,
 };
 use admission_control_proto::{AdmissionControlStatus, SubmitTransactionResponse};
+use assert_matches::assert_matches;
+use config::config::{
+    IdentityQuotaConfig, LoadSheddingConfig, PreValidationConfig, RateLimitConfig,
+};
 
-use crypto::{ed25519::*, test_utils::TEST_SEED};
-use mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
+use crypto::{ed25519::*, hash::CryptoHash, test_utils::TEST_SEED, HashValue};
+use disk_monitor::DiskMonitor;
+use futures::{Future, Stream};
+use grpcio::RpcStatusCode;
+use mempool_shared_proto::{
+    proto::mempool_status::{MempoolAddTransactionStatusCode, MempoolTransactionStatusCode},
+    GasPriceEstimate, MempoolAddTransactionStatus,
+};
 use rand::SeedableRng;
 use std::convert::TryFrom;
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 use storage_service::mocks::mock_storage_client::MockStorageReadClient;
+use trusted_ledger::TrustedLedger;
 use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
+    crypto_proxies::LedgerInfoWithSignatures,
+    ledger_info::LedgerInfo,
+    proto::types::UpdateToLatestLedgerRequest,
     test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::Version,
     vm_error::{StatusCode, VMStatus},
 };
 use vm_validator::mocks::mock_vm_validator::MockVMValidator;
@@ -29,6 +56,8 @@ pub fn create_ac_service_for_ut() -> AdmissionControlService<LocalMockMempool, M
         Arc::new(MockStorageReadClient),
         Arc::new(MockVMValidator),
         false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
     )
 }
 
@@ -184,3 +213,846 @@ fn test_submit_txn_inner_mempool() {
         MempoolAddTransactionStatusCode::MempoolIsFull,
     );
 }
+
+#[test]
+fn test_submit_txn_inner_disk_full() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    // No disk has that much space left.
+    let disk_monitor = DiskMonitor::new(std::env::temp_dir(), std::u64::MAX);
+    let ac_service = AdmissionControlService::new(
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        TrustedLedger::new(),
+        disk_monitor.clone(),
+    );
+    let mut req: SubmitTransactionRequest = SubmitTransactionRequest::default();
+    let keypair = compat::generate_keypair(&mut rng);
+    req.signed_txn = Some(
+        get_test_signed_txn(
+            AccountAddress::new([103; ADDRESS_LENGTH]),
+            0,
+            keypair.0,
+            keypair.1,
+            None,
+        )
+        .into(),
+    );
+
+    disk_monitor.check().unwrap();
+    let response = SubmitTransactionResponse::try_from(
+        ac_service.submit_transaction_inner(req.clone()).unwrap(),
+    )
+    .unwrap();
+    assert_matches!(
+        response.ac_status.unwrap(),
+        AdmissionControlStatus::Rejected(_)
+    );
+}
+
+#[test]
+fn test_update_to_latest_ledger_not_older_than_trusted() {
+    let trusted_ledger = TrustedLedger::new();
+    let ac_service = AdmissionControlService::new(
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        trusted_ledger.clone(),
+        DiskMonitor::default(),
+    );
+    let trust_version = |version| {
+        trusted_ledger.update(LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                version,
+                HashValue::zero(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                0,
+                None,
+            ),
+            HashMap::new(),
+        ))
+    };
+
+    // The mock storage responds as of version 7.
+    assert!(trust_version(7));
+    assert!(ac_service
+        .update_to_latest_ledger_inner(UpdateToLatestLedgerRequest::default())
+        .is_ok());
+    assert!(trust_version(8));
+    assert!(ac_service
+        .update_to_latest_ledger_inner(UpdateToLatestLedgerRequest::default())
+        .is_err());
+}
+
+/// AC service refusing the reads while the node lags more than 10 versions behind its peers,
+/// which advertised `network_version`
+fn create_ac_service_behind(
+    network_version: Version,
+) -> AdmissionControlService<LocalMockMempool, MockVMValidator> {
+    let trusted_ledger = TrustedLedger::new();
+    trusted_ledger.update_network_version(network_version);
+    AdmissionControlService::new(
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        trusted_ledger,
+        DiskMonitor::default(),
+    )
+    .with_max_sync_lag(10)
+}
+
+#[test]
+fn test_update_to_latest_ledger_node_behind() {
+    // The mock storage responds with a ledger info timestamped at the epoch, which doesn't make
+    // the node behind as long as its peers aren't ahead.
+    let ac_service = create_ac_service_behind(0);
+    assert!(ac_service
+        .update_to_latest_ledger_inner(UpdateToLatestLedgerRequest::default())
+        .is_ok());
+
+    // The mock storage responds as of version 7.
+    let ac_service = create_ac_service_behind(17);
+    assert!(ac_service
+        .update_to_latest_ledger_inner(UpdateToLatestLedgerRequest::default())
+        .is_ok());
+
+    let ac_service = create_ac_service_behind(1000);
+    let err = ac_service
+        .update_to_latest_ledger_inner(UpdateToLatestLedgerRequest::default())
+        .unwrap_err();
+    let node_behind = err.downcast_ref::<NodeBehind>().unwrap();
+    assert_eq!(node_behind.version, 7);
+    assert_eq!(node_behind.sync_lag, 993);
+}
+
+#[test]
+fn test_get_account_state() {
+    let ac_service = create_ac_service_for_ut();
+    let address = AccountAddress::new([103; ADDRESS_LENGTH]);
+    let mut req = GetAccountStateRequest::default();
+    req.address = address.to_vec();
+    let response = admission_control_proto::GetAccountStateResponse::try_from(
+        ac_service.get_account_state_inner(req.clone()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(response.ledger_info_with_sigs.ledger_info().version(), 7);
+    assert!(response.account_state_with_proof.blob.is_some());
+
+    // the reads are refused while the node is behind, as UpdateToLatestLedger ones are
+    let err = create_ac_service_behind(1000)
+        .get_account_state_inner(req)
+        .unwrap_err();
+    assert!(err.downcast_ref::<NodeBehind>().is_some());
+
+    let mut req = GetAccountStateRequest::default();
+    req.address = vec![1, 2, 3];
+    assert!(ac_service.get_account_state_inner(req).is_err());
+}
+
+#[test]
+fn test_get_gas_price_estimate() {
+    let ac_service = create_ac_service_for_ut();
+    let response = ac_service
+        .get_gas_price_estimate_inner(GetGasPriceEstimateRequest::default())
+        .unwrap();
+    let estimate = GasPriceEstimate::from(response.estimate.unwrap());
+    assert_eq!(estimate.next_block_gas_price, 6);
+    assert_eq!(estimate.mempool.p90, 5);
+}
+
+#[test]
+fn test_get_transaction_status() {
+    let ac_service = create_ac_service_for_ut();
+    let status = |sender: [u8; ADDRESS_LENGTH]| {
+        let mut req = GetTransactionStatusRequest::default();
+        req.sender = sender.to_vec();
+        ac_service
+            .get_transaction_status_inner(req)
+            .unwrap()
+            .status()
+    };
+    assert_eq!(
+        status([103; ADDRESS_LENGTH]),
+        MempoolTransactionStatusCode::Pending
+    );
+    assert_eq!(
+        status([105; ADDRESS_LENGTH]),
+        MempoolTransactionStatusCode::Expired
+    );
+    assert_eq!(
+        status([1; ADDRESS_LENGTH]),
+        MempoolTransactionStatusCode::Unknown
+    );
+}
+
+#[test]
+fn test_submit_transactions_batch() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let keypair = compat::generate_keypair(&mut rng);
+    let txn_req = |sender: [u8; ADDRESS_LENGTH]| {
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(
+            get_test_signed_txn(
+                AccountAddress::new(sender),
+                0,
+                keypair.0.clone(),
+                keypair.1.clone(),
+                None,
+            )
+            .into(),
+        );
+        req
+    };
+    let mut req = SubmitTransactionsBatchRequest::default();
+    req.transactions = vec![
+        txn_req([103; ADDRESS_LENGTH]),
+        txn_req([0; ADDRESS_LENGTH]),
+        txn_req([100; ADDRESS_LENGTH]),
+        SubmitTransactionRequest::default(),
+        txn_req([103; ADDRESS_LENGTH]),
+        txn_req([7; ADDRESS_LENGTH]),
+    ];
+    let responses: Vec<_> = ac_service
+        .submit_transactions_batch_inner(req)
+        .unwrap()
+        .responses
+        .into_iter()
+        .map(|response| SubmitTransactionResponse::try_from(response).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 6);
+    // each response is the same as if the transaction was submitted on its own
+    assert_eq!(
+        responses[0].ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+    assert_eq!(
+        responses[1].vm_error.as_ref().unwrap().major_status,
+        StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST
+    );
+    assert_eq!(
+        responses[2].mempool_error.as_ref().unwrap().code,
+        MempoolAddTransactionStatusCode::InsufficientBalance
+    );
+    assert_matches!(
+        responses[3].ac_status,
+        Some(AdmissionControlStatus::Rejected(_))
+    );
+    assert_eq!(
+        responses[4].ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+    // a transaction which fails to be validated doesn't fail the batch
+    assert_matches!(
+        responses[5].ac_status,
+        Some(AdmissionControlStatus::InternalError(_))
+    );
+}
+
+#[test]
+fn test_submit_transactions_batch_too_large() {
+    let ac_service = create_ac_service_for_ut();
+    let mut req = SubmitTransactionsBatchRequest::default();
+    req.transactions =
+        vec![SubmitTransactionRequest::default(); MAX_SUBMIT_TRANSACTIONS_BATCH_SIZE + 1];
+    assert!(ac_service.submit_transactions_batch_inner(req).is_err());
+}
+
+#[test]
+fn test_get_transaction_status_by_hash() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let keypair = compat::generate_keypair(&mut rng);
+    // submits a transaction and returns its hash
+    let submit = |sender: [u8; ADDRESS_LENGTH]| {
+        let signed_txn = get_test_signed_txn(
+            AccountAddress::new(sender),
+            0,
+            keypair.0.clone(),
+            keypair.1.clone(),
+            None,
+        );
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(signed_txn.clone().into());
+        ac_service.submit_transaction_inner(req).unwrap();
+        signed_txn.hash()
+    };
+    let status_by_hash = |hash: HashValue| {
+        let mut req = GetTransactionStatusByHashRequest::default();
+        req.hash = hash.to_vec();
+        ac_service
+            .get_transaction_status_by_hash_inner(req)
+            .unwrap()
+    };
+
+    // accepted and waiting in mempool
+    let response = status_by_hash(submit([103; ADDRESS_LENGTH]));
+    assert_eq!(response.status(), TransactionStatusCode::TxnPending);
+
+    // rejected by mempool at submission
+    let response = status_by_hash(submit([100; ADDRESS_LENGTH]));
+    assert_eq!(response.status(), TransactionStatusCode::TxnRejected);
+    let rejection = SubmitTransactionResponse::try_from(response.rejection.unwrap()).unwrap();
+    assert_eq!(
+        rejection.mempool_error.unwrap().code,
+        MempoolAddTransactionStatusCode::InsufficientBalance
+    );
+
+    // rejected by the VM at submission
+    let response = status_by_hash(submit([0; ADDRESS_LENGTH]));
+    assert_eq!(response.status(), TransactionStatusCode::TxnRejected);
+    let rejection = SubmitTransactionResponse::try_from(response.rejection.unwrap()).unwrap();
+    assert_eq!(
+        rejection.vm_error.unwrap().major_status,
+        StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST
+    );
+
+    // accepted, then expired in mempool without being committed
+    let response = status_by_hash(submit([105; ADDRESS_LENGTH]));
+    assert_eq!(response.status(), TransactionStatusCode::TxnExpired);
+
+    // never submitted
+    let response = status_by_hash(HashValue::random());
+    assert_eq!(response.status(), TransactionStatusCode::TxnUnknown);
+
+    let mut req = GetTransactionStatusByHashRequest::default();
+    req.hash = vec![1, 2, 3];
+    assert!(ac_service
+        .get_transaction_status_by_hash_inner(req)
+        .is_err());
+}
+
+#[test]
+fn test_resubmission_of_pending_txn() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let keypair = compat::generate_keypair(&mut rng);
+    let signed_txn = get_test_signed_txn(
+        AccountAddress::new([103; ADDRESS_LENGTH]),
+        0,
+        keypair.0,
+        keypair.1,
+        None,
+    );
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(signed_txn.clone().into());
+    let response = ac_service.submit_transaction_inner(req).unwrap();
+
+    // a resubmission of the pending transaction is rejected by mempool as underpriced, which
+    // doesn't make it rejected
+    let underpriced = SubmitTransactionResponse {
+        ac_status: None,
+        mempool_error: Some(MempoolAddTransactionStatus::new(
+            MempoolAddTransactionStatusCode::Underpriced,
+            String::new(),
+        )),
+        vm_error: None,
+        validator_id: vec![],
+    };
+    ac_service
+        .submissions
+        .record(&signed_txn, &underpriced.into());
+    let mut req = GetTransactionStatusByHashRequest::default();
+    req.hash = signed_txn.hash().to_vec();
+    let status = ac_service
+        .get_transaction_status_by_hash_inner(req)
+        .unwrap();
+    assert_eq!(status.status(), TransactionStatusCode::TxnPending);
+    assert_eq!(
+        ac_service.submissions.get_response(&signed_txn.hash()),
+        Some(response)
+    );
+}
+
+#[test]
+fn test_subscribe_transaction_events() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let events = |filter: Filter| {
+        let mut req = SubscribeTransactionEventsRequest::default();
+        req.filter = Some(filter);
+        let mut events = ac_service
+            .subscribe_transaction_events_inner(req)
+            .unwrap()
+            .wait()
+            .collect::<Vec<_>>();
+        // the stream fails once Mempool ends it, as the events after that would be lost
+        let lost = events.pop().unwrap().unwrap_err();
+        assert!(lost.downcast_ref::<EventsLost>().is_some());
+        events
+            .into_iter()
+            .map(|event| {
+                let event = event.unwrap();
+                (event.event_type(), event.sequence_number)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // all the transactions of an account
+    assert_eq!(
+        events(Filter::Sender(vec![103; ADDRESS_LENGTH])),
+        vec![
+            (TransactionEventType::EventAccepted, 0),
+            (TransactionEventType::EventBroadcast, 0),
+            (TransactionEventType::EventCommitted, 0),
+            (TransactionEventType::EventAccepted, 1),
+        ]
+    );
+
+    // a transaction submitted to the node
+    let keypair = compat::generate_keypair(&mut rng);
+    let signed_txn = get_test_signed_txn(
+        AccountAddress::new([105; ADDRESS_LENGTH]),
+        0,
+        keypair.0,
+        keypair.1,
+        None,
+    );
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(signed_txn.clone().into());
+    ac_service.submit_transaction_inner(req).unwrap();
+    assert_eq!(
+        events(Filter::Hash(signed_txn.hash().to_vec())),
+        vec![
+            (TransactionEventType::EventAccepted, 0),
+            (TransactionEventType::EventExpired, 0),
+        ]
+    );
+
+    // transactions which were not submitted to the node can't be followed by hash
+    let mut req = SubscribeTransactionEventsRequest::default();
+    req.filter = Some(Filter::Hash(HashValue::random().to_vec()));
+    assert!(ac_service.subscribe_transaction_events_inner(req).is_err());
+    let req = SubscribeTransactionEventsRequest::default();
+    assert!(ac_service.subscribe_transaction_events_inner(req).is_err());
+}
+
+#[test]
+fn test_submit_txn_rate_limited() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let rate_limit = RateLimitConfig {
+        requests_per_sec: 1,
+        burst: 1,
+    };
+    let ac_service = create_ac_service_for_ut()
+        .with_sender_rate_limit(&rate_limit)
+        .with_ip_rate_limit(&rate_limit);
+    let keypair = compat::generate_keypair(&mut rng);
+    let submit = |sender: u8, client: Option<IpAddr>| {
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(
+            get_test_signed_txn(
+                AccountAddress::new([sender; ADDRESS_LENGTH]),
+                0,
+                keypair.0.clone(),
+                keypair.1.clone(),
+                None,
+            )
+            .into(),
+        );
+        SubmitTransactionResponse::try_from(
+            ac_service.submit_transaction_from(req, client).unwrap(),
+        )
+        .unwrap()
+        .ac_status
+    };
+
+    // per sender
+    assert_eq!(submit(103, None), Some(AdmissionControlStatus::Accepted));
+    assert_matches!(
+        submit(103, None),
+        Some(AdmissionControlStatus::RateLimited(retry_after)) if retry_after > Duration::from_millis(0)
+    );
+
+    // per client, whichever the sender
+    let client = "10.0.0.1".parse().ok();
+    assert_eq!(submit(106, client), Some(AdmissionControlStatus::Accepted));
+    assert_matches!(
+        submit(107, client),
+        Some(AdmissionControlStatus::RateLimited(_))
+    );
+    let other_client = "10.0.0.2".parse().ok();
+    assert_eq!(
+        submit(107, other_client),
+        Some(AdmissionControlStatus::Accepted)
+    );
+
+    // transactions with an invalid signature don't take the tokens of their sender
+    let other_keypair = compat::generate_keypair(&mut rng);
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(
+        get_test_signed_txn(
+            AccountAddress::new([108; ADDRESS_LENGTH]),
+            0,
+            other_keypair.0.clone(),
+            keypair.1.clone(),
+            None,
+        )
+        .into(),
+    );
+    assert_eq!(
+        SubmitTransactionResponse::try_from(ac_service.submit_transaction_inner(req).unwrap())
+            .unwrap()
+            .ac_status,
+        Some(AdmissionControlStatus::Rejected(
+            "Invalid signature".to_string()
+        ))
+    );
+    assert_eq!(submit(108, None), Some(AdmissionControlStatus::Accepted));
+}
+
+#[test]
+fn test_submit_txn_duplicate() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let keypair = compat::generate_keypair(&mut rng);
+    // submits a transaction and returns its hash along with the response
+    let submit = |sender: u8| {
+        let signed_txn = get_test_signed_txn(
+            AccountAddress::new([sender; ADDRESS_LENGTH]),
+            0,
+            keypair.0.clone(),
+            keypair.1.clone(),
+            None,
+        );
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(signed_txn.clone().into());
+        let response = ac_service.submit_transaction_inner(req).unwrap();
+        (signed_txn.hash(), response)
+    };
+
+    // the responses which a resubmission would get again are remembered and returned again
+    let (hash, response) = submit(103);
+    assert_eq!(
+        ac_service.submissions.get_response(&hash),
+        Some(response.clone())
+    );
+    assert_eq!(submit(103).1, response);
+    let (hash, response) = submit(0);
+    assert_eq!(
+        ac_service.submissions.get_response(&hash),
+        Some(response.clone())
+    );
+    assert_eq!(submit(0).1, response);
+
+    // mempool may have room for the transaction on a retry
+    let (hash, response) = submit(104);
+    assert_eq!(
+        SubmitTransactionResponse::try_from(response)
+            .unwrap()
+            .mempool_error
+            .unwrap()
+            .code,
+        MempoolAddTransactionStatusCode::MempoolIsFull
+    );
+    assert!(ac_service.submissions.get_response(&hash).is_none());
+}
+
+#[test]
+fn test_submit_txn_pre_validation() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut().with_pre_validation(&PreValidationConfig {
+        max_transaction_size_bytes: Some(10),
+        ..PreValidationConfig::default()
+    });
+    let keypair = compat::generate_keypair(&mut rng);
+    // the VM would reject the transaction of this sender as well, but it is not asked to
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(
+        get_test_signed_txn(
+            AccountAddress::new([0; ADDRESS_LENGTH]),
+            0,
+            keypair.0,
+            keypair.1,
+            None,
+        )
+        .into(),
+    );
+    let response =
+        SubmitTransactionResponse::try_from(ac_service.submit_transaction_inner(req).unwrap())
+            .unwrap();
+    assert_matches!(
+        response.ac_status,
+        Some(AdmissionControlStatus::Rejected(_))
+    );
+}
+
+#[test]
+fn test_submit_txn_async() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut().with_async_submission(1, 10);
+    let keypair = compat::generate_keypair(&mut rng);
+    // submits the transaction of `sender` and polls for the result of its submission
+    let submit = |sender: u8| {
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(
+            get_test_signed_txn(
+                AccountAddress::new([sender; ADDRESS_LENGTH]),
+                0,
+                keypair.0.clone(),
+                keypair.1.clone(),
+                None,
+            )
+            .into(),
+        );
+        let ticket = match ac_service
+            .submit_transaction_async_inner(req, None)
+            .unwrap()
+            .result
+        {
+            Some(submit_transaction_async_response::Result::Ticket(ticket)) => ticket,
+            result => panic!("Expected a ticket, got {:?}", result),
+        };
+        let mut req = GetSubmissionResultRequest::default();
+        req.ticket = ticket;
+        let start = Instant::now();
+        loop {
+            let response = ac_service.get_submission_result_inner(req.clone()).unwrap();
+            if response.status() != SubmissionResultStatus::SubmissionPending {
+                assert_eq!(response.status(), SubmissionResultStatus::SubmissionDone);
+                return SubmitTransactionResponse::try_from(response.response.unwrap()).unwrap();
+            }
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    assert_eq!(
+        submit(103).ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+    assert_eq!(
+        submit(0).vm_error.unwrap().major_status,
+        StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST
+    );
+
+    // invalid transactions are answered right away
+    let response = ac_service
+        .submit_transaction_async_inner(SubmitTransactionRequest::default(), None)
+        .unwrap();
+    assert_matches!(
+        response.result,
+        Some(submit_transaction_async_response::Result::Response(_))
+    );
+
+    let mut req = GetSubmissionResultRequest::default();
+    req.ticket = HashValue::random().to_vec();
+    assert_eq!(
+        ac_service
+            .get_submission_result_inner(req)
+            .unwrap()
+            .status(),
+        SubmissionResultStatus::SubmissionUnknown
+    );
+}
+
+#[test]
+fn test_submit_txn_status_counters() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let ac_service = create_ac_service_for_ut();
+    let keypair = compat::generate_keypair(&mut rng);
+    // other tests submit concurrently, so the counters only grow
+    let count = |status: &str| {
+        OP_COUNTERS
+            .counter(&format!("submit_txn.status.{}", status))
+            .get()
+    };
+    let rejected = count("vm.SENDING_ACCOUNT_DOES_NOT_EXIST");
+    let mempool_full = count("mempool.MempoolIsFull");
+    for (sender, sequence_number) in &[(0, 0), (104, 0), (104, 1)] {
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(
+            get_test_signed_txn(
+                AccountAddress::new([*sender; ADDRESS_LENGTH]),
+                *sequence_number,
+                keypair.0.clone(),
+                keypair.1.clone(),
+                None,
+            )
+            .into(),
+        );
+        ac_service.submit_transaction_inner(req).unwrap();
+    }
+    assert!(count("vm.SENDING_ACCOUNT_DOES_NOT_EXIST") > rejected);
+    assert!(count("mempool.MempoolIsFull") >= mempool_full + 2);
+}
+
+#[test]
+fn test_submit_txn_load_shedding() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let keypair = compat::generate_keypair(&mut rng);
+    let priority_sender = AccountAddress::from_public_key(&keypair.1);
+    // the key of this sender is not the one signing the transactions below
+    let impersonated_sender = AccountAddress::new([103; ADDRESS_LENGTH]);
+    // every transaction is over the limit of the senders without priority
+    let ac_service = create_ac_service_for_ut().with_load_shedding(&LoadSheddingConfig {
+        max_in_flight: 0,
+        max_in_flight_priority: 1,
+        retry_after_ms: 100,
+        priority_senders: vec![
+            hex::encode(priority_sender),
+            hex::encode(impersonated_sender),
+        ],
+    });
+    let submit = |sender: AccountAddress| {
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn =
+            Some(get_test_signed_txn(sender, 0, keypair.0.clone(), keypair.1.clone(), None).into());
+        SubmitTransactionResponse::try_from(ac_service.submit_transaction_inner(req).unwrap())
+            .unwrap()
+    };
+
+    assert_eq!(
+        submit(AccountAddress::new([50; ADDRESS_LENGTH])).ac_status,
+        Some(AdmissionControlStatus::Overloaded(Duration::from_millis(
+            100
+        )))
+    );
+    assert_eq!(
+        submit(impersonated_sender).ac_status,
+        Some(AdmissionControlStatus::Overloaded(Duration::from_millis(
+            100
+        )))
+    );
+    assert_eq!(
+        submit(priority_sender).ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+}
+
+#[test]
+fn test_submit_transactions_batch_load_shedding() {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    // a batch takes a single slot, however many transactions it holds
+    let ac_service = create_ac_service_for_ut().with_load_shedding(&LoadSheddingConfig {
+        max_in_flight: 1,
+        max_in_flight_priority: 1,
+        retry_after_ms: 100,
+        priority_senders: vec![],
+    });
+    let keypair = compat::generate_keypair(&mut rng);
+    let mut req = SubmitTransactionsBatchRequest::default();
+    req.transactions = (100..105)
+        .map(|sender| {
+            let mut txn_req = SubmitTransactionRequest::default();
+            txn_req.signed_txn = Some(
+                get_test_signed_txn(
+                    AccountAddress::new([sender; ADDRESS_LENGTH]),
+                    0,
+                    keypair.0.clone(),
+                    keypair.1.clone(),
+                    None,
+                )
+                .into(),
+            );
+            txn_req
+        })
+        .collect();
+    let responses = ac_service
+        .submit_transactions_batch_inner(req)
+        .unwrap()
+        .responses;
+    assert_eq!(responses.len(), 5);
+    for response in responses {
+        assert_eq!(
+            SubmitTransactionResponse::try_from(response)
+                .unwrap()
+                .ac_status,
+            Some(AdmissionControlStatus::Accepted)
+        );
+    }
+}
+
+#[test]
+fn test_health_check_timeout() {
+    let ac_service = create_ac_service_for_ut();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    // a hung component is unhealthy
+    let result = ac_service.run_health_check("hung", move || {
+        let _ = release_rx.recv();
+        Ok(String::new())
+    });
+    assert!(result.is_err());
+    // and isn't checked again until its previous check completes
+    let result = ac_service.run_health_check("hung", || Ok(String::new()));
+    assert!(result.is_err());
+
+    drop(release_tx);
+    let start = Instant::now();
+    while ac_service
+        .run_health_check("hung", || Ok("done".to_string()))
+        .is_err()
+    {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_health_check() {
+    let ac_service = create_ac_service_for_ut().with_network_health(Arc::new(|| 0));
+    let response = ac_service
+        .health_check_inner(HealthCheckRequest::default())
+        .unwrap();
+    let components: HashMap<_, _> = response
+        .components
+        .into_iter()
+        .map(|component| (component.name.clone(), component))
+        .collect();
+    // the mock mempool goes through an unhealthy period, so only its presence is checked
+    assert!(components.contains_key("mempool"));
+    assert!(components["storage"].is_healthy);
+    assert!(!components.contains_key("vm_validator"));
+    assert!(!components["network"].is_healthy);
+    assert_eq!(components["network"].message, "No connected peers");
+    assert!(!response.is_healthy);
+
+    let ac_service = ac_service.with_network_health(Arc::new(|| 3));
+    let response = ac_service
+        .health_check_inner(HealthCheckRequest::default())
+        .unwrap();
+    let network = response
+        .components
+        .iter()
+        .find(|component| component.name == "network")
+        .unwrap();
+    assert!(network.is_healthy);
+    assert_eq!(network.message, "3 connected peers");
+}
+
+#[test]
+fn test_identity_quota() {
+    let ac_service = create_ac_service_for_ut();
+    assert!(ac_service.acquire_quota().unwrap().is_none());
+
+    let ac_service = ac_service.with_identity_quota(
+        "exchange",
+        &IdentityQuotaConfig {
+            rate_limit: None,
+            max_in_flight: Some(1),
+        },
+    );
+    let quota = ac_service.acquire_quota().unwrap();
+    assert!(quota.is_some());
+    assert_eq!(
+        ac_service.acquire_quota().err().unwrap().status,
+        RpcStatusCode::RESOURCE_EXHAUSTED
+    );
+    drop(quota);
+    assert!(ac_service.acquire_quota().is_ok());
+}
+
+#[test]
+fn test_parse_peer_ip() {
+    assert_eq!(
+        parse_peer_ip("ipv4:127.0.0.1:50051"),
+        "127.0.0.1".parse().ok()
+    );
+    assert_eq!(parse_peer_ip("ipv6:[::1]:50051"), "::1".parse().ok());
+    assert_eq!(parse_peer_ip("unix:/tmp/ac.sock"), None);
+}
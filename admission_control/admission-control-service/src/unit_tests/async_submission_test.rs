@@ -0,0 +1,49 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::async_submission::AsyncSubmitter;
+use admission_control_proto::proto::admission_control::SubmitTransactionResponse;
+use crypto::HashValue;
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn test_queue_full() {
+    // without workers, the submissions stay queued
+    let submitter = AsyncSubmitter::new(0, 1, 10, Duration::from_secs(600));
+    let ticket = HashValue::random();
+    assert!(submitter.submit(ticket, || Ok(SubmitTransactionResponse::default())));
+    assert_eq!(submitter.result(&ticket), Some(None));
+    // the same ticket is not queued twice
+    assert!(submitter.submit(ticket, || Ok(SubmitTransactionResponse::default())));
+    assert!(!submitter.submit(HashValue::random(), || Ok(
+        SubmitTransactionResponse::default()
+    )));
+    assert_eq!(submitter.result(&HashValue::random()), None);
+}
+
+#[test]
+fn test_result() {
+    let submitter = AsyncSubmitter::new(1, 10, 10, Duration::from_secs(600));
+    let (sender, receiver) = mpsc::channel();
+    let ticket = HashValue::random();
+    assert!(submitter.submit(ticket, move || {
+        receiver.recv().unwrap();
+        Err("failed".to_string())
+    }));
+    assert_eq!(submitter.result(&ticket), Some(None));
+
+    sender.send(()).unwrap();
+    let start = Instant::now();
+    while submitter.result(&ticket) == Some(None) {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        submitter.result(&ticket),
+        Some(Some(Err("failed".to_string())))
+    );
+}
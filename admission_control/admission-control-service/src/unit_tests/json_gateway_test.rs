@@ -0,0 +1,174 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    admission_control_service::AdmissionControlService,
+    json_gateway::{
+        JsonGateway, JsonHealthResponse, JsonSubmitTransactionResponse,
+        JsonTransactionStatusResponse, MAX_REQUEST_BODY_BYTES,
+    },
+    mocks::local_mock_mempool::LocalMockMempool,
+};
+use config::config::IdentityQuotaConfig;
+use crypto::{ed25519::*, test_utils::TEST_SEED};
+use disk_monitor::DiskMonitor;
+use futures::{Future, Stream};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use rand::SeedableRng;
+use std::{net::IpAddr, sync::Arc};
+use storage_service::mocks::mock_storage_client::MockStorageReadClient;
+use trusted_ledger::TrustedLedger;
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    proto::types::SignedTransaction,
+    test_helpers::transaction_test_helpers::get_test_signed_txn,
+};
+use vm_validator::mocks::mock_vm_validator::MockVMValidator;
+
+fn create_service() -> AdmissionControlService<LocalMockMempool, MockVMValidator> {
+    AdmissionControlService::new(
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
+    )
+}
+
+fn create_gateway() -> JsonGateway<LocalMockMempool, MockVMValidator> {
+    JsonGateway::new(create_service())
+}
+
+fn submit_body(sender: [u8; ADDRESS_LENGTH]) -> Vec<u8> {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let keypair = compat::generate_keypair(&mut rng);
+    let signed_txn: SignedTransaction =
+        get_test_signed_txn(AccountAddress::new(sender), 0, keypair.0, keypair.1, None).into();
+    format!(
+        r#"{{"signed_txn": "{}"}}"#,
+        hex::encode(signed_txn.signed_txn)
+    )
+    .into_bytes()
+}
+
+fn into_json<T: serde::de::DeserializeOwned>(response: Response<Body>) -> T {
+    let body = response.into_body().concat2().wait().unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn test_submit_transaction() {
+    let gateway = create_gateway();
+
+    let response = gateway.handle(
+        &Method::POST,
+        "/v1/transactions",
+        &submit_body([103; ADDRESS_LENGTH]),
+        None,
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        into_json::<JsonSubmitTransactionResponse>(response),
+        JsonSubmitTransactionResponse {
+            status: "accepted".to_string(),
+            code: None,
+            message: None,
+        }
+    );
+
+    let response = gateway.handle(
+        &Method::POST,
+        "/v1/transactions",
+        &submit_body([100; ADDRESS_LENGTH]),
+        None,
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+    let response: JsonSubmitTransactionResponse = into_json(response);
+    assert_eq!(response.status, "mempool_error");
+    assert_eq!(response.code, Some("InsufficientBalance".to_string()));
+}
+
+#[test]
+fn test_malformed_requests() {
+    let gateway = create_gateway();
+    let response = gateway.handle(&Method::POST, "/v1/transactions", b"{\"txn\": 1}", None);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = gateway.handle(
+        &Method::POST,
+        "/v1/transactions",
+        b"{\"signed_txn\": \"not hex\"}",
+        None,
+    );
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = gateway.handle(&Method::GET, "/v1/transactions/00/0/status", &[], None);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = gateway.handle(&Method::GET, "/v1/accounts", &[], None);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_get_transaction_status() {
+    let gateway = create_gateway();
+    let status = |sender: [u8; ADDRESS_LENGTH]| {
+        let path = format!("/v1/transactions/{}/0/status", hex::encode(sender));
+        let response = gateway.handle(&Method::GET, &path, &[], None);
+        assert_eq!(response.status(), StatusCode::OK);
+        into_json::<JsonTransactionStatusResponse>(response).status
+    };
+    assert_eq!(status([103; ADDRESS_LENGTH]), "Pending");
+    assert_eq!(status([105; ADDRESS_LENGTH]), "Expired");
+    assert_eq!(status([1; ADDRESS_LENGTH]), "Unknown");
+}
+
+#[test]
+fn test_health_check() {
+    let gateway = JsonGateway::new(create_service().with_network_health(Arc::new(|| 0)));
+    let response = gateway.handle(&Method::GET, "/v1/health", &[], None);
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let health: JsonHealthResponse = into_json(response);
+    assert!(!health.is_healthy);
+    let network = health
+        .components
+        .iter()
+        .find(|component| component.name == "network")
+        .unwrap();
+    assert!(!network.is_healthy);
+    assert_eq!(network.message, Some("No connected peers".to_string()));
+}
+
+#[test]
+fn test_body_size_limit() {
+    let gateway = create_gateway();
+    let client: IpAddr = "127.0.0.1".parse().unwrap();
+
+    let request = Request::post("/v1/transactions")
+        .body(Body::from(vec![b' '; MAX_REQUEST_BODY_BYTES + 1]))
+        .unwrap();
+    let response = gateway.serve(request, client).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let request = Request::post("/v1/transactions")
+        .body(Body::from(submit_body([103; ADDRESS_LENGTH])))
+        .unwrap();
+    let response = gateway.serve(request, client).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_quota() {
+    let quota = IdentityQuotaConfig {
+        rate_limit: None,
+        max_in_flight: Some(0),
+    };
+    let gateway = JsonGateway::new(create_service().with_identity_quota("test", &quota));
+    let response = gateway.handle(
+        &Method::POST,
+        "/v1/transactions",
+        &submit_body([103; ADDRESS_LENGTH]),
+        None,
+    );
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = gateway.handle(&Method::GET, "/v1/health", &[], None);
+    assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,36 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::load_shedder::LoadShedder;
+use config::config::LoadSheddingConfig;
+use std::time::Duration;
+use types::account_address::{AccountAddress, ADDRESS_LENGTH};
+
+#[test]
+fn test_priority_senders() {
+    let priority_sender = AccountAddress::new([1; ADDRESS_LENGTH]);
+    let other_sender = AccountAddress::new([2; ADDRESS_LENGTH]);
+    let shedder = LoadShedder::new(&LoadSheddingConfig {
+        max_in_flight: 1,
+        max_in_flight_priority: 2,
+        retry_after_ms: 100,
+        priority_senders: vec![hex::encode(priority_sender)],
+    });
+
+    assert!(shedder.is_priority_sender(&priority_sender));
+    assert!(!shedder.is_priority_sender(&other_sender));
+
+    let first = shedder.try_admit(false).unwrap();
+    assert_eq!(
+        shedder.try_admit(false).err(),
+        Some(Duration::from_millis(100))
+    );
+    // priority senders still get through, up to their own limit
+    let _second = shedder.try_admit(true).unwrap();
+    assert!(shedder.try_admit(true).is_err());
+    assert_eq!(shedder.in_flight(), 2);
+
+    drop(first);
+    assert_eq!(shedder.in_flight(), 1);
+    assert!(shedder.try_admit(true).is_ok());
+}
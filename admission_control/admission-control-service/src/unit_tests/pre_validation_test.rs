@@ -0,0 +1,141 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::pre_validation::{check_name, PreValidationError, PreValidator};
+use config::config::PreValidationConfig;
+use crypto::{ed25519::*, test_utils::TEST_SEED, HashValue};
+use failure::prelude::*;
+use mempool::StatelessValidationError;
+use rand::SeedableRng;
+use std::time::{SystemTime, UNIX_EPOCH};
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    test_helpers::transaction_test_helpers::{get_test_signed_transaction, placeholder_script},
+    transaction::{Script, SignedTransaction},
+};
+
+fn signed_txn(script: Script, gas_unit_price: u64) -> SignedTransaction {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let (private_key, public_key) = compat::generate_keypair(&mut rng);
+    let expiration_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 10;
+    get_test_signed_transaction(
+        AccountAddress::new([103; ADDRESS_LENGTH]),
+        0,
+        private_key,
+        public_key,
+        Some(script),
+        expiration_time,
+        gas_unit_price,
+        None,
+    )
+}
+
+/// The error `result` failed with, if it is one of the stateless validations of Mempool.
+fn validation_error(result: Result<()>) -> Option<StatelessValidationError> {
+    result.err().map(|e| {
+        e.downcast::<StatelessValidationError>()
+            .expect("not a stateless validation error")
+    })
+}
+
+#[test]
+fn test_no_check_enabled() {
+    let pre_validator = PreValidator::default();
+    let txn = signed_txn(Script::new(vec![0; 10_000], vec![]), std::u64::MAX);
+    assert!(pre_validator.check("", &txn).is_ok());
+}
+
+#[test]
+fn test_chain_id() {
+    let pre_validator = PreValidator::new(PreValidationConfig {
+        chain_id: Some("testnet".to_string()),
+        ..PreValidationConfig::default()
+    });
+    let txn = signed_txn(placeholder_script(), 0);
+    assert!(pre_validator.check("testnet", &txn).is_ok());
+    for chain_id in &["mainnet", ""] {
+        let error = pre_validator.check(chain_id, &txn).unwrap_err();
+        assert_eq!(check_name(&error), "wrong_chain");
+        assert_eq!(
+            error.downcast::<PreValidationError>().unwrap(),
+            PreValidationError::WrongChain {
+                chain_id: chain_id.to_string(),
+                expected: "testnet".to_string(),
+            }
+        );
+    }
+}
+
+#[test]
+fn test_max_transaction_size() {
+    let pre_validator = PreValidator::new(PreValidationConfig {
+        max_transaction_size_bytes: Some(1_000),
+        ..PreValidationConfig::default()
+    });
+    assert!(pre_validator
+        .check("", &signed_txn(placeholder_script(), 0))
+        .is_ok());
+    // the public key and the signature count towards the size
+    let txn = signed_txn(Script::new(vec![0; 850], vec![]), 0);
+    assert!(txn.raw_txn_bytes_len() <= 1_000);
+    assert_eq!(
+        validation_error(pre_validator.check("", &txn)),
+        Some(StatelessValidationError::TooLarge {
+            size: txn.signed_txn_bytes_len(),
+            max: 1_000,
+        })
+    );
+}
+
+#[test]
+fn test_max_gas_unit_price() {
+    let pre_validator = PreValidator::new(PreValidationConfig {
+        max_gas_unit_price: Some(100),
+        ..PreValidationConfig::default()
+    });
+    assert!(pre_validator
+        .check("", &signed_txn(placeholder_script(), 100))
+        .is_ok());
+    assert_eq!(
+        validation_error(pre_validator.check("", &signed_txn(placeholder_script(), 101))),
+        Some(StatelessValidationError::GasUnitPriceTooHigh {
+            price: 101,
+            max: 100,
+        })
+    );
+}
+
+#[test]
+fn test_allowed_and_denied_scripts() {
+    let allowed_script = Script::new(vec![1, 2, 3], vec![]);
+    let allowed_hash = HashValue::from_sha3_256(allowed_script.code());
+    let other_script = Script::new(vec![4, 5, 6], vec![]);
+    let other_hash = HashValue::from_sha3_256(other_script.code());
+
+    let mut config = PreValidationConfig::default();
+    config.allowed_scripts.insert(*allowed_hash.as_ref());
+    let pre_validator = PreValidator::new(config.clone());
+    assert!(pre_validator
+        .check("", &signed_txn(allowed_script.clone(), 0))
+        .is_ok());
+    assert_eq!(
+        validation_error(pre_validator.check("", &signed_txn(other_script.clone(), 0))),
+        Some(StatelessValidationError::ScriptNotAllowed(other_hash))
+    );
+
+    // denied scripts are rejected even if they are allowed
+    config.denied_scripts.insert(*allowed_hash.as_ref());
+    let pre_validator = PreValidator::new(config);
+    let error = pre_validator
+        .check("", &signed_txn(allowed_script, 0))
+        .unwrap_err();
+    assert_eq!(check_name(&error), "script_denied");
+    assert_eq!(
+        validation_error(Err(error)),
+        Some(StatelessValidationError::ScriptDenied(allowed_hash))
+    );
+}
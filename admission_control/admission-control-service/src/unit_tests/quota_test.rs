@@ -0,0 +1,51 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::quota::{Quota, QuotaExceeded};
+use config::config::{IdentityQuotaConfig, RateLimitConfig};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_max_in_flight() {
+    let quota = Quota::new(
+        "exchange".to_string(),
+        &IdentityQuotaConfig {
+            rate_limit: None,
+            max_in_flight: Some(2),
+        },
+    );
+    let now = Instant::now();
+    let first = quota.try_acquire(now).unwrap();
+    let _second = quota.try_acquire(now).unwrap();
+    assert_eq!(
+        quota.try_acquire(now).err(),
+        Some(QuotaExceeded::TooManyInFlight("exchange".to_string(), 2))
+    );
+    // the slots are given back once the requests complete, refused ones included
+    drop(first);
+    assert!(quota.try_acquire(now).is_ok());
+}
+
+#[test]
+fn test_rate_limit() {
+    let quota = Quota::new(
+        "wallet".to_string(),
+        &IdentityQuotaConfig {
+            rate_limit: Some(RateLimitConfig {
+                requests_per_sec: 1,
+                burst: 1,
+            }),
+            max_in_flight: None,
+        },
+    );
+    let now = Instant::now();
+    assert!(quota.try_acquire(now).is_ok());
+    assert_eq!(
+        quota.try_acquire(now).err(),
+        Some(QuotaExceeded::RateLimited(
+            "wallet".to_string(),
+            Duration::from_secs(1)
+        ))
+    );
+    assert!(quota.try_acquire(now + Duration::from_secs(1)).is_ok());
+}
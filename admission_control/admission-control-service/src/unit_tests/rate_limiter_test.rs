@@ -0,0 +1,62 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::rate_limiter::{RateLimiter, MAX_BUCKETS};
+use config::config::RateLimitConfig;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_token_bucket() {
+    let limiter = RateLimiter::new(&RateLimitConfig {
+        requests_per_sec: 2,
+        burst: 3,
+    });
+    let start = Instant::now();
+
+    // the burst goes through at once
+    for _ in 0..3 {
+        assert_eq!(limiter.try_acquire(1, start), Ok(()));
+    }
+    assert_eq!(
+        limiter.try_acquire(1, start),
+        Err(Duration::from_millis(500))
+    );
+    // other keys have their own bucket
+    assert_eq!(limiter.try_acquire(2, start), Ok(()));
+
+    // a token every 500ms
+    let later = start + Duration::from_millis(250);
+    assert_eq!(
+        limiter.try_acquire(1, later),
+        Err(Duration::from_millis(250))
+    );
+    let later = start + Duration::from_millis(500);
+    assert_eq!(limiter.try_acquire(1, later), Ok(()));
+    assert!(limiter.try_acquire(1, later).is_err());
+
+    // the bucket is refilled up to the burst only
+    let later = start + Duration::from_secs(60);
+    for _ in 0..3 {
+        assert_eq!(limiter.try_acquire(1, later), Ok(()));
+    }
+    assert!(limiter.try_acquire(1, later).is_err());
+}
+
+#[test]
+fn test_least_recently_used_buckets_forgotten() {
+    let limiter = RateLimiter::new(&RateLimitConfig {
+        requests_per_sec: 1,
+        burst: 1,
+    });
+    let now = Instant::now();
+    assert_eq!(limiter.try_acquire(0, now), Ok(()));
+    assert_eq!(limiter.try_acquire(1, now), Ok(()));
+    assert!(limiter.try_acquire(0, now).is_err());
+
+    // key 1 is the least recently used one once all the others have a bucket
+    for key in 2..=MAX_BUCKETS {
+        assert_eq!(limiter.try_acquire(key, now), Ok(()));
+    }
+    assert!(limiter.try_acquire(0, now).is_err());
+    assert_eq!(limiter.try_acquire(1, now), Ok(()));
+}
@@ -0,0 +1,182 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    admission_control_service::AdmissionControlService,
+    mocks::local_mock_mempool::LocalMockMempool,
+    upstream::{serve_network_submissions, UpstreamForwarder},
+};
+use admission_control_proto::{
+    proto::admission_control::{
+        admission_control_msg::Message as AdmissionControlMsg_oneof,
+        submit_transaction_response::Status, AdmissionControlMsg, SubmitTransactionRequest,
+        SubmitTransactionResponse as ProtoSubmitTransactionResponse,
+    },
+    AdmissionControlStatus, SubmitTransactionResponse,
+};
+use bytes::Bytes;
+use config::config::UpstreamConfig;
+use crypto::{ed25519::*, test_utils::TEST_SEED};
+use disk_monitor::DiskMonitor;
+use futures03::{channel::oneshot, executor::block_on, SinkExt, StreamExt};
+use network::{
+    interface::{NetworkNotification, NetworkRequest},
+    protocols::rpc::InboundRpcRequest,
+    validator_network::{
+        AdmissionControlNetworkEvents, AdmissionControlNetworkSender,
+        ADMISSION_CONTROL_RPC_PROTOCOL,
+    },
+    ProtocolId,
+};
+use prost::Message;
+use rand::SeedableRng;
+use std::{
+    convert::TryFrom,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use storage_service::mocks::mock_storage_client::MockStorageReadClient;
+use trusted_ledger::TrustedLedger;
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    test_helpers::transaction_test_helpers::get_test_signed_txn,
+    PeerId,
+};
+use vm_validator::mocks::mock_vm_validator::MockVMValidator;
+
+fn submit_transaction_request() -> SubmitTransactionRequest {
+    let mut rng = ::rand::rngs::StdRng::from_seed(TEST_SEED);
+    let keypair = compat::generate_keypair(&mut rng);
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(
+        get_test_signed_txn(
+            AccountAddress::new([103; ADDRESS_LENGTH]),
+            0,
+            keypair.0,
+            keypair.1,
+            None,
+        )
+        .into(),
+    );
+    req
+}
+
+fn to_bytes(msg: AdmissionControlMsg) -> Bytes {
+    let mut data = vec![];
+    msg.encode(&mut data).unwrap();
+    Bytes::from(data)
+}
+
+fn to_response(data: &Bytes) -> SubmitTransactionResponse {
+    match AdmissionControlMsg::decode(data.as_ref()).unwrap().message {
+        Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(response)) => {
+            SubmitTransactionResponse::try_from(response).unwrap()
+        }
+        message => panic!("Unexpected message: {:?}", message),
+    }
+}
+
+// A full node should forward the transactions submitted to it to its upstream peers.
+#[test]
+fn test_forward_transaction() {
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let upstream_peer = PeerId::random();
+    let upstream = UpstreamForwarder::new(
+        AdmissionControlNetworkSender::new(network_reqs_tx),
+        &UpstreamConfig {
+            preferred_peers: vec![upstream_peer.to_string()],
+            ..UpstreamConfig::default()
+        },
+    );
+    let ac_service = AdmissionControlService::new(
+        None::<Arc<LocalMockMempool>>,
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
+    )
+    .with_upstream(upstream);
+
+    let upstream_node = thread::spawn(move || match block_on(network_reqs_rx.next()).unwrap() {
+        NetworkRequest::SendRpc(peer_id, req) => {
+            assert_eq!(peer_id, upstream_peer);
+            let mut response = ProtoSubmitTransactionResponse::default();
+            response.status = Some(Status::AcStatus(AdmissionControlStatus::Accepted.into()));
+            let response_msg = AdmissionControlMsg {
+                message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
+                    response,
+                )),
+            };
+            req.res_tx.send(Ok(to_bytes(response_msg))).unwrap();
+        }
+        req => panic!("Unexpected network request: {:?}", req),
+    });
+    let response = ac_service
+        .submit_or_forward_transaction(submit_transaction_request(), None)
+        .unwrap();
+    upstream_node.join().unwrap();
+    assert_eq!(
+        SubmitTransactionResponse::try_from(response)
+            .unwrap()
+            .ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+}
+
+// A node without Mempool nor upstream peers should refuse the transactions submitted to it.
+#[test]
+fn test_no_mempool_nor_upstream() {
+    let ac_service = AdmissionControlService::new(
+        None::<Arc<LocalMockMempool>>,
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
+    );
+    assert!(ac_service
+        .submit_or_forward_transaction(submit_transaction_request(), None)
+        .is_err());
+}
+
+// The transactions forwarded by the downstream nodes should be submitted to Mempool.
+#[test]
+fn test_serve_network_submissions() {
+    let (mut notifs_tx, notifs_rx) = channel::new_test(8);
+    let ac_service = AdmissionControlService::new(
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+        TrustedLedger::new(),
+        DiskMonitor::default(),
+    );
+    let server = thread::spawn(move || {
+        serve_network_submissions(ac_service, AdmissionControlNetworkEvents::new(notifs_rx))
+    });
+
+    let req_msg = AdmissionControlMsg {
+        message: Some(AdmissionControlMsg_oneof::SubmitTransactionRequest(
+            submit_transaction_request(),
+        )),
+    };
+    let (res_tx, res_rx) = oneshot::channel();
+    let rpc_req = InboundRpcRequest {
+        protocol: ProtocolId::from_static(ADMISSION_CONTROL_RPC_PROTOCOL),
+        data: to_bytes(req_msg),
+        res_tx,
+        deadline: Instant::now() + Duration::from_secs(5),
+    };
+    block_on(notifs_tx.send(NetworkNotification::RecvRpc(PeerId::random(), rpc_req))).unwrap();
+    let res_data = block_on(res_rx).unwrap().unwrap();
+    assert_eq!(
+        to_response(&res_data).ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+
+    // the server stops along with the network
+    drop(notifs_tx);
+    server.join().unwrap();
+}
@@ -0,0 +1,116 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Submission of transactions over the network: a full node, which has no Mempool, forwards the
+//! transactions submitted to it to its upstream peers, and the upstream peers serve the
+//! transactions forwarded by their downstream nodes like the ones submitted over gRPC.
+
+use crate::admission_control_service::AdmissionControlService;
+use admission_control_proto::proto::admission_control::{
+    admission_control_msg::Message as AdmissionControlMsg_oneof, AdmissionControlMsg,
+    SubmitTransactionRequest, SubmitTransactionResponse,
+};
+use bytes::Bytes;
+use config::config::UpstreamConfig;
+use failure::prelude::*;
+use futures03::executor::{block_on, block_on_stream};
+use logger::prelude::*;
+use mempool::proto::mempool_client::MempoolClientTrait;
+use network::validator_network::{
+    AdmissionControlNetworkEvents, AdmissionControlNetworkSender, AdmissionControlUpstreams, Event,
+    RpcError,
+};
+use prost::Message;
+use std::time::{Duration, Instant};
+use vm_validator::vm_validator::TransactionValidation;
+
+#[cfg(test)]
+#[path = "unit_tests/upstream_test.rs"]
+mod upstream_test;
+
+/// Time each upstream peer is given to respond to a forwarded transaction.
+pub const UPSTREAM_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Forwards the transactions submitted to a full node to its upstream peers, failing over to the
+/// next upstream peer each time a request fails. Clones share the same health of the peers.
+#[derive(Clone)]
+pub struct UpstreamForwarder {
+    network_sender: AdmissionControlNetworkSender,
+    upstreams: AdmissionControlUpstreams,
+}
+
+impl UpstreamForwarder {
+    /// Forwards the transactions over `network_sender` to the upstream peers of `config`.
+    pub fn new(network_sender: AdmissionControlNetworkSender, config: &UpstreamConfig) -> Self {
+        Self {
+            network_sender,
+            upstreams: AdmissionControlUpstreams::new(config),
+        }
+    }
+
+    /// Sends the submission `req` to an upstream peer, and returns its response. Fails if no
+    /// upstream peer responded.
+    pub(crate) fn forward(
+        &self,
+        req: SubmitTransactionRequest,
+    ) -> Result<SubmitTransactionResponse> {
+        let mut network_sender = self.network_sender.clone();
+        let (peer_id, response) = block_on(network_sender.send_transaction_to_upstreams(
+            &self.upstreams,
+            req,
+            UPSTREAM_REQUEST_TIMEOUT,
+        ))?;
+        debug!("Transaction forwarded to upstream peer {}", peer_id);
+        Ok(response)
+    }
+}
+
+/// Serves the transactions submitted by the downstream nodes over the network as if they were
+/// submitted to `service` over gRPC, until the network stops delivering `events`. Blocks the
+/// calling thread, as the submissions are handled synchronously.
+pub fn serve_network_submissions<M: 'static, V: 'static>(
+    service: AdmissionControlService<M, V>,
+    events: AdmissionControlNetworkEvents,
+) where
+    M: MempoolClientTrait,
+    V: TransactionValidation,
+{
+    for event in block_on_stream(Box::pin(events)) {
+        let (peer_id, msg, callback, deadline) = match event {
+            Ok(Event::RpcRequest(request)) => request,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Failed to receive a submission from the network: {:?}", e);
+                continue;
+            }
+        };
+        // The caller has already given up on this request, so don't bother serving it.
+        if Instant::now() >= deadline || callback.is_canceled() {
+            debug!("Dropping expired submission from {}", peer_id);
+            continue;
+        }
+        let req = match msg.message {
+            Some(AdmissionControlMsg_oneof::SubmitTransactionRequest(req)) => req,
+            message => {
+                warn!("Unexpected AC RPC from {}: {:?}", peer_id, message);
+                continue;
+            }
+        };
+        let response = service
+            .submit_or_forward_transaction(req, None)
+            .and_then(|response| {
+                let response_msg = AdmissionControlMsg {
+                    message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
+                        response,
+                    )),
+                };
+                let mut response_data = vec![];
+                response_msg.encode(&mut response_data)?;
+                Ok(Bytes::from(response_data))
+            })
+            .map_err(RpcError::ApplicationError);
+        if callback.send(response).is_err() {
+            debug!("Submission from {} answered past its deadline", peer_id);
+        }
+    }
+}
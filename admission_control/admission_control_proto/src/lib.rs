@@ -6,8 +6,20 @@ pub mod proto;
 use failure::prelude::*;
 use logger::prelude::*;
 use mempool_shared_proto::MempoolAddTransactionStatus;
-use std::convert::TryFrom;
-use types::vm_error::VMStatus;
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    time::Duration,
+};
+use types::{
+    account_address::AccountAddress,
+    account_state_blob::AccountStateWithProof,
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof, ValidatorVerifier},
+    get_with_proof::{verify_update_to_latest_ledger_response, RequestItem, ResponseItem},
+    proof::AccumulatorConsistencyProof,
+    transaction::Version,
+    vm_error::VMStatus,
+};
 
 /// AC response status of submit_transaction to clients.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -18,6 +30,13 @@ pub enum AdmissionControlStatus {
     Blacklisted(String),
     /// The transaction is rejected, e.g. due to incorrect signature.
     Rejected(String),
+    /// The sender or the client submits too many transactions, and is to retry after the
+    /// duration.
+    RateLimited(Duration),
+    /// The node is overloaded, and the transaction is to be submitted again after the duration.
+    Overloaded(Duration),
+    /// The node failed to validate the transaction, which may be submitted again.
+    InternalError(String),
 }
 
 impl TryFrom<crate::proto::admission_control::AdmissionControlStatus> for AdmissionControlStatus {
@@ -35,6 +54,16 @@ impl TryFrom<crate::proto::admission_control::AdmissionControlStatus> for Admiss
                 let msg = proto.message;
                 AdmissionControlStatus::Rejected(msg)
             }
+            ProtoStatusCode::RateLimited => {
+                AdmissionControlStatus::RateLimited(Duration::from_millis(proto.retry_after_ms))
+            }
+            ProtoStatusCode::Overloaded => {
+                AdmissionControlStatus::Overloaded(Duration::from_millis(proto.retry_after_ms))
+            }
+            ProtoStatusCode::InternalError => {
+                let msg = proto.message;
+                AdmissionControlStatus::InternalError(msg)
+            }
         };
         Ok(ret)
     }
@@ -56,6 +85,18 @@ impl From<AdmissionControlStatus> for crate::proto::admission_control::Admission
                 admission_control_status.message = msg;
                 admission_control_status.set_code(ProtoStatusCode::Rejected)
             }
+            AdmissionControlStatus::RateLimited(retry_after) => {
+                admission_control_status.retry_after_ms = retry_after.as_millis() as u64;
+                admission_control_status.set_code(ProtoStatusCode::RateLimited)
+            }
+            AdmissionControlStatus::Overloaded(retry_after) => {
+                admission_control_status.retry_after_ms = retry_after.as_millis() as u64;
+                admission_control_status.set_code(ProtoStatusCode::Overloaded)
+            }
+            AdmissionControlStatus::InternalError(msg) => {
+                admission_control_status.message = msg;
+                admission_control_status.set_code(ProtoStatusCode::InternalError)
+            }
         }
         admission_control_status
     }
@@ -122,3 +163,85 @@ impl From<SubmitTransactionResponse>
         proto
     }
 }
+
+/// Rust structure for GetAccountStateResponse protobuf definition.
+#[derive(Clone, Debug)]
+pub struct GetAccountStateResponse {
+    /// Blob of the account state, along with the proof of it.
+    pub account_state_with_proof: AccountStateWithProof,
+    /// Latest ledger info of the node, which the proof is relative to.
+    pub ledger_info_with_sigs: LedgerInfoWithSignatures,
+    /// Validator change events since the version the client knew of.
+    pub validator_change_events: Vec<ValidatorChangeEventWithProof>,
+    /// Proof that the latest ledger extends the one at the version the client knew of.
+    pub ledger_consistency_proof: AccumulatorConsistencyProof,
+}
+
+impl GetAccountStateResponse {
+    /// Verifies that the ledger info is signed by the validators of `validator_verifier` and not
+    /// older than `client_known_version`, and that it proves the state of the account at
+    /// `address`.
+    ///
+    /// After calling this one can trust the account state without further verification.
+    pub fn verify(
+        &self,
+        validator_verifier: Arc<ValidatorVerifier>,
+        address: AccountAddress,
+        client_known_version: Version,
+    ) -> Result<()> {
+        verify_update_to_latest_ledger_response(
+            validator_verifier,
+            client_known_version,
+            &[RequestItem::GetAccountState { address }],
+            &[ResponseItem::GetAccountState {
+                account_state_with_proof: self.account_state_with_proof.clone(),
+            }],
+            &self.ledger_info_with_sigs,
+        )
+    }
+}
+
+impl TryFrom<crate::proto::admission_control::GetAccountStateResponse> for GetAccountStateResponse {
+    type Error = Error;
+
+    fn try_from(proto: crate::proto::admission_control::GetAccountStateResponse) -> Result<Self> {
+        let account_state_with_proof = proto
+            .account_state_with_proof
+            .ok_or_else(|| format_err!("Missing account_state_with_proof"))?
+            .try_into()?;
+        let ledger_info_with_sigs = proto
+            .ledger_info_with_sigs
+            .ok_or_else(|| format_err!("Missing ledger_info_with_sigs"))?
+            .try_into()?;
+        let validator_change_events = proto
+            .validator_change_events
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<Vec<_>>>()?;
+        let ledger_consistency_proof = proto
+            .ledger_consistency_proof
+            .unwrap_or_else(Default::default)
+            .try_into()?;
+        Ok(Self {
+            account_state_with_proof,
+            ledger_info_with_sigs,
+            validator_change_events,
+            ledger_consistency_proof,
+        })
+    }
+}
+
+impl From<GetAccountStateResponse> for crate::proto::admission_control::GetAccountStateResponse {
+    fn from(response: GetAccountStateResponse) -> Self {
+        Self {
+            account_state_with_proof: Some(response.account_state_with_proof.into()),
+            ledger_info_with_sigs: Some(response.ledger_info_with_sigs.into()),
+            validator_change_events: response
+                .validator_change_events
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            ledger_consistency_proof: Some(response.ledger_consistency_proof.into()),
+        }
+    }
+}
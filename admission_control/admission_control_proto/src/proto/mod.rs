@@ -4,7 +4,7 @@
 #![allow(bare_trait_objects)]
 
 use ::types::proto::*;
-use mempool_shared_proto::proto::mempool_status;
+use mempool_shared_proto::proto::{gas_price, mempool_status};
 
 pub mod admission_control {
     include!(concat!(env!("OUT_DIR"), "/admission_control.rs"));
@@ -12,4 +12,5 @@ pub mod admission_control {
 
 pub use self::admission_control::{
     AdmissionControlMsg, SubmitTransactionRequest, SubmitTransactionResponse,
+    SubmitTransactionsBatchRequest, SubmitTransactionsBatchResponse,
 };
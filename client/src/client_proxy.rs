@@ -1,11 +1,23 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{commands::*, grpc_client::GRPCClient, AccountData, AccountStatus};
-use admission_control_proto::proto::admission_control::SubmitTransactionRequest;
-use config::{config::PersistableConfig, trusted_peers::ConsensusPeersConfig};
+use crate::{
+    commands::*,
+    grpc_client::GRPCClient,
+    remote_state_view::{AccountStateSource, RemoteStateView},
+    AccountData, AccountStatus,
+};
+use admission_control_proto::{
+    proto::admission_control::SubmitTransactionRequest, SubmitTransactionResponse,
+};
+use bytecode_verifier::VerifiedScript;
+use config::{
+    config::{PersistableConfig, VMConfig},
+    trusted_peers::ConsensusPeersConfig,
+};
 use crypto::{ed25519::*, test_utils::KeyPair};
 use failure::prelude::*;
+use libra_dev_node::LibraDevNode;
 use libra_wallet::{io_utils, wallet_library::WalletLibrary};
 use logger::prelude::*;
 use num_traits::{
@@ -32,21 +44,24 @@ use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
     account_config::{
         association_address, core_code_address, get_account_resource_or_default, AccountResource,
-        ACCOUNT_RECEIVED_EVENT_PATH, ACCOUNT_SENT_EVENT_PATH,
     },
     account_state_blob::{AccountStateBlob, AccountStateWithProof},
     contract_event::{ContractEvent, EventWithProof},
     transaction::{
         parse_as_transaction_argument, RawTransaction, Script, SignedTransaction,
-        TransactionPayload, Version,
+        TransactionOutput, TransactionPayload, Version,
     },
     transaction_helpers::{create_signed_txn, create_unsigned_txn, TransactionSigner},
 };
+use vm::file_format::CompiledScript;
+use vm_runtime::{MoveVM, VMExecutor};
 
 const CLIENT_WALLET_MNEMONIC_FILE: &str = "client.mnemonic";
 const GAS_UNIT_PRICE: u64 = 0;
 const MAX_GAS_AMOUNT: u64 = 140_000;
 const TX_EXPIRATION: i64 = 100;
+/// Coins given by the faucet to the accounts created on the dev node.
+const DEV_NODE_ACCOUNT_BALANCE: u64 = 1_000_000_000;
 
 /// Enum used for error formatting.
 #[derive(Debug)]
@@ -99,6 +114,8 @@ pub struct ClientProxy {
     sync_on_wallet_recovery: bool,
     /// temp files (alive for duration of program)
     temp_files: Vec<PathBuf>,
+    /// Local chain the scripts are dry run against, started on first use.
+    dev_node: Option<LibraDevNode>,
 }
 
 impl ClientProxy {
@@ -161,6 +178,7 @@ impl ClientProxy {
             wallet: Self::get_libra_wallet(mnemonic_file)?,
             sync_on_wallet_recovery,
             temp_files: vec![],
+            dev_node: None,
         })
     }
 
@@ -582,6 +600,112 @@ impl ClientProxy {
         )
     }
 
+    /// Compile a script and execute it locally as the next transaction of its sender, without
+    /// submitting it, against the latest state of the validator or, with `on_dev_node`, of a local
+    /// dev node. Fails if the script does not pass bytecode verification.
+    pub fn dry_run_script(
+        &mut self,
+        space_delim_strings: &[&str],
+        on_dev_node: bool,
+    ) -> Result<TransactionOutput> {
+        let sender_address = self.get_account_address_from_parameter(space_delim_strings[1])?;
+        let compiled_path = self.compile_program(&[
+            space_delim_strings[0],
+            space_delim_strings[1],
+            space_delim_strings[2],
+            "script",
+        ])?;
+        let script: Script = serde_json::from_slice(&fs::read(compiled_path)?)?;
+        let (script_bytes, _) = script.into_inner();
+        let arguments = space_delim_strings[3..]
+            .iter()
+            .map(|arg| parse_as_transaction_argument(arg))
+            .collect::<Result<Vec<_>>>()?;
+        self.dry_run(
+            sender_address,
+            Script::new(script_bytes, arguments),
+            on_dev_node,
+        )
+    }
+
+    /// Execute `script` locally as the next transaction of the account at `sender_address`. The
+    /// dev node is started on the first dry run against it, and the sender account is created on
+    /// it if it doesn't exist yet.
+    fn dry_run(
+        &mut self,
+        sender_address: AccountAddress,
+        script: Script,
+        on_dev_node: bool,
+    ) -> Result<TransactionOutput> {
+        let compiled_script = CompiledScript::deserialize(script.code())
+            .map_err(|status| format_err!("Failed to deserialize script: {}", status))?;
+        if let Err((_, errors)) = VerifiedScript::new(compiled_script) {
+            let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+            bail!(
+                "Script failed bytecode verification:\n{}",
+                errors.join("\n")
+            );
+        }
+
+        let sender_ref_id = self.get_account_ref_id(&sender_address)?;
+        if on_dev_node && self.dev_node.is_none() {
+            self.dev_node = Some(LibraDevNode::new());
+        }
+        let source: &dyn AccountStateSource = match &self.dev_node {
+            Some(dev_node) if on_dev_node => {
+                Self::create_dev_node_account(dev_node, sender_address)?;
+                dev_node
+            }
+            _ => &self.client,
+        };
+        let sequence_number =
+            get_account_resource_or_default(&source.get_account_state(sender_address)?)?
+                .sequence_number();
+        let signed_txn = self.sign_transaction(
+            TransactionPayload::Script(script),
+            &self.accounts[sender_ref_id],
+            sequence_number,
+            None,
+            None,
+        );
+        let state_view = RemoteStateView::new(source);
+        Ok(MoveVM::execute_block(vec![signed_txn], &VMConfig::default(), &state_view).remove(0))
+    }
+
+    /// Create the account at `address` on the dev node with the coins of the faucet, unless it
+    /// already exists.
+    fn create_dev_node_account(dev_node: &LibraDevNode, address: AccountAddress) -> Result<()> {
+        if dev_node.get_account_state(address)?.is_some() {
+            return Ok(());
+        }
+        let faucet_sequence_number =
+            get_account_resource_or_default(&dev_node.get_account_state(association_address())?)?
+                .sequence_number();
+        let signed_txn = create_signed_txn(
+            dev_node.faucet_keypair(),
+            TransactionPayload::Script(transaction_builder::encode_create_account_script(
+                &address,
+                DEV_NODE_ACCOUNT_BALANCE,
+            )),
+            association_address(),
+            faucet_sequence_number,
+            MAX_GAS_AMOUNT,
+            GAS_UNIT_PRICE,
+            TX_EXPIRATION,
+        )?;
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(signed_txn.into());
+        // The dev node commits the transaction before responding.
+        let response = SubmitTransactionResponse::try_from(dev_node.submit_transaction(req)?)?;
+        ensure!(
+            dev_node.get_account_state(address)?.is_some(),
+            "Failed to create account {} on the dev node: {:?}",
+            address,
+            response
+        );
+        Ok(())
+    }
+
     /// Get the latest account state from validator.
     pub fn get_latest_account_state(
         &mut self,
@@ -701,15 +825,14 @@ impl ClientProxy {
             "Invalid number of arguments to get events by access path"
         );
         let account = self.get_account_address_from_parameter(space_delim_strings[1])?;
-        let path = match space_delim_strings[2] {
-            "sent" => ACCOUNT_SENT_EVENT_PATH.to_vec(),
-            "received" => ACCOUNT_RECEIVED_EVENT_PATH.to_vec(),
+        let access_path = match space_delim_strings[2] {
+            "sent" => AccessPath::new_for_sent_event(account),
+            "received" => AccessPath::new_for_received_event(account),
             _ => bail!(
                 "Unknown event type: {:?}, only sent and received are supported",
                 space_delim_strings[2]
             ),
         };
-        let access_path = AccessPath::new(account, path);
         let start_seq_number = space_delim_strings[3].parse::<u64>().map_err(|error| {
             format_parse_data_error(
                 "start_seq_number",
@@ -1005,23 +1128,40 @@ impl ClientProxy {
         max_gas_amount: Option<u64>,
         gas_unit_price: Option<u64>,
     ) -> Result<SubmitTransactionRequest> {
+        let signed_txn = self.sign_transaction(
+            program,
+            sender_account,
+            sender_account.sequence_number,
+            max_gas_amount,
+            gas_unit_price,
+        );
+        let mut req = SubmitTransactionRequest::default();
+        req.signed_txn = Some(signed_txn.into());
+        Ok(req)
+    }
+
+    fn sign_transaction(
+        &self,
+        program: TransactionPayload,
+        sender_account: &AccountData,
+        sequence_number: u64,
+        max_gas_amount: Option<u64>,
+        gas_unit_price: Option<u64>,
+    ) -> SignedTransaction {
         let signer: Box<&dyn TransactionSigner> = match &sender_account.key_pair {
             Some(key_pair) => Box::new(key_pair),
             None => Box::new(&self.wallet),
         };
-        let signed_txn = create_signed_txn(
+        create_signed_txn(
             *signer,
             program,
             sender_account.address,
-            sender_account.sequence_number,
+            sequence_number,
             max_gas_amount.unwrap_or(MAX_GAS_AMOUNT),
             gas_unit_price.unwrap_or(GAS_UNIT_PRICE),
             TX_EXPIRATION,
         )
-        .unwrap();
-        let mut req = SubmitTransactionRequest::default();
-        req.signed_txn = Some(signed_txn.into());
-        Ok(req)
+        .unwrap()
     }
 
     fn mut_account_from_parameter(&mut self, para: &str) -> Result<&mut AccountData> {
@@ -1079,11 +1219,19 @@ impl fmt::Display for AccountEntry {
 
 #[cfg(test)]
 mod tests {
-    use crate::client_proxy::{parse_bool, AddressAndIndex, ClientProxy};
+    use crate::{
+        client_proxy::{parse_bool, AddressAndIndex, ClientProxy},
+        remote_state_view::AccountStateSource,
+    };
     use config::{config::PersistableConfig, trusted_peers::ConfigHelpers};
     use libra_wallet::io_utils;
     use proptest::prelude::*;
     use tools::tempdir::TempPath;
+    use types::{
+        account_config::get_account_resource_or_default,
+        transaction::{Script, TransactionStatus},
+        vm_error::{StatusCode, VMStatus},
+    };
 
     fn generate_accounts_from_wallet(count: usize) -> (ClientProxy, Vec<AddressAndIndex>) {
         let mut accounts = Vec::new();
@@ -1171,6 +1319,41 @@ mod tests {
         assert_eq!(client.wallet.mnemonic(), wallet.mnemonic());
     }
 
+    #[test]
+    fn test_dry_run_on_dev_node() {
+        let (mut client, accounts) = generate_accounts_from_wallet(2);
+        let sender = accounts[0].address;
+        let script = transaction_builder::encode_transfer_script(&accounts[1].address, 100);
+
+        let output = client.dry_run(sender, script.clone(), true).unwrap();
+        assert_eq!(
+            output.status(),
+            &TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED))
+        );
+        assert!(output.gas_used() > 0);
+        assert!(!output.events().is_empty());
+
+        // Nothing was submitted, so the same transaction can be dry run again.
+        let dev_node = client.dev_node.as_ref().unwrap();
+        let sender_state = dev_node.get_account_state(sender).unwrap();
+        assert_eq!(
+            get_account_resource_or_default(&sender_state)
+                .unwrap()
+                .sequence_number(),
+            0
+        );
+        assert_eq!(client.dry_run(sender, script, true).unwrap(), output);
+    }
+
+    #[test]
+    fn test_dry_run_invalid_script() {
+        let (mut client, accounts) = generate_accounts_from_wallet(1);
+        let script = Script::new(vec![0xff; 16], vec![]);
+        assert!(client.dry_run(accounts[0].address, script, true).is_err());
+        // The script is refused before anything is executed.
+        assert!(client.dev_node.is_none());
+    }
+
     proptest! {
         // Proptest is used to verify that the conversion will not panic with random input.
         #[test]
@@ -18,6 +18,7 @@ impl Command for DevCommand {
             Box::new(DevCommandCompile {}),
             Box::new(DevCommandPublish {}),
             Box::new(DevCommandExecute {}),
+            Box::new(DevCommandDryRun {}),
         ];
         subcommand_execute(&params[0], commands, client, &params[1..]);
     }
@@ -104,3 +105,45 @@ impl Command for DevCommandExecute {
         }
     }
 }
+
+/// Sub command to try out a move script without submitting it
+pub struct DevCommandDryRun {}
+
+impl Command for DevCommandDryRun {
+    fn get_aliases(&self) -> Vec<&'static str> {
+        vec!["dry-run", "d"]
+    }
+
+    fn get_params_help(&self) -> &'static str {
+        "[--dev-node] <sender_account_address>|<sender_account_ref_id> <script_file_path> [parameters]"
+    }
+
+    fn get_description(&self) -> &'static str {
+        "Compile, verify and execute move script locally against the latest state of the validator, or of a local dev node with --dev-node, without submitting it"
+    }
+
+    fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
+        let on_dev_node = params.get(1) == Some(&"--dev-node");
+        let params = if on_dev_node {
+            [&params[..1], &params[2..]].concat()
+        } else {
+            params.to_vec()
+        };
+        if params.len() < 3 {
+            println!("Invalid number of arguments for dry run");
+            return;
+        }
+        println!(">> Dry running script");
+        match client.dry_run_script(&params, on_dev_node) {
+            Ok(output) => {
+                println!("Status: {:?}", output.status());
+                println!("Gas used: {}", output.gas_used());
+                println!("Events:");
+                for event in output.events() {
+                    println!("{}", event);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+}
@@ -85,12 +85,12 @@ impl GRPCClient {
                     sender_account.sequence_number =
                         self.get_sequence_number(sender_account.address)?;
                     bail!(
-                        "Transaction failed with vm status: {:?}, please retry your transaction.",
+                        "Transaction failed with vm {}, please retry your transaction.",
                         vm_error
                     );
                 }
             }
-            bail!("Transaction failed with vm status: {:?}", vm_error);
+            bail!("Transaction failed with vm {}", vm_error);
         } else if let Some(mempool_error) = completed_resp.mempool_error {
             bail!(
                 "Transaction failed with mempool status: {:?}",
@@ -22,6 +22,8 @@ pub(crate) mod dev_commands;
 /// gRPC client wrapper to connect to validator.
 pub(crate) mod grpc_client;
 pub(crate) mod query_commands;
+/// State view over the latest state of a validator, for executing transactions locally.
+pub(crate) mod remote_state_view;
 pub(crate) mod transfer_commands;
 
 /// Struct used to store data for each created account.  We track the sequence number
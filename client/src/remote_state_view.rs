@@ -0,0 +1,92 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::grpc_client::GRPCClient;
+use crypto::ed25519::*;
+use failure::prelude::*;
+use libra_dev_node::LibraDevNode;
+use state_view::StateView;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+};
+use types::{
+    access_path::AccessPath,
+    account_address::AccountAddress,
+    account_state_blob::AccountStateBlob,
+    get_with_proof::{RequestItem, UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse},
+};
+
+/// A node serving the latest state of accounts.
+pub(crate) trait AccountStateSource {
+    /// The latest state of the account at `address`, if the account exists.
+    fn get_account_state(&self, address: AccountAddress) -> Result<Option<AccountStateBlob>>;
+}
+
+impl AccountStateSource for GRPCClient {
+    fn get_account_state(&self, address: AccountAddress) -> Result<Option<AccountStateBlob>> {
+        Ok(self.get_account_blob(address)?.0)
+    }
+}
+
+impl AccountStateSource for LibraDevNode {
+    fn get_account_state(&self, address: AccountAddress) -> Result<Option<AccountStateBlob>> {
+        let request =
+            UpdateToLatestLedgerRequest::new(0, vec![RequestItem::GetAccountState { address }]);
+        let mut response: UpdateToLatestLedgerResponse<Ed25519Signature> = self
+            .update_to_latest_ledger(request.clone().into())?
+            .try_into()?;
+        response.verify(Arc::new(self.validator_verifier()), &request)?;
+        Ok(response
+            .response_items
+            .remove(0)
+            .into_get_account_state_response()?
+            .blob)
+    }
+}
+
+/// A state view reading the latest state of accounts from a node, for the VM to execute
+/// transactions locally. The state of each account is fetched once, when first read, so reads of
+/// different accounts may be served at different versions.
+pub(crate) struct RemoteStateView<'a> {
+    source: &'a dyn AccountStateSource,
+    account_states: RefCell<HashMap<AccountAddress, BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl<'a> RemoteStateView<'a> {
+    pub(crate) fn new(source: &'a dyn AccountStateSource) -> Self {
+        Self {
+            source,
+            account_states: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<'a> StateView for RemoteStateView<'a> {
+    fn get(&self, access_path: &AccessPath) -> Result<Option<Vec<u8>>> {
+        let mut account_states = self.account_states.borrow_mut();
+        if !account_states.contains_key(&access_path.address) {
+            let account_state = match self.source.get_account_state(access_path.address)? {
+                Some(blob) => BTreeMap::try_from(&blob)?,
+                None => BTreeMap::new(),
+            };
+            account_states.insert(access_path.address, account_state);
+        }
+        Ok(account_states[&access_path.address]
+            .get(&access_path.path)
+            .cloned())
+    }
+
+    fn multi_get(&self, access_paths: &[AccessPath]) -> Result<Vec<Option<Vec<u8>>>> {
+        access_paths
+            .iter()
+            .map(|access_path| self.get(access_path))
+            .collect()
+    }
+
+    fn is_genesis(&self) -> bool {
+        false
+    }
+}
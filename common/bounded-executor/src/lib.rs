@@ -7,12 +7,21 @@
 
 use futures::future::{Future, FutureExt, TryFutureExt};
 use futures_semaphore::Semaphore;
+use task_manager::TaskManager;
 use tokio::runtime::TaskExecutor;
 
 #[derive(Clone, Debug)]
 pub struct BoundedExecutor {
     semaphore: Semaphore,
-    executor: TaskExecutor,
+    spawner: Spawner,
+}
+
+#[derive(Clone, Debug)]
+enum Spawner {
+    Executor(TaskExecutor),
+    /// Spawns the tasks through the manager under the given name, so that they are cancelled
+    /// along with the other tasks of its component.
+    TaskManager(TaskManager, &'static str),
 }
 
 /// Returned by [`BoundedExecutor::try_spawn`] if it is at capacity.
@@ -27,7 +36,21 @@ impl BoundedExecutor {
         let semaphore = Semaphore::new(capacity);
         Self {
             semaphore,
-            executor,
+            spawner: Spawner::Executor(executor),
+        }
+    }
+
+    /// Same as [`new`](BoundedExecutor::new), with the tasks spawned through `task_manager`
+    /// under `name`.
+    pub fn with_task_manager(
+        capacity: usize,
+        task_manager: TaskManager,
+        name: &'static str,
+    ) -> Self {
+        let semaphore = Semaphore::new(capacity);
+        Self {
+            semaphore,
+            spawner: Spawner::TaskManager(task_manager, name),
         }
     }
 
@@ -39,7 +62,10 @@ impl BoundedExecutor {
     {
         let spawn_permit = self.semaphore.acquire().await;
         let f = f.map(move |_| drop(spawn_permit));
-        self.executor.spawn(f.boxed().unit_error().compat());
+        match &self.spawner {
+            Spawner::Executor(executor) => executor.spawn(f.boxed().unit_error().compat()),
+            Spawner::TaskManager(task_manager, name) => task_manager.spawn(*name, f),
+        }
     }
 }
 
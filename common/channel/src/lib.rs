@@ -11,12 +11,13 @@ use futures::{
     task::{Context, Poll},
 };
 use logger::prelude::*;
-use metrics::IntGauge;
+use metrics::{IntCounter, IntGauge};
 use std::{
     pin::Pin,
     time::{Duration, Instant},
 };
 
+mod lossy;
 #[cfg(test)]
 mod test;
 
@@ -38,16 +39,41 @@ impl<T> WithEntryTimestamp<T> {
     }
 }
 
+/// What a `Sender` does with a message sent while the channel is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest message of the channel to make room for the new one.
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+    /// Wait for the receiver to make room, i.e. exert backpressure on the sender.
+    Block,
+}
+
+enum SenderInner<T> {
+    Bounded(mpsc::Sender<WithEntryTimestamp<T>>),
+    Lossy(lossy::Sender<WithEntryTimestamp<T>>),
+}
+
+enum ReceiverInner<T> {
+    Bounded(mpsc::Receiver<WithEntryTimestamp<T>>),
+    Lossy(lossy::Receiver<WithEntryTimestamp<T>>),
+}
+
 /// Similar to `mpsc::Sender`, but with an `IntGauge`
 pub struct Sender<T> {
-    inner: mpsc::Sender<WithEntryTimestamp<T>>,
+    inner: SenderInner<T>,
     gauge: IntGauge,
 }
 
 impl<T> Clone for Sender<T> {
     fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            SenderInner::Bounded(sender) => SenderInner::Bounded(sender.clone()),
+            SenderInner::Lossy(sender) => SenderInner::Lossy(sender.clone()),
+        };
         Sender {
-            inner: self.inner.clone(),
+            inner,
             gauge: self.gauge.clone(),
         }
     }
@@ -55,60 +81,98 @@ impl<T> Clone for Sender<T> {
 
 /// Similar to `mpsc::Receiver`, but with an `IntGauge`
 pub struct Receiver<T> {
-    inner: mpsc::Receiver<WithEntryTimestamp<T>>,
+    inner: ReceiverInner<T>,
     gauge: IntGauge,
     timeout: Duration,
 }
 
 /// `Sender` implements `Sink` in the same way as `mpsc::Sender`, but it increments the
-/// associated `IntGauge` when it sends a message successfully.
+/// associated `IntGauge` when it sends a message successfully. A sender with a lossy overflow
+/// policy is always ready, and only fails once the receiver is gone.
 impl<T> Sink<T> for Sender<T> {
     type Error = mpsc::SendError;
 
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        (*self).inner.poll_ready(cx)
+        match &mut self.inner {
+            SenderInner::Bounded(sender) => sender.poll_ready(cx),
+            SenderInner::Lossy(sender) => Poll::Ready(if sender.is_disconnected() {
+                Err(disconnected())
+            } else {
+                Ok(())
+            }),
+        }
     }
 
     fn start_send(mut self: Pin<&mut Self>, msg: T) -> Result<(), Self::Error> {
-        self.gauge.inc();
-        (*self)
-            .inner
-            .start_send(WithEntryTimestamp::new(msg))
-            .map_err(|e| {
-                self.gauge.dec();
-                e
-            })?;
-        Ok(())
+        let gauge = self.gauge.clone();
+        match &mut self.inner {
+            SenderInner::Bounded(sender) => {
+                gauge.inc();
+                Pin::new(sender)
+                    .start_send(WithEntryTimestamp::new(msg))
+                    .map_err(|e| {
+                        gauge.dec();
+                        e
+                    })
+            }
+            SenderInner::Lossy(sender) => sender
+                .push(WithEntryTimestamp::new(msg))
+                .map_err(|_| disconnected()),
+        }
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.inner).poll_flush(cx)
+        match &mut self.inner {
+            SenderInner::Bounded(sender) => Pin::new(sender).poll_flush(cx),
+            SenderInner::Lossy(_) => Poll::Ready(Ok(())),
+        }
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.inner).poll_close(cx)
+        match &mut self.inner {
+            SenderInner::Bounded(sender) => Pin::new(sender).poll_close(cx),
+            SenderInner::Lossy(_) => Poll::Ready(Ok(())),
+        }
     }
 }
 
 impl<T> Sender<T> {
     pub fn try_send(&mut self, msg: T) -> Result<(), mpsc::SendError> {
-        self.gauge.inc();
-        (*self)
-            .inner
-            .try_send(WithEntryTimestamp::new(msg))
-            .map_err(|e| {
-                self.gauge.dec();
-                e.into_send_error()
-            })
+        match &mut self.inner {
+            SenderInner::Bounded(sender) => {
+                self.gauge.inc();
+                sender.try_send(WithEntryTimestamp::new(msg)).map_err(|e| {
+                    self.gauge.dec();
+                    e.into_send_error()
+                })
+            }
+            SenderInner::Lossy(sender) => sender
+                .push(WithEntryTimestamp::new(msg))
+                .map_err(|_| disconnected()),
+        }
     }
 }
 
+/// The error of a lossy `Sender` whose receiver is gone. `mpsc::SendError` can't be built
+/// directly, so it is taken from a disconnected `mpsc` channel.
+fn disconnected() -> mpsc::SendError {
+    let (mut sender, receiver) = mpsc::channel(0);
+    drop(receiver);
+    sender
+        .try_send(())
+        .expect_err("Sending to a disconnected channel fails.")
+        .into_send_error()
+}
+
 impl<T> FusedStream for Receiver<T>
 where
     T: std::fmt::Debug,
 {
     fn is_terminated(&self) -> bool {
-        self.inner.is_terminated()
+        match &self.inner {
+            ReceiverInner::Bounded(receiver) => receiver.is_terminated(),
+            ReceiverInner::Lossy(receiver) => receiver.is_terminated(),
+        }
     }
 }
 
@@ -122,9 +186,19 @@ where
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         loop {
-            match Pin::new(&mut self.inner).poll_next(cx) {
+            let poll = match &mut self.inner {
+                ReceiverInner::Bounded(receiver) => {
+                    let poll = Pin::new(receiver).poll_next(cx);
+                    if let Poll::Ready(Some(_)) = poll {
+                        self.gauge.dec();
+                    }
+                    poll
+                }
+                // The lossy queue maintains the gauge itself, as it drops messages.
+                ReceiverInner::Lossy(receiver) => receiver.poll_next(cx),
+            };
+            match poll {
                 Poll::Ready(Some(msg)) => {
-                    self.gauge.dec();
                     // If the message times out, it gets dropped
                     if Instant::now().duration_since(msg.entry_time) > self.timeout {
                         warn!("Message dropped due to timeout: {:?}", msg.value);
@@ -158,11 +232,40 @@ pub fn new_with_timeout<T>(
     let (sender, receiver) = mpsc::channel(size);
     (
         Sender {
-            inner: sender,
+            inner: SenderInner::Bounded(sender),
+            gauge: gauge.clone(),
+        },
+        Receiver {
+            inner: ReceiverInner::Bounded(receiver),
+            gauge: gauge.clone(),
+            timeout,
+        },
+    )
+}
+
+/// Creates a channel holding up to `size` messages, which applies `policy` to the messages sent
+/// while it is full. `dropped` counts the messages dropped, either because of `policy` or because
+/// the receiver is gone. With [`OverflowPolicy::Block`] this is the same as `new_with_timeout`.
+pub fn new_with_policy<T>(
+    size: usize,
+    gauge: &IntGauge,
+    dropped: &IntCounter,
+    timeout: Duration,
+    policy: OverflowPolicy,
+) -> (Sender<T>, Receiver<T>) {
+    let drop_policy = match policy {
+        OverflowPolicy::DropOldest => lossy::DropPolicy::Oldest,
+        OverflowPolicy::DropNewest => lossy::DropPolicy::Newest,
+        OverflowPolicy::Block => return new_with_timeout(size, gauge, timeout),
+    };
+    let (sender, receiver) = lossy::new(size, drop_policy, gauge, dropped);
+    (
+        Sender {
+            inner: SenderInner::Lossy(sender),
             gauge: gauge.clone(),
         },
         Receiver {
-            inner: receiver,
+            inner: ReceiverInner::Lossy(receiver),
             gauge: gauge.clone(),
             timeout,
         },
@@ -0,0 +1,160 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A bounded queue which never exerts backpressure: when it is full, a message is dropped instead.
+
+use metrics::{IntCounter, IntGauge};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// Which message is dropped when the queue is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum DropPolicy {
+    Oldest,
+    Newest,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    num_senders: usize,
+    receiver_alive: bool,
+    receiver_waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    policy: DropPolicy,
+    gauge: IntGauge,
+    dropped: IntCounter,
+}
+
+/// Error of a send to a queue whose receiver is gone.
+#[derive(Debug)]
+pub(crate) struct Disconnected;
+
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    terminated: bool,
+}
+
+pub(crate) fn new<T>(
+    capacity: usize,
+    policy: DropPolicy,
+    gauge: &IntGauge,
+    dropped: &IntCounter,
+) -> (Sender<T>, Receiver<T>) {
+    let capacity = std::cmp::max(capacity, 1);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            num_senders: 1,
+            receiver_alive: true,
+            receiver_waker: None,
+        }),
+        capacity,
+        policy,
+        gauge: gauge.clone(),
+        dropped: dropped.clone(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver {
+            shared,
+            terminated: false,
+        },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Queues `msg`, dropping a message if the queue is full. Fails, dropping `msg`, once the
+    /// receiver is gone.
+    pub(crate) fn push(&self, msg: T) -> Result<(), Disconnected> {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if !state.receiver_alive {
+            shared.dropped.inc();
+            return Err(Disconnected);
+        }
+        if state.queue.len() >= shared.capacity {
+            shared.dropped.inc();
+            match shared.policy {
+                DropPolicy::Newest => return Ok(()),
+                DropPolicy::Oldest => {
+                    state.queue.pop_front();
+                    shared.gauge.dec();
+                }
+            }
+        }
+        state.queue.push_back(msg);
+        shared.gauge.inc();
+        if let Some(waker) = state.receiver_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        !self.shared.state.lock().unwrap().receiver_alive
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().num_senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.num_senders -= 1;
+        if state.num_senders == 0 {
+            if let Some(waker) = state.receiver_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    pub(crate) fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(msg) = state.queue.pop_front() {
+            self.shared.gauge.dec();
+            return Poll::Ready(Some(msg));
+        }
+        if state.num_senders == 0 {
+            drop(state);
+            self.terminated = true;
+            return Poll::Ready(None);
+        }
+        state.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver_alive = false;
+        self.shared.gauge.sub(state.queue.len() as i64);
+        state.queue.clear();
+    }
+}
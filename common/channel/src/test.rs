@@ -1,12 +1,17 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{new_test, new_test_with_timeout, TEST_COUNTER};
+use crate::{
+    new_test, new_test_with_timeout, new_with_policy, OverflowPolicy, Receiver, Sender,
+    TEST_COUNTER,
+};
 use futures::{
     executor::block_on,
+    stream::FusedStream,
     task::{noop_waker, Context, Poll},
     FutureExt, SinkExt, StreamExt,
 };
+use metrics::{IntCounter, IntGauge};
 use rusty_fork::{rusty_fork_id, rusty_fork_test, rusty_fork_test_name};
 use std::{thread, time::Duration};
 
@@ -128,3 +133,83 @@ fn test_timeout() {
     assert_eq!(TEST_COUNTER.get(), 0);
 }
 }
+
+fn new_lossy_test(
+    size: usize,
+    policy: OverflowPolicy,
+) -> (Sender<u32>, Receiver<u32>, IntGauge, IntCounter) {
+    let gauge = IntGauge::new("lossy_test_pending", "Pending messages").unwrap();
+    let dropped = IntCounter::new("lossy_test_dropped", "Dropped messages").unwrap();
+    let (tx, rx) = new_with_policy(size, &gauge, &dropped, Duration::from_secs(60), policy);
+    (tx, rx, gauge, dropped)
+}
+
+#[test]
+fn test_drop_oldest() {
+    let (mut tx, mut rx, gauge, dropped) = new_lossy_test(2, OverflowPolicy::DropOldest);
+    for item in 1..=3 {
+        block_on(tx.send(item)).unwrap();
+    }
+    assert_eq!(gauge.get(), 2);
+    assert_eq!(dropped.get(), 1);
+    assert_eq!(block_on(rx.next()), Some(2));
+    assert_eq!(block_on(rx.next()), Some(3));
+    assert_eq!(gauge.get(), 0);
+
+    drop(tx);
+    assert_eq!(block_on(rx.next()), None);
+    assert!(rx.is_terminated());
+}
+
+#[test]
+fn test_drop_newest() {
+    let (mut tx, mut rx, gauge, dropped) = new_lossy_test(2, OverflowPolicy::DropNewest);
+    for item in 1..=3 {
+        tx.try_send(item).unwrap();
+    }
+    assert_eq!(gauge.get(), 2);
+    assert_eq!(dropped.get(), 1);
+    assert_eq!(block_on(rx.next()), Some(1));
+    assert_eq!(block_on(rx.next()), Some(2));
+}
+
+#[test]
+fn test_lossy_close() {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    let (mut tx, mut rx, _gauge, _dropped) = new_lossy_test(1, OverflowPolicy::DropOldest);
+    let mut tx2 = tx.clone();
+    assert_eq!(rx.poll_next_unpin(&mut cx), Poll::Pending);
+    block_on(tx2.send(1)).unwrap();
+    assert_eq!(rx.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+
+    // The channel is closed once all the senders are dropped.
+    drop(tx2);
+    assert_eq!(rx.poll_next_unpin(&mut cx), Poll::Pending);
+    block_on(tx.send(2)).unwrap();
+    drop(tx);
+    assert_eq!(rx.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+    assert_eq!(rx.poll_next_unpin(&mut cx), Poll::Ready(None));
+}
+
+#[test]
+fn test_lossy_disconnect() {
+    let (mut tx, rx, gauge, dropped) = new_lossy_test(1, OverflowPolicy::DropOldest);
+    // The gauge may be shared with other channels, so it is left as is when creating one.
+    gauge.set(5);
+    let (_tx2, _rx2) = new_with_policy::<u32>(
+        1,
+        &gauge,
+        &dropped,
+        Duration::from_secs(60),
+        OverflowPolicy::DropNewest,
+    );
+    assert_eq!(gauge.get(), 5);
+
+    tx.try_send(1).unwrap();
+    drop(rx);
+    assert!(tx.try_send(2).unwrap_err().is_disconnected());
+    assert!(block_on(tx.send(3)).unwrap_err().is_disconnected());
+    assert_eq!(gauge.get(), 5);
+}
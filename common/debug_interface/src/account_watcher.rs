@@ -0,0 +1,143 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Watch list of accounts whose changes the node reports.
+//!
+//! Operators register the accounts to watch through the debug interface. Whenever a committed
+//! transaction changes the state of a watched account, the executor reports the new state of the
+//! account, both in the log and as a `watched_account_changed` event, served by `GetEvents`. The
+//! watch list of a node is persisted to a file of its data directory, so that it survives restarts.
+
+use failure::prelude::*;
+use logger::prelude::*;
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use types::{
+    account_address::AccountAddress, account_config::AccountResource,
+    account_state_blob::AccountStateBlob, transaction::Version,
+};
+
+/// Handle to the watch list of a node. All the clones of a handle share the same list.
+#[derive(Clone, Default)]
+pub struct AccountWatcher {
+    watched: Arc<RwLock<HashSet<AccountAddress>>>,
+    // File the watch list is persisted to, if any, so that it survives restarts
+    file: Option<Arc<PathBuf>>,
+}
+
+impl AccountWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch list persisted to `file`, starting from the accounts it lists, if it exists.
+    pub fn with_file(file: PathBuf) -> Result<Self> {
+        let watched = if file.exists() {
+            read_watch_list(&file)?
+        } else {
+            HashSet::new()
+        };
+        Ok(Self {
+            watched: Arc::new(RwLock::new(watched)),
+            file: Some(Arc::new(file)),
+        })
+    }
+
+    /// Adds `address` to the watch list. Returns whether it was not watched yet.
+    pub fn watch(&self, address: AccountAddress) -> bool {
+        let mut watched = self.watched.write().unwrap();
+        let inserted = watched.insert(address);
+        if inserted {
+            self.persist(&watched);
+        }
+        inserted
+    }
+
+    /// Removes `address` from the watch list. Returns whether it was watched.
+    pub fn unwatch(&self, address: &AccountAddress) -> bool {
+        let mut watched = self.watched.write().unwrap();
+        let removed = watched.remove(address);
+        if removed {
+            self.persist(&watched);
+        }
+        removed
+    }
+
+    /// The watched accounts, in no particular order.
+    pub fn watched(&self) -> Vec<AccountAddress> {
+        self.watched.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Reports that the committed transaction at `version` changed the state of the watched
+    /// account at `address` to `blob`.
+    pub fn report(&self, address: AccountAddress, version: Version, blob: &AccountStateBlob) {
+        let account = blob
+            .try_into()
+            .and_then(|account_btree| AccountResource::make_from(&account_btree));
+        match account {
+            Ok(account) => {
+                info!(
+                    "Watched account {} changed at version {}: sequence number {}, balance {}",
+                    address,
+                    version,
+                    account.sequence_number(),
+                    account.balance()
+                );
+                event!("watched_account_changed",
+                    "address": address.to_string(),
+                    "version": version,
+                    "sequence_number": account.sequence_number(),
+                    "balance": account.balance(),
+                );
+            }
+            // The account may hold no account resource, e.g. one of the accounts of the system
+            // modules.
+            Err(e) => warn!(
+                "Watched account {} changed at version {}, but its account resource can't be \
+                 read: {}",
+                address, version, e
+            ),
+        }
+    }
+
+    /// Rewrites the file of the watch list, if any. The change to the watch list is kept in
+    /// memory even if it can't be persisted.
+    fn persist(&self, watched: &HashSet<AccountAddress>) {
+        if let Some(file) = &self.file {
+            if let Err(e) = write_watch_list(file, watched) {
+                error!("Failed to persist the watch list to {:?}: {}", file, e);
+            }
+        }
+    }
+}
+
+/// Reads the watch list of `file`, one hex-encoded address per line.
+fn read_watch_list(file: &Path) -> Result<HashSet<AccountAddress>> {
+    fs::read_to_string(file)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .map_err(|e| format_err!("Invalid account address {:?} in {:?}: {}", line, file, e))
+        })
+        .collect()
+}
+
+/// Writes `watched` to `file` through a temporary file, so that a crash never leaves the watch
+/// list half written.
+fn write_watch_list(file: &Path, watched: &HashSet<AccountAddress>) -> Result<()> {
+    let contents: String = watched
+        .iter()
+        .map(|address| format!("{:x}\n", address))
+        .collect();
+    let tmp_file = file.with_extension("tmp");
+    fs::write(&tmp_file, contents)?;
+    fs::rename(&tmp_file, file)?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::account_watcher::AccountWatcher;
+use std::fs;
+use tools::tempdir::TempPath;
+use types::account_address::AccountAddress;
+
+#[test]
+fn test_watch_list() {
+    let watcher = AccountWatcher::new();
+    let watched = AccountAddress::random();
+    assert!(watcher.watched().is_empty());
+
+    assert!(watcher.watch(watched));
+    assert!(!watcher.watch(watched));
+    assert_eq!(watcher.watched(), vec![watched]);
+
+    // The clones of the watcher share its watch list.
+    assert!(watcher.clone().unwatch(&watched));
+    assert!(!watcher.unwatch(&watched));
+    assert!(watcher.watched().is_empty());
+}
+
+#[test]
+fn test_persisted_watch_list() {
+    let tmp_dir = TempPath::new();
+    tmp_dir.create_as_dir().unwrap();
+    let file = tmp_dir.path().join("watched_accounts");
+    let first = AccountAddress::random();
+    let second = AccountAddress::random();
+
+    let watcher = AccountWatcher::with_file(file.clone()).unwrap();
+    assert!(watcher.watched().is_empty());
+    assert!(watcher.watch(first));
+    assert!(watcher.watch(second));
+    assert!(watcher.unwatch(&first));
+
+    // A restarted node watches the same accounts.
+    let restarted = AccountWatcher::with_file(file.clone()).unwrap();
+    assert_eq!(restarted.watched(), vec![second]);
+
+    fs::write(&file, "not an address\n").unwrap();
+    assert!(AccountWatcher::with_file(file).is_err());
+}
@@ -1,10 +1,14 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::proto::{GetNodeDetailsRequest, NodeDebugInterfaceClient};
+use crate::proto::{
+    GetMempoolStateRequest, GetNodeDetailsRequest, MempoolState, NodeDebugInterfaceClient,
+    SetNetworkFaultsRequest, UnwatchAccountsRequest, WatchAccountsRequest,
+};
 use failure::prelude::*;
 use grpcio::{ChannelBuilder, EnvBuilder};
 use std::{collections::HashMap, sync::Arc};
+use types::account_address::AccountAddress;
 
 // Generated
 pub mod proto;
@@ -13,6 +17,10 @@ pub mod node_debug_helpers;
 pub mod node_debug_service;
 #[macro_use]
 pub mod json_log;
+pub mod account_watcher;
+#[cfg(test)]
+mod account_watcher_test;
+pub mod mempool_introspector;
 
 /// Implement default utility client for NodeDebugInterface
 pub struct NodeDebugClient {
@@ -57,4 +65,45 @@ impl NodeDebugClient {
             })
             .collect()
     }
+
+    /// Replaces the faults injected into the network connections of the node. The node must have
+    /// network fault injection enabled.
+    pub fn set_network_faults(&self, request: &SetNetworkFaultsRequest) -> Result<()> {
+        self.client
+            .set_network_faults(request)
+            .context("Unable to set network faults")?;
+        Ok(())
+    }
+
+    /// Adds `addresses` to the watch list of the node.
+    pub fn watch_accounts(&self, addresses: &[AccountAddress]) -> Result<()> {
+        let mut request = WatchAccountsRequest::default();
+        request.addresses = addresses.iter().map(|address| address.to_vec()).collect();
+        self.client
+            .watch_accounts(&request)
+            .context("Unable to watch accounts")?;
+        Ok(())
+    }
+
+    /// Removes `addresses` from the watch list of the node.
+    pub fn unwatch_accounts(&self, addresses: &[AccountAddress]) -> Result<()> {
+        let mut request = UnwatchAccountsRequest::default();
+        request.addresses = addresses.iter().map(|address| address.to_vec()).collect();
+        self.client
+            .unwatch_accounts(&request)
+            .context("Unable to unwatch accounts")?;
+        Ok(())
+    }
+
+    /// Returns the internal state of the mempool of the node, with up to `max_accounts` accounts,
+    /// the ones with the most transactions first.
+    pub fn get_mempool_state(&self, max_accounts: u32) -> Result<MempoolState> {
+        let mut request = GetMempoolStateRequest::default();
+        request.max_accounts = max_accounts;
+        let response = self
+            .client
+            .get_mempool_state(&request)
+            .context("Unable to query mempool state")?;
+        Ok(response.state.unwrap_or_default())
+    }
 }
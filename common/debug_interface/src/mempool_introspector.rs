@@ -0,0 +1,41 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Introspection of the mempool of the node.
+//!
+//! The debug interface is started before mempool, so that mempool registers itself with the
+//! introspector once it is running. Until then, and on nodes which don't run mempool, the debug
+//! interface reports that there is no mempool to look into.
+
+use crate::proto::MempoolState;
+use std::sync::{Arc, RwLock};
+
+/// Source of the state of mempool reported by the debug interface.
+pub trait MempoolIntrospection: Send + Sync {
+    /// State of mempool, with up to `max_accounts` accounts, the ones with the most transactions
+    /// first.
+    fn mempool_state(&self, max_accounts: usize) -> MempoolState;
+}
+
+/// Handle to the mempool of a node, if any. All the clones of a handle share the same mempool.
+#[derive(Clone, Default)]
+pub struct MempoolIntrospector {
+    mempool: Arc<RwLock<Option<Arc<dyn MempoolIntrospection>>>>,
+}
+
+impl MempoolIntrospector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the mempool of the node, replacing the previous one, if any.
+    pub fn register(&self, mempool: Arc<dyn MempoolIntrospection>) {
+        *self.mempool.write().unwrap() = Some(mempool);
+    }
+
+    /// State of the registered mempool, if any.
+    pub fn mempool_state(&self, max_accounts: usize) -> Option<MempoolState> {
+        let mempool = self.mempool.read().unwrap().clone();
+        mempool.map(|mempool| mempool.mempool_state(max_accounts))
+    }
+}
@@ -4,23 +4,114 @@
 //! Debug interface to access information in a specific node.
 
 use crate::{
+    account_watcher::AccountWatcher,
     json_log,
+    mempool_introspector::MempoolIntrospector,
     proto::{
-        Event, GetEventsRequest, GetEventsResponse, GetNodeDetailsRequest, GetNodeDetailsResponse,
-        NodeDebugInterface,
+        Event, GetEventsRequest, GetEventsResponse, GetMempoolStateRequest,
+        GetMempoolStateResponse, GetNodeDetailsRequest, GetNodeDetailsResponse,
+        GetWatchedAccountsRequest, GetWatchedAccountsResponse, NetworkFaultProfile,
+        NodeDebugInterface, SetNetworkFaultsRequest, SetNetworkFaultsResponse,
+        UnwatchAccountsRequest, UnwatchAccountsResponse, WatchAccountsRequest,
+        WatchAccountsResponse,
     },
 };
+use failure::prelude::*;
 use futures::Future;
+use grpcio::{RpcStatus, RpcStatusCode};
 use logger::prelude::*;
 use metrics::counters::COUNTER_ADMISSION_CONTROL_CANNOT_SEND_REPLY;
+use netcore::transport::fault::{FaultProfile, RandomFaults};
+use std::{convert::TryFrom, net::IpAddr, sync::Arc, time::Duration};
+use types::account_address::AccountAddress;
 
 #[derive(Clone, Default)]
-pub struct NodeDebugService {}
+pub struct NodeDebugService {
+    // Faults injected into the network connections of the node, if fault injection is enabled.
+    network_faults: Option<Arc<RandomFaults>>,
+    // Watch list of the accounts whose changes the node reports.
+    account_watcher: AccountWatcher,
+    // Mempool of the node, once it is running.
+    mempool_introspector: MempoolIntrospector,
+}
 
 impl NodeDebugService {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Debug service which can inject faults into the network connections of the node through
+    /// `network_faults`.
+    pub fn with_network_faults(network_faults: Arc<RandomFaults>) -> Self {
+        Self {
+            network_faults: Some(network_faults),
+            ..Default::default()
+        }
+    }
+
+    /// Lets the debug service manage the watch list of `account_watcher`.
+    pub fn with_account_watcher(mut self, account_watcher: AccountWatcher) -> Self {
+        self.account_watcher = account_watcher;
+        self
+    }
+
+    /// Lets the debug service report the state of the mempool registered with
+    /// `mempool_introspector`.
+    pub fn with_mempool_introspector(mut self, mempool_introspector: MempoolIntrospector) -> Self {
+        self.mempool_introspector = mempool_introspector;
+        self
+    }
+}
+
+// Number of accounts reported by `GetMempoolState` unless the request asks for another number.
+const DEFAULT_MAX_MEMPOOL_ACCOUNTS: usize = 100;
+
+fn to_fault_profile(profile: NetworkFaultProfile) -> Result<FaultProfile> {
+    let fault_profile = FaultProfile {
+        drop_probability: profile.drop_probability,
+        corrupt_probability: profile.corrupt_probability,
+        duplicate_probability: profile.duplicate_probability,
+        delay_probability: profile.delay_probability,
+        delay: Duration::from_millis(profile.delay_ms),
+    };
+    ensure!(
+        fault_profile.is_valid(),
+        "Fault probabilities must be between 0 and 1: {:?}",
+        profile
+    );
+    Ok(fault_profile)
+}
+
+fn set_network_faults(network_faults: &RandomFaults, req: SetNetworkFaultsRequest) -> Result<()> {
+    let default_profile = req
+        .default_profile
+        .map(to_fault_profile)
+        .transpose()?
+        .unwrap_or_default();
+    let profiles_by_ip = req
+        .profiles_by_ip
+        .into_iter()
+        .map(|(ip, profile)| {
+            let ip = ip
+                .parse::<IpAddr>()
+                .map_err(|e| format_err!("Invalid IP address {}: {}", ip, e))?;
+            Ok((ip, to_fault_profile(profile)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    network_faults.clear();
+    network_faults.set_default_profile(default_profile);
+    for (ip, profile) in profiles_by_ip {
+        network_faults.set_profile(ip, Some(profile));
+    }
+    Ok(())
+}
+
+fn to_addresses(addresses: Vec<Vec<u8>>) -> Result<Vec<AccountAddress>> {
+    addresses
+        .into_iter()
+        .map(AccountAddress::try_from)
+        .collect()
 }
 
 impl NodeDebugInterface for NodeDebugService {
@@ -54,6 +145,131 @@ impl NodeDebugInterface for NodeDebugService {
         }
         ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
     }
+
+    fn set_network_faults(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: SetNetworkFaultsRequest,
+        sink: ::grpcio::UnarySink<SetNetworkFaultsResponse>,
+    ) {
+        info!("[GRPC] set_network_faults: {:?}", req);
+        let result = match &self.network_faults {
+            Some(network_faults) => set_network_faults(network_faults, req)
+                .map_err(|e| RpcStatus::new(RpcStatusCode::INVALID_ARGUMENT, Some(e.to_string()))),
+            None => Err(RpcStatus::new(
+                RpcStatusCode::FAILED_PRECONDITION,
+                Some("Network fault injection is not enabled on this node".to_string()),
+            )),
+        };
+        match result {
+            Ok(()) => ctx.spawn(
+                sink.success(SetNetworkFaultsResponse::default())
+                    .map_err(default_reply_error_logger),
+            ),
+            Err(status) => ctx.spawn(sink.fail(status).map_err(default_reply_error_logger)),
+        }
+    }
+
+    fn watch_accounts(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: WatchAccountsRequest,
+        sink: ::grpcio::UnarySink<WatchAccountsResponse>,
+    ) {
+        info!("[GRPC] watch_accounts");
+        match to_addresses(req.addresses) {
+            Ok(addresses) => {
+                for address in addresses {
+                    if self.account_watcher.watch(address) {
+                        info!("Watching account {}", address);
+                    }
+                }
+                ctx.spawn(
+                    sink.success(WatchAccountsResponse::default())
+                        .map_err(default_reply_error_logger),
+                )
+            }
+            Err(e) => ctx.spawn(
+                sink.fail(RpcStatus::new(
+                    RpcStatusCode::INVALID_ARGUMENT,
+                    Some(e.to_string()),
+                ))
+                .map_err(default_reply_error_logger),
+            ),
+        }
+    }
+
+    fn unwatch_accounts(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: UnwatchAccountsRequest,
+        sink: ::grpcio::UnarySink<UnwatchAccountsResponse>,
+    ) {
+        info!("[GRPC] unwatch_accounts");
+        match to_addresses(req.addresses) {
+            Ok(addresses) => {
+                for address in addresses {
+                    if self.account_watcher.unwatch(&address) {
+                        info!("Stopped watching account {}", address);
+                    }
+                }
+                ctx.spawn(
+                    sink.success(UnwatchAccountsResponse::default())
+                        .map_err(default_reply_error_logger),
+                )
+            }
+            Err(e) => ctx.spawn(
+                sink.fail(RpcStatus::new(
+                    RpcStatusCode::INVALID_ARGUMENT,
+                    Some(e.to_string()),
+                ))
+                .map_err(default_reply_error_logger),
+            ),
+        }
+    }
+
+    fn get_watched_accounts(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        _req: GetWatchedAccountsRequest,
+        sink: ::grpcio::UnarySink<GetWatchedAccountsResponse>,
+    ) {
+        let mut response = GetWatchedAccountsResponse::default();
+        response.addresses = self
+            .account_watcher
+            .watched()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
+    }
+
+    fn get_mempool_state(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: GetMempoolStateRequest,
+        sink: ::grpcio::UnarySink<GetMempoolStateResponse>,
+    ) {
+        info!("[GRPC] get_mempool_state");
+        let max_accounts = match req.max_accounts {
+            0 => DEFAULT_MAX_MEMPOOL_ACCOUNTS,
+            max_accounts => max_accounts as usize,
+        };
+        match self.mempool_introspector.mempool_state(max_accounts) {
+            Some(state) => {
+                let mut response = GetMempoolStateResponse::default();
+                response.state = Some(state);
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
+            }
+            None => ctx.spawn(
+                sink.fail(RpcStatus::new(
+                    RpcStatusCode::FAILED_PRECONDITION,
+                    Some("Mempool is not running on this node".to_string()),
+                ))
+                .map_err(default_reply_error_logger),
+            ),
+        }
+    }
 }
 
 fn default_reply_error_logger<T: ::std::fmt::Debug>(e: T) {
@@ -0,0 +1,167 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Disk usage of a node, and the protective mode it enters when its disk is about to be full.
+//!
+//! RocksDB may leave its files corrupted when it runs out of space halfway through a write. To
+//! keep that from happening, the node stops taking in new data while the free space of the disk
+//! holding its storage is below a threshold: state synchronizer stops fetching chunks, consensus
+//! pauses its commits and admission control rejects transaction submissions. The node leaves the protective mode on its
+//! own once enough space is freed.
+
+use failure::prelude::*;
+use logger::prelude::*;
+use metrics::OpMetrics;
+use prometheus::{IntCounter, IntGauge};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use walkdir::WalkDir;
+
+lazy_static::lazy_static! {
+    static ref OP_COUNTERS: OpMetrics = OpMetrics::new_and_registered("disk");
+
+    /// Size of the files in the storage directory.
+    static ref STORAGE_DIR_SIZE_BYTES: IntGauge = OP_COUNTERS.gauge("storage_dir_size_bytes");
+
+    /// Space available on the disk holding the storage directory.
+    static ref FREE_SPACE_BYTES: IntGauge = OP_COUNTERS.gauge("free_space_bytes");
+
+    /// 1 while the node is in protective mode, 0 otherwise.
+    static ref PROTECTIVE_MODE: IntGauge = OP_COUNTERS.gauge("protective_mode");
+
+    /// Number of times the node entered protective mode.
+    static ref PROTECTIVE_MODE_ENTERED: IntCounter = OP_COUNTERS.counter("protective_mode_entered");
+}
+
+/// Disk usage of the storage of a node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DiskUsage {
+    pub dir_size_bytes: u64,
+    pub free_space_bytes: u64,
+}
+
+/// Handle to the disk usage monitor of a node. All the clones of a handle share the same
+/// protective mode.
+///
+/// The default handle monitors nothing and never enters protective mode.
+#[derive(Clone, Default)]
+pub struct DiskMonitor {
+    dir: PathBuf,
+    min_free_space_bytes: u64,
+    protective: Arc<AtomicBool>,
+}
+
+impl DiskMonitor {
+    /// Monitors the disk usage of `dir`, which is in protective mode while less than
+    /// `min_free_space_bytes` are available on its disk.
+    pub fn new<P: AsRef<Path>>(dir: P, min_free_space_bytes: u64) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            min_free_space_bytes,
+            protective: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the node must refrain from writing new data to its storage.
+    pub fn is_protective(&self) -> bool {
+        self.protective.load(Ordering::SeqCst)
+    }
+
+    /// Measures the disk usage, and enters or leaves protective mode accordingly.
+    pub fn check(&self) -> Result<DiskUsage> {
+        let free_space_bytes = fs2::available_space(&self.dir)?;
+        let dir_size_bytes = dir_size(&self.dir);
+        STORAGE_DIR_SIZE_BYTES.set(dir_size_bytes as i64);
+        FREE_SPACE_BYTES.set(free_space_bytes as i64);
+
+        let protective = free_space_bytes < self.min_free_space_bytes;
+        let was_protective = self.protective.swap(protective, Ordering::SeqCst);
+        if protective && !was_protective {
+            error!(
+                "Only {} bytes left on the disk of {:?}, less than the minimum of {} bytes. \
+                 Entering protective mode: no new data is accepted until space is freed.",
+                free_space_bytes, self.dir, self.min_free_space_bytes
+            );
+            PROTECTIVE_MODE_ENTERED.inc();
+        } else if !protective && was_protective {
+            info!(
+                "{} bytes available on the disk of {:?} again, leaving protective mode",
+                free_space_bytes, self.dir
+            );
+        }
+        PROTECTIVE_MODE.set(protective as i64);
+        Ok(DiskUsage {
+            dir_size_bytes,
+            free_space_bytes,
+        })
+    }
+
+    /// Checks the disk usage every `interval` on a dedicated thread, for the lifetime of the
+    /// process.
+    pub fn spawn(&self, interval: Duration) {
+        let monitor = self.clone();
+        thread::Builder::new()
+            .name("disk-monitor".to_string())
+            .spawn(move || loop {
+                if let Err(e) = monitor.check() {
+                    warn!("Failed to check the disk usage of {:?}: {}", monitor.dir, e);
+                }
+                thread::sleep(interval);
+            })
+            .expect("Failed to spawn the disk monitor thread");
+    }
+}
+
+/// Total size of the files under `dir`. Files which are deleted while they are walked, as RocksDB
+/// does during compactions, are skipped.
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tools::tempdir::TempPath;
+
+    #[test]
+    fn protective_mode() {
+        let dir = TempPath::new();
+        dir.create_as_dir().unwrap();
+        fs::write(dir.path().join("data"), [0u8; 1000]).unwrap();
+
+        let monitor = DiskMonitor::new(dir.path(), 0);
+        let usage = monitor.check().unwrap();
+        assert_eq!(usage.dir_size_bytes, 1000);
+        assert!(!monitor.is_protective());
+
+        // No disk has that much space left. Clones share the protective mode.
+        let full_monitor = DiskMonitor {
+            min_free_space_bytes: std::u64::MAX,
+            ..monitor.clone()
+        };
+        full_monitor.check().unwrap();
+        assert!(monitor.is_protective());
+
+        monitor.check().unwrap();
+        assert!(!full_monitor.is_protective());
+    }
+
+    #[test]
+    fn default_is_never_protective() {
+        assert!(!DiskMonitor::default().is_protective());
+    }
+}
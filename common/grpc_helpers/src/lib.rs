@@ -3,19 +3,41 @@
 
 use failure::{prelude::*, Result};
 use futures::{compat::Future01CompatExt, future::Future, prelude::*};
-use futures_01::future::Future as Future01;
-use grpcio::{ChannelBuilder, EnvBuilder, ServerBuilder};
+use futures_01::{future::Future as Future01, stream as stream01, Sink as Sink01};
+use grpcio::{Channel, ChannelBuilder, ConnectivityState, EnvBuilder, ServerBuilder, WriteFlags};
+use lazy_static::lazy_static;
 use logger::prelude::*;
-use metrics::counters::SVC_COUNTERS;
+use metrics::{counters::SVC_COUNTERS, OpMetrics};
 use std::{
+    ffi::CString,
     str::from_utf8,
     sync::{
         mpsc::{self, Sender},
-        Arc,
+        Arc, Mutex, Once,
     },
     thread, time,
 };
 
+#[cfg(test)]
+mod test;
+
+/// Interval between keepalive pings on internal channels. A ping which is not acknowledged within
+/// `KEEPALIVE_TIMEOUT` closes the connection, so a silently dropped TCP connection fails the
+/// in-flight calls and is re-established instead of hanging forever.
+const KEEPALIVE_TIME: time::Duration = time::Duration::from_secs(10);
+const KEEPALIVE_TIMEOUT: time::Duration = time::Duration::from_secs(20);
+/// Channels without any call for this long release their connection and reconnect on the next call.
+const IDLE_TIMEOUT: time::Duration = time::Duration::from_secs(300);
+/// Upper bound on the backoff between two reconnection attempts.
+const MAX_RECONNECT_BACKOFF: time::Duration = time::Duration::from_secs(5);
+/// How often the connectivity state of monitored channels is exported.
+const CHANNEL_STATE_POLL_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+lazy_static! {
+    static ref CHANNEL_COUNTERS: OpMetrics = OpMetrics::new_and_registered("grpc_channel");
+    static ref MONITORED_CHANNELS: Mutex<Vec<MonitoredChannel>> = Mutex::new(vec![]);
+}
+
 pub fn default_reply_error_logger<T: std::fmt::Debug>(e: T) {
     error!("Failed to reply error due to {:?}", e)
 }
@@ -53,6 +75,39 @@ pub fn provide_grpc_response<ResponseType: std::fmt::Debug>(
     SVC_COUNTERS.resp(&ctx, success);
 }
 
+/// Same as [`provide_grpc_response`], for calls streaming their responses: each response of
+/// `resp` is sent to the GRPC context in turn, then the stream is closed.
+pub fn provide_grpc_streaming_response<ResponseType: Send + 'static>(
+    resp: Result<Vec<ResponseType>>,
+    ctx: ::grpcio::RpcContext<'_>,
+    sink: ::grpcio::ServerStreamingSink<ResponseType>,
+) {
+    let mut success = true;
+    match resp {
+        Ok(resps) => {
+            let resps = stream01::iter_ok::<_, ::grpcio::Error>(
+                resps.into_iter().map(|resp| (resp, WriteFlags::default())),
+            );
+            let f = sink
+                .send_all(resps)
+                .map(|_| ())
+                .map_err(default_reply_error_logger);
+            ctx.spawn(f)
+        }
+        Err(e) => {
+            success = false;
+            let f = sink
+                .fail(create_grpc_invalid_arg_status(
+                    from_utf8(ctx.method()).expect("Unable to convert function name to string"),
+                    e,
+                ))
+                .map_err(default_reply_error_logger);
+            ctx.spawn(f)
+        }
+    }
+    SVC_COUNTERS.resp(&ctx, success);
+}
+
 pub fn spawn_service_thread(
     service: ::grpcio::Service,
     service_host_address: String,
@@ -81,14 +136,12 @@ where
     F: FnOnce() + 'static,
 {
     let env = Arc::new(EnvBuilder::new().name_prefix(service_name).build());
-    let mut builder = ServerBuilder::new(Arc::clone(&env));
+    let mut args = internal_server_channel_builder(Arc::clone(&env));
     if let Some(len) = service_max_recv_msg_len {
-        let args = ChannelBuilder::new(Arc::clone(&env))
-            .max_receive_message_len(len)
-            .build_args();
-        builder = builder.channel_args(args);
+        args = args.max_receive_message_len(len);
     }
-    let server = builder
+    let server = ServerBuilder::new(Arc::clone(&env))
+        .channel_args(args.build_args())
         .register_service(service)
         .bind(service_host_address, service_public_port)
         .build()
@@ -96,8 +149,83 @@ where
     ServerHandle::setup_with_drop_closure(server, Some(Box::new(service_drop_closure)))
 }
 
+/// Returns the channel arguments for servers of internal channels, which must accept the keepalive
+/// pings sent by [`connect_internal`] even when there is no call in flight.
+pub fn internal_server_channel_builder(env: Arc<grpcio::Environment>) -> ChannelBuilder {
+    ChannelBuilder::new(env)
+        .keepalive_permit_without_calls(true)
+        .http2_min_recv_ping_interval_without_data(KEEPALIVE_TIME / 2)
+}
+
+/// Connects to `address` with the keepalive, idle timeout and reconnect settings used for the
+/// long-lived channels between the components of a node, e.g. AC to mempool or any component to
+/// storage. The connectivity state of the channel is exported as the `<channel_name>.state`
+/// gauge of the `grpc_channel` metrics for as long as the process runs.
+pub fn connect_internal(builder: ChannelBuilder, address: &str, channel_name: &str) -> Channel {
+    let channel = builder
+        .keepalive_time(KEEPALIVE_TIME)
+        .keepalive_timeout(KEEPALIVE_TIMEOUT)
+        .keepalive_permit_without_calls(true)
+        .http2_max_pings_without_data(0)
+        .raw_cfg_int(
+            CString::new("grpc.client_idle_timeout_ms").unwrap(),
+            IDLE_TIMEOUT.as_millis() as i32,
+        )
+        .max_reconnect_backoff(MAX_RECONNECT_BACKOFF)
+        .connect(address);
+    monitor_channel(channel.clone(), channel_name);
+    channel
+}
+
+struct MonitoredChannel {
+    channel: Channel,
+    name: String,
+    state: ConnectivityState,
+}
+
+fn monitor_channel(channel: Channel, name: &str) {
+    static START_MONITOR: Once = Once::new();
+
+    let state = channel.check_connectivity_state(false);
+    CHANNEL_COUNTERS.set(&format!("{}.state", name), state as usize);
+    MONITORED_CHANNELS.lock().unwrap().push(MonitoredChannel {
+        channel,
+        name: name.to_string(),
+        state,
+    });
+    START_MONITOR.call_once(|| {
+        thread::Builder::new()
+            .name("grpc-channel-monitor".to_string())
+            .spawn(|| loop {
+                thread::sleep(CHANNEL_STATE_POLL_INTERVAL);
+                poll_channel_states();
+            })
+            .expect("Unable to spawn grpc channel monitor");
+    });
+}
+
+fn poll_channel_states() {
+    for monitored in MONITORED_CHANNELS.lock().unwrap().iter_mut() {
+        let state = monitored.channel.check_connectivity_state(false);
+        if state == monitored.state {
+            continue;
+        }
+        debug!(
+            "grpc channel {} changed state from {:?} to {:?}",
+            monitored.name, monitored.state, state
+        );
+        if state == ConnectivityState::GRPC_CHANNEL_TRANSIENT_FAILURE {
+            CHANNEL_COUNTERS.inc(&format!("{}.transient_failure", monitored.name));
+        }
+        CHANNEL_COUNTERS.set(&format!("{}.state", monitored.name), state as usize);
+        monitored.state = state;
+    }
+}
+
 pub struct ServerHandle {
-    stop_sender: Sender<()>,
+    /// Stops the server thread. The server is shut down gracefully if a sender is provided, which
+    /// is notified once it is done.
+    stop_sender: Sender<Option<Sender<()>>>,
     drop_closure: Option<Box<dyn FnOnce()>>,
 }
 
@@ -107,7 +235,7 @@ impl ServerHandle {
         drop_closure: Option<Box<dyn FnOnce()>>,
     ) -> Self {
         let (start_sender, start_receiver) = mpsc::channel();
-        let (stop_sender, stop_receiver) = mpsc::channel();
+        let (stop_sender, stop_receiver) = mpsc::channel::<Option<Sender<()>>>();
         let handle = Self {
             stop_sender,
             drop_closure,
@@ -116,7 +244,13 @@ impl ServerHandle {
             server.start();
             start_sender.send(()).unwrap();
             loop {
-                if stop_receiver.try_recv().is_ok() {
+                if let Ok(done_sender) = stop_receiver.try_recv() {
+                    if let Some(done_sender) = done_sender {
+                        if let Err(e) = server.shutdown().wait() {
+                            error!("Failed to shut down grpc server: {:?}", e);
+                        }
+                        let _ = done_sender.send(());
+                    }
                     return;
                 }
                 thread::sleep(time::Duration::from_millis(100));
@@ -129,11 +263,22 @@ impl ServerHandle {
     pub fn setup(server: ::grpcio::Server) -> Self {
         Self::setup_with_drop_closure(server, None)
     }
+
+    /// Stops accepting new calls and returns once the calls in flight are completed, whereas
+    /// dropping the handle cancels them.
+    pub fn shutdown(self) {
+        let (done_sender, done_receiver) = mpsc::channel();
+        self.stop_sender
+            .send(Some(done_sender))
+            .expect("Server thread exited before shutdown");
+        let _ = done_receiver.recv();
+    }
 }
 
 impl Drop for ServerHandle {
     fn drop(&mut self) {
-        self.stop_sender.send(()).unwrap();
+        // The server thread is already gone if the server was shut down.
+        let _ = self.stop_sender.send(None);
         if let Some(f) = self.drop_closure.take() {
             f()
         }
@@ -0,0 +1,79 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use grpcio::Server;
+use std::net::TcpListener;
+
+// Waits until the connectivity state of `channel` is `expected`, asking the channel to connect
+// if it is idle.
+fn wait_for_state(channel: &Channel, expected: ConnectivityState) {
+    let deadline = time::Instant::now() + time::Duration::from_secs(10);
+    while channel.check_connectivity_state(true) != expected {
+        assert!(
+            time::Instant::now() < deadline,
+            "channel did not reach {:?}",
+            expected
+        );
+        thread::sleep(time::Duration::from_millis(10));
+    }
+}
+
+fn start_server() -> (Server, u16) {
+    let env = Arc::new(EnvBuilder::new().build());
+    let mut server = ServerBuilder::new(Arc::clone(&env))
+        .channel_args(internal_server_channel_builder(env).build_args())
+        .bind("localhost", 0)
+        .build()
+        .expect("Unable to create grpc server");
+    server.start();
+    let (_, port) = server.bind_addrs()[0];
+    (server, port)
+}
+
+#[test]
+fn connect_internal_to_internal_server() {
+    let (_server, port) = start_server();
+    let env = Arc::new(EnvBuilder::new().build());
+    let channel = connect_internal(
+        ChannelBuilder::new(env),
+        &format!("localhost:{}", port),
+        "test-connected",
+    );
+
+    wait_for_state(&channel, ConnectivityState::GRPC_CHANNEL_READY);
+    poll_channel_states();
+    assert_eq!(
+        CHANNEL_COUNTERS.gauge("test-connected.state").get(),
+        ConnectivityState::GRPC_CHANNEL_READY as i64
+    );
+}
+
+#[test]
+fn transient_failure_exported() {
+    // Find a port nobody listens on.
+    let port = TcpListener::bind("localhost:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let env = Arc::new(EnvBuilder::new().build());
+    let channel = connect_internal(
+        ChannelBuilder::new(env),
+        &format!("localhost:{}", port),
+        "test-unreachable",
+    );
+
+    wait_for_state(&channel, ConnectivityState::GRPC_CHANNEL_TRANSIENT_FAILURE);
+    poll_channel_states();
+    assert_eq!(
+        CHANNEL_COUNTERS.gauge("test-unreachable.state").get(),
+        ConnectivityState::GRPC_CHANNEL_TRANSIENT_FAILURE as i64
+    );
+    assert!(
+        CHANNEL_COUNTERS
+            .counter("test-unreachable.transient_failure")
+            .get()
+            >= 1
+    );
+}
@@ -0,0 +1,179 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ledger context (epoch, round, version) attached to every log line.
+//!
+//! Every thread has its own [`LogContext`], which is used by synchronous code such as the block
+//! processor of the executor. A future can carry its own context across the threads it is polled
+//! on by wrapping it with [`with_log_context`]; while the future is polled, its context replaces
+//! the context of the thread.
+//!
+//! The fields of the current context are appended to every log line as `epoch`, `round` and
+//! `version` key-values, when set. They are read when the log macro is invoked, so this also works
+//! with the asynchronous drain.
+
+use slog::{BorrowedKV, Drain, OwnedKVList, Record, RecordStatic, Serializer, KV};
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// The ledger state a component is working on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogContext {
+    pub epoch: Option<u64>,
+    pub round: Option<u64>,
+    pub version: Option<u64>,
+}
+
+type SharedLogContext = Arc<Mutex<LogContext>>;
+
+thread_local! {
+    static CURRENT: RefCell<SharedLogContext> = RefCell::new(SharedLogContext::default());
+}
+
+/// Returns a snapshot of the current context.
+pub fn current() -> LogContext {
+    CURRENT.with(|current| *current.borrow().lock().unwrap())
+}
+
+/// Updates the current context in place.
+pub fn update(f: impl FnOnce(&mut LogContext)) {
+    CURRENT.with(|current| f(&mut current.borrow().lock().unwrap()))
+}
+
+pub fn set_epoch(epoch: u64) {
+    update(|context| context.epoch = Some(epoch));
+}
+
+pub fn set_round(round: u64) {
+    update(|context| context.round = Some(round));
+}
+
+pub fn set_version(version: u64) {
+    update(|context| context.version = Some(version));
+}
+
+/// Gives `future` its own context, starting from a copy of the current one. Updates made while
+/// polling `future` are only visible to `future`.
+pub fn with_log_context<F: Future>(future: F) -> WithLogContext<F> {
+    WithLogContext {
+        inner: future,
+        context: Arc::new(Mutex::new(current())),
+    }
+}
+
+/// Future returned by [`with_log_context`].
+pub struct WithLogContext<F> {
+    inner: F,
+    context: SharedLogContext,
+}
+
+impl<F: Future> Future for WithLogContext<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // This is safe since `inner` is never moved out of the pinned `WithLogContext`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        let prev = CURRENT.with(|current| current.replace(Arc::clone(&this.context)));
+        let poll = inner.poll(cx);
+        CURRENT.with(|current| current.replace(prev));
+        poll
+    }
+}
+
+impl KV for LogContext {
+    fn serialize(&self, _record: &Record, serializer: &mut dyn Serializer) -> slog::Result {
+        if let Some(epoch) = self.epoch {
+            serializer.emit_u64("epoch", epoch)?;
+        }
+        if let Some(round) = self.round {
+            serializer.emit_u64("round", round)?;
+        }
+        if let Some(version) = self.version {
+            serializer.emit_u64("version", version)?;
+        }
+        Ok(())
+    }
+}
+
+/// The key-values of a record followed by the context.
+struct RecordKV<'a> {
+    record_kv: BorrowedKV<'a>,
+    context: LogContext,
+}
+
+impl<'a> KV for RecordKV<'a> {
+    fn serialize(&self, record: &Record, serializer: &mut dyn Serializer) -> slog::Result {
+        self.record_kv.0.serialize(record, serializer)?;
+        self.context.serialize(record, serializer)
+    }
+}
+
+/// A `Drain` which appends the current context to the key-values of each record.
+pub struct ContextDrain<D> {
+    drain: D,
+}
+
+impl<D> ContextDrain<D> {
+    pub fn new(drain: D) -> Self {
+        Self { drain }
+    }
+}
+
+impl<D: Drain> Drain for ContextDrain<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let kv = RecordKV {
+            record_kv: record.kv(),
+            context: current(),
+        };
+        let record_static = RecordStatic {
+            location: record.location(),
+            tag: record.tag(),
+            level: record.level(),
+        };
+        self.drain.log(
+            &Record::new(&record_static, record.msg(), BorrowedKV(&kv)),
+            values,
+        )
+    }
+
+    fn is_enabled(&self, level: slog::Level) -> bool {
+        self.drain.is_enabled(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_task_context() {
+        set_epoch(1);
+        let (tx, rx) = mpsc::channel();
+        let future = with_log_context(async move {
+            set_round(2);
+            tx.send(current()).unwrap();
+        });
+        // The future starts from a copy of the thread's context, but does not modify it.
+        block_on(future);
+        assert_eq!(
+            rx.recv().unwrap(),
+            LogContext {
+                epoch: Some(1),
+                round: Some(2),
+                version: None,
+            }
+        );
+        assert_eq!(current().round, None);
+    }
+}
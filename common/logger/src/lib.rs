@@ -23,12 +23,13 @@
 extern crate slog;
 
 mod collector_serializer;
+pub mod context;
 mod glog_format;
 mod kv_categorizer;
 mod security;
 mod simple_logger;
 
-use crate::kv_categorizer::ErrorCategorizer;
+use crate::{context::ContextDrain, kv_categorizer::ErrorCategorizer};
 use glog_format::GlogFormat;
 use lazy_static::lazy_static;
 use slog::{o, Discard, Drain, FilterLevel, Logger, Never};
@@ -94,7 +95,7 @@ where
 fn create_test_root_logger() -> Logger {
     let drain = GlogFormat::new(TermDecorator::new().build(), ErrorCategorizer).fuse();
     let envlogger = create_env_logger_with_level(drain, FilterLevel::Debug);
-    Logger::root(Mutex::new(envlogger).fuse(), o!())
+    Logger::root(Mutex::new(ContextDrain::new(envlogger)).fuse(), o!())
 }
 
 // TODO: redo this
@@ -137,8 +138,10 @@ where
             Some(chan_size_inner) => Async::new(drain).chan_size(chan_size_inner),
             None => Async::new(drain),
         };
-        Logger::root(async_builder.build().fuse(), o!())
+        // The context must be captured before the record is handed over to the async drain's
+        // thread.
+        Logger::root(ContextDrain::new(async_builder.build()).fuse(), o!())
     } else {
-        Logger::root(Mutex::new(drain).fuse(), o!())
+        Logger::root(ContextDrain::new(Mutex::new(drain)).fuse(), o!())
     }
 }
@@ -9,6 +9,7 @@ extern crate prometheus;
 pub mod counters;
 mod json_encoder;
 pub mod metric_server;
+pub mod telemetry;
 
 mod service_metrics;
 pub use service_metrics::ServiceMetrics;
@@ -39,6 +39,7 @@ pub struct OpMetrics {
     counters: IntCounterVec,
     gauges: IntGaugeVec,
     peer_gauges: IntGaugeVec,
+    protocol_counters: IntCounterVec,
     duration_histograms: HistogramVec,
 }
 
@@ -68,6 +69,14 @@ impl OpMetrics {
                 &["op", "remote_peer_id"],
             )
             .unwrap(),
+            protocol_counters: IntCounterVec::new(
+                Opts::new(
+                    format!("{}_protocol_counter", name_str.clone()),
+                    format!("Counters of each protocol and remote peer for {}", name_str),
+                ),
+                &["op", "protocol", "remote_peer_id"],
+            )
+            .unwrap(),
             duration_histograms: HistogramVec::new(
                 HistogramOpts::new(
                     format!("{}_duration", name_str.clone()),
@@ -96,6 +105,21 @@ impl OpMetrics {
         self.peer_gauges.with_label_values(&[name, remote_peer_id])
     }
 
+    /// Stops exporting the gauge `name` of `remote_peer_id`, e.g. once the peer is gone, so that
+    /// the peer gauges don't grow with every peer ever seen.
+    pub fn remove_peer_gauge(&self, name: &str, remote_peer_id: &str) {
+        // The gauge is missing if it was never set.
+        let _ = self
+            .peer_gauges
+            .remove_label_values(&[name, remote_peer_id]);
+    }
+
+    #[inline]
+    pub fn protocol_counter(&self, name: &str, protocol: &str, remote_peer_id: &str) -> IntCounter {
+        self.protocol_counters
+            .with_label_values(&[name, protocol, remote_peer_id])
+    }
+
     #[inline]
     pub fn counter(&self, name: &str) -> IntCounter {
         self.counters.with_label_values(&[name])
@@ -110,6 +134,21 @@ impl OpMetrics {
         DurationHistogram::new(self.duration_histograms.with_label_values(&[name]))
     }
 
+    /// Exports the fields of the current log context (see `logger::context`) as the
+    /// `context.epoch`, `context.round` and `context.version` gauges.
+    pub fn export_log_context(&self) {
+        let context = logger::context::current();
+        if let Some(epoch) = context.epoch {
+            self.set("context.epoch", epoch as usize);
+        }
+        if let Some(round) = context.round {
+            self.set("context.round", round as usize);
+        }
+        if let Some(version) = context.version {
+            self.set("context.version", version as usize);
+        }
+    }
+
     #[inline]
     pub fn inc(&self, op: &str) {
         self.counters.with_label_values(&[op]).inc();
@@ -159,19 +198,21 @@ impl OpMetrics {
 
 impl Collector for OpMetrics {
     fn desc(&self) -> Vec<&Desc> {
-        let mut ms = Vec::with_capacity(4);
+        let mut ms = Vec::with_capacity(5);
         ms.extend(self.counters.desc());
         ms.extend(self.gauges.desc());
         ms.extend(self.peer_gauges.desc());
+        ms.extend(self.protocol_counters.desc());
         ms.extend(self.duration_histograms.desc());
         ms
     }
 
     fn collect(&self) -> Vec<MetricFamily> {
-        let mut ms = Vec::with_capacity(4);
+        let mut ms = Vec::with_capacity(5);
         ms.extend(self.counters.collect());
         ms.extend(self.gauges.collect());
         ms.extend(self.peer_gauges.collect());
+        ms.extend(self.protocol_counters.collect());
         ms.extend(self.duration_histograms.collect());
         ms
     }
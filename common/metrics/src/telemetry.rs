@@ -0,0 +1,127 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opt-in reporting of the health of the node to a collector run by the operators of a network,
+//! so that they can follow the health of the ecosystem during testnets.
+//!
+//! Reports are anonymized: they are built from a fixed whitelist of fields, all of them either
+//! numbers or the version and role of the node, and never carry the peer id, the addresses or the
+//! accounts of the node, nor any other metric.
+
+use failure::prelude::*;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    rt::{self, Future, Stream},
+    Body, Client, Method, Request, Uri,
+};
+use hyper_rustls::HttpsConnector;
+use logger::prelude::*;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{prelude::FutureExt, timer::Interval};
+
+const CONNECTED_PEERS: &str = "network_gauge{op=connected_peers}";
+const COMMITTED_VERSION: &str = "state_sync_gauge{op=committed_version}";
+const TARGET_VERSION: &str = "state_sync_gauge{op=target_version}";
+
+/// Builds the reports of the health of the node and sends them to the collector.
+pub struct TelemetryReporter {
+    version: String,
+    role: &'static str,
+    // Committed version at the time of the previous report, which the commit rate is derived from.
+    last_committed: Option<(u64, Instant)>,
+}
+
+impl TelemetryReporter {
+    pub fn new(version: String, role: &'static str) -> Self {
+        Self {
+            version,
+            role,
+            last_committed: None,
+        }
+    }
+
+    /// Builds the report of the health of the node out of `metrics`, as returned by
+    /// [`get_all_metrics`](crate::get_all_metrics), at time `now`. Only the whitelisted fields
+    /// make it into the report.
+    pub fn report(&mut self, metrics: &HashMap<String, String>, now: Instant) -> Value {
+        let gauge = |name| {
+            metrics
+                .get(name)
+                .and_then(|value| value.parse::<f64>().ok())
+                .map_or(0, |value| value.max(0.0) as u64)
+        };
+        let committed_version = gauge(COMMITTED_VERSION);
+        let commit_rate = match self.last_committed {
+            Some((last_version, last_time)) if now > last_time => {
+                committed_version.saturating_sub(last_version) as f64
+                    / (now - last_time).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        self.last_committed = Some((committed_version, now));
+
+        json!({
+            "version": self.version,
+            "role": self.role,
+            "sync_lag": gauge(TARGET_VERSION).saturating_sub(committed_version),
+            "connected_peers": gauge(CONNECTED_PEERS),
+            "commits_per_sec": commit_rate,
+        })
+    }
+
+    /// Spawns a thread POSTing a report to `collector_url`, an https URL, every `interval`. The
+    /// certificate of the collector is verified against the webpki roots. A report the collector
+    /// fails to receive is dropped.
+    pub fn start(mut self, collector_url: &str, interval: Duration) -> Result<()> {
+        let uri: Uri = collector_url.parse()?;
+        ensure!(
+            uri.scheme_part().is_some() && uri.host().is_some(),
+            "Telemetry collector URL must be absolute"
+        );
+        ensure!(
+            uri.scheme_str() == Some("https"),
+            "Telemetry collector URL must be an https URL"
+        );
+        // The URL may carry credentials of the operator, so only its host is ever logged.
+        info!(
+            "Reporting telemetry to {} every {} ms",
+            uri.host().unwrap_or_default(),
+            interval.as_millis()
+        );
+
+        thread::Builder::new()
+            .name("telemetry".to_string())
+            .spawn(move || {
+                let client = Client::builder().build::<_, Body>(HttpsConnector::new(1));
+                let reports = Interval::new(Instant::now() + interval, interval)
+                    .map_err(|e| error!("Telemetry timer failed: {}", e))
+                    .for_each(move |now| {
+                        let report = self.report(&crate::get_all_metrics(), now);
+                        let mut request = Request::new(Body::from(report.to_string()));
+                        *request.method_mut() = Method::POST;
+                        *request.uri_mut() = uri.clone();
+                        request
+                            .headers_mut()
+                            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                        client.request(request).timeout(interval).then(|result| {
+                            match result {
+                                Ok(response) if !response.status().is_success() => warn!(
+                                    "Telemetry collector rejected the report: {}",
+                                    response.status()
+                                ),
+                                Ok(_) => (),
+                                Err(e) => warn!("Failed to send telemetry report: {}", e),
+                            }
+                            Ok(())
+                        })
+                    });
+                rt::run(reports);
+            })?;
+        Ok(())
+    }
+}
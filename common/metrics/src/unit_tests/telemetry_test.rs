@@ -0,0 +1,61 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::telemetry::TelemetryReporter;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+fn metrics(committed_version: u64, target_version: u64) -> HashMap<String, String> {
+    let mut metrics = HashMap::new();
+    for (name, value) in &[
+        ("network_gauge{op=connected_peers}", 4),
+        ("state_sync_gauge{op=committed_version}", committed_version),
+        ("state_sync_gauge{op=target_version}", target_version),
+        ("mempool{op=get_block}", 12),
+    ] {
+        metrics.insert(name.to_string(), value.to_string());
+    }
+    metrics
+}
+
+#[test]
+fn test_report() {
+    let mut reporter = TelemetryReporter::new("0.1.0".to_string(), "validator");
+    let start = Instant::now();
+    assert_eq!(
+        reporter.report(&metrics(100, 150), start),
+        json!({
+            "version": "0.1.0",
+            "role": "validator",
+            "sync_lag": 50,
+            "connected_peers": 4,
+            "commits_per_sec": 0.0,
+        })
+    );
+
+    // The commit rate is derived from the versions committed since the previous report, and the
+    // metrics out of the whitelist never make it into the report.
+    let report = reporter.report(&metrics(300, 300), start + Duration::from_secs(10));
+    assert_eq!(report["sync_lag"], 0);
+    assert_eq!(report["commits_per_sec"], 20.0);
+    assert_eq!(report.as_object().unwrap().len(), 5);
+
+    // Missing metrics are reported as zero.
+    let report = reporter.report(&HashMap::new(), start + Duration::from_secs(20));
+    assert_eq!(report["connected_peers"], 0);
+    assert_eq!(report["commits_per_sec"], 0.0);
+}
+
+#[test]
+fn test_start_requires_https() {
+    let reporter = || TelemetryReporter::new("0.1.0".to_string(), "validator");
+    let interval = Duration::from_secs(300);
+    assert!(reporter().start("collector/report", interval).is_err());
+    // The reports are never sent in the clear.
+    assert!(reporter()
+        .start("http://collector.example.com/report", interval)
+        .is_err());
+}
@@ -1,6 +1,8 @@
 use bytes::{Bytes, BytesMut};
 use prost::{EncodeError, Message};
 
+pub mod wire;
+
 impl<T: ?Sized> MessageExt for T where T: Message {}
 
 pub trait MessageExt: Message {
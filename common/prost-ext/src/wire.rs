@@ -0,0 +1,154 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Walks the fields of an encoded protobuf message without decoding them.
+//!
+//! prost decodes `bytes` fields into `Vec<u8>`, which copies every payload out of the received
+//! buffer. Large messages, such as chunks of transactions, can instead be walked field by field:
+//! the values of length-delimited fields are handed out as slices of the original `Bytes`, which
+//! share its memory, and can be deserialized straight into the final types.
+
+use bytes::Bytes;
+use prost::{
+    encoding::{decode_key, decode_varint, WireType},
+    DecodeError,
+};
+use std::io::Cursor;
+
+/// A single field of an encoded message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WireField {
+    pub tag: u32,
+    pub wire_type: WireType,
+    /// The whole encoded field, key included. Merging it into a message decodes the field as
+    /// `Message::decode` would.
+    pub raw: Bytes,
+    /// The encoded value, without its key, nor its length for length-delimited fields.
+    pub value: Bytes,
+}
+
+impl WireField {
+    /// The value of a length-delimited field, i.e. of a `bytes`, `string` or message field.
+    pub fn length_delimited(&self) -> Result<Bytes, DecodeError> {
+        if self.wire_type != WireType::LengthDelimited {
+            return Err(DecodeError::new(format!(
+                "invalid wire type for field {}: {:?} (expected {:?})",
+                self.tag,
+                self.wire_type,
+                WireType::LengthDelimited
+            )));
+        }
+        Ok(self.value.clone())
+    }
+}
+
+/// Iterator over the fields of an encoded message, in the order they are encoded. Stops after
+/// the first malformed field.
+pub struct WireFields {
+    bytes: Bytes,
+    pos: usize,
+}
+
+/// Walks the fields of the message encoded in `bytes`.
+pub fn wire_fields(bytes: Bytes) -> WireFields {
+    WireFields { bytes, pos: 0 }
+}
+
+impl WireFields {
+    fn next_field(&mut self) -> Result<WireField, DecodeError> {
+        let start = self.pos;
+        let mut cursor = Cursor::new(&self.bytes[start..]);
+        let (tag, wire_type) = decode_key(&mut cursor)?;
+        let value_len = match wire_type {
+            WireType::Varint => {
+                // Varints are handed out whole, their length is only measured.
+                let mut varint = cursor.clone();
+                decode_varint(&mut varint)?;
+                (varint.position() - cursor.position()) as usize
+            }
+            WireType::SixtyFourBit => 8,
+            WireType::ThirtyTwoBit => 4,
+            WireType::LengthDelimited => decode_varint(&mut cursor)? as usize,
+            WireType::StartGroup | WireType::EndGroup => {
+                return Err(DecodeError::new("groups are not supported"));
+            }
+        };
+        let value_start = start + cursor.position() as usize;
+        if value_len > self.bytes.len() - value_start {
+            return Err(DecodeError::new("buffer underflow"));
+        }
+        let end = value_start + value_len;
+        self.pos = end;
+        Ok(WireField {
+            tag,
+            wire_type,
+            raw: self.bytes.slice(start, end),
+            value: self.bytes.slice(value_start, end),
+        })
+    }
+}
+
+impl Iterator for WireFields {
+    type Item = Result<WireField, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.bytes.len() {
+            return None;
+        }
+        let field = self.next_field();
+        if field.is_err() {
+            self.pos = self.bytes.len();
+        }
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageExt;
+    use prost::Message;
+
+    #[derive(Clone, PartialEq, Message)]
+    struct TestMessage {
+        #[prost(uint64, tag = "1")]
+        version: u64,
+        #[prost(bytes, repeated, tag = "2")]
+        payloads: Vec<Vec<u8>>,
+        #[prost(fixed32, tag = "3")]
+        checksum: u32,
+    }
+
+    #[test]
+    fn walk_fields() {
+        let msg = TestMessage {
+            version: 300,
+            payloads: vec![vec![1, 2, 3], vec![4; 200]],
+            checksum: 7,
+        };
+        let bytes = msg.to_bytes().unwrap();
+
+        let fields = wire_fields(bytes.clone())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            fields.iter().map(|field| field.tag).collect::<Vec<_>>(),
+            vec![1, 2, 2, 3]
+        );
+        assert_eq!(fields[1].length_delimited().unwrap(), &[1, 2, 3][..]);
+        assert_eq!(fields[2].length_delimited().unwrap(), &[4; 200][..]);
+        assert!(fields[0].length_delimited().is_err());
+
+        // Merging the raw fields back gives the original message.
+        let mut merged = TestMessage::default();
+        for field in &fields {
+            merged.merge(field.raw.clone()).unwrap();
+        }
+        assert_eq!(merged, msg);
+
+        // Truncated messages are rejected.
+        let mut truncated = wire_fields(bytes.slice_to(bytes.len() - 10));
+        assert!(truncated.any(|field| field.is_err()));
+        assert!(truncated.next().is_none());
+    }
+}
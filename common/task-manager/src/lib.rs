@@ -0,0 +1,259 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Spawning of the long-running tasks of the components of a node (network, consensus, state
+//! sync, shared mempool) on their tokio runtimes.
+//!
+//! A [`TaskManager`] names each task it spawns, so that the logs tell which task of which
+//! component terminated or panicked, and registers it with a [`CancellationToken`] shared by all
+//! the tasks of the component, so that they can all be stopped together, e.g. when the component
+//! shuts down while other components keep using the same runtime. Tokio swallows the panics of
+//! the tasks it runs; the task manager catches them instead and logs them along with the name of
+//! the task.
+
+use futures::future::{AbortHandle, Abortable, Future, FutureExt, TryFutureExt};
+use logger::prelude::*;
+use std::{
+    any::Any,
+    collections::HashMap,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+};
+use tokio::runtime::{Builder, Runtime, TaskExecutor};
+
+/// Builds a runtime whose threads are named after `name_prefix`, which logs the panics escaping
+/// the tasks it runs instead of silently dropping them.
+pub fn build_runtime(name_prefix: &str) -> Runtime {
+    let component = name_prefix.trim_end_matches('-').to_string();
+    Builder::new()
+        .name_prefix(name_prefix)
+        .panic_handler(move |panic| {
+            crit!(
+                "[{}] task panicked: {}",
+                component,
+                panic_message(panic.as_ref())
+            )
+        })
+        .build()
+        .unwrap_or_else(|e| panic!("[{}] failed to create runtime: {}", name_prefix, e))
+}
+
+/// Cancels all the tasks registered with it at once. Cloning it is cheap and all clones cancel
+/// the same tasks.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    state: Arc<Mutex<TokenState>>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: bool,
+    next_task_id: u64,
+    // handles of the tasks which are still running
+    tasks: HashMap<u64, AbortHandle>,
+}
+
+impl CancellationToken {
+    /// Creates a token with no task registered yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aborts all the tasks registered with the token, which are dropped the next time they
+    /// would be polled. Tasks registered afterwards are aborted right away.
+    pub fn cancel(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("Failed to lock cancellation token");
+        state.cancelled = true;
+        for (_, task) in state.tasks.drain() {
+            task.abort();
+        }
+    }
+
+    /// Whether [`cancel`](CancellationToken::cancel) was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.state
+            .lock()
+            .expect("Failed to lock cancellation token")
+            .cancelled
+    }
+
+    /// Number of registered tasks which are still running.
+    pub fn num_tasks(&self) -> usize {
+        self.state
+            .lock()
+            .expect("Failed to lock cancellation token")
+            .tasks
+            .len()
+    }
+
+    fn register(&self, task: AbortHandle) -> u64 {
+        let mut state = self
+            .state
+            .lock()
+            .expect("Failed to lock cancellation token");
+        if state.cancelled {
+            task.abort();
+        }
+        let id = state.next_task_id;
+        state.next_task_id += 1;
+        state.tasks.insert(id, task);
+        id
+    }
+
+    fn unregister(&self, id: u64) {
+        self.state
+            .lock()
+            .expect("Failed to lock cancellation token")
+            .tasks
+            .remove(&id);
+    }
+}
+
+/// Spawns the named tasks of a component onto an executor. See the
+/// [crate documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct TaskManager {
+    component: &'static str,
+    executor: TaskExecutor,
+    token: CancellationToken,
+}
+
+impl TaskManager {
+    /// Creates a manager spawning the tasks of `component` onto `executor`, with a new
+    /// cancellation token.
+    pub fn new(component: &'static str, executor: TaskExecutor) -> Self {
+        Self::with_token(component, executor, CancellationToken::new())
+    }
+
+    /// Same as [`new`](TaskManager::new), with the tasks registered with `token`, e.g. the
+    /// token of a parent component which cancels them along with its own.
+    pub fn with_token(
+        component: &'static str,
+        executor: TaskExecutor,
+        token: CancellationToken,
+    ) -> Self {
+        Self {
+            component,
+            executor,
+            token,
+        }
+    }
+
+    /// The token cancelling the tasks spawned by this manager.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// The executor the tasks are spawned onto.
+    pub fn executor(&self) -> &TaskExecutor {
+        &self.executor
+    }
+
+    /// Spawns `task` under `name`. The task runs until it completes, panics, or the token of the
+    /// manager is cancelled, whichever comes first.
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (handle, registration) = AbortHandle::new_pair();
+        let id = self.token.register(handle);
+        let token = self.token.clone();
+        let component = self.component;
+        let task = Abortable::new(AssertUnwindSafe(task).catch_unwind(), registration).map(
+            move |result| {
+                token.unregister(id);
+                match result {
+                    Ok(Ok(())) => debug!("[{}] task {} terminated", component, name),
+                    Ok(Err(panic)) => crit!(
+                        "[{}] task {} panicked: {}",
+                        component,
+                        name,
+                        panic_message(panic.as_ref())
+                    ),
+                    Err(_) => debug!("[{}] task {} cancelled", component, name),
+                }
+            },
+        );
+        self.executor.spawn(task.boxed().unit_error().compat());
+    }
+
+    /// Cancels all the tasks spawned by this manager, and any other task registered with its
+    /// token.
+    pub fn shutdown(&self) {
+        info!(
+            "[{}] cancelling {} tasks",
+            self.component,
+            self.token.num_tasks()
+        );
+        self.token.cancel();
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{channel::oneshot, compat::Future01CompatExt, executor::block_on};
+    use std::time::{Duration, Instant};
+    use tokio::timer::Delay;
+
+    #[test]
+    fn cancel_running_tasks() {
+        let runtime = build_runtime("test-");
+        let manager = TaskManager::new("test", runtime.executor());
+
+        let (done_sender, done_receiver) = oneshot::channel();
+        manager.spawn("short", async move {
+            done_sender.send(()).unwrap();
+        });
+        block_on(done_receiver).unwrap();
+
+        // the task holds the sender until it is dropped, which only cancelling it does
+        let (sender, receiver) = oneshot::channel::<()>();
+        manager.spawn("long", async move {
+            let _sender = sender;
+            futures::future::pending::<()>().await;
+        });
+        manager.shutdown();
+        assert!(block_on(receiver).is_err());
+        assert!(manager.token().is_cancelled());
+        assert_eq!(manager.token().num_tasks(), 0);
+
+        // tasks spawned after the cancellation never run
+        let (sender, receiver) = oneshot::channel();
+        manager.spawn("late", async move {
+            sender.send(()).unwrap();
+        });
+        assert!(block_on(receiver).is_err());
+    }
+
+    #[test]
+    fn catch_panics() {
+        let runtime = build_runtime("test-");
+        let manager = TaskManager::new("test", runtime.executor());
+        manager.spawn("panicking", async { panic!("expected panic") });
+
+        // the panic does not take the runtime down
+        let (sender, receiver) = oneshot::channel();
+        manager.spawn("healthy", async move {
+            Delay::new(Instant::now() + Duration::from_millis(10))
+                .compat()
+                .await
+                .unwrap();
+            sender.send(()).unwrap();
+        });
+        block_on(receiver).unwrap();
+    }
+}
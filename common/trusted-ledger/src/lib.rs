@@ -0,0 +1,151 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The latest ledger info verified by a node, shared between its components.
+//!
+//! Consensus and state synchronizer record here every ledger info they commit. The other
+//! components (e.g. admission control, or state synchronizer serving its peers) read it instead of
+//! each keeping their own notion of the current version of the ledger.
+//!
+//! State synchronizer also records the version of the verified ledger infos its peers advertise,
+//! so that the components can tell how far the node lags behind the network.
+
+use std::sync::{Arc, Mutex, RwLock};
+use types::{
+    crypto_proxies::LedgerInfoWithSignatures, ledger_info::LedgerInfo, transaction::Version,
+};
+
+/// Handle to the latest trusted ledger info of a node. All the clones of a handle share the same
+/// ledger info.
+#[derive(Clone, Default)]
+pub struct TrustedLedger {
+    latest: Arc<RwLock<Option<LedgerInfoWithSignatures>>>,
+    // highest version of the verified ledger infos of the peers of the node
+    network_version: Arc<Mutex<Version>>,
+}
+
+impl TrustedLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `ledger_info_with_sigs` as the latest trusted ledger info, unless it is not newer
+    /// than the current one. Returns whether it was recorded.
+    ///
+    /// The caller is responsible for only passing ledger infos which are verified and whose
+    /// transactions are committed to storage.
+    pub fn update(&self, ledger_info_with_sigs: LedgerInfoWithSignatures) -> bool {
+        let mut latest = self.latest.write().unwrap();
+        let is_newer = latest.as_ref().map_or(true, |latest| {
+            is_newer(ledger_info_with_sigs.ledger_info(), latest.ledger_info())
+        });
+        if is_newer {
+            *latest = Some(ledger_info_with_sigs);
+        }
+        is_newer
+    }
+
+    /// The latest trusted ledger info, if any was recorded yet.
+    pub fn latest(&self) -> Option<LedgerInfoWithSignatures> {
+        self.latest.read().unwrap().clone()
+    }
+
+    /// Version of the latest trusted ledger info.
+    pub fn version(&self) -> Option<Version> {
+        self.latest
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|latest| latest.ledger_info().version())
+    }
+
+    /// Records `version` as the version of a ledger info a peer advertised, unless a higher one
+    /// was recorded before.
+    ///
+    /// The caller is responsible for only passing the versions of verified ledger infos, so that
+    /// peers can't make the node look behind.
+    pub fn update_network_version(&self, version: Version) {
+        let mut network_version = self.network_version.lock().unwrap();
+        *network_version = std::cmp::max(*network_version, version);
+    }
+
+    /// Highest version advertised by the peers of the node. Nothing is known about the network
+    /// until a peer advertises its version, e.g. on a node without peers, so that it is 0 then.
+    pub fn network_version(&self) -> Version {
+        *self.network_version.lock().unwrap()
+    }
+
+    /// Epoch of the latest trusted ledger info.
+    pub fn epoch(&self) -> Option<u64> {
+        self.latest
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|latest| latest.ledger_info().epoch_num())
+    }
+}
+
+/// A ledger info of a later epoch is newer. Within an epoch, blocks without transactions commit
+/// ledger infos of the same version, so the timestamp breaks the tie.
+fn is_newer(ledger_info: &LedgerInfo, than: &LedgerInfo) -> bool {
+    (
+        ledger_info.epoch_num(),
+        ledger_info.version(),
+        ledger_info.timestamp_usecs(),
+    ) > (than.epoch_num(), than.version(), than.timestamp_usecs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::HashValue;
+    use std::collections::HashMap;
+
+    fn ledger_info(epoch: u64, version: Version, timestamp_usecs: u64) -> LedgerInfoWithSignatures {
+        LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                version,
+                HashValue::zero(),
+                HashValue::zero(),
+                HashValue::zero(),
+                epoch,
+                timestamp_usecs,
+                None,
+            ),
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn only_newer_updates() {
+        let trusted_ledger = TrustedLedger::new();
+        assert_eq!(trusted_ledger.version(), None);
+
+        assert!(trusted_ledger.update(ledger_info(0, 10, 100)));
+        // Clones share the ledger info.
+        let clone = trusted_ledger.clone();
+        assert_eq!(clone.version(), Some(10));
+
+        assert!(!clone.update(ledger_info(0, 9, 200)));
+        assert!(!clone.update(ledger_info(0, 10, 100)));
+        assert_eq!(trusted_ledger.latest(), Some(ledger_info(0, 10, 100)));
+
+        // An empty block commits the same version later on.
+        assert!(clone.update(ledger_info(0, 10, 150)));
+        assert_eq!(trusted_ledger.latest(), Some(ledger_info(0, 10, 150)));
+
+        assert!(clone.update(ledger_info(1, 10, 120)));
+        assert_eq!(trusted_ledger.epoch(), Some(1));
+    }
+
+    #[test]
+    fn only_higher_network_versions_update() {
+        let trusted_ledger = TrustedLedger::new();
+        assert_eq!(trusted_ledger.network_version(), 0);
+
+        trusted_ledger.update_network_version(100);
+        // Clones share the network version.
+        trusted_ledger.clone().update_network_version(50);
+        assert_eq!(trusted_ledger.network_version(), 100);
+    }
+}
@@ -25,9 +25,14 @@ struct Args {
     #[structopt(short = "s", long)]
     /// Use the provided seed for generating keys for each of the validators
     key_seed: Option<String>,
-    #[structopt(short = "m", long)]
-    /// File location from which to load faucet account generated via generate_keypair tool
-    faucet_account_file: String,
+    #[structopt(long)]
+    /// Derive all the keys, peer ids and ports from the provided seed (32 bytes, hex encoded), to
+    /// generate the same configs and genesis on any machine
+    seed: Option<String>,
+    #[structopt(short = "m", long, required_unless = "seed")]
+    /// File location from which to load faucet account generated via generate_keypair tool. If a
+    /// seed is provided instead, the faucet account key is derived from it
+    faucet_account_file: Option<String>,
     #[structopt(short = "r", long, default_value = "validator")]
     /// Role for the nodes: one of {"validator", "full_node"}
     role: String,
@@ -43,8 +48,6 @@ fn main() {
     } else {
         ::std::env::current_dir().expect("Failed to access current directory.")
     };
-    let (faucet_account_keypair, _faucet_key_file_path, _temp_dir) =
-        generate_keypair::load_faucet_key_or_create_default(Some(args.faucet_account_file.clone()));
     let role: RoleType = args.role.clone().into();
 
     let mut config_builder = SwarmConfigBuilder::new();
@@ -53,19 +56,26 @@ fn main() {
         .with_role(role)
         .with_base(&args.base)
         .with_output_dir(output_dir)
-        .with_faucet_keypair(faucet_account_keypair)
         .with_upstream_config_dir(args.upstream_config_dir.clone());
+    if let Some(faucet_account_file) = args.faucet_account_file.as_ref() {
+        let (faucet_account_keypair, _faucet_key_file_path, _temp_dir) =
+            generate_keypair::load_faucet_key_or_create_default(Some(faucet_account_file.clone()));
+        config_builder.with_faucet_keypair(faucet_account_keypair);
+    }
 
     if args.discovery {
         config_builder.force_discovery();
     }
     if let Some(key_seed) = args.key_seed.as_ref() {
-        let seed = hex::decode(key_seed).expect("Invalid hex in seed.");
-        config_builder.with_key_seed(
-            seed[..32]
-                .try_into()
-                .expect("Seed should be 32 bytes long."),
-        );
+        config_builder.with_key_seed(decode_seed(key_seed));
+    }
+    if let Some(seed) = args.seed.as_ref() {
+        config_builder.with_seed(decode_seed(seed));
     }
     config_builder.build().expect("Unable to generate configs");
 }
+
+fn decode_seed(seed: &str) -> [u8; 32] {
+    let seed = hex::decode(seed).expect("Invalid hex in seed.");
+    seed[..].try_into().expect("Seed should be 32 bytes long.")
+}
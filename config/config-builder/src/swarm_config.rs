@@ -12,14 +12,15 @@ use config::{
     seed_peers::{SeedPeersConfig, SeedPeersConfigHelpers},
     trusted_peers::{
         ConfigHelpers, ConsensusPeersConfig, ConsensusPrivateKey, NetworkPeersConfig,
-        NetworkPrivateKeys, UpstreamPeersConfig,
+        NetworkPrivateKeys,
     },
-    utils::get_available_port,
+    utils::PortGenerator,
 };
-use crypto::{ed25519::*, test_utils::KeyPair};
+use crypto::{ed25519::*, test_utils::KeyPair, HashValue};
 use failure::prelude::*;
 use logger::prelude::*;
 use parity_multiaddr::{Multiaddr, Protocol};
+use rand::{rngs::StdRng, SeedableRng};
 use std::{
     collections::BTreeMap,
     fs::{self, File},
@@ -40,6 +41,8 @@ impl SwarmConfig {
         prune_seed_peers_for_discovery: bool,
         is_ipv4: bool,
         key_seed: Option<[u8; 32]>,
+        upstream_key_seed: [u8; 32],
+        ports: &mut PortGenerator,
         output_dir: &Path,
         is_permissioned: bool,
         upstream_config_dir: PathBuf,
@@ -49,7 +52,7 @@ impl SwarmConfig {
             NodeConfig::load(&upstream_config_dir.join("node.config.toml"))?;
         // Generate new network config for upstream peer (permissioned if so).
         let (mut upstream_private_keys, upstream_network_peers_config) =
-            ConfigHelpers::gen_full_nodes(1, Some([2u8; 32]));
+            ConfigHelpers::gen_full_nodes(1, Some(upstream_key_seed));
         let upstream_peer_id = *upstream_private_keys.keys().nth(0).unwrap();
         let upstream_private_keys = upstream_private_keys
             .remove_entry(&upstream_peer_id)
@@ -68,7 +71,7 @@ impl SwarmConfig {
             } else {
                 addr.push(Protocol::Ip6("::1".parse().unwrap()));
             }
-            addr.push(Protocol::Tcp(get_available_port()));
+            addr.push(Protocol::Tcp(ports.next_port()));
             addr
         };
         // Save new network keys for upstream peer.
@@ -87,8 +90,23 @@ impl SwarmConfig {
             advertised_address: upstream_full_node_address.clone(),
             discovery_interval_ms: template_network.discovery_interval_ms,
             connectivity_check_interval_ms: template_network.connectivity_check_interval_ms,
+            direct_send_batch_window_ms: template_network.direct_send_batch_window_ms,
+            direct_send_max_batch_bytes: template_network.direct_send_max_batch_bytes,
+            relay_listen_address: None,
+            relays: template_network.relays.clone(),
+            outbound_connections: template_network.outbound_connections.clone(),
+            max_inbound_per_subnet: template_network.max_inbound_per_subnet,
+            peer_queue: template_network.peer_queue.clone(),
+            socket: template_network.socket.clone(),
+            persist_peers: template_network.persist_peers,
+            chain_id: template_network.chain_id.clone(),
+            network_id: template_network.network_id.clone(),
+            mempool_channel: template_network.mempool_channel.clone(),
+            consensus_channel: template_network.consensus_channel.clone(),
+            state_sync_channel: template_network.state_sync_channel.clone(),
             enable_encryption_and_authentication: template_network
                 .enable_encryption_and_authentication,
+            secure_transport: template_network.secure_transport,
             is_permissioned,
             // Dummy values - will be loaded from corresponding files.
             network_keypairs: NetworkKeyPairs::default(),
@@ -115,15 +133,13 @@ impl SwarmConfig {
             .push(upstream_full_node_config);
         // Write contents of upstream config to file.
         upstream_peer_config.save_config(&upstream_config_dir.join("node.config.toml"));
-        // Add upstream peer to StateSync::UpstreamPeersConfig.
-        template.state_sync.upstream_peers = UpstreamPeersConfig {
-            upstream_peers: vec![upstream_peer_id.to_string()],
-        };
+        // Make the upstream peer the preferred upstream of the full nodes.
+        template.upstream.preferred_peers = vec![upstream_peer_id.to_string()];
         // Setup seed peers config.
-        let mut seed_peers_config = SeedPeersConfigHelpers::get_test_config_with_ipver(
+        let mut seed_peers_config = SeedPeersConfigHelpers::get_test_config_with_ports(
             &network_peers_config,
-            None,
             is_ipv4,
+            ports,
         );
         // Extract peer addresses for full nodes from seed peer config.
         let peer_addresses: BTreeMap<_, _> = seed_peers_config
@@ -195,6 +211,7 @@ impl SwarmConfig {
                 &consensus_peers_config,
                 &node_dir,
                 &addrs,
+                ports,
             );
             let config_file = node_dir.join("node.config.toml");
             full_node_config.save_config(&config_file);
@@ -210,14 +227,15 @@ impl SwarmConfig {
         prune_seed_peers_for_discovery: bool,
         is_ipv4: bool,
         key_seed: Option<[u8; 32]>,
+        ports: &mut PortGenerator,
         output_dir: &Path,
     ) -> Result<Self> {
         let (mut private_keys, consensus_peers_config, network_peers_config) =
             ConfigHelpers::gen_validator_nodes(num_nodes, key_seed);
-        let mut seed_peers_config = SeedPeersConfigHelpers::get_test_config_with_ipver(
+        let mut seed_peers_config = SeedPeersConfigHelpers::get_test_config_with_ports(
             &network_peers_config,
-            None,
             is_ipv4,
+            ports,
         );
         let raw_genesis_transaction = gen_genesis_transaction_bytes(
             &faucet_key,
@@ -270,6 +288,7 @@ impl SwarmConfig {
                 &consensus_peers_config,
                 &node_dir,
                 &addrs,
+                ports,
             );
             let config_file = node_dir.join("node.config.toml");
             validator_config.save_config(&config_file);
@@ -289,6 +308,7 @@ impl SwarmConfig {
         consensus_peers_config: &ConsensusPeersConfig,
         output_dir: &Path,
         addrs: &[Multiaddr],
+        ports: &mut PortGenerator,
     ) -> NodeConfig {
         // Save consensus keys if present.
         let mut consensus_keys_file_name = "".to_string();
@@ -329,8 +349,23 @@ impl SwarmConfig {
             advertised_address: addrs[0].clone(),
             discovery_interval_ms: template_network.discovery_interval_ms,
             connectivity_check_interval_ms: template_network.connectivity_check_interval_ms,
+            direct_send_batch_window_ms: template_network.direct_send_batch_window_ms,
+            direct_send_max_batch_bytes: template_network.direct_send_max_batch_bytes,
+            relay_listen_address: None,
+            relays: template_network.relays.clone(),
+            outbound_connections: template_network.outbound_connections.clone(),
+            max_inbound_per_subnet: template_network.max_inbound_per_subnet,
+            peer_queue: template_network.peer_queue.clone(),
+            socket: template_network.socket.clone(),
+            persist_peers: template_network.persist_peers,
+            chain_id: template_network.chain_id.clone(),
+            network_id: template_network.network_id.clone(),
+            mempool_channel: template_network.mempool_channel.clone(),
+            consensus_channel: template_network.consensus_channel.clone(),
+            state_sync_channel: template_network.state_sync_channel.clone(),
             enable_encryption_and_authentication: template_network
                 .enable_encryption_and_authentication,
+            secure_transport: template_network.secure_transport,
             is_permissioned: template_network.is_permissioned,
             // Dummy values - will be loaded from corresponding files.
             network_keypairs: NetworkKeyPairs::default(),
@@ -339,10 +374,16 @@ impl SwarmConfig {
         };
         let consensus_config = ConsensusConfig {
             max_block_size: template.consensus.max_block_size,
+            max_block_bytes: template.consensus.max_block_bytes,
+            max_block_gas: template.consensus.max_block_gas,
             proposer_type: template.consensus.proposer_type.clone(),
             contiguous_rounds: template.consensus.contiguous_rounds,
+            proposer_reputation_window: template.consensus.proposer_reputation_window,
             max_pruned_blocks_in_mem: template.consensus.max_pruned_blocks_in_mem,
+            pruned_blocks_retention_rounds: template.consensus.pruned_blocks_retention_rounds,
             pacemaker_initial_timeout_ms: template.consensus.pacemaker_initial_timeout_ms,
+            adaptive_timeout: template.consensus.adaptive_timeout.clone(),
+            aggregate_signatures: template.consensus.aggregate_signatures,
             consensus_keypair_file: consensus_keys_file_name.into(),
             consensus_peers_file: consensus_peers_file_name.into(),
             // Dummy values - will be loaded from corresponding files.
@@ -354,17 +395,19 @@ impl SwarmConfig {
             networks: vec![network_config],
             consensus: consensus_config,
             metrics: template.metrics.clone(),
+            telemetry: template.telemetry.clone(),
             execution: template.execution.clone(),
             admission_control: template.admission_control.clone(),
             debug_interface: template.debug_interface.clone(),
             storage: template.storage.clone(),
             mempool: template.mempool.clone(),
             state_sync: template.state_sync.clone(),
+            upstream: template.upstream.clone(),
             log_collector: template.log_collector.clone(),
             vm_config: template.vm_config.clone(),
             secret_service: template.secret_service.clone(),
         };
-        NodeConfigHelpers::randomize_config_ports(&mut config);
+        NodeConfigHelpers::assign_config_ports(&mut config, ports);
         config.vm_config.publishing_options = VMPublishingOption::Open;
         config
     }
@@ -377,6 +420,7 @@ pub struct SwarmConfigBuilder {
     force_discovery: bool,
     is_ipv4: bool,
     key_seed: Option<[u8; 32]>,
+    seed: Option<[u8; 32]>,
     faucet_account_keypair_filepath: Option<PathBuf>,
     faucet_account_keypair: Option<KeyPair<Ed25519PrivateKey, Ed25519PublicKey>>,
     role: RoleType,
@@ -393,6 +437,7 @@ impl Default for SwarmConfigBuilder {
             force_discovery: false,
             is_ipv4: false,
             key_seed: None,
+            seed: None,
             faucet_account_keypair_filepath: None,
             faucet_account_keypair: None,
             role: RoleType::Validator,
@@ -460,6 +505,15 @@ impl SwarmConfigBuilder {
         self
     }
 
+    /// Derive everything generated, i.e., keys, peer ids and ports, from `seed`, so that the
+    /// same seed produces the same configs and genesis on any machine. Keys from a seed given to
+    /// `with_key_seed` or a faucet keypair take precedence. The ports are not checked to be
+    /// available.
+    pub fn with_seed(&mut self, seed: [u8; 32]) -> &mut Self {
+        self.seed = Some(seed);
+        self
+    }
+
     pub fn with_upstream_config_dir(&mut self, upstream_config_dir: Option<String>) -> &mut Self {
         self.upstream_config_dir = upstream_config_dir;
         self
@@ -473,12 +527,35 @@ impl SwarmConfigBuilder {
     pub fn build(mut self) -> Result<SwarmConfig> {
         // verify required fields
         let faucet_key_path = self.faucet_account_keypair_filepath.clone();
-        let faucet_key = self.faucet_account_keypair.take().unwrap_or_else(|| {
-            generate_keypair::load_key_from_file(
-                faucet_key_path.expect("Must provide faucet key file"),
-            )
-            .expect("Faucet account key is required to generate config")
-        });
+        let seed = self.seed;
+        let faucet_key =
+            self.faucet_account_keypair
+                .take()
+                .unwrap_or_else(|| match (faucet_key_path, seed) {
+                    (Some(faucet_key_path), _) => {
+                        generate_keypair::load_key_from_file(faucet_key_path)
+                            .expect("Faucet account key is required to generate config")
+                    }
+                    (None, Some(seed)) => {
+                        let mut rng = StdRng::from_seed(derive_seed(&seed, "faucet"));
+                        KeyPair::from(compat::generate_keypair(&mut rng).0)
+                    }
+                    (None, None) => panic!("Must provide faucet key file"),
+                });
+        let mut ports = match self.seed {
+            Some(seed) => PortGenerator::from_seed(&seed),
+            None => PortGenerator::Available,
+        };
+        // Validators and full nodes get keys from different seeds, for the keys of a validator
+        // swarm and of the full node swarm attached to it to differ.
+        let role_name = match self.role {
+            RoleType::Validator => "validator",
+            RoleType::FullNode => "full_node",
+        };
+        let key_seed = self
+            .key_seed
+            .or_else(|| seed.map(|seed| derive_seed(&seed, role_name)));
+        let upstream_key_seed = seed.map_or([2u8; 32], |seed| derive_seed(&seed, "upstream"));
 
         // generate all things needed for generation
         if !self.output_dir.is_dir() {
@@ -512,7 +589,8 @@ impl SwarmConfigBuilder {
                 faucet_key,
                 self.force_discovery,
                 self.is_ipv4,
-                self.key_seed,
+                key_seed,
+                &mut ports,
                 &self.output_dir,
             )
         } else {
@@ -521,7 +599,9 @@ impl SwarmConfigBuilder {
                 self.num_nodes,
                 self.force_discovery,
                 self.is_ipv4,
-                self.key_seed,
+                key_seed,
+                upstream_key_seed,
+                &mut ports,
                 &self.output_dir,
                 self.is_permissioned,
                 PathBuf::from(
@@ -532,3 +612,11 @@ impl SwarmConfigBuilder {
         }
     }
 }
+
+/// Derives the seed of one kind of generated data from the seed of a swarm, for no two kinds to
+/// be generated from the same seed.
+fn derive_seed(seed: &[u8; 32], purpose: &str) -> [u8; 32] {
+    let mut bytes = seed.to_vec();
+    bytes.extend_from_slice(purpose.as_bytes());
+    *HashValue::from_sha3_256(&bytes).as_ref()
+}
@@ -2,14 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    config::ConsensusProposerType::{FixedProposer, MultipleOrderedProposers, RotatingProposer},
+    config::ConsensusProposerType::{
+        FixedProposer, MultipleOrderedProposers, ReputationProposer, RotatingProposer,
+        WeightedProposer,
+    },
     keys::{ConsensusKeyPair, NetworkKeyPairs},
     seed_peers::{SeedPeersConfig, SeedPeersConfigHelpers},
     trusted_peers::{
         ConfigHelpers, ConsensusPeersConfig, ConsensusPrivateKey, NetworkPeersConfig,
-        NetworkPrivateKeys, UpstreamPeersConfig,
+        NetworkPrivateKeys,
     },
-    utils::{deserialize_whitelist, get_available_port, get_local_ip, serialize_whitelist},
+    utils::{deserialize_whitelist, get_local_ip, serialize_whitelist, PortGenerator},
 };
 use crypto::ValidKey;
 use failure::prelude::*;
@@ -19,14 +22,16 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     collections::HashSet,
     convert::TryFrom,
-    fs::File,
+    fs::{self, File},
     io::{Read, Write},
     path::{Path, PathBuf},
+    str::FromStr,
     string::ToString,
 };
 use toml;
 use tools::tempdir::TempPath;
 use types::{
+    account_address::AccountAddress,
     transaction::{SignedTransaction, SCRIPT_HASH_LENGTH},
     PeerId,
 };
@@ -36,6 +41,9 @@ use types::{
 mod config_test;
 
 pub const DISPOSABLE_DIR_MARKER: &str = "<USE_TEMP_DIR>";
+// Batching of DirectSend messages is disabled by default.
+pub const DIRECT_SEND_BATCH_WINDOW_MS: u64 = 0;
+pub const DIRECT_SEND_MAX_BATCH_BYTES: usize = 64 * 1024;
 
 // path is relative to this file location
 static CONFIG_TEMPLATE: &[u8] = include_bytes!("../data/configs/node.config.toml");
@@ -53,6 +61,8 @@ pub struct NodeConfig {
     #[serde(default)]
     pub metrics: MetricsConfig,
     #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
     pub execution: ExecutionConfig,
     #[serde(default)]
     pub admission_control: AdmissionControlConfig,
@@ -70,6 +80,8 @@ pub struct NodeConfig {
     #[serde(default)]
     pub state_sync: StateSyncConfig,
     #[serde(default)]
+    pub upstream: UpstreamConfig,
+    #[serde(default)]
     pub log_collector: LoggerConfig,
     #[serde(default)]
     pub vm_config: VMConfig,
@@ -173,6 +185,28 @@ impl Default for MetricsConfig {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    // Whether the node reports its anonymized health metrics to the collector. Off unless the
+    // operator opts in.
+    pub enabled: bool,
+    // URL of the collector the reports are POSTed to, over TLS. Must be an https URL.
+    pub collector_url: String,
+    // Interval between two reports.
+    pub report_interval_ms: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> TelemetryConfig {
+        TelemetryConfig {
+            enabled: false,
+            collector_url: "".to_string(),
+            report_interval_ms: 300_000,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ExecutionConfig {
@@ -183,6 +217,14 @@ pub struct ExecutionConfig {
     // account creation
     pub testnet_genesis: bool,
     pub genesis_file_location: String,
+    // Gas the VM may spend executing each block. Once exhausted, the transactions of the block
+    // which were not executed yet are left to a later block, so that a block of expensive
+    // transactions cannot stall consensus. Unlimited if not set.
+    //
+    // The gas used by a transaction doesn't depend on the validator executing it, so all the
+    // validators cut a block at the same transaction, provided they share the same budget: like
+    // the VM config, it must be the same on all the validators.
+    pub block_gas_budget: Option<u64>,
 }
 
 impl Default for ExecutionConfig {
@@ -192,6 +234,7 @@ impl Default for ExecutionConfig {
             port: 6183,
             testnet_genesis: false,
             genesis_file_location: "genesis.blob".to_string(),
+            block_gas_budget: None,
         }
     }
 }
@@ -234,6 +277,39 @@ pub struct AdmissionControlConfig {
     pub address: String,
     pub admission_control_service_port: u16,
     pub need_to_check_mempool_before_validation: bool,
+    // Reads are refused with a "node behind" status while the ledger of the node lags more
+    // versions than this behind the verified ledger infos of its peers, e.g. while state sync
+    // catches up, so that clients don't act on stale data. Not checked if not set.
+    pub max_sync_lag_versions: Option<u64>,
+    // Port of the HTTP+JSON gateway to Admission Control, for clients without a gRPC toolchain.
+    // The gateway listens on `address` as well. Not started if not set.
+    pub json_gateway_port: Option<u16>,
+    // Number of the transactions submitted to the node remembered at a time, and how long they are
+    // remembered for after their submission, so that clients can track them by hash.
+    pub submission_cache_capacity: usize,
+    pub submission_cache_ttl_secs: u64,
+    // How long after their submission the identical resubmissions of a transaction are answered
+    // with the same response, without validating them again.
+    pub response_cache_ttl_secs: u64,
+    // Limits the rate of the transactions submitted by each sender account, and by each client IP
+    // address, so that a single client can't monopolize the validation of transactions. Not
+    // limited if not set.
+    pub sender_rate_limit: Option<RateLimitConfig>,
+    pub ip_rate_limit: Option<RateLimitConfig>,
+    // Stateless checks rejecting the obviously bad transactions before their VM validation.
+    pub pre_validation: PreValidationConfig,
+    // Number of the threads validating the transactions submitted asynchronously, and number of
+    // the submissions waiting for them at most, beyond which submissions are rejected.
+    // Asynchronous submissions are refused if there are no such threads.
+    pub async_submission_workers: usize,
+    pub async_submission_queue_size: usize,
+    // Sheds the submissions of the senders without priority while the node is overloaded. Not
+    // shed if not set.
+    pub load_shedding: Option<LoadSheddingConfig>,
+    // Tiers of service for the clients authenticated with mutual TLS, each on its own port, and
+    // quota of the clients of the unauthenticated port. Only the unauthenticated port is served,
+    // without quota, if not set.
+    pub tls: Option<AdmissionControlTlsConfig>,
 }
 
 impl Default for AdmissionControlConfig {
@@ -242,10 +318,188 @@ impl Default for AdmissionControlConfig {
             address: "0.0.0.0".to_string(),
             admission_control_service_port: 8000,
             need_to_check_mempool_before_validation: false,
+            max_sync_lag_versions: None,
+            json_gateway_port: None,
+            submission_cache_capacity: 100_000,
+            submission_cache_ttl_secs: 600,
+            response_cache_ttl_secs: 10,
+            sender_rate_limit: None,
+            ip_rate_limit: None,
+            pre_validation: PreValidationConfig::default(),
+            async_submission_workers: 4,
+            async_submission_queue_size: 1_000,
+            load_shedding: None,
+            tls: None,
+        }
+    }
+}
+
+/// Stateless checks of the transactions submitted to Admission Control, each of which is disabled
+/// unless set.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct PreValidationConfig {
+    // Submissions which don't name this chain are rejected, usually the `chain_id` of the networks
+    // of the node.
+    pub chain_id: Option<String>,
+    // Signed transactions larger than this, their public key and signature included, are rejected.
+    pub max_transaction_size_bytes: Option<u64>,
+    // Transactions offering a higher gas unit price than this are rejected, as they most likely
+    // result from a mistake of the client.
+    pub max_gas_unit_price: Option<u64>,
+    // Only the scripts with these hashes, hex encoded SHA3-256 hashes of their code, are allowed
+    // if the list is not empty.
+    #[serde(deserialize_with = "deserialize_whitelist")]
+    #[serde(serialize_with = "serialize_whitelist")]
+    pub allowed_scripts: HashSet<[u8; SCRIPT_HASH_LENGTH]>,
+    // The scripts with these hashes are rejected.
+    #[serde(deserialize_with = "deserialize_whitelist")]
+    #[serde(serialize_with = "serialize_whitelist")]
+    pub denied_scripts: HashSet<[u8; SCRIPT_HASH_LENGTH]>,
+}
+
+/// Bounds the transactions Admission Control validates and adds to Mempool at once, the ones
+/// submitted asynchronously and waiting for a worker included.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LoadSheddingConfig {
+    // Number of the transactions in flight beyond which the submissions of the senders without
+    // priority are shed
+    pub max_in_flight: usize,
+    // Number of the transactions in flight beyond which the submissions of the priority senders
+    // are shed as well
+    pub max_in_flight_priority: usize,
+    // How long the clients whose submissions are shed are told to wait before retrying
+    pub retry_after_ms: u64,
+    // Hex encoded addresses of the priority senders
+    pub priority_senders: Vec<String>,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> LoadSheddingConfig {
+        LoadSheddingConfig {
+            max_in_flight: 64,
+            max_in_flight_priority: 128,
+            retry_after_ms: 500,
+            priority_senders: vec![],
+        }
+    }
+}
+
+impl LoadSheddingConfig {
+    /// Returns the senders whose submissions are shed last.
+    pub fn get_priority_senders(&self) -> HashSet<AccountAddress> {
+        self.priority_senders
+            .iter()
+            .map(|address| {
+                AccountAddress::from_str(address).unwrap_or_else(|_| {
+                    panic!("Failed to parse priority sender address: {}", address)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Mutual TLS on the Admission Control gRPC listeners of the authenticated clients.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AdmissionControlTlsConfig {
+    // PEM encoded certificate chain and private key the node presents to the clients
+    pub server_cert_path: PathBuf,
+    pub server_key_path: PathBuf,
+    #[serde(skip)]
+    pub server_cert: Vec<u8>,
+    #[serde(skip)]
+    pub server_key: Vec<u8>,
+    pub identities: Vec<ClientIdentityConfig>,
+    // Quota shared by all the clients of the unauthenticated port and of the JSON gateway
+    #[serde(default)]
+    pub unauthenticated_quota: IdentityQuotaConfig,
+}
+
+impl AdmissionControlTlsConfig {
+    /// Reads the certificates and the key, relative to the config file at `path`, and checks that
+    /// each certificate of a client grants at most one identity, on a port of its own.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let read = |file: &Path, what: &str| {
+            let file = path.as_ref().with_file_name(file);
+            fs::read(&file).map_err(|e| format_err!("Unable to read AC {} {:?}: {}", what, file, e))
+        };
+        self.server_cert = read(&self.server_cert_path, "server certificate")?;
+        self.server_key = read(&self.server_key_path, "server key")?;
+        for identity in &mut self.identities {
+            identity.client_ca_cert = read(
+                &identity.client_ca_cert_path,
+                &format!("client CA certificate of identity {}", identity.name),
+            )?;
+        }
+        self.validate()
+    }
+
+    /// Checks that the identities have distinct names and ports, and distinct CA certificates,
+    /// so that the certificate presented by a client tells its identity.
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        let mut ports = HashSet::new();
+        let mut client_ca_certs = HashSet::new();
+        for identity in &self.identities {
+            ensure!(
+                names.insert(&identity.name),
+                "AC identity {} is configured more than once",
+                identity.name
+            );
+            ensure!(
+                ports.insert(identity.port),
+                "AC identity {} shares port {} with another identity",
+                identity.name,
+                identity.port
+            );
+            ensure!(
+                client_ca_certs.insert(&identity.client_ca_cert),
+                "AC identity {} shares its client CA certificate with another identity",
+                identity.name
+            );
         }
+        Ok(())
     }
 }
 
+/// Named identity of the clients presenting a certificate issued by `client_ca_cert_path`, served
+/// on `port` with their own quota. The identity of a client is the one whose CA issued its
+/// certificate: the listener of the identity only completes the TLS handshake of the clients
+/// presenting such a certificate, and no two identities share a CA.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ClientIdentityConfig {
+    pub name: String,
+    // Port of the listener of the identity, on the address of Admission Control. Clients without
+    // a certificate of the identity can't connect to it.
+    pub port: u16,
+    // PEM encoded certificate of the CA issuing the certificates of the identity, or the
+    // self-signed certificate of its only client
+    pub client_ca_cert_path: PathBuf,
+    #[serde(skip)]
+    pub client_ca_cert: Vec<u8>,
+    #[serde(default)]
+    pub quota: IdentityQuotaConfig,
+}
+
+/// Quota shared by all the clients of an identity, each limit of which is disabled unless set.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct IdentityQuotaConfig {
+    // Rate of the requests of the identity, of any kind
+    pub rate_limit: Option<RateLimitConfig>,
+    // Number of the requests of the identity processed at once
+    pub max_in_flight: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RateLimitConfig {
+    // Sustained rate of the requests allowed
+    pub requests_per_sec: u64,
+    // Number of requests allowed at once, after a quiet period
+    pub burst: u64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct DebugInterfaceConfig {
@@ -255,6 +509,9 @@ pub struct DebugInterfaceConfig {
     // This has similar use to the core-node-debug-server itself
     pub metrics_server_port: u16,
     pub address: String,
+    // Lets the debug interface inject faults into the network connections of the node, to
+    // simulate flaky links in tests. Never to be enabled in production.
+    pub enable_network_fault_injection: bool,
 }
 
 impl Default for DebugInterfaceConfig {
@@ -265,6 +522,7 @@ impl Default for DebugInterfaceConfig {
             secret_service_node_debug_port: 6195,
             metrics_server_port: 9101,
             address: "localhost".to_string(),
+            enable_network_fault_injection: false,
         }
     }
 }
@@ -276,6 +534,11 @@ pub struct StorageConfig {
     pub port: u16,
     pub dir: PathBuf,
     pub grpc_max_receive_len: Option<i32>,
+    // The node stops taking in new data while less space than this is available on the disk
+    // holding `dir`, so that the DB never runs out of space halfway through a write.
+    pub min_free_space_bytes: u64,
+    // Interval between checks of the disk usage.
+    pub disk_check_interval_ms: u64,
 }
 
 impl Default for StorageConfig {
@@ -285,6 +548,8 @@ impl Default for StorageConfig {
             port: 6184,
             dir: PathBuf::from("libradb/db"),
             grpc_max_receive_len: Some(100_000_000),
+            min_free_space_bytes: 1024 * 1024 * 1024,
+            disk_check_interval_ms: 10_000,
         }
     }
 }
@@ -301,14 +566,53 @@ pub struct NetworkConfig {
     pub advertised_address: Multiaddr,
     pub discovery_interval_ms: u64,
     pub connectivity_check_interval_ms: u64,
-    // Flag to toggle if Noise is used for encryption and authentication.
+    // How long DirectSend may hold outbound messages to coalesce them into a single write.
+    // Batching is disabled if set to 0.
+    pub direct_send_batch_window_ms: u64,
+    // Size at which a DirectSend batch is written out before the end of the batch window.
+    pub direct_send_max_batch_bytes: usize,
+    // If set, the node acts as a relay for peers which cannot accept inbound connections (e.g.,
+    // full nodes behind a NAT) and listens for relay requests on this address.
+    pub relay_listen_address: Option<Multiaddr>,
+    // Relays through which this node accepts connections and dials peers which cannot be dialed
+    // directly.
+    pub relays: Vec<Multiaddr>,
+    // Limits on the connections the node initiates, which keep an attacker controlling many
+    // addresses from eclipsing it.
+    pub outbound_connections: OutboundConnectionsConfig,
+    // Maximum number of connections that peers in the same IP subnet (/24 for IPv4, /48 for IPv6)
+    // initiate to the node, which keeps an attacker from taking all the connections of a network
+    // anyone can connect to. Unlimited if not set.
+    pub max_inbound_per_subnet: Option<usize>,
+    // Queue of requests to each connected peer.
+    pub peer_queue: PeerQueueConfig,
+    // Options set on the TCP sockets of the network.
+    pub socket: SocketConfig,
+    // If set, what the network learns about its peers (addresses, dial outcomes, round trip times
+    // and supported protocols) is persisted in the storage dir of the node, and loaded on restart
+    // to reconnect to the peers without waiting for discovery.
+    pub persist_peers: bool,
+    // Flag to toggle if encryption and authentication are used.
     pub enable_encryption_and_authentication: bool,
+    // Protocol used for encryption and authentication, if enabled. All the peers of the network
+    // must use the same protocol.
+    pub secure_transport: SecureTransport,
     // If the network is permissioned, only trusted peers are allowed to connect. Otherwise, any
     // node can connect. If this flag is set to true, the `enable_encryption_and_authentication`
     // must also be set to true.
     pub is_permissioned: bool,
     // The role of the node in the network. One of: {"validator", "full_node"}.
     pub role: String,
+    // Identifier of the chain the node is on, advertised to peers during the identity exchange.
+    pub chain_id: String,
+    // If set, the network shares its listener with the other networks of the node listening on the
+    // same address, and is selected by dialers under this id. All the peers of the network must
+    // use the same id.
+    pub network_id: String,
+    // Queues of inbound messages and peer events for each upstream component.
+    pub mempool_channel: UpstreamChannelConfig,
+    pub consensus_channel: UpstreamChannelConfig,
+    pub state_sync_channel: UpstreamChannelConfig,
     // network_keypairs contains the node's network keypairs.
     // it is filled later on from network_keypairs_file.
     #[serde(skip)]
@@ -330,11 +634,26 @@ impl Default for NetworkConfig {
         NetworkConfig {
             peer_id: "".to_string(),
             role: "validator".to_string(),
+            chain_id: "".to_string(),
+            network_id: "".to_string(),
+            mempool_channel: UpstreamChannelConfig::default(),
+            consensus_channel: UpstreamChannelConfig::default(),
+            state_sync_channel: UpstreamChannelConfig::default(),
             listen_address: "/ip4/0.0.0.0/tcp/6180".parse::<Multiaddr>().unwrap(),
             advertised_address: "/ip4/127.0.0.1/tcp/6180".parse::<Multiaddr>().unwrap(),
             discovery_interval_ms: 1000,
             connectivity_check_interval_ms: 5000,
+            direct_send_batch_window_ms: DIRECT_SEND_BATCH_WINDOW_MS,
+            direct_send_max_batch_bytes: DIRECT_SEND_MAX_BATCH_BYTES,
+            relay_listen_address: None,
+            relays: vec![],
+            outbound_connections: OutboundConnectionsConfig::default(),
+            max_inbound_per_subnet: None,
+            peer_queue: PeerQueueConfig::default(),
+            socket: SocketConfig::default(),
+            persist_peers: true,
             enable_encryption_and_authentication: true,
+            secure_transport: SecureTransport::Noise,
             is_permissioned: true,
             network_keypairs_file: PathBuf::from("network_keypairs.config.toml"),
             network_keypairs: NetworkKeyPairs::default(),
@@ -346,6 +665,16 @@ impl Default for NetworkConfig {
     }
 }
 
+/// Protocol encrypting and authenticating the connections of a network.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecureTransport {
+    /// Noise IX handshake, authenticating peers by their network identity key.
+    Noise,
+    /// TLS 1.3, authenticating peers by a self-signed certificate for their network signing key.
+    Tls,
+}
+
 impl NetworkConfig {
     pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         if !self.network_peers_file.as_os_str().is_empty() {
@@ -370,29 +699,172 @@ impl NetworkConfig {
             self.listen_address =
                 get_local_ip().ok_or_else(|| ::failure::err_msg("No local IP"))?;
         }
-        // If PeerId is not set, it is derived from NetworkIdentityKey.
+        // If PeerId is not set, it is derived from the network keys.
         if self.peer_id == "" {
-            self.peer_id = PeerId::try_from(
+            self.peer_id = self.derived_peer_id().to_string();
+        }
+        self.validate()
+    }
+
+    /// Checks that the network doesn't use relays over a shared listener: relay requests are sent
+    /// before the network is selected, so a shared listener can't route them.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.network_id.is_empty() || self.relays.is_empty(),
+            "Network {} can't use relays, as it shares its listener",
+            self.network_id
+        );
+        Ok(())
+    }
+
+    /// The PeerId derived from the public key the secure transport authenticates the node with:
+    /// its network identity key with Noise, its network signing key with TLS. Permissionless
+    /// networks know their peers by this PeerId.
+    pub fn derived_peer_id(&self) -> PeerId {
+        match self.secure_transport {
+            SecureTransport::Noise => PeerId::try_from(
                 self.network_keypairs
                     .get_network_identity_public()
                     .to_bytes(),
-            )
-            .unwrap()
-            .to_string();
+            ),
+            SecureTransport::Tls => PeerId::try_from(
+                &self
+                    .network_keypairs
+                    .get_network_signing_public()
+                    .to_bytes(),
+            ),
+        }
+        .unwrap()
+    }
+}
+
+/// What the network does with an inbound message or peer event for an upstream component whose
+/// queue is full.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued item to make room for the new one.
+    DropOldest,
+    /// Drop the new item.
+    DropNewest,
+    /// Wait for the upstream component to catch up. This holds back the delivery to the other
+    /// upstream components as well.
+    Block,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UpstreamChannelConfig {
+    pub capacity: usize,
+    // Note that dropping policies may drop NewPeer and LostPeer events as well as messages.
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl Default for UpstreamChannelConfig {
+    fn default() -> UpstreamChannelConfig {
+        UpstreamChannelConfig {
+            capacity: 1024,
+            overflow_policy: OverflowPolicy::Block,
         }
-        Ok(())
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutboundConnectionsConfig {
+    // Maximum number of peers the node dials out to. Connections that peers initiate do not count
+    // towards it. If not set, every eligible peer is dialed, which is what validators need.
+    pub max_outbound: Option<usize>,
+    // Maximum number of peers in the same IP subnet (/24 for IPv4, /48 for IPv6) the node dials
+    // out to. Unlimited if not set.
+    pub max_outbound_per_subnet: Option<usize>,
+    // Number of outbound connections the node makes even if it has to go over
+    // `max_outbound_per_subnet` to do so.
+    pub min_outbound: usize,
+    // Interval at which the oldest outbound connection is closed to make room for a connection to
+    // another eligible peer. Connections are only rotated when `max_outbound` is set.
+    pub rotation_interval_ms: Option<u64>,
+}
+
+impl Default for OutboundConnectionsConfig {
+    fn default() -> OutboundConnectionsConfig {
+        OutboundConnectionsConfig {
+            max_outbound: None,
+            max_outbound_per_subnet: None,
+            min_outbound: 0,
+            rotation_interval_ms: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PeerQueueConfig {
+    // Maximum number of requests, e.g. to open substreams, queued for each connected peer. The
+    // requests to a peer whose queue is full are dropped, so that a slow peer does not hold up
+    // the requests to the other peers.
+    pub capacity: usize,
+    // Time after which a peer whose queue stays full is disconnected.
+    pub max_full_duration_ms: u64,
+}
+
+impl Default for PeerQueueConfig {
+    fn default() -> PeerQueueConfig {
+        PeerQueueConfig {
+            capacity: 1024,
+            max_full_duration_ms: 30_000,
+        }
+    }
+}
+
+// Options left unset keep the default of the OS.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SocketConfig {
+    // Whether TCP_NODELAY is set, disabling Nagle's algorithm so that small messages are sent
+    // without delay.
+    pub nodelay: Option<bool>,
+    // Size of the send buffer (SO_SNDBUF) of the sockets.
+    pub send_buffer_size: Option<usize>,
+    // Size of the receive buffer (SO_RCVBUF) of the sockets.
+    pub recv_buffer_size: Option<usize>,
+    // Idle time after which TCP keepalive probes are sent on the connections, so that dead peers
+    // are detected even when no message is exchanged with them.
+    pub keepalive_ms: Option<u64>,
+}
+
 #[cfg_attr(any(test, feature = "testing"), derive(Clone))]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ConsensusConfig {
     pub max_block_size: u64,
+    // Max cumulative size in bytes of the signed transactions of a proposed block, if any.
+    // Validators don't vote for the proposals over these limits.
+    pub max_block_bytes: Option<u64>,
+    // Max cumulative max gas amount of the transactions of a proposed block, if any.
+    pub max_block_gas: Option<u64>,
     pub proposer_type: String,
     pub contiguous_rounds: u32,
+    // Number of rounds during which a proposer whose round failed in the committed chain is
+    // skipped, with the reputation_proposer type.
+    pub proposer_reputation_window: u64,
     pub max_pruned_blocks_in_mem: Option<u64>,
+    // Number of rounds below the last committed block during which the pruned blocks and their
+    // quorum certificates are kept, in memory (at most max_pruned_blocks_in_mem of them) and in
+    // ConsensusDB, before being garbage-collected. The window only holds while the node runs: the
+    // recovery after a restart starts from the last committed block and deletes the blocks below
+    // it from ConsensusDB.
+    pub pruned_blocks_retention_rounds: u64,
     pub pacemaker_initial_timeout_ms: Option<u64>,
+    // Round timeouts adapting to the durations of the recent rounds, if any, instead of starting
+    // from pacemaker_initial_timeout_ms after every commit.
+    pub adaptive_timeout: Option<AdaptiveTimeoutConfig>,
+    // Whether the validators aggregate the signatures of their votes into the quorum and timeout
+    // certificates, with the BLS12-381 keys derived from their consensus keys whose public keys are
+    // registered in consensus_peers_file. All the validators of the initial epoch must enable it
+    // together. After a reconfiguration, whose validator set carries no such keys, the
+    // certificates are made of the individual signatures again.
+    pub aggregate_signatures: bool,
     // consensus_keypair contains the node's consensus keypair.
     // it is filled later on from consensus_keypair_file.
     #[serde(skip)]
@@ -407,10 +879,16 @@ impl Default for ConsensusConfig {
     fn default() -> ConsensusConfig {
         ConsensusConfig {
             max_block_size: 100,
+            max_block_bytes: None,
+            max_block_gas: None,
             proposer_type: "multiple_ordered_proposers".to_string(),
             contiguous_rounds: 2,
+            proposer_reputation_window: 10,
             max_pruned_blocks_in_mem: None,
+            pruned_blocks_retention_rounds: 100,
             pacemaker_initial_timeout_ms: None,
+            adaptive_timeout: None,
+            aggregate_signatures: false,
             consensus_keypair: ConsensusKeyPair::default(),
             consensus_keypair_file: PathBuf::from("consensus_keypair.config.toml"),
             consensus_peers: ConsensusPeersConfig::default(),
@@ -419,6 +897,32 @@ impl Default for ConsensusConfig {
     }
 }
 
+/// Sets the timeout of the rounds following a commit from a percentile of the durations of the
+/// recent rounds which were certified before timing out. The timeouts of the rounds which follow
+/// still increase exponentially.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct AdaptiveTimeoutConfig {
+    // Number of the most recent certified rounds whose durations are kept
+    pub window_size: usize,
+    // Percentile of the round durations the timeout is set to, from 0 to 100
+    pub percentile: f64,
+    // Bounds of the timeout of the rounds following a commit
+    pub min_timeout_ms: u64,
+    pub max_timeout_ms: u64,
+}
+
+impl Default for AdaptiveTimeoutConfig {
+    fn default() -> AdaptiveTimeoutConfig {
+        AdaptiveTimeoutConfig {
+            window_size: 100,
+            percentile: 99.0,
+            min_timeout_ms: 500,
+            max_timeout_ms: 10_000,
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum ConsensusProposerType {
     // Choose the smallest PeerId as the proposer
@@ -427,6 +931,10 @@ pub enum ConsensusProposerType {
     RotatingProposer,
     // Multiple ordered proposers per round (primary, secondary, etc.)
     MultipleOrderedProposers,
+    // Proposer picked with a probability proportional to its voting power
+    WeightedProposer,
+    // Round robin rotation of proposers, skipping the ones which recently failed their rounds
+    ReputationProposer,
 }
 
 impl ConsensusConfig {
@@ -449,6 +957,8 @@ impl ConsensusConfig {
             "fixed_proposer" => FixedProposer,
             "rotating_proposer" => RotatingProposer,
             "multiple_ordered_proposers" => MultipleOrderedProposers,
+            "weighted_proposer" => WeightedProposer,
+            "reputation_proposer" => ReputationProposer,
             &_ => unimplemented!("Invalid proposer type: {}", self.proposer_type),
         }
     }
@@ -457,17 +967,41 @@ impl ConsensusConfig {
         self.contiguous_rounds
     }
 
+    pub fn proposer_reputation_window(&self) -> u64 {
+        self.proposer_reputation_window
+    }
+
     pub fn max_block_size(&self) -> u64 {
         self.max_block_size
     }
 
+    pub fn max_block_bytes(&self) -> &Option<u64> {
+        &self.max_block_bytes
+    }
+
+    pub fn max_block_gas(&self) -> &Option<u64> {
+        &self.max_block_gas
+    }
+
     pub fn max_pruned_blocks_in_mem(&self) -> &Option<u64> {
         &self.max_pruned_blocks_in_mem
     }
 
+    pub fn pruned_blocks_retention_rounds(&self) -> u64 {
+        self.pruned_blocks_retention_rounds
+    }
+
     pub fn pacemaker_initial_timeout_ms(&self) -> &Option<u64> {
         &self.pacemaker_initial_timeout_ms
     }
+
+    pub fn adaptive_timeout(&self) -> &Option<AdaptiveTimeoutConfig> {
+        &self.adaptive_timeout
+    }
+
+    pub fn aggregate_signatures(&self) -> bool {
+        self.aggregate_signatures
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -475,11 +1009,86 @@ impl ConsensusConfig {
 pub struct MempoolConfig {
     pub broadcast_transactions: bool,
     pub shared_mempool_tick_interval_ms: u64,
+    // max number of transactions broadcast to a peer at once. Batches to a peer shrink down to
+    // `shared_mempool_min_batch_size` while it takes longer than
+    // `shared_mempool_slow_send_threshold_ms` to acknowledge them
     pub shared_mempool_batch_size: usize,
+    pub shared_mempool_min_batch_size: usize,
+    pub shared_mempool_slow_send_threshold_ms: u64,
+    // max number of ticks skipped between two broadcasts to a peer whose sends are slow
+    pub shared_mempool_max_skipped_ticks: u64,
+    // max number of ticks a peer is not broadcast to after consecutive failed sends
+    pub shared_mempool_max_failure_backoff_ticks: u64,
+    // time a peer has to acknowledge a broadcast before it is considered failed
+    pub shared_mempool_ack_timeout_ms: u64,
     pub shared_mempool_max_concurrent_inbound_syncs: usize,
+    // number of threads verifying in parallel the signatures of the transactions received from
+    // peers, one per CPU if 0
+    pub shared_mempool_signature_verification_threads: usize,
+    // number of most recently inserted transactions summarized in the bloom filter attached to
+    // the sync messages sent to peers, so that they don't send those transactions back. 0 to
+    // attach no filter
+    pub shared_mempool_known_transactions_window: usize,
+    // number of ticks between two sends of the filter to all the peers, along with a broadcast or
+    // on its own. 0 to only attach it to the broadcasts
+    pub shared_mempool_known_transactions_interval_ticks: u64,
     pub capacity: usize,
     // max number of transactions per user in Mempool
     pub capacity_per_user: usize,
+    // max number of transactions per user which can't be executed yet because of a gap in their
+    // sequence numbers, so that an account can't fill its share of Mempool with transactions far
+    // ahead of its sequence number
+    pub non_ready_capacity_per_user: usize,
+    // max distance between the sequence number of a transaction and the current sequence number
+    // of its sender, beyond which the transaction is rejected instead of waiting in Mempool for
+    // the gap to be filled until it expires
+    pub sequence_number_future_window: u64,
+    // bounds of the time left before a transaction expires, as of its insertion. Transactions
+    // about to expire would only waste room in blocks, and transactions which expire far in the
+    // future could sit in Mempool long enough to keep others out. `None` for no bound
+    pub min_expiration_window_secs: Option<u64>,
+    pub max_expiration_window_secs: Option<u64>,
+    // max number of transactions submitted to this node through Admission Control
+    pub local_capacity: usize,
+    // max number of transactions received from other peers. Together with `local_capacity`, it
+    // should not exceed `capacity`, so that peers can never fill the space reserved for local
+    // transactions
+    pub peer_capacity: usize,
+    // when Mempool is full, the non-ready transaction with the lowest gas price is evicted to make
+    // room for a new transaction paying at least this much more per gas unit
+    pub eviction_min_gas_price_bump: u64,
+    // a pending transaction is only replaced by a transaction with the same sequence number paying
+    // at least this much more per gas unit
+    pub replacement_min_gas_price_bump: u64,
+    // senders, e.g. governance accounts, whose transactions go into a privileged lane: they are
+    // pulled into blocks before all other transactions, and are neither evicted nor rejected when
+    // Mempool is full, up to `privileged_capacity` of them. Account addresses as hex strings
+    pub privileged_senders: Vec<String>,
+    // max number of transactions of the privileged lane let in beyond the capacity of Mempool and
+    // of its partitions. The others are turned away like the transactions of any other sender
+    pub privileged_capacity: usize,
+    // stateless policies enforced on the transactions entering Mempool: max size in bytes of the
+    // signed transactions, senders whose transactions are rejected as hex strings, and hashes of the
+    // only scripts accepted as hex strings, any script being accepted if empty
+    pub max_transaction_size: Option<usize>,
+    pub banned_senders: Vec<String>,
+    pub script_allow_list: Vec<String>,
+    // if set, the transactions of Mempool are periodically written to this file, relative to the
+    // data dir of the node, and reloaded after re-validation when the node restarts
+    pub snapshot_file: Option<PathBuf>,
+    pub snapshot_interval_ms: u64,
+    // max number of transactions written to a snapshot, the ones paying the most per gas unit
+    // first
+    pub snapshot_max_transactions: usize,
+    // number of most recently committed transactions whose gas unit prices are tracked to
+    // estimate the gas price needed to get into the next block
+    pub gas_estimator_window: usize,
+    // time after which a transaction submitted to this node stops being tracked to export its
+    // latencies, whether it was committed by then or not
+    pub latency_tracker_ttl_secs: u64,
+    // number of the last removed transactions whose removal reason is kept, for clients asking
+    // what happened to their transaction
+    pub removal_log_capacity: usize,
     pub system_transaction_timeout_secs: u64,
     pub system_transaction_gc_interval_ms: u64,
     pub mempool_service_port: u16,
@@ -492,9 +1101,36 @@ impl Default for MempoolConfig {
             broadcast_transactions: true,
             shared_mempool_tick_interval_ms: 50,
             shared_mempool_batch_size: 100,
+            shared_mempool_min_batch_size: 10,
+            shared_mempool_slow_send_threshold_ms: 100,
+            shared_mempool_max_skipped_ticks: 20,
+            shared_mempool_max_failure_backoff_ticks: 1200,
+            shared_mempool_ack_timeout_ms: 5_000,
             shared_mempool_max_concurrent_inbound_syncs: 100,
+            shared_mempool_signature_verification_threads: 0,
+            shared_mempool_known_transactions_window: 1024,
+            shared_mempool_known_transactions_interval_ticks: 20,
             capacity: 1_000_000,
             capacity_per_user: 100,
+            non_ready_capacity_per_user: 20,
+            sequence_number_future_window: 100,
+            min_expiration_window_secs: None,
+            max_expiration_window_secs: None,
+            local_capacity: 200_000,
+            peer_capacity: 800_000,
+            eviction_min_gas_price_bump: 0,
+            replacement_min_gas_price_bump: 1,
+            privileged_senders: vec![],
+            privileged_capacity: 10_000,
+            max_transaction_size: None,
+            banned_senders: vec![],
+            script_allow_list: vec![],
+            snapshot_file: None,
+            snapshot_interval_ms: 10_000,
+            snapshot_max_transactions: 100_000,
+            gas_estimator_window: 10_000,
+            latency_tracker_ttl_secs: 100,
+            removal_log_capacity: 10_000,
             system_transaction_timeout_secs: 86400,
             address: "localhost".to_string(),
             mempool_service_port: 6182,
@@ -503,6 +1139,37 @@ impl Default for MempoolConfig {
     }
 }
 
+impl MempoolConfig {
+    /// Checks that the capacity of Mempool is large enough for both of its partitions, so that
+    /// peers can never fill the space reserved for local transactions.
+    pub fn validate(&self) -> Result<()> {
+        let partitions_capacity = self
+            .local_capacity
+            .checked_add(self.peer_capacity)
+            .unwrap_or(usize::max_value());
+        ensure!(
+            partitions_capacity <= self.capacity,
+            "Mempool local_capacity ({}) and peer_capacity ({}) exceed its capacity ({})",
+            self.local_capacity,
+            self.peer_capacity,
+            self.capacity
+        );
+        Ok(())
+    }
+
+    /// Returns the senders whose transactions go into the privileged lane.
+    pub fn get_privileged_senders(&self) -> HashSet<AccountAddress> {
+        self.privileged_senders
+            .iter()
+            .map(|address| {
+                AccountAddress::from_str(address).unwrap_or_else(|_| {
+                    panic!("Failed to parse privileged sender address: {}", address)
+                })
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct StateSyncConfig {
@@ -516,9 +1183,19 @@ pub struct StateSyncConfig {
     pub max_chunk_limit: u64,
     // valid maximum timeout limit for sanity check
     pub max_timeout_ms: u64,
-    // List of peers to use as upstream in state sync protocols.
-    #[serde(flatten)]
-    pub upstream_peers: UpstreamPeersConfig,
+    // Bytes of chunks served to each unauthorized peer per day, unlimited if not set
+    pub chunk_serving_daily_byte_budget: Option<u64>,
+    // Bytes of chunks served to all the unauthorized peers together per day, unlimited if not set
+    pub chunk_serving_total_daily_byte_budget: Option<u64>,
+    // Peers served chunks without limit. Peers are identified by the Noise handshake of their
+    // connection, so this requires the network to enable Noise encryption
+    pub chunk_serving_authorized_peers: Vec<String>,
+    // Whether chunks are only served to authorized peers
+    pub chunk_serving_require_auth: bool,
+    // If set, the chunks of a sync target are requested over rpc, and each request is also sent
+    // to another upstream peer once the first one has not responded after this delay, typically
+    // the p99 latency of the chunk requests. Otherwise they are requested over direct-send
+    pub chunk_request_hedge_delay_ms: Option<u64>,
 }
 
 impl Default for StateSyncConfig {
@@ -529,11 +1206,126 @@ impl Default for StateSyncConfig {
             long_poll_timeout_ms: 30000,
             max_chunk_limit: 1000,
             max_timeout_ms: 120_000,
-            upstream_peers: UpstreamPeersConfig::default(),
+            chunk_serving_daily_byte_budget: None,
+            chunk_serving_total_daily_byte_budget: None,
+            chunk_serving_authorized_peers: vec![],
+            chunk_serving_require_auth: false,
+            chunk_request_hedge_delay_ms: None,
+        }
+    }
+}
+
+impl StateSyncConfig {
+    /// Returns the peers served chunks without limit.
+    pub fn get_chunk_serving_authorized_peers(&self) -> Vec<PeerId> {
+        self.chunk_serving_authorized_peers
+            .iter()
+            .map(|peer_id_str| {
+                PeerId::from_str(peer_id_str).unwrap_or_else(|_| {
+                    panic!("Failed to parse peer_id from string: {}", peer_id_str)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Which of the upstream peers of a full node a component talks to.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamRouting {
+    /// The preferred peers, failing over to the fallback peers while none of them is usable.
+    Failover,
+    /// The preferred peers only.
+    PreferredOnly,
+    /// The fallback peers only.
+    FallbackOnly,
+    /// No upstream peer.
+    Disabled,
+}
+
+/// Which of the usable upstream peers of a full node each transaction submitted to it is
+/// forwarded to.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamSelection {
+    /// Each peer in turn.
+    RoundRobin,
+    /// The peer which responded the fastest lately.
+    LowestLatency,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    // Upstream peers of a full node, as PeerIds serialized as strings. The preferred peers are
+    // used as long as they work, the fallback peers while none of them does.
+    pub preferred_peers: Vec<String>,
+    pub fallback_peers: Vec<String>,
+    // Number of consecutive failed requests after which an upstream peer is failed over.
+    pub failover_threshold: u64,
+    // How long a failed over upstream peer is left alone before it is tried again.
+    pub failover_retry_interval_ms: u64,
+    // Upstream peers that state sync requests chunks from.
+    pub sync_routing: UpstreamRouting,
+    // Upstream peers that the transactions submitted to the node are forwarded to.
+    pub submission_routing: UpstreamRouting,
+    // Upstream peer that each transaction forwarded by Admission Control is sent to first, the
+    // next ones being tried in turn while the requests fail.
+    pub submission_selection: UpstreamSelection,
+    // How long a forwarded transaction waits for the upstream peer before it is also sent to the
+    // next one, typically the p99 latency of the submissions. Zero disables the hedging.
+    pub submission_hedge_delay_ms: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            preferred_peers: vec![],
+            fallback_peers: vec![],
+            failover_threshold: 3,
+            failover_retry_interval_ms: 30_000,
+            sync_routing: UpstreamRouting::Failover,
+            submission_routing: UpstreamRouting::Failover,
+            submission_selection: UpstreamSelection::RoundRobin,
+            submission_hedge_delay_ms: 0,
         }
     }
 }
 
+impl UpstreamConfig {
+    /// Returns the preferred and the fallback upstream peers selected by `routing`.
+    pub fn get_peers(&self, routing: UpstreamRouting) -> (Vec<PeerId>, Vec<PeerId>) {
+        let parse = |peer_ids: &[String]| -> Vec<PeerId> {
+            peer_ids
+                .iter()
+                .map(|peer_id_str| {
+                    PeerId::from_str(peer_id_str).unwrap_or_else(|_| {
+                        panic!("Failed to parse peer_id from string: {}", peer_id_str)
+                    })
+                })
+                .collect()
+        };
+        match routing {
+            UpstreamRouting::Failover => {
+                (parse(&self.preferred_peers), parse(&self.fallback_peers))
+            }
+            UpstreamRouting::PreferredOnly => (parse(&self.preferred_peers), vec![]),
+            UpstreamRouting::FallbackOnly => (vec![], parse(&self.fallback_peers)),
+            UpstreamRouting::Disabled => (vec![], vec![]),
+        }
+    }
+
+    /// Returns the preferred and the fallback upstream peers of state sync.
+    pub fn get_sync_peers(&self) -> (Vec<PeerId>, Vec<PeerId>) {
+        self.get_peers(self.sync_routing)
+    }
+
+    /// Returns the preferred and the fallback upstream peers of transaction submission.
+    pub fn get_submission_peers(&self) -> (Vec<PeerId>, Vec<PeerId>) {
+        self.get_peers(self.submission_routing)
+    }
+}
+
 impl NodeConfig {
     /// Reads the config file and returns the configuration object in addition to doing some
     /// post-processing of the config
@@ -556,6 +1348,10 @@ impl NodeConfig {
             }
         }
         config.consensus.load(path.as_ref())?;
+        config.mempool.validate()?;
+        if let Some(tls) = &mut config.admission_control.tls {
+            tls.load(path.as_ref())?;
+        }
         NodeConfigHelpers::update_data_dir_path_if_needed(&mut config)?;
         Ok(config)
     }
@@ -615,6 +1411,15 @@ impl NodeConfig {
             path
         }
     }
+
+    pub fn get_mempool_snapshot_file(&self) -> Option<PathBuf> {
+        let path = self.mempool.snapshot_file.clone()?;
+        if path.is_relative() {
+            Some(self.base.data_dir_path.join(path))
+        } else {
+            Some(path)
+        }
+    }
 }
 
 pub struct NodeConfigHelpers {}
@@ -693,15 +1498,30 @@ impl NodeConfigHelpers {
     }
 
     pub fn randomize_config_ports(config: &mut NodeConfig) {
-        config.admission_control.admission_control_service_port = get_available_port();
-        config.debug_interface.admission_control_node_debug_port = get_available_port();
-        config.debug_interface.metrics_server_port = get_available_port();
-        config.debug_interface.secret_service_node_debug_port = get_available_port();
-        config.debug_interface.storage_node_debug_port = get_available_port();
-        config.execution.port = get_available_port();
-        config.mempool.mempool_service_port = get_available_port();
-        config.secret_service.secret_service_port = get_available_port();
-        config.storage.port = get_available_port();
+        Self::assign_config_ports(config, &mut PortGenerator::Available);
+    }
+
+    /// Assigns the ports of the services of the node from `ports`.
+    pub fn assign_config_ports(config: &mut NodeConfig, ports: &mut PortGenerator) {
+        config.admission_control.admission_control_service_port = ports.next_port();
+        config.debug_interface.admission_control_node_debug_port = ports.next_port();
+        config.debug_interface.metrics_server_port = ports.next_port();
+        config.debug_interface.secret_service_node_debug_port = ports.next_port();
+        config.debug_interface.storage_node_debug_port = ports.next_port();
+        config.execution.port = ports.next_port();
+        config.mempool.mempool_service_port = ports.next_port();
+        config.secret_service.secret_service_port = ports.next_port();
+        config.storage.port = ports.next_port();
+        // only the optional services which are enabled get a port, after the other ones so that
+        // enabling them does not move the ports of the other services
+        if config.admission_control.json_gateway_port.is_some() {
+            config.admission_control.json_gateway_port = Some(ports.next_port());
+        }
+        if let Some(tls) = &mut config.admission_control.tls {
+            for identity in &mut tls.identities {
+                identity.port = ports.next_port();
+            }
+        }
     }
 }
 
@@ -121,6 +121,10 @@ impl NetworkKeyPairs {
         self.network_signing_private_key.take()
     }
 
+    pub fn get_network_signing_public(&self) -> &Ed25519PublicKey {
+        &self.network_signing_public_key
+    }
+
     pub fn get_network_identity_private(&self) -> X25519StaticPrivateKey {
         self.network_identity_private_key.clone()
     }
@@ -184,6 +188,10 @@ impl ConsensusKeyPair {
         self.consensus_private_key.take()
     }
 
+    pub fn get_consensus_public(&self) -> Option<&Ed25519PublicKey> {
+        self.consensus_public_key.as_ref()
+    }
+
     pub fn is_present(&self) -> bool {
         match self.consensus_private_key {
             PrivateKeyContainer::Present(_) => true,
@@ -1,7 +1,10 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{trusted_peers::NetworkPeersConfig, utils::get_available_port};
+use crate::{
+    trusted_peers::{serialize_ordered_map, NetworkPeersConfig},
+    utils::PortGenerator,
+};
 use parity_multiaddr::{Multiaddr, Protocol};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -13,6 +16,7 @@ mod seed_peers_test;
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SeedPeersConfig {
     // All peers config. Key:a unique peer id, will be PK in future, Value: peer discovery info
+    #[serde(serialize_with = "serialize_ordered_map")]
     pub seed_peers: HashMap<String, Vec<Multiaddr>>,
 }
 
@@ -34,15 +38,29 @@ impl SeedPeersConfigHelpers {
         network_peers: &NetworkPeersConfig,
         port: Option<u16>,
         ipv4: bool,
+    ) -> SeedPeersConfig {
+        // If a port is supplied, we should have only 1 peer.
+        let mut ports = match port {
+            Some(port) => {
+                assert_eq!(1, network_peers.peers.len());
+                PortGenerator::Sequential(port)
+            }
+            None => PortGenerator::Available,
+        };
+        Self::get_test_config_with_ports(network_peers, ipv4, &mut ports)
+    }
+
+    /// Creates a new SeedPeersConfig based on provided NetworkPeersConfig.
+    /// Each node gets the next port of `ports`, in the order of the peer ids.
+    pub fn get_test_config_with_ports(
+        network_peers: &NetworkPeersConfig,
+        ipv4: bool,
+        ports: &mut PortGenerator,
     ) -> SeedPeersConfig {
         let mut seed_peers = HashMap::new();
         // sort to have same repeatable order
         let mut peers: Vec<String> = network_peers.peers.keys().cloned().collect();
         peers.sort_unstable_by_key(std::clone::Clone::clone);
-        // If a port is supplied, we should have only 1 peer.
-        if port.is_some() {
-            assert_eq!(1, peers.len());
-        }
         for peer_id in peers {
             // Create a new PeerInfo and increment the ports
             let mut addr = Multiaddr::empty();
@@ -51,7 +69,7 @@ impl SeedPeersConfigHelpers {
             } else {
                 addr.push(Protocol::Ip6("::1".parse().unwrap()));
             }
-            addr.push(Protocol::Tcp(port.unwrap_or_else(get_available_port)));
+            addr.push(Protocol::Tcp(ports.next_port()));
             seed_peers.insert(peer_id.clone(), vec![addr]);
         }
         SeedPeersConfig { seed_peers }
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crypto::{
+    bls12381::{BLS12381PublicKey, BLS12381Signature},
     ed25519::{compat, *},
     traits::{ValidKey, ValidKeyStringExt},
     x25519::{self, X25519StaticPrivateKey, X25519StaticPublicKey},
 };
+use failure::format_err;
 use mirai_annotations::postcondition;
 use rand::{rngs::StdRng, SeedableRng};
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
@@ -17,9 +19,10 @@ use std::{
 };
 use types::{
     account_address::AccountAddress,
-    crypto_proxies::{ValidatorInfo, ValidatorVerifier},
+    crypto_proxies::{derive_aggregate_key, AggregateVerifier, ValidatorInfo, ValidatorVerifier},
     validator_public_keys::ValidatorPublicKeys,
     validator_set::ValidatorSet,
+    validator_verifier::ValidatorInfo as RawValidatorInfo,
     PeerId,
 };
 
@@ -57,6 +60,31 @@ pub struct ConsensusPeerInfo {
     #[serde(deserialize_with = "deserialize_key")]
     #[serde(rename = "c")]
     pub consensus_pubkey: Ed25519PublicKey,
+    #[serde(default = "default_voting_power")]
+    #[serde(rename = "v")]
+    pub voting_power: u64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "a")]
+    pub consensus_aggregate_key: Option<AggregateKeyInfo>,
+}
+
+/// The public key a validator aggregates its consensus signatures with, registered along with the
+/// proof of possession of its private key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AggregateKeyInfo {
+    #[serde(serialize_with = "serialize_key")]
+    #[serde(deserialize_with = "deserialize_key")]
+    #[serde(rename = "k")]
+    pub public_key: BLS12381PublicKey,
+    #[serde(serialize_with = "serialize_signature")]
+    #[serde(deserialize_with = "deserialize_signature")]
+    #[serde(rename = "p")]
+    pub proof_of_possession: BLS12381Signature,
+}
+
+fn default_voting_power() -> u64 {
+    1
 }
 
 pub struct ConsensusPrivateKey {
@@ -70,12 +98,6 @@ pub struct ConsensusPeersConfig {
     pub peers: HashMap<String, ConsensusPeerInfo>,
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct UpstreamPeersConfig {
-    /// List of PeerIds serialized as string.
-    pub upstream_peers: Vec<String>,
-}
-
 impl ConsensusPeersConfig {
     /// Return a sorted vector of ValidatorPublicKey's
     pub fn get_validator_set(&self, network_peers_config: &NetworkPeersConfig) -> ValidatorSet {
@@ -86,8 +108,7 @@ impl ConsensusPeersConfig {
                 ValidatorPublicKeys::new(
                     AccountAddress::from_str(peer_id_str).expect("[config] invalid peer_id"),
                     peer_info.consensus_pubkey.clone(),
-                    // TODO: Add support for dynamic voting weights in config
-                    1,
+                    peer_info.voting_power,
                     network_peers_config
                         .peers
                         .get(peer_id_str)
@@ -121,13 +142,38 @@ impl ConsensusPeersConfig {
                                 peer_id_str
                             )
                         }),
-                        // TODO: Add support for dynamic voting weights in config
-                        ValidatorInfo::new(peer_info.consensus_pubkey.clone(), 1),
+                        ValidatorInfo::new(
+                            peer_info.consensus_pubkey.clone(),
+                            peer_info.voting_power,
+                        ),
                     )
                 })
                 .collect(),
         )
     }
+
+    /// Returns the verifier of the signatures the validators aggregate, which requires all of
+    /// them to have registered an aggregate key.
+    pub fn get_aggregate_validator_verifier(&self) -> failure::Result<AggregateVerifier> {
+        let mut address_to_validator_info = HashMap::new();
+        let mut proofs_of_possession = HashMap::new();
+        for (peer_id_str, peer_info) in &self.peers {
+            let peer_id = PeerId::from_str(peer_id_str)?;
+            let aggregate_key = peer_info
+                .consensus_aggregate_key
+                .as_ref()
+                .ok_or_else(|| format_err!("No aggregate key for validator {}", peer_id_str))?;
+            address_to_validator_info.insert(
+                peer_id,
+                RawValidatorInfo::new(aggregate_key.public_key.clone(), peer_info.voting_power),
+            );
+            proofs_of_possession.insert(peer_id, aggregate_key.proof_of_possession.clone());
+        }
+        AggregateVerifier::new_with_proofs_of_possession(
+            address_to_validator_info,
+            &proofs_of_possession,
+        )
+    }
 }
 
 // TODO: move to mod utils.
@@ -158,10 +204,16 @@ impl ConfigHelpers {
             let (private2, public2) = compat::generate_keypair(&mut fast_rng);
             // Generate peer id from consensus public key.
             let peer_id = AccountAddress::from_public_key(&public2);
+            let aggregate_key = derive_aggregate_key(&private2);
             consensus_peers.insert(
                 peer_id.to_string(),
                 ConsensusPeerInfo {
                     consensus_pubkey: public2,
+                    voting_power: default_voting_power(),
+                    consensus_aggregate_key: Some(AggregateKeyInfo {
+                        public_key: (&aggregate_key).into(),
+                        proof_of_possession: aggregate_key.create_proof_of_possession(),
+                    }),
                 },
             );
             consensus_private_keys.insert(
@@ -271,6 +323,23 @@ where
         .map_err(<D::Error as serde::de::Error>::custom)
 }
 
+fn serialize_signature<S>(signature: &BLS12381Signature, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&hex::encode(&signature.to_bytes()[..]))
+}
+
+fn deserialize_signature<'de, D>(deserializer: D) -> Result<BLS12381Signature, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let encoded_signature: String = Deserialize::deserialize(deserializer)?;
+    let bytes = hex::decode(encoded_signature).map_err(<D::Error as serde::de::Error>::custom)?;
+    BLS12381Signature::try_from(&bytes[..])
+        .map_err(|e| <D::Error as serde::de::Error>::custom(format!("{:?}", e)))
+}
+
 pub fn serialize_ordered_map<S, V, H>(
     value: &HashMap<String, V, H>,
     serializer: S,
@@ -26,3 +26,124 @@ fn verify_all_configs() {
         }
     }
 }
+
+#[test]
+fn verify_upstream_routing() {
+    let preferred_peer = PeerId::random();
+    let fallback_peer = PeerId::random();
+    let mut config = UpstreamConfig::default();
+    config.preferred_peers = vec![preferred_peer.to_string()];
+    config.fallback_peers = vec![fallback_peer.to_string()];
+    config.submission_routing = UpstreamRouting::PreferredOnly;
+
+    assert_eq!(
+        config.get_sync_peers(),
+        (vec![preferred_peer], vec![fallback_peer])
+    );
+    assert_eq!(
+        config.get_submission_peers(),
+        (vec![preferred_peer], vec![])
+    );
+    assert_eq!(
+        config.get_peers(UpstreamRouting::FallbackOnly),
+        (vec![], vec![fallback_peer])
+    );
+    assert_eq!(
+        config.get_peers(UpstreamRouting::Disabled),
+        (vec![], vec![])
+    );
+}
+
+#[test]
+fn verify_mempool_capacity_split() {
+    let mut config = MempoolConfig::default();
+    assert!(config.validate().is_ok());
+
+    config.local_capacity = config.capacity;
+    config.peer_capacity = 1;
+    assert!(config.validate().is_err());
+
+    config.peer_capacity = usize::max_value();
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn verify_ac_tls_identities() {
+    let identity = |name: &str, port, client_ca_cert: &[u8]| ClientIdentityConfig {
+        name: name.to_string(),
+        port,
+        client_ca_cert_path: PathBuf::from(format!("{}.pem", name)),
+        client_ca_cert: client_ca_cert.to_vec(),
+        quota: IdentityQuotaConfig::default(),
+    };
+    let mut config = AdmissionControlTlsConfig {
+        server_cert_path: PathBuf::from("server.pem"),
+        server_key_path: PathBuf::from("server.key"),
+        server_cert: vec![],
+        server_key: vec![],
+        identities: vec![identity("a", 1, b"a"), identity("b", 2, b"b")],
+        unauthenticated_quota: IdentityQuotaConfig::default(),
+    };
+    assert!(config.validate().is_ok());
+
+    config.identities[1] = identity("a", 2, b"b");
+    assert!(config.validate().is_err());
+    config.identities[1] = identity("b", 1, b"b");
+    assert!(config.validate().is_err());
+    // a certificate issued by the CA would grant both identities
+    config.identities[1] = identity("b", 2, b"a");
+    assert!(config.validate().is_err());
+
+    // a missing certificate is a config error
+    let dir = TempPath::new();
+    dir.create_as_dir().unwrap();
+    assert!(config.load(dir.path().join("node.config.toml")).is_err());
+}
+
+#[test]
+fn verify_shared_listener_without_relays() {
+    let mut config = NetworkConfig::default();
+    config.relays = vec!["/ip4/127.0.0.1/tcp/6181".parse().unwrap()];
+    assert!(config.validate().is_ok());
+    config.network_id = "/libra/network/public".to_string();
+    assert!(config.validate().is_err());
+    config.relays.clear();
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn verify_seeded_ports() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(false);
+    config.admission_control.json_gateway_port = None;
+    config.admission_control.tls = None;
+    let mut plain = config.clone();
+    NodeConfigHelpers::assign_config_ports(&mut plain, &mut PortGenerator::Sequential(10_000));
+
+    config.admission_control.json_gateway_port = Some(0);
+    config.admission_control.tls = Some(AdmissionControlTlsConfig {
+        server_cert_path: PathBuf::from("server.pem"),
+        server_key_path: PathBuf::from("server.key"),
+        server_cert: vec![],
+        server_key: vec![],
+        identities: vec![ClientIdentityConfig {
+            name: "a".to_string(),
+            port: 0,
+            client_ca_cert_path: PathBuf::from("a.pem"),
+            client_ca_cert: vec![],
+            quota: IdentityQuotaConfig::default(),
+        }],
+        unauthenticated_quota: IdentityQuotaConfig::default(),
+    });
+    NodeConfigHelpers::assign_config_ports(&mut config, &mut PortGenerator::Sequential(10_000));
+
+    // enabling the optional services does not move the ports of the other ones
+    assert_eq!(
+        config.admission_control.admission_control_service_port,
+        plain.admission_control.admission_control_service_port
+    );
+    assert_eq!(config.storage.port, plain.storage.port);
+    let json_gateway_port = config.admission_control.json_gateway_port.unwrap();
+    let identity_port = config.admission_control.tls.as_ref().unwrap().identities[0].port;
+    assert!(json_gateway_port > plain.storage.port);
+    assert!(identity_port > json_gateway_port);
+}
@@ -2,10 +2,39 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::SeedPeersConfigHelpers;
-use crate::trusted_peers::ConfigHelpers;
+use crate::{trusted_peers::ConfigHelpers, utils::PortGenerator};
+use parity_multiaddr::Protocol;
 
 #[test]
 fn generate_test_config() {
     let (_, _, network_peers_config) = ConfigHelpers::gen_validator_nodes(10, None);
     let _ = SeedPeersConfigHelpers::get_test_config(&network_peers_config, None);
 }
+
+#[test]
+fn generate_seeded_test_config() {
+    let (_, _, network_peers_config) = ConfigHelpers::gen_validator_nodes(10, None);
+    let gen_config = || {
+        SeedPeersConfigHelpers::get_test_config_with_ports(
+            &network_peers_config,
+            true,
+            &mut PortGenerator::from_seed(&[7u8; 32]),
+        )
+    };
+    let config = gen_config();
+    assert_eq!(config.seed_peers, gen_config().seed_peers);
+
+    // The peers get consecutive ports, in the order of their ids.
+    let mut peers: Vec<_> = config.seed_peers.into_iter().collect();
+    peers.sort_by(|(peer1, _), (peer2, _)| peer1.cmp(peer2));
+    let ports: Vec<_> = peers
+        .iter()
+        .map(|(_, addrs)| match addrs[0].iter().nth(1) {
+            Some(Protocol::Tcp(port)) => port,
+            _ => panic!("Seed peer address without a port"),
+        })
+        .collect();
+    for window in ports.windows(2) {
+        assert_eq!(window[0] + 1, window[1]);
+    }
+}
@@ -1,7 +1,8 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ConfigHelpers;
+use super::{ConfigHelpers, ConsensusPeersConfig};
+use crate::config::PersistableConfig;
 
 #[test]
 fn generate_test_config() {
@@ -9,3 +10,28 @@ fn generate_test_config() {
         ConfigHelpers::gen_validator_nodes(10, None);
     let (_keys, _network_peers_config) = ConfigHelpers::gen_full_nodes(10, None);
 }
+
+#[test]
+fn test_aggregate_keys() {
+    let (_keys, consensus_peers_config, _network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(4, None);
+    let aggregate_verifier = consensus_peers_config
+        .get_aggregate_validator_verifier()
+        .unwrap();
+    assert_eq!(aggregate_verifier.len(), 4);
+
+    // The aggregate keys and their proofs of possession are persisted with the consensus peers.
+    let serialized = toml::to_string(&consensus_peers_config).unwrap();
+    let parsed = ConsensusPeersConfig::parse(&serialized).unwrap();
+    assert!(parsed.get_aggregate_validator_verifier().is_ok());
+
+    // The validators cannot aggregate their signatures unless they all registered a key.
+    let mut partial_config = parsed.clone();
+    let peer_id = partial_config.peers.keys().next().unwrap().clone();
+    partial_config
+        .peers
+        .get_mut(&peer_id)
+        .unwrap()
+        .consensus_aggregate_key = None;
+    assert!(partial_config.get_aggregate_validator_verifier().is_err());
+}
@@ -26,6 +26,40 @@ pub fn get_available_port() -> u16 {
     panic!("Error: could not find an available port");
 }
 
+/// Hands out the ports of generated configs.
+pub enum PortGenerator {
+    /// Ephemeral ports, available on this machine at the time they are handed out.
+    Available,
+    /// Consecutive ports, starting from the given one.
+    Sequential(u16),
+}
+
+impl PortGenerator {
+    /// First port of the ports derived from a seed. They are below the ephemeral port range of
+    /// common OSes, so that the OS does not hand them out to other sockets in the meantime.
+    const MIN_SEEDED_PORT: u16 = 10_000;
+    const MAX_SEEDED_PORT: u16 = 30_000;
+
+    /// Consecutive ports starting from a port derived from `seed`: the ports are the same on
+    /// every machine, but not necessarily available on any.
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let offset = u16::from_be_bytes([seed[0], seed[1]])
+            % (Self::MAX_SEEDED_PORT - Self::MIN_SEEDED_PORT);
+        PortGenerator::Sequential(Self::MIN_SEEDED_PORT + offset)
+    }
+
+    pub fn next_port(&mut self) -> u16 {
+        match self {
+            PortGenerator::Available => get_available_port(),
+            PortGenerator::Sequential(next) => {
+                let port = *next;
+                *next = next.checked_add(1).expect("Ran out of ports");
+                port
+            }
+        }
+    }
+}
+
 fn get_ephemeral_port() -> ::std::io::Result<u16> {
     // Request a random available port from the OS
     let listener = TcpListener::bind(("localhost", 0))?;
@@ -90,6 +124,8 @@ where
     S: Serializer,
     H: BuildHasher,
 {
-    let encoded_whitelist: Vec<String> = whitelist.iter().map(hex::encode).collect();
+    let mut encoded_whitelist: Vec<String> = whitelist.iter().map(hex::encode).collect();
+    // Sort to serialize a set always the same way.
+    encoded_whitelist.sort();
     encoded_whitelist.serialize(serializer)
 }
@@ -77,6 +77,7 @@ impl<T: Payload> BlockStore<T> {
         state_computer: Arc<dyn StateComputer<Payload = T>>,
         enforce_increasing_timestamps: bool,
         max_pruned_blocks_in_mem: usize,
+        pruned_blocks_retention_rounds: Round,
     ) -> Self {
         let (root, blocks, quorum_certs) = initial_data.take();
         let inner = Arc::new(RwLock::new(
@@ -86,6 +87,7 @@ impl<T: Payload> BlockStore<T> {
                 quorum_certs,
                 Arc::clone(&state_computer),
                 max_pruned_blocks_in_mem,
+                pruned_blocks_retention_rounds,
             )
             .await,
         ));
@@ -104,6 +106,7 @@ impl<T: Payload> BlockStore<T> {
         quorum_certs: Vec<QuorumCert>,
         state_computer: Arc<dyn StateComputer<Payload = T>>,
         max_pruned_blocks_in_mem: usize,
+        pruned_blocks_retention_rounds: Round,
     ) -> BlockTree<T> {
         let (root_block, root_qc, root_li) = (root.0, root.1, root.2);
 
@@ -115,6 +118,7 @@ impl<T: Payload> BlockStore<T> {
             root_qc,
             root_li,
             max_pruned_blocks_in_mem,
+            pruned_blocks_retention_rounds,
         );
         let quorum_certs = quorum_certs
             .into_iter()
@@ -155,13 +159,20 @@ impl<T: Payload> BlockStore<T> {
         blocks: Vec<Block<T>>,
         quorum_certs: Vec<QuorumCert>,
     ) {
-        let max_pruned_blocks_in_mem = self.inner.read().unwrap().max_pruned_blocks_in_mem();
+        let (max_pruned_blocks_in_mem, pruned_blocks_retention_rounds) = {
+            let tree = self.inner.read().unwrap();
+            (
+                tree.max_pruned_blocks_in_mem(),
+                tree.pruned_blocks_retention_rounds(),
+            )
+        };
         let tree = Self::build_block_tree(
             root,
             blocks,
             quorum_certs,
             Arc::clone(&self.state_computer),
             max_pruned_blocks_in_mem,
+            pruned_blocks_retention_rounds,
         )
         .await;
         let to_remove = self.inner.read().unwrap().get_all_block_id();
@@ -301,6 +312,12 @@ impl<T: Payload> BlockStore<T> {
             .insert_vote(&vote_msg, validator_verifier)
     }
 
+    /// Drops the votes which didn't form a quorum cert yet, e.g. upon a reconfiguration, after
+    /// which they're counted against a different validator set.
+    pub fn clear_pending_votes(&self) {
+        self.inner.write().unwrap().clear_pending_votes()
+    }
+
     /// Prune the tree up to next_root_id (keep next_root_id's block).  Any branches not part of
     /// the next_root_id's tree should be removed as well.
     ///
@@ -311,6 +328,9 @@ impl<T: Payload> BlockStore<T> {
     /// prune_tree(B3) should be left with
     /// B3--> B4, root = B3
     ///
+    /// The pruned blocks are kept, in memory and in the persistent storage, until they fall out
+    /// of the retention window below the new root: they're garbage-collected from both at once.
+    ///
     /// Returns the block ids of the blocks removed.
     pub fn prune_tree(&self, next_root_id: HashValue) -> VecDeque<HashValue> {
         let id_to_remove = self
@@ -318,25 +338,24 @@ impl<T: Payload> BlockStore<T> {
             .read()
             .unwrap()
             .find_blocks_to_prune(next_root_id);
-        if let Err(e) = self
-            .storage
-            .prune_tree(id_to_remove.clone().into_iter().collect())
-        {
+        let id_to_collect = self
+            .inner
+            .write()
+            .unwrap()
+            .process_pruned_blocks(next_root_id, id_to_remove.clone());
+        if let Err(e) = self.storage.prune_tree(id_to_collect) {
             // it's fine to fail here, as long as the commit succeeds, the next restart will clean
             // up dangling blocks, and we need to prune the tree to keep the root consistent with
             // executor.
             error!("fail to delete block: {:?}", e);
         }
-        self.inner
-            .write()
-            .unwrap()
-            .process_pruned_blocks(next_root_id, id_to_remove.clone());
         id_to_remove
     }
 
-    /// If block id information is found, returns the ledger info placeholder, otherwise, return
-    /// a placeholder with info of the genesis block.
-    pub fn ledger_info_placeholder(&self, id: Option<HashValue>) -> LedgerInfo {
+    /// If block id information is found, returns the ledger info placeholder in `epoch`, which
+    /// carries the validator set the block moves to if any, otherwise, return a placeholder with
+    /// info of the genesis block.
+    pub fn ledger_info_placeholder(&self, id: Option<HashValue>, epoch: u64) -> LedgerInfo {
         let block_id = match id {
             None => return Self::zero_ledger_info_placeholder(),
             Some(id) => id,
@@ -347,10 +366,11 @@ impl<T: Payload> BlockStore<T> {
                 return Self::zero_ledger_info_placeholder();
             }
         };
-        let (state_id, version) = match self.get_compute_result(block_id) {
+        let (state_id, version, next_validator_set) = match self.get_compute_result(block_id) {
             Some(compute_state) => (
                 compute_state.executed_state.state_id,
                 compute_state.executed_state.version,
+                compute_state.executed_state.validators.clone(),
             ),
             None => {
                 return Self::zero_ledger_info_placeholder();
@@ -361,9 +381,9 @@ impl<T: Payload> BlockStore<T> {
             state_id,
             HashValue::zero(),
             block_id,
-            0, // TODO [Reconfiguration] use the real epoch number.
+            epoch,
             block.timestamp_usecs(),
-            None,
+            next_validator_set,
         )
     }
 
@@ -12,7 +12,7 @@ use crate::chained_bft::{
     },
     test_utils::{
         build_empty_tree, build_empty_tree_with_custom_signing, placeholder_certificate_for_block,
-        placeholder_ledger_info, TreeInserter,
+        placeholder_ledger_info, EmptyStateComputer, MockStorage, TreeInserter,
     },
 };
 use crypto::{HashValue, PrivateKey};
@@ -309,6 +309,41 @@ fn test_block_tree_gc() {
     }
 }
 
+#[test]
+fn test_block_tree_gc_retention_rounds() {
+    // build a chain of 20 nodes, max_pruned_nodes_in_mem = 10, pruned_blocks_retention_rounds = 3
+    let (storage, initial_data) = MockStorage::<Vec<usize>>::start_for_testing();
+    let block_store = Arc::new(block_on(BlockStore::new(
+        storage.clone(),
+        initial_data,
+        ValidatorSigner::random(None),
+        Arc::new(EmptyStateComputer),
+        true,
+        10,
+        3,
+    )));
+    let genesis = block_store.root();
+    let mut cur_node = block_store.get_block(genesis.id()).unwrap();
+    let mut inserter = TreeInserter::new(block_store.clone());
+    for round in 1..=20 {
+        cur_node = if round == 1 {
+            inserter.insert_block_with_qc(QuorumCert::certificate_for_genesis(), &cur_node, round)
+        } else {
+            inserter.insert_block(&cur_node, round)
+        };
+        block_store.prune_tree(cur_node.id());
+        // Only the pruned blocks of the 3 rounds below the root are kept, both in memory and in
+        // the persistent storage.
+        assert_eq!(block_store.len(), 1);
+        assert_eq!(block_store.pruned_blocks_in_mem(), min(round as usize, 3));
+        let stored_blocks = storage.shared_storage.block.lock().unwrap();
+        assert_eq!(stored_blocks.len(), 1 + min(round as usize, 3));
+        assert!(stored_blocks
+            .values()
+            .all(|block| block.round() + 3 >= round));
+    }
+}
+
 #[test]
 fn test_path_from_root() {
     let block_store = build_empty_tree();
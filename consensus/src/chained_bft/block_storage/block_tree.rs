@@ -5,6 +5,7 @@ use crate::chained_bft::block_storage::pending_votes::PendingVotes;
 use crate::{
     chained_bft::{
         block_storage::VoteReceptionResult,
+        common::Round,
         consensus_types::{block::ExecutedBlock, quorum_cert::QuorumCert, vote_msg::VoteMsg},
     },
     counters,
@@ -90,6 +91,8 @@ pub struct BlockTree<T> {
     pruned_block_ids: VecDeque<HashValue>,
     /// Num pruned blocks to keep in memory.
     max_pruned_blocks_in_mem: usize,
+    /// Num rounds below the root during which the pruned blocks are kept in memory.
+    pruned_blocks_retention_rounds: Round,
 }
 
 impl<T> BlockTree<T>
@@ -101,6 +104,7 @@ where
         root_quorum_cert: QuorumCert,
         root_ledger_info: QuorumCert,
         max_pruned_blocks_in_mem: usize,
+        pruned_blocks_retention_rounds: Round,
     ) -> Self {
         assert_eq!(
             root.id(),
@@ -115,6 +119,7 @@ where
         let mut id_to_block = HashMap::new();
         id_to_block.insert(root_id, LinkableBlock::new(root));
         counters::NUM_BLOCKS_IN_TREE.set(1);
+        counters::NUM_PRUNED_BLOCKS_IN_MEM.set(0);
 
         let root_quorum_cert = Arc::new(root_quorum_cert);
         let mut id_to_quorum_cert = HashMap::new();
@@ -135,6 +140,7 @@ where
             id_to_quorum_cert,
             pruned_block_ids,
             max_pruned_blocks_in_mem,
+            pruned_blocks_retention_rounds,
         }
     }
 
@@ -302,6 +308,10 @@ where
         res
     }
 
+    pub(super) fn clear_pending_votes(&mut self) {
+        self.pending_votes = PendingVotes::new();
+    }
+
     /// Find the blocks to prune up to next_root_id (keep next_root_id's block). Any branches not
     /// part of the next_root_id's tree should be removed as well.
     ///
@@ -345,27 +355,45 @@ where
     /// Note that we do not necessarily remove the pruned blocks: they're kept in a separate buffer
     /// for some time in order to enable other peers to retrieve the blocks even after they've
     /// been committed.
+    ///
+    /// Returns the ids of the blocks garbage-collected from the tree, i.e. the pruned blocks
+    /// which fell out of the retention window below the new root.
     pub(super) fn process_pruned_blocks(
         &mut self,
         root_id: HashValue,
-        mut newly_pruned_blocks: VecDeque<HashValue>,
-    ) {
+        newly_pruned_blocks: VecDeque<HashValue>,
+    ) -> Vec<HashValue> {
         assert!(self.block_exists(&root_id));
         // Update the next root
         self.root_id = root_id;
         counters::NUM_BLOCKS_IN_TREE.sub(newly_pruned_blocks.len() as i64);
-        // The newly pruned blocks are pushed back to the deque pruned_block_ids.
-        // In case the overall number of the elements is greater than the predefined threshold,
-        // the oldest elements (in the front of the deque) are removed from the tree.
-        self.pruned_block_ids.append(&mut newly_pruned_blocks);
-        if self.pruned_block_ids.len() > self.max_pruned_blocks_in_mem {
-            let num_blocks_to_remove = self.pruned_block_ids.len() - self.max_pruned_blocks_in_mem;
-            for _ in 0..num_blocks_to_remove {
-                if let Some(id) = self.pruned_block_ids.pop_front() {
-                    self.remove_block(id);
-                }
+        // The newly pruned blocks are pushed back to the deque pruned_block_ids, lowest round
+        // first. The oldest elements (in the front of the deque) are removed from the tree once
+        // their round falls below the retention window, or in case the overall number of the
+        // elements is greater than the predefined threshold.
+        let mut newly_pruned_blocks: Vec<HashValue> = newly_pruned_blocks.into_iter().collect();
+        newly_pruned_blocks.sort_by_key(|id| self.get_block(id).map(|block| block.round()));
+        self.pruned_block_ids.extend(newly_pruned_blocks);
+        let lowest_retained_round = self
+            .root()
+            .round()
+            .saturating_sub(self.pruned_blocks_retention_rounds);
+        let mut removed_block_ids = vec![];
+        while let Some(id) = self.pruned_block_ids.front().cloned() {
+            let is_expired = self.pruned_block_ids.len() > self.max_pruned_blocks_in_mem
+                || self
+                    .get_block(&id)
+                    .map_or(true, |block| block.round() < lowest_retained_round);
+            if !is_expired {
+                break;
             }
+            self.pruned_block_ids.pop_front();
+            self.remove_block(id);
+            removed_block_ids.push(id);
         }
+        counters::NUM_PRUNED_BLOCKS_IN_MEM.set(self.pruned_block_ids.len() as i64);
+        counters::GARBAGE_COLLECTED_BLOCKS_COUNT.inc_by(removed_block_ids.len() as i64);
+        removed_block_ids
     }
 
     /// Returns all the blocks between the root and the given block, including the given block
@@ -401,6 +429,10 @@ where
         self.max_pruned_blocks_in_mem
     }
 
+    pub(super) fn pruned_blocks_retention_rounds(&self) -> Round {
+        self.pruned_blocks_retention_rounds
+    }
+
     pub(super) fn get_all_block_id(&self) -> Vec<HashValue> {
         self.id_to_block.keys().cloned().collect()
     }
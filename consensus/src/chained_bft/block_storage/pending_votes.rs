@@ -12,7 +12,9 @@ use crypto::{hash::CryptoHash, HashValue};
 use logger::prelude::*;
 use std::{collections::HashMap, sync::Arc};
 use types::{
-    crypto_proxies::{LedgerInfoWithSignatures, ValidatorVerifier},
+    crypto_proxies::{
+        AggregateSignature, LedgerInfoWithSignatures, SignatureShare, ValidatorVerifier,
+    },
     validator_verifier::VerifyError,
 };
 
@@ -36,12 +38,18 @@ pub struct PendingVotes {
     /// Thus, the structure of `li_digest_to_votes` is as follows:
     /// HashMap<ledger_info_digest, LedgerInfoWithSignatures>
     li_digest_to_votes: HashMap<HashValue, LedgerInfoWithSignatures>,
+    /// The signature shares of the votes of `li_digest_to_votes`, which are aggregated into the
+    /// QuorumCert if the validators aggregate their signatures.
+    li_digest_to_signature_shares: HashMap<HashValue, HashMap<Author, SignatureShare>>,
     /// Tracks all the signatures of the votes for the given round. In case we succeed to
     /// aggregate 2f+1 signatures for the same round a TimeoutCertificate is formed.
     /// Note that QuorumCert has higher priority than TimeoutCertificate (in case 2f+1 votes are
     /// gathered for the same ledger info we are going to generate QuorumCert and not the
     /// TimeoutCertificate).
     round_to_tc: HashMap<Round, TimeoutCertificate>,
+    /// The round signature shares of the votes of `round_to_tc`, which are aggregated into the
+    /// TimeoutCertificate if the validators aggregate their signatures.
+    round_to_signature_shares: HashMap<Round, HashMap<Author, SignatureShare>>,
     /// Map of Author to last vote info. Any pending vote from Author is cleaned up
     /// whenever a new vote is added by same Author
     author_to_last_voted_info: HashMap<Author, LastVoteInfo>,
@@ -51,7 +59,9 @@ impl PendingVotes {
     pub fn new() -> Self {
         PendingVotes {
             li_digest_to_votes: HashMap::new(),
+            li_digest_to_signature_shares: HashMap::new(),
             round_to_tc: HashMap::new(),
+            round_to_signature_shares: HashMap::new(),
             author_to_last_voted_info: HashMap::new(),
         }
     }
@@ -96,12 +106,29 @@ impl PendingVotes {
             .signature()
             .clone()
             .add_to_li(vote_msg.author(), li_with_sig);
+        if let Some(signature_share) = vote_msg.signature_share() {
+            self.li_digest_to_signature_shares
+                .entry(li_digest)
+                .or_insert_with(HashMap::new)
+                .insert(vote_msg.author(), signature_share.clone());
+        }
 
         match validator_verifier.check_voting_power(li_with_sig.signatures().keys()) {
-            Ok(_) => VoteReceptionResult::NewQuorumCertificate(Arc::new(QuorumCert::new(
-                vote_msg.vote_data().clone(),
-                li_with_sig.clone(),
-            ))),
+            Ok(_) => {
+                let qc = match aggregate_signature_shares(
+                    validator_verifier,
+                    li_with_sig.signatures().keys(),
+                    self.li_digest_to_signature_shares.get(&li_digest),
+                ) {
+                    Some(aggregate_signature) => QuorumCert::new_with_aggregate_signature(
+                        vote_msg.vote_data().clone(),
+                        li_with_sig.clone(),
+                        aggregate_signature,
+                    ),
+                    None => QuorumCert::new(vote_msg.vote_data().clone(), li_with_sig.clone()),
+                };
+                VoteReceptionResult::NewQuorumCertificate(Arc::new(qc))
+            }
             Err(VerifyError::TooLittleVotingPower { voting_power, .. }) => {
                 VoteReceptionResult::VoteAdded(voting_power)
             }
@@ -128,10 +155,26 @@ impl PendingVotes {
             .entry(round)
             .or_insert_with(|| TimeoutCertificate::new(round, HashMap::new()));
         tc.add_signature(vote_msg.author(), round_signature);
+        if let Some(round_signature_share) = vote_msg.round_signature_share() {
+            self.round_to_signature_shares
+                .entry(round)
+                .or_insert_with(HashMap::new)
+                .insert(vote_msg.author(), round_signature_share.clone());
+        }
         match validator_verifier.check_voting_power(tc.signatures().keys()) {
-            Ok(_) => Some(VoteReceptionResult::NewTimeoutCertificate(Arc::new(
-                tc.clone(),
-            ))),
+            Ok(_) => {
+                let tc = match aggregate_signature_shares(
+                    validator_verifier,
+                    tc.signatures().keys(),
+                    self.round_to_signature_shares.get(&round),
+                ) {
+                    Some(aggregate_signature) => {
+                        TimeoutCertificate::new_with_aggregate_signature(round, aggregate_signature)
+                    }
+                    None => tc.clone(),
+                };
+                Some(VoteReceptionResult::NewTimeoutCertificate(Arc::new(tc)))
+            }
             Err(VerifyError::TooLittleVotingPower { .. }) => None,
             _ => panic!("Unexpected verification error, vote_msg = {}", vote_msg),
         }
@@ -180,6 +223,11 @@ impl PendingVotes {
                 self.li_digest_to_votes.remove(&last_voted_info.li_digest);
             }
         }
+        remove_signature_share(
+            &mut self.li_digest_to_signature_shares,
+            &last_voted_info.li_digest,
+            author,
+        );
 
         // Prune last pending vote from the pending timeout certificates.
         if round == last_voted_info.round {
@@ -200,7 +248,48 @@ impl PendingVotes {
                 self.round_to_tc.remove(&last_voted_info.round);
             }
         }
+        remove_signature_share(
+            &mut self.round_to_signature_shares,
+            &last_voted_info.round,
+            author,
+        );
 
         Ok(())
     }
 }
+
+/// Aggregates the signature shares of the `signers` if the validators aggregate their signatures
+/// and every signer sent its share, otherwise the certificate is made of the signatures.
+fn aggregate_signature_shares<'a>(
+    validator_verifier: &ValidatorVerifier,
+    signers: impl Iterator<Item = &'a Author>,
+    signature_shares: Option<&HashMap<Author, SignatureShare>>,
+) -> Option<AggregateSignature> {
+    let aggregate_verifier = validator_verifier.aggregate_verifier()?;
+    let signature_shares = signature_shares?;
+    let mut signer_shares = HashMap::new();
+    for signer in signers {
+        signer_shares.insert(*signer, signature_shares.get(signer)?.clone());
+    }
+    match SignatureShare::aggregate(aggregate_verifier, &signer_shares) {
+        Ok(aggregate_signature) => Some(aggregate_signature),
+        Err(e) => {
+            error!("Fail to aggregate the signature shares: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Prunes the signature share of `author` from the shares pending under `key`.
+fn remove_signature_share<K: std::hash::Hash + Eq>(
+    signature_shares: &mut HashMap<K, HashMap<Author, SignatureShare>>,
+    key: &K,
+    author: Author,
+) {
+    if let Some(pending_shares) = signature_shares.get_mut(key) {
+        pending_shares.remove(&author);
+        if pending_shares.is_empty() {
+            signature_shares.remove(key);
+        }
+    }
+}
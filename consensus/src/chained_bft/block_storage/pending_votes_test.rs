@@ -5,11 +5,14 @@ use crate::chained_bft::block_storage::pending_votes::PendingVotes;
 use crate::chained_bft::common::Round;
 use crate::chained_bft::{
     block_storage::VoteReceptionResult,
-    consensus_types::{vote_data::VoteData, vote_msg::VoteMsg},
+    consensus_types::{
+        quorum_cert::QuorumCert, timeout_certificate::TimeoutCertificate, vote_data::VoteData,
+        vote_msg::VoteMsg,
+    },
 };
 use crypto::HashValue;
-use std::sync::Arc;
-use types::crypto_proxies::random_validator_verifier;
+use std::{convert::TryFrom, sync::Arc};
+use types::crypto_proxies::{random_aggregate_validator_verifier, random_validator_verifier};
 use types::ledger_info::LedgerInfo;
 
 fn random_ledger_info() -> LedgerInfo {
@@ -24,6 +27,19 @@ fn random_ledger_info() -> LedgerInfo {
     )
 }
 
+/// LedgerInfo of a vote which does not commit any block
+fn random_uncommitting_ledger_info() -> LedgerInfo {
+    LedgerInfo::new(
+        0,
+        HashValue::random(),
+        HashValue::random(),
+        HashValue::zero(),
+        0,
+        0,
+        None,
+    )
+}
+
 fn random_vote_data(round: Round) -> VoteData {
     assert!(round >= 1);
     VoteData::new(
@@ -308,3 +324,140 @@ fn test_tc_aggregation_keep_last_only() {
         }
     };
 }
+
+#[test]
+/// Verify that the signature shares of the votes are aggregated into the QC when the validators
+/// aggregate their signatures
+fn test_qc_aggregation_with_aggregate_signatures() {
+    ::logger::try_init_for_testing();
+
+    let (signers, validator) = random_aggregate_validator_verifier(4);
+    let validator_verifier = Arc::new(validator);
+    let (_, plain_validator) = random_validator_verifier(4, None, false);
+
+    for (li, commits) in vec![
+        (random_uncommitting_ledger_info(), false),
+        (random_ledger_info(), true),
+    ] {
+        let mut pending_votes = PendingVotes::new();
+        let vote_data = random_vote_data(1);
+        let mut qc = None;
+        for signer in signers.iter().take(3) {
+            let vote = VoteMsg::new(vote_data.clone(), signer.author(), li.clone(), signer);
+            assert!(vote.signature_share().is_some());
+            assert!(vote.verify(&validator_verifier).is_ok());
+            if let VoteReceptionResult::NewQuorumCertificate(new_qc) =
+                pending_votes.insert_vote(&vote, Arc::clone(&validator_verifier))
+            {
+                qc = Some(new_qc);
+            }
+        }
+        let qc = qc.expect("No QC formed.");
+        assert!(qc.aggregate_signature().is_some());
+        // The individual signatures only make up the commit proof of a committed block.
+        assert_eq!(
+            qc.ledger_info().signatures().len(),
+            if commits { 3 } else { 0 }
+        );
+        assert!(qc.verify(&validator_verifier).is_ok());
+        // The aggregated signature is not verified without the aggregate keys.
+        assert!(qc.verify(&plain_validator).is_err());
+
+        let proto_qc: network::proto::QuorumCert = (*qc).clone().into();
+        assert_eq!(QuorumCert::try_from(proto_qc).unwrap(), *qc);
+    }
+}
+
+#[test]
+/// Verify that the QC is made of the signatures when some votes lack their signature shares
+fn test_qc_aggregation_without_signature_shares() {
+    ::logger::try_init_for_testing();
+
+    let (signers, validator) = random_aggregate_validator_verifier(4);
+    let validator_verifier = Arc::new(validator);
+    // The same validators, which do not sign with their aggregate keys
+    let (plain_signers, _) = random_validator_verifier(4, None, false);
+    let mut pending_votes = PendingVotes::new();
+
+    let li = random_uncommitting_ledger_info();
+    let vote_data = random_vote_data(1);
+    let votes = vec![
+        VoteMsg::new(
+            vote_data.clone(),
+            signers[0].author(),
+            li.clone(),
+            &signers[0],
+        ),
+        VoteMsg::new(
+            vote_data.clone(),
+            signers[1].author(),
+            li.clone(),
+            &signers[1],
+        ),
+        VoteMsg::new(
+            vote_data.clone(),
+            plain_signers[2].author(),
+            li.clone(),
+            &plain_signers[2],
+        ),
+    ];
+    assert!(votes[2].signature_share().is_none());
+    assert_eq!(
+        pending_votes.insert_vote(&votes[0], Arc::clone(&validator_verifier)),
+        VoteReceptionResult::VoteAdded(1)
+    );
+    assert_eq!(
+        pending_votes.insert_vote(&votes[1], Arc::clone(&validator_verifier)),
+        VoteReceptionResult::VoteAdded(2)
+    );
+    match pending_votes.insert_vote(&votes[2], Arc::clone(&validator_verifier)) {
+        VoteReceptionResult::NewQuorumCertificate(qc) => {
+            assert!(qc.aggregate_signature().is_none());
+            assert_eq!(qc.ledger_info().signatures().len(), 3);
+            assert!(qc.verify(&validator_verifier).is_ok());
+        }
+        _ => {
+            panic!("No QC formed.");
+        }
+    };
+}
+
+#[test]
+/// Verify that the round signature shares of the votes are aggregated into the TC when the
+/// validators aggregate their signatures
+fn test_tc_aggregation_with_aggregate_signatures() {
+    ::logger::try_init_for_testing();
+
+    let (signers, validator) = random_aggregate_validator_verifier(4);
+    let validator_verifier = Arc::new(validator);
+    let (_, plain_validator) = random_validator_verifier(4, None, false);
+    let mut pending_votes = PendingVotes::new();
+
+    // votes for different values in the same round can only form a TC
+    let mut tc = None;
+    for signer in signers.iter().take(3) {
+        let mut vote = VoteMsg::new(
+            random_vote_data(1),
+            signer.author(),
+            random_ledger_info(),
+            signer,
+        );
+        vote.add_round_signature(signer);
+        assert!(vote.round_signature_share().is_some());
+        assert!(vote.verify(&validator_verifier).is_ok());
+        if let VoteReceptionResult::NewTimeoutCertificate(new_tc) =
+            pending_votes.insert_vote(&vote, Arc::clone(&validator_verifier))
+        {
+            tc = Some(new_tc);
+        }
+    }
+    let tc = tc.expect("No TC formed.");
+    assert_eq!(tc.round(), 1);
+    assert!(tc.aggregate_signature().is_some());
+    assert!(tc.signatures().is_empty());
+    assert!(tc.verify(&validator_verifier).is_ok());
+    assert!(tc.verify(&plain_validator).is_err());
+
+    let proto_tc: network::proto::TimeoutCertificate = (*tc).clone().into();
+    assert_eq!(TimeoutCertificate::try_from(proto_tc).unwrap(), *tc);
+}
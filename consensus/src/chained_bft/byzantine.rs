@@ -0,0 +1,166 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Byzantine behaviors of a validator, used to check that the honest validators keep their safety
+//! and liveness guarantees in end-to-end tests.
+//!
+//! None of the behaviors is active unless [`set_byzantine_behavior`] is called, which only the
+//! test-only `byzantine-node` binary does.
+
+use crate::{
+    chained_bft::{
+        block_storage::{BlockReader, BlockStore},
+        common::Payload,
+        consensus_types::proposal_msg::ProposalMsg,
+        epoch_manager::EpochManager,
+        network::ConsensusNetworkImpl,
+    },
+    util::time_service::TimeService,
+};
+use failure::prelude::*;
+use lazy_static::lazy_static;
+use logger::prelude::*;
+use network::proto::{Block as BlockProto, ConsensusMsg, ConsensusMsg_oneof, Proposal, Vote};
+use rand::{thread_rng, Rng};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// How often a [`ByzantineBehavior::GarbageSpammer`] broadcasts malformed messages.
+const GARBAGE_INTERVAL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    static ref BEHAVIOR: RwLock<Option<ByzantineBehavior>> = RwLock::new(None);
+}
+
+/// A deviation from the protocol that a validator can be made to follow.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ByzantineBehavior {
+    /// In the rounds it leads, sends half of the validators a proposal and the other half a
+    /// conflicting one.
+    EquivocatingProposer,
+    /// Votes as usual but never sends its votes out.
+    VoteWithholder,
+    /// Keeps broadcasting malformed and badly signed consensus messages.
+    GarbageSpammer,
+}
+
+impl FromStr for ByzantineBehavior {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "equivocating-proposer" => Ok(ByzantineBehavior::EquivocatingProposer),
+            "vote-withholder" => Ok(ByzantineBehavior::VoteWithholder),
+            "garbage-spammer" => Ok(ByzantineBehavior::GarbageSpammer),
+            _ => bail!("Unknown byzantine behavior: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for ByzantineBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ByzantineBehavior::EquivocatingProposer => "equivocating-proposer",
+            ByzantineBehavior::VoteWithholder => "vote-withholder",
+            ByzantineBehavior::GarbageSpammer => "garbage-spammer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Makes consensus follow `behavior` from now on. Must be called before consensus is started.
+pub fn set_byzantine_behavior(behavior: ByzantineBehavior) {
+    warn!("Consensus is going to behave as a byzantine {}", behavior);
+    *BEHAVIOR.write().unwrap() = Some(behavior);
+}
+
+pub(crate) fn byzantine_behavior() -> Option<ByzantineBehavior> {
+    *BEHAVIOR.read().unwrap()
+}
+
+/// Sends `proposal_msg` to the first half of the validators and a proposal for a conflicting
+/// block of the same round to the second half.
+pub(crate) async fn equivocate<T: Payload>(
+    block_store: &BlockStore<T>,
+    network: &ConsensusNetworkImpl,
+    epoch_mgr: &EpochManager,
+    proposal_msg: ProposalMsg<T>,
+) {
+    let proposal = proposal_msg.proposal();
+    let parent = match block_store.get_block(proposal.parent_id()) {
+        Some(parent) => parent,
+        None => {
+            error!(
+                "Parent of proposal {} not found, not equivocating",
+                proposal
+            );
+            return;
+        }
+    };
+    // Any different block of the same round will do: a different timestamp changes its id.
+    let conflicting_block = block_store.create_block(
+        parent.block(),
+        T::default(),
+        proposal.round(),
+        proposal.timestamp_usecs() + 1,
+    );
+    let conflicting_proposal_msg =
+        ProposalMsg::new(conflicting_block, proposal_msg.sync_info().clone());
+    debug!(
+        "Equivocating between {} and {}",
+        proposal_msg.proposal(),
+        conflicting_proposal_msg.proposal()
+    );
+
+    let mut validators = epoch_mgr.validators().get_ordered_account_addresses();
+    let second_half = validators.split_off(validators.len() / 2);
+    network.send_proposal(proposal_msg, validators).await;
+    network
+        .send_proposal(conflicting_proposal_msg, second_half)
+        .await;
+}
+
+/// Broadcasts malformed proposals and votes with random signatures to all the validators, for as
+/// long as consensus is running.
+pub(crate) async fn spam_garbage(
+    mut network: ConsensusNetworkImpl,
+    time_service: Arc<dyn TimeService>,
+) {
+    loop {
+        let messages = {
+            let mut rng = thread_rng();
+            let proposal = Proposal {
+                proposed_block: Some(BlockProto {
+                    id: rng.gen::<[u8; 32]>().to_vec(),
+                    payload: rng.gen::<[u8; 32]>().to_vec(),
+                    round: rng.gen(),
+                    height: rng.gen(),
+                    ..BlockProto::default()
+                }),
+                sync_info: None,
+            };
+            let vote = Vote {
+                author: rng.gen::<[u8; 32]>().to_vec(),
+                signature: rng.gen::<[u8; 32]>().to_vec(),
+                round_signature: rng.gen::<[u8; 32]>().to_vec(),
+                ..Vote::default()
+            };
+            vec![
+                ConsensusMsg_oneof::Proposal(proposal),
+                ConsensusMsg_oneof::Vote(vote),
+            ]
+        };
+        for message in messages {
+            network
+                .broadcast_raw(ConsensusMsg {
+                    message: Some(message),
+                })
+                .await;
+        }
+        time_service.sleep(GARBAGE_INTERVAL).await;
+    }
+}
@@ -10,12 +10,12 @@ use crate::{
         persistent_storage::{PersistentStorage, StorageWriteProxy},
     },
     consensus_provider::ConsensusProvider,
-    counters,
     state_computer::ExecutionProxy,
     state_replication::StateMachineReplication,
     txn_manager::MempoolProxy,
 };
-use config::config::{ConsensusProposerType::FixedProposer, NodeConfig};
+use config::config::NodeConfig;
+use disk_monitor::DiskMonitor;
 use executor::Executor;
 use failure::prelude::*;
 use logger::prelude::*;
@@ -23,10 +23,11 @@ use mempool::proto::mempool::MempoolClient;
 use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
 use state_synchronizer::StateSyncClient;
 use std::{convert::TryFrom, sync::Arc};
-use tokio::runtime;
+use task_manager::build_runtime;
+use trusted_ledger::TrustedLedger;
 use types::{
     account_address::AccountAddress,
-    crypto_proxies::{ValidatorSigner, ValidatorVerifier},
+    crypto_proxies::{derive_aggregate_key, ValidatorSigner, ValidatorVerifier},
     transaction::SignedTransaction,
 };
 use vm_runtime::MoveVM;
@@ -40,9 +41,11 @@ struct InitialSetup {
 /// Supports the implementation of ConsensusProvider using LibraBFT.
 pub struct ChainedBftProvider {
     smr: ChainedBftSMR<Vec<SignedTransaction>>,
-    mempool_client: Arc<MempoolClient>,
+    txn_manager: Arc<MempoolProxy>,
     executor: Arc<Executor<MoveVM>>,
     synchronizer_client: Arc<StateSyncClient>,
+    trusted_ledger: TrustedLedger,
+    disk_monitor: DiskMonitor,
 }
 
 impl ChainedBftProvider {
@@ -53,37 +56,42 @@ impl ChainedBftProvider {
         mempool_client: Arc<MempoolClient>,
         executor: Arc<Executor<MoveVM>>,
         synchronizer_client: Arc<StateSyncClient>,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
     ) -> Self {
-        let runtime = runtime::Builder::new()
-            .name_prefix("consensus-")
-            .build()
-            .expect("Failed to create Tokio runtime!");
+        let runtime = build_runtime("consensus-");
 
         let initial_setup = Self::initialize_setup(node_config);
-        let epoch_mgr = Arc::new(EpochManager::new(0, initial_setup.validator.clone()));
+        let (storage, initial_data) = StorageWriteProxy::start(node_config);
+        info!(
+            "Starting up the consensus state machine with recovery data - {:?}, {}",
+            initial_data.state(),
+            initial_data.highest_timeout_certificates()
+        );
+        // A node which went through a reconfiguration restarts in the epoch it reached, the
+        // validators of the node config only make up the initial epoch.
+        let epoch_mgr = Arc::new(match initial_data.epoch_info() {
+            Some(epoch_info) => EpochManager::from_epoch_info(epoch_info),
+            None => EpochManager::new(0, initial_setup.validator),
+        });
         let network = ConsensusNetworkImpl::new(
             initial_setup.author,
             network_sender.clone(),
             network_events,
             Arc::clone(&epoch_mgr),
         );
-        let proposer = {
-            let peers = epoch_mgr.validators().get_ordered_account_addresses();
-            if node_config.consensus.get_proposer_type() == FixedProposer {
-                vec![Self::choose_leader(peers)]
-            } else {
-                peers
-            }
-        };
+        let proposer = ChainedBftSMR::<Vec<SignedTransaction>>::proposers(
+            node_config.consensus.get_proposer_type(),
+            &epoch_mgr.validators(),
+        );
         debug!("[Consensus] My peer: {:?}", initial_setup.author);
         debug!("[Consensus] Chosen proposer: {:?}", proposer);
         let config = ChainedBftSMRConfig::from_node_config(&node_config.consensus);
-        let (storage, initial_data) = StorageWriteProxy::start(node_config);
-        info!(
-            "Starting up the consensus state machine with recovery data - {:?}, {}",
-            initial_data.state(),
-            initial_data.highest_timeout_certificates()
-        );
+        let txn_manager = Arc::new(MempoolProxy::new(
+            mempool_client,
+            *node_config.consensus.max_block_bytes(),
+            *node_config.consensus.max_block_gas(),
+        ));
         let smr = ChainedBftSMR::new(
             initial_setup.author,
             initial_setup.signer,
@@ -97,9 +105,11 @@ impl ChainedBftProvider {
         );
         Self {
             smr,
-            mempool_client,
+            txn_manager,
             executor,
             synchronizer_client,
+            trusted_ledger,
+            disk_monitor,
         }
     }
 
@@ -120,16 +130,34 @@ impl ChainedBftProvider {
             .expect(
             "Failed to move a Consensus private key from a NodeConfig, key absent or already read",
         );
-        let signer = ValidatorSigner::new(author, private_key);
+        let aggregate_key = derive_aggregate_key(&private_key);
+        let mut signer = ValidatorSigner::new(author, private_key);
         // Keeping the initial set of validators in a node config is embarrassing and we should
         // all feel bad about it.
-        let validator = node_config
+        let mut validator = node_config
             .consensus
             .consensus_peers
             .get_validator_verifier();
-        counters::EPOCH_NUM.set(0); // No reconfiguration yet, so it is always zero
-        counters::CURRENT_EPOCH_NUM_VALIDATORS.set(validator.len() as i64);
-        counters::CURRENT_EPOCH_QUORUM_SIZE.set(validator.quorum_voting_power() as i64);
+        if node_config.consensus.aggregate_signatures() {
+            let aggregate_validator = node_config
+                .consensus
+                .consensus_peers
+                .get_aggregate_validator_verifier()
+                .expect("Failed to load the aggregate keys of the validators");
+            validator = validator
+                .with_aggregate_verifier(aggregate_validator)
+                .expect("Failed to load the aggregate keys of the validators");
+            signer = signer.with_aggregate_key(aggregate_key);
+            assert_eq!(
+                signer
+                    .aggregate_signer()
+                    .map(|aggregate_signer| aggregate_signer.public_key()),
+                validator
+                    .aggregate_verifier()
+                    .and_then(|aggregate_validator| aggregate_validator.get_public_key(&author)),
+                "The aggregate key of this validator is not the one registered"
+            );
+        }
         debug!(
             "[Consensus]: quorum_size = {:?}",
             validator.quorum_voting_power()
@@ -140,22 +168,16 @@ impl ChainedBftProvider {
             validator,
         }
     }
-
-    /// Choose a proposer that is going to be the single leader (relevant for a mock fixed proposer
-    /// election only).
-    fn choose_leader(peers: Vec<Author>) -> Author {
-        // As it is just a tmp hack function, pick the max PeerId to be a proposer.
-        // TODO: VRF will be integrated later.
-        peers.into_iter().max().expect("No trusted peers found!")
-    }
 }
 
 impl ConsensusProvider for ChainedBftProvider {
     fn start(&mut self) -> Result<()> {
-        let txn_manager = Arc::new(MempoolProxy::new(self.mempool_client.clone()));
+        let txn_manager = Arc::clone(&self.txn_manager);
         let state_computer = Arc::new(ExecutionProxy::new(
             Arc::clone(&self.executor),
             self.synchronizer_client.clone(),
+            self.trusted_ledger.clone(),
+            self.disk_monitor.clone(),
         ));
         debug!("Starting consensus provider.");
         self.smr.start(txn_manager, state_computer)
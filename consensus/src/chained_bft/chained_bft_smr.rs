@@ -1,18 +1,25 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "byzantine")]
+use crate::chained_bft::byzantine::{self, ByzantineBehavior};
 use crate::{
     chained_bft::{
         block_storage::BlockStore,
         common::{Payload, Round},
+        consensus_types::{quorum_cert::QuorumCert, sync_info::SyncInfo},
         event_processor::EventProcessor,
         liveness::{
             multi_proposer_election::MultiProposer,
-            pacemaker::{ExponentialTimeInterval, Pacemaker},
+            pacemaker::{
+                AdaptiveTimeInterval, ExponentialTimeInterval, Pacemaker, PacemakerTimeInterval,
+            },
             pacemaker_timeout_manager::HighestTimeoutCertificates,
             proposal_generator::ProposalGenerator,
             proposer_election::ProposerElection,
+            reputation_proposer_election::{ProposerFailures, ReputationProposer},
             rotating_proposer_election::RotatingProposer,
+            weighted_proposer_election::WeightedProposer,
         },
         network::{ConsensusNetworkImpl, NetworkReceivers},
         persistent_storage::{PersistentLivenessStorage, PersistentStorage, RecoveryData},
@@ -24,31 +31,34 @@ use crate::{
 };
 use channel;
 use failure::prelude::*;
-use futures::{
-    compat::Future01CompatExt,
-    executor::block_on,
-    future::{FutureExt, TryFutureExt},
-    select,
-    stream::StreamExt,
-};
+use futures::{compat::Future01CompatExt, executor::block_on, select, stream::StreamExt};
 
 use crate::chained_bft::{common::Author, epoch_manager::EpochManager};
-use config::config::{ConsensusConfig, ConsensusProposerType};
-use logger::prelude::*;
+use config::config::{AdaptiveTimeoutConfig, ConsensusConfig, ConsensusProposerType};
+use logger::{context::with_log_context, prelude::*};
+use network::validator_network::{BroadcastPolicy, DeliveryStatus};
 use std::{sync::Arc, time::Duration};
-use tokio::runtime::{Runtime, TaskExecutor};
-use types::crypto_proxies::ValidatorSigner;
+use task_manager::TaskManager;
+use tokio::runtime::Runtime;
+use types::crypto_proxies::{ValidatorSigner, ValidatorVerifier};
 
 /// Consensus configuration derived from ConsensusConfig
+#[derive(Clone)]
 pub struct ChainedBftSMRConfig {
     /// Keep up to this number of committed blocks before cleaning them up from the block store.
     pub max_pruned_blocks_in_mem: usize,
+    /// Keep the committed blocks until they are this number of rounds below the root.
+    pub pruned_blocks_retention_rounds: u64,
     /// Initial timeout for pacemaker
     pub pacemaker_initial_timeout: Duration,
+    /// Pacemaker timeouts adapting to the observed round durations, if any
+    pub adaptive_timeout: Option<AdaptiveTimeoutConfig>,
     /// Consensus proposer type
     pub proposer_type: ConsensusProposerType,
     /// Contiguous rounds for proposer
     pub contiguous_rounds: u32,
+    /// Rounds during which a proposer which failed its round is skipped by the reputation proposer
+    pub proposer_reputation_window: u64,
     /// Max block size (number of transactions) that consensus pulls from mempool
     pub max_block_size: u64,
 }
@@ -58,9 +68,12 @@ impl ChainedBftSMRConfig {
         let pacemaker_initial_timeout_ms = cfg.pacemaker_initial_timeout_ms().unwrap_or(1000);
         ChainedBftSMRConfig {
             max_pruned_blocks_in_mem: cfg.max_pruned_blocks_in_mem().unwrap_or(10000) as usize,
+            pruned_blocks_retention_rounds: cfg.pruned_blocks_retention_rounds(),
             pacemaker_initial_timeout: Duration::from_millis(pacemaker_initial_timeout_ms),
+            adaptive_timeout: cfg.adaptive_timeout().clone(),
             proposer_type: cfg.get_proposer_type(),
             contiguous_rounds: cfg.contiguous_rounds(),
+            proposer_reputation_window: cfg.proposer_reputation_window(),
             max_block_size: cfg.max_block_size(),
         }
     }
@@ -74,6 +87,8 @@ pub struct ChainedBftSMR<T> {
     signer: Option<ValidatorSigner>,
     proposers: Vec<Author>,
     runtime: Option<Runtime>,
+    // Spawns the tasks of consensus onto the runtime once started
+    task_manager: Option<TaskManager>,
     block_store: Option<Arc<BlockStore<T>>>,
     network: ConsensusNetworkImpl,
     config: ChainedBftSMRConfig,
@@ -99,6 +114,7 @@ impl<T: Payload> ChainedBftSMR<T> {
             signer: Some(signer),
             proposers,
             runtime: Some(runtime),
+            task_manager: None,
             block_store: None,
             network,
             config,
@@ -122,11 +138,19 @@ impl<T: Payload> ChainedBftSMR<T> {
     ) -> Pacemaker {
         // 1.5^6 ~= 11
         // Timeout goes from initial_timeout to initial_timeout*11 in 6 steps
-        let time_interval = Box::new(ExponentialTimeInterval::new(
-            self.config.pacemaker_initial_timeout,
-            1.5,
-            6,
-        ));
+        let time_interval: Box<dyn PacemakerTimeInterval> = match &self.config.adaptive_timeout {
+            Some(adaptive_timeout) => Box::new(AdaptiveTimeInterval::new(
+                self.config.pacemaker_initial_timeout,
+                1.5,
+                6,
+                adaptive_timeout,
+            )),
+            None => Box::new(ExponentialTimeInterval::new(
+                self.config.pacemaker_initial_timeout,
+                1.5,
+                6,
+            )),
+        };
         Pacemaker::new(
             persistent_liveness_storage,
             time_interval,
@@ -136,31 +160,92 @@ impl<T: Payload> ChainedBftSMR<T> {
         )
     }
 
-    /// Create a proposer election handler based on proposers
-    fn create_proposer_election(&self) -> Box<dyn ProposerElection<T> + Send + Sync> {
-        assert!(!self.proposers.is_empty());
-        match self.config.proposer_type {
+    /// Returns the proposers among `validators` for the given proposer type.
+    pub fn proposers(
+        proposer_type: ConsensusProposerType,
+        validators: &ValidatorVerifier,
+    ) -> Vec<Author> {
+        let peers = validators.get_ordered_account_addresses();
+        if proposer_type == ConsensusProposerType::FixedProposer {
+            // As it is just a tmp hack, pick the max PeerId to be the single leader.
+            // TODO: VRF will be integrated later.
+            vec![peers.into_iter().max().expect("No trusted peers found!")]
+        } else {
+            peers
+        }
+    }
+
+    /// Create a proposer election handler based on proposers. The reputation proposer starts
+    /// from the `proposer_failures` of `epoch`, and persists them to `storage`.
+    fn create_proposer_election(
+        config: &ChainedBftSMRConfig,
+        proposers: Vec<Author>,
+        validators: &ValidatorVerifier,
+        epoch: u64,
+        proposer_failures: ProposerFailures,
+        storage: Box<dyn PersistentLivenessStorage>,
+    ) -> Box<dyn ProposerElection<T> + Send + Sync> {
+        assert!(!proposers.is_empty());
+        match config.proposer_type {
             ConsensusProposerType::MultipleOrderedProposers => {
-                Box::new(MultiProposer::new(self.proposers.clone(), 2))
+                Box::new(MultiProposer::new(proposers, 2))
             }
-            // We don't really have a fixed proposer!
-            _ => Box::new(RotatingProposer::new(
-                self.proposers.clone(),
-                self.config.contiguous_rounds,
+            ConsensusProposerType::WeightedProposer => {
+                let proposers = proposers
+                    .into_iter()
+                    .map(|author| (author, validators.get_voting_power(&author).unwrap_or(0)))
+                    .collect();
+                Box::new(WeightedProposer::new(proposers, config.contiguous_rounds))
+            }
+            ConsensusProposerType::ReputationProposer => Box::new(ReputationProposer::new(
+                proposers,
+                config.contiguous_rounds,
+                config.proposer_reputation_window,
+                epoch,
+                proposer_failures,
+                storage,
             )),
+            // We don't really have a fixed proposer!
+            _ => Box::new(RotatingProposer::new(proposers, config.contiguous_rounds)),
         }
     }
 
     fn start_event_processing(
         &mut self,
-        executor: TaskExecutor,
+        task_manager: &TaskManager,
         mut event_processor: EventProcessor<T>,
         mut pacemaker_timeout_sender_rx: channel::Receiver<Round>,
         mut network_receivers: NetworkReceivers<T>,
+        pending_commit: Option<QuorumCert>,
     ) {
+        let config = self.config.clone();
+        let epoch_mgr = Arc::clone(&self.epoch_mgr);
+        let storage = Arc::clone(&self.storage);
         let fut = async move {
+            let mut epoch = epoch_mgr.epoch();
+            if let Some(pending_commit) = pending_commit {
+                if let Err(e) = event_processor.replay_pending_commit(pending_commit).await {
+                    error!("Failed to replay the pending commit: {:?}", e);
+                }
+            }
             event_processor.start().await;
             loop {
+                // A commit moved consensus to a new epoch: its proposers are elected among the
+                // validators of this epoch.
+                if epoch_mgr.epoch() != epoch {
+                    epoch = epoch_mgr.epoch();
+                    let validators = epoch_mgr.validators();
+                    let proposers = Self::proposers(config.proposer_type, &validators);
+                    debug!("[Consensus] Proposers of epoch {}: {:?}", epoch, proposers);
+                    event_processor.start_new_epoch(Self::create_proposer_election(
+                        &config,
+                        proposers,
+                        &validators,
+                        epoch,
+                        ProposerFailures::default(),
+                        storage.persistent_liveness_storage(),
+                    ));
+                }
                 select! {
                     proposal_msg = network_receivers.proposals.select_next_some() => {
                         event_processor.process_proposal_msg(proposal_msg).await;
@@ -186,7 +271,9 @@ impl<T: Payload> ChainedBftSMR<T> {
                 }
             }
         };
-        executor.spawn(fut.boxed().unit_error().compat());
+        // The event processor keeps track of the epoch and round it is working on in the log
+        // context of its task.
+        task_manager.spawn("event_processor", with_log_context(fut));
     }
 }
 
@@ -205,14 +292,26 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
             .executor();
         // Start network receivers before blocking on state synchronizer to unblock delivery of
         // network events.
-        let network_receivers = self.network.start(&executor);
-        let time_service = Arc::new(ClockTimeService::new(executor.clone()));
-        let initial_data = self
+        let task_manager = TaskManager::new("consensus", executor.clone());
+        let network_receivers = self.network.start(&task_manager);
+        let time_service = Arc::new(ClockTimeService::new(executor));
+        let mut initial_data = self
             .initial_data
             .take()
             .expect("already started, initial data is None");
         let consensus_state = initial_data.state();
         let highest_timeout_certificates = initial_data.highest_timeout_certificates().clone();
+        let proposer_failures = initial_data.proposer_failures().clone();
+        let pending_commit = initial_data.take_pending_commit();
+        // The network only knows the validators of the node config, which may have left since.
+        if let Some(epoch_info) = initial_data.epoch_info().cloned() {
+            let network = self.network.clone();
+            task_manager.spawn("restored_eligible_nodes", async move {
+                network
+                    .update_eligible_nodes(&epoch_info.validator_set)
+                    .await
+            });
+        }
         if initial_data.need_sync() {
             // make sure we sync to the root state in case we're not
             state_computer.sync_to_or_bail(initial_data.root_ledger_info());
@@ -230,6 +329,7 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
             Arc::clone(&state_computer),
             true,
             self.config.max_pruned_blocks_in_mem,
+            self.config.pruned_blocks_retention_rounds,
         )));
 
         self.block_store = Some(Arc::clone(&block_store));
@@ -255,7 +355,51 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
             highest_timeout_certificates,
         );
 
-        let proposer_election = self.create_proposer_election();
+        // Announce the recovered certificates to the other validators, so that the ones which
+        // lag behind catch up.
+        if block_store.highest_quorum_cert().certified_block_round() > 0 {
+            let sync_info = SyncInfo::new(
+                block_store.highest_quorum_cert().as_ref().clone(),
+                block_store.highest_ledger_info().as_ref().clone(),
+                pacemaker.highest_timeout_certificate(),
+            );
+            match self
+                .network
+                .broadcast_sync_info(sync_info.clone(), BroadcastPolicy::default())
+            {
+                Ok(mut broadcast) => {
+                    let network = self.network.clone();
+                    let f_broadcast = async move {
+                        if !broadcast.quorum().await {
+                            warn!("Failed to announce the recovered certificates to a quorum");
+                        }
+                        broadcast.complete().await;
+                        // Validators running a version which doesn't acknowledge sync info rpcs
+                        // yet get the sync info as a plain message instead.
+                        let unacked_peers: Vec<_> = broadcast
+                            .statuses()
+                            .iter()
+                            .filter(|(_, status)| **status == DeliveryStatus::Failed)
+                            .map(|(peer_id, _)| *peer_id)
+                            .collect();
+                        for peer_id in unacked_peers {
+                            network.send_sync_info(sync_info.clone(), peer_id).await;
+                        }
+                    };
+                    task_manager.spawn("recovered_sync_info_broadcast", f_broadcast);
+                }
+                Err(e) => error!("Failed to announce the recovered certificates: {:?}", e),
+            }
+        }
+
+        let proposer_election = Self::create_proposer_election(
+            &self.config,
+            self.proposers.clone(),
+            &self.epoch_mgr.validators(),
+            self.epoch_mgr.epoch(),
+            proposer_failures,
+            self.storage.persistent_liveness_storage(),
+        );
         let event_processor = EventProcessor::new(
             self.author,
             Arc::clone(&block_store),
@@ -272,19 +416,34 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
             Arc::clone(&self.epoch_mgr),
         );
 
+        #[cfg(feature = "byzantine")]
+        {
+            if byzantine::byzantine_behavior() == Some(ByzantineBehavior::GarbageSpammer) {
+                task_manager.spawn(
+                    "garbage_spammer",
+                    byzantine::spam_garbage(self.network.clone(), time_service.clone()),
+                );
+            }
+        }
+
         self.start_event_processing(
-            executor,
+            &task_manager,
             event_processor,
             timeout_receiver,
             network_receivers,
+            pending_commit,
         );
 
+        self.task_manager = Some(task_manager);
         debug!("Chained BFT SMR started.");
         Ok(())
     }
 
     /// Stop is synchronous: waits for all the worker threads to terminate.
     fn stop(&mut self) {
+        if let Some(task_manager) = self.task_manager.take() {
+            task_manager.shutdown();
+        }
         if let Some(rt) = self.runtime.take() {
             block_on(rt.shutdown_now().compat()).unwrap();
             debug!("Chained BFT SMR stopped.")
@@ -31,7 +31,8 @@ use crate::chained_bft::{
     test_utils::{consensus_runtime, with_smr_id},
 };
 use config::config::ConsensusProposerType::{
-    self, FixedProposer, MultipleOrderedProposers, RotatingProposer,
+    self, FixedProposer, MultipleOrderedProposers, ReputationProposer, RotatingProposer,
+    WeightedProposer,
 };
 use std::time::Duration;
 use tokio::runtime;
@@ -84,9 +85,12 @@ impl SMRNode {
 
         let config = ChainedBftSMRConfig {
             max_pruned_blocks_in_mem: 10000,
+            pruned_blocks_retention_rounds: 100,
             pacemaker_initial_timeout: Duration::from_secs(3),
+            adaptive_timeout: None,
             proposer_type,
             contiguous_rounds: 2,
+            proposer_reputation_window: 10,
             max_block_size: 50,
         };
         let mut smr = ChainedBftSMR::new(
@@ -158,7 +162,10 @@ impl SMRNode {
         let proposer = {
             match proposer_type {
                 FixedProposer => vec![peers[0]],
-                RotatingProposer | MultipleOrderedProposers => peers,
+                RotatingProposer
+                | MultipleOrderedProposers
+                | WeightedProposer
+                | ReputationProposer => peers,
             }
         };
         let mut nodes = vec![];
@@ -307,6 +314,18 @@ fn happy_path_with_multi_proposer() {
     basic_full_round(2, 2, MultipleOrderedProposers);
 }
 
+#[test]
+/// Basic happy path with the proposers weighted by voting power
+fn happy_path_with_weighted_proposer() {
+    basic_full_round(2, 2, WeightedProposer);
+}
+
+#[test]
+/// Basic happy path with the proposers skipping the ones which failed their rounds
+fn happy_path_with_reputation_proposer() {
+    basic_full_round(2, 2, ReputationProposer);
+}
+
 /// Verify the basic e2e flow: blocks are committed, txn manager is notified, block tree is
 /// pruned, restart the node and we can still continue.
 #[test]
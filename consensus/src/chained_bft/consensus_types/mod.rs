@@ -3,6 +3,8 @@
 
 pub(crate) mod block;
 pub(crate) mod proposal_msg;
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) mod proptest_types;
 pub(crate) mod quorum_cert;
 pub(crate) mod sync_info;
 pub(crate) mod timeout_certificate;
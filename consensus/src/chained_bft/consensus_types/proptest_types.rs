@@ -0,0 +1,184 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proptest strategies for the consensus messages exchanged over the network.
+//!
+//! The generated messages are well formed and carry valid signatures of a quorum of the validators
+//! they are generated for, so they pass `verify` against [`validator_verifier`] and exercise the
+//! message handling code beyond the signature checks. Invalid messages can be derived from them by
+//! tampering with a single field.
+
+use crate::chained_bft::{
+    common::Round,
+    consensus_types::{
+        quorum_cert::QuorumCert,
+        sync_info::SyncInfo,
+        timeout_msg::{PacemakerTimeout, PacemakerTimeoutCertificate, TimeoutMsg},
+        vote_data::VoteData,
+    },
+};
+use crypto::{hash::CryptoHash, HashValue};
+use proptest::{prelude::*, sample::Index};
+use types::{
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorInfo, ValidatorSigner, ValidatorVerifier},
+    ledger_info::LedgerInfo,
+};
+
+/// Size of the largest validator set generated.
+const MAX_VALIDATORS: usize = 4;
+
+/// Generates a validator set of signers with distinct keys.
+pub fn arb_validator_signers() -> impl Strategy<Value = Vec<ValidatorSigner>> {
+    (1..=MAX_VALIDATORS).prop_map(|num_validators| {
+        (0..num_validators)
+            .map(|i| ValidatorSigner::random([i as u8; 32]))
+            .collect()
+    })
+}
+
+/// Returns the verifier of the validator set formed by `signers`, each with a voting power of 1.
+pub fn validator_verifier(signers: &[ValidatorSigner]) -> ValidatorVerifier {
+    ValidatorVerifier::new(
+        signers
+            .iter()
+            .map(|signer| (signer.author(), ValidatorInfo::new(signer.public_key(), 1)))
+            .collect(),
+    )
+}
+
+prop_compose! {
+    /// Generates the VoteData of a non-genesis block, with strictly increasing rounds from the
+    /// grandparent to the block.
+    pub fn arb_vote_data()(
+        block_id in any::<HashValue>(),
+        executed_state_id in any::<HashValue>(),
+        parent_block_id in any::<HashValue>(),
+        grandparent_block_id in any::<HashValue>(),
+        grandparent_block_round in 0..1000u64,
+        parent_increment in 1..10u64,
+        increment in 1..10u64,
+    ) -> VoteData {
+        let parent_block_round = grandparent_block_round + parent_increment;
+        VoteData::new(
+            block_id,
+            executed_state_id,
+            parent_block_round + increment,
+            parent_block_id,
+            parent_block_round,
+            grandparent_block_id,
+            grandparent_block_round,
+        )
+    }
+}
+
+/// Certifies `vote_data` with the signatures of all `signers`.
+pub fn certify(vote_data: VoteData, version: u64, signers: &[ValidatorSigner]) -> QuorumCert {
+    let ledger_info = LedgerInfo::new(
+        version,
+        vote_data.executed_state_id(),
+        vote_data.hash(),
+        HashValue::zero(),
+        0,
+        0,
+        None,
+    );
+    let signatures = signers
+        .iter()
+        .map(|signer| {
+            let signature = signer
+                .sign_message(ledger_info.hash())
+                .expect("Failed to sign LedgerInfo");
+            (signer.author(), signature)
+        })
+        .collect();
+    QuorumCert::new(
+        vote_data,
+        LedgerInfoWithSignatures::new(ledger_info, signatures),
+    )
+}
+
+/// Generates a QuorumCert signed by all of `signers`.
+pub fn arb_quorum_cert(signers: Vec<ValidatorSigner>) -> impl Strategy<Value = QuorumCert> {
+    (arb_vote_data(), any::<u64>())
+        .prop_map(move |(vote_data, version)| certify(vote_data, version, &signers))
+}
+
+/// Generates a PacemakerTimeoutCertificate for a round, made of the timeouts of all of `signers`.
+pub fn arb_timeout_certificate(
+    signers: Vec<ValidatorSigner>,
+) -> impl Strategy<Value = PacemakerTimeoutCertificate> {
+    (1..1000u64).prop_map(move |round| {
+        let timeouts = signers
+            .iter()
+            .map(|signer| PacemakerTimeout::new(round, signer, None))
+            .collect();
+        PacemakerTimeoutCertificate::new(round, timeouts)
+    })
+}
+
+/// Generates a SyncInfo whose certificates are signed by all of `signers`. The highest ledger
+/// info is never above the highest quorum cert.
+pub fn arb_sync_info(signers: Vec<ValidatorSigner>) -> impl Strategy<Value = SyncInfo> {
+    (
+        arb_quorum_cert(signers.clone()),
+        arb_quorum_cert(signers.clone()),
+        proptest::option::of(arb_timeout_certificate(signers)),
+    )
+        .prop_map(|(qc1, qc2, highest_timeout_cert)| {
+            let (highest_quorum_cert, highest_ledger_info) =
+                if qc1.certified_block_round() >= qc2.certified_block_round() {
+                    (qc1, qc2)
+                } else {
+                    (qc2, qc1)
+                };
+            SyncInfo::new(
+                highest_quorum_cert,
+                highest_ledger_info,
+                highest_timeout_cert,
+            )
+        })
+}
+
+/// Generates a TimeoutMsg from one of `signers`, for a round above the highest round of its
+/// SyncInfo.
+pub fn arb_timeout_msg(signers: Vec<ValidatorSigner>) -> impl Strategy<Value = TimeoutMsg> {
+    (arb_sync_info(signers.clone()), 1..10u64, any::<Index>()).prop_map(
+        move |(sync_info, increment, author_index)| {
+            let signer = author_index.get(&signers);
+            let round: Round = sync_info.highest_round() + increment;
+            let pacemaker_timeout = PacemakerTimeout::new(round, signer, None);
+            TimeoutMsg::new(sync_info, pacemaker_timeout, signer)
+        },
+    )
+}
+
+impl Arbitrary for QuorumCert {
+    type Parameters = ();
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        arb_validator_signers()
+            .prop_flat_map(arb_quorum_cert)
+            .boxed()
+    }
+
+    type Strategy = BoxedStrategy<Self>;
+}
+
+impl Arbitrary for SyncInfo {
+    type Parameters = ();
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        arb_validator_signers().prop_flat_map(arb_sync_info).boxed()
+    }
+
+    type Strategy = BoxedStrategy<Self>;
+}
+
+impl Arbitrary for TimeoutMsg {
+    type Parameters = ();
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        arb_validator_signers()
+            .prop_flat_map(arb_timeout_msg)
+            .boxed()
+    }
+
+    type Strategy = BoxedStrategy<Self>;
+}
@@ -7,6 +7,7 @@ use crypto::{
     HashValue,
 };
 use failure::ResultExt;
+use rmp_serde::{from_slice, to_vec_named};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -14,7 +15,9 @@ use std::{
     fmt::{Display, Formatter},
 };
 use types::{
-    crypto_proxies::{LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier},
+    crypto_proxies::{
+        AggregateSignature, LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier,
+    },
     ledger_info::LedgerInfo,
 };
 
@@ -24,6 +27,9 @@ pub struct QuorumCert {
     vote_data: VoteData,
     /// The signed LedgerInfo of a committed block that carries the data about the certified block.
     signed_ledger_info: LedgerInfoWithSignatures,
+    /// The aggregated signature of the LedgerInfo by the quorum, if the validators aggregate
+    /// their signatures.
+    aggregate_signature: Option<AggregateSignature>,
 }
 
 impl Display for QuorumCert {
@@ -41,8 +47,34 @@ impl QuorumCert {
         QuorumCert {
             vote_data,
             signed_ledger_info,
+            aggregate_signature: None,
         }
     }
+
+    /// Creates a QuorumCert certified by the aggregated signature of the LedgerInfo. The
+    /// individual signatures are only kept if the LedgerInfo commits a block: they make up the
+    /// commit proof that storage persists and that state sync and clients verify.
+    pub fn new_with_aggregate_signature(
+        vote_data: VoteData,
+        signed_ledger_info: LedgerInfoWithSignatures,
+        aggregate_signature: AggregateSignature,
+    ) -> Self {
+        let signed_ledger_info = if signed_ledger_info
+            .ledger_info()
+            .consensus_block_id()
+            .is_zero()
+        {
+            LedgerInfoWithSignatures::new(signed_ledger_info.ledger_info().clone(), HashMap::new())
+        } else {
+            signed_ledger_info
+        };
+        QuorumCert {
+            vote_data,
+            signed_ledger_info,
+            aggregate_signature: Some(aggregate_signature),
+        }
+    }
+
     /// All the vote data getters are just proxies for retrieving the values from the VoteData
     pub fn certified_block_id(&self) -> HashValue {
         self.vote_data.block_id()
@@ -75,6 +107,10 @@ impl QuorumCert {
         &self.signed_ledger_info
     }
 
+    pub fn aggregate_signature(&self) -> Option<&AggregateSignature> {
+        self.aggregate_signature.as_ref()
+    }
+
     pub fn committed_block_id(&self) -> Option<HashValue> {
         let id = self.ledger_info().ledger_info().consensus_block_id();
         if id.is_zero() {
@@ -141,6 +177,21 @@ impl QuorumCert {
         {
             return Ok(());
         }
+        if let Some(aggregate_signature) = self.aggregate_signature() {
+            let aggregate_verifier = validator.aggregate_verifier().ok_or_else(|| {
+                format_err!("Fail to verify QuorumCert: no aggregate keys for its signature")
+            })?;
+            aggregate_signature
+                .verify(aggregate_verifier, self.ledger_info().ledger_info().hash())
+                .with_context(|e| format!("Fail to verify QuorumCert: {:?}", e))?;
+            if self.committed_block_id().is_none() {
+                ensure!(
+                    self.ledger_info().signatures().is_empty(),
+                    "Aggregated QuorumCert carries the signatures of a LedgerInfo not committing"
+                );
+                return Ok(());
+            }
+        }
         self.ledger_info()
             .verify(validator)
             .with_context(|e| format!("Fail to verify QuorumCert: {:?}", e))?;
@@ -160,10 +211,16 @@ impl TryFrom<network::proto::QuorumCert> for QuorumCert {
             .signed_ledger_info
             .ok_or_else(|| format_err!("Missing signed_ledger_info"))?
             .try_into()?;
+        let aggregate_signature = if proto.aggregate_signature.is_empty() {
+            None
+        } else {
+            Some(from_slice(&proto.aggregate_signature)?)
+        };
 
         Ok(QuorumCert {
             vote_data,
             signed_ledger_info,
+            aggregate_signature,
         })
     }
 }
@@ -173,6 +230,10 @@ impl From<QuorumCert> for network::proto::QuorumCert {
         Self {
             vote_data: Some(cert.vote_data.into()),
             signed_ledger_info: Some(cert.signed_ledger_info.into()),
+            aggregate_signature: cert
+                .aggregate_signature
+                .map(|sig| to_vec_named(&sig).expect("fail to serialize aggregate signature"))
+                .unwrap_or_else(Vec::new),
         }
     }
 }
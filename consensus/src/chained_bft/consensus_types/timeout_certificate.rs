@@ -4,12 +4,13 @@
 use crate::chained_bft::common::{self, Author, Round};
 use failure::prelude::*;
 use network;
+use rmp_serde::{from_slice, to_vec_named};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::{collections::HashMap, fmt};
 use types::{
     account_address::AccountAddress,
-    crypto_proxies::{Signature, ValidatorVerifier},
+    crypto_proxies::{AggregateSignature, Signature, ValidatorVerifier},
 };
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
@@ -18,6 +19,9 @@ use types::{
 pub struct TimeoutCertificate {
     round: Round,
     signatures: HashMap<Author, Signature>,
+    /// The aggregated round signature of the participants, instead of their signatures, if the
+    /// validators aggregate their signatures.
+    aggregate_signature: Option<AggregateSignature>,
 }
 
 impl fmt::Display for TimeoutCertificate {
@@ -30,13 +34,44 @@ impl fmt::Display for TimeoutCertificate {
 impl TimeoutCertificate {
     /// Creates new TimeoutCertificate
     pub fn new(round: Round, signatures: HashMap<Author, Signature>) -> Self {
-        Self { round, signatures }
+        Self {
+            round,
+            signatures,
+            aggregate_signature: None,
+        }
+    }
+
+    /// Creates new TimeoutCertificate certified by the aggregated round signature
+    pub fn new_with_aggregate_signature(
+        round: Round,
+        aggregate_signature: AggregateSignature,
+    ) -> Self {
+        Self {
+            round,
+            signatures: HashMap::new(),
+            aggregate_signature: Some(aggregate_signature),
+        }
     }
 
     /// Verifies the signatures for the round
     pub fn verify(&self, validator: &ValidatorVerifier) -> failure::Result<()> {
-        validator.check_voting_power(self.signatures().keys())?;
         let round_digest = common::round_hash(self.round());
+        if let Some(aggregate_signature) = &self.aggregate_signature {
+            ensure!(
+                self.signatures.is_empty(),
+                "Aggregated TimeoutCertificate carries individual signatures"
+            );
+            let aggregate_verifier = validator.aggregate_verifier().ok_or_else(|| {
+                format_err!(
+                    "Fail to verify TimeoutCertificate: no aggregate keys for its signature"
+                )
+            })?;
+            aggregate_signature
+                .verify(aggregate_verifier, round_digest)
+                .with_context(|e| format!("Fail to verify TimeoutCertificate: {:?}", e))?;
+            return Ok(());
+        }
+        validator.check_voting_power(self.signatures().keys())?;
         for (author, signature) in self.signatures() {
             signature
                 .verify(validator, *author, round_digest)
@@ -55,6 +90,11 @@ impl TimeoutCertificate {
         &self.signatures
     }
 
+    /// Returns the aggregated round signature, if any
+    pub fn aggregate_signature(&self) -> Option<&AggregateSignature> {
+        self.aggregate_signature.as_ref()
+    }
+
     pub fn add_signature(&mut self, author: Author, signature: Signature) {
         self.signatures.entry(author).or_insert(signature);
     }
@@ -78,7 +118,16 @@ impl TryFrom<network::proto::TimeoutCertificate> for TimeoutCertificate {
                 Ok((author, signature))
             })
             .collect::<Result<HashMap<_, _>>>()?;
-        Ok(TimeoutCertificate::new(round, signatures))
+        let aggregate_signature = if proto.aggregate_signature.is_empty() {
+            None
+        } else {
+            Some(from_slice(&proto.aggregate_signature)?)
+        };
+        Ok(TimeoutCertificate {
+            round,
+            signatures,
+            aggregate_signature,
+        })
     }
 }
 
@@ -98,6 +147,10 @@ impl From<TimeoutCertificate> for network::proto::TimeoutCertificate {
         Self {
             round: cert.round,
             signatures,
+            aggregate_signature: cert
+                .aggregate_signature
+                .map(|sig| to_vec_named(&sig).expect("fail to serialize aggregate signature"))
+                .unwrap_or_else(Vec::new),
         }
     }
 }
@@ -11,7 +11,7 @@ use std::{
     fmt::{Display, Formatter},
 };
 use types::{
-    crypto_proxies::{Signature, ValidatorSigner, ValidatorVerifier},
+    crypto_proxies::{Signature, SignatureShare, ValidatorSigner, ValidatorVerifier},
     ledger_info::LedgerInfo,
 };
 
@@ -31,6 +31,12 @@ pub struct VoteMsg {
     signature: Signature,
     /// The round signatures can be aggregated into a timeout certificate if present.
     round_signature: Option<Signature>,
+    /// Signature of the LedgerInfo with the aggregate key of the voter, if it has one, which can
+    /// be aggregated into a quorum certificate.
+    signature_share: Option<SignatureShare>,
+    /// Round signature with the aggregate key of the voter, if it has one and the round signature
+    /// is present, which can be aggregated into a timeout certificate.
+    round_signature_share: Option<SignatureShare>,
 }
 
 impl Display for VoteMsg {
@@ -58,12 +64,20 @@ impl VoteMsg {
         let li_sig = validator_signer
             .sign_message(ledger_info_placeholder.hash())
             .expect("Failed to sign LedgerInfo");
+        let signature_share = validator_signer.aggregate_signer().map(|aggregate_signer| {
+            aggregate_signer
+                .sign_message(ledger_info_placeholder.hash())
+                .expect("Failed to sign LedgerInfo")
+                .into()
+        });
         Self {
             vote_data,
             author,
             ledger_info: ledger_info_placeholder,
             signature: li_sig.into(),
             round_signature: None,
+            signature_share,
+            round_signature_share: None,
         }
     }
 
@@ -73,12 +87,19 @@ impl VoteMsg {
         if self.round_signature.is_some() {
             return; // round signature is already set
         }
+        let round_hash = common::round_hash(self.vote_data().block_round());
         self.round_signature.replace(
             validator_signer
-                .sign_message(common::round_hash(self.vote_data().block_round()))
+                .sign_message(round_hash)
                 .expect("Failed to sign round")
                 .into(),
         );
+        self.round_signature_share = validator_signer.aggregate_signer().map(|aggregate_signer| {
+            aggregate_signer
+                .sign_message(round_hash)
+                .expect("Failed to sign round")
+                .into()
+        });
     }
 
     pub fn vote_data(&self) -> &VoteData {
@@ -106,6 +127,16 @@ impl VoteMsg {
         self.round_signature.as_ref()
     }
 
+    /// Returns the signature of the LedgerInfo that can be aggregated into a QuorumCert, if any.
+    pub fn signature_share(&self) -> Option<&SignatureShare> {
+        self.signature_share.as_ref()
+    }
+
+    /// Returns the round signature that can be aggregated into a TimeoutCertificate, if any.
+    pub fn round_signature_share(&self) -> Option<&SignatureShare> {
+        self.round_signature_share.as_ref()
+    }
+
     /// Verifies that the consensus data hash of LedgerInfo corresponds to the vote info,
     /// and then verifies the signature.
     pub fn verify(&self, validator: &ValidatorVerifier) -> failure::Result<()> {
@@ -125,6 +156,27 @@ impl VoteMsg {
                 )
                 .with_context(|e| format!("Fail to verify VoteMsg: {:?}", e))?;
         }
+        ensure!(
+            self.round_signature.is_some() || self.round_signature_share.is_none(),
+            "VoteMsg carries a round signature share without a round signature"
+        );
+        // The signature shares only matter to the validators which aggregate them.
+        if let Some(aggregate_verifier) = validator.aggregate_verifier() {
+            if let Some(signature_share) = &self.signature_share {
+                signature_share
+                    .verify(aggregate_verifier, self.author(), self.ledger_info.hash())
+                    .with_context(|e| format!("Fail to verify VoteMsg: {:?}", e))?;
+            }
+            if let Some(round_signature_share) = &self.round_signature_share {
+                round_signature_share
+                    .verify(
+                        aggregate_verifier,
+                        self.author(),
+                        common::round_hash(self.vote_data().block_round()),
+                    )
+                    .with_context(|e| format!("Fail to verify VoteMsg: {:?}", e))?;
+            }
+        }
         Ok(())
     }
 }
@@ -148,12 +200,24 @@ impl TryFrom<network::proto::Vote> for VoteMsg {
         } else {
             Some(Signature::try_from(&proto.round_signature)?)
         };
+        let signature_share = if proto.signature_share.is_empty() {
+            None
+        } else {
+            Some(SignatureShare::try_from(&proto.signature_share)?)
+        };
+        let round_signature_share = if proto.round_signature_share.is_empty() {
+            None
+        } else {
+            Some(SignatureShare::try_from(&proto.round_signature_share)?)
+        };
         Ok(Self {
             vote_data,
             author,
             ledger_info,
             signature,
             round_signature,
+            signature_share,
+            round_signature_share,
         })
     }
 }
@@ -180,6 +244,14 @@ impl From<VoteMsg> for network::proto::Vote {
                 .round_signature
                 .map(|sig| sig.to_bytes())
                 .unwrap_or_else(Vec::new),
+            signature_share: vote
+                .signature_share
+                .map(|sig| sig.to_bytes())
+                .unwrap_or_else(Vec::new),
+            round_signature_share: vote
+                .round_signature_share
+                .map(|sig| sig.to_bytes())
+                .unwrap_or_else(Vec::new),
         }
     }
 }
@@ -53,3 +53,29 @@ fn test_delete_block_and_qc() {
     assert_eq!(db.get_blocks::<i64>().unwrap().len(), 0);
     assert_eq!(db.get_quorum_certificates().unwrap().len(), 0);
 }
+
+#[test]
+fn test_pending_commit() {
+    let tmp_dir = TempPath::new();
+    let db = ConsensusDB::new(&tmp_dir);
+
+    assert!(db.get_pending_commit().unwrap().is_none());
+    db.save_pending_commit(vec![0x01, 0x02, 0x03]).unwrap();
+    assert_eq!(
+        db.get_pending_commit().unwrap(),
+        Some(vec![0x01, 0x02, 0x03])
+    );
+    db.delete_pending_commit().unwrap();
+    assert!(db.get_pending_commit().unwrap().is_none());
+}
+
+#[test]
+fn test_epoch() {
+    let tmp_dir = TempPath::new();
+    let db = ConsensusDB::new(&tmp_dir);
+
+    assert!(db.get_epoch().unwrap().is_none());
+    db.save_epoch(vec![0x01, 0x02]).unwrap();
+    db.save_epoch(vec![0x03]).unwrap();
+    assert_eq!(db.get_epoch().unwrap(), Some(vec![0x03]));
+}
@@ -5,14 +5,17 @@
 mod consensusdb_test;
 mod schema;
 
-use crate::chained_bft::{
-    common::Payload,
-    consensus_types::{block::Block, quorum_cert::QuorumCert},
-    consensusdb::schema::{
-        block::BlockSchema,
-        quorum_certificate::QCSchema,
-        single_entry::{SingleEntryKey, SingleEntrySchema},
+use crate::{
+    chained_bft::{
+        common::Payload,
+        consensus_types::{block::Block, quorum_cert::QuorumCert},
+        consensusdb::schema::{
+            block::BlockSchema,
+            quorum_certificate::QCSchema,
+            single_entry::{SingleEntryKey, SingleEntrySchema},
+        },
     },
+    counters,
 };
 use crypto::HashValue;
 use failure::prelude::*;
@@ -21,13 +24,27 @@ use schema::{BLOCK_CF_NAME, QC_CF_NAME, SINGLE_ENTRY_CF_NAME};
 use schemadb::{
     ColumnFamilyOptions, ColumnFamilyOptionsMap, ReadOptions, SchemaBatch, DB, DEFAULT_CF_NAME,
 };
-use std::{collections::HashMap, iter::Iterator, path::Path, time::Instant};
+use std::{
+    collections::HashMap,
+    iter::Iterator,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 type HighestTimeoutCertificates = Vec<u8>;
 type ConsensusStateData = Vec<u8>;
+type PendingCommitData = Vec<u8>;
+type EpochData = Vec<u8>;
+type ProposerFailuresData = Vec<u8>;
+
+/// Min interval between two queries of the approximate size of the DB, which only feeds a gauge.
+const SIZE_QUERY_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct ConsensusDB {
     db: DB,
+    // Time of the last query of the size of the DB, if any.
+    last_size_query: Mutex<Option<Instant>>,
 }
 
 impl ConsensusDB {
@@ -57,7 +74,10 @@ impl ConsensusDB {
             instant.elapsed().as_millis()
         );
 
-        Self { db }
+        Self {
+            db,
+            last_size_query: Mutex::new(None),
+        }
     }
 
     pub fn get_data<T: Payload>(
@@ -106,6 +126,47 @@ impl ConsensusDB {
         self.commit(batch)
     }
 
+    pub fn save_pending_commit(&self, pending_commit: PendingCommitData) -> Result<()> {
+        let mut batch = SchemaBatch::new();
+        batch.put::<SingleEntrySchema>(&SingleEntryKey::PendingCommit, &pending_commit)?;
+        self.commit(batch)
+    }
+
+    pub fn delete_pending_commit(&self) -> Result<()> {
+        let mut batch = SchemaBatch::new();
+        batch.delete::<SingleEntrySchema>(&SingleEntryKey::PendingCommit)?;
+        self.commit(batch)
+    }
+
+    /// Get the commit handed to the StateComputer and not completed yet, if any.
+    pub fn get_pending_commit(&self) -> Result<Option<PendingCommitData>> {
+        self.db
+            .get::<SingleEntrySchema>(&SingleEntryKey::PendingCommit)
+    }
+
+    pub fn save_epoch(&self, epoch: EpochData) -> Result<()> {
+        let mut batch = SchemaBatch::new();
+        batch.put::<SingleEntrySchema>(&SingleEntryKey::Epoch, &epoch)?;
+        self.commit(batch)
+    }
+
+    /// Get the epoch consensus last reconfigured to, if any.
+    pub fn get_epoch(&self) -> Result<Option<EpochData>> {
+        self.db.get::<SingleEntrySchema>(&SingleEntryKey::Epoch)
+    }
+
+    pub fn save_proposer_failures(&self, proposer_failures: ProposerFailuresData) -> Result<()> {
+        let mut batch = SchemaBatch::new();
+        batch.put::<SingleEntrySchema>(&SingleEntryKey::ProposerFailures, &proposer_failures)?;
+        self.commit(batch)
+    }
+
+    /// Get the proposers of the recently failed rounds, if any.
+    pub fn get_proposer_failures(&self) -> Result<Option<ProposerFailuresData>> {
+        self.db
+            .get::<SingleEntrySchema>(&SingleEntryKey::ProposerFailures)
+    }
+
     pub fn save_blocks_and_quorum_certificates<T: Payload>(
         &self,
         block_data: Vec<Block<T>>,
@@ -144,9 +205,34 @@ impl ConsensusDB {
     }
 
     /// Write the whole schema batch including all data necessary to mutate the ledger
-    /// state of some transaction by leveraging rocksdb atomicity support.
+    /// state of some transaction by leveraging rocksdb atomicity support. Also updates the
+    /// size counter of the DB, if it wasn't in the last `SIZE_QUERY_INTERVAL`.
     fn commit(&self, batch: SchemaBatch) -> Result<()> {
-        self.db.write_schemas(batch)
+        self.db.write_schemas(batch)?;
+        self.update_size_counter();
+        Ok(())
+    }
+
+    /// Updates the size counter of the DB, at most once per `SIZE_QUERY_INTERVAL`, so that the
+    /// writes on the critical path of consensus rarely pay for the query.
+    fn update_size_counter(&self) {
+        {
+            let mut last_size_query = self.last_size_query.lock().unwrap();
+            if last_size_query.map_or(false, |last| last.elapsed() < SIZE_QUERY_INTERVAL) {
+                return;
+            }
+            *last_size_query = Some(Instant::now());
+        }
+
+        match self.db.get_approximate_sizes_cf() {
+            Ok(cf_sizes) => {
+                counters::CONSENSUSDB_SIZE_BYTES.set(cf_sizes.values().sum::<u64>() as i64)
+            }
+            Err(err) => warn!(
+                "Failed to get approximate size of column families: {}.",
+                err
+            ),
+        }
     }
 
     /// Get latest timeout certificates (we only store the latest highest timeout certificates).
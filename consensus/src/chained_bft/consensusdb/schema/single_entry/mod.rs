@@ -35,6 +35,13 @@ pub enum SingleEntryKey {
     ConsensusState = 0,
     // Used to store the highest timeout certificates
     HighestTimeoutCertificates = 1,
+    // Used to store the ledger info of the commit handed to the StateComputer and not completed
+    // yet
+    PendingCommit = 2,
+    // Used to store the epoch consensus reconfigured to and its validator set
+    Epoch = 3,
+    // Used to store the proposers of the recently failed rounds
+    ProposerFailures = 4,
 }
 
 impl KeyCodec<SingleEntrySchema> for SingleEntryKey {
@@ -1,26 +1,69 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::counters;
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
-use types::crypto_proxies::ValidatorVerifier;
+use types::{crypto_proxies::ValidatorVerifier, validator_set::ValidatorSet};
+
+/// The epoch consensus moved to upon its last reconfiguration, along with the validator set of
+/// this epoch. It is persisted so that consensus restarts in this epoch.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EpochInfo {
+    pub epoch: u64,
+    pub validator_set: ValidatorSet,
+}
 
 /// Manages the current epoch and validator set to provide quorum size/voting power and signature
 /// verification.
 pub struct EpochManager {
-    #[allow(dead_code)]
-    epoch: usize,
+    epoch: RwLock<u64>,
     validators: RwLock<Arc<ValidatorVerifier>>,
 }
 
 impl EpochManager {
-    pub fn new(epoch: usize, validators: ValidatorVerifier) -> Self {
+    pub fn new(epoch: u64, validators: ValidatorVerifier) -> Self {
+        Self::update_counters(epoch, &validators);
         Self {
-            epoch,
+            epoch: RwLock::new(epoch),
             validators: RwLock::new(Arc::new(validators)),
         }
     }
 
+    /// Restores the epoch consensus reconfigured to before a restart.
+    pub fn from_epoch_info(epoch_info: &EpochInfo) -> Self {
+        Self::new(
+            epoch_info.epoch,
+            ValidatorVerifier::from(&epoch_info.validator_set),
+        )
+    }
+
+    pub fn epoch(&self) -> u64 {
+        *self.epoch.read().unwrap()
+    }
+
     pub fn validators(&self) -> Arc<ValidatorVerifier> {
         Arc::clone(&self.validators.read().unwrap())
     }
+
+    /// Moves to the next epoch, whose validators are the ones of `validator_set`. Returns the
+    /// information of the new epoch. A validator set carries no aggregate keys, so the validators
+    /// of the new epoch do not aggregate their signatures.
+    pub fn reconfigure(&self, validator_set: ValidatorSet) -> EpochInfo {
+        let validators = ValidatorVerifier::from(&validator_set);
+        let mut epoch = self.epoch.write().unwrap();
+        *epoch += 1;
+        Self::update_counters(*epoch, &validators);
+        *self.validators.write().unwrap() = Arc::new(validators);
+        EpochInfo {
+            epoch: *epoch,
+            validator_set,
+        }
+    }
+
+    fn update_counters(epoch: u64, validators: &ValidatorVerifier) {
+        counters::EPOCH_NUM.set(epoch as i64);
+        counters::CURRENT_EPOCH_NUM_VALIDATORS.set(validators.len() as i64);
+        counters::CURRENT_EPOCH_QUORUM_SIZE.set(validators.quorum_voting_power() as i64);
+    }
 }
@@ -0,0 +1,49 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::epoch_manager::EpochManager;
+use proptest::{collection::vec, prelude::*};
+use types::{
+    crypto_proxies::random_validator_verifier, validator_public_keys::ValidatorPublicKeys,
+    validator_set::ValidatorSet,
+};
+
+proptest! {
+    #[test]
+    fn test_reconfigure(keys in vec(any::<ValidatorPublicKeys>(), 1..10)) {
+        // arbitrary voting powers would overflow the total voting power
+        let validator_set = ValidatorSet::new(
+            keys.iter()
+                .map(|keys| {
+                    ValidatorPublicKeys::new(
+                        *keys.account_address(),
+                        keys.consensus_public_key().clone(),
+                        1,
+                        keys.network_signing_public_key().clone(),
+                        keys.network_identity_public_key().clone(),
+                    )
+                })
+                .collect(),
+        );
+        let (_, initial_validators) = random_validator_verifier(4, None, false);
+        let epoch_mgr = EpochManager::new(0, initial_validators);
+
+        let epoch_info = epoch_mgr.reconfigure(validator_set.clone());
+        prop_assert_eq!(epoch_info.epoch, 1);
+        prop_assert_eq!(&epoch_info.validator_set, &validator_set);
+        prop_assert_eq!(epoch_mgr.epoch(), 1);
+        let validators = epoch_mgr.validators();
+        prop_assert_eq!(validators.len(), validator_set.payload().len());
+        for keys in validator_set.payload() {
+            prop_assert_eq!(validators.get_voting_power(keys.account_address()), Some(1));
+        }
+
+        // A restart carries on in the same epoch with the same validators.
+        let restored = EpochManager::from_epoch_info(&epoch_info);
+        prop_assert_eq!(restored.epoch(), 1);
+        prop_assert_eq!(
+            restored.validators().get_ordered_account_addresses(),
+            validators.get_ordered_account_addresses()
+        );
+    }
+}
@@ -1,6 +1,8 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "byzantine")]
+use crate::chained_bft::byzantine::{self, ByzantineBehavior};
 #[cfg(test)]
 use crate::chained_bft::safety::safety_rules::ConsensusState;
 use crate::{
@@ -16,7 +18,7 @@ use crate::{
             vote_data::VoteData,
             vote_msg::VoteMsg,
         },
-        epoch_manager::EpochManager,
+        epoch_manager::{EpochInfo, EpochManager},
         liveness::{
             pacemaker::{NewRoundEvent, NewRoundReason, Pacemaker},
             proposal_generator::ProposalGenerator,
@@ -41,9 +43,12 @@ use mirai_annotations::{
     debug_checked_verify_eq,
 };
 use network::proto::BlockRetrievalStatus;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use termion::color::*;
-use types::crypto_proxies::LedgerInfoWithSignatures;
+use types::{crypto_proxies::LedgerInfoWithSignatures, validator_set::ValidatorSet};
 
 #[cfg(test)]
 #[path = "event_processor_test.rs"]
@@ -131,6 +136,8 @@ impl<T: Payload> EventProcessor<T> {
     ///
     /// Do nothing
     async fn process_new_round_event(&self, new_round_event: NewRoundEvent) {
+        logger::context::set_round(new_round_event.round);
+        counters::OP_COUNTERS.export_log_context();
         debug!("Processing {}", new_round_event);
         counters::CURRENT_ROUND.set(new_round_event.round as i64);
         counters::ROUND_TIMEOUT_MS.set(new_round_event.timeout.as_millis() as i64);
@@ -149,6 +156,8 @@ impl<T: Payload> EventProcessor<T> {
         {
             return;
         }
+        // A proposal which is still queued once its round is over is of no use to the peers.
+        let round_timeout = new_round_event.timeout;
         let proposal_msg = match self.generate_proposal(new_round_event).await {
             Ok(x) => x,
             Err(e) => {
@@ -156,8 +165,24 @@ impl<T: Payload> EventProcessor<T> {
                 return;
             }
         };
+        #[cfg(feature = "byzantine")]
+        {
+            if byzantine::byzantine_behavior() == Some(ByzantineBehavior::EquivocatingProposer) {
+                byzantine::equivocate(
+                    self.block_store.as_ref(),
+                    &self.network,
+                    self.epoch_mgr.as_ref(),
+                    proposal_msg,
+                )
+                .await;
+                counters::PROPOSALS_COUNT.inc();
+                return;
+            }
+        }
         let mut network = self.network.clone();
-        network.broadcast_proposal(proposal_msg).await;
+        network
+            .broadcast_proposal(proposal_msg, Some(round_timeout))
+            .await;
         counters::PROPOSALS_COUNT.inc();
     }
 
@@ -227,6 +252,16 @@ impl<T: Payload> EventProcessor<T> {
             );
             return None;
         }
+        if let Some(payload) = proposal_msg.proposal().payload() {
+            if let Err(e) = self.txn_manager.check_payload(payload) {
+                warn!(
+                    "Payload of proposal {} is over the limits of a block: {:?}",
+                    proposal_msg.proposal(),
+                    e
+                );
+                return None;
+            }
+        }
         if let Err(e) = self
             .sync_up(proposal_msg.sync_info(), proposal_msg.proposer(), true)
             .await
@@ -361,8 +396,7 @@ impl<T: Payload> EventProcessor<T> {
             &sync_info.highest_quorum_cert(),
             sync_info.highest_timeout_certificate(),
         )
-        .await;
-        Ok(())
+        .await
     }
 
     /// Process the SyncInfo sent by peers to catch up to latest state.
@@ -457,7 +491,7 @@ impl<T: Payload> EventProcessor<T> {
         &mut self,
         qc: &QuorumCert,
         tc: Option<&PacemakerTimeoutCertificate>,
-    ) {
+    ) -> failure::Result<()> {
         self.safety_rules.update(qc);
 
         let mut highest_committed_proposal_round = None;
@@ -470,7 +504,7 @@ impl<T: Payload> EventProcessor<T> {
                 highest_committed_proposal_round = Some(block.round());
             }
             let finality_proof = qc.ledger_info().clone();
-            self.process_commit(block.id(), finality_proof).await;
+            self.process_commit(block.id(), finality_proof).await?;
         }
 
         if let Some(new_round_event) = self.pacemaker.process_certificates(
@@ -480,6 +514,7 @@ impl<T: Payload> EventProcessor<T> {
         ) {
             self.process_new_round_event(new_round_event).await;
         }
+        Ok(())
     }
 
     /// This function processes a proposal that was chosen as a representative of its round:
@@ -554,6 +589,13 @@ impl<T: Payload> EventProcessor<T> {
             .block_store
             .get_block(proposal_parent_id)
             .map_or(false, |parent_block| parent_block.round() < proposal_round));
+        #[cfg(feature = "byzantine")]
+        {
+            if byzantine::byzantine_behavior() == Some(ByzantineBehavior::VoteWithholder) {
+                debug!("Withholding the vote for {}", proposal_id);
+                return;
+            }
+        }
         self.network.send_vote(vote_msg, recipients).await;
     }
 
@@ -658,7 +700,7 @@ impl<T: Payload> EventProcessor<T> {
 
         let ledger_info_placeholder = self
             .block_store
-            .ledger_info_placeholder(vote_info.potential_commit_id());
+            .ledger_info_placeholder(vote_info.potential_commit_id(), self.epoch_mgr.epoch());
         Ok(VoteMsg::new(
             VoteData::new(
                 proposal_id,
@@ -736,7 +778,10 @@ impl<T: Payload> EventProcessor<T> {
                 error!("Error inserting qc {}: {:?}", qc, e);
                 return None;
             }
-            self.process_certificates(qc.as_ref(), None).await;
+            if let Err(e) = self.process_certificates(qc.as_ref(), None).await {
+                error!("Error processing qc {}: {:?}", qc, e);
+                return None;
+            }
             return Some(qc);
         };
         None
@@ -744,25 +789,28 @@ impl<T: Payload> EventProcessor<T> {
 
     /// Upon (potentially) new commit:
     /// 0. Verify that this commit is newer than the current root.
-    /// 1. Notify state computer with the finality proof.
+    /// 1. Record the commit as pending and notify state computer with the finality proof.
     /// 2. After the state is finalized, update the txn manager with the status of the committed
-    /// transactions.
-    /// 3. Prune the tree.
+    /// transactions, and the proposer election with the committed blocks.
+    /// 3. Prune the tree and clear the pending commit.
+    /// 4. Move to the next epoch if one of the committed blocks changed the validator set.
+    ///
+    /// Returns an error if the move to the next epoch could not be persisted.
     async fn process_commit(
-        &self,
+        &mut self,
         block_id_to_commit: HashValue,
         finality_proof: LedgerInfoWithSignatures,
-    ) {
+    ) -> failure::Result<()> {
         let block_to_commit = match self.block_store.get_block(block_id_to_commit) {
             Some(block) => block,
             None => {
-                return;
+                return Ok(());
             }
         };
 
         // First make sure that this commit is new.
         if block_to_commit.round() <= self.block_store.root().round() {
-            return;
+            return Ok(());
         }
 
         // Verify that the ledger info is indeed for the block we're planning to
@@ -772,6 +820,14 @@ impl<T: Payload> EventProcessor<T> {
             block_to_commit.id()
         );
 
+        // If the node crashes before the end of the commit, the pending commit lets the recovery
+        // find out whether the storage completed it.
+        if let Err(e) = self
+            .storage
+            .save_pending_commit(finality_proof.ledger_info().clone())
+        {
+            error!("Failed to persist pending commit: {:?}", e);
+        }
         if let Err(e) = self.state_computer.commit(finality_proof).await {
             // We assume that state computer cannot enter an inconsistent state that might
             // violate safety of the protocol. Specifically, an executor service is going to panic
@@ -781,11 +837,12 @@ impl<T: Payload> EventProcessor<T> {
                 "Failed to persist commit, mempool will not be notified: {:?}",
                 e
             );
-            return;
+            return Ok(());
         }
         // At this moment the new state is persisted and we can notify the clients.
         // Multiple blocks might be committed at once: notify about all the transactions in the
         // path from the old root to the new root.
+        let mut next_validator_set = None;
         for committed in self
             .block_store
             .path_from_root(block_id_to_commit)
@@ -796,11 +853,16 @@ impl<T: Payload> EventProcessor<T> {
             {
                 counters::CREATION_TO_COMMIT_S.observe_duration(time_to_commit);
             }
+            let compute_result = self
+                .block_store
+                .get_compute_result(committed.id())
+                .expect("Compute result of a pending block is unknown");
+            if let Some(validator_set) = &compute_result.executed_state.validators {
+                next_validator_set = Some(validator_set.clone());
+            }
+            self.proposer_election
+                .process_committed_block(committed.block());
             if let Some(payload) = committed.payload() {
-                let compute_result = self
-                    .block_store
-                    .get_compute_result(committed.id())
-                    .expect("Compute result of a pending block is unknown");
                 if let Err(e) = self
                     .txn_manager
                     .commit_txns(
@@ -822,12 +884,68 @@ impl<T: Payload> EventProcessor<T> {
             "parent_id": block_to_commit.parent_id().short_str(),
         );
         self.block_store.prune_tree(block_to_commit.id());
+        if let Err(e) = self.storage.clear_pending_commit() {
+            error!("Failed to clear pending commit: {:?}", e);
+        }
+        if let Some(validator_set) = next_validator_set {
+            self.reconfigure(validator_set).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves consensus and the network to the epoch of `validator_set`. The proposer election
+    /// of the new epoch is handed over by the caller with `start_new_epoch()`.
+    ///
+    /// The new epoch is persisted first: consensus stays in the current epoch if it cannot be.
+    async fn reconfigure(&self, validator_set: ValidatorSet) -> failure::Result<()> {
+        let epoch_info = EpochInfo {
+            epoch: self.epoch_mgr.epoch() + 1,
+            validator_set,
+        };
+        self.storage
+            .save_epoch(epoch_info.clone())
+            .with_context(|e| format!("Failed to persist epoch {}: {:?}", epoch_info.epoch, e))?;
+        let epoch_info = self.epoch_mgr.reconfigure(epoch_info.validator_set);
+        info!(
+            "Reconfigured to epoch {} with {} validators",
+            epoch_info.epoch,
+            epoch_info.validator_set.payload().len()
+        );
+        self.network
+            .update_eligible_nodes(&epoch_info.validator_set)
+            .await;
+        // The votes gathered so far were checked against the previous validator set.
+        self.block_store.clear_pending_votes();
+        Ok(())
+    }
+
+    /// Carries on in the epoch consensus reconfigured to, with the proposers of this epoch.
+    pub fn start_new_epoch(
+        &mut self,
+        proposer_election: Box<dyn ProposerElection<T> + Send + Sync>,
+    ) {
+        self.proposer_election = proposer_election;
+        logger::context::set_epoch(self.epoch_mgr.epoch());
+    }
+
+    /// Hands over again to the state computer the commit certified by `qc`, which was interrupted
+    /// before the storage completed it.
+    pub async fn replay_pending_commit(&mut self, qc: QuorumCert) -> failure::Result<()> {
+        if let Some(block_id) = qc.committed_block_id() {
+            info!("Replaying the pending commit of block {}", block_id);
+            self.process_commit(block_id, qc.ledger_info().clone())
+                .await?;
+        }
+        Ok(())
     }
 
     /// Retrieve a n chained blocks from the block store starting from
     /// an initial parent id, returning with <n (as many as possible) if
     /// id or its ancestors can not be found.
     ///
+    /// Nothing is returned once the deadline of the request has passed: the requester has given up
+    /// on the response by then.
+    ///
     /// The current version of the function is not really async, but keeping it this way for
     /// future possible changes.
     pub async fn process_block_retrieval(&self, request: BlockRetrievalRequest<T>) {
@@ -835,6 +953,15 @@ impl<T: Payload> EventProcessor<T> {
         let mut status = BlockRetrievalStatus::Succeeded;
         let mut id = request.block_id;
         while (blocks.len() as u64) < request.num_blocks {
+            // Dropping the sender lets the network side know that nothing is coming.
+            if Instant::now() >= request.deadline || request.response_sender.is_canceled() {
+                counters::EXPIRED_BLOCK_RETRIEVAL_COUNT.inc();
+                debug!(
+                    "Abandoning retrieval of {} blocks from {} past its deadline",
+                    request.num_blocks, request.block_id
+                );
+                return;
+            }
             if let Some(executed_block) = self.block_store.get_block(id) {
                 id = executed_block.parent_id();
                 blocks.push(executed_block.block().clone());
@@ -858,6 +985,7 @@ impl<T: Payload> EventProcessor<T> {
 
     /// To jump start new round with the current certificates we have.
     pub async fn start(&mut self) {
+        logger::context::set_epoch(self.epoch_mgr.epoch());
         let hqc = self.block_store.highest_quorum_cert();
         let last_committed_round = self.block_store.root().round();
         let new_round_event = self
@@ -67,7 +67,8 @@ fn build_empty_store(
         signer,
         Arc::new(EmptyStateComputer),
         true,
-        10, // max pruned blocks in mem
+        10,  // max pruned blocks in mem
+        100, // pruned blocks retention rounds
     )))
 }
 
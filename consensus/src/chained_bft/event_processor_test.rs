@@ -47,7 +47,10 @@ use network::{
     validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender},
 };
 use std::convert::TryFrom;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::runtime::TaskExecutor;
 use types::crypto_proxies::{
     random_validator_verifier, LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier,
@@ -77,7 +80,8 @@ impl NodeSetup {
             signer,
             state_computer,
             true,
-            10, // max pruned blocks in mem
+            10,  // max pruned blocks in mem
+            100, // pruned blocks retention rounds
         )))
     }
 
@@ -738,7 +742,8 @@ fn process_block_retrieval() {
     block_on(async move {
         node.event_processor
             .process_certificates(block.quorum_cert(), None)
-            .await;
+            .await
+            .unwrap();
         node.event_processor.process_proposed_block(block).await;
 
         // first verify that we can retrieve the block if it's in the tree
@@ -746,6 +751,7 @@ fn process_block_retrieval() {
         let single_block_request = BlockRetrievalRequest {
             block_id,
             num_blocks: 1,
+            deadline: Instant::now() + Duration::from_secs(5),
             response_sender: tx1,
         };
         node.event_processor
@@ -764,6 +770,7 @@ fn process_block_retrieval() {
         let missing_block_request = BlockRetrievalRequest {
             block_id: HashValue::random(),
             num_blocks: 1,
+            deadline: Instant::now() + Duration::from_secs(5),
             response_sender: tx2,
         };
         node.event_processor
@@ -782,6 +789,7 @@ fn process_block_retrieval() {
         let many_block_request = BlockRetrievalRequest {
             block_id,
             num_blocks: 3,
+            deadline: Instant::now() + Duration::from_secs(5),
             response_sender: tx3,
         };
         node.event_processor
@@ -795,6 +803,19 @@ fn process_block_retrieval() {
             }
             _ => panic!("block retrieval failure"),
         }
+
+        // requests past their deadline are abandoned
+        let (tx4, rx4) = oneshot::channel();
+        let expired_block_request = BlockRetrievalRequest {
+            block_id,
+            num_blocks: 1,
+            deadline: Instant::now(),
+            response_sender: tx4,
+        };
+        node.event_processor
+            .process_block_retrieval(expired_block_request)
+            .await;
+        assert!(rx4.await.is_err());
     });
 }
 
@@ -825,7 +846,8 @@ fn basic_restart_test() {
             node_mut
                 .event_processor
                 .process_certificates(proposal.quorum_cert(), None),
-        );
+        )
+        .unwrap();
         block_on(
             node_mut
                 .event_processor
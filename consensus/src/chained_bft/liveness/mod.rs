@@ -6,11 +6,17 @@ pub(crate) mod pacemaker;
 pub(crate) mod pacemaker_timeout_manager;
 pub(crate) mod proposal_generator;
 pub(crate) mod proposer_election;
+pub(crate) mod reputation_proposer_election;
 pub(crate) mod rotating_proposer_election;
+pub(crate) mod weighted_proposer_election;
 
 #[cfg(test)]
 mod multi_proposer_test;
 #[cfg(test)]
 mod pacemaker_test;
 #[cfg(test)]
+mod reputation_proposer_test;
+#[cfg(test)]
 mod rotating_proposer_test;
+#[cfg(test)]
+mod weighted_proposer_test;
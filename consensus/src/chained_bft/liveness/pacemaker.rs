@@ -14,8 +14,10 @@ use crate::{
     util::time_service::{SendTask, TimeService},
 };
 use channel;
+use config::config::AdaptiveTimeoutConfig;
 use logger::prelude::*;
 use std::{
+    collections::VecDeque,
     fmt,
     sync::Arc,
     time::{Duration, Instant},
@@ -73,6 +75,10 @@ pub trait PacemakerTimeInterval: Send + Sync + 'static {
     /// to calculate the round duration of round 6 and the highest committed round is 3 (meaning
     /// the highest round to commit a block is round 5, then the round index is 0.
     fn get_round_duration(&self, round_index_after_committed_qc: usize) -> Duration;
+
+    /// Records the duration of a round which gathered a QC before timing out, for the intervals
+    /// adapting to the observed round latency
+    fn record_round_duration(&mut self, _duration: Duration) {}
 }
 
 /// Round durations increase exponentially
@@ -124,6 +130,91 @@ impl PacemakerTimeInterval for ExponentialTimeInterval {
     }
 }
 
+/// Round durations adapt to the observed round latency: the duration of the round index 0 is a
+/// percentile of the durations of the recent rounds which gathered a QC before timing out,
+/// bounded by `min` and `max`, and increases exponentially for the next round indices as for the
+/// ExponentialTimeInterval. Until a round is observed, the initial duration is used.
+/// Fixed timeouts either waste time on fast networks or thrash on slow ones.
+pub struct AdaptiveTimeInterval {
+    // Durations of the most recent certified rounds, oldest first
+    round_durations: VecDeque<Duration>,
+    // Maximum number of round durations kept
+    window_size: usize,
+    // Percentile of the round durations used for the round index 0, from 0 to 100
+    percentile: f64,
+    // Bounds of the duration of the round index 0
+    min: Duration,
+    max: Duration,
+    // Duration of the round index 0 before any round is observed
+    initial: Duration,
+    // By how much we increase interval every time
+    exponent_base: f64,
+    // Maximum power of exponent_base the duration of the round index 0 is multiplied by
+    max_exponent: usize,
+}
+
+impl AdaptiveTimeInterval {
+    pub fn new(
+        initial: Duration,
+        exponent_base: f64,
+        max_exponent: usize,
+        config: &AdaptiveTimeoutConfig,
+    ) -> Self {
+        assert!(config.window_size > 0, "window_size should be positive");
+        assert!(
+            config.percentile >= 0.0 && config.percentile <= 100.0,
+            "percentile should be between 0 and 100"
+        );
+        assert!(
+            config.min_timeout_ms <= config.max_timeout_ms,
+            "min_timeout_ms should not exceed max_timeout_ms"
+        );
+        assert!(
+            max_exponent < 32,
+            "max_exponent for PacemakerTimeInterval should be <32"
+        );
+        Self {
+            round_durations: VecDeque::with_capacity(config.window_size),
+            window_size: config.window_size,
+            percentile: config.percentile,
+            min: Duration::from_millis(config.min_timeout_ms),
+            max: Duration::from_millis(config.max_timeout_ms),
+            initial,
+            exponent_base,
+            max_exponent,
+        }
+    }
+
+    /// Duration of the round index 0
+    fn base_duration(&self) -> Duration {
+        if self.round_durations.is_empty() {
+            return self.initial;
+        }
+        let mut round_durations: Vec<Duration> = self.round_durations.iter().cloned().collect();
+        round_durations.sort();
+        // nearest-rank percentile
+        let rank = (self.percentile / 100.0 * round_durations.len() as f64).ceil() as usize;
+        let duration = round_durations[rank.max(1).min(round_durations.len()) - 1];
+        duration.max(self.min).min(self.max)
+    }
+}
+
+impl PacemakerTimeInterval for AdaptiveTimeInterval {
+    fn get_round_duration(&self, round_index_after_committed_qc: usize) -> Duration {
+        let pow = round_index_after_committed_qc.min(self.max_exponent) as u32;
+        let base_multiplier = self.exponent_base.powf(f64::from(pow));
+        let duration_ms = (self.base_duration().as_millis() as f64 * base_multiplier).ceil();
+        Duration::from_millis(duration_ms as u64)
+    }
+
+    fn record_round_duration(&mut self, duration: Duration) {
+        if self.round_durations.len() == self.window_size {
+            self.round_durations.pop_front();
+        }
+        self.round_durations.push_back(duration);
+    }
+}
+
 /// `Pacemaker` is a Pacemaker implementation that relies on increasing local timeouts
 /// in order to eventually come up with the timeout that is large enough to guarantee overlap of the
 /// "current round" of multiple participants.
@@ -154,6 +245,8 @@ pub struct Pacemaker {
     // update_current_round take care of updating current_round and sending new round event if
     // it changes
     current_round: Round,
+    // When the current round started
+    current_round_start: Instant,
     // Approximate deadline when current round ends
     current_round_deadline: Instant,
     // Service for timer
@@ -183,6 +276,7 @@ impl Pacemaker {
             highest_committed_round: 0,
             highest_qc_round: 0,
             current_round: 0,
+            current_round_start: Instant::now(),
             current_round_deadline: Instant::now(),
             time_service,
             timeout_sender,
@@ -270,7 +364,18 @@ impl Pacemaker {
             self.current_round,
             new_round
         );
+        let now = Instant::now();
+        // The round the replica took part in from its start gathered a QC
+        if best_reason == NewRoundReason::QCReady
+            && self.current_round > 0
+            && self.highest_qc_round == self.current_round
+        {
+            let round_duration = now.duration_since(self.current_round_start);
+            counters::QC_ROUND_DURATION_S.observe_duration(round_duration);
+            self.time_interval.record_round_duration(round_duration);
+        }
         self.current_round = new_round;
+        self.current_round_start = now;
         let timeout = self.setup_timeout();
         Some(NewRoundEvent {
             round: self.current_round,
@@ -7,8 +7,8 @@ use crate::{
         consensus_types::timeout_msg::PacemakerTimeout,
         liveness::{
             pacemaker::{
-                ExponentialTimeInterval, NewRoundEvent, NewRoundReason, Pacemaker,
-                PacemakerTimeInterval,
+                AdaptiveTimeInterval, ExponentialTimeInterval, NewRoundEvent, NewRoundReason,
+                Pacemaker, PacemakerTimeInterval,
             },
             pacemaker_timeout_manager::HighestTimeoutCertificates,
         },
@@ -18,6 +18,7 @@ use crate::{
     util::mock_time_service::SimulatedTimeService,
 };
 use channel;
+use config::config::AdaptiveTimeoutConfig;
 use futures::{executor::block_on, StreamExt};
 use std::{sync::Arc, time::Duration, u64};
 use types::crypto_proxies::random_validator_verifier;
@@ -35,6 +36,40 @@ fn test_pacemaker_time_interval() {
     assert_eq!(6750, interval.get_round_duration(1000).as_millis());
 }
 
+#[test]
+fn test_adaptive_time_interval() {
+    let config = AdaptiveTimeoutConfig {
+        window_size: 10,
+        percentile: 90.0,
+        min_timeout_ms: 100,
+        max_timeout_ms: 2000,
+    };
+    let mut interval = AdaptiveTimeInterval::new(Duration::from_millis(3000), 1.5, 2, &config);
+    // The initial duration is used until a round is observed
+    assert_eq!(3000, interval.get_round_duration(0).as_millis());
+
+    // 90th percentile of 10..=100 ms is 90 ms, raised to the lower bound
+    for duration_ms in (10..=100).step_by(10) {
+        interval.record_round_duration(Duration::from_millis(duration_ms));
+    }
+    assert_eq!(100, interval.get_round_duration(0).as_millis());
+
+    // Older durations leave the window: 90th percentile of 500..=1400 ms is 1300 ms
+    for duration_ms in (500..=1400).step_by(100) {
+        interval.record_round_duration(Duration::from_millis(duration_ms));
+    }
+    assert_eq!(1300, interval.get_round_duration(0).as_millis());
+    assert_eq!(1950, interval.get_round_duration(1).as_millis());
+    assert_eq!(2925, interval.get_round_duration(2).as_millis());
+    assert_eq!(2925, interval.get_round_duration(1000).as_millis());
+
+    // Slow rounds are capped by the upper bound
+    for _ in 0..10 {
+        interval.record_round_duration(Duration::from_secs(60));
+    }
+    assert_eq!(2000, interval.get_round_duration(0).as_millis());
+}
+
 #[test]
 /// Verify that Pacemaker properly outputs PacemakerTimeoutMsg upon timeout
 fn test_basic_timeout() {
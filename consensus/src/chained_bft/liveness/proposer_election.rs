@@ -33,4 +33,8 @@ pub trait ProposerElection<T> {
     /// Note that once the backup proposal is taken and no other proposals are submitted, the
     /// following take requests are going to return None.
     fn take_backup_proposal(&mut self, round: Round) -> Option<Block<T>>;
+
+    /// Notify proposer election about a newly committed block. The blocks are notified in the
+    /// order of the committed chain.
+    fn process_committed_block(&mut self, _block: &Block<T>) {}
 }
@@ -0,0 +1,158 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    common::{Author, Payload, Round},
+    consensus_types::block::Block,
+    liveness::proposer_election::ProposerElection,
+    persistent_storage::PersistentLivenessStorage,
+};
+use logger::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Number of the last rounds before a round whose failures are ignored by the election of its
+/// proposer. A failed round is learnt once the block following it is committed, which takes three
+/// more certified rounds, so the replicas may not all know about the failures of these rounds yet.
+pub const COMMIT_LAG_ROUNDS: Round = 4;
+
+/// Proposers of the failed rounds of the committed chain of an epoch, persisted so that a replica
+/// elects the same proposers as the other replicas after a restart.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProposerFailures {
+    pub epoch: u64,
+    pub failed_rounds: BTreeMap<Round, Author>,
+}
+
+/// The reputation proposer maps a round to an author according to a round-robin rotation, like
+/// the rotating proposer, but skips the proposers which recently failed their rounds: the
+/// rotation moves on to the next proposer which didn't fail any of the `window` rounds before the
+/// last `COMMIT_LAG_ROUNDS` rounds.
+///
+/// A round failed when the committed chain has no block for it, or a NIL block. The failures are
+/// only learnt from the committed blocks, which are the same for all the replicas, so that the
+/// replicas agree on the proposer of a round. A replica which did not commit the blocks the other
+/// ones did yet may disagree until it catches up, which delays the round until it times out, but
+/// does not affect safety.
+pub struct ReputationProposer {
+    // Ordering of proposers to rotate through (all honest replicas must agree on this)
+    proposers: Vec<Author>,
+    // Number of contiguous rounds (i.e. round numbers increase by 1) a proposer is active
+    // in a row
+    contiguous_rounds: u32,
+    // Number of rounds during which a proposer which failed its round is skipped
+    window: u64,
+    // Proposers of the failed rounds still within the window of the rounds after the highest
+    // committed block
+    failures: ProposerFailures,
+    // Persists the failures whenever a block is committed
+    storage: Box<dyn PersistentLivenessStorage>,
+}
+
+impl ReputationProposer {
+    /// Creates the proposer election of `epoch`, starting from the `failures` recovered from the
+    /// storage, which are dropped if they are the failures of another epoch.
+    pub fn new(
+        proposers: Vec<Author>,
+        contiguous_rounds: u32,
+        window: u64,
+        epoch: u64,
+        failures: ProposerFailures,
+        storage: Box<dyn PersistentLivenessStorage>,
+    ) -> Self {
+        assert!(!proposers.is_empty());
+        let failed_rounds = if failures.epoch == epoch {
+            failures.failed_rounds
+        } else {
+            BTreeMap::new()
+        };
+        Self {
+            proposers,
+            contiguous_rounds,
+            window,
+            failures: ProposerFailures {
+                epoch,
+                failed_rounds,
+            },
+            storage,
+        }
+    }
+
+    fn get_proposer(&self, round: Round) -> Author {
+        let base = (round / u64::from(self.contiguous_rounds)) % self.proposers.len() as u64;
+        let until = round.saturating_sub(COMMIT_LAG_ROUNDS);
+        let since = until.saturating_sub(self.window);
+        let has_failed = |author: &Author| {
+            self.failures
+                .failed_rounds
+                .range(since..until)
+                .any(|(_, failed_author)| failed_author == author)
+        };
+        (0..self.proposers.len())
+            .map(|offset| self.proposers[(base as usize + offset) % self.proposers.len()])
+            .find(|author| !has_failed(author))
+            // every proposer failed recently, fall back to the plain rotation
+            .unwrap_or_else(|| self.proposers[base as usize])
+    }
+
+    /// Records the proposers of the rounds which failed before the committed `block`, and of its
+    /// round if it is a NIL block, and forgets the failures no later round looks at.
+    fn record_failed_rounds<T>(&mut self, block: &Block<T>) {
+        let round = block.round();
+        let horizon = round.saturating_sub(self.window + COMMIT_LAG_ROUNDS);
+        // the parent of a committed block is the previous block of the committed chain
+        let first_failed_round = std::cmp::max(
+            block
+                .quorum_cert()
+                .certified_block_round()
+                .saturating_add(1),
+            horizon,
+        );
+        let last_failed_round = if block.is_nil_block() {
+            round
+        } else {
+            round.saturating_sub(1)
+        };
+        for failed_round in first_failed_round..=last_failed_round {
+            if !self.failures.failed_rounds.contains_key(&failed_round) {
+                let author = self.get_proposer(failed_round);
+                self.failures.failed_rounds.insert(failed_round, author);
+            }
+        }
+        self.failures.failed_rounds = self.failures.failed_rounds.split_off(&horizon);
+    }
+}
+
+impl<T: Payload> ProposerElection<T> for ReputationProposer {
+    fn is_valid_proposer(&self, author: Author, round: Round) -> Option<Author> {
+        if self.get_proposer(round) == author {
+            Some(author)
+        } else {
+            None
+        }
+    }
+
+    fn get_valid_proposers(&self, round: Round) -> Vec<Author> {
+        vec![self.get_proposer(round)]
+    }
+
+    fn process_proposal(&mut self, proposal: Block<T>) -> Option<Block<T>> {
+        let round_author = self.get_proposer(proposal.round());
+        if Some(round_author) != proposal.author() {
+            None
+        } else {
+            Some(proposal)
+        }
+    }
+
+    fn take_backup_proposal(&mut self, _round: Round) -> Option<Block<T>> {
+        None
+    }
+
+    fn process_committed_block(&mut self, block: &Block<T>) {
+        self.record_failed_rounds(block);
+        if let Err(e) = self.storage.save_proposer_failures(self.failures.clone()) {
+            error!("Failed to persist the proposer failures: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    consensus_types::{block::Block, quorum_cert::QuorumCert},
+    liveness::{
+        proposer_election::ProposerElection,
+        reputation_proposer_election::{ProposerFailures, ReputationProposer},
+    },
+    persistent_storage::PersistentStorage,
+    test_utils::{EmptyStorage, MockStorage},
+};
+use types::validator_signer::ValidatorSigner;
+
+#[test]
+fn test_reputation_proposer() {
+    let first_validator_signer = ValidatorSigner::random([0u8; 32]);
+    let first_author = first_validator_signer.author();
+    let second_validator_signer = ValidatorSigner::random([1u8; 32]);
+    let second_author = second_validator_signer.author();
+    let third_author = ValidatorSigner::random([2u8; 32]).author();
+    let proposers = vec![first_author, second_author, third_author];
+    let (storage, _) = MockStorage::<u32>::start_for_testing();
+    let mut pe: Box<dyn ProposerElection<u32>> = Box::new(ReputationProposer::new(
+        proposers.clone(),
+        1,
+        10,
+        1,
+        ProposerFailures::default(),
+        storage.persistent_liveness_storage(),
+    ));
+
+    // Without failures, the proposers follow the round-robin rotation.
+    assert_eq!(pe.get_valid_proposers(1), vec![second_author]);
+    assert_eq!(pe.get_valid_proposers(2), vec![third_author]);
+    assert_eq!(pe.get_valid_proposers(3), vec![first_author]);
+    assert_eq!(pe.get_valid_proposers(7), vec![second_author]);
+
+    // Proposals don't tell about failures, even from a valid proposer.
+    let genesis_block = Block::make_genesis_block();
+    let quorum_cert = QuorumCert::certificate_for_genesis();
+    let bad_proposal = Block::make_block(
+        &genesis_block,
+        1,
+        3,
+        1,
+        quorum_cert.clone(),
+        &second_validator_signer,
+    );
+    let good_proposal = Block::make_block(
+        &genesis_block,
+        1,
+        3,
+        1,
+        quorum_cert,
+        &first_validator_signer,
+    );
+    assert_eq!(pe.process_proposal(bad_proposal), None);
+    assert_eq!(
+        pe.process_proposal(good_proposal.clone()),
+        Some(good_proposal.clone())
+    );
+    assert_eq!(pe.get_valid_proposers(7), vec![second_author]);
+
+    // Committing the block of round 3 extending genesis tells that rounds 1 and 2 failed.
+    pe.process_committed_block(&good_proposal);
+
+    // The proposers of rounds 1 and 2 are skipped once the last rounds can't be uncommitted
+    // anymore, until their failures leave the window.
+    assert_eq!(pe.get_valid_proposers(5), vec![third_author]);
+    assert_eq!(pe.get_valid_proposers(6), vec![first_author]);
+    assert_eq!(pe.get_valid_proposers(7), vec![first_author]);
+    assert_eq!(pe.is_valid_proposer(second_author, 7), None);
+    assert_eq!(pe.is_valid_proposer(first_author, 7), Some(first_author));
+    assert_eq!(pe.get_valid_proposers(8), vec![first_author]);
+    assert_eq!(pe.get_valid_proposers(15), vec![first_author]);
+    assert_eq!(pe.get_valid_proposers(16), vec![second_author]);
+    assert_eq!(pe.get_valid_proposers(17), vec![third_author]);
+
+    // The failures are persisted, and a restarted replica elects the same proposers.
+    let failures = storage
+        .shared_storage
+        .proposer_failures
+        .lock()
+        .unwrap()
+        .clone();
+    assert_eq!(failures.epoch, 1);
+    assert_eq!(
+        failures.failed_rounds.into_iter().collect::<Vec<_>>(),
+        vec![(1, second_author), (2, third_author)]
+    );
+    let restarted: Box<dyn ProposerElection<u32>> = Box::new(ReputationProposer::new(
+        proposers.clone(),
+        1,
+        10,
+        1,
+        storage
+            .shared_storage
+            .proposer_failures
+            .lock()
+            .unwrap()
+            .clone(),
+        storage.persistent_liveness_storage(),
+    ));
+    assert_eq!(restarted.get_valid_proposers(7), vec![first_author]);
+
+    // The failures of another epoch are ignored.
+    let next_epoch: Box<dyn ProposerElection<u32>> = Box::new(ReputationProposer::new(
+        proposers,
+        1,
+        10,
+        2,
+        storage
+            .shared_storage
+            .proposer_failures
+            .lock()
+            .unwrap()
+            .clone(),
+        storage.persistent_liveness_storage(),
+    ));
+    assert_eq!(next_epoch.get_valid_proposers(7), vec![second_author]);
+}
+
+#[test]
+fn test_reputation_proposer_nil_block() {
+    let first_author = ValidatorSigner::random([0u8; 32]).author();
+    let second_author = ValidatorSigner::random([1u8; 32]).author();
+    let third_author = ValidatorSigner::random([2u8; 32]).author();
+    let mut pe: Box<dyn ProposerElection<u32>> = Box::new(ReputationProposer::new(
+        vec![first_author, second_author, third_author],
+        1,
+        10,
+        1,
+        ProposerFailures::default(),
+        Box::new(EmptyStorage),
+    ));
+
+    // The round of a committed NIL block failed.
+    let nil_block = Block::make_nil_block(
+        &Block::make_genesis_block(),
+        1,
+        QuorumCert::certificate_for_genesis(),
+    );
+    pe.process_committed_block(&nil_block);
+    assert_eq!(pe.get_valid_proposers(6), vec![first_author]);
+    assert_eq!(pe.get_valid_proposers(7), vec![third_author]);
+}
+
+#[test]
+fn test_reputation_proposer_all_failed() {
+    let first_author = ValidatorSigner::random([0u8; 32]).author();
+    let second_validator_signer = ValidatorSigner::random([1u8; 32]);
+    let second_author = second_validator_signer.author();
+    let mut pe: Box<dyn ProposerElection<u32>> = Box::new(ReputationProposer::new(
+        vec![first_author, second_author],
+        1,
+        10,
+        1,
+        ProposerFailures::default(),
+        Box::new(EmptyStorage),
+    ));
+
+    // Committing the block of round 3 extending genesis tells that both proposers failed a round.
+    let block = Block::make_block(
+        &Block::make_genesis_block(),
+        1,
+        3,
+        1,
+        QuorumCert::certificate_for_genesis(),
+        &second_validator_signer,
+    );
+    pe.process_committed_block(&block);
+
+    // With every proposer skipped, the proposers follow the round-robin rotation.
+    assert_eq!(pe.get_valid_proposers(7), vec![second_author]);
+    assert_eq!(pe.get_valid_proposers(8), vec![first_author]);
+}
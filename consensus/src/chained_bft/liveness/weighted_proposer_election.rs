@@ -0,0 +1,85 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    common::{Author, Payload, Round},
+    consensus_types::block::Block,
+    liveness::{multi_proposer_election::hash, proposer_election::ProposerElection},
+};
+
+/// The weighted proposer maps a round to an author picked with a probability proportional to its
+/// weight, e.g. its voting power, so that validators running on more capable hardware can be
+/// given a larger share of the proposals.
+/// The proposer is determined by hash(round) % total_weight, mapped onto the cumulative weights
+/// of the proposers. As for the MultiProposer, the hash doesn't have to be cryptographic.
+pub struct WeightedProposer {
+    // Ordering of proposers with their cumulative weights (all honest replicas must agree on
+    // this), proposers without weight are left out
+    proposers: Vec<(Author, u64)>,
+    // Sum of the weights of all the proposers
+    total_weight: u64,
+    // Number of contiguous rounds (i.e. round numbers increase by 1) a proposer is active
+    // in a row
+    contiguous_rounds: u32,
+}
+
+impl WeightedProposer {
+    pub fn new(proposers: Vec<(Author, u64)>, contiguous_rounds: u32) -> Self {
+        let mut total_weight = 0u64;
+        let proposers: Vec<(Author, u64)> = proposers
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0)
+            .map(|(author, weight)| {
+                total_weight = total_weight
+                    .checked_add(weight)
+                    .expect("total weight of the proposers overflows");
+                (author, total_weight)
+            })
+            .collect();
+        assert!(total_weight > 0, "no proposer has any weight");
+        Self {
+            proposers,
+            total_weight,
+            contiguous_rounds,
+        }
+    }
+
+    fn get_proposer(&self, round: Round) -> Author {
+        let slot = hash(round / u64::from(self.contiguous_rounds)) % self.total_weight;
+        // the first proposer whose cumulative weight exceeds the slot owns it
+        let idx = self
+            .proposers
+            .iter()
+            .position(|(_, cumulative_weight)| slot < *cumulative_weight)
+            .expect("slot is lower than the total weight");
+        self.proposers[idx].0
+    }
+}
+
+impl<T: Payload> ProposerElection<T> for WeightedProposer {
+    fn is_valid_proposer(&self, author: Author, round: Round) -> Option<Author> {
+        if self.get_proposer(round) == author {
+            Some(author)
+        } else {
+            None
+        }
+    }
+
+    fn get_valid_proposers(&self, round: Round) -> Vec<Author> {
+        vec![self.get_proposer(round)]
+    }
+
+    fn process_proposal(&mut self, proposal: Block<T>) -> Option<Block<T>> {
+        // Like the rotating proposer, there is no mutable state to synchronize.
+        let round_author = self.get_proposer(proposal.round());
+        if Some(round_author) != proposal.author() {
+            None
+        } else {
+            Some(proposal)
+        }
+    }
+
+    fn take_backup_proposal(&mut self, _round: Round) -> Option<Block<T>> {
+        None
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    consensus_types::{block::Block, quorum_cert::QuorumCert},
+    liveness::{proposer_election::ProposerElection, weighted_proposer_election::WeightedProposer},
+};
+use types::validator_signer::ValidatorSigner;
+
+#[test]
+fn test_weighted_proposer() {
+    let light_validator_signer = ValidatorSigner::random([0u8; 32]);
+    let light_author = light_validator_signer.author();
+    let heavy_validator_signer = ValidatorSigner::random([1u8; 32]);
+    let heavy_author = heavy_validator_signer.author();
+    let idle_author = ValidatorSigner::random([2u8; 32]).author();
+    let proposers = vec![(light_author, 1), (heavy_author, 3), (idle_author, 0)];
+    let mut pe: Box<dyn ProposerElection<u32>> = Box::new(WeightedProposer::new(proposers, 1));
+
+    // Each round has a single proposer, picked proportionally to its weight, and the proposers
+    // without weight are never picked.
+    let mut light_rounds = 0;
+    for round in 1..=1000 {
+        let valid_proposers = pe.get_valid_proposers(round);
+        assert_eq!(valid_proposers.len(), 1);
+        assert_ne!(valid_proposers[0], idle_author);
+        if valid_proposers[0] == light_author {
+            light_rounds += 1;
+        }
+        assert_eq!(
+            pe.is_valid_proposer(valid_proposers[0], round),
+            Some(valid_proposers[0])
+        );
+    }
+    assert!(light_rounds > 150 && light_rounds < 350);
+
+    // Only the proposal of the chosen author wins.
+    let genesis_block = Block::make_genesis_block();
+    let quorum_cert = QuorumCert::certificate_for_genesis();
+    let (good_signer, bad_signer) = if pe.get_valid_proposers(1) == vec![light_author] {
+        (&light_validator_signer, &heavy_validator_signer)
+    } else {
+        (&heavy_validator_signer, &light_validator_signer)
+    };
+    let good_proposal =
+        Block::make_block(&genesis_block, 1, 1, 1, quorum_cert.clone(), good_signer);
+    let bad_proposal = Block::make_block(&genesis_block, 2, 1, 2, quorum_cert, bad_signer);
+    assert_eq!(
+        pe.process_proposal(good_proposal.clone()),
+        Some(good_proposal)
+    );
+    assert_eq!(pe.process_proposal(bad_proposal), None);
+}
+
+#[test]
+fn test_weighted_proposer_with_three_contiguous_rounds() {
+    let first_author = ValidatorSigner::random([0u8; 32]).author();
+    let second_author = ValidatorSigner::random([1u8; 32]).author();
+    let pe: Box<dyn ProposerElection<u32>> = Box::new(WeightedProposer::new(
+        vec![(first_author, 1), (second_author, 1)],
+        3,
+    ));
+
+    // The same proposer is active for 3 contiguous rounds.
+    for round in (0..300).step_by(3) {
+        let proposers = pe.get_valid_proposers(round);
+        assert_eq!(pe.get_valid_proposers(round + 1), proposers);
+        assert_eq!(pe.get_valid_proposers(round + 2), proposers);
+    }
+}
@@ -7,6 +7,8 @@ mod consensusdb;
 mod safety;
 
 mod block_storage;
+#[cfg(feature = "byzantine")]
+pub mod byzantine;
 pub mod chained_bft_consensus_provider;
 pub use consensus_types::quorum_cert::QuorumCert;
 mod chained_bft_smr;
@@ -19,9 +21,15 @@ mod sync_manager;
 #[cfg(test)]
 mod chained_bft_smr_test;
 #[cfg(test)]
+mod epoch_manager_test;
+#[cfg(test)]
 mod network_tests;
 #[cfg(test)]
+mod persistent_storage_test;
+#[cfg(test)]
 mod proto_test;
+#[cfg(test)]
+mod twins_test;
 
 #[cfg(any(test, feature = "fuzzing"))]
 mod test_utils;
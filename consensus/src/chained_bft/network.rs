@@ -19,17 +19,16 @@ use bytes::Bytes;
 use channel;
 use crypto::HashValue;
 use failure::{self, ResultExt};
-use futures::{
-    channel::oneshot, stream::select, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt,
-    TryStreamExt,
-};
+use futures::{channel::oneshot, stream::select, SinkExt, Stream, StreamExt, TryStreamExt};
 use logger::prelude::*;
 use network::{
     proto::{
         BlockRetrievalStatus, ConsensusMsg, ConsensusMsg_oneof, Proposal, RequestBlock,
         RespondBlock, SyncInfo as SyncInfoProto, TimeoutMsg as TimeoutMsgProto, Vote,
     },
-    validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender, Event, RpcError},
+    validator_network::{
+        Broadcast, BroadcastPolicy, ConsensusNetworkEvents, ConsensusNetworkSender, Event, RpcError,
+    },
 };
 use prost_ext::MessageExt;
 use std::{
@@ -37,8 +36,8 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::runtime::TaskExecutor;
-use types::account_address::AccountAddress;
+use task_manager::TaskManager;
+use types::{account_address::AccountAddress, validator_set::ValidatorSet};
 
 /// The response sent back from EventProcessor for the BlockRetrievalRequest.
 #[derive(Debug)]
@@ -49,6 +48,12 @@ pub struct BlockRetrievalResponse<T> {
 
 impl<T: Payload> BlockRetrievalResponse<T> {
     pub fn verify(&self, block_id: HashValue, num_blocks: u64) -> failure::Result<()> {
+        ensure!(
+            self.blocks.len() as u64 <= num_blocks,
+            "too many blocks returned, expect at most {}, get {}",
+            num_blocks,
+            self.blocks.len(),
+        );
         ensure!(
             self.status != BlockRetrievalStatus::Succeeded
                 || self.blocks.len() as u64 == num_blocks,
@@ -77,6 +82,9 @@ impl<T: Payload> BlockRetrievalResponse<T> {
 pub struct BlockRetrievalRequest<T> {
     pub block_id: HashValue,
     pub num_blocks: u64,
+    /// The time by which the requester expects the response. Past it, the response can no longer
+    /// be delivered and the retrieval is abandoned.
+    pub deadline: Instant,
     pub response_sender: oneshot::Sender<BlockRetrievalResponse<T>>,
 }
 
@@ -135,7 +143,7 @@ impl ConsensusNetworkImpl {
     }
 
     /// Establishes the initial connections with the peers and returns the receivers.
-    pub fn start<T: Payload>(&mut self, executor: &TaskExecutor) -> NetworkReceivers<T> {
+    pub fn start<T: Payload>(&mut self, task_manager: &TaskManager) -> NetworkReceivers<T> {
         let (proposal_tx, proposal_rx) = channel::new(1_024, &counters::PENDING_PROPOSAL);
         let (vote_tx, vote_rx) = channel::new(1_024, &counters::PENDING_VOTES);
         let (block_request_tx, block_request_rx) =
@@ -153,7 +161,8 @@ impl ConsensusNetworkImpl {
             .take()
             .expect("[consensus]: self receiver is already taken");
         let all_events = select(network_events, own_msgs);
-        executor.spawn(
+        task_manager.spawn(
+            "network_task",
             NetworkTask {
                 proposal_tx,
                 vote_tx,
@@ -163,10 +172,7 @@ impl ConsensusNetworkImpl {
                 all_events,
                 epoch_mgr: Arc::clone(&self.epoch_mgr),
             }
-            .run()
-            .boxed()
-            .unit_error()
-            .compat(),
+            .run(),
         );
         NetworkReceivers {
             proposals: proposal_rx,
@@ -226,14 +232,53 @@ impl ConsensusNetworkImpl {
     /// internal(to provide back pressure), it does not indicate the message is delivered or sent
     /// out. It does not give indication about when the message is delivered to the recipients,
     /// as well as there is no indication about the network failures.
-    pub async fn broadcast_proposal<T: Payload>(&mut self, proposal: ProposalMsg<T>) {
+    ///
+    /// The proposal is dropped instead of being sent to the peers it is still queued for once
+    /// `ttl` has elapsed, e.g. once its round is over.
+    pub async fn broadcast_proposal<T: Payload>(
+        &mut self,
+        proposal: ProposalMsg<T>,
+        ttl: Option<Duration>,
+    ) {
         let msg = ConsensusMsg {
             message: Some(ConsensusMsg_oneof::Proposal(proposal.into())),
         };
-        self.broadcast(msg).await
+        self.broadcast(msg, ttl).await
+    }
+
+    /// Sends the given proposal to the given recipients only, which honest proposers never do.
+    #[cfg(feature = "byzantine")]
+    pub async fn send_proposal<T: Payload>(
+        &self,
+        proposal: ProposalMsg<T>,
+        recipients: Vec<Author>,
+    ) {
+        let mut network_sender = self.network_sender.clone();
+        let mut self_sender = self.self_sender.clone();
+        let msg = ConsensusMsg {
+            message: Some(ConsensusMsg_oneof::Proposal(proposal.into())),
+        };
+        for peer in recipients {
+            if self.author == peer {
+                let self_msg = Event::Message((self.author, msg.clone()));
+                if let Err(err) = self_sender.send(Ok(self_msg)).await {
+                    error!("Error delivering a self proposal: {:?}", err);
+                }
+                continue;
+            }
+            if let Err(e) = network_sender.send_to(peer, msg.clone()).await {
+                error!("Failed to send a proposal to peer {:?}: {:?}", peer, e);
+            }
+        }
+    }
+
+    /// Broadcasts the given message as is, even if it is malformed.
+    #[cfg(feature = "byzantine")]
+    pub async fn broadcast_raw(&mut self, msg: ConsensusMsg) {
+        self.broadcast(msg, None).await
     }
 
-    async fn broadcast(&mut self, msg: ConsensusMsg) {
+    async fn broadcast(&mut self, msg: ConsensusMsg, ttl: Option<Duration>) {
         for peer in self.epoch_mgr.validators().get_ordered_account_addresses() {
             if self.author == peer {
                 let self_msg = Event::Message((self.author, msg.clone()));
@@ -242,7 +287,11 @@ impl ConsensusNetworkImpl {
                 }
                 continue;
             }
-            if let Err(err) = self.network_sender.send_to(peer, msg.clone()).await {
+            if let Err(err) = self
+                .network_sender
+                .send_to_with_ttl(peer, msg.clone(), ttl)
+                .await
+            {
                 error!(
                     "Error broadcasting proposal to peer: {:?}, error: {:?}, msg: {:?}",
                     peer, err, msg
@@ -284,7 +333,7 @@ impl ConsensusNetworkImpl {
         let msg = ConsensusMsg {
             message: Some(ConsensusMsg_oneof::TimeoutMsg(timeout_msg.into())),
         };
-        self.broadcast(msg).await
+        self.broadcast(msg, None).await
     }
 
     /// Sends the given sync info to the given author.
@@ -306,6 +355,42 @@ impl ConsensusNetworkImpl {
             );
         }
     }
+
+    /// Starts broadcasting the given sync info to all the other validators, each of them
+    /// acknowledging it once processed. The returned broadcast must be driven to completion for
+    /// the sync info to be delivered.
+    pub fn broadcast_sync_info(
+        &self,
+        sync_info: SyncInfo,
+        policy: BroadcastPolicy,
+    ) -> failure::Result<Broadcast> {
+        let recipients = self
+            .epoch_mgr
+            .validators()
+            .get_ordered_account_addresses()
+            .into_iter()
+            .filter(|peer| *peer != self.author)
+            .collect();
+        let msg = ConsensusMsg {
+            message: Some(ConsensusMsg_oneof::SyncInfo(sync_info.into())),
+        };
+        Ok(self.network_sender.broadcast(recipients, &msg, policy)?)
+    }
+
+    /// Lets the network connect to the validators of `validator_set` only, once consensus moved
+    /// to the epoch of this validator set.
+    pub async fn update_eligible_nodes(&self, validator_set: &ValidatorSet) {
+        let mut network_sender = self.network_sender.clone();
+        if let Err(e) = network_sender
+            .update_eligible_nodes(validator_set.payload().to_vec())
+            .await
+        {
+            error!(
+                "Failed to update the eligible nodes of the network: {:?}",
+                e
+            );
+        }
+    }
 }
 
 struct NetworkTask<T, S> {
@@ -356,10 +441,20 @@ where
                         warn!("Failed to process msg {:?}", e)
                     }
                 }
-                Event::RpcRequest((peer_id, msg, callback)) => {
+                Event::RpcRequest((peer_id, msg, callback, deadline)) => {
+                    // The caller has already given up on this request, so don't bother serving it.
+                    if Instant::now() >= deadline || callback.is_canceled() {
+                        debug!("Dropping expired RPC from {}", peer_id);
+                        continue;
+                    }
                     let r = match msg.message {
                         Some(RequestBlock(request)) => {
-                            self.process_request_block(request, callback).await
+                            self.process_request_block(request, callback, deadline)
+                                .await
+                        }
+                        Some(SyncInfo(sync_info)) => {
+                            self.process_sync_info_rpc(sync_info, peer_id, callback)
+                                .await
                         }
                         _ => {
                             warn!("Unexpected RPC from {}: {:?}", peer_id, msg);
@@ -439,10 +534,27 @@ where
         Ok(())
     }
 
+    // Processes a sync info broadcast by a peer, acknowledging it once it is queued.
+    async fn process_sync_info_rpc(
+        &mut self,
+        sync_info: SyncInfoProto,
+        peer: AccountAddress,
+        callback: oneshot::Sender<Result<Bytes, RpcError>>,
+    ) -> failure::Result<()> {
+        let response = match self.process_sync_info(sync_info, peer).await {
+            Ok(()) => Ok(ConsensusMsg::default().to_bytes()?),
+            Err(e) => Err(RpcError::ApplicationError(e)),
+        };
+        callback
+            .send(response)
+            .map_err(|_| format_err!("handling inbound rpc call timed out"))
+    }
+
     async fn process_request_block(
         &mut self,
         request: RequestBlock,
         callback: oneshot::Sender<Result<Bytes, RpcError>>,
+        deadline: Instant,
     ) -> failure::Result<()> {
         let block_id = HashValue::from_slice(&request.block_id[..])?;
         let num_blocks = request.num_blocks;
@@ -454,10 +566,20 @@ where
         let request = BlockRetrievalRequest {
             block_id,
             num_blocks,
+            deadline,
             response_sender: tx,
         };
         self.block_request_tx.try_send(request)?;
-        let BlockRetrievalResponse { status, blocks } = rx.await?;
+        let BlockRetrievalResponse { status, blocks } = match rx.await {
+            Ok(response) => response,
+            Err(_) => {
+                debug!(
+                    "Block retrieval for {} abandoned past its deadline",
+                    block_id
+                );
+                return Ok(());
+            }
+        };
         let mut response = RespondBlock::default();
         response.set_status(status);
         response.blocks = blocks.into_iter().map(Into::into).collect();
@@ -25,30 +25,44 @@ use prost::Message;
 use std::{
     collections::{HashMap, HashSet},
     sync::{Arc, Mutex, RwLock},
-    time::Duration,
+    time::{Duration, Instant},
 };
+use task_manager::TaskManager;
 use tokio::runtime::TaskExecutor;
 
+/// Identifies an instance of a node in the `NetworkPlayground`. Twins share the same Author, and
+/// hence the same keys, but each of them has its own `NodeId`.
+pub type NodeId = usize;
+
 /// `NetworkPlayground` mocks the network implementation and provides convenience
 /// methods for testing. Test clients can use `wait_for_messages` or
 /// `deliver_messages` to inspect the direct-send messages sent between peers.
-/// They can also configure network messages to be dropped between specific peers.
+/// They can also configure network messages to be dropped between specific peers,
+/// or split the network into partitions.
+///
+/// Several instances of a node, called twins, can be added with the same Author: the messages
+/// sent to this Author are delivered to all its instances within the partition of the sender.
 ///
 /// Currently, RPC messages are delivered immediately and are not controlled by
 /// `wait_for_messages` or `deliver_messages` for delivery. They are also not
 /// currently dropped according to the `NetworkPlayground`'s drop config.
 pub struct NetworkPlayground {
-    /// Maps each Author to a Sender of their inbound network notifications.
+    /// Maps each Author to the Senders of the inbound network notifications of its instances.
     /// These events will usually be handled by the event loop spawned in
     /// `ConsensusNetworkImpl`.
-    node_consensus_txs: Arc<Mutex<HashMap<Author, channel::Sender<NetworkNotification>>>>,
+    node_consensus_txs:
+        Arc<Mutex<HashMap<Author, Vec<(NodeId, channel::Sender<NetworkNotification>)>>>>,
     /// Nodes' outbound handlers forward their outbound non-rpc messages to this
     /// queue.
-    outbound_msgs_tx: mpsc::Sender<(Author, NetworkRequest)>,
+    outbound_msgs_tx: mpsc::Sender<(NodeId, Author, NetworkRequest)>,
     /// NetworkPlayground reads all nodes' outbound messages through this queue.
-    outbound_msgs_rx: mpsc::Receiver<(Author, NetworkRequest)>,
+    outbound_msgs_rx: mpsc::Receiver<(NodeId, Author, NetworkRequest)>,
     /// Allow test code to drop direct-send messages between peers.
     drop_config: Arc<RwLock<DropConfig>>,
+    /// Allow test code to split the network: nodes only reach the nodes of their partition.
+    partitions: Arc<RwLock<Partitions>>,
+    /// Number of node instances added so far, which is the id of the next one.
+    num_nodes: NodeId,
     /// An executor for spawning node outbound network event handlers
     executor: TaskExecutor,
 }
@@ -62,6 +76,8 @@ impl NetworkPlayground {
             outbound_msgs_tx,
             outbound_msgs_rx,
             drop_config: Arc::new(RwLock::new(DropConfig(HashMap::new()))),
+            partitions: Arc::new(RwLock::new(Partitions(HashMap::new()))),
+            num_nodes: 0,
             executor,
         }
     }
@@ -76,10 +92,14 @@ impl NetworkPlayground {
     /// they don't block.
     async fn start_node_outbound_handler(
         drop_config: Arc<RwLock<DropConfig>>,
+        partitions: Arc<RwLock<Partitions>>,
+        src_node: NodeId,
         src: Author,
         mut network_reqs_rx: channel::Receiver<NetworkRequest>,
-        mut outbound_msgs_tx: mpsc::Sender<(Author, NetworkRequest)>,
-        node_consensus_txs: Arc<Mutex<HashMap<Author, channel::Sender<NetworkNotification>>>>,
+        mut outbound_msgs_tx: mpsc::Sender<(NodeId, Author, NetworkRequest)>,
+        node_consensus_txs: Arc<
+            Mutex<HashMap<Author, Vec<(NodeId, channel::Sender<NetworkNotification>)>>>,
+        >,
     ) {
         while let Some(net_req) = network_reqs_rx.next().await {
             let drop_rpc = drop_config
@@ -101,17 +121,29 @@ impl NetworkPlayground {
                     if drop_rpc {
                         continue;
                     }
-                    let mut node_consensus_tx = node_consensus_txs
-                        .lock()
-                        .unwrap()
-                        .get(&dst)
-                        .unwrap()
-                        .clone();
+                    // An rpc is answered once: it goes to the first instance of the destination
+                    // which the sender can reach.
+                    let node_consensus_tx = {
+                        let partitions = partitions.read().unwrap();
+                        node_consensus_txs
+                            .lock()
+                            .unwrap()
+                            .get(&dst)
+                            .unwrap()
+                            .iter()
+                            .find(|(dst_node, _)| partitions.are_connected(src_node, *dst_node))
+                            .map(|(_, tx)| tx.clone())
+                    };
+                    let mut node_consensus_tx = match node_consensus_tx {
+                        Some(tx) => tx,
+                        None => continue,
+                    };
 
                     let inbound_req = InboundRpcRequest {
                         protocol: outbound_req.protocol,
                         data: outbound_req.data,
                         res_tx: outbound_req.res_tx,
+                        deadline: Instant::now() + outbound_req.timeout,
                     };
 
                     node_consensus_tx
@@ -119,16 +151,19 @@ impl NetworkPlayground {
                         .await
                         .unwrap();
                 }
+                // All the nodes of the playground stay connected across reconfigurations.
+                NetworkRequest::UpdateEligibleNodes(_) => {}
                 // Other NetworkRequest get buffered for `deliver_messages` to
                 // synchronously drain.
                 net_req => {
-                    let _ = outbound_msgs_tx.send((src, net_req)).await;
+                    let _ = outbound_msgs_tx.send((src_node, src, net_req)).await;
                 }
             }
         }
     }
 
-    /// Add a new node to the NetworkPlayground.
+    /// Add a new node to the NetworkPlayground and returns its id. Adding a node with the Author
+    /// of a node already added makes them twins.
     pub fn add_node(
         &mut self,
         author: Author,
@@ -139,65 +174,79 @@ impl NetworkPlayground {
         // `Sender` side of this queue is usually wrapped in a
         // `ConsensusNetworkSender` adapter.
         network_reqs_rx: channel::Receiver<NetworkRequest>,
-    ) {
+    ) -> NodeId {
+        let node = self.num_nodes;
+        self.num_nodes += 1;
         self.node_consensus_txs
             .lock()
             .unwrap()
-            .insert(author, consensus_tx);
+            .entry(author)
+            .or_insert_with(Vec::new)
+            .push((node, consensus_tx));
         self.drop_config.write().unwrap().add_node(author);
 
         let fut = NetworkPlayground::start_node_outbound_handler(
             Arc::clone(&self.drop_config),
+            Arc::clone(&self.partitions),
+            node,
             author,
             network_reqs_rx,
             self.outbound_msgs_tx.clone(),
             self.node_consensus_txs.clone(),
         );
         self.executor.spawn(fut.boxed().unit_error().compat());
+        node
     }
 
-    /// Deliver a `NetworkRequest` from peer `src` to the destination peer.
-    /// Returns a copy of the delivered message and the sending peer id.
+    /// Deliver a `NetworkRequest` from peer `src` to the instances of the destination peer
+    /// which are in the partition of `src_node`.
+    /// Returns a copy of the delivered message and the sending peer id, or None if no instance of
+    /// the destination peer can be reached.
     async fn deliver_message(
         &mut self,
+        src_node: NodeId,
         src: Author,
         msg: NetworkRequest,
-    ) -> (Author, ConsensusMsg) {
+    ) -> Option<(Author, ConsensusMsg)> {
         // extract destination peer
         let dst = match &msg {
-            NetworkRequest::SendMessage(dst, _) => *dst,
+            NetworkRequest::SendMessage(dst, _, _) => *dst,
             msg => panic!("[network playground] Unexpected NetworkRequest: {:?}", msg),
         };
 
-        // get his sender
-        let mut node_consensus_tx = self
-            .node_consensus_txs
-            .lock()
-            .unwrap()
-            .get(&dst)
-            .unwrap()
-            .clone();
+        // get the senders of its instances in the same partition
+        let node_consensus_txs: Vec<_> = {
+            let partitions = self.partitions.read().unwrap();
+            self.node_consensus_txs
+                .lock()
+                .unwrap()
+                .get(&dst)
+                .unwrap()
+                .iter()
+                .filter(|(dst_node, _)| partitions.are_connected(src_node, *dst_node))
+                .map(|(_, tx)| tx.clone())
+                .collect()
+        };
+        if node_consensus_txs.is_empty() {
+            return None;
+        }
 
         // convert NetworkRequest to corresponding NetworkNotification
-        let msg_notif = match msg {
-            NetworkRequest::SendMessage(_dst, msg) => NetworkNotification::RecvMessage(src, msg),
+        let msg = match msg {
+            NetworkRequest::SendMessage(_dst, msg, _) => msg,
             msg => panic!("[network playground] Unexpected NetworkRequest: {:?}", msg),
         };
 
         // copy message data
-        let msg_copy = match &msg_notif {
-            NetworkNotification::RecvMessage(src, msg) => {
-                let msg = ConsensusMsg::decode(msg.mdata.as_ref()).unwrap();
-                (*src, msg)
-            }
-            msg_notif => panic!(
-                "[network playground] Unexpected NetworkNotification: {:?}",
-                msg_notif
-            ),
-        };
+        let msg_copy = (src, ConsensusMsg::decode(msg.mdata.as_ref()).unwrap());
 
-        node_consensus_tx.send(msg_notif).await.unwrap();
-        msg_copy
+        for mut node_consensus_tx in node_consensus_txs {
+            node_consensus_tx
+                .send(NetworkNotification::RecvMessage(src, msg.clone()))
+                .await
+                .unwrap();
+        }
+        Some(msg_copy)
     }
 
     /// Wait for exactly `num_messages` to be enqueued and delivered. Return a
@@ -215,14 +264,15 @@ impl NetworkPlayground {
         let mut msg_copies = vec![];
         while msg_copies.len() < num_messages {
             // Take the next queued message
-            let (src, net_req) = self.outbound_msgs_rx.next().await
+            let (src_node, src, net_req) = self.outbound_msgs_rx.next().await
                 .expect("[network playground] waiting for messages, but message queue has shutdown unexpectedly");
 
             // Deliver and copy message it if it's not dropped
             if !self.is_message_dropped(&src, &net_req) {
-                let msg_copy = self.deliver_message(src, net_req).await;
-                if msg_inspector(&msg_copy) {
-                    msg_copies.push(msg_copy);
+                if let Some(msg_copy) = self.deliver_message(src_node, src, net_req).await {
+                    if msg_inspector(&msg_copy) {
+                        msg_copies.push(msg_copy);
+                    }
                 }
             }
         }
@@ -293,6 +343,23 @@ impl NetworkPlayground {
             .unwrap()
             .stop_drop_message_for(src, dst)
     }
+
+    /// Splits the network into the given partitions of nodes: the messages are only delivered
+    /// within a partition, and the nodes which aren't part of any partition are isolated.
+    pub fn split_network(&mut self, partitions: Vec<Vec<NodeId>>) {
+        *self.partitions.write().unwrap() = Partitions(
+            partitions
+                .into_iter()
+                .enumerate()
+                .flat_map(|(idx, nodes)| nodes.into_iter().map(move |node| (node, idx)))
+                .collect(),
+        );
+    }
+
+    /// Reconnects all the nodes split by `split_network`.
+    pub fn heal_network(&mut self) {
+        *self.partitions.write().unwrap() = Partitions(HashMap::new());
+    }
 }
 
 struct DropConfig(HashMap<Author, HashSet<Author>>);
@@ -300,7 +367,7 @@ struct DropConfig(HashMap<Author, HashSet<Author>>);
 impl DropConfig {
     pub fn is_message_dropped(&self, src: &Author, net_req: &NetworkRequest) -> bool {
         match net_req {
-            NetworkRequest::SendMessage(dst, _) => self.0.get(src).unwrap().contains(&dst),
+            NetworkRequest::SendMessage(dst, _, _) => self.0.get(src).unwrap().contains(&dst),
             NetworkRequest::SendRpc(dst, _) => self.0.get(src).unwrap().contains(&dst),
             _ => true,
         }
@@ -315,7 +382,16 @@ impl DropConfig {
     }
 
     fn add_node(&mut self, src: Author) {
-        self.0.insert(src, HashSet::new());
+        self.0.entry(src).or_insert_with(HashSet::new);
+    }
+}
+
+/// Maps each node to the index of its partition, all the nodes are connected when it is empty.
+struct Partitions(HashMap<NodeId, usize>);
+
+impl Partitions {
+    fn are_connected(&self, src: NodeId, dst: NodeId) -> bool {
+        self.0.is_empty() || (self.0.contains_key(&src) && self.0.get(&src) == self.0.get(&dst))
     }
 }
 
@@ -345,7 +421,7 @@ fn test_network_api() {
             network_events,
             Arc::clone(&epoch_mgr),
         );
-        receivers.push(node.start(&runtime.executor()));
+        receivers.push(node.start(&TaskManager::new("consensus", runtime.executor())));
         nodes.push(node);
     }
     let vote = VoteMsg::new(
@@ -377,7 +453,7 @@ fn test_network_api() {
             let v = r.votes.next().await.unwrap();
             assert_eq!(v, vote);
         }
-        nodes[4].broadcast_proposal(proposal.clone()).await;
+        nodes[4].broadcast_proposal(proposal.clone(), None).await;
         playground
             .wait_for_messages(4, NetworkPlayground::take_all)
             .await;
@@ -413,7 +489,7 @@ fn test_rpc() {
             Arc::clone(&epoch_mgr),
         );
         senders.push(network_sender);
-        receivers.push(node.start(&runtime.executor()));
+        receivers.push(node.start(&TaskManager::new("consensus", runtime.executor())));
         nodes.push(node);
     }
     let receiver_1 = receivers.remove(1);
@@ -445,3 +521,66 @@ fn test_rpc() {
         assert_eq!(response.blocks[0], *genesis);
     });
 }
+
+#[test]
+fn test_rpc_too_many_blocks() {
+    let runtime = consensus_runtime();
+    let mut receivers: Vec<NetworkReceivers<u64>> = Vec::new();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let mut nodes = Vec::new();
+    let (signers, validator_verifier) = random_validator_verifier(2, None, false);
+    let peers: Vec<_> = signers.iter().map(|signer| signer.author()).collect();
+    let epoch_mgr = Arc::new(EpochManager::new(0, validator_verifier));
+    for peer in peers.iter() {
+        let (network_reqs_tx, network_reqs_rx) = channel::new_test(8);
+        let (consensus_tx, consensus_rx) = channel::new_test(8);
+        let network_sender = ConsensusNetworkSender::new(network_reqs_tx);
+        let network_events = ConsensusNetworkEvents::new(consensus_rx);
+
+        playground.add_node(*peer, consensus_tx, network_reqs_rx);
+        let mut node = ConsensusNetworkImpl::new(
+            *peer,
+            network_sender,
+            network_events,
+            Arc::clone(&epoch_mgr),
+        );
+        receivers.push(node.start(&TaskManager::new("consensus", runtime.executor())));
+        nodes.push(node);
+    }
+    let receiver_1 = receivers.remove(1);
+    let genesis = Block::<u64>::make_genesis_block();
+    let block = Block::make_block(
+        &genesis,
+        0,
+        1,
+        0,
+        QuorumCert::certificate_for_genesis(),
+        &signers[1],
+    );
+    let block_id = block.id();
+
+    // A byzantine peer answers a request for a single block with a chain of two blocks, under a
+    // status which doesn't claim to carry all the requested blocks.
+    let mut block_retrieval = receiver_1.block_retrieval;
+    let on_request_block = async move {
+        while let Some(request) = block_retrieval.next().await {
+            request
+                .response_sender
+                .send(BlockRetrievalResponse {
+                    status: BlockRetrievalStatus::NotEnoughBlocks,
+                    blocks: vec![block.clone(), genesis.clone()],
+                })
+                .unwrap();
+        }
+    };
+    runtime
+        .executor()
+        .spawn(on_request_block.boxed().unit_error().compat());
+    let peer = peers[1];
+    block_on(async move {
+        assert!(nodes[0]
+            .request_block(block_id, 1, peer, Duration::from_secs(5))
+            .await
+            .is_err());
+    });
+}
@@ -6,7 +6,11 @@ use crate::{
         common::Payload,
         consensus_types::{block::Block, quorum_cert::QuorumCert},
         consensusdb::ConsensusDB,
-        liveness::pacemaker_timeout_manager::HighestTimeoutCertificates,
+        epoch_manager::EpochInfo,
+        liveness::{
+            pacemaker_timeout_manager::HighestTimeoutCertificates,
+            reputation_proposer_election::ProposerFailures,
+        },
         safety::safety_rules::ConsensusState,
     },
     consensus_provider::create_storage_read_client,
@@ -30,6 +34,10 @@ pub trait PersistentLivenessStorage: Send + Sync {
         &self,
         highest_timeout_certs: HighestTimeoutCertificates,
     ) -> Result<()>;
+
+    /// Persist the proposers of the recently failed rounds, so that the reputation of the
+    /// proposers is the same as on the other replicas after a restart.
+    fn save_proposer_failures(&self, proposer_failures: ProposerFailures) -> Result<()>;
 }
 
 /// Persistent storage is essential for maintaining safety when a node crashes.  Specifically,
@@ -52,6 +60,17 @@ pub trait PersistentStorage<T>: PersistentLivenessStorage + Send + Sync {
     /// Persist the consensus state.
     fn save_consensus_state(&self, state: ConsensusState) -> Result<()>;
 
+    /// Record the ledger info of a commit about to be handed to the StateComputer, so that a
+    /// commit interrupted by a crash is detected on restart.
+    fn save_pending_commit(&self, ledger_info: LedgerInfo) -> Result<()>;
+
+    /// Clear the pending commit once the StateComputer is done with it and its transactions are
+    /// reported to the clients.
+    fn clear_pending_commit(&self) -> Result<()>;
+
+    /// Persist the epoch consensus reconfigured to, so that it restarts in this epoch.
+    fn save_epoch(&self, epoch_info: EpochInfo) -> Result<()>;
+
     /// When the node restart, construct the instance and returned the data read from db.
     /// This could guarantee we only read once during start, and we would panic if the
     /// read fails.
@@ -76,10 +95,19 @@ pub struct RecoveryData<T> {
 
     // Liveness data
     highest_timeout_certificates: HighestTimeoutCertificates,
+    proposer_failures: ProposerFailures,
 
     // If root is not consistent with StateComputer, need to state synchronize before
     // starting
     need_sync: bool,
+
+    // The QC carrying the finality proof of a commit handed to the StateComputer before the
+    // restart, which the storage never completed: consensus hands it over again on start.
+    pending_commit: Option<QuorumCert>,
+
+    // The epoch consensus last reconfigured to, none if it still runs the initial epoch of the
+    // node config.
+    epoch_info: Option<EpochInfo>,
 }
 
 impl<T: Payload> RecoveryData<T> {
@@ -89,6 +117,9 @@ impl<T: Payload> RecoveryData<T> {
         mut quorum_certs: Vec<QuorumCert>,
         storage_ledger: &LedgerInfo,
         highest_timeout_certificates: HighestTimeoutCertificates,
+        proposer_failures: ProposerFailures,
+        pending_commit: Option<LedgerInfo>,
+        epoch_info: Option<EpochInfo>,
     ) -> Result<Self> {
         let root =
             Self::find_root(&mut blocks, &mut quorum_certs, storage_ledger).with_context(|e| {
@@ -117,6 +148,20 @@ impl<T: Payload> RecoveryData<T> {
         ));
         // if the root is different than the LI(S).block, we need to sync before start
         let need_sync = storage_ledger.consensus_block_id() != root.0.id();
+        // A pending commit of a block which is still a descendant of the root was never completed
+        // by the storage. Otherwise, the storage completed it, or synchronized past it, and there
+        // is nothing left to do.
+        let pending_commit = pending_commit.and_then(|ledger_info| {
+            let block_id = ledger_info.consensus_block_id();
+            if blocks.iter().any(|block| block.id() == block_id) {
+                quorum_certs
+                    .iter()
+                    .find(|qc| qc.committed_block_id() == Some(block_id))
+                    .cloned()
+            } else {
+                None
+            }
+        });
         Ok(RecoveryData {
             state,
             root,
@@ -124,7 +169,10 @@ impl<T: Payload> RecoveryData<T> {
             quorum_certs,
             blocks_to_prune,
             highest_timeout_certificates,
+            proposer_failures,
             need_sync,
+            pending_commit,
+            epoch_info,
         })
     }
 
@@ -152,6 +200,10 @@ impl<T: Payload> RecoveryData<T> {
         &self.highest_timeout_certificates
     }
 
+    pub fn proposer_failures(&self) -> &ProposerFailures {
+        &self.proposer_failures
+    }
+
     pub fn root_ledger_info(&self) -> QuorumCert {
         self.root.2.clone()
     }
@@ -160,6 +212,14 @@ impl<T: Payload> RecoveryData<T> {
         self.need_sync
     }
 
+    pub fn take_pending_commit(&mut self) -> Option<QuorumCert> {
+        self.pending_commit.take()
+    }
+
+    pub fn epoch_info(&self) -> Option<&EpochInfo> {
+        self.epoch_info.as_ref()
+    }
+
     /// Finds the root (last committed block) and returns the root block, the QC to the root block
     /// and the ledger info for the root block, return an error if it can not be found.
     ///
@@ -263,7 +323,8 @@ impl<T: Payload> RecoveryData<T> {
         blocks: &mut Vec<Block<T>>,
         quorum_certs: &mut Vec<QuorumCert>,
     ) -> Vec<HashValue> {
-        // prune all the blocks that don't have root as ancestor
+        // prune all the blocks that don't have root as ancestor, which includes the committed
+        // blocks retained before the restart
         let mut tree = HashSet::new();
         let mut to_remove = vec![];
         tree.insert(root_id);
@@ -277,7 +338,19 @@ impl<T: Payload> RecoveryData<T> {
                 false
             }
         });
-        quorum_certs.retain(|qc| tree.contains(&qc.certified_block_id()));
+        // as well as the quorum certs of these blocks, and the ones left without their block
+        let pruned_blocks: HashSet<HashValue> = to_remove.iter().cloned().collect();
+        quorum_certs.retain(|qc| {
+            let block_id = qc.certified_block_id();
+            if tree.contains(&block_id) {
+                true
+            } else {
+                if !pruned_blocks.contains(&block_id) {
+                    to_remove.push(block_id);
+                }
+                false
+            }
+        });
         to_remove
     }
 }
@@ -301,6 +374,11 @@ impl PersistentLivenessStorage for StorageWriteProxy {
         self.db
             .save_highest_timeout_certificates(to_vec_named(&highest_timeout_certs)?)
     }
+
+    fn save_proposer_failures(&self, proposer_failures: ProposerFailures) -> Result<()> {
+        self.db
+            .save_proposer_failures(to_vec_named(&proposer_failures)?)
+    }
 }
 
 impl<T: Payload> PersistentStorage<T> for StorageWriteProxy {
@@ -326,6 +404,18 @@ impl<T: Payload> PersistentStorage<T> for StorageWriteProxy {
         self.db.save_state(to_vec_named(&state)?)
     }
 
+    fn save_pending_commit(&self, ledger_info: LedgerInfo) -> Result<()> {
+        self.db.save_pending_commit(to_vec_named(&ledger_info)?)
+    }
+
+    fn clear_pending_commit(&self) -> Result<()> {
+        self.db.delete_pending_commit()
+    }
+
+    fn save_epoch(&self, epoch_info: EpochInfo) -> Result<()> {
+        self.db.save_epoch(to_vec_named(&epoch_info)?)
+    }
+
     fn start(config: &NodeConfig) -> (Arc<Self>, RecoveryData<T>) {
         info!("Start consensus recovery.");
         let read_client = create_storage_read_client(config);
@@ -341,6 +431,20 @@ impl<T: Payload> PersistentStorage<T> for StorageWriteProxy {
             .map_or_else(HighestTimeoutCertificates::default, |s| {
                 from_slice(&s[..]).expect("unable to deserialize highest timeout certificates")
             });
+        let proposer_failures = db
+            .get_proposer_failures()
+            .expect("unable to read proposer failures")
+            .map_or_else(ProposerFailures::default, |s| {
+                from_slice(&s[..]).expect("unable to deserialize proposer failures")
+            });
+        let pending_commit: Option<LedgerInfo> = db
+            .get_pending_commit()
+            .expect("unable to read pending commit")
+            .map(|s| from_slice(&s[..]).expect("unable to deserialize pending commit"));
+        let epoch_info: Option<EpochInfo> = db
+            .get_epoch()
+            .expect("unable to read epoch")
+            .map(|s| from_slice(&s[..]).expect("unable to deserialize epoch"));
         let mut blocks = initial_data.2;
         let mut quorum_certs: Vec<_> = initial_data.3;
         // bootstrap the empty store with genesis block and qc.
@@ -375,6 +479,9 @@ impl<T: Payload> PersistentStorage<T> for StorageWriteProxy {
             quorum_certs,
             ledger_info.ledger_info(),
             highest_timeout_certificates,
+            proposer_failures,
+            pending_commit.clone(),
+            epoch_info,
         )
         .unwrap_or_else(|e| panic!("Can not construct recovery data due to {}", e));
 
@@ -382,6 +489,24 @@ impl<T: Payload> PersistentStorage<T> for StorageWriteProxy {
             .expect("unable to prune dangling blocks during restart");
 
         info!("Consensus root to start with: {}", initial_data.root.0);
+        if let Some(epoch_info) = &initial_data.epoch_info {
+            info!("Consensus restarts in epoch {}", epoch_info.epoch);
+        }
+
+        if let Some(pending_commit) = pending_commit {
+            if initial_data.pending_commit.is_some() {
+                warn!(
+                    "The commit of block {} was interrupted before the storage completed it, it \
+                     is handed to the StateComputer again.",
+                    pending_commit.consensus_block_id()
+                );
+            } else {
+                info!(
+                    "The commit of block {} was completed by the storage before the restart.",
+                    pending_commit.consensus_block_id()
+                );
+            }
+        }
 
         if initial_data.need_sync {
             info!("Consensus recovery done but additional state synchronization is required.");
@@ -0,0 +1,143 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    consensus_types::{block::Block, quorum_cert::QuorumCert, vote_data::VoteData},
+    liveness::{
+        pacemaker_timeout_manager::HighestTimeoutCertificates,
+        reputation_proposer_election::ProposerFailures,
+    },
+    persistent_storage::RecoveryData,
+    safety::safety_rules::ConsensusState,
+    test_utils::TestPayload,
+};
+use crypto::HashValue;
+use executor::ExecutedState;
+use std::collections::HashMap;
+use types::{
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorSigner},
+    ledger_info::LedgerInfo,
+};
+
+/// A QC certifying `block`, whose ledger info commits `committed`, if any.
+fn certificate_for(
+    block: &Block<TestPayload>,
+    committed: Option<&Block<TestPayload>>,
+) -> QuorumCert {
+    let ledger_info = LedgerInfo::new(
+        0,
+        HashValue::zero(),
+        HashValue::zero(),
+        committed.map_or_else(HashValue::zero, |block| block.id()),
+        0,
+        committed.map_or(0, |block| block.timestamp_usecs()),
+        None,
+    );
+    QuorumCert::new(
+        VoteData::new(
+            block.id(),
+            ExecutedState::state_for_genesis().state_id,
+            block.round(),
+            block.quorum_cert().certified_block_id(),
+            block.quorum_cert().certified_block_round(),
+            block.quorum_cert().parent_block_id(),
+            block.quorum_cert().parent_block_round(),
+        ),
+        LedgerInfoWithSignatures::new(ledger_info, HashMap::new()),
+    )
+}
+
+/// Genesis followed by a chain of 4 blocks, along with the QCs of all the blocks. The QC of the
+/// block of round r commits the block of round r - 2.
+fn build_chain() -> (Vec<Block<TestPayload>>, Vec<QuorumCert>) {
+    let signer = ValidatorSigner::random(None);
+    let mut blocks = vec![Block::make_genesis_block()];
+    let mut quorum_certs = vec![QuorumCert::certificate_for_genesis()];
+    for round in 1..=4 {
+        let parent = blocks.last().unwrap();
+        let block = Block::make_block(
+            parent,
+            vec![round as usize],
+            round,
+            parent.timestamp_usecs() + 1,
+            quorum_certs.last().unwrap().clone(),
+            &signer,
+        );
+        let committed = if round >= 3 {
+            Some(&blocks[round as usize - 2])
+        } else {
+            None
+        };
+        quorum_certs.push(certificate_for(&block, committed));
+        blocks.push(block);
+    }
+    (blocks, quorum_certs)
+}
+
+fn recover(
+    blocks: &[Block<TestPayload>],
+    quorum_certs: &[QuorumCert],
+    storage_ledger: &LedgerInfo,
+    pending_commit: Option<LedgerInfo>,
+) -> RecoveryData<TestPayload> {
+    RecoveryData::new(
+        ConsensusState::default(),
+        blocks.to_vec(),
+        quorum_certs.to_vec(),
+        storage_ledger,
+        HighestTimeoutCertificates::default(),
+        ProposerFailures::default(),
+        pending_commit,
+        None,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_interrupted_commit_is_replayed() {
+    let (blocks, quorum_certs) = build_chain();
+    // The storage committed block 1, consensus crashed while committing block 2.
+    let storage_ledger = quorum_certs[3].ledger_info().ledger_info();
+    let pending_commit = quorum_certs[4].ledger_info().ledger_info().clone();
+
+    let mut data = recover(&blocks, &quorum_certs, storage_ledger, Some(pending_commit));
+    assert!(!data.need_sync());
+    assert_eq!(data.take_pending_commit(), Some(quorum_certs[4].clone()));
+}
+
+#[test]
+fn test_completed_commit_is_not_replayed() {
+    let (blocks, quorum_certs) = build_chain();
+    // The storage committed block 2, consensus crashed before learning it.
+    let storage_ledger = quorum_certs[4].ledger_info().ledger_info();
+    let pending_commit = storage_ledger.clone();
+
+    let mut data = recover(&blocks, &quorum_certs, storage_ledger, Some(pending_commit));
+    assert!(!data.need_sync());
+    assert_eq!(data.take_pending_commit(), None);
+
+    let mut data = recover(&blocks, &quorum_certs, storage_ledger, None);
+    assert_eq!(data.take_pending_commit(), None);
+}
+
+#[test]
+fn test_retained_blocks_are_pruned() {
+    let (blocks, mut quorum_certs) = build_chain();
+    // The blocks below the root are the committed blocks retained before the restart, the last
+    // quorum cert is left without its block.
+    let (orphan_blocks, _) = build_chain();
+    quorum_certs.push(certificate_for(&orphan_blocks[4], None));
+    let storage_ledger = quorum_certs[4].ledger_info().ledger_info();
+
+    let mut data = recover(&blocks, &quorum_certs, storage_ledger, None);
+    let mut blocks_to_prune = data.take_blocks_to_prune();
+    blocks_to_prune.sort();
+    let mut expected = vec![blocks[0].id(), blocks[1].id(), orphan_blocks[4].id()];
+    expected.sort();
+    assert_eq!(blocks_to_prune, expected);
+
+    let (root, blocks_to_keep, quorum_certs_to_keep) = data.take();
+    assert_eq!(root.0, blocks[2]);
+    assert_eq!(blocks_to_keep, blocks[3..].to_vec());
+    assert_eq!(quorum_certs_to_keep, quorum_certs[2..5].to_vec());
+}
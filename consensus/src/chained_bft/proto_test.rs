@@ -5,8 +5,13 @@ use crate::chained_bft::{
     consensus_types::{
         block::Block,
         proposal_msg::{ProposalMsg, ProposalUncheckedSignatures},
+        proptest_types::{
+            arb_quorum_cert, arb_sync_info, arb_timeout_msg, arb_validator_signers,
+            validator_verifier,
+        },
         quorum_cert::QuorumCert,
         sync_info::SyncInfo,
+        timeout_msg::TimeoutMsg,
         vote_data::VoteData,
         vote_msg::VoteMsg,
     },
@@ -14,6 +19,7 @@ use crate::chained_bft::{
 };
 use crypto::HashValue;
 use executor::ExecutedState;
+use proptest::prelude::*;
 use prost::Message;
 use prost_ext::MessageExt;
 use std::convert::{TryFrom, TryInto};
@@ -71,3 +77,44 @@ fn test_proto_convert_vote() {
     let vote_proto = network::proto::Vote::from(vote.clone());
     assert_eq!(vote, vote_proto.try_into().unwrap());
 }
+
+proptest! {
+    #[test]
+    fn test_proto_convert_quorum_cert(qc in any::<QuorumCert>()) {
+        let qc_proto = network::proto::QuorumCert::from(qc.clone());
+        prop_assert_eq!(qc, qc_proto.try_into().unwrap());
+    }
+
+    #[test]
+    fn test_proto_convert_sync_info(sync_info in any::<SyncInfo>()) {
+        let sync_info_proto = network::proto::SyncInfo::from(sync_info.clone());
+        prop_assert_eq!(sync_info, sync_info_proto.try_into().unwrap());
+    }
+
+    #[test]
+    fn test_proto_convert_timeout_msg(timeout_msg in any::<TimeoutMsg>()) {
+        let timeout_msg_proto = network::proto::TimeoutMsg::from(timeout_msg.clone());
+        prop_assert_eq!(timeout_msg, timeout_msg_proto.try_into().unwrap());
+    }
+
+    #[test]
+    fn test_generated_messages_verify(
+        (signers, qc, sync_info, timeout_msg) in arb_validator_signers().prop_flat_map(|signers| {
+            (
+                Just(signers.clone()),
+                arb_quorum_cert(signers.clone()),
+                arb_sync_info(signers.clone()),
+                arb_timeout_msg(signers),
+            )
+        })
+    ) {
+        let verifier = validator_verifier(&signers);
+        prop_assert!(qc.verify(&verifier).is_ok());
+        prop_assert!(sync_info.verify(&verifier).is_ok());
+        prop_assert!(timeout_msg.verify(&verifier).is_ok());
+        prop_assert!(timeout_msg.sync_info().verify(&verifier).is_ok());
+        prop_assert!(
+            timeout_msg.pacemaker_timeout().round() > timeout_msg.sync_info().highest_round()
+        );
+    }
+}
@@ -16,17 +16,23 @@ use crate::{
     counters,
     state_replication::StateComputer,
 };
+use crypto::HashValue;
 use failure;
+use futures::stream::{FuturesUnordered, StreamExt};
 use logger::prelude::*;
-use network::proto::BlockRetrievalStatus;
-use rand::{prelude::*, Rng};
+use network::{proto::BlockRetrievalStatus, validator_network::RpcError};
+use rand::{seq::SliceRandom, thread_rng};
 use std::{
     clone::Clone,
+    collections::VecDeque,
     sync::Arc,
     time::{Duration, Instant},
 };
 use termion::color::*;
-use types::account_address::AccountAddress;
+
+#[cfg(test)]
+#[path = "sync_manager_test.rs"]
+mod sync_manager_test;
 
 /// SyncManager is responsible for fetching dependencies and 'catching up' for given qc/ledger info
 pub struct SyncManager<T> {
@@ -132,27 +138,17 @@ where
         preferred_peer: Author,
         deadline: Instant,
     ) -> failure::Result<()> {
-        let mut pending = vec![];
-        let network = self.network.clone();
-        let mut retriever = BlockRetriever {
-            network,
-            deadline,
-            preferred_peer,
-        };
-        let mut retrieve_qc = qc.clone();
-        loop {
-            if self
-                .block_store
-                .block_exists(retrieve_qc.certified_block_id())
-            {
-                break;
-            }
-            let mut blocks = retriever.retrieve_block_for_qc(&retrieve_qc, 1).await?;
-            // retrieve_block_for_qc guarantees that blocks has exactly 1 element
-            let block = blocks.remove(0);
-            retrieve_qc = block.quorum_cert().clone();
-            pending.push(block);
-        }
+        // The blocks missing between the root and the qc can't be more than their rounds apart.
+        let max_missing_blocks = qc
+            .certified_block_round()
+            .saturating_sub(self.block_store.root().round());
+        let mut retriever =
+            BlockRetrievalManager::new(self.network.clone(), &qc, preferred_peer, deadline);
+        let mut pending = retriever
+            .retrieve_chain(qc.certified_block_id(), max_missing_blocks, |id| {
+                self.block_store.block_exists(id)
+            })
+            .await?;
         // insert the qc <- block pair
         while let Some(block) = pending.pop() {
             let block_qc = block.quorum_cert().clone();
@@ -192,14 +188,10 @@ where
             highest_ledger_info.certified_block_round() - 2,
             self.block_store.root()
         );
-        let network = self.network.clone();
-        let mut retriever = BlockRetriever {
-            network,
-            deadline,
-            preferred_peer: peer,
-        };
+        let mut retriever =
+            BlockRetrievalManager::new(self.network.clone(), &highest_ledger_info, peer, deadline);
         let mut blocks = retriever
-            .retrieve_block_for_qc(&highest_ledger_info, 3)
+            .retrieve_chain(highest_ledger_info.certified_block_id(), 3, |_| false)
             .await?;
         assert_eq!(
             blocks.last().expect("should have 3-chain").id(),
@@ -230,106 +222,200 @@ where
     }
 }
 
-/// BlockRetriever is used internally to retrieve blocks
-struct BlockRetriever {
+// Max number of blocks requested from a peer at once
+const MAX_BLOCKS_PER_RETRIEVAL: u64 = 10;
+// Number of peers a range of blocks is requested from at once
+const RETRIEVAL_FANOUT: usize = 2;
+
+/// BlockRetrievalManager is used internally to retrieve chains of blocks backwards, starting
+/// from the block certified by a quorum cert.
+///
+/// A chain is retrieved in disjoint ranges of at most `max_blocks_per_request` blocks. The first
+/// block of a range is only known from the last block of the previous range, hence the ranges
+/// are retrieved one after the other, but each of them is requested at once from `fanout` peers
+/// and the first complete response wins. The peers rotate from a range to the next one, so that
+/// the retrieval of a long chain is spread across them.
+///
+/// The peers are the signers of the quorum cert, which are up to date with its block, with the
+/// preferred peer tried first. A peer which fails or doesn't know the requested block
+/// (`BlockRetrievalStatus::IdNotFound`) is not asked again, but a peer which times out, e.g. as it
+/// is overloaded, is asked again once the other peers were. A peer which only has the first
+/// blocks of a range (`BlockRetrievalStatus::NotEnoughBlocks`), e.g. because it pruned the
+/// others, contributes these blocks and the rest of the range is requested from other peers.
+struct BlockRetrievalManager {
     network: ConsensusNetworkImpl,
     deadline: Instant,
-    preferred_peer: Author,
+    // Peers to request the next ranges from, in turn
+    peers: VecDeque<Author>,
+    fanout: usize,
+    max_blocks_per_request: u64,
 }
 
-impl BlockRetriever {
-    /// Retrieve chain of n blocks for given QC
-    ///
-    /// Returns Result with Vec that has a guaranteed size of num_blocks
-    /// This guarantee is based on BlockRetrievalResponse::verify that ensures that number of
-    /// blocks in response is equal to number of blocks requested.  This method will
-    /// continue until either the round deadline is reached or the quorum certificate members all
-    /// fail to return the missing chain.
+impl BlockRetrievalManager {
+    fn new(
+        network: ConsensusNetworkImpl,
+        qc: &QuorumCert,
+        preferred_peer: Author,
+        deadline: Instant,
+    ) -> Self {
+        let mut peers: Vec<Author> = qc
+            .ledger_info()
+            .signatures()
+            .keys()
+            .filter(|peer| **peer != preferred_peer)
+            .cloned()
+            .collect();
+        peers.shuffle(&mut thread_rng());
+        let mut peers: VecDeque<Author> = peers.into();
+        // The preferred peer is tried first, even if it didn't sign the quorum cert: this is
+        // typically the leader driving the quorum cert creation.
+        peers.push_front(preferred_peer);
+        Self {
+            network,
+            deadline,
+            peers,
+            fanout: RETRIEVAL_FANOUT,
+            max_blocks_per_request: MAX_BLOCKS_PER_RETRIEVAL,
+        }
+    }
+
+    /// Retrieves the chain of blocks starting at `block_id` backwards, until `num_blocks` blocks
+    /// are retrieved or the parent of the last one is known according to `is_known`. The blocks
+    /// are returned from the newest to the oldest.
     ///
-    /// The first attempt of block retrieval will always be sent to preferred_peer to allow the
-    /// leader to drive quorum certificate creation The other peers from the quorum certificate
-    /// will be randomly tried next.  If all members of the quorum certificate are exhausted, an
-    /// error is returned
-    pub async fn retrieve_block_for_qc<'a, T>(
-        &'a mut self,
-        qc: &'a QuorumCert,
+    /// This method continues until either the round deadline is reached or all the peers fail
+    /// to return the missing chain, in which case an error is returned.
+    async fn retrieve_chain<T, F>(
+        &mut self,
+        block_id: HashValue,
         num_blocks: u64,
+        is_known: F,
     ) -> failure::Result<Vec<Block<T>>>
     where
         T: Payload,
+        F: Fn(HashValue) -> bool,
     {
-        let block_id = qc.certified_block_id();
-        let mut peers: Vec<&AccountAddress> = qc.ledger_info().signatures().keys().collect();
-        let mut attempt = 0_u32;
+        let mut blocks = vec![];
+        let mut next_id = block_id;
+        while (blocks.len() as u64) < num_blocks && !is_known(next_id) {
+            let range_len = self
+                .max_blocks_per_request
+                .min(num_blocks - blocks.len() as u64);
+            // The blocks of a range form a chain starting at the requested block.
+            for block in self.retrieve_range::<T>(next_id, range_len).await? {
+                if is_known(next_id) || blocks.len() as u64 == num_blocks {
+                    break;
+                }
+                next_id = block.parent_id();
+                blocks.push(block);
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Retrieves the chain of up to `num_blocks` blocks starting at `block_id` backwards: all of
+    /// them unless no peer could return more than some of the first ones.
+    async fn retrieve_range<T: Payload>(
+        &mut self,
+        block_id: HashValue,
+        num_blocks: u64,
+    ) -> failure::Result<Vec<Block<T>>> {
+        let mut attempt = 0;
         loop {
-            if peers.is_empty() {
+            if self.peers.is_empty() {
                 bail!(
                     "Failed to fetch block {} in {} attempts: no more peers available",
                     block_id,
                     attempt
                 );
             }
-            let peer = self.pick_peer(attempt, &mut peers);
             attempt += 1;
-
-            let timeout = retrieval_timeout(&self.deadline, attempt);
-            let timeout = if let Some(timeout) = timeout {
-                timeout
-            } else {
-                bail!("Failed to fetch block {} from {}, attempt {}: round deadline was reached, won't make more attempts", block_id, peer, attempt);
+            let timeout = match retrieval_timeout(&self.deadline, attempt) {
+                Some(timeout) => timeout,
+                None => bail!(
+                    "Failed to fetch block {}, attempt {}: round deadline was reached, won't make more attempts",
+                    block_id,
+                    attempt
+                ),
             };
+            let num_peers = self.fanout.min(self.peers.len());
+            let mut waiting: Vec<Author> = self.peers.drain(..num_peers).collect();
             debug!(
-                "Fetching {} from {}, attempt {}",
-                block_id,
-                peer.short_str(),
-                attempt
+                "Fetching {} blocks from {} from {:?}, attempt {}",
+                num_blocks, block_id, waiting, attempt
             );
-            let response = self
-                .network
-                .request_block(block_id, num_blocks, peer, timeout)
-                .await;
-            let response = match response {
-                Err(e) => {
-                    warn!(
+            let mut requests: FuturesUnordered<_> = waiting
+                .iter()
+                .map(|peer| {
+                    let peer = *peer;
+                    let mut network = self.network.clone();
+                    async move {
+                        let response = network
+                            .request_block::<T>(block_id, num_blocks, peer, timeout)
+                            .await;
+                        (peer, response)
+                    }
+                })
+                .collect();
+            let mut responsive = vec![];
+            let mut timed_out = vec![];
+            let mut longest_partial: Vec<Block<T>> = vec![];
+            while let Some((peer, response)) = requests.next().await {
+                waiting.retain(|waiting_peer| *waiting_peer != peer);
+                let response = match response {
+                    Ok(response) => response,
+                    Err(e) => {
+                        warn!(
+                            "Failed to fetch block {} from {}: {:?}, trying another peer",
+                            block_id,
+                            peer.short_str(),
+                            e
+                        );
+                        if let Some(RpcError::TimedOut) = e.downcast_ref::<RpcError>() {
+                            timed_out.push(peer);
+                        }
+                        continue;
+                    }
+                };
+                match response.status {
+                    BlockRetrievalStatus::Succeeded => {
+                        // The peers which didn't respond yet get another chance with the next
+                        // ranges.
+                        self.peers.extend(responsive);
+                        self.peers.extend(waiting);
+                        self.peers.push_back(peer);
+                        self.peers.extend(timed_out);
+                        return Ok(response.blocks);
+                    }
+                    BlockRetrievalStatus::NotEnoughBlocks if !response.blocks.is_empty() => {
+                        debug!(
+                            "Fetched {} out of {} blocks from {} from {}",
+                            response.blocks.len(),
+                            num_blocks,
+                            block_id,
+                            peer.short_str(),
+                        );
+                        responsive.push(peer);
+                        if response.blocks.len() > longest_partial.len() {
+                            longest_partial = response.blocks;
+                        }
+                    }
+                    status => warn!(
                         "Failed to fetch block {} from {}: {:?}, trying another peer",
                         block_id,
                         peer.short_str(),
-                        e
-                    );
-                    continue;
+                        status
+                    ),
                 }
-                Ok(response) => response,
-            };
-            if response.status != BlockRetrievalStatus::Succeeded {
-                warn!(
-                    "Failed to fetch block {} from {}: {:?}, trying another peer",
-                    block_id,
-                    peer.short_str(),
-                    response.status
-                );
-                continue;
             }
-            return Ok(response.blocks);
-        }
-    }
-
-    fn pick_peer(&self, attempt: u32, peers: &mut Vec<&AccountAddress>) -> AccountAddress {
-        assert!(!peers.is_empty(), "pick_peer on empty peer list");
-
-        if attempt == 0 {
-            // remove preferred_peer if its in list of peers
-            // (strictly speaking it is not required to be there)
-            for i in 0..peers.len() {
-                if *peers[i] == self.preferred_peer {
-                    peers.remove(i);
-                    break;
-                }
+            // The peers which returned some of the blocks come last, so that the rest of the
+            // range is requested from the others first, and the ones which timed out after them.
+            self.peers.extend(responsive);
+            self.peers.extend(timed_out);
+            if !longest_partial.is_empty() {
+                return Ok(longest_partial);
             }
-            return self.preferred_peer;
         }
-
-        let peer_idx = thread_rng().gen_range(0, peers.len());
-        *peers.remove(peer_idx)
     }
 }
 
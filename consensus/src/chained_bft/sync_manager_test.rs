@@ -0,0 +1,212 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::chained_bft::{
+    common::Author,
+    consensus_types::{block::Block, quorum_cert::QuorumCert},
+    epoch_manager::EpochManager,
+    network::{BlockRetrievalResponse, ConsensusNetworkImpl},
+    network_tests::NetworkPlayground,
+    sync_manager::BlockRetrievalManager,
+    test_utils::{consensus_runtime, placeholder_certificate_for_block},
+};
+use channel;
+use crypto::HashValue;
+use futures::{executor::block_on, FutureExt, StreamExt, TryFutureExt};
+use network::{
+    proto::BlockRetrievalStatus,
+    validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
+use tokio::runtime::Runtime;
+use types::crypto_proxies::{random_validator_verifier, ValidatorSigner};
+
+fn certificate_for(block: &Block<u64>, signers: Vec<&ValidatorSigner>) -> QuorumCert {
+    placeholder_certificate_for_block(
+        signers,
+        block.id(),
+        block.round(),
+        block.quorum_cert().certified_block_id(),
+        block.quorum_cert().certified_block_round(),
+        block.quorum_cert().parent_block_id(),
+        block.quorum_cert().parent_block_round(),
+    )
+}
+
+/// Builds a chain of `len` blocks on top of genesis, returned from the oldest to the newest.
+fn make_chain(len: u64, signer: &ValidatorSigner) -> Vec<Block<u64>> {
+    let mut chain = vec![Block::make_genesis_block()];
+    for round in 1..=len {
+        let parent = chain.last().unwrap();
+        let block = Block::make_block(
+            parent,
+            round,
+            round,
+            round,
+            certificate_for(parent, vec![signer]),
+            signer,
+        );
+        chain.push(block);
+    }
+    chain.remove(0);
+    chain
+}
+
+/// Starts a node for each signer, the first one being the retriever and the others serving the
+/// blocks of their store, as `EventProcessor::process_block_retrieval` does.
+fn start_nodes(
+    runtime: &Runtime,
+    playground: &mut NetworkPlayground,
+    signers: &[ValidatorSigner],
+    stores: Vec<HashMap<HashValue, Block<u64>>>,
+    epoch_mgr: Arc<EpochManager>,
+) -> ConsensusNetworkImpl {
+    let mut retriever = None;
+    let mut stores = stores.into_iter();
+    for signer in signers {
+        let (network_reqs_tx, network_reqs_rx) = channel::new_test(8);
+        let (consensus_tx, consensus_rx) = channel::new_test(8);
+        let network_sender = ConsensusNetworkSender::new(network_reqs_tx);
+        let network_events = ConsensusNetworkEvents::new(consensus_rx);
+        playground.add_node(signer.author(), consensus_tx, network_reqs_rx);
+        let mut node = ConsensusNetworkImpl::new(
+            signer.author(),
+            network_sender,
+            network_events,
+            Arc::clone(&epoch_mgr),
+        );
+        let receivers = node.start::<u64>(&TaskManager::new("consensus", runtime.executor()));
+        if retriever.is_none() {
+            retriever = Some(node);
+            continue;
+        }
+        let store = stores.next().unwrap();
+        let mut block_retrieval = receivers.block_retrieval;
+        let on_request_block = async move {
+            while let Some(request) = block_retrieval.next().await {
+                let mut blocks = vec![];
+                let mut id = request.block_id;
+                while (blocks.len() as u64) < request.num_blocks {
+                    match store.get(&id) {
+                        Some(block) => {
+                            id = block.parent_id();
+                            blocks.push(block.clone());
+                        }
+                        None => break,
+                    }
+                }
+                let status = if blocks.is_empty() {
+                    BlockRetrievalStatus::IdNotFound
+                } else if (blocks.len() as u64) < request.num_blocks {
+                    BlockRetrievalStatus::NotEnoughBlocks
+                } else {
+                    BlockRetrievalStatus::Succeeded
+                };
+                request
+                    .response_sender
+                    .send(BlockRetrievalResponse { status, blocks })
+                    .unwrap();
+            }
+        };
+        runtime
+            .executor()
+            .spawn(on_request_block.boxed().unit_error().compat());
+    }
+    retriever.unwrap()
+}
+
+fn store_of(blocks: &[Block<u64>]) -> HashMap<HashValue, Block<u64>> {
+    blocks
+        .iter()
+        .map(|block| (block.id(), block.clone()))
+        .collect()
+}
+
+#[test]
+/// The chain is retrieved in ranges from the peers which have it, although one of them doesn't
+/// know any block and another one only has the most recent ones.
+fn test_retrieve_chain_from_multiple_peers() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let (signers, validator_verifier) = random_validator_verifier(4, Some(1), false);
+    let epoch_mgr = Arc::new(EpochManager::new(0, validator_verifier));
+    let chain = make_chain(7, &signers[1]);
+    let stores = vec![HashMap::new(), store_of(&chain[4..]), store_of(&chain)];
+    let network = start_nodes(&runtime, &mut playground, &signers, stores, epoch_mgr);
+
+    let last = chain.last().unwrap();
+    let qc = certificate_for(last, signers[1..].iter().collect());
+    let peers: Vec<Author> = signers[1..].iter().map(|signer| signer.author()).collect();
+    for preferred_peer in peers {
+        let mut retriever = BlockRetrievalManager::new(
+            network.clone(),
+            &qc,
+            preferred_peer,
+            Instant::now() + Duration::from_secs(10),
+        );
+        retriever.max_blocks_per_request = 2;
+        let blocks: Vec<Block<u64>> =
+            block_on(retriever.retrieve_chain(last.id(), 7, |_| false)).unwrap();
+        let expected: Vec<Block<u64>> = chain.iter().rev().cloned().collect();
+        assert_eq!(blocks, expected);
+    }
+}
+
+#[test]
+/// The retrieval stops at the first block already known.
+fn test_retrieve_chain_until_known_block() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let (signers, validator_verifier) = random_validator_verifier(2, Some(1), false);
+    let epoch_mgr = Arc::new(EpochManager::new(0, validator_verifier));
+    let chain = make_chain(5, &signers[1]);
+    let network = start_nodes(
+        &runtime,
+        &mut playground,
+        &signers,
+        vec![store_of(&chain)],
+        epoch_mgr,
+    );
+
+    let last = chain.last().unwrap();
+    let qc = certificate_for(last, vec![&signers[1]]);
+    let mut retriever = BlockRetrievalManager::new(
+        network,
+        &qc,
+        signers[1].author(),
+        Instant::now() + Duration::from_secs(10),
+    );
+    retriever.max_blocks_per_request = 2;
+    let known = chain[1].id();
+    let blocks: Vec<Block<u64>> =
+        block_on(retriever.retrieve_chain(last.id(), 5, |id| id == known)).unwrap();
+    let expected: Vec<Block<u64>> = chain[2..].iter().rev().cloned().collect();
+    assert_eq!(blocks, expected);
+}
+
+#[test]
+/// The retrieval fails once no peer has the missing blocks.
+fn test_retrieve_chain_no_more_peers() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let (signers, validator_verifier) = random_validator_verifier(3, Some(1), false);
+    let epoch_mgr = Arc::new(EpochManager::new(0, validator_verifier));
+    let chain = make_chain(3, &signers[1]);
+    let stores = vec![store_of(&chain[2..]), HashMap::new()];
+    let network = start_nodes(&runtime, &mut playground, &signers, stores, epoch_mgr);
+
+    let last = chain.last().unwrap();
+    let qc = certificate_for(last, signers[1..].iter().collect());
+    let mut retriever = BlockRetrievalManager::new(
+        network,
+        &qc,
+        signers[1].author(),
+        Instant::now() + Duration::from_secs(10),
+    );
+    assert!(block_on(retriever.retrieve_chain::<u64, _>(last.id(), 3, |_| false)).is_err());
+}
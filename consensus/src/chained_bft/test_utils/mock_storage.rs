@@ -4,7 +4,11 @@
 use crate::chained_bft::{
     common::Payload,
     consensus_types::{block::Block, quorum_cert::QuorumCert},
-    liveness::pacemaker_timeout_manager::HighestTimeoutCertificates,
+    epoch_manager::EpochInfo,
+    liveness::{
+        pacemaker_timeout_manager::HighestTimeoutCertificates,
+        reputation_proposer_election::ProposerFailures,
+    },
     persistent_storage::{PersistentLivenessStorage, PersistentStorage, RecoveryData},
     safety::safety_rules::ConsensusState,
 };
@@ -22,9 +26,12 @@ pub struct MockSharedStorage<T> {
     pub block: Mutex<HashMap<HashValue, Block<T>>>,
     pub qc: Mutex<HashMap<HashValue, QuorumCert>>,
     pub state: Mutex<ConsensusState>,
+    pub pending_commit: Mutex<Option<LedgerInfo>>,
+    pub epoch_info: Mutex<Option<EpochInfo>>,
 
     // Liveness state
     pub highest_timeout_certificates: Mutex<HighestTimeoutCertificates>,
+    pub proposer_failures: Mutex<ProposerFailures>,
 }
 
 /// A storage that simulates the operations in-memory, used in the tests that cares about storage
@@ -77,6 +84,13 @@ impl<T: Payload> MockStorage<T> {
                 .lock()
                 .unwrap()
                 .clone(),
+            self.shared_storage
+                .proposer_failures
+                .lock()
+                .unwrap()
+                .clone(),
+            self.shared_storage.pending_commit.lock().unwrap().clone(),
+            self.shared_storage.epoch_info.lock().unwrap().clone(),
         )
     }
 
@@ -109,6 +123,11 @@ impl<T: Payload> PersistentLivenessStorage for MockStorage<T> {
             .unwrap() = highest_timeout_certificates;
         Ok(())
     }
+
+    fn save_proposer_failures(&self, proposer_failures: ProposerFailures) -> Result<()> {
+        *self.shared_storage.proposer_failures.lock().unwrap() = proposer_failures;
+        Ok(())
+    }
 }
 
 // A impl that always start from genesis.
@@ -154,12 +173,30 @@ impl<T: Payload> PersistentStorage<T> for MockStorage<T> {
         Ok(())
     }
 
+    fn save_pending_commit(&self, ledger_info: LedgerInfo) -> Result<()> {
+        *self.shared_storage.pending_commit.lock().unwrap() = Some(ledger_info);
+        Ok(())
+    }
+
+    fn clear_pending_commit(&self) -> Result<()> {
+        *self.shared_storage.pending_commit.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn save_epoch(&self, epoch_info: EpochInfo) -> Result<()> {
+        *self.shared_storage.epoch_info.lock().unwrap() = Some(epoch_info);
+        Ok(())
+    }
+
     fn start(_config: &NodeConfig) -> (Arc<Self>, RecoveryData<T>) {
         let shared_storage = Arc::new(MockSharedStorage {
             block: Mutex::new(HashMap::new()),
             qc: Mutex::new(HashMap::new()),
             state: Mutex::new(ConsensusState::default()),
+            pending_commit: Mutex::new(None),
+            epoch_info: Mutex::new(None),
             highest_timeout_certificates: Mutex::new(HighestTimeoutCertificates::new(None, None)),
+            proposer_failures: Mutex::new(ProposerFailures::default()),
         });
         let storage = MockStorage::new(Arc::clone(&shared_storage));
 
@@ -190,6 +227,10 @@ impl PersistentLivenessStorage for EmptyStorage {
     fn save_highest_timeout_cert(&self, _: HighestTimeoutCertificates) -> Result<()> {
         Ok(())
     }
+
+    fn save_proposer_failures(&self, _: ProposerFailures) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl<T: Payload> PersistentStorage<T> for EmptyStorage {
@@ -209,6 +250,18 @@ impl<T: Payload> PersistentStorage<T> for EmptyStorage {
         Ok(())
     }
 
+    fn save_pending_commit(&self, _: LedgerInfo) -> Result<()> {
+        Ok(())
+    }
+
+    fn clear_pending_commit(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn save_epoch(&self, _: EpochInfo) -> Result<()> {
+        Ok(())
+    }
+
     fn start(_: &NodeConfig) -> (Arc<Self>, RecoveryData<T>) {
         let genesis = Block::make_genesis_block();
         let genesis_qc = QuorumCert::certificate_for_genesis();
@@ -221,6 +274,9 @@ impl<T: Payload> PersistentStorage<T> for EmptyStorage {
                 vec![genesis_qc.clone()],
                 genesis_qc.ledger_info().ledger_info(),
                 htc,
+                ProposerFailures::default(),
+                None,
+                None,
             )
             .unwrap(),
         )
@@ -47,7 +47,8 @@ pub fn build_empty_tree_with_custom_signing(
         my_signer,
         Arc::new(EmptyStateComputer),
         true,
-        10, // max pruned blocks in mem
+        10,  // max pruned blocks in mem
+        100, // pruned blocks retention rounds
     )))
 }
 
@@ -0,0 +1,271 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Twins testing: a validator is run as several instances, called twins, which share its keys
+//! but not their state. The twins behave like an equivocating validator, without having to write
+//! any byzantine behavior, and the network playground splits the instances into partitions to
+//! drive them into conflicting decisions. Whatever the schedule of the partitions, the honest
+//! validators must never commit conflicting blocks as long as at most f validators have twins.
+
+use crate::{
+    chained_bft::{
+        chained_bft_smr::{ChainedBftSMR, ChainedBftSMRConfig},
+        common::{Author, Round},
+        epoch_manager::EpochManager,
+        network::ConsensusNetworkImpl,
+        network_tests::{NetworkPlayground, NodeId},
+        test_utils::{
+            consensus_runtime, with_smr_id, MockStateComputer, MockStorage, MockTransactionManager,
+            TestPayload,
+        },
+    },
+    state_replication::StateMachineReplication,
+};
+use channel;
+use config::config::ConsensusProposerType;
+use crypto::HashValue;
+use futures::{channel::mpsc, executor::block_on};
+use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::runtime;
+use types::crypto_proxies::{
+    random_validator_verifier, LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier,
+};
+
+/// An instance of a validator: twins share the signer of their validator, but each of them has
+/// its own storage and runs its own consensus.
+struct TwinNode {
+    id: NodeId,
+    author: Author,
+    // Kept to run the consensus of the instance until the end of the test
+    _smr: ChainedBftSMR<TestPayload>,
+    commit_cb_receiver: mpsc::UnboundedReceiver<LedgerInfoWithSignatures>,
+    storage: Arc<MockStorage<TestPayload>>,
+}
+
+impl TwinNode {
+    fn start(
+        playground: &mut NetworkPlayground,
+        signer: ValidatorSigner,
+        validator_verifier: ValidatorVerifier,
+        proposers: Vec<Author>,
+    ) -> Self {
+        let author = signer.author();
+        let (network_reqs_tx, network_reqs_rx) = channel::new_test(8);
+        let (consensus_tx, consensus_rx) = channel::new_test(8);
+        let network_sender = ConsensusNetworkSender::new(network_reqs_tx);
+        let network_events = ConsensusNetworkEvents::new(consensus_rx);
+        let id = playground.add_node(author, consensus_tx, network_reqs_rx);
+
+        let runtime = runtime::Builder::new()
+            .after_start(with_smr_id(format!("{}#{}", author.short_str(), id)))
+            .build()
+            .expect("Failed to create Tokio runtime!");
+        let epoch_mgr = Arc::new(EpochManager::new(0, validator_verifier));
+        let network = ConsensusNetworkImpl::new(
+            author,
+            network_sender,
+            network_events,
+            Arc::clone(&epoch_mgr),
+        );
+        let config = ChainedBftSMRConfig {
+            max_pruned_blocks_in_mem: 10000,
+            // the safety check walks the committed blocks back from the storage
+            pruned_blocks_retention_rounds: 10000,
+            pacemaker_initial_timeout: Duration::from_millis(500),
+            adaptive_timeout: None,
+            proposer_type: ConsensusProposerType::RotatingProposer,
+            contiguous_rounds: 1,
+            proposer_reputation_window: 10,
+            max_block_size: 50,
+        };
+        let (storage, initial_data) = MockStorage::start_for_testing();
+        let mut smr = ChainedBftSMR::new(
+            author,
+            signer,
+            proposers,
+            network,
+            runtime,
+            config,
+            storage.clone(),
+            initial_data,
+            epoch_mgr,
+        );
+        let (commit_cb_sender, commit_cb_receiver) = mpsc::unbounded::<LedgerInfoWithSignatures>();
+        smr.start(
+            Arc::new(MockTransactionManager::new()),
+            Arc::new(MockStateComputer::new(
+                commit_cb_sender,
+                Arc::clone(&storage),
+            )),
+        )
+        .expect("Failed to start SMR!");
+        Self {
+            id,
+            author,
+            _smr: smr,
+            commit_cb_receiver,
+            storage,
+        }
+    }
+
+    /// Starts `num_nodes` validators, the first `num_twins` of them with two instances each.
+    /// The instances of a validator come one after the other.
+    fn start_num_nodes(
+        num_nodes: usize,
+        num_twins: usize,
+        playground: &mut NetworkPlayground,
+    ) -> Vec<Self> {
+        assert!(num_twins <= num_nodes);
+        let (signers, validator_verifier) = random_validator_verifier(num_nodes, None, true);
+        let proposers = validator_verifier.get_ordered_account_addresses();
+        let mut nodes = vec![];
+        for (idx, signer) in signers.into_iter().enumerate() {
+            let num_instances = if idx < num_twins { 2 } else { 1 };
+            for _ in 0..num_instances {
+                nodes.push(Self::start(
+                    playground,
+                    signer.clone(),
+                    validator_verifier.clone(),
+                    proposers.clone(),
+                ));
+            }
+        }
+        nodes
+    }
+}
+
+/// The partitions the network is split into, and the number of messages delivered within them.
+type Phase = (Vec<Vec<NodeId>>, usize);
+
+/// Delivers the messages of each phase in turn, then heals the network and delivers
+/// `num_final_messages` more.
+fn run_phases(playground: &mut NetworkPlayground, phases: Vec<Phase>, num_final_messages: usize) {
+    block_on(async move {
+        for (partitions, num_messages) in phases {
+            playground.split_network(partitions);
+            playground
+                .wait_for_messages(num_messages, NetworkPlayground::take_all)
+                .await;
+        }
+        playground.heal_network();
+        playground
+            .wait_for_messages(num_final_messages, NetworkPlayground::take_all)
+            .await;
+    });
+}
+
+/// Generates `num_phases` phases, each splitting the nodes into two random partitions or
+/// keeping them all together, for a random number of messages.
+fn random_phases(rng: &mut StdRng, nodes: &[TwinNode], num_phases: usize) -> Vec<Phase> {
+    let mut ids: Vec<NodeId> = nodes.iter().map(|node| node.id).collect();
+    (0..num_phases)
+        .map(|_| {
+            ids.shuffle(rng);
+            let split = rng.gen_range(0, ids.len());
+            let partitions = if split == 0 {
+                vec![ids.clone()]
+            } else {
+                vec![ids[..split].to_vec(), ids[split..].to_vec()]
+            };
+            (partitions, rng.gen_range(10, 40))
+        })
+        .collect()
+}
+
+/// Asserts that the blocks committed by all the instances lie on a single chain, i.e. that no
+/// two instances committed conflicting blocks, and returns the number of commits per instance.
+fn assert_no_conflicting_commits(nodes: &mut [TwinNode]) -> HashMap<NodeId, usize> {
+    let mut parents: HashMap<HashValue, (HashValue, Round)> = HashMap::new();
+    let mut commits: Vec<(NodeId, HashValue)> = vec![];
+    let mut num_commits = HashMap::new();
+    for node in nodes.iter_mut() {
+        for (id, block) in node.storage.shared_storage.block.lock().unwrap().iter() {
+            parents.insert(*id, (block.parent_id(), block.round()));
+        }
+        let mut count = 0;
+        while let Ok(Some(commit)) = node.commit_cb_receiver.try_next() {
+            commits.push((node.id, commit.ledger_info().consensus_block_id()));
+            count += 1;
+        }
+        num_commits.insert(node.id, count);
+    }
+    let round_of = |id: &HashValue| -> Round {
+        parents
+            .get(id)
+            .unwrap_or_else(|| panic!("Committed block {} is unknown", id))
+            .1
+    };
+    let highest = match commits.iter().max_by_key(|(_, id)| round_of(id)) {
+        Some((_, id)) => *id,
+        None => return num_commits,
+    };
+    let mut chain = HashSet::new();
+    let mut id = highest;
+    while let Some((parent_id, _)) = parents.get(&id) {
+        chain.insert(id);
+        id = *parent_id;
+    }
+    for (node_id, id) in &commits {
+        assert!(
+            chain.contains(id),
+            "Node {} committed block {} at round {}, which conflicts with block {} at round {}",
+            node_id,
+            id,
+            round_of(id),
+            highest,
+            round_of(&highest),
+        );
+    }
+    num_commits
+}
+
+#[test]
+/// A validator and its twin in the same partition as all the other validators: the twins
+/// equivocate as soon as they propose, and the other validators keep on committing safely.
+fn twins_without_partition_test() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let mut nodes = TwinNode::start_num_nodes(4, 1, &mut playground);
+
+    run_phases(&mut playground, vec![], 100);
+    assert_no_conflicting_commits(&mut nodes);
+}
+
+#[test]
+/// The twins of a validator are split between two partitions, one of them together with a
+/// quorum of the other validators: only this partition commits, then the network is healed.
+fn twins_split_between_partitions_test() {
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.executor());
+    let mut nodes = TwinNode::start_num_nodes(4, 1, &mut playground);
+    assert_eq!(nodes[0].author, nodes[1].author);
+
+    // Nodes 0 and 1 are the twins, nodes 2, 3 and 4 the other validators.
+    let phases = vec![(vec![vec![0, 2, 3], vec![1, 4]], 100)];
+    run_phases(&mut playground, phases, 50);
+    let num_commits = assert_no_conflicting_commits(&mut nodes);
+    assert!(num_commits[&2] > 0);
+    assert!(num_commits[&3] > 0);
+}
+
+#[test]
+/// Randomized schedules of partitions of the instances, with one validator out of four having
+/// a twin: the commits never conflict.
+fn twins_random_partitions_test() {
+    for seed in 0..3 {
+        let runtime = consensus_runtime();
+        let mut playground = NetworkPlayground::new(runtime.executor());
+        let mut nodes = TwinNode::start_num_nodes(4, 1, &mut playground);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let phases = random_phases(&mut rng, &nodes, 5);
+        run_phases(&mut playground, phases, 50);
+        assert_no_conflicting_commits(&mut nodes);
+    }
+}
@@ -6,12 +6,15 @@ use failure::prelude::*;
 use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
 
 use crate::chained_bft::chained_bft_consensus_provider::ChainedBftProvider;
+use disk_monitor::DiskMonitor;
 use executor::Executor;
+use grpc_helpers::connect_internal;
 use grpcio::{ChannelBuilder, EnvBuilder};
 use mempool::proto::mempool::MempoolClient;
 use state_synchronizer::StateSyncClient;
 use std::sync::Arc;
 use storage_client::{StorageRead, StorageReadServiceClient};
+use trusted_ledger::TrustedLedger;
 use vm_runtime::MoveVM;
 
 /// Public interface to a consensus protocol.
@@ -34,6 +37,8 @@ pub fn make_consensus_provider(
     network_receiver: ConsensusNetworkEvents,
     executor: Arc<Executor<MoveVM>>,
     state_sync_client: Arc<StateSyncClient>,
+    trusted_ledger: TrustedLedger,
+    disk_monitor: DiskMonitor,
 ) -> Box<dyn ConsensusProvider> {
     Box::new(ChainedBftProvider::new(
         node_config,
@@ -42,6 +47,8 @@ pub fn make_consensus_provider(
         create_mempool_client(node_config),
         executor,
         state_sync_client,
+        trusted_ledger,
+        disk_monitor,
     ))
 }
 /// Create a mempool client assuming the mempool is running on localhost
@@ -50,9 +57,11 @@ fn create_mempool_client(config: &NodeConfig) -> Arc<MempoolClient> {
     let connection_str = format!("localhost:{}", port);
 
     let env = Arc::new(EnvBuilder::new().name_prefix("grpc-con-mem-").build());
-    Arc::new(MempoolClient::new(
-        ChannelBuilder::new(env).connect(&connection_str),
-    ))
+    Arc::new(MempoolClient::new(connect_internal(
+        ChannelBuilder::new(env),
+        &connection_str,
+        "consensus-mempool",
+    )))
 }
 
 /// Create a storage read client based on the config
@@ -38,9 +38,17 @@ pub static ref COMMITTED_TXNS_COUNT: IntCounter = OP_COUNTERS.counter("committed
 pub static ref SUCCESS_TXNS_COUNT: IntCounter = OP_COUNTERS.counter("success_txns_count");
 
 /// Count of failed txns in the committed blocks since last restart.
-/// FAILED_TXNS_COUNT + SUCCESS_TXN_COUNT == COMMITTED_TXNS_COUNT
+/// FAILED_TXNS_COUNT + SUCCESS_TXN_COUNT + RETRIED_TXNS_COUNT == COMMITTED_TXNS_COUNT
 pub static ref FAILED_TXNS_COUNT: IntCounter = OP_COUNTERS.counter("failed_txns_count");
 
+/// Count of txns in the committed blocks which were not executed as their block ran out of
+/// execution time, and are left to a later block, since last restart.
+pub static ref RETRIED_TXNS_COUNT: IntCounter = OP_COUNTERS.counter("retried_txns_count");
+
+/// Count of txns pulled from mempool which were dropped from a proposal as they were already in
+/// the pending blocks it extends (or pulled twice), since last restart.
+pub static ref DUPLICATE_PULLED_TXNS_COUNT: IntCounter = OP_COUNTERS.counter("duplicate_pulled_txns_count");
+
 //////////////////////
 // PROPOSAL ELECTION
 //////////////////////
@@ -74,6 +82,9 @@ pub static ref TIMEOUT_COUNT: IntCounter = OP_COUNTERS.counter("timeout_count");
 /// The timeout of the current round.
 pub static ref ROUND_TIMEOUT_MS: IntGauge = OP_COUNTERS.gauge("round_timeout_ms");
 
+/// Histogram of the duration of the rounds that gathered QC before timing out.
+pub static ref QC_ROUND_DURATION_S: DurationHistogram = OP_COUNTERS.duration_histogram("qc_round_duration_s");
+
 ////////////////////////
 // SYNCMANAGER COUNTERS
 ////////////////////////
@@ -83,6 +94,10 @@ pub static ref STATE_SYNC_COUNT: IntCounter = OP_COUNTERS.counter("state_sync_co
 /// Count the number of block retrieval requests issued since last restart.
 pub static ref BLOCK_RETRIEVAL_COUNT: IntCounter = OP_COUNTERS.counter("block_retrieval_count");
 
+/// Count the number of block retrieval requests served by this node which were abandoned because
+/// their deadline had passed.
+pub static ref EXPIRED_BLOCK_RETRIEVAL_COUNT: IntCounter = OP_COUNTERS.counter("expired_block_retrieval_count");
+
 /// Histogram of block retrieval duration.
 pub static ref BLOCK_RETRIEVAL_DURATION_S: DurationHistogram = OP_COUNTERS.duration_histogram("block_retrieval_duration_s");
 
@@ -113,6 +128,16 @@ pub static ref CURRENT_EPOCH_QUORUM_SIZE: IntGauge = OP_COUNTERS.gauge("current_
 /// In a "happy path" with no collisions and timeouts, should be equal to 3 or 4.
 pub static ref NUM_BLOCKS_IN_TREE: IntGauge = OP_COUNTERS.gauge("num_blocks_in_tree");
 
+/// Counter for the number of pruned blocks kept in memory (and in ConsensusDB) until they fall out
+/// of the retention window.
+pub static ref NUM_PRUNED_BLOCKS_IN_MEM: IntGauge = OP_COUNTERS.gauge("num_pruned_blocks_in_mem");
+
+/// Count of the pruned blocks garbage-collected from the block store and ConsensusDB.
+pub static ref GARBAGE_COLLECTED_BLOCKS_COUNT: IntCounter = OP_COUNTERS.counter("garbage_collected_blocks_count");
+
+/// Approximate size (bytes) of the blocks, quorum certs and state stored in ConsensusDB.
+pub static ref CONSENSUSDB_SIZE_BYTES: IntGauge = OP_COUNTERS.gauge("consensusdb_size_bytes");
+
 //////////////////////
 // PERFORMANCE COUNTERS
 //////////////////////
@@ -23,6 +23,10 @@ mod util;
 #[cfg(feature = "fuzzing")]
 pub use chained_bft::event_processor_fuzzing;
 
+/// Test-only byzantine behaviors of a validator.
+#[cfg(feature = "byzantine")]
+pub use chained_bft::byzantine;
+
 /// Defines the public consensus provider traits to implement for
 /// use in the Libra Core blockchain.
 pub mod consensus_provider;
@@ -32,3 +36,5 @@ mod counters;
 mod state_computer;
 mod state_replication;
 mod txn_manager;
+#[cfg(test)]
+mod txn_manager_test;
@@ -3,9 +3,10 @@
 
 use crate::{chained_bft::QuorumCert, counters, state_replication::StateComputer};
 use crypto::HashValue;
+use disk_monitor::DiskMonitor;
 use executor::{Executor, StateComputeResult};
 use failure::Result;
-use futures::{Future, FutureExt};
+use futures::{compat::Future01CompatExt, Future, FutureExt};
 use logger::prelude::*;
 use state_synchronizer::StateSyncClient;
 use std::{
@@ -14,21 +15,37 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
+use tokio::timer::Delay;
+use trusted_ledger::TrustedLedger;
 use types::{crypto_proxies::LedgerInfoWithSignatures, transaction::SignedTransaction};
 use vm_runtime::MoveVM;
 
+/// How often a commit paused by the protective mode of the disk monitor checks whether the
+/// mode was left.
+const DISK_SPACE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Basic communication with the Execution module;
 /// implements StateComputer traits.
 pub struct ExecutionProxy {
     executor: Arc<Executor<MoveVM>>,
     synchronizer: Arc<StateSyncClient>,
+    trusted_ledger: TrustedLedger,
+    // commits are paused while it is in protective mode
+    disk_monitor: DiskMonitor,
 }
 
 impl ExecutionProxy {
-    pub fn new(executor: Arc<Executor<MoveVM>>, synchronizer: Arc<StateSyncClient>) -> Self {
+    pub fn new(
+        executor: Arc<Executor<MoveVM>>,
+        synchronizer: Arc<StateSyncClient>,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
+    ) -> Self {
         Self {
             executor,
             synchronizer,
+            trusted_ledger,
+            disk_monitor,
         }
     }
 }
@@ -85,15 +102,37 @@ impl StateComputer for ExecutionProxy {
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
         let version = commit.ledger_info().version();
         counters::LAST_COMMITTED_VERSION.set(version as i64);
+        logger::context::set_version(version);
+        counters::OP_COUNTERS.export_log_context();
 
-        let pre_commit_instant = Instant::now();
         let synchronizer = Arc::clone(&self.synchronizer);
-        let commit_future = self.executor.commit_block(commit);
+        let trusted_ledger = self.trusted_ledger.clone();
+        let executor = Arc::clone(&self.executor);
+        let disk_monitor = self.disk_monitor.clone();
         async move {
-            match commit_future.await {
+            // Do not write to storage while the disk is nearly full. Consensus waits for the
+            // commit, so the node stops making progress until space is freed.
+            if disk_monitor.is_protective() {
+                warn!(
+                    "Node is low on disk space, pausing the commit of version {}",
+                    version
+                );
+                while disk_monitor.is_protective() {
+                    if let Err(e) = Delay::new(Instant::now() + DISK_SPACE_POLL_INTERVAL)
+                        .compat()
+                        .await
+                    {
+                        error!("Failed to wait for disk space: {:?}", e);
+                    }
+                }
+                info!("Resuming the commit of version {}", version);
+            }
+            let pre_commit_instant = Instant::now();
+            match executor.commit_block(commit.clone()).await {
                 Ok(Ok(())) => {
                     counters::BLOCK_COMMIT_DURATION_S
                         .observe_duration(pre_commit_instant.elapsed());
+                    trusted_ledger.update(commit);
                     if let Err(e) = synchronizer.commit(version).await {
                         error!("failed to notify state synchronizer: {:?}", e);
                     }
@@ -22,6 +22,12 @@ pub trait TxnManager: Send + Sync {
         exclude_txns: Vec<&Self::Payload>,
     ) -> Pin<Box<dyn Future<Output = Result<Self::Payload>> + Send>>;
 
+    /// Checks that the payload of a proposal from another validator stays within the limits the
+    /// payloads of this node are pulled with, so that oversized blocks are not voted for.
+    fn check_payload(&self, _payload: &Self::Payload) -> Result<()> {
+        Ok(())
+    }
+
     /// Notifies TxnManager about the payload of the committed block including the state compute
     /// result, which includes the specifics of what transactions succeeded and failed.
     fn commit_txns<'a>(
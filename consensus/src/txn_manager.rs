@@ -3,25 +3,66 @@
 
 use crate::{counters, state_replication::TxnManager};
 use executor::StateComputeResult;
-use failure::Result;
+use failure::prelude::*;
 use futures::{compat::Future01CompatExt, future, Future, FutureExt};
 use logger::prelude::*;
 use mempool::proto::mempool::{
-    CommitTransactionsRequest, CommittedTransaction, GetBlockRequest, MempoolClient,
-    TransactionExclusion,
+    AccountSequenceNumber, CommitTransactionsRequest, CommittedTransaction, GetBlockRequest,
+    MempoolClient, TransactionExclusion,
 };
-use std::{convert::TryFrom, pin::Pin, sync::Arc};
-use types::transaction::{SignedTransaction, TransactionStatus};
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    pin::Pin,
+    sync::Arc,
+};
+use types::{
+    account_address::AccountAddress,
+    transaction::{SignedTransaction, TransactionStatus},
+};
+
+/// Index of the transactions of the pending blocks a proposal extends, by sender and sequence
+/// number: these are the transactions mempool still holds but which must not be proposed again.
+/// It is rebuilt on every pull out of the payloads to exclude, which the proposal generator
+/// gathers along the path from the root anyway, rather than kept across proposals.
+pub(crate) type PendingTxnIndex = HashSet<(AccountAddress, u64)>;
+
+/// Drops from `txns`, pulled from mempool, the pending transactions of `pending_txns` as well as
+/// the ones pulled twice, and counts them.
+pub(crate) fn drop_pending_txns(
+    txns: impl Iterator<Item = SignedTransaction>,
+    pending_txns: &mut PendingTxnIndex,
+) -> Vec<SignedTransaction> {
+    txns.filter(|txn| {
+        let is_new = pending_txns.insert((txn.sender(), txn.sequence_number()));
+        if !is_new {
+            counters::DUPLICATE_PULLED_TXNS_COUNT.inc();
+        }
+        is_new
+    })
+    .collect()
+}
 
 /// Proxy interface to mempool
 pub struct MempoolProxy {
     mempool: Arc<MempoolClient>,
+    // Max cumulative size in bytes of the signed transactions of a block, 0 for no limit
+    max_block_bytes: u64,
+    // Max cumulative max gas amount of the transactions of a block, 0 for no limit
+    max_block_gas: u64,
 }
 
 impl MempoolProxy {
-    pub fn new(mempool: Arc<MempoolClient>) -> Self {
+    pub fn new(
+        mempool: Arc<MempoolClient>,
+        max_block_bytes: Option<u64>,
+        max_block_gas: Option<u64>,
+    ) -> Self {
         Self {
             mempool: Arc::clone(&mempool),
+            max_block_bytes: max_block_bytes.unwrap_or(0),
+            max_block_gas: max_block_gas.unwrap_or(0),
         }
     }
 
@@ -32,6 +73,7 @@ impl MempoolProxy {
         timestamp_usecs: u64,
     ) -> CommitTransactionsRequest {
         let mut all_updates = Vec::new();
+        let mut sequence_numbers: HashMap<AccountAddress, u64> = HashMap::new();
         assert_eq!(txns.len(), compute_result.compute_status.len());
         for (txn, status) in txns.iter().zip(compute_result.compute_status.iter()) {
             let mut transaction = CommittedTransaction::default();
@@ -41,16 +83,32 @@ impl MempoolProxy {
                 TransactionStatus::Keep(_) => {
                     counters::SUCCESS_TXNS_COUNT.inc();
                     transaction.is_rejected = false;
+                    let sequence_number = sequence_numbers.entry(txn.sender()).or_insert(0);
+                    *sequence_number = max(*sequence_number, txn.sequence_number() + 1);
                 }
                 TransactionStatus::Discard(_) => {
                     counters::FAILED_TXNS_COUNT.inc();
                     transaction.is_rejected = true;
                 }
+                // The transaction stays in mempool, to be pulled into a later block.
+                TransactionStatus::Retry => {
+                    counters::RETRIED_TXNS_COUNT.inc();
+                    continue;
+                }
             };
             all_updates.push(transaction);
         }
         let mut req = CommitTransactionsRequest::default();
         req.transactions = all_updates;
+        req.account_sequence_numbers = sequence_numbers
+            .into_iter()
+            .map(|(address, sequence_number)| {
+                let mut account = AccountSequenceNumber::default();
+                account.address = address.as_ref().to_vec();
+                account.sequence_number = sequence_number;
+                account
+            })
+            .collect();
         req.block_timestamp_usecs = timestamp_usecs;
         req
     }
@@ -82,39 +140,52 @@ impl TxnManager for MempoolProxy {
         max_size: u64,
         exclude_payloads: Vec<&Self::Payload>,
     ) -> Pin<Box<dyn Future<Output = Result<Self::Payload>> + Send>> {
-        let mut exclude_txns = vec![];
-        for payload in exclude_payloads {
-            for signed_txn in payload {
+        // The same transaction may be carried by several pending blocks, e.g. when a block
+        // failed to execute it and left it to a later one: it is excluded only once.
+        let mut pending_txns: PendingTxnIndex = exclude_payloads
+            .into_iter()
+            .flatten()
+            .map(|signed_txn| (signed_txn.sender(), signed_txn.sequence_number()))
+            .collect();
+        let exclude_txns = pending_txns
+            .iter()
+            .map(|(sender, sequence_number)| {
                 let mut txn_meta = TransactionExclusion::default();
-                txn_meta.sender = signed_txn.sender().into();
-                txn_meta.sequence_number = signed_txn.sequence_number();
-                exclude_txns.push(txn_meta);
-            }
-        }
+                txn_meta.sender = sender.into();
+                txn_meta.sequence_number = *sequence_number;
+                txn_meta
+            })
+            .collect();
         let mut get_block_request = GetBlockRequest::default();
         get_block_request.max_block_size = max_size;
+        get_block_request.max_block_bytes = self.max_block_bytes;
+        get_block_request.max_block_gas = self.max_block_gas;
         get_block_request.transactions = exclude_txns;
         match self.mempool.get_block_async(&get_block_request) {
             Ok(receiver) => async move {
                 match receiver.compat().await {
-                    Ok(response) => Ok(response
-                        .block
-                        .unwrap_or_else(Default::default)
-                        .transactions
-                        .into_iter()
-                        .filter_map(|proto_txn| {
-                            match SignedTransaction::try_from(proto_txn.clone()) {
-                                Ok(t) => Some(t),
-                                Err(e) => {
-                                    security_log(SecurityEvent::InvalidTransactionConsensus)
-                                        .error(&e)
-                                        .data(&proto_txn)
-                                        .log();
-                                    None
+                    Ok(response) => {
+                        let txns = response
+                            .block
+                            .unwrap_or_else(Default::default)
+                            .transactions
+                            .into_iter()
+                            .filter_map(|proto_txn| {
+                                match SignedTransaction::try_from(proto_txn.clone()) {
+                                    Ok(t) => Some(t),
+                                    Err(e) => {
+                                        security_log(SecurityEvent::InvalidTransactionConsensus)
+                                            .error(&e)
+                                            .data(&proto_txn)
+                                            .log();
+                                        None
+                                    }
                                 }
-                            }
-                        })
-                        .collect()),
+                            });
+                        // Mempool may still return a pending transaction, e.g. one it re-admitted
+                        // after a GC: proposing it again would only waste space in the block.
+                        Ok(drop_pending_txns(txns, &mut pending_txns))
+                    }
                     Err(e) => Err(e.into()),
                 }
             }
@@ -123,6 +194,29 @@ impl TxnManager for MempoolProxy {
         }
     }
 
+    fn check_payload(&self, payload: &Self::Payload) -> Result<()> {
+        let bytes: u64 = payload
+            .iter()
+            .map(|txn| txn.signed_txn_bytes_len() as u64)
+            .sum();
+        ensure!(
+            self.max_block_bytes == 0 || bytes <= self.max_block_bytes,
+            "block of {} bytes, max {} bytes",
+            bytes,
+            self.max_block_bytes
+        );
+        let gas = payload
+            .iter()
+            .fold(0u64, |gas, txn| gas.saturating_add(txn.max_gas_amount()));
+        ensure!(
+            self.max_block_gas == 0 || gas <= self.max_block_gas,
+            "block of {} max gas, max {} gas",
+            gas,
+            self.max_block_gas
+        );
+        Ok(())
+    }
+
     fn commit_txns<'a>(
         &'a self,
         txns: &Self::Payload,
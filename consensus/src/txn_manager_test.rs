@@ -0,0 +1,39 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::txn_manager::{drop_pending_txns, PendingTxnIndex};
+use crypto::ed25519::compat;
+use types::{
+    account_address::AccountAddress, test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::SignedTransaction,
+};
+
+fn signed_txn(sender: AccountAddress, sequence_number: u64) -> SignedTransaction {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    get_test_signed_txn(sender, sequence_number, private_key, public_key, None)
+}
+
+#[test]
+fn test_drop_pending_txns() {
+    let sender = AccountAddress::random();
+    let pending = signed_txn(sender, 0);
+    let new = signed_txn(sender, 1);
+    let mut pending_txns: PendingTxnIndex = vec![(sender, 0)].into_iter().collect();
+
+    // Mempool returns the transaction of a pending block along with a new one, the latter twice.
+    let pulled = vec![pending, new.clone(), new.clone()];
+    assert_eq!(
+        drop_pending_txns(pulled.into_iter(), &mut pending_txns),
+        vec![new]
+    );
+    // the new transaction is pending from now on
+    assert_eq!(
+        pending_txns,
+        vec![(sender, 0), (sender, 1)]
+            .into_iter()
+            .collect::<PendingTxnIndex>()
+    );
+    assert!(
+        drop_pending_txns(vec![signed_txn(sender, 1)].into_iter(), &mut pending_txns).is_empty()
+    );
+}
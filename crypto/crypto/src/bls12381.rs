@@ -27,6 +27,11 @@
 //! **Note**: The above example generates a private key using a private function intended only for
 //! testing purposes. Production code should generate the key according to the spec [draft-irtf-cfrg-bls-signature-00](https://tools.ietf.org/id/draft-irtf-cfrg-bls-signature-00.html#keygen).
 //!
+//! Signatures of a same message can be aggregated into a single signature, see
+//! [`AggregatableSignature`], which shrinks the certificates of the votes of many validators to
+//! the size of one signature. The public keys of the signers must come with a proof of
+//! possession, see [`BLS12381PrivateKey::create_proof_of_possession`].
+//!
 //! This module is not currently used, but could be included in the future for improved
 //! performance in consensus.
 
@@ -36,8 +41,8 @@ use core::convert::TryFrom;
 use crypto_derive::{Deref, SilentDebug, SilentDisplay};
 use failure::prelude::*;
 use pairing::{
-    bls12_381::{Fr, FrRepr},
-    PrimeField,
+    bls12_381::{Fr, FrRepr, G1Compressed, G2Compressed, G1, G2},
+    CurveAffine, CurveProjective, EncodedPoint, PrimeField,
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
@@ -252,6 +257,69 @@ impl Signature for BLS12381Signature {
     }
 }
 
+impl AggregatableSignature for BLS12381Signature {
+    /// Aggregates the signatures by adding up their points of G2.
+    fn aggregate(signatures: &[BLS12381Signature]) -> Result<BLS12381Signature> {
+        ensure!(!signatures.is_empty(), "No signature to aggregate");
+        let mut aggregate = G2::zero();
+        for signature in signatures {
+            let mut compressed = G2Compressed::empty();
+            compressed.as_mut().copy_from_slice(&signature.0.to_bytes());
+            let point = compressed
+                .into_affine()
+                .map_err(|e| format_err!("Invalid signature point: {}", e))?;
+            aggregate.add_assign(&point.into_projective());
+        }
+        let mut bytes = [0u8; BLS12381_SIGNATURE_LENGTH];
+        bytes.copy_from_slice(aggregate.into_affine().into_compressed().as_ref());
+        let signature = threshold_crypto::Signature::from_bytes(&bytes)
+            .map_err(|e| format_err!("Invalid aggregated signature: {}", e))?;
+        Ok(BLS12381Signature(signature))
+    }
+
+    fn verify_aggregate(
+        &self,
+        message: &HashValue,
+        public_keys: &[&BLS12381PublicKey],
+    ) -> Result<()> {
+        let public_key = BLS12381PublicKey::aggregate(public_keys)?;
+        self.verify(message, &public_key)
+    }
+}
+
+impl BLS12381PrivateKey {
+    /// Signs the proof of possession of this private key, which must accompany its public key
+    /// wherever the key is registered to verify aggregated signatures.
+    pub fn create_proof_of_possession(&self) -> BLS12381Signature {
+        let public_key: BLS12381PublicKey = self.into();
+        self.sign_message(&proof_of_possession_message(&public_key.to_bytes()))
+    }
+}
+
+impl BLS12381PublicKey {
+    /// Aggregates public keys by adding up their points of G1. An aggregated signature verifies
+    /// against the aggregate of the public keys of its signers.
+    pub fn aggregate(public_keys: &[&BLS12381PublicKey]) -> Result<BLS12381PublicKey> {
+        ensure!(!public_keys.is_empty(), "No public key to aggregate");
+        let mut aggregate = G1::zero();
+        for public_key in public_keys {
+            let mut compressed = G1Compressed::empty();
+            compressed
+                .as_mut()
+                .copy_from_slice(&public_key.0.to_bytes());
+            let point = compressed
+                .into_affine()
+                .map_err(|e| format_err!("Invalid public key point: {}", e))?;
+            aggregate.add_assign(&point.into_projective());
+        }
+        let mut bytes = [0u8; BLS12381_PUBLIC_KEY_LENGTH];
+        bytes.copy_from_slice(aggregate.into_affine().into_compressed().as_ref());
+        let public_key = threshold_crypto::PublicKey::from_bytes(bytes)
+            .map_err(|e| format_err!("Invalid aggregated public key: {}", e))?;
+        Ok(BLS12381PublicKey(public_key))
+    }
+}
+
 impl TryFrom<&[u8]> for BLS12381Signature {
     type Error = CryptoMaterialError;
 
@@ -217,6 +217,44 @@ pub trait Signature:
     }
 }
 
+/// A type family for signature schemes whose signatures of a same message can be aggregated into
+/// a single signature, which verifies against the aggregate of the public keys of the signers.
+///
+/// Each public key must be registered along with a proof of possession of its private key, see
+/// [`verify_proof_of_possession`]: otherwise a rogue key, derived from the public keys of others,
+/// would let its owner forge an aggregated signature of all of them. Registering a key in a
+/// validator set does not prove possession by itself.
+///
+/// [`verify_proof_of_possession`]: AggregatableSignature::verify_proof_of_possession
+pub trait AggregatableSignature: Signature {
+    /// Aggregates signatures of a same message into a single signature.
+    fn aggregate(signatures: &[Self]) -> Result<Self>;
+
+    /// Checks that the aggregated signature is valid for `message` using the `public_keys` of all
+    /// the signers.
+    fn verify_aggregate(
+        &self,
+        message: &HashValue,
+        public_keys: &[&Self::VerifyingKeyMaterial],
+    ) -> Result<()>;
+
+    /// Checks that this signature proves the possession of the private key of `public_key`, i.e.
+    /// that it signs the [`proof_of_possession_message`] of `public_key`.
+    fn verify_proof_of_possession(&self, public_key: &Self::VerifyingKeyMaterial) -> Result<()> {
+        self.verify(
+            &proof_of_possession_message(&public_key.to_bytes()),
+            public_key,
+        )
+        .map_err(|e| format_err!("Invalid proof of possession: {}", e))
+    }
+}
+
+/// Returns the message a private key signs to prove its possession, given the bytes of its public
+/// key. The message is domain separated from the hashes of the messages signed otherwise.
+pub fn proof_of_possession_message(public_key_bytes: &[u8]) -> HashValue {
+    HashValue::from_sha3_256(&[b"PROOF_OF_POSSESSION::".as_ref(), public_key_bytes].concat())
+}
+
 /// A type family for schemes which know how to generate key material from
 /// a cryptographically-secure [`CryptoRng`][::rand::CryptoRng].
 pub trait Uniform {
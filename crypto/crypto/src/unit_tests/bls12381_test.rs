@@ -89,4 +89,52 @@ proptest! {
         let deserialized = deserialize::<BLS12381Signature>(&serialized).unwrap();
         prop_assert!(keypair.public_key.verify_signature(&hash, &deserialized).is_ok());
     }
+
+    #[test]
+    fn test_aggregate_and_verify(
+        hash in any::<HashValue>(),
+        other_hash in any::<HashValue>(),
+        keypairs in proptest::collection::vec(
+            uniform_keypair_strategy::<BLS12381PrivateKey, BLS12381PublicKey>(),
+            2..5,
+        )
+    ) {
+        prop_assume!(hash != other_hash);
+        let signatures: Vec<BLS12381Signature> = keypairs
+            .iter()
+            .map(|keypair| keypair.private_key.sign_message(&hash))
+            .collect();
+        let public_keys: Vec<&BLS12381PublicKey> =
+            keypairs.iter().map(|keypair| &keypair.public_key).collect();
+        let aggregate = BLS12381Signature::aggregate(&signatures).unwrap();
+        prop_assert_eq!(aggregate.to_bytes().len(), BLS12381_SIGNATURE_LENGTH);
+        prop_assert!(aggregate.verify_aggregate(&hash, &public_keys).is_ok());
+        // all the signers are needed, on the same message
+        prop_assert!(aggregate.verify_aggregate(&hash, &public_keys[1..]).is_err());
+        prop_assert!(aggregate.verify_aggregate(&other_hash, &public_keys).is_err());
+        let partial_aggregate = BLS12381Signature::aggregate(&signatures[1..]).unwrap();
+        prop_assert!(partial_aggregate.verify_aggregate(&hash, &public_keys[1..]).is_ok());
+        prop_assert!(partial_aggregate.verify_aggregate(&hash, &public_keys).is_err());
+    }
+
+    #[test]
+    fn test_proof_of_possession(
+        hash in any::<HashValue>(),
+        keypair in uniform_keypair_strategy::<BLS12381PrivateKey, BLS12381PublicKey>(),
+        other_keypair in uniform_keypair_strategy::<BLS12381PrivateKey, BLS12381PublicKey>(),
+    ) {
+        prop_assume!(keypair.public_key != other_keypair.public_key);
+        let proof = keypair.private_key.create_proof_of_possession();
+        prop_assert!(proof.verify_proof_of_possession(&keypair.public_key).is_ok());
+        prop_assert!(proof.verify_proof_of_possession(&other_keypair.public_key).is_err());
+        // a signature of another message does not prove possession
+        let signature = keypair.private_key.sign_message(&hash);
+        prop_assert!(signature.verify_proof_of_possession(&keypair.public_key).is_err());
+    }
+}
+
+#[test]
+fn test_aggregate_nothing() {
+    assert!(BLS12381Signature::aggregate(&[]).is_err());
+    assert!(BLS12381PublicKey::aggregate(&[]).is_err());
 }
@@ -0,0 +1,58 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reports the committed changes to the state of a set of accounts, e.g. the accounts operators
+//! watch through the debug interface of the node.
+
+use std::collections::HashSet;
+use types::{
+    account_address::AccountAddress,
+    account_state_blob::AccountStateBlob,
+    transaction::{TransactionToCommit, Version},
+};
+
+/// Change to the state of an observed account, made by the transaction at `version`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountChange {
+    pub address: AccountAddress,
+    pub version: Version,
+    pub blob: AccountStateBlob,
+}
+
+/// Observer of the changes the committed transactions make to the state of some accounts.
+pub trait AccountObserver: Send + Sync {
+    /// The accounts whose changes are reported, looked up once per commit.
+    fn observed_accounts(&self) -> HashSet<AccountAddress>;
+
+    /// Reports `changes`, once the transactions making them are committed.
+    fn on_committed(&self, changes: Vec<AccountChange>);
+}
+
+/// Changes to the accounts `observer` observes made by `txns_to_commit`, the first of which is at
+/// `first_version`, in the order of the transactions.
+pub(crate) fn observed_changes(
+    observer: &dyn AccountObserver,
+    first_version: Version,
+    txns_to_commit: &[TransactionToCommit],
+) -> Vec<AccountChange> {
+    let observed = observer.observed_accounts();
+    if observed.is_empty() {
+        return vec![];
+    }
+    txns_to_commit
+        .iter()
+        .enumerate()
+        .flat_map(|(i, txn_to_commit)| {
+            let observed = &observed;
+            txn_to_commit
+                .account_states()
+                .iter()
+                .filter(move |(address, _)| observed.contains(address))
+                .map(move |(address, blob)| AccountChange {
+                    address: *address,
+                    version: first_version + i as Version,
+                    blob: blob.clone(),
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,71 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::account_observer::{observed_changes, AccountChange, AccountObserver};
+use crypto::ed25519::compat;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+use types::{
+    account_address::AccountAddress, account_state_blob::AccountStateBlob,
+    test_helpers::transaction_test_helpers::get_test_signed_txn, transaction::TransactionToCommit,
+    vm_error::StatusCode,
+};
+
+#[derive(Default)]
+struct TestObserver {
+    observed: Mutex<HashSet<AccountAddress>>,
+}
+
+impl AccountObserver for TestObserver {
+    fn observed_accounts(&self) -> HashSet<AccountAddress> {
+        self.observed.lock().unwrap().clone()
+    }
+
+    fn on_committed(&self, _changes: Vec<AccountChange>) {}
+}
+
+fn txn_to_commit(account_states: &[(AccountAddress, AccountStateBlob)]) -> TransactionToCommit {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    TransactionToCommit::new(
+        get_test_signed_txn(AccountAddress::random(), 0, private_key, public_key, None),
+        account_states.iter().cloned().collect::<HashMap<_, _>>(),
+        vec![],
+        0,
+        StatusCode::EXECUTED,
+    )
+}
+
+#[test]
+fn test_observed_changes() {
+    let observer = TestObserver::default();
+    let observed = AccountAddress::random();
+    let other = AccountAddress::random();
+    let blob = |byte| AccountStateBlob::from(vec![byte]);
+    let txns_to_commit = vec![
+        txn_to_commit(&[(observed, blob(1)), (other, blob(2))]),
+        txn_to_commit(&[(other, blob(3))]),
+        txn_to_commit(&[(observed, blob(4))]),
+    ];
+
+    // Nothing is reported until the account is observed.
+    assert!(observed_changes(&observer, 10, &txns_to_commit).is_empty());
+
+    observer.observed.lock().unwrap().insert(observed);
+    assert_eq!(
+        observed_changes(&observer, 10, &txns_to_commit),
+        vec![
+            AccountChange {
+                address: observed,
+                version: 10,
+                blob: blob(1),
+            },
+            AccountChange {
+                address: observed,
+                version: 12,
+                blob: blob(4),
+            },
+        ]
+    );
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    account_observer::{observed_changes, AccountChange, AccountObserver},
     block_tree::{Block, BlockTree},
     transaction_block::{ProcessedVMOutput, TransactionBlock, TransactionData},
     Command, ExecutedState, ExecutedTrees, StateComputeResult, OP_COUNTERS,
@@ -33,6 +34,7 @@ use types::{
         SignedTransaction, TransactionInfo, TransactionListWithProof, TransactionOutput,
         TransactionPayload, TransactionStatus, TransactionToCommit, Version,
     },
+    validator_set::ValidatorSet,
     write_set::{WriteOp, WriteSet},
 };
 use vm_runtime::VMExecutor;
@@ -72,6 +74,13 @@ pub(crate) struct BlockProcessor<V> {
     /// Configuration for the VM. The block processor currently creates a new VM for each block.
     vm_config: VMConfig,
 
+    /// Gas the VM may spend executing each block, if limited. The transactions of a block which
+    /// were not executed within the budget are left for a later block.
+    block_gas_budget: Option<u64>,
+
+    /// Observer of the accounts whose committed changes are reported, if any.
+    account_observer: Option<Arc<dyn AccountObserver>>,
+
     phantom: PhantomData<V>,
 }
 
@@ -90,6 +99,8 @@ where
         storage_read_client: Arc<dyn StorageRead>,
         storage_write_client: Arc<dyn StorageWrite>,
         vm_config: VMConfig,
+        block_gas_budget: Option<u64>,
+        account_observer: Option<Arc<dyn AccountObserver>>,
     ) -> Self {
         BlockProcessor {
             command_receiver,
@@ -110,12 +121,37 @@ where
             storage_write_client,
             mode: Mode::Normal,
             vm_config,
+            block_gas_budget,
+            account_observer,
             phantom: PhantomData,
         }
     }
 
+    /// Changes to the observed accounts made by `txns_to_commit`, the first of which is at
+    /// `first_version`.
+    fn observed_changes(
+        &self,
+        first_version: Version,
+        txns_to_commit: &[TransactionToCommit],
+    ) -> Vec<AccountChange> {
+        match &self.account_observer {
+            Some(observer) => observed_changes(observer.as_ref(), first_version, txns_to_commit),
+            None => vec![],
+        }
+    }
+
+    /// Reports the `changes` to the observed accounts, once committed.
+    fn notify_observer(&self, changes: Vec<AccountChange>) {
+        if let Some(observer) = &self.account_observer {
+            if !changes.is_empty() {
+                observer.on_committed(changes);
+            }
+        }
+    }
+
     /// Keeps processing blocks until the command sender is disconnected.
     pub fn run(&mut self) {
+        self.update_log_context(None /* epoch */);
         loop {
             // Fetch and process all commands sent by consensus until there is no more left in the
             // channel.
@@ -177,6 +213,19 @@ where
         }
     }
 
+    /// Updates the log context of the block processor thread with the committed version and, if
+    /// known, the epoch of the latest commit.
+    fn update_log_context(&self, epoch: Option<u64>) {
+        let (version, _) = self.committed_trees.version_and_state_root();
+        logger::context::update(|context| {
+            context.version = version;
+            if epoch.is_some() {
+                context.epoch = epoch;
+            }
+        });
+        OP_COUNTERS.export_log_context();
+    }
+
     /// Processes a single command from consensus. Note that this only modifies the block tree, the
     /// actual block execution and commit may happen later.
     fn process_command(&mut self, cmd: Command) {
@@ -361,12 +410,16 @@ where
                 "Gas used do not match for {}-th transaction in chunk.",
                 i,
             );
+            let major_status = match txn_data.status().vm_status() {
+                Some(vm_status) => vm_status.major_status,
+                None => bail!("{}-th transaction in chunk was not executed.", i),
+            };
             txns_to_commit.push(TransactionToCommit::new(
                 txn,
                 txn_data.account_blobs().clone(),
                 txn_data.events().to_vec(),
                 txn_data.gas_used(),
-                txn_data.status().vm_status().major_status,
+                major_status,
             ));
         }
 
@@ -396,13 +449,20 @@ where
             }
             None
         };
+        let observed_changes = self.observed_changes(first_version, &txns_to_commit);
         self.storage_write_client.save_transactions(
             txns_to_commit,
             first_version,
             ledger_info_to_commit.clone(),
         )?;
+        self.notify_observer(observed_changes);
 
         self.committed_trees = output.executed_trees().clone();
+        self.update_log_context(
+            ledger_info_to_commit
+                .as_ref()
+                .map(|ledger_info_with_sigs| ledger_info_with_sigs.ledger_info().epoch_num()),
+        );
         if let Some(ledger_info_with_sigs) = ledger_info_to_commit {
             self.committed_timestamp_usecs = ledger_info_with_sigs.ledger_info().timestamp_usecs();
             self.block_tree
@@ -497,13 +557,13 @@ where
                     .expect("All blocks in self.blocks_to_store should have finished execution.")
                     .transaction_data(),
             ) {
-                if let TransactionStatus::Keep(_) = txn_data.status() {
+                if let TransactionStatus::Keep(status) = txn_data.status() {
                     txns_to_commit.push(TransactionToCommit::new(
                         txn.clone(),
                         txn_data.account_blobs().clone(),
                         txn_data.events().to_vec(),
                         txn_data.gas_used(),
-                        txn_data.status().vm_status().major_status,
+                        status.major_status,
                     ));
                     num_accounts_created += txn_data.num_account_created();
                 }
@@ -533,6 +593,8 @@ where
         );
 
         let num_txns_to_commit = txns_to_commit.len() as u64;
+        let first_version = version + 1 - num_txns_to_commit;
+        let observed_changes = self.observed_changes(first_version, &txns_to_commit);
         {
             let _timer = OP_COUNTERS.timer("storage_save_transactions_time_s");
             OP_COUNTERS.observe(
@@ -541,10 +603,11 @@ where
             );
             self.storage_write_client.save_transactions(
                 txns_to_commit,
-                version + 1 - num_txns_to_commit, /* first_version */
+                first_version,
                 Some(ledger_info_with_sigs.clone()),
             )?;
         }
+        self.notify_observer(observed_changes);
         // Only bump the counter when the commit succeeds.
         OP_COUNTERS.inc_by("num_accounts", num_accounts_created);
 
@@ -552,6 +615,7 @@ where
         // in-memory state.
         self.committed_timestamp_usecs = ledger_info_with_sigs.ledger_info().timestamp_usecs();
         self.committed_trees = last_block.executed_trees().clone();
+        self.update_log_context(Some(ledger_info_with_sigs.ledger_info().epoch_num()));
         last_block.send_commit_block_response();
 
         let num_saved = block_batch.len();
@@ -605,12 +669,32 @@ where
         );
         let vm_outputs = {
             let _timer = OP_COUNTERS.timer("vm_execute_block_time_s");
-            V::execute_block(
-                block_to_execute.transactions().to_vec(),
-                &self.vm_config,
-                &state_view,
-            )
+            match self.block_gas_budget {
+                Some(gas_budget) => V::execute_block_with_gas_budget(
+                    block_to_execute.transactions().to_vec(),
+                    &self.vm_config,
+                    &state_view,
+                    gas_budget,
+                ),
+                None => V::execute_block(
+                    block_to_execute.transactions().to_vec(),
+                    &self.vm_config,
+                    &state_view,
+                ),
+            }
         };
+        let num_retried = vm_outputs
+            .iter()
+            .filter(|output| *output.status() == TransactionStatus::Retry)
+            .count();
+        if num_retried > 0 {
+            warn!(
+                "Execution of block {:x} ran out of gas, {} transactions left for a later block.",
+                id, num_retried
+            );
+            OP_COUNTERS.inc("block_execution_interrupted");
+            OP_COUNTERS.inc_by("txns_left_for_later_block", num_retried);
+        }
 
         let status: Vec<_> = vm_outputs
             .iter()
@@ -628,8 +712,13 @@ where
             block_to_execute.transactions(),
             vm_outputs,
             &parent_trees,
-        ) {
-            Ok(output) => {
+        )
+        .and_then(|output| {
+            // The validators move to the new validator set once the block is committed.
+            let validators = Self::find_validator_set_change(&output)?;
+            Ok((output, validators))
+        }) {
+            Ok((output, validators)) => {
                 let accu_root_hash = output.executed_trees().txn_accumulator().root_hash();
                 let version =
                     (output.executed_trees().txn_accumulator().num_leaves() - 1) as Version;
@@ -637,13 +726,11 @@ where
 
                 // Now that we have the root hash and execution status we can send the response to
                 // consensus.
-                // TODO: The VM will support a special transaction to set the validators for the
-                // next epoch that is part of a block execution.
                 let state_compute_result = StateComputeResult {
                     executed_state: ExecutedState {
                         state_id: accu_root_hash,
                         version,
-                        validators: None,
+                        validators,
                     },
                     compute_status: status,
                 };
@@ -675,6 +762,23 @@ where
         }
     }
 
+    /// Returns the validator set of the last validator set change event emitted by the
+    /// transactions of a block, if any. Fails if the event does not hold a validator set.
+    fn find_validator_set_change(output: &ProcessedVMOutput) -> Result<Option<ValidatorSet>> {
+        let change_event_key = ValidatorSet::change_event_key();
+        output
+            .transaction_data()
+            .iter()
+            .flat_map(TransactionData::events)
+            .filter(|event| *event.key() == change_event_key)
+            .last()
+            .map(|event| {
+                ValidatorSet::from_bytes(event.event_data())
+                    .map_err(|e| format_err!("Failed to deserialize the new validator set: {}", e))
+            })
+            .transpose()
+    }
+
     /// Post-processing of what the VM outputs. Returns the entire block's output.
     fn process_vm_outputs(
         mut account_to_btree: HashMap<AccountAddress, BTreeMap<Vec<u8>, Vec<u8>>>,
@@ -725,7 +829,7 @@ where
                     );
                     txn_info_hashes.push(txn_info.hash());
                 }
-                TransactionStatus::Discard(_) => {
+                TransactionStatus::Discard(_) | TransactionStatus::Retry => {
                     ensure!(
                         vm_output.write_set().is_empty(),
                         "Discarded transaction has non-empty write set.",
@@ -27,7 +27,7 @@ use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
     crypto_proxies::LedgerInfoWithSignatures,
     ledger_info::LedgerInfo,
-    transaction::{SignedTransaction, TransactionListWithProof, Version},
+    transaction::{SignedTransaction, TransactionListWithProof, TransactionStatus, Version},
 };
 use vm_genesis::{encode_genesis_transaction, GENESIS_KEYPAIR};
 
@@ -113,7 +113,10 @@ struct TestExecutor {
 
 impl TestExecutor {
     fn new() -> TestExecutor {
-        let mut config = get_config();
+        Self::with_config(get_config())
+    }
+
+    fn with_config(mut config: NodeConfig) -> TestExecutor {
         let (storage_server, shutdown_receiver) = create_storage_server(&mut config);
         let executor = create_executor(&config);
 
@@ -201,6 +204,29 @@ fn test_executor_status() {
     );
 }
 
+#[test]
+fn test_executor_block_gas_budget() {
+    let mut config = get_config();
+    config.execution.block_gas_budget = Some(4);
+    let executor = TestExecutor::with_config(config);
+
+    let txns = (0..10)
+        .map(|i| encode_mint_transaction(gen_address(i), 100))
+        .collect();
+    let response = block_on(executor.execute_block(txns, *GENESIS_BLOCK_ID, gen_block_id(1)))
+        .unwrap()
+        .unwrap();
+
+    // The budget is exhausted after the first 4 transactions, the others are left for later.
+    assert_eq!(response.version(), 4);
+    assert!(response.status()[..4]
+        .iter()
+        .all(|status| *status == *KEEP_STATUS));
+    assert!(response.status()[4..]
+        .iter()
+        .all(|status| *status == TransactionStatus::Retry));
+}
+
 #[test]
 fn test_executor_one_block() {
     let executor = TestExecutor::new();
@@ -1,15 +1,20 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod account_observer;
 mod block_processor;
 mod block_tree;
 mod transaction_block;
 
+#[cfg(test)]
+mod account_observer_test;
 #[cfg(test)]
 mod executor_test;
 #[cfg(test)]
 mod mock_vm;
 
+pub use crate::account_observer::{AccountChange, AccountObserver};
+
 use crate::block_processor::BlockProcessor;
 use canonical_serialization::{CanonicalSerialize, CanonicalSerializer};
 use config::config::NodeConfig;
@@ -87,8 +92,8 @@ pub struct ExecutedState {
     /// Version of after executing a proposed block.  This state must be persisted to ensure
     /// that on restart that the version is calculated correctly
     pub version: Version,
-    /// If set, this is the validator set that should be changed to if this block is committed.
-    /// TODO [Reconfiguration] the validators are currently ignored, no reconfiguration yet.
+    /// If set, this is the validator set that should be changed to if this block is committed,
+    /// as emitted by the validator set change event of one of its transactions.
     pub validators: Option<ValidatorSet>,
 }
 
@@ -115,7 +120,7 @@ impl CanonicalSerialize for ExecutedState {
 /// `Executor` implements all functionalities the execution module needs to provide.
 pub struct Executor<V> {
     /// A thread that keeps processing blocks.
-    block_processor_thread: Option<std::thread::JoinHandle<()>>,
+    block_processor_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
 
     /// Where we can send command to the block processor. The block processor sits at the other end
     /// of the channel and processes the commands.
@@ -133,6 +138,31 @@ where
         storage_read_client: Arc<dyn StorageRead>,
         storage_write_client: Arc<dyn StorageWrite>,
         config: &NodeConfig,
+    ) -> Self {
+        Self::build(storage_read_client, storage_write_client, config, None)
+    }
+
+    /// Constructs an `Executor` reporting the committed changes to the accounts observed by
+    /// `account_observer`.
+    pub fn with_account_observer(
+        storage_read_client: Arc<dyn StorageRead>,
+        storage_write_client: Arc<dyn StorageWrite>,
+        config: &NodeConfig,
+        account_observer: Arc<dyn AccountObserver>,
+    ) -> Self {
+        Self::build(
+            storage_read_client,
+            storage_write_client,
+            config,
+            Some(account_observer),
+        )
+    }
+
+    fn build(
+        storage_read_client: Arc<dyn StorageRead>,
+        storage_write_client: Arc<dyn StorageWrite>,
+        config: &NodeConfig,
+        account_observer: Option<Arc<dyn AccountObserver>>,
     ) -> Self {
         let startup_info = storage_read_client
             .get_startup_info()
@@ -171,8 +201,9 @@ where
         let (command_sender, command_receiver) = mpsc::channel();
 
         let vm_config = config.vm_config.clone();
+        let block_gas_budget = config.execution.block_gas_budget;
         let executor = Executor {
-            block_processor_thread: Some(
+            block_processor_thread: Mutex::new(Some(
                 std::thread::Builder::new()
                     .name("block_processor".into())
                     .spawn(move || {
@@ -186,11 +217,13 @@ where
                             storage_read_client,
                             storage_write_client,
                             vm_config,
+                            block_gas_budget,
+                            account_observer,
                         );
                         block_processor.run();
                     })
                     .expect("Failed to create block processor thread."),
-            ),
+            )),
             command_sender: Mutex::new(Some(command_sender)),
             phantom: PhantomData,
         };
@@ -336,19 +369,31 @@ where
     }
 }
 
-impl<V> Drop for Executor<V> {
-    fn drop(&mut self) {
+impl<V> Executor<V> {
+    /// Stops accepting new commands, then waits for the block processor to process the commands
+    /// sent so far and save all the committed blocks to storage.
+    pub fn shutdown(&self) {
         // Drop the sender so the block processor thread will exit.
         self.command_sender
+            .lock()
+            .expect("Failed to lock mutex.")
+            .take();
+        if let Some(block_processor_thread) = self
+            .block_processor_thread
             .lock()
             .expect("Failed to lock mutex.")
             .take()
-            .expect("Command sender should exist.");
-        self.block_processor_thread
-            .take()
-            .expect("Block processor thread should exist.")
-            .join()
-            .expect("Did block processor thread panic?");
+        {
+            block_processor_thread
+                .join()
+                .expect("Did block processor thread panic?");
+        }
+    }
+}
+
+impl<V> Drop for Executor<V> {
+    fn drop(&mut self) {
+        self.shutdown();
     }
 }
 
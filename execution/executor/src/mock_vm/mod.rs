@@ -53,88 +53,112 @@ impl VMExecutor for MockVM {
         _config: &VMConfig,
         state_view: &dyn StateView,
     ) -> Vec<TransactionOutput> {
-        if state_view.is_genesis() {
-            assert_eq!(
-                transactions.len(),
-                1,
-                "Genesis block should have only one transaction."
-            );
-            let output =
-                TransactionOutput::new(gen_genesis_writeset(), vec![], 0, KEEP_STATUS.clone());
-            return vec![output];
-        }
-
-        // output_cache is used to store the output of transactions so they are visible to later
-        // transactions.
-        let mut output_cache = HashMap::new();
-        let mut outputs = vec![];
+        execute_block_until(transactions, state_view, None)
+    }
 
-        for txn in transactions {
-            match decode_transaction(&txn) {
-                Transaction::Mint { sender, amount } => {
-                    let old_balance = read_balance(&output_cache, state_view, sender);
-                    let new_balance = old_balance + amount;
-                    let old_seqnum = read_seqnum(&output_cache, state_view, sender);
-                    let new_seqnum = old_seqnum + 1;
+    fn execute_block_with_gas_budget(
+        transactions: Vec<SignedTransaction>,
+        _config: &VMConfig,
+        state_view: &dyn StateView,
+        gas_budget: u64,
+    ) -> Vec<TransactionOutput> {
+        execute_block_until(transactions, state_view, Some(gas_budget))
+    }
+}
 
-                    output_cache.insert(balance_ap(sender), new_balance);
-                    output_cache.insert(seqnum_ap(sender), new_seqnum);
+fn execute_block_until(
+    transactions: Vec<SignedTransaction>,
+    state_view: &dyn StateView,
+    gas_budget: Option<u64>,
+) -> Vec<TransactionOutput> {
+    if state_view.is_genesis() {
+        assert_eq!(
+            transactions.len(),
+            1,
+            "Genesis block should have only one transaction."
+        );
+        let output = TransactionOutput::new(gen_genesis_writeset(), vec![], 0, KEEP_STATUS.clone());
+        return vec![output];
+    }
 
-                    let write_set = gen_mint_writeset(sender, new_balance, new_seqnum);
-                    let events = gen_events(sender);
+    // output_cache is used to store the output of transactions so they are visible to later
+    // transactions.
+    let mut output_cache = HashMap::new();
+    let mut outputs = vec![];
+
+    let num_txns = transactions.len();
+    for (i, txn) in transactions.into_iter().enumerate() {
+        // The mock transactions use no gas, each of them counts as 1 against the budget instead.
+        if gas_budget.map_or(false, |gas_budget| i as u64 >= gas_budget) {
+            outputs.extend((i..num_txns).map(|_| {
+                TransactionOutput::new(WriteSet::default(), vec![], 0, TransactionStatus::Retry)
+            }));
+            break;
+        }
+        match decode_transaction(&txn) {
+            Transaction::Mint { sender, amount } => {
+                let old_balance = read_balance(&output_cache, state_view, sender);
+                let new_balance = old_balance + amount;
+                let old_seqnum = read_seqnum(&output_cache, state_view, sender);
+                let new_seqnum = old_seqnum + 1;
+
+                output_cache.insert(balance_ap(sender), new_balance);
+                output_cache.insert(seqnum_ap(sender), new_seqnum);
+
+                let write_set = gen_mint_writeset(sender, new_balance, new_seqnum);
+                let events = gen_events(sender);
+                outputs.push(TransactionOutput::new(
+                    write_set,
+                    events,
+                    0,
+                    KEEP_STATUS.clone(),
+                ));
+            }
+            Transaction::Payment {
+                sender,
+                recipient,
+                amount,
+            } => {
+                let sender_old_balance = read_balance(&output_cache, state_view, sender);
+                let recipient_old_balance = read_balance(&output_cache, state_view, recipient);
+                if sender_old_balance < amount {
                     outputs.push(TransactionOutput::new(
-                        write_set,
-                        events,
+                        WriteSet::default(),
+                        vec![],
                         0,
-                        KEEP_STATUS.clone(),
+                        DISCARD_STATUS.clone(),
                     ));
+                    continue;
                 }
-                Transaction::Payment {
+
+                let sender_old_seqnum = read_seqnum(&output_cache, state_view, sender);
+                let sender_new_seqnum = sender_old_seqnum + 1;
+                let sender_new_balance = sender_old_balance - amount;
+                let recipient_new_balance = recipient_old_balance + amount;
+
+                output_cache.insert(balance_ap(sender), sender_new_balance);
+                output_cache.insert(seqnum_ap(sender), sender_new_seqnum);
+                output_cache.insert(balance_ap(recipient), recipient_new_balance);
+
+                let write_set = gen_payment_writeset(
                     sender,
+                    sender_new_balance,
+                    sender_new_seqnum,
                     recipient,
-                    amount,
-                } => {
-                    let sender_old_balance = read_balance(&output_cache, state_view, sender);
-                    let recipient_old_balance = read_balance(&output_cache, state_view, recipient);
-                    if sender_old_balance < amount {
-                        outputs.push(TransactionOutput::new(
-                            WriteSet::default(),
-                            vec![],
-                            0,
-                            DISCARD_STATUS.clone(),
-                        ));
-                        continue;
-                    }
-
-                    let sender_old_seqnum = read_seqnum(&output_cache, state_view, sender);
-                    let sender_new_seqnum = sender_old_seqnum + 1;
-                    let sender_new_balance = sender_old_balance - amount;
-                    let recipient_new_balance = recipient_old_balance + amount;
-
-                    output_cache.insert(balance_ap(sender), sender_new_balance);
-                    output_cache.insert(seqnum_ap(sender), sender_new_seqnum);
-                    output_cache.insert(balance_ap(recipient), recipient_new_balance);
-
-                    let write_set = gen_payment_writeset(
-                        sender,
-                        sender_new_balance,
-                        sender_new_seqnum,
-                        recipient,
-                        recipient_new_balance,
-                    );
-                    let events = gen_events(sender);
-                    outputs.push(TransactionOutput::new(
-                        write_set,
-                        events,
-                        0,
-                        TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED)),
-                    ));
-                }
+                    recipient_new_balance,
+                );
+                let events = gen_events(sender);
+                outputs.push(TransactionOutput::new(
+                    write_set,
+                    events,
+                    0,
+                    TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED)),
+                ));
             }
         }
-
-        outputs
     }
+
+    outputs
 }
 
 fn read_balance(
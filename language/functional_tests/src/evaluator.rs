@@ -259,6 +259,7 @@ fn run_transaction(
                 }
             }
             TransactionStatus::Discard(_) => Err(ErrorKind::DiscardedTransaction(output).into()),
+            TransactionStatus::Retry => unreachable!("Block executed without a gas budget"),
         }
     } else {
         panic!("transaction outputs size mismatch");
@@ -24,12 +24,17 @@ use types::{
 };
 use vm_cache_map::Arena;
 
+/// Executes the transactions of the block in order. If the transactions executed use up
+/// `gas_budget` before the end of the block, execution is interrupted before the next transaction:
+/// the transactions left are marked as `TransactionStatus::Retry`, with an empty output. The gas
+/// used being deterministic, all the validators interrupt the block at the same transaction.
 pub fn execute_block<'alloc>(
     txn_block: Vec<SignedTransaction>,
     code_cache: &VMModuleCache<'alloc>,
     script_cache: &ScriptCache<'alloc>,
     data_view: &dyn StateView,
     publishing_option: &VMPublishingOption,
+    gas_budget: Option<u64>,
 ) -> Vec<TransactionOutput> {
     trace!("[VM] Execute block, transaction count: {}", txn_block.len());
     report_block_count(txn_block.len());
@@ -68,7 +73,21 @@ pub fn execute_block<'alloc>(
         })
         .collect();
 
-    for transaction in signature_verified_block {
+    let mut gas_used: u64 = 0;
+    let mut signature_verified_block = signature_verified_block.into_iter();
+    while let Some(transaction) = signature_verified_block.next() {
+        if gas_budget.map_or(false, |gas_budget| gas_used >= gas_budget) {
+            let num_retried = 1 + signature_verified_block.len();
+            warn!(
+                "[VM] Block execution interrupted, {} transactions left to retry",
+                num_retried
+            );
+            report_block_interrupted(num_retried);
+            result.extend((0..num_retried).map(|_| {
+                TransactionOutput::new(WriteSet::default(), vec![], 0, TransactionStatus::Retry)
+            }));
+            break;
+        }
         record_stats! {time_hist | TXN_TOTAL_TIME_TAKEN | {
                 let output = match transaction {
                     Ok(t) => transaction_flow(
@@ -83,6 +102,7 @@ pub fn execute_block<'alloc>(
                 };
                 report_execution_status(output.status());
                 data_cache.push_write_set(&output.write_set());
+                gas_used = gas_used.saturating_add(output.gas_used());
 
                 // `result` is initally empty, a single element is pushed per loop iteration and
                 // the number of iterations is bound to the max size of `signature_verified_block`
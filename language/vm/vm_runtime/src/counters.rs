@@ -13,6 +13,8 @@ use types::{
 // constants used to create counters
 const TXN_EXECUTION_KEEP: &str = "txn.execution.keep";
 const TXN_EXECUTION_DISCARD: &str = "txn.execution.discard";
+const TXN_EXECUTION_RETRY: &str = "txn.execution.retry";
+const BLOCK_EXECUTION_INTERRUPTED: &str = "block.execution.interrupted";
 const TXN_VERIFICATION_SUCCESS: &str = "txn.verification.success";
 const TXN_VERIFICATION_FAIL: &str = "txn.verification.fail";
 const TXN_BLOCK_COUNT: &str = "txn.block.count";
@@ -31,6 +33,8 @@ lazy_static::lazy_static! {
 
     static ref VERIFIED_TRANSACTION: IntCounter = VM_COUNTERS.counter(TXN_VERIFICATION_SUCCESS);
     static ref BLOCK_TRANSACTION_COUNT: IntGauge = VM_COUNTERS.gauge(TXN_BLOCK_COUNT);
+    static ref RETRIED_TRANSACTION: IntCounter = VM_COUNTERS.counter(TXN_EXECUTION_RETRY);
+    static ref INTERRUPTED_BLOCK: IntCounter = VM_COUNTERS.counter(BLOCK_EXECUTION_INTERRUPTED);
 }
 
 /// Wrapper around time::Instant.
@@ -94,14 +98,22 @@ macro_rules! record_stats {
     }};
 }
 
+/// Reports the interruption of the execution of a block, leaving `count` transactions to retry.
+pub fn report_block_interrupted(count: usize) {
+    INTERRUPTED_BLOCK.inc();
+    RETRIED_TRANSACTION.inc_by(count as i64);
+}
+
 /// Reports the result of a transaction execution.
 ///
 /// Counters are prefixed with `TXN_EXECUTION_KEEP` or `TXN_EXECUTION_DISCARD`.
 /// The prefix can be used with regex to combine different counters in a dashboard.
+/// Transactions to retry are reported by [`report_block_interrupted`].
 pub fn report_execution_status(status: &TransactionStatus) {
     match status {
         TransactionStatus::Keep(vm_status) => inc_counter(TXN_EXECUTION_KEEP, vm_status),
         TransactionStatus::Discard(vm_status) => inc_counter(TXN_EXECUTION_DISCARD, vm_status),
+        TransactionStatus::Retry => (),
     }
 }
 
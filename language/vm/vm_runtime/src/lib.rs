@@ -139,7 +139,7 @@ pub use txn_executor::execute_function;
 use config::config::VMConfig;
 use state_view::StateView;
 use types::{
-    transaction::{SignedTransaction, TransactionOutput},
+    transaction::{SignatureCheckedTransaction, SignedTransaction, TransactionOutput},
     vm_error::VMStatus,
 };
 use vm::IndexKind;
@@ -154,6 +154,14 @@ pub trait VMVerifier {
         transaction: SignedTransaction,
         state_view: &dyn StateView,
     ) -> Option<VMStatus>;
+
+    /// Same as `validate_transaction`, for a transaction whose signature was already verified,
+    /// which is not verified again.
+    fn validate_signature_checked_transaction(
+        &self,
+        transaction: SignatureCheckedTransaction,
+        state_view: &dyn StateView,
+    ) -> Option<VMStatus>;
 }
 
 /// This trait describes the VM's execution interface.
@@ -169,4 +177,15 @@ pub trait VMExecutor {
         config: &VMConfig,
         state_view: &dyn StateView,
     ) -> Vec<TransactionOutput>;
+
+    /// Same as `execute_block`, except that execution is interrupted once the transactions
+    /// executed used up `gas_budget`. The transactions which were not executed by then are marked
+    /// as `TransactionStatus::Retry`. As the gas used doesn't depend on the machine executing the
+    /// block, neither does the point of interruption.
+    fn execute_block_with_gas_budget(
+        transactions: Vec<SignedTransaction>,
+        config: &VMConfig,
+        state_view: &dyn StateView,
+        gas_budget: u64,
+    ) -> Vec<TransactionOutput>;
 }
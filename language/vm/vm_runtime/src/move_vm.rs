@@ -8,7 +8,7 @@ use crate::{
 use state_view::StateView;
 use std::sync::Arc;
 use types::{
-    transaction::{SignedTransaction, TransactionOutput},
+    transaction::{SignatureCheckedTransaction, SignedTransaction, TransactionOutput},
     vm_error::VMStatus,
 };
 use vm_cache_map::Arena;
@@ -58,6 +58,19 @@ impl VMVerifier for MoveVM {
             }
         }
     }
+
+    fn validate_signature_checked_transaction(
+        &self,
+        transaction: SignatureCheckedTransaction,
+        state_view: &dyn StateView,
+    ) -> Option<VMStatus> {
+        record_stats! {time_hist | TXN_VALIDATION_TIME_TAKEN | {
+            self.inner.rent(move |runtime| {
+                runtime.verify_signature_checked_transaction(transaction, state_view)
+            })
+            }
+        }
+    }
 }
 
 impl VMExecutor for MoveVM {
@@ -66,13 +79,31 @@ impl VMExecutor for MoveVM {
         config: &VMConfig,
         state_view: &dyn StateView,
     ) -> Vec<TransactionOutput> {
-        let vm = MoveVMImpl::new(Box::new(Arena::new()), |arena| {
-            // XXX This means that scripts and modules are NOT tested against the whitelist! This
-            // needs to be fixed.
-            VMRuntime::new(&*arena, config)
-        });
-        vm.rent(|runtime| runtime.execute_block_transactions(transactions, state_view))
+        execute_block_until(transactions, config, state_view, None)
     }
+
+    fn execute_block_with_gas_budget(
+        transactions: Vec<SignedTransaction>,
+        config: &VMConfig,
+        state_view: &dyn StateView,
+        gas_budget: u64,
+    ) -> Vec<TransactionOutput> {
+        execute_block_until(transactions, config, state_view, Some(gas_budget))
+    }
+}
+
+fn execute_block_until(
+    transactions: Vec<SignedTransaction>,
+    config: &VMConfig,
+    state_view: &dyn StateView,
+    gas_budget: Option<u64>,
+) -> Vec<TransactionOutput> {
+    let vm = MoveVMImpl::new(Box::new(Arena::new()), |arena| {
+        // XXX This means that scripts and modules are NOT tested against the whitelist! This
+        // needs to be fixed.
+        VMRuntime::new(&*arena, config)
+    });
+    vm.rent(|runtime| runtime.execute_block_transactions(transactions, state_view, gas_budget))
 }
 
 #[test]
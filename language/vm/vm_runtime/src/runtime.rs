@@ -17,7 +17,7 @@ use config::config::{VMConfig, VMPublishingOption};
 use logger::prelude::*;
 use state_view::StateView;
 use types::{
-    transaction::{SignedTransaction, TransactionOutput},
+    transaction::{SignatureCheckedTransaction, SignedTransaction, TransactionOutput},
     vm_error::{StatusCode, VMStatus},
 };
 use vm_cache_map::Arena;
@@ -66,18 +66,28 @@ impl<'alloc> VMRuntime<'alloc> {
         txn: SignedTransaction,
         data_view: &dyn StateView,
     ) -> Option<VMStatus> {
-        trace!("[VM] Verify transaction: {:?}", txn);
+        match txn.check_signature() {
+            Ok(signature_verified_txn) => {
+                self.verify_signature_checked_transaction(signature_verified_txn, data_view)
+            }
+            Err(_) => Some(VMStatus::new(StatusCode::INVALID_SIGNATURE)),
+        }
+    }
+
+    /// Same as `verify_transaction`, for a transaction whose signature was already verified,
+    /// which is not verified again.
+    pub fn verify_signature_checked_transaction(
+        &self,
+        signature_verified_txn: SignatureCheckedTransaction,
+        data_view: &dyn StateView,
+    ) -> Option<VMStatus> {
+        trace!("[VM] Verify transaction: {:?}", signature_verified_txn);
         // Treat a transaction as a single block.
         let module_cache =
             BlockModuleCache::new(&self.code_cache, ModuleFetcherImpl::new(data_view));
         let data_cache = BlockDataCache::new(data_view);
 
         let arena = Arena::new();
-        let signature_verified_txn = match txn.check_signature() {
-            Ok(t) => t,
-            Err(_) => return Some(VMStatus::new(StatusCode::INVALID_SIGNATURE)),
-        };
-
         let process_txn =
             ProcessTransaction::new(signature_verified_txn, module_cache, &data_cache, &arena);
         let mode = if data_view.is_genesis() {
@@ -106,11 +116,13 @@ impl<'alloc> VMRuntime<'alloc> {
     /// input vector. The discarded transactions will be marked as `TransactionStatus::Discard` and
     /// have an empty writeset. Also the data view is immutable, and also does not have interior
     /// mutability. writes to be applied to the data view are encoded in the write set part of a
-    /// transaction output.
+    /// transaction output. If the transactions executed use up `gas_budget` before the end of the
+    /// block, the transactions left are marked as `TransactionStatus::Retry`.
     pub fn execute_block_transactions(
         &self,
         txn_block: Vec<SignedTransaction>,
         data_view: &dyn StateView,
+        gas_budget: Option<u64>,
     ) -> Vec<TransactionOutput> {
         execute_block(
             txn_block,
@@ -118,6 +130,7 @@ impl<'alloc> VMRuntime<'alloc> {
             &self.script_cache,
             data_view,
             &self.publishing_option,
+            gas_budget,
         )
     }
 }
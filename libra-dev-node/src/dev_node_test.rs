@@ -0,0 +1,126 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::LibraDevNode;
+use admission_control_proto::{
+    proto::admission_control::SubmitTransactionRequest, AdmissionControlStatus,
+    SubmitTransactionResponse,
+};
+use crypto::ed25519::*;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+};
+use transaction_builder::encode_create_account_script;
+use types::{
+    account_address::AccountAddress,
+    account_config::association_address,
+    get_with_proof::{
+        RequestItem, ResponseItem, UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse,
+    },
+    test_helpers::transaction_test_helpers::get_test_signed_transaction,
+};
+
+fn submit_create_account(
+    node: &LibraDevNode,
+    sequence_number: u64,
+    address: AccountAddress,
+) -> Option<AdmissionControlStatus> {
+    let faucet_keypair = node.faucet_keypair();
+    let txn = get_test_signed_transaction(
+        association_address(),
+        sequence_number,
+        faucet_keypair.private_key.clone(),
+        faucet_keypair.public_key.clone(),
+        Some(encode_create_account_script(&address, 1_000)),
+        u64::max_value(),
+        0,
+        None,
+    );
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(txn.into());
+    let response = node.submit_transaction(req).unwrap();
+    SubmitTransactionResponse::try_from(response)
+        .unwrap()
+        .ac_status
+}
+
+fn get_account_state(node: &LibraDevNode, address: AccountAddress) -> (u64, bool) {
+    let request =
+        UpdateToLatestLedgerRequest::new(0, vec![RequestItem::GetAccountState { address }]);
+    let response: UpdateToLatestLedgerResponse<Ed25519Signature> = node
+        .update_to_latest_ledger(request.clone().into())
+        .unwrap()
+        .try_into()
+        .unwrap();
+    // The response carries a proof signed by the node.
+    response
+        .verify(Arc::new(node.validator_verifier()), &request)
+        .unwrap();
+    match &response.response_items[0] {
+        ResponseItem::GetAccountState {
+            account_state_with_proof,
+        } => (
+            response.ledger_info_with_sigs.ledger_info().version(),
+            account_state_with_proof.blob.is_some(),
+        ),
+        item => panic!("Unexpected response item {:?}", item),
+    }
+}
+
+#[test]
+fn test_commit_on_submission() {
+    let node = LibraDevNode::new();
+    let mut rng = StdRng::from_seed([1u8; 32]);
+    let (_, public_key) = compat::generate_keypair(&mut rng);
+    let address = AccountAddress::from_public_key(&public_key);
+    assert_eq!(get_account_state(&node, address), (0, false));
+
+    // The genesis bumped the sequence number of the association account to 1.
+    assert_eq!(
+        submit_create_account(&node, 1, address),
+        Some(AdmissionControlStatus::Accepted)
+    );
+    assert_eq!(get_account_state(&node, address), (1, true));
+}
+
+#[test]
+fn test_commit_once_ready() {
+    let node = LibraDevNode::new();
+    let mut rng = StdRng::from_seed([1u8; 32]);
+    let (_, first_public_key) = compat::generate_keypair(&mut rng);
+    let (_, second_public_key) = compat::generate_keypair(&mut rng);
+    let first_address = AccountAddress::from_public_key(&first_public_key);
+    let second_address = AccountAddress::from_public_key(&second_public_key);
+
+    // The transaction is accepted, but cannot be executed before the previous transaction of its
+    // sender.
+    assert_eq!(
+        submit_create_account(&node, 2, second_address),
+        Some(AdmissionControlStatus::Accepted)
+    );
+    assert_eq!(get_account_state(&node, second_address), (0, false));
+
+    // Both transactions are committed in a single block.
+    assert_eq!(
+        submit_create_account(&node, 1, first_address),
+        Some(AdmissionControlStatus::Accepted)
+    );
+    assert_eq!(get_account_state(&node, first_address), (2, true));
+    assert_eq!(get_account_state(&node, second_address), (2, true));
+}
+
+#[test]
+fn test_reject_invalid_transaction() {
+    let node = LibraDevNode::new();
+    let mut req = SubmitTransactionRequest::default();
+    req.signed_txn = Some(Default::default());
+    let response = SubmitTransactionResponse::try_from(node.submit_transaction(req).unwrap());
+    match response.unwrap().ac_status {
+        Some(AdmissionControlStatus::Rejected(_)) => {}
+        status => panic!("Unexpected status {:?}", status),
+    }
+    // Nothing was committed.
+    assert_eq!(get_account_state(&node, association_address()), (0, true));
+}
@@ -0,0 +1,274 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![deny(missing_docs)]
+
+//! An embedded, single-node Libra chain for the integration tests of applications.
+//!
+//! [`LibraDevNode`] runs Admission Control, Mempool, the executor and storage inside the calling
+//! process, with neither networking nor consensus: as soon as a transaction is accepted, it is
+//! executed and committed in a block of its own, along with any transaction of mempool it made
+//! ready. Blocks are executed by the real VM and the ledger infos are signed with the consensus
+//! key of the node, the only validator of its genesis, so that responses carry proofs which
+//! verify as they would against a real network.
+//!
+//! The node serves the requests of the Admission Control API as plain function calls:
+//!
+//! ```no_run
+//! # use libra_dev_node::LibraDevNode;
+//! # use types::proto::types::UpdateToLatestLedgerRequest;
+//! let node = LibraDevNode::new();
+//! let response = node
+//!     .update_to_latest_ledger(UpdateToLatestLedgerRequest::default())
+//!     .unwrap();
+//! ```
+
+use admission_control_proto::proto::admission_control::{
+    SubmitTransactionRequest, SubmitTransactionResponse,
+};
+use admission_control_service::admission_control_service::AdmissionControlService;
+use config::config::{NodeConfig, NodeConfigHelpers};
+use crypto::{ed25519::*, hash::CryptoHash, test_utils::KeyPair, HashValue};
+use disk_monitor::DiskMonitor;
+use executor::Executor;
+use failure::prelude::*;
+use futures::executor::block_on;
+use mempool::LocalMempool;
+use rand::{rngs::OsRng, Rng, SeedableRng};
+use std::{
+    cmp::max,
+    collections::HashMap,
+    convert::TryFrom,
+    fs::File,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use storage_client::StorageRead;
+use storage_service::local_client::LocalStorageClient;
+use trusted_ledger::TrustedLedger;
+use types::{
+    account_address::AccountAddress,
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier},
+    ledger_info::LedgerInfo,
+    proto::types::{UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse},
+    transaction::TransactionStatus,
+};
+use vm_runtime::MoveVM;
+use vm_validator::vm_validator::VMValidator;
+
+#[cfg(test)]
+mod dev_node_test;
+
+/// A single-node chain running in-process. See the [crate documentation](index.html).
+pub struct LibraDevNode {
+    // Kept around as it owns the temporary directory holding the data of the node.
+    config: NodeConfig,
+    faucet_keypair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+    admission_control: AdmissionControlService<LocalMempool, VMValidator>,
+    mempool: LocalMempool,
+    executor: Executor<MoveVM>,
+    signer: ValidatorSigner,
+    // Id and timestamp of the last committed block, which the next block extends. Blocks are
+    // produced one at a time.
+    last_committed: Mutex<(HashValue, u64)>,
+}
+
+impl LibraDevNode {
+    /// Starts a node on a fresh chain, in a temporary directory removed when the node is dropped.
+    /// The genesis mints the coins of the faucet account to a new random key.
+    pub fn new() -> Self {
+        let mut seed_rng = OsRng::new().expect("can't access OsRng");
+        let seed: [u8; 32] = seed_rng.gen();
+        let (private_key, _) = compat::generate_keypair(&mut rand::rngs::StdRng::from_seed(seed));
+        Self::with_faucet_keypair(KeyPair::from(private_key))
+    }
+
+    /// Same as [`new`](Self::new), with the faucet account owned by `faucet_keypair`.
+    pub fn with_faucet_keypair(
+        faucet_keypair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+    ) -> Self {
+        let mut config = NodeConfigHelpers::get_single_node_test_config(false);
+        let network_peers = &config
+            .get_validator_network_config()
+            .expect("Test config without a validator network")
+            .network_peers;
+        let genesis_transaction = config_builder::util::gen_genesis_transaction_bytes(
+            &faucet_keypair,
+            &config.consensus.consensus_peers,
+            network_peers,
+        );
+        File::create(config.get_genesis_transaction_file())
+            .and_then(|mut file| file.write_all(&genesis_transaction))
+            .expect("Failed to write the genesis transaction");
+
+        let storage = Arc::new(LocalStorageClient::new(config.get_storage_dir()));
+        // Commits the genesis transaction.
+        let executor = Executor::new(storage.clone(), storage.clone(), &config);
+        let genesis_ledger_info = storage
+            .get_startup_info()
+            .expect("Failed to read startup info from storage")
+            .expect("Genesis transaction not committed")
+            .ledger_info;
+
+        let storage: Arc<dyn StorageRead> = storage;
+        let mempool = LocalMempool::new(&config);
+        let admission_control = AdmissionControlService::new(
+            Some(Arc::new(mempool.clone())),
+            storage.clone(),
+            Arc::new(VMValidator::new(&config, storage)),
+            false,
+            TrustedLedger::new(),
+            DiskMonitor::new(
+                config.get_storage_dir(),
+                config.storage.min_free_space_bytes,
+            ),
+        );
+
+        let author = AccountAddress::try_from(
+            config
+                .get_validator_network_config()
+                .expect("Test config without a validator network")
+                .peer_id
+                .clone(),
+        )
+        .expect("Failed to parse peer id of the validator");
+        let consensus_private_key = config
+            .consensus
+            .consensus_keypair
+            .take_consensus_private()
+            .expect("Test config without a consensus private key");
+
+        Self {
+            faucet_keypair,
+            admission_control,
+            mempool,
+            executor,
+            signer: ValidatorSigner::new(author, consensus_private_key),
+            last_committed: Mutex::new((
+                genesis_ledger_info.consensus_block_id(),
+                genesis_ledger_info.timestamp_usecs(),
+            )),
+            config,
+        }
+    }
+
+    /// The keypair of the faucet account, which holds all the coins minted by the genesis.
+    pub fn faucet_keypair(&self) -> &KeyPair<Ed25519PrivateKey, Ed25519PublicKey> {
+        &self.faucet_keypair
+    }
+
+    /// The verifier of the ledger infos signed by the node.
+    pub fn validator_verifier(&self) -> ValidatorVerifier {
+        self.config
+            .consensus
+            .consensus_peers
+            .get_validator_verifier()
+    }
+
+    /// Submits a transaction, as through the `SubmitTransaction` API of Admission Control. Once
+    /// accepted, the transaction is committed before returning, unless it is not ready to be
+    /// executed, e.g. because of a gap in the sequence numbers of its sender.
+    pub fn submit_transaction(
+        &self,
+        req: SubmitTransactionRequest,
+    ) -> Result<SubmitTransactionResponse> {
+        let response = self.admission_control.submit_transaction_inner(req)?;
+        self.commit_ready_transactions()?;
+        Ok(response)
+    }
+
+    /// Serves the `UpdateToLatestLedger` API of Admission Control.
+    pub fn update_to_latest_ledger(
+        &self,
+        req: UpdateToLatestLedgerRequest,
+    ) -> Result<UpdateToLatestLedgerResponse> {
+        self.admission_control.update_to_latest_ledger_inner(req)
+    }
+
+    /// Executes and commits the block of transactions of mempool ready to be executed, if any.
+    fn commit_ready_transactions(&self) -> Result<()> {
+        let mut last_committed = self
+            .last_committed
+            .lock()
+            .expect("Failed to lock last committed block");
+        let transactions = self
+            .mempool
+            .get_block(self.config.consensus.max_block_size());
+        if transactions.is_empty() {
+            return Ok(());
+        }
+
+        let (parent_id, parent_timestamp_usecs) = *last_committed;
+        let block_id = HashValue::random();
+        let state_compute_result = block_on(self.executor.execute_block(
+            transactions.clone(),
+            parent_id,
+            block_id,
+        ))??;
+
+        // Ledger infos must have increasing timestamps.
+        let now_usecs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_micros() as u64;
+        let timestamp_usecs = max(now_usecs, parent_timestamp_usecs + 1);
+        let ledger_info = LedgerInfo::new(
+            state_compute_result.version(),
+            state_compute_result.root_hash(),
+            /* consensus_data_hash = */ HashValue::zero(),
+            block_id,
+            /* epoch_num = */ 0,
+            timestamp_usecs,
+            None,
+        );
+        let signature = self.signer.sign_message(ledger_info.hash())?;
+        let mut signatures = HashMap::new();
+        signatures.insert(self.signer.author(), signature);
+        block_on(
+            self.executor
+                .commit_block(LedgerInfoWithSignatures::new(ledger_info, signatures)),
+        )??;
+        *last_committed = (block_id, timestamp_usecs);
+
+        let committed_transactions: Vec<_> = transactions
+            .iter()
+            .zip(state_compute_result.status())
+            .filter_map(|(transaction, status)| {
+                let is_rejected = match status {
+                    TransactionStatus::Keep(_) => false,
+                    TransactionStatus::Discard(_) => true,
+                    // Left in mempool for the next block.
+                    TransactionStatus::Retry => return None,
+                };
+                Some((
+                    transaction.sender(),
+                    transaction.sequence_number(),
+                    is_rejected,
+                ))
+            })
+            .collect();
+        // Kept transactions advance the sequence numbers of their senders, which makes any other
+        // transaction of theirs below the new sequence numbers stale.
+        let mut account_sequence_numbers: HashMap<AccountAddress, u64> = HashMap::new();
+        for (sender, sequence_number, is_rejected) in &committed_transactions {
+            if !is_rejected {
+                let entry = account_sequence_numbers.entry(*sender).or_insert(0);
+                *entry = max(*entry, sequence_number + 1);
+            }
+        }
+        let account_sequence_numbers: Vec<_> = account_sequence_numbers.into_iter().collect();
+        self.mempool.commit_transactions(
+            &committed_transactions,
+            &account_sequence_numbers,
+            timestamp_usecs,
+        );
+        Ok(())
+    }
+}
+
+impl Default for LibraDevNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Glue between the debug interface and the components it looks into, so that the core crates
+//! don't depend on the debug interface.
+
+use debug_interface::{
+    account_watcher::AccountWatcher,
+    mempool_introspector::MempoolIntrospection,
+    proto::{self, MempoolState},
+};
+use executor::{AccountChange, AccountObserver};
+use mempool::MempoolStateReader;
+use std::collections::HashSet;
+use types::account_address::AccountAddress;
+
+/// Reports the committed changes to the accounts of the watch list of the debug interface.
+pub struct WatchedAccounts(pub AccountWatcher);
+
+impl AccountObserver for WatchedAccounts {
+    fn observed_accounts(&self) -> HashSet<AccountAddress> {
+        self.0.watched().into_iter().collect()
+    }
+
+    fn on_committed(&self, changes: Vec<AccountChange>) {
+        for change in changes {
+            self.0.report(change.address, change.version, &change.blob);
+        }
+    }
+}
+
+/// Reports the state of mempool to the debug interface.
+pub struct MempoolStateIntrospection(pub MempoolStateReader);
+
+impl MempoolIntrospection for MempoolStateIntrospection {
+    fn mempool_state(&self, max_accounts: usize) -> MempoolState {
+        let snapshot = self.0.state_snapshot(max_accounts);
+        MempoolState {
+            num_transactions: snapshot.num_transactions as u64,
+            priority_index_size: snapshot.priority_index_size as u64,
+            parking_lot_index_size: snapshot.parking_lot_index_size as u64,
+            timeline_index_size: snapshot.timeline_index_size as u64,
+            system_ttl_index_size: snapshot.system_ttl_index_size as u64,
+            expiration_time_index_size: snapshot.expiration_time_index_size as u64,
+            num_accounts: snapshot.num_accounts as u64,
+            accounts: snapshot
+                .accounts
+                .into_iter()
+                .map(|queue| proto::AccountQueue {
+                    address: queue.address.to_vec(),
+                    num_transactions: queue.num_transactions as u64,
+                    num_ready: queue.num_ready as u64,
+                })
+                .collect(),
+            oldest_transaction_age_ms: snapshot
+                .oldest_transaction_age
+                .map_or(0, |age| age.as_millis() as u64),
+            bytes_resident: snapshot.bytes_resident as u64,
+        }
+    }
+}
@@ -1,4 +1,6 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod debug_adapters;
 pub mod main_node;
+pub mod self_test;
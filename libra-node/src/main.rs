@@ -2,13 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use executable_helpers::helpers::setup_executable;
+use libra_node::self_test::{run_self_test, Waypoint};
 use signal_hook;
 use std::{
     path::PathBuf,
+    process,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 use structopt::StructOpt;
 
@@ -21,6 +24,25 @@ struct Args {
     #[structopt(short = "d", long)]
     /// Disable logging
     no_logging: bool,
+    #[structopt(long, default_value = "10000")]
+    /// Time given to the node to shut down gracefully once signaled, after which it exits anyway
+    shutdown_timeout_ms: u64,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(name = "self-test")]
+    /// Validate the config, storage, key material and seed peer connectivity, then exit
+    SelfTest {
+        #[structopt(long, default_value = "2000")]
+        /// Timeout for each test dial to a seed peer
+        dial_timeout_ms: u64,
+        #[structopt(long)]
+        /// Trusted ledger info the committed ledger must agree with, as <version>:<hash>
+        waypoint: Option<Waypoint>,
+    },
 }
 
 #[global_allocator]
@@ -50,7 +72,21 @@ fn main() {
     let (mut config, _logger) =
         setup_executable(args.config.as_ref().map(PathBuf::as_path), args.no_logging);
 
-    let (_ac_handle, _node_handle) = libra_node::main_node::setup_environment(&mut config);
+    if let Some(Command::SelfTest {
+        dial_timeout_ms,
+        waypoint,
+    }) = args.cmd
+    {
+        let report = run_self_test(
+            &config,
+            waypoint.as_ref(),
+            Duration::from_millis(dial_timeout_ms),
+        );
+        println!("{}", report);
+        process::exit(if report.passed() { 0 } else { 1 });
+    }
+
+    let (_ac_handle, node_handle) = libra_node::main_node::setup_environment(&mut config);
 
     let term = Arc::new(AtomicBool::new(false));
     register_signals(Arc::clone(&term));
@@ -58,4 +94,8 @@ fn main() {
     while !term.load(Ordering::Acquire) {
         std::thread::park();
     }
+
+    if !node_handle.shutdown(Duration::from_millis(args.shutdown_timeout_ms)) {
+        process::exit(1);
+    }
 }
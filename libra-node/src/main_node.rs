@@ -1,55 +1,156 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::debug_adapters::{MempoolStateIntrospection, WatchedAccounts};
 use admission_control_proto::proto::admission_control::{
     create_admission_control, AdmissionControlClient,
 };
-use admission_control_service::admission_control_service::AdmissionControlService;
-use config::config::{NetworkConfig, NodeConfig, RoleType};
+use admission_control_service::{
+    admission_control_service::AdmissionControlService,
+    json_gateway::JsonGateway,
+    upstream::{serve_network_submissions, UpstreamForwarder},
+};
+use config::config::{
+    AdmissionControlTlsConfig, NetworkConfig, NodeConfig, RoleType, SecureTransport,
+};
 use consensus::consensus_provider::{make_consensus_provider, ConsensusProvider};
 use crypto::{ed25519::*, ValidKey};
-use debug_interface::{node_debug_service::NodeDebugService, proto::create_node_debug_interface};
+use debug_interface::{
+    account_watcher::AccountWatcher, mempool_introspector::MempoolIntrospector,
+    node_debug_service::NodeDebugService, proto::create_node_debug_interface,
+};
+use disk_monitor::DiskMonitor;
 use executor::Executor;
-use futures::future::{FutureExt, TryFutureExt};
-use grpc_helpers::ServerHandle;
-use grpcio::{ChannelBuilder, EnvBuilder, ServerBuilder};
+use futures::{
+    compat::Future01CompatExt,
+    executor::block_on,
+    future::{join_all, FutureExt, TryFutureExt},
+};
+use grpc_helpers::{connect_internal, ServerHandle};
+use grpcio::{
+    CertificateRequestType, ChannelBuilder, EnvBuilder, ServerBuilder, ServerCredentialsBuilder,
+};
 use logger::prelude::*;
 use mempool::{proto::mempool::MempoolClient, MempoolRuntime};
-use metrics::metric_server;
+use metrics::{metric_server, telemetry::TelemetryReporter};
+use netcore::transport::{
+    fault::{FaultInjector, RandomFaults},
+    tcp::TcpTransport,
+};
 use network::{
+    build_tcp_socket_transport,
+    shared_listener::{NetworkTransport, SharedListener},
     validator_network::{
         network_builder::{NetworkBuilder, TransportType},
-        LibraNetworkProvider, CONSENSUS_DIRECT_SEND_PROTOCOL, CONSENSUS_RPC_PROTOCOL,
+        AdmissionControlNetworkEvents, AdmissionControlNetworkSender, LibraNetworkProvider,
+        ADMISSION_CONTROL_RPC_PROTOCOL, CONSENSUS_DIRECT_SEND_PROTOCOL, CONSENSUS_RPC_PROTOCOL,
         MEMPOOL_DIRECT_SEND_PROTOCOL, STATE_SYNCHRONIZER_MSG_PROTOCOL,
+        STATE_SYNCHRONIZER_RPC_PROTOCOL,
     },
-    NetworkPublicKeys, ProtocolId,
+    NetworkPublicKeys, NetworkShutdownHandle, PeerStore, ProtocolId,
 };
+use parity_multiaddr::Multiaddr;
 use state_synchronizer::StateSynchronizer;
 use std::{
     cmp::min,
+    collections::HashMap,
     convert::{TryFrom, TryInto},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use storage_client::{StorageRead, StorageReadServiceClient, StorageWriteServiceClient};
 use storage_service::start_storage_service;
+use tls::TlsConfig;
 use tokio::runtime::{Builder, Runtime};
+use trusted_ledger::TrustedLedger;
 use types::account_address::AccountAddress as PeerId;
 use vm_runtime::MoveVM;
 use vm_validator::vm_validator::VMValidator;
 
+/// File of the data directory the watch list of the debug interface is persisted to.
+const WATCHED_ACCOUNTS_FILE: &str = "watched_accounts";
+
 pub struct LibraHandle {
-    _ac: ServerHandle,
-    _mempool: Option<MempoolRuntime>,
-    _state_synchronizer: StateSynchronizer,
-    _network_runtimes: Vec<Runtime>,
+    // the unauthenticated AC server, then the one of each identity of the authenticated clients
+    ac: Vec<ServerHandle>,
+    mempool: Option<MempoolRuntime>,
+    state_synchronizer: Option<StateSynchronizer>,
+    network_runtimes: Vec<Runtime>,
+    network_shutdown_handles: Vec<NetworkShutdownHandle>,
     consensus: Option<Box<dyn ConsensusProvider>>,
-    _storage: ServerHandle,
+    executor: Arc<Executor<MoveVM>>,
+    storage: Option<ServerHandle>,
     _debug: ServerHandle,
 }
 
+impl LibraHandle {
+    /// Shuts the node down, stopping each component only once the components feeding it are
+    /// stopped:
+    ///
+    /// 1. AC stops accepting transactions and completes the submissions in flight.
+    /// 2. Mempool stops serving AC and consensus and broadcasts its ready transactions a last time.
+    /// 3. Consensus stops. Its safety data is persisted before being acted upon, so it can stop in
+    ///    the middle of a round.
+    /// 4. State synchronizer stops.
+    /// 5. The executor saves all the committed blocks to storage.
+    /// 6. Storage completes the requests in flight and closes the DB.
+    /// 7. The networks write out the messages queued so far. They then send a GoAway to their
+    ///    peers and complete the rpcs in flight before closing their connections and stopping.
+    ///
+    /// Returns whether the whole sequence completed within `timeout`. Otherwise, it returns as
+    /// soon as the deadline passes, leaving the thread of the current step running for the
+    /// process to exit.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let ac = std::mem::replace(&mut self.ac, vec![]);
+        let mempool = self.mempool.take();
+        let consensus = self.consensus.take();
+        let state_synchronizer = self.state_synchronizer.take();
+        let executor = Arc::clone(&self.executor);
+        let storage = self.storage.take();
+        let network_runtimes = std::mem::replace(&mut self.network_runtimes, vec![]);
+        let network_shutdown_handles =
+            std::mem::replace(&mut self.network_shutdown_handles, vec![]);
+
+        run_shutdown_step("admission control", deadline, move || {
+            for server in ac {
+                server.shutdown();
+            }
+        }) && run_shutdown_step("mempool", deadline, move || {
+            if let Some(mempool) = mempool {
+                mempool.shutdown();
+            }
+        }) && run_shutdown_step("consensus", deadline, move || {
+            if let Some(mut consensus) = consensus {
+                consensus.stop();
+            }
+        }) && run_shutdown_step("state synchronizer", deadline, move || {
+            drop(state_synchronizer);
+        }) && run_shutdown_step("executor", deadline, move || {
+            executor.shutdown();
+        }) && run_shutdown_step("storage", deadline, move || {
+            if let Some(storage) = storage {
+                storage.shutdown();
+            }
+        }) && run_shutdown_step("network", deadline, move || {
+            block_on(join_all(
+                network_shutdown_handles
+                    .iter()
+                    .map(|handle| handle.shutdown(NETWORK_DRAIN_TIMEOUT)),
+            ));
+            for runtime in network_runtimes {
+                block_on(runtime.shutdown_now().compat())
+                    .expect("Failed to shut down network runtime");
+            }
+        })
+    }
+}
+
 impl Drop for LibraHandle {
     fn drop(&mut self) {
         if let Some(consensus) = &mut self.consensus {
@@ -58,7 +159,52 @@ impl Drop for LibraHandle {
     }
 }
 
-fn setup_ac(config: &NodeConfig) -> (::grpcio::Server, AdmissionControlClient) {
+/// How long the networks wait for the messages queued to be written out, then for the rpcs their
+/// peers sent to complete before closing the connections.
+const NETWORK_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs a step of the node shutdown on its own thread, which is joined unless the step is still
+/// running at `deadline`. Returns whether it completed before then.
+fn run_shutdown_step<F>(name: &'static str, deadline: Instant, step: F) -> bool
+where
+    F: FnOnce() + Send + 'static,
+{
+    let instant = Instant::now();
+    let (done_sender, done_receiver) = mpsc::channel();
+    let step_thread = thread::Builder::new()
+        .name(format!("shutdown-{}", name.replace(' ', "-")))
+        .spawn(move || {
+            step();
+            let _ = done_sender.send(());
+        })
+        .expect("Failed to spawn shutdown thread");
+    match done_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(()) => {
+            step_thread
+                .join()
+                .expect("Shutdown thread panicked after its step completed");
+            info!("Shut down {} in {} ms", name, instant.elapsed().as_millis());
+            true
+        }
+        // The sender is dropped without notifying if the step panics.
+        Err(RecvTimeoutError::Disconnected) => {
+            let _ = step_thread.join();
+            error!("Failed to shut down {}", name);
+            false
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            error!("Timed out shutting down {}", name);
+            false
+        }
+    }
+}
+
+fn setup_ac(
+    config: &NodeConfig,
+    trusted_ledger: TrustedLedger,
+    disk_monitor: DiskMonitor,
+    ac_network_handles: Vec<(AdmissionControlNetworkSender, AdmissionControlNetworkEvents)>,
+) -> (Vec<::grpcio::Server>, AdmissionControlClient) {
     let env = Arc::new(
         EnvBuilder::new()
             .name_prefix("grpc-ac-")
@@ -71,9 +217,11 @@ fn setup_ac(config: &NodeConfig) -> (::grpcio::Server, AdmissionControlClient) {
     let connection_str = format!("localhost:{}", config.mempool.mempool_service_port);
     let env2 = Arc::new(EnvBuilder::new().name_prefix("grpc-ac-mem-").build());
     let mempool_client = if config.is_validator() {
-        Some(Arc::new(MempoolClient::new(
-            ChannelBuilder::new(env2).connect(&connection_str),
-        )))
+        Some(Arc::new(MempoolClient::new(connect_internal(
+            ChannelBuilder::new(env2),
+            &connection_str,
+            "ac-mempool",
+        ))))
     } else {
         None
     };
@@ -87,27 +235,131 @@ fn setup_ac(config: &NodeConfig) -> (::grpcio::Server, AdmissionControlClient) {
 
     let vm_validator = Arc::new(VMValidator::new(&config, Arc::clone(&storage_client)));
 
-    let handle = AdmissionControlService::new(
+    let mut handle = AdmissionControlService::new(
         mempool_client,
         storage_client,
         vm_validator,
         config
             .admission_control
             .need_to_check_mempool_before_validation,
-    );
+        trusted_ledger,
+        disk_monitor,
+    )
+    .with_submission_cache(
+        config.admission_control.submission_cache_capacity,
+        Duration::from_secs(config.admission_control.submission_cache_ttl_secs),
+        Duration::from_secs(config.admission_control.response_cache_ttl_secs),
+    )
+    .with_pre_validation(&config.admission_control.pre_validation);
+    if let Some(sender_rate_limit) = &config.admission_control.sender_rate_limit {
+        handle = handle.with_sender_rate_limit(sender_rate_limit);
+    }
+    if let Some(ip_rate_limit) = &config.admission_control.ip_rate_limit {
+        handle = handle.with_ip_rate_limit(ip_rate_limit);
+    }
+    if let Some(load_shedding) = &config.admission_control.load_shedding {
+        handle = handle.with_load_shedding(load_shedding);
+    }
+    if config.admission_control.async_submission_workers > 0 {
+        handle = handle.with_async_submission(
+            config.admission_control.async_submission_workers,
+            config.admission_control.async_submission_queue_size,
+        );
+    }
+    // Full nodes can't do anything useful while they are cut off from their upstream peers.
+    if !config.is_validator() {
+        handle = handle.with_network_health(Arc::new(network::connected_peers));
+        // The upstream peers of a full node are reached over its first network.
+        if let Some((network_sender, _)) = ac_network_handles.first() {
+            handle = handle.with_upstream(UpstreamForwarder::new(
+                network_sender.clone(),
+                &config.upstream,
+            ));
+        }
+    }
+    if let Some(max_sync_lag) = config.admission_control.max_sync_lag_versions {
+        handle = handle.with_max_sync_lag(max_sync_lag);
+    }
+    // Downstream nodes forward the transactions submitted to them over any network of the node.
+    for (index, (_, network_events)) in ac_network_handles.into_iter().enumerate() {
+        let handle = handle.clone();
+        thread::Builder::new()
+            .name(format!("ac-network-{}", index))
+            .spawn(move || serve_network_submissions(handle, network_events))
+            .expect("Unable to spawn the AC network thread");
+    }
+    // The clients of the unauthenticated port and of the JSON gateway share their own quota once
+    // the other clients authenticate with mutual TLS.
+    if let Some(tls) = &config.admission_control.tls {
+        handle = handle.with_identity_quota("unauthenticated", &tls.unauthenticated_quota);
+    }
+    if let Some(json_gateway_port) = config.admission_control.json_gateway_port {
+        let gateway = JsonGateway::new(handle.clone());
+        let address = config.admission_control.address.clone();
+        thread::spawn(move || gateway.run((address.as_str(), json_gateway_port)));
+    }
+    let mut servers = vec![];
+    if let Some(tls) = &config.admission_control.tls {
+        servers = setup_ac_identities(
+            &config.admission_control.address,
+            tls,
+            &handle,
+            Arc::clone(&env),
+        );
+    }
     let service = create_admission_control(handle);
     let server = ServerBuilder::new(Arc::clone(&env))
         .register_service(service)
         .bind(config.admission_control.address.clone(), port)
         .build()
         .expect("Unable to create grpc server");
+    servers.insert(0, server);
 
     let connection_str = format!("localhost:{}", port);
     let client = AdmissionControlClient::new(ChannelBuilder::new(env).connect(&connection_str));
-    (server, client)
+    (servers, client)
+}
+
+/// Serves each identity of the clients authenticated with mutual TLS on its own port, with its own
+/// quota. The gRPC handlers can't tell which certificate a client presented, so the identity of a
+/// client is told by the port it could connect to, which only completes the handshake of the
+/// clients presenting a certificate issued by the CA of the identity, which no other identity
+/// shares. The certificates were read along with the config.
+fn setup_ac_identities(
+    address: &str,
+    tls: &AdmissionControlTlsConfig,
+    handle: &AdmissionControlService<MempoolClient, VMValidator>,
+    env: Arc<grpcio::Environment>,
+) -> Vec<::grpcio::Server> {
+    tls.identities
+        .iter()
+        .map(|identity| {
+            let credentials = ServerCredentialsBuilder::new()
+                .root_cert(
+                    identity.client_ca_cert.clone(),
+                    CertificateRequestType::RequestAndRequireClientCertificateAndVerify,
+                )
+                .add_cert(tls.server_cert.clone(), tls.server_key.clone())
+                .build();
+            let service = create_admission_control(
+                handle
+                    .clone()
+                    .with_identity_quota(&identity.name, &identity.quota),
+            );
+            info!(
+                "AC serving identity {} on port {}",
+                identity.name, identity.port
+            );
+            ServerBuilder::new(Arc::clone(&env))
+                .register_service(service)
+                .bind_secure(address.to_string(), identity.port, credentials)
+                .build()
+                .expect("Unable to create grpc server")
+        })
+        .collect()
 }
 
-fn setup_executor(config: &NodeConfig) -> Arc<Executor<MoveVM>> {
+fn setup_executor(config: &NodeConfig, account_watcher: AccountWatcher) -> Arc<Executor<MoveVM>> {
     let client_env = Arc::new(EnvBuilder::new().name_prefix("grpc-exe-sto-").build());
     let storage_read_client = Arc::new(StorageReadServiceClient::new(
         Arc::clone(&client_env),
@@ -121,17 +373,29 @@ fn setup_executor(config: &NodeConfig) -> Arc<Executor<MoveVM>> {
         config.storage.grpc_max_receive_len,
     ));
 
-    Arc::new(Executor::new(
+    Arc::new(Executor::with_account_observer(
         Arc::clone(&storage_read_client) as Arc<dyn StorageRead>,
         storage_write_client,
         config,
+        Arc::new(WatchedAccounts(account_watcher)),
     ))
 }
 
-fn setup_debug_interface(config: &NodeConfig) -> ::grpcio::Server {
+fn setup_debug_interface(
+    config: &NodeConfig,
+    network_faults: Option<Arc<RandomFaults>>,
+    account_watcher: AccountWatcher,
+    mempool_introspector: MempoolIntrospector,
+) -> ::grpcio::Server {
     let env = Arc::new(EnvBuilder::new().name_prefix("grpc-debug-").build());
     // Start Debug interface
-    let debug_service = create_node_debug_interface(NodeDebugService::new());
+    let debug_service = match network_faults {
+        Some(network_faults) => NodeDebugService::with_network_faults(network_faults),
+        None => NodeDebugService::new(),
+    }
+    .with_account_watcher(account_watcher)
+    .with_mempool_introspector(mempool_introspector);
+    let debug_service = create_node_debug_interface(debug_service);
     ::grpcio::ServerBuilder::new(env)
         .register_service(debug_service)
         .bind(
@@ -146,6 +410,9 @@ fn setup_debug_interface(config: &NodeConfig) -> ::grpcio::Server {
 pub fn setup_network(
     peer_id: PeerId,
     config: &mut NetworkConfig,
+    network_transport: Option<NetworkTransport<TcpTransport>>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+    peer_store: Option<PeerStore>,
 ) -> (Runtime, Box<dyn LibraNetworkProvider>) {
     let runtime = Builder::new()
         .name_prefix("network-")
@@ -160,13 +427,45 @@ pub fn setup_network(
     );
     network_builder
         .permissioned(config.is_permissioned)
+        .software_version(env!("CARGO_PKG_VERSION").to_string())
+        .chain_id(config.chain_id.clone())
         .advertised_address(config.advertised_address.clone())
         .direct_send_protocols(vec![
             ProtocolId::from_static(CONSENSUS_DIRECT_SEND_PROTOCOL),
             ProtocolId::from_static(MEMPOOL_DIRECT_SEND_PROTOCOL),
             ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL),
         ])
-        .rpc_protocols(vec![ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL)]);
+        .rpc_protocols(vec![
+            ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL),
+            ProtocolId::from_static(ADMISSION_CONTROL_RPC_PROTOCOL),
+            ProtocolId::from_static(STATE_SYNCHRONIZER_RPC_PROTOCOL),
+        ])
+        .direct_send_batch_window_ms(config.direct_send_batch_window_ms)
+        .direct_send_max_batch_bytes(config.direct_send_max_batch_bytes)
+        .mempool_channel(config.mempool_channel.clone())
+        .consensus_channel(config.consensus_channel.clone())
+        .state_sync_channel(config.state_sync_channel.clone())
+        .relays(config.relays.clone())
+        .outbound_connections(config.outbound_connections.clone())
+        .max_inbound_per_subnet(config.max_inbound_per_subnet)
+        .peer_queue(config.peer_queue.clone())
+        .socket(config.socket.clone());
+    if let Some(relay_listen_address) = &config.relay_listen_address {
+        network_builder.relay_listen_address(relay_listen_address.clone());
+    }
+    // The networks of a node sharing a role are told apart by their ids.
+    if !config.network_id.is_empty() {
+        network_builder.metrics_label(format!("{}.{}", config.role, config.network_id));
+    }
+    if let Some(network_transport) = network_transport {
+        network_builder.shared_listener(network_transport);
+    }
+    if let Some(fault_injector) = fault_injector {
+        network_builder.fault_injector(fault_injector);
+    }
+    if let Some(peer_store) = peer_store {
+        network_builder.peer_store(peer_store);
+    }
     if config.is_permissioned {
         // If the node wants to run in permissioned mode, it should also have authentication and
         // encryption.
@@ -198,10 +497,16 @@ pub fn setup_network(
         let network_signing_private = config.network_keypairs.take_network_signing_private()
             .expect("Failed to move network signing private key out of NodeConfig, key not set or moved already");
         let network_signing_public: Ed25519PublicKey = (&network_signing_private).into();
-        network_builder
-            .transport(TransportType::TcpNoise(Some(
+        let transport = match config.secure_transport {
+            SecureTransport::Noise => TransportType::TcpNoise(Some(
                 config.network_keypairs.get_network_identity_keypair(),
-            )))
+            )),
+            SecureTransport::Tls => {
+                TransportType::TcpTls(Some(build_tls_config(&network_signing_private)))
+            }
+        };
+        network_builder
+            .transport(transport)
             .connectivity_check_interval_ms(config.connectivity_check_interval_ms)
             .seed_peers(seed_peers)
             .trusted_peers(trusted_peers)
@@ -209,10 +514,21 @@ pub fn setup_network(
             .discovery_interval_ms(config.discovery_interval_ms);
     } else if config.enable_encryption_and_authentication {
         // Even if a network end-point is permissionless, it might want to prove its identity to
-        // another peer it connects to. For this, we use TCP + Noise but in a permission-less way.
-        network_builder.transport(TransportType::PermissionlessTcpNoise(Some(
-            config.network_keypairs.get_network_identity_keypair(),
-        )));
+        // another peer it connects to. For this, we use TCP + Noise or TLS but in a
+        // permission-less way.
+        let transport = match config.secure_transport {
+            SecureTransport::Noise => TransportType::PermissionlessTcpNoise(Some(
+                config.network_keypairs.get_network_identity_keypair(),
+            )),
+            SecureTransport::Tls => {
+                let network_signing_private = config.network_keypairs.take_network_signing_private()
+                    .expect("Failed to move network signing private key out of NodeConfig, key not set or moved already");
+                TransportType::PermissionlessTcpTls(Some(build_tls_config(
+                    &network_signing_private,
+                )))
+            }
+        };
+        network_builder.transport(transport);
     } else {
         network_builder.transport(TransportType::Tcp);
     }
@@ -220,6 +536,11 @@ pub fn setup_network(
     (runtime, network_provider)
 }
 
+fn build_tls_config(network_signing_private: &Ed25519PrivateKey) -> TlsConfig {
+    TlsConfig::new(network_signing_private)
+        .expect("Failed to build a TLS certificate for the network signing key")
+}
+
 pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClient, LibraHandle) {
     crash_handler::setup_panic_handler();
 
@@ -237,18 +558,108 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClien
         instant.elapsed().as_millis()
     );
 
+    // The node stops taking in new data while its storage is about to run out of space.
+    let disk_monitor = DiskMonitor::new(
+        node_config.get_storage_dir(),
+        node_config.storage.min_free_space_bytes,
+    );
+    disk_monitor.spawn(Duration::from_millis(
+        node_config.storage.disk_check_interval_ms,
+    ));
+
     instant = Instant::now();
-    let executor = setup_executor(&node_config);
+    // Shared by the debug interface, which manages the watch list, and the executor, which reports
+    // the committed changes to the watched accounts.
+    let watched_accounts_file = node_config.base.data_dir_path.join(WATCHED_ACCOUNTS_FILE);
+    let account_watcher = AccountWatcher::with_file(watched_accounts_file).unwrap_or_else(|e| {
+        error!("Failed to read the watch list, starting without it: {}", e);
+        AccountWatcher::new()
+    });
+    let executor = setup_executor(&node_config, account_watcher.clone());
     debug!("Executor setup in {} ms", instant.elapsed().as_millis());
     let mut network_runtimes = vec![];
+    let mut network_shutdown_handles = vec![];
     let mut state_sync_network_handles = vec![];
+    let mut ac_network_handles = vec![];
+    // Faults injected into the connections of all the networks, at the request of the debug
+    // interface.
+    let network_faults = if node_config.debug_interface.enable_network_fault_injection {
+        warn!("Network fault injection is enabled");
+        Some(Arc::new(RandomFaults::new()))
+    } else {
+        None
+    };
     let mut validator_network_provider = None;
 
-    for mut network in &mut node_config.networks {
+    // Networks with a network id share a single listener with all the other networks listening on
+    // the same address, using the socket options of the first of these networks.
+    let mut shared_listeners: HashMap<Multiaddr, SharedListener<TcpTransport>> = HashMap::new();
+    let network_transports: Vec<_> = node_config
+        .networks
+        .iter()
+        .map(|network| {
+            if network.network_id.is_empty() {
+                return None;
+            }
+            let shared_listener = shared_listeners
+                .entry(network.listen_address.clone())
+                .or_insert_with(|| {
+                    SharedListener::new(
+                        build_tcp_socket_transport(&network.socket),
+                        network.listen_address.clone(),
+                    )
+                });
+            Some(shared_listener.add_network(ProtocolId::from(network.network_id.clone())))
+        })
+        .collect();
+    if !shared_listeners.is_empty() {
+        let runtime = Builder::new()
+            .name_prefix("shared-listener-")
+            .build()
+            .expect("Failed to start runtime. Won't be able to start networking.");
+        for (_, shared_listener) in shared_listeners {
+            debug!(
+                "Shared listener started on: {}",
+                shared_listener.listen_addr()
+            );
+            runtime
+                .executor()
+                .spawn(shared_listener.start().boxed().unit_error().compat());
+        }
+        network_runtimes.push(runtime);
+    }
+
+    let storage_dir = node_config.get_storage_dir();
+    for (mut network, network_transport) in node_config
+        .networks
+        .iter_mut()
+        .zip(network_transports.into_iter())
+    {
         let peer_id = PeerId::try_from(network.peer_id.clone()).expect("Invalid PeerId");
-        let (runtime, mut network_provider) = setup_network(peer_id, &mut network);
+        // Each network of the node has a store of its own.
+        let peer_store = if network.persist_peers {
+            Some(PeerStore::new(
+                storage_dir.join("peerstore").join(&network.peer_id),
+            ))
+        } else {
+            None
+        };
+        let (runtime, mut network_provider) = setup_network(
+            peer_id,
+            &mut network,
+            network_transport,
+            network_faults
+                .clone()
+                .map(|network_faults| network_faults as Arc<dyn FaultInjector>),
+            peer_store,
+        );
+        network_shutdown_handles.push(network_provider.shutdown_handle());
         state_sync_network_handles.push(network_provider.add_state_synchronizer(vec![
             ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL),
+            ProtocolId::from_static(STATE_SYNCHRONIZER_RPC_PROTOCOL),
+        ]));
+        ac_network_handles.push(network_provider.add_admission_control(vec![
+            ProtocolId::from_static(ADMISSION_CONTROL_RPC_PROTOCOL),
         ]));
         if let RoleType::Validator = (&network.role).into() {
             validator_network_provider = Some((peer_id, runtime, network_provider));
@@ -274,16 +685,40 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClien
         }
     }
 
-    let debug_if = ServerHandle::setup(setup_debug_interface(&node_config));
+    // Mempool registers itself once it is started, so that the debug interface reports its state.
+    let mempool_introspector = MempoolIntrospector::new();
+    let debug_if = ServerHandle::setup(setup_debug_interface(
+        &node_config,
+        network_faults,
+        account_watcher,
+        mempool_introspector.clone(),
+    ));
 
     let metrics_port = node_config.debug_interface.metrics_server_port;
     let metric_host = node_config.debug_interface.address.clone();
     thread::spawn(move || metric_server::start_server((metric_host.as_str(), metrics_port)));
 
+    if node_config.telemetry.enabled {
+        let role = if node_config.is_validator() {
+            "validator"
+        } else {
+            "full_node"
+        };
+        if let Err(e) = TelemetryReporter::new(env!("CARGO_PKG_VERSION").to_string(), role).start(
+            &node_config.telemetry.collector_url,
+            Duration::from_millis(node_config.telemetry.report_interval_ms),
+        ) {
+            error!("Failed to start telemetry reporting: {}", e);
+        }
+    }
+
+    let trusted_ledger = TrustedLedger::new();
     let state_synchronizer = StateSynchronizer::bootstrap(
         state_sync_network_handles,
         Arc::clone(&executor),
         &node_config,
+        trusted_ledger.clone(),
+        disk_monitor.clone(),
     );
     let mut mempool = None;
     let mut consensus = None;
@@ -312,11 +747,12 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClien
 
         // Initialize and start mempool.
         instant = Instant::now();
-        mempool = Some(MempoolRuntime::bootstrap(
-            &node_config,
-            mempool_network_sender,
-            mempool_network_events,
-        ));
+        let mempool_runtime =
+            MempoolRuntime::bootstrap(&node_config, mempool_network_sender, mempool_network_events);
+        mempool_introspector.register(Arc::new(MempoolStateIntrospection(
+            mempool_runtime.state_reader(),
+        )));
+        mempool = Some(mempool_runtime);
         debug!("Mempool started in {} ms", instant.elapsed().as_millis());
 
         // Initialize and start consensus.
@@ -325,8 +761,10 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClien
             node_config,
             consensus_network_sender,
             consensus_network_events,
-            executor,
+            Arc::clone(&executor),
             state_synchronizer.create_client(),
+            trusted_ledger.clone(),
+            disk_monitor.clone(),
         );
         consensus_provider
             .start()
@@ -337,17 +775,24 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> (AdmissionControlClien
 
     // Initialize and start AC.
     instant = Instant::now();
-    let (ac_server, ac_client) = setup_ac(&node_config);
-    let ac = ServerHandle::setup(ac_server);
+    let (ac_servers, ac_client) = setup_ac(
+        &node_config,
+        trusted_ledger,
+        disk_monitor,
+        ac_network_handles,
+    );
+    let ac = ac_servers.into_iter().map(ServerHandle::setup).collect();
     debug!("AC started in {} ms", instant.elapsed().as_millis());
 
     let libra_handle = LibraHandle {
-        _network_runtimes: network_runtimes,
-        _ac: ac,
-        _mempool: mempool,
-        _state_synchronizer: state_synchronizer,
+        network_runtimes,
+        network_shutdown_handles,
+        ac,
+        mempool,
+        state_synchronizer: Some(state_synchronizer),
         consensus,
-        _storage: storage,
+        executor,
+        storage: Some(storage),
         _debug: debug_if,
     };
     (ac_client, libra_handle)
@@ -0,0 +1,459 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Offline sanity checks behind `libra-node self-test`.
+//!
+//! The self test is meant to be run by an operator before starting the real service. It never
+//! writes to storage: the DB is opened read-only, or not at all if the node has never run, and the
+//! only side effect is a test dial to each seed peer.
+
+use config::config::{NodeConfig, RoleType};
+use crypto::{hash::CryptoHash, HashValue};
+use libradb::LibraDB;
+use parity_multiaddr::{Multiaddr, Protocol};
+use std::{
+    fmt,
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+use types::{account_address::AccountAddress as PeerId, transaction::Version};
+
+#[cfg(test)]
+#[path = "self_test_test.rs"]
+mod self_test_test;
+
+/// Outcome of a single self-test check.
+#[derive(Debug)]
+pub enum CheckStatus {
+    Pass(String),
+    Fail(String),
+    /// The check could not be performed, e.g. because a check it depends on failed.
+    Skip(String),
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus) -> Self {
+        Self { name, status }
+    }
+}
+
+/// Structured summary of all the checks performed by [`run_self_test`].
+#[derive(Debug)]
+pub struct SelfTestReport {
+    results: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    /// Returns true if no check failed. Skipped checks do not fail the self test.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| match result.status {
+            CheckStatus::Fail(_) => false,
+            _ => true,
+        })
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            let (tag, detail) = match &result.status {
+                CheckStatus::Pass(detail) => ("PASS", detail),
+                CheckStatus::Fail(detail) => ("FAIL", detail),
+                CheckStatus::Skip(detail) => ("SKIP", detail),
+            };
+            writeln!(f, "[{}] {}: {}", tag, result.name, detail)?;
+        }
+        write!(
+            f,
+            "self-test {}",
+            if self.passed() { "passed" } else { "failed" }
+        )
+    }
+}
+
+/// Ledger info the operator trusts, e.g. the one ending an epoch published out of band, which the
+/// committed ledger must agree with. Written as `<version>:<hex of the hash of the ledger info>`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Waypoint {
+    pub version: Version,
+    pub ledger_info_hash: HashValue,
+}
+
+impl FromStr for Waypoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let (version, hash) = match (parts.next(), parts.next()) {
+            (Some(version), Some(hash)) => (version, hash),
+            _ => return Err(format!("waypoint {:?} is not <version>:<hash>", s)),
+        };
+        let version = version
+            .parse()
+            .map_err(|e| format!("invalid waypoint version {:?}: {}", version, e))?;
+        let ledger_info_hash = hex::decode(hash)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| HashValue::from_slice(&bytes).map_err(|e| e.to_string()))
+            .map_err(|e| format!("invalid waypoint hash {:?}: {}", hash, e))?;
+        Ok(Self {
+            version,
+            ledger_info_hash,
+        })
+    }
+}
+
+/// Runs all the checks against `config`, and against `waypoint` if given. `dial_timeout` bounds
+/// each test dial to a seed peer.
+pub fn run_self_test(
+    config: &NodeConfig,
+    waypoint: Option<&Waypoint>,
+    dial_timeout: Duration,
+) -> SelfTestReport {
+    let db = open_storage(&config.get_storage_dir());
+    let results = vec![
+        check_config(config),
+        check_storage(&db),
+        check_genesis(config, &db),
+        check_waypoint(waypoint, &db),
+        check_identity(config, db.as_ref().ok().and_then(Option::as_ref)),
+        check_seed_peers(config, dial_timeout),
+    ];
+    SelfTestReport { results }
+}
+
+/// Opens the storage under `storage_dir` read-only. Returns `None` if the node has never created
+/// it, i.e. on a fresh node which has yet to bootstrap.
+fn open_storage(storage_dir: &Path) -> Result<Option<LibraDB>, String> {
+    if !LibraDB::exists(storage_dir) {
+        return Ok(None);
+    }
+    LibraDB::open_readonly(storage_dir)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+fn check_config(config: &NodeConfig) -> CheckResult {
+    let mut errors = vec![];
+    if config.networks.is_empty() {
+        errors.push("no network configured".to_string());
+    }
+    let num_validator_networks = config
+        .networks
+        .iter()
+        .filter(|network| RoleType::Validator == (&network.role).into())
+        .count();
+    if num_validator_networks > 1 {
+        errors.push(format!(
+            "{} validator networks configured, at most 1 is allowed",
+            num_validator_networks
+        ));
+    }
+    for network in &config.networks {
+        let peer_id = match PeerId::from_str(&network.peer_id) {
+            Ok(peer_id) => peer_id,
+            Err(e) => {
+                errors.push(format!("invalid peer id {:?}: {}", network.peer_id, e));
+                continue;
+            }
+        };
+        if network.is_permissioned && !network.enable_encryption_and_authentication {
+            errors.push(format!(
+                "network {} is permissioned but authentication is disabled",
+                peer_id
+            ));
+        }
+        if RoleType::FullNode == (&network.role).into() {
+            // For non-validator roles, the peer_id must be derived from the network key the
+            // secure transport authenticates with.
+            if network.derived_peer_id() != peer_id {
+                errors.push(format!(
+                    "peer id {} does not match the network key of its {:?} transport",
+                    peer_id, network.secure_transport
+                ));
+            }
+        }
+    }
+    if config.is_validator() && !config.consensus.consensus_keypair.is_present() {
+        errors.push("validator has no consensus private key".to_string());
+    }
+
+    let status = if errors.is_empty() {
+        CheckStatus::Pass(format!("{} network(s) configured", config.networks.len()))
+    } else {
+        CheckStatus::Fail(errors.join("; "))
+    };
+    CheckResult::new("config", status)
+}
+
+fn check_storage(db: &Result<Option<LibraDB>, String>) -> CheckResult {
+    let status = match db {
+        Err(e) => CheckStatus::Fail(format!("unable to open storage read-only: {}", e)),
+        Ok(None) => CheckStatus::Pass("no storage yet, node will bootstrap".to_string()),
+        Ok(Some(db)) => match db.get_startup_info() {
+            Err(e) => CheckStatus::Fail(format!("unable to read startup info: {}", e)),
+            Ok(None) => CheckStatus::Pass("storage is empty, node will bootstrap".to_string()),
+            Ok(Some(startup_info)) => CheckStatus::Pass(format!(
+                "latest version {}, epoch {}",
+                startup_info.latest_version,
+                startup_info.ledger_info.epoch_num()
+            )),
+        },
+    };
+    CheckResult::new("storage", status)
+}
+
+fn check_genesis(config: &NodeConfig, db: &Result<Option<LibraDB>, String>) -> CheckResult {
+    let genesis_file = config.get_genesis_transaction_file();
+    if !genesis_file.is_file() {
+        return CheckResult::new(
+            "genesis",
+            CheckStatus::Fail(format!("genesis file {:?} not found", genesis_file)),
+        );
+    }
+    let genesis_txn = match config.get_genesis_transaction() {
+        Ok(txn) => txn,
+        Err(e) => {
+            return CheckResult::new(
+                "genesis",
+                CheckStatus::Fail(format!("unable to decode genesis file: {}", e)),
+            )
+        }
+    };
+
+    let db = match db {
+        Ok(Some(db)) => db,
+        Ok(None) => {
+            return CheckResult::new(
+                "genesis",
+                CheckStatus::Pass("genesis file is valid, storage not bootstrapped yet".into()),
+            )
+        }
+        Err(_) => {
+            return CheckResult::new("genesis", CheckStatus::Skip("storage unavailable".into()))
+        }
+    };
+    let latest_version = match db.get_startup_info() {
+        Ok(Some(startup_info)) => startup_info.latest_version,
+        Ok(None) => {
+            return CheckResult::new(
+                "genesis",
+                CheckStatus::Pass("genesis file is valid, storage not bootstrapped yet".into()),
+            )
+        }
+        Err(e) => return CheckResult::new("genesis", CheckStatus::Skip(e.to_string())),
+    };
+
+    let status = match db.get_transactions(0, 1, latest_version, false /* fetch_events */) {
+        Err(e) => CheckStatus::Fail(format!("unable to read genesis from storage: {}", e)),
+        Ok(txn_list) => match txn_list.transaction_and_infos.first() {
+            Some((committed_genesis, _)) if *committed_genesis == genesis_txn => {
+                CheckStatus::Pass("genesis file matches committed genesis".to_string())
+            }
+            Some(_) => CheckStatus::Fail(
+                "genesis file does not match the genesis committed in storage".to_string(),
+            ),
+            None => CheckStatus::Fail("no genesis transaction in storage".to_string()),
+        },
+    };
+    CheckResult::new("genesis", status)
+}
+
+/// Checks that the ledger info committed at the version of `waypoint` is the one it names. Only the
+/// latest ledger info of each epoch is kept in storage, so the waypoint has to name one of them,
+/// e.g. the ledger info ending an epoch.
+fn check_waypoint(
+    waypoint: Option<&Waypoint>,
+    db: &Result<Option<LibraDB>, String>,
+) -> CheckResult {
+    let skip = |reason: String| CheckResult::new("waypoint", CheckStatus::Skip(reason));
+    let waypoint = match waypoint {
+        Some(waypoint) => waypoint,
+        None => return skip("no waypoint given".into()),
+    };
+    let db = match db {
+        Ok(Some(db)) => db,
+        Ok(None) => return skip("storage not bootstrapped yet".into()),
+        Err(_) => return skip("storage unavailable".into()),
+    };
+    match db.get_startup_info() {
+        Ok(Some(startup_info)) if startup_info.latest_version >= waypoint.version => (),
+        Ok(Some(startup_info)) => {
+            return skip(format!(
+                "storage at version {} has not reached the waypoint at version {}",
+                startup_info.latest_version, waypoint.version
+            ))
+        }
+        Ok(None) => return skip("storage not bootstrapped yet".into()),
+        Err(e) => return skip(e.to_string()),
+    }
+
+    let status = match db.get_latest_ledger_infos_per_epoch(0) {
+        Err(e) => CheckStatus::Fail(format!("unable to read ledger infos: {}", e)),
+        Ok(ledger_infos) => match ledger_infos
+            .iter()
+            .map(|li| li.ledger_info())
+            .find(|li| li.version() == waypoint.version)
+        {
+            Some(li) if li.hash() == waypoint.ledger_info_hash => CheckStatus::Pass(format!(
+                "ledger info at version {} matches the waypoint",
+                waypoint.version
+            )),
+            Some(li) => CheckStatus::Fail(format!(
+                "ledger info at version {} has hash {}, not the hash of the waypoint {}",
+                waypoint.version,
+                li.hash(),
+                waypoint.ledger_info_hash
+            )),
+            None => CheckStatus::Fail(format!(
+                "no epoch ending ledger info at the version {} of the waypoint",
+                waypoint.version
+            )),
+        },
+    };
+    CheckResult::new("waypoint", status)
+}
+
+fn check_identity(config: &NodeConfig, db: Option<&LibraDB>) -> CheckResult {
+    let network = match config.get_validator_network_config() {
+        Some(network) => network,
+        None => {
+            return CheckResult::new("identity", CheckStatus::Skip("not a validator".into()));
+        }
+    };
+    let peer_id = match PeerId::from_str(&network.peer_id) {
+        Ok(peer_id) => peer_id,
+        Err(_) => {
+            return CheckResult::new("identity", CheckStatus::Skip("invalid peer id".into()));
+        }
+    };
+    let signing_public = network.network_keypairs.get_network_signing_public();
+    let identity_public = network.network_keypairs.get_network_identity_public();
+    let consensus_public = config.consensus.consensus_keypair.get_consensus_public();
+
+    let mut errors = vec![];
+    match network.network_peers.peers.get(&network.peer_id) {
+        Some(info) => {
+            if &info.network_signing_pubkey != signing_public
+                || &info.network_identity_pubkey != identity_public
+            {
+                errors.push("network keys do not match network peers config".to_string());
+            }
+        }
+        None => errors.push("peer id missing from network peers config".to_string()),
+    }
+    match config.consensus.consensus_peers.peers.get(&network.peer_id) {
+        Some(info) => {
+            if Some(&info.consensus_pubkey) != consensus_public {
+                errors.push("consensus key does not match consensus peers config".to_string());
+            }
+        }
+        None => errors.push("peer id missing from consensus peers config".to_string()),
+    }
+
+    // The validator set of the latest reconfiguration committed to storage is the on-chain view
+    // of this validator's identity.
+    let validator_set = db
+        .and_then(|db| db.get_latest_ledger_infos_per_epoch(0).ok())
+        .and_then(|ledger_infos| {
+            ledger_infos
+                .into_iter()
+                .filter_map(|li| li.ledger_info().next_validator_set().cloned())
+                .last()
+        });
+    let checked_on_chain = validator_set.is_some();
+    if let Some(validator_set) = validator_set {
+        match validator_set
+            .payload()
+            .iter()
+            .find(|keys| *keys.account_address() == peer_id)
+        {
+            Some(keys) => {
+                if Some(keys.consensus_public_key()) != consensus_public {
+                    errors.push("consensus key does not match on-chain validator set".to_string());
+                }
+                if keys.network_signing_public_key() != signing_public
+                    || keys.network_identity_public_key() != identity_public
+                {
+                    errors.push("network keys do not match on-chain validator set".to_string());
+                }
+            }
+            None => errors.push("peer id missing from on-chain validator set".to_string()),
+        }
+    }
+
+    let status = if !errors.is_empty() {
+        CheckStatus::Fail(errors.join("; "))
+    } else if checked_on_chain {
+        CheckStatus::Pass("key material matches config and on-chain validator set".to_string())
+    } else {
+        CheckStatus::Pass("key material matches config, no on-chain validator set yet".to_string())
+    };
+    CheckResult::new("identity", status)
+}
+
+fn check_seed_peers(config: &NodeConfig, dial_timeout: Duration) -> CheckResult {
+    let mut num_dialed = 0;
+    let mut unreachable = vec![];
+    for network in &config.networks {
+        let mut network_reachable = false;
+        let mut network_unreachable = vec![];
+        for (peer_id, addrs) in &network.seed_peers.seed_peers {
+            if *peer_id == network.peer_id {
+                continue;
+            }
+            for addr in addrs {
+                num_dialed += 1;
+                match multiaddr_to_socketaddr(addr)
+                    .and_then(|socket_addr| dial(&socket_addr, dial_timeout))
+                {
+                    Ok(()) => network_reachable = true,
+                    Err(e) => network_unreachable.push(format!("{} ({})", addr, e)),
+                }
+            }
+        }
+        // A single reachable seed is enough to join the network.
+        if !network_reachable {
+            unreachable.extend(network_unreachable);
+        }
+    }
+
+    let status = if num_dialed == 0 {
+        CheckStatus::Skip("no seed peers configured".to_string())
+    } else if unreachable.is_empty() {
+        CheckStatus::Pass(format!("dialed {} seed peer address(es)", num_dialed))
+    } else {
+        CheckStatus::Fail(format!("unreachable: {}", unreachable.join(", ")))
+    };
+    CheckResult::new("seed_peers", status)
+}
+
+fn dial(addr: &SocketAddr, timeout: Duration) -> Result<(), String> {
+    TcpStream::connect_timeout(addr, timeout)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Result<SocketAddr, String> {
+    let mut iter = addr.iter();
+    match (iter.next(), iter.next()) {
+        (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port))) => {
+            Ok(SocketAddr::new(ip.into(), port))
+        }
+        (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port))) => {
+            Ok(SocketAddr::new(ip.into(), port))
+        }
+        _ => Err("unsupported address".to_string()),
+    }
+}
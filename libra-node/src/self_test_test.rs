@@ -0,0 +1,89 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::self_test::{
+    check_storage, check_waypoint, multiaddr_to_socketaddr, open_storage, CheckStatus, Waypoint,
+};
+use crypto::{hash::CryptoHash, HashValue};
+use libradb::mock_genesis::{db_with_mock_genesis, GENESIS_INFO};
+use std::str::FromStr;
+use tools::tempdir::TempPath;
+
+fn genesis_waypoint() -> Waypoint {
+    Waypoint {
+        version: 0,
+        ledger_info_hash: GENESIS_INFO.1.ledger_info().hash(),
+    }
+}
+
+#[test]
+fn test_parse_waypoint() {
+    let waypoint = genesis_waypoint();
+    let s = format!("0:{}", hex::encode(waypoint.ledger_info_hash.to_vec()));
+    assert_eq!(Waypoint::from_str(&s).unwrap(), waypoint);
+
+    assert!(Waypoint::from_str("0").is_err());
+    assert!(Waypoint::from_str("x:00").is_err());
+    assert!(Waypoint::from_str("0:00").is_err());
+}
+
+#[test]
+fn test_fresh_storage() {
+    let dir = TempPath::new();
+    let db = open_storage(dir.path());
+    assert!(db.as_ref().unwrap().is_none());
+
+    match check_storage(&db).status {
+        CheckStatus::Pass(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+    match check_waypoint(Some(&genesis_waypoint()), &db).status {
+        CheckStatus::Skip(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+}
+
+#[test]
+fn test_waypoint() {
+    let dir = TempPath::new();
+    drop(db_with_mock_genesis(&dir).unwrap());
+    let db = open_storage(dir.path());
+    assert!(db.as_ref().unwrap().is_some());
+
+    match check_waypoint(None, &db).status {
+        CheckStatus::Skip(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+    match check_waypoint(Some(&genesis_waypoint()), &db).status {
+        CheckStatus::Pass(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+
+    let wrong_hash = Waypoint {
+        version: 0,
+        ledger_info_hash: HashValue::random(),
+    };
+    match check_waypoint(Some(&wrong_hash), &db).status {
+        CheckStatus::Fail(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+
+    // Storage has yet to sync up to the waypoint.
+    let ahead = Waypoint {
+        version: 10,
+        ..genesis_waypoint()
+    };
+    match check_waypoint(Some(&ahead), &db).status {
+        CheckStatus::Skip(_) => (),
+        status => panic!("Unexpected status {:?}", status),
+    }
+}
+
+#[test]
+fn test_multiaddr_to_socketaddr() {
+    assert_eq!(
+        multiaddr_to_socketaddr(&"/ip4/127.0.0.1/tcp/6180".parse().unwrap()).unwrap(),
+        "127.0.0.1:6180".parse().unwrap()
+    );
+    assert!(multiaddr_to_socketaddr(&"/dns4/example.com/tcp/6180".parse().unwrap()).is_err());
+}
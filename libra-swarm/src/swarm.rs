@@ -9,7 +9,7 @@ use debug_interface::NodeDebugClient;
 use failure::prelude::*;
 use logger::prelude::*;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env,
     fs::File,
     io::{self, Read},
@@ -20,6 +20,7 @@ use std::{
 use tools::tempdir::TempPath;
 
 const LIBRA_NODE_BIN: &str = "libra-node";
+const BYZANTINE_NODE_BIN: &str = "byzantine-node";
 
 pub struct LibraNode {
     node: Child,
@@ -53,15 +54,53 @@ impl LibraNode {
         config_path: &Path,
         log_path: PathBuf,
         disable_logging: bool,
+    ) -> Result<Self> {
+        Self::launch_bin(
+            LIBRA_NODE_BIN,
+            &[],
+            node_id,
+            config_path,
+            log_path,
+            disable_logging,
+        )
+    }
+
+    /// Launches a validator which deviates from the consensus protocol following `behavior`
+    /// (e.g. "equivocating-proposer", "vote-withholder" or "garbage-spammer").
+    pub fn launch_byzantine(
+        node_id: String,
+        config_path: &Path,
+        log_path: PathBuf,
+        disable_logging: bool,
+        behavior: &str,
+    ) -> Result<Self> {
+        Self::launch_bin(
+            BYZANTINE_NODE_BIN,
+            &["--behavior", behavior],
+            node_id,
+            config_path,
+            log_path,
+            disable_logging,
+        )
+    }
+
+    fn launch_bin(
+        bin: &str,
+        args: &[&str],
+        node_id: String,
+        config_path: &Path,
+        log_path: PathBuf,
+        disable_logging: bool,
     ) -> Result<Self> {
         let config = NodeConfig::load(&config_path)
             .unwrap_or_else(|_| panic!("Failed to load NodeConfig from file: {:?}", config_path));
         let log_file = File::create(&log_path)?;
-        let mut node_command = Command::new(utils::get_bin(LIBRA_NODE_BIN));
+        let mut node_command = Command::new(utils::get_bin(bin));
         node_command
             .current_dir(utils::workspace_root())
             .arg("-f")
-            .arg(config_path);
+            .arg(config_path)
+            .args(args);
         if env::var("RUST_LOG").is_err() {
             // Only set our RUST_LOG if its not present in environment
             node_command.env("RUST_LOG", "debug");
@@ -117,6 +156,23 @@ impl LibraNode {
         }
     }
 
+    /// Digest of the mempool of the node: for each account with transactions in mempool, the
+    /// number of its transactions and the number of them which are ready for the next block.
+    pub fn mempool_digest(&self) -> Result<BTreeMap<Vec<u8>, (u64, u64)>> {
+        // All the accounts are reported, since the digests are compared as a whole.
+        let state = self.debug_client.get_mempool_state(u32::max_value())?;
+        Ok(state
+            .accounts
+            .into_iter()
+            .map(|account| {
+                (
+                    account.address,
+                    (account.num_transactions, account.num_ready),
+                )
+            })
+            .collect())
+    }
+
     pub fn check_connectivity(&self, expected_peers: i64) -> bool {
         if let Some(num_connected_peers) = self.get_metric("network_gauge{op=connected_peers}") {
             if num_connected_peers < expected_peers {
@@ -470,6 +526,40 @@ impl LibraSwarm {
         false
     }
 
+    /// Waits for the mempools of all the nodes to report the same digest, i.e. the same
+    /// transactions and the same ready ones for each account, for up to `num_attempts` seconds.
+    /// Returns the digest they converged to, if they did.
+    pub fn wait_for_mempools_to_converge(
+        &self,
+        num_attempts: usize,
+    ) -> Option<BTreeMap<Vec<u8>, (u64, u64)>> {
+        for i in 0..num_attempts {
+            let digests: Vec<_> = self
+                .nodes
+                .values()
+                .filter_map(|node| match node.mempool_digest() {
+                    Ok(digest) => Some(digest),
+                    Err(e) => {
+                        debug!("Failed to get the mempool of node {}: {}", node.node_id, e);
+                        None
+                    }
+                })
+                .collect();
+            if digests.len() == self.nodes.len()
+                && digests.windows(2).all(|pair| pair[0] == pair[1])
+            {
+                return digests.into_iter().next();
+            }
+            debug!(
+                "Mempools did not converge yet, attempt: {} of {}",
+                i + 1,
+                num_attempts
+            );
+            ::std::thread::sleep(::std::time::Duration::from_millis(1000));
+        }
+        None
+    }
+
     /// A specific public AC port of a validator or a full node.
     pub fn get_ac_port(&self, index: usize) -> u16 {
         let node_id = format!("{}", index);
@@ -517,8 +607,47 @@ impl LibraSwarm {
             .unwrap_or_else(|| panic!("Node at index {} not found", idx));
         let log_file_path = self.dir.as_ref().join("logs").join(format!("{}.log", idx));
         let node_id = format!("{}", idx);
-        let mut node =
+        let node =
             LibraNode::launch(node_id.clone(), path, log_file_path, disable_logging).unwrap();
+        self.wait_for_added_node(node_id, node)
+    }
+
+    /// Restarts the node at `idx` as a byzantine validator following `behavior`, keeping its
+    /// config and storage. See `LibraNode::launch_byzantine`.
+    pub fn restart_as_byzantine(
+        &mut self,
+        idx: usize,
+        behavior: &str,
+        disable_logging: bool,
+    ) -> std::result::Result<(), SwarmLaunchFailure> {
+        self.kill_node(idx);
+        let path = self
+            .config
+            .configs
+            .get(idx)
+            .unwrap_or_else(|| panic!("Node at index {} not found", idx));
+        let log_file_path = self
+            .dir
+            .as_ref()
+            .join("logs")
+            .join(format!("{}.byzantine.log", idx));
+        let node_id = format!("{}", idx);
+        let node = LibraNode::launch_byzantine(
+            node_id.clone(),
+            path,
+            log_file_path,
+            disable_logging,
+            behavior,
+        )
+        .unwrap();
+        self.wait_for_added_node(node_id, node)
+    }
+
+    fn wait_for_added_node(
+        &mut self,
+        node_id: String,
+        mut node: LibraNode,
+    ) -> std::result::Result<(), SwarmLaunchFailure> {
         for _ in 0..60 {
             if let HealthStatus::Healthy = node.health_check() {
                 self.nodes.insert(node_id, node);
@@ -0,0 +1,90 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+// Allow fns to take &usize, since criterion only passes parameters by ref
+#![allow(clippy::trivially_copy_pass_by_ref)]
+
+//! Measures how reading the timeline of Mempool, pulling a block out of it and garbage collecting
+//! it scale with the number of transactions it holds, up to 500k resident transactions.
+//!
+//! Run with `cargo bench -p mempool --features bench --bench core_mempool_bench`.
+
+use config::config::NodeConfigHelpers;
+use criterion::{criterion_group, criterion_main, Bencher, Criterion, ParameterizedBenchmark};
+use crypto::ed25519::compat;
+use mempool::{CoreMempool, TimelineState};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{collections::HashSet, time::Duration};
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    transaction::{RawTransaction, Script},
+};
+
+const TXNS_PER_ACCOUNT: usize = 10;
+const BATCH_SIZE: usize = 100;
+
+/// Mempool holding `num_txns` ready transactions, spread over accounts with `TXNS_PER_ACCOUNT`
+/// transactions each and paying a range of gas unit prices
+fn make_mempool(num_txns: usize) -> CoreMempool {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = num_txns;
+    config.mempool.local_capacity = num_txns;
+    config.mempool.capacity_per_user = TXNS_PER_ACCOUNT;
+    let mut pool = CoreMempool::new(&config);
+
+    // CoreMempool doesn't check signatures, so all transactions are signed with the same key
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let (private_key, public_key) = compat::generate_keypair(&mut rng);
+    for i in 0..num_txns {
+        let mut address = [0u8; ADDRESS_LENGTH];
+        address[..8].copy_from_slice(&((i / TXNS_PER_ACCOUNT) as u64).to_le_bytes());
+        let txn = RawTransaction::new_script(
+            AccountAddress::new(address),
+            (i % TXNS_PER_ACCOUNT) as u64,
+            Script::new(vec![], vec![]),
+            /* max_gas_amount = */ 100,
+            /* gas_unit_price = */ (i % 1000) as u64,
+            Duration::from_secs(u64::max_value()),
+        )
+        .sign(&private_key, public_key.clone())
+        .expect("Failed to sign raw transaction.")
+        .into_inner();
+        pool.add_txn(txn, 100, 0, u64::max_value(), TimelineState::NotReady);
+    }
+    pool
+}
+
+fn read_timeline_bench(b: &mut Bencher, num_txns: &usize) {
+    let pool = make_mempool(*num_txns);
+    // read from the middle of the timeline, as a peer which is halfway through would
+    let timeline_id = (*num_txns / 2) as u64;
+    b.iter(|| pool.read_timeline(timeline_id, BATCH_SIZE));
+}
+
+fn get_block_bench(b: &mut Bencher, num_txns: &usize) {
+    let mut pool = make_mempool(*num_txns);
+    b.iter(|| pool.get_block(BATCH_SIZE as u64, HashSet::new()));
+}
+
+fn gc_bench(b: &mut Bencher, num_txns: &usize) {
+    let mut pool = make_mempool(*num_txns);
+    // none of the transactions expire, as on most commits
+    b.iter(|| pool.gc_by_expiration_time(Duration::from_secs(1)));
+}
+
+fn core_mempool_benchmark(c: &mut Criterion) {
+    c.bench(
+        "core_mempool",
+        ParameterizedBenchmark::new(
+            "read_timeline",
+            read_timeline_bench,
+            vec![10_000usize, 100_000, 500_000],
+        )
+        .with_function("get_block", get_block_bench)
+        .with_function("gc_by_expiration_time", gc_bench)
+        .sample_size(10),
+    );
+}
+
+criterion_group!(benches, core_mempool_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+// Allow fns to take &usize, since criterion only passes parameters by ref
+#![allow(clippy::trivially_copy_pass_by_ref)]
+
+//! Compares the serial verification of the signatures of a batch of transactions, as the VM did it
+//! while validating them one after the other, with their parallel verification by
+//! `SignatureVerifier`, after which the VM doesn't verify them again.
+
+use criterion::{criterion_group, criterion_main, Bencher, Criterion, ParameterizedBenchmark};
+use crypto::ed25519::compat;
+use futures_preview::executor::block_on;
+use mempool::SignatureVerifier;
+use rand::{rngs::StdRng, SeedableRng};
+use std::time::Duration;
+use types::{
+    account_address::AccountAddress,
+    transaction::{RawTransaction, Script, SignedTransaction},
+};
+
+fn make_batch(batch_size: usize) -> Vec<SignedTransaction> {
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let (private_key, public_key) = compat::generate_keypair(&mut rng);
+    let sender = AccountAddress::from_public_key(&public_key);
+    (0..batch_size as u64)
+        .map(|sequence_number| {
+            RawTransaction::new_script(
+                sender,
+                sequence_number,
+                Script::new(vec![], vec![]),
+                /* max_gas_amount = */ 100,
+                /* gas_unit_price = */ 1,
+                Duration::from_secs(u64::max_value()),
+            )
+            .sign(&private_key, public_key.clone())
+            .expect("Failed to sign raw transaction.")
+            .into_inner()
+        })
+        .collect()
+}
+
+fn serial_bench(b: &mut Bencher, batch_size: &usize) {
+    let batch = make_batch(*batch_size);
+    b.iter(|| {
+        batch
+            .iter()
+            .cloned()
+            .filter_map(|txn| txn.check_signature().ok())
+            .count()
+    });
+}
+
+fn parallel_bench(b: &mut Bencher, batch_size: &usize) {
+    let batch = make_batch(*batch_size);
+    let verifier = SignatureVerifier::new(0);
+    b.iter(|| block_on(verifier.verify_async(batch.clone())).len());
+}
+
+fn signature_verification_benchmark(c: &mut Criterion) {
+    c.bench(
+        "signature_verification",
+        ParameterizedBenchmark::new("serial", serial_bench, vec![10usize, 100, 1000])
+            .with_function("parallel", parallel_bench)
+            .sample_size(10),
+    );
+}
+
+criterion_group!(benches, signature_verification_benchmark);
+criterion_main!(benches);
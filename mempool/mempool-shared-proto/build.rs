@@ -3,7 +3,10 @@
 
 //! Builds the proto files needed for the mempool-shared-proto crate.
 fn main() {
-    let proto_files_prost = ["src/proto/mempool_status.proto"];
+    let proto_files_prost = [
+        "src/proto/gas_price.proto",
+        "src/proto/mempool_status.proto",
+    ];
     let includes = ["src/proto"];
     prost_build::compile_protos(&proto_files_prost, &includes).unwrap();
 }
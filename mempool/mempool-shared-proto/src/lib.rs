@@ -47,3 +47,70 @@ impl From<MempoolAddTransactionStatus>
         mempool_add_transaction_status
     }
 }
+
+/// Percentiles of the gas unit prices of a set of transactions, all zero when the set is empty
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasPricePercentiles {
+    /// Median gas unit price
+    pub p50: u64,
+    /// 90th percentile of the gas unit prices
+    pub p90: u64,
+    /// 99th percentile of the gas unit prices
+    pub p99: u64,
+    /// Number of transactions the percentiles are computed over
+    pub count: u64,
+}
+
+/// Estimate of the gas unit price a transaction needs to pay to be included in the next block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasPriceEstimate {
+    /// Gas unit prices of the transactions currently waiting in mempool
+    pub mempool: GasPricePercentiles,
+    /// Gas unit prices of the most recently committed transactions
+    pub committed: GasPricePercentiles,
+    /// Lowest gas unit price which outbids enough of the transactions in mempool to fit in the
+    /// next block
+    pub next_block_gas_price: u64,
+}
+
+impl From<crate::proto::gas_price::GasPricePercentiles> for GasPricePercentiles {
+    fn from(proto: crate::proto::gas_price::GasPricePercentiles) -> Self {
+        Self {
+            p50: proto.p50,
+            p90: proto.p90,
+            p99: proto.p99,
+            count: proto.count,
+        }
+    }
+}
+
+impl From<GasPricePercentiles> for crate::proto::gas_price::GasPricePercentiles {
+    fn from(percentiles: GasPricePercentiles) -> Self {
+        Self {
+            p50: percentiles.p50,
+            p90: percentiles.p90,
+            p99: percentiles.p99,
+            count: percentiles.count,
+        }
+    }
+}
+
+impl From<crate::proto::gas_price::GasPriceEstimate> for GasPriceEstimate {
+    fn from(proto: crate::proto::gas_price::GasPriceEstimate) -> Self {
+        Self {
+            mempool: proto.mempool.map(Into::into).unwrap_or_default(),
+            committed: proto.committed.map(Into::into).unwrap_or_default(),
+            next_block_gas_price: proto.next_block_gas_price,
+        }
+    }
+}
+
+impl From<GasPriceEstimate> for crate::proto::gas_price::GasPriceEstimate {
+    fn from(estimate: GasPriceEstimate) -> Self {
+        Self {
+            mempool: Some(estimate.mempool.into()),
+            committed: Some(estimate.committed.into()),
+            next_block_gas_price: estimate.next_block_gas_price,
+        }
+    }
+}
@@ -1,6 +1,10 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod gas_price {
+    include!(concat!(env!("OUT_DIR"), "/gas_price.rs"));
+}
+
 pub mod mempool_status {
     include!(concat!(env!("OUT_DIR"), "/mempool_status.rs"));
 }
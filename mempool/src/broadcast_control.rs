@@ -0,0 +1,118 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Flow control of the broadcasts of SharedMempool to each of its peers.
+//!
+//! Peers acknowledge each broadcast once they have processed its transactions, and are only sent
+//! the next one after that. The time from the send to the acknowledgement is the backpressure
+//! signal: it grows when the peer or the link to it is slow. While the acknowledgements are slow,
+//! the batches sent to the peer shrink and it is broadcast to on fewer ticks, and both recover
+//! while they are fast again (additive increase, multiplicative decrease). A peer whose last
+//! broadcasts failed or were not acknowledged in time is not broadcast to for a number of ticks
+//! doubling with every consecutive failure, so that the transactions meant for it don't pile up
+//! in the network stack.
+//!
+//! Peers running an older version never acknowledge broadcasts. For those, how long handing a
+//! message over to the network takes is the signal instead, as the send waits for room in the
+//! bounded channel to the connection with the peer.
+
+use config::config::MempoolConfig;
+use std::{
+    cmp::{max, min},
+    time::Duration,
+};
+
+/// Bounds of the flow control, common to all peers
+#[derive(Clone, Debug)]
+pub(crate) struct BroadcastLimits {
+    min_batch_size: usize,
+    max_batch_size: usize,
+    max_skipped_ticks: u64,
+    slow_send_threshold: Duration,
+    max_failure_backoff_ticks: u64,
+    ack_timeout: Duration,
+}
+
+impl BroadcastLimits {
+    pub(crate) fn new(config: &MempoolConfig) -> Self {
+        let max_batch_size = max(1, config.shared_mempool_batch_size);
+        Self {
+            min_batch_size: min(max_batch_size, max(1, config.shared_mempool_min_batch_size)),
+            max_batch_size,
+            max_skipped_ticks: config.shared_mempool_max_skipped_ticks,
+            slow_send_threshold: Duration::from_millis(
+                config.shared_mempool_slow_send_threshold_ms,
+            ),
+            max_failure_backoff_ticks: config.shared_mempool_max_failure_backoff_ticks,
+            ack_timeout: Duration::from_millis(config.shared_mempool_ack_timeout_ms),
+        }
+    }
+
+    /// Time a peer has to acknowledge a broadcast before it is considered failed
+    pub(crate) fn ack_timeout(&self) -> Duration {
+        self.ack_timeout
+    }
+}
+
+/// Flow control state of the broadcasts to a peer
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BroadcastState {
+    batch_size: usize,
+    // number of ticks skipped after each broadcast to the peer
+    skipped_ticks: u64,
+    // number of ticks left to skip before the next broadcast
+    ticks_to_skip: u64,
+    consecutive_failures: u32,
+}
+
+impl BroadcastState {
+    /// State of a newly connected peer, which is assumed to keep up until proven otherwise
+    pub(crate) fn new(limits: &BroadcastLimits) -> Self {
+        Self {
+            batch_size: limits.max_batch_size,
+            skipped_ticks: 0,
+            ticks_to_skip: 0,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Max number of transactions of the next broadcast
+    pub(crate) fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Advances to the next tick. Returns whether to broadcast to the peer on this tick
+    pub(crate) fn tick(&mut self) -> bool {
+        if self.ticks_to_skip > 0 {
+            self.ticks_to_skip -= 1;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Adapts to a broadcast which took `latency` to be acknowledged, or to be handed over to the
+    /// network if the peer doesn't acknowledge broadcasts
+    pub(crate) fn on_sent(&mut self, limits: &BroadcastLimits, latency: Duration) {
+        self.consecutive_failures = 0;
+        if latency > limits.slow_send_threshold {
+            self.batch_size = max(limits.min_batch_size, self.batch_size / 2);
+            self.skipped_ticks = min(limits.max_skipped_ticks, max(1, self.skipped_ticks * 2));
+        } else {
+            let step = max(1, limits.max_batch_size / 10);
+            self.batch_size = min(limits.max_batch_size, self.batch_size + step);
+            self.skipped_ticks /= 2;
+        }
+        self.ticks_to_skip = self.skipped_ticks;
+    }
+
+    /// Pauses the broadcasts after a failed one
+    pub(crate) fn on_failed(&mut self, limits: &BroadcastLimits) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.batch_size = limits.min_batch_size;
+        let backoff = 1u64
+            .checked_shl(self.consecutive_failures - 1)
+            .unwrap_or(u64::max_value());
+        self.ticks_to_skip = min(limits.max_failure_backoff_ticks, backoff);
+    }
+}
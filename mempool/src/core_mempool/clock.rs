@@ -0,0 +1,57 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Source of the current time of Mempool, for the insertion times of the transactions, their
+//! system TTL and the latencies it exports. Tests substitute a [`MockClock`] to move time forward
+//! deterministically instead of sleeping.
+
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Tells the current time, as the time elapsed since the UNIX epoch
+pub trait Clock: Send + Sync {
+    /// Current time, as the time elapsed since the UNIX epoch
+    fn now(&self) -> Duration;
+}
+
+/// Clock of the system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("init timestamp failure")
+    }
+}
+
+/// Virtual clock which only moves when told to. Clones share the same time
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MockClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Creates a clock stopped at `now`
+    pub(crate) fn new(now: Duration) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the clock `duration` forward
+    pub(crate) fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("[mock clock] acquire lock") += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().expect("[mock clock] acquire lock")
+    }
+}
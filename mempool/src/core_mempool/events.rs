@@ -0,0 +1,164 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Events of the transactions entering and leaving Mempool, broadcast to subscribers so that
+//! they can follow the status of transactions without polling Mempool.
+//!
+//! Each subscriber selects the transactions it follows, and only receives their events. Events
+//! are never dropped silently: a subscriber which falls too far behind is disconnected, which ends
+//! its stream, so that it looks up the status of its transactions before subscribing again.
+
+use crate::{core_mempool::index::TxnPointer, proto::mempool as proto, OP_COUNTERS};
+use failure::prelude::*;
+use futures_preview::channel::mpsc;
+use std::{
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
+use types::account_address::AccountAddress;
+
+/// Number of events buffered for each subscriber. The subscribers which fall further behind are
+/// disconnected
+pub(crate) const SUBSCRIBER_BUFFER_SIZE: usize = 1024;
+/// Maximum number of subscribers at a time
+pub const MAX_SUBSCRIBERS: usize = 1024;
+
+/// What happened to a transaction of Mempool, identified by its sender and sequence number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MempoolEvent {
+    // transaction entered Mempool, possibly replacing one with the same sequence number
+    TxnAdded(TxnPointer),
+    // transaction was committed, or a transaction with a higher sequence number of the same
+    // account was
+    TxnRemovedCommitted(TxnPointer),
+    // transaction was removed because a transaction of the same account was rejected by the VM
+    TxnRejected(TxnPointer),
+    // transaction expired, either its own expiration time or the system TTL of Mempool
+    TxnExpired(TxnPointer),
+    // transaction was evicted to make room for a transaction paying more
+    TxnEvicted(TxnPointer),
+    // transaction was replaced by one with the same sequence number paying more, whose
+    // `TxnAdded` event follows
+    TxnReplaced(TxnPointer),
+    // transaction submitted to this node was broadcast to a peer for the first time
+    TxnBroadcast(TxnPointer),
+}
+
+impl MempoolEvent {
+    /// Transaction the event is about
+    pub fn txn(&self) -> TxnPointer {
+        match *self {
+            MempoolEvent::TxnAdded(txn)
+            | MempoolEvent::TxnRemovedCommitted(txn)
+            | MempoolEvent::TxnRejected(txn)
+            | MempoolEvent::TxnExpired(txn)
+            | MempoolEvent::TxnEvicted(txn)
+            | MempoolEvent::TxnReplaced(txn)
+            | MempoolEvent::TxnBroadcast(txn) => txn,
+        }
+    }
+}
+
+/// Selects the transactions a subscriber receives the events of: the transactions of `sender`,
+/// or only its transaction with `sequence_number` if set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventFilter {
+    pub sender: AccountAddress,
+    pub sequence_number: Option<u64>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &MempoolEvent) -> bool {
+        let (sender, sequence_number) = event.txn();
+        sender == self.sender
+            && self
+                .sequence_number
+                .map_or(true, |seq| seq == sequence_number)
+    }
+}
+
+impl TryFrom<proto::SubscribeEventsRequest> for EventFilter {
+    type Error = Error;
+
+    fn try_from(req: proto::SubscribeEventsRequest) -> Result<Self> {
+        Ok(Self {
+            sender: AccountAddress::try_from(&req.sender[..])?,
+            sequence_number: req.sequence_number,
+        })
+    }
+}
+
+impl From<MempoolEvent> for proto::MempoolEvent {
+    fn from(event: MempoolEvent) -> Self {
+        let (event_type, (sender, sequence_number)) = match event {
+            MempoolEvent::TxnAdded(txn) => (proto::MempoolEventType::TxnAdded, txn),
+            MempoolEvent::TxnRemovedCommitted(txn) => {
+                (proto::MempoolEventType::TxnRemovedCommitted, txn)
+            }
+            MempoolEvent::TxnRejected(txn) => (proto::MempoolEventType::TxnRejected, txn),
+            MempoolEvent::TxnExpired(txn) => (proto::MempoolEventType::TxnExpired, txn),
+            MempoolEvent::TxnEvicted(txn) => (proto::MempoolEventType::TxnEvicted, txn),
+            MempoolEvent::TxnReplaced(txn) => (proto::MempoolEventType::TxnReplaced, txn),
+            MempoolEvent::TxnBroadcast(txn) => (proto::MempoolEventType::TxnBroadcast, txn),
+        };
+        let mut proto_event = proto::MempoolEvent::default();
+        proto_event.set_event_type(event_type);
+        proto_event.sender = sender.to_vec();
+        proto_event.sequence_number = sequence_number;
+        proto_event
+    }
+}
+
+/// Broadcasts the events of Mempool to its subscribers
+#[derive(Clone, Default)]
+pub struct MempoolEventBroadcaster {
+    subscribers: Arc<Mutex<Vec<(EventFilter, mpsc::Sender<MempoolEvent>)>>>,
+}
+
+impl MempoolEventBroadcaster {
+    /// Returns a stream of the events of the transactions `filter` selects from now on, unless
+    /// there are `MAX_SUBSCRIBERS` subscribers already
+    pub(crate) fn subscribe(&self, filter: EventFilter) -> Result<mpsc::Receiver<MempoolEvent>> {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .expect("[mempool] failed to acquire subscribers lock");
+        subscribers.retain(|(_, subscriber)| !subscriber.is_closed());
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            OP_COUNTERS.inc("events.subscriptions_refused");
+            bail!(
+                "Mempool has {} event subscribers already",
+                subscribers.len()
+            );
+        }
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE);
+        subscribers.push((filter, sender));
+        Ok(receiver)
+    }
+
+    /// Sends `event` to the subscribers following its transaction, forgetting the ones which are
+    /// gone and disconnecting the ones which fell too far behind
+    pub(crate) fn emit(&self, event: MempoolEvent) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .expect("[mempool] failed to acquire subscribers lock");
+        let mut live_subscribers = Vec::with_capacity(subscribers.len());
+        for (filter, mut subscriber) in subscribers.drain(..) {
+            if !filter.matches(&event) {
+                if !subscriber.is_closed() {
+                    live_subscribers.push((filter, subscriber));
+                }
+                continue;
+            }
+            match subscriber.try_send(event) {
+                Ok(()) => live_subscribers.push((filter, subscriber)),
+                // dropping the sender ends the stream of the subscriber once it received the
+                // buffered events
+                Err(e) if e.is_full() => OP_COUNTERS.inc("events.subscriber_lagged"),
+                Err(_) => (),
+            }
+        }
+        *subscribers = live_subscribers;
+    }
+}
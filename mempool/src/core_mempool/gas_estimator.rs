@@ -0,0 +1,118 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rolling percentiles of the gas unit prices of the transactions of Mempool and of the most
+//! recently committed transactions, so that clients can pick a gas price which gets their
+//! transaction into the next block without overpaying.
+
+use mempool_shared_proto::{GasPriceEstimate, GasPricePercentiles};
+use std::{
+    cmp::max,
+    collections::{BTreeMap, VecDeque},
+};
+
+/// Number of transactions paying each gas unit price, updated as transactions come and go
+#[derive(Default)]
+pub(crate) struct GasPriceHistogram {
+    counts: BTreeMap<u64, u64>,
+    total: u64,
+}
+
+impl GasPriceHistogram {
+    pub(crate) fn insert(&mut self, gas_price: u64) {
+        *self.counts.entry(gas_price).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    pub(crate) fn remove(&mut self, gas_price: u64) {
+        if let Some(count) = self.counts.get_mut(&gas_price) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&gas_price);
+            }
+            self.total -= 1;
+        }
+    }
+
+    /// Gas unit price of the transaction at `percentile` in ascending order of gas price, by the
+    /// nearest-rank method
+    fn percentile(&self, percentile: u64) -> u64 {
+        let rank = max(1, (self.total * percentile + 99) / 100);
+        let mut seen = 0;
+        for (gas_price, count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return *gas_price;
+            }
+        }
+        0
+    }
+
+    pub(crate) fn percentiles(&self) -> GasPricePercentiles {
+        GasPricePercentiles {
+            p50: self.percentile(50),
+            p90: self.percentile(90),
+            p99: self.percentile(99),
+            count: self.total,
+        }
+    }
+
+    /// Lowest gas unit price which ranks a new transaction among the `block_size` transactions
+    /// paying the most, or 0 if there are fewer transactions than that
+    fn next_block_gas_price(&self, block_size: u64) -> u64 {
+        let mut seen = 0;
+        for (gas_price, count) in self.counts.iter().rev() {
+            seen += count;
+            if seen >= block_size {
+                return gas_price + 1;
+            }
+        }
+        0
+    }
+}
+
+/// Estimates the gas unit price needed to get into the next block from the transactions of
+/// Mempool and the last `window` committed transactions
+pub(crate) struct GasEstimator {
+    committed_gas_prices: VecDeque<u64>,
+    committed: GasPriceHistogram,
+    window: usize,
+    block_size: u64,
+}
+
+impl GasEstimator {
+    pub(crate) fn new(window: usize, block_size: u64) -> Self {
+        Self {
+            committed_gas_prices: VecDeque::with_capacity(window),
+            committed: GasPriceHistogram::default(),
+            window,
+            block_size,
+        }
+    }
+
+    /// Records the gas unit price of a committed transaction, forgetting the oldest one once the
+    /// window is full
+    pub(crate) fn record_committed(&mut self, gas_price: u64) {
+        if self.window == 0 {
+            return;
+        }
+        if self.committed_gas_prices.len() == self.window {
+            if let Some(oldest) = self.committed_gas_prices.pop_front() {
+                self.committed.remove(oldest);
+            }
+        }
+        self.committed_gas_prices.push_back(gas_price);
+        self.committed.insert(gas_price);
+    }
+
+    /// Estimate given the gas unit prices of the transactions currently in Mempool. Transactions
+    /// which are not ready yet are counted as competing for the next block, which errs on the
+    /// side of paying a little more
+    pub(crate) fn estimate(&self, mempool: &GasPriceHistogram) -> GasPriceEstimate {
+        GasPriceEstimate {
+            mempool: mempool.percentiles(),
+            committed: self.committed.percentiles(),
+            next_block_gas_price: mempool.next_block_gas_price(self.block_size),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use std::{
     cmp::Ordering,
     collections::{btree_set::Iter, BTreeMap, BTreeSet},
     iter::Rev,
+    mem,
     ops::Bound,
     time::Duration,
 };
@@ -16,7 +17,8 @@ pub type AccountTransactions = BTreeMap<u64, MempoolTransaction>;
 
 /// PriorityIndex represents main Priority Queue in Mempool
 /// It's used to form transaction block for Consensus
-/// Transactions are ordered by gas price. Second level ordering is done by expiration time
+/// Transactions of privileged senders come first. Then transactions are ordered by gas price.
+/// Second level ordering is done by expiration time
 ///
 /// We don't store full content of transaction in index
 /// Instead we use `OrderedQueueKey` - logical reference to transaction in main store
@@ -49,6 +51,7 @@ impl PriorityIndex {
 
     fn make_key(&self, txn: &MempoolTransaction) -> OrderedQueueKey {
         OrderedQueueKey {
+            is_privileged: txn.is_privileged,
             gas_price: txn.get_gas_price(),
             expiration_time: txn.expiration_time,
             address: txn.get_sender(),
@@ -68,6 +71,7 @@ impl PriorityIndex {
 
 #[derive(Eq, PartialEq, Clone, Debug, Hash)]
 pub struct OrderedQueueKey {
+    pub is_privileged: bool,
     pub gas_price: u64,
     pub expiration_time: Duration,
     pub address: AccountAddress,
@@ -82,6 +86,10 @@ impl PartialOrd for OrderedQueueKey {
 
 impl Ord for OrderedQueueKey {
     fn cmp(&self, other: &OrderedQueueKey) -> Ordering {
+        match self.is_privileged.cmp(&other.is_privileged) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
         match self.gas_price.cmp(&other.gas_price) {
             Ordering::Equal => {}
             ordering => return ordering,
@@ -130,6 +138,8 @@ impl TTLIndex {
     }
 
     /// GC all old transactions
+    /// Only the expired keys are visited: the active ones are split off into a tree of their own
+    /// rather than merged back, which would take time linear in the size of Mempool
     pub(crate) fn gc(&mut self, now: Duration) -> Vec<TTLOrderingKey> {
         let ttl_key = TTLOrderingKey {
             expiration_time: now,
@@ -137,11 +147,8 @@ impl TTLIndex {
             sequence_number: 0,
         };
 
-        let mut active = self.data.split_off(&ttl_key);
-        let ttl_transactions = self.data.iter().cloned().collect();
-        self.data.clear();
-        self.data.append(&mut active);
-        ttl_transactions
+        let active = self.data.split_off(&ttl_key);
+        mem::replace(&mut self.data, active).into_iter().collect()
     }
 
     fn make_key(&self, txn: &MempoolTransaction) -> TTLOrderingKey {
@@ -155,6 +162,11 @@ impl TTLIndex {
     pub(crate) fn size(&self) -> usize {
         self.data.len()
     }
+
+    /// Key of the transaction which expires first, if any
+    pub(crate) fn first(&self) -> Option<&TTLOrderingKey> {
+        self.data.iter().next()
+    }
 }
 
 #[derive(Eq, PartialEq, PartialOrd, Clone, Debug)]
@@ -182,6 +194,8 @@ impl Ord for TTLOrderingKey {
 /// It's represented as Map <timeline_id, (Address, sequence_number)>
 ///    where timeline_id is auto increment unique id of "ready" transaction in local Mempool
 ///    (Address, sequence_number) is a logical reference to transaction content in main storage
+/// Removed transactions leave no entry behind, so that a read seeks to `timeline_id` in
+/// O(log n) and then only visits the transactions it returns
 pub struct TimelineIndex {
     timeline_id: u64,
     timeline: BTreeMap<u64, (AccountAddress, u64)>,
@@ -195,23 +209,14 @@ impl TimelineIndex {
         }
     }
 
-    /// read all transactions from timeline since <timeline_id>
-    pub(crate) fn read_timeline(
-        &mut self,
-        timeline_id: u64,
-        count: usize,
-    ) -> Vec<(AccountAddress, u64)> {
-        let mut batch = vec![];
-        for (_, &(address, sequence_number)) in self
-            .timeline
+    /// read up to `count` transactions from timeline since <timeline_id>, along with their
+    /// timeline ids
+    pub(crate) fn read_timeline(&self, timeline_id: u64, count: usize) -> Vec<(u64, TxnPointer)> {
+        self.timeline
             .range((Bound::Excluded(timeline_id), Bound::Unbounded))
-        {
-            batch.push((address, sequence_number));
-            if batch.len() == count {
-                break;
-            }
-        }
-        batch
+            .take(count)
+            .map(|(&id, &pointer)| (id, pointer))
+            .collect()
     }
 
     /// add transaction to index
@@ -230,14 +235,20 @@ impl TimelineIndex {
             self.timeline.remove(&timeline_id);
         }
     }
+
+    pub(crate) fn size(&self) -> usize {
+        self.timeline.len()
+    }
 }
 
 /// ParkingLotIndex keeps track of "not_ready" transactions
 /// e.g. transactions that can't be included in next block
 /// (because their sequence number is too high)
 /// we keep separate index to be able to efficiently evict them when Mempool is full
+/// Transactions are ordered by gas price, so that the cheapest ones are evicted first, after all
+/// the transactions of non-privileged senders
 pub struct ParkingLotIndex {
-    data: BTreeSet<TxnPointer>,
+    data: BTreeSet<ParkingLotKey>,
 }
 
 impl ParkingLotIndex {
@@ -249,17 +260,18 @@ impl ParkingLotIndex {
 
     /// add transaction to index
     pub(crate) fn insert(&mut self, txn: &MempoolTransaction) {
-        self.data.insert(TxnPointer::from(txn));
+        self.data.insert(ParkingLotKey::from(txn));
     }
 
     /// remove transaction from index
     pub(crate) fn remove(&mut self, txn: &MempoolTransaction) {
-        self.data.remove(&TxnPointer::from(txn));
+        self.data.remove(&ParkingLotKey::from(txn));
     }
 
-    /// returns random "non-ready" transaction (with highest sequence number for that account)
-    pub(crate) fn pop(&mut self) -> Option<TxnPointer> {
-        self.data.iter().rev().next().cloned()
+    /// returns "non-ready" transaction of a non-privileged sender with lowest gas price
+    /// (with highest sequence number for that account among equally priced ones)
+    pub(crate) fn cheapest(&self) -> Option<&ParkingLotKey> {
+        self.data.iter().next().filter(|key| !key.is_privileged)
     }
 
     pub(crate) fn size(&self) -> usize {
@@ -267,6 +279,49 @@ impl ParkingLotIndex {
     }
 }
 
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct ParkingLotKey {
+    pub is_privileged: bool,
+    pub gas_price: u64,
+    pub address: AccountAddress,
+    pub sequence_number: u64,
+}
+
+impl PartialOrd for ParkingLotKey {
+    fn partial_cmp(&self, other: &ParkingLotKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ParkingLotKey {
+    fn cmp(&self, other: &ParkingLotKey) -> Ordering {
+        match self.is_privileged.cmp(&other.is_privileged) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+        match self.gas_price.cmp(&other.gas_price) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+        match self.address.cmp(&other.address) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+        self.sequence_number.cmp(&other.sequence_number).reverse()
+    }
+}
+
+impl From<&MempoolTransaction> for ParkingLotKey {
+    fn from(txn: &MempoolTransaction) -> Self {
+        Self {
+            is_privileged: txn.is_privileged,
+            gas_price: txn.get_gas_price(),
+            address: txn.get_sender(),
+            sequence_number: txn.get_sequence_number(),
+        }
+    }
+}
+
 /// Logical pointer to `MempoolTransaction`
 /// Includes Account's address and transaction sequence number
 pub type TxnPointer = (AccountAddress, u64);
@@ -282,3 +337,9 @@ impl From<&OrderedQueueKey> for TxnPointer {
         (key.address, key.sequence_number)
     }
 }
+
+impl From<&ParkingLotKey> for TxnPointer {
+    fn from(key: &ParkingLotKey) -> Self {
+        (key.address, key.sequence_number)
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracks how long the transactions submitted to this node take to go through Mempool: from their
+//! insertion to their first broadcast to a peer, to the first block Consensus pulls them into and
+//! to their commit. The latencies are exported as histograms of the Mempool op counters.
+//!
+//! Only the transactions submitted to this node are tracked, since the ones received from peers
+//! entered the system elsewhere. At most `capacity` transactions are tracked at a time, the least
+//! recently used one being dropped first, and a transaction is forgotten `ttl` after it entered
+//! Mempool, whether it was committed by then or not.
+
+use crate::{
+    core_mempool::{clock::Clock, index::TxnPointer},
+    OP_COUNTERS,
+};
+use std::{sync::Arc, time::Duration};
+use ttl_cache::TtlCache;
+
+/// Times at which a transaction reached each stage, as told by the clock of Mempool
+struct TxnTimestamps {
+    inserted: Duration,
+    broadcast: Option<Duration>,
+    pulled: Option<Duration>,
+}
+
+pub(crate) struct LatencyTracker {
+    timestamps: TtlCache<TxnPointer, TxnTimestamps>,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl LatencyTracker {
+    pub(crate) fn new(capacity: usize, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            timestamps: TtlCache::new(capacity),
+            ttl,
+            clock,
+        }
+    }
+
+    /// Starts tracking a transaction which just entered Mempool, replacing the timestamps of the
+    /// transaction it replaces, if any
+    pub(crate) fn record_insertion(&mut self, txn: TxnPointer) {
+        let timestamps = TxnTimestamps {
+            inserted: self.clock.now(),
+            broadcast: None,
+            pulled: None,
+        };
+        self.timestamps.insert(txn, timestamps, self.ttl);
+    }
+
+    /// Records the first broadcast of a transaction to a peer. Returns whether it was the first
+    /// broadcast of a tracked transaction
+    pub(crate) fn record_broadcast(&mut self, txn: &TxnPointer) -> bool {
+        if let Some(timestamps) = self.timestamps.get_mut(txn) {
+            if timestamps.broadcast.is_none() {
+                let now = self.clock.now();
+                OP_COUNTERS.observe_duration(
+                    "latency.insertion_to_broadcast",
+                    elapsed(now, timestamps.inserted),
+                );
+                timestamps.broadcast = Some(now);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Records the first time a transaction is pulled into a block by Consensus
+    pub(crate) fn record_pulled(&mut self, txn: &TxnPointer) {
+        if let Some(timestamps) = self.timestamps.get_mut(txn) {
+            if timestamps.pulled.is_none() {
+                let now = self.clock.now();
+                OP_COUNTERS
+                    .observe_duration("txn_pre_consensus_s", elapsed(now, timestamps.inserted));
+                timestamps.pulled = Some(now);
+            }
+        }
+    }
+
+    /// Stops tracking a transaction which left Mempool, recording its commit latencies unless it
+    /// was rejected
+    pub(crate) fn record_removal(&mut self, txn: &TxnPointer, is_rejected: bool) {
+        if let Some(timestamps) = self.timestamps.remove(txn) {
+            if is_rejected {
+                return;
+            }
+            let now = self.clock.now();
+            OP_COUNTERS.observe_duration("e2e.latency", elapsed(now, timestamps.inserted));
+            if let Some(broadcast) = timestamps.broadcast {
+                OP_COUNTERS
+                    .observe_duration("latency.broadcast_to_commit", elapsed(now, broadcast));
+            }
+            if let Some(pulled) = timestamps.pulled {
+                OP_COUNTERS.observe_duration("latency.pulled_to_commit", elapsed(now, pulled));
+            }
+        }
+    }
+
+    /// Whether a transaction is tracked
+    pub(crate) fn is_tracked(&self, txn: &TxnPointer) -> bool {
+        self.timestamps.get(txn).is_some()
+    }
+}
+
+/// Time elapsed from `since` to `now`, zero if the clock went backwards in between
+fn elapsed(now: Duration, since: Duration) -> Duration {
+    now.checked_sub(since).unwrap_or_default()
+}
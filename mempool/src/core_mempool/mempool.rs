@@ -3,49 +3,91 @@
 
 //! mempool is used to track transactions which have been submitted but not yet
 //! agreed upon.
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{sync::Arc, time::Duration};
 
 use crate::{
     core_mempool::{
+        clock::{Clock, SystemClock},
+        events::{EventFilter, MempoolEvent},
+        gas_estimator::GasEstimator,
         index::TxnPointer,
-        transaction::{MempoolTransaction, TimelineState},
+        latency_tracker::LatencyTracker,
+        state_snapshot::{AccountQueue, MempoolStateSnapshot},
+        transaction::{MempoolTransaction, PendingTransaction, TimelineState, TxnSource},
         transaction_store::TransactionStore,
     },
+    known_transactions::{BloomFilter, KnownTransactions},
     OP_COUNTERS,
 };
-use chrono::Utc;
 use config::config::NodeConfig;
+use crypto::hash::CryptoHash;
+use futures_preview::channel::mpsc;
 use logger::prelude::*;
 use lru_cache::LruCache;
 use mempool_shared_proto::{
-    proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
+    proto::mempool_status::{MempoolAddTransactionStatusCode, MempoolTransactionStatusCode},
+    GasPriceEstimate, MempoolAddTransactionStatus,
 };
-use std::{cmp::max, collections::HashSet, convert::TryFrom};
-use ttl_cache::TtlCache;
+use std::{cmp::max, collections::HashSet};
 use types::{account_address::AccountAddress, transaction::SignedTransaction};
 
+/// Transactions of Mempool along with the indexes ordering them for Consensus and SharedMempool
 pub struct Mempool {
     // stores metadata of all transactions in mempool (of all states)
     transactions: TransactionStore,
 
     sequence_number_cache: LruCache<AccountAddress, u64>,
-    // temporary DS. TODO: eventually retire it
-    // for each transaction, entry with timestamp is added when transaction enters mempool
-    // used to measure e2e latency of transaction in system, as well as time it takes to pick it up
-    // by consensus
-    pub(crate) metrics_cache: TtlCache<(AccountAddress, u64), i64>,
+    // timestamps of the stages of the transactions submitted to this node, to export their
+    // latencies
+    pub(crate) latency_tracker: LatencyTracker,
+    /// Time a transaction is kept in Mempool for, at most
     pub system_transaction_timeout: Duration,
+    // bounds of the time left before a transaction expires when it is inserted
+    min_expiration_window: Option<Duration>,
+    max_expiration_window: Option<Duration>,
+    // gas unit prices of the recently committed transactions
+    gas_estimator: GasEstimator,
+    // hashes of the recently inserted transactions, advertised to peers
+    known_transactions: KnownTransactions,
+    // source of the insertion times and of the time the system TTL is checked against
+    clock: Arc<dyn Clock>,
 }
 
 impl Mempool {
-    pub(crate) fn new(config: &NodeConfig) -> Self {
+    /// Creates an empty Mempool, configured by `config`
+    pub fn new(config: &NodeConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Same as [`new`](Mempool::new), with the time told by `clock` instead of the system clock
+    pub(crate) fn with_clock(config: &NodeConfig, clock: Arc<dyn Clock>) -> Self {
         Mempool {
             transactions: TransactionStore::new(&config.mempool),
             sequence_number_cache: LruCache::new(config.mempool.capacity),
-            metrics_cache: TtlCache::new(config.mempool.capacity),
+            latency_tracker: LatencyTracker::new(
+                config.mempool.capacity,
+                Duration::from_secs(config.mempool.latency_tracker_ttl_secs),
+                Arc::clone(&clock),
+            ),
             system_transaction_timeout: Duration::from_secs(
                 config.mempool.system_transaction_timeout_secs,
             ),
+            min_expiration_window: config
+                .mempool
+                .min_expiration_window_secs
+                .map(Duration::from_secs),
+            max_expiration_window: config
+                .mempool
+                .max_expiration_window_secs
+                .map(Duration::from_secs),
+            gas_estimator: GasEstimator::new(
+                config.mempool.gas_estimator_window,
+                config.consensus.max_block_size(),
+            ),
+            known_transactions: KnownTransactions::new(
+                config.mempool.shared_mempool_known_transactions_window,
+            ),
+            clock,
         }
     }
 
@@ -60,8 +102,8 @@ impl Mempool {
             "[Mempool] Removing transaction from mempool: {}:{}:{}",
             sender, sequence_number, is_rejected
         );
-        self.log_latency(sender.clone(), sequence_number, "e2e.latency");
-        self.metrics_cache.remove(&(*sender, sequence_number));
+        self.latency_tracker
+            .record_removal(&(*sender, sequence_number), is_rejected);
         OP_COUNTERS.inc(&format!("remove_transaction.{}", is_rejected));
 
         if is_rejected {
@@ -77,6 +119,9 @@ impl Mempool {
                 .sequence_number_cache
                 .remove(&sender)
                 .unwrap_or_default();
+            if let Some(txn) = self.transactions.get(sender, sequence_number) {
+                self.gas_estimator.record_committed(txn.gas_unit_price());
+            }
             let new_seq_number = max(current_seq_number, sequence_number + 1);
             self.sequence_number_cache
                 .insert(sender.clone(), new_seq_number);
@@ -85,22 +130,87 @@ impl Mempool {
         }
     }
 
-    fn log_latency(&mut self, account: AccountAddress, sequence_number: u64, metric: &str) {
-        if let Some(&creation_time) = self.metrics_cache.get(&(account, sequence_number)) {
-            if let Ok(time_delta_ms) = u64::try_from(Utc::now().timestamp_millis() - creation_time)
-            {
-                OP_COUNTERS.observe_duration(metric, Duration::from_millis(time_delta_ms));
+    /// Applies a committed block in one go: removes the committed and rejected `transactions`,
+    /// each identified by its sender, sequence number and whether it was rejected, then every
+    /// transaction made stale by the new sequence numbers of `account_sequence_numbers`, and
+    /// finally the transactions expired as of `block_time`, if given
+    pub(crate) fn commit_block(
+        &mut self,
+        transactions: &[(AccountAddress, u64, bool)],
+        account_sequence_numbers: &[(AccountAddress, u64)],
+        block_time: Option<Duration>,
+    ) {
+        for (sender, sequence_number, is_rejected) in transactions {
+            self.remove_transaction(sender, *sequence_number, *is_rejected);
+        }
+        for (account, sequence_number) in account_sequence_numbers {
+            self.update_sequence_number(account, *sequence_number);
+        }
+        if let Some(block_time) = block_time {
+            self.gc_by_expiration_time(block_time);
+        }
+    }
+
+    /// Removes the transactions of `account` below its new `sequence_number`, which were
+    /// committed through other nodes or replaced by other transactions
+    fn update_sequence_number(&mut self, account: &AccountAddress, sequence_number: u64) {
+        let current_seq_number = self
+            .sequence_number_cache
+            .remove(account)
+            .unwrap_or_default();
+        let new_seq_number = max(current_seq_number, sequence_number);
+        self.sequence_number_cache.insert(*account, new_seq_number);
+        self.transactions
+            .commit_transaction(account, new_seq_number);
+    }
+
+    /// Checks that the time left before `txn` expires, as of `now`, is within the configured
+    /// expiration window
+    fn check_expiration_window(
+        &self,
+        txn: &SignedTransaction,
+        now: Duration,
+    ) -> Result<(), MempoolAddTransactionStatus> {
+        let expiration_time = txn.expiration_time();
+        if let Some(min_window) = self.min_expiration_window {
+            if expiration_time < now + min_window {
+                OP_COUNTERS.inc("expiration_too_soon");
+                return Err(MempoolAddTransactionStatus::new(
+                    MempoolAddTransactionStatusCode::ExpirationTooSoon,
+                    format!(
+                        "transaction expires at {}s, min expiration time is {}s",
+                        expiration_time.as_secs(),
+                        (now + min_window).as_secs(),
+                    ),
+                ));
+            }
+        }
+        if let Some(max_window) = self.max_expiration_window {
+            if expiration_time > now + max_window {
+                OP_COUNTERS.inc("expiration_too_far");
+                return Err(MempoolAddTransactionStatus::new(
+                    MempoolAddTransactionStatusCode::ExpirationTooFar,
+                    format!(
+                        "transaction expires at {}s, max expiration time is {}s",
+                        expiration_time.as_secs(),
+                        (now + max_window).as_secs(),
+                    ),
+                ));
             }
         }
+        Ok(())
     }
 
     fn get_required_balance(&mut self, txn: &SignedTransaction, gas_amount: u64) -> u64 {
-        txn.gas_unit_price() * gas_amount + self.transactions.get_required_balance(&txn.sender())
+        txn.gas_unit_price() * gas_amount
+            + self
+                .transactions
+                .get_required_balance(&txn.sender(), txn.sequence_number())
     }
 
     /// Used to add a transaction to the Mempool
     /// Performs basic validation: checks account's balance and sequence number
-    pub(crate) fn add_txn(
+    pub fn add_txn(
         &mut self,
         txn: SignedTransaction,
         gas_amount: u64,
@@ -144,35 +254,73 @@ impl Mempool {
             );
         }
 
-        let expiration_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("init timestamp failure")
-            + self.system_transaction_timeout;
+        let insertion_time = self.clock.now();
+        if let Err(status) = self.check_expiration_window(&txn, insertion_time) {
+            return status;
+        }
+        let expiration_time = insertion_time + self.system_transaction_timeout;
         if timeline_state != TimelineState::NonQualified {
-            self.metrics_cache.insert(
-                (txn.sender(), txn.sequence_number()),
-                Utc::now().timestamp_millis(),
-                Duration::from_secs(100),
-            );
+            self.latency_tracker
+                .record_insertion((txn.sender(), txn.sequence_number()));
         }
 
-        let txn_info = MempoolTransaction::new(txn, expiration_time, gas_amount, timeline_state);
+        // hashed before the transaction moves into the store
+        let txn_hash = if self.known_transactions.is_enabled() {
+            Some(txn.hash())
+        } else {
+            None
+        };
+        let txn_info = MempoolTransaction::new(
+            txn,
+            insertion_time,
+            expiration_time,
+            gas_amount,
+            timeline_state,
+        );
 
         let status = self.transactions.insert(txn_info, sequence_number);
         OP_COUNTERS.inc(&format!("insert.{:?}", status));
+        if let Some(txn_hash) = txn_hash {
+            if status.code == MempoolAddTransactionStatusCode::Valid
+                || status.code == MempoolAddTransactionStatusCode::Replaced
+            {
+                self.known_transactions.record(txn_hash);
+            }
+        }
         status
     }
 
+    /// Bloom filter of the transactions recently inserted into Mempool, which peers don't need to
+    /// broadcast to this node
+    pub(crate) fn known_transactions_filter(&self) -> Option<BloomFilter> {
+        self.known_transactions.filter()
+    }
+
     /// Fetches next block of transactions for consensus
     /// `batch_size` - size of requested block
     /// `seen_txns` - transactions that were sent to Consensus but were not committed yet
     ///  Mempool should filter out such transactions
-    pub(crate) fn get_block(
+    pub fn get_block(
         &mut self,
         batch_size: u64,
+        seen: HashSet<TxnPointer>,
+    ) -> Vec<SignedTransaction> {
+        self.get_block_with_budget(batch_size, u64::max_value(), u64::max_value(), seen)
+    }
+
+    /// Same as `get_block`, but also stops adding transactions to the block once their
+    /// cumulative size in bytes or max gas amount would exceed `max_bytes` or `max_gas`.
+    /// A transaction which doesn't fit is passed over, so that smaller transactions with a
+    /// lower gas price can still fill the rest of the block
+    pub(crate) fn get_block_with_budget(
+        &mut self,
+        batch_size: u64,
+        max_bytes: u64,
+        max_gas: u64,
         mut seen: HashSet<TxnPointer>,
     ) -> Vec<SignedTransaction> {
         let mut result = vec![];
+        let mut budget = BlockBudget::new(max_bytes, max_gas);
         // Helper DS. Helps to mitigate scenarios where account submits several transactions
         // with increasing gas price (e.g. user submits transactions with sequence number 1, 2
         // and gas_price 1, 10 respectively)
@@ -193,6 +341,10 @@ impl Mempool {
             // we've already sent its ancestor to Consensus
             if seen_previous || account_sequence_number == Some(&mut seq) {
                 let ptr = TxnPointer::from(txn);
+                if !budget.charge(self.transactions.get_size_and_gas(&ptr.0, ptr.1)) {
+                    OP_COUNTERS.inc("get_block.over_budget");
+                    continue;
+                }
                 seen.insert(ptr);
                 result.push(ptr);
                 if (result.len() as u64) == batch_size {
@@ -203,6 +355,13 @@ impl Mempool {
                 // that were skipped before for given account
                 let mut skipped_txn = (txn.address, seq + 1);
                 while skipped.contains(&skipped_txn) {
+                    let size_and_gas = self
+                        .transactions
+                        .get_size_and_gas(&skipped_txn.0, skipped_txn.1);
+                    if !budget.charge(size_and_gas) {
+                        OP_COUNTERS.inc("get_block.over_budget");
+                        break;
+                    }
                     seen.insert(skipped_txn);
                     result.push(skipped_txn);
                     if (result.len() as u64) == batch_size {
@@ -220,37 +379,132 @@ impl Mempool {
             .filter_map(|(address, seq)| self.transactions.get(&address, seq))
             .collect();
         for transaction in &block {
-            self.log_latency(
-                transaction.sender(),
-                transaction.sequence_number(),
-                "txn_pre_consensus_s",
-            );
+            self.latency_tracker
+                .record_pulled(&(transaction.sender(), transaction.sequence_number()));
         }
         block
     }
 
     /// TTL based garbage collection. Remove all transactions that got expired
     pub(crate) fn gc_by_system_ttl(&mut self) {
-        self.transactions.gc_by_system_ttl();
+        self.transactions.gc_by_system_ttl(self.clock.now());
     }
 
     /// Garbage collection based on client-specified expiration time
-    pub(crate) fn gc_by_expiration_time(&mut self, block_time: Duration) {
+    pub fn gc_by_expiration_time(&mut self, block_time: Duration) {
         self.transactions.gc_by_expiration_time(block_time);
     }
 
+    /// Records the broadcast of `transactions` to a peer, telling the subscribers about the
+    /// first broadcast of the transactions submitted to this node
+    pub(crate) fn record_broadcast(&mut self, transactions: &[TxnPointer]) {
+        for txn in transactions {
+            if self.latency_tracker.record_broadcast(txn) {
+                self.transactions.emit(MempoolEvent::TxnBroadcast(*txn));
+            }
+        }
+    }
+
+    /// Fetches the transaction of `address` with `sequence_number`, if still in Mempool
+    pub(crate) fn get_transaction(
+        &self,
+        address: &AccountAddress,
+        sequence_number: u64,
+    ) -> Option<SignedTransaction> {
+        self.transactions.get(address, sequence_number)
+    }
+
     /// Read `count` transactions from timeline since `timeline_id`
     /// Returns block of transactions and new last_timeline_id
-    pub(crate) fn read_timeline(
-        &mut self,
-        timeline_id: u64,
-        count: usize,
-    ) -> (Vec<SignedTransaction>, u64) {
+    pub fn read_timeline(&self, timeline_id: u64, count: usize) -> (Vec<SignedTransaction>, u64) {
         self.transactions.read_timeline(timeline_id, count)
     }
 
+    /// Returns up to `count` transactions of Mempool along with their source, to be written to a
+    /// snapshot
+    pub(crate) fn snapshot(&self, count: usize) -> Vec<(SignedTransaction, TxnSource)> {
+        self.transactions.snapshot(count)
+    }
+
+    /// Returns a stream of the events of the transactions of Mempool `filter` selects from now on,
+    /// unless Mempool has too many subscribers already
+    pub(crate) fn subscribe_events(
+        &self,
+        filter: EventFilter,
+    ) -> failure::Result<mpsc::Receiver<MempoolEvent>> {
+        self.transactions.subscribe_events(filter)
+    }
+
+    /// Returns the transactions of `address` waiting in Mempool, by sequence number
+    pub(crate) fn get_account_transactions(
+        &self,
+        address: &AccountAddress,
+    ) -> Vec<PendingTransaction> {
+        self.transactions.get_account_transactions(address)
+    }
+
+    /// Returns whether the transaction of `sender` with `sequence_number` is waiting in Mempool,
+    /// or why it was removed if it was recently
+    pub(crate) fn get_transaction_status(
+        &self,
+        sender: &AccountAddress,
+        sequence_number: u64,
+    ) -> MempoolTransactionStatusCode {
+        self.transactions
+            .get_transaction_status(sender, sequence_number)
+    }
+
+    /// Returns a summary of the internal state of Mempool without the queues of the accounts,
+    /// along with the number of transactions of each account
+    pub(crate) fn state_summary(&self) -> (MempoolStateSnapshot, Vec<(AccountAddress, usize)>) {
+        self.transactions.state_summary(self.clock.now())
+    }
+
+    /// Returns the queues of the accounts at `addresses` which still have transactions
+    pub(crate) fn account_queues(&self, addresses: &[AccountAddress]) -> Vec<AccountQueue> {
+        addresses
+            .iter()
+            .map(|address| self.transactions.account_queue(address))
+            .filter(|queue| queue.num_transactions > 0)
+            .collect()
+    }
+
+    /// Returns the percentiles of the gas unit prices of the transactions of Mempool and of the
+    /// recently committed ones, along with the gas unit price needed to get into the next block
+    pub(crate) fn get_gas_price_estimate(&self) -> GasPriceEstimate {
+        self.gas_estimator.estimate(self.transactions.gas_prices())
+    }
+
     /// Check the health of core mempool.
     pub(crate) fn health_check(&self) -> bool {
         self.transactions.health_check()
     }
 }
+
+/// Remaining room of a block pulled by `get_block_with_budget`
+struct BlockBudget {
+    bytes: u64,
+    gas: u64,
+}
+
+impl BlockBudget {
+    fn new(max_bytes: u64, max_gas: u64) -> Self {
+        Self {
+            bytes: max_bytes,
+            gas: max_gas,
+        }
+    }
+
+    /// Takes the size and max gas amount of a transaction out of the budget, if there is enough
+    /// room left for it
+    fn charge(&mut self, size_and_gas: Option<(u64, u64)>) -> bool {
+        match size_and_gas {
+            Some((bytes, gas)) if bytes <= self.bytes && gas <= self.gas => {
+                self.bytes -= bytes;
+                self.gas -= gas;
+                true
+            }
+            _ => false,
+        }
+    }
+}
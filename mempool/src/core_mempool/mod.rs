@@ -1,12 +1,24 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod clock;
+mod events;
+mod gas_estimator;
 mod index;
+mod latency_tracker;
 mod mempool;
+mod removal_log;
+pub(crate) mod state_snapshot;
 mod transaction;
 mod transaction_store;
 
-pub use self::{index::TxnPointer, mempool::Mempool as CoreMempool, transaction::TimelineState};
+pub use self::{
+    events::{EventFilter, MempoolEvent, MAX_SUBSCRIBERS},
+    index::TxnPointer,
+    mempool::Mempool as CoreMempool,
+    state_snapshot::{AccountQueue, MempoolStateSnapshot},
+    transaction::{PendingTransaction, TimelineState, TxnSource},
+};
 
 #[cfg(test)]
 mod unit_tests;
@@ -0,0 +1,63 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Why the transactions recently removed from Mempool were removed, so that clients asking what
+//! happened to their transaction get an answer once it is gone.
+
+use crate::core_mempool::{events::MempoolEvent, index::TxnPointer};
+use mempool_shared_proto::proto::mempool_status::MempoolTransactionStatusCode;
+use std::collections::{HashMap, VecDeque};
+
+/// Ring buffer of the last `capacity` removals, the oldest one being dropped first
+pub(crate) struct RemovalLog {
+    removals: VecDeque<TxnPointer>,
+    // last reason each transaction of the ring buffer was removed for, along with the number of
+    // its removals in the ring buffer
+    index: HashMap<TxnPointer, (MempoolTransactionStatusCode, usize)>,
+    capacity: usize,
+}
+
+impl RemovalLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            removals: VecDeque::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records the removal a Mempool event reports, if it reports one
+    pub(crate) fn record(&mut self, event: MempoolEvent) {
+        let (txn, reason) = match event {
+            MempoolEvent::TxnAdded(_) | MempoolEvent::TxnBroadcast(_) => return,
+            MempoolEvent::TxnRemovedCommitted(txn) => {
+                (txn, MempoolTransactionStatusCode::Committed)
+            }
+            MempoolEvent::TxnRejected(txn) => (txn, MempoolTransactionStatusCode::Rejected),
+            MempoolEvent::TxnExpired(txn) => (txn, MempoolTransactionStatusCode::Expired),
+            MempoolEvent::TxnEvicted(txn) => (txn, MempoolTransactionStatusCode::Evicted),
+            MempoolEvent::TxnReplaced(txn) => (txn, MempoolTransactionStatusCode::Replaced),
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        if self.removals.len() == self.capacity {
+            if let Some(oldest) = self.removals.pop_front() {
+                if let Some((_, count)) = self.index.get_mut(&oldest) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.index.remove(&oldest);
+                    }
+                }
+            }
+        }
+        self.removals.push_back(txn);
+        let entry = self.index.entry(txn).or_insert((reason, 0));
+        *entry = (reason, entry.1 + 1);
+    }
+
+    /// Why `txn` was last removed, if it still is in the log
+    pub(crate) fn get(&self, txn: &TxnPointer) -> Option<MempoolTransactionStatusCode> {
+        self.index.get(txn).map(|(reason, _)| *reason)
+    }
+}
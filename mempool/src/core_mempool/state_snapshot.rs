@@ -0,0 +1,73 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Summary of the internal state of Mempool, so that operators can look into a misbehaving Mempool,
+//! e.g. through the debug interface of the node.
+
+use crate::core_mempool::CoreMempool;
+use std::{cmp::Reverse, ops::Deref, time::Duration};
+use types::account_address::AccountAddress;
+
+/// Number of transactions of an account waiting in Mempool
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountQueue {
+    /// Address of the account
+    pub address: AccountAddress,
+    /// Number of transactions of the account
+    pub num_transactions: usize,
+    /// Number of transactions which can be included in the next block
+    pub num_ready: usize,
+}
+
+/// Sizes of the indexes of Mempool, depths of the queues of the accounts with the most
+/// transactions, age of the oldest transaction and size of all transactions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MempoolStateSnapshot {
+    /// Number of transactions in Mempool
+    pub num_transactions: usize,
+    /// Number of transactions which can be included in the next block
+    pub priority_index_size: usize,
+    /// Number of transactions waiting for a transaction of their account with a lower sequence
+    /// number
+    pub parking_lot_index_size: usize,
+    /// Number of transactions which can be broadcast to peers
+    pub timeline_index_size: usize,
+    /// Number of transactions expiring by the system TTL
+    pub system_ttl_index_size: usize,
+    /// Number of transactions expiring by their expiration time
+    pub expiration_time_index_size: usize,
+    /// Number of accounts with transactions in Mempool
+    pub num_accounts: usize,
+    /// Deepest queue first
+    pub accounts: Vec<AccountQueue>,
+    /// `None` if Mempool is empty
+    pub oldest_transaction_age: Option<Duration>,
+    /// Cumulative size in bytes of the raw transactions
+    pub bytes_resident: usize,
+}
+
+/// Summary of the internal state of the Mempool `lock` gives access to, with the queues of the
+/// `max_accounts` accounts with the most transactions.
+/// Mempool is only accessed to copy the depths of the queues and then to count the ready
+/// transactions of the reported accounts, the accounts being ranked in between, so that a lock
+/// guarding Mempool is held briefly
+pub(crate) fn take_snapshot<M: Deref<Target = CoreMempool>>(
+    lock: impl Fn() -> M,
+    max_accounts: usize,
+) -> MempoolStateSnapshot {
+    let (mut snapshot, depths) = lock().state_summary();
+    let addresses = deepest_accounts(depths, max_accounts);
+    snapshot.accounts = lock().account_queues(&addresses);
+    snapshot
+}
+
+/// Addresses of the `max_accounts` accounts with the deepest queues, given the depth of the queue
+/// of each account
+fn deepest_accounts(
+    mut depths: Vec<(AccountAddress, usize)>,
+    max_accounts: usize,
+) -> Vec<AccountAddress> {
+    depths.sort_by_key(|(address, depth)| (Reverse(*depth), *address));
+    depths.truncate(max_accounts);
+    depths.into_iter().map(|(address, _)| address).collect()
+}
@@ -7,24 +7,31 @@ use types::{account_address::AccountAddress, transaction::SignedTransaction};
 #[derive(Clone)]
 pub struct MempoolTransaction {
     pub txn: SignedTransaction,
+    // system time at which the transaction entered mempool
+    pub insertion_time: Duration,
     // system expiration time of transaction. It should be removed from mempool by that time
     pub expiration_time: Duration,
     pub gas_amount: u64,
     pub timeline_state: TimelineState,
+    // whether the sender is privileged, see `MempoolConfig::privileged_senders`
+    pub is_privileged: bool,
 }
 
 impl MempoolTransaction {
     pub(crate) fn new(
         txn: SignedTransaction,
+        insertion_time: Duration,
         expiration_time: Duration,
         gas_amount: u64,
         timeline_state: TimelineState,
     ) -> Self {
         Self {
             txn,
+            insertion_time,
             gas_amount,
             expiration_time,
             timeline_state,
+            is_privileged: false,
         }
     }
     pub(crate) fn get_sequence_number(&self) -> u64 {
@@ -36,17 +43,55 @@ impl MempoolTransaction {
     pub(crate) fn get_gas_price(&self) -> u64 {
         self.txn.gas_unit_price()
     }
+    pub(crate) fn get_source(&self) -> TxnSource {
+        // transactions received from other peers are the only ones that are never broadcast
+        match self.timeline_state {
+            TimelineState::NonQualified => TxnSource::Peer,
+            _ => TxnSource::Local,
+        }
+    }
+}
+
+/// A transaction of an account waiting in Mempool to be committed
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingTransaction {
+    pub txn: SignedTransaction,
+    /// System time, since the Unix epoch, at which the transaction entered Mempool
+    pub insertion_time: Duration,
+    /// Whether the transaction can be included in the next block. A transaction is not ready
+    /// while a transaction with a lower sequence number of the same account is missing from
+    /// Mempool
+    pub is_ready: bool,
 }
 
+/// Whether a transaction can be broadcast to peers
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum TimelineState {
-    // transaction is ready for broadcast
-    // Associated integer represents it's position in log of such transactions
+    /// transaction is ready for broadcast
+    /// Associated integer represents it's position in log of such transactions
     Ready(u64),
-    // transaction is not yet ready for broadcast
-    // but it might change in a future
+    /// transaction is not yet ready for broadcast
+    /// but it might change in a future
     NotReady,
-    // transaction will never be qualified for broadcasting
-    // currently we don't broadcast transactions originated on other peers
+    /// transaction will never be qualified for broadcasting
+    /// currently we don't broadcast transactions originated on other peers
     NonQualified,
 }
+
+/// Where a transaction was received from. Each source has its own share of Mempool capacity
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum TxnSource {
+    // submitted to this node through Admission Control
+    Local,
+    // received from another peer through SharedMempool
+    Peer,
+}
+
+impl TxnSource {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            TxnSource::Local => "local",
+            TxnSource::Peer => "peer",
+        }
+    }
+}
@@ -3,24 +3,31 @@
 
 use crate::{
     core_mempool::{
+        events::{EventFilter, MempoolEvent, MempoolEventBroadcaster},
+        gas_estimator::GasPriceHistogram,
         index::{
             AccountTransactions, ParkingLotIndex, PriorityIndex, PriorityQueueIter, TTLIndex,
-            TimelineIndex,
+            TimelineIndex, TxnPointer,
         },
-        transaction::{MempoolTransaction, TimelineState},
+        removal_log::RemovalLog,
+        state_snapshot::{AccountQueue, MempoolStateSnapshot},
+        transaction::{MempoolTransaction, PendingTransaction, TimelineState, TxnSource},
     },
     OP_COUNTERS,
 };
 use config::config::MempoolConfig;
 use failure::prelude::*;
+use futures_preview::channel::mpsc;
 use logger::prelude::*;
 use mempool_shared_proto::{
-    proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
+    proto::mempool_status::{MempoolAddTransactionStatusCode, MempoolTransactionStatusCode},
+    MempoolAddTransactionStatus,
 };
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     ops::Bound,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::Duration,
 };
 use types::{account_address::AccountAddress, transaction::SignedTransaction};
 
@@ -41,9 +48,32 @@ pub struct TransactionStore {
     // keeps track of "non-ready" txns (transactions that can't be included in next block)
     parking_lot_index: ParkingLotIndex,
 
+    // number of transactions from each source, see `TxnSource`
+    local_txns: usize,
+    peer_txns: usize,
+    // number of transactions of privileged senders
+    privileged_txns: usize,
+    // cumulative size in bytes of the raw transactions
+    bytes_resident: usize,
+
+    // subscribers to the events of the transactions
+    events: MempoolEventBroadcaster,
+    // reasons of the last removals of transactions
+    removals: RemovalLog,
+    // gas unit prices of the transactions
+    gas_prices: GasPriceHistogram,
+
     // configuration
     capacity: usize,
     capacity_per_user: usize,
+    non_ready_capacity_per_user: usize,
+    sequence_number_future_window: u64,
+    local_capacity: usize,
+    peer_capacity: usize,
+    eviction_min_gas_price_bump: u64,
+    replacement_min_gas_price_bump: u64,
+    privileged_senders: HashSet<AccountAddress>,
+    privileged_capacity: usize,
 }
 
 impl TransactionStore {
@@ -61,9 +91,26 @@ impl TransactionStore {
             timeline_index: TimelineIndex::new(),
             parking_lot_index: ParkingLotIndex::new(),
 
+            local_txns: 0,
+            peer_txns: 0,
+            privileged_txns: 0,
+            bytes_resident: 0,
+
+            events: MempoolEventBroadcaster::default(),
+            removals: RemovalLog::new(config.removal_log_capacity),
+            gas_prices: GasPriceHistogram::default(),
+
             // configuration
             capacity: config.capacity,
             capacity_per_user: config.capacity_per_user,
+            non_ready_capacity_per_user: config.non_ready_capacity_per_user,
+            sequence_number_future_window: config.sequence_number_future_window,
+            local_capacity: config.local_capacity,
+            peer_capacity: config.peer_capacity,
+            eviction_min_gas_price_bump: config.eviction_min_gas_price_bump,
+            replacement_min_gas_price_bump: config.replacement_min_gas_price_bump,
+            privileged_senders: config.get_privileged_senders(),
+            privileged_capacity: config.privileged_capacity,
         }
     }
 
@@ -83,27 +130,101 @@ impl TransactionStore {
         None
     }
 
+    /// size in bytes of the signed transaction and max gas amount of the transaction identified by
+    /// account address + sequence_number
+    pub(crate) fn get_size_and_gas(
+        &self,
+        address: &AccountAddress,
+        sequence_number: u64,
+    ) -> Option<(u64, u64)> {
+        self.transactions
+            .get(&address)
+            .and_then(|txns| txns.get(&sequence_number))
+            .map(|txn| {
+                (
+                    txn.txn.signed_txn_bytes_len() as u64,
+                    txn.txn.max_gas_amount(),
+                )
+            })
+    }
+
     /// insert transaction into TransactionStore
     /// performs validation checks and updates indexes
     pub(crate) fn insert(
         &mut self,
-        txn: MempoolTransaction,
+        mut txn: MempoolTransaction,
         current_sequence_number: u64,
     ) -> MempoolAddTransactionStatus {
-        if self.handle_gas_price_update(&txn).is_err() {
+        txn.is_privileged = self.privileged_senders.contains(&txn.get_sender());
+
+        let max_sequence_number =
+            current_sequence_number.saturating_add(self.sequence_number_future_window);
+        if txn.get_sequence_number() > max_sequence_number {
+            OP_COUNTERS.inc("sequence_number_too_new");
+            return MempoolAddTransactionStatus::new(
+                MempoolAddTransactionStatusCode::SequenceNumberTooNew,
+                format!(
+                    "transaction sequence number is {}, max sequence number is {}",
+                    txn.get_sequence_number(),
+                    max_sequence_number,
+                ),
+            );
+        }
+
+        // the transaction being replaced is only removed once the replacement passed every check,
+        // so that it is kept if the replacement is turned away. Until then, it is left out of the
+        // counts the replacement is checked against, as the replacement takes its place
+        let replaced = match self.check_replacement(&txn) {
+            Ok(replaced) => replaced,
+            Err(e) => {
+                return MempoolAddTransactionStatus::new(
+                    MempoolAddTransactionStatusCode::Underpriced,
+                    e.to_string(),
+                );
+            }
+        };
+        let is_replacement = replaced.is_some();
+
+        // the transactions of privileged senders are not turned away for lack of space, as long as
+        // their lane has room left
+        let mut privileged_txns = self.privileged_txns;
+        if replaced.map_or(false, |(_, is_privileged)| is_privileged) {
+            privileged_txns -= 1;
+        }
+        let bypasses_capacity = txn.is_privileged && privileged_txns < self.privileged_capacity;
+        if txn.is_privileged && !bypasses_capacity {
+            OP_COUNTERS.inc("privileged_lane_full");
+        }
+
+        // the partition is checked first, so that no transaction is evicted to make room for a
+        // transaction which is then turned away because its partition is full
+        let source = txn.get_source();
+        let (mut partition_size, partition_capacity) = self.partition(source);
+        if replaced.map_or(false, |(replaced_source, _)| replaced_source == source) {
+            partition_size -= 1;
+        }
+        if !bypasses_capacity && partition_size >= partition_capacity {
+            OP_COUNTERS.inc(&format!("partition_full.{}", source.name()));
             return MempoolAddTransactionStatus::new(
-                MempoolAddTransactionStatusCode::InvalidUpdate,
-                format!("Failed to update gas price to {}", txn.get_gas_price()),
+                MempoolAddTransactionStatusCode::MempoolIsFull,
+                format!(
+                    "{} transactions: {}, capacity: {}",
+                    source.name(),
+                    partition_size,
+                    partition_capacity,
+                ),
             );
         }
 
-        if self.check_if_full() {
+        // a replacement takes the place of the transaction it replaces, so that it never needs room
+        if !bypasses_capacity && !is_replacement && self.check_if_full(txn.get_gas_price()) {
             return MempoolAddTransactionStatus::new(
                 MempoolAddTransactionStatusCode::MempoolIsFull,
                 format!(
-                    "mempool size: {}, capacity: {}",
+                    "mempool size: {}, capacity: {}, gas price: {}",
                     self.system_ttl_index.size(),
                     self.capacity,
+                    txn.get_gas_price(),
                 ),
             );
         }
@@ -115,33 +236,100 @@ impl TransactionStore {
             .entry(address)
             .or_insert_with(AccountTransactions::new);
 
-        if let Some(txns) = self.transactions.get_mut(&address) {
+        if let Some(txns) = self.transactions.get(&address) {
             // capacity check
-            if txns.len() >= self.capacity_per_user {
+            let num_txns = if is_replacement {
+                txns.len() - 1
+            } else {
+                txns.len()
+            };
+            if num_txns >= self.capacity_per_user {
                 return MempoolAddTransactionStatus::new(
                     MempoolAddTransactionStatusCode::TooManyTransactions,
                     format!(
                         "txns length: {} capacity per user: {}",
-                        txns.len(),
-                        self.capacity_per_user,
+                        num_txns, self.capacity_per_user,
                     ),
                 );
             }
 
+            // non-ready capacity check, which a replacement passes as it doesn't add a transaction
+            let mut next_sequence_number = current_sequence_number;
+            while txns.contains_key(&next_sequence_number) {
+                next_sequence_number += 1;
+            }
+            if !is_replacement && sequence_number > next_sequence_number {
+                let non_ready_txns = txns
+                    .range((Bound::Excluded(next_sequence_number), Bound::Unbounded))
+                    .count();
+                if non_ready_txns >= self.non_ready_capacity_per_user {
+                    OP_COUNTERS.inc("non_ready_capacity_per_user_reached");
+                    return MempoolAddTransactionStatus::new(
+                        MempoolAddTransactionStatusCode::TooManyNonReadyTransactions,
+                        format!(
+                            "non-ready txns: {} non-ready capacity per user: {}",
+                            non_ready_txns, self.non_ready_capacity_per_user,
+                        ),
+                    );
+                }
+            }
+        }
+
+        if is_replacement {
+            if let Some(replaced_txn) = self
+                .transactions
+                .get_mut(&address)
+                .and_then(|txns| txns.remove(&sequence_number))
+            {
+                self.index_remove(&replaced_txn);
+                self.emit(MempoolEvent::TxnReplaced((address, sequence_number)));
+            }
+        }
+
+        if let Some(txns) = self.transactions.get_mut(&address) {
             // insert into storage and other indexes
             self.system_ttl_index.insert(&txn);
             self.expiration_time_index.insert(&txn);
+            self.gas_prices.insert(txn.get_gas_price());
+            self.bytes_resident += txn.txn.raw_txn_bytes_len();
+            if txn.is_privileged {
+                self.privileged_txns += 1;
+            }
             txns.insert(sequence_number, txn);
+            match source {
+                TxnSource::Local => self.local_txns += 1,
+                TxnSource::Peer => self.peer_txns += 1,
+            }
             self.track_indices();
         }
         self.process_ready_transactions(&address, current_sequence_number);
-        MempoolAddTransactionStatus::new(MempoolAddTransactionStatusCode::Valid, "".to_string())
+        self.emit(MempoolEvent::TxnAdded((address, sequence_number)));
+        if is_replacement {
+            OP_COUNTERS.inc("replaced");
+            MempoolAddTransactionStatus::new(
+                MempoolAddTransactionStatusCode::Replaced,
+                "".to_string(),
+            )
+        } else {
+            MempoolAddTransactionStatus::new(MempoolAddTransactionStatusCode::Valid, "".to_string())
+        }
     }
 
     fn track_indices(&self) {
         OP_COUNTERS.set("txn.system_ttl_index", self.system_ttl_index.size());
         OP_COUNTERS.set("txn.parking_lot_index", self.parking_lot_index.size());
         OP_COUNTERS.set("txn.priority_index", self.priority_index.size());
+        OP_COUNTERS.set("txn.partition.local", self.local_txns);
+        OP_COUNTERS.set("txn.partition.peer", self.peer_txns);
+        OP_COUNTERS.set("txn.privileged", self.privileged_txns);
+    }
+
+    /// returns number of transactions and capacity of the partition for given source
+    fn partition(&self, source: TxnSource) -> (usize, usize) {
+        match source {
+            TxnSource::Local => (self.local_txns, self.local_capacity),
+            TxnSource::Peer => (self.peer_txns, self.peer_capacity),
+        }
     }
 
     /// Check if mempool can handle new insertion requests
@@ -150,17 +338,28 @@ impl TransactionStore {
     }
 
     /// checks if Mempool is full
-    /// If it's full, tries to free some space by evicting transactions from ParkingLot
-    fn check_if_full(&mut self) -> bool {
+    /// If it's full, tries to free some space by evicting the cheapest transaction from ParkingLot,
+    /// provided that the new transaction pays at least `eviction_min_gas_price_bump` more per gas
+    /// unit
+    fn check_if_full(&mut self, gas_price: u64) -> bool {
         if self.system_ttl_index.size() >= self.capacity {
             // try to free some space in Mempool from ParkingLot
-            if let Some((address, sequence_number)) = self.parking_lot_index.pop() {
-                if let Some(txn) = self
-                    .transactions
-                    .get_mut(&address)
-                    .and_then(|txns| txns.remove(&sequence_number))
+            if let Some(key) = self.parking_lot_index.cheapest().cloned() {
+                if gas_price
+                    >= key
+                        .gas_price
+                        .saturating_add(self.eviction_min_gas_price_bump)
                 {
-                    self.index_remove(&txn);
+                    let (address, sequence_number) = TxnPointer::from(&key);
+                    if let Some(txn) = self
+                        .transactions
+                        .get_mut(&address)
+                        .and_then(|txns| txns.remove(&sequence_number))
+                    {
+                        OP_COUNTERS.inc("evicted.parking_lot");
+                        self.index_remove(&txn);
+                        self.emit(MempoolEvent::TxnEvicted((address, sequence_number)));
+                    }
                 }
             }
         }
@@ -168,27 +367,33 @@ impl TransactionStore {
     }
 
     /// check if transaction is already present in Mempool
-    /// e.g. given request is update
-    /// we allow increase in gas price to speed up process
-    fn handle_gas_price_update(&mut self, txn: &MempoolTransaction) -> Result<()> {
-        if let Some(txns) = self.transactions.get_mut(&txn.get_sender()) {
-            if let Some(current_version) = txns.get_mut(&txn.get_sequence_number()) {
-                if current_version.txn.max_gas_amount() == txn.txn.max_gas_amount()
-                    && current_version.txn.payload() == txn.txn.payload()
-                    && current_version.txn.expiration_time() == txn.txn.expiration_time()
-                    && current_version.get_gas_price() < txn.get_gas_price()
-                {
-                    if let Some(txn) = txns.remove(&txn.get_sequence_number()) {
-                        self.index_remove(&txn);
-                    }
-                } else {
-                    return Err(format_err!("Invalid gas price update. txn gas price: {}, current_version gas price: {}",
-                            txn.get_gas_price(),
-                            current_version.get_gas_price()));
+    /// e.g. given request is replacement
+    /// a pending transaction can be replaced by any other one with the same sequence number, as long
+    /// as it pays at least `replacement_min_gas_price_bump` more per gas unit, so that a sender can
+    /// unblock its account when a transaction is stuck because of a low gas price.
+    /// Returns the source of the transaction `txn` would replace, and whether it is privileged, if
+    /// there is one. It is up to the caller to remove it
+    fn check_replacement(&self, txn: &MempoolTransaction) -> Result<Option<(TxnSource, bool)>> {
+        if let Some(txns) = self.transactions.get(&txn.get_sender()) {
+            if let Some(current_version) = txns.get(&txn.get_sequence_number()) {
+                let min_gas_price = current_version
+                    .get_gas_price()
+                    .saturating_add(self.replacement_min_gas_price_bump);
+                if txn.get_gas_price() < min_gas_price {
+                    bail!(
+                        "Underpriced replacement. txn gas price: {}, current_version gas price: {}, min gas price: {}",
+                        txn.get_gas_price(),
+                        current_version.get_gas_price(),
+                        min_gas_price,
+                    );
                 }
+                return Ok(Some((
+                    current_version.get_source(),
+                    current_version.is_privileged,
+                )));
             }
         }
-        Ok(())
+        Ok(None)
     }
 
     /// fixes following invariants:
@@ -196,6 +401,7 @@ impl TransactionStore {
     /// supposed to be included in both PriorityIndex (ordering for Consensus) and
     /// TimelineIndex (txns for SharedMempool)
     /// Other txns are considered to be "non-ready" and should be added to ParkingLotIndex
+    /// Parked txns are promoted as soon as the gap before them is filled
     fn process_ready_transactions(
         &mut self,
         address: &AccountAddress,
@@ -204,6 +410,7 @@ impl TransactionStore {
         if let Some(txns) = self.transactions.get_mut(&address) {
             let mut sequence_number = current_sequence_number;
             while let Some(txn) = txns.get_mut(&sequence_number) {
+                self.parking_lot_index.remove(txn);
                 self.priority_index.insert(txn);
 
                 if txn.timeline_state == TimelineState::NotReady {
@@ -248,6 +455,10 @@ impl TransactionStore {
 
             for transaction in txns_for_removal.values() {
                 self.index_remove(transaction);
+                self.emit(MempoolEvent::TxnRemovedCommitted((
+                    *account,
+                    transaction.get_sequence_number(),
+                )));
             }
         }
         self.process_ready_transactions(account, account_sequence_number);
@@ -257,6 +468,10 @@ impl TransactionStore {
         if let Some(txns) = self.transactions.remove(&account) {
             for transaction in txns.values() {
                 self.index_remove(&transaction);
+                self.emit(MempoolEvent::TxnRejected((
+                    *account,
+                    transaction.get_sequence_number(),
+                )));
             }
         }
     }
@@ -268,48 +483,188 @@ impl TransactionStore {
         self.priority_index.remove(&txn);
         self.timeline_index.remove(&txn);
         self.parking_lot_index.remove(&txn);
+        self.gas_prices.remove(txn.get_gas_price());
+        self.bytes_resident -= txn.txn.raw_txn_bytes_len();
+        match txn.get_source() {
+            TxnSource::Local => self.local_txns -= 1,
+            TxnSource::Peer => self.peer_txns -= 1,
+        }
+        if txn.is_privileged {
+            self.privileged_txns -= 1;
+        }
         self.track_indices();
     }
 
     /// returns gas amount required to process all transactions for given account
-    pub(crate) fn get_required_balance(&mut self, address: &AccountAddress) -> u64 {
+    /// except the one with `sequence_number`, which a new transaction would replace
+    pub(crate) fn get_required_balance(
+        &mut self,
+        address: &AccountAddress,
+        sequence_number: u64,
+    ) -> u64 {
         self.transactions.get_mut(&address).map_or(0, |txns| {
-            txns.iter().fold(0, |acc, (_, txn)| {
-                acc + txn.txn.gas_unit_price() * txn.gas_amount
-            })
+            txns.iter()
+                .filter(|(txn_sequence_number, _)| **txn_sequence_number != sequence_number)
+                .fold(0, |acc, (_, txn)| {
+                    acc + txn.txn.gas_unit_price() * txn.gas_amount
+                })
         })
     }
 
     /// Read `count` transactions from timeline since `timeline_id`
     /// Returns block of transactions and new last_timeline_id
     pub(crate) fn read_timeline(
-        &mut self,
+        &self,
         timeline_id: u64,
         count: usize,
     ) -> (Vec<SignedTransaction>, u64) {
         let mut batch = vec![];
         let mut last_timeline_id = timeline_id;
-        for (address, sequence_number) in self.timeline_index.read_timeline(timeline_id, count) {
-            if let Some(txn) = self
-                .transactions
-                .get_mut(&address)
-                .and_then(|txns| txns.get(&sequence_number))
-            {
-                batch.push(txn.txn.clone());
-                if let TimelineState::Ready(timeline_id) = txn.timeline_state {
-                    last_timeline_id = timeline_id;
-                }
+        for (id, (address, sequence_number)) in
+            self.timeline_index.read_timeline(timeline_id, count)
+        {
+            if let Some(txn) = self.get(&address, sequence_number) {
+                batch.push(txn);
             }
+            last_timeline_id = id;
         }
         (batch, last_timeline_id)
     }
 
-    /// GC old transactions
-    pub(crate) fn gc_by_system_ttl(&mut self) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("init timestamp failure");
+    /// Returns up to `count` transactions along with their source, the ones paying the most per
+    /// gas unit first
+    pub(crate) fn snapshot(&self, count: usize) -> Vec<(SignedTransaction, TxnSource)> {
+        let mut txns: Vec<_> = self
+            .transactions
+            .values()
+            .flat_map(|txns| txns.values())
+            .collect();
+        txns.sort_by_key(|txn| {
+            (
+                Reverse(txn.get_gas_price()),
+                txn.get_sender(),
+                txn.get_sequence_number(),
+            )
+        });
+        txns.into_iter()
+            .take(count)
+            .map(|txn| (txn.txn.clone(), txn.get_source()))
+            .collect()
+    }
+
+    /// Returns the sizes of the indexes, the age of the oldest transaction as of `now` and the size
+    /// of all transactions, without the queues of the accounts, along with the number of
+    /// transactions of each account. Only the accounts are visited, not their transactions, so
+    /// that Mempool is locked no longer than it takes to copy the depths of their queues
+    pub(crate) fn state_summary(
+        &self,
+        now: Duration,
+    ) -> (MempoolStateSnapshot, Vec<(AccountAddress, usize)>) {
+        let depths: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(_, txns)| !txns.is_empty())
+            .map(|(address, txns)| (*address, txns.len()))
+            .collect();
+        // all transactions live the same system TTL, so that the first to expire is the oldest
+        let oldest_transaction_age = self
+            .system_ttl_index
+            .first()
+            .and_then(|key| {
+                self.transactions
+                    .get(&key.address)?
+                    .get(&key.sequence_number)
+            })
+            .map(|txn| now.checked_sub(txn.insertion_time).unwrap_or_default());
+        let snapshot = MempoolStateSnapshot {
+            num_transactions: self.system_ttl_index.size(),
+            priority_index_size: self.priority_index.size(),
+            parking_lot_index_size: self.parking_lot_index.size(),
+            timeline_index_size: self.timeline_index.size(),
+            system_ttl_index_size: self.system_ttl_index.size(),
+            expiration_time_index_size: self.expiration_time_index.size(),
+            num_accounts: depths.len(),
+            accounts: vec![],
+            oldest_transaction_age,
+            bytes_resident: self.bytes_resident,
+        };
+        (snapshot, depths)
+    }
+
+    /// Returns the queue of `address`, with the number of its transactions which can be included
+    /// in the next block
+    pub(crate) fn account_queue(&self, address: &AccountAddress) -> AccountQueue {
+        let txns = self.transactions.get(address);
+        AccountQueue {
+            address: *address,
+            num_transactions: txns.map_or(0, |txns| txns.len()),
+            num_ready: txns.map_or(0, |txns| {
+                txns.values()
+                    .filter(|txn| self.priority_index.contains(txn))
+                    .count()
+            }),
+        }
+    }
+
+    /// Returns the gas unit prices of the transactions
+    pub(crate) fn gas_prices(&self) -> &GasPriceHistogram {
+        &self.gas_prices
+    }
+
+    /// Returns a stream of the events of the transactions `filter` selects from now on
+    pub(crate) fn subscribe_events(
+        &self,
+        filter: EventFilter,
+    ) -> Result<mpsc::Receiver<MempoolEvent>> {
+        self.events.subscribe(filter)
+    }
+
+    /// Records the removal `event` reports, if any, and sends it to the subscribers
+    pub(crate) fn emit(&mut self, event: MempoolEvent) {
+        self.removals.record(event);
+        self.events.emit(event);
+    }
+
+    /// Returns whether a transaction is stored, or why it was removed if it was recently
+    pub(crate) fn get_transaction_status(
+        &self,
+        sender: &AccountAddress,
+        sequence_number: u64,
+    ) -> MempoolTransactionStatusCode {
+        let is_pending = self
+            .transactions
+            .get(sender)
+            .map_or(false, |txns| txns.contains_key(&sequence_number));
+        if is_pending {
+            return MempoolTransactionStatusCode::Pending;
+        }
+        self.removals
+            .get(&(*sender, sequence_number))
+            .unwrap_or(MempoolTransactionStatusCode::Unknown)
+    }
+
+    /// Returns the transactions of `address`, by sequence number
+    /// Transactions are ready when they are in the PriorityIndex, e.g. when they can be included in
+    /// the next block
+    pub(crate) fn get_account_transactions(
+        &self,
+        address: &AccountAddress,
+    ) -> Vec<PendingTransaction> {
+        self.transactions
+            .get(address)
+            .map_or_else(Vec::new, |txns| {
+                txns.values()
+                    .map(|txn| PendingTransaction {
+                        txn: txn.txn.clone(),
+                        insertion_time: txn.insertion_time,
+                        is_ready: self.priority_index.contains(txn),
+                    })
+                    .collect()
+            })
+    }
 
+    /// GC the transactions whose system TTL is over as of `now`
+    pub(crate) fn gc_by_system_ttl(&mut self, now: Duration) {
         self.gc(now, true);
     }
 
@@ -339,6 +694,7 @@ impl TransactionStore {
                     let status = if is_active { "active" } else { "parked" };
                     OP_COUNTERS.inc(&format!("{}.{}", index_name, status));
                     self.index_remove(&txn);
+                    self.emit(MempoolEvent::TxnExpired((key.address, key.sequence_number)));
                 }
             }
         }
@@ -146,7 +146,6 @@ impl ConsensusMock {
 
 pub(crate) fn exist_in_metrics_cache(mempool: &CoreMempool, txn: &SignedTransaction) -> bool {
     mempool
-        .metrics_cache
-        .get(&(txn.sender(), txn.sequence_number()))
-        .is_some()
+        .latency_tracker
+        .is_tracked(&(txn.sender(), txn.sequence_number()))
 }
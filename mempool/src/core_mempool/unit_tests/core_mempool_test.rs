@@ -2,15 +2,26 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::core_mempool::{
+    clock::MockClock,
+    events::{MempoolEventBroadcaster, SUBSCRIBER_BUFFER_SIZE},
+    latency_tracker::LatencyTracker,
+    removal_log::RemovalLog,
+    state_snapshot::{take_snapshot, AccountQueue, MempoolStateSnapshot},
     unit_tests::common::{
         add_signed_txn, add_txn, add_txns_to_mempool, exist_in_metrics_cache, setup_mempool,
         TestTransaction,
     },
-    CoreMempool, TimelineState,
+    CoreMempool, EventFilter, MempoolEvent, TimelineState, TxnSource, MAX_SUBSCRIBERS,
 };
 use config::config::NodeConfigHelpers;
-use mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
-use std::{collections::HashSet, time::Duration};
+use mempool_shared_proto::proto::mempool_status::{
+    MempoolAddTransactionStatusCode, MempoolTransactionStatusCode,
+};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use types::transaction::SignedTransaction;
 
 #[test]
@@ -100,17 +111,107 @@ fn test_update_invalid_transaction_in_mempool() {
         vec![TestTransaction::new(0, 0, 1), TestTransaction::new(1, 0, 2)],
     );
     let updated_txn = TestTransaction::make_signed_transaction_with_max_gas_amount(
-        &TestTransaction::new(0, 0, 5),
+        &TestTransaction::new(0, 0, 1),
         200,
     );
-    let _added_tnx = add_signed_txn(&mut mempool, updated_txn);
+    assert_eq!(
+        mempool
+            .add_txn(updated_txn, 0, 0, 1000, TimelineState::NotReady)
+            .code,
+        MempoolAddTransactionStatusCode::Underpriced
+    );
 
-    // since both gas price and mas gas amount were updated, the ordering should not have changed.
+    // since the gas price was not increased, the transaction was not replaced.
     // the second transaction with gas price 2 should come first
     assert_eq!(consensus.get_block(&mut mempool, 1), vec![txns[1].clone()]);
     let next_tnx = consensus.get_block(&mut mempool, 1);
     assert_eq!(next_tnx, vec![txns[0].clone()]);
-    assert_eq!(next_tnx[0].gas_unit_price(), 1);
+    assert_eq!(next_tnx[0].max_gas_amount(), 100);
+}
+
+#[test]
+fn test_replace_transaction_in_mempool() {
+    let (mut mempool, mut consensus) = setup_mempool();
+    let txns = add_txns_to_mempool(
+        &mut mempool,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(1, 0, 2)],
+    );
+    // a replacement may change more than the gas price
+    let replacement = TestTransaction::make_signed_transaction_with_max_gas_amount(
+        &TestTransaction::new(0, 0, 5),
+        200,
+    );
+    assert_eq!(
+        mempool
+            .add_txn(replacement.clone(), 0, 0, 1000, TimelineState::NotReady)
+            .code,
+        MempoolAddTransactionStatusCode::Replaced
+    );
+
+    assert_eq!(
+        consensus.get_block(&mut mempool, 1),
+        vec![replacement.clone()]
+    );
+    assert_eq!(consensus.get_block(&mut mempool, 1), vec![txns[1].clone()]);
+    assert!(consensus.get_block(&mut mempool, 1).is_empty());
+}
+
+#[test]
+fn test_rejected_replacement_keeps_original() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.peer_capacity = 1;
+    let mut pool = CoreMempool::new(&config);
+    let mut consensus = ConsensusMock::new();
+    let original = add_txns_to_mempool(&mut pool, vec![TestTransaction::new(0, 0, 1)]);
+    // a peer transaction fills the peer partition
+    let peer_txn = TestTransaction::new(1, 0, 2).make_signed_transaction();
+    pool.add_txn(peer_txn.clone(), 0, 0, 1000, TimelineState::NonQualified);
+
+    // the replacement received from a peer pays enough, but doesn't fit in the peer partition
+    let replacement = TestTransaction::new(0, 0, 5).make_signed_transaction();
+    assert_eq!(
+        pool.add_txn(replacement, 0, 0, 1000, TimelineState::NonQualified)
+            .code,
+        MempoolAddTransactionStatusCode::MempoolIsFull
+    );
+
+    // the original transaction is still there
+    assert_eq!(consensus.get_block(&mut pool, 1), vec![peer_txn]);
+    assert_eq!(consensus.get_block(&mut pool, 1), original);
+    assert_eq!(
+        pool.get_transaction_status(&TestTransaction::get_address(0), 0),
+        MempoolTransactionStatusCode::Pending
+    );
+}
+
+#[test]
+fn test_replacement_events() {
+    let mut pool = setup_mempool().0;
+    let address = TestTransaction::get_address(0);
+    let mut events = pool
+        .subscribe_events(EventFilter {
+            sender: address,
+            sequence_number: Some(0),
+        })
+        .unwrap();
+    add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(0, 0, 5)],
+    );
+
+    // the replaced transaction is reported as removed before the replacement is added
+    let mut received = vec![];
+    while let Ok(Some(event)) = events.try_next() {
+        received.push(event);
+    }
+    assert_eq!(
+        received,
+        vec![
+            MempoolEvent::TxnAdded((address, 0)),
+            MempoolEvent::TxnReplaced((address, 0)),
+            MempoolEvent::TxnAdded((address, 0)),
+        ]
+    );
 }
 
 #[test]
@@ -302,6 +403,102 @@ fn test_capacity() {
     assert!(add_txn(&mut pool, TestTransaction::new(1, 2, 1)).is_ok());
 }
 
+#[test]
+fn test_non_ready_capacity_per_user() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.non_ready_capacity_per_user = 2;
+    let mut pool = CoreMempool::new(&config);
+
+    // transactions 2 and 3 can't be executed before transaction 1
+    for seq in &[0, 2, 3] {
+        add_txn(&mut pool, TestTransaction::new(1, *seq, 1)).unwrap();
+    }
+    let txn = TestTransaction::new(1, 5, 1).make_signed_transaction();
+    assert_eq!(
+        pool.add_txn(txn, 0, 0, 1000, TimelineState::NotReady).code,
+        MempoolAddTransactionStatusCode::TooManyNonReadyTransactions
+    );
+    // other accounts are not affected
+    add_txn(&mut pool, TestTransaction::new(0, 5, 1)).unwrap();
+
+    // filling the gap makes room for more non-ready transactions
+    add_txn(&mut pool, TestTransaction::new(1, 1, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 5, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 6, 1)).unwrap();
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 7, 1)).is_err());
+    // ready transactions are only bound by the capacity per user
+    add_txn(&mut pool, TestTransaction::new(1, 4, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 7, 1)).unwrap();
+}
+
+#[test]
+fn test_capacity_partitions() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = 10;
+    config.mempool.local_capacity = 2;
+    config.mempool.peer_capacity = 3;
+    let mut pool = CoreMempool::new(&config);
+    let add_peer_txn = |pool: &mut CoreMempool, txn: TestTransaction| {
+        pool.add_txn(
+            txn.make_signed_transaction(),
+            0,
+            0,
+            1000,
+            TimelineState::NonQualified,
+        )
+        .code
+    };
+
+    // peers can't use more than their share of mempool
+    for seq in 0..3 {
+        assert_eq!(
+            add_peer_txn(&mut pool, TestTransaction::new(0, seq, 1)),
+            MempoolAddTransactionStatusCode::Valid
+        );
+    }
+    assert_eq!(
+        add_peer_txn(&mut pool, TestTransaction::new(0, 3, 1)),
+        MempoolAddTransactionStatusCode::MempoolIsFull
+    );
+
+    // local transactions are still accepted, up to their own share
+    add_txn(&mut pool, TestTransaction::new(1, 0, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 1, 1)).unwrap();
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 2, 1)).is_err());
+
+    // committing transactions frees space in their partition only
+    pool.remove_transaction(&TestTransaction::get_address(1), 0, false);
+    assert_eq!(
+        add_peer_txn(&mut pool, TestTransaction::new(0, 3, 1)),
+        MempoolAddTransactionStatusCode::MempoolIsFull
+    );
+    add_txn(&mut pool, TestTransaction::new(1, 2, 1)).unwrap();
+}
+
+#[test]
+fn test_full_partition_does_not_evict() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = 3;
+    config.mempool.local_capacity = 1;
+    config.mempool.peer_capacity = 2;
+    let mut pool = CoreMempool::new(&config);
+    // two non-ready peer transactions and a local one fill Mempool and the local partition
+    for address in 0..2 {
+        pool.add_txn(
+            TestTransaction::new(address, 1, 1).make_signed_transaction(),
+            0,
+            0,
+            1000,
+            TimelineState::NonQualified,
+        );
+    }
+    add_txn(&mut pool, TestTransaction::new(2, 0, 1)).unwrap();
+
+    // a local transaction is turned away without evicting a peer transaction from ParkingLot
+    assert!(add_txn(&mut pool, TestTransaction::new(3, 0, 100)).is_err());
+    assert_eq!(pool.snapshot(10).len(), 3);
+}
+
 #[test]
 fn test_parking_lot_eviction() {
     let mut config = NodeConfigHelpers::get_single_node_test_config(true);
@@ -328,6 +525,46 @@ fn test_parking_lot_eviction() {
     assert!(add_txn(&mut pool, TestTransaction::new(0, 2, 1)).is_err());
 }
 
+#[test]
+fn test_parking_lot_eviction_by_gas_price() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = 3;
+    config.mempool.eviction_min_gas_price_bump = 5;
+    let mut pool = CoreMempool::new(&config);
+    // one ready transaction and two non-ready ones, with different gas prices
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(1, 1, 10)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(2, 1, 2)).unwrap();
+
+    // Mempool is full. The cheapest non-ready transaction is only evicted for a transaction paying
+    // at least the minimum bump more
+    assert!(add_txn(&mut pool, TestTransaction::new(3, 0, 6)).is_err());
+    add_txn(&mut pool, TestTransaction::new(3, 0, 7)).unwrap();
+
+    // the next cheapest one goes next
+    assert!(add_txn(&mut pool, TestTransaction::new(4, 0, 14)).is_err());
+    add_txn(&mut pool, TestTransaction::new(4, 0, 15)).unwrap();
+
+    // ready transactions are never evicted, whatever they pay
+    assert!(add_txn(&mut pool, TestTransaction::new(5, 0, 100)).is_err());
+
+    // the evicted transactions don't become ready once the gap in their sequence numbers is filled
+    pool.remove_transaction(&TestTransaction::get_address(1), 0, false);
+    pool.remove_transaction(&TestTransaction::get_address(2), 0, false);
+    let mut senders: Vec<_> = pool
+        .get_block(10, HashSet::new())
+        .iter()
+        .map(SignedTransaction::sender)
+        .collect();
+    senders.sort();
+    let mut expected: Vec<_> = [0, 3, 4]
+        .iter()
+        .map(|address| TestTransaction::get_address(*address))
+        .collect();
+    expected.sort();
+    assert_eq!(senders, expected);
+}
+
 #[test]
 fn test_gc_ready_transaction() {
     let mut pool = setup_mempool().0;
@@ -359,3 +596,541 @@ fn test_gc_ready_transaction() {
     assert_eq!(timeline.len(), 1);
     assert_eq!(timeline[0].sequence_number(), 0);
 }
+
+#[test]
+fn test_snapshot() {
+    let mut pool = setup_mempool().0;
+    let transactions = add_txns_to_mempool(
+        &mut pool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(1, 0, 3),
+            TestTransaction::new(2, 0, 2),
+        ],
+    );
+    let peer_txn = TestTransaction::new(3, 0, 4).make_signed_transaction();
+    pool.add_txn(peer_txn.clone(), 0, 0, 1000, TimelineState::NonQualified);
+
+    // the transactions paying the most per gas unit come first, whatever their source
+    assert_eq!(
+        pool.snapshot(3),
+        vec![
+            (peer_txn, TxnSource::Peer),
+            (transactions[1].clone(), TxnSource::Local),
+            (transactions[2].clone(), TxnSource::Local),
+        ]
+    );
+    assert_eq!(pool.snapshot(10).len(), 4);
+}
+
+#[test]
+fn test_get_account_transactions() {
+    let mut pool = setup_mempool().0;
+    let transactions = add_txns_to_mempool(
+        &mut pool,
+        vec![
+            TestTransaction::new(0, 2, 1),
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(1, 0, 1),
+        ],
+    );
+    let address = TestTransaction::get_address(0);
+
+    // transactions come by sequence number, the ones after a gap are not ready
+    let pending = pool.get_account_transactions(&address);
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0].txn, transactions[1]);
+    assert!(pending[0].is_ready);
+    assert_eq!(pending[1].txn, transactions[0]);
+    assert!(!pending[1].is_ready);
+    assert!(pending[1].insertion_time <= pending[0].insertion_time);
+
+    // filling the gap makes the following transactions ready
+    add_txn(&mut pool, TestTransaction::new(0, 1, 1)).unwrap();
+    let pending = pool.get_account_transactions(&address);
+    assert_eq!(pending.len(), 3);
+    assert!(pending.iter().all(|txn| txn.is_ready));
+
+    assert!(pool
+        .get_account_transactions(&TestTransaction::get_address(2))
+        .is_empty());
+}
+
+#[test]
+fn test_subscribe_events() {
+    let mut pool = setup_mempool().0;
+    let address = TestTransaction::get_address;
+    let subscribe = |pool: &CoreMempool, sender, sequence_number| {
+        pool.subscribe_events(EventFilter {
+            sender,
+            sequence_number,
+        })
+        .unwrap()
+    };
+    let mut events = subscribe(&pool, address(0), None);
+    let mut txn_events = subscribe(&pool, address(1), Some(0));
+    let mut other_txn_events = subscribe(&pool, address(1), Some(1));
+    add_txns_to_mempool(
+        &mut pool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(0, 1, 1),
+            TestTransaction::new(1, 0, 1),
+        ],
+    );
+    pool.remove_transaction(&TestTransaction::get_address(0), 1, false);
+    pool.remove_transaction(&TestTransaction::get_address(1), 0, true);
+
+    // each subscriber only receives the events of the transactions it follows
+    let mut received = vec![];
+    while let Ok(Some(event)) = events.try_next() {
+        received.push(event);
+    }
+    assert_eq!(
+        received,
+        vec![
+            MempoolEvent::TxnAdded((address(0), 0)),
+            MempoolEvent::TxnAdded((address(0), 1)),
+            MempoolEvent::TxnRemovedCommitted((address(0), 0)),
+            MempoolEvent::TxnRemovedCommitted((address(0), 1)),
+        ]
+    );
+    let mut received = vec![];
+    while let Ok(Some(event)) = txn_events.try_next() {
+        received.push(event);
+    }
+    assert_eq!(
+        received,
+        vec![
+            MempoolEvent::TxnAdded((address(1), 0)),
+            MempoolEvent::TxnRejected((address(1), 0)),
+        ]
+    );
+    assert!(other_txn_events.try_next().is_err());
+}
+
+#[test]
+fn test_lagging_subscriber_disconnected() {
+    let broadcaster = MempoolEventBroadcaster::default();
+    let txn = (TestTransaction::get_address(0), 0);
+    let other_txn = (TestTransaction::get_address(1), 0);
+    let mut events = broadcaster
+        .subscribe(EventFilter {
+            sender: txn.0,
+            sequence_number: None,
+        })
+        .unwrap();
+
+    // the events of other transactions don't fill the buffer of the subscriber
+    for _ in 0..2 * SUBSCRIBER_BUFFER_SIZE {
+        broadcaster.emit(MempoolEvent::TxnAdded(other_txn));
+    }
+    assert!(events.try_next().is_err());
+
+    // a subscriber which falls too far behind receives the buffered events, then its stream ends
+    // instead of skipping events
+    for _ in 0..2 * SUBSCRIBER_BUFFER_SIZE {
+        broadcaster.emit(MempoolEvent::TxnAdded(txn));
+    }
+    let mut received = 0;
+    loop {
+        match events.try_next() {
+            Ok(Some(event)) => {
+                assert_eq!(event, MempoolEvent::TxnAdded(txn));
+                received += 1;
+            }
+            Ok(None) => break,
+            Err(_) => panic!("the stream of a lagging subscriber should end"),
+        }
+    }
+    assert!(received >= SUBSCRIBER_BUFFER_SIZE && received <= SUBSCRIBER_BUFFER_SIZE + 1);
+}
+
+#[test]
+fn test_max_subscribers() {
+    let broadcaster = MempoolEventBroadcaster::default();
+    let filter = EventFilter {
+        sender: TestTransaction::get_address(0),
+        sequence_number: None,
+    };
+    let mut subscriptions = (0..MAX_SUBSCRIBERS)
+        .map(|_| broadcaster.subscribe(filter).unwrap())
+        .collect::<Vec<_>>();
+    assert!(broadcaster.subscribe(filter).is_err());
+
+    // the subscribers which went away free their slot
+    subscriptions.pop();
+    assert!(broadcaster.subscribe(filter).is_ok());
+}
+
+#[test]
+fn test_broadcast_events() {
+    let mut pool = setup_mempool().0;
+    let mut events = pool
+        .subscribe_events(EventFilter {
+            sender: TestTransaction::get_address(0),
+            sequence_number: None,
+        })
+        .unwrap();
+    add_txns_to_mempool(&mut pool, vec![TestTransaction::new(0, 0, 1)]);
+    let txn = (TestTransaction::get_address(0), 0);
+    // only the first broadcast of a transaction is reported
+    pool.record_broadcast(&[txn]);
+    pool.record_broadcast(&[txn]);
+
+    let mut received = vec![];
+    while let Ok(Some(event)) = events.try_next() {
+        received.push(event);
+    }
+    assert_eq!(
+        received,
+        vec![MempoolEvent::TxnAdded(txn), MempoolEvent::TxnBroadcast(txn)]
+    );
+}
+
+#[test]
+fn test_gas_price_estimate() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.consensus.max_block_size = 2;
+    let mut pool = CoreMempool::new(&config);
+
+    let estimate = pool.get_gas_price_estimate();
+    assert_eq!(estimate.mempool.count, 0);
+    assert_eq!(estimate.next_block_gas_price, 0);
+
+    // gas prices from 1 to 10
+    add_txns_to_mempool(
+        &mut pool,
+        (0..10)
+            .map(|sequence_number| TestTransaction::new(0, sequence_number, sequence_number + 1))
+            .collect(),
+    );
+    let estimate = pool.get_gas_price_estimate();
+    assert_eq!(estimate.mempool.count, 10);
+    assert_eq!(estimate.mempool.p50, 5);
+    assert_eq!(estimate.mempool.p90, 9);
+    assert_eq!(estimate.mempool.p99, 10);
+    // outbids the transaction paying the second most
+    assert_eq!(estimate.next_block_gas_price, 10);
+    assert_eq!(estimate.committed.count, 0);
+
+    // committed transactions leave mempool and count towards the committed percentiles
+    pool.remove_transaction(&TestTransaction::get_address(0), 0, false);
+    pool.remove_transaction(&TestTransaction::get_address(0), 1, false);
+    let estimate = pool.get_gas_price_estimate();
+    assert_eq!(estimate.mempool.count, 8);
+    assert_eq!(estimate.mempool.p50, 6);
+    assert_eq!(estimate.committed.count, 2);
+    assert_eq!(estimate.committed.p50, 1);
+    assert_eq!(estimate.committed.p99, 2);
+}
+
+#[test]
+fn test_get_block_with_budget() {
+    let mut pool = setup_mempool().0;
+    let expensive = TestTransaction::new(0, 0, 5).make_signed_transaction_with_max_gas_amount(1000);
+    let cheap = TestTransaction::new(1, 0, 1).make_signed_transaction_with_max_gas_amount(100);
+    add_signed_txn(&mut pool, expensive.clone()).unwrap();
+    add_signed_txn(&mut pool, cheap.clone()).unwrap();
+
+    // no budget
+    let block = pool.get_block_with_budget(10, u64::max_value(), u64::max_value(), HashSet::new());
+    assert_eq!(block, vec![expensive.clone(), cheap.clone()]);
+
+    // the transaction paying the most doesn't fit in the gas budget, the next one still does
+    let block = pool.get_block_with_budget(10, u64::max_value(), 500, HashSet::new());
+    assert_eq!(block, vec![cheap.clone()]);
+
+    // room for a single transaction
+    let max_bytes = expensive.signed_txn_bytes_len() as u64;
+    let block = pool.get_block_with_budget(10, max_bytes, u64::max_value(), HashSet::new());
+    assert_eq!(block, vec![expensive.clone()]);
+
+    // an ancestor that doesn't fit holds back its successors
+    add_signed_txn(
+        &mut pool,
+        TestTransaction::new(0, 1, 10).make_signed_transaction_with_max_gas_amount(100),
+    )
+    .unwrap();
+    let block = pool.get_block_with_budget(10, u64::max_value(), 500, HashSet::new());
+    assert_eq!(block, vec![cheap]);
+}
+
+#[test]
+fn test_privileged_senders() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = 2;
+    config.mempool.privileged_senders = vec![String::from(&TestTransaction::get_address(1))];
+    config.mempool.privileged_capacity = 1;
+    let mut pool = CoreMempool::new(&config);
+
+    // fill Mempool up, including with a non-ready transaction which could be evicted
+    add_txn(&mut pool, TestTransaction::new(0, 0, 10)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(0, 2, 10)).unwrap();
+
+    // the privileged sender still gets in, without evicting anything, despite paying less
+    let privileged = add_txns_to_mempool(&mut pool, vec![TestTransaction::new(1, 0, 1)]);
+    assert_eq!(
+        pool.get_account_transactions(&TestTransaction::get_address(1))
+            .len(),
+        1
+    );
+    assert_eq!(
+        pool.get_account_transactions(&TestTransaction::get_address(0))
+            .len(),
+        2
+    );
+    // while other senders are still turned away
+    assert!(add_txn(&mut pool, TestTransaction::new(0, 1, 1)).is_err());
+    // and so is the privileged sender, now that its lane is full
+    assert!(add_txn(&mut pool, TestTransaction::new(1, 1, 1)).is_err());
+
+    // the privileged transaction is drained first
+    let block = pool.get_block(3, HashSet::new());
+    assert_eq!(block.len(), 2);
+    assert_eq!(block[0], privileged[0]);
+}
+
+#[test]
+fn test_sequence_number_future_window() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.sequence_number_future_window = 2;
+    let mut pool = CoreMempool::new(&config);
+
+    let txn = TestTransaction::new(1, 3, 1).make_signed_transaction();
+    assert_eq!(
+        pool.add_txn(txn.clone(), 0, 0, 1000, TimelineState::NotReady)
+            .code,
+        MempoolAddTransactionStatusCode::SequenceNumberTooNew
+    );
+    // the window moves along with the sequence number of the account
+    assert_eq!(
+        pool.add_txn(txn, 0, 1, 1000, TimelineState::NotReady).code,
+        MempoolAddTransactionStatusCode::Valid
+    );
+}
+
+#[test]
+fn test_promote_parked_transactions() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.capacity = 3;
+    let mut pool = CoreMempool::new(&config);
+
+    // transaction 2 is parked until transaction 1 fills the gap
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+    add_txn(&mut pool, TestTransaction::new(0, 2, 1)).unwrap();
+    assert_eq!(pool.get_block(3, HashSet::new()).len(), 1);
+    add_txn(&mut pool, TestTransaction::new(0, 1, 1)).unwrap();
+    assert_eq!(pool.get_block(3, HashSet::new()).len(), 3);
+
+    // Mempool is full and there is no parked transaction left to evict
+    assert!(!pool.health_check());
+}
+
+#[test]
+fn test_commit_block() {
+    let (mut pool, mut consensus) = setup_mempool();
+    let transactions = add_txns_to_mempool(
+        &mut pool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(0, 1, 1),
+            TestTransaction::new(0, 2, 1),
+            TestTransaction::new(1, 3, 1),
+        ],
+    );
+    let account_0 = TestTransaction::get_address(0);
+    let account_1 = TestTransaction::get_address(1);
+
+    // transaction 1 of account 0 was committed through another node in the same block, and the
+    // sequence number of account 1 caught up with its parked transaction
+    pool.commit_block(
+        &[(account_0, 0, false)],
+        &[(account_0, 2), (account_1, 3)],
+        None,
+    );
+    let block: HashSet<_> = consensus.get_block(&mut pool, 3).into_iter().collect();
+    let expected: HashSet<_> = vec![transactions[2].clone(), transactions[3].clone()]
+        .into_iter()
+        .collect();
+    assert_eq!(block, expected);
+    assert_eq!(pool.get_account_transactions(&account_0).len(), 1);
+}
+
+#[test]
+fn test_state_snapshot() {
+    let (mut pool, _) = setup_mempool();
+    assert_eq!(take_snapshot(|| &pool, 10), MempoolStateSnapshot::default());
+
+    let transactions = add_txns_to_mempool(
+        &mut pool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(0, 1, 1),
+            TestTransaction::new(1, 0, 1),
+            // parked until transaction 1 of account 1 arrives
+            TestTransaction::new(1, 2, 1),
+            TestTransaction::new(1, 3, 1),
+        ],
+    );
+    let snapshot = take_snapshot(|| &pool, 1);
+    assert_eq!(snapshot.num_transactions, 5);
+    assert_eq!(snapshot.priority_index_size, 3);
+    assert_eq!(snapshot.parking_lot_index_size, 2);
+    assert_eq!(snapshot.timeline_index_size, 3);
+    assert_eq!(snapshot.system_ttl_index_size, 5);
+    assert_eq!(snapshot.num_accounts, 2);
+    // only the deepest queue is reported
+    assert_eq!(
+        snapshot.accounts,
+        vec![AccountQueue {
+            address: TestTransaction::get_address(1),
+            num_transactions: 3,
+            num_ready: 1,
+        }]
+    );
+    assert!(snapshot.oldest_transaction_age.is_some());
+    assert_eq!(
+        snapshot.bytes_resident,
+        transactions
+            .iter()
+            .map(SignedTransaction::raw_txn_bytes_len)
+            .sum::<usize>()
+    );
+}
+
+#[test]
+fn test_latency_tracker() {
+    let txn_a = (TestTransaction::get_address(0), 0);
+    let txn_b = (TestTransaction::get_address(1), 0);
+    let mut tracker =
+        LatencyTracker::new(1, Duration::from_secs(100), Arc::new(MockClock::default()));
+
+    tracker.record_insertion(txn_a);
+    tracker.record_broadcast(&txn_a);
+    tracker.record_pulled(&txn_a);
+    assert!(tracker.is_tracked(&txn_a));
+    tracker.record_removal(&txn_a, false);
+    assert!(!tracker.is_tracked(&txn_a));
+
+    // memory is bounded: the least recently used transaction is dropped first
+    tracker.record_insertion(txn_a);
+    tracker.record_insertion(txn_b);
+    assert!(!tracker.is_tracked(&txn_a));
+    assert!(tracker.is_tracked(&txn_b));
+
+    // transactions are forgotten after the TTL
+    let mut tracker =
+        LatencyTracker::new(1, Duration::from_millis(1), Arc::new(MockClock::default()));
+    tracker.record_insertion(txn_a);
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(!tracker.is_tracked(&txn_a));
+}
+
+#[test]
+fn test_expiration_window() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.min_expiration_window_secs = Some(10);
+    config.mempool.max_expiration_window_secs = Some(1000);
+    let mut pool = CoreMempool::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    let mut add_txn_expiring_in = |window: Duration| {
+        let txn = TestTransaction::new(0, 0, 1)
+            .make_signed_transaction_with_expiration_time(now + window);
+        pool.add_txn(txn, 0, 0, 1000, TimelineState::NotReady).code
+    };
+    assert_eq!(
+        add_txn_expiring_in(Duration::from_secs(1)),
+        MempoolAddTransactionStatusCode::ExpirationTooSoon
+    );
+    assert_eq!(
+        add_txn_expiring_in(Duration::from_secs(10 * 365 * 24 * 3600)),
+        MempoolAddTransactionStatusCode::ExpirationTooFar
+    );
+    assert_eq!(
+        add_txn_expiring_in(Duration::from_secs(100)),
+        MempoolAddTransactionStatusCode::Valid
+    );
+}
+
+#[test]
+fn test_transaction_status() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.system_transaction_timeout_secs = 0;
+    config.mempool.removal_log_capacity = 2;
+    let mut pool = CoreMempool::new(&config);
+    let address = TestTransaction::get_address;
+
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+    pool.system_transaction_timeout = Duration::from_secs(10);
+    add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(1, 0, 1), TestTransaction::new(1, 1, 1)],
+    );
+    assert_eq!(
+        pool.get_transaction_status(&address(0), 0),
+        MempoolTransactionStatusCode::Pending
+    );
+
+    pool.gc_by_system_ttl();
+    assert_eq!(
+        pool.get_transaction_status(&address(0), 0),
+        MempoolTransactionStatusCode::Expired
+    );
+    pool.remove_transaction(&address(1), 0, false);
+    assert_eq!(
+        pool.get_transaction_status(&address(1), 0),
+        MempoolTransactionStatusCode::Committed
+    );
+    assert_eq!(
+        pool.get_transaction_status(&address(1), 1),
+        MempoolTransactionStatusCode::Pending
+    );
+
+    // the oldest removal is forgotten to make room for the last one
+    pool.remove_transaction(&address(1), 1, true);
+    assert_eq!(
+        pool.get_transaction_status(&address(1), 1),
+        MempoolTransactionStatusCode::Rejected
+    );
+    assert_eq!(
+        pool.get_transaction_status(&address(0), 0),
+        MempoolTransactionStatusCode::Unknown
+    );
+    assert_eq!(
+        pool.get_transaction_status(&address(0), 5),
+        MempoolTransactionStatusCode::Unknown
+    );
+}
+
+#[test]
+fn test_removal_log_repeated_removals() {
+    let mut log = RemovalLog::new(2);
+    let txn = (TestTransaction::get_address(0), 0);
+    let other_txn = (TestTransaction::get_address(1), 0);
+
+    // a transaction removed again, e.g. after being resubmitted, is reported by its last removal
+    log.record(MempoolEvent::TxnExpired(txn));
+    log.record(MempoolEvent::TxnRemovedCommitted(txn));
+    assert_eq!(log.get(&txn), Some(MempoolTransactionStatusCode::Committed));
+
+    // forgetting its oldest removal keeps the last one
+    log.record(MempoolEvent::TxnEvicted(other_txn));
+    assert_eq!(log.get(&txn), Some(MempoolTransactionStatusCode::Committed));
+    assert_eq!(
+        log.get(&other_txn),
+        Some(MempoolTransactionStatusCode::Evicted)
+    );
+
+    log.record(MempoolEvent::TxnRejected(other_txn));
+    assert_eq!(log.get(&txn), None);
+    assert_eq!(
+        log.get(&other_txn),
+        Some(MempoolTransactionStatusCode::Rejected)
+    );
+
+    // replaced transactions are logged as such
+    log.record(MempoolEvent::TxnReplaced(txn));
+    assert_eq!(log.get(&txn), Some(MempoolTransactionStatusCode::Replaced));
+}
@@ -4,3 +4,4 @@
 mod common;
 mod core_mempool_test;
 mod shared_mempool_test;
+mod simulation_test;
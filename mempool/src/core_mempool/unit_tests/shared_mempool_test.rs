@@ -2,18 +2,26 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    broadcast_control::{BroadcastLimits, BroadcastState},
     core_mempool::{unit_tests::common::TestTransaction, CoreMempool, TimelineState},
-    shared_mempool::{start_shared_mempool, SharedMempoolNotification, SyncEvent},
+    shared_mempool::{
+        start_shared_mempool, timer_with_shutdown, SharedMempoolNotification, SyncEvent,
+    },
+    signature_verifier::SignatureVerifier,
+    snapshot::write_snapshot,
+    stateless_validation::StatelessValidator,
+    upstream::{UpstreamHealth, UpstreamPeers},
 };
 use channel;
-use config::config::{NodeConfig, NodeConfigHelpers};
+use config::config::{NodeConfig, NodeConfigHelpers, UpstreamConfig};
 use failure::prelude::*;
 use futures::{
     sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
     Stream,
 };
 use futures_preview::{
-    compat::Stream01CompatExt, executor::block_on, SinkExt, StreamExt, TryStreamExt,
+    channel::oneshot, compat::Stream01CompatExt, executor::block_on, FutureExt, SinkExt, StreamExt,
+    TryFutureExt, TryStreamExt,
 };
 use network::{
     interface::{NetworkNotification, NetworkRequest},
@@ -25,10 +33,15 @@ use std::{
     collections::{HashMap, HashSet},
     convert::TryFrom,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 use storage_service::mocks::mock_storage_client::MockStorageReadClient;
 use tokio::runtime::Runtime;
-use types::{transaction::SignedTransaction, PeerId};
+use tools::tempdir::TempPath;
+use types::{
+    transaction::{SignatureCheckedTransaction, SignedTransaction},
+    PeerId,
+};
 use vm_validator::mocks::mock_vm_validator::MockVMValidator;
 
 #[derive(Default)]
@@ -55,13 +68,14 @@ impl SharedMempoolNetwork {
             let (sender, subscriber) = unbounded();
             let (timer_sender, timer_receiver) = unbounded();
 
-            let runtime = start_shared_mempool(
+            let (runtime, _) = start_shared_mempool(
                 &config,
                 Arc::clone(&mempool),
                 network_sender,
                 network_events,
                 Arc::new(MockStorageReadClient),
                 Arc::new(MockVMValidator),
+                StatelessValidator::from_config(&config.mempool),
                 vec![sender],
                 Some(
                     timer_receiver
@@ -82,14 +96,28 @@ impl SharedMempoolNetwork {
     }
 
     fn bootstrap(peers: Vec<PeerId>) -> Self {
-        Self::bootstrap_with_config(peers, NodeConfigHelpers::get_single_node_test_config(true))
+        let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+        // the filters of the known transactions are only sent along with the broadcasts
+        config
+            .mempool
+            .shared_mempool_known_transactions_interval_ticks = 0;
+        Self::bootstrap_with_config(peers, config)
     }
 
     fn add_txns(&mut self, peer_id: &PeerId, txns: Vec<TestTransaction>) {
+        self.add_txns_with_state(peer_id, txns, TimelineState::NotReady);
+    }
+
+    fn add_txns_with_state(
+        &mut self,
+        peer_id: &PeerId,
+        txns: Vec<TestTransaction>,
+        timeline_state: TimelineState,
+    ) {
         let mut mempool = self.mempools.get(peer_id).unwrap().lock().unwrap();
         for txn in txns {
             let transaction = txn.make_signed_transaction_with_max_gas_amount(5);
-            mempool.add_txn(transaction, 0, 0, 10, TimelineState::NotReady);
+            mempool.add_txn(transaction, 0, 0, 10, timeline_state);
         }
     }
 
@@ -115,12 +143,23 @@ impl SharedMempoolNetwork {
             .unbounded_send(SyncEvent)
             .unwrap();
 
-        // await next message from node
+        // await next broadcast from node, skipping the acknowledgements of the broadcasts of its
+        // peers
         let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
-        let network_req = block_on(network_reqs_rx.next()).unwrap();
+        let network_req = loop {
+            let network_req = block_on(network_reqs_rx.next()).unwrap();
+            match &network_req {
+                NetworkRequest::SendMessage(_, msg, _)
+                    if MempoolSyncMsg::decode(msg.mdata.as_ref())
+                        .unwrap()
+                        .transactions
+                        .is_empty() => {}
+                _ => break network_req,
+            }
+        };
 
         match network_req {
-            NetworkRequest::SendMessage(peer_id, msg) => {
+            NetworkRequest::SendMessage(peer_id, msg, _) => {
                 let mut sync_msg = MempoolSyncMsg::decode(msg.mdata.as_ref()).unwrap();
                 let transaction =
                     SignedTransaction::try_from(sync_msg.transactions.pop().unwrap()).unwrap();
@@ -144,15 +183,74 @@ impl SharedMempoolNetwork {
         }
     }
 
+    /// delivers next acknowledgement from given node to the peer whose broadcast it acknowledges
+    fn deliver_ack(&mut self, peer: &PeerId) {
+        let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
+        match block_on(network_reqs_rx.next()).unwrap() {
+            NetworkRequest::SendMessage(peer_id, msg, _) => {
+                let sync_msg = MempoolSyncMsg::decode(msg.mdata.as_ref()).unwrap();
+                assert!(sync_msg.transactions.is_empty());
+                assert_ne!(sync_msg.ack_broadcast_id, 0);
+                let receiver_network_notif_tx = self.network_notifs_txs.get_mut(&peer_id).unwrap();
+                block_on(
+                    receiver_network_notif_tx.send(NetworkNotification::RecvMessage(*peer, msg)),
+                )
+                .unwrap();
+                self.wait_for_event(&peer_id, SharedMempoolNotification::BroadcastAcked);
+            }
+            _ => panic!("peer {:?} didn't acknowledge broadcast", peer),
+        }
+    }
+
+    /// delivers next message from given node, which only carries the filter of its known
+    /// transactions, to its peer
+    fn deliver_known_transactions(&mut self, peer: &PeerId) {
+        // emulate timer tick
+        self.timers
+            .get(peer)
+            .unwrap()
+            .unbounded_send(SyncEvent)
+            .unwrap();
+
+        let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
+        match block_on(network_reqs_rx.next()).unwrap() {
+            NetworkRequest::SendMessage(peer_id, msg, _) => {
+                let sync_msg = MempoolSyncMsg::decode(msg.mdata.as_ref()).unwrap();
+                assert!(sync_msg.transactions.is_empty());
+                assert!(sync_msg.known_transactions.is_some());
+                let receiver_network_notif_tx = self.network_notifs_txs.get_mut(&peer_id).unwrap();
+                block_on(
+                    receiver_network_notif_tx.send(NetworkNotification::RecvMessage(*peer, msg)),
+                )
+                .unwrap();
+                self.wait_for_event(&peer_id, SharedMempoolNotification::KnownTransactions);
+            }
+            _ => panic!("peer {:?} didn't send its known transactions", peer),
+        }
+    }
+
+    /// whether given node has sent nothing since its last message was read
+    fn is_idle(&mut self, peer: &PeerId) -> bool {
+        let network_reqs_rx = self.network_reqs_rxs.get_mut(peer).unwrap();
+        network_reqs_rx.next().now_or_never().is_none()
+    }
+
+    /// emulates a timer tick of given node and waits for the end of the sync with its peers
+    fn sync(&mut self, peer: &PeerId) {
+        self.timers
+            .get(peer)
+            .unwrap()
+            .unbounded_send(SyncEvent)
+            .unwrap();
+        self.wait_for_event(peer, SharedMempoolNotification::Sync);
+    }
+
     fn exist_in_metrics_cache(&self, peer_id: &PeerId, txn: &TestTransaction) -> bool {
         let mempool = self.mempools.get(peer_id).unwrap().lock().unwrap();
-        mempool
-            .metrics_cache
-            .get(&(
-                TestTransaction::get_address(txn.address),
-                txn.sequence_number,
-            ))
-            .is_some()
+        mempool.latency_tracker.is_tracked(&(
+            TestTransaction::get_address(txn.address),
+            txn.sequence_number,
+        ))
     }
 }
 
@@ -345,3 +443,313 @@ fn test_broadcast_updated_transaction() {
     assert_eq!(txn.sequence_number(), 0);
     assert_eq!(txn.gas_unit_price(), 5);
 }
+
+#[test]
+fn test_skip_known_transactions() {
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut smp = SharedMempoolNetwork::bootstrap(vec![peer_a, peer_b]);
+
+    // both A and B were submitted txn0, only A txn1
+    smp.add_txns(&peer_a, vec![TestTransaction::new(0, 0, 1)]);
+    smp.add_txns(&peer_b, vec![TestTransaction::new(0, 0, 1)]);
+    smp.add_txns(&peer_a, vec![TestTransaction::new(1, 0, 1)]);
+
+    // A and B discover each other
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_b));
+    smp.send_event(&peer_b, NetworkNotification::NewPeer(peer_a));
+
+    // B sends txn0 to A, along with the filter of the transactions it has
+    smp.deliver_message(&peer_b);
+
+    // A skips txn0, which B already has, and moves on to txn1
+    smp.sync(&peer_a);
+    let txn = smp.deliver_message(&peer_a).0;
+    assert_eq!(txn.sender(), TestTransaction::get_address(1));
+}
+
+#[test]
+fn test_known_transactions_sent_on_their_own() {
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config
+        .mempool
+        .shared_mempool_known_transactions_interval_ticks = 1;
+    let mut smp = SharedMempoolNetwork::bootstrap_with_config(vec![peer_a, peer_b], config);
+
+    // A was submitted txn0 and txn1, B got txn0 from another peer, so it has nothing to broadcast
+    smp.add_txns(
+        &peer_a,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(1, 0, 1)],
+    );
+    smp.add_txns_with_state(
+        &peer_b,
+        vec![TestTransaction::new(0, 0, 1)],
+        TimelineState::NonQualified,
+    );
+
+    // A and B discover each other
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_b));
+    smp.send_event(&peer_b, NetworkNotification::NewPeer(peer_a));
+
+    // B sends A the filter of the transactions it has on its own
+    smp.deliver_known_transactions(&peer_b);
+
+    // A skips txn0, which B already has, and moves on to txn1
+    smp.sync(&peer_a);
+    let txn = smp.deliver_message(&peer_a).0;
+    assert_eq!(txn.sender(), TestTransaction::get_address(1));
+
+    // B reconnects, A no longer knows which transactions it has and sends it txn0 after all
+    smp.send_event(&peer_a, NetworkNotification::LostPeer(peer_b));
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_b));
+    let txn = smp.deliver_message(&peer_a).0;
+    assert_eq!(txn.sender(), TestTransaction::get_address(0));
+}
+
+#[test]
+fn test_timer_with_shutdown() {
+    let mut rt = Runtime::new().unwrap();
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+    let mut timer = timer_with_shutdown(3_600_000, shutdown_receiver);
+    let (done_sender, mut done_receiver) = oneshot::channel();
+    shutdown_sender.send(done_sender).unwrap();
+
+    let f = async move {
+        // The shutdown triggers a last sync without waiting for the next tick.
+        assert!(timer.next().await.unwrap().is_ok());
+        assert_eq!(done_receiver.try_recv(), Ok(None));
+        // The requester is notified once the last sync is processed.
+        assert!(timer.next().await.is_none());
+        done_receiver.await.unwrap();
+    };
+    rt.block_on(f.boxed().unit_error().compat()).unwrap();
+}
+
+#[test]
+fn test_snapshot_reload() {
+    let snapshot_dir = TempPath::new();
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.snapshot_file = Some(snapshot_dir.path().join("mempool.snapshot"));
+
+    // a local transaction and a transaction received from a peer are snapshotted
+    let mempool = Mutex::new(CoreMempool::new(&config));
+    {
+        let mut mempool = mempool.lock().unwrap();
+        let local_txn =
+            TestTransaction::new(0, 0, 1).make_signed_transaction_with_max_gas_amount(5);
+        mempool.add_txn(local_txn, 0, 0, 10, TimelineState::NotReady);
+        let peer_txn = TestTransaction::new(1, 0, 2).make_signed_transaction_with_max_gas_amount(5);
+        mempool.add_txn(peer_txn, 0, 0, 10, TimelineState::NonQualified);
+    }
+    let snapshot = mempool.lock().unwrap().snapshot(10);
+    let path = config.get_mempool_snapshot_file().unwrap();
+    assert_eq!(
+        write_snapshot(&mempool, &path, config.mempool.snapshot_max_transactions).unwrap(),
+        2
+    );
+
+    // the restarted node reloads both, with their source
+    let peer = PeerId::random();
+    let mut smp = SharedMempoolNetwork::bootstrap_with_config(vec![peer], config);
+    smp.wait_for_event(&peer, SharedMempoolNotification::NewTransactions);
+    let mut mempool = smp.mempools.get(&peer).unwrap().lock().unwrap();
+    assert_eq!(mempool.snapshot(10), snapshot);
+    // the local transaction is ready to be broadcast again
+    let (timeline, _) = mempool.read_timeline(0, 10);
+    assert_eq!(timeline, vec![snapshot[1].0.clone()]);
+}
+
+#[test]
+fn test_signature_verifier() {
+    let valid: Vec<_> = (0..10)
+        .map(|sequence_number| {
+            TestTransaction::new(0, sequence_number, 1).make_signed_transaction()
+        })
+        .collect();
+    // a transaction carrying the signature of another one
+    let forged = SignedTransaction::new(
+        TestTransaction::new(1, 0, 1)
+            .make_signed_transaction()
+            .into_raw_transaction(),
+        valid[0].public_key(),
+        valid[0].signature(),
+    );
+    let mut batch = valid.clone();
+    batch.insert(5, forged);
+
+    let verifier = SignatureVerifier::new(4);
+    let verified = |batch: Vec<SignatureCheckedTransaction>| -> Vec<SignedTransaction> {
+        batch
+            .into_iter()
+            .map(SignatureCheckedTransaction::into_inner)
+            .collect()
+    };
+    assert_eq!(verified(verifier.verify(batch.clone())), valid);
+    assert_eq!(verified(block_on(verifier.verify_async(batch))), valid);
+}
+
+#[test]
+fn test_broadcast_backpressure() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.shared_mempool_batch_size = 100;
+    config.mempool.shared_mempool_min_batch_size = 10;
+    config.mempool.shared_mempool_slow_send_threshold_ms = 100;
+    config.mempool.shared_mempool_max_skipped_ticks = 4;
+    config.mempool.shared_mempool_max_failure_backoff_ticks = 3;
+    let limits = BroadcastLimits::new(&config.mempool);
+    let (fast, slow) = (Duration::from_millis(1), Duration::from_millis(500));
+
+    let mut state = BroadcastState::new(&limits);
+    assert!(state.tick());
+    assert_eq!(state.batch_size(), 100);
+
+    // slow sends halve the batches, down to the min, and space the broadcasts out
+    state.on_sent(&limits, slow);
+    assert_eq!(state.batch_size(), 50);
+    assert!(!state.tick());
+    assert!(state.tick());
+    for _ in 0..3 {
+        state.on_sent(&limits, slow);
+    }
+    assert_eq!(state.batch_size(), 10);
+    assert_eq!((0..5).filter(|_| state.tick()).count(), 1);
+
+    // fast sends recover
+    for _ in 0..10 {
+        state.on_sent(&limits, fast);
+    }
+    assert_eq!(state.batch_size(), 100);
+    assert!(state.tick());
+
+    // consecutive failures pause the broadcasts for longer and longer
+    state.on_failed(&limits);
+    assert_eq!(state.batch_size(), 10);
+    assert_eq!((0..2).filter(|_| state.tick()).count(), 1);
+    state.on_failed(&limits);
+    assert_eq!((0..3).filter(|_| state.tick()).count(), 1);
+    state.on_failed(&limits);
+    state.on_failed(&limits);
+    assert_eq!((0..4).filter(|_| state.tick()).count(), 1);
+}
+
+#[test]
+fn test_broadcast_acks() {
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    // broadcasts time out as soon as the next tick
+    config.mempool.shared_mempool_ack_timeout_ms = 0;
+    let mut smp = SharedMempoolNetwork::bootstrap_with_config(vec![peer_a, peer_b], config);
+    smp.add_txns(
+        &peer_a,
+        vec![
+            TestTransaction::new(1, 0, 1),
+            TestTransaction::new(1, 1, 1),
+            TestTransaction::new(1, 2, 1),
+        ],
+    );
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_b));
+
+    // B acknowledges the broadcasts of A
+    assert_eq!(smp.deliver_message(&peer_a).0.sequence_number(), 0);
+    smp.deliver_ack(&peer_b);
+    assert_eq!(smp.deliver_message(&peer_a).0.sequence_number(), 1);
+
+    // the next broadcast waits for the acknowledgement of the last one, which times out
+    smp.sync(&peer_a);
+    assert!(smp.is_idle(&peer_a));
+
+    // the transactions of the broadcast which wasn't acknowledged are broadcast again
+    assert_eq!(smp.deliver_message(&peer_a).0.sequence_number(), 1);
+    smp.deliver_ack(&peer_b);
+    smp.deliver_ack(&peer_b);
+    assert_eq!(smp.deliver_message(&peer_a).0.sequence_number(), 2);
+}
+
+#[test]
+fn test_upstream_failover_on_ack_timeout() {
+    let (peer_a, peer_b, peer_c) = (PeerId::random(), PeerId::random(), PeerId::random());
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.shared_mempool_ack_timeout_ms = 0;
+    config.upstream.preferred_peers = vec![format!("{:x}", peer_b)];
+    config.upstream.fallback_peers = vec![format!("{:x}", peer_c)];
+    config.upstream.failover_threshold = 1;
+    config.upstream.failover_retry_interval_ms = 3_600_000;
+    let mut smp = SharedMempoolNetwork::bootstrap_with_config(vec![peer_a, peer_b, peer_c], config);
+    smp.add_txns(
+        &peer_a,
+        vec![TestTransaction::new(1, 0, 1), TestTransaction::new(1, 1, 1)],
+    );
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_b));
+    smp.send_event(&peer_a, NetworkNotification::NewPeer(peer_c));
+
+    // the preferred peer is broadcast to while it acknowledges the broadcasts
+    assert_eq!(smp.deliver_message(&peer_a).1, peer_b);
+    smp.deliver_ack(&peer_b);
+    assert_eq!(smp.deliver_message(&peer_a).1, peer_b);
+
+    // it is failed over once a broadcast isn't acknowledged in time
+    smp.sync(&peer_a);
+    let (txn, peer_id) = smp.deliver_message(&peer_a);
+    assert_eq!((txn.sequence_number(), peer_id), (0, peer_c));
+}
+
+#[test]
+fn test_upstream_failover() {
+    let (preferred, fallback, other) = (PeerId::random(), PeerId::random(), PeerId::random());
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.upstream.preferred_peers = vec![format!("{:x}", preferred)];
+    config.upstream.fallback_peers = vec![format!("{:x}", fallback)];
+    config.upstream.failover_threshold = 2;
+    config.upstream.failover_retry_interval_ms = 1000;
+    let upstream = UpstreamPeers::new(&config.upstream);
+    let now = Instant::now();
+
+    let mut peers: HashMap<PeerId, UpstreamHealth> = [preferred, fallback, other]
+        .iter()
+        .map(|peer_id| (*peer_id, UpstreamHealth::default()))
+        .collect();
+    let selected = |peers: &HashMap<PeerId, UpstreamHealth>, now: Instant| {
+        upstream.select(peers, now).into_iter().collect::<Vec<_>>()
+    };
+
+    // only the preferred peer is broadcast to while it works
+    assert_eq!(selected(&peers, now), vec![preferred]);
+    let health = peers.get_mut(&preferred).unwrap();
+    health.on_failed(&upstream, &preferred, now);
+    health.on_sent();
+    health.on_failed(&upstream, &preferred, now);
+    assert_eq!(selected(&peers, now), vec![preferred]);
+
+    // consecutive failures fail it over to the fallback peer
+    peers
+        .get_mut(&preferred)
+        .unwrap()
+        .on_failed(&upstream, &preferred, now);
+    assert_eq!(selected(&peers, now), vec![fallback]);
+
+    // it is tried again after the retry interval
+    let later = now + Duration::from_millis(1000);
+    assert_eq!(selected(&peers, later), vec![preferred]);
+    let health = peers.get_mut(&preferred).unwrap();
+    health.on_failed(&upstream, &preferred, later);
+    assert_eq!(selected(&peers, later), vec![fallback]);
+    peers.get_mut(&preferred).unwrap().on_sent();
+    assert_eq!(selected(&peers, later), vec![preferred]);
+
+    // a disconnected preferred peer is failed over right away
+    peers.remove(&preferred);
+    assert_eq!(selected(&peers, now), vec![fallback]);
+
+    // all the upstream peers keep being tried once they are all failed over
+    for _ in 0..2 {
+        peers
+            .get_mut(&fallback)
+            .unwrap()
+            .on_failed(&upstream, &fallback, now);
+    }
+    assert_eq!(selected(&peers, now), vec![fallback]);
+
+    // without upstream peers, all peers are broadcast to
+    let upstream = UpstreamPeers::new(&UpstreamConfig::default());
+    assert_eq!(upstream.select(&peers, now).len(), 2);
+}
@@ -0,0 +1,148 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Simulations of Mempool over virtual time: the clock only moves when the test advances it, so
+//! that expiration, GC and the aging of the timeline are exercised deterministically and without
+//! sleeping.
+
+use crate::core_mempool::{
+    clock::{Clock, MockClock},
+    state_snapshot::take_snapshot,
+    unit_tests::common::{add_txn, add_txns_to_mempool, TestTransaction},
+    CoreMempool, TimelineState,
+};
+use config::config::NodeConfigHelpers;
+use mempool_shared_proto::proto::mempool_status::{
+    MempoolAddTransactionStatusCode, MempoolTransactionStatusCode,
+};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+const SYSTEM_TTL: Duration = Duration::from_secs(10);
+
+/// Mempool with a system TTL of `SYSTEM_TTL`, along with the virtual clock it runs on
+fn setup_simulation() -> (CoreMempool, MockClock) {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.system_transaction_timeout_secs = SYSTEM_TTL.as_secs();
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let pool = CoreMempool::with_clock(&config, Arc::new(clock.clone()));
+    (pool, clock)
+}
+
+fn sequence_numbers(pool: &CoreMempool, address: usize) -> Vec<u64> {
+    pool.get_account_transactions(&TestTransaction::get_address(address))
+        .into_iter()
+        .map(|txn| txn.txn.sequence_number())
+        .collect()
+}
+
+#[test]
+fn test_system_ttl_expiry() {
+    let (mut pool, clock) = setup_simulation();
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+    clock.advance(Duration::from_secs(5));
+    add_txn(&mut pool, TestTransaction::new(1, 0, 1)).unwrap();
+    assert_eq!(
+        take_snapshot(|| &pool, 10).oldest_transaction_age,
+        Some(Duration::from_secs(5))
+    );
+
+    // a transaction lives exactly its system TTL
+    clock.advance(Duration::from_secs(5));
+    pool.gc_by_system_ttl();
+    assert_eq!(sequence_numbers(&pool, 0), vec![0]);
+    clock.advance(Duration::from_millis(1));
+    pool.gc_by_system_ttl();
+    assert!(sequence_numbers(&pool, 0).is_empty());
+    assert_eq!(sequence_numbers(&pool, 1), vec![0]);
+    assert_eq!(
+        pool.get_transaction_status(&TestTransaction::get_address(0), 0),
+        MempoolTransactionStatusCode::Expired
+    );
+
+    clock.advance(Duration::from_secs(5));
+    pool.gc_by_system_ttl();
+    assert!(sequence_numbers(&pool, 1).is_empty());
+    assert_eq!(take_snapshot(|| &pool, 10).oldest_transaction_age, None);
+}
+
+#[test]
+fn test_client_expiration() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.min_expiration_window_secs = Some(10);
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let mut pool = CoreMempool::with_clock(&config, Arc::new(clock.clone()));
+    let expiration_time = clock.now() + Duration::from_secs(15);
+    let mut add_txn_of = |address: usize| {
+        let txn = TestTransaction::new(address, 0, 1)
+            .make_signed_transaction_with_expiration_time(expiration_time);
+        pool.add_txn(txn, 0, 0, 1000, TimelineState::NotReady).code
+    };
+
+    // the expiration window is checked against the time of the insertion
+    assert_eq!(add_txn_of(0), MempoolAddTransactionStatusCode::Valid);
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(
+        add_txn_of(1),
+        MempoolAddTransactionStatusCode::ExpirationTooSoon
+    );
+
+    // the client-specified expiration is checked against the time of the committed blocks,
+    // whatever the clock of Mempool tells
+    pool.gc_by_expiration_time(expiration_time - Duration::from_secs(1));
+    assert_eq!(sequence_numbers(&pool, 0), vec![0]);
+    pool.gc_by_expiration_time(expiration_time + Duration::from_secs(1));
+    assert!(sequence_numbers(&pool, 0).is_empty());
+}
+
+#[test]
+fn test_gc_replacement_race() {
+    let (mut pool, clock) = setup_simulation();
+    add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(0, 1, 1)],
+    );
+
+    // replacing a transaction right before its system TTL is over restarts it
+    clock.advance(Duration::from_secs(8));
+    let replacement = TestTransaction::new(0, 0, 100).make_signed_transaction();
+    let status = pool.add_txn(replacement.clone(), 0, 0, 1000, TimelineState::NotReady);
+    assert_eq!(status.code, MempoolAddTransactionStatusCode::Replaced);
+    clock.advance(Duration::from_secs(3));
+    pool.gc_by_system_ttl();
+    assert_eq!(sequence_numbers(&pool, 0), vec![0]);
+    assert_eq!(pool.get_block(10, HashSet::new()), vec![replacement]);
+
+    clock.advance(Duration::from_secs(8));
+    pool.gc_by_system_ttl();
+    assert!(sequence_numbers(&pool, 0).is_empty());
+}
+
+#[test]
+fn test_timeline_aging() {
+    let (mut pool, clock) = setup_simulation();
+    let old_txns = add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(0, 1, 1)],
+    );
+    clock.advance(Duration::from_secs(5));
+    let new_txns = add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(1, 0, 1), TestTransaction::new(1, 1, 1)],
+    );
+    let (txns, old_timeline_id) = pool.read_timeline(0, 2);
+    assert_eq!(txns, old_txns);
+
+    // expired transactions leave the timeline, the others keep their place in it
+    clock.advance(Duration::from_secs(6));
+    pool.gc_by_system_ttl();
+    assert_eq!(pool.read_timeline(0, 10).0, new_txns);
+    let (txns, last_timeline_id) = pool.read_timeline(old_timeline_id, 10);
+    assert_eq!(txns, new_txns);
+
+    // resubmitted transactions join the end of the timeline
+    let resubmitted_txns = add_txns_to_mempool(
+        &mut pool,
+        vec![TestTransaction::new(0, 0, 1), TestTransaction::new(0, 1, 1)],
+    );
+    assert_eq!(pool.read_timeline(last_timeline_id, 10).0, resubmitted_txns);
+}
@@ -0,0 +1,152 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Summaries of the transactions a node already has, which SharedMempool attaches to the sync
+//! messages it sends so that its peers skip those transactions when broadcasting to it, instead
+//! of sending them again.
+//!
+//! A summary is a bloom filter of the hashes of the transactions most recently inserted into
+//! Mempool. It is built with a new random seed every time, so that the false positives of the
+//! successive filters of a node differ. A false positive makes a peer skip a transaction the node
+//! doesn't have yet, which the peer tries again against the next filters of the node, so that the
+//! transaction is only delayed.
+
+use crypto::HashValue;
+use failure::prelude::*;
+use network::proto::TransactionFilter;
+use std::{cmp::max, collections::VecDeque, convert::TryFrom};
+
+// ~1% of false positives
+const BITS_PER_TRANSACTION: usize = 10;
+const NUM_HASHES: u32 = 7;
+// bounds the work done for every lookup in a filter received from a peer
+const MAX_NUM_HASHES: u32 = 16;
+
+/// Bloom filter of transaction hashes
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u8>,
+    num_hashes: u32,
+    seed: u64,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `num_transactions` transactions, whose bit indices depend
+    /// on `seed`
+    pub(crate) fn new(num_transactions: usize, seed: u64) -> Self {
+        let num_bytes = (num_transactions * BITS_PER_TRANSACTION + 7) / 8;
+        Self {
+            bits: vec![0; max(num_bytes, 1)],
+            num_hashes: NUM_HASHES,
+            seed,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, hash: &HashValue) {
+        for index in self.bit_indices(hash) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    pub(crate) fn contains(&self, hash: &HashValue) -> bool {
+        self.bit_indices(hash)
+            .all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Indices of the bits of `hash`, by double hashing. Transaction hashes are uniformly
+    /// distributed already, so that their bytes, mixed with the seed, serve as the two base hashes
+    fn bit_indices(&self, hash: &HashValue) -> impl Iterator<Item = usize> {
+        let bytes = hash.as_ref();
+        let h1 = mix(read_u64(&bytes[..8]) ^ self.seed);
+        let h2 = mix(read_u64(&bytes[8..16]) ^ self.seed) | 1;
+        let num_bits = self.bits.len() as u64 * 8;
+        (0..u64::from(self.num_hashes))
+            .map(move |k| (h1.wrapping_add(k.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// Finalizer of SplitMix64, so that the indices of a hash change entirely with the seed
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl From<BloomFilter> for TransactionFilter {
+    fn from(filter: BloomFilter) -> Self {
+        Self {
+            bits: filter.bits,
+            num_hashes: filter.num_hashes,
+            seed: filter.seed,
+        }
+    }
+}
+
+impl TryFrom<TransactionFilter> for BloomFilter {
+    type Error = Error;
+
+    fn try_from(proto: TransactionFilter) -> Result<Self> {
+        ensure!(!proto.bits.is_empty(), "Transaction filter has no bits");
+        ensure!(
+            proto.num_hashes > 0 && proto.num_hashes <= MAX_NUM_HASHES,
+            "Transaction filter has {} hashes, expected 1 to {}",
+            proto.num_hashes,
+            MAX_NUM_HASHES
+        );
+        Ok(Self {
+            bits: proto.bits,
+            num_hashes: proto.num_hashes,
+            seed: proto.seed,
+        })
+    }
+}
+
+/// Hashes of the last `window` transactions inserted into Mempool
+pub(crate) struct KnownTransactions {
+    hashes: VecDeque<HashValue>,
+    window: usize,
+}
+
+impl KnownTransactions {
+    pub(crate) fn new(window: usize) -> Self {
+        Self {
+            hashes: VecDeque::with_capacity(window),
+            window,
+        }
+    }
+
+    /// Whether transactions are recorded at all
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.window > 0
+    }
+
+    /// Records the hash of a transaction, forgetting the oldest one once the window is full
+    pub(crate) fn record(&mut self, hash: HashValue) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.hashes.len() == self.window {
+            self.hashes.pop_front();
+        }
+        self.hashes.push_back(hash);
+    }
+
+    /// Filter of the transactions of the window, with a new random seed, or `None` if there are
+    /// none
+    pub(crate) fn filter(&self) -> Option<BloomFilter> {
+        if self.hashes.is_empty() {
+            return None;
+        }
+        let mut filter = BloomFilter::new(self.window, rand::random());
+        for hash in &self.hashes {
+            filter.insert(hash);
+        }
+        Some(filter)
+    }
+}
@@ -52,12 +52,33 @@
 //! every Consensus commit request. We use a separate system TTL to ensure that a transaction won't
 //! remain stuck in Mempool forever, even if Consensus doesn't make progress
 pub mod proto;
-pub use runtime::MempoolRuntime;
+pub use core_mempool::{AccountQueue, MempoolStateSnapshot};
+#[cfg(feature = "bench")]
+pub use core_mempool::{CoreMempool, TimelineState};
+pub use core_mempool::{EventFilter, MempoolEvent, PendingTransaction, MAX_SUBSCRIBERS};
+pub use local_mempool::LocalMempool;
+pub use mempool_service::MAX_ADD_TRANSACTIONS_BATCH_SIZE;
+pub use runtime::{MempoolRuntime, MempoolStateReader};
+pub use signature_verifier::SignatureVerifier;
+/// Stateless check of a transaction, under the name deployments implement their policies with.
+/// Unrelated to `vm_validator::vm_validator::TransactionValidation`, the validation by the VM
+pub use stateless_validation::StatelessValidation as TransactionValidation;
+pub use stateless_validation::{
+    BannedSenders, MaxGasUnitPrice, MaxTransactionSize, ScriptAllowList, ScriptDenyList,
+    StatelessValidation, StatelessValidationError, StatelessValidator,
+};
 
+mod broadcast_control;
 mod core_mempool;
+mod known_transactions;
+mod local_mempool;
 mod mempool_service;
 mod runtime;
 mod shared_mempool;
+mod signature_verifier;
+mod snapshot;
+mod stateless_validation;
+mod upstream;
 
 // module op counters
 use lazy_static::lazy_static;
@@ -0,0 +1,242 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-process access to a mempool, for nodes which run all their components in a single process
+//! and do not share their transactions with other nodes.
+
+use crate::{
+    core_mempool::{CoreMempool, EventFilter, MempoolEvent, PendingTransaction, TimelineState},
+    mempool_service::add_transactions,
+    proto::{
+        mempool::{
+            AddTransactionWithValidationRequest, AddTransactionWithValidationResponse,
+            AddTransactionsWithValidationRequest, AddTransactionsWithValidationResponse,
+            GetGasPriceEstimateRequest, GetGasPriceEstimateResponse, GetTransactionStatusRequest,
+            GetTransactionStatusResponse, HealthCheckRequest, HealthCheckResponse,
+            SubscribeEventsRequest,
+        },
+        mempool_client::{MempoolClientTrait, MempoolEventStream},
+    },
+    stateless_validation::StatelessValidator,
+};
+use config::config::NodeConfig;
+use futures_preview::{channel::mpsc, StreamExt, TryStreamExt};
+use grpc_helpers::create_grpc_invalid_arg_status;
+use grpcio::{RpcStatus, RpcStatusCode};
+use mempool_shared_proto::{
+    proto::mempool_status::{MempoolAddTransactionStatusCode, MempoolTransactionStatusCode},
+    GasPriceEstimate, MempoolAddTransactionStatus,
+};
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use types::{account_address::AccountAddress, transaction::SignedTransaction};
+
+/// A mempool served in-process rather than over gRPC. It can be handed to Admission Control as
+/// its mempool client.
+#[derive(Clone)]
+pub struct LocalMempool {
+    core_mempool: Arc<Mutex<CoreMempool>>,
+    stateless_validator: StatelessValidator,
+}
+
+impl LocalMempool {
+    /// Creates an empty mempool, configured by `config`.
+    pub fn new(config: &NodeConfig) -> Self {
+        Self::with_validator(config, StatelessValidator::from_config(&config.mempool))
+    }
+
+    /// Same as [`new`](LocalMempool::new), with the transactions submitted to the mempool checked
+    /// by `stateless_validator` instead of the one built from `config`.
+    pub fn with_validator(config: &NodeConfig, stateless_validator: StatelessValidator) -> Self {
+        Self {
+            core_mempool: Arc::new(Mutex::new(CoreMempool::new(config))),
+            stateless_validator,
+        }
+    }
+
+    /// Returns the next block of at most `max_block_size` transactions, ordered as they can be
+    /// executed.
+    pub fn get_block(&self, max_block_size: u64) -> Vec<SignedTransaction> {
+        self.core_mempool
+            .lock()
+            .expect("[get_block] acquire mempool lock")
+            .get_block(max_block_size.max(1), HashSet::new())
+    }
+
+    /// Returns the transactions of `address` waiting in mempool, by sequence number.
+    pub fn get_account_transactions(&self, address: &AccountAddress) -> Vec<PendingTransaction> {
+        self.core_mempool
+            .lock()
+            .expect("[get_account_transactions] acquire mempool lock")
+            .get_account_transactions(address)
+    }
+
+    /// Returns the percentiles of the gas unit prices of the transactions of mempool and of the
+    /// recently committed ones, along with the gas unit price needed to get into the next block.
+    pub fn get_gas_price_estimate(&self) -> GasPriceEstimate {
+        self.core_mempool
+            .lock()
+            .expect("[get_gas_price_estimate] acquire mempool lock")
+            .get_gas_price_estimate()
+    }
+
+    /// Returns whether the transaction of `sender` with `sequence_number` is waiting in mempool,
+    /// or why it was removed if it was recently.
+    pub fn get_transaction_status(
+        &self,
+        sender: &AccountAddress,
+        sequence_number: u64,
+    ) -> MempoolTransactionStatusCode {
+        self.core_mempool
+            .lock()
+            .expect("[get_transaction_status] acquire mempool lock")
+            .get_transaction_status(sender, sequence_number)
+    }
+
+    /// Returns a stream of the events of the transactions of mempool `filter` selects from now on,
+    /// unless mempool has too many subscribers already. The stream ends if it falls too far
+    /// behind.
+    pub fn subscribe_events(
+        &self,
+        filter: EventFilter,
+    ) -> failure::Result<mpsc::Receiver<MempoolEvent>> {
+        self.core_mempool
+            .lock()
+            .expect("[subscribe_events] acquire mempool lock")
+            .subscribe_events(filter)
+    }
+
+    /// Removes the transactions of a block once it is committed. Each transaction is identified
+    /// by its sender and sequence number, along with whether it was rejected by the VM. The
+    /// transactions of the accounts of `account_sequence_numbers` below their new sequence number
+    /// are removed as well, and expired transactions are removed as of `block_timestamp_usecs`.
+    pub fn commit_transactions(
+        &self,
+        transactions: &[(AccountAddress, u64, bool)],
+        account_sequence_numbers: &[(AccountAddress, u64)],
+        block_timestamp_usecs: u64,
+    ) {
+        self.core_mempool
+            .lock()
+            .expect("[update status] acquire mempool lock")
+            .commit_block(
+                transactions,
+                account_sequence_numbers,
+                if block_timestamp_usecs > 0 {
+                    Some(Duration::from_micros(block_timestamp_usecs))
+                } else {
+                    None
+                },
+            );
+    }
+}
+
+impl MempoolClientTrait for LocalMempool {
+    fn add_transaction_with_validation(
+        &self,
+        req: &AddTransactionWithValidationRequest,
+    ) -> ::grpcio::Result<AddTransactionWithValidationResponse> {
+        let proto_transaction = req.signed_txn.clone().unwrap_or_else(Default::default);
+        let transaction = SignedTransaction::try_from(proto_transaction).map_err(|e| {
+            ::grpcio::Error::RpcFailure(create_grpc_invalid_arg_status(
+                "add_transaction_with_validation",
+                e,
+            ))
+        })?;
+        let insertion_result = match self.stateless_validator.validate(&transaction) {
+            Err(e) => MempoolAddTransactionStatus::new(
+                MempoolAddTransactionStatusCode::FailedStatelessValidation,
+                e.to_string(),
+            ),
+            Ok(()) => self
+                .core_mempool
+                .lock()
+                .expect("[add txn] acquire mempool lock")
+                .add_txn(
+                    transaction,
+                    req.max_gas_cost,
+                    req.latest_sequence_number,
+                    req.account_balance,
+                    TimelineState::NonQualified,
+                ),
+        };
+        let mut response = AddTransactionWithValidationResponse::default();
+        response.status = Some(insertion_result.into());
+        Ok(response)
+    }
+
+    fn add_transactions_with_validation(
+        &self,
+        req: &AddTransactionsWithValidationRequest,
+    ) -> ::grpcio::Result<AddTransactionsWithValidationResponse> {
+        let statuses = add_transactions(
+            &self.core_mempool,
+            &self.stateless_validator,
+            &req.transactions,
+            TimelineState::NonQualified,
+        )
+        .map_err(|e| {
+            ::grpcio::Error::RpcFailure(create_grpc_invalid_arg_status(
+                "add_transactions_with_validation",
+                e,
+            ))
+        })?;
+        let mut response = AddTransactionsWithValidationResponse::default();
+        response.statuses = statuses.into_iter().map(Into::into).collect();
+        Ok(response)
+    }
+
+    fn health_check(&self, _req: &HealthCheckRequest) -> ::grpcio::Result<HealthCheckResponse> {
+        let mut response = HealthCheckResponse::default();
+        response.is_healthy = self
+            .core_mempool
+            .lock()
+            .expect("[health_check] acquire mempool lock")
+            .health_check();
+        Ok(response)
+    }
+
+    fn get_gas_price_estimate(
+        &self,
+        _req: &GetGasPriceEstimateRequest,
+    ) -> ::grpcio::Result<GetGasPriceEstimateResponse> {
+        let mut response = GetGasPriceEstimateResponse::default();
+        response.estimate = Some(self.get_gas_price_estimate().into());
+        Ok(response)
+    }
+
+    fn get_transaction_status(
+        &self,
+        req: &GetTransactionStatusRequest,
+    ) -> ::grpcio::Result<GetTransactionStatusResponse> {
+        let sender = AccountAddress::try_from(&req.sender[..]).map_err(|e| {
+            ::grpcio::Error::RpcFailure(create_grpc_invalid_arg_status("get_transaction_status", e))
+        })?;
+        let mut response = GetTransactionStatusResponse::default();
+        response.set_status(self.get_transaction_status(&sender, req.sequence_number));
+        Ok(response)
+    }
+
+    fn subscribe_events(
+        &self,
+        req: &SubscribeEventsRequest,
+    ) -> ::grpcio::Result<MempoolEventStream> {
+        let filter = EventFilter::try_from(req.clone()).map_err(|e| {
+            ::grpcio::Error::RpcFailure(create_grpc_invalid_arg_status("subscribe_events", e))
+        })?;
+        let events = LocalMempool::subscribe_events(self, filter)
+            .map_err(|e| {
+                ::grpcio::Error::RpcFailure(RpcStatus::new(
+                    RpcStatusCode::RESOURCE_EXHAUSTED,
+                    Some(e.to_string()),
+                ))
+            })?
+            .map(|event| Ok::<_, ::grpcio::Error>(event.into()))
+            .compat();
+        Ok(Box::new(events))
+    }
+}
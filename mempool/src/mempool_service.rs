@@ -2,13 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    core_mempool::{CoreMempool, TimelineState, TxnPointer},
-    proto::mempool::Mempool,
+    core_mempool::{CoreMempool, EventFilter, TimelineState, TxnPointer},
+    proto::mempool::{AddTransactionWithValidationRequest, Mempool},
+    stateless_validation::StatelessValidator,
     OP_COUNTERS,
 };
-use futures::Future;
+use failure::prelude::*;
+use futures::{Future, Sink};
+use futures_preview::{StreamExt, TryStreamExt};
 use grpc_helpers::{create_grpc_invalid_arg_status, default_reply_error_logger};
+use grpcio::{RpcStatus, RpcStatusCode, WriteFlags};
 use logger::prelude::*;
+use mempool_shared_proto::{
+    proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
+};
 use metrics::counters::SVC_COUNTERS;
 use std::{
     cmp,
@@ -22,9 +29,13 @@ use types::{
     transaction::SignedTransaction,
 };
 
+/// Maximum number of transactions of an AddTransactionsWithValidation request
+pub const MAX_ADD_TRANSACTIONS_BATCH_SIZE: usize = 1000;
+
 #[derive(Clone)]
 pub(crate) struct MempoolService {
     pub(crate) core_mempool: Arc<Mutex<CoreMempool>>,
+    pub(crate) stateless_validator: StatelessValidator,
 }
 
 impl Mempool for MempoolService {
@@ -50,17 +61,26 @@ impl Mempool for MempoolService {
                 );
             }
             Ok(transaction) => {
-                let insertion_result = self
-                    .core_mempool
-                    .lock()
-                    .expect("[add txn] acquire mempool lock")
-                    .add_txn(
-                        transaction,
-                        req.max_gas_cost,
-                        req.latest_sequence_number,
-                        req.account_balance,
-                        TimelineState::NotReady,
-                    );
+                let insertion_result = match self.stateless_validator.validate(&transaction) {
+                    Err(e) => {
+                        OP_COUNTERS.inc("stateless_validation_failed");
+                        MempoolAddTransactionStatus::new(
+                            MempoolAddTransactionStatusCode::FailedStatelessValidation,
+                            e.to_string(),
+                        )
+                    }
+                    Ok(()) => self
+                        .core_mempool
+                        .lock()
+                        .expect("[add txn] acquire mempool lock")
+                        .add_txn(
+                            transaction,
+                            req.max_gas_cost,
+                            req.latest_sequence_number,
+                            req.account_balance,
+                            TimelineState::NotReady,
+                        ),
+                };
 
                 let mut response =
                     crate::proto::mempool::AddTransactionWithValidationResponse::default();
@@ -71,6 +91,41 @@ impl Mempool for MempoolService {
         SVC_COUNTERS.resp(&ctx, success);
     }
 
+    fn add_transactions_with_validation(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::AddTransactionsWithValidationRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::AddTransactionsWithValidationResponse>,
+    ) {
+        trace!("[GRPC] Mempool::add_transactions_with_validation");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        OP_COUNTERS.inc_by("add_transactions.requested", req.transactions.len());
+        match add_transactions(
+            &self.core_mempool,
+            &self.stateless_validator,
+            &req.transactions,
+            TimelineState::NotReady,
+        ) {
+            Err(e) => {
+                ctx.spawn(
+                    sink.fail(create_grpc_invalid_arg_status(
+                        "add_transactions_with_validation",
+                        e,
+                    ))
+                    .map_err(default_reply_error_logger),
+                );
+                SVC_COUNTERS.resp(&ctx, false);
+            }
+            Ok(statuses) => {
+                let mut response =
+                    crate::proto::mempool::AddTransactionsWithValidationResponse::default();
+                response.statuses = statuses.into_iter().map(Into::into).collect();
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+                SVC_COUNTERS.resp(&ctx, true);
+            }
+        }
+    }
+
     fn get_block(
         &mut self,
         ctx: ::grpcio::RpcContext<'_>,
@@ -94,7 +149,12 @@ impl Mempool for MempoolService {
             .core_mempool
             .lock()
             .expect("[get_block] acquire mempool lock")
-            .get_block(block_size, exclude_transactions);
+            .get_block_with_budget(
+                block_size,
+                no_limit_if_zero(req.max_block_bytes),
+                no_limit_if_zero(req.max_block_gas),
+                exclude_transactions,
+            );
 
         let transactions = txns.drain(..).map(SignedTransaction::into).collect();
 
@@ -116,20 +176,37 @@ impl Mempool for MempoolService {
         trace!("[GRPC] Mempool::commit_transaction");
         let _timer = SVC_COUNTERS.req(&ctx);
         OP_COUNTERS.inc_by("commit_transactions.requested", req.transactions.len());
-        let mut pool = self
-            .core_mempool
-            .lock()
-            .expect("[update status] acquire mempool lock");
-        for transaction in &req.transactions {
-            if let Ok(address) = AccountAddress::try_from(&transaction.sender[..]) {
-                let sequence_number = transaction.sequence_number;
-                pool.remove_transaction(&address, sequence_number, transaction.is_rejected);
-            }
-        }
+        let transactions: Vec<_> = req
+            .transactions
+            .iter()
+            .filter_map(|t| {
+                AccountAddress::try_from(&t.sender[..])
+                    .ok()
+                    .map(|address| (address, t.sequence_number, t.is_rejected))
+            })
+            .collect();
+        let account_sequence_numbers: Vec<_> = req
+            .account_sequence_numbers
+            .iter()
+            .filter_map(|a| {
+                AccountAddress::try_from(&a.address[..])
+                    .ok()
+                    .map(|address| (address, a.sequence_number))
+            })
+            .collect();
         let block_timestamp_usecs = req.block_timestamp_usecs;
-        if block_timestamp_usecs > 0 {
-            pool.gc_by_expiration_time(Duration::from_micros(block_timestamp_usecs));
-        }
+        self.core_mempool
+            .lock()
+            .expect("[update status] acquire mempool lock")
+            .commit_block(
+                &transactions,
+                &account_sequence_numbers,
+                if block_timestamp_usecs > 0 {
+                    Some(Duration::from_micros(block_timestamp_usecs))
+                } else {
+                    None
+                },
+            );
         let response = crate::proto::mempool::CommitTransactionsResponse::default();
         ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
         SVC_COUNTERS.resp(&ctx, true);
@@ -150,4 +227,197 @@ impl Mempool for MempoolService {
         response.is_healthy = pool.health_check();
         ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
     }
+
+    fn get_gas_price_estimate(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        _req: crate::proto::mempool::GetGasPriceEstimateRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::GetGasPriceEstimateResponse>,
+    ) {
+        trace!("[GRPC] Mempool::get_gas_price_estimate");
+        let estimate = self
+            .core_mempool
+            .lock()
+            .expect("[get_gas_price_estimate] acquire mempool lock")
+            .get_gas_price_estimate();
+        let mut response = crate::proto::mempool::GetGasPriceEstimateResponse::default();
+        response.estimate = Some(estimate.into());
+        ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+    }
+
+    fn get_transaction_status(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::GetTransactionStatusRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::GetTransactionStatusResponse>,
+    ) {
+        trace!("[GRPC] Mempool::get_transaction_status");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        match AccountAddress::try_from(&req.sender[..]) {
+            Err(e) => {
+                ctx.spawn(
+                    sink.fail(create_grpc_invalid_arg_status("get_transaction_status", e))
+                        .map_err(default_reply_error_logger),
+                );
+                SVC_COUNTERS.resp(&ctx, false);
+            }
+            Ok(sender) => {
+                let status = self
+                    .core_mempool
+                    .lock()
+                    .expect("[get_transaction_status] acquire mempool lock")
+                    .get_transaction_status(&sender, req.sequence_number);
+                let mut response = crate::proto::mempool::GetTransactionStatusResponse::default();
+                response.set_status(status);
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+                SVC_COUNTERS.resp(&ctx, true);
+            }
+        }
+    }
+
+    fn subscribe_events(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::SubscribeEventsRequest,
+        sink: ::grpcio::ServerStreamingSink<crate::proto::mempool::MempoolEvent>,
+    ) {
+        trace!("[GRPC] Mempool::subscribe_events");
+        let filter = match EventFilter::try_from(req) {
+            Ok(filter) => filter,
+            Err(e) => {
+                let status = create_grpc_invalid_arg_status("subscribe_events", e);
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger));
+                return;
+            }
+        };
+        let subscription = self
+            .core_mempool
+            .lock()
+            .expect("[subscribe_events] acquire mempool lock")
+            .subscribe_events(filter);
+        let events = match subscription {
+            Ok(events) => events,
+            Err(e) => {
+                let status = RpcStatus::new(RpcStatusCode::RESOURCE_EXHAUSTED, Some(e.to_string()));
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger));
+                return;
+            }
+        };
+        let events = events
+            .map(|event| {
+                Ok::<_, ::grpcio::Error>((
+                    crate::proto::mempool::MempoolEvent::from(event),
+                    WriteFlags::default(),
+                ))
+            })
+            .compat();
+        // the stream ends when the subscriber goes away, as sending it the next event fails, or
+        // when it falls too far behind
+        ctx.spawn(
+            sink.send_all(events)
+                .map(|_| ())
+                .map_err(default_reply_error_logger),
+        );
+    }
+
+    fn get_account_transactions(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::GetAccountTransactionsRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::GetAccountTransactionsResponse>,
+    ) {
+        trace!("[GRPC] Mempool::get_account_transactions");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        match AccountAddress::try_from(&req.address[..]) {
+            Err(e) => {
+                ctx.spawn(
+                    sink.fail(create_grpc_invalid_arg_status(
+                        "get_account_transactions",
+                        e,
+                    ))
+                    .map_err(default_reply_error_logger),
+                );
+                SVC_COUNTERS.resp(&ctx, false);
+            }
+            Ok(address) => {
+                let txns = self
+                    .core_mempool
+                    .lock()
+                    .expect("[get_account_transactions] acquire mempool lock")
+                    .get_account_transactions(&address);
+
+                let mut response = crate::proto::mempool::GetAccountTransactionsResponse::default();
+                response.transactions = txns
+                    .into_iter()
+                    .map(|txn| crate::proto::mempool::PendingTransaction {
+                        signed_txn: Some(txn.txn.into()),
+                        insertion_time_usecs: txn.insertion_time.as_micros() as u64,
+                        is_ready: txn.is_ready,
+                    })
+                    .collect();
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+                SVC_COUNTERS.resp(&ctx, true);
+            }
+        }
+    }
+}
+
+/// Adds each transaction of a batch which passes the stateless validations to `core_mempool` in
+/// `timeline_state`, taking the lock for each of them in turn so that the other users of the
+/// mempool are not held up by the whole batch. Returns the status of each transaction, in the
+/// order of the batch, and fails only if the batch has more than
+/// `MAX_ADD_TRANSACTIONS_BATCH_SIZE` transactions.
+pub(crate) fn add_transactions(
+    core_mempool: &Mutex<CoreMempool>,
+    stateless_validator: &StatelessValidator,
+    requests: &[AddTransactionWithValidationRequest],
+    timeline_state: TimelineState,
+) -> Result<Vec<MempoolAddTransactionStatus>> {
+    ensure!(
+        requests.len() <= MAX_ADD_TRANSACTIONS_BATCH_SIZE,
+        "Batch of {} transactions is larger than the limit of {}",
+        requests.len(),
+        MAX_ADD_TRANSACTIONS_BATCH_SIZE
+    );
+    Ok(requests
+        .iter()
+        .map(|req| {
+            let proto_transaction = req.signed_txn.clone().unwrap_or_else(Default::default);
+            let txn = match SignedTransaction::try_from(proto_transaction) {
+                Ok(txn) => txn,
+                Err(e) => {
+                    return MempoolAddTransactionStatus::new(
+                        MempoolAddTransactionStatusCode::InvalidTransaction,
+                        e.to_string(),
+                    )
+                }
+            };
+            if let Err(e) = stateless_validator.validate(&txn) {
+                OP_COUNTERS.inc("stateless_validation_failed");
+                return MempoolAddTransactionStatus::new(
+                    MempoolAddTransactionStatusCode::FailedStatelessValidation,
+                    e.to_string(),
+                );
+            }
+            core_mempool
+                .lock()
+                .expect("[add txns] acquire mempool lock")
+                .add_txn(
+                    txn,
+                    req.max_gas_cost,
+                    req.latest_sequence_number,
+                    req.account_balance,
+                    timeline_state,
+                )
+        })
+        .collect())
+}
+
+/// Budgets of GetBlockRequest left to 0 don't limit the block
+fn no_limit_if_zero(budget: u64) -> u64 {
+    if budget == 0 {
+        u64::max_value()
+    } else {
+        budget
+    }
 }
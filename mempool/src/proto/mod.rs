@@ -5,13 +5,18 @@
 #![allow(missing_docs)]
 
 use ::types::proto::*;
-use mempool_shared_proto::proto::mempool_status;
+use mempool_shared_proto::proto::{gas_price, mempool_status};
 
 pub mod mempool {
     include!(concat!(env!("OUT_DIR"), "/mempool.rs"));
 }
 
 pub mod mempool_client {
+    /// Stream of the events of the transactions of mempool
+    pub type MempoolEventStream = Box<
+        dyn futures::Stream<Item = super::mempool::MempoolEvent, Error = ::grpcio::Error> + Send,
+    >;
+
     pub trait MempoolClientTrait: Clone + Send + Sync {
         fn add_transaction_with_validation(
             &self,
@@ -20,12 +25,40 @@ pub mod mempool_client {
             unimplemented!();
         }
 
+        fn add_transactions_with_validation(
+            &self,
+            _req: &super::mempool::AddTransactionsWithValidationRequest,
+        ) -> ::grpcio::Result<super::mempool::AddTransactionsWithValidationResponse> {
+            unimplemented!();
+        }
+
         fn health_check(
             &self,
             _req: &super::mempool::HealthCheckRequest,
         ) -> ::grpcio::Result<super::mempool::HealthCheckResponse> {
             unimplemented!();
         }
+
+        fn get_gas_price_estimate(
+            &self,
+            _req: &super::mempool::GetGasPriceEstimateRequest,
+        ) -> ::grpcio::Result<super::mempool::GetGasPriceEstimateResponse> {
+            unimplemented!();
+        }
+
+        fn get_transaction_status(
+            &self,
+            _req: &super::mempool::GetTransactionStatusRequest,
+        ) -> ::grpcio::Result<super::mempool::GetTransactionStatusResponse> {
+            unimplemented!();
+        }
+
+        fn subscribe_events(
+            &self,
+            _req: &super::mempool::SubscribeEventsRequest,
+        ) -> ::grpcio::Result<MempoolEventStream> {
+            unimplemented!();
+        }
     }
 
     impl MempoolClientTrait for super::mempool::MempoolClient {
@@ -36,11 +69,39 @@ pub mod mempool_client {
             self.add_transaction_with_validation(req)
         }
 
+        fn add_transactions_with_validation(
+            &self,
+            req: &super::mempool::AddTransactionsWithValidationRequest,
+        ) -> ::grpcio::Result<super::mempool::AddTransactionsWithValidationResponse> {
+            self.add_transactions_with_validation(req)
+        }
+
         fn health_check(
             &self,
             req: &super::mempool::HealthCheckRequest,
         ) -> ::grpcio::Result<super::mempool::HealthCheckResponse> {
             self.health_check(req)
         }
+
+        fn get_gas_price_estimate(
+            &self,
+            req: &super::mempool::GetGasPriceEstimateRequest,
+        ) -> ::grpcio::Result<super::mempool::GetGasPriceEstimateResponse> {
+            self.get_gas_price_estimate(req)
+        }
+
+        fn get_transaction_status(
+            &self,
+            req: &super::mempool::GetTransactionStatusRequest,
+        ) -> ::grpcio::Result<super::mempool::GetTransactionStatusResponse> {
+            self.get_transaction_status(req)
+        }
+
+        fn subscribe_events(
+            &self,
+            req: &super::mempool::SubscribeEventsRequest,
+        ) -> ::grpcio::Result<MempoolEventStream> {
+            Ok(Box::new(self.subscribe_events(req)?))
+        }
     }
 }
@@ -2,18 +2,33 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    core_mempool::CoreMempool, mempool_service::MempoolService, proto::mempool,
-    shared_mempool::start_shared_mempool,
+    core_mempool::{
+        state_snapshot::take_snapshot, CoreMempool, EventFilter, MempoolEvent, MempoolStateSnapshot,
+    },
+    mempool_service::MempoolService,
+    proto::mempool,
+    shared_mempool::{start_shared_mempool, timer_with_shutdown},
+    snapshot::write_snapshot,
+    stateless_validation::StatelessValidator,
 };
 use config::config::NodeConfig;
-use grpc_helpers::ServerHandle;
+use failure::prelude::*;
+use futures_preview::{
+    channel::{mpsc, oneshot},
+    compat::Future01CompatExt,
+    executor::block_on,
+};
+use grpc_helpers::{internal_server_channel_builder, ServerHandle};
 use grpcio::EnvBuilder;
+use logger::prelude::*;
 use network::validator_network::{MempoolNetworkEvents, MempoolNetworkSender};
 use std::{
     cmp::max,
+    path::PathBuf,
     sync::{Arc, Mutex},
 };
 use storage_client::{StorageRead, StorageReadServiceClient};
+use task_manager::TaskManager;
 use tokio::runtime::Runtime;
 use vm_validator::vm_validator::VMValidator;
 
@@ -23,6 +38,14 @@ pub struct MempoolRuntime {
     pub grpc_server: ServerHandle,
     /// separate shared mempool runtime
     pub shared_mempool: Runtime,
+    /// cancels the tasks of shared mempool on shutdown
+    shared_mempool_tasks: TaskManager,
+    /// requests the last broadcast of shared mempool before shutdown
+    shutdown_sender: oneshot::Sender<oneshot::Sender<()>>,
+    core_mempool: Arc<Mutex<CoreMempool>>,
+    /// file a last snapshot of mempool is written to on shutdown, if any
+    snapshot_file: Option<PathBuf>,
+    snapshot_max_transactions: usize,
 }
 
 impl MempoolRuntime {
@@ -31,6 +54,22 @@ impl MempoolRuntime {
         config: &NodeConfig,
         network_sender: MempoolNetworkSender,
         network_events: MempoolNetworkEvents,
+    ) -> Self {
+        Self::bootstrap_with_validator(
+            config,
+            network_sender,
+            network_events,
+            StatelessValidator::from_config(&config.mempool),
+        )
+    }
+
+    /// setup Mempool runtime, with the transactions entering Mempool checked by
+    /// `stateless_validator` instead of the one built from `config`
+    pub fn bootstrap_with_validator(
+        config: &NodeConfig,
+        network_sender: MempoolNetworkSender,
+        network_events: MempoolNetworkEvents,
+        stateless_validator: StatelessValidator,
     ) -> Self {
         let mempool = Arc::new(Mutex::new(CoreMempool::new(&config)));
 
@@ -43,9 +82,11 @@ impl MempoolRuntime {
         );
         let handle = MempoolService {
             core_mempool: Arc::clone(&mempool),
+            stateless_validator: stateless_validator.clone(),
         };
         let service = mempool::create_mempool(handle);
-        let grpc_server = ::grpcio::ServerBuilder::new(env)
+        let grpc_server = ::grpcio::ServerBuilder::new(Arc::clone(&env))
+            .channel_args(internal_server_channel_builder(env).build_args())
             .register_service(service)
             .bind(
                 config.mempool.address.clone(),
@@ -61,19 +102,84 @@ impl MempoolRuntime {
             config.storage.port,
         ));
         let vm_validator = Arc::new(VMValidator::new(&config, Arc::clone(&storage_client)));
-        let shared_mempool = start_shared_mempool(
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let (shared_mempool, shared_mempool_tasks) = start_shared_mempool(
             config,
-            mempool,
+            Arc::clone(&mempool),
             network_sender,
             network_events,
             storage_client,
             vm_validator,
+            stateless_validator,
             vec![],
-            None,
+            Some(timer_with_shutdown(
+                config.mempool.shared_mempool_tick_interval_ms,
+                shutdown_receiver,
+            )),
         );
         Self {
             grpc_server: ServerHandle::setup(grpc_server),
             shared_mempool,
+            shared_mempool_tasks,
+            shutdown_sender,
+            core_mempool: mempool,
+            snapshot_file: config.get_mempool_snapshot_file(),
+            snapshot_max_transactions: config.mempool.snapshot_max_transactions,
+        }
+    }
+
+    /// Returns a stream of the events of the transactions of mempool `filter` selects from now on,
+    /// so that other components can follow the status of transactions
+    pub fn subscribe_events(&self, filter: EventFilter) -> Result<mpsc::Receiver<MempoolEvent>> {
+        self.core_mempool
+            .lock()
+            .expect("[mempool] failed to acquire mempool lock")
+            .subscribe_events(filter)
+    }
+
+    /// Handle through which other components, e.g. the debug interface of the node, report the
+    /// state of mempool
+    pub fn state_reader(&self) -> MempoolStateReader {
+        MempoolStateReader(Arc::clone(&self.core_mempool))
+    }
+
+    /// Stops serving AC and consensus, broadcasts the ready transactions to peers a last time,
+    /// then shuts shared mempool down and writes a last snapshot of mempool, if enabled.
+    pub fn shutdown(self) {
+        self.grpc_server.shutdown();
+        let (done_sender, done_receiver) = oneshot::channel();
+        if self.shutdown_sender.send(done_sender).is_ok() {
+            let _ = block_on(done_receiver);
         }
+        self.shared_mempool_tasks.shutdown();
+        block_on(self.shared_mempool.shutdown_now().compat())
+            .expect("[mempool] failed to shut down shared mempool runtime");
+        if let Some(path) = self.snapshot_file {
+            if let Err(e) =
+                write_snapshot(&self.core_mempool, &path, self.snapshot_max_transactions)
+            {
+                error!("[mempool] failed to write snapshot {:?}: {:?}", path, e);
+            }
+        }
+    }
+}
+
+/// Reports the state of mempool. All the clones of a reader share the same mempool.
+#[derive(Clone)]
+pub struct MempoolStateReader(Arc<Mutex<CoreMempool>>);
+
+impl MempoolStateReader {
+    /// Summary of the internal state of mempool, with the queues of the `max_accounts` accounts
+    /// with the most transactions. Mempool is locked twice, briefly, so that reading its state
+    /// doesn't hold up the submission of transactions.
+    pub fn state_snapshot(&self, max_accounts: usize) -> MempoolStateSnapshot {
+        take_snapshot(
+            || {
+                self.0
+                    .lock()
+                    .expect("[mempool] failed to acquire mempool lock")
+            },
+            max_accounts,
+        )
     }
 }
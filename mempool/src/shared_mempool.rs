@@ -2,50 +2,90 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    core_mempool::{CoreMempool, TimelineState},
+    broadcast_control::{BroadcastLimits, BroadcastState},
+    core_mempool::{CoreMempool, TimelineState, TxnPointer, TxnSource},
+    known_transactions::BloomFilter,
+    signature_verifier::SignatureVerifier,
+    snapshot::{read_snapshot, write_snapshot},
+    stateless_validation::StatelessValidator,
+    upstream::{UpstreamHealth, UpstreamPeers},
     OP_COUNTERS,
 };
 use bounded_executor::BoundedExecutor;
 use config::config::{MempoolConfig, NodeConfig};
+use crypto::{hash::CryptoHash, HashValue};
 use failure::prelude::*;
 use futures::sync::mpsc::UnboundedSender;
 use futures_preview::{
+    channel::oneshot,
     compat::{Future01CompatExt, Stream01CompatExt},
-    future::join_all,
-    FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
+    future::{self, join_all},
+    stream, Stream, StreamExt, TryStreamExt,
 };
 use logger::prelude::*;
+use mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
 use network::{
-    proto::MempoolSyncMsg,
+    proto::{MempoolSyncMsg, TransactionFilter},
     validator_network::{Event, MempoolNetworkEvents, MempoolNetworkSender},
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
     ops::Deref,
+    path::PathBuf,
     pin::Pin,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use storage_client::StorageRead;
-use tokio::{
-    runtime::{Builder, Runtime, TaskExecutor},
-    timer::Interval,
+use task_manager::{build_runtime, TaskManager};
+use tokio::{runtime::Runtime, timer::Interval};
+use types::{
+    transaction::{SignatureCheckedTransaction, SignedTransaction},
+    PeerId,
 };
-use types::{transaction::SignedTransaction, PeerId};
 use vm_validator::vm_validator::{get_account_state, TransactionValidation};
 
 /// state of last sync with peer
 /// `timeline_id` is position in log of ready transactions
 /// `is_alive` - is connection healthy
+/// `known_transactions` - filter of the transactions the peer last advertised having
+/// `skipped` - transactions skipped as the peer likely has them, along with their hash, tried
+/// again against the next filters of the peer
+/// `broadcast` - flow control of the broadcasts to the peer
+/// `upstream_health` - failover state of the peer, if it is an upstream peer
+/// `pending_ack` - last broadcast to the peer, until it is acknowledged or times out
+/// `last_ack` - id and receipt time of the last acknowledgement received from the peer
+/// `acks_broadcasts` - whether the peer acknowledges broadcasts, which older versions don't
 #[derive(Clone)]
 struct PeerSyncState {
     timeline_id: u64,
     is_alive: bool,
+    known_transactions: Option<Arc<BloomFilter>>,
+    skipped: Vec<(TxnPointer, HashValue)>,
+    broadcast: BroadcastState,
+    upstream_health: UpstreamHealth,
+    pending_ack: Option<PendingAck>,
+    last_ack: Option<(u64, Instant)>,
+    acks_broadcasts: bool,
+}
+
+/// broadcast awaiting its acknowledgement
+/// `timeline_id` - position in log of ready transactions the broadcast started from, to which the
+/// peer is rewound if the broadcast is not acknowledged in time
+#[derive(Clone)]
+struct PendingAck {
+    broadcast_id: u64,
+    sent_at: Instant,
+    timeline_id: u64,
 }
 
 type PeerInfo = HashMap<PeerId, PeerSyncState>;
 
+/// max number of skipped transactions tried again per peer. Beyond it, the transactions skipped
+/// first are given up on, as the peer most likely has them indeed
+const MAX_SKIPPED_TRANSACTIONS: usize = 1024;
+
 /// Outbound peer syncing event emitted by [`IntervalStream`].
 #[derive(Debug)]
 pub(crate) struct SyncEvent;
@@ -57,6 +97,8 @@ pub enum SharedMempoolNotification {
     Sync,
     PeerStateChange,
     NewTransactions,
+    BroadcastAcked,
+    KnownTransactions,
 }
 
 /// Struct that owns all dependencies required by shared mempool routines
@@ -69,6 +111,9 @@ where
     config: MempoolConfig,
     storage_read_client: Arc<dyn StorageRead>,
     validator: Arc<V>,
+    signature_verifier: SignatureVerifier,
+    stateless_validator: StatelessValidator,
+    upstream: UpstreamPeers,
     peer_info: Arc<Mutex<PeerInfo>>,
     subscribers: Vec<UnboundedSender<SharedMempoolNotification>>,
 }
@@ -86,6 +131,9 @@ where
             config: self.config.clone(),
             storage_read_client: Arc::clone(&self.storage_read_client),
             validator: Arc::clone(&self.validator),
+            signature_verifier: self.signature_verifier.clone(),
+            stateless_validator: self.stateless_validator.clone(),
+            upstream: self.upstream.clone(),
             peer_info: self.peer_info.clone(),
             subscribers: self.subscribers.clone(),
         }
@@ -109,18 +157,71 @@ fn default_timer(tick_ms: u64) -> IntervalStream {
         .boxed()
 }
 
+enum TimerEvent {
+    Tick(Result<SyncEvent>),
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// Same as [`default_timer`] until a shutdown is requested through `shutdown_receiver`. The stream
+/// then emits a last [`SyncEvent`] and ends, notifying the requester once that last broadcast is
+/// done.
+pub(crate) fn timer_with_shutdown(
+    tick_ms: u64,
+    shutdown_receiver: oneshot::Receiver<oneshot::Sender<()>>,
+) -> IntervalStream {
+    let events = stream::select(
+        default_timer(tick_ms).map(TimerEvent::Tick),
+        stream::once(shutdown_receiver)
+            .filter_map(|done_sender| future::ready(done_sender.ok()))
+            .map(TimerEvent::Shutdown),
+    );
+    stream::unfold(
+        (events, None),
+        |(mut events, done_sender): (_, Option<oneshot::Sender<()>>)| {
+            async move {
+                // `outbound_sync_task` only polls for the next event once it is done with the
+                // previous one, so the last broadcast is over by now.
+                if let Some(done_sender) = done_sender {
+                    let _ = done_sender.send(());
+                    return None;
+                }
+                match events.next().await? {
+                    TimerEvent::Tick(tick) => Some((tick, (events, None))),
+                    TimerEvent::Shutdown(done_sender) => {
+                        Some((Ok(SyncEvent), (events, Some(done_sender))))
+                    }
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
 /// new peer discovery handler
 /// adds new entry to `peer_info`
-fn new_peer(peer_info: &Mutex<PeerInfo>, peer_id: PeerId) {
-    peer_info
+/// a reconnected peer starts over with a fresh flow control state
+fn new_peer(peer_info: &Mutex<PeerInfo>, peer_id: PeerId, limits: &BroadcastLimits) {
+    let mut peer_info = peer_info
         .lock()
-        .expect("[shared mempool] failed to acquire peer_info lock")
-        .entry(peer_id)
-        .or_insert(PeerSyncState {
-            timeline_id: 0,
-            is_alive: true,
-        })
-        .is_alive = true;
+        .expect("[shared mempool] failed to acquire peer_info lock");
+    let state = peer_info.entry(peer_id).or_insert_with(|| PeerSyncState {
+        timeline_id: 0,
+        is_alive: true,
+        known_transactions: None,
+        skipped: vec![],
+        broadcast: BroadcastState::new(limits),
+        upstream_health: UpstreamHealth::default(),
+        pending_ack: None,
+        last_ack: None,
+        acks_broadcasts: false,
+    });
+    state.is_alive = true;
+    state.broadcast = BroadcastState::new(limits);
+    state.upstream_health = UpstreamHealth::default();
+    // the peer may come back running another version
+    state.pending_ack = None;
+    state.last_ack = None;
+    state.acks_broadcasts = false;
 }
 
 /// lost peer handler. Marks connection as dead
@@ -131,16 +232,104 @@ fn lost_peer(peer_info: &Mutex<PeerInfo>, peer_id: PeerId) {
         .get_mut(&peer_id)
     {
         state.is_alive = false;
+        // the peer may come back with an empty Mempool
+        state.known_transactions = None;
+    }
+}
+
+/// Replaces the filter of the transactions `peer_id` has with the one it attached to its last
+/// sync message
+fn update_known_transactions(
+    peer_info: &Mutex<PeerInfo>,
+    peer_id: PeerId,
+    filter: Option<TransactionFilter>,
+) {
+    let filter = match filter.map(BloomFilter::try_from) {
+        Some(Ok(filter)) => Some(Arc::new(filter)),
+        Some(Err(e)) => {
+            security_log(SecurityEvent::InvalidTransactionMP)
+                .error(&e)
+                .data(&peer_id)
+                .log();
+            None
+        }
+        None => None,
+    };
+    if let Some(state) = peer_info
+        .lock()
+        .expect("[shared mempool] failed to acquire peer_info lock")
+        .get_mut(&peer_id)
+    {
+        state.known_transactions = filter;
+    }
+}
+
+/// Drops the transactions `filter`, the last filter of a peer, contains from `transactions`, and
+/// records them in `skipped`. The transactions skipped before which the filter doesn't contain,
+/// e.g. as it is built with another seed than the one they were skipped by, are fetched again and
+/// returned first, so that a false positive of a filter doesn't skip a transaction for good
+fn filter_known_transactions(
+    transactions: Vec<SignedTransaction>,
+    skipped: &mut Vec<(TxnPointer, HashValue)>,
+    filter: Option<&BloomFilter>,
+    mempool: &Mutex<CoreMempool>,
+) -> Vec<SignedTransaction> {
+    let is_known = |hash: &HashValue| filter.map_or(false, |filter| filter.contains(hash));
+    let (mut still_skipped, retried): (Vec<_>, Vec<_>) =
+        skipped.drain(..).partition(|(_, hash)| is_known(hash));
+    let mut result: Vec<_> = {
+        let mempool = mempool
+            .lock()
+            .expect("[shared mempool] failed to acquire mempool lock");
+        // the transactions committed or evicted in the meantime are gone
+        retried
+            .into_iter()
+            .filter_map(|((address, sequence_number), _)| {
+                mempool.get_transaction(&address, sequence_number)
+            })
+            .collect()
+    };
+    OP_COUNTERS.inc_by("smp.sync_with_peers.retried_skipped", result.len());
+    for txn in transactions {
+        let hash = txn.hash();
+        if is_known(&hash) {
+            OP_COUNTERS.inc("smp.sync_with_peers.skipped_known");
+            still_skipped.push(((txn.sender(), txn.sequence_number()), hash));
+        } else {
+            result.push(txn);
+        }
+    }
+    if still_skipped.len() > MAX_SKIPPED_TRANSACTIONS {
+        still_skipped.drain(..still_skipped.len() - MAX_SKIPPED_TRANSACTIONS);
+    }
+    *skipped = still_skipped;
+    result
+}
+
+/// Records the acknowledgement by `peer_id` of broadcast `broadcast_id`, which the next sync with
+/// the peer accounts for
+fn record_ack(peer_info: &Mutex<PeerInfo>, peer_id: PeerId, broadcast_id: u64) {
+    if let Some(state) = peer_info
+        .lock()
+        .expect("[shared mempool] failed to acquire peer_info lock")
+        .get_mut(&peer_id)
+    {
+        state.last_ack = Some((broadcast_id, Instant::now()));
     }
 }
 
 /// sync routine
-/// used to periodically broadcast ready to go transactions to peers
+/// used to periodically broadcast ready to go transactions to peers, or only to the selected
+/// upstream peers on a full node with upstream peers. If `send_filter`, the filter of the
+/// transactions of this node is also sent on its own to the peers it broadcast nothing to
 async fn sync_with_peers<'a>(
     peer_info: &'a Mutex<PeerInfo>,
     mempool: &'a Mutex<CoreMempool>,
     network_sender: &'a mut MempoolNetworkSender,
-    batch_size: usize,
+    limits: &'a BroadcastLimits,
+    upstream: &'a UpstreamPeers,
+    last_broadcast_id: &'a mut u64,
+    send_filter: bool,
 ) {
     // Clone the underlying peer_info map and use this to sync and collect
     // state updates. We do this instead of holding the lock for the whole
@@ -152,38 +341,157 @@ async fn sync_with_peers<'a>(
         .clone();
 
     let mut state_updates = vec![];
+    let known_transactions = mempool
+        .lock()
+        .expect("[shared mempool] failed to acquire mempool lock")
+        .known_transactions_filter()
+        .map(TransactionFilter::from);
+
+    let alive_peers: HashMap<PeerId, UpstreamHealth> = peer_info_copy
+        .iter()
+        .filter(|(_, peer_state)| peer_state.is_alive)
+        .map(|(peer_id, peer_state)| (*peer_id, peer_state.upstream_health.clone()))
+        .collect();
+    let targets = upstream.select(&alive_peers, Instant::now());
+    let mut broadcast_peers = HashSet::new();
+
+    for (peer_id, mut peer_state) in peer_info_copy.into_iter() {
+        if targets.contains(&peer_id) {
+            if let Some(pending) = peer_state.pending_ack.clone() {
+                match peer_state.last_ack {
+                    Some((broadcast_id, received_at)) if broadcast_id == pending.broadcast_id => {
+                        peer_state
+                            .broadcast
+                            .on_sent(limits, received_at.duration_since(pending.sent_at));
+                        peer_state.upstream_health.on_sent();
+                        peer_state.pending_ack = None;
+                    }
+                    _ if peer_state.acks_broadcasts
+                        && pending.sent_at.elapsed() >= limits.ack_timeout() =>
+                    {
+                        OP_COUNTERS.inc("smp.sync_with_peers.ack_timeout");
+                        peer_state.broadcast.on_failed(limits);
+                        peer_state
+                            .upstream_health
+                            .on_failed(upstream, &peer_id, Instant::now());
+                        // the same transactions are broadcast again once the peer is resumed
+                        peer_state.timeline_id = pending.timeline_id;
+                        peer_state.pending_ack = None;
+                    }
+                    _ => {}
+                }
+            }
+            if peer_state.last_ack.is_some() {
+                peer_state.acks_broadcasts = true;
+            }
 
-    for (peer_id, peer_state) in peer_info_copy.into_iter() {
-        if peer_state.is_alive {
             let timeline_id = peer_state.timeline_id;
+            let mut new_timeline_id = timeline_id;
 
-            let (transactions, new_timeline_id) = mempool
-                .lock()
-                .expect("[shared mempool] failed to acquire mempool lock")
-                .read_timeline(timeline_id, batch_size);
+            // a peer acknowledging broadcasts is sent the next one once it acknowledged the last
+            if peer_state.acks_broadcasts && peer_state.pending_ack.is_some() {
+                OP_COUNTERS.inc("smp.sync_with_peers.awaiting_ack");
+            } else if peer_state.broadcast.tick() {
+                let (transactions, last_timeline_id) = mempool
+                    .lock()
+                    .expect("[shared mempool] failed to acquire mempool lock")
+                    .read_timeline(timeline_id, peer_state.broadcast.batch_size());
+                new_timeline_id = last_timeline_id;
+
+                // skip the transactions the peer already has
+                let transactions = filter_known_transactions(
+                    transactions,
+                    &mut peer_state.skipped,
+                    peer_state.known_transactions.as_ref().map(Arc::as_ref),
+                    mempool,
+                );
 
-            if !transactions.is_empty() {
-                OP_COUNTERS.inc_by("smp.sync_with_peers", transactions.len());
-                let mut msg = MempoolSyncMsg::default();
-                msg.peer_id = peer_id.into();
-                msg.transactions = transactions
-                    .into_iter()
-                    .map(|txn| txn.try_into().unwrap())
-                    .collect();
+                if !transactions.is_empty() {
+                    OP_COUNTERS.inc_by("smp.sync_with_peers", transactions.len());
+                    let broadcast: Vec<TxnPointer> = transactions
+                        .iter()
+                        .map(|txn| (txn.sender(), txn.sequence_number()))
+                        .collect();
+                    let mut msg = MempoolSyncMsg::default();
+                    msg.peer_id = peer_id.into();
+                    msg.transactions = transactions
+                        .into_iter()
+                        .map(|txn| txn.try_into().unwrap())
+                        .collect();
+                    msg.known_transactions = known_transactions.clone();
+                    *last_broadcast_id += 1;
+                    msg.broadcast_id = *last_broadcast_id;
 
-                debug!(
-                    "MempoolNetworkSender.send_to peer {} msg {:?}",
-                    peer_id, msg
-                );
-                // Since this is a direct-send, this will only error if the network
-                // module has unexpectedly crashed or shutdown.
-                network_sender
-                    .send_to(peer_id, msg)
-                    .await
-                    .expect("[shared mempool] failed to direct-send mempool sync message");
+                    debug!(
+                        "MempoolNetworkSender.send_to peer {} msg {:?}",
+                        peer_id, msg
+                    );
+                    // Since this is a direct-send, this only waits for room in the channel to
+                    // the network, and will only error if the network module has unexpectedly
+                    // crashed or shutdown.
+                    let send_time = Instant::now();
+                    match network_sender.send_to(peer_id, msg).await {
+                        Ok(()) => {
+                            broadcast_peers.insert(peer_id);
+                            peer_state.pending_ack = Some(PendingAck {
+                                broadcast_id: *last_broadcast_id,
+                                sent_at: send_time,
+                                timeline_id,
+                            });
+                            // older versions never acknowledge broadcasts, how long the handoff
+                            // to the network took is all there is to adapt to for those
+                            if !peer_state.acks_broadcasts {
+                                peer_state.broadcast.on_sent(limits, send_time.elapsed());
+                                peer_state.upstream_health.on_sent();
+                            }
+                            mempool
+                                .lock()
+                                .expect("[shared mempool] failed to acquire mempool lock")
+                                .record_broadcast(&broadcast);
+                        }
+                        Err(e) => {
+                            OP_COUNTERS.inc("smp.sync_with_peers.failed");
+                            error!(
+                                "[shared mempool] failed to direct-send mempool sync message to {}: {:?}",
+                                peer_id, e
+                            );
+                            peer_state.broadcast.on_failed(limits);
+                            peer_state.upstream_health.on_failed(
+                                upstream,
+                                &peer_id,
+                                Instant::now(),
+                            );
+                            // the same transactions are broadcast again once the peer is resumed
+                            new_timeline_id = timeline_id;
+                        }
+                    }
+                }
+            } else {
+                OP_COUNTERS.inc("smp.sync_with_peers.skipped_tick");
             }
 
-            state_updates.push((peer_id, new_timeline_id));
+            peer_state.timeline_id = new_timeline_id;
+            state_updates.push((peer_id, peer_state));
+        }
+    }
+
+    // the peers this node broadcast nothing to, e.g. the downstream peers of a full node, get the
+    // filter on its own, so that the one they hold doesn't go stale
+    if send_filter && known_transactions.is_some() {
+        for peer_id in alive_peers.keys() {
+            if broadcast_peers.contains(peer_id) {
+                continue;
+            }
+            let mut msg = MempoolSyncMsg::default();
+            msg.peer_id = (*peer_id).into();
+            msg.known_transactions = known_transactions.clone();
+            if let Err(e) = network_sender.send_to(*peer_id, msg).await {
+                OP_COUNTERS.inc("smp.known_transactions.failed");
+                error!(
+                    "[shared mempool] failed to send known transactions to {}: {:?}",
+                    peer_id, e
+                );
+            }
         }
     }
 
@@ -191,21 +499,48 @@ async fn sync_with_peers<'a>(
     let mut peer_info = peer_info
         .lock()
         .expect("[shared mempool] failed to acquire peer_info lock");
-    for (peer_id, new_timeline_id) in state_updates {
-        peer_info
-            .entry(peer_id)
-            .and_modify(|t| t.timeline_id = new_timeline_id);
+    // `last_ack` is left as is, an acknowledgement may have been received in the meantime
+    for (peer_id, peer_state) in state_updates {
+        peer_info.entry(peer_id).and_modify(|t| {
+            t.timeline_id = peer_state.timeline_id;
+            t.skipped = peer_state.skipped;
+            t.broadcast = peer_state.broadcast;
+            t.upstream_health = peer_state.upstream_health;
+            t.pending_ack = peer_state.pending_ack;
+            t.acks_broadcasts = peer_state.acks_broadcasts;
+        });
     }
 }
 
-/// used to validate incoming transactions and add them to local Mempool
-async fn process_incoming_transactions<V>(
-    smp: SharedMempool<V>,
-    peer_id: PeerId,
-    transactions: Vec<SignedTransaction>,
-) where
+/// Validates `transactions`, whose signature was already verified, against the latest state and
+/// adds the valid ones to local Mempool in `timeline_state`. Returns the insertion status code of each transaction which passed the
+/// stateless validations and was not committed yet, or `None` if it failed validation by the VM
+async fn validate_and_add_transactions<V>(
+    smp: &SharedMempool<V>,
+    transactions: Vec<SignatureCheckedTransaction>,
+    timeline_state: TimelineState,
+) -> Vec<Option<MempoolAddTransactionStatusCode>>
+where
     V: TransactionValidation,
 {
+    // stateless validations first, they are much cheaper than the ones of the VM
+    let transactions: Vec<_> = transactions
+        .into_iter()
+        .filter(|t| match smp.stateless_validator.validate(t) {
+            Ok(()) => true,
+            Err(e) => {
+                OP_COUNTERS.inc("smp.transactions.stateless_validation_failed");
+                debug!(
+                    "[shared mempool] transaction {}:{} failed stateless validation: {}",
+                    t.sender(),
+                    t.sequence_number(),
+                    e
+                );
+                false
+            }
+        })
+        .collect();
+
     let account_states = join_all(
         transactions
             .iter()
@@ -227,39 +562,81 @@ async fn process_incoming_transactions<V>(
         })
         .collect();
 
-    let validations = join_all(
-        transactions
-            .iter()
-            .map(|t| smp.validator.validate_transaction(t.0.clone()).compat()),
-    )
+    let validations = join_all(transactions.iter().map(|t| {
+        smp.validator
+            .validate_signature_checked_transaction(t.0.clone())
+            .compat()
+    }))
     .await;
 
-    {
-        let mut mempool = smp
-            .mempool
-            .lock()
-            .expect("[shared mempool] failed to acquire mempool lock");
+    let mut mempool = smp
+        .mempool
+        .lock()
+        .expect("[shared mempool] failed to acquire mempool lock");
 
-        for (idx, (transaction, sequence_number, balance)) in transactions.into_iter().enumerate() {
+    transactions
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (transaction, sequence_number, balance))| {
             if let Ok(None) = validations[idx] {
                 let gas_cost = transaction.max_gas_amount();
                 let insertion_result = mempool.add_txn(
-                    transaction,
+                    transaction.into_inner(),
                     gas_cost,
                     sequence_number,
                     balance,
-                    TimelineState::NonQualified,
+                    timeline_state,
                 );
-                OP_COUNTERS.inc(&format!(
-                    "smp.transactions.status.{:?}.{:?}",
-                    insertion_result.code, peer_id
-                ));
+                Some(insertion_result.code)
             } else {
-                OP_COUNTERS.inc(&format!(
-                    "smp.transactions.status.validation_failed.{:?}",
-                    peer_id
-                ));
+                None
             }
+        })
+        .collect()
+}
+
+/// used to validate incoming transactions and add them to local Mempool, then to acknowledge
+/// broadcast `broadcast_id` of the peer if it expects it
+async fn process_incoming_transactions<V>(
+    smp: SharedMempool<V>,
+    peer_id: PeerId,
+    transactions: Vec<SignedTransaction>,
+    broadcast_id: u64,
+) where
+    V: TransactionValidation,
+{
+    // transactions with an invalid signature are dropped before reaching the VM, whose validation
+    // is much more expensive and doesn't verify their signature again
+    let transactions = smp.signature_verifier.verify_async(transactions).await;
+    let statuses =
+        validate_and_add_transactions(&smp, transactions, TimelineState::NonQualified).await;
+    for status in statuses {
+        match status {
+            Some(code) => {
+                OP_COUNTERS.inc(&format!("smp.transactions.status.{:?}.{:?}", code, peer_id))
+            }
+            None => OP_COUNTERS.inc(&format!(
+                "smp.transactions.status.validation_failed.{:?}",
+                peer_id
+            )),
+        }
+    }
+    if broadcast_id != 0 {
+        let mut msg = MempoolSyncMsg::default();
+        msg.peer_id = peer_id.into();
+        msg.ack_broadcast_id = broadcast_id;
+        // an acknowledgement received after the timeout of the broadcast is of no use to the peer
+        let ttl = Duration::from_millis(smp.config.shared_mempool_ack_timeout_ms);
+        let mut network_sender = smp.network_sender.clone();
+        if let Err(e) = network_sender
+            .send_to_with_ttl(peer_id, msg, Some(ttl))
+            .await
+        {
+            OP_COUNTERS.inc("smp.ack.failed");
+            error!(
+                "[shared mempool] failed to acknowledge broadcast {} of {}: {:?}",
+                broadcast_id, peer_id, e
+            );
         }
     }
     notify_subscribers(SharedMempoolNotification::NewTransactions, &smp.subscribers);
@@ -274,14 +651,28 @@ where
     let peer_info = smp.peer_info;
     let mempool = smp.mempool;
     let mut network_sender = smp.network_sender;
-    let batch_size = smp.config.shared_mempool_batch_size;
+    let limits = BroadcastLimits::new(&smp.config);
+    let upstream = smp.upstream;
     let subscribers = smp.subscribers;
+    let filter_interval_ticks = smp.config.shared_mempool_known_transactions_interval_ticks;
+    let mut last_broadcast_id = 0;
+    let mut num_ticks: u64 = 0;
 
     while let Some(sync_event) = interval.next().await {
         trace!("SyncEvent: {:?}", sync_event);
         match sync_event {
             Ok(_) => {
-                sync_with_peers(&peer_info, &mempool, &mut network_sender, batch_size).await;
+                num_ticks += 1;
+                sync_with_peers(
+                    &peer_info,
+                    &mempool,
+                    &mut network_sender,
+                    &limits,
+                    &upstream,
+                    &mut last_broadcast_id,
+                    filter_interval_ticks > 0 && num_ticks % filter_interval_ticks == 0,
+                )
+                .await;
                 notify_subscribers(SharedMempoolNotification::Sync, &subscribers);
             }
             Err(e) => {
@@ -291,24 +682,29 @@ where
         }
     }
 
-    crit!("SharedMempool outbound_sync_task terminated");
+    info!("SharedMempool outbound_sync_task terminated");
 }
 
 /// This task handles inbound network events.
 async fn inbound_network_task<V>(
     smp: SharedMempool<V>,
-    executor: TaskExecutor,
+    task_manager: TaskManager,
     mut network_events: MempoolNetworkEvents,
 ) where
     V: TransactionValidation,
 {
     let peer_info = smp.peer_info.clone();
     let subscribers = smp.subscribers.clone();
+    let limits = BroadcastLimits::new(&smp.config);
 
     // Use a BoundedExecutor to restrict only `workers_available` concurrent
     // worker tasks that can process incoming transactions.
     let workers_available = smp.config.shared_mempool_max_concurrent_inbound_syncs;
-    let bounded_executor = BoundedExecutor::new(workers_available, executor);
+    let bounded_executor = BoundedExecutor::with_task_manager(
+        workers_available,
+        task_manager,
+        "process_incoming_transactions",
+    );
 
     while let Some(event) = network_events.next().await {
         trace!("SharedMempoolEvent::NetworkEvent::{:?}", event);
@@ -316,7 +712,7 @@ async fn inbound_network_task<V>(
             Ok(network_event) => match network_event {
                 Event::NewPeer(peer_id) => {
                     OP_COUNTERS.inc("smp.event.new_peer");
-                    new_peer(&peer_info, peer_id);
+                    new_peer(&peer_info, peer_id, &limits);
                     notify_subscribers(SharedMempoolNotification::PeerStateChange, &subscribers);
                 }
                 Event::LostPeer(peer_id) => {
@@ -326,6 +722,24 @@ async fn inbound_network_task<V>(
                 }
                 Event::Message((peer_id, msg)) => {
                     OP_COUNTERS.inc("smp.event.message");
+                    if msg.ack_broadcast_id != 0 {
+                        OP_COUNTERS.inc("smp.event.ack");
+                        record_ack(&peer_info, peer_id, msg.ack_broadcast_id);
+                        notify_subscribers(SharedMempoolNotification::BroadcastAcked, &subscribers);
+                        // an acknowledgement carries no filter of the transactions of the peer
+                        if msg.transactions.is_empty() {
+                            continue;
+                        }
+                    }
+                    update_known_transactions(&peer_info, peer_id, msg.known_transactions.clone());
+                    // a message carrying only the filter of the peer
+                    if msg.transactions.is_empty() && msg.broadcast_id == 0 {
+                        notify_subscribers(
+                            SharedMempoolNotification::KnownTransactions,
+                            &subscribers,
+                        );
+                        continue;
+                    }
                     let transactions: Vec<_> = msg
                         .transactions
                         .clone()
@@ -350,6 +764,7 @@ async fn inbound_network_task<V>(
                             smp.clone(),
                             peer_id,
                             transactions,
+                            msg.broadcast_id,
                         ))
                         .await;
                 }
@@ -362,9 +777,14 @@ async fn inbound_network_task<V>(
                 }
             },
             Err(e) => {
-                security_log(SecurityEvent::InvalidNetworkEventMP)
-                    .error(&e)
-                    .log();
+                if e.is_peer_fault() {
+                    security_log(SecurityEvent::InvalidNetworkEventMP)
+                        .error(&e)
+                        .data(e.peer_id())
+                        .log();
+                } else {
+                    error!("[shared mempool] network error: {:?}", e);
+                }
             }
         }
     }
@@ -392,11 +812,77 @@ async fn gc_task(mempool: Arc<Mutex<CoreMempool>>, gc_interval_ms: u64) {
     crit!("SharedMempool gc_task terminated");
 }
 
+/// Reloads the transactions of the last snapshot of Mempool, re-validated against the latest
+/// state, then periodically replaces the snapshot with the current transactions of Mempool
+async fn snapshot_task<V>(smp: SharedMempool<V>, path: PathBuf)
+where
+    V: TransactionValidation,
+{
+    match read_snapshot(&path) {
+        Ok(transactions) => {
+            let count = transactions.len();
+            let (local, peer): (Vec<_>, Vec<_>) = transactions
+                .into_iter()
+                .partition(|(_, source)| *source == TxnSource::Local);
+            // local transactions are broadcast again once ready, as they were before the restart
+            let local = smp
+                .signature_verifier
+                .verify_async(local.into_iter().map(|(txn, _)| txn).collect())
+                .await;
+            let peer = smp
+                .signature_verifier
+                .verify_async(peer.into_iter().map(|(txn, _)| txn).collect())
+                .await;
+            let mut statuses =
+                validate_and_add_transactions(&smp, local, TimelineState::NotReady).await;
+            statuses.extend(
+                validate_and_add_transactions(&smp, peer, TimelineState::NonQualified).await,
+            );
+            let restored = statuses
+                .into_iter()
+                .filter(|status| {
+                    *status == Some(MempoolAddTransactionStatusCode::Valid)
+                        || *status == Some(MempoolAddTransactionStatusCode::Replaced)
+                })
+                .count();
+            OP_COUNTERS.inc_by("smp.snapshot.restored", restored);
+            info!(
+                "Reloaded {} of the {} transactions of the Mempool snapshot {:?}",
+                restored, count, path
+            );
+            notify_subscribers(SharedMempoolNotification::NewTransactions, &smp.subscribers);
+        }
+        Err(e) => error!("Failed to read the Mempool snapshot {:?}: {:?}", path, e),
+    }
+
+    let mut interval =
+        Interval::new_interval(Duration::from_millis(smp.config.snapshot_interval_ms)).compat();
+    while let Some(res) = interval.next().await {
+        match res {
+            Ok(_) => {
+                match write_snapshot(&smp.mempool, &path, smp.config.snapshot_max_transactions) {
+                    Ok(count) => OP_COUNTERS.set("smp.snapshot.transactions", count),
+                    Err(e) => error!("Failed to write the Mempool snapshot {:?}: {:?}", path, e),
+                }
+            }
+            Err(e) => {
+                error!("Error in snapshot_task timer interval: {:?}", e);
+                break;
+            }
+        }
+    }
+
+    crit!("SharedMempool snapshot_task terminated");
+}
+
 /// bootstrap of SharedMempool
 /// creates separate Tokio Runtime that runs following routines:
 ///   - outbound_sync_task (task that periodically broadcasts transactions to peers)
 ///   - inbound_network_task (task that handles inbound mempool messages and network events)
 ///   - gc_task (task that performs GC of all expired transactions by SystemTTL)
+///   - snapshot_task (task that reloads the last snapshot of Mempool, then periodically writes new
+///     ones), if a snapshot file is configured
+/// Returns the runtime along with the task manager which cancels these routines on shutdown.
 pub(crate) fn start_shared_mempool<V>(
     config: &NodeConfig,
     mempool: Arc<Mutex<CoreMempool>>,
@@ -404,17 +890,15 @@ pub(crate) fn start_shared_mempool<V>(
     network_events: MempoolNetworkEvents,
     storage_read_client: Arc<dyn StorageRead>,
     validator: Arc<V>,
+    stateless_validator: StatelessValidator,
     subscribers: Vec<UnboundedSender<SharedMempoolNotification>>,
     timer: Option<IntervalStream>,
-) -> Runtime
+) -> (Runtime, TaskManager)
 where
     V: TransactionValidation + 'static,
 {
-    let runtime = Builder::new()
-        .name_prefix("shared-mem-")
-        .build()
-        .expect("[shared mempool] failed to create runtime");
-    let executor = runtime.executor();
+    let runtime = build_runtime("shared-mem-");
+    let task_manager = TaskManager::new("shared_mempool", runtime.executor());
 
     let peer_info = Arc::new(Mutex::new(PeerInfo::new()));
 
@@ -424,6 +908,11 @@ where
         network_sender,
         storage_read_client,
         validator,
+        signature_verifier: SignatureVerifier::new(
+            config.mempool.shared_mempool_signature_verification_threads,
+        ),
+        stateless_validator,
+        upstream: UpstreamPeers::new(&config.upstream),
         peer_info,
         subscribers,
     };
@@ -431,26 +920,21 @@ where
     let interval =
         timer.unwrap_or_else(|| default_timer(config.mempool.shared_mempool_tick_interval_ms));
 
-    executor.spawn(
-        outbound_sync_task(smp.clone(), interval)
-            .boxed()
-            .unit_error()
-            .compat(),
-    );
+    task_manager.spawn("outbound_sync", outbound_sync_task(smp.clone(), interval));
+
+    if let Some(path) = config.get_mempool_snapshot_file() {
+        task_manager.spawn("snapshot", snapshot_task(smp.clone(), path));
+    }
 
-    executor.spawn(
-        inbound_network_task(smp, executor.clone(), network_events)
-            .boxed()
-            .unit_error()
-            .compat(),
+    task_manager.spawn(
+        "inbound_network",
+        inbound_network_task(smp, task_manager.clone(), network_events),
     );
 
-    executor.spawn(
-        gc_task(mempool, config.mempool.system_transaction_gc_interval_ms)
-            .boxed()
-            .unit_error()
-            .compat(),
+    task_manager.spawn(
+        "gc",
+        gc_task(mempool, config.mempool.system_transaction_gc_interval_ms),
     );
 
-    runtime
+    (runtime, task_manager)
 }
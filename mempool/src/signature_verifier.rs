@@ -0,0 +1,69 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of the signatures of the transactions SharedMempool receives from its peers.
+//!
+//! Checking the signatures of a batch one after the other on the task which received it makes
+//! the large batches of the syncs between peers hold a worker of SharedMempool for a long time.
+//! Instead, batches are verified in parallel on a thread pool of their own, and only the
+//! transactions with a valid signature move on to validation by the VM, which doesn't verify their
+//! signature again, and insertion into Mempool.
+
+use crate::OP_COUNTERS;
+use futures_preview::channel::oneshot;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use std::sync::Arc;
+use types::transaction::{SignatureCheckedTransaction, SignedTransaction};
+
+/// Pool of threads verifying the signatures of batches of transactions. Cloning it is cheap and
+/// all clones share the same threads.
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    pool: Arc<ThreadPool>,
+}
+
+impl SignatureVerifier {
+    /// Creates a pool of `num_threads` threads, or of one thread per CPU if `num_threads` is 0.
+    pub fn new(num_threads: usize) -> Self {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("mempool-sig-{}", index))
+            .build()
+            .expect("[mempool] failed to create signature verification pool");
+        Self {
+            pool: Arc::new(pool),
+        }
+    }
+
+    /// Returns the transactions of `transactions` with a valid signature, in their original order.
+    /// Blocks the calling thread until the whole batch is verified.
+    pub fn verify(&self, transactions: Vec<SignedTransaction>) -> Vec<SignatureCheckedTransaction> {
+        self.pool.install(|| verify_batch(transactions))
+    }
+
+    /// Same as [`verify`](SignatureVerifier::verify), without blocking the calling task.
+    pub async fn verify_async(
+        &self,
+        transactions: Vec<SignedTransaction>,
+    ) -> Vec<SignatureCheckedTransaction> {
+        let (sender, receiver) = oneshot::channel();
+        self.pool.spawn(move || {
+            // the receiver only goes away if the task awaiting the batch was dropped
+            let _ = sender.send(verify_batch(transactions));
+        });
+        receiver
+            .await
+            .expect("[mempool] signature verification pool dropped a batch")
+    }
+}
+
+/// Verifies the signatures of `transactions` in parallel on the current rayon pool
+fn verify_batch(transactions: Vec<SignedTransaction>) -> Vec<SignatureCheckedTransaction> {
+    let count = transactions.len();
+    let verified: Vec<_> = transactions
+        .into_par_iter()
+        .filter_map(|txn| txn.check_signature().ok())
+        .collect();
+    OP_COUNTERS.inc_by("smp.transactions.invalid_signature", count - verified.len());
+    verified
+}
@@ -0,0 +1,72 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshots of the transactions of Mempool, written to disk so that the transactions accepted
+//! but not committed yet are not lost when the node restarts. On startup, SharedMempool
+//! re-validates the transactions of the last snapshot against the latest state and reloads the
+//! ones which are still valid.
+
+use crate::{
+    core_mempool::{CoreMempool, TxnSource},
+    proto::mempool::MempoolSnapshot,
+};
+use failure::prelude::*;
+use prost::Message;
+use prost_ext::MessageExt;
+use std::{
+    convert::{TryFrom, TryInto},
+    fs,
+    path::Path,
+    sync::Mutex,
+};
+use types::transaction::SignedTransaction;
+
+/// Writes up to `max_transactions` transactions of `mempool` to `path`. The snapshot replaces the
+/// previous one atomically, so that a crash while writing it never leaves a truncated file behind.
+/// Returns the number of transactions written.
+pub(crate) fn write_snapshot(
+    mempool: &Mutex<CoreMempool>,
+    path: &Path,
+    max_transactions: usize,
+) -> Result<usize> {
+    let transactions = mempool
+        .lock()
+        .expect("[mempool] failed to acquire mempool lock")
+        .snapshot(max_transactions);
+    let count = transactions.len();
+
+    let mut snapshot = MempoolSnapshot::default();
+    for (txn, source) in transactions {
+        let txn = txn.try_into()?;
+        match source {
+            TxnSource::Local => snapshot.local_transactions.push(txn),
+            TxnSource::Peer => snapshot.peer_transactions.push(txn),
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, snapshot.to_vec()?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(count)
+}
+
+/// Reads the transactions of the snapshot at `path` along with their source. A missing snapshot,
+/// e.g. on the first start of the node, holds no transaction.
+pub(crate) fn read_snapshot(path: &Path) -> Result<Vec<(SignedTransaction, TxnSource)>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let snapshot = MempoolSnapshot::decode(fs::read(path)?)?;
+    let local = snapshot
+        .local_transactions
+        .into_iter()
+        .map(|txn| Ok((SignedTransaction::try_from(txn)?, TxnSource::Local)));
+    let peer = snapshot
+        .peer_transactions
+        .into_iter()
+        .map(|txn| Ok((SignedTransaction::try_from(txn)?, TxnSource::Peer)));
+    local.chain(peer).collect()
+}
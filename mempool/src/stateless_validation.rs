@@ -0,0 +1,200 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stateless checks of the transactions entering Mempool, on top of their validation by the VM.
+//!
+//! Deployments plug in their own policies by implementing [`StatelessValidation`] and adding them
+//! to the [`StatelessValidator`] Mempool is bootstrapped with. The checks run on the transactions
+//! received from peers before they are validated by the VM, which is much more expensive, and on
+//! the transactions submitted through Admission Control before they are inserted. The validator
+//! built from the configuration of Mempool enforces the max transaction size, the banned senders
+//! and the script allow-list configured there. Admission Control builds its pre-validation out of
+//! the same validations.
+
+use config::config::MempoolConfig;
+use crypto::HashValue;
+use failure::prelude::*;
+use std::{collections::HashSet, str::FromStr, sync::Arc};
+use types::{
+    account_address::AccountAddress,
+    transaction::{SignedTransaction, TransactionPayload},
+};
+
+/// A check of a transaction which doesn't depend on the state of the ledger.
+pub trait StatelessValidation: Send + Sync {
+    /// Returns an error describing why `txn` is rejected, if it is.
+    fn validate(&self, txn: &SignedTransaction) -> Result<()>;
+}
+
+/// Why a transaction failed one of the validations of this module.
+#[derive(Debug, Fail, PartialEq)]
+pub enum StatelessValidationError {
+    #[fail(display = "Transaction of {} bytes is larger than {} bytes", size, max)]
+    TooLarge { size: usize, max: usize },
+    #[fail(display = "Gas unit price {} is higher than {}", price, max)]
+    GasUnitPriceTooHigh { price: u64, max: u64 },
+    #[fail(display = "Sender {} is banned", _0)]
+    BannedSender(AccountAddress),
+    #[fail(display = "Script {} is not allowed", _0)]
+    ScriptNotAllowed(HashValue),
+    #[fail(display = "Script {} is denied", _0)]
+    ScriptDenied(HashValue),
+}
+
+impl StatelessValidationError {
+    /// Name of the check which failed, for the counters.
+    pub fn check_name(&self) -> &'static str {
+        match self {
+            StatelessValidationError::TooLarge { .. } => "too_large",
+            StatelessValidationError::GasUnitPriceTooHigh { .. } => "gas_unit_price_too_high",
+            StatelessValidationError::BannedSender(_) => "banned_sender",
+            StatelessValidationError::ScriptNotAllowed(_) => "script_not_allowed",
+            StatelessValidationError::ScriptDenied(_) => "script_denied",
+        }
+    }
+}
+
+/// Rejects the signed transactions larger than the given number of bytes, their public key and
+/// signature included.
+pub struct MaxTransactionSize(pub usize);
+
+impl StatelessValidation for MaxTransactionSize {
+    fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        let size = txn.signed_txn_bytes_len();
+        if size > self.0 {
+            return Err(StatelessValidationError::TooLarge { size, max: self.0 }.into());
+        }
+        Ok(())
+    }
+}
+
+/// Rejects the transactions offering a higher gas unit price than the given one, as they most
+/// likely result from a mistake of the client.
+pub struct MaxGasUnitPrice(pub u64);
+
+impl StatelessValidation for MaxGasUnitPrice {
+    fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        let price = txn.gas_unit_price();
+        if price > self.0 {
+            return Err(
+                StatelessValidationError::GasUnitPriceTooHigh { price, max: self.0 }.into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Rejects the transactions of the given senders.
+pub struct BannedSenders(pub HashSet<AccountAddress>);
+
+impl StatelessValidation for BannedSenders {
+    fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        if self.0.contains(&txn.sender()) {
+            return Err(StatelessValidationError::BannedSender(txn.sender()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Only accepts the scripts whose code hashes to one of the given hashes, like the script
+/// whitelist of the VM. Transactions publishing modules or carrying write sets are left to the
+/// publishing options of the VM.
+pub struct ScriptAllowList(pub HashSet<HashValue>);
+
+impl StatelessValidation for ScriptAllowList {
+    fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        match script_hash(txn) {
+            Some(hash) if !self.0.contains(&hash) => {
+                Err(StatelessValidationError::ScriptNotAllowed(hash).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rejects the scripts whose code hashes to one of the given hashes.
+pub struct ScriptDenyList(pub HashSet<HashValue>);
+
+impl StatelessValidation for ScriptDenyList {
+    fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        match script_hash(txn) {
+            Some(hash) if self.0.contains(&hash) => {
+                Err(StatelessValidationError::ScriptDenied(hash).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Hash of the script of `txn`, hashed the same way as the scripts whitelisted in the VM config,
+/// if it runs one.
+fn script_hash(txn: &SignedTransaction) -> Option<HashValue> {
+    let code = match txn.payload() {
+        TransactionPayload::Program(program) => program.code(),
+        TransactionPayload::Script(script) => script.code(),
+        TransactionPayload::Module(_) | TransactionPayload::WriteSet(_) => return None,
+    };
+    Some(HashValue::from_sha3_256(code))
+}
+
+/// Runs a list of stateless validations. Cloning it is cheap and all clones share the same
+/// validations.
+#[derive(Clone, Default)]
+pub struct StatelessValidator {
+    validations: Vec<Arc<dyn StatelessValidation>>,
+}
+
+impl StatelessValidator {
+    /// Creates a validator accepting all transactions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a validator enforcing the policies configured in `config`.
+    pub fn from_config(config: &MempoolConfig) -> Self {
+        let mut validator = Self::new();
+        if let Some(max_size) = config.max_transaction_size {
+            validator = validator.with(MaxTransactionSize(max_size));
+        }
+        if !config.banned_senders.is_empty() {
+            let senders = config
+                .banned_senders
+                .iter()
+                .map(|address| {
+                    AccountAddress::from_str(address).unwrap_or_else(|_| {
+                        panic!("Failed to parse banned sender address: {}", address)
+                    })
+                })
+                .collect();
+            validator = validator.with(BannedSenders(senders));
+        }
+        if !config.script_allow_list.is_empty() {
+            let hashes = config
+                .script_allow_list
+                .iter()
+                .map(|hash| {
+                    hex::decode(hash)
+                        .ok()
+                        .and_then(|bytes| HashValue::from_slice(&bytes).ok())
+                        .unwrap_or_else(|| panic!("Failed to parse allowed script hash: {}", hash))
+                })
+                .collect();
+            validator = validator.with(ScriptAllowList(hashes));
+        }
+        validator
+    }
+
+    /// Adds `validation` to the validations run on every transaction.
+    pub fn with(mut self, validation: impl StatelessValidation + 'static) -> Self {
+        self.validations.push(Arc::new(validation));
+        self
+    }
+
+    /// Returns the error of the first validation rejecting `txn`, if any.
+    pub fn validate(&self, txn: &SignedTransaction) -> Result<()> {
+        for validation in &self.validations {
+            validation.validate(txn)?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{core_mempool::CoreMempool, mempool_service::MempoolService, proto::mempool::*};
+use crate::{
+    core_mempool::CoreMempool,
+    mempool_service::{MempoolService, MAX_ADD_TRANSACTIONS_BATCH_SIZE},
+    proto::mempool::*,
+    stateless_validation::{BannedSenders, StatelessValidator},
+};
 use config::config::NodeConfigHelpers;
 use crypto::ed25519::compat::generate_keypair;
 use grpc_helpers::ServerHandle;
@@ -19,11 +24,20 @@ use types::{
 };
 
 fn setup_mempool() -> (::grpcio::Server, MempoolClient) {
+    setup_mempool_with_validator(StatelessValidator::new())
+}
+
+fn setup_mempool_with_validator(
+    stateless_validator: StatelessValidator,
+) -> (::grpcio::Server, MempoolClient) {
     let node_config = NodeConfigHelpers::get_single_node_test_config(true);
 
     let env = Arc::new(EnvBuilder::new().build());
     let core_mempool = Arc::new(Mutex::new(CoreMempool::new(&node_config)));
-    let handle = MempoolService { core_mempool };
+    let handle = MempoolService {
+        core_mempool,
+        stateless_validator,
+    };
     let service = create_mempool(handle);
 
     let server = ::grpcio::ServerBuilder::new(env.clone())
@@ -74,6 +88,73 @@ fn test_add_transaction() {
     );
 }
 
+#[test]
+fn test_stateless_validation() {
+    let req = create_add_transaction_request(0);
+    let sender = SignedTransaction::try_from(req.signed_txn.clone().unwrap())
+        .unwrap()
+        .sender();
+    let (server, client) = setup_mempool_with_validator(
+        StatelessValidator::new().with(BannedSenders(vec![sender].into_iter().collect())),
+    );
+    let _handle = ServerHandle::setup(server);
+
+    let response = client.add_transaction_with_validation(&req).unwrap();
+    assert_eq!(
+        response.status.unwrap().code(),
+        MempoolAddTransactionStatusCode::FailedStatelessValidation
+    );
+    let response = client.get_block(&GetBlockRequest::default()).unwrap();
+    assert!(response.block.unwrap().transactions.is_empty());
+}
+
+#[test]
+fn test_add_transactions() {
+    let banned = create_add_transaction_request(0);
+    let banned_sender = SignedTransaction::try_from(banned.signed_txn.clone().unwrap())
+        .unwrap()
+        .sender();
+    let (server, client) = setup_mempool_with_validator(
+        StatelessValidator::new().with(BannedSenders(vec![banned_sender].into_iter().collect())),
+    );
+    let _handle = ServerHandle::setup(server);
+
+    let valid = create_add_transaction_request(0);
+    let mut insufficient_balance = create_add_transaction_request(0);
+    insufficient_balance.account_balance = 0;
+    let mut req = AddTransactionsWithValidationRequest::default();
+    req.transactions = vec![
+        valid.clone(),
+        banned,
+        AddTransactionWithValidationRequest::default(),
+        insufficient_balance,
+    ];
+    let response = client.add_transactions_with_validation(&req).unwrap();
+    let codes: Vec<_> = response
+        .statuses
+        .into_iter()
+        .map(|status| status.code())
+        .collect();
+    assert_eq!(
+        codes,
+        vec![
+            MempoolAddTransactionStatusCode::Valid,
+            MempoolAddTransactionStatusCode::FailedStatelessValidation,
+            MempoolAddTransactionStatusCode::InvalidTransaction,
+            MempoolAddTransactionStatusCode::InsufficientBalance,
+        ]
+    );
+    let response = client.get_block(&GetBlockRequest::default()).unwrap();
+    assert_eq!(
+        response.block.unwrap().transactions,
+        vec![valid.signed_txn.clone().unwrap()]
+    );
+
+    // Batches over the limit are refused as a whole
+    req.transactions = vec![valid; MAX_ADD_TRANSACTIONS_BATCH_SIZE + 1];
+    assert!(client.add_transactions_with_validation(&req).is_err());
+}
+
 #[test]
 fn test_get_block() {
     let (server, client) = setup_mempool();
@@ -147,3 +228,30 @@ fn test_gc_by_expiration_time() {
     let response = client.get_block(&GetBlockRequest::default()).unwrap();
     assert_eq!(response.block.unwrap().transactions.len(), 1);
 }
+
+#[test]
+fn test_get_account_transactions() {
+    let (server, client) = setup_mempool();
+    let _handle = ServerHandle::setup(server);
+
+    let add_req = create_add_transaction_request(0);
+    client.add_transaction_with_validation(&add_req).unwrap();
+    let signed_txn = SignedTransaction::try_from(add_req.signed_txn.clone().unwrap()).unwrap();
+
+    let mut req = GetAccountTransactionsRequest::default();
+    req.address = signed_txn.sender().to_vec();
+    let response = client.get_account_transactions(&req).unwrap();
+    assert_eq!(response.transactions.len(), 1);
+    assert_eq!(response.transactions[0].signed_txn, add_req.signed_txn);
+    assert!(response.transactions[0].insertion_time_usecs > 0);
+    assert!(response.transactions[0].is_ready);
+
+    // other accounts have no pending transaction
+    req.address = AccountAddress::random().to_vec();
+    let response = client.get_account_transactions(&req).unwrap();
+    assert!(response.transactions.is_empty());
+
+    // invalid addresses are rejected
+    req.address = vec![0; 3];
+    assert!(client.get_account_transactions(&req).is_err());
+}
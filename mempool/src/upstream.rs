@@ -0,0 +1,124 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Selection of the upstream peers a full node broadcasts its transactions to.
+//!
+//! Validators broadcast to all their peers. A full node configured with upstream peers for
+//! transaction submission only broadcasts to its preferred upstream peers, and fails over to its
+//! fallback peers while none of the preferred ones is usable. A peer is unusable while it is
+//! disconnected, or once the broadcasts to it failed or were not acknowledged in time
+//! `failover_threshold` times in a row, until `failover_retry_interval_ms` later, when it is tried
+//! again. All the usable peers of the selected tier are broadcast to, each with the batch size
+//! and frequency its own backpressure allows, see `broadcast_control`, so that healthier peers
+//! carry more of the transactions.
+
+use crate::OP_COUNTERS;
+use config::config::UpstreamConfig;
+use logger::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+use types::PeerId;
+
+/// Upstream peers of the node, common to all peers
+#[derive(Clone, Debug)]
+pub(crate) struct UpstreamPeers {
+    preferred: Vec<PeerId>,
+    fallback: Vec<PeerId>,
+    failover_threshold: u64,
+    retry_interval: Duration,
+}
+
+impl UpstreamPeers {
+    pub(crate) fn new(config: &UpstreamConfig) -> Self {
+        let (preferred, fallback) = config.get_submission_peers();
+        Self {
+            preferred,
+            fallback,
+            failover_threshold: config.failover_threshold,
+            retry_interval: Duration::from_millis(config.failover_retry_interval_ms),
+        }
+    }
+
+    /// Whether the node has upstream peers, as opposed to broadcasting to all its peers
+    fn is_enabled(&self) -> bool {
+        !self.preferred.is_empty() || !self.fallback.is_empty()
+    }
+
+    /// Peers to broadcast to, among the connected `peers`
+    pub(crate) fn select(
+        &self,
+        peers: &HashMap<PeerId, UpstreamHealth>,
+        now: Instant,
+    ) -> HashSet<PeerId> {
+        if !self.is_enabled() {
+            return peers.keys().cloned().collect();
+        }
+        let usable = |tier: &[PeerId]| -> HashSet<PeerId> {
+            tier.iter()
+                .filter(|peer_id| {
+                    peers
+                        .get(peer_id)
+                        .map_or(false, |health| health.is_usable(self, now))
+                })
+                .cloned()
+                .collect()
+        };
+        let preferred = usable(&self.preferred);
+        if !preferred.is_empty() {
+            return preferred;
+        }
+        let fallback = usable(&self.fallback);
+        if !fallback.is_empty() {
+            return fallback;
+        }
+        // every connected upstream peer was failed over, keep trying all of them
+        self.preferred
+            .iter()
+            .chain(self.fallback.iter())
+            .filter(|peer_id| peers.contains_key(peer_id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Health of the broadcasts to a peer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct UpstreamHealth {
+    consecutive_failures: u64,
+    // when the peer was last failed over, if it was
+    failed_over_at: Option<Instant>,
+}
+
+impl UpstreamHealth {
+    fn is_usable(&self, upstream: &UpstreamPeers, now: Instant) -> bool {
+        match self.failed_over_at {
+            Some(failed_over_at) => now.duration_since(failed_over_at) >= upstream.retry_interval,
+            None => true,
+        }
+    }
+
+    /// Resets the failures once a broadcast is acknowledged, or handed over to the network if the
+    /// peer doesn't acknowledge broadcasts
+    pub(crate) fn on_sent(&mut self) {
+        self.consecutive_failures = 0;
+        self.failed_over_at = None;
+    }
+
+    /// Fails the peer over once its broadcasts failed `failover_threshold` times in a row. A peer
+    /// which is tried again after its retry interval is failed over again by its next failure
+    pub(crate) fn on_failed(&mut self, upstream: &UpstreamPeers, peer_id: &PeerId, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if upstream.is_enabled() && self.consecutive_failures >= upstream.failover_threshold {
+            if self.failed_over_at.is_none() {
+                OP_COUNTERS.inc("smp.upstream.failover");
+                warn!(
+                    "[shared mempool] failing over upstream peer {} after {} failed broadcasts",
+                    peer_id, self.consecutive_failures
+                );
+            }
+            self.failed_over_at = Some(now);
+        }
+    }
+}
@@ -7,6 +7,7 @@ fn main() {
         "src/proto/consensus.proto",
         "src/proto/mempool.proto",
         "src/proto/network.proto",
+        "src/proto/peer_store.proto",
         "src/proto/state_synchronizer.proto",
     ];
 
@@ -0,0 +1,525 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fault injection into the connections of a transport.
+//!
+//! A [`FaultTransport`] wraps the sockets of the connections established by the underlying
+//! transport, so that a [`FaultInjector`] can delay, drop, duplicate or corrupt the data written to
+//! each of them. It is meant for tests simulating flaky links: as faults are injected below any
+//! encryption or framing, the upper layers see them as they would see a bad network.
+//!
+//! Only the data written to a connection is subject to faults, so both ends of a link have to
+//! inject faults to disrupt both of its directions.
+
+use crate::transport::{ConnectionOrigin, Transport};
+use futures::{
+    compat::Compat01As03,
+    future::Future,
+    io::{AsyncRead, AsyncWrite},
+    ready,
+    stream::Stream,
+};
+use parity_multiaddr::{Multiaddr, Protocol};
+use pin_utils::{unsafe_pinned, unsafe_unpinned};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::timer::Delay;
+
+/// A fault injected into a write to a connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// Holds the write back for the given duration, stalling the connection.
+    Delay(Duration),
+    /// Reports the write as successful without sending anything.
+    Drop,
+    /// Sends the written data twice.
+    Duplicate,
+    /// Flips the bits of the middle byte of the written data.
+    Corrupt,
+}
+
+/// The connection a write is made to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectionInfo {
+    pub origin: ConnectionOrigin,
+    /// Address of the remote end: the dialed address for outbound connections, the address of the
+    /// dialer for inbound ones.
+    pub remote_addr: Multiaddr,
+}
+
+/// Decides which faults a [`FaultTransport`] injects into its connections.
+pub trait FaultInjector: Send + Sync {
+    /// Called before every write of `len` bytes to `connection`, returns the fault to inject into
+    /// it, if any.
+    fn on_write(&self, connection: &ConnectionInfo, len: usize) -> Option<Fault>;
+}
+
+/// Probabilities of the faults [`RandomFaults`] injects into the writes to a connection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultProfile {
+    pub drop_probability: f64,
+    pub corrupt_probability: f64,
+    pub duplicate_probability: f64,
+    pub delay_probability: f64,
+    /// How long a delayed write is held back.
+    pub delay: Duration,
+}
+
+impl FaultProfile {
+    /// Whether all the probabilities of the profile are between 0 and 1.
+    pub fn is_valid(&self) -> bool {
+        [
+            self.drop_probability,
+            self.corrupt_probability,
+            self.duplicate_probability,
+            self.delay_probability,
+        ]
+        .iter()
+        .all(|p| *p >= 0.0 && *p <= 1.0)
+    }
+
+    fn pick_fault(&self) -> Option<Fault> {
+        let mut rng = rand::thread_rng();
+        let mut happens = |p: f64| p > 0.0 && rng.gen_bool(p.min(1.0));
+        if happens(self.drop_probability) {
+            Some(Fault::Drop)
+        } else if happens(self.corrupt_probability) {
+            Some(Fault::Corrupt)
+        } else if happens(self.duplicate_probability) {
+            Some(Fault::Duplicate)
+        } else if happens(self.delay_probability) {
+            Some(Fault::Delay(self.delay))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+struct Profiles {
+    default: FaultProfile,
+    by_ip: HashMap<IpAddr, FaultProfile>,
+}
+
+/// A [`FaultInjector`] which picks faults at random, following the [`FaultProfile`] of the remote
+/// IP address of each connection.
+///
+/// Profiles can be changed at any time, the change applies to the existing connections as well.
+/// No faults are injected until a profile is set.
+#[derive(Default)]
+pub struct RandomFaults {
+    profiles: RwLock<Profiles>,
+}
+
+impl RandomFaults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the profile of the connections whose remote IP address has no profile of its own.
+    pub fn set_default_profile(&self, profile: FaultProfile) {
+        self.profiles.write().unwrap().default = profile;
+    }
+
+    /// Sets the profile of the connections with the remote IP address `ip`, or removes it if
+    /// `profile` is `None`.
+    pub fn set_profile(&self, ip: IpAddr, profile: Option<FaultProfile>) {
+        let mut profiles = self.profiles.write().unwrap();
+        match profile {
+            Some(profile) => profiles.by_ip.insert(ip, profile),
+            None => profiles.by_ip.remove(&ip),
+        };
+    }
+
+    /// Removes all the profiles, so that no more faults are injected.
+    pub fn clear(&self) {
+        *self.profiles.write().unwrap() = Profiles::default();
+    }
+}
+
+impl FaultInjector for RandomFaults {
+    fn on_write(&self, connection: &ConnectionInfo, _len: usize) -> Option<Fault> {
+        let profiles = self.profiles.read().unwrap();
+        remote_ip(&connection.remote_addr)
+            .and_then(|ip| profiles.by_ip.get(&ip))
+            .unwrap_or(&profiles.default)
+            .pick_fault()
+    }
+}
+
+fn remote_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// A [`FaultTransport`] is a transport which lets a [`FaultInjector`] inject faults into the
+/// connections of another transport.
+pub struct FaultTransport<T> {
+    transport: T,
+    injector: Option<Arc<dyn FaultInjector>>,
+}
+
+impl<T> FaultTransport<T> {
+    /// Wraps around a [`Transport`], letting `injector` inject faults into the connections created
+    /// by it. Without an injector, the connections are left untouched.
+    pub(crate) fn new(transport: T, injector: Option<Arc<dyn FaultInjector>>) -> Self {
+        Self {
+            transport,
+            injector,
+        }
+    }
+}
+
+impl<T> Transport for FaultTransport<T>
+where
+    T: Transport,
+{
+    type Output = FaultySocket<T::Output>;
+    type Error = T::Error;
+    type Listener = FaultListener<T::Listener>;
+    type Inbound = FaultFuture<T::Inbound>;
+    type Outbound = FaultFuture<T::Outbound>;
+
+    fn listen_on(&self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), Self::Error> {
+        let (listener, addr) = self.transport.listen_on(addr)?;
+        let listener = FaultListener {
+            listener,
+            injector: self.injector.clone(),
+        };
+
+        Ok((listener, addr))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::Outbound, Self::Error> {
+        let fut = self.transport.dial(addr.clone())?;
+        let connection = ConnectionInfo {
+            origin: ConnectionOrigin::Outbound,
+            remote_addr: addr,
+        };
+
+        Ok(FaultFuture::new(fut, self.injector.clone(), connection))
+    }
+}
+
+/// Listener stream returned by [listen_on](Transport::listen_on) on a FaultTransport.
+#[must_use = "streams do nothing unless polled"]
+pub struct FaultListener<St> {
+    listener: St,
+    injector: Option<Arc<dyn FaultInjector>>,
+}
+
+impl<St, Fut, E> Stream for FaultListener<St>
+where
+    St: Stream<Item = Result<(Fut, Multiaddr), E>> + Unpin,
+{
+    type Item = Result<(FaultFuture<Fut>, Multiaddr), E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        match ready!(Pin::new(&mut self.listener).poll_next(context)) {
+            None => Poll::Ready(None),
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            Some(Ok((fut, addr))) => {
+                let connection = ConnectionInfo {
+                    origin: ConnectionOrigin::Inbound,
+                    remote_addr: addr.clone(),
+                };
+                let fut = FaultFuture::new(fut, self.injector.clone(), connection);
+                Poll::Ready(Some(Ok((fut, addr))))
+            }
+        }
+    }
+}
+
+/// Future generated by the [`FaultTransport`], wrapping the socket of the underlying transport
+/// once the connection is established.
+#[must_use = "futures do nothing unless polled"]
+pub struct FaultFuture<Fut> {
+    inner: Fut,
+    faults: Option<Faults>,
+}
+
+impl<Fut, S, E> FaultFuture<Fut>
+where
+    Fut: Future<Output = Result<S, E>>,
+{
+    // This use of `unsafe_pinned` is safe because:
+    //   1. This struct does not implement [`Drop`]
+    //   2. This struct does not implement [`Unpin`]
+    //   3. This struct is not `#[repr(packed)]`
+    unsafe_pinned!(inner: Fut);
+    unsafe_unpinned!(faults: Option<Faults>);
+
+    fn new(
+        inner: Fut,
+        injector: Option<Arc<dyn FaultInjector>>,
+        connection: ConnectionInfo,
+    ) -> Self {
+        Self {
+            inner,
+            faults: Some(injector.map(|injector| (injector, connection))),
+        }
+    }
+}
+
+impl<Fut, S, E> Future for FaultFuture<Fut>
+where
+    Fut: Future<Output = Result<S, E>>,
+{
+    type Output = Result<FaultySocket<S>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let socket = ready!(self.as_mut().inner().poll(context))?;
+        let faults = self
+            .as_mut()
+            .faults()
+            .take()
+            .expect("FaultFuture polled after completion");
+        Poll::Ready(Ok(FaultySocket::new(socket, faults)))
+    }
+}
+
+type Faults = Option<(Arc<dyn FaultInjector>, ConnectionInfo)>;
+
+/// Socket of a connection established by a [`FaultTransport`].
+///
+/// The data of a duplicated or corrupted write is reported as written right away, and is actually
+/// written by the next calls to write, flush or close.
+pub struct FaultySocket<S> {
+    socket: S,
+    faults: Faults,
+    /// Data to write to the socket before anything else.
+    pending: Vec<u8>,
+    /// Delay of the write in progress, if it is delayed.
+    delay: Option<Compat01As03<Delay>>,
+}
+
+impl<S> FaultySocket<S> {
+    fn new(socket: S, faults: Faults) -> Self {
+        Self {
+            socket,
+            faults,
+            pending: vec![],
+            delay: None,
+        }
+    }
+}
+
+impl<S> FaultySocket<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write_pending(&mut self, context: &mut Context) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let n = ready!(Pin::new(&mut self.socket).poll_write(context, &self.pending))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> fmt::Debug for FaultySocket<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultySocket {{ socket: {:?} }}", self.socket)
+    }
+}
+
+impl<S> AsyncRead for FaultySocket<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.socket).poll_read(context, buf)
+    }
+}
+
+impl<S> AsyncWrite for FaultySocket<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_write_pending(context))?;
+
+        if this.delay.is_none() {
+            if let Some((injector, connection)) = &this.faults {
+                match injector.on_write(connection, buf.len()) {
+                    None => {}
+                    Some(Fault::Delay(duration)) => {
+                        this.delay = Some(Compat01As03::new(Delay::new(Instant::now() + duration)));
+                    }
+                    Some(Fault::Drop) => return Poll::Ready(Ok(buf.len())),
+                    Some(Fault::Duplicate) => {
+                        this.pending.extend_from_slice(buf);
+                        this.pending.extend_from_slice(buf);
+                        return Poll::Ready(Ok(buf.len()));
+                    }
+                    Some(Fault::Corrupt) => {
+                        this.pending.extend_from_slice(buf);
+                        if let Some(byte) = this.pending.get_mut(buf.len() / 2) {
+                            *byte ^= 0xff;
+                        }
+                        return Poll::Ready(Ok(buf.len()));
+                    }
+                }
+            }
+        }
+
+        // The delay is kept until the write goes through, so that the write is not subject to
+        // another fault if the socket is not ready for it once the delay is over.
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(Pin::new(delay).poll(context))
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+        let poll = Pin::new(&mut this.socket).poll_write(context, buf);
+        if poll.is_ready() {
+            this.delay = None;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_pending(context))?;
+        Pin::new(&mut self.socket).poll_flush(context)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_pending(context))?;
+        Pin::new(&mut self.socket).poll_close(context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{memory::MemoryTransport, TransportExt};
+    use futures::{
+        executor::block_on,
+        future::join,
+        io::{AsyncReadExt, AsyncWriteExt},
+        stream::StreamExt,
+    };
+    use std::sync::Mutex;
+
+    /// Injects the given faults into the successive writes of outbound connections.
+    struct ScriptedFaults(Mutex<Vec<Option<Fault>>>);
+
+    impl FaultInjector for ScriptedFaults {
+        fn on_write(&self, connection: &ConnectionInfo, _len: usize) -> Option<Fault> {
+            assert_eq!(connection.origin, ConnectionOrigin::Outbound);
+            let mut faults = self.0.lock().unwrap();
+            if faults.is_empty() {
+                None
+            } else {
+                faults.remove(0)
+            }
+        }
+    }
+
+    /// Writes every message of `messages` separately through a connection injecting `faults`, and
+    /// returns what was received on the other end.
+    fn send_with_faults(messages: &[&[u8]], faults: Vec<Option<Fault>>) -> Vec<u8> {
+        let injector: Arc<dyn FaultInjector> = Arc::new(ScriptedFaults(Mutex::new(faults)));
+        let t = MemoryTransport::default().with_faults(Some(injector));
+        let (listener, addr) = t.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let outbound = t.dial(addr).unwrap();
+
+        let listener = async move {
+            let (item, _listener) = listener.into_future().await;
+            let (inbound, _addr) = item.unwrap().unwrap();
+            let mut socket = inbound.await.unwrap();
+            let mut buf = Vec::new();
+            socket.read_to_end(&mut buf).await.unwrap();
+            buf
+        };
+        let dialer = async move {
+            let mut socket = outbound.await.unwrap();
+            for message in messages {
+                socket.write_all(message).await.unwrap();
+            }
+            socket.close().await.unwrap();
+        };
+
+        block_on(join(dialer, listener)).1
+    }
+
+    #[test]
+    fn no_faults() {
+        assert_eq!(
+            send_with_faults(&[b"hello", b"world"], vec![]),
+            b"helloworld"
+        );
+    }
+
+    #[test]
+    fn drop_write() {
+        assert_eq!(
+            send_with_faults(&[b"hello", b"world"], vec![Some(Fault::Drop)]),
+            b"world"
+        );
+    }
+
+    #[test]
+    fn duplicate_write() {
+        assert_eq!(
+            send_with_faults(&[b"hello", b"world"], vec![None, Some(Fault::Duplicate)]),
+            b"helloworldworld"
+        );
+    }
+
+    #[test]
+    fn corrupt_write() {
+        let received = send_with_faults(&[b"hello", b"world"], vec![Some(Fault::Corrupt)]);
+        assert_eq!(received.len(), 10);
+        assert_eq!(received[2], b'l' ^ 0xff);
+        assert_eq!(&received[5..], b"world");
+    }
+
+    #[test]
+    fn random_faults_follow_profiles() {
+        let faults = RandomFaults::new();
+        let connection = |addr: &str| ConnectionInfo {
+            origin: ConnectionOrigin::Outbound,
+            remote_addr: addr.parse().unwrap(),
+        };
+        let drop_all = FaultProfile {
+            drop_probability: 1.0,
+            ..FaultProfile::default()
+        };
+        assert_eq!(faults.on_write(&connection("/ip4/1.2.3.4/tcp/80"), 1), None);
+
+        faults.set_profile("1.2.3.4".parse().unwrap(), Some(drop_all));
+        assert_eq!(
+            faults.on_write(&connection("/ip4/1.2.3.4/tcp/80"), 1),
+            Some(Fault::Drop)
+        );
+        assert_eq!(faults.on_write(&connection("/ip4/5.6.7.8/tcp/80"), 1), None);
+
+        faults.clear();
+        assert_eq!(faults.on_write(&connection("/ip4/1.2.3.4/tcp/80"), 1), None);
+    }
+}
@@ -13,10 +13,11 @@
 
 use futures::{future::Future, stream::Stream};
 use parity_multiaddr::Multiaddr;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 pub mod and_then;
 pub mod boxed;
+pub mod fault;
 pub mod memory;
 pub mod tcp;
 pub mod timeout;
@@ -140,4 +141,18 @@ pub trait TransportExt: Transport {
     {
         timeout::TimeoutTransport::new(self, timeout)
     }
+
+    /// Wraps a [`Transport`] so that `injector` can inject faults into the data written to its
+    /// connections. With no injector, the connections are left untouched.
+    ///
+    /// See the [`fault`](crate::transport::fault) module for the faults which can be injected.
+    fn with_faults(
+        self,
+        injector: Option<Arc<dyn fault::FaultInjector>>,
+    ) -> fault::FaultTransport<Self>
+    where
+        Self: Sized,
+    {
+        fault::FaultTransport::new(self, injector)
+    }
 }
@@ -11,6 +11,7 @@ use futures::{
     stream::Stream,
 };
 use parity_multiaddr::{Multiaddr, Protocol};
+use socket2::{Domain, Socket, Type};
 use std::{
     io,
     net::{Shutdown, SocketAddr},
@@ -18,7 +19,10 @@ use std::{
     task::{Context, Poll},
     time::Duration,
 };
-use tokio::net::tcp::{ConnectFuture, Incoming, TcpListener, TcpStream};
+use tokio::{
+    net::tcp::{ConnectFuture, Incoming, TcpListener, TcpStream},
+    reactor::Handle,
+};
 
 /// Transport to build TCP connections
 #[derive(Debug, Clone, Default)]
@@ -37,15 +41,59 @@ pub struct TcpTransport {
 }
 
 impl TcpTransport {
-    fn apply_config(&self, stream: &TcpStream) -> ::std::io::Result<()> {
+    /// Sets the size of the recv buffer (`SO_RCVBUF`) of opened sockets.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of opened sockets.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the TTL of opened sockets.
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the keep alive duration of opened sockets, or disables keep alive if `None`.
+    pub fn keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on opened sockets.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Creates a socket for `addr` with the buffer sizes set. They have to be set before the
+    /// socket connects or listens, since the TCP window scale is negotiated during the handshake,
+    /// and accepted sockets inherit them from the listening socket.
+    fn new_socket(&self, addr: &SocketAddr) -> ::std::io::Result<Socket> {
+        let domain = if addr.is_ipv4() {
+            Domain::ipv4()
+        } else {
+            Domain::ipv6()
+        };
+        let socket = Socket::new(domain, Type::stream(), None)?;
+
         if let Some(size) = self.recv_buffer_size {
-            stream.set_recv_buffer_size(size)?;
+            socket.set_recv_buffer_size(size)?;
         }
 
         if let Some(size) = self.send_buffer_size {
-            stream.set_send_buffer_size(size)?;
+            socket.set_send_buffer_size(size)?;
         }
 
+        Ok(socket)
+    }
+
+    fn apply_config(&self, stream: &TcpStream) -> ::std::io::Result<()> {
         if let Some(ttl) = self.ttl {
             stream.set_ttl(ttl)?;
         }
@@ -72,7 +120,14 @@ impl Transport for TcpTransport {
     fn listen_on(&self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), Self::Error> {
         let socket_addr = multiaddr_to_socketaddr(&addr)?;
         let config = self.clone();
-        let listener = TcpListener::bind(&socket_addr)?;
+        let socket = self.new_socket(&socket_addr)?;
+        // like `TcpListener::bind`
+        if cfg!(unix) {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&socket_addr.into())?;
+        socket.listen(1024)?;
+        let listener = TcpListener::from_std(socket.into_tcp_listener(), &Handle::default())?;
         let local_addr = socketaddr_to_multiaddr(listener.local_addr()?);
         Ok((
             TcpListenerStream {
@@ -86,7 +141,9 @@ impl Transport for TcpTransport {
     fn dial(&self, addr: Multiaddr) -> Result<Self::Outbound, Self::Error> {
         let socket_addr = multiaddr_to_socketaddr(&addr)?;
         let config = self.clone();
-        let f = TcpStream::connect(&socket_addr).compat();
+        let socket = self.new_socket(&socket_addr)?;
+        let f = TcpStream::connect_std(socket.into_tcp_stream(), &socket_addr, &Handle::default())
+            .compat();
         Ok(TcpOutbound { inner: f, config })
     }
 }
@@ -234,6 +291,7 @@ mod test {
         io::{AsyncReadExt, AsyncWriteExt},
         stream::StreamExt,
     };
+    use std::time::Duration;
 
     #[test]
     fn simple_listen_and_dial() -> Result<(), ::std::io::Error> {
@@ -280,4 +338,31 @@ mod test {
         let result = t.dial("/memory/22".parse().unwrap());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn socket_options() -> Result<(), ::std::io::Error> {
+        let t = TcpTransport::default()
+            .recv_buffer_size(1 << 20)
+            .send_buffer_size(1 << 20)
+            .nodelay(true)
+            .keepalive(Some(Duration::from_secs(30)));
+
+        let (listener, addr) = t.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())?;
+        let dial = t.dial(addr)?;
+        let listener = listener.into_future().then(|(maybe_result, _stream)| {
+            let (incoming, _addr) = maybe_result.unwrap().unwrap();
+            incoming.map(Result::unwrap)
+        });
+
+        let (outgoing, incoming) = block_on(join(dial, listener));
+        for socket in &[outgoing?, incoming] {
+            let stream = socket.inner.get_ref();
+            // the kernel may round the buffer sizes up
+            assert!(stream.recv_buffer_size()? >= 1 << 20);
+            assert!(stream.send_buffer_size()? >= 1 << 20);
+            assert!(stream.nodelay()?);
+            assert_eq!(stream.keepalive()?, Some(Duration::from_secs(30)));
+        }
+        Ok(())
+    }
 }
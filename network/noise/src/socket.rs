@@ -69,7 +69,7 @@ enum ReadState {
     /// End of file reached, result indicated if EOF was expected or not
     Eof(Result<(), ()>),
     /// Decryption Error
-    DecryptionError(snow::SnowError),
+    DecryptionError(snow::Error),
 }
 
 /// Possible write states for a [NoiseSocket]
@@ -92,7 +92,55 @@ enum WriteState {
     /// End of file reached
     Eof,
     /// Encryption Error
-    EncryptionError(snow::SnowError),
+    EncryptionError(snow::Error),
+}
+
+/// State of the noise protocol of a [NoiseSocket]
+#[derive(Debug)]
+enum NoiseSession {
+    /// The handshake is in progress
+    Handshake(snow::HandshakeState),
+    /// The handshake completed, messages are encrypted with the keys it established
+    Transport(snow::TransportState),
+}
+
+impl NoiseSession {
+    fn read_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, snow::Error> {
+        match self {
+            NoiseSession::Handshake(session) => session.read_message(message, payload),
+            NoiseSession::Transport(session) => session.read_message(message, payload),
+        }
+    }
+
+    fn write_message(&mut self, payload: &[u8], message: &mut [u8]) -> Result<usize, snow::Error> {
+        match self {
+            NoiseSession::Handshake(session) => session.write_message(payload, message),
+            NoiseSession::Transport(session) => session.write_message(payload, message),
+        }
+    }
+
+    fn get_remote_static(&self) -> Option<&[u8]> {
+        match self {
+            NoiseSession::Handshake(session) => session.get_remote_static(),
+            NoiseSession::Transport(session) => session.get_remote_static(),
+        }
+    }
+
+    fn is_initiator(&self) -> bool {
+        match self {
+            NoiseSession::Handshake(session) => session.is_initiator(),
+            NoiseSession::Transport(session) => session.is_initiator(),
+        }
+    }
+
+    fn into_transport_mode(self) -> Result<Self, snow::Error> {
+        match self {
+            NoiseSession::Handshake(session) => {
+                session.into_transport_mode().map(NoiseSession::Transport)
+            }
+            transport @ NoiseSession::Transport(_) => Ok(transport),
+        }
+    }
 }
 
 /// A Noise session with a remote
@@ -103,17 +151,17 @@ enum WriteState {
 #[derive(Debug)]
 pub struct NoiseSocket<TSocket> {
     socket: TSocket,
-    session: snow::Session,
+    session: NoiseSession,
     buffers: Box<NoiseBuffers>,
     read_state: ReadState,
     write_state: WriteState,
 }
 
 impl<TSocket> NoiseSocket<TSocket> {
-    fn new(socket: TSocket, session: snow::Session) -> Self {
+    fn new(socket: TSocket, session: snow::HandshakeState) -> Self {
         Self {
             socket,
-            session,
+            session: NoiseSession::Handshake(session),
             buffers: Box::new(NoiseBuffers::new()),
             read_state: ReadState::Init,
             write_state: WriteState::Init,
@@ -503,8 +551,8 @@ where
 pub(super) struct Handshake<TSocket>(NoiseSocket<TSocket>);
 
 impl<TSocket> Handshake<TSocket> {
-    /// Build a new `Handshake` struct given a socket and a new snow Session
-    pub fn new(socket: TSocket, session: snow::Session) -> Self {
+    /// Build a new `Handshake` struct given a socket and a new snow HandshakeState
+    pub fn new(socket: TSocket, session: snow::HandshakeState) -> Self {
         let noise_socket = NoiseSocket::new(socket, session);
         Self(noise_socket)
     }
@@ -581,7 +629,7 @@ mod test {
         io::{AsyncReadExt, AsyncWriteExt},
     };
     use memsocket::MemorySocket;
-    use snow::{params::NoiseParams, Builder, Keypair, SnowError};
+    use snow::{params::NoiseParams, Builder, Error as SnowError, Keypair};
     use std::io;
 
     fn build_test_connection() -> Result<
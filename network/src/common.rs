@@ -25,7 +25,7 @@ impl<TSubstream> fmt::Debug for NegotiatedSubstream<TSubstream> {
 }
 
 /// Public keys used at the network layer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct NetworkPublicKeys {
     /// This key can validate signed messages at the network layer.
     pub signing_public_key: Ed25519PublicKey,
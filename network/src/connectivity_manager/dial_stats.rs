@@ -0,0 +1,79 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outcomes of the dials to each address, recorded by the [`ConnectivityManager`] and used by
+//! Discovery to hand out the addresses of a peer which are the most likely to work first.
+//!
+//! [`ConnectivityManager`]: super::ConnectivityManager
+
+use parity_multiaddr::Multiaddr;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Dial outcomes of a single address.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AddrDialStats {
+    pub successes: u64,
+    pub failures: u64,
+    /// Failures since the last success.
+    pub consecutive_failures: u64,
+}
+
+/// Dial outcomes of every address dialed so far. Clones share the same stats.
+#[derive(Clone, Debug, Default)]
+pub struct DialStats {
+    stats: Arc<RwLock<HashMap<Multiaddr, AddrDialStats>>>,
+}
+
+impl DialStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_success(&self, addr: &Multiaddr) {
+        let mut stats = self.stats.write().unwrap();
+        let addr_stats = stats.entry(addr.clone()).or_default();
+        addr_stats.successes += 1;
+        addr_stats.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, addr: &Multiaddr) {
+        let mut stats = self.stats.write().unwrap();
+        let addr_stats = stats.entry(addr.clone()).or_default();
+        addr_stats.failures += 1;
+        addr_stats.consecutive_failures += 1;
+    }
+
+    /// Sets the stats of `addr`, e.g., to restore the stats persisted before a restart.
+    pub fn insert(&self, addr: Multiaddr, addr_stats: AddrDialStats) {
+        self.stats.write().unwrap().insert(addr, addr_stats);
+    }
+
+    /// Forgets the stats of `addr`, e.g., once no peer advertises it anymore.
+    pub fn remove(&self, addr: &Multiaddr) {
+        self.stats.write().unwrap().remove(addr);
+    }
+
+    pub fn get(&self, addr: &Multiaddr) -> AddrDialStats {
+        self.stats
+            .read()
+            .unwrap()
+            .get(addr)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Orders `addrs` by increasing number of consecutive dial failures. Addresses with as many
+    /// failures keep their relative order, so that the preference of the peer is respected as
+    /// long as its addresses work equally well.
+    pub fn sort_addrs(&self, addrs: &mut [Multiaddr]) {
+        let stats = self.stats.read().unwrap();
+        addrs.sort_by_key(|addr| {
+            stats
+                .get(addr)
+                .map_or(0, |addr_stats| addr_stats.consecutive_failures)
+        });
+    }
+}
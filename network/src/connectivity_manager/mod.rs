@@ -10,32 +10,72 @@
 //! eligible nodes, and the Discovery actor infroms it about updates to addresses of eligible
 //! nodes.
 //!
-//! When dialing a peer with a given list of addresses, we attempt each address
-//! in order with a capped exponential backoff delay until we eventually connect
-//! to the peer.
+//! When dialing a peer with a given list of addresses, we race dials to the addresses with
+//! staggered starts, "happy eyeballs" style: the dial to an address starts once the dial to the
+//! previous one failed, or has been in progress for a while without completing. The first dial to
+//! succeed wins and the others are abandoned. The circuit addresses a peer advertises at its
+//! relays are dialed after its other addresses, so that a peer which cannot be reached directly,
+//! e.g., because it is behind a NAT, is only dialed through its relays last. If no dial succeeds,
+//! the peer is dialed again with a capped exponential backoff delay until we eventually connect
+//! to it. The outcome of the dial to each address is
+//! recorded in [`DialStats`], which Discovery uses to order the addresses it hands out.
+//!
+//! If a [`PeerStore`] is given, the addresses of the peers and the outcomes of the dials to them
+//! are persisted after each dial, and loaded on startup, so that peers can be dialed at the
+//! addresses which worked before a restart without waiting for Discovery. Networks which aren't
+//! permissioned have no ConnectivityManager, and only dial the peers of their [`PeerStore`] once
+//! on startup with [`warm_start`], which spares them waiting for their peers to reconnect.
+//!
+//! Changes to the set of eligible nodes are applied incrementally: only the nodes which were
+//! added, removed or whose keys changed are affected, and connections to all other nodes are left
+//! untouched. A full set of eligible nodes is diffed against the current one before being applied.
+//!
+//! Peers which the HealthChecker finds unresponsive are disconnected, and dialed again like any
+//! other disconnected peer.
+//!
+//! The number of outstanding dials, i.e., dials which are queued or in progress, is bounded by a
+//! global budget, so that a partition from many peers does not result in a dial storm. Peers
+//! which do not fit in the budget are picked at random on a later connectivity check.
+//!
+//! To keep an attacker who controls many addresses from eclipsing the node, the connections we
+//! initiate can be limited by an [`OutboundConnectionsConfig`]: we then only dial out to a number
+//! of peers picked at random, spread across as many IP subnets as possible, and periodically
+//! replace the oldest of these connections with a connection to another peer. Connections the
+//! peers initiate never take the place of the ones we initiate. The same limits apply to the peers
+//! [`warm_start`] dials on networks which aren't permissioned, and these networks can limit the
+//! connections peers initiate from a single subnet as well, see
+//! [`PeerManager`](crate::peer_manager::PeerManager).
+pub use self::dial_stats::{AddrDialStats, DialStats};
 use crate::{
     common::NetworkPublicKeys,
+    counters,
     peer_manager::{PeerManagerError, PeerManagerNotification, PeerManagerRequestSender},
+    peer_store::PeerStore,
+    relay,
 };
 use channel;
+use config::config::OutboundConnectionsConfig;
 use futures::{
     channel::oneshot,
     compat::Future01CompatExt,
-    future::{BoxFuture, FutureExt},
-    stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
+    future::{self, BoxFuture, FutureExt},
+    stream::{self, FusedStream, FuturesUnordered, Stream, StreamExt},
 };
 use logger::prelude::*;
-use parity_multiaddr::Multiaddr;
+use parity_multiaddr::{Multiaddr, Protocol};
+use rand::seq::SliceRandom;
 use std::{
-    cmp::min,
-    collections::HashMap,
+    cmp::{max, min},
+    collections::{HashMap, HashSet},
     fmt::Debug,
+    net::Ipv4Addr,
     sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 use tokio::timer;
 use types::PeerId;
 
+mod dial_stats;
 #[cfg(test)]
 mod test;
 
@@ -47,6 +87,9 @@ pub struct ConnectivityManager<TTicker, TSubstream, TBackoff> {
     connected: HashMap<PeerId, Multiaddr>,
     /// Addresses of peers received from Discovery module.
     peer_addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Connected peers whose keys changed since they connected. They are disconnected on the next
+    /// connectivity check, and dialed again with their new keys.
+    rekeyed: HashSet<PeerId>,
     /// Ticker to trigger connectivity checks to provide the guarantees stated above.
     ticker: TTicker,
     /// Channel to send requests to PeerManager.
@@ -65,6 +108,26 @@ pub struct ConnectivityManager<TTicker, TSubstream, TBackoff> {
     backoff_strategy: TBackoff,
     /// Maximum delay b/w 2 consecutive attempts to connect with a disconnected peer.
     max_delay_ms: u64,
+    /// Maximum number of outstanding dials across all peers.
+    max_concurrent_dials: usize,
+    /// Delay after which the dial to the next address of a peer starts if the dial to the current
+    /// one has not completed yet.
+    dial_stagger_ms: u64,
+    /// Outcomes of the dials to each address.
+    dial_stats: DialStats,
+    /// Store in which the addresses of the peers and the outcomes of the dials to them are
+    /// persisted.
+    peer_store: Option<PeerStore>,
+    /// Limits on the connections we initiate.
+    outbound_config: OutboundConnectionsConfig,
+    /// Peers we connected to by dialing them, with the address we connected at and since when.
+    /// The connection to a peer is only ours as long as the peer is connected at that address.
+    outbound: HashMap<PeerId, (Multiaddr, Instant)>,
+    /// When an outbound connection was last rotated.
+    last_rotation: Instant,
+    /// Peer whose connection was last rotated. It is left out of the next peers to dial, for a
+    /// new peer to take its place.
+    rotated_out: Option<PeerId>,
     /// A local counter incremented on receiving an incoming message. Printing this in debugging
     /// allows for easy debugging.
     event_id: u32,
@@ -75,28 +138,44 @@ pub struct ConnectivityManager<TTicker, TSubstream, TBackoff> {
 pub enum ConnectivityRequest {
     /// Request to update known addresses of peer with id `PeerId` to given list.
     UpdateAddresses(PeerId, Vec<Multiaddr>),
-    /// Update set of nodes eligible to join the network.
+    /// Update set of nodes eligible to join the network. The new set is diffed against the current
+    /// one, and applied as a list of [`PeerSetUpdate`]s.
     UpdateEligibleNodes(HashMap<PeerId, NetworkPublicKeys>),
+    /// Apply incremental changes to the set of eligible nodes and their addresses.
+    UpdatePeerSet(Vec<PeerSetUpdate>),
+    /// Notifies that a connected peer stopped responding to health checks. Its connection is
+    /// closed, and the peer is dialed again on the next connectivity check.
+    PeerUnhealthy(PeerId),
     /// Gets current size of dial queue. This is useful in tests.
     GetDialQueueSize(oneshot::Sender<usize>),
 }
 
+/// An incremental change to the set of eligible nodes.
+#[derive(Clone, Debug)]
+pub enum PeerSetUpdate {
+    /// Makes a node eligible, or updates the keys of an eligible node. A connected node whose
+    /// keys change is reconnected, as its connection was authenticated with the old keys.
+    Add(PeerId, NetworkPublicKeys),
+    /// Makes a node ineligible. Its connection is closed and any pending dial to it is cancelled
+    /// on the next connectivity check.
+    Remove(PeerId),
+    /// Updates the known addresses of a node. The current connection to the node, if any, is
+    /// kept.
+    UpdateAddresses(PeerId, Vec<Multiaddr>),
+}
+
 #[derive(Debug)]
 enum DialResult {
-    Success,
+    Success(Multiaddr),
     Cancelled,
     Failed(PeerManagerError),
 }
 
-/// The state needed to compute the next dial delay and dial addr for a given
-/// peer.
+/// The state needed to compute the next dial delay for a given peer.
 #[derive(Debug, Clone)]
 struct DialState<TBackoff> {
     /// The current state of this peer's backoff delay.
     backoff: TBackoff,
-    /// The index of the next address to dial. Index of an address in the peer's
-    /// `peer_addresses` entry.
-    addr_idx: usize,
 }
 
 impl<TTicker, TSubstream, TBackoff> ConnectivityManager<TTicker, TSubstream, TBackoff>
@@ -114,11 +193,20 @@ where
         requests_rx: channel::Receiver<ConnectivityRequest>,
         backoff_strategy: TBackoff,
         max_delay_ms: u64,
+        max_concurrent_dials: usize,
+        dial_stagger_ms: u64,
+        dial_stats: DialStats,
+        peer_store: Option<PeerStore>,
+        outbound_config: OutboundConnectionsConfig,
     ) -> Self {
+        let peer_addresses = peer_store.as_ref().map_or_else(HashMap::new, |peer_store| {
+            load_peer_addresses(peer_store, &dial_stats)
+        });
         Self {
             eligible,
             connected: HashMap::new(),
-            peer_addresses: HashMap::new(),
+            peer_addresses,
+            rekeyed: HashSet::new(),
             ticker,
             peer_mgr_reqs_tx,
             peer_mgr_notifs_rx,
@@ -127,6 +215,14 @@ where
             dial_states: HashMap::new(),
             backoff_strategy,
             max_delay_ms,
+            max_concurrent_dials,
+            dial_stagger_ms,
+            dial_stats,
+            peer_store,
+            outbound_config,
+            outbound: HashMap::new(),
+            last_rotation: Instant::now(),
+            rotated_out: None,
             event_id: 0,
         }
     }
@@ -149,14 +245,18 @@ where
                 },
                 req = self.requests_rx.select_next_some() => {
                     trace!("Event Id: {}, type: ConnectivityRequest, req: {:?}", self.event_id, req);
-                    self.handle_request(req);
+                    self.handle_request(req).await;
                 },
                 notif = self.peer_mgr_notifs_rx.select_next_some() => {
                     trace!("Event Id: {}, type: PeerManagerNotification, notif: {:?}", self.event_id, notif);
                     self.handle_peer_mgr_notification(notif);
                 },
-                peer_id = pending_dials.select_next_some() => {
+                (peer_id, dialed_addr) = pending_dials.select_next_some() => {
                     trace!("Event Id: {}, type: Dial complete, peer: {}", self.event_id, peer_id.short_str());
+                    self.persist_dial_outcome(peer_id, dialed_addr.as_ref());
+                    if let Some(addr) = dialed_addr {
+                        self.record_outbound(peer_id, addr);
+                    }
                     self.dial_queue.remove(&peer_id);
                 },
                 complete => {
@@ -167,7 +267,7 @@ where
         }
     }
 
-    /// Disconnect from all peers that are no longer eligible.
+    /// Disconnect from all peers that are no longer eligible, or whose keys changed.
     ///
     /// For instance, a validator might leave the validator set after a
     /// reconfiguration. If we are currently connected to this validator, calling
@@ -177,9 +277,10 @@ where
         let stale_connections: Vec<_> = self
             .connected
             .keys()
-            .filter(|peer_id| !eligible.contains_key(peer_id))
+            .filter(|peer_id| !eligible.contains_key(peer_id) || self.rekeyed.contains(peer_id))
             .cloned()
             .collect();
+        self.rekeyed.clear();
         for p in stale_connections.into_iter() {
             info!("Should no longer be connected to peer: {}", p.short_str());
             // Close existing connection.
@@ -213,7 +314,7 @@ where
 
     async fn dial_eligible_peers<'a>(
         &'a mut self,
-        pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, PeerId>>,
+        pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, (PeerId, Option<Multiaddr>)>>,
     ) {
         let eligible = self.eligible.read().unwrap().clone();
         let to_connect: Vec<_> = self
@@ -224,6 +325,7 @@ where
                     && self.connected.get(peer_id).is_none() // The node is not already connected.
                     && self.dial_queue.get(peer_id).is_none() // There is no pending dial to this node.
                     && !addrs.is_empty() // There is an address to dial.
+                    && self.rotated_out.as_ref() != Some(peer_id) // The node was not just rotated out.
             })
             .collect();
 
@@ -240,22 +342,45 @@ where
                             .count() as f64))) as u64,
         );
 
-        // The initial dial state; it has zero dial delay and uses the first
-        // address.
+        // Only dial as many peers as the limits on outbound connections allow.
+        let mut to_connect = self.select_outbound(to_connect);
+        if !to_connect.is_empty() {
+            self.rotated_out = None;
+        }
+
+        // Only queue as many dials as the budget allows. The peers to dial are picked at random so
+        // that peers which are always unreachable cannot starve the others.
+        let budget = self
+            .max_concurrent_dials
+            .saturating_sub(self.dial_queue.len());
+        if to_connect.len() > budget {
+            info!(
+                "Deferring dials to {} peers; outstanding dial budget exhausted",
+                to_connect.len() - budget
+            );
+            counters::DIALS_DEFERRED.inc_by((to_connect.len() - budget) as i64);
+            to_connect.shuffle(&mut rand::thread_rng());
+            to_connect.truncate(budget);
+        }
+
+        // The initial dial state; it has zero dial delay.
         let init_dial_state = DialState::new(self.backoff_strategy.clone());
 
         for (p, addrs) in to_connect.into_iter() {
-            let mut peer_mgr_reqs_tx = self.peer_mgr_reqs_tx.clone();
+            let peer_mgr_reqs_tx = self.peer_mgr_reqs_tx.clone();
             let peer_id = *p;
             let dial_state = self
                 .dial_states
                 .entry(peer_id)
                 .or_insert_with(|| init_dial_state.clone());
 
-            // Choose the next addr to dial for this peer. Currently, we just
-            // round-robin the selection, i.e., try the sequence:
-            // addr[0], .., addr[len-1], addr[0], ..
-            let addr = dial_state.next_addr(&addrs).clone();
+            // The peer's circuit addresses are moved after its other addresses, so relays are
+            // only used once all direct dials have failed.
+            let (circuit_addrs, direct_addrs): (Vec<_>, Vec<_>) = addrs
+                .iter()
+                .cloned()
+                .partition(|addr| relay::parse_circuit_addr(addr).is_some());
+            let addrs: Vec<_> = direct_addrs.into_iter().chain(circuit_addrs).collect();
 
             // Using the DialState's backoff strategy, compute the delay until
             // the next dial attempt for this peer.
@@ -267,76 +392,195 @@ where
             let (cancel_tx, cancel_rx) = oneshot::channel();
 
             info!(
-                "Create dial future: peer: {}, at address: {}, after delay: {:?}",
+                "Create dial future: peer: {}, at addresses: {:?}, after delay: {:?}",
                 peer_id.short_str(),
-                addr,
+                addrs,
                 dial_delay,
             );
 
+            let dial_stagger = Duration::from_millis(self.dial_stagger_ms);
+            let dial_stats = self.dial_stats.clone();
             // Create future which completes by either dialing after calculated
             // delay or on cancellation.
             let f = async move {
                 info!(
-                    "Dial future: dialing peer: {}, at address: {}, after delay: {:?}",
+                    "Dial future: dialing peer: {}, after delay: {:?}",
                     peer_id.short_str(),
-                    addr,
                     f_delay.deadline().duration_since(now)
                 );
                 // We dial after a delay. The dial can be cancelled by sending to or dropping
                 // `cancel_rx`.
                 let dial_result = ::futures::select! {
                     _ = f_delay.compat().fuse() => {
-                        info!("Dialing peer: {}, at addr: {}", peer_id.short_str(), addr);
-                        match peer_mgr_reqs_tx.dial_peer(peer_id, addr.clone()).await {
-                            Ok(_) => DialResult::Success,
-                            Err(e) => DialResult::Failed(e),
-                        }
+                        dial_addrs(peer_mgr_reqs_tx, peer_id, addrs, dial_stagger, dial_stats).await
                     },
                     _ = cancel_rx.fuse() => {
                         DialResult::Cancelled
                     },
                 };
-                log_dial_result(peer_id, addr, dial_result);
-                // Send peer_id as future result so it can be removed from dial queue.
-                peer_id
+                let dialed_addr = match &dial_result {
+                    DialResult::Success(addr) => Some(addr.clone()),
+                    _ => None,
+                };
+                log_dial_result(peer_id, dial_result);
+                // Send peer_id as future result so it can be removed from dial queue, along with
+                // the address we connected at, if any.
+                (peer_id, dialed_addr)
             };
             pending_dials.push(f.boxed());
             self.dial_queue.insert(peer_id, cancel_tx);
         }
     }
 
+    /// Records that we connected to `peer_id` at `addr` by dialing it. PeerManager answers the
+    /// dial before notifying us of the new connection, so the connection is still ours if we
+    /// have yet to hear of it, i.e., the dial is still queued.
+    fn record_outbound(&mut self, peer_id: PeerId, addr: Multiaddr) {
+        let is_ours = match self.connected.get(&peer_id) {
+            Some(connected_addr) => *connected_addr == addr,
+            None => self.dial_queue.contains_key(&peer_id),
+        };
+        if is_ours {
+            self.outbound.insert(peer_id, (addr, Instant::now()));
+        }
+    }
+
+    /// Picks, among the `candidates` peers to dial, those that the limits on outbound connections
+    /// leave room for. The candidates are picked at random, preferring peers in subnets which we
+    /// do not dial out to yet: an attacker with many addresses in few subnets can then only take
+    /// a few of our outbound connections.
+    fn select_outbound<'a>(
+        &self,
+        mut candidates: Vec<(&'a PeerId, &'a Vec<Multiaddr>)>,
+    ) -> Vec<(&'a PeerId, &'a Vec<Multiaddr>)> {
+        // Count the peers we dial out to, or are about to, in each subnet.
+        let mut subnet_counts: HashMap<Subnet, usize> = HashMap::new();
+        let outbound_addrs = self.outbound.values().map(|(addr, _)| addr);
+        let queued_addrs = self
+            .dial_queue
+            .keys()
+            .filter_map(|peer_id| self.peer_addresses.get(peer_id))
+            .filter_map(|addrs| addrs.first());
+        for addr_subnet in outbound_addrs.chain(queued_addrs).filter_map(subnet) {
+            *subnet_counts.entry(addr_subnet).or_insert(0) += 1;
+        }
+
+        candidates.shuffle(&mut rand::thread_rng());
+        select_outbound_peers(
+            &self.outbound_config,
+            self.outbound.len() + self.dial_queue.len(),
+            subnet_counts,
+            candidates,
+            |(_, addrs)| addrs.first().and_then(subnet),
+        )
+    }
+
+    /// Closes the oldest outbound connection once per rotation interval, so that the peers we
+    /// dial out to change over time, and an attacker who got hold of them does not keep them. The
+    /// connection is only closed if we have as many outbound connections as allowed and there is
+    /// another peer to take its place.
+    async fn rotate_outbound_connection(&mut self) {
+        let (max_outbound, rotation_interval) = match (
+            self.outbound_config.max_outbound,
+            self.outbound_config.rotation_interval_ms,
+        ) {
+            (Some(max_outbound), Some(rotation_interval_ms)) => {
+                (max_outbound, Duration::from_millis(rotation_interval_ms))
+            }
+            _ => return,
+        };
+        // Connections are rotated one at a time: the last one must have been replaced first.
+        if self.rotated_out.is_some()
+            || self.last_rotation.elapsed() < rotation_interval
+            || self.outbound.len() < max_outbound
+        {
+            return;
+        }
+        let eligible = self.eligible.read().unwrap().clone();
+        let has_replacement = self.peer_addresses.iter().any(|(peer_id, addrs)| {
+            eligible.contains_key(peer_id)
+                && !self.connected.contains_key(peer_id)
+                && !self.dial_queue.contains_key(peer_id)
+                && !addrs.is_empty()
+        });
+        if !has_replacement {
+            return;
+        }
+        let oldest = self
+            .outbound
+            .iter()
+            .filter(|(peer_id, (addr, _))| self.connected.get(peer_id) == Some(addr))
+            .min_by_key(|(_, (_, since))| *since)
+            .map(|(peer_id, _)| *peer_id);
+        if let Some(peer_id) = oldest {
+            info!(
+                "Rotating outbound connection to peer: {}",
+                peer_id.short_str()
+            );
+            self.last_rotation = Instant::now();
+            self.rotated_out = Some(peer_id);
+            counters::OUTBOUND_CONNECTIONS_ROTATED.inc();
+            if let Err(e) = self.peer_mgr_reqs_tx.disconnect_peer(peer_id).await {
+                info!(
+                    "Failed to disconnect from peer: {}. Error: {:?}",
+                    peer_id.short_str(),
+                    e
+                );
+            }
+        }
+    }
+
     // Note: We do not check that the connections to older incarnations of a node are broken, and
     // instead rely on the node moving to a new epoch to break connections made from older
     // incarnations.
     async fn check_connectivity<'a>(
         &'a mut self,
-        pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, PeerId>>,
+        pending_dials: &'a mut FuturesUnordered<BoxFuture<'static, (PeerId, Option<Multiaddr>)>>,
     ) {
         // Cancel dials to peers that are no longer eligible.
         self.cancel_stale_dials().await;
         // Disconnect from connected peers that are no longer eligible.
         self.close_stale_connections().await;
+        // Make room for a connection to another peer, if it is time to.
+        self.rotate_outbound_connection().await;
         // Dial peers which are eligible but are neither connected nor queued for dialing in the
         // future.
         self.dial_eligible_peers(pending_dials).await;
     }
 
-    fn handle_request(&mut self, req: ConnectivityRequest) {
+    async fn handle_request(&mut self, req: ConnectivityRequest) {
         match req {
             ConnectivityRequest::UpdateAddresses(peer_id, addrs) => {
-                trace!(
-                    "Received updated addresses for peer: {}",
-                    peer_id.short_str()
-                );
-                self.peer_addresses.insert(peer_id, addrs);
-                // Ensure that the next dial attempt starts from the first addr.
-                if let Some(dial_state) = self.dial_states.get_mut(&peer_id) {
-                    dial_state.reset_addr();
-                }
+                self.update_peer_set(PeerSetUpdate::UpdateAddresses(peer_id, addrs));
             }
             ConnectivityRequest::UpdateEligibleNodes(nodes) => {
                 trace!("Received updated list of eligible nodes",);
-                *self.eligible.write().unwrap() = nodes;
+                let updates = diff_eligible(&self.eligible.read().unwrap(), nodes);
+                for update in updates {
+                    self.update_peer_set(update);
+                }
+            }
+            ConnectivityRequest::UpdatePeerSet(updates) => {
+                trace!("Received {} updates to the peer set", updates.len());
+                for update in updates {
+                    self.update_peer_set(update);
+                }
+            }
+            ConnectivityRequest::PeerUnhealthy(peer_id) => {
+                if !self.connected.contains_key(&peer_id) {
+                    return;
+                }
+                info!(
+                    "Cycling connection to unhealthy peer: {}",
+                    peer_id.short_str()
+                );
+                if let Err(e) = self.peer_mgr_reqs_tx.disconnect_peer(peer_id).await {
+                    info!(
+                        "Failed to disconnect from peer: {}. Error: {:?}",
+                        peer_id.short_str(),
+                        e
+                    );
+                }
             }
             ConnectivityRequest::GetDialQueueSize(sender) => {
                 sender.send(self.dial_queue.len()).unwrap();
@@ -344,19 +588,123 @@ where
         }
     }
 
+    fn update_peer_set(&mut self, update: PeerSetUpdate) {
+        match update {
+            PeerSetUpdate::Add(peer_id, keys) => {
+                let prev_keys = self.eligible.write().unwrap().insert(peer_id, keys.clone());
+                match prev_keys {
+                    Some(ref prev_keys) if *prev_keys == keys => {}
+                    Some(_) => {
+                        info!("Keys of peer: {} changed", peer_id.short_str());
+                        if self.connected.contains_key(&peer_id) {
+                            self.rekeyed.insert(peer_id);
+                        }
+                    }
+                    None => {
+                        info!("Peer: {} is now eligible", peer_id.short_str());
+                    }
+                }
+            }
+            PeerSetUpdate::Remove(peer_id) => {
+                if self.eligible.write().unwrap().remove(&peer_id).is_some() {
+                    info!("Peer: {} is no longer eligible", peer_id.short_str());
+                    if let Some(peer_store) = &self.peer_store {
+                        peer_store.remove(&peer_id);
+                    }
+                    if let Some(addrs) = self.peer_addresses.get(&peer_id) {
+                        self.forget_dial_stats(peer_id, addrs);
+                    }
+                }
+                self.rekeyed.remove(&peer_id);
+            }
+            PeerSetUpdate::UpdateAddresses(peer_id, addrs) => {
+                trace!(
+                    "Received updated addresses for peer: {}",
+                    peer_id.short_str()
+                );
+                if self.peer_addresses.get(&peer_id) == Some(&addrs) {
+                    return;
+                }
+                if let Some(prev_addrs) = self.peer_addresses.insert(peer_id, addrs.clone()) {
+                    let dropped_addrs: Vec<_> = prev_addrs
+                        .into_iter()
+                        .filter(|addr| !addrs.contains(addr))
+                        .collect();
+                    self.forget_dial_stats(peer_id, &dropped_addrs);
+                }
+            }
+        }
+    }
+
+    /// Forgets the dial stats of `addrs`, addresses of `peer_id` which are gone, unless another
+    /// eligible peer advertises them, so that the stats don't grow with every address ever dialed.
+    fn forget_dial_stats(&self, peer_id: PeerId, addrs: &[Multiaddr]) {
+        let eligible = self.eligible.read().unwrap();
+        for addr in addrs {
+            let is_advertised = self
+                .peer_addresses
+                .iter()
+                .any(|(other_peer_id, other_addrs)| {
+                    *other_peer_id != peer_id
+                        && eligible.contains_key(other_peer_id)
+                        && other_addrs.contains(addr)
+                });
+            if !is_advertised {
+                self.dial_stats.remove(addr);
+            }
+        }
+    }
+
+    /// Persists the known addresses of `peer_id` and the outcomes of the dials to them, along with
+    /// the address we connected at, if the dial succeeded.
+    fn persist_dial_outcome(&self, peer_id: PeerId, dialed_addr: Option<&Multiaddr>) {
+        let peer_store = match &self.peer_store {
+            Some(peer_store) => peer_store,
+            None => return,
+        };
+        let addrs = self
+            .peer_addresses
+            .get(&peer_id)
+            .cloned()
+            .unwrap_or_default();
+        let dial_stats = &self.dial_stats;
+        peer_store.update(peer_id, |record| {
+            record.dial_stats = addrs
+                .iter()
+                .map(|addr| (addr.clone(), dial_stats.get(addr)))
+                .collect();
+            record.addrs = addrs;
+            if let Some(addr) = dialed_addr {
+                record.last_connected_addr = Some(addr.clone());
+            }
+        });
+    }
+
     fn handle_peer_mgr_notification(&mut self, notif: PeerManagerNotification<TSubstream>) {
         match notif {
             PeerManagerNotification::NewPeer(peer_id, addr) => {
+                // A connection at another address than the one we dialed is not ours.
+                if self
+                    .outbound
+                    .get(&peer_id)
+                    .map_or(false, |(outbound_addr, _)| *outbound_addr != addr)
+                {
+                    self.outbound.remove(&peer_id);
+                }
                 self.connected.insert(peer_id, addr);
                 // Cancel possible queued dial to this peer.
                 self.dial_states.remove(&peer_id);
                 self.dial_queue.remove(&peer_id);
             }
+            PeerManagerNotification::PeerAddressChanged(peer_id, addr) => {
+                self.connected.insert(peer_id, addr);
+            }
             PeerManagerNotification::LostPeer(peer_id, addr) => {
                 match self.connected.get(&peer_id) {
                     Some(curr_addr) if *curr_addr == addr => {
                         // Remove node from connected peers list.
                         self.connected.remove(&peer_id);
+                        self.outbound.remove(&peer_id);
                     }
                     _ => {
                         debug!(
@@ -374,9 +722,278 @@ where
     }
 }
 
-fn log_dial_result(peer_id: PeerId, addr: Multiaddr, dial_result: DialResult) {
+/// Picks, in order, the `candidates` peers to dial that the limits of `config` leave room for,
+/// given the `num_outbound` peers we already dial out to and their number in each subnet. Peers in
+/// subnets which have their share of our outbound connections are only picked to reach
+/// `config.min_outbound`.
+fn select_outbound_peers<C>(
+    config: &OutboundConnectionsConfig,
+    mut num_outbound: usize,
+    mut subnet_counts: HashMap<Subnet, usize>,
+    candidates: Vec<C>,
+    candidate_subnet: impl Fn(&C) -> Option<Subnet>,
+) -> Vec<C> {
+    let max_outbound = config.max_outbound.unwrap_or_else(usize::max_value);
+    let max_per_subnet = config
+        .max_outbound_per_subnet
+        .unwrap_or_else(usize::max_value);
+    if max_outbound == usize::max_value() && max_per_subnet == usize::max_value() {
+        return candidates;
+    }
+
+    let mut selected = vec![];
+    let mut crowded = vec![];
+    for candidate in candidates {
+        if num_outbound >= max_outbound {
+            break;
+        }
+        if let Some(candidate_subnet) = candidate_subnet(&candidate) {
+            let count = subnet_counts.entry(candidate_subnet).or_insert(0);
+            if *count >= max_per_subnet {
+                crowded.push(candidate);
+                continue;
+            }
+            *count += 1;
+        }
+        num_outbound += 1;
+        selected.push(candidate);
+    }
+    // Peers in crowded subnets are only dialed when there are not enough others to reach the
+    // minimum number of outbound connections.
+    let min_outbound = min(config.min_outbound, max_outbound);
+    for candidate in crowded {
+        if num_outbound >= min_outbound {
+            counters::DIALS_SKIPPED_FOR_DIVERSITY.inc();
+            continue;
+        }
+        num_outbound += 1;
+        selected.push(candidate);
+    }
+    selected
+}
+
+/// An IP subnet, which stands for a network operator: the peers of a subnet are likely to be under
+/// the same control.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Subnet {
+    /// The /24 of an IPv4 address.
+    V4([u8; 3]),
+    /// The /48 of an IPv6 address.
+    V6([u16; 3]),
+}
+
+/// The subnet of `addr`, if it starts with an IP address.
+pub(crate) fn subnet(addr: &Multiaddr) -> Option<Subnet> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(ipv4_subnet(ip)),
+        Protocol::Ip6(ip) => {
+            let segments = ip.segments();
+            // IPv4-mapped addresses are in the subnet of their IPv4 address.
+            if segments[..6] == [0, 0, 0, 0, 0, 0xffff] {
+                ip.to_ipv4().map(ipv4_subnet)
+            } else {
+                Some(Subnet::V6([segments[0], segments[1], segments[2]]))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn ipv4_subnet(ip: Ipv4Addr) -> Subnet {
+    let octets = ip.octets();
+    Subnet::V4([octets[0], octets[1], octets[2]])
+}
+
+/// Computes the updates which turn the `current` set of eligible nodes into the `new` one. Nodes
+/// present in both sets with the same keys are left out.
+fn diff_eligible(
+    current: &HashMap<PeerId, NetworkPublicKeys>,
+    mut new: HashMap<PeerId, NetworkPublicKeys>,
+) -> Vec<PeerSetUpdate> {
+    let mut updates: Vec<_> = current
+        .keys()
+        .filter(|peer_id| !new.contains_key(peer_id))
+        .map(|peer_id| PeerSetUpdate::Remove(*peer_id))
+        .collect();
+    new.retain(|peer_id, keys| current.get(peer_id) != Some(keys));
+    updates.extend(
+        new.into_iter()
+            .map(|(peer_id, keys)| PeerSetUpdate::Add(peer_id, keys)),
+    );
+    updates
+}
+
+/// Loads the addresses of the peers persisted in `peer_store`, and restores the outcomes of the
+/// dials to them into `dial_stats`.
+fn load_peer_addresses(
+    peer_store: &PeerStore,
+    dial_stats: &DialStats,
+) -> HashMap<PeerId, Vec<Multiaddr>> {
+    let records = peer_store.get_all();
+    info!("Loaded the records of {} peers", records.len());
+    records
+        .into_iter()
+        .filter_map(|(peer_id, record)| {
+            for (addr, addr_stats) in &record.dial_stats {
+                dial_stats.insert(addr.clone(), *addr_stats);
+            }
+            let addrs = record.dial_addrs();
+            if addrs.is_empty() {
+                None
+            } else {
+                Some((peer_id, addrs))
+            }
+        })
+        .collect()
+}
+
+/// Dials the peers recorded in `peer_store` once, the peers with the lowest round trip time first,
+/// with at most `max_concurrent_dials` dials in progress at a time. Only the peers which the limits
+/// of `outbound_config` leave room for are dialed, so that a peer store filled by an attacker
+/// cannot eclipse the node. The addresses the peers are connected at, and the outcomes of the
+/// dials to them, are persisted in `peer_store`.
+pub async fn warm_start<TSubstream>(
+    peer_store: PeerStore,
+    peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
+    max_concurrent_dials: usize,
+    stagger: Duration,
+    outbound_config: OutboundConnectionsConfig,
+) where
+    TSubstream: Debug + Send + 'static,
+{
+    let dial_stats = DialStats::new();
+    let mut records: Vec<_> = peer_store
+        .get_all()
+        .into_iter()
+        .filter(|(_, record)| !record.dial_addrs().is_empty())
+        .collect();
+    // the peers never pinged go last
+    records.sort_by_key(|(_, record)| (record.smoothed_rtt.is_none(), record.smoothed_rtt));
+    let records = select_outbound_peers(
+        &outbound_config,
+        0, /* num_outbound */
+        HashMap::new(),
+        records,
+        |(_, record)| record.dial_addrs().first().and_then(subnet),
+    );
+    info!("Warm start dialing {} known peers", records.len());
+    let dials = records.into_iter().map({
+        let dial_stats = dial_stats.clone();
+        move |(peer_id, record)| {
+            for (addr, addr_stats) in &record.dial_stats {
+                dial_stats.insert(addr.clone(), *addr_stats);
+            }
+            let mut addrs = record.dial_addrs();
+            dial_stats.sort_addrs(&mut addrs);
+            dial_addrs(
+                peer_mgr_reqs_tx.clone(),
+                peer_id,
+                addrs,
+                stagger,
+                dial_stats.clone(),
+            )
+            .map(move |dial_result| (peer_id, dial_result))
+        }
+    });
+    stream::iter(dials)
+        .buffer_unordered(max(max_concurrent_dials, 1))
+        .for_each(|(peer_id, dial_result)| {
+            // The records of the peers which can't be reached anymore are left to expire.
+            if let DialResult::Success(addr) = &dial_result {
+                peer_store.update(peer_id, |record| {
+                    record.dial_stats = record
+                        .addrs
+                        .iter()
+                        .map(|addr| (addr.clone(), dial_stats.get(addr)))
+                        .collect();
+                    record.last_connected_addr = Some(addr.clone());
+                });
+            }
+            log_dial_result(peer_id, dial_result);
+            future::ready(())
+        })
+        .await;
+}
+
+/// Dials `peer_id` at each of `addrs` in turn. The dial to an address starts once the dial to the
+/// previous one failed, or has been in progress for `stagger`. The result of the first dial to
+/// succeed is returned, and the dials still in progress are abandoned, which makes PeerManager
+/// drop the connections they may still establish. If every dial fails, the last error is
+/// returned.
+async fn dial_addrs<TSubstream>(
+    peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
+    peer_id: PeerId,
+    addrs: Vec<Multiaddr>,
+    stagger: Duration,
+    dial_stats: DialStats,
+) -> DialResult
+where
+    TSubstream: Debug + Send + 'static,
+{
+    let mut remaining_addrs = addrs.into_iter().peekable();
+    let mut pending_dials = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        if let Some(addr) = remaining_addrs.next() {
+            info!("Dialing peer: {}, at addr: {}", peer_id.short_str(), addr);
+            let mut peer_mgr_reqs_tx = peer_mgr_reqs_tx.clone();
+            pending_dials.push(
+                async move {
+                    let result = peer_mgr_reqs_tx.dial_peer(peer_id, addr.clone()).await;
+                    (addr, result)
+                }
+                    .boxed(),
+            );
+        }
+        if pending_dials.is_empty() {
+            return DialResult::Failed(
+                last_error.expect("A peer to dial has at least one address"),
+            );
+        }
+        // Wait for a dial to complete, or for the stagger delay to start the next one.
+        let mut f_stagger = if remaining_addrs.peek().is_some() {
+            timer::Delay::new(Instant::now() + stagger)
+                .compat()
+                .map(|_| ())
+                .boxed()
+                .fuse()
+        } else {
+            future::pending().boxed().fuse()
+        };
+        ::futures::select! {
+            (addr, result) = pending_dials.select_next_some() => match result {
+                Ok(()) => {
+                    dial_stats.record_success(&addr);
+                    return DialResult::Success(addr);
+                }
+                // Only failures to reach the address are worth trying the next one for.
+                Err(e @ PeerManagerError::IoError(_))
+                | Err(e @ PeerManagerError::TransportError(_)) => {
+                    info!(
+                        "Failed to connect to peer: {} at address: {}; error: {}",
+                        peer_id.short_str(),
+                        addr,
+                        e
+                    );
+                    dial_stats.record_failure(&addr);
+                    counters::DIAL_ADDR_FAILURES.inc();
+                    last_error = Some(e);
+                }
+                Err(e) => return DialResult::Failed(e),
+            },
+            _ = f_stagger => {
+                debug!(
+                    "Dial to peer: {} is slow, dialing the next address",
+                    peer_id.short_str()
+                );
+            },
+        }
+    }
+}
+
+fn log_dial_result(peer_id: PeerId, dial_result: DialResult) {
     match dial_result {
-        DialResult::Success => {
+        DialResult::Success(addr) => {
             info!(
                 "Successfully connected to peer: {} at address: {}",
                 peer_id.short_str(),
@@ -396,9 +1013,8 @@ fn log_dial_result(peer_id: PeerId, addr: Multiaddr, dial_result: DialResult) {
             }
             e => {
                 info!(
-                    "Failed to connect to peer: {} at address: {}; error: {}",
+                    "Failed to connect to peer: {}; error: {}",
                     peer_id.short_str(),
-                    addr,
                     e
                 );
             }
@@ -411,21 +1027,7 @@ where
     TBackoff: Iterator<Item = Duration> + Clone,
 {
     fn new(backoff: TBackoff) -> Self {
-        Self {
-            backoff,
-            addr_idx: 0,
-        }
-    }
-
-    fn reset_addr(&mut self) {
-        self.addr_idx = 0;
-    }
-
-    fn next_addr<'a>(&mut self, addrs: &'a [Multiaddr]) -> &'a Multiaddr {
-        let addr_idx = self.addr_idx;
-        self.addr_idx = self.addr_idx.wrapping_add(1);
-
-        &addrs[addr_idx % addrs.len()]
+        Self { backoff }
     }
 
     fn next_backoff_delay(&mut self, max_delay: Duration) -> Duration {
@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use crate::peer_manager::PeerManagerRequest;
+use crate::{
+    peer_manager::PeerManagerRequest, validator_network::network_builder::MAX_CONCURRENT_DIALS,
+};
 use core::str::FromStr;
 use crypto::{ed25519::compat, test_utils::TEST_SEED, x25519};
 use futures::{FutureExt, SinkExt, TryFutureExt};
@@ -11,6 +13,11 @@ use rand::{rngs::StdRng, SeedableRng};
 use std::io;
 use tokio::runtime::Runtime;
 use tokio_retry::strategy::FixedInterval;
+use tools::tempdir::TempPath;
+
+// Long enough for the next address of a peer to only be dialed once the dial to the previous one
+// fails, which keeps the order of the dial requests deterministic.
+const TEST_DIAL_STAGGER_MS: u64 = 60_000;
 
 fn setup_conn_mgr(
     rt: &mut Runtime,
@@ -20,6 +27,31 @@ fn setup_conn_mgr(
     channel::Sender<PeerManagerNotification<MemorySocket>>,
     channel::Sender<ConnectivityRequest>,
     channel::Sender<()>,
+) {
+    setup_conn_mgr_with_options(
+        rt,
+        seed_peer_id,
+        MAX_CONCURRENT_DIALS,
+        TEST_DIAL_STAGGER_MS,
+        OutboundConnectionsConfig::default(),
+        None,
+        DialStats::new(),
+    )
+}
+
+fn setup_conn_mgr_with_options(
+    rt: &mut Runtime,
+    seed_peer_id: PeerId,
+    max_concurrent_dials: usize,
+    dial_stagger_ms: u64,
+    outbound_config: OutboundConnectionsConfig,
+    peer_store: Option<PeerStore>,
+    dial_stats: DialStats,
+) -> (
+    channel::Receiver<PeerManagerRequest<MemorySocket>>,
+    channel::Sender<PeerManagerNotification<MemorySocket>>,
+    channel::Sender<ConnectivityRequest>,
+    channel::Sender<()>,
 ) {
     let (peer_mgr_reqs_tx, peer_mgr_reqs_rx): (
         channel::Sender<PeerManagerRequest<MemorySocket>>,
@@ -50,6 +82,11 @@ fn setup_conn_mgr(
             conn_mgr_reqs_rx,
             FixedInterval::from_millis(100),
             300, /* ms */
+            max_concurrent_dials,
+            dial_stagger_ms,
+            dial_stats,
+            peer_store,
+            outbound_config,
         )
     };
     rt.spawn(conn_mgr.start().boxed().unit_error().compat());
@@ -84,6 +121,20 @@ async fn get_dial_queue_size(conn_mgr_reqs_tx: &mut channel::Sender<Connectivity
     queue_size_rx.await.unwrap()
 }
 
+// Triggers connectivity checks until a dial is queued. This is needed after a disconnect, since
+// a tick may be handled before the notification of the lost peer.
+async fn tick_until_dial_queued(
+    ticker_tx: &mut channel::Sender<()>,
+    conn_mgr_reqs_tx: &mut channel::Sender<ConnectivityRequest>,
+) {
+    loop {
+        ticker_tx.send(()).await.unwrap();
+        if get_dial_queue_size(conn_mgr_reqs_tx).await > 0 {
+            break;
+        }
+    }
+}
+
 async fn expect_disconnect_request<'a, TSubstream>(
     peer_mgr_reqs_rx: &'a mut channel::Receiver<PeerManagerRequest<TSubstream>>,
     peer_mgr_notifs_tx: &'a mut channel::Sender<PeerManagerNotification<TSubstream>>,
@@ -111,10 +162,11 @@ async fn expect_disconnect_request<'a, TSubstream>(
     }
 }
 
-async fn expect_dial_request<'a, TSubstream>(
+// Answers the next dial request with `result`, without waiting for the dial to complete. Used for
+// the addresses of a peer which are followed by others in the same dial.
+async fn answer_dial_request<'a, TSubstream>(
     peer_mgr_reqs_rx: &'a mut channel::Receiver<PeerManagerRequest<TSubstream>>,
     peer_mgr_notifs_tx: &'a mut channel::Sender<PeerManagerNotification<TSubstream>>,
-    conn_mgr_reqs_tx: &'a mut channel::Sender<ConnectivityRequest>,
     peer_id: PeerId,
     address: Multiaddr,
     result: Result<(), PeerManagerError>,
@@ -142,6 +194,26 @@ async fn expect_dial_request<'a, TSubstream>(
             .await
             .unwrap();
     }
+}
+
+async fn expect_dial_request<'a, TSubstream>(
+    peer_mgr_reqs_rx: &'a mut channel::Receiver<PeerManagerRequest<TSubstream>>,
+    peer_mgr_notifs_tx: &'a mut channel::Sender<PeerManagerNotification<TSubstream>>,
+    conn_mgr_reqs_tx: &'a mut channel::Sender<ConnectivityRequest>,
+    peer_id: PeerId,
+    address: Multiaddr,
+    result: Result<(), PeerManagerError>,
+) where
+    TSubstream: Debug,
+{
+    answer_dial_request(
+        peer_mgr_reqs_rx,
+        peer_mgr_notifs_tx,
+        peer_id,
+        address,
+        result,
+    )
+    .await;
 
     // Wait for dial queue to be empty. Without this, it's impossible to guarantee that a completed
     // dial is removed from a dial queue. We need this guarantee to see the effects of future
@@ -326,6 +398,68 @@ fn lost_connection() {
         .unwrap();
 }
 
+#[test]
+fn unhealthy_peer() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, seed_peer_id);
+
+    // Fake peer manager, discovery and health checker.
+    let f_peer_mgr = async move {
+        let seed_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_address.clone()],
+            ))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_address.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // The health checker reports the seed peer as unhealthy.
+        info!("Sending PeerUnhealthy request for seed peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::PeerUnhealthy(seed_peer_id))
+            .await
+            .unwrap();
+
+        // Peer manager receives a request to disconnect from the seed peer.
+        expect_disconnect_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            seed_peer_id,
+            seed_address.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // The seed peer is dialed again on the next connectivity check.
+        tick_until_dial_queued(&mut ticker_tx, &mut conn_mgr_reqs_tx).await;
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_address,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
 #[test]
 fn disconnect() {
     ::logger::try_init_for_testing();
@@ -489,6 +623,175 @@ fn retry_on_failure() {
     rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
 }
 
+#[test]
+// Tests that peers are dialed at the addresses persisted in the peer store, without waiting for
+// their addresses, and that the outcomes of the dials are persisted.
+fn warm_start_from_peer_store() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let seed_addr_1 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+    let seed_addr_2 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+    let tmp_dir = TempPath::new();
+    let peer_store = PeerStore::new(&tmp_dir);
+    peer_store.update(seed_peer_id, |record| {
+        record.addrs = vec![seed_addr_1.clone(), seed_addr_2.clone()];
+        record.last_connected_addr = Some(seed_addr_2.clone());
+    });
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig::default(),
+            Some(peer_store.clone()),
+            DialStats::new(),
+        );
+
+    let events_f = async move {
+        // The peer is first dialed at the address we last connected to it at.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr_2.clone(),
+            Ok(()),
+        )
+        .await;
+
+        let record = peer_store.get(&seed_peer_id).unwrap();
+        assert_eq!(record.addrs, vec![seed_addr_2.clone(), seed_addr_1.clone()]);
+        assert_eq!(record.last_connected_addr, Some(seed_addr_2.clone()));
+        assert_eq!(record.dial_stats[&seed_addr_2].successes, 1);
+        assert_eq!(record.dial_stats[&seed_addr_1], AddrDialStats::default());
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
+#[test]
+// Tests that the networks without a connectivity manager dial the peers of the peer store once,
+// the fastest peers first, up to the limit of outbound connections.
+fn warm_start_without_conn_mgr() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let tmp_dir = TempPath::new();
+    let peer_store = PeerStore::new(&tmp_dir);
+    let slow_peer_id = PeerId::random();
+    let fast_peer_id = PeerId::random();
+    let unpinged_peer_id = PeerId::random();
+    let fast_addr = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+    for (peer_id, port, rtt_ms) in &[
+        (slow_peer_id, 9090, Some(100)),
+        (fast_peer_id, 9091, Some(10)),
+        (unpinged_peer_id, 9092, None),
+    ] {
+        peer_store.update(*peer_id, |record| {
+            record.addrs =
+                vec![Multiaddr::from_str(&format!("/ip4/127.0.0.1/tcp/{}", port)).unwrap()];
+            record.smoothed_rtt = rtt_ms.map(Duration::from_millis);
+        });
+    }
+    let (peer_mgr_reqs_tx, mut peer_mgr_reqs_rx): (
+        channel::Sender<PeerManagerRequest<MemorySocket>>,
+        _,
+    ) = channel::new_test(0);
+    let warm_start_f = warm_start(
+        peer_store.clone(),
+        PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+        1,
+        Duration::from_millis(TEST_DIAL_STAGGER_MS),
+        OutboundConnectionsConfig {
+            max_outbound: Some(2),
+            ..OutboundConnectionsConfig::default()
+        },
+    );
+    rt.spawn(warm_start_f.boxed().unit_error().compat());
+
+    let events_f = async move {
+        let mut dialed = vec![];
+        for result in vec![Ok(()), Err(PeerManagerError::NotConnected(slow_peer_id))] {
+            match peer_mgr_reqs_rx.next().await.unwrap() {
+                PeerManagerRequest::DialPeer(peer_id, addr, error_tx) => {
+                    error_tx.send(result).unwrap();
+                    dialed.push((peer_id, addr));
+                }
+                _ => panic!("unexpected request to peer manager"),
+            }
+        }
+        assert_eq!(dialed[0], (fast_peer_id, fast_addr.clone()));
+        assert_eq!(dialed[1].0, slow_peer_id);
+        // the peer without ping is past the limit
+        assert!(peer_mgr_reqs_rx.next().await.is_none());
+        assert_eq!(
+            peer_store.get(&fast_peer_id).unwrap().last_connected_addr,
+            Some(fast_addr)
+        );
+        assert_eq!(
+            peer_store.get(&slow_peer_id).unwrap().last_connected_addr,
+            None
+        );
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
+#[test]
+// Tests that the warm start of the networks without a connectivity manager spreads the peers it
+// dials across subnets.
+fn warm_start_limits_peers_per_subnet() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let tmp_dir = TempPath::new();
+    let peer_store = PeerStore::new(&tmp_dir);
+    let fast_peer_id = PeerId::random();
+    let same_subnet_peer_id = PeerId::random();
+    let other_subnet_peer_id = PeerId::random();
+    for (peer_id, addr, rtt_ms) in &[
+        (fast_peer_id, "/ip4/10.0.0.1/tcp/6180", 10),
+        (same_subnet_peer_id, "/ip4/10.0.0.2/tcp/6180", 20),
+        (other_subnet_peer_id, "/ip4/10.0.1.1/tcp/6180", 30),
+    ] {
+        peer_store.update(*peer_id, |record| {
+            record.addrs = vec![Multiaddr::from_str(addr).unwrap()];
+            record.smoothed_rtt = Some(Duration::from_millis(*rtt_ms));
+        });
+    }
+    let (peer_mgr_reqs_tx, mut peer_mgr_reqs_rx): (
+        channel::Sender<PeerManagerRequest<MemorySocket>>,
+        _,
+    ) = channel::new_test(0);
+    let warm_start_f = warm_start(
+        peer_store,
+        PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+        1,
+        Duration::from_millis(TEST_DIAL_STAGGER_MS),
+        OutboundConnectionsConfig {
+            max_outbound_per_subnet: Some(1),
+            ..OutboundConnectionsConfig::default()
+        },
+    );
+    rt.spawn(warm_start_f.boxed().unit_error().compat());
+
+    let events_f = async move {
+        let mut dialed = vec![];
+        while let Some(request) = peer_mgr_reqs_rx.next().await {
+            match request {
+                PeerManagerRequest::DialPeer(peer_id, _addr, error_tx) => {
+                    error_tx.send(Ok(())).unwrap();
+                    dialed.push(peer_id);
+                }
+                _ => panic!("unexpected request to peer manager"),
+            }
+        }
+        assert_eq!(dialed, vec![fast_peer_id, other_subnet_peer_id]);
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
 #[test]
 // Tests that if we dial an already connected peer or disconnect from an already disconnected
 // peer, connectivity manager does not send any additional dial or disconnect requests.
@@ -701,10 +1004,9 @@ fn multiple_addrs_basic() {
 
         // Assume that the first listen addr fails to connect.
         info!("Waiting to receive dial request");
-        expect_dial_request(
+        answer_dial_request(
             &mut peer_mgr_reqs_rx,
             &mut peer_mgr_notifs_tx,
-            &mut conn_mgr_reqs_tx,
             seed_peer_id,
             seed_addr_1.clone(),
             Err(PeerManagerError::IoError(io::Error::from(
@@ -713,12 +1015,8 @@ fn multiple_addrs_basic() {
         )
         .await;
 
-        // Trigger another connectivity check.
-        info!("Sending tick to trigger connectivity check");
-        ticker_tx.send(()).await.unwrap();
-
-        // Since the last connection attempt failed for seed_addr_1, we should
-        // attempt the next available listener address. In this case, the call
+        // Since the dial to seed_addr_1 failed, the same attempt goes on with
+        // the next available listener address. In this case, the call
         // succeeds and we should connect to the peer.
         info!("Waiting to receive dial request");
         expect_dial_request(
@@ -767,10 +1065,9 @@ fn multiple_addrs_wrapping() {
 
         // Assume that the first listen addr fails to connect.
         info!("Waiting to receive dial request");
-        expect_dial_request(
+        answer_dial_request(
             &mut peer_mgr_reqs_rx,
             &mut peer_mgr_notifs_tx,
-            &mut conn_mgr_reqs_tx,
             seed_peer_id,
             seed_addr_1.clone(),
             Err(PeerManagerError::IoError(io::Error::from(
@@ -779,11 +1076,7 @@ fn multiple_addrs_wrapping() {
         )
         .await;
 
-        // Trigger another connectivity check.
-        info!("Sending tick to trigger connectivity check");
-        ticker_tx.send(()).await.unwrap();
-
-        // The second attempt also fails.
+        // The dial to the second address also fails.
         info!("Waiting to receive dial request");
         expect_dial_request(
             &mut peer_mgr_reqs_rx,
@@ -801,7 +1094,7 @@ fn multiple_addrs_wrapping() {
         info!("Sending tick to trigger connectivity check");
         ticker_tx.send(()).await.unwrap();
 
-        // Our next attempt should wrap around to the first address.
+        // Our next attempt should start over from the first address.
         info!("Waiting to receive dial request");
         expect_dial_request(
             &mut peer_mgr_reqs_rx,
@@ -852,14 +1145,27 @@ fn multiple_addrs_shrinking() {
         info!("Sending tick to trigger connectivity check");
         ticker_tx.send(()).await.unwrap();
 
-        // Assume that the first listen addr fails to connect.
+        // Assume that none of the listen addrs connects.
+        for seed_addr in vec![seed_addr_1, seed_addr_2] {
+            info!("Waiting to receive dial request");
+            answer_dial_request(
+                &mut peer_mgr_reqs_rx,
+                &mut peer_mgr_notifs_tx,
+                seed_peer_id,
+                seed_addr,
+                Err(PeerManagerError::IoError(io::Error::from(
+                    io::ErrorKind::ConnectionRefused,
+                ))),
+            )
+            .await;
+        }
         info!("Waiting to receive dial request");
         expect_dial_request(
             &mut peer_mgr_reqs_rx,
             &mut peer_mgr_notifs_tx,
             &mut conn_mgr_reqs_tx,
             seed_peer_id,
-            seed_addr_1,
+            seed_addr_3,
             Err(PeerManagerError::IoError(io::Error::from(
                 io::ErrorKind::ConnectionRefused,
             ))),
@@ -899,3 +1205,729 @@ fn multiple_addrs_shrinking() {
     rt.block_on(f_peer_mgr.boxed().unit_error().compat())
         .unwrap();
 }
+
+#[test]
+// Tests that the dial stats of the addresses a peer stops advertising, and of the peers which are
+// no longer eligible, are forgotten.
+fn dial_stats_forgotten() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    let dial_stats = DialStats::new();
+    let (_peer_mgr_reqs_rx, _peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, _ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig::default(),
+            None,
+            dial_stats.clone(),
+        );
+
+    let events_f = async move {
+        let seed_addr_1 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+        let seed_addr_2 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_addr_1.clone(), seed_addr_2.clone()],
+            ))
+            .await
+            .unwrap();
+        dial_stats.record_failure(&seed_addr_1);
+        dial_stats.record_failure(&seed_addr_2);
+
+        // The peer stops advertising its first address.
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_addr_2.clone()],
+            ))
+            .await
+            .unwrap();
+        // The requests are handled in order, so the update is handled once the queue size is in.
+        get_dial_queue_size(&mut conn_mgr_reqs_tx).await;
+        assert_eq!(dial_stats.get(&seed_addr_1), AddrDialStats::default());
+        assert_eq!(dial_stats.get(&seed_addr_2).failures, 1);
+
+        // The peer is no longer eligible.
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdatePeerSet(vec![
+                PeerSetUpdate::Remove(seed_peer_id),
+            ]))
+            .await
+            .unwrap();
+        get_dial_queue_size(&mut conn_mgr_reqs_tx).await;
+        assert_eq!(dial_stats.get(&seed_addr_2), AddrDialStats::default());
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
+// Test that connectivity manager falls back to dialing a peer through the relay it advertises once
+// the peer's other addresses have failed.
+#[test]
+fn relay_fallback() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let relay_addr = Multiaddr::from_str("/ip4/127.0.0.1/tcp/6181").unwrap();
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, seed_peer_id);
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let seed_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        let seed_circuit_address = relay::circuit_addr(&relay_addr, seed_peer_id);
+
+        // Send addresses of seed peer. The circuit address is dialed last whatever its position.
+        info!("Sending addresses of seed peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_circuit_address.clone(), seed_address.clone()],
+            ))
+            .await
+            .unwrap();
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        // The direct dial fails, e.g., because the peer is behind a NAT.
+        info!("Waiting to receive dial request");
+        answer_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            seed_peer_id,
+            seed_address.clone(),
+            Err(PeerManagerError::IoError(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            ))),
+        )
+        .await;
+
+        // The same attempt then goes through the relay.
+        info!("Waiting to receive dial request through relay");
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_circuit_address,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Test that connectivity manager dials the next address of a peer when the dial to the previous
+// one is slow, and abandons the slow dial once the other succeeds.
+#[test]
+fn multiple_addrs_staggered() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            100, /* dial_stagger_ms */
+            OutboundConnectionsConfig::default(),
+            None,
+            DialStats::new(),
+        );
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let seed_addr_1 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+        let seed_addr_2 = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9092").unwrap();
+
+        // Send addresses of seed peer.
+        info!("Sending address of seed peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_addr_1.clone(), seed_addr_2.clone()],
+            ))
+            .await
+            .unwrap();
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        // The dial to the first listen addr hangs.
+        info!("Waiting to receive dial request");
+        let start = Instant::now();
+        let slow_error_tx = match peer_mgr_reqs_rx.next().await.unwrap() {
+            PeerManagerRequest::DialPeer(p, addr, error_tx) => {
+                assert_eq!(seed_peer_id, p);
+                assert_eq!(seed_addr_1, addr);
+                error_tx
+            }
+            _ => {
+                panic!("unexpected request to peer manager");
+            }
+        };
+
+        // The second listen addr is dialed once the stagger delay has passed, and succeeds.
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_addr_2,
+            Ok(()),
+        )
+        .await;
+        let elapsed = Instant::now().duration_since(start);
+        info!("Duration elapsed: {:?}", elapsed);
+        assert!(elapsed.as_millis() >= 100);
+
+        // The slow dial has been abandoned.
+        assert!(slow_error_tx.is_canceled());
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn dial_budget() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            1, /* max_concurrent_dials */
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig::default(),
+            None,
+            DialStats::new(),
+        );
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let seed_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        let (other_peer_id, other_pubkeys) = gen_peer();
+        let other_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+
+        // Make another peer eligible and send the addresses of both peers.
+        info!("Sending list of eligible peers");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateEligibleNodes(
+                vec![(seed_peer_id, gen_peer().1), (other_peer_id, other_pubkeys)]
+                    .into_iter()
+                    .collect(),
+            ))
+            .await
+            .unwrap();
+        info!("Sending addresses of peers");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_address.clone()],
+            ))
+            .await
+            .unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                other_peer_id,
+                vec![other_address.clone()],
+            ))
+            .await
+            .unwrap();
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        // Only one of the peers is dialed, since the budget only allows for a single outstanding
+        // dial.
+        info!("Waiting to receive dial request");
+        let (first_peer_id, first_address) = match peer_mgr_reqs_rx.next().await.unwrap() {
+            PeerManagerRequest::DialPeer(p, addr, error_tx) => {
+                assert_eq!(get_dial_queue_size(&mut conn_mgr_reqs_tx).await, 1);
+                error_tx.send(Ok(())).unwrap();
+                (p, addr)
+            }
+            _ => {
+                panic!("unexpected request to peer manager");
+            }
+        };
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewPeer(
+                first_peer_id,
+                first_address,
+            ))
+            .await
+            .unwrap();
+        while get_dial_queue_size(&mut conn_mgr_reqs_tx).await != 0 {}
+
+        // Trigger connectivity check.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+
+        // The other peer is dialed once the first dial has completed.
+        let (second_peer_id, second_address) = if first_peer_id == seed_peer_id {
+            (other_peer_id, other_address)
+        } else {
+            (seed_peer_id, seed_address)
+        };
+        info!("Waiting to receive dial request");
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            second_peer_id,
+            second_address,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Tests that a new set of eligible nodes leaves the connections to unchanged nodes untouched.
+#[test]
+fn update_eligible_nodes_keeps_unchanged_peers() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, seed_peer_id);
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let seed_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_address.clone()],
+            ))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_address,
+            Ok(()),
+        )
+        .await;
+
+        // Add another peer, keeping the seed peer with the same keys.
+        let (other_peer_id, other_pubkeys) = gen_peer();
+        let other_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap();
+        info!("Sending list of eligible peers");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateEligibleNodes(
+                vec![(seed_peer_id, gen_peer().1), (other_peer_id, other_pubkeys)]
+                    .into_iter()
+                    .collect(),
+            ))
+            .await
+            .unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                other_peer_id,
+                vec![other_address.clone()],
+            ))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+
+        // The new peer is dialed, and the seed peer is not disconnected.
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            other_peer_id,
+            other_address,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Tests that a connected peer whose keys change is reconnected.
+#[test]
+fn rekeyed_peer() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr(&mut rt, seed_peer_id);
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let seed_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                seed_peer_id,
+                vec![seed_address.clone()],
+            ))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_address.clone(),
+            Ok(()),
+        )
+        .await;
+
+        // Resending the current keys and addresses of the seed peer is a no-op.
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdatePeerSet(vec![
+                PeerSetUpdate::Add(seed_peer_id, gen_peer().1),
+                PeerSetUpdate::UpdateAddresses(seed_peer_id, vec![seed_address.clone()]),
+            ]))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+        assert_eq!(get_dial_queue_size(&mut conn_mgr_reqs_tx).await, 0);
+
+        // Rotate the keys of the seed peer.
+        let mut rng = StdRng::from_seed([1u8; 32]);
+        let (_, signing_public_key) = compat::generate_keypair(&mut rng);
+        let (_, identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+        info!("Sending new keys of seed peer");
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdatePeerSet(vec![
+                PeerSetUpdate::Add(
+                    seed_peer_id,
+                    NetworkPublicKeys {
+                        identity_public_key,
+                        signing_public_key,
+                    },
+                ),
+            ]))
+            .await
+            .unwrap();
+        ticker_tx.send(()).await.unwrap();
+
+        // The seed peer is disconnected, and dialed again on the next connectivity check.
+        expect_disconnect_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            seed_peer_id,
+            seed_address.clone(),
+            Ok(()),
+        )
+        .await;
+        tick_until_dial_queued(&mut ticker_tx, &mut conn_mgr_reqs_tx).await;
+        expect_dial_request(
+            &mut peer_mgr_reqs_rx,
+            &mut peer_mgr_notifs_tx,
+            &mut conn_mgr_reqs_tx,
+            seed_peer_id,
+            seed_address,
+            Ok(()),
+        )
+        .await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Triggers connectivity checks until connectivity manager sends a request to peer manager.
+async fn tick_until_request<'a, TSubstream>(
+    ticker_tx: &'a mut channel::Sender<()>,
+    peer_mgr_reqs_rx: &'a mut channel::Receiver<PeerManagerRequest<TSubstream>>,
+) -> PeerManagerRequest<TSubstream> {
+    loop {
+        ticker_tx.send(()).await.unwrap();
+        let mut f_delay = timer::Delay::new(Instant::now() + Duration::from_millis(100))
+            .compat()
+            .fuse();
+        ::futures::select! {
+            req = peer_mgr_reqs_rx.select_next_some() => return req,
+            _ = f_delay => {},
+        }
+    }
+}
+
+// Makes the given peers eligible, at the given addresses.
+async fn send_eligible_peers(
+    conn_mgr_reqs_tx: &mut channel::Sender<ConnectivityRequest>,
+    peers: &[(PeerId, Multiaddr)],
+) {
+    conn_mgr_reqs_tx
+        .send(ConnectivityRequest::UpdateEligibleNodes(
+            peers
+                .iter()
+                .map(|(peer_id, _)| (*peer_id, gen_peer().1))
+                .collect(),
+        ))
+        .await
+        .unwrap();
+    for (peer_id, address) in peers {
+        conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdateAddresses(
+                *peer_id,
+                vec![address.clone()],
+            ))
+            .await
+            .unwrap();
+    }
+}
+
+// Answers the next `count` dial requests with success, and returns the dialed peers.
+async fn answer_dial_requests<'a, TSubstream>(
+    peer_mgr_reqs_rx: &'a mut channel::Receiver<PeerManagerRequest<TSubstream>>,
+    peer_mgr_notifs_tx: &'a mut channel::Sender<PeerManagerNotification<TSubstream>>,
+    count: usize,
+) -> Vec<(PeerId, Multiaddr)> {
+    let mut dialed = vec![];
+    for _ in 0..count {
+        match peer_mgr_reqs_rx.next().await.unwrap() {
+            PeerManagerRequest::DialPeer(p, addr, error_tx) => {
+                error_tx.send(Ok(())).unwrap();
+                dialed.push((p, addr));
+            }
+            _ => {
+                panic!("unexpected request to peer manager");
+            }
+        }
+    }
+    for (peer_id, address) in &dialed {
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewPeer(*peer_id, address.clone()))
+            .await
+            .unwrap();
+    }
+    dialed
+}
+
+// Tests that no more than the allowed number of peers of a subnet are dialed.
+#[test]
+fn outbound_subnet_diversity() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig {
+                max_outbound_per_subnet: Some(1),
+                ..OutboundConnectionsConfig::default()
+            },
+            None,
+            DialStats::new(),
+        );
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let other_subnet_peer = (
+            PeerId::random(),
+            Multiaddr::from_str("/ip4/10.0.1.1/tcp/9090").unwrap(),
+        );
+        let peers = vec![
+            (
+                seed_peer_id,
+                Multiaddr::from_str("/ip4/10.0.0.1/tcp/9090").unwrap(),
+            ),
+            (
+                PeerId::random(),
+                Multiaddr::from_str("/ip4/10.0.0.2/tcp/9090").unwrap(),
+            ),
+            other_subnet_peer.clone(),
+        ];
+        send_eligible_peers(&mut conn_mgr_reqs_tx, &peers).await;
+
+        // Only one of the two peers of the crowded subnet is dialed.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        let dialed = answer_dial_requests(&mut peer_mgr_reqs_rx, &mut peer_mgr_notifs_tx, 2).await;
+        assert!(dialed.contains(&other_subnet_peer));
+        while get_dial_queue_size(&mut conn_mgr_reqs_tx).await != 0 {}
+
+        // The other one is left alone on later connectivity checks.
+        ticker_tx.send(()).await.unwrap();
+        assert_eq!(get_dial_queue_size(&mut conn_mgr_reqs_tx).await, 0);
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Tests that the subnet limit is exceeded if that is the only way to have the minimum number of
+// outbound connections.
+#[test]
+fn min_outbound_over_subnet_limit() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig {
+                max_outbound_per_subnet: Some(1),
+                min_outbound: 2,
+                ..OutboundConnectionsConfig::default()
+            },
+            None,
+            DialStats::new(),
+        );
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let peers = vec![
+            (
+                seed_peer_id,
+                Multiaddr::from_str("/ip4/10.0.0.1/tcp/9090").unwrap(),
+            ),
+            (
+                PeerId::random(),
+                Multiaddr::from_str("/ip4/10.0.0.2/tcp/9090").unwrap(),
+            ),
+        ];
+        send_eligible_peers(&mut conn_mgr_reqs_tx, &peers).await;
+
+        // Both peers are dialed, although they are in the same subnet.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        let dialed = answer_dial_requests(&mut peer_mgr_reqs_rx, &mut peer_mgr_notifs_tx, 2).await;
+        for peer in &peers {
+            assert!(dialed.contains(peer));
+        }
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// Tests that the oldest outbound connection is replaced by a connection to another peer once the
+// rotation interval has passed.
+#[test]
+fn outbound_rotation() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let seed_peer_id = PeerId::random();
+    info!("Seed peer_id is {}", seed_peer_id.short_str());
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_tx, mut ticker_tx) =
+        setup_conn_mgr_with_options(
+            &mut rt,
+            seed_peer_id,
+            MAX_CONCURRENT_DIALS,
+            TEST_DIAL_STAGGER_MS,
+            OutboundConnectionsConfig {
+                max_outbound: Some(1),
+                rotation_interval_ms: Some(0),
+                ..OutboundConnectionsConfig::default()
+            },
+            None,
+            DialStats::new(),
+        );
+
+    // Fake peer manager and discovery.
+    let f_peer_mgr = async move {
+        let peers = vec![
+            (
+                seed_peer_id,
+                Multiaddr::from_str("/ip4/10.0.0.1/tcp/9090").unwrap(),
+            ),
+            (
+                PeerId::random(),
+                Multiaddr::from_str("/ip4/10.0.1.1/tcp/9090").unwrap(),
+            ),
+        ];
+        send_eligible_peers(&mut conn_mgr_reqs_tx, &peers).await;
+
+        // Only one of the peers is dialed.
+        info!("Sending tick to trigger connectivity check");
+        ticker_tx.send(()).await.unwrap();
+        let dialed = answer_dial_requests(&mut peer_mgr_reqs_rx, &mut peer_mgr_notifs_tx, 1).await;
+        let (first_peer_id, first_address) = dialed[0].clone();
+        while get_dial_queue_size(&mut conn_mgr_reqs_tx).await != 0 {}
+
+        // Its connection is rotated out.
+        match tick_until_request(&mut ticker_tx, &mut peer_mgr_reqs_rx).await {
+            PeerManagerRequest::DisconnectPeer(p, error_tx) => {
+                assert_eq!(first_peer_id, p);
+                error_tx.send(Ok(())).unwrap();
+            }
+            _ => {
+                panic!("unexpected request to peer manager");
+            }
+        }
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::LostPeer(
+                first_peer_id,
+                first_address,
+            ))
+            .await
+            .unwrap();
+
+        // And the other peer takes its place.
+        let (second_peer_id, second_address) = peers
+            .into_iter()
+            .find(|(peer_id, _)| *peer_id != first_peer_id)
+            .unwrap();
+        match tick_until_request(&mut ticker_tx, &mut peer_mgr_reqs_rx).await {
+            PeerManagerRequest::DialPeer(p, addr, error_tx) => {
+                assert_eq!(second_peer_id, p);
+                assert_eq!(second_address, addr);
+                error_tx.send(Ok(())).unwrap();
+            }
+            _ => {
+                panic!("unexpected request to peer manager");
+            }
+        }
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn addr_subnet() {
+    let subnet_of = |addr: &str| subnet(&Multiaddr::from_str(addr).unwrap());
+    assert_eq!(
+        subnet_of("/ip4/10.0.0.1/tcp/6180"),
+        subnet_of("/ip4/10.0.0.254/tcp/6181")
+    );
+    assert_ne!(
+        subnet_of("/ip4/10.0.0.1/tcp/6180"),
+        subnet_of("/ip4/10.0.1.1/tcp/6180")
+    );
+    assert_eq!(
+        subnet_of("/ip6/2001:db8:1:2::1/tcp/6180"),
+        subnet_of("/ip6/2001:db8:1:3::1/tcp/6180")
+    );
+    assert_ne!(
+        subnet_of("/ip6/2001:db8:1::1/tcp/6180"),
+        subnet_of("/ip6/2001:db8:2::1/tcp/6180")
+    );
+    // IPv4-mapped IPv6 addresses are in the subnet of their IPv4 address.
+    assert_eq!(
+        subnet_of("/ip6/::ffff:10.0.0.1/tcp/6180"),
+        subnet_of("/ip4/10.0.0.2/tcp/6180")
+    );
+    assert_eq!(subnet_of("/memory/6180"), None);
+}
@@ -2,16 +2,68 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use lazy_static;
-use metrics::{Histogram, IntCounter, IntGauge, OpMetrics};
+use metrics::{DurationHistogram, Histogram, IntCounter, IntGauge, OpMetrics};
 
 lazy_static::lazy_static! {
     pub static ref OP_COUNTERS: OpMetrics = OpMetrics::new_and_registered("network");
 }
 
 lazy_static::lazy_static! {
-    /// Counter of currently connected peers
+    /// Counter of currently connected peers, across all the networks of the node. The peers of each
+    /// network are counted by `connected_peers.<label>`, see `NetworkBuilder::metrics_label`
     pub static ref CONNECTED_PEERS: IntGauge = OP_COUNTERS.gauge("connected_peers");
 
+    /// Counter of connections replaced by a connection from the same peer at a new address
+    pub static ref PEER_CONNECTION_MIGRATIONS: IntCounter = OP_COUNTERS.counter("peer_connection_migrations");
+
+    /// Counter of connections spliced together by this node acting as a relay
+    pub static ref RELAY_CIRCUITS_ESTABLISHED: IntCounter = OP_COUNTERS.counter("relay_circuits_established");
+
+    /// Counter of GoAways received from peers shutting down
+    pub static ref PEER_GOAWAYS_RECEIVED: IntCounter = OP_COUNTERS.counter("peer_goaways_received");
+
+    /// Counter of peers disconnected as their queue of requests stayed full
+    pub static ref PEERS_DISCONNECTED_QUEUE_FULL: IntCounter = OP_COUNTERS.counter("peers_disconnected_queue_full");
+
+    /// Counter of discovery notes dropped as they expired
+    pub static ref DISCOVERY_NOTES_EXPIRED: IntCounter = OP_COUNTERS.counter("discovery_notes_expired");
+
+    /// Counter of relay requests rejected because the target peer had no reservation
+    pub static ref RELAY_CIRCUITS_REJECTED: IntCounter = OP_COUNTERS.counter("relay_circuits_rejected");
+
+    /// Counter of reservations rejected by this node acting as a relay, because the peer failed to
+    /// authenticate or the relay holds as many reservations as it accepts
+    pub static ref RELAY_RESERVATIONS_REJECTED: IntCounter = OP_COUNTERS.counter("relay_reservations_rejected");
+
+    /// Counter of inbound connections on a shared listener which did not select a known network
+    pub static ref SHARED_LISTENER_CONNECTIONS_REJECTED: IntCounter = OP_COUNTERS.counter("shared_listener_connections_rejected");
+
+    /// Counter of dials postponed because too many dials were already outstanding
+    pub static ref DIALS_DEFERRED: IntCounter = OP_COUNTERS.counter("dials_deferred");
+
+    /// Counter of dials not made because the subnet of the peer already has its share of our
+    /// outbound connections
+    pub static ref DIALS_SKIPPED_FOR_DIVERSITY: IntCounter = OP_COUNTERS.counter("dials_skipped_for_diversity");
+
+    /// Counter of inbound connections closed because the subnet of the peer already has its share
+    /// of our inbound connections
+    pub static ref INBOUND_CONNECTIONS_REJECTED_FOR_DIVERSITY: IntCounter = OP_COUNTERS.counter("inbound_connections_rejected_for_diversity");
+
+    /// Counter of outbound connections closed to make room for connections to other peers
+    pub static ref OUTBOUND_CONNECTIONS_ROTATED: IntCounter = OP_COUNTERS.counter("outbound_connections_rotated");
+
+    /// Counter of failed dials to one of the addresses of a peer
+    pub static ref DIAL_ADDR_FAILURES: IntCounter = OP_COUNTERS.counter("dial_addr_failures");
+
+    /// Histogram of the round trip time of successful pings
+    pub static ref PING_RTT: DurationHistogram = OP_COUNTERS.duration_histogram("ping_rtt");
+
+    /// Counter of failed pings
+    pub static ref PING_FAILURES: IntCounter = OP_COUNTERS.counter("ping_failures");
+
+    /// Counter of peers declared unhealthy after too many successive ping failures
+    pub static ref UNHEALTHY_PEERS: IntCounter = OP_COUNTERS.counter("unhealthy_peers");
+
     /// Counter of rpc requests sent
     pub static ref RPC_REQUESTS_SENT: IntCounter = OP_COUNTERS.counter("rpc_requests_sent");
 
@@ -21,6 +73,21 @@ lazy_static::lazy_static! {
     /// Counter of rpc requests failed
     pub static ref RPC_REQUESTS_FAILED: IntCounter = OP_COUNTERS.counter("rpc_requests_failed");
 
+    /// Counter of rpc requests sent again to another peer after a failed attempt
+    pub static ref RPC_REQUESTS_RETRIED: IntCounter = OP_COUNTERS.counter("rpc_requests_retried");
+
+    /// Counter of rpc requests sent to a second peer as the first one was slow to respond
+    pub static ref RPC_REQUESTS_HEDGED: IntCounter = OP_COUNTERS.counter("rpc_requests_hedged");
+
+    /// Counter of upstream peers failed over after too many failed rpc requests
+    pub static ref UPSTREAM_FAILOVERS: IntCounter = OP_COUNTERS.counter("upstream_failovers");
+
+    /// Counter of broadcast messages sent again to a peer after a failed attempt
+    pub static ref BROADCAST_DELIVERIES_RETRIED: IntCounter = OP_COUNTERS.counter("broadcast_deliveries_retried");
+
+    /// Counter of broadcast messages which a peer never acknowledged
+    pub static ref BROADCAST_DELIVERIES_FAILED: IntCounter = OP_COUNTERS.counter("broadcast_deliveries_failed");
+
     /// Counter of rpc requests cancelled
     pub static ref RPC_REQUESTS_CANCELLED: IntCounter = OP_COUNTERS.counter("rpc_requests_cancelled");
 
@@ -48,16 +115,29 @@ lazy_static::lazy_static! {
     /// Counter of messages dropped via the direct send protocol
     pub static ref DIRECT_SEND_MESSAGES_DROPPED: IntCounter = OP_COUNTERS.counter("direct_send_messages_dropped");
 
+    /// Counter of messages dropped by the direct send protocol because their deadline passed
+    /// while they were queued
+    pub static ref DIRECT_SEND_MESSAGES_EXPIRED: IntCounter = OP_COUNTERS.counter("direct_send_messages_expired");
+
     /// Counter of messages received via the direct send protocol
     pub static ref DIRECT_SEND_MESSAGES_RECEIVED: IntCounter = OP_COUNTERS.counter("direct_send_messages_received");
 
     /// Counter of bytes received via the direct send protocol
     pub static ref DIRECT_SEND_BYTES_RECEIVED: IntCounter = OP_COUNTERS.counter("direct_send_bytes_received");
 
+    /// Counter of coalesced writes made by the direct send protocol when batching is enabled
+    pub static ref DIRECT_SEND_BATCHES_SENT: IntCounter = OP_COUNTERS.counter("direct_send_batches_sent");
+
+    /// Histogram of the number of messages coalesced into a single direct send write
+    pub static ref DIRECT_SEND_MESSAGES_PER_BATCH: Histogram = OP_COUNTERS.histogram("direct_send_messages_per_batch");
+
     ///
     /// Channel Counters
     ///
 
+    /// Counter of pending inbound connections routed by a shared listener to a network
+    pub static ref PENDING_SHARED_LISTENER_CONNECTIONS: IntGauge = OP_COUNTERS.gauge("pending_shared_listener_connections");
+
     /// Counter of pending requests in Network Provider
     pub static ref PENDING_NETWORK_REQUESTS: IntGauge = OP_COUNTERS.gauge("pending_network_requests");
 
@@ -70,6 +150,28 @@ lazy_static::lazy_static! {
     /// Counter of pending network events to Consensus
     pub static ref PENDING_STATE_SYNCHRONIZER_NETWORK_EVENTS: IntGauge = OP_COUNTERS.gauge("pending_state_sync_network_events");
 
+    /// Counter of pending network events to Admission Control
+    pub static ref PENDING_ADMISSION_CONTROL_NETWORK_EVENTS: IntGauge = OP_COUNTERS.gauge("pending_admission_control_network_events");
+
+    /// Counter of pending network events to application protocol handlers
+    pub static ref PENDING_PROTOCOL_HANDLER_NETWORK_EVENTS: IntGauge = OP_COUNTERS.gauge("pending_protocol_handler_network_events");
+
+    /// Counter of inbound messages and RPC requests dropped because no upstream component handles
+    /// their protocol
+    pub static ref UNHANDLED_NETWORK_MESSAGES: IntCounter = OP_COUNTERS.counter("unhandled_network_messages");
+
+    /// Counter of network events to Mempool dropped because its queue was full
+    pub static ref DROPPED_MEMPOOL_NETWORK_EVENTS: IntCounter = OP_COUNTERS.counter("dropped_mempool_network_events");
+
+    /// Counter of network events to Consensus dropped because its queue was full
+    pub static ref DROPPED_CONSENSUS_NETWORK_EVENTS: IntCounter = OP_COUNTERS.counter("dropped_consensus_network_events");
+
+    /// Counter of network events to State Synchronizer dropped because its queue was full
+    pub static ref DROPPED_STATE_SYNCHRONIZER_NETWORK_EVENTS: IntCounter = OP_COUNTERS.counter("dropped_state_sync_network_events");
+
+    /// Counter of network events to Admission Control dropped because its queue was full
+    pub static ref DROPPED_ADMISSION_CONTROL_NETWORK_EVENTS: IntCounter = OP_COUNTERS.counter("dropped_admission_control_network_events");
+
     /// Counter of pending requests in Peer Manager
     pub static ref PENDING_PEER_MANAGER_REQUESTS: IntGauge = OP_COUNTERS.gauge("pending_peer_manager_requests");
 
@@ -112,9 +214,24 @@ lazy_static::lazy_static! {
     /// Counter of pending dial requests in Peer Manager
     pub static ref PENDING_PEER_MANAGER_DIAL_REQUESTS: IntGauge  = OP_COUNTERS.gauge("pending_peer_manager_dial_requests");
 
+    /// Smoothed round trip time of the pings to each remote peer, in milliseconds
+    pub static ref PEER_PING_RTT_MS: &'static str = "peer_ping_rtt_ms";
+
     /// Counter of pending requests for each remote peer
     pub static ref PENDING_PEER_REQUESTS: &'static str = "pending_peer_requests";
 
+    /// Counter of requests dropped for each protocol and remote peer, as the queue of the peer was full
+    pub static ref PEER_REQUESTS_DROPPED: &'static str = "peer_requests_dropped";
+
+    /// Counter of bytes sent on substreams for each protocol and remote peer
+    pub static ref PROTOCOL_BYTES_SENT: &'static str = "protocol_bytes_sent";
+
+    /// Counter of bytes received on substreams for each protocol and remote peer
+    pub static ref PROTOCOL_BYTES_RECEIVED: &'static str = "protocol_bytes_received";
+
     /// Counter of pending outbound messages in Direct Send for each remote peer
     pub static ref PENDING_DIRECT_SEND_OUTBOUND_MESSAGES: &'static str = "pending_direct_send_outbound_messages";
+
+    /// Counter of outbound messages dropped in Direct Send for each protocol and remote peer, as the queue was full
+    pub static ref DIRECT_SEND_QUEUE_OVERFLOWS: &'static str = "direct_send_queue_overflows";
 }
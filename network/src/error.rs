@@ -9,12 +9,18 @@ use std::{
     io,
 };
 use tokio::timer;
-use types::validator_verifier::VerifyError;
+use types::{validator_verifier::VerifyError, PeerId};
 
 /// Errors propagated from the network module.
+///
+/// Besides its [`NetworkErrorKind`], an error carries the id of the peer it relates to, when
+/// known, so that the users of the network can tell whether to retry the operation which failed
+/// ([`is_retryable`](NetworkError::is_retryable)) or to hold the peer responsible for it
+/// ([`is_peer_fault`](NetworkError::is_peer_fault)).
 #[derive(Debug)]
 pub struct NetworkError {
     inner: Context<NetworkErrorKind>,
+    peer_id: Option<PeerId>,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
@@ -22,8 +28,14 @@ pub enum NetworkErrorKind {
     #[fail(display = "IO error")]
     IoError,
 
-    #[fail(display = "Error parsing protobuf message")]
-    ProtobufParseError,
+    #[fail(display = "Transport error")]
+    TransportError,
+
+    #[fail(display = "Error encoding protobuf message")]
+    EncodeError,
+
+    #[fail(display = "Error decoding protobuf message")]
+    DecodeError,
 
     #[fail(display = "Invalid signature error")]
     SignatureError,
@@ -31,8 +43,8 @@ pub enum NetworkErrorKind {
     #[fail(display = "Failed to parse multiaddrs")]
     MultiaddrError,
 
-    #[fail(display = "Error sending on mpsc channel")]
-    MpscSendError,
+    #[fail(display = "Channel to or from the network closed")]
+    ChannelClosed,
 
     #[fail(display = "Error setting timeout")]
     TimerError,
@@ -53,6 +65,33 @@ pub enum NetworkErrorKind {
     NotConnected,
 }
 
+impl NetworkErrorKind {
+    /// Whether the operation which failed may succeed if tried again later, e.g. once the
+    /// connection to the peer is reestablished. Errors which are not retryable will fail the same
+    /// way again, either because the network is shutting down or because of what was sent.
+    pub fn is_retryable(self) -> bool {
+        match self {
+            NetworkErrorKind::IoError
+            | NetworkErrorKind::TransportError
+            | NetworkErrorKind::TimedOut
+            | NetworkErrorKind::NotConnected => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the error was caused by what the remote peer sent, e.g. a message which does not
+    /// decode or a bad signature, as opposed to a local or connectivity issue.
+    pub fn is_peer_fault(self) -> bool {
+        match self {
+            NetworkErrorKind::DecodeError
+            | NetworkErrorKind::SignatureError
+            | NetworkErrorKind::MultiaddrError
+            | NetworkErrorKind::ParsingError => true,
+            _ => false,
+        }
+    }
+}
+
 impl Fail for NetworkError {
     fn cause(&self) -> Option<&dyn Fail> {
         self.inner.cause()
@@ -65,7 +104,10 @@ impl Fail for NetworkError {
 
 impl Display for NetworkError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", &self.inner)
+        match self.peer_id {
+            Some(peer_id) => write!(f, "{} (peer {})", &self.inner, peer_id.short_str()),
+            None => write!(f, "{}", &self.inner),
+        }
     }
 }
 
@@ -73,19 +115,41 @@ impl NetworkError {
     pub fn kind(&self) -> NetworkErrorKind {
         *self.inner.get_context()
     }
+
+    /// The peer the error relates to, if known.
+    pub fn peer_id(&self) -> Option<PeerId> {
+        self.peer_id
+    }
+
+    /// Attaches the peer the error relates to.
+    pub fn with_peer(mut self, peer_id: PeerId) -> Self {
+        self.peer_id = Some(peer_id);
+        self
+    }
+
+    /// See [`NetworkErrorKind::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+
+    /// See [`NetworkErrorKind::is_peer_fault`].
+    pub fn is_peer_fault(&self) -> bool {
+        self.kind().is_peer_fault()
+    }
 }
 
 impl From<NetworkErrorKind> for NetworkError {
     fn from(kind: NetworkErrorKind) -> NetworkError {
-        NetworkError {
-            inner: Context::new(kind),
-        }
+        Context::new(kind).into()
     }
 }
 
 impl From<Context<NetworkErrorKind>> for NetworkError {
     fn from(inner: Context<NetworkErrorKind>) -> NetworkError {
-        NetworkError { inner }
+        NetworkError {
+            inner,
+            peer_id: None,
+        }
     }
 }
 
@@ -103,13 +167,13 @@ impl From<VerifyError> for NetworkError {
 
 impl From<prost::EncodeError> for NetworkError {
     fn from(err: prost::EncodeError) -> NetworkError {
-        err.context(NetworkErrorKind::ProtobufParseError).into()
+        err.context(NetworkErrorKind::EncodeError).into()
     }
 }
 
 impl From<prost::DecodeError> for NetworkError {
     fn from(err: prost::DecodeError) -> NetworkError {
-        err.context(NetworkErrorKind::ProtobufParseError).into()
+        err.context(NetworkErrorKind::DecodeError).into()
     }
 }
 
@@ -121,7 +185,7 @@ impl From<parity_multiaddr::Error> for NetworkError {
 
 impl From<mpsc::SendError> for NetworkError {
     fn from(err: mpsc::SendError) -> NetworkError {
-        err.context(NetworkErrorKind::MpscSendError).into()
+        err.context(NetworkErrorKind::ChannelClosed).into()
     }
 }
 
@@ -129,7 +193,17 @@ impl From<PeerManagerError> for NetworkError {
     fn from(err: PeerManagerError) -> NetworkError {
         match err {
             PeerManagerError::IoError(_) => err.context(NetworkErrorKind::IoError).into(),
-            PeerManagerError::NotConnected(_) => err.context(NetworkErrorKind::NotConnected).into(),
+            PeerManagerError::TransportError(_) => {
+                err.context(NetworkErrorKind::TransportError).into()
+            }
+            PeerManagerError::NotConnected(peer_id) => {
+                NetworkError::from(err.context(NetworkErrorKind::NotConnected)).with_peer(peer_id)
+            }
+            PeerManagerError::ShuttingDown
+            | PeerManagerError::ShuttingDownPeer
+            | PeerManagerError::OneshotSenderDropped => {
+                err.context(NetworkErrorKind::ChannelClosed).into()
+            }
             err => err.context(NetworkErrorKind::PeerManagerError).into(),
         }
     }
@@ -185,4 +259,32 @@ mod test {
             eprintln!("Error: {}", e);
         }
     }
+
+    #[test]
+    fn classification() {
+        let decode_error: NetworkError = prost::DecodeError::new("invalid message").into();
+        assert_eq!(decode_error.kind(), NetworkErrorKind::DecodeError);
+        assert!(decode_error.is_peer_fault());
+        assert!(!decode_error.is_retryable());
+        assert_eq!(decode_error.peer_id(), None);
+
+        let peer_id = PeerId::random();
+        let not_connected: NetworkError = PeerManagerError::NotConnected(peer_id).into();
+        assert_eq!(not_connected.kind(), NetworkErrorKind::NotConnected);
+        assert!(not_connected.is_retryable());
+        assert!(!not_connected.is_peer_fault());
+        assert_eq!(not_connected.peer_id(), Some(peer_id));
+
+        let shutting_down: NetworkError = PeerManagerError::ShuttingDown.into();
+        assert_eq!(shutting_down.kind(), NetworkErrorKind::ChannelClosed);
+        assert!(!shutting_down.is_retryable());
+        assert!(!shutting_down.is_peer_fault());
+
+        let other_peer_id = PeerId::random();
+        let io_error =
+            NetworkError::from(io::Error::from(io::ErrorKind::BrokenPipe)).with_peer(other_peer_id);
+        assert!(io_error.is_retryable());
+        assert_eq!(io_error.peer_id(), Some(other_peer_id));
+        assert!(io_error.to_string().contains(&other_peer_id.short_str()));
+    }
 }
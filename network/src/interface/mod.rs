@@ -15,34 +15,47 @@ use crate::{
     common::NetworkPublicKeys,
     connectivity_manager::ConnectivityRequest,
     counters,
-    peer_manager::PeerManagerNotification,
+    peer_manager::{PeerManagerNotification, PeerManagerShutdownHandle, PeerMetadataStore},
     protocols::{
         direct_send::{DirectSendNotification, DirectSendRequest, Message},
         rpc::{InboundRpcRequest, OutboundRpcRequest, RpcNotification, RpcRequest},
     },
     validator_network::{
-        ConsensusNetworkEvents, ConsensusNetworkSender, MempoolNetworkEvents, MempoolNetworkSender,
-        StateSynchronizerEvents, StateSynchronizerSender,
+        AdmissionControlNetworkEvents, AdmissionControlNetworkSender, ConsensusNetworkEvents,
+        ConsensusNetworkSender, MempoolNetworkEvents, MempoolNetworkSender, NetworkEvents,
+        NetworkSender, StateSynchronizerEvents, StateSynchronizerSender,
     },
     ProtocolId,
 };
 use channel;
-use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+use config::config::{OverflowPolicy, UpstreamChannelConfig};
+use futures::{
+    channel::oneshot, compat::Future01CompatExt, future::BoxFuture, FutureExt, SinkExt, StreamExt,
+};
 use logger::prelude::*;
-use std::{collections::HashMap, fmt::Debug, time::Duration};
+use metrics::{IntCounter, IntGauge};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+use tokio::timer::Delay;
 use types::PeerId;
 
 pub const CONSENSUS_INBOUND_MSG_TIMEOUT_MS: u64 = 60 * 1000; // 1 minute
 pub const MEMPOOL_INBOUND_MSG_TIMEOUT_MS: u64 = 60 * 1000; // 1 minute
 pub const STATE_SYNCHRONIZER_INBOUND_MSG_TIMEOUT_MS: u64 = 60 * 1000; // 1 minute
+pub const ADMISSION_CONTROL_INBOUND_MSG_TIMEOUT_MS: u64 = 60 * 1000; // 1 minute
+pub const PROTOCOL_HANDLER_INBOUND_MSG_TIMEOUT_MS: u64 = 60 * 1000; // 1 minute
 
 /// Requests [`NetworkProvider`] receives from the network interface.
 #[derive(Debug)]
 pub enum NetworkRequest {
     /// Send an RPC request to a remote peer.
     SendRpc(PeerId, OutboundRpcRequest),
-    /// Fire-and-forget style message send to a remote peer.
-    SendMessage(PeerId, Message),
+    /// Fire-and-forget style message send to a remote peer. The message is dropped if it is still
+    /// queued past the optional deadline.
+    SendMessage(PeerId, Message, Option<Instant>),
     /// Update set of nodes eligible to join the network.
     UpdateEligibleNodes(HashMap<PeerId, NetworkPublicKeys>),
 }
@@ -62,6 +75,25 @@ pub enum NetworkNotification {
     RecvMessage(PeerId, Message),
 }
 
+/// Creates the channel over which the network provider delivers notifications to an upstream
+/// component, as configured by `config`.
+fn new_upstream_channel(
+    config: &UpstreamChannelConfig,
+    gauge: &IntGauge,
+    dropped: &IntCounter,
+    timeout: Duration,
+) -> (
+    channel::Sender<NetworkNotification>,
+    channel::Receiver<NetworkNotification>,
+) {
+    let policy = match config.overflow_policy {
+        OverflowPolicy::DropOldest => channel::OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest => channel::OverflowPolicy::DropNewest,
+        OverflowPolicy::Block => channel::OverflowPolicy::Block,
+    };
+    channel::new_with_policy(config.capacity, gauge, dropped, timeout, policy)
+}
+
 /// Trait that any provider of network interface needs to implement.
 pub trait LibraNetworkProvider {
     fn add_mempool(
@@ -76,9 +108,57 @@ pub trait LibraNetworkProvider {
         &mut self,
         state_sync_protocols: Vec<ProtocolId>,
     ) -> (StateSynchronizerSender, StateSynchronizerEvents);
+    fn add_admission_control(
+        &mut self,
+        admission_control_protocols: Vec<ProtocolId>,
+    ) -> (AdmissionControlNetworkSender, AdmissionControlNetworkEvents);
+    /// Returns the sender and events of `protocol`, an application protocol registered with the
+    /// `NetworkBuilder` through `add_protocol_handler` or `add_rpc_protocol_handler`. As for the
+    /// other components, it must be called before the provider is started; the messages of a
+    /// protocol whose handler is never taken are dropped.
+    ///
+    /// Panics if `protocol` was not registered, or if its handler was already taken.
+    fn protocol_handler(&mut self, protocol: ProtocolId) -> (NetworkSender, NetworkEvents);
+    /// Returns the store of the metadata advertised by connected peers.
+    fn peer_metadata(&self) -> PeerMetadataStore;
+    /// Returns a handle to shut the network down gracefully before the node stops.
+    fn shutdown_handle(&self) -> NetworkShutdownHandle;
     fn start(self: Box<Self>) -> BoxFuture<'static, ()>;
 }
 
+/// Handle to shut a network down gracefully, by writing out the messages queued so far and then
+/// draining its connections.
+#[derive(Clone)]
+pub struct NetworkShutdownHandle {
+    ds_reqs_tx: channel::Sender<DirectSendRequest>,
+    peer_mgr: PeerManagerShutdownHandle,
+}
+
+impl NetworkShutdownHandle {
+    /// Waits up to `drain_timeout` for the DirectSend messages queued so far to be written out,
+    /// then drains the connections of PeerManager, which waits up to `drain_timeout` for the rpcs
+    /// of the peers to complete. Returns once all the connections are closed.
+    pub async fn shutdown(&self, drain_timeout: Duration) {
+        let (flush_tx, flush_rx) = oneshot::channel();
+        let mut ds_reqs_tx = self.ds_reqs_tx.clone();
+        if ds_reqs_tx
+            .send(DirectSendRequest::Flush(flush_tx))
+            .await
+            .is_ok()
+        {
+            let mut f_flush = flush_rx.fuse();
+            let mut f_timeout = Delay::new(Instant::now() + drain_timeout).compat().fuse();
+            futures::select! {
+                _ = f_flush => {},
+                _ = f_timeout => {
+                    warn!("Timed out writing out the queued messages");
+                },
+            }
+        }
+        self.peer_mgr.shutdown(drain_timeout).await;
+    }
+}
+
 pub struct NetworkProvider<TSubstream> {
     /// Map from protocol to upstream handlers for events of that protocol type.
     upstream_handlers: HashMap<ProtocolId, channel::Sender<NetworkNotification>>,
@@ -94,6 +174,10 @@ pub struct NetworkProvider<TSubstream> {
     ds_notifs_rx: channel::Receiver<DirectSendNotification>,
     /// Channel over which we send requests to the ConnectivityManager actor.
     conn_mgr_reqs_tx: Option<channel::Sender<ConnectivityRequest>>,
+    /// Metadata of connected peers, kept up to date by PeerManager.
+    peer_metadata: PeerMetadataStore,
+    /// Handle to shut PeerManager down gracefully.
+    shutdown_handle: PeerManagerShutdownHandle,
     /// Channel to receive requests from other actors.
     requests_rx: channel::Receiver<NetworkRequest>,
     /// Channel over which other actors send requests to network.
@@ -105,8 +189,17 @@ pub struct NetworkProvider<TSubstream> {
     /// RPC and Direct Send that can be handled.
     /// Back-pressure takes effect via bounded mpsc channel beyond the limit.
     max_concurrent_notifs: u32,
-    /// Size of channels between different actors.
-    channel_size: usize,
+    /// Configuration of the channels to each upstream component.
+    mempool_channel: UpstreamChannelConfig,
+    consensus_channel: UpstreamChannelConfig,
+    state_sync_channel: UpstreamChannelConfig,
+    /// Number of the peers this network is connected to, on top of the total across all the
+    /// networks of the node.
+    connected_peers: IntGauge,
+    /// Events of each application protocol whose handler is not taken yet. The channels of these
+    /// protocols are created along with the provider, so that every protocol the network
+    /// advertises has a handler.
+    handler_events: HashMap<ProtocolId, channel::Receiver<NetworkNotification>>,
 }
 
 impl<TSubstream> LibraNetworkProvider for NetworkProvider<TSubstream>
@@ -118,9 +211,10 @@ where
         mempool_protocols: Vec<ProtocolId>,
     ) -> (MempoolNetworkSender, MempoolNetworkEvents) {
         // Construct Mempool network interfaces
-        let (mempool_tx, mempool_rx) = channel::new_with_timeout(
-            self.channel_size,
+        let (mempool_tx, mempool_rx) = new_upstream_channel(
+            &self.mempool_channel,
             &counters::PENDING_MEMPOOL_NETWORK_EVENTS,
+            &counters::DROPPED_MEMPOOL_NETWORK_EVENTS,
             Duration::from_millis(MEMPOOL_INBOUND_MSG_TIMEOUT_MS),
         );
         let mempool_network_sender = MempoolNetworkSender::new(self.requests_tx.clone());
@@ -137,9 +231,10 @@ where
         consensus_protocols: Vec<ProtocolId>,
     ) -> (ConsensusNetworkSender, ConsensusNetworkEvents) {
         // Construct Consensus network interfaces
-        let (consensus_tx, consensus_rx) = channel::new_with_timeout(
-            self.channel_size,
+        let (consensus_tx, consensus_rx) = new_upstream_channel(
+            &self.consensus_channel,
             &counters::PENDING_CONSENSUS_NETWORK_EVENTS,
+            &counters::DROPPED_CONSENSUS_NETWORK_EVENTS,
             Duration::from_millis(CONSENSUS_INBOUND_MSG_TIMEOUT_MS),
         );
         let consensus_network_sender = ConsensusNetworkSender::new(self.requests_tx.clone());
@@ -156,12 +251,14 @@ where
         state_sync_protocols: Vec<ProtocolId>,
     ) -> (StateSynchronizerSender, StateSynchronizerEvents) {
         // Construct StateSynchronizer network interfaces
-        let (state_sync_tx, state_sync_rx) = channel::new_with_timeout(
-            self.channel_size,
+        let (state_sync_tx, state_sync_rx) = new_upstream_channel(
+            &self.state_sync_channel,
             &counters::PENDING_STATE_SYNCHRONIZER_NETWORK_EVENTS,
+            &counters::DROPPED_STATE_SYNCHRONIZER_NETWORK_EVENTS,
             Duration::from_millis(STATE_SYNCHRONIZER_INBOUND_MSG_TIMEOUT_MS),
         );
-        let state_sync_network_sender = StateSynchronizerSender::new(self.requests_tx.clone());
+        let state_sync_network_sender = StateSynchronizerSender::new(self.requests_tx.clone())
+            .with_peer_metadata(self.peer_metadata.clone());
         let state_sync_network_events = StateSynchronizerEvents::new(state_sync_rx);
         let state_sync_handlers = state_sync_protocols
             .iter()
@@ -170,7 +267,59 @@ where
         (state_sync_network_sender, state_sync_network_events)
     }
 
+    fn add_admission_control(
+        &mut self,
+        admission_control_protocols: Vec<ProtocolId>,
+    ) -> (AdmissionControlNetworkSender, AdmissionControlNetworkEvents) {
+        // Construct Admission Control network interfaces
+        let (ac_tx, ac_rx) = new_upstream_channel(
+            &UpstreamChannelConfig::default(),
+            &counters::PENDING_ADMISSION_CONTROL_NETWORK_EVENTS,
+            &counters::DROPPED_ADMISSION_CONTROL_NETWORK_EVENTS,
+            Duration::from_millis(ADMISSION_CONTROL_INBOUND_MSG_TIMEOUT_MS),
+        );
+        let ac_network_sender = AdmissionControlNetworkSender::new(self.requests_tx.clone());
+        let ac_network_events = AdmissionControlNetworkEvents::new(ac_rx);
+        let ac_handlers = admission_control_protocols
+            .iter()
+            .map(|p| (p.clone(), ac_tx.clone()));
+        self.upstream_handlers.extend(ac_handlers);
+        (ac_network_sender, ac_network_events)
+    }
+
+    fn protocol_handler(&mut self, protocol: ProtocolId) -> (NetworkSender, NetworkEvents) {
+        let handler_rx = self
+            .handler_events
+            .remove(&protocol)
+            .unwrap_or_else(|| panic!("No handler registered for protocol {:?}", protocol));
+        (
+            NetworkSender::new(protocol, self.requests_tx.clone()),
+            NetworkEvents::new(handler_rx),
+        )
+    }
+
+    fn peer_metadata(&self) -> PeerMetadataStore {
+        self.peer_metadata.clone()
+    }
+
+    fn shutdown_handle(&self) -> NetworkShutdownHandle {
+        NetworkShutdownHandle {
+            ds_reqs_tx: self.ds_reqs_tx.clone(),
+            peer_mgr: self.shutdown_handle.clone(),
+        }
+    }
+
     fn start(self: Box<Self>) -> BoxFuture<'static, ()> {
+        // The messages of the application protocols nobody took the handler of are dropped, as if
+        // the protocols were unknown.
+        let mut upstream_handlers = self.upstream_handlers.clone();
+        for protocol in self.handler_events.keys() {
+            warn!(
+                "The handler of protocol {:?} was not taken, its messages will be dropped",
+                protocol
+            );
+            upstream_handlers.remove(protocol);
+        }
         let f = async move {
             let rpc_reqs_tx = self.rpc_reqs_tx.clone();
             let ds_reqs_tx = self.ds_reqs_tx.clone();
@@ -188,23 +337,28 @@ where
                 })
                 .buffer_unordered(self.max_concurrent_reqs as usize);
 
-            let upstream_handlers = self.upstream_handlers.clone();
+            let peer_mgr_upstream_handlers = upstream_handlers.clone();
+            let connected_peers = self.connected_peers.clone();
             let mut peer_mgr_notifs = self
                 .peer_mgr_notifs_rx
                 .map(move |notif| {
-                    Self::handle_peer_mgr_notification(notif, upstream_handlers.clone()).boxed()
+                    Self::handle_peer_mgr_notification(
+                        notif,
+                        peer_mgr_upstream_handlers.clone(),
+                        connected_peers.clone(),
+                    )
+                    .boxed()
                 })
                 .buffer_unordered(self.max_concurrent_notifs as usize);
 
-            let upstream_handlers = self.upstream_handlers.clone();
+            let rpc_upstream_handlers = upstream_handlers.clone();
             let mut rpc_notifs = self
                 .rpc_notifs_rx
                 .map(move |notif| {
-                    Self::handle_rpc_notification(notif, upstream_handlers.clone()).boxed()
+                    Self::handle_rpc_notification(notif, rpc_upstream_handlers.clone()).boxed()
                 })
                 .buffer_unordered(self.max_concurrent_notifs as usize);
 
-            let upstream_handlers = self.upstream_handlers.clone();
             let mut ds_notifs = self
                 .ds_notifs_rx
                 .map(|notif| Self::handle_ds_notification(upstream_handlers.clone(), notif).boxed())
@@ -238,25 +392,48 @@ where
         ds_reqs_tx: channel::Sender<DirectSendRequest>,
         ds_notifs_rx: channel::Receiver<DirectSendNotification>,
         conn_mgr_reqs_tx: Option<channel::Sender<ConnectivityRequest>>,
+        peer_metadata: PeerMetadataStore,
+        shutdown_handle: PeerManagerShutdownHandle,
         requests_rx: channel::Receiver<NetworkRequest>,
         requests_tx: channel::Sender<NetworkRequest>,
         max_concurrent_reqs: u32,
         max_concurrent_notifs: u32,
-        channel_size: usize,
+        mempool_channel: UpstreamChannelConfig,
+        consensus_channel: UpstreamChannelConfig,
+        state_sync_channel: UpstreamChannelConfig,
+        connected_peers: IntGauge,
+        handler_queue_sizes: HashMap<ProtocolId, usize>,
     ) -> Self {
+        let mut upstream_handlers = HashMap::new();
+        let mut handler_events = HashMap::new();
+        for (protocol, queue_size) in handler_queue_sizes {
+            let (handler_tx, handler_rx) = channel::new_with_timeout(
+                queue_size,
+                &counters::PENDING_PROTOCOL_HANDLER_NETWORK_EVENTS,
+                Duration::from_millis(PROTOCOL_HANDLER_INBOUND_MSG_TIMEOUT_MS),
+            );
+            upstream_handlers.insert(protocol.clone(), handler_tx);
+            handler_events.insert(protocol, handler_rx);
+        }
         Self {
-            upstream_handlers: HashMap::new(),
+            upstream_handlers,
             peer_mgr_notifs_rx,
             rpc_reqs_tx,
             rpc_notifs_rx,
             ds_reqs_tx,
             ds_notifs_rx,
             conn_mgr_reqs_tx,
+            peer_metadata,
+            shutdown_handle,
             requests_rx,
             requests_tx,
             max_concurrent_reqs,
             max_concurrent_notifs,
-            channel_size,
+            mempool_channel,
+            consensus_channel,
+            state_sync_channel,
+            connected_peers,
+            handler_events,
         }
     }
 
@@ -274,11 +451,11 @@ where
                     .await
                     .unwrap();
             }
-            NetworkRequest::SendMessage(peer_id, msg) => {
+            NetworkRequest::SendMessage(peer_id, msg, deadline) => {
                 counters::DIRECT_SEND_MESSAGES_SENT.inc();
                 counters::DIRECT_SEND_BYTES_SENT.inc_by(msg.mdata.len() as i64);
                 ds_reqs_tx
-                    .send(DirectSendRequest::SendMessage(peer_id, msg))
+                    .send(DirectSendRequest::SendMessage(peer_id, msg, deadline))
                     .await
                     .unwrap();
             }
@@ -297,25 +474,42 @@ where
     async fn handle_peer_mgr_notification(
         notif: PeerManagerNotification<TSubstream>,
         mut upstream_handlers: HashMap<ProtocolId, channel::Sender<NetworkNotification>>,
+        connected_peers: IntGauge,
     ) {
         trace!("PeerManagerNotification::{:?}", notif);
         match notif {
             PeerManagerNotification::NewPeer(peer_id, _addr) => {
                 counters::CONNECTED_PEERS.inc();
-                for ch in upstream_handlers.values_mut() {
-                    ch.send(NetworkNotification::NewPeer(peer_id))
-                        .await
-                        .unwrap();
+                connected_peers.inc();
+                for (protocol, ch) in upstream_handlers.iter_mut() {
+                    if let Err(e) = ch.send(NetworkNotification::NewPeer(peer_id)).await {
+                        warn!(
+                            "Failed to notify the handler of {:?} of new peer {}: {:?}",
+                            protocol,
+                            peer_id.short_str(),
+                            e
+                        );
+                    }
                 }
             }
             PeerManagerNotification::LostPeer(peer_id, _addr) => {
                 counters::CONNECTED_PEERS.dec();
-                for ch in upstream_handlers.values_mut() {
-                    ch.send(NetworkNotification::LostPeer(peer_id))
-                        .await
-                        .unwrap();
+                connected_peers.dec();
+                for (protocol, ch) in upstream_handlers.iter_mut() {
+                    if let Err(e) = ch.send(NetworkNotification::LostPeer(peer_id)).await {
+                        warn!(
+                            "Failed to notify the handler of {:?} of lost peer {}: {:?}",
+                            protocol,
+                            peer_id.short_str(),
+                            e
+                        );
+                    }
                 }
             }
+            PeerManagerNotification::PeerAddressChanged(peer_id, addr) => {
+                // The connection was migrated transparently, upstream actors keep their state.
+                debug!("Peer {} migrated to address {}", peer_id.short_str(), addr);
+            }
             _ => {
                 unreachable!("Received unexpected event from PeerManager");
             }
@@ -329,12 +523,29 @@ where
         trace!("RpcNotification::{:?}", notif);
         match notif {
             RpcNotification::RecvRpc(peer_id, req) => {
-                if let Some(ch) = upstream_handlers.get_mut(&req.protocol) {
-                    ch.send(NetworkNotification::RecvRpc(peer_id, req))
-                        .await
-                        .unwrap();
-                } else {
-                    unreachable!();
+                // Dropping an RPC request drops its response channel, which fails the RPC on the
+                // side of the peer.
+                let protocol = req.protocol.clone();
+                match upstream_handlers.get_mut(&protocol) {
+                    Some(ch) => {
+                        if let Err(e) = ch.send(NetworkNotification::RecvRpc(peer_id, req)).await {
+                            warn!(
+                                "Dropped RPC request for protocol {:?} from peer {}: {:?}",
+                                protocol,
+                                peer_id.short_str(),
+                                e
+                            );
+                            counters::UNHANDLED_NETWORK_MESSAGES.inc();
+                        }
+                    }
+                    None => {
+                        warn!(
+                            "Dropped RPC request from peer {}: no handler for protocol {:?}",
+                            peer_id.short_str(),
+                            protocol
+                        );
+                        counters::UNHANDLED_NETWORK_MESSAGES.inc();
+                    }
                 }
             }
         }
@@ -349,12 +560,31 @@ where
             DirectSendNotification::RecvMessage(peer_id, msg) => {
                 counters::DIRECT_SEND_MESSAGES_RECEIVED.inc();
                 counters::DIRECT_SEND_BYTES_RECEIVED.inc_by(msg.mdata.len() as i64);
-                let ch = upstream_handlers
-                    .get_mut(&msg.protocol)
-                    .expect("DirectSend protocol not registered");
-                ch.send(NetworkNotification::RecvMessage(peer_id, msg))
-                    .await
-                    .unwrap();
+                let protocol = msg.protocol.clone();
+                match upstream_handlers.get_mut(&protocol) {
+                    Some(ch) => {
+                        if let Err(e) = ch
+                            .send(NetworkNotification::RecvMessage(peer_id, msg))
+                            .await
+                        {
+                            warn!(
+                                "Dropped message for protocol {:?} from peer {}: {:?}",
+                                protocol,
+                                peer_id.short_str(),
+                                e
+                            );
+                            counters::UNHANDLED_NETWORK_MESSAGES.inc();
+                        }
+                    }
+                    None => {
+                        warn!(
+                            "Dropped message from peer {}: no handler for protocol {:?}",
+                            peer_id.short_str(),
+                            protocol
+                        );
+                        counters::UNHANDLED_NETWORK_MESSAGES.inc();
+                    }
+                }
             }
         }
     }
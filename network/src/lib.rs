@@ -8,11 +8,16 @@
 
 // Public exports
 pub use common::NetworkPublicKeys;
-pub use interface::NetworkProvider;
+pub use error::{NetworkError, NetworkErrorKind};
+pub use interface::{NetworkProvider, NetworkShutdownHandle};
+pub use peer_manager::{PeerManagerShutdownHandle, PeerMetadata, PeerMetadataStore};
+pub use peer_store::{PeerRecord, PeerStore};
+pub use transport::build_tcp_socket_transport;
 
 pub mod interface;
 pub mod proto;
 pub mod protocols;
+pub mod shared_listener;
 pub mod validator_network;
 
 mod common;
@@ -20,9 +25,16 @@ mod connectivity_manager;
 mod counters;
 mod error;
 mod peer_manager;
+mod peer_store;
+mod relay;
 mod sink;
 mod transport;
 mod utils;
 
 /// Type for unique identifier associated with each network protocol
 pub type ProtocolId = bytes::Bytes;
+
+/// Number of the peers the networks of the node are connected to, across all of them.
+pub fn connected_peers() -> i64 {
+    counters::CONNECTED_PEERS.get()
+}
@@ -19,6 +19,9 @@ pub enum PeerManagerError {
     #[fail(display = "Shutting down Peer")]
     ShuttingDownPeer,
 
+    #[fail(display = "Shutting down PeerManager")]
+    ShuttingDown,
+
     #[fail(display = "Not connected with Peer {}", _0)]
     NotConnected(PeerId),
 
@@ -0,0 +1,136 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A substream wrapper which accounts for the bytes sent and received on it.
+//!
+//! PeerManager wraps every substream it hands out once a protocol has been negotiated on it, so
+//! the bandwidth used by each protocol with each remote peer is exported via the metrics endpoint,
+//! independently of how the protocol frames its messages.
+//!
+//! A substream can also hold a guard until it is dropped, which lets the Peer actor wait for the
+//! inbound rpcs in flight to complete before closing the connection on shutdown.
+
+use crate::{counters, ProtocolId};
+use futures::{
+    channel::mpsc,
+    io::{AsyncRead, AsyncWrite},
+};
+use metrics::IntCounter;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use types::PeerId;
+
+pub struct MeteredSubstream<TSubstream> {
+    inner: TSubstream,
+    bytes_sent: IntCounter,
+    bytes_received: IntCounter,
+    _guard: Option<mpsc::UnboundedSender<()>>,
+}
+
+impl<TSubstream> MeteredSubstream<TSubstream> {
+    pub fn new(inner: TSubstream, protocol: &ProtocolId, peer_id: PeerId) -> Self {
+        let protocol = String::from_utf8_lossy(protocol);
+        let peer_id = peer_id.short_str();
+        Self {
+            inner,
+            bytes_sent: counters::OP_COUNTERS.protocol_counter(
+                &counters::PROTOCOL_BYTES_SENT,
+                &protocol,
+                &peer_id,
+            ),
+            bytes_received: counters::OP_COUNTERS.protocol_counter(
+                &counters::PROTOCOL_BYTES_RECEIVED,
+                &protocol,
+                &peer_id,
+            ),
+            _guard: None,
+        }
+    }
+
+    /// Holds `guard` for as long as the substream is alive.
+    pub fn hold(&mut self, guard: mpsc::UnboundedSender<()>) {
+        self._guard = Some(guard);
+    }
+}
+
+impl<TSubstream: fmt::Debug> fmt::Debug for MeteredSubstream<TSubstream> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MeteredSubstream {{ inner: {:?} }}", self.inner)
+    }
+}
+
+impl<TSubstream> AsyncRead for MeteredSubstream<TSubstream>
+where
+    TSubstream: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(context, buf);
+        if let Poll::Ready(Ok(num_bytes)) = poll {
+            self.bytes_received.inc_by(num_bytes as i64);
+        }
+        poll
+    }
+}
+
+impl<TSubstream> AsyncWrite for MeteredSubstream<TSubstream>
+where
+    TSubstream: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(context, buf);
+        if let Poll::Ready(Ok(num_bytes)) = poll {
+            self.bytes_sent.inc_by(num_bytes as i64);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(context)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{
+        executor::block_on,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+    use memsocket::MemorySocket;
+
+    #[test]
+    fn counts_bytes_in_both_directions() {
+        let protocol = ProtocolId::from_static(b"/metered/1.0.0");
+        let (a, b) = MemorySocket::new_pair();
+        let mut a = MeteredSubstream::new(a, &protocol, PeerId::random());
+        let mut b = MeteredSubstream::new(b, &protocol, PeerId::random());
+
+        block_on(async move {
+            a.write_all(b"hello").await.unwrap();
+            a.flush().await.unwrap();
+            let mut buf = [0; 5];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            assert_eq!(a.bytes_sent.get(), 5);
+            assert_eq!(a.bytes_received.get(), 0);
+            assert_eq!(b.bytes_received.get(), 5);
+            assert_eq!(b.bytes_sent.get(), 0);
+        });
+    }
+}
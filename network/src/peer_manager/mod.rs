@@ -12,11 +12,50 @@
 //!  * An actor responsible for dialing and listening for new connections.
 //!  * An actor per Peer which owns the underlying connection and is responsible for listening for
 //!  and opening substreams as well as negotiating particular protocols on those substreams.
-use crate::{common::NegotiatedSubstream, counters, protocols::identity::Identity, ProtocolId};
+//!
+//! Every substream handed out after protocol negotiation is wrapped in a [`MeteredSubstream`],
+//! which accounts the bytes sent and received per protocol and per remote peer.
+//!
+//! The metadata every connected peer advertised in its identity (software version, chain id and
+//! supported protocols) is kept in a [`PeerMetadataStore`] shared with upper layers.
+//!
+//! ## Connection migration
+//!
+//! A known peer may reconnect from a new address (e.g. a validator behind a dynamic cloud IP)
+//! before its previous connection is detected as lost. Since the transport authenticates the
+//! remote identity, a new connection with the same origin as the existing one but from a
+//! different host is treated as a migration rather than a duplicate: the existing connection
+//! is closed and replaced, and subscribers get a [`PeerManagerNotification::PeerAddressChanged`]
+//! instead of a LostPeer/NewPeer pair, so that the logical state they keep for the peer survives.
+//! Outbound substream requests which had not been negotiated on the old connection when it was
+//! closed are retried on the new one. Substreams already handed out are not, as the protocols
+//! using them are not known to be idempotent. A new connection from the same host, which only
+//! differs by its (ephemeral) port, is a duplicate subject to simultaneous dial tie-breaking.
+//!
+//! ## Graceful shutdown
+//!
+//! Through a [`PeerManagerShutdownHandle`], PeerManager can be asked to drain its connections
+//! before the node stops, so that its peers see neither failed rpcs nor an unexpected connection
+//! loss. It then stops accepting new connections and sends a GoAway frame, i.e., an empty
+//! substream negotiated to [`GOAWAY_PROTOCOL`], to every connected peer supporting it. Each Peer
+//! actor stops accepting new inbound substreams and closes its connection once the rpcs the remote
+//! peer sent before the GoAway are completed. Connections still open when the drain timeout
+//! expires are closed right away. Once all the connections are closed, the tasks of the network
+//! are cancelled.
+//!
+//! A peer receiving a GoAway reports the sender as lost to its subscribers immediately and stops
+//! opening new substreams to it, while keeping the connection open until the sender closes it.
+use crate::{
+    common::NegotiatedSubstream, connectivity_manager::subnet, counters,
+    protocols::identity::Identity, ProtocolId,
+};
 use channel;
+use config::config::PeerQueueConfig;
 use futures::{
-    channel::oneshot,
-    future::{BoxFuture, FutureExt, TryFutureExt},
+    channel::{mpsc, oneshot},
+    compat::Future01CompatExt,
+    future::{self, BoxFuture, FutureExt},
+    io::AsyncWriteExt,
     sink::SinkExt,
     stream::{Fuse, FuturesUnordered, StreamExt},
 };
@@ -26,22 +65,44 @@ use netcore::{
     negotiate::{negotiate_inbound, negotiate_outbound_interactive, negotiate_outbound_select},
     transport::{ConnectionOrigin, Transport},
 };
-use parity_multiaddr::Multiaddr;
-use std::{collections::HashMap, marker::PhantomData};
-use tokio::runtime::TaskExecutor;
+use parity_multiaddr::{Multiaddr, Protocol};
+use std::{
+    collections::HashMap,
+    io,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
+use tokio::timer::Delay;
 use types::PeerId;
 
 mod error;
+mod metered_substream;
+mod peer_metadata;
 #[cfg(test)]
 mod tests;
 
-pub use self::error::PeerManagerError;
+pub use self::{
+    error::PeerManagerError,
+    metered_substream::MeteredSubstream,
+    peer_metadata::{PeerMetadata, PeerMetadataStore},
+};
+
+/// Protocol of the substream a peer opens to announce that it is shutting down. Nothing is sent
+/// over the substream.
+pub const GOAWAY_PROTOCOL: &[u8] = b"/libra/goaway/0.1.0";
 
 /// Notifications about new/lost peers.
 #[derive(Debug)]
 pub enum PeerManagerNotification<TSubstream> {
     NewPeer(PeerId, Multiaddr),
     LostPeer(PeerId, Multiaddr),
+    /// A connected peer migrated its connection to a new address.
+    PeerAddressChanged(PeerId, Multiaddr),
     NewInboundSubstream(PeerId, NegotiatedSubstream<TSubstream>),
 }
 
@@ -57,7 +118,7 @@ pub enum PeerManagerRequest<TSubstream> {
     OpenSubstream(
         PeerId,
         ProtocolId,
-        oneshot::Sender<Result<TSubstream, PeerManagerError>>,
+        oneshot::Sender<Result<NegotiatedSubstream<TSubstream>, PeerManagerError>>,
     ),
 }
 
@@ -80,7 +141,8 @@ impl<TSubstream> PeerManagerRequestSender<TSubstream> {
     }
 
     /// Request that a given Peer be dialed at the provided `Multiaddr` and synchronously wait for
-    /// the request to be performed.
+    /// the request to be performed. Dropping the returned future abandons the dial: a connection
+    /// established afterwards is closed right away.
     pub async fn dial_peer(
         &mut self,
         peer_id: PeerId,
@@ -103,11 +165,15 @@ impl<TSubstream> PeerManagerRequestSender<TSubstream> {
 
     /// Request that a new substream be opened with the given Peer and that the provided `protocol`
     /// be negotiated on that substream and synchronously wait for the request to be performed.
+    ///
+    /// If `protocol` is versioned, the highest version of it supported by both ends is negotiated
+    /// instead, which may differ from `protocol`. The returned substream carries the protocol
+    /// which was actually negotiated, so that the caller speaks the matching wire format.
     pub async fn open_substream(
         &mut self,
         peer_id: PeerId,
         protocol: ProtocolId,
-    ) -> Result<TSubstream, PeerManagerError> {
+    ) -> Result<NegotiatedSubstream<TSubstream>, PeerManagerError> {
         let (oneshot_tx, oneshot_rx) = oneshot::channel();
         let request = PeerManagerRequest::OpenSubstream(peer_id, protocol, oneshot_tx);
         self.inner.send(request).await.unwrap();
@@ -119,10 +185,36 @@ impl<TSubstream> PeerManagerRequestSender<TSubstream> {
     }
 }
 
+/// Handle to shut a [`PeerManager`] down gracefully, by draining its connections first.
+#[derive(Clone)]
+pub struct PeerManagerShutdownHandle {
+    inner: mpsc::UnboundedSender<(Duration, oneshot::Sender<()>)>,
+}
+
+impl PeerManagerShutdownHandle {
+    /// Sends a GoAway to all the connected peers, waits up to `drain_timeout` for the rpcs they
+    /// sent us to complete, then closes the connections. Returns once all the connections are
+    /// closed, or right away if PeerManager is gone already.
+    pub async fn shutdown(&self, drain_timeout: Duration) {
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        if self
+            .inner
+            .unbounded_send((drain_timeout, oneshot_tx))
+            .is_ok()
+        {
+            // PeerManager may stop before all the connections are closed, e.g., if its runtime is
+            // shut down.
+            let _ = oneshot_rx.await;
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum DisconnectReason {
     Requested,
     ConnectionLost,
+    /// The connection was replaced by a new connection from the same peer at another address.
+    Migrated,
 }
 
 #[derive(Debug)]
@@ -131,8 +223,23 @@ where
     TMuxer: StreamMultiplexer,
 {
     NewConnection(Identity, Multiaddr, ConnectionOrigin, TMuxer),
-    NewSubstream(PeerId, NegotiatedSubstream<TMuxer::Substream>),
-    PeerDisconnected(PeerId, ConnectionOrigin, DisconnectReason),
+    NewSubstream(
+        PeerId,
+        NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>,
+    ),
+    PeerDisconnected(PeerId, Multiaddr, ConnectionOrigin, DisconnectReason),
+    /// The remote peer of a connection sent a GoAway.
+    PeerGoingAway(PeerId, Multiaddr, ConnectionOrigin),
+    /// The drain of the connections on shutdown took too long.
+    DrainTimedOut,
+    /// An outbound substream request which could not be served by a migrated connection.
+    RetryOutboundSubstream(
+        PeerId,
+        ProtocolId,
+        oneshot::Sender<
+            Result<NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>, PeerManagerError>,
+        >,
+    ),
 }
 
 /// Responsible for handling and maintaining connections to other Peers
@@ -141,8 +248,9 @@ where
     TTransport: Transport,
     TMuxer: StreamMultiplexer,
 {
-    /// A handle to a tokio executor.
-    executor: TaskExecutor,
+    /// Task manager to spawn the connection listener and the Peer actors. Its tasks are cancelled
+    /// once the connections are drained or PeerManager terminates.
+    task_manager: TaskManager,
     /// PeerId of "self".
     own_peer_id: PeerId,
     /// Address to listen on for incoming connections.
@@ -150,15 +258,23 @@ where
     /// Connection Listener, listening on `listen_addr`
     connection_handler: Option<ConnectionHandler<TTransport, TMuxer>>,
     /// Map from PeerId to corresponding Peer object.
-    active_peers: HashMap<PeerId, PeerHandle<TMuxer::Substream>>,
+    active_peers: HashMap<PeerId, PeerHandle<MeteredSubstream<TMuxer::Substream>>>,
+    /// Metadata of the peers in `active_peers`.
+    peer_metadata: PeerMetadataStore,
     /// Channel to receive requests from other actors.
-    requests_rx: channel::Receiver<PeerManagerRequest<TMuxer::Substream>>,
+    requests_rx: channel::Receiver<PeerManagerRequest<MeteredSubstream<TMuxer::Substream>>>,
     /// Map from protocol to handler for substreams which want to "speak" that protocol.
-    protocol_handlers:
-        HashMap<ProtocolId, channel::Sender<PeerManagerNotification<TMuxer::Substream>>>,
+    protocol_handlers: HashMap<
+        ProtocolId,
+        channel::Sender<PeerManagerNotification<MeteredSubstream<TMuxer::Substream>>>,
+    >,
+    /// Protocols whose inbound substreams each carry a single rpc. The substreams of these
+    /// protocols are completed before closing a connection on shutdown.
+    rpc_protocols: Vec<ProtocolId>,
     /// Channel to send NewPeer/LostPeer notifications to other actors.
     /// Note: NewInboundSubstream notifications are not sent via these channels.
-    peer_event_handlers: Vec<channel::Sender<PeerManagerNotification<TMuxer::Substream>>>,
+    peer_event_handlers:
+        Vec<channel::Sender<PeerManagerNotification<MeteredSubstream<TMuxer::Substream>>>>,
     /// Channel used to send Dial requests to the ConnectionHandler actor
     dial_request_tx: channel::Sender<ConnectionHandlerRequest>,
     /// Internal event Receiver
@@ -167,6 +283,17 @@ where
     internal_event_tx: channel::Sender<InternalEvent<TMuxer>>,
     /// A map of outstanding disconnect requests
     outstanding_disconnect_requests: HashMap<PeerId, oneshot::Sender<Result<(), PeerManagerError>>>,
+    /// Channel to receive shutdown requests from [`PeerManagerShutdownHandle`]s.
+    shutdown_rx: mpsc::UnboundedReceiver<(Duration, oneshot::Sender<()>)>,
+    shutdown_tx: mpsc::UnboundedSender<(Duration, oneshot::Sender<()>)>,
+    /// Size of the queue of requests to each peer, and how long it may stay full.
+    peer_queue: PeerQueueConfig,
+    /// Maximum number of inbound connections from peers in the same subnet, if any.
+    max_inbound_per_subnet: Option<usize>,
+    /// Set once the connections are being drained for shutdown.
+    is_draining: bool,
+    /// Shutdown requests to answer once all the connections are drained.
+    drain_response_txs: Vec<oneshot::Sender<()>>,
     /// Pin the transport type corresponding to this PeerManager instance
     phantom_transport: PhantomData<TTransport>,
 }
@@ -179,15 +306,20 @@ where
     /// Construct a new PeerManager actor
     pub fn new(
         transport: TTransport,
-        executor: TaskExecutor,
+        task_manager: TaskManager,
         own_peer_id: PeerId,
         listen_addr: Multiaddr,
-        requests_rx: channel::Receiver<PeerManagerRequest<TMuxer::Substream>>,
+        peer_metadata: PeerMetadataStore,
+        requests_rx: channel::Receiver<PeerManagerRequest<MeteredSubstream<TMuxer::Substream>>>,
         protocol_handlers: HashMap<
             ProtocolId,
-            channel::Sender<PeerManagerNotification<TMuxer::Substream>>,
+            channel::Sender<PeerManagerNotification<MeteredSubstream<TMuxer::Substream>>>,
         >,
-        peer_event_handlers: Vec<channel::Sender<PeerManagerNotification<TMuxer::Substream>>>,
+        rpc_protocols: Vec<ProtocolId>,
+        peer_event_handlers: Vec<
+            channel::Sender<PeerManagerNotification<MeteredSubstream<TMuxer::Substream>>>,
+        >,
+        peer_queue: PeerQueueConfig,
     ) -> Self {
         let (internal_event_tx, internal_event_rx) =
             channel::new(1024, &counters::PENDING_PEER_MANAGER_INTERNAL_EVENTS);
@@ -199,29 +331,52 @@ where
             dial_request_rx,
             internal_event_tx.clone(),
         );
+        let (shutdown_tx, shutdown_rx) = mpsc::unbounded();
 
         Self {
-            executor,
+            task_manager,
             own_peer_id,
             listen_addr,
             connection_handler: Some(connection_handler),
             active_peers: HashMap::new(),
+            peer_metadata,
             requests_rx,
             protocol_handlers,
+            rpc_protocols,
             peer_event_handlers,
             dial_request_tx,
             internal_event_tx,
             internal_event_rx,
             outstanding_disconnect_requests: HashMap::new(),
+            shutdown_rx,
+            shutdown_tx,
+            peer_queue,
+            max_inbound_per_subnet: None,
+            is_draining: false,
+            drain_response_txs: Vec::new(),
             phantom_transport: PhantomData,
         }
     }
 
+    /// Limit the number of connections that peers in the same subnet may initiate to us, so that
+    /// an attacker with many addresses in few subnets cannot take all our connections.
+    pub fn with_max_inbound_per_subnet(mut self, max_inbound_per_subnet: Option<usize>) -> Self {
+        self.max_inbound_per_subnet = max_inbound_per_subnet;
+        self
+    }
+
     /// Get the [`Multiaddr`] we're listening for incoming connections on
     pub fn listen_addr(&self) -> &Multiaddr {
         &self.listen_addr
     }
 
+    /// Get a handle to shut this PeerManager down gracefully.
+    pub fn shutdown_handle(&self) -> PeerManagerShutdownHandle {
+        PeerManagerShutdownHandle {
+            inner: self.shutdown_tx.clone(),
+        }
+    }
+
     /// Start listening on the set address and return a future which runs PeerManager
     pub async fn start(mut self) {
         // Start listening for connections.
@@ -238,12 +393,18 @@ where
                         self.handle_request(request).await;
                     }
                 }
+                maybe_shutdown = self.shutdown_rx.next() => {
+                    if let Some((drain_timeout, response_tx)) = maybe_shutdown {
+                        self.drain(drain_timeout, response_tx).await;
+                    }
+                }
                 complete => {
                     crit!("Peer manager actor terminated");
                     break;
                 }
             }
         }
+        self.task_manager.shutdown();
     }
 
     async fn handle_internal_event(&mut self, event: InternalEvent<TMuxer>) {
@@ -260,7 +421,12 @@ where
                 let event = PeerManagerNotification::NewInboundSubstream(peer_id, substream);
                 ch.send(event).await.unwrap();
             }
-            InternalEvent::PeerDisconnected(peer_id, origin, _reason) => {
+            InternalEvent::PeerDisconnected(peer_id, address, origin, reason) => {
+                // A migrated connection has already been replaced in `add_peer`, possibly by a
+                // connection which is gone by now as well.
+                if reason == DisconnectReason::Migrated {
+                    return;
+                }
                 let peer = self
                     .active_peers
                     .remove(&peer_id)
@@ -269,37 +435,100 @@ where
                 // If we receive a PeerDisconnected event and the connection origin isn't the same
                 // as the one we have stored in PeerManager this particular event is from a Peer
                 // actor which is being shutdown due to simultaneous dial tie-breaking and we don't
-                // need to send a LostPeer notification to all subscribers.
-                if peer.origin != origin {
+                // need to send a LostPeer notification to all subscribers. Likewise if the address
+                // differs, the event is from a connection which has been migrated.
+                if peer.origin != origin || peer.address != address {
                     self.active_peers.insert(peer_id, peer);
                     return;
                 }
                 info!("Disconnected from peer: {}", peer_id.short_str());
+                self.peer_metadata.remove(&peer_id);
                 if let Some(oneshot_tx) = self.outstanding_disconnect_requests.remove(&peer_id) {
                     if oneshot_tx.send(Ok(())).is_err() {
                         error!("oneshot channel receiver dropped");
                     }
                 }
-                // Send LostPeer notifications to subscribers
+                // Send LostPeer notifications to subscribers, unless they got one already when
+                // the peer sent a GoAway.
+                if !peer.is_going_away() {
+                    for ch in &mut self.peer_event_handlers {
+                        ch.send(PeerManagerNotification::LostPeer(
+                            peer_id,
+                            peer.address().clone(),
+                        ))
+                        .await
+                        .unwrap();
+                    }
+                }
+                self.complete_drain_if_done();
+            }
+            InternalEvent::PeerGoingAway(peer_id, address, origin) => {
+                // Ignore a GoAway sent over a connection which has been replaced since.
+                match self.active_peers.get_mut(&peer_id) {
+                    Some(peer)
+                        if peer.origin == origin
+                            && peer.address == address
+                            && !peer.is_going_away() =>
+                    {
+                        peer.set_going_away();
+                    }
+                    _ => return,
+                }
+                info!("Peer {} is going away", peer_id.short_str());
+                counters::PEER_GOAWAYS_RECEIVED.inc();
                 for ch in &mut self.peer_event_handlers {
-                    ch.send(PeerManagerNotification::LostPeer(
-                        peer_id,
-                        peer.address().clone(),
-                    ))
-                    .await
-                    .unwrap();
+                    ch.send(PeerManagerNotification::LostPeer(peer_id, address.clone()))
+                        .await
+                        .unwrap();
                 }
             }
+            InternalEvent::DrainTimedOut => {
+                if !self.active_peers.is_empty() {
+                    warn!(
+                        "Timed out draining connections, closing the {} left",
+                        self.active_peers.len()
+                    );
+                }
+                for peer in self.active_peers.values_mut() {
+                    peer.disconnect();
+                }
+            }
+            InternalEvent::RetryOutboundSubstream(peer_id, protocol, response_tx) => {
+                debug!(
+                    "Retrying outbound substream '{:?}' with migrated Peer {}",
+                    protocol,
+                    peer_id.short_str()
+                );
+                self.handle_request(PeerManagerRequest::OpenSubstream(
+                    peer_id,
+                    protocol,
+                    response_tx,
+                ))
+                .await;
+            }
         }
     }
 
-    async fn handle_request(&mut self, request: PeerManagerRequest<TMuxer::Substream>) {
+    async fn handle_request(
+        &mut self,
+        request: PeerManagerRequest<MeteredSubstream<TMuxer::Substream>>,
+    ) {
         trace!("PeerManagerRequest::{:?}", request);
         match request {
             PeerManagerRequest::DialPeer(requested_peer_id, addr, response_tx) => {
                 // Only dial peers which we aren't already connected with
-                if let Some(peer) = self.active_peers.get(&requested_peer_id) {
-                    let error = if peer.is_shutting_down() {
+                if self.is_draining {
+                    if response_tx
+                        .send(Err(PeerManagerError::ShuttingDown))
+                        .is_err()
+                    {
+                        warn!(
+                            "Receiver for DialPeer {} dropped",
+                            requested_peer_id.short_str()
+                        );
+                    }
+                } else if let Some(peer) = self.active_peers.get(&requested_peer_id) {
+                    let error = if peer.is_shutting_down() || peer.is_going_away() {
                         PeerManagerError::ShuttingDownPeer
                     } else {
                         PeerManagerError::AlreadyConnected(peer.address().to_owned())
@@ -326,8 +555,18 @@ where
             }
             PeerManagerRequest::OpenSubstream(peer_id, protocol, request_tx) => {
                 match self.active_peers.get_mut(&peer_id) {
-                    Some(ref mut peer) if !peer.is_shutting_down() => {
-                        peer.open_substream(protocol, request_tx).await;
+                    Some(ref mut peer) if !peer.is_shutting_down() && !peer.is_going_away() => {
+                        if let Some(full_duration) = peer.open_substream(protocol, request_tx) {
+                            if full_duration >= self.peer_queue_max_full_duration() {
+                                warn!(
+                                    "Disconnecting from peer {} whose queue has been full for {:?}",
+                                    peer_id.short_str(),
+                                    full_duration
+                                );
+                                counters::PEERS_DISCONNECTED_QUEUE_FULL.inc();
+                                peer.disconnect();
+                            }
+                        }
                     }
                     _ => {
                         // If we don't have a connection open with this peer, or if the connection
@@ -348,13 +587,62 @@ where
         }
     }
 
+    fn peer_queue_max_full_duration(&self) -> Duration {
+        Duration::from_millis(self.peer_queue.max_full_duration_ms)
+    }
+
+    /// Starts draining the connections for shutdown. `response_tx` is notified once they are all
+    /// closed.
+    async fn drain(&mut self, drain_timeout: Duration, response_tx: oneshot::Sender<()>) {
+        self.drain_response_txs.push(response_tx);
+        if self.is_draining {
+            self.complete_drain_if_done();
+            return;
+        }
+        info!(
+            "Draining connections with {} peers",
+            self.active_peers.len()
+        );
+        self.is_draining = true;
+        for peer in self.active_peers.values_mut() {
+            if !peer.is_shutting_down() {
+                peer.go_away();
+            }
+        }
+
+        let mut internal_event_tx = self.internal_event_tx.clone();
+        let drain_timer = async move {
+            if let Err(e) = Delay::new(Instant::now() + drain_timeout).compat().await {
+                error!("Drain timer failed: {:?}", e);
+            }
+            // PeerManager may be gone already.
+            let _ = internal_event_tx.send(InternalEvent::DrainTimedOut).await;
+        };
+        self.task_manager.spawn("drain_timer", drain_timer);
+        self.complete_drain_if_done();
+    }
+
+    fn complete_drain_if_done(&mut self) {
+        if !self.is_draining || !self.active_peers.is_empty() {
+            return;
+        }
+        for response_tx in self.drain_response_txs.drain(..) {
+            if response_tx.send(()).is_err() {
+                warn!("Receiver for shutdown request dropped");
+            }
+        }
+        // The network is going down: stop the connection listener and the other network tasks
+        // registered with the same token.
+        self.task_manager.shutdown();
+    }
+
     fn start_connection_listener(&mut self) {
         let connection_handler = self
             .connection_handler
             .take()
             .expect("Connection handler already taken");
-        self.executor
-            .spawn(connection_handler.listen().boxed().unit_error().compat());
+        self.task_manager
+            .spawn("connection_listener", connection_handler.listen());
     }
 
     /// In the event two peers simultaneously dial each other we need to be able to do
@@ -380,6 +668,51 @@ where
         }
     }
 
+    /// A new connection replaces an existing one as a migration, rather than as a duplicate, if
+    /// it has the same origin but the address of the peer changed. The TCP ports are left out of
+    /// the comparison, since a peer dials from a new ephemeral port every time. Connections from
+    /// the same host or with different origins are subject to simultaneous dial tie-breaking
+    /// instead.
+    fn is_connection_migration(
+        existing_origin: ConnectionOrigin,
+        existing_address: &Multiaddr,
+        new_origin: ConnectionOrigin,
+        new_address: &Multiaddr,
+    ) -> bool {
+        let host = |address: &Multiaddr| {
+            address
+                .iter()
+                .filter(|protocol| match protocol {
+                    Protocol::Tcp(_) => false,
+                    _ => true,
+                })
+                .fold(Multiaddr::empty(), Multiaddr::with)
+        };
+        existing_origin == new_origin && host(existing_address) != host(new_address)
+    }
+
+    /// Returns true if the subnet of `address` already has its share of the connections peers
+    /// initiate to us. The connection `peer_id` may already have does not count, so that a peer
+    /// can always reconnect.
+    fn is_inbound_subnet_full(&self, peer_id: PeerId, address: &Multiaddr) -> bool {
+        let (max_inbound_per_subnet, new_subnet) =
+            match (self.max_inbound_per_subnet, subnet(address)) {
+                (Some(max_inbound_per_subnet), Some(new_subnet)) => {
+                    (max_inbound_per_subnet, new_subnet)
+                }
+                _ => return false,
+            };
+        self.active_peers
+            .iter()
+            .filter(|(other_peer_id, peer)| {
+                **other_peer_id != peer_id
+                    && peer.origin() == ConnectionOrigin::Inbound
+                    && subnet(peer.address()) == Some(new_subnet)
+            })
+            .count()
+            >= max_inbound_per_subnet
+    }
+
     async fn add_peer(
         &mut self,
         identity: Identity,
@@ -391,17 +724,71 @@ where
         assert_ne!(self.own_peer_id, peer_id);
 
         let mut send_new_peer_notification = true;
+        let mut send_address_changed_notification = false;
+
+        if self.is_draining {
+            connection.close().await.unwrap_or_else(|e| {
+                error!(
+                    "Closing connection with Peer {} failed with error: {}",
+                    peer_id.short_str(),
+                    e
+                )
+            });
+            info!(
+                "Closing new connection with Peer {} since we are shutting down",
+                peer_id.short_str()
+            );
+            return;
+        }
+
+        if origin == ConnectionOrigin::Inbound && self.is_inbound_subnet_full(peer_id, &address) {
+            connection.close().await.unwrap_or_else(|e| {
+                error!(
+                    "Closing connection with Peer {} failed with error: {}",
+                    peer_id.short_str(),
+                    e
+                )
+            });
+            info!(
+                "Closing incoming connection with Peer {} since its subnet has too many connections",
+                peer_id.short_str()
+            );
+            counters::INBOUND_CONNECTIONS_REJECTED_FOR_DIVERSITY.inc();
+            return;
+        }
 
-        // Check for and handle simultaneous dialing
+        // Check for and handle connection migration and simultaneous dialing
         if let Some(mut peer) = self.active_peers.remove(&peer_id) {
-            if Self::simultaneous_dial_tie_breaking(
+            if peer.is_going_away() {
+                // The peer came back, e.g., after a restart, before its previous connection is
+                // closed. Subscribers have been told about the loss of the peer already, so the
+                // new connection is reported as a new peer.
+                peer.migrate();
+                info!(
+                    "Replacing connection with Peer {} which went away",
+                    peer_id.short_str()
+                );
+            } else if Self::is_connection_migration(peer.origin(), peer.address(), origin, &address)
+            {
+                // Drop the existing connection and replace it with the new connection
+                peer.migrate();
+                info!(
+                    "Migrating connection with Peer {} from {} to {}",
+                    peer_id.short_str(),
+                    peer.address(),
+                    address
+                );
+                counters::PEER_CONNECTION_MIGRATIONS.inc();
+                send_new_peer_notification = false;
+                send_address_changed_notification = true;
+            } else if Self::simultaneous_dial_tie_breaking(
                 self.own_peer_id,
                 peer.peer_id(),
                 peer.origin(),
                 origin,
             ) {
                 // Drop the existing connection and replace it with the new connection
-                peer.disconnect().await;
+                peer.disconnect();
                 info!(
                     "Closing existing connection with Peer {} to mitigate simultaneous dial",
                     peer_id.short_str()
@@ -427,27 +814,39 @@ where
         }
 
         let (peer_req_tx, peer_req_rx) = channel::new(
-            1024,
+            self.peer_queue.capacity,
             &counters::OP_COUNTERS
                 .peer_gauge(&counters::PENDING_PEER_REQUESTS, &peer_id.short_str()),
         );
+        let (peer_control_tx, peer_control_rx) = mpsc::unbounded();
+        self.peer_metadata.insert(peer_id, PeerMetadata::from(&identity));
+        let mut own_supported_protocols: Vec<_> = self.protocol_handlers.keys().cloned().collect();
+        own_supported_protocols.push(ProtocolId::from_static(GOAWAY_PROTOCOL));
         let peer = Peer::new(
             identity,
+            address.clone(),
             connection,
             origin,
-            self.protocol_handlers.keys().cloned().collect(),
+            own_supported_protocols,
+            self.rpc_protocols.clone(),
             self.internal_event_tx.clone(),
             peer_req_rx,
+            peer_control_rx,
+        );
+        let peer_handle = PeerHandle::new(
+            peer_id,
+            address.clone(),
+            origin,
+            peer_req_tx,
+            peer_control_tx,
         );
-        let peer_handle = PeerHandle::new(peer_id, address.clone(), origin, peer_req_tx);
         info!(
             "{:?} connection with peer {} established",
             origin,
             peer_id.short_str()
         );
         self.active_peers.insert(peer_id, peer_handle);
-        self.executor
-            .spawn(peer.start().boxed().unit_error().compat());
+        self.task_manager.spawn("peer", peer.start());
 
         if send_new_peer_notification {
             for ch in &mut self.peer_event_handlers {
@@ -455,6 +854,15 @@ where
                     .await
                     .unwrap();
             }
+        } else if send_address_changed_notification {
+            for ch in &mut self.peer_event_handlers {
+                ch.send(PeerManagerNotification::PeerAddressChanged(
+                    peer_id,
+                    address.clone(),
+                ))
+                .await
+                .unwrap();
+            }
         }
     }
 
@@ -475,7 +883,7 @@ where
         response_tx: oneshot::Sender<Result<(), PeerManagerError>>,
     ) {
         if let Some(peer) = self.active_peers.get_mut(&peer_id) {
-            peer.disconnect().await;
+            peer.disconnect();
             self.outstanding_disconnect_requests
                 .insert(peer_id, response_tx);
         } else if response_tx
@@ -626,6 +1034,22 @@ where
         response_tx: oneshot::Sender<Result<(), PeerManagerError>>,
     ) {
         match upgrade {
+            Ok((identity, connection)) if response_tx.is_canceled() => {
+                // Whoever asked for the dial is no longer interested, e.g., because the peer was
+                // reached at another of its addresses in the meantime.
+                info!(
+                    "Dropping connection to Peer {} at {} since the dial was abandoned",
+                    identity.peer_id().short_str(),
+                    addr
+                );
+                connection.close().await.unwrap_or_else(|e| {
+                    error!(
+                        "Closing connection with Peer {} failed with error: {}",
+                        identity.peer_id().short_str(),
+                        e
+                    )
+                });
+            }
             Ok((identity, connection)) => {
                 let response = if identity.peer_id() == peer_id {
                     debug!(
@@ -701,12 +1125,22 @@ where
     }
 }
 
+/// Handle to the actor of a connected peer.
+///
+/// Requests to open substreams go through a bounded queue of their own for each peer and are
+/// dropped when it is full, so that a slow peer never holds up PeerManager. Requests to close the
+/// connection go through a separate queue, so that they get through even then.
 struct PeerHandle<TSubstream> {
     peer_id: PeerId,
     sender: channel::Sender<PeerRequest<TSubstream>>,
+    control_sender: mpsc::UnboundedSender<PeerRequest<TSubstream>>,
+    /// Time since which the queue of `sender` has been full, if it is.
+    full_since: Option<Instant>,
     origin: ConnectionOrigin,
     address: Multiaddr,
     is_shutting_down: bool,
+    /// Set once the remote peer sent a GoAway.
+    is_going_away: bool,
 }
 
 impl<TSubstream> PeerHandle<TSubstream> {
@@ -715,13 +1149,17 @@ impl<TSubstream> PeerHandle<TSubstream> {
         address: Multiaddr,
         origin: ConnectionOrigin,
         sender: channel::Sender<PeerRequest<TSubstream>>,
+        control_sender: mpsc::UnboundedSender<PeerRequest<TSubstream>>,
     ) -> Self {
         Self {
             peer_id,
             address,
             origin,
             sender,
+            control_sender,
+            full_since: None,
             is_shutting_down: false,
+            is_going_away: false,
         }
     }
 
@@ -729,6 +1167,14 @@ impl<TSubstream> PeerHandle<TSubstream> {
         self.is_shutting_down
     }
 
+    pub fn is_going_away(&self) -> bool {
+        self.is_going_away
+    }
+
+    pub fn set_going_away(&mut self) {
+        self.is_going_away = true;
+    }
+
     pub fn address(&self) -> &Multiaddr {
         &self.address
     }
@@ -741,41 +1187,69 @@ impl<TSubstream> PeerHandle<TSubstream> {
         self.origin
     }
 
-    pub async fn open_substream(
+    /// Queues a request to open a substream with the peer. If the queue of the peer is full, the
+    /// request is dropped, which the requester sees as the peer not being connected, and the time
+    /// the queue has been full for is returned.
+    pub fn open_substream(
         &mut self,
         protocol: ProtocolId,
-        response_tx: oneshot::Sender<Result<TSubstream, PeerManagerError>>,
-    ) {
-        // If we fail to send the request to the Peer, then it must have already been shutdown.
-        if self
+        response_tx: oneshot::Sender<Result<NegotiatedSubstream<TSubstream>, PeerManagerError>>,
+    ) -> Option<Duration> {
+        let protocol_name = String::from_utf8_lossy(&protocol).into_owned();
+        match self
             .sender
-            .send(PeerRequest::OpenSubstream(protocol, response_tx))
-            .await
-            .is_err()
+            .try_send(PeerRequest::OpenSubstream(protocol, response_tx))
         {
-            error!(
-                "Sending OpenSubstream request to Peer {} \
-                 failed because it has already been shutdown.",
-                self.peer_id.short_str()
-            );
+            Ok(()) => {
+                self.full_since = None;
+                None
+            }
+            Err(e) if e.is_full() => {
+                counters::OP_COUNTERS
+                    .protocol_counter(
+                        &counters::PEER_REQUESTS_DROPPED,
+                        &protocol_name,
+                        &self.peer_id.short_str(),
+                    )
+                    .inc();
+                let full_since = *self.full_since.get_or_insert_with(Instant::now);
+                Some(full_since.elapsed())
+            }
+            // If we fail to send the request to the Peer, then it must have already been shutdown.
+            Err(_) => {
+                error!(
+                    "Sending OpenSubstream request to Peer {} \
+                     failed because it has already been shutdown.",
+                    self.peer_id.short_str()
+                );
+                None
+            }
         }
     }
 
-    pub async fn disconnect(&mut self) {
+    pub fn disconnect(&mut self) {
+        self.send_control_request(PeerRequest::CloseConnection);
+        self.is_shutting_down = true;
+    }
+
+    pub fn migrate(&mut self) {
+        self.send_control_request(PeerRequest::Migrate);
+        self.is_shutting_down = true;
+    }
+
+    pub fn go_away(&mut self) {
+        self.send_control_request(PeerRequest::GoAway);
+        self.is_shutting_down = true;
+    }
+
+    fn send_control_request(&mut self, request: PeerRequest<TSubstream>) {
         // If we fail to send the request to the Peer, then it must have already been shutdown.
-        if self
-            .sender
-            .send(PeerRequest::CloseConnection)
-            .await
-            .is_err()
-        {
+        if self.control_sender.unbounded_send(request).is_err() {
             error!(
-                "Sending CloseConnection request to Peer {} \
-                 failed because it has already been shutdown.",
+                "Sending request to Peer {} failed because it has already been shutdown.",
                 self.peer_id.short_str()
             );
         }
-        self.is_shutting_down = true;
     }
 }
 
@@ -783,9 +1257,13 @@ impl<TSubstream> PeerHandle<TSubstream> {
 enum PeerRequest<TSubstream> {
     OpenSubstream(
         ProtocolId,
-        oneshot::Sender<Result<TSubstream, PeerManagerError>>,
+        oneshot::Sender<Result<NegotiatedSubstream<TSubstream>, PeerManagerError>>,
     ),
     CloseConnection,
+    /// Close the connection because it is replaced by a new connection with the same peer.
+    Migrate,
+    /// Send a GoAway to the peer, then close the connection once the rpcs in flight are completed.
+    GoAway,
 }
 
 struct Peer<TMuxer>
@@ -794,12 +1272,28 @@ where
 {
     /// Identity of the remote peer
     identity: Identity,
+    /// Address of the remote peer
+    address: Multiaddr,
     connection: TMuxer,
     own_supported_protocols: Vec<ProtocolId>,
+    /// Protocols whose inbound substreams each carry a single rpc.
+    rpc_protocols: Vec<ProtocolId>,
     internal_event_tx: channel::Sender<InternalEvent<TMuxer>>,
-    requests_rx: channel::Receiver<PeerRequest<TMuxer::Substream>>,
+    requests_rx: channel::Receiver<PeerRequest<MeteredSubstream<TMuxer::Substream>>>,
+    /// Requests to close the connection, which are not queued behind the other requests.
+    control_rx: mpsc::UnboundedReceiver<PeerRequest<MeteredSubstream<TMuxer::Substream>>>,
     origin: ConnectionOrigin,
     shutdown: bool,
+    /// Set once this connection has been replaced by a new connection with the same peer.
+    /// Shared with the pending outbound substream negotiations so they get retried on the new
+    /// connection.
+    migrated: Arc<AtomicBool>,
+    /// Set once a GoAway was sent to the peer. No inbound substreams are accepted anymore.
+    is_draining: bool,
+    /// Every inbound rpc substream holds a clone of this sender, so that `in_flight_rx` ends once
+    /// all of them are dropped after it is released on GoAway.
+    in_flight_tx: Option<mpsc::UnboundedSender<()>>,
+    in_flight_rx: mpsc::UnboundedReceiver<()>,
 }
 
 impl<TMuxer> Peer<TMuxer>
@@ -810,20 +1304,31 @@ where
 {
     fn new(
         identity: Identity,
+        address: Multiaddr,
         connection: TMuxer,
         origin: ConnectionOrigin,
         own_supported_protocols: Vec<ProtocolId>,
+        rpc_protocols: Vec<ProtocolId>,
         internal_event_tx: channel::Sender<InternalEvent<TMuxer>>,
-        requests_rx: channel::Receiver<PeerRequest<TMuxer::Substream>>,
+        requests_rx: channel::Receiver<PeerRequest<MeteredSubstream<TMuxer::Substream>>>,
+        control_rx: mpsc::UnboundedReceiver<PeerRequest<MeteredSubstream<TMuxer::Substream>>>,
     ) -> Self {
+        let (in_flight_tx, in_flight_rx) = mpsc::unbounded();
         Self {
             identity,
+            address,
             connection,
             origin,
             own_supported_protocols,
+            rpc_protocols,
             internal_event_tx,
             requests_rx,
+            control_rx,
             shutdown: false,
+            migrated: Arc::new(AtomicBool::new(false)),
+            is_draining: false,
+            in_flight_tx: Some(in_flight_tx),
+            in_flight_rx,
         }
     }
 
@@ -847,10 +1352,27 @@ where
                         );
                     }
                 },
+                maybe_req = self.control_rx.next() => {
+                    if let Some(request) = maybe_req {
+                        self.handle_request(&mut pending_outbound_substreams, request).await;
+                    } else {
+                        unreachable!(
+                            "Peer {} control PeerRequest sender gets dropped",
+                            self.identity.peer_id().short_str()
+                        );
+                    }
+                },
                 maybe_substream = substream_rx.next() => {
                     match maybe_substream {
                         Some(Ok(substream)) => {
-                            self.handle_inbound_substream(&mut pending_inbound_substreams, substream);
+                            if self.is_draining {
+                                debug!(
+                                    "Dropping inbound substream from peer {} after GoAway",
+                                    self.identity.peer_id().short_str()
+                                );
+                            } else {
+                                self.handle_inbound_substream(&mut pending_inbound_substreams, substream);
+                            }
                         }
                         Some(Err(e)) => {
                             warn!("Inbound substream error {:?} with peer {}",
@@ -867,10 +1389,18 @@ where
                 inbound_substream = pending_inbound_substreams.select_next_some() => {
                     match inbound_substream {
                         Ok(negotiated_substream) => {
-                            let event = InternalEvent::NewSubstream(
-                                self.identity.peer_id(),
-                                negotiated_substream,
-                            );
+                            let event = if negotiated_substream.protocol.as_ref() == GOAWAY_PROTOCOL {
+                                InternalEvent::PeerGoingAway(
+                                    self.identity.peer_id(),
+                                    self.address.clone(),
+                                    self.origin,
+                                )
+                            } else {
+                                InternalEvent::NewSubstream(
+                                    self.identity.peer_id(),
+                                    negotiated_substream,
+                                )
+                            };
                             self.internal_event_tx.send(event).await.unwrap();
                         }
                         Err(e) => {
@@ -884,6 +1414,11 @@ where
                 _ = pending_outbound_substreams.select_next_some() => {
                     // Do nothing since these futures have an output of "()"
                 },
+                _ = self.in_flight_rx.next() => {
+                    // Nothing is ever sent over the channel, so this only happens once the GoAway
+                    // is sent and the inbound rpcs in flight are completed.
+                    self.close_connection(DisconnectReason::Requested).await;
+                },
                 complete => unreachable!(),
             }
 
@@ -900,7 +1435,7 @@ where
     async fn handle_request<'a>(
         &'a mut self,
         pending: &'a mut FuturesUnordered<BoxFuture<'static, ()>>,
-        request: PeerRequest<TMuxer::Substream>,
+        request: PeerRequest<MeteredSubstream<TMuxer::Substream>>,
     ) {
         trace!(
             "Peer {} PeerRequest::{:?}",
@@ -914,14 +1449,66 @@ where
             PeerRequest::CloseConnection => {
                 self.close_connection(DisconnectReason::Requested).await;
             }
+            PeerRequest::Migrate => {
+                self.migrated.store(true, Ordering::SeqCst);
+                self.close_connection(DisconnectReason::Migrated).await;
+            }
+            PeerRequest::GoAway => {
+                if !self.is_draining {
+                    self.is_draining = true;
+                    pending.push(self.handle_go_away_request());
+                }
+            }
+        }
+    }
+
+    fn handle_go_away_request(&mut self) -> BoxFuture<'static, ()> {
+        let peer_id = self.identity.peer_id();
+        // The connection is held open until the GoAway is sent.
+        let in_flight_tx = self.in_flight_tx.take();
+        if !self
+            .identity
+            .is_protocol_supported(&ProtocolId::from_static(GOAWAY_PROTOCOL))
+        {
+            debug!(
+                "Peer {} does not support GoAway, draining without it",
+                peer_id.short_str()
+            );
+            return future::ready(()).boxed();
+        }
+        let outbound = self.connection.open_outbound();
+        async move {
+            match Self::send_go_away(outbound).await {
+                Ok(()) => debug!("Sent GoAway to Peer {}", peer_id.short_str()),
+                Err(e) => warn!(
+                    "Unable to send GoAway to Peer {}: {}",
+                    peer_id.short_str(),
+                    e
+                ),
+            }
+            drop(in_flight_tx);
         }
+            .boxed()
+    }
+
+    async fn send_go_away(outbound_fut: TMuxer::Outbound) -> io::Result<()> {
+        let substream = outbound_fut.await?;
+        let mut substream = negotiate_outbound_select(substream, GOAWAY_PROTOCOL).await?;
+        substream.close().await
     }
 
     fn handle_open_outbound_substream_request(
         &self,
         protocol: ProtocolId,
-        channel: oneshot::Sender<Result<TMuxer::Substream, PeerManagerError>>,
+        channel: oneshot::Sender<
+            Result<NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>, PeerManagerError>,
+        >,
     ) -> BoxFuture<'static, ()> {
+        // Speak the highest version of the protocol which is supported by both ends, if any.
+        let protocol = self
+            .identity
+            .select_protocol_version(&self.own_supported_protocols, &protocol)
+            .unwrap_or(protocol);
         let outbound = self.connection.open_outbound();
         let optimistic_negotiation = self.identity.is_protocol_supported(&protocol);
         let negotiate = Self::negotiate_outbound_substream(
@@ -930,6 +1517,8 @@ where
             protocol,
             optimistic_negotiation,
             channel,
+            Arc::clone(&self.migrated),
+            self.internal_event_tx.clone(),
         );
 
         negotiate.boxed()
@@ -940,7 +1529,11 @@ where
         outbound_fut: TMuxer::Outbound,
         protocol: ProtocolId,
         optimistic_negotiation: bool,
-        channel: oneshot::Sender<Result<TMuxer::Substream, PeerManagerError>>,
+        channel: oneshot::Sender<
+            Result<NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>, PeerManagerError>,
+        >,
+        migrated: Arc<AtomicBool>,
+        mut internal_event_tx: channel::Sender<InternalEvent<TMuxer>>,
     ) {
         let response = match outbound_fut.await {
             Ok(substream) => {
@@ -962,6 +1555,10 @@ where
             }
             Err(e) => Err(e),
         }
+        .map(|substream| NegotiatedSubstream {
+            substream: MeteredSubstream::new(substream, &protocol, peer_id),
+            protocol: protocol.clone(),
+        })
         .map_err(Into::into);
 
         match response {
@@ -978,6 +1575,20 @@ where
             ),
         }
 
+        // Nothing has been sent on behalf of the requester yet, so it is safe to retry the
+        // request on the connection which replaced this one.
+        if response.is_err() && migrated.load(Ordering::SeqCst) {
+            let event = InternalEvent::RetryOutboundSubstream(peer_id, protocol, channel);
+            if let Err(e) = internal_event_tx.send(event).await {
+                warn!(
+                    "Unable to retry outbound substream with peer {}: {:?}",
+                    peer_id.short_str(),
+                    e
+                );
+            }
+            return;
+        }
+
         if channel.send(response).is_err() {
             warn!(
                 "oneshot channel receiver dropped for new substream with peer {} for protocol {:?}",
@@ -990,7 +1601,10 @@ where
     fn handle_inbound_substream<'a>(
         &'a mut self,
         pending: &'a mut FuturesUnordered<
-            BoxFuture<'static, Result<NegotiatedSubstream<TMuxer::Substream>, PeerManagerError>>,
+            BoxFuture<
+                'static,
+                Result<NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>, PeerManagerError>,
+            >,
         >,
         substream: TMuxer::Substream,
     ) {
@@ -999,16 +1613,31 @@ where
             self.identity.peer_id().short_str()
         );
 
-        let negotiate =
-            Self::negotiate_inbound_substream(substream, self.own_supported_protocols.clone());
+        let negotiate = Self::negotiate_inbound_substream(
+            self.identity.peer_id(),
+            substream,
+            self.own_supported_protocols.clone(),
+            self.rpc_protocols.clone(),
+            self.in_flight_tx.clone(),
+        );
         pending.push(negotiate.boxed());
     }
 
     async fn negotiate_inbound_substream(
+        peer_id: PeerId,
         substream: TMuxer::Substream,
         own_supported_protocols: Vec<ProtocolId>,
-    ) -> Result<NegotiatedSubstream<TMuxer::Substream>, PeerManagerError> {
+        rpc_protocols: Vec<ProtocolId>,
+        in_flight_tx: Option<mpsc::UnboundedSender<()>>,
+    ) -> Result<NegotiatedSubstream<MeteredSubstream<TMuxer::Substream>>, PeerManagerError> {
         let (substream, protocol) = negotiate_inbound(substream, own_supported_protocols).await?;
+        let mut substream = MeteredSubstream::new(substream, &protocol, peer_id);
+        // Rpcs are completed before closing the connection on GoAway.
+        if let Some(in_flight_tx) = in_flight_tx {
+            if rpc_protocols.contains(&protocol) {
+                substream.hold(in_flight_tx);
+            }
+        }
         Ok(NegotiatedSubstream {
             protocol,
             substream,
@@ -1042,6 +1671,7 @@ where
         self.internal_event_tx
             .send(InternalEvent::PeerDisconnected(
                 self.identity.peer_id(),
+                self.address.clone(),
                 self.origin,
                 reason,
             ))
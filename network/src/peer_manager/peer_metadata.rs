@@ -0,0 +1,135 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Metadata about connected peers, as advertised by them during the identity exchange.
+//!
+//! PeerManager records the metadata of a peer when a connection with it is established and drops
+//! it once the peer is lost, so a [`PeerMetadataStore`] only ever holds connected peers. Upper
+//! layers use it to avoid sending requests to peers which are on another chain or which run an
+//! incompatible version of the software. The protocols supported by each peer are also persisted
+//! in the [`PeerStore`] of the network, if there is one, and so outlive the connection.
+
+use crate::{peer_store::PeerStore, protocols::identity::Identity, ProtocolId};
+use config::config::RoleType;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use types::PeerId;
+
+/// What a connected peer advertised about itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerMetadata {
+    pub role: RoleType,
+    pub software_version: String,
+    pub chain_id: String,
+    pub supported_protocols: Vec<ProtocolId>,
+}
+
+impl From<&Identity> for PeerMetadata {
+    fn from(identity: &Identity) -> Self {
+        Self {
+            role: identity.role(),
+            software_version: identity.software_version().to_string(),
+            chain_id: identity.chain_id().to_string(),
+            supported_protocols: identity.supported_protocols().to_vec(),
+        }
+    }
+}
+
+/// Shared view of the metadata of the peers connected to a network. Cloning it is cheap and all
+/// clones see the same peers.
+#[derive(Clone, Debug)]
+pub struct PeerMetadataStore {
+    own_chain_id: String,
+    peers: Arc<RwLock<HashMap<PeerId, PeerMetadata>>>,
+    peer_store: Option<PeerStore>,
+}
+
+impl PeerMetadataStore {
+    pub fn new(own_chain_id: String) -> Self {
+        Self {
+            own_chain_id,
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            peer_store: None,
+        }
+    }
+
+    /// Persists the protocols supported by each peer in `peer_store` as the peer connects.
+    pub fn with_peer_store(mut self, peer_store: PeerStore) -> Self {
+        self.peer_store = Some(peer_store);
+        self
+    }
+
+    /// Returns the metadata of `peer_id`, if it is connected.
+    pub fn get(&self, peer_id: &PeerId) -> Option<PeerMetadata> {
+        self.peers.read().unwrap().get(peer_id).cloned()
+    }
+
+    /// Returns whether `peer_id` is connected and on the same chain as this node.
+    pub fn is_same_chain(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .map_or(false, |metadata| metadata.chain_id == self.own_chain_id)
+    }
+
+    /// Returns whether `peer_id` is connected and supports `protocol`.
+    pub fn supports_protocol(&self, peer_id: &PeerId, protocol: &ProtocolId) -> bool {
+        self.peers
+            .read()
+            .unwrap()
+            .get(peer_id)
+            .map_or(false, |metadata| {
+                metadata.supported_protocols.iter().any(|p| p == protocol)
+            })
+    }
+
+    pub(crate) fn insert(&self, peer_id: PeerId, metadata: PeerMetadata) {
+        if let Some(peer_store) = &self.peer_store {
+            let supported_protocols = metadata.supported_protocols.clone();
+            peer_store.update(peer_id, |record| {
+                record.supported_protocols = supported_protocols;
+            });
+        }
+        self.peers.write().unwrap().insert(peer_id, metadata);
+    }
+
+    pub(crate) fn remove(&self, peer_id: &PeerId) {
+        self.peers.write().unwrap().remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_chain() {
+        let store = PeerMetadataStore::new("testnet".to_string());
+        let peer_id = PeerId::random();
+        let identity = Identity::new(
+            peer_id,
+            vec![ProtocolId::from_static(b"/proto/1.0.0")],
+            RoleType::Validator,
+        )
+        .with_node_info("0.1.0".to_string(), "testnet".to_string());
+        assert!(!store.is_same_chain(&peer_id));
+
+        store.insert(peer_id, PeerMetadata::from(&identity));
+        assert!(store.is_same_chain(&peer_id));
+        assert!(store.supports_protocol(&peer_id, &ProtocolId::from_static(b"/proto/1.0.0")));
+        assert!(!store.supports_protocol(&peer_id, &ProtocolId::from_static(b"/proto/2.0.0")));
+        assert_eq!(store.get(&peer_id).unwrap().software_version, "0.1.0");
+
+        let other_id = PeerId::random();
+        let other = Identity::new(other_id, vec![], RoleType::FullNode)
+            .with_node_info("0.1.0".to_string(), "mainnet".to_string());
+        store.clone().insert(other_id, PeerMetadata::from(&other));
+        assert!(!store.is_same_chain(&other_id));
+
+        store.remove(&peer_id);
+        assert_eq!(store.get(&peer_id), None);
+    }
+}
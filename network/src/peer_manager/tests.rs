@@ -2,21 +2,24 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    common::NegotiatedSubstream,
     peer_manager::{
-        DisconnectReason, InternalEvent, Peer, PeerHandle, PeerManager, PeerManagerNotification,
-        PeerManagerRequest,
+        DisconnectReason, InternalEvent, MeteredSubstream, Peer, PeerHandle, PeerManager,
+        PeerManagerError, PeerManagerNotification, PeerManagerRequest, PeerMetadataStore,
+        GOAWAY_PROTOCOL,
     },
     protocols::identity::{exchange_identity, Identity},
     ProtocolId,
 };
 use channel;
-use config::config::RoleType;
+use config::config::{PeerQueueConfig, RoleType};
 use futures::{
-    channel::oneshot,
+    channel::{mpsc, oneshot},
     compat::Compat01As03,
     executor::block_on,
     future::{join, FutureExt, TryFutureExt},
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sink::SinkExt,
     stream::StreamExt,
 };
 use memsocket::MemorySocket;
@@ -30,6 +33,7 @@ use netcore::{
 };
 use parity_multiaddr::Multiaddr;
 use std::{collections::HashMap, io, time::Duration};
+use task_manager::TaskManager;
 use tokio::{runtime::TaskExecutor, timer::Timeout};
 use types::PeerId;
 
@@ -76,25 +80,57 @@ fn build_test_peer(
     origin: ConnectionOrigin,
 ) -> (
     Peer<Yamux<MemorySocket>>,
-    PeerHandle<StreamHandle<MemorySocket>>,
+    PeerHandle<MeteredSubstream<StreamHandle<MemorySocket>>>,
+    Yamux<MemorySocket>,
+    channel::Receiver<InternalEvent<Yamux<MemorySocket>>>,
+) {
+    build_test_peer_with_protocols(
+        origin,
+        vec![ProtocolId::from_static(GOAWAY_PROTOCOL)],
+        vec![
+            ProtocolId::from_static(HELLO_PROTOCOL),
+            ProtocolId::from_static(GOAWAY_PROTOCOL),
+        ],
+    )
+}
+
+// Builds a peer whose remote end supports `remote_protocols`, while the local end supports
+// `own_protocols`.
+fn build_test_peer_with_protocols(
+    origin: ConnectionOrigin,
+    remote_protocols: Vec<ProtocolId>,
+    own_protocols: Vec<ProtocolId>,
+) -> (
+    Peer<Yamux<MemorySocket>>,
+    PeerHandle<MeteredSubstream<StreamHandle<MemorySocket>>>,
     Yamux<MemorySocket>,
     channel::Receiver<InternalEvent<Yamux<MemorySocket>>>,
 ) {
     let (a, b) = build_test_connection();
-    let identity = build_test_identity(PeerId::random());
+    let identity = Identity::new(PeerId::random(), remote_protocols, RoleType::Validator);
     let peer_id = identity.peer_id();
     let (internal_event_tx, internal_event_rx) = channel::new_test(1);
     let (peer_req_tx, peer_req_rx) = channel::new_test(0);
+    let (peer_control_tx, peer_control_rx) = mpsc::unbounded();
 
     let peer = Peer::new(
         identity,
+        Multiaddr::empty(),
         a,
         origin,
+        own_protocols,
         vec![ProtocolId::from_static(HELLO_PROTOCOL)],
         internal_event_tx,
         peer_req_rx,
+        peer_control_rx,
+    );
+    let peer_handle = PeerHandle::new(
+        peer_id,
+        Multiaddr::empty(),
+        origin,
+        peer_req_tx,
+        peer_control_tx,
     );
-    let peer_handle = PeerHandle::new(peer_id, Multiaddr::empty(), origin, peer_req_tx);
 
     (peer, peer_handle, b, internal_event_rx)
 }
@@ -102,12 +138,12 @@ fn build_test_peer(
 fn build_test_connected_peers() -> (
     (
         Peer<Yamux<MemorySocket>>,
-        PeerHandle<StreamHandle<MemorySocket>>,
+        PeerHandle<MeteredSubstream<StreamHandle<MemorySocket>>>,
         channel::Receiver<InternalEvent<Yamux<MemorySocket>>>,
     ),
     (
         Peer<Yamux<MemorySocket>>,
-        PeerHandle<StreamHandle<MemorySocket>>,
+        PeerHandle<MeteredSubstream<StreamHandle<MemorySocket>>>,
         channel::Receiver<InternalEvent<Yamux<MemorySocket>>>,
     ),
 ) {
@@ -153,7 +189,11 @@ fn peer_open_substream() {
             substream_tx,
         )
         .await;
-        let mut substream = substream_rx.await.unwrap().unwrap();
+        let NegotiatedSubstream {
+            protocol,
+            mut substream,
+        } = substream_rx.await.unwrap().unwrap();
+        assert_eq!(protocol, HELLO_PROTOCOL);
         let mut buf = Vec::new();
         substream.read_to_end(&mut buf).await.unwrap();
         substream.close().await.unwrap();
@@ -163,6 +203,52 @@ fn peer_open_substream() {
     block_on(join(server, client));
 }
 
+// Test that the highest version of a protocol supported by both ends is negotiated, and returned
+// to the requester along with the substream.
+#[test]
+fn peer_open_substream_negotiates_highest_common_version() {
+    const HELLO_PROTOCOL_V2: &[u8] = b"/hello-world/2.0.0";
+    const HELLO_PROTOCOL_V3: &[u8] = b"/hello-world/3.0.0";
+    let (peer, _peer_handle, connection, _internal_event_rx) = build_test_peer_with_protocols(
+        ConnectionOrigin::Inbound,
+        vec![
+            ProtocolId::from_static(HELLO_PROTOCOL),
+            ProtocolId::from_static(HELLO_PROTOCOL_V2),
+            ProtocolId::from_static(HELLO_PROTOCOL_V3),
+        ],
+        vec![
+            ProtocolId::from_static(HELLO_PROTOCOL),
+            ProtocolId::from_static(HELLO_PROTOCOL_V2),
+        ],
+    );
+
+    let server = async move {
+        let substream_listener = connection.listen_for_inbound();
+        let (substream, _substream_listener) = substream_listener.into_future().await;
+        let (mut substream, protocol) = negotiate_inbound(
+            substream.unwrap().unwrap(),
+            [HELLO_PROTOCOL, HELLO_PROTOCOL_V2, HELLO_PROTOCOL_V3],
+        )
+        .await
+        .unwrap();
+        assert_eq!(protocol, HELLO_PROTOCOL_V2);
+        substream.close().await.unwrap();
+    };
+
+    let client = async move {
+        let (substream_tx, substream_rx) = oneshot::channel();
+        peer.handle_open_outbound_substream_request(
+            ProtocolId::from_static(HELLO_PROTOCOL),
+            substream_tx,
+        )
+        .await;
+        let negotiated = substream_rx.await.unwrap().unwrap();
+        assert_eq!(negotiated.protocol, HELLO_PROTOCOL_V2);
+    };
+
+    block_on(join(server, client));
+}
+
 // Test that if two peers request to open a substream with each other simultaneously that
 // we won't deadlock.
 #[test]
@@ -178,12 +264,8 @@ fn peer_open_substream_simultaneous() {
         let (substream_tx_b, substream_rx_b) = oneshot::channel();
 
         // Send open substream requests to both peer_a and peer_b
-        peer_handle_a
-            .open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx_a)
-            .await;
-        peer_handle_b
-            .open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx_b)
-            .await;
+        peer_handle_a.open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx_a);
+        peer_handle_b.open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx_b);
 
         // These both should complete, but in the event they deadlock wrap them in a timeout
         let timeout_a = Compat01As03::new(Timeout::new(
@@ -202,7 +284,7 @@ fn peer_open_substream_simultaneous() {
         assert_new_substream_event(peer_handle_b.peer_id, &mut internal_event_rx_b).await;
 
         // Shut one peers and the other should shutdown due to ConnectionLost
-        peer_handle_a.disconnect().await;
+        peer_handle_a.disconnect();
 
         // Check that we received both shutdown events
         assert_peer_disconnected_event(
@@ -233,7 +315,7 @@ fn peer_disconnect_request() {
         build_test_peer(ConnectionOrigin::Inbound);
 
     let test = async move {
-        peer_handle.disconnect().await;
+        peer_handle.disconnect();
         assert_peer_disconnected_event(
             peer_handle.peer_id,
             DisconnectReason::Requested,
@@ -263,6 +345,92 @@ fn peer_disconnect_connection_lost() {
     block_on(join(test, peer.start()));
 }
 
+// Test that the requests to open substreams are dropped once the queue of the peer is full, while
+// the requests to close the connection still get through.
+#[test]
+fn peer_queue_full() {
+    let (peer, mut peer_handle, _connection, mut internal_event_rx) =
+        build_test_peer(ConnectionOrigin::Inbound);
+
+    // The peer is not started yet, so its queue fills up.
+    let (substream_tx, _substream_rx) = oneshot::channel();
+    assert_eq!(
+        peer_handle.open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx),
+        None
+    );
+    let (substream_tx, substream_rx) = oneshot::channel();
+    assert!(peer_handle
+        .open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx)
+        .is_some());
+    assert!(block_on(substream_rx).is_err());
+
+    let test = async move {
+        peer_handle.disconnect();
+        assert_peer_disconnected_event(
+            peer_handle.peer_id,
+            DisconnectReason::Requested,
+            &mut internal_event_rx,
+        )
+        .await;
+    };
+
+    block_on(join(test, peer.start()));
+}
+
+// Test that a peer sending a GoAway closes the connection only once the rpcs it received are
+// completed, and that the remote peer is told about the GoAway.
+#[test]
+fn peer_go_away_drains_rpcs() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+    let (
+        (peer_a, mut peer_handle_a, mut internal_event_rx_a),
+        (peer_b, mut peer_handle_b, mut internal_event_rx_b),
+    ) = build_test_connected_peers();
+
+    let test = async move {
+        // Peer b sends an rpc to peer a, which is still being handled by peer a.
+        let (substream_tx, substream_rx) = oneshot::channel();
+        peer_handle_b.open_substream(ProtocolId::from_static(HELLO_PROTOCOL), substream_tx);
+        let _outbound_substream = substream_rx.await.unwrap().unwrap();
+        let inbound_substream = match internal_event_rx_a.next().await {
+            Some(InternalEvent::NewSubstream(_, substream)) => substream,
+            event => panic!("Expected a NewSubstream, received: {:?}", event),
+        };
+
+        // Peer b learns that peer a is going away, while the connection is held open.
+        peer_handle_a.go_away();
+        match internal_event_rx_b.next().await {
+            Some(InternalEvent::PeerGoingAway(peer_id, _address, origin)) => {
+                assert_eq!(peer_id, peer_handle_b.peer_id);
+                assert_eq!(origin, ConnectionOrigin::Outbound);
+            }
+            event => panic!("Expected a PeerGoingAway, received: {:?}", event),
+        }
+
+        // Completing the rpc lets peer a close the connection.
+        drop(inbound_substream);
+        assert_peer_disconnected_event(
+            peer_handle_a.peer_id,
+            DisconnectReason::Requested,
+            &mut internal_event_rx_a,
+        )
+        .await;
+        assert_peer_disconnected_event(
+            peer_handle_b.peer_id,
+            DisconnectReason::ConnectionLost,
+            &mut internal_event_rx_b,
+        )
+        .await;
+    };
+
+    runtime.spawn(peer_a.start().boxed().unit_error().compat());
+    runtime.spawn(peer_b.start().boxed().unit_error().compat());
+
+    runtime
+        .block_on_all(test.boxed().unit_error().compat())
+        .unwrap();
+}
+
 #[test]
 #[should_panic]
 fn peer_panics_when_request_tx_has_dropped() {
@@ -304,12 +472,15 @@ fn build_test_peer_manager(
 
     let peer_manager = PeerManager::new(
         build_test_transport(Identity::new(peer_id, vec![], RoleType::Validator)),
-        executor.clone(),
+        TaskManager::new("network", executor),
         peer_id,
         "/memory/0".parse().unwrap(),
+        PeerMetadataStore::new(String::new()),
         peer_manager_request_rx,
         protocol_handlers,
+        vec![protocol],
         Vec::new(),
+        PeerQueueConfig::default(),
     );
 
     (peer_manager, peer_manager_request_tx, hello_rx)
@@ -341,7 +512,7 @@ async fn assert_peer_disconnected_event<TMuxer: StreamMultiplexer>(
     internal_event_rx: &mut channel::Receiver<InternalEvent<TMuxer>>,
 ) {
     match internal_event_rx.next().await {
-        Some(InternalEvent::PeerDisconnected(actual_peer_id, _origin, actual_reason)) => {
+        Some(InternalEvent::PeerDisconnected(actual_peer_id, _address, _origin, actual_reason)) => {
             assert_eq!(actual_peer_id, peer_id);
             assert_eq!(actual_reason, reason);
         }
@@ -708,6 +879,7 @@ fn peer_manager_simultaneous_dial_disconnect_event() {
         // removed from PeerManager
         let event = InternalEvent::PeerDisconnected(
             ids[0],
+            Multiaddr::empty(),
             ConnectionOrigin::Inbound,
             DisconnectReason::ConnectionLost,
         );
@@ -720,3 +892,335 @@ fn peer_manager_simultaneous_dial_disconnect_event() {
         .block_on(test.boxed().unit_error().compat())
         .unwrap();
 }
+
+//
+// Connection Migration Tests
+//
+
+#[test]
+fn peer_manager_connection_migration() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (peer_event_tx, mut peer_event_rx) = channel::new_test(1);
+    let (mut peer_manager, _request_tx, _hello_rx) =
+        build_test_peer_manager(runtime.executor(), ids[1]);
+    peer_manager.peer_event_handlers.push(peer_event_tx);
+    let old_address: Multiaddr = "/ip4/1.2.3.4/tcp/6180".parse().unwrap();
+    let new_address: Multiaddr = "/ip4/5.6.7.8/tcp/6180".parse().unwrap();
+
+    let test = async move {
+        // The remote peer connects from a first address
+        let (outbound1, inbound1) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                old_address.clone(),
+                ConnectionOrigin::Inbound,
+                inbound1,
+            )
+            .await;
+        match peer_event_rx.next().await {
+            Some(PeerManagerNotification::NewPeer(peer_id, address)) => {
+                assert_eq!(peer_id, ids[0]);
+                assert_eq!(address, old_address);
+            }
+            event => panic!("Expected a NewPeer, received: {:?}", event),
+        }
+
+        // The remote peer reconnects from a new address
+        let (outbound2, inbound2) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                new_address.clone(),
+                ConnectionOrigin::Inbound,
+                inbound2,
+            )
+            .await;
+        match peer_event_rx.next().await {
+            Some(PeerManagerNotification::PeerAddressChanged(peer_id, address)) => {
+                assert_eq!(peer_id, ids[0]);
+                assert_eq!(address, new_address);
+            }
+            event => panic!("Expected a PeerAddressChanged, received: {:?}", event),
+        }
+
+        // The old connection is closed as migrated
+        assert_peer_disconnected_event(
+            ids[0],
+            DisconnectReason::Migrated,
+            &mut peer_manager.internal_event_rx,
+        )
+        .await;
+        assert!(open_hello_substream(&outbound1).await.is_err());
+
+        // Handling the disconnect event of the old connection keeps the new one around
+        let event = InternalEvent::PeerDisconnected(
+            ids[0],
+            old_address.clone(),
+            ConnectionOrigin::Inbound,
+            DisconnectReason::Migrated,
+        );
+        peer_manager.handle_internal_event(event).await;
+        assert_eq!(
+            peer_manager.active_peers.get(&ids[0]).unwrap().address(),
+            &new_address
+        );
+
+        assert!(open_hello_substream(&outbound2).await.is_ok());
+        assert_new_substream_event(ids[0], &mut peer_manager.internal_event_rx).await;
+    };
+
+    runtime
+        .block_on(test.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn peer_manager_no_migration_from_new_port() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, _request_tx, _hello_rx) =
+        build_test_peer_manager(runtime.executor(), ids[1]);
+    let old_address: Multiaddr = "/ip4/1.2.3.4/tcp/50001".parse().unwrap();
+    let new_address: Multiaddr = "/ip4/1.2.3.4/tcp/50002".parse().unwrap();
+
+    let test = async move {
+        let (outbound1, inbound1) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                old_address,
+                ConnectionOrigin::Inbound,
+                inbound1,
+            )
+            .await;
+
+        // A second inbound connection from the same host only differs by its ephemeral port
+        let (outbound2, inbound2) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                new_address,
+                ConnectionOrigin::Inbound,
+                inbound2,
+            )
+            .await;
+
+        // outbound2 should have been dropped since it was the second inbound connection
+        check_correct_connection_is_live(
+            outbound1,
+            outbound2,
+            ids[0],
+            false,
+            &mut peer_manager.internal_event_rx,
+        )
+        .await;
+    };
+
+    runtime
+        .block_on(test.boxed().unit_error().compat())
+        .unwrap();
+}
+
+//
+// Inbound Connection Limit Tests
+//
+
+#[test]
+fn peer_manager_inbound_limit_per_subnet() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(5);
+    let (peer_manager, _request_tx, _hello_rx) =
+        build_test_peer_manager(runtime.executor(), ids[4]);
+    let mut peer_manager = peer_manager.with_max_inbound_per_subnet(Some(1));
+
+    let test = async move {
+        let (_outbound0, inbound0) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                "/ip4/1.2.3.4/tcp/6180".parse().unwrap(),
+                ConnectionOrigin::Inbound,
+                inbound0,
+            )
+            .await;
+        assert!(peer_manager.active_peers.contains_key(&ids[0]));
+
+        // Another peer of the same subnet connecting to us is turned down
+        let (outbound1, inbound1) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[1]),
+                "/ip4/1.2.3.5/tcp/6180".parse().unwrap(),
+                ConnectionOrigin::Inbound,
+                inbound1,
+            )
+            .await;
+        assert!(!peer_manager.active_peers.contains_key(&ids[1]));
+        assert!(open_hello_substream(&outbound1).await.is_err());
+
+        // Peers of other subnets, and the peers we dial, are not affected
+        let (_outbound2, inbound2) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[2]),
+                "/ip4/5.6.7.8/tcp/6180".parse().unwrap(),
+                ConnectionOrigin::Inbound,
+                inbound2,
+            )
+            .await;
+        assert!(peer_manager.active_peers.contains_key(&ids[2]));
+        let (_outbound3, inbound3) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[3]),
+                "/ip4/1.2.3.6/tcp/6180".parse().unwrap(),
+                ConnectionOrigin::Outbound,
+                inbound3,
+            )
+            .await;
+        assert!(peer_manager.active_peers.contains_key(&ids[3]));
+    };
+
+    runtime
+        .block_on(test.boxed().unit_error().compat())
+        .unwrap();
+}
+
+//
+// Graceful Shutdown Tests
+//
+
+#[test]
+fn peer_manager_peer_going_away() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (peer_event_tx, mut peer_event_rx) = channel::new_test(1);
+    let (mut peer_manager, _request_tx, _hello_rx) =
+        build_test_peer_manager(runtime.executor(), ids[1]);
+    peer_manager.peer_event_handlers.push(peer_event_tx);
+    let old_address: Multiaddr = "/ip4/1.2.3.4/tcp/6180".parse().unwrap();
+    let new_address: Multiaddr = "/ip4/1.2.3.4/tcp/6181".parse().unwrap();
+
+    let test = async move {
+        let (_outbound1, inbound1) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                old_address.clone(),
+                ConnectionOrigin::Inbound,
+                inbound1,
+            )
+            .await;
+        match peer_event_rx.next().await {
+            Some(PeerManagerNotification::NewPeer(peer_id, _)) => assert_eq!(peer_id, ids[0]),
+            event => panic!("Expected a NewPeer, received: {:?}", event),
+        }
+
+        // The peer is reported lost as soon as it sends a GoAway
+        let event =
+            InternalEvent::PeerGoingAway(ids[0], old_address.clone(), ConnectionOrigin::Inbound);
+        peer_manager.handle_internal_event(event).await;
+        match peer_event_rx.next().await {
+            Some(PeerManagerNotification::LostPeer(peer_id, address)) => {
+                assert_eq!(peer_id, ids[0]);
+                assert_eq!(address, old_address);
+            }
+            event => panic!("Expected a LostPeer, received: {:?}", event),
+        }
+        let (substream_tx, substream_rx) = oneshot::channel();
+        let request = PeerManagerRequest::OpenSubstream(
+            ids[0],
+            ProtocolId::from_static(HELLO_PROTOCOL),
+            substream_tx,
+        );
+        peer_manager.handle_request(request).await;
+        assert!(substream_rx.await.unwrap().is_err());
+
+        // The peer comes back before its previous connection is closed, which is reported as a
+        // new peer rather than as a migration
+        let (_outbound2, inbound2) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                new_address.clone(),
+                ConnectionOrigin::Inbound,
+                inbound2,
+            )
+            .await;
+        match peer_event_rx.next().await {
+            Some(PeerManagerNotification::NewPeer(peer_id, address)) => {
+                assert_eq!(peer_id, ids[0]);
+                assert_eq!(address, new_address);
+            }
+            event => panic!("Expected a NewPeer, received: {:?}", event),
+        }
+        assert_peer_disconnected_event(
+            ids[0],
+            DisconnectReason::Migrated,
+            &mut peer_manager.internal_event_rx,
+        )
+        .await;
+    };
+
+    runtime
+        .block_on(test.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn peer_manager_drain_on_shutdown() {
+    let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+    let ids = ordered_peer_ids(2);
+    let (mut peer_manager, mut request_tx, _hello_rx) =
+        build_test_peer_manager(runtime.executor(), ids[1]);
+    let shutdown_handle = peer_manager.shutdown_handle();
+
+    let setup = async move {
+        let (outbound, inbound) = build_test_connection();
+        peer_manager
+            .add_peer(
+                build_test_identity(ids[0]),
+                Multiaddr::empty(),
+                ConnectionOrigin::Inbound,
+                inbound,
+            )
+            .await;
+        (peer_manager, outbound)
+    };
+    let (peer_manager, outbound) = runtime
+        .block_on(setup.boxed().unit_error().compat())
+        .unwrap();
+    runtime.spawn(peer_manager.start().boxed().unit_error().compat());
+
+    let test = async move {
+        // Without rpcs in flight, the connection is closed right away
+        shutdown_handle.shutdown(Duration::from_secs(10)).await;
+        assert!(open_hello_substream(&outbound).await.is_err());
+
+        // No new peers are dialed anymore
+        let (dial_tx, dial_rx) = oneshot::channel();
+        request_tx
+            .send(PeerManagerRequest::DialPeer(
+                ids[0],
+                Multiaddr::empty(),
+                dial_tx,
+            ))
+            .await
+            .unwrap();
+        match dial_rx.await.unwrap() {
+            Err(PeerManagerError::ShuttingDown) => {}
+            result => panic!("Expected a ShuttingDown error, received: {:?}", result),
+        }
+    };
+
+    runtime
+        .block_on(test.boxed().unit_error().compat())
+        .unwrap();
+}
@@ -0,0 +1,378 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent store of what the node learned about its peers: the addresses they were last known
+//! at, the outcomes of the dials to these addresses, the round trip time of the pings to them and
+//! the protocols they support.
+//!
+//! The store is loaded when the network starts, so that the peers are dialed at the addresses
+//! which worked before the restart, in the order which worked best, instead of waiting for
+//! Discovery to learn them again. Each actor of the network records what it learns as it goes:
+//! the ConnectivityManager the outcomes of its dials, the HealthChecker the round trip times, and
+//! PeerManager the protocols advertised during the identity exchange.
+//!
+//! The records are updated in memory, so that the actors never wait for RocksDB, and the records
+//! updated since the last flush are written in a single batch every `flush_interval` by a thread
+//! of the store, and once more when the store is dropped. The record of a peer which wasn't
+//! updated for `record_ttl` is dropped, as the peer is most likely gone for good.
+
+mod schema;
+#[cfg(test)]
+mod test;
+
+use crate::{connectivity_manager::AddrDialStats, proto, ProtocolId};
+use failure::prelude::*;
+use logger::prelude::*;
+use parity_multiaddr::Multiaddr;
+use schema::{PeerRecordSchema, PEER_RECORD_CF_NAME};
+use schemadb::{
+    ColumnFamilyOptions, ColumnFamilyOptionsMap, ReadOptions, SchemaBatch, DB, DEFAULT_CF_NAME,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use types::PeerId;
+
+/// Default interval at which the records updated since the last flush are written to RocksDB.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// Default time after which the record of a peer which wasn't updated is dropped.
+pub const DEFAULT_RECORD_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// What the node learned about a peer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerRecord {
+    /// Addresses the peer was last known at, in order of preference.
+    pub addrs: Vec<Multiaddr>,
+    /// Address at which the node last connected to the peer.
+    pub last_connected_addr: Option<Multiaddr>,
+    /// Outcomes of the dials to the addresses of the peer.
+    pub dial_stats: HashMap<Multiaddr, AddrDialStats>,
+    /// Smoothed round trip time of the pings to the peer.
+    pub smoothed_rtt: Option<Duration>,
+    /// Protocols the peer advertised during the last identity exchange with it.
+    pub supported_protocols: Vec<ProtocolId>,
+    /// Seconds since the Unix epoch at which the record was last updated, 0 if unknown.
+    pub last_updated_secs: u64,
+}
+
+impl PeerRecord {
+    /// The addresses to dial the peer at: the address the node last connected to it at, if any,
+    /// followed by the other known addresses of the peer.
+    pub fn dial_addrs(&self) -> Vec<Multiaddr> {
+        let mut addrs: Vec<_> = self.last_connected_addr.iter().cloned().collect();
+        addrs.extend(
+            self.addrs
+                .iter()
+                .filter(|addr| Some(*addr) != self.last_connected_addr.as_ref())
+                .cloned(),
+        );
+        addrs
+    }
+}
+
+impl TryFrom<proto::PeerRecord> for PeerRecord {
+    type Error = failure::Error;
+
+    fn try_from(record: proto::PeerRecord) -> Result<Self> {
+        let addrs = record
+            .addrs
+            .into_iter()
+            .map(Multiaddr::try_from)
+            .collect::<::std::result::Result<_, _>>()?;
+        let last_connected_addr = if record.last_connected_addr.is_empty() {
+            None
+        } else {
+            Some(Multiaddr::try_from(record.last_connected_addr)?)
+        };
+        let dial_stats = record
+            .dial_stats
+            .into_iter()
+            .map(|addr_record| {
+                let stats = AddrDialStats {
+                    successes: addr_record.successes,
+                    failures: addr_record.failures,
+                    consecutive_failures: addr_record.consecutive_failures,
+                };
+                Ok((Multiaddr::try_from(addr_record.addr)?, stats))
+            })
+            .collect::<Result<_>>()?;
+        let smoothed_rtt = if record.smoothed_rtt_us == 0 {
+            None
+        } else {
+            Some(Duration::from_micros(record.smoothed_rtt_us))
+        };
+        Ok(Self {
+            addrs,
+            last_connected_addr,
+            dial_stats,
+            smoothed_rtt,
+            supported_protocols: record
+                .supported_protocols
+                .into_iter()
+                .map(ProtocolId::from)
+                .collect(),
+            last_updated_secs: record.last_updated_secs,
+        })
+    }
+}
+
+impl From<PeerRecord> for proto::PeerRecord {
+    fn from(record: PeerRecord) -> Self {
+        Self {
+            addrs: record
+                .addrs
+                .into_iter()
+                .map(|addr| addr.as_ref().to_vec())
+                .collect(),
+            last_connected_addr: record
+                .last_connected_addr
+                .map_or_else(Vec::new, |addr| addr.as_ref().to_vec()),
+            dial_stats: record
+                .dial_stats
+                .into_iter()
+                .map(|(addr, stats)| proto::AddrDialRecord {
+                    addr: addr.as_ref().to_vec(),
+                    successes: stats.successes,
+                    failures: stats.failures,
+                    consecutive_failures: stats.consecutive_failures,
+                })
+                .collect(),
+            smoothed_rtt_us: record.smoothed_rtt.map_or(0, |rtt| rtt.as_micros() as u64),
+            supported_protocols: record
+                .supported_protocols
+                .into_iter()
+                .map(|protocol| protocol.to_vec())
+                .collect(),
+            last_updated_secs: record.last_updated_secs,
+        }
+    }
+}
+
+/// Handle to the records of the peers of a network, kept in memory and backed by RocksDB. Cloning
+/// it is cheap and all clones share the same records.
+#[derive(Clone, Debug)]
+pub struct PeerStore {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    db: DB,
+    records: Mutex<Records>,
+    // Serializes the flushes, so that an older version of a record never overwrites a newer one.
+    flush_lock: Mutex<()>,
+    record_ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Records {
+    records: HashMap<PeerId, PeerRecord>,
+    // Peers whose record was updated or removed since the last flush.
+    dirty: HashSet<PeerId>,
+}
+
+impl PeerStore {
+    /// Opens the store at `path` with the default flush interval and record TTL, creating it,
+    /// along with any missing parent directory, if it does not exist yet.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self::open(path, DEFAULT_FLUSH_INTERVAL, DEFAULT_RECORD_TTL)
+    }
+
+    /// Opens the store at `path`, flushing the updated records every `flush_interval` and dropping
+    /// the records which weren't updated for `record_ttl`.
+    pub fn open<P: AsRef<Path>>(path: P, flush_interval: Duration, record_ttl: Duration) -> Self {
+        let cf_opts_map: ColumnFamilyOptionsMap = [
+            (
+                /* UNUSED CF = */ DEFAULT_CF_NAME,
+                ColumnFamilyOptions::default(),
+            ),
+            (PEER_RECORD_CF_NAME, ColumnFamilyOptions::default()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let path = path.as_ref();
+        fs::create_dir_all(path).unwrap_or_else(|e| {
+            panic!(
+                "PeerStore directory creation failed due to {:?}, unable to continue",
+                e
+            )
+        });
+        let instant = Instant::now();
+        let db = DB::open(path, cf_opts_map)
+            .unwrap_or_else(|e| panic!("PeerStore open failed due to {:?}, unable to continue", e));
+        let mut records = load_records(&db);
+        // The records written before they were timestamped expire `record_ttl` from now.
+        let mut dirty = HashSet::new();
+        for (peer_id, record) in records.iter_mut() {
+            if record.last_updated_secs == 0 {
+                record.last_updated_secs = now_secs();
+                dirty.insert(*peer_id);
+            }
+        }
+
+        info!(
+            "Opened PeerStore at {:?} with the records of {} peers in {} ms",
+            path,
+            records.len(),
+            instant.elapsed().as_millis()
+        );
+
+        let inner = Arc::new(Inner {
+            db,
+            records: Mutex::new(Records { records, dirty }),
+            flush_lock: Mutex::new(()),
+            record_ttl,
+        });
+        // The records expired while the node was down are dropped by the first flush.
+        inner.expire();
+        let weak_inner = Arc::downgrade(&inner);
+        thread::Builder::new()
+            .name("peer-store-flush".to_string())
+            .spawn(move || loop {
+                thread::sleep(flush_interval);
+                match weak_inner.upgrade() {
+                    Some(inner) => inner.flush(),
+                    None => break,
+                }
+            })
+            .expect("Unable to spawn the PeerStore flush thread");
+        Self { inner }
+    }
+
+    /// Returns the record of `peer_id`, if any.
+    pub fn get(&self, peer_id: &PeerId) -> Option<PeerRecord> {
+        self.inner
+            .records
+            .lock()
+            .unwrap()
+            .records
+            .get(peer_id)
+            .cloned()
+    }
+
+    /// Returns the records of all the peers.
+    pub fn get_all(&self) -> HashMap<PeerId, PeerRecord> {
+        self.inner.records.lock().unwrap().records.clone()
+    }
+
+    /// Applies `f` to the record of `peer_id`, starting from an empty record if there is none yet.
+    /// The record is written to RocksDB by the next flush.
+    pub fn update<F>(&self, peer_id: PeerId, f: F)
+    where
+        F: FnOnce(&mut PeerRecord),
+    {
+        let mut records = self.inner.records.lock().unwrap();
+        let record = records.records.entry(peer_id).or_default();
+        f(record);
+        record.last_updated_secs = now_secs();
+        records.dirty.insert(peer_id);
+    }
+
+    /// Removes the record of `peer_id`, if any. The record is deleted from RocksDB by the next
+    /// flush.
+    pub fn remove(&self, peer_id: &PeerId) {
+        let mut records = self.inner.records.lock().unwrap();
+        if records.records.remove(peer_id).is_some() {
+            records.dirty.insert(*peer_id);
+        }
+    }
+
+    /// Drops the expired records and writes the records updated since the last flush to RocksDB.
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+impl Inner {
+    /// Drops the records which weren't updated for `record_ttl`.
+    fn expire(&self) {
+        let oldest_secs = now_secs().saturating_sub(self.record_ttl.as_secs());
+        let mut records = self.records.lock().unwrap();
+        let expired: Vec<_> = records
+            .records
+            .iter()
+            .filter(|(_, record)| record.last_updated_secs < oldest_secs)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired {
+            debug!("Record of peer: {} expired", peer_id.short_str());
+            records.records.remove(&peer_id);
+            records.dirty.insert(peer_id);
+        }
+    }
+
+    fn flush(&self) {
+        let _guard = self.flush_lock.lock().unwrap();
+        self.expire();
+        let updates: Vec<_> = {
+            let mut records = self.records.lock().unwrap();
+            let dirty: Vec<_> = records.dirty.drain().collect();
+            dirty
+                .into_iter()
+                .map(|peer_id| (peer_id, records.records.get(&peer_id).cloned()))
+                .collect()
+        };
+        if updates.is_empty() {
+            return;
+        }
+        let res = updates.iter().try_fold(
+            SchemaBatch::new(),
+            |mut batch, (peer_id, record)| -> Result<_> {
+                match record {
+                    Some(record) => batch.put::<PeerRecordSchema>(peer_id, record)?,
+                    None => batch.delete::<PeerRecordSchema>(peer_id)?,
+                }
+                Ok(batch)
+            },
+        );
+        if let Err(e) = res.and_then(|batch| self.db.write_schemas(batch)) {
+            warn!("Failed to flush the records of peers; error: {:?}", e);
+            // The records are written again by the next flush.
+            let mut records = self.records.lock().unwrap();
+            records
+                .dirty
+                .extend(updates.into_iter().map(|(peer_id, _)| peer_id));
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Reads the records of all the peers from `db`. The records which can't be read are skipped, and
+/// overwritten later.
+fn load_records(db: &DB) -> HashMap<PeerId, PeerRecord> {
+    let mut iter = match db.iter::<PeerRecordSchema>(ReadOptions::default()) {
+        Ok(iter) => iter,
+        Err(e) => {
+            warn!("Failed to load the records of peers; error: {:?}", e);
+            return HashMap::new();
+        }
+    };
+    iter.seek_to_first();
+    iter.filter_map(|res| match res {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            warn!("Failed to load the record of a peer; error: {:?}", e);
+            None
+        }
+    })
+    .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
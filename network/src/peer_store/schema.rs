@@ -0,0 +1,48 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines the physical storage schema of the records of peers.
+//!
+//! Serialized [`PeerRecord`]s identified by the id of the peer.
+//! ```text
+//! |<--key-->|<---value--->|
+//! | peer_id | peer_record |
+//! ```
+
+use super::PeerRecord;
+use crate::proto;
+use failure::prelude::*;
+use prost::Message;
+use prost_ext::MessageExt;
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+    ColumnFamilyName,
+};
+use std::convert::TryFrom;
+use types::PeerId;
+
+pub(super) const PEER_RECORD_CF_NAME: ColumnFamilyName = "peer_record";
+
+define_schema!(PeerRecordSchema, PeerId, PeerRecord, PEER_RECORD_CF_NAME);
+
+impl KeyCodec<PeerRecordSchema> for PeerId {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(self.to_vec())
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        PeerId::try_from(data)
+    }
+}
+
+impl ValueCodec<PeerRecordSchema> for PeerRecord {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        let record: proto::PeerRecord = self.clone().into();
+        Ok(record.to_vec()?)
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        PeerRecord::try_from(proto::PeerRecord::decode(data)?)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use schema::PeerRecordSchema;
+use schemadb::schema::assert_encode_decode;
+use tools::tempdir::TempPath;
+
+fn sample_record() -> PeerRecord {
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/6180".parse().unwrap();
+    let other_addr: Multiaddr = "/ip4/10.0.0.1/tcp/6180".parse().unwrap();
+    let mut dial_stats = HashMap::new();
+    dial_stats.insert(
+        addr.clone(),
+        AddrDialStats {
+            successes: 3,
+            failures: 1,
+            consecutive_failures: 0,
+        },
+    );
+    dial_stats.insert(
+        other_addr.clone(),
+        AddrDialStats {
+            successes: 0,
+            failures: 2,
+            consecutive_failures: 2,
+        },
+    );
+    PeerRecord {
+        addrs: vec![other_addr, addr.clone()],
+        last_connected_addr: Some(addr),
+        dial_stats,
+        smoothed_rtt: Some(Duration::from_millis(42)),
+        supported_protocols: vec![ProtocolId::from_static(b"/libra/ping/0.1.0")],
+        last_updated_secs: 1_570_000_000,
+    }
+}
+
+#[test]
+fn test_encode_decode() {
+    assert_encode_decode::<PeerRecordSchema>(&PeerId::random(), &sample_record());
+    assert_encode_decode::<PeerRecordSchema>(&PeerId::random(), &PeerRecord::default());
+}
+
+#[test]
+fn test_dial_addrs() {
+    let record = sample_record();
+    assert_eq!(
+        record.dial_addrs(),
+        vec![record.addrs[1].clone(), record.addrs[0].clone()]
+    );
+    assert!(PeerRecord::default().dial_addrs().is_empty());
+}
+
+#[test]
+fn test_update_and_reopen() {
+    let tmp_dir = TempPath::new();
+    let peer_id = PeerId::random();
+    let mut record = sample_record();
+    {
+        let store = PeerStore::new(&tmp_dir);
+        assert_eq!(store.get(&peer_id), None);
+        store.update(peer_id, |r| {
+            r.addrs = record.addrs.clone();
+            r.last_connected_addr = record.last_connected_addr.clone();
+            r.dial_stats = record.dial_stats.clone();
+        });
+        // Updates only touch the fields they set, and the time of the update.
+        store.update(peer_id, |r| {
+            r.smoothed_rtt = record.smoothed_rtt;
+            r.supported_protocols = record.supported_protocols.clone();
+        });
+        let stored = store.get(&peer_id).unwrap();
+        assert!(stored.last_updated_secs >= now_secs() - 1);
+        record.last_updated_secs = stored.last_updated_secs;
+        assert_eq!(stored, record);
+    }
+
+    // The records are flushed as the store is closed, and survive it being opened again.
+    let store = PeerStore::new(&tmp_dir);
+    let other_id = PeerId::random();
+    store.update(other_id, |_| ());
+    let all = store.get_all();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[&peer_id], record);
+    assert_eq!(all[&other_id].addrs, vec![]);
+
+    store.remove(&peer_id);
+    assert_eq!(store.get(&peer_id), None);
+    assert_eq!(store.get_all().len(), 1);
+    drop(store);
+    let store = PeerStore::new(&tmp_dir);
+    assert_eq!(store.get(&peer_id), None);
+    assert_eq!(store.get_all().len(), 1);
+}
+
+#[test]
+fn test_flush_and_expire() {
+    let tmp_dir = TempPath::new();
+    let peer_id = PeerId::random();
+    let stale_id = PeerId::random();
+    // Long enough an interval for the flushes of the test to be the only ones.
+    let store = PeerStore::open(
+        &tmp_dir,
+        Duration::from_secs(3600),
+        Duration::from_secs(3600),
+    );
+    store.update(peer_id, |r| {
+        r.smoothed_rtt = Some(Duration::from_millis(42))
+    });
+    store.update(stale_id, |_| ());
+    let stored = |peer_id: &PeerId| store.inner.db.get::<PeerRecordSchema>(peer_id).unwrap();
+
+    // Nothing is written until the records are flushed.
+    assert_eq!(stored(&peer_id), None);
+    store.flush();
+    assert_eq!(stored(&peer_id), store.get(&peer_id));
+    assert_eq!(stored(&stale_id), store.get(&stale_id));
+
+    // The next flush drops the record which wasn't updated for the TTL.
+    store
+        .inner
+        .records
+        .lock()
+        .unwrap()
+        .records
+        .get_mut(&stale_id)
+        .unwrap()
+        .last_updated_secs = now_secs() - 3601;
+    store.flush();
+    assert_eq!(store.get(&stale_id), None);
+    assert_eq!(stored(&stale_id), None);
+    assert!(store.get(&peer_id).is_some());
+}
@@ -26,10 +26,10 @@ pub use self::{
         PacemakerTimeout, PacemakerTimeoutCertificate, Proposal, QuorumCert, RequestBlock,
         RespondBlock, SyncInfo, TimeoutCertificate, TimeoutMsg, Vote, VoteData,
     },
-    mempool::MempoolSyncMsg,
+    mempool::{MempoolSyncMsg, TransactionFilter},
     network::{
-        identity_msg::Role as IdentityMsg_Role, DiscoveryMsg, FullNodePayload, IdentityMsg, Note,
-        PeerInfo, Ping, Pong, SignedFullNodePayload, SignedPeerInfo,
+        identity_msg::Role as IdentityMsg_Role, AddrDialRecord, DiscoveryMsg, FullNodePayload,
+        IdentityMsg, Note, PeerInfo, PeerRecord, Ping, Pong, SignedFullNodePayload, SignedPeerInfo,
     },
     state_synchronizer::{
         state_synchronizer_msg::Message as StateSynchronizerMsg_oneof, GetChunkRequest,
@@ -43,40 +43,80 @@
 //! Note: negotiated substreams are currently framed with the
 //! [muiltiformats unsigned varint length-prefix](https://github.com/multiformats/unsigned-varint)
 //!
+//! ## Batching
+//!
+//! Chatty protocols (e.g. discovery, mempool sync) tend to send many small messages to the same
+//! peer in quick succession. When a [`BatchConfig`] is provided, the dialer holds on to the first
+//! message queued on a substream for up to `window`, and writes every message queued in the
+//! meantime (up to `max_bytes`) to the substream in a single write followed by a single flush.
+//! Each message is still framed individually, so batching is invisible to the listener and
+//! peers with and without batching enabled interoperate.
+//!
+//! ## Unconnected peers
+//!
+//! DirectSend keeps track of the peers PeerManager is connected to, and fails the messages to any
+//! other peer right away instead of queueing them, as they would only be dropped once the
+//! substream fails to open. The queues of a peer are dropped along with its connection.
+//!
+//! ## Message expiry
+//!
+//! A message may carry a deadline past which it is no longer worth delivering, e.g. a consensus
+//! proposal for a round which has timed out. Such a message is dropped instead of being written
+//! out if it is still queued when its deadline passes.
+//!
+//! ## Flush
+//!
+//! Before the node stops, DirectSend can be asked to write out the messages queued so far. It
+//! then drops every message queue, so that the tasks forwarding them end once they have written
+//! out the messages left, and responds once all of these tasks are done. No more messages are sent
+//! out afterwards.
+//!
 //! [muxers]: ../../../netcore/multiplexing/index.html
 //! [substream negotiation]: ../../../netcore/negotiate/index.html
 //! [`protocol-select`]: ../../../netcore/negotiate/index.html
 use crate::{
+    common::NegotiatedSubstream,
     counters,
-    error::NetworkError,
+    error::{NetworkError, NetworkErrorKind},
     peer_manager::{PeerManagerNotification, PeerManagerRequestSender},
     ProtocolId,
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use channel;
 use futures::{
-    compat::Sink01CompatExt,
-    future::{FutureExt, TryFutureExt},
-    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    channel::{mpsc, oneshot},
+    compat::{Future01CompatExt, Sink01CompatExt},
+    future::{self, FutureExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     sink::SinkExt,
     stream::StreamExt,
 };
 use logger::prelude::*;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Debug,
+    io,
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
+use tokio::{
+    codec::{Encoder, Framed},
+    timer,
 };
-use tokio::{codec::Framed, runtime::TaskExecutor};
 use types::PeerId;
 use unsigned_varint::codec::UviBytes;
 
 #[cfg(test)]
 mod test;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Debug)]
 pub enum DirectSendRequest {
-    /// A request to send out a message.
-    SendMessage(PeerId, Message),
+    /// A request to send out a message, which is dropped if it is still queued past the optional
+    /// deadline.
+    SendMessage(PeerId, Message, Option<Instant>),
+    /// A request to write out the messages queued so far, notified once they are. The messages
+    /// sent afterwards are dropped.
+    Flush(oneshot::Sender<()>),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -108,10 +148,20 @@ impl Debug for Message {
     }
 }
 
+/// Configuration of outbound message batching.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BatchConfig {
+    /// How long the first message of a batch may wait for more messages to the same peer and
+    /// protocol before the batch is written out.
+    pub window: Duration,
+    /// A batch is written out as soon as its framed size reaches this many bytes.
+    pub max_bytes: usize,
+}
+
 /// The DirectSend actor.
 pub struct DirectSend<TSubstream> {
-    /// A handle to a tokio executor.
-    executor: TaskExecutor,
+    /// Task manager to spawn the tasks handling the substreams.
+    task_manager: TaskManager,
     /// Channel to receive requests from other upstream actors.
     ds_requests_rx: channel::Receiver<DirectSendRequest>,
     /// Channels to send notifictions to upstream actors.
@@ -120,8 +170,16 @@ pub struct DirectSend<TSubstream> {
     peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
     /// Channel to send requests to PeerManager.
     peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
+    /// Peers PeerManager is connected to.
+    connected_peers: HashSet<PeerId>,
     /// Outbound message queues for each (PeerId, ProtocolId) pair.
-    message_queues: HashMap<(PeerId, ProtocolId), channel::Sender<Bytes>>,
+    message_queues: HashMap<(PeerId, ProtocolId), channel::Sender<(Bytes, Option<Instant>)>>,
+    /// Batching of outbound messages, disabled if `None`.
+    batch_config: Option<BatchConfig>,
+    /// Every message queue task holds a clone of this sender, so that `queue_tasks_rx` ends once
+    /// all of them are done after it is released on flush. `None` once flushed.
+    queue_tasks_tx: Option<mpsc::UnboundedSender<()>>,
+    queue_tasks_rx: Option<mpsc::UnboundedReceiver<()>>,
 }
 
 impl<TSubstream> DirectSend<TSubstream>
@@ -129,19 +187,25 @@ where
     TSubstream: AsyncRead + AsyncWrite + Send + Unpin + Debug + 'static,
 {
     pub fn new(
-        executor: TaskExecutor,
+        task_manager: TaskManager,
         ds_requests_rx: channel::Receiver<DirectSendRequest>,
         ds_notifs_tx: channel::Sender<DirectSendNotification>,
         peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
         peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
+        batch_config: Option<BatchConfig>,
     ) -> Self {
+        let (queue_tasks_tx, queue_tasks_rx) = mpsc::unbounded();
         Self {
-            executor,
+            task_manager,
             ds_requests_rx,
             ds_notifs_tx,
             peer_mgr_notifs_rx,
             peer_mgr_reqs_tx,
+            connected_peers: HashSet::new(),
             message_queues: HashMap::new(),
+            batch_config,
+            queue_tasks_tx: Some(queue_tasks_tx),
+            queue_tasks_rx: Some(queue_tasks_rx),
         }
     }
 
@@ -149,7 +213,7 @@ where
         loop {
             futures::select! {
                 req = self.ds_requests_rx.select_next_some() => {
-                    self.handle_direct_send_request(req).await;
+                    self.handle_direct_send_request(req);
                 }
                 notif = self.peer_mgr_notifs_rx.select_next_some() => {
                     self.handle_peer_mgr_notification(notif);
@@ -162,24 +226,32 @@ where
         }
     }
 
-    // Handle PeerManagerNotification, which can only be NewInboundSubstream for now.
-    fn handle_peer_mgr_notification(&self, notif: PeerManagerNotification<TSubstream>) {
+    // Handle PeerManagerNotification: new inbound substreams, and the peers connecting and
+    // disconnecting.
+    fn handle_peer_mgr_notification(&mut self, notif: PeerManagerNotification<TSubstream>) {
         trace!("PeerManagerNotification::{:?}", notif);
         match notif {
+            PeerManagerNotification::NewPeer(peer_id, _) => {
+                self.connected_peers.insert(peer_id);
+            }
+            PeerManagerNotification::LostPeer(peer_id, _) => {
+                self.connected_peers.remove(&peer_id);
+                // Dropping the queues ends the tasks forwarding them to the closed connection.
+                self.message_queues
+                    .retain(|(queue_peer_id, _), _| *queue_peer_id != peer_id);
+            }
+            PeerManagerNotification::PeerAddressChanged(_, _) => {}
             PeerManagerNotification::NewInboundSubstream(peer_id, substream) => {
-                self.executor.spawn(
+                self.task_manager.spawn(
+                    "inbound_substream",
                     Self::handle_inbound_substream(
                         peer_id,
                         substream.protocol,
                         substream.substream,
                         self.ds_notifs_tx.clone(),
-                    )
-                    .boxed()
-                    .unit_error()
-                    .compat(),
+                    ),
                 );
             }
-            _ => unreachable!("Unexpected PeerManagerNotification"),
         }
     }
 
@@ -223,16 +295,19 @@ where
         );
     }
 
-    // Create a new message queue and spawn a task to forward the messages from the queue to the
-    // corresponding substream.
-    async fn start_message_queue_handler(
-        executor: TaskExecutor,
+    // Create a new message queue and spawn a task to open the corresponding substream and forward
+    // the messages from the queue to it. The substream is opened by the spawned task, so that a
+    // slow peer does not hold up the messages to the other peers.
+    fn start_message_queue_handler(
+        task_manager: &TaskManager,
         mut peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
         peer_id: PeerId,
         protocol: ProtocolId,
-    ) -> Result<channel::Sender<Bytes>, NetworkError> {
+        batch_config: Option<BatchConfig>,
+        queue_task_tx: mpsc::UnboundedSender<()>,
+    ) -> channel::Sender<(Bytes, Option<Instant>)> {
         // Create a channel for the (PeerId, ProtocolId) pair.
-        let (msg_tx, msg_rx) = channel::new::<Bytes>(
+        let (msg_tx, msg_rx) = channel::new::<(Bytes, Option<Instant>)>(
             1024,
             &counters::OP_COUNTERS.peer_gauge(
                 &counters::PENDING_DIRECT_SEND_OUTBOUND_MESSAGES,
@@ -240,19 +315,51 @@ where
             ),
         );
 
-        // Open a new substream for the (PeerId, ProtocolId) pair
-        let raw_substream = peer_mgr_reqs_tx.open_substream(peer_id, protocol).await?;
-        let substream =
-            Framed::new(raw_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
-
-        // Spawn a task to forward the messages from the queue to the substream.
+        // Spawn a task to open a new substream for the (PeerId, ProtocolId) pair and forward the
+        // messages from the queue to it.
         let f_substream = async move {
-            if let Err(e) = msg_rx.map(Ok).forward(substream).await {
-                warn!(
-                    "Forward messages to peer {} error {:?}",
-                    peer_id.short_str(),
-                    e
-                );
+            // Held until the messages left in the queue are written out or dropped.
+            let _queue_task_tx = queue_task_tx;
+            match peer_mgr_reqs_tx.open_substream(peer_id, protocol).await {
+                Ok(NegotiatedSubstream {
+                    substream: raw_substream,
+                    ..
+                }) => {
+                    let result = match batch_config {
+                        Some(batch_config) => {
+                            Self::forward_batched(msg_rx, raw_substream, batch_config).await
+                        }
+                        None => {
+                            let substream =
+                                Framed::new(raw_substream.compat(), UviBytes::<Bytes>::default())
+                                    .sink_compat();
+                            msg_rx
+                                .filter_map(|(mdata, deadline)| {
+                                    future::ready(if is_expired(deadline) {
+                                        None
+                                    } else {
+                                        Some(Ok(mdata))
+                                    })
+                                })
+                                .forward(substream)
+                                .await
+                        }
+                    };
+                    if let Err(e) = result {
+                        warn!(
+                            "Forward messages to peer {} error {:?}",
+                            peer_id.short_str(),
+                            e
+                        );
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to open substream with peer {}: {}",
+                        peer_id.short_str(),
+                        e
+                    );
+                }
             }
             // The messages in queue will be dropped
             counters::DIRECT_SEND_MESSAGES_DROPPED.inc_by(
@@ -264,57 +371,163 @@ where
                     .get(),
             );
         };
-        executor.spawn(f_substream.boxed().unit_error().compat());
+        task_manager.spawn("message_queue", f_substream);
 
-        Ok(msg_tx)
+        msg_tx
+    }
+
+    // Forward the messages from the queue to the substream, coalescing the messages queued within
+    // `batch_config.window` of each other into a single write.
+    async fn forward_batched(
+        mut msg_rx: channel::Receiver<(Bytes, Option<Instant>)>,
+        mut substream: TSubstream,
+        batch_config: BatchConfig,
+    ) -> io::Result<()> {
+        let mut codec = UviBytes::<Bytes>::default();
+        let mut buf = BytesMut::new();
+        while let Some((msg, deadline)) = msg_rx.next().await {
+            if is_expired(deadline) {
+                continue;
+            }
+            codec.encode(msg, &mut buf)?;
+            let mut num_msgs = 1;
+            let mut f_window = timer::Delay::new(Instant::now() + batch_config.window)
+                .compat()
+                .fuse();
+            while buf.len() < batch_config.max_bytes {
+                futures::select! {
+                    (msg, deadline) = msg_rx.select_next_some() => {
+                        if !is_expired(deadline) {
+                            codec.encode(msg, &mut buf)?;
+                            num_msgs += 1;
+                        }
+                    }
+                    _ = f_window => break,
+                }
+            }
+            substream.write_all(&buf).await?;
+            substream.flush().await?;
+            buf.clear();
+            counters::DIRECT_SEND_BATCHES_SENT.inc();
+            counters::DIRECT_SEND_MESSAGES_PER_BATCH.observe(num_msgs as f64);
+        }
+        Ok(())
     }
 
     // Try to send a message to the message queue.
-    async fn try_send_msg(
+    fn try_send_msg(
         &mut self,
         peer_id: PeerId,
         msg: Message,
+        deadline: Option<Instant>,
         peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
     ) -> Result<(), NetworkError> {
         let protocol = msg.protocol.clone();
+        let queue_tasks_tx = match &self.queue_tasks_tx {
+            Some(queue_tasks_tx) => queue_tasks_tx.clone(),
+            None => return Err(NetworkError::from(NetworkErrorKind::ChannelClosed)),
+        };
+        if !self.connected_peers.contains(&peer_id) {
+            // The notification of a new connection may not have been handled yet.
+            self.handle_pending_peer_mgr_notifications();
+            if !self.connected_peers.contains(&peer_id) {
+                return Err(NetworkError::from(NetworkErrorKind::NotConnected).with_peer(peer_id));
+            }
+        }
 
         let substream_queue_tx = match self.message_queues.entry((peer_id, protocol.clone())) {
             Entry::Occupied(entry) => entry.into_mut(),
             Entry::Vacant(entry) => {
                 let msg_tx = Self::start_message_queue_handler(
-                    self.executor.clone(),
+                    &self.task_manager,
                     peer_mgr_reqs_tx,
                     peer_id,
                     protocol.clone(),
-                )
-                .await?;
+                    self.batch_config,
+                    queue_tasks_tx,
+                );
                 entry.insert(msg_tx)
             }
         };
 
-        substream_queue_tx.try_send(msg.mdata).map_err(|e| {
-            // If the channel is full, simply drop the message on the floor;
-            // If the channel is disconnected, remove the message queue from the collection.
-            if e.is_disconnected() {
-                self.message_queues.remove(&(peer_id, protocol));
-            }
-            e.into()
-        })
+        substream_queue_tx
+            .try_send((msg.mdata, deadline))
+            .map_err(|e| {
+                // If the channel is full, simply drop the message on the floor;
+                // If the channel is disconnected, remove the message queue from the collection.
+                if e.is_full() {
+                    counters::OP_COUNTERS
+                        .protocol_counter(
+                            &counters::DIRECT_SEND_QUEUE_OVERFLOWS,
+                            &String::from_utf8_lossy(&protocol),
+                            &peer_id.short_str(),
+                        )
+                        .inc();
+                } else if e.is_disconnected() {
+                    self.message_queues.remove(&(peer_id, protocol));
+                }
+                e.into()
+            })
     }
 
-    // Handle DirectSendRequest, which can only be SendMessage request for now.
-    async fn handle_direct_send_request(&mut self, req: DirectSendRequest) {
+    // Handle the notifications from PeerManager which are already queued, without waiting for
+    // more.
+    fn handle_pending_peer_mgr_notifications(&mut self) {
+        while let Some(Some(notif)) = self.peer_mgr_notifs_rx.next().now_or_never() {
+            self.handle_peer_mgr_notification(notif);
+        }
+    }
+
+    // Handle DirectSendRequest: send out a message, or flush the messages queued so far.
+    fn handle_direct_send_request(&mut self, req: DirectSendRequest) {
         trace!("DirectSendRequest::{:?}", req);
         match req {
-            DirectSendRequest::SendMessage(peer_id, msg) => {
-                if let Err(e) = self
-                    .try_send_msg(peer_id, msg.clone(), self.peer_mgr_reqs_tx.clone())
-                    .await
-                {
+            DirectSendRequest::SendMessage(peer_id, msg, deadline) => {
+                if is_expired(deadline) {
+                    return;
+                }
+                if let Err(e) = self.try_send_msg(
+                    peer_id,
+                    msg.clone(),
+                    deadline,
+                    self.peer_mgr_reqs_tx.clone(),
+                ) {
                     counters::DIRECT_SEND_MESSAGES_DROPPED.inc();
                     warn!("DirectSend to peer {} failed: {}", peer_id.short_str(), e);
                 }
             }
+            DirectSendRequest::Flush(response_tx) => {
+                // Dropping the queues ends the tasks forwarding them once the messages left are
+                // written out.
+                self.message_queues.clear();
+                self.queue_tasks_tx = None;
+                match self.queue_tasks_rx.take() {
+                    Some(mut queue_tasks_rx) => {
+                        let f_flush = async move {
+                            // Nothing is ever sent over the channel, so this only returns once
+                            // every queue task is done.
+                            while queue_tasks_rx.next().await.is_some() {}
+                            let _ = response_tx.send(());
+                        };
+                        self.task_manager.spawn("flush", f_flush);
+                    }
+                    // A previous flush was requested already.
+                    None => {
+                        let _ = response_tx.send(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Returns whether a message with the given deadline has expired, counting it as such if so.
+fn is_expired(deadline: Option<Instant>) -> bool {
+    match deadline {
+        Some(deadline) if deadline <= Instant::now() => {
+            counters::DIRECT_SEND_MESSAGES_EXPIRED.inc();
+            true
         }
+        _ => false,
     }
 }
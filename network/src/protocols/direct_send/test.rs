@@ -6,19 +6,28 @@ use crate::{
     peer_manager::{
         PeerManagerError, PeerManagerNotification, PeerManagerRequest, PeerManagerRequestSender,
     },
-    protocols::direct_send::{DirectSend, DirectSendNotification, DirectSendRequest, Message},
+    protocols::direct_send::{
+        BatchConfig, DirectSend, DirectSendNotification, DirectSendRequest, Message,
+    },
     ProtocolId,
 };
 use bytes::Bytes;
 use channel;
 use futures::{
+    channel::oneshot,
     compat::Sink01CompatExt,
-    future::{FutureExt, TryFutureExt},
+    future::{join, FutureExt, TryFutureExt},
     io::AsyncReadExt,
     sink::SinkExt,
     stream::StreamExt,
 };
 use memsocket::MemorySocket;
+use parity_multiaddr::Multiaddr;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
 use tokio::{
     codec::Framed,
     runtime::{Runtime, TaskExecutor},
@@ -39,17 +48,30 @@ fn start_direct_send_actor(
     channel::Receiver<DirectSendNotification>,
     channel::Sender<PeerManagerNotification<MemorySocket>>,
     channel::Receiver<PeerManagerRequest<MemorySocket>>,
+) {
+    start_direct_send_actor_with_batching(executor, None)
+}
+
+fn start_direct_send_actor_with_batching(
+    executor: TaskExecutor,
+    batch_config: Option<BatchConfig>,
+) -> (
+    channel::Sender<DirectSendRequest>,
+    channel::Receiver<DirectSendNotification>,
+    channel::Sender<PeerManagerNotification<MemorySocket>>,
+    channel::Receiver<PeerManagerRequest<MemorySocket>>,
 ) {
     let (ds_requests_tx, ds_requests_rx) = channel::new_test(8);
     let (ds_notifs_tx, ds_notifs_rx) = channel::new_test(8);
     let (peer_mgr_notifs_tx, peer_mgr_notifs_rx) = channel::new_test(8);
     let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) = channel::new_test(8);
     let direct_send = DirectSend::new(
-        executor.clone(),
+        TaskManager::new("network", executor.clone()),
         ds_requests_rx,
         ds_notifs_tx,
         peer_mgr_notifs_rx,
         PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+        batch_config,
     );
     executor.spawn(direct_send.start().boxed().unit_error().compat());
 
@@ -61,6 +83,22 @@ fn start_direct_send_actor(
     )
 }
 
+// Notify DirectSend that PeerManager is connected to `peer_id`.
+fn connect_peer(
+    rt: &mut Runtime,
+    peer_mgr_notifs_tx: &mut channel::Sender<PeerManagerNotification<MemorySocket>>,
+    peer_id: PeerId,
+) {
+    let address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+    rt.block_on(
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewPeer(peer_id, address))
+            .boxed()
+            .compat(),
+    )
+    .unwrap();
+}
+
 async fn expect_network_provider_recv_message(
     ds_notifs_rx: &mut channel::Receiver<DirectSendNotification>,
     expected_peer_id: PeerId,
@@ -88,7 +126,12 @@ async fn expect_open_substream_request<TSubstream>(
         PeerManagerRequest::OpenSubstream(peer_id, protocol, substream_tx) => {
             assert_eq!(peer_id, expected_peer_id);
             assert_eq!(protocol.as_ref(), expected_protocol);
-            substream_tx.send(response).unwrap();
+            substream_tx
+                .send(response.map(|substream| NegotiatedSubstream {
+                    protocol,
+                    substream,
+                }))
+                .unwrap();
         }
         _ => panic!("Unexpected event"),
     }
@@ -191,10 +234,11 @@ fn test_inbound_substream_closed() {
 fn test_outbound_single_protocol() {
     let mut rt = Runtime::new().unwrap();
 
-    let (mut ds_requests_tx, _ds_notifs_rx, _peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
         start_direct_send_actor(rt.executor());
 
     let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
     let (dialer_substream, listener_substream) = MemorySocket::new_pair();
 
     // Fake the dialer NetworkProvider
@@ -207,6 +251,7 @@ fn test_outbound_single_protocol() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_1),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -217,6 +262,7 @@ fn test_outbound_single_protocol() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_2),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -246,14 +292,205 @@ fn test_outbound_single_protocol() {
         .unwrap();
 }
 
+#[test]
+fn test_outbound_batched() {
+    let mut rt = Runtime::new().unwrap();
+
+    let batch_config = BatchConfig {
+        window: Duration::from_millis(200),
+        max_bytes: 1024,
+    };
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+        start_direct_send_actor_with_batching(rt.executor(), Some(batch_config));
+
+    let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
+    let (dialer_substream, mut listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider
+    let f_network_provider = async move {
+        // Send 3 messages with the same protocol in quick succession.
+        for message in &[MESSAGE_1, MESSAGE_2, MESSAGE_3] {
+            ds_requests_tx
+                .send(DirectSendRequest::SendMessage(
+                    peer_id,
+                    Message {
+                        protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                        mdata: Bytes::from_static(message),
+                    },
+                    None,
+                ))
+                .await
+                .unwrap();
+        }
+
+        expect_open_substream_request(
+            &mut peer_mgr_reqs_rx,
+            peer_id,
+            PROTOCOL_1,
+            Ok(dialer_substream),
+        )
+        .await;
+    };
+
+    // The listener should receive the 3 framed messages in a single write.
+    let f_substream = async move {
+        let mut expected = vec![];
+        for message in &[MESSAGE_1, MESSAGE_2, MESSAGE_3] {
+            expected.push(message.len() as u8);
+            expected.extend_from_slice(message);
+        }
+        let mut buf = [0; 1024];
+        let num_bytes = listener_substream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..num_bytes], expected.as_slice());
+    };
+
+    rt.spawn(f_network_provider.boxed().unit_error().compat());
+    rt.block_on(f_substream.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn test_outbound_expired() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+        start_direct_send_actor(rt.executor());
+
+    let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
+    let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider
+    let f_network_provider = async move {
+        // The first message is past its deadline by the time DirectSend handles it.
+        ds_requests_tx
+            .send(DirectSendRequest::SendMessage(
+                peer_id,
+                Message {
+                    protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                    mdata: Bytes::from_static(MESSAGE_1),
+                },
+                Some(Instant::now()),
+            ))
+            .await
+            .unwrap();
+        ds_requests_tx
+            .send(DirectSendRequest::SendMessage(
+                peer_id,
+                Message {
+                    protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                    mdata: Bytes::from_static(MESSAGE_2),
+                },
+                Some(Instant::now() + Duration::from_secs(60)),
+            ))
+            .await
+            .unwrap();
+
+        expect_open_substream_request(
+            &mut peer_mgr_reqs_rx,
+            peer_id,
+            PROTOCOL_1,
+            Ok(dialer_substream),
+        )
+        .await;
+    };
+
+    // Only the second message should be received.
+    let f_substream = async move {
+        let mut listener_substream =
+            Framed::new(listener_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+        let msg = listener_substream.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_ref(), MESSAGE_2);
+    };
+
+    rt.spawn(f_network_provider.boxed().unit_error().compat());
+    rt.block_on(f_substream.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn test_outbound_flush() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+        start_direct_send_actor(rt.executor());
+
+    let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
+    let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider
+    let f_network_provider = async move {
+        ds_requests_tx
+            .send(DirectSendRequest::SendMessage(
+                peer_id,
+                Message {
+                    protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                    mdata: Bytes::from_static(MESSAGE_1),
+                },
+                None,
+            ))
+            .await
+            .unwrap();
+        let (flush_tx, flush_rx) = oneshot::channel();
+        ds_requests_tx
+            .send(DirectSendRequest::Flush(flush_tx))
+            .await
+            .unwrap();
+        // The messages sent after the flush are dropped.
+        ds_requests_tx
+            .send(DirectSendRequest::SendMessage(
+                peer_id,
+                Message {
+                    protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                    mdata: Bytes::from_static(MESSAGE_2),
+                },
+                None,
+            ))
+            .await
+            .unwrap();
+
+        // The substream is only opened after the flush is requested, which still waits for the
+        // message queued before to be written out.
+        expect_open_substream_request(
+            &mut peer_mgr_reqs_rx,
+            peer_id,
+            PROTOCOL_1,
+            Ok(dialer_substream),
+        )
+        .await;
+        flush_rx.await.unwrap();
+    };
+
+    // Only the first message should be received, after which the substream is closed.
+    let f_substream = async move {
+        let mut listener_substream =
+            Framed::new(listener_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+        let msg = listener_substream.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_ref(), MESSAGE_1);
+        assert!(listener_substream.next().await.is_none());
+    };
+
+    rt.block_on(
+        join(f_network_provider, f_substream)
+            .map(|_| ())
+            .boxed()
+            .unit_error()
+            .compat(),
+    )
+    .unwrap();
+}
+
 #[test]
 fn test_outbound_multiple_protocols() {
     let mut rt = Runtime::new().unwrap();
 
-    let (mut ds_requests_tx, _ds_notifs_rx, _peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
         start_direct_send_actor(rt.executor());
 
     let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
     let (dialer_substream_1, listener_substream_1) = MemorySocket::new_pair();
     let (dialer_substream_2, listener_substream_2) = MemorySocket::new_pair();
 
@@ -267,6 +504,7 @@ fn test_outbound_multiple_protocols() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_1),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -277,6 +515,7 @@ fn test_outbound_multiple_protocols() {
                     protocol: Bytes::from_static(&PROTOCOL_2[..]),
                     mdata: Bytes::from_static(MESSAGE_2),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -320,10 +559,11 @@ fn test_outbound_not_connected() {
     ::logger::try_init_for_testing();
     let mut rt = Runtime::new().unwrap();
 
-    let (mut ds_requests_tx, _ds_notifs_rx, _peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
         start_direct_send_actor(rt.executor());
 
     let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
     let (dialer_substream, listener_substream) = MemorySocket::new_pair();
 
     // Fake the dialer NetworkProvider
@@ -336,6 +576,7 @@ fn test_outbound_not_connected() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_1),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -357,6 +598,7 @@ fn test_outbound_not_connected() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_2),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -386,15 +628,68 @@ fn test_outbound_not_connected() {
         .unwrap();
 }
 
+#[test]
+fn test_outbound_unknown_peer() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+        start_direct_send_actor(rt.executor());
+
+    let unknown_peer_id = PeerId::random();
+    let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
+    let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider
+    let f_network_provider = async move {
+        // Send a message to a peer PeerManager isn't connected to, then to a connected one.
+        for (peer_id, message) in &[(unknown_peer_id, MESSAGE_1), (peer_id, MESSAGE_2)] {
+            ds_requests_tx
+                .send(DirectSendRequest::SendMessage(
+                    *peer_id,
+                    Message {
+                        protocol: Bytes::from_static(&PROTOCOL_1[..]),
+                        mdata: Bytes::from_static(*message),
+                    },
+                    None,
+                ))
+                .await
+                .unwrap();
+        }
+
+        // The first message fails without a substream being requested for it.
+        expect_open_substream_request(
+            &mut peer_mgr_reqs_rx,
+            peer_id,
+            PROTOCOL_1,
+            Ok(dialer_substream),
+        )
+        .await;
+    };
+
+    let f_substream = async move {
+        let mut listener_substream =
+            Framed::new(listener_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+        let msg = listener_substream.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_ref(), MESSAGE_2);
+    };
+
+    rt.spawn(f_network_provider.boxed().unit_error().compat());
+    rt.block_on(f_substream.boxed().unit_error().compat())
+        .unwrap();
+}
+
 #[test]
 fn test_outbound_connection_closed() {
     ::logger::try_init_for_testing();
     let mut rt = Runtime::new().unwrap();
 
-    let (mut ds_requests_tx, _ds_notifs_rx, _peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
+    let (mut ds_requests_tx, _ds_notifs_rx, mut peer_mgr_notifs_tx, mut peer_mgr_reqs_rx) =
         start_direct_send_actor(rt.executor());
 
     let peer_id = PeerId::random();
+    connect_peer(&mut rt, &mut peer_mgr_notifs_tx, peer_id);
     let (dialer_substream_1, listener_substream_1) = MemorySocket::new_pair();
     let (dialer_substream_2, listener_substream_2) = MemorySocket::new_pair();
 
@@ -408,6 +703,7 @@ fn test_outbound_connection_closed() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_1),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -450,6 +746,7 @@ fn test_outbound_connection_closed() {
                     protocol: Bytes::from_static(&PROTOCOL_1[..]),
                     mdata: Bytes::from_static(MESSAGE_2),
                 },
+                None,
             ))
             .await
             .unwrap();
@@ -471,6 +768,7 @@ fn test_outbound_connection_closed() {
                         protocol: Bytes::from_static(&PROTOCOL_1[..]),
                         mdata: Bytes::from_static(MESSAGE_3),
                     },
+                    None,
                 ))
                 .await
                 .unwrap();
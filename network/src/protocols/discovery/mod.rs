@@ -20,7 +20,18 @@
 //! Currently we do not use this mechanism to detect peer failures - instead, we simply connect to
 //! all the peers in the network, and hope to learn about their failure on connection errors.
 //!
-//! TODO: We need to handle to case of peers who may no longer be a part of the network.
+//! The addresses of a peer are passed on ordered by how well dialing them worked so far, as
+//! recorded by the [`ConnectivityManager`] in [`DialStats`], so that the addresses which keep
+//! failing are dialed last. The order is reevaluated on every tick. The addresses which changed
+//! at once, e.g. with the notes of a single discovery message, are passed on together as one
+//! incremental update to the peer set, which leaves the connections to the other peers alone.
+//!
+//! Notes expire: each note carries the time after which it is stale, and expired notes are
+//! dropped from the state of the actor on every tick, so that the addresses of peers which left
+//! the network are eventually forgotten. To keep its own note alive, each node re-signs it with a
+//! new epoch and expiration time once half of its time to live has elapsed. The notes of nodes
+//! which predate the expiration of notes carry no expiration time, and are kept until replaced
+//! with a newer note, as they were before.
 //!
 //! ## Future work
 //!
@@ -34,7 +45,8 @@
 //! [`ConnectivityManager`]: ../../connectivity_manager
 use crate::{
     common::NegotiatedSubstream,
-    connectivity_manager::ConnectivityRequest,
+    connectivity_manager::{ConnectivityRequest, DialStats, PeerSetUpdate},
+    counters,
     error::{NetworkError, NetworkErrorKind},
     peer_manager::{PeerManagerNotification, PeerManagerRequestSender},
     proto::{DiscoveryMsg, FullNodePayload, Note, PeerInfo, SignedFullNodePayload, SignedPeerInfo},
@@ -81,10 +93,19 @@ mod test;
 
 pub const DISCOVERY_PROTOCOL_NAME: &[u8] = b"/libra/discovery/0.1.0";
 
+// TODO(philiphayes): wire through config
+const DNS_SEED_ADDR: &[u8] = b"example.com";
+
 /// The actor running the discovery protocol.
 pub struct Discovery<TTicker, TSubstream> {
     /// Note for self.
     self_note: Note,
+    /// Addresses advertised in the note for self.
+    self_addrs: Vec<Multiaddr>,
+    /// Signer of the note for self.
+    signer: Signer,
+    /// Time to live of the note for self.
+    note_ttl: Duration,
     /// Validator for verifying signatures on messages.
     trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
     /// Current state, maintaining the most recent Note for each peer, alongside parsed PeerInfo.
@@ -102,6 +123,10 @@ pub struct Discovery<TTicker, TSubstream> {
     peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
     /// Channel to send requests to ConnectivityManager.
     conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+    /// Outcomes of the dials to each address, used to order the addresses of peers.
+    dial_stats: DialStats,
+    /// Addresses of each peer last sent to ConnectivityManager.
+    sent_addrs: HashMap<PeerId, Vec<Multiaddr>>,
     /// Message timeout duration.
     msg_timeout: Duration,
     /// Random-number generator.
@@ -123,13 +148,12 @@ where
         peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
         peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
         conn_mgr_reqs_tx: channel::Sender<ConnectivityRequest>,
+        dial_stats: DialStats,
         msg_timeout: Duration,
+        note_ttl: Duration,
     ) -> Self {
-        // TODO(philiphayes): wire through config
-        let dns_seed_addr = b"example.com";
-
-        let self_peer_info = create_peer_info(self_addrs);
-        let self_full_node_payload = create_full_node_payload(dns_seed_addr);
+        let self_peer_info = create_peer_info(self_addrs.clone(), note_ttl);
+        let self_full_node_payload = create_full_node_payload(DNS_SEED_ADDR);
         let self_note = create_note(
             &signer,
             self_peer_id,
@@ -142,6 +166,9 @@ where
             .collect();
         Self {
             self_note,
+            self_addrs,
+            signer,
+            note_ttl,
             seed_peers,
             trusted_peers,
             known_peers,
@@ -150,6 +177,8 @@ where
             peer_mgr_reqs_tx,
             peer_mgr_notifs_rx,
             conn_mgr_reqs_tx,
+            dial_stats,
+            sent_addrs: HashMap::new(),
             msg_timeout,
             rng: SmallRng::from_entropy(),
         }
@@ -161,29 +190,118 @@ where
         debug!("Connecting to seed peers");
         let self_peer_id =
             PeerId::try_from(self.self_note.peer_id.clone()).expect("PeerId parsing failed");
-        for (peer_id, peer_info) in self
+        let seed_peer_ids: Vec<_> = self
             .seed_peers
+            .keys()
+            .filter(|peer_id| **peer_id != self_peer_id)
+            .cloned()
+            .collect();
+        self.send_peer_addrs(seed_peer_ids).await;
+    }
+
+    // Sends the addresses of the given peers to ConnectivityManager in a single update, leaving
+    // out the peers whose addresses were already sent.
+    async fn send_peer_addrs(&mut self, peer_ids: Vec<PeerId>) {
+        let updates: Vec<_> = peer_ids
+            .into_iter()
+            .filter_map(|peer_id| self.peer_addrs_update(peer_id))
+            .collect();
+        if updates.is_empty() {
+            return;
+        }
+        self.conn_mgr_reqs_tx
+            .send(ConnectivityRequest::UpdatePeerSet(updates))
+            .await
+            .expect("ConnectivityRequest::UpdatePeerSet send");
+    }
+
+    // Returns the update to the addresses of the given peer, ordered by their dial stats, unless
+    // they were already sent in the same order.
+    fn peer_addrs_update(&mut self, peer_id: PeerId) -> Option<PeerSetUpdate> {
+        // The multiaddrs in the peer's discovery Note.
+        let mut peer_addrs: Vec<Multiaddr> = match self.known_peers.get(&peer_id) {
+            Some((peer_info, _)) => peer_info
+                .addrs
+                .iter()
+                .cloned()
+                .map(|addr| Multiaddr::try_from(addr).expect("Multiaddr parsing fails"))
+                .collect(),
+            None => vec![],
+        };
+
+        // Append the addrs in the seed PeerInfo if this peer is
+        // configured as one of our seed peers.
+        if let Some(seed_info) = self.seed_peers.get(&peer_id) {
+            let seed_addrs_iter = seed_info
+                .addrs
+                .iter()
+                .cloned()
+                .map(|addr| Multiaddr::try_from(addr).expect("Multiaddr parsing fails"));
+            peer_addrs.extend(seed_addrs_iter);
+        }
+
+        self.dial_stats.sort_addrs(&mut peer_addrs);
+        if self.sent_addrs.get(&peer_id) == Some(&peer_addrs) {
+            return None;
+        }
+        self.sent_addrs.insert(peer_id, peer_addrs.clone());
+        Some(PeerSetUpdate::UpdateAddresses(peer_id, peer_addrs))
+    }
+
+    // Sends the addresses of the peers whose order changed with the latest dial stats.
+    async fn reorder_peer_addrs(&mut self) {
+        let peer_ids: Vec<_> = self.sent_addrs.keys().cloned().collect();
+        self.send_peer_addrs(peer_ids).await;
+    }
+
+    // Re-signs the note for self with a new epoch and expiration time, once half of its time to
+    // live has elapsed.
+    fn refresh_self_note(&mut self) {
+        let self_peer_id =
+            PeerId::try_from(self.self_note.peer_id.clone()).expect("PeerId parsing fails");
+        let half_ttl = self.note_ttl.as_millis() as u64 / 2;
+        match self.known_peers.get(&self_peer_id) {
+            Some((peer_info, _)) if now_millis() + half_ttl < peer_info.expiration_time => return,
+            _ => {}
+        }
+        debug!("Refreshing own discovery note");
+        let self_peer_info = create_peer_info(self.self_addrs.clone(), self.note_ttl);
+        self.self_note = create_note(
+            &self.signer,
+            self_peer_id,
+            self_peer_info.clone(),
+            create_full_node_payload(DNS_SEED_ADDR),
+        );
+        self.known_peers
+            .insert(self_peer_id, (self_peer_info, self.self_note.clone()));
+    }
+
+    // Drops the expired notes of other peers, and sends the addresses left for these peers, i.e.,
+    // the ones of their seed PeerInfo if any, to ConnectivityManager.
+    async fn expire_notes(&mut self) {
+        let self_peer_id =
+            PeerId::try_from(self.self_note.peer_id.clone()).expect("PeerId parsing fails");
+        let now = now_millis();
+        let expired: Vec<_> = self
+            .known_peers
             .iter()
-            .filter(|(peer_id, _)| **peer_id != self_peer_id)
-        {
-            self.conn_mgr_reqs_tx
-                .send(ConnectivityRequest::UpdateAddresses(
-                    *peer_id,
-                    peer_info
-                        .addrs
-                        .iter()
-                        .cloned()
-                        .map(|addr| Multiaddr::try_from(addr).expect("Multiaddr parsing failed"))
-                        .collect(),
-                ))
-                .await
-                .expect("ConnectivityRequest::UpdateAddresses send");
+            .filter(|(peer_id, (peer_info, _))| {
+                **peer_id != self_peer_id && is_expired(peer_info, now)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in &expired {
+            info!("Discovery note of peer {} expired", peer_id.short_str());
+            self.known_peers.remove(peer_id);
+            counters::DISCOVERY_NOTES_EXPIRED.inc();
         }
+        self.send_peer_addrs(expired).await;
     }
 
     // Starts the main event loop for the discovery actor. We bootstrap by first dialing all the
     // seed peers, and then entering the event handling loop. Messages are received from:
-    // - a ticker to trigger discovery message send to a random connected peer
+    // - a ticker to refresh the note for self, drop expired notes, and trigger discovery message
+    // send to a random connected peer
     // - an incoming substream from a peer wishing to send its state
     // - an internal task once it has processed incoming messages from a peer, and wishes for
     // discovery actor to update its state.
@@ -195,7 +313,10 @@ where
         loop {
             futures::select! {
                 _ = self.ticker.select_next_some() => {
+                    self.refresh_self_note();
+                    self.expire_notes().await;
                     self.handle_tick(&mut unprocessed_outbound);
+                    self.reorder_peer_addrs().await;
                 }
                 notif = self.peer_mgr_notifs_rx.select_next_some() => {
                     self.handle_peer_mgr_notification(notif, &mut unprocessed_inbound);
@@ -268,6 +389,9 @@ where
                 // Add peer to connected peer list.
                 self.connected_peers.insert(peer_id, addr);
             }
+            PeerManagerNotification::PeerAddressChanged(peer_id, addr) => {
+                self.connected_peers.insert(peer_id, addr);
+            }
             PeerManagerNotification::LostPeer(peer_id, addr) => {
                 match self.connected_peers.get(&peer_id) {
                     Some(curr_addr) if *curr_addr == addr => {
@@ -326,10 +450,20 @@ where
         // corresponding entry in the map.
         let self_peer_id =
             PeerId::try_from(self.self_note.peer_id.clone()).expect("PeerId parsing fails");
+        let now = now_millis();
+        let mut updated = vec![];
         for note in remote_notes {
             let peer_id = PeerId::try_from(note.peer_id.clone()).expect("PeerId parsing fails");
             let peer_info_bytes = &note.signed_peer_info.as_ref().unwrap().peer_info;
             let peer_info = PeerInfo::decode(peer_info_bytes).expect("PeerInfo parsing fails");
+            if is_expired(&peer_info, now) {
+                debug!(
+                    "Received expired note for peer: {} from peer: {}",
+                    peer_id.short_str(),
+                    remote_peer.short_str()
+                );
+                continue;
+            }
 
             match self.known_peers.get_mut(&peer_id) {
                 // If we know about this peer, and receive the same or an older epoch, we do
@@ -354,46 +488,38 @@ where
                     // ourselves have broadcasted.
                     assert_ne!(peer_id, self_peer_id);
                     // Update internal state of the peer with new Note.
-                    self.known_peers.insert(peer_id, (peer_info.clone(), note));
-
-                    // The multiaddrs in the peer's discovery Note.
-                    let mut peer_addrs: Vec<Multiaddr> = peer_info
-                        .addrs
-                        .iter()
-                        .cloned()
-                        .map(|addr| Multiaddr::try_from(addr).expect("Multiaddr parsing fails"))
-                        .collect();
-
-                    // Append the addrs in the seed PeerInfo if this peer is
-                    // configured as one of our seed peers.
-                    if let Some(seed_info) = self.seed_peers.get(&peer_id) {
-                        let seed_addrs_iter = seed_info.addrs.iter().cloned().map(|addr| {
-                            Multiaddr::try_from(addr).expect("Multiaddr parsing fails")
-                        });
-                        peer_addrs.extend(seed_addrs_iter);
-                    }
-
-                    self.conn_mgr_reqs_tx
-                        .send(ConnectivityRequest::UpdateAddresses(peer_id, peer_addrs))
-                        .await
-                        .expect("ConnectivityRequest::UpdateAddresses send");
+                    self.known_peers.insert(peer_id, (peer_info, note));
+                    updated.push(peer_id);
                 }
             }
         }
+        self.send_peer_addrs(updated).await;
     }
 }
 
-// Creates a PeerInfo combining the given addresses with the current unix timestamp as epoch.
-fn create_peer_info(addrs: Vec<Multiaddr>) -> PeerInfo {
-    let mut peer_info = PeerInfo::default();
+// Milliseconds elapsed since the unix epoch.
+fn now_millis() -> u64 {
     // TODO: Currently, SystemTime::now() in Rust is not guaranteed to use a monotonic clock.
     // At the moment, it's unclear how to do this in a platform-agnostic way. For Linux, we
     // could use something like the [timerfd trait](https://docs.rs/crate/timerfd/1.0.0).
-    let time_since_epoch = SystemTime::now()
+    SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("System clock reset to before unix epoch")
-        .as_millis() as u64;
+        .as_millis() as u64
+}
+
+// Notes from the nodes which predate their expiration carry no expiration time, and never expire.
+fn is_expired(peer_info: &PeerInfo, now: u64) -> bool {
+    peer_info.expiration_time != 0 && peer_info.expiration_time <= now
+}
+
+// Creates a PeerInfo combining the given addresses with the current unix timestamp as epoch,
+// which expires after `ttl`.
+fn create_peer_info(addrs: Vec<Multiaddr>, ttl: Duration) -> PeerInfo {
+    let mut peer_info = PeerInfo::default();
+    let time_since_epoch = now_millis();
     peer_info.epoch = time_since_epoch;
+    peer_info.expiration_time = time_since_epoch.saturating_add(ttl.as_millis() as u64);
     peer_info.addrs = addrs.into_iter().map(|addr| addr.as_ref().into()).collect();
     peer_info
 }
@@ -403,11 +529,7 @@ fn create_full_node_payload(dns_seed_addr: &[u8]) -> FullNodePayload {
     // TODO: Currently, SystemTime::now() in Rust is not guaranteed to use a monotonic clock.
     // At the moment, it's unclear how to do this in a platform-agnostic way. For Linux, we
     // could use something like the [timerfd trait](https://docs.rs/crate/timerfd/1.0.0).
-    let time_since_epoch = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("System clock reset to before unix epoch")
-        .as_millis() as u64;
-    full_node_payload.epoch = time_since_epoch;
+    full_node_payload.epoch = now_millis();
     full_node_payload.dns_seed_addr = dns_seed_addr.into();
     full_node_payload
 }
@@ -481,7 +603,7 @@ where
         Ok(msg.notes)
     });
 
-    (peer_id, res_notes)
+    (peer_id, res_notes.map_err(|err| err.with_peer(peer_id)))
 }
 
 // Verifies validity of notes. Following conditions should be met for validity:
@@ -594,7 +716,8 @@ where
     // Request a new substream to peer.
     let substream = sender
         .open_substream(peer_id, ProtocolId::from_static(DISCOVERY_PROTOCOL_NAME))
-        .await?;
+        .await?
+        .substream;
     // Messages are length-prefixed. Wrap in a framed stream.
     let mut substream = Framed::new(substream.compat(), UviBytes::default()).sink_compat();
     // Send serialized message to peer.
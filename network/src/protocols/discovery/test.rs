@@ -7,12 +7,17 @@ use core::str::FromStr;
 use crypto::{test_utils::TEST_SEED, *};
 use futures::future::{FutureExt, TryFutureExt};
 use memsocket::MemorySocket;
+use proptest::{collection::vec, prelude::*};
 use rand::{rngs::StdRng, SeedableRng};
+use std::thread;
 use tokio::runtime::Runtime;
 
+const NOTE_TTL: Duration = Duration::from_secs(3600);
+
 fn gen_peer_info() -> PeerInfo {
     let mut peer_info = PeerInfo::default();
     peer_info.epoch = 1;
+    peer_info.expiration_time = u64::max_value();
     peer_info.addrs.push(
         Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090")
             .unwrap()
@@ -55,6 +60,8 @@ fn setup_discovery(
     seed_peer_info: PeerInfo,
     signer: Signer,
     trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    dial_stats: DialStats,
+    note_ttl: Duration,
 ) -> (
     channel::Receiver<PeerManagerRequest<MemorySocket>>,
     channel::Receiver<ConnectivityRequest>,
@@ -76,7 +83,9 @@ fn setup_discovery(
             PeerManagerRequestSender::new(peer_mgr_reqs_tx),
             peer_mgr_notifs_rx,
             conn_mgr_reqs_tx,
+            dial_stats,
             Duration::from_secs(180),
+            note_ttl,
         )
     };
     rt.spawn(discovery.start().boxed().unit_error().compat());
@@ -92,11 +101,27 @@ async fn expect_address_update(
     conn_mgr_reqs_rx: &mut channel::Receiver<ConnectivityRequest>,
     expected_peer_id: PeerId,
     expected_addrs: &[Multiaddr],
+) {
+    expect_address_updates(conn_mgr_reqs_rx, &[(expected_peer_id, expected_addrs)]).await;
+}
+
+// Expects the addresses of several peers, in a single update to the peer set.
+async fn expect_address_updates(
+    conn_mgr_reqs_rx: &mut channel::Receiver<ConnectivityRequest>,
+    expected: &[(PeerId, &[Multiaddr])],
 ) {
     match conn_mgr_reqs_rx.next().await.unwrap() {
-        ConnectivityRequest::UpdateAddresses(peer_id, addrs) => {
-            assert_eq!(expected_peer_id, peer_id);
-            assert_eq!(expected_addrs, &addrs[..]);
+        ConnectivityRequest::UpdatePeerSet(updates) => {
+            assert_eq!(updates.len(), expected.len());
+            for (update, (expected_peer_id, expected_addrs)) in updates.iter().zip(expected) {
+                match update {
+                    PeerSetUpdate::UpdateAddresses(peer_id, addrs) => {
+                        assert_eq!(expected_peer_id, peer_id);
+                        assert_eq!(expected_addrs, &&addrs[..]);
+                    }
+                    update => panic!("Unexpected update to the peer set: {:?}", update),
+                }
+            }
         }
         req => {
             panic!("Unexpected request to connectivity manager: {:?}", req);
@@ -117,6 +142,58 @@ fn generate_network_pub_keys_and_signer(peer_id: PeerId) -> (NetworkPublicKeys,
     )
 }
 
+prop_compose! {
+    fn arb_peer_info()(
+        epoch in any::<u64>(),
+        addrs in vec((any::<[u8; 4]>(), any::<u16>()), 0..4),
+    ) -> PeerInfo {
+        let mut peer_info = PeerInfo::default();
+        peer_info.epoch = epoch;
+        peer_info.addrs = addrs
+            .into_iter()
+            .map(|(ip, port)| {
+                let addr = format!("/ip4/{}.{}.{}.{}/tcp/{}", ip[0], ip[1], ip[2], ip[3], port);
+                Multiaddr::from_str(&addr).unwrap().as_ref().into()
+            })
+            .collect();
+        peer_info
+    }
+}
+
+prop_compose! {
+    fn arb_full_node_payload()(
+        epoch in any::<u64>(),
+        dns_seed_addr in vec(any::<u8>(), 0..32),
+    ) -> FullNodePayload {
+        let mut payload = FullNodePayload::default();
+        payload.epoch = epoch;
+        payload.dns_seed_addr = dns_seed_addr;
+        payload
+    }
+}
+
+/// Generates a DiscoveryMsg made of a valid note from each of a few peers, along with the keys of
+/// these peers.
+fn arb_discovery_msg() -> impl Strategy<Value = (HashMap<PeerId, NetworkPublicKeys>, DiscoveryMsg)>
+{
+    vec(
+        (any::<[u8; 32]>(), arb_peer_info(), arb_full_node_payload()),
+        1..4,
+    )
+    .prop_map(|notes| {
+        let mut trusted_peers = HashMap::new();
+        let mut msg = DiscoveryMsg::default();
+        for (peer_id, peer_info, full_node_payload) in notes {
+            let peer_id = PeerId::new(peer_id);
+            let (network_pub_keys, signer) = generate_network_pub_keys_and_signer(peer_id);
+            trusted_peers.insert(peer_id, network_pub_keys);
+            msg.notes
+                .push(create_note(&signer, peer_id, peer_info, full_node_payload));
+        }
+        (trusted_peers, msg)
+    })
+}
+
 #[test]
 // Test behavior on receipt of an inbound DiscoveryMsg.
 fn inbound() {
@@ -149,6 +226,8 @@ fn inbound() {
         seed_peer_info.clone(),
         self_signer,
         trusted_peers.clone(),
+        DialStats::new(),
+        NOTE_TTL,
     );
 
     // Fake connectivity manager and dialer.
@@ -191,6 +270,7 @@ fn inbound() {
             .insert(peer_id_other, pub_keys_other);
         let note_other = {
             let mut peer_info = PeerInfo::default();
+            peer_info.expiration_time = u64::max_value();
             peer_info.addrs = addrs_other
                 .iter()
                 .map(|addr| addr.as_ref().into())
@@ -206,19 +286,19 @@ fn inbound() {
             .await
             .unwrap();
 
-        // Connectivity manager receives address of new peer.
-        expect_address_update(&mut conn_mgr_reqs_rx, peer_id_other, &addrs_other[..]).await;
-
         // The addrs sent to connectivity manager should also include the
         // configured seed peer addrs for seed-peer-received notes.
         let mut expected_seed_addrs = seed_peer_addrs.clone();
         expected_seed_addrs.extend_from_slice(&seed_peer_addrs[..]);
 
-        // Connectivity manager receives a connect to the seed peer at the same address.
-        expect_address_update(
+        // Connectivity manager receives address of new peer, along with a connect to the seed
+        // peer at the same address.
+        expect_address_updates(
             &mut conn_mgr_reqs_rx,
-            seed_peer_id,
-            &expected_seed_addrs[..],
+            &[
+                (peer_id_other, &addrs_other[..]),
+                (seed_peer_id, &expected_seed_addrs[..]),
+            ],
         )
         .await;
 
@@ -243,7 +323,7 @@ fn inbound() {
         msg.notes.push(note_other);
         let new_seed_addrs = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/8098").unwrap()];
         {
-            let seed_peer_info = create_peer_info(new_seed_addrs.clone());
+            let seed_peer_info = create_peer_info(new_seed_addrs.clone(), NOTE_TTL);
             let seed_note = create_note(
                 &seed_signer,
                 seed_peer_id,
@@ -306,6 +386,8 @@ fn outbound() {
             seed_peer_info.clone(),
             self_signer,
             trusted_peers.clone(),
+            DialStats::new(),
+            NOTE_TTL,
         );
 
     // Fake connectivity manager and dialer.
@@ -328,7 +410,11 @@ fn outbound() {
             PeerManagerRequest::OpenSubstream(peer, protocol, ch) => {
                 assert_eq!(peer, seed_peer_id);
                 assert_eq!(protocol, DISCOVERY_PROTOCOL_NAME);
-                ch.send(Ok(dialer_substream)).unwrap();
+                ch.send(Ok(NegotiatedSubstream {
+                    protocol,
+                    substream: dialer_substream,
+                }))
+                .unwrap();
             }
             req => {
                 panic!("Unexpected request to peer manager: {:?}", req);
@@ -378,6 +464,8 @@ fn addr_update_includes_seed_addrs() {
         seed_peer_info,
         self_signer,
         trusted_peers,
+        DialStats::new(),
+        NOTE_TTL,
     );
 
     // Fake connectivity manager and dialer.
@@ -405,7 +493,7 @@ fn addr_update_includes_seed_addrs() {
         // The discovery actor should send the addrs in the new seed peer note
         // _and_ the configured seed addrs to the connectivity manager.
         let new_seed_addrs = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap()];
-        let new_seed_info = create_peer_info(new_seed_addrs.clone());
+        let new_seed_info = create_peer_info(new_seed_addrs.clone(), NOTE_TTL);
         let seed_note = create_note(&seed_signer, seed_peer_id, new_seed_info, seed_peer_payload);
         let mut msg = DiscoveryMsg::default();
         msg.notes.push(seed_note.clone());
@@ -431,3 +519,297 @@ fn addr_update_includes_seed_addrs() {
     rt.block_on(f_peer_mgr.boxed().unit_error().compat())
         .unwrap();
 }
+
+#[test]
+fn addrs_reordered_by_dial_stats() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let peer_id = PeerId::random();
+    let addrs = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+    let (self_pub_keys, self_signer) = generate_network_pub_keys_and_signer(peer_id);
+
+    // Setup seed, with two addresses.
+    let seed_peer_addrs = vec![
+        Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap(),
+        Multiaddr::from_str("/ip4/127.0.0.1/tcp/9092").unwrap(),
+    ];
+    let seed_peer_info = create_peer_info(seed_peer_addrs.clone(), NOTE_TTL);
+    let seed_peer_id = PeerId::random();
+    let (seed_pub_keys, _) = generate_network_pub_keys_and_signer(seed_peer_id);
+    let trusted_peers = Arc::new(RwLock::new(
+        vec![(seed_peer_id, seed_pub_keys), (peer_id, self_pub_keys)]
+            .into_iter()
+            .collect(),
+    ));
+
+    // Setup discovery.
+    let dial_stats = DialStats::new();
+    let (_, mut conn_mgr_reqs_rx, _, mut ticker_tx) = setup_discovery(
+        &mut rt,
+        peer_id,
+        addrs,
+        seed_peer_id,
+        seed_peer_info,
+        self_signer,
+        trusted_peers,
+        dial_stats.clone(),
+        NOTE_TTL,
+    );
+
+    // Fake connectivity manager.
+    let f_conn_mgr = async move {
+        // Connectivity manager receives addresses of the seed peer during bootstrap, in the
+        // configured order.
+        expect_address_update(&mut conn_mgr_reqs_rx, seed_peer_id, &seed_peer_addrs[..]).await;
+
+        // Dials to the first address fail, so it is handed out last from the next tick on.
+        dial_stats.record_failure(&seed_peer_addrs[0]);
+        ticker_tx.send(()).await.unwrap();
+        let reordered_addrs = vec![seed_peer_addrs[1].clone(), seed_peer_addrs[0].clone()];
+        expect_address_update(&mut conn_mgr_reqs_rx, seed_peer_id, &reordered_addrs[..]).await;
+
+        // Once the first address works again, the configured order is restored.
+        dial_stats.record_success(&seed_peer_addrs[0]);
+        ticker_tx.send(()).await.unwrap();
+        expect_address_update(&mut conn_mgr_reqs_rx, seed_peer_id, &seed_peer_addrs[..]).await;
+    };
+    rt.block_on(f_conn_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+// Test that notes are ignored once expired, and dropped when they expire.
+fn expired_notes_dropped() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let peer_id = PeerId::random();
+    let addrs = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+    let (self_pub_keys, self_signer) = generate_network_pub_keys_and_signer(peer_id);
+
+    // Setup seed.
+    let seed_peer_info = gen_peer_info();
+    let seed_peer_addrs = get_addrs_from_info(&seed_peer_info);
+    let seed_peer_id = PeerId::random();
+    let (seed_pub_keys, _) = generate_network_pub_keys_and_signer(seed_peer_id);
+    let trusted_peers = Arc::new(RwLock::new(
+        vec![(seed_peer_id, seed_pub_keys), (peer_id, self_pub_keys)]
+            .into_iter()
+            .collect(),
+    ));
+
+    // Setup discovery.
+    let (_, mut conn_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut ticker_tx) = setup_discovery(
+        &mut rt,
+        peer_id,
+        addrs,
+        seed_peer_id,
+        seed_peer_info,
+        self_signer,
+        trusted_peers.clone(),
+        DialStats::new(),
+        NOTE_TTL,
+    );
+
+    // Fake connectivity manager and dialer.
+    let f_peer_mgr = async move {
+        // Connectivity manager receives addresses of the seed peer during bootstrap.
+        expect_address_update(&mut conn_mgr_reqs_rx, seed_peer_id, &seed_peer_addrs[..]).await;
+
+        // Notify discovery actor of inbound substream.
+        let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewInboundSubstream(
+                seed_peer_id,
+                NegotiatedSubstream {
+                    protocol: ProtocolId::from_static(DISCOVERY_PROTOCOL_NAME),
+                    substream: listener_substream,
+                },
+            ))
+            .await
+            .unwrap();
+        // Wrap dialer substream in a framed substream.
+        let mut dialer_substream =
+            Framed::new(dialer_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+
+        // Send DiscoveryMsg consisting of an expired note of a peer, followed by a note of
+        // another peer which expires shortly, and a note without expiration time from a node
+        // which predates their expiration.
+        let mut msg = DiscoveryMsg::default();
+        let peer_id_expired = PeerId::random();
+        let (pub_keys_expired, signer_expired) =
+            generate_network_pub_keys_and_signer(peer_id_expired);
+        let mut peer_info_expired = create_peer_info(
+            vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9091").unwrap()],
+            NOTE_TTL,
+        );
+        peer_info_expired.expiration_time = 1;
+        msg.notes.push(create_note(
+            &signer_expired,
+            peer_id_expired,
+            peer_info_expired,
+            gen_full_node_payload(),
+        ));
+        let peer_id_other = PeerId::random();
+        let addrs_other = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9092").unwrap()];
+        let (pub_keys_other, signer_other) = generate_network_pub_keys_and_signer(peer_id_other);
+        msg.notes.push(create_note(
+            &signer_other,
+            peer_id_other,
+            create_peer_info(addrs_other.clone(), Duration::from_millis(500)),
+            gen_full_node_payload(),
+        ));
+        let peer_id_legacy = PeerId::random();
+        let addrs_legacy = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9093").unwrap()];
+        let (pub_keys_legacy, signer_legacy) = generate_network_pub_keys_and_signer(peer_id_legacy);
+        let mut peer_info_legacy = create_peer_info(addrs_legacy.clone(), NOTE_TTL);
+        peer_info_legacy.expiration_time = 0;
+        msg.notes.push(create_note(
+            &signer_legacy,
+            peer_id_legacy,
+            peer_info_legacy,
+            gen_full_node_payload(),
+        ));
+        {
+            let mut trusted_peers = trusted_peers.write().unwrap();
+            trusted_peers.insert(peer_id_expired, pub_keys_expired);
+            trusted_peers.insert(peer_id_other, pub_keys_other);
+            trusted_peers.insert(peer_id_legacy, pub_keys_legacy);
+        }
+        dialer_substream
+            .send(msg.to_bytes().unwrap())
+            .await
+            .unwrap();
+
+        // Connectivity manager only receives the addresses of the peers whose note is not
+        // expired.
+        expect_address_updates(
+            &mut conn_mgr_reqs_rx,
+            &[
+                (peer_id_other, &addrs_other[..]),
+                (peer_id_legacy, &addrs_legacy[..]),
+            ],
+        )
+        .await;
+
+        // Once the note expired, the addresses of the peer are dropped on the next tick.
+        thread::sleep(Duration::from_millis(600));
+        ticker_tx.send(()).await.unwrap();
+        expect_address_update(&mut conn_mgr_reqs_rx, peer_id_other, &[]).await;
+    };
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+// Test that the note for self is refreshed before it expires.
+fn self_note_refreshed() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+
+    // Setup self.
+    let peer_id = PeerId::random();
+    let addrs = vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap()];
+    let (self_pub_keys, self_signer) = generate_network_pub_keys_and_signer(peer_id);
+
+    // Setup seed.
+    let seed_peer_id = PeerId::random();
+    let seed_peer_info = gen_peer_info();
+    let seed_peer_addrs = get_addrs_from_info(&seed_peer_info);
+    let (seed_pub_keys, _) = generate_network_pub_keys_and_signer(seed_peer_id);
+    let trusted_peers = Arc::new(RwLock::new(
+        vec![(seed_peer_id, seed_pub_keys), (peer_id, self_pub_keys)]
+            .into_iter()
+            .collect(),
+    ));
+
+    // Setup discovery, with a note for self which expires shortly.
+    let (mut peer_mgr_reqs_rx, _conn_mgr_req_rx, mut peer_mgr_notifs_tx, mut ticker_tx) =
+        setup_discovery(
+            &mut rt,
+            peer_id,
+            addrs.clone(),
+            seed_peer_id,
+            seed_peer_info,
+            self_signer,
+            trusted_peers,
+            DialStats::new(),
+            Duration::from_millis(200),
+        );
+
+    // Fake connectivity manager and dialer.
+    let f_peer_mgr = async move {
+        let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+        // Notify discovery actor of connection to seed peer.
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewPeer(
+                seed_peer_id,
+                seed_peer_addrs[0].clone(),
+            ))
+            .await
+            .unwrap();
+
+        // Trigger outbound msg once the initial note for self expired.
+        thread::sleep(Duration::from_millis(300));
+        ticker_tx.send(()).await.unwrap();
+
+        // Request outgoing substream from PeerManager.
+        match peer_mgr_reqs_rx.next().await.unwrap() {
+            PeerManagerRequest::OpenSubstream(peer, protocol, ch) => {
+                assert_eq!(peer, seed_peer_id);
+                assert_eq!(protocol, DISCOVERY_PROTOCOL_NAME);
+                ch.send(Ok(NegotiatedSubstream {
+                    protocol,
+                    substream: dialer_substream,
+                }))
+                .unwrap();
+            }
+            req => {
+                panic!("Unexpected request to peer manager: {:?}", req);
+            }
+        }
+
+        // The note for self in the DiscoveryMsg was refreshed.
+        let msg = recv_msg(listener_substream).await.unwrap();
+        assert_eq!(1, msg.notes.len());
+        assert_eq!(Vec::from(peer_id), msg.notes[0].peer_id);
+        assert_eq!(addrs, get_addrs_from_note(&msg.notes[0]));
+        let signed_peer_info = msg.notes[0].signed_peer_info.as_ref().unwrap();
+        let peer_info = PeerInfo::decode(&signed_peer_info.peer_info).unwrap();
+        assert!(!is_expired(&peer_info, now_millis()));
+    };
+
+    rt.block_on(f_peer_mgr.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn notes_without_expiration_time_never_expire() {
+    let mut peer_info = gen_peer_info();
+    peer_info.expiration_time = 0;
+    assert!(!is_expired(&peer_info, now_millis()));
+    peer_info.expiration_time = 1;
+    assert!(is_expired(&peer_info, now_millis()));
+}
+
+proptest! {
+    #[test]
+    fn generated_notes_are_valid((trusted_peers, msg) in arb_discovery_msg()) {
+        let trusted_peers = RwLock::new(trusted_peers);
+        for note in &msg.notes {
+            prop_assert!(is_valid(note, &trusted_peers).is_ok());
+        }
+    }
+
+    #[test]
+    fn tampered_notes_are_invalid((trusted_peers, mut msg) in arb_discovery_msg()) {
+        let signed_peer_info = msg.notes[0].signed_peer_info.as_mut().unwrap();
+        let mut peer_info = PeerInfo::decode(&signed_peer_info.peer_info).unwrap();
+        peer_info.epoch = peer_info.epoch.wrapping_add(1);
+        signed_peer_info.peer_info = peer_info.to_bytes().unwrap().to_vec();
+        prop_assert!(is_valid(&msg.notes[0], &RwLock::new(trusted_peers)).is_err());
+    }
+}
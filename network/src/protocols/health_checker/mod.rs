@@ -4,12 +4,19 @@
 //! Protocol used to ensure peer liveness
 //!
 //! The HealthChecker is responsible for ensuring liveness of all peers of a node.
-//! It does so by periodically selecting a random connected peer and sending a Ping probe. A
-//! healthy peer is expected to respond with a corresponding Pong message.
+//! It does so by periodically sending a Ping probe to every connected peer, at most one at a time
+//! per peer. A healthy peer is expected to respond with a corresponding Pong message. The round
+//! trip time of every successful probe is recorded in the `ping_rtt` histogram, and a smoothed
+//! round trip time is kept for each peer, and persisted in the [`PeerStore`] if there is one.
 //!
-//! If a certain number of successive liveness probes for a peer fail, the HealthChecker initiates a
-//! disconnect from the peer. It relies on ConnectivityManager or the remote peer to re-establish
-//! the connection.
+//! [`PeerStore`]: crate::PeerStore
+//!
+//! If a certain number of successive liveness probes for a peer fail, the peer is declared
+//! unhealthy. The HealthChecker then notifies the ConnectivityManager, which disconnects from the
+//! peer and dials it again on its next connectivity check. Without a ConnectivityManager (i.e. in
+//! permissionless networks), the HealthChecker disconnects from the peer itself, and relies on the
+//! remote peer to re-establish the connection. Either way, a peer which stops responding is
+//! disconnected within `(ping_failures_tolerated + 1) * max(ping interval, ping_timeout)`.
 //!
 //! Future Work
 //! -----------
@@ -18,8 +25,11 @@
 //! - Use successful inbound pings as a sign of remote note being healthy
 //! - Ping a peer only in periods of no application-level communication with the peer
 use crate::{
+    connectivity_manager::ConnectivityRequest,
+    counters,
     error::NetworkError,
     peer_manager::{PeerManagerNotification, PeerManagerRequestSender},
+    peer_store::PeerStore,
     proto::{Ping, Pong},
     utils::{read_proto, MessageExt},
     ProtocolId,
@@ -34,8 +44,11 @@ use futures::{
     stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
 };
 use logger::prelude::*;
-use rand::{rngs::SmallRng, seq::SliceRandom, FromEntropy};
-use std::{collections::HashMap, fmt::Debug, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
 use tokio::{codec::Framed, prelude::FutureExt as _};
 use types::PeerId;
 use unsigned_varint::codec::UviBytes;
@@ -46,26 +59,59 @@ mod test;
 /// Protocol name for Ping.
 pub const PING_PROTOCOL_NAME: &[u8] = b"/libra/ping/0.1.0";
 
+/// Health of a connected peer, as measured by its pings.
+#[derive(Debug, Default)]
+struct PeerHealth {
+    /// Last round of a successful ping.
+    last_success_round: u64,
+    /// Number of failed pings since the last successful one.
+    failures: u64,
+    /// Whether a ping to the peer is outstanding.
+    ping_in_flight: bool,
+    /// Exponentially weighted moving average of the round trip time of successful pings.
+    smoothed_rtt: Option<Duration>,
+}
+
+impl PeerHealth {
+    fn new(round: u64) -> Self {
+        Self {
+            last_success_round: round,
+            ..Self::default()
+        }
+    }
+
+    /// Records the round trip time of a successful ping, smoothed as in RFC 6298.
+    fn record_rtt(&mut self, rtt: Duration) -> Duration {
+        let smoothed_rtt = match self.smoothed_rtt {
+            Some(smoothed_rtt) => (smoothed_rtt * 7 + rtt) / 8,
+            None => rtt,
+        };
+        self.smoothed_rtt = Some(smoothed_rtt);
+        smoothed_rtt
+    }
+}
+
 /// The actor performing health checks by running the Ping protocol
 pub struct HealthChecker<TTicker, TSubstream> {
-    /// Ticker to trigger ping to a random peer. In production, the ticker is likely to be
+    /// Ticker to trigger pings to connected peers. In production, the ticker is likely to be
     /// fixed duration interval timer.
     ticker: TTicker,
     /// Channel to send requests to PeerManager.
     peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
     /// Channel to receive notifications from PeerManager about new/lost connections.
     peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
-    /// Map from connected peer to last round of successful ping, and number of failures since
-    /// then.
-    connected: HashMap<PeerId, (u64, u64)>,
-    /// Random-number generator.
-    rng: SmallRng,
+    /// Channel to notify ConnectivityManager of unhealthy peers, if there is one.
+    conn_mgr_reqs_tx: Option<channel::Sender<ConnectivityRequest>>,
+    /// Health of each connected peer.
+    connected: HashMap<PeerId, PeerHealth>,
     /// Ping timmeout duration.
     ping_timeout: Duration,
     /// Number of successive ping failures we tolerate before declaring a node as unhealthy and
     /// disconnecting from it. In the future, this can be replaced with a more general failure
     /// detection policy.
     ping_failures_tolerated: u64,
+    /// Store in which the smoothed round trip time of each peer is persisted, if any.
+    peer_store: Option<PeerStore>,
     /// Counter incremented in each round of health checks
     round: u64,
 }
@@ -80,17 +126,20 @@ where
         ticker: TTicker,
         peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
         peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
+        conn_mgr_reqs_tx: Option<channel::Sender<ConnectivityRequest>>,
         ping_timeout: Duration,
         ping_failures_tolerated: u64,
+        peer_store: Option<PeerStore>,
     ) -> Self {
         HealthChecker {
             ticker,
             peer_mgr_reqs_tx,
             peer_mgr_notifs_rx,
+            conn_mgr_reqs_tx,
             connected: HashMap::new(),
-            rng: SmallRng::from_entropy(),
             ping_timeout,
             ping_failures_tolerated,
+            peer_store,
             round: 0,
         }
     }
@@ -103,10 +152,15 @@ where
                 notif = self.peer_mgr_notifs_rx.select_next_some() => {
                     match notif {
                         PeerManagerNotification::NewPeer(peer_id, _) => {
-                            self.connected.insert(peer_id, (self.round, 0));
+                            self.connected.insert(peer_id, PeerHealth::new(self.round));
                         }
                         PeerManagerNotification::LostPeer(peer_id, _) => {
                             self.connected.remove(&peer_id);
+                            counters::OP_COUNTERS
+                                .remove_peer_gauge(&counters::PEER_PING_RTT_MS, &peer_id.short_str());
+                        }
+                        PeerManagerNotification::PeerAddressChanged(_, _) => {
+                            // The peer stays connected, keep its failure count.
                         }
                         PeerManagerNotification::NewInboundSubstream(peer_id, substream) => {
                             assert_eq!(substream.protocol, PING_PROTOCOL_NAME);
@@ -117,19 +171,23 @@ where
                 _ = self.ticker.select_next_some() => {
                     self.round += 1;
                     debug!("Round number: {}", self.round);
-                    match self.get_random_peer() {
-                        Some(peer_id) => {
-                            debug!("Will ping: {}", peer_id.short_str());
-                            tick_handlers.push(
-                                Self::ping_peer(
-                                    peer_id,
-                                    self.round,
-                                    self.peer_mgr_reqs_tx.clone(),
-                                    self.ping_timeout.clone()));
-                        }
-                        None => {
-                            debug!("No connected peer to ping");
+                    if self.connected.is_empty() {
+                        debug!("No connected peer to ping");
+                    }
+                    for (peer_id, health) in self.connected.iter_mut() {
+                        // A peer which is slow to respond is not pinged again before its
+                        // outstanding ping completes or times out.
+                        if health.ping_in_flight {
+                            continue;
                         }
+                        debug!("Will ping: {}", peer_id.short_str());
+                        health.ping_in_flight = true;
+                        tick_handlers.push(
+                            Self::ping_peer(
+                                *peer_id,
+                                self.round,
+                                self.peer_mgr_reqs_tx.clone(),
+                                self.ping_timeout));
                     }
                 }
                 res = tick_handlers.select_next_some() => {
@@ -149,65 +207,91 @@ where
         &mut self,
         peer_id: PeerId,
         round: u64,
-        ping_result: Result<(), NetworkError>,
+        ping_result: Result<Duration, NetworkError>,
     ) {
         debug!("Got result for ping round: {}", round);
+        // If we are no longer connected to the peer, we ignore the result.
+        let health = match self.connected.get_mut(&peer_id) {
+            Some(health) => health,
+            None => return,
+        };
+        health.ping_in_flight = false;
         match ping_result {
-            Ok(_) => {
-                debug!("Ping successful for peer: {}", peer_id.short_str());
+            Ok(rtt) => {
+                counters::PING_RTT.observe_duration(rtt);
+                let smoothed_rtt = health.record_rtt(rtt);
+                counters::OP_COUNTERS
+                    .peer_gauge(&counters::PEER_PING_RTT_MS, &peer_id.short_str())
+                    .set(smoothed_rtt.as_millis() as i64);
+                debug!(
+                    "Ping successful for peer: {}, rtt: {:?}, smoothed rtt: {:?}",
+                    peer_id.short_str(),
+                    rtt,
+                    smoothed_rtt
+                );
+                if let Some(peer_store) = &self.peer_store {
+                    peer_store.update(peer_id, |record| record.smoothed_rtt = Some(smoothed_rtt));
+                }
                 // Update last successful ping to current round.
-                self.connected
-                    .entry(peer_id)
-                    .and_modify(|(ref mut r, ref mut count)| {
-                        if round > *r {
-                            *r = round;
-                            *count = 0;
-                        }
-                    });
+                health.last_success_round = round;
+                health.failures = 0;
             }
             Err(err) => {
+                counters::PING_FAILURES.inc();
                 warn!(
                     "Ping failed for peer: {} with error: {:?}",
                     peer_id.short_str(),
                     err
                 );
-                match self.connected.get_mut(&peer_id) {
-                    None => {
-                        // If we are no longer connected to the peer, we ignore ping
-                        // failure.
-                    }
-                    Some((ref mut prev, ref mut failures)) => {
-                        // If this is the result of an older ping, we ignore it.
-                        if *prev > round {
-                            return;
-                        }
-                        // Increment num of failures. If the ping failures are now more than
-                        // `self.ping_failures_tolerated`, we disconnect from the node.
-                        // The HealthChecker only performs the disconnect. It relies on
-                        // ConnectivityManager or the remote peer to re-establish the connection.
-                        *failures += 1;
-                        if *failures > self.ping_failures_tolerated {
-                            info!("Disonnecting from peer: {}", peer_id.short_str());
-                            if let Err(err) = self.peer_mgr_reqs_tx.disconnect_peer(peer_id).await {
-                                warn!(
-                                    "Failed to disconnect from peer: {} with error: {:?}",
-                                    peer_id.short_str(),
-                                    err
-                                );
-                            }
-                        }
-                    }
+                // Increment num of failures. If the ping failures are now more than
+                // `self.ping_failures_tolerated`, the peer is unhealthy.
+                health.failures += 1;
+                if health.failures > self.ping_failures_tolerated {
+                    // Start counting again, in case the peer does not get disconnected.
+                    health.failures = 0;
+                    self.handle_unhealthy_peer(peer_id).await;
                 }
             }
         }
     }
 
+    /// Gets the connection to an unhealthy peer cycled, by the ConnectivityManager if there is
+    /// one, and by disconnecting from the peer otherwise.
+    async fn handle_unhealthy_peer(&mut self, peer_id: PeerId) {
+        counters::UNHEALTHY_PEERS.inc();
+        if let Some(conn_mgr_reqs_tx) = self.conn_mgr_reqs_tx.as_mut() {
+            info!(
+                "Notifying connectivity manager of unhealthy peer: {}",
+                peer_id.short_str()
+            );
+            if let Err(err) = conn_mgr_reqs_tx
+                .send(ConnectivityRequest::PeerUnhealthy(peer_id))
+                .await
+            {
+                warn!(
+                    "Failed to notify connectivity manager of unhealthy peer: {} with error: {:?}",
+                    peer_id.short_str(),
+                    err
+                );
+            }
+            return;
+        }
+        info!("Disonnecting from peer: {}", peer_id.short_str());
+        if let Err(err) = self.peer_mgr_reqs_tx.disconnect_peer(peer_id).await {
+            warn!(
+                "Failed to disconnect from peer: {} with error: {:?}",
+                peer_id.short_str(),
+                err
+            );
+        }
+    }
+
     async fn ping_peer(
         peer_id: PeerId,
         round: u64,
         mut peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
         ping_timeout: Duration,
-    ) -> (PeerId, u64, Result<(), NetworkError>) {
+    ) -> (PeerId, u64, Result<Duration, NetworkError>) {
         let ping_result = async move {
             // Request a new substream to peer.
             debug!(
@@ -216,11 +300,13 @@ where
             );
             let substream = peer_mgr_reqs_tx
                 .open_substream(peer_id, ProtocolId::from_static(PING_PROTOCOL_NAME))
-                .await?;
+                .await?
+                .substream;
             // Messages are length-prefixed. Wrap in a framed stream.
             let mut substream = Framed::new(substream.compat(), UviBytes::default()).sink_compat();
             // Send Ping.
             debug!("Sending Ping to peer: {}", peer_id.short_str());
+            let ping_time = Instant::now();
             substream
                 .send(
                     Ping::default()
@@ -231,8 +317,8 @@ where
             // Read Pong.
             debug!("Waiting for Pong from peer: {}", peer_id.short_str());
             let _: Pong = read_proto(&mut substream).await?;
-            // Return success.
-            Ok(())
+            // Return the round trip time.
+            Ok(ping_time.elapsed())
         };
         (
             peer_id,
@@ -242,7 +328,7 @@ where
                 .compat()
                 .timeout(ping_timeout)
                 .compat()
-                .map_err(Into::<NetworkError>::into)
+                .map_err(|err| NetworkError::from(err).with_peer(peer_id))
                 .await,
         )
     }
@@ -280,9 +366,4 @@ where
             return;
         }
     }
-
-    fn get_random_peer(&mut self) -> Option<PeerId> {
-        let peers: Vec<_> = self.connected.keys().cloned().collect();
-        peers.choose(&mut self.rng).cloned()
-    }
 }
@@ -26,13 +26,45 @@ fn setup_permissive_health_checker(
         ticker_rx,
         PeerManagerRequestSender::new(peer_mgr_reqs_tx),
         peer_mgr_notifs_rx,
+        None,
         PING_TIMEOUT,
         ping_failures_tolerated,
+        None,
     );
     rt.spawn(health_checker.start().boxed().unit_error().compat());
     (peer_mgr_reqs_rx, peer_mgr_notifs_tx, ticker_tx)
 }
 
+fn setup_health_checker_with_conn_mgr(
+    rt: &mut Runtime,
+) -> (
+    channel::Receiver<PeerManagerRequest<MemorySocket>>,
+    channel::Sender<PeerManagerNotification<MemorySocket>>,
+    channel::Receiver<ConnectivityRequest>,
+    channel::Sender<()>,
+) {
+    let (ticker_tx, ticker_rx) = channel::new_test(0);
+    let (peer_mgr_reqs_tx, peer_mgr_reqs_rx) = channel::new_test(0);
+    let (peer_mgr_notifs_tx, peer_mgr_notifs_rx) = channel::new_test(0);
+    let (conn_mgr_reqs_tx, conn_mgr_reqs_rx) = channel::new_test(0);
+    let health_checker = HealthChecker::new(
+        ticker_rx,
+        PeerManagerRequestSender::new(peer_mgr_reqs_tx),
+        peer_mgr_notifs_rx,
+        Some(conn_mgr_reqs_tx),
+        PING_TIMEOUT,
+        0,
+        None,
+    );
+    rt.spawn(health_checker.start().boxed().unit_error().compat());
+    (
+        peer_mgr_reqs_rx,
+        peer_mgr_notifs_tx,
+        conn_mgr_reqs_rx,
+        ticker_tx,
+    )
+}
+
 fn setup_default_health_checker(
     rt: &mut Runtime,
 ) -> (
@@ -47,8 +79,10 @@ fn setup_default_health_checker(
         ticker_rx,
         PeerManagerRequestSender::new(peer_mgr_reqs_tx),
         peer_mgr_notifs_rx,
+        None,
         PING_TIMEOUT,
         0,
+        None,
     );
     rt.spawn(health_checker.start().boxed().unit_error().compat());
     (peer_mgr_reqs_rx, peer_mgr_notifs_tx, ticker_tx)
@@ -138,7 +172,11 @@ async fn expect_open_substream(
         PeerManagerRequest::OpenSubstream(peer, protocol, ch) => {
             assert_eq!(peer, peer_id);
             assert_eq!(protocol, PING_PROTOCOL_NAME);
-            ch.send(Ok(dialer_substream)).unwrap();
+            ch.send(Ok(NegotiatedSubstream {
+                protocol,
+                substream: dialer_substream,
+            }))
+            .unwrap();
         }
         _ => {
             panic!("unexpected request to peer manager");
@@ -374,3 +412,92 @@ fn ping_timeout() {
     };
     rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
 }
+
+#[test]
+fn unhealthy_peer_reported_to_conn_mgr() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut conn_mgr_reqs_rx, mut ticker_tx) =
+        setup_health_checker_with_conn_mgr(&mut rt);
+
+    let events_f = async move {
+        // Notify HealthChecker of new connected node.
+        let peer_id = PeerId::random();
+        let peer_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        peer_mgr_notifs_tx
+            .send(PeerManagerNotification::NewPeer(peer_id, peer_address))
+            .await
+            .unwrap();
+
+        // Trigger ping to a peer, which fails.
+        ticker_tx.send(()).await.unwrap();
+        let listener_substream = expect_open_substream(peer_id, &mut peer_mgr_reqs_rx).await;
+        expect_ping_send_notok(listener_substream).await;
+
+        // Health checker should leave the disconnect to the connectivity manager.
+        match conn_mgr_reqs_rx.next().await.unwrap() {
+            ConnectivityRequest::PeerUnhealthy(peer) => assert_eq!(peer, peer_id),
+            req => panic!("Unexpected request to connectivity manager: {:?}", req),
+        }
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
+#[test]
+fn pings_all_connected_peers() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let (mut peer_mgr_reqs_rx, mut peer_mgr_notifs_tx, mut ticker_tx) =
+        setup_default_health_checker(&mut rt);
+
+    let events_f = async move {
+        let peer_address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9090").unwrap();
+        let mut peer_ids = vec![PeerId::random(), PeerId::random()];
+        for peer_id in &peer_ids {
+            peer_mgr_notifs_tx
+                .send(PeerManagerNotification::NewPeer(
+                    *peer_id,
+                    peer_address.clone(),
+                ))
+                .await
+                .unwrap();
+        }
+
+        // A single round pings every connected peer.
+        ticker_tx.send(()).await.unwrap();
+        let mut pinged = vec![];
+        for _ in 0..peer_ids.len() {
+            let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+            match peer_mgr_reqs_rx.next().await.unwrap() {
+                PeerManagerRequest::OpenSubstream(peer, protocol, ch) => {
+                    assert_eq!(protocol, PING_PROTOCOL_NAME);
+                    pinged.push(peer);
+                    ch.send(Ok(NegotiatedSubstream {
+                        protocol,
+                        substream: dialer_substream,
+                    }))
+                    .unwrap();
+                }
+                _ => panic!("unexpected request to peer manager"),
+            }
+            expect_ping_send_ok(listener_substream).await;
+        }
+        peer_ids.sort();
+        pinged.sort();
+        assert_eq!(peer_ids, pinged);
+    };
+    rt.block_on(events_f.boxed().unit_error().compat()).unwrap();
+}
+
+#[test]
+fn smoothed_rtt() {
+    let mut health = PeerHealth::new(0);
+    assert_eq!(
+        health.record_rtt(Duration::from_millis(80)),
+        Duration::from_millis(80)
+    );
+    assert_eq!(
+        health.record_rtt(Duration::from_millis(160)),
+        Duration::from_millis(90)
+    );
+}
@@ -3,8 +3,20 @@
 
 //! Protocol used to identify key information about a remote
 //!
-//! Currently, the information shared as part of this protocol includes the peer identity and a
-//! list of protocols supported by the peer.
+//! Currently, the information shared as part of this protocol includes the peer identity, a list
+//! of protocols supported by the peer, the version of the software it runs and the id of the chain
+//! it is on. The latter two are not checked by the network itself; they are made available to upper
+//! layers through the `PeerMetadataStore`.
+//!
+//! ## Protocol versions
+//!
+//! Protocol ids are expected to end with a `<major>.<minor>.<patch>` version, e.g.
+//! `/libra/consensus/rpc/0.1.0`. A node may support several versions of the same protocol at
+//! once, in which case it advertises all of them. Once identities are exchanged, both ends know
+//! which versions the other supports and agree on the highest common one (see
+//! [`Identity::select_protocol_version`]), which allows rolling out a new wire format across the
+//! network: nodes first upgrade to support both versions, then drop the old one once every peer
+//! supports the new one.
 use crate::{
     proto::{IdentityMsg, IdentityMsg_Role},
     utils::MessageExt,
@@ -35,6 +47,8 @@ pub struct Identity {
     peer_id: PeerId,
     role: RoleType,
     supported_protocols: Vec<ProtocolId>,
+    software_version: String,
+    chain_id: String,
 }
 
 impl Identity {
@@ -43,9 +57,18 @@ impl Identity {
             peer_id,
             role,
             supported_protocols,
+            software_version: String::new(),
+            chain_id: String::new(),
         }
     }
 
+    /// Sets the software version and chain id advertised with this identity.
+    pub fn with_node_info(mut self, software_version: String, chain_id: String) -> Self {
+        self.software_version = software_version;
+        self.chain_id = chain_id;
+        self
+    }
+
     pub fn peer_id(&self) -> PeerId {
         self.peer_id
     }
@@ -63,6 +86,59 @@ impl Identity {
     pub fn supported_protocols(&self) -> &[ProtocolId] {
         &self.supported_protocols
     }
+
+    pub fn software_version(&self) -> &str {
+        &self.software_version
+    }
+
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Returns the highest version of the protocol `requested` is a version of, which is
+    /// supported both by this (remote) identity and by `own_supported_protocols`. Returns `None`
+    /// if there is no common version, or if `requested` isn't versioned and isn't supported as is.
+    pub fn select_protocol_version(
+        &self,
+        own_supported_protocols: &[ProtocolId],
+        requested: &ProtocolId,
+    ) -> Option<ProtocolId> {
+        let name = match split_protocol_version(requested) {
+            Some((name, _)) => name,
+            None => {
+                return if self.is_protocol_supported(requested) {
+                    Some(requested.clone())
+                } else {
+                    None
+                };
+            }
+        };
+        own_supported_protocols
+            .iter()
+            .filter(|proto| self.is_protocol_supported(proto))
+            .filter_map(|proto| match split_protocol_version(proto) {
+                Some((proto_name, version)) if proto_name == name => Some((version, proto)),
+                _ => None,
+            })
+            .max_by_key(|(version, _)| *version)
+            .map(|(_, proto)| proto.clone())
+    }
+}
+
+/// Splits a protocol id of the form `<name>/<major>.<minor>.<patch>` into its name and version.
+pub fn split_protocol_version(protocol: &[u8]) -> Option<(&[u8], (u64, u64, u64))> {
+    let separator = protocol.iter().rposition(|b| *b == b'/')?;
+    let (name, version) = (&protocol[..separator], &protocol[separator + 1..]);
+    let mut parts = std::str::from_utf8(version)
+        .ok()?
+        .split('.')
+        .map(|part| part.parse::<u64>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+            Some((name, (major, minor, patch)))
+        }
+        _ => None,
+    }
 }
 
 /// The Identity exchange protocol
@@ -113,6 +189,8 @@ where
     } else {
         IdentityMsg_Role::FullNode
     });
+    msg.software_version = own_identity.software_version().to_string();
+    msg.chain_id = own_identity.chain_id().to_string();
 
     // Send serialized message to peer.
     let bytes = msg
@@ -145,14 +223,15 @@ where
         .into_iter()
         .map(Into::into)
         .collect();
-    let identity = Identity::new(peer_id, supported_protocols, role);
+    let identity = Identity::new(peer_id, supported_protocols, role)
+        .with_node_info(response.software_version, response.chain_id);
     Ok((identity, connection))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        protocols::identity::{exchange_identity, Identity},
+        protocols::identity::{exchange_identity, split_protocol_version, Identity},
         ProtocolId,
     };
     use config::config::RoleType;
@@ -183,7 +262,8 @@ mod tests {
                 ProtocolId::from_static(b"/proto/2.0.0"),
             ],
             RoleType::Validator,
-        );
+        )
+        .with_node_info("0.1.0".to_string(), "testnet".to_string());
         let client_identity = Identity::new(
             PeerId::random(),
             vec![
@@ -192,7 +272,8 @@ mod tests {
                 ProtocolId::from_static(b"/proto/3.0.0"),
             ],
             RoleType::Validator,
-        );
+        )
+        .with_node_info("0.2.0".to_string(), "testnet".to_string());
         let server_identity_config = server_identity.clone();
         let client_identity_config = client_identity.clone();
 
@@ -219,4 +300,51 @@ mod tests {
 
         block_on(join(server, client));
     }
+
+    #[test]
+    fn protocol_version_parsing() {
+        assert_eq!(
+            split_protocol_version(b"/libra/consensus/rpc/0.10.2"),
+            Some((&b"/libra/consensus/rpc"[..], (0, 10, 2)))
+        );
+        assert_eq!(split_protocol_version(b"/proto/1.0"), None);
+        assert_eq!(split_protocol_version(b"/proto/1.0.0.0"), None);
+        assert_eq!(split_protocol_version(b"/proto/a.0.0"), None);
+        assert_eq!(split_protocol_version(b"proto"), None);
+    }
+
+    #[test]
+    fn select_highest_common_protocol_version() {
+        let remote = Identity::new(
+            PeerId::random(),
+            vec![
+                ProtocolId::from_static(b"/proto/1.0.0"),
+                ProtocolId::from_static(b"/proto/2.0.0"),
+                ProtocolId::from_static(b"/proto/10.0.0"),
+                ProtocolId::from_static(b"/other/3.0.0"),
+                ProtocolId::from_static(b"/unversioned"),
+            ],
+            RoleType::Validator,
+        );
+        let own = vec![
+            ProtocolId::from_static(b"/proto/1.0.0"),
+            ProtocolId::from_static(b"/proto/2.0.0"),
+            ProtocolId::from_static(b"/proto/3.0.0"),
+            ProtocolId::from_static(b"/other/1.0.0"),
+            ProtocolId::from_static(b"/unversioned"),
+        ];
+
+        assert_eq!(
+            remote.select_protocol_version(&own, &ProtocolId::from_static(b"/proto/1.0.0")),
+            Some(ProtocolId::from_static(b"/proto/2.0.0"))
+        );
+        assert_eq!(
+            remote.select_protocol_version(&own, &ProtocolId::from_static(b"/other/1.0.0")),
+            None
+        );
+        assert_eq!(
+            remote.select_protocol_version(&own, &ProtocolId::from_static(b"/unversioned")),
+            Some(ProtocolId::from_static(b"/unversioned"))
+        );
+    }
 }
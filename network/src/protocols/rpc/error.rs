@@ -30,6 +30,9 @@ pub enum RpcError {
     #[fail(display = "Received unexpected rpc response message; expected remote to half-close.")]
     UnexpectedRpcResponse,
 
+    #[fail(display = "Received invalid rpc timeout; expected 8 bytes.")]
+    InvalidRpcTimeout,
+
     #[fail(display = "Received unexpected rpc request message; expected remote to half-close.")]
     UnexpectedRpcRequest,
 
@@ -45,6 +48,9 @@ pub enum RpcError {
     #[fail(display = "Rpc timed out")]
     TimedOut,
 
+    #[fail(display = "No peer to send the rpc request to")]
+    NoPeers,
+
     #[fail(display = "Error setting timeout: {:?}", _0)]
     TimerError(#[fail(cause)] timer::Error),
 
@@ -21,7 +21,7 @@
 //!    higher layers to specify. The rpc protocol is only concerned with shipping
 //!    around opaque blobs. Current libra rpc clients (consensus, mempool) mostly
 //!    send protobuf enums around over a single rpc protocol,
-//!    e.g., `/libra/consensus/rpc/0.1.0`.
+//!    e.g., `/libra/consensus/rpc/0.2.0`.
 //!
 //! ## Wire Protocol (dialer):
 //!
@@ -29,11 +29,13 @@
 //!
 //! 1. Requests a new outbound substream from the muxer.
 //! 2. Negotiates the substream using [`protocol-select`] to the rpc method they
-//!    wish to call, e.g., `/libra/mempool/rpc/0.1.0`.
-//! 3. Sends the serialized request arguments on the newly negotiated substream.
-//! 4. Half-closes their output side.
-//! 5. Awaits the serialized response message from remote.
-//! 6. Awaits the listener's half-close to complete the substream close.
+//!    wish to call, e.g., `/libra/mempool/rpc/0.2.0`.
+//! 3. Sends the time remaining until the rpc call times out, in milliseconds, as
+//!    a big-endian `u64`, unless a legacy version of the protocol was negotiated.
+//! 4. Sends the serialized request arguments on the newly negotiated substream.
+//! 5. Half-closes their output side.
+//! 6. Awaits the serialized response message from remote.
+//! 7. Awaits the listener's half-close to complete the substream close.
 //!
 //! ## Wire Protocol (listener):
 //!
@@ -42,14 +44,30 @@
 //! 1. Polls for new inbound substreams on the muxer.
 //! 2. Negotiates inbound substreams using [`protocol-select`]. The negotiation
 //!    must only succeed if the requested rpc method is actually supported.
-//! 3. Awaits the serialized request arguments on the newly negotiated substream.
-//! 4. Awaits the dialer's half-close.
-//! 5. Handles the request by sending it up through the
+//! 3. Awaits the dialer's remaining timeout, unless a legacy version of the
+//!    protocol was negotiated.
+//! 4. Awaits the serialized request arguments on the newly negotiated substream.
+//! 5. Awaits the dialer's half-close.
+//! 6. Handles the request by sending it up through the
 //!    [`NetworkProvider`](crate::interface::NetworkProvider)
 //!    actor to a higher layer rpc client like consensus or mempool, who then
 //!    sends the serialed rpc response back down to the rpc layer.
-//! 6. Sends the serialized response message to the dialer.
-//! 7. Half-closes their output side to complete the substream close.
+//! 7. Sends the serialized response message to the dialer.
+//! 8. Half-closes their output side to complete the substream close.
+//!
+//! The listener stops waiting for the response once the dialer's timeout (or
+//! its own inbound rpc timeout, whichever is shorter) has elapsed, since the
+//! dialer will not accept the response anymore. The deadline is passed up
+//! along with the request so that the upper layer can skip or abandon work
+//! for calls whose dialer has already given up.
+//!
+//! Nodes which predate the propagation of rpc deadlines speak legacy versions of
+//! the rpc protocols, whose requests are not prefixed with the dialer's timeout.
+//! Each legacy version is supported along with the current version of its
+//! protocol. When a peer only supports the legacy version, it is the one
+//! negotiated on the substreams with that peer, and the timeout is neither sent
+//! nor expected on them. Inbound requests are passed up with the current version
+//! of their protocol, so that upper layers need not know about legacy versions.
 //!
 //! Note: negotiated substreams are currently framed with the
 //! [muiltiformats unsigned varint length-prefix](https://github.com/multiformats/unsigned-varint)
@@ -78,8 +96,17 @@ use futures::{
     task::Context,
 };
 use logger::prelude::*;
-use std::{fmt::Debug, io, time::Duration};
-use tokio::{codec::Framed, prelude::FutureExt as Future01Ext, runtime::TaskExecutor};
+use std::{
+    cmp::min,
+    collections::HashMap,
+    convert::TryInto,
+    fmt::Debug,
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
+use tokio::{codec::Framed, prelude::FutureExt as Future01Ext};
 use types::PeerId;
 use unsigned_varint::codec::UviBytes;
 
@@ -112,6 +139,10 @@ pub struct InboundRpcRequest {
     /// disconnected when trying to send their response, as the rpc call might
     /// have timed out while handling the request.
     pub res_tx: oneshot::Sender<Result<Bytes, RpcError>>,
+    /// The time after which the response will not be sent to the dialer
+    /// anymore, i.e., the earlier of the dialer's timeout and our inbound rpc
+    /// timeout. Once it has passed, `res_tx` is disconnected.
+    pub deadline: Instant,
 }
 
 /// A wrapper struct for an outbound rpc request and its associated context.
@@ -152,8 +183,8 @@ pub enum RpcNotification {
 
 /// The rpc actor.
 pub struct Rpc<TSubstream> {
-    /// Executor to spawn inbound and outbound handler tasks.
-    executor: TaskExecutor,
+    /// Task manager to spawn inbound and outbound handler tasks.
+    task_manager: TaskManager,
     /// Channel to receive requests from other upstream actors.
     requests_rx: channel::Receiver<RpcRequest>,
     /// Channel to receive notifications from [`PeerManager`](crate::peer_manager::PeerManager).
@@ -172,6 +203,9 @@ pub struct Rpc<TSubstream> {
     // TODO(philiphayes): partition inbound queue by peer to prevent one peer
     // from starving other peers' rpcs?
     max_concurrent_inbound_rpcs: u32,
+    /// The legacy versions of the rpc protocols, whose requests are not
+    /// prefixed with the dialer's timeout, mapped to their current version.
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 }
 
 impl<TSubstream> Rpc<TSubstream>
@@ -180,7 +214,7 @@ where
 {
     /// Create a new instance of the [`Rpc`] protocol actor.
     pub fn new(
-        executor: TaskExecutor,
+        task_manager: TaskManager,
         requests_rx: channel::Receiver<RpcRequest>,
         peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
         peer_mgr_reqs_tx: PeerManagerRequestSender<TSubstream>,
@@ -188,9 +222,10 @@ where
         inbound_rpc_timeout: Duration,
         max_concurrent_outbound_rpcs: u32,
         max_concurrent_inbound_rpcs: u32,
+        legacy_protocols: HashMap<ProtocolId, ProtocolId>,
     ) -> Self {
         Self {
-            executor,
+            task_manager,
             requests_rx,
             peer_mgr_notifs_rx,
             peer_mgr_reqs_tx,
@@ -198,13 +233,14 @@ where
             inbound_rpc_timeout,
             max_concurrent_outbound_rpcs,
             max_concurrent_inbound_rpcs,
+            legacy_protocols: Arc::new(legacy_protocols),
         }
     }
 
     /// Start the [`Rpc`] actor's event loop.
     pub async fn start(self) {
         // unpack self to satisfy borrow checker
-        let executor = self.executor;
+        let task_manager = self.task_manager;
         let requests_rx = self.requests_rx;
         let peer_mgr_notifs_rx = self.peer_mgr_notifs_rx;
         let peer_mgr_reqs_tx = self.peer_mgr_reqs_tx;
@@ -212,22 +248,33 @@ where
         let inbound_rpc_timeout = self.inbound_rpc_timeout;
         let max_concurrent_outbound_rpcs = self.max_concurrent_outbound_rpcs;
         let max_concurrent_inbound_rpcs = self.max_concurrent_inbound_rpcs;
+        let legacy_protocols = self.legacy_protocols;
 
         // inbound and outbound requests use separate bounded executors to ensure
         // backpressure propagates independently and doesn't starve the other
         // handler.
 
         let outbound_handler = handle_outbounds(
-            BoundedExecutor::new(max_concurrent_outbound_rpcs as usize, executor.clone()),
+            BoundedExecutor::with_task_manager(
+                max_concurrent_outbound_rpcs as usize,
+                task_manager.clone(),
+                "outbound_rpc",
+            ),
             requests_rx,
             peer_mgr_reqs_tx,
+            legacy_protocols.clone(),
         );
 
         let inbound_handler = handle_inbounds(
-            BoundedExecutor::new(max_concurrent_inbound_rpcs as usize, executor),
+            BoundedExecutor::with_task_manager(
+                max_concurrent_inbound_rpcs as usize,
+                task_manager,
+                "inbound_rpc",
+            ),
             peer_mgr_notifs_rx,
             rpc_handler_tx,
             inbound_rpc_timeout,
+            legacy_protocols,
         );
 
         // drive inbound and outbound handlers to completion
@@ -242,12 +289,17 @@ async fn handle_outbounds<TSubstream>(
     executor: BoundedExecutor,
     mut requests_rx: channel::Receiver<RpcRequest>,
     peer_mgr_tx: PeerManagerRequestSender<TSubstream>,
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 ) where
     TSubstream: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
     while let Some(req) = requests_rx.next().await {
         executor
-            .spawn(handle_outbound_rpc(peer_mgr_tx.clone(), req))
+            .spawn(handle_outbound_rpc(
+                peer_mgr_tx.clone(),
+                req,
+                legacy_protocols.clone(),
+            ))
             .await;
     }
 }
@@ -258,6 +310,7 @@ async fn handle_inbounds<TSubstream>(
     mut peer_mgr_notifs_rx: channel::Receiver<PeerManagerNotification<TSubstream>>,
     rpc_handler_tx: channel::Sender<RpcNotification>,
     inbound_rpc_timeout: Duration,
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 ) where
     TSubstream: AsyncRead + AsyncWrite + Debug + Send + Unpin + 'static,
 {
@@ -267,6 +320,7 @@ async fn handle_inbounds<TSubstream>(
                 rpc_handler_tx.clone(),
                 notif,
                 inbound_rpc_timeout,
+                legacy_protocols.clone(),
             ))
             .await;
     }
@@ -285,6 +339,7 @@ async fn handle_inbounds<TSubstream>(
 async fn handle_outbound_rpc<TSubstream>(
     peer_mgr_tx: PeerManagerRequestSender<TSubstream>,
     req: RpcRequest,
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 ) where
     TSubstream: AsyncRead + AsyncWrite + Send + Unpin,
 {
@@ -294,15 +349,23 @@ async fn handle_outbound_rpc<TSubstream>(
             let req_data = req.data;
             let mut res_tx = req.res_tx;
             let timeout = req.timeout;
+            let deadline = Instant::now() + timeout;
 
             // Future to run the actual outbound rpc protocol and get the results.
-            let mut f_rpc_res = handle_outbound_rpc_inner(peer_mgr_tx, peer_id, protocol, req_data)
-                .boxed()
-                .compat()
-                .timeout(timeout)
-                .compat()
-                // Convert tokio timeout::Error to RpcError
-                .map_err(Into::<RpcError>::into);
+            let mut f_rpc_res = handle_outbound_rpc_inner(
+                peer_mgr_tx,
+                peer_id,
+                protocol,
+                req_data,
+                deadline,
+                legacy_protocols,
+            )
+            .boxed()
+            .compat()
+            .timeout(timeout)
+            .compat()
+            // Convert tokio timeout::Error to RpcError
+            .map_err(Into::<RpcError>::into);
 
             // If the rpc client drops their oneshot receiver, this future should
             // cancel the request.
@@ -341,15 +404,25 @@ async fn handle_outbound_rpc_inner<TSubstream>(
     peer_id: PeerId,
     protocol: ProtocolId,
     req_data: Bytes,
+    deadline: Instant,
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 ) -> Result<Bytes, RpcError>
 where
     TSubstream: AsyncRead + AsyncWrite + Send + Unpin,
 {
     let _timer = counters::RPC_LATENCY.start_timer();
-    // Request a new substream with the peer.
-    let substream = peer_mgr_tx.open_substream(peer_id, protocol).await?;
+    // Request a new substream with the peer. The legacy version of the protocol
+    // is negotiated if the peer does not support the current one.
+    let negotiated = peer_mgr_tx.open_substream(peer_id, protocol).await?;
     // Rpc messages are length-prefixed.
-    let mut substream = Framed::new(substream.compat(), UviBytes::default()).sink_compat();
+    let mut substream =
+        Framed::new(negotiated.substream.compat(), UviBytes::default()).sink_compat();
+    // Let the listener know how long we are willing to wait for the response.
+    if !legacy_protocols.contains_key(&negotiated.protocol) {
+        substream
+            .buffered_send(encode_timeout(remaining(deadline)))
+            .await?;
+    }
     // Send the rpc request data.
     let req_len = req_data.len();
     substream.buffered_send(req_data).await?;
@@ -380,17 +453,26 @@ async fn handle_inbound_substream<TSubstream>(
     notification_tx: channel::Sender<RpcNotification>,
     notif: PeerManagerNotification<TSubstream>,
     timeout: Duration,
+    legacy_protocols: Arc<HashMap<ProtocolId, ProtocolId>>,
 ) where
     TSubstream: AsyncRead + AsyncWrite + Debug + Send + Unpin,
 {
     match notif {
         PeerManagerNotification::NewInboundSubstream(peer_id, substream) => {
+            // Requests of a legacy version of a protocol carry no timeout, and are
+            // passed up with the current version of the protocol.
+            let (protocol, has_timeout) = match legacy_protocols.get(&substream.protocol) {
+                Some(current) => (current.clone(), false),
+                None => (substream.protocol, true),
+            };
             // Run the actual inbound rpc protocol.
             let res = handle_inbound_substream_inner(
                 notification_tx,
                 peer_id,
-                substream.protocol,
+                protocol,
                 substream.substream,
+                Instant::now() + timeout,
+                has_timeout,
             )
             .boxed()
             .compat()
@@ -424,12 +506,27 @@ async fn handle_inbound_substream_inner<TSubstream>(
     peer_id: PeerId,
     protocol: ProtocolId,
     substream: TSubstream,
+    deadline: Instant,
+    has_timeout: bool,
 ) -> Result<(), RpcError>
 where
     TSubstream: AsyncRead + AsyncWrite + Send + Unpin,
 {
     // Rpc messages are length-prefixed.
     let mut substream = Framed::new(substream.compat(), UviBytes::default()).sink_compat();
+    // Read the dialer's timeout. There is no point in handling the request after
+    // the dialer has stopped waiting for the response.
+    let deadline = if has_timeout {
+        let dialer_timeout = match substream.next().await {
+            Some(timeout) => decode_timeout(&timeout?)?,
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        };
+        Instant::now()
+            .checked_add(dialer_timeout)
+            .map_or(deadline, |dialer_deadline| min(deadline, dialer_deadline))
+    } else {
+        deadline
+    };
     // Read the rpc request data.
     let req_data = match substream.next().await {
         Some(req_data) => req_data?.freeze(),
@@ -452,14 +549,23 @@ where
             protocol,
             data: req_data,
             res_tx,
+            deadline,
         },
     );
     // TODO(philiphayes): impl correct shutdown process so this never panics
     // Forward request to upper layer.
     notification_tx.send(notification).await.unwrap();
 
-    // Wait for response from upper layer.
-    let res_data = res_rx.await??;
+    // Wait for response from upper layer, until the deadline. Dropping `res_rx`
+    // lets the upper layer know that the response is not needed anymore.
+    let res_data = async move { res_rx.await? }
+        .boxed()
+        .compat()
+        .timeout(remaining(deadline))
+        .compat()
+        .await
+        // Convert tokio timeout::Error to RpcError
+        .map_err(Into::<RpcError>::into)?;
     let res_len = res_data.len();
 
     // Send the response to remote
@@ -474,3 +580,23 @@ where
 
     Ok(())
 }
+
+// Returns the time left until `deadline`, or zero if it has already passed.
+fn remaining(deadline: Instant) -> Duration {
+    let now = Instant::now();
+    if deadline > now {
+        deadline - now
+    } else {
+        Duration::from_millis(0)
+    }
+}
+
+fn encode_timeout(timeout: Duration) -> Bytes {
+    let timeout_ms = timeout.as_millis().try_into().unwrap_or(std::u64::MAX);
+    Bytes::from(u64::to_be_bytes(timeout_ms).to_vec())
+}
+
+fn decode_timeout(data: &[u8]) -> Result<Duration, RpcError> {
+    let timeout_ms = data.try_into().map_err(|_| RpcError::InvalidRpcTimeout)?;
+    Ok(Duration::from_millis(u64::from_be_bytes(timeout_ms)))
+}
@@ -4,10 +4,17 @@
 use super::{error::RpcError, *};
 use crate::{
     common::NegotiatedSubstream,
+    interface::NetworkRequest,
     peer_manager::{PeerManagerNotification, PeerManagerRequest},
+    proto::RequestBlock,
+    utils::MessageExt,
+};
+use futures::{
+    executor::block_on,
+    future::{join, join3, join4},
 };
-use futures::future::{join, join3, join4};
 use memsocket::MemorySocket;
+use prost::Message as _;
 use tokio::runtime::Runtime;
 
 async fn do_outbound_rpc_req<TSubstream>(
@@ -28,7 +35,7 @@ where
         timeout,
     };
     let rpc_req = RpcRequest::SendRpc(recipient, outbound_req);
-    handle_outbound_rpc(peer_mgr_tx, rpc_req).await;
+    handle_outbound_rpc(peer_mgr_tx, rpc_req, Arc::new(HashMap::new())).await;
     res_rx.await.unwrap()
 }
 
@@ -39,8 +46,13 @@ async fn mock_peer_manager<TSubstream: Debug>(
 ) {
     // Return a mocked substream on the next OpenSubstream request
     match peer_mgr_rx.next().await.unwrap() {
-        PeerManagerRequest::OpenSubstream(_peer_id, _protocol, substream_tx) => {
-            substream_tx.send(Ok(substream)).unwrap();
+        PeerManagerRequest::OpenSubstream(_peer_id, protocol, substream_tx) => {
+            substream_tx
+                .send(Ok(NegotiatedSubstream {
+                    protocol,
+                    substream,
+                }))
+                .unwrap();
         }
         req => panic!(
             "Unexpected PeerManagerRequest: {:?}, expected OpenSubstream",
@@ -92,6 +104,7 @@ fn upgrades() {
         listener_rpc_notifs_tx,
         inbound_notif,
         Duration::from_millis(500),
+        Arc::new(HashMap::new()),
     );
 
     // Make an outbound substream request
@@ -122,6 +135,108 @@ fn upgrades() {
         .unwrap();
 }
 
+// Rpcs over a legacy version of the protocol should carry no timeout, and be
+// passed up with the current version of the protocol.
+#[test]
+fn legacy_protocol() {
+    ::logger::try_init_for_testing();
+
+    let listener_peer_id = PeerId::random();
+    let dialer_peer_id = PeerId::random();
+    let protocol_id = b"/get_blocks/2.0.0";
+    let legacy_protocol_id = b"/get_blocks/1.0.0";
+    let req_data = b"hello";
+    let res_data = b"goodbye";
+    let legacy_protocols: Arc<HashMap<_, _>> = Arc::new(
+        vec![(
+            ProtocolId::from_static(legacy_protocol_id),
+            ProtocolId::from_static(protocol_id),
+        )]
+        .into_iter()
+        .collect(),
+    );
+
+    let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider, negotiating the legacy version of the
+    // protocol.
+    let (dialer_peer_mgr_reqs_tx, mut dialer_peer_mgr_reqs_rx) = channel::new_test(8);
+    let dialer_peer_mgr_reqs_tx = PeerManagerRequestSender::new(dialer_peer_mgr_reqs_tx);
+    let f_dialer_peer_mgr = async move {
+        match dialer_peer_mgr_reqs_rx.next().await.unwrap() {
+            PeerManagerRequest::OpenSubstream(_peer_id, protocol, substream_tx) => {
+                assert_eq!(protocol.as_ref(), protocol_id);
+                substream_tx
+                    .send(Ok(NegotiatedSubstream {
+                        protocol: ProtocolId::from_static(legacy_protocol_id),
+                        substream: dialer_substream,
+                    }))
+                    .unwrap();
+            }
+            req => panic!(
+                "Unexpected PeerManagerRequest: {:?}, expected OpenSubstream",
+                req
+            ),
+        }
+    };
+
+    // Fake the listener NetworkProvider
+    let (listener_rpc_notifs_tx, mut listener_rpc_notifs_rx) = channel::new_test(8);
+    let f_listener_network = async move {
+        // Handle the inbound rpc request
+        match listener_rpc_notifs_rx.next().await.unwrap() {
+            RpcNotification::RecvRpc(peer_id, req) => {
+                assert_eq!(peer_id, dialer_peer_id);
+                assert_eq!(req.protocol.as_ref(), protocol_id);
+                assert_eq!(req.data.as_ref(), req_data);
+                req.res_tx.send(Ok(Bytes::from_static(res_data))).unwrap();
+            }
+        }
+    };
+
+    let substream = NegotiatedSubstream {
+        protocol: ProtocolId::from_static(legacy_protocol_id),
+        substream: listener_substream,
+    };
+    let inbound_notif = PeerManagerNotification::NewInboundSubstream(dialer_peer_id, substream);
+
+    // Handle the inbound substream
+    let f_listener_upgrade = handle_inbound_substream(
+        listener_rpc_notifs_tx,
+        inbound_notif,
+        Duration::from_millis(500),
+        legacy_protocols.clone(),
+    );
+
+    // Make an outbound substream request
+    let f_dialer_upgrade = async move {
+        let (res_tx, res_rx) = oneshot::channel();
+        let outbound_req = OutboundRpcRequest {
+            protocol: ProtocolId::from_static(protocol_id),
+            data: Bytes::from_static(req_data),
+            res_tx,
+            timeout: Duration::from_secs(1),
+        };
+        let rpc_req = RpcRequest::SendRpc(listener_peer_id, outbound_req);
+        handle_outbound_rpc(dialer_peer_mgr_reqs_tx, rpc_req, legacy_protocols).await;
+
+        // Check the rpc response data
+        let data = res_rx.await.unwrap().unwrap();
+        assert_eq!(data.as_ref(), res_data);
+    };
+
+    let f = join4(
+        f_dialer_peer_mgr,
+        f_dialer_upgrade,
+        f_listener_network,
+        f_listener_upgrade,
+    );
+    Runtime::new()
+        .unwrap()
+        .block_on(f.boxed().unit_error().compat())
+        .unwrap();
+}
+
 // An outbound rpc request should fail if the listener drops the connection after
 // receiving the request.
 #[test]
@@ -163,6 +278,12 @@ fn listener_close_before_response() {
         // rpc messages are length-prefixed
         let mut substream =
             Framed::new(listener_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+        // read the dialer's timeout
+        let timeout = match substream.next().await {
+            Some(data) => decode_timeout(&data.unwrap()).unwrap(),
+            None => panic!("listener: expected rpc timeout from dialer"),
+        };
+        assert!(timeout <= Duration::from_secs(1));
         // read the rpc request data
         let data = match substream.next().await {
             Some(data) => data.unwrap().freeze(),
@@ -250,6 +371,8 @@ fn dialer_close_before_listener_recv() {
             dialer_peer_id,
             ProtocolId::from_static(protocol_id),
             listener_substream,
+            Instant::now() + Duration::from_secs(1),
+            true,
         )
         .await;
 
@@ -302,6 +425,8 @@ fn dialer_close_before_listener_send() {
             dialer_peer_id,
             ProtocolId::from_static(protocol_id),
             listener_substream,
+            Instant::now() + Duration::from_secs(1),
+            true,
         )
         .await;
 
@@ -317,6 +442,11 @@ fn dialer_close_before_listener_send() {
         // Rpc messages are length-prefixed.
         let mut substream =
             Framed::new(dialer_substream.compat(), UviBytes::default()).sink_compat();
+        // Send the dialer's timeout.
+        substream
+            .buffered_send(encode_timeout(Duration::from_secs(1)))
+            .await
+            .unwrap();
         // Send the rpc request data.
         substream
             .buffered_send(Bytes::from_static(req_data))
@@ -353,6 +483,8 @@ fn dialer_sends_two_requests_err() {
             dialer_peer_id,
             ProtocolId::from_static(protocol_id),
             listener_substream,
+            Instant::now() + Duration::from_secs(1),
+            true,
         )
         .await;
 
@@ -368,6 +500,11 @@ fn dialer_sends_two_requests_err() {
         // Rpc messages are length-prefixed.
         let mut substream =
             Framed::new(dialer_substream.compat(), UviBytes::default()).sink_compat();
+        // Send the dialer's timeout.
+        substream
+            .buffered_send(encode_timeout(Duration::from_secs(1)))
+            .await
+            .unwrap();
         // Send the rpc request data.
         substream
             .buffered_send(Bytes::from_static(req_data))
@@ -459,6 +596,7 @@ fn inbound_rpc_timeout() {
         listener_rpc_notifs_tx,
         inbound_notif,
         Duration::from_millis(100),
+        Arc::new(HashMap::new()),
     );
 
     // The listener future should complete (with a timeout) despite the dialer
@@ -469,6 +607,82 @@ fn inbound_rpc_timeout() {
         .unwrap();
 }
 
+// Test that the listener stops waiting for the response once the dialer's
+// timeout has elapsed, even if its own inbound timeout is much longer.
+#[test]
+fn inbound_rpc_dialer_deadline() {
+    ::logger::try_init_for_testing();
+
+    let listener_peer_id = PeerId::random();
+    let dialer_peer_id = PeerId::random();
+    let protocol_id = b"/get_blocks/1.0.0";
+    let req_data = b"hello";
+    let dialer_timeout = Duration::from_millis(100);
+
+    let (dialer_substream, listener_substream) = MemorySocket::new_pair();
+
+    // Fake the dialer NetworkProvider
+    let (dialer_peer_mgr_reqs_tx, dialer_peer_mgr_reqs_rx) = channel::new_test(8);
+    let dialer_peer_mgr_reqs_tx = PeerManagerRequestSender::new(dialer_peer_mgr_reqs_tx);
+    let f_dialer_peer_mgr = mock_peer_manager(dialer_peer_mgr_reqs_rx, dialer_substream);
+
+    // Fake the listener NetworkProvider, which never responds to the request.
+    let (listener_rpc_notifs_tx, mut listener_rpc_notifs_rx) = channel::new_test(8);
+    let f_listener_network = async move {
+        match listener_rpc_notifs_rx.next().await.unwrap() {
+            RpcNotification::RecvRpc(peer_id, mut req) => {
+                assert_eq!(peer_id, dialer_peer_id);
+                assert!(req.deadline <= Instant::now() + dialer_timeout);
+                // The listener should give up on the request once the dialer's
+                // deadline has passed.
+                future::poll_fn(|cx: &mut Context| req.res_tx.poll_cancel(cx)).await;
+                assert!(Instant::now() >= req.deadline);
+            }
+        }
+    };
+
+    let substream = NegotiatedSubstream {
+        protocol: ProtocolId::from_static(protocol_id),
+        substream: listener_substream,
+    };
+    let inbound_notif = PeerManagerNotification::NewInboundSubstream(dialer_peer_id, substream);
+    let f_listener_upgrade = handle_inbound_substream(
+        listener_rpc_notifs_tx,
+        inbound_notif,
+        Duration::from_secs(10),
+        Arc::new(HashMap::new()),
+    );
+
+    let f_dialer_upgrade = async move {
+        let res = do_outbound_rpc_req(
+            dialer_peer_mgr_reqs_tx,
+            listener_peer_id,
+            ProtocolId::from_static(protocol_id),
+            Bytes::from_static(req_data),
+            dialer_timeout,
+        )
+        .await;
+
+        // Check error is timeout error
+        let err = res.expect_err("Dialer's rpc request should fail");
+        match err {
+            RpcError::TimedOut => {}
+            err => panic!("Unexpected error: {:?}, expected TimedOut", err),
+        };
+    };
+
+    let f = join4(
+        f_dialer_peer_mgr,
+        f_dialer_upgrade,
+        f_listener_network,
+        f_listener_upgrade,
+    );
+    Runtime::new()
+        .unwrap()
+        .block_on(f.boxed().unit_error().compat())
+        .unwrap();
+}
+
 // Test that outbound rpcs can be canceled before sending
 #[test]
 fn outbound_cancellation_before_send() {
@@ -492,7 +706,7 @@ fn outbound_cancellation_before_send() {
         timeout: Duration::from_secs(1),
     };
     let rpc_req = RpcRequest::SendRpc(listener_peer_id, outbound_req);
-    let f_rpc = handle_outbound_rpc(dialer_peer_mgr_reqs_tx, rpc_req);
+    let f_rpc = handle_outbound_rpc(dialer_peer_mgr_reqs_tx, rpc_req, Arc::new(HashMap::new()));
 
     // drop res_rx to cancel the rpc request
     drop(res_rx);
@@ -544,7 +758,8 @@ fn outbound_cancellation_recv() {
         };
         let rpc_req = RpcRequest::SendRpc(listener_peer_id, outbound_req);
         let (f_rpc, f_rpc_done) =
-            handle_outbound_rpc(dialer_peer_mgr_reqs_tx, rpc_req).remote_handle();
+            handle_outbound_rpc(dialer_peer_mgr_reqs_tx, rpc_req, Arc::new(HashMap::new()))
+                .remote_handle();
         executor.spawn(f_rpc.unit_error().boxed().compat());
 
         futures::select! {
@@ -567,6 +782,12 @@ fn outbound_cancellation_recv() {
         // rpc messages are length-prefixed
         let mut substream =
             Framed::new(listener_substream.compat(), UviBytes::<Bytes>::default()).sink_compat();
+        // read the dialer's timeout
+        let timeout = match substream.next().await {
+            Some(data) => decode_timeout(&data.unwrap()).unwrap(),
+            None => panic!("listener: expected rpc timeout from dialer"),
+        };
+        assert!(timeout <= Duration::from_secs(1));
         // read the rpc request data
         let data = match substream.next().await {
             Some(data) => data.unwrap().freeze(),
@@ -618,7 +839,7 @@ fn rpc_protocol() {
     let dialer_peer_mgr_reqs_tx = PeerManagerRequestSender::new(dialer_peer_mgr_reqs_tx);
     let (rpc_handler_tx, _) = channel::new_test(8);
     let dialer_rpc = Rpc::new(
-        rt.executor(),
+        TaskManager::new("network", rt.executor()),
         dialer_rpc_rx,
         dialer_peer_mgr_notifs_rx,
         dialer_peer_mgr_reqs_tx,
@@ -626,6 +847,7 @@ fn rpc_protocol() {
         Duration::from_millis(500),
         10,
         10,
+        HashMap::new(),
     );
 
     // Fake the dialer NetworkProvider
@@ -650,7 +872,12 @@ fn rpc_protocol() {
             PeerManagerRequest::OpenSubstream(peer_id, protocol, substream_tx) => {
                 assert_eq!(peer_id, listener_peer_id);
                 assert_eq!(protocol.as_ref(), protocol_id);
-                substream_tx.send(Ok(dialer_substream)).unwrap();
+                substream_tx
+                    .send(Ok(NegotiatedSubstream {
+                        protocol,
+                        substream: dialer_substream,
+                    }))
+                    .unwrap();
             }
             _ => {
                 unreachable!();
@@ -669,7 +896,7 @@ fn rpc_protocol() {
     let listener_peer_mgr_reqs_tx = PeerManagerRequestSender::new(listener_peer_mgr_reqs_tx);
     let (listener_rpc_notifs_tx, mut listener_rpc_notifs_rx) = channel::new_test(8);
     let listener_rpc = Rpc::new(
-        rt.executor(),
+        TaskManager::new("network", rt.executor()),
         listener_rpc_reqs_rx,
         listener_peer_mgr_notifs_rx,
         listener_peer_mgr_reqs_tx,
@@ -677,6 +904,7 @@ fn rpc_protocol() {
         Duration::from_millis(500),
         10,
         10,
+        HashMap::new(),
     );
 
     // Fake the listener NetworkProvider
@@ -713,3 +941,170 @@ fn rpc_protocol() {
     );
     rt.block_on(f.boxed().unit_error().compat()).unwrap();
 }
+
+fn request_block(num_blocks: u64) -> RequestBlock {
+    RequestBlock {
+        num_blocks,
+        ..RequestBlock::default()
+    }
+}
+
+// Rpc requests timing out are sent again to the next peer.
+#[test]
+fn unary_rpc_retry_on_timeout() {
+    ::logger::try_init_for_testing();
+
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let f_res = utils::unary_rpc_with_policy(
+        network_reqs_tx,
+        peers.clone(),
+        ProtocolId::from_static(b"/get_blocks/1.0.0"),
+        request_block(1),
+        Instant::now() + Duration::from_secs(5),
+        utils::RpcPolicy::RetryOnTimeout {
+            attempt_timeout: Duration::from_secs(1),
+            max_attempts: 3,
+        },
+    );
+
+    let expected_peers = peers.clone();
+    let f_network = async move {
+        // The first peer times out.
+        match network_reqs_rx.next().await.unwrap() {
+            NetworkRequest::SendRpc(peer_id, req) => {
+                assert_eq!(peer_id, expected_peers[0]);
+                assert!(req.timeout <= Duration::from_secs(1));
+                req.res_tx.send(Err(RpcError::TimedOut)).unwrap();
+            }
+            req => panic!("Unexpected NetworkRequest: {:?}", req),
+        }
+        // The second peer responds.
+        match network_reqs_rx.next().await.unwrap() {
+            NetworkRequest::SendRpc(peer_id, req) => {
+                assert_eq!(peer_id, expected_peers[1]);
+                assert_eq!(
+                    RequestBlock::decode(req.data.as_ref()).unwrap(),
+                    request_block(1)
+                );
+                let res_data = request_block(2).to_bytes().unwrap();
+                req.res_tx.send(Ok(res_data)).unwrap();
+            }
+            req => panic!("Unexpected NetworkRequest: {:?}", req),
+        }
+    };
+
+    let (res, ()) = block_on(join(f_res, f_network));
+    assert_eq!(res.unwrap(), (peers[1], request_block(2)));
+}
+
+// Rpc requests failing for another reason than a timeout are not retried.
+#[test]
+fn unary_rpc_no_retry_on_error() {
+    ::logger::try_init_for_testing();
+
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let f_res = utils::unary_rpc_with_policy(
+        network_reqs_tx,
+        peers.clone(),
+        ProtocolId::from_static(b"/get_blocks/1.0.0"),
+        request_block(1),
+        Instant::now() + Duration::from_secs(5),
+        utils::RpcPolicy::RetryOnTimeout {
+            attempt_timeout: Duration::from_secs(1),
+            max_attempts: 3,
+        },
+    );
+
+    let f_network = async move {
+        match network_reqs_rx.next().await.unwrap() {
+            NetworkRequest::SendRpc(peer_id, req) => {
+                req.res_tx
+                    .send(Err(RpcError::NotConnected(peer_id)))
+                    .unwrap();
+            }
+            req => panic!("Unexpected NetworkRequest: {:?}", req),
+        }
+        network_reqs_rx
+    };
+
+    let (res, mut network_reqs_rx) = block_on(join(f_res, f_network));
+    match res.expect_err("Rpc request should fail") {
+        RpcError::NotConnected(peer_id) => assert_eq!(peer_id, peers[0]),
+        err => panic!("Unexpected error: {:?}, expected NotConnected", err),
+    }
+    // No other request was sent.
+    assert!(block_on(network_reqs_rx.next()).is_none());
+}
+
+// A second request is sent to the next peer once the first peer is slow to respond, and the
+// first response wins.
+#[test]
+fn unary_rpc_hedge() {
+    ::logger::try_init_for_testing();
+
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let f_res = utils::unary_rpc_with_policy(
+        network_reqs_tx,
+        peers.clone(),
+        ProtocolId::from_static(b"/get_blocks/1.0.0"),
+        request_block(1),
+        Instant::now() + Duration::from_secs(5),
+        utils::RpcPolicy::Hedge {
+            delay: Duration::from_millis(50),
+        },
+    );
+
+    let expected_peers = peers.clone();
+    let f_network = async move {
+        // The first peer hangs.
+        let slow_req = match network_reqs_rx.next().await.unwrap() {
+            NetworkRequest::SendRpc(peer_id, req) => {
+                assert_eq!(peer_id, expected_peers[0]);
+                req
+            }
+            req => panic!("Unexpected NetworkRequest: {:?}", req),
+        };
+        // The second peer responds.
+        match network_reqs_rx.next().await.unwrap() {
+            NetworkRequest::SendRpc(peer_id, req) => {
+                assert_eq!(peer_id, expected_peers[1]);
+                let res_data = request_block(2).to_bytes().unwrap();
+                req.res_tx.send(Ok(res_data)).unwrap();
+            }
+            req => panic!("Unexpected NetworkRequest: {:?}", req),
+        }
+        slow_req
+    };
+
+    let f = async move {
+        let (res, slow_req) = join(f_res, f_network).await;
+        assert_eq!(res.unwrap(), (peers[1], request_block(2)));
+        // The request to the slow peer is canceled.
+        assert!(slow_req.res_tx.is_canceled());
+    };
+    Runtime::new()
+        .unwrap()
+        .block_on(f.boxed().unit_error().compat())
+        .unwrap();
+}
+
+// An rpc request can't be sent without peers.
+#[test]
+fn unary_rpc_no_peers() {
+    let (network_reqs_tx, _network_reqs_rx) = channel::new_test(8);
+    let res = block_on(utils::unary_rpc_with_policy(
+        network_reqs_tx,
+        vec![],
+        ProtocolId::from_static(b"/get_blocks/1.0.0"),
+        request_block(1),
+        Instant::now() + Duration::from_secs(5),
+        utils::RpcPolicy::NoRetry,
+    ));
+    assert!(match res {
+        Err(RpcError::NoPeers) => true,
+        _ => false,
+    });
+}
@@ -1,19 +1,49 @@
 use crate::{
+    counters,
     interface::NetworkRequest,
-    protocols::rpc::{error::RpcError, OutboundRpcRequest},
+    protocols::rpc::{error::RpcError, remaining, OutboundRpcRequest},
     utils::MessageExt,
     ProtocolId,
 };
-use futures::{channel::oneshot, SinkExt};
-use std::time::Duration;
+use bytes::Bytes;
+use futures::{
+    channel::oneshot,
+    compat::Future01CompatExt,
+    future::{BoxFuture, FutureExt},
+    stream::{FuturesUnordered, StreamExt},
+    SinkExt,
+};
+use std::{
+    cmp::min,
+    time::{Duration, Instant},
+};
+use tokio::timer;
 use types::PeerId;
 
+/// How [`unary_rpc_with_policy`] deals with peers which are slow to respond.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RpcPolicy {
+    /// Send a single request, to the first peer.
+    NoRetry,
+    /// Send the request to the first peer, and to the next one each time a request times out,
+    /// cycling through the peers until `max_attempts` requests were sent. Each request is given
+    /// at most `attempt_timeout`.
+    RetryOnTimeout {
+        attempt_timeout: Duration,
+        max_attempts: usize,
+    },
+    /// Send the request to the first peer and, if it has not responded after `delay` (typically
+    /// the p99 latency of the protocol), to the second peer too. The first successful response
+    /// wins. The second request is sent right away if the first one fails before `delay`.
+    Hedge { delay: Duration },
+}
+
 /// Send a unary rpc request to remote peer `recipient`. Handles serialization and deserialization
 /// of the message types, assuming that the request and response both have the same message type.
 ///
 /// TODO: specify error cases
 pub async fn unary_rpc<T: prost::Message + Default>(
-    mut inner: channel::Sender<NetworkRequest>,
+    inner: channel::Sender<NetworkRequest>,
     recipient: PeerId,
     protocol: ProtocolId,
     req_msg: T,
@@ -21,8 +51,187 @@ pub async fn unary_rpc<T: prost::Message + Default>(
 ) -> Result<T, RpcError> {
     // serialize request
     let req_data = req_msg.to_bytes()?;
+    // ask network to fulfill rpc request and wait for response
+    let res_data = send_rpc(inner, recipient, protocol, req_data, timeout).await?;
+    // deserialize response
+    let res_msg = T::decode(res_data.as_ref())?;
+    Ok(res_msg)
+}
+
+/// Same as [`unary_rpc`], except that the request may be sent to several of `peers`, in order,
+/// as specified by `policy`. No request is sent, nor waited for, past `deadline`. Returns the peer
+/// which responded along with its response, or the error of the last request sent.
+///
+/// Fails with [`RpcError::NoPeers`] if `peers` is empty.
+pub async fn unary_rpc_with_policy<T: prost::Message + Default>(
+    inner: channel::Sender<NetworkRequest>,
+    peers: Vec<PeerId>,
+    protocol: ProtocolId,
+    req_msg: T,
+    deadline: Instant,
+    policy: RpcPolicy,
+) -> Result<(PeerId, T), RpcError> {
+    let req_data = req_msg.to_bytes()?;
+    let (peer_id, res_data) =
+        send_rpc_with_policy(inner, peers, protocol, req_data, deadline, policy).await?;
+    let res_msg = T::decode(res_data.as_ref())?;
+    Ok((peer_id, res_msg))
+}
+
+/// Same as [`unary_rpc_with_policy`], for a serialized request and response.
+pub(crate) async fn send_rpc_with_policy(
+    inner: channel::Sender<NetworkRequest>,
+    peers: Vec<PeerId>,
+    protocol: ProtocolId,
+    req_data: Bytes,
+    deadline: Instant,
+    policy: RpcPolicy,
+) -> Result<(PeerId, Bytes), RpcError> {
+    if peers.is_empty() {
+        return Err(RpcError::NoPeers);
+    }
+    match policy {
+        RpcPolicy::NoRetry => {
+            let peer_id = peers[0];
+            let res_data =
+                send_rpc(inner, peer_id, protocol, req_data, remaining(deadline)).await?;
+            Ok((peer_id, res_data))
+        }
+        RpcPolicy::RetryOnTimeout {
+            attempt_timeout,
+            max_attempts,
+        } => {
+            retry_on_timeout(
+                inner,
+                peers,
+                protocol,
+                req_data,
+                deadline,
+                attempt_timeout,
+                max_attempts,
+            )
+            .await
+        }
+        RpcPolicy::Hedge { delay } => {
+            hedge(inner, peers, protocol, req_data, deadline, delay).await
+        }
+    }
+}
 
-    // ask network to fulfill rpc request
+async fn retry_on_timeout(
+    inner: channel::Sender<NetworkRequest>,
+    peers: Vec<PeerId>,
+    protocol: ProtocolId,
+    req_data: Bytes,
+    deadline: Instant,
+    attempt_timeout: Duration,
+    max_attempts: usize,
+) -> Result<(PeerId, Bytes), RpcError> {
+    let mut last_err = RpcError::TimedOut;
+    for (attempt, peer_id) in peers.into_iter().cycle().take(max_attempts).enumerate() {
+        let timeout = min(attempt_timeout, remaining(deadline));
+        if timeout == Duration::from_millis(0) {
+            break;
+        }
+        if attempt > 0 {
+            counters::RPC_REQUESTS_RETRIED.inc();
+        }
+        match send_rpc(
+            inner.clone(),
+            peer_id,
+            protocol.clone(),
+            req_data.clone(),
+            timeout,
+        )
+        .await
+        {
+            Ok(res_data) => return Ok((peer_id, res_data)),
+            Err(RpcError::TimedOut) => last_err = RpcError::TimedOut,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_err)
+}
+
+async fn hedge(
+    inner: channel::Sender<NetworkRequest>,
+    peers: Vec<PeerId>,
+    protocol: ProtocolId,
+    req_data: Bytes,
+    deadline: Instant,
+    delay: Duration,
+) -> Result<(PeerId, Bytes), RpcError> {
+    let mut pending_rpcs = FuturesUnordered::new();
+    pending_rpcs.push(send_rpc_to(
+        inner.clone(),
+        peers[0],
+        protocol.clone(),
+        req_data.clone(),
+        remaining(deadline),
+    ));
+    let mut hedge_peer = peers.get(1).cloned();
+    let mut f_hedge = timer::Delay::new(min(Instant::now() + delay, deadline))
+        .compat()
+        .fuse();
+    loop {
+        ::futures::select! {
+            (peer_id, res) = pending_rpcs.select_next_some() => match res {
+                Ok(res_data) => return Ok((peer_id, res_data)),
+                Err(err) => {
+                    if let Some(peer_id) = hedge_peer.take() {
+                        if remaining(deadline) > Duration::from_millis(0) {
+                            counters::RPC_REQUESTS_RETRIED.inc();
+                            pending_rpcs.push(send_rpc_to(
+                                inner.clone(),
+                                peer_id,
+                                protocol.clone(),
+                                req_data.clone(),
+                                remaining(deadline),
+                            ));
+                        }
+                    }
+                    if pending_rpcs.is_empty() {
+                        return Err(err);
+                    }
+                }
+            },
+            _ = f_hedge => {
+                if let Some(peer_id) = hedge_peer.take() {
+                    counters::RPC_REQUESTS_HEDGED.inc();
+                    pending_rpcs.push(send_rpc_to(
+                        inner.clone(),
+                        peer_id,
+                        protocol.clone(),
+                        req_data.clone(),
+                        remaining(deadline),
+                    ));
+                }
+            },
+        }
+    }
+}
+
+fn send_rpc_to(
+    inner: channel::Sender<NetworkRequest>,
+    recipient: PeerId,
+    protocol: ProtocolId,
+    req_data: Bytes,
+    timeout: Duration,
+) -> BoxFuture<'static, (PeerId, Result<Bytes, RpcError>)> {
+    async move {
+        let res = send_rpc(inner, recipient, protocol, req_data, timeout).await;
+        (recipient, res)
+    }
+    .boxed()
+}
+
+pub(crate) async fn send_rpc(
+    mut inner: channel::Sender<NetworkRequest>,
+    recipient: PeerId,
+    protocol: ProtocolId,
+    req_data: Bytes,
+    timeout: Duration,
+) -> Result<Bytes, RpcError> {
     let (res_tx, res_rx) = oneshot::channel();
     let req = OutboundRpcRequest {
         protocol,
@@ -31,8 +240,5 @@ pub async fn unary_rpc<T: prost::Message + Default>(
         timeout,
     };
     inner.send(NetworkRequest::SendRpc(recipient, req)).await?;
-    // wait for response and deserialize
-    let res_data = res_rx.await??;
-    let res_msg = T::decode(res_data.as_ref())?;
-    Ok(res_msg)
+    res_rx.await?
 }
@@ -0,0 +1,596 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Relaying of connections to peers which cannot accept inbound connections, e.g., full nodes
+//! behind a NAT.
+//!
+//! A relay is a publicly reachable node which has opted in to forward connections on behalf of
+//! other peers. Relaying happens on the raw connection, before any of the connection upgrades:
+//! the relay splices the two connections byte-for-byte, so the Noise handshake, multiplexing and
+//! identity exchange all run end-to-end between the dialer and the target. The relay never sees
+//! plaintext and cannot impersonate either end.
+//!
+//! Protocol:
+//! * The target keeps a *reservation* open with each of its relays: it dials the relay, sends
+//!   `RESERVE` followed by its own peer id, authenticates with a Noise handshake and waits.
+//! * The target advertises its circuit address at each of its relays,
+//!   `<relay address>/p2p-circuit/p2p/<target peer id>`, along with its own address. A dialer
+//!   reaches the target at one of these: it dials the relay and sends `CONNECT` followed by the
+//!   target's peer id.
+//! * The relay pairs the dialer with one of the target's reservations, writes `ACCEPTED` to both
+//!   ends and then forwards bytes in both directions until either end closes. If the target has
+//!   no reservation, the relay writes `REJECTED` to the dialer and closes the connection.
+//!
+//! [`RelayTransport`] implements both ends of the protocol on top of an inner transport: its
+//! listener yields accepted reservations as inbound connections (and opens a new reservation in
+//! their place), and it dials circuit addresses through the relay. The [`Relay`] actor is only
+//! run by nodes which opted in to act as a relay.
+//!
+//! The Noise handshake of a reservation proves that the target holds the network identity key of
+//! the peer id it reserves for: on a permissioned network, the key trusted for that peer; on a
+//! permissionless one, the key the peer id is derived from. The handshake only authenticates the
+//! reservation, and the relayed connection is then spliced on the raw connection. Only transports
+//! with a Noise identity key can open reservations. The relay holds a bounded number of
+//! reservations, and rejects those of new peers once it is full.
+use crate::{common::NetworkPublicKeys, counters};
+use crypto::ValidKey;
+use futures::{
+    compat::Future01CompatExt,
+    future::{self, BoxFuture, FutureExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    stream::{self, BoxStream, Fuse, FuturesUnordered, Stream, StreamExt},
+};
+use logger::prelude::*;
+use netcore::transport::{ConnectionOrigin, Transport};
+use noise::NoiseConfig;
+use parity_multiaddr::{multihash::Multihash, Multiaddr, Protocol};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    io,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use task_manager::TaskManager;
+use tokio::timer;
+use types::{account_address::ADDRESS_LENGTH, PeerId};
+
+#[cfg(test)]
+mod test;
+
+const RESERVE: u8 = 0;
+const CONNECT: u8 = 1;
+const ACCEPTED: u8 = 0;
+const REJECTED: u8 = 1;
+
+/// Multihash code of sha2-256. PeerIds are 32 bytes long and are carried in circuit addresses
+/// as the digest of a sha2-256 multihash.
+const SHA2_256_CODE: u8 = 0x12;
+
+/// Reservations kept by the relay for a single peer. Reservations beyond this are assumed to have
+/// been abandoned by the peer and the oldest are dropped.
+pub const MAX_RESERVATIONS_PER_PEER: usize = 4;
+/// Peers the relay holds reservations for. Reservations of new peers are rejected beyond this.
+pub const MAX_RESERVED_PEERS: usize = 256;
+/// Connections to the relay which have yet to send their request. Connections beyond this are
+/// dropped.
+const MAX_PENDING_REQUESTS: usize = 256;
+/// Time allowed for a dialer or target to send its request once connected to the relay.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before retrying to open a reservation with a relay after a failure.
+const RESERVATION_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const SPLICE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Returns the address at which `peer_id` is reachable through the relay listening on
+/// `relay_addr`.
+pub fn circuit_addr(relay_addr: &Multiaddr, peer_id: PeerId) -> Multiaddr {
+    let mut bytes = vec![SHA2_256_CODE, ADDRESS_LENGTH as u8];
+    bytes.extend_from_slice(peer_id.as_ref());
+    let peer_hash = Multihash::from_bytes(bytes).expect("PeerId is a valid sha2-256 digest");
+
+    let mut addr = relay_addr.clone();
+    addr.push(Protocol::P2pCircuit);
+    addr.push(Protocol::P2p(peer_hash));
+    addr
+}
+
+/// Splits a circuit address into the address of the relay and the id of the relayed peer.
+/// Returns `None` if `addr` is not a circuit address.
+pub fn parse_circuit_addr(addr: &Multiaddr) -> Option<(Multiaddr, PeerId)> {
+    let mut protocols: Vec<_> = addr.iter().collect();
+    let peer_id = match protocols.pop() {
+        Some(Protocol::P2p(peer_hash)) => PeerId::try_from(peer_hash.digest()).ok()?,
+        _ => return None,
+    };
+    match protocols.pop() {
+        Some(Protocol::P2pCircuit) if !protocols.is_empty() => {
+            Some((protocols.into_iter().collect(), peer_id))
+        }
+        _ => None,
+    }
+}
+
+/// Transport which can reach peers through relays in addition to dialing them directly over the
+/// inner transport, and which accepts relayed connections through the reservations it keeps
+/// with `relays`. Reservations are authenticated with `noise_config`, and are not opened without
+/// one.
+pub struct RelayTransport<TTransport> {
+    inner: Arc<TTransport>,
+    peer_id: PeerId,
+    noise_config: Option<Arc<NoiseConfig>>,
+    relays: Vec<Multiaddr>,
+}
+
+impl<TTransport> RelayTransport<TTransport> {
+    pub fn new(
+        inner: TTransport,
+        peer_id: PeerId,
+        noise_config: Option<NoiseConfig>,
+        relays: Vec<Multiaddr>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            peer_id,
+            noise_config: noise_config.map(Arc::new),
+            relays,
+        }
+    }
+}
+
+impl<TTransport> Transport for RelayTransport<TTransport>
+where
+    TTransport: Transport<Error = io::Error> + Send + Sync + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    type Output = TTransport::Output;
+    type Error = io::Error;
+    type Listener = BoxStream<'static, io::Result<(Self::Inbound, Multiaddr)>>;
+    type Inbound = BoxFuture<'static, io::Result<Self::Output>>;
+    type Outbound = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn listen_on(&self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), Self::Error> {
+        let (listener, addr) = self.inner.listen_on(addr)?;
+        let listener = listener.map(|result| result.map(|(inbound, addr)| (inbound.boxed(), addr)));
+        let relays = match &self.noise_config {
+            Some(_) => self.relays.clone(),
+            None => {
+                if !self.relays.is_empty() {
+                    error!(
+                        "Relays {:?} ignored: reservations require a Noise identity key",
+                        self.relays
+                    );
+                }
+                vec![]
+            }
+        };
+        let reservations = stream::select_all(relays.into_iter().map(|relay_addr| {
+            let noise_config = self
+                .noise_config
+                .clone()
+                .expect("Reservations are only opened with a Noise identity key");
+            reservations(self.inner.clone(), relay_addr, self.peer_id, noise_config).boxed()
+        }));
+        Ok((stream::select(listener, reservations).boxed(), addr))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::Outbound, Self::Error> {
+        match parse_circuit_addr(&addr) {
+            Some((relay_addr, peer_id)) => {
+                let outbound = self.inner.dial(relay_addr)?;
+                Ok(async move {
+                    let mut socket = outbound.await?;
+                    write_request(&mut socket, CONNECT, peer_id).await?;
+                    read_response(&mut socket).await?;
+                    Ok(socket)
+                }
+                .boxed())
+            }
+            None => Ok(self.inner.dial(addr)?.boxed()),
+        }
+    }
+}
+
+// Stream of the connections relayed to us by the relay at `relay_addr`. A single reservation is
+// kept open at a time; the next one is opened once the previous one has been consumed.
+fn reservations<TTransport>(
+    transport: Arc<TTransport>,
+    relay_addr: Multiaddr,
+    peer_id: PeerId,
+    noise_config: Arc<NoiseConfig>,
+) -> impl Stream<
+    Item = io::Result<(
+        BoxFuture<'static, io::Result<TTransport::Output>>,
+        Multiaddr,
+    )>,
+>
+where
+    TTransport: Transport<Error = io::Error> + Send + Sync + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    // The real address of the dialer is not known, so relayed connections are attributed to the
+    // relay.
+    let mut remote_addr = relay_addr.clone();
+    remote_addr.push(Protocol::P2pCircuit);
+    stream::unfold((), move |()| {
+        let transport = transport.clone();
+        let relay_addr = relay_addr.clone();
+        let remote_addr = remote_addr.clone();
+        let noise_config = noise_config.clone();
+        async move {
+            loop {
+                match reserve(
+                    transport.as_ref(),
+                    relay_addr.clone(),
+                    peer_id,
+                    &noise_config,
+                )
+                .await
+                {
+                    Ok(socket) => {
+                        debug!("Accepted relayed connection through: {}", relay_addr);
+                        let inbound = future::ready(Ok(socket)).boxed();
+                        return Some((Ok((inbound, remote_addr)), ()));
+                    }
+                    Err(e) => {
+                        warn!("Reservation with relay {} failed: {:?}", relay_addr, e);
+                        if let Err(e) =
+                            timer::Delay::new(Instant::now() + RESERVATION_RETRY_INTERVAL)
+                                .compat()
+                                .await
+                        {
+                            error!("Timer error: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+// Opens a reservation with the relay, authenticates it and waits until a dialer is paired with
+// it.
+async fn reserve<TTransport>(
+    transport: &TTransport,
+    relay_addr: Multiaddr,
+    peer_id: PeerId,
+    noise_config: &NoiseConfig,
+) -> io::Result<TTransport::Output>
+where
+    TTransport: Transport<Error = io::Error>,
+    TTransport::Output: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut socket = transport.dial(relay_addr)?.await?;
+    write_request(&mut socket, RESERVE, peer_id).await?;
+    // The Noise session is dropped once the handshake is done; it only authenticates us to the
+    // relay.
+    noise_config
+        .upgrade_connection(&mut socket, ConnectionOrigin::Outbound)
+        .await?;
+    read_response(&mut socket).await?;
+    Ok(socket)
+}
+
+// Authenticates the reservation of `peer_id` on `socket`, returning whether the remote end holds
+// the identity key of `peer_id`.
+async fn authenticate_reservation<TSocket>(
+    socket: &mut TSocket,
+    peer_id: PeerId,
+    noise_config: &NoiseConfig,
+    trusted_peers: Option<&RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+) -> io::Result<bool>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    let (remote_static_key, _) = noise_config
+        .upgrade_connection(socket, ConnectionOrigin::Inbound)
+        .await?;
+    Ok(match trusted_peers {
+        Some(trusted_peers) => trusted_peers
+            .read()
+            .unwrap()
+            .get(&peer_id)
+            .map_or(false, |keys| {
+                keys.identity_public_key.to_bytes() == remote_static_key
+            }),
+        None => PeerId::try_from(remote_static_key.as_slice()).ok() == Some(peer_id),
+    })
+}
+
+async fn write_request<TSocket>(socket: &mut TSocket, kind: u8, peer_id: PeerId) -> io::Result<()>
+where
+    TSocket: AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(1 + ADDRESS_LENGTH);
+    buf.push(kind);
+    buf.extend_from_slice(peer_id.as_ref());
+    socket.write_all(&buf).await?;
+    socket.flush().await
+}
+
+async fn read_request<TSocket>(mut socket: TSocket) -> io::Result<(u8, PeerId, TSocket)>
+where
+    TSocket: AsyncRead + Unpin,
+{
+    let mut buf = [0u8; 1 + ADDRESS_LENGTH];
+    socket.read_exact(&mut buf).await?;
+    let peer_id = PeerId::try_from(&buf[1..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    match buf[0] {
+        RESERVE | CONNECT => Ok((buf[0], peer_id, socket)),
+        kind => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown relay request: {}", kind),
+        )),
+    }
+}
+
+async fn read_response<TSocket>(socket: &mut TSocket) -> io::Result<()>
+where
+    TSocket: AsyncRead + Unpin,
+{
+    let mut buf = [0u8; 1];
+    socket.read_exact(&mut buf).await?;
+    match buf[0] {
+        ACCEPTED => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Relay rejected the circuit",
+        )),
+    }
+}
+
+/// The Relay actor, which pairs dialers with the reservations of the peers they want to reach.
+pub struct Relay<TTransport>
+where
+    TTransport: Transport,
+{
+    /// Task manager to spawn the tasks forwarding the relayed connections.
+    task_manager: TaskManager,
+    /// Listener for connections from dialers and targets.
+    listener: Fuse<TTransport::Listener>,
+    /// Address the relay is listening on.
+    listen_addr: Multiaddr,
+    /// Noise config authenticating the reservations. The relay's own key is ephemeral as the
+    /// targets do not authenticate the relay.
+    noise_config: Arc<NoiseConfig>,
+    /// Keys of the peers allowed to reserve on a permissioned network. On a permissionless
+    /// network, any peer which holds the key its peer id is derived from may reserve.
+    trusted_peers: Option<Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>>,
+    /// Open reservations of each relayed peer, oldest first.
+    reservations: HashMap<PeerId, VecDeque<TTransport::Output>>,
+}
+
+impl<TTransport> Relay<TTransport>
+where
+    TTransport: Transport<Error = io::Error>,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Unpin,
+    TTransport::Inbound: Send + 'static,
+{
+    /// Creates a new instance of the [`Relay`] actor listening on `listen_addr`. Reservations are
+    /// only accepted from `trusted_peers` if given.
+    pub fn new(
+        transport: TTransport,
+        task_manager: TaskManager,
+        listen_addr: Multiaddr,
+        trusted_peers: Option<Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>>,
+    ) -> Self {
+        let (listener, listen_addr) = transport
+            .listen_on(listen_addr)
+            .expect("Relay transport listen on fails");
+        debug!("Relay listening on {}", listen_addr);
+        Self {
+            task_manager,
+            listener: listener.fuse(),
+            listen_addr,
+            noise_config: Arc::new(NoiseConfig::new_random()),
+            trusted_peers,
+            reservations: HashMap::new(),
+        }
+    }
+
+    /// Get the [`Multiaddr`] we're listening for relay requests on.
+    pub fn listen_addr(&self) -> &Multiaddr {
+        &self.listen_addr
+    }
+
+    /// Starts the [`Relay`] actor.
+    pub async fn start(mut self) {
+        let mut pending_requests = FuturesUnordered::new();
+        loop {
+            ::futures::select! {
+                incoming = self.listener.select_next_some() => {
+                    match incoming {
+                        Ok((upgrade, addr)) => {
+                            if pending_requests.len() >= MAX_PENDING_REQUESTS {
+                                debug!(
+                                    "Too many pending relay requests, dropping connection from {}",
+                                    addr
+                                );
+                                continue;
+                            }
+                            pending_requests.push(
+                                Self::receive_request(
+                                    upgrade,
+                                    addr,
+                                    self.noise_config.clone(),
+                                    self.trusted_peers.clone(),
+                                )
+                                .boxed(),
+                            );
+                        }
+                        Err(e) => {
+                            warn!("Incoming relay connection error {:?}", e);
+                        }
+                    }
+                }
+                request = pending_requests.select_next_some() => {
+                    if let Some((kind, peer_id, socket)) = request {
+                        self.handle_request(kind, peer_id, socket);
+                    }
+                }
+                complete => {
+                    crit!("Relay actor terminated");
+                    break;
+                }
+            }
+        }
+    }
+
+    // Reads the request of a new connection, authenticating it if it is a reservation. Reservations
+    // which fail to authenticate are rejected.
+    async fn receive_request(
+        upgrade: TTransport::Inbound,
+        addr: Multiaddr,
+        noise_config: Arc<NoiseConfig>,
+        trusted_peers: Option<Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>>,
+    ) -> Option<(u8, PeerId, TTransport::Output)> {
+        let f_request = async move {
+            let (kind, peer_id, mut socket) = read_request(upgrade.await?).await?;
+            if kind == RESERVE
+                && !authenticate_reservation(
+                    &mut socket,
+                    peer_id,
+                    &noise_config,
+                    trusted_peers.as_ref().map(|trusted_peers| &**trusted_peers),
+                )
+                .await?
+            {
+                counters::RELAY_RESERVATIONS_REJECTED.inc();
+                socket.write_all(&[REJECTED]).await?;
+                socket.close().await?;
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "Reservation failed to authenticate as peer: {}",
+                        peer_id.short_str()
+                    ),
+                ));
+            }
+            Ok((kind, peer_id, socket))
+        }
+            .fuse();
+        let mut f_timeout = timer::Delay::new(Instant::now() + REQUEST_TIMEOUT)
+            .compat()
+            .fuse();
+        ::futures::pin_mut!(f_request);
+        ::futures::select! {
+            request = f_request => match request {
+                Ok(request) => Some(request),
+                Err(e) => {
+                    debug!("Invalid relay request from {}: {:?}", addr, e);
+                    None
+                }
+            },
+            _ = f_timeout => {
+                debug!("Timed out waiting for relay request from {}", addr);
+                None
+            }
+        }
+    }
+
+    fn handle_request(&mut self, kind: u8, peer_id: PeerId, socket: TTransport::Output) {
+        match kind {
+            RESERVE => {
+                if !self.reservations.contains_key(&peer_id)
+                    && self.reservations.len() >= MAX_RESERVED_PEERS
+                {
+                    counters::RELAY_RESERVATIONS_REJECTED.inc();
+                    debug!(
+                        "Reservations of {} peers held, rejecting peer: {}",
+                        MAX_RESERVED_PEERS,
+                        peer_id.short_str()
+                    );
+                    self.reject(socket);
+                    return;
+                }
+                trace!("New reservation for peer: {}", peer_id.short_str());
+                let reservations = self.reservations.entry(peer_id).or_default();
+                reservations.push_back(socket);
+                if reservations.len() > MAX_RESERVATIONS_PER_PEER {
+                    reservations.pop_front();
+                }
+            }
+            CONNECT => {
+                let target = self
+                    .reservations
+                    .get_mut(&peer_id)
+                    .and_then(|reservations| reservations.pop_front());
+                if self
+                    .reservations
+                    .get(&peer_id)
+                    .map_or(false, |reservations| reservations.is_empty())
+                {
+                    self.reservations.remove(&peer_id);
+                }
+                match target {
+                    Some(target) => {
+                        counters::RELAY_CIRCUITS_ESTABLISHED.inc();
+                        let f_splice = async move {
+                            if let Err(e) = splice(socket, target).await {
+                                debug!("Relayed connection to {} closed: {:?}", peer_id, e);
+                            }
+                        };
+                        self.task_manager.spawn("relayed_circuit", f_splice);
+                    }
+                    None => {
+                        counters::RELAY_CIRCUITS_REJECTED.inc();
+                        debug!("No reservation for peer: {}", peer_id.short_str());
+                        self.reject(socket);
+                    }
+                }
+            }
+            _ => unreachable!("Unknown relay requests are rejected when received"),
+        }
+    }
+
+    // Writes `REJECTED` to `socket` and closes it.
+    fn reject(&self, mut socket: TTransport::Output) {
+        let f_reject = async move {
+            let _ = socket.write_all(&[REJECTED]).await;
+            let _ = socket.close().await;
+        };
+        self.task_manager.spawn("rejected_request", f_reject);
+    }
+}
+
+// Notifies both ends that the circuit is established and forwards bytes between them until both
+// directions are closed.
+async fn splice<TSocket>(mut dialer: TSocket, mut target: TSocket) -> io::Result<()>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    target.write_all(&[ACCEPTED]).await?;
+    target.flush().await?;
+    dialer.write_all(&[ACCEPTED]).await?;
+    dialer.flush().await?;
+
+    let (dialer_read, dialer_write) = dialer.split();
+    let (target_read, target_write) = target.split();
+    let (outbound, inbound) = future::join(
+        forward(dialer_read, target_write),
+        forward(target_read, dialer_write),
+    )
+    .await;
+    outbound.and(inbound)
+}
+
+async fn forward<TReader, TWriter>(mut reader: TReader, mut writer: TWriter) -> io::Result<()>
+where
+    TReader: AsyncRead + Unpin,
+    TWriter: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; SPLICE_BUFFER_SIZE];
+    loop {
+        let num_bytes = reader.read(&mut buf).await?;
+        if num_bytes == 0 {
+            return writer.close().await;
+        }
+        writer.write_all(&buf[..num_bytes]).await?;
+        writer.flush().await?;
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crypto::{ed25519::compat, test_utils::TEST_SEED, x25519};
+use futures::{FutureExt, TryFutureExt};
+use netcore::transport::memory::MemoryTransport;
+use rand::{rngs::StdRng, SeedableRng};
+use std::str::FromStr;
+use tokio::runtime::Runtime;
+
+fn start_relay(
+    rt: &mut Runtime,
+    trusted_peers: Option<Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>>,
+) -> Multiaddr {
+    let relay = Relay::new(
+        MemoryTransport::default(),
+        TaskManager::new("network", rt.executor()),
+        Multiaddr::from_str("/memory/0").unwrap(),
+        trusted_peers,
+    );
+    let relay_addr = relay.listen_addr().clone();
+    rt.spawn(relay.start().boxed().unit_error().compat());
+    relay_addr
+}
+
+// Returns a target holding a Noise identity key, along with the peer id derived from that key.
+fn build_target(
+    rng: &mut StdRng,
+    relay_addr: Multiaddr,
+) -> (RelayTransport<MemoryTransport>, PeerId) {
+    let (private_key, public_key) = x25519::compat::generate_keypair(rng);
+    let peer_id = PeerId::try_from(public_key.to_bytes()).unwrap();
+    let target = RelayTransport::new(
+        MemoryTransport::default(),
+        peer_id,
+        Some(NoiseConfig::new((private_key, public_key))),
+        vec![relay_addr],
+    );
+    (target, peer_id)
+}
+
+// Opens a reservation for `peer_id` with a new identity key.
+fn reserve_with_new_key(
+    rng: &mut StdRng,
+    relay_addr: Multiaddr,
+    peer_id: PeerId,
+) -> BoxFuture<'static, io::Result<()>> {
+    let noise_config = NoiseConfig::new(x25519::compat::generate_keypair(rng));
+    async move {
+        reserve(
+            &MemoryTransport::default(),
+            relay_addr,
+            peer_id,
+            &noise_config,
+        )
+        .await?;
+        Ok(())
+    }
+        .boxed()
+}
+
+// Dials `addr` until the relay accepts the circuit, as the target may not have opened its
+// reservation yet.
+async fn dial_until_accepted(
+    dialer: &RelayTransport<MemoryTransport>,
+    addr: Multiaddr,
+) -> memsocket::MemorySocket {
+    loop {
+        match dialer.dial(addr.clone()).unwrap().await {
+            Ok(socket) => return socket,
+            Err(_) => timer::Delay::new(Instant::now() + Duration::from_millis(10))
+                .compat()
+                .await
+                .unwrap(),
+        }
+    }
+}
+
+#[test]
+fn circuit_addr_roundtrip() {
+    let relay_addr = Multiaddr::from_str("/ip4/1.2.3.4/tcp/6181").unwrap();
+    let peer_id = PeerId::random();
+    let addr = circuit_addr(&relay_addr, peer_id);
+    assert_eq!(
+        parse_circuit_addr(&addr),
+        Some((relay_addr.clone(), peer_id))
+    );
+
+    // Addresses which are not circuit addresses.
+    assert_eq!(parse_circuit_addr(&relay_addr), None);
+    let mut no_peer = relay_addr.clone();
+    no_peer.push(Protocol::P2pCircuit);
+    assert_eq!(parse_circuit_addr(&no_peer), None);
+    let mut no_relay = Multiaddr::empty();
+    no_relay.push(Protocol::P2pCircuit);
+    no_relay.push(Protocol::P2p(
+        Multihash::from_bytes(
+            vec![SHA2_256_CODE, 32]
+                .into_iter()
+                .chain(vec![0; 32])
+                .collect(),
+        )
+        .unwrap(),
+    ));
+    assert_eq!(parse_circuit_addr(&no_relay), None);
+}
+
+#[test]
+fn relayed_connection() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let relay_addr = start_relay(&mut rt, None);
+
+    let mut rng = StdRng::from_seed(TEST_SEED);
+    let (target, target_id) = build_target(&mut rng, relay_addr.clone());
+    let (mut target_listener, _) = target
+        .listen_on(Multiaddr::from_str("/memory/0").unwrap())
+        .unwrap();
+    let dialer = RelayTransport::new(MemoryTransport::default(), PeerId::random(), None, vec![]);
+    let target_circuit_addr = circuit_addr(&relay_addr, target_id);
+
+    let f_target = async move {
+        let (inbound, addr) = target_listener.next().await.unwrap().unwrap();
+        let mut expected_addr = relay_addr;
+        expected_addr.push(Protocol::P2pCircuit);
+        assert_eq!(addr, expected_addr);
+        let mut socket = inbound.await.unwrap();
+        let mut buf = [0u8; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        socket.write_all(b"pong").await.unwrap();
+        socket.flush().await.unwrap();
+    };
+    let f_dialer = async move {
+        let mut socket = dial_until_accepted(&dialer, target_circuit_addr).await;
+        socket.write_all(b"ping").await.unwrap();
+        socket.flush().await.unwrap();
+        let mut buf = [0u8; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    };
+    rt.block_on(
+        future::join(f_target, f_dialer)
+            .map(|_| ())
+            .boxed()
+            .unit_error()
+            .compat(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn reject_without_reservation() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let relay_addr = start_relay(&mut rt, None);
+
+    let dialer = RelayTransport::new(MemoryTransport::default(), PeerId::random(), None, vec![]);
+    let f_dial = dialer
+        .dial(circuit_addr(&relay_addr, PeerId::random()))
+        .unwrap();
+    let err = rt
+        .block_on(f_dial.map(|result| result.map(|_| ())).compat())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+}
+
+// Test that a relay on a permissioned network only accepts the reservations of trusted peers
+// holding their trusted identity key.
+#[test]
+fn reservation_authenticated_by_trusted_key() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let mut rng = StdRng::from_seed(TEST_SEED);
+
+    // The trusted peer reserves under an id which is not derived from its key.
+    let trusted_id = PeerId::random();
+    let (private_key, identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+    let (_, signing_public_key) = compat::generate_keypair(&mut rng);
+    let trusted_peers = Arc::new(RwLock::new(
+        vec![(
+            trusted_id,
+            NetworkPublicKeys {
+                signing_public_key,
+                identity_public_key: identity_public_key.clone(),
+            },
+        )]
+        .into_iter()
+        .collect(),
+    ));
+    let relay_addr = start_relay(&mut rt, Some(trusted_peers));
+
+    // An impostor claims the id of the trusted peer with a key of its own.
+    let err = rt
+        .block_on(reserve_with_new_key(&mut rng, relay_addr.clone(), trusted_id).compat())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    // The impostor holds no reservation, so the circuit is rejected.
+    let dialer = RelayTransport::new(MemoryTransport::default(), PeerId::random(), None, vec![]);
+    let f_dial = dialer.dial(circuit_addr(&relay_addr, trusted_id)).unwrap();
+    let err = rt
+        .block_on(f_dial.map(|result| result.map(|_| ())).compat())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+    // The trusted peer with its trusted key is relayed.
+    let target = RelayTransport::new(
+        MemoryTransport::default(),
+        trusted_id,
+        Some(NoiseConfig::new((private_key, identity_public_key))),
+        vec![relay_addr.clone()],
+    );
+    let (mut target_listener, _) = target
+        .listen_on(Multiaddr::from_str("/memory/0").unwrap())
+        .unwrap();
+    let target_circuit_addr = circuit_addr(&relay_addr, trusted_id);
+    let f_target = async move {
+        let (inbound, _) = target_listener.next().await.unwrap().unwrap();
+        inbound.await.unwrap();
+    };
+    let f_dialer = async move {
+        dial_until_accepted(&dialer, target_circuit_addr).await;
+    };
+    rt.block_on(
+        future::join(f_target, f_dialer)
+            .map(|_| ())
+            .boxed()
+            .unit_error()
+            .compat(),
+    )
+    .unwrap();
+}
+
+// Test that a relay on a permissionless network rejects the reservations of a peer id which is not
+// derived from the key of the peer.
+#[test]
+fn reservation_authenticated_by_derived_peer_id() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let mut rng = StdRng::from_seed(TEST_SEED);
+    let relay_addr = start_relay(&mut rt, None);
+
+    let err = rt
+        .block_on(reserve_with_new_key(&mut rng, relay_addr, PeerId::random()).compat())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+}
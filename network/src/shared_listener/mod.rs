@@ -0,0 +1,258 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Multiplexing of several logical networks over a single listening socket.
+//!
+//! A node taking part in several networks, e.g., the validator network and a public full node
+//! network, would otherwise need a listener, and thus a port, for each of them. Instead, the
+//! networks can share a listener: every connection starts with the dialer selecting the id of the
+//! network it wants to reach, using the same negotiation as for substream protocols. The
+//! [`SharedListener`] actor accepts the connections, and hands each of them to the network it
+//! selected. All further upgrades (Noise with the keys of that network, multiplexing and identity
+//! exchange) are then run by the transport of that network, so networks sharing a listener keep
+//! distinct peer sets, keys and protocols.
+//!
+//! Each network obtains a [`NetworkTransport`] from the shared listener, which it uses as the base
+//! of its transport stack: its listener yields the connections which selected the network, and
+//! its dials select the network before handing out the connection. As a consequence, every peer
+//! of a network which is reached over a shared listener must use the same network id.
+//!
+//! Relays are not supported over a shared listener, as relay requests are sent before the network
+//! is selected.
+use crate::{counters, ProtocolId};
+use channel;
+use futures::{
+    compat::Future01CompatExt,
+    future::{self, BoxFuture, FutureExt},
+    io::{AsyncRead, AsyncWrite},
+    stream::{BoxStream, Fuse, FuturesUnordered, StreamExt},
+};
+use logger::prelude::*;
+use netcore::{
+    negotiate::{negotiate_inbound, negotiate_outbound_interactive},
+    transport::Transport,
+};
+use parity_multiaddr::Multiaddr;
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::timer;
+
+#[cfg(test)]
+mod test;
+
+/// Inbound connections accepted for a single network which it has not picked up yet. Connections
+/// beyond this are dropped.
+const NETWORK_CONNECTIONS_CHANNEL_SIZE: usize = 128;
+/// Time allowed for a dialer to select a network once connected.
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections which may be selecting a network at the same time. Connections beyond this are
+/// dropped, so that dialers which never select a network can't exhaust the memory of the node.
+const MAX_PENDING_NEGOTIATIONS: usize = 256;
+
+/// The SharedListener actor, which routes the connections accepted on a single listener to the
+/// networks they select.
+pub struct SharedListener<TTransport>
+where
+    TTransport: Transport,
+{
+    /// Transport the listener was opened on, used by the networks to dial.
+    transport: Arc<TTransport>,
+    /// Listener for connections to any of the networks.
+    listener: Fuse<TTransport::Listener>,
+    /// Address the shared listener is listening on.
+    listen_addr: Multiaddr,
+    /// Channels to each network over which the connections which selected it are handed out.
+    networks: HashMap<ProtocolId, channel::Sender<(TTransport::Output, Multiaddr)>>,
+}
+
+impl<TTransport> SharedListener<TTransport>
+where
+    TTransport: Transport<Error = io::Error>,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Unpin,
+    TTransport::Inbound: Send + 'static,
+{
+    /// Creates a new instance of the [`SharedListener`] actor listening on `listen_addr`.
+    pub fn new(transport: TTransport, listen_addr: Multiaddr) -> Self {
+        let (listener, listen_addr) = transport
+            .listen_on(listen_addr)
+            .expect("Shared listener transport listen on fails");
+        debug!("Shared listener listening on {}", listen_addr);
+        Self {
+            transport: Arc::new(transport),
+            listener: listener.fuse(),
+            listen_addr,
+            networks: HashMap::new(),
+        }
+    }
+
+    /// Get the [`Multiaddr`] we're listening for connections on.
+    pub fn listen_addr(&self) -> &Multiaddr {
+        &self.listen_addr
+    }
+
+    /// Adds the network identified by `network_id` to the networks sharing this listener, and
+    /// returns the transport on top of which the network is to be built.
+    pub fn add_network(&mut self, network_id: ProtocolId) -> NetworkTransport<TTransport> {
+        let (connections_tx, connections_rx) = channel::new(
+            NETWORK_CONNECTIONS_CHANNEL_SIZE,
+            &counters::PENDING_SHARED_LISTENER_CONNECTIONS,
+        );
+        assert!(
+            self.networks
+                .insert(network_id.clone(), connections_tx)
+                .is_none(),
+            "Network {:?} added twice to the shared listener",
+            network_id
+        );
+        NetworkTransport {
+            transport: self.transport.clone(),
+            network_id,
+            listen_addr: self.listen_addr.clone(),
+            connections_rx: Mutex::new(Some(connections_rx)),
+        }
+    }
+
+    /// Starts the [`SharedListener`] actor.
+    pub async fn start(mut self) {
+        let network_ids: Vec<_> = self.networks.keys().cloned().collect();
+        let mut pending_negotiations = FuturesUnordered::new();
+        loop {
+            ::futures::select! {
+                incoming = self.listener.select_next_some() => {
+                    match incoming {
+                        Ok((upgrade, addr)) => {
+                            if pending_negotiations.len() >= MAX_PENDING_NEGOTIATIONS {
+                                counters::SHARED_LISTENER_CONNECTIONS_REJECTED.inc();
+                                debug!("Too many connections selecting a network, dropping connection from {}", addr);
+                            } else {
+                                pending_negotiations.push(
+                                    Self::select_network(upgrade, addr, network_ids.clone()).boxed(),
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Incoming connection error {:?}", e);
+                        }
+                    }
+                }
+                negotiated = pending_negotiations.select_next_some() => {
+                    if let Some((network_id, socket, addr)) = negotiated {
+                        self.route_connection(network_id, socket, addr);
+                    }
+                }
+                complete => {
+                    crit!("Shared listener actor terminated");
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn select_network(
+        upgrade: TTransport::Inbound,
+        addr: Multiaddr,
+        network_ids: Vec<ProtocolId>,
+    ) -> Option<(ProtocolId, TTransport::Output, Multiaddr)> {
+        let f_negotiate =
+            async move { negotiate_inbound(upgrade.await?, network_ids).await }.fuse();
+        let mut f_timeout = timer::Delay::new(Instant::now() + NEGOTIATION_TIMEOUT)
+            .compat()
+            .fuse();
+        ::futures::pin_mut!(f_negotiate);
+        ::futures::select! {
+            negotiated = f_negotiate => match negotiated {
+                Ok((socket, network_id)) => Some((network_id, socket, addr)),
+                Err(e) => {
+                    counters::SHARED_LISTENER_CONNECTIONS_REJECTED.inc();
+                    debug!("Failed to select network for connection from {}: {:?}", addr, e);
+                    None
+                }
+            },
+            _ = f_timeout => {
+                counters::SHARED_LISTENER_CONNECTIONS_REJECTED.inc();
+                debug!("Timed out waiting for network selection from {}", addr);
+                None
+            }
+        }
+    }
+
+    fn route_connection(
+        &mut self,
+        network_id: ProtocolId,
+        socket: TTransport::Output,
+        addr: Multiaddr,
+    ) {
+        let connections_tx = self
+            .networks
+            .get_mut(&network_id)
+            .expect("Only the ids of added networks are negotiated");
+        if let Err(e) = connections_tx.try_send((socket, addr.clone())) {
+            warn!(
+                "Dropping connection from {} to network {:?}: {:?}",
+                addr, network_id, e
+            );
+        }
+    }
+}
+
+/// Transport of a single network sharing a listener. Its listener yields the connections which
+/// selected the network, and its dials select the network on the new connection.
+pub struct NetworkTransport<TTransport>
+where
+    TTransport: Transport,
+{
+    transport: Arc<TTransport>,
+    network_id: ProtocolId,
+    listen_addr: Multiaddr,
+    /// Connections routed to this network, taken by the first call to `listen_on`.
+    connections_rx: Mutex<Option<channel::Receiver<(TTransport::Output, Multiaddr)>>>,
+}
+
+impl<TTransport> Transport for NetworkTransport<TTransport>
+where
+    TTransport: Transport<Error = io::Error> + Send + Sync + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    type Output = TTransport::Output;
+    type Error = io::Error;
+    type Listener = BoxStream<'static, io::Result<(Self::Inbound, Multiaddr)>>;
+    type Inbound = BoxFuture<'static, io::Result<Self::Output>>;
+    type Outbound = BoxFuture<'static, io::Result<Self::Output>>;
+
+    /// Returns the connections which selected this network. As the listener is shared, `addr` is
+    /// ignored and the address of the shared listener is returned instead.
+    fn listen_on(&self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), Self::Error> {
+        if addr != self.listen_addr {
+            debug!(
+                "Network {:?} listens on shared address {} instead of {}",
+                self.network_id, self.listen_addr, addr
+            );
+        }
+        let connections_rx = self.connections_rx.lock().unwrap().take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "Network is already listening on the shared listener",
+            )
+        })?;
+        let listener = connections_rx
+            .map(|(socket, addr)| Ok((future::ready(Ok(socket)).boxed(), addr)))
+            .boxed();
+        Ok((listener, self.listen_addr.clone()))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::Outbound, Self::Error> {
+        let outbound = self.transport.dial(addr)?;
+        let network_id = self.network_id.clone();
+        Ok(async move {
+            let (socket, _) = negotiate_outbound_interactive(outbound.await?, [network_id]).await?;
+            Ok(socket)
+        }
+        .boxed())
+    }
+}
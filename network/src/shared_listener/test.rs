@@ -0,0 +1,106 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use futures::{
+    future::join,
+    io::{AsyncReadExt, AsyncWriteExt},
+    TryFutureExt,
+};
+use netcore::transport::memory::MemoryTransport;
+use std::str::FromStr;
+use tokio::runtime::Runtime;
+
+const VALIDATOR_NETWORK: &[u8] = b"/libra/network/validator";
+const FULL_NODE_NETWORK: &[u8] = b"/libra/network/full_node";
+
+#[test]
+fn route_connections_to_networks() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let mut shared_listener = SharedListener::new(
+        MemoryTransport::default(),
+        Multiaddr::from_str("/memory/0").unwrap(),
+    );
+    let listen_addr = shared_listener.listen_addr().clone();
+    let validator_network = shared_listener.add_network(ProtocolId::from_static(VALIDATOR_NETWORK));
+    let full_node_network = shared_listener.add_network(ProtocolId::from_static(FULL_NODE_NETWORK));
+    rt.spawn(shared_listener.start().boxed().unit_error().compat());
+
+    let (mut validator_listener, validator_addr) = validator_network
+        .listen_on(Multiaddr::from_str("/memory/0").unwrap())
+        .unwrap();
+    assert_eq!(validator_addr, listen_addr);
+    let (mut full_node_listener, full_node_addr) =
+        full_node_network.listen_on(listen_addr.clone()).unwrap();
+    assert_eq!(full_node_addr, listen_addr);
+    // The connections of a network can only be listened for once.
+    assert!(validator_network.listen_on(listen_addr.clone()).is_err());
+
+    // A dialer of each network, sharing nothing but the listen address.
+    let validator_dialer = SharedListener::new(
+        MemoryTransport::default(),
+        Multiaddr::from_str("/memory/0").unwrap(),
+    )
+    .add_network(ProtocolId::from_static(VALIDATOR_NETWORK));
+    let full_node_dialer = SharedListener::new(
+        MemoryTransport::default(),
+        Multiaddr::from_str("/memory/0").unwrap(),
+    )
+    .add_network(ProtocolId::from_static(FULL_NODE_NETWORK));
+
+    let f_listeners = async move {
+        let (inbound, _) = full_node_listener.next().await.unwrap().unwrap();
+        let mut socket = inbound.await.unwrap();
+        let mut buf = [0u8; 9];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"full node");
+
+        let (inbound, _) = validator_listener.next().await.unwrap().unwrap();
+        let mut socket = inbound.await.unwrap();
+        let mut buf = [0u8; 9];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"validator");
+    };
+    let f_dialers = async move {
+        let mut socket = full_node_dialer
+            .dial(listen_addr.clone())
+            .unwrap()
+            .await
+            .unwrap();
+        socket.write_all(b"full node").await.unwrap();
+        socket.flush().await.unwrap();
+
+        let mut socket = validator_dialer.dial(listen_addr).unwrap().await.unwrap();
+        socket.write_all(b"validator").await.unwrap();
+        socket.flush().await.unwrap();
+        socket
+    };
+    rt.block_on(join(f_listeners, f_dialers).boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn reject_unknown_network() {
+    ::logger::try_init_for_testing();
+    let mut rt = Runtime::new().unwrap();
+    let mut shared_listener = SharedListener::new(
+        MemoryTransport::default(),
+        Multiaddr::from_str("/memory/0").unwrap(),
+    );
+    let listen_addr = shared_listener.listen_addr().clone();
+    let _validator_network =
+        shared_listener.add_network(ProtocolId::from_static(VALIDATOR_NETWORK));
+    rt.spawn(shared_listener.start().boxed().unit_error().compat());
+
+    let full_node_dialer = SharedListener::new(
+        MemoryTransport::default(),
+        Multiaddr::from_str("/memory/0").unwrap(),
+    )
+    .add_network(ProtocolId::from_static(FULL_NODE_NETWORK));
+    let f_dial = async move { full_node_dialer.dial(listen_addr).unwrap().await };
+    assert!(rt
+        .block_on(f_dial.boxed().unit_error().compat())
+        .unwrap()
+        .is_err());
+}
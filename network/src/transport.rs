@@ -4,17 +4,22 @@
 use crate::{
     common::NetworkPublicKeys,
     protocols::identity::{exchange_identity, Identity},
+    relay::RelayTransport,
+    shared_listener::NetworkTransport,
 };
+use config::config::SocketConfig;
 use crypto::{
     x25519::{X25519StaticPrivateKey, X25519StaticPublicKey},
     ValidKey,
 };
+use futures::io::{AsyncRead, AsyncWrite};
 use logger::prelude::*;
 use netcore::{
     multiplexing::{yamux::Yamux, StreamMultiplexer},
-    transport::{boxed, memory, tcp, TransportExt},
+    transport::{boxed, fault::FaultInjector, memory, tcp, Transport, TransportExt},
 };
 use noise::NoiseConfig;
+use parity_multiaddr::Multiaddr;
 use std::{
     collections::HashMap,
     convert::TryFrom,
@@ -22,6 +27,7 @@ use std::{
     sync::{Arc, RwLock},
     time::Duration,
 };
+use tls::TlsConfig;
 use types::PeerId;
 
 /// A timeout for the connection to open and complete all of the upgrade steps.
@@ -40,7 +46,20 @@ fn identity_key_to_peer_id(
     None
 }
 
-// Ensures that peer id in received identity is same as peer id derived from noise handshake.
+fn signing_key_to_peer_id(
+    trusted_peers: &RwLock<HashMap<PeerId, NetworkPublicKeys>>,
+    remote_signing_key: &[u8],
+) -> Option<PeerId> {
+    for (peer_id, public_keys) in trusted_peers.read().unwrap().iter() {
+        if public_keys.signing_public_key.to_bytes() == remote_signing_key {
+            return Some(*peer_id);
+        }
+    }
+
+    None
+}
+
+// Ensures that peer id in received identity is same as peer id derived from the handshake.
 fn match_peer_id(identity: Identity, peer_id: PeerId) -> Result<Identity, io::Error> {
     if identity.peer_id() != peer_id {
         security_log(SecurityEvent::InvalidNetworkPeer)
@@ -51,7 +70,7 @@ fn match_peer_id(identity: Identity, peer_id: PeerId) -> Result<Identity, io::Er
         Err(io::Error::new(
                 io::ErrorKind::Other,
                 format!(
-                    "PeerId received from Noise Handshake ({}) doesn't match one received from Identity Exchange ({})",
+                    "PeerId received from Handshake ({}) doesn't match one received from Identity Exchange ({})",
                     peer_id.short_str(),
                     identity.peer_id().short_str()
                 )
@@ -178,16 +197,217 @@ pub fn build_memory_transport(
         .boxed()
 }
 
+/// TCP transport setting the options of `socket` on the sockets it opens.
+pub fn build_tcp_socket_transport(socket: &SocketConfig) -> tcp::TcpTransport {
+    let mut tcp_transport = tcp::TcpTransport::default();
+    if let Some(nodelay) = socket.nodelay {
+        tcp_transport = tcp_transport.nodelay(nodelay);
+    }
+    if let Some(size) = socket.send_buffer_size {
+        tcp_transport = tcp_transport.send_buffer_size(size);
+    }
+    if let Some(size) = socket.recv_buffer_size {
+        tcp_transport = tcp_transport.recv_buffer_size(size);
+    }
+    if let Some(keepalive_ms) = socket.keepalive_ms {
+        tcp_transport = tcp_transport.keepalive(Some(Duration::from_millis(keepalive_ms)));
+    }
+    tcp_transport
+}
+
+// TCP transport which also accepts and dials relayed connections through `relays`. Connections
+// are relayed before they are upgraded, so the upgrades run end-to-end with the remote peer. The
+// reservations with the relays are authenticated with `identity_keypair`, and are only opened if
+// it is given.
+fn build_tcp_relay_transport(
+    tcp_transport: tcp::TcpTransport,
+    own_identity: &Identity,
+    identity_keypair: Option<&(X25519StaticPrivateKey, X25519StaticPublicKey)>,
+    relays: Vec<Multiaddr>,
+) -> RelayTransport<tcp::TcpTransport> {
+    let noise_config = identity_keypair.map(|keypair| NoiseConfig::new(keypair.clone()));
+    RelayTransport::new(tcp_transport, own_identity.peer_id(), noise_config, relays)
+}
+
 //TODO(bmwill) Maybe create an Either Transport so we can merge the building of Memory + Tcp
 pub fn build_tcp_noise_transport(
+    tcp_transport: tcp::TcpTransport,
     own_identity: Identity,
     identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
     trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    relays: Vec<Multiaddr>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    let tcp_transport = build_tcp_relay_transport(
+        tcp_transport,
+        &own_identity,
+        Some(&identity_keypair),
+        relays,
+    );
+    upgrade_noise_transport(
+        tcp_transport,
+        own_identity,
+        identity_keypair,
+        trusted_peers,
+        fault_injector,
+    )
+}
+
+// Transport based on TCP + Noise, but permissionless -- i.e., any node is allowed to connect.
+pub fn build_permissionless_tcp_noise_transport(
+    tcp_transport: tcp::TcpTransport,
+    own_identity: Identity,
+    identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
+    relays: Vec<Multiaddr>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
 ) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
-    let tcp_transport = tcp::TcpTransport::default();
+    let tcp_transport = build_tcp_relay_transport(
+        tcp_transport,
+        &own_identity,
+        Some(&identity_keypair),
+        relays,
+    );
+    upgrade_permissionless_noise_transport(
+        tcp_transport,
+        own_identity,
+        identity_keypair,
+        fault_injector,
+    )
+}
+
+// Transport based on TCP + TLS, only accepting trusted peers.
+pub fn build_tcp_tls_transport(
+    tcp_transport: tcp::TcpTransport,
+    own_identity: Identity,
+    tls_config: TlsConfig,
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    relays: Vec<Multiaddr>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    let tcp_transport = build_tcp_relay_transport(tcp_transport, &own_identity, None, relays);
+    upgrade_tls_transport(
+        tcp_transport,
+        own_identity,
+        tls_config,
+        trusted_peers,
+        fault_injector,
+    )
+}
+
+// Transport based on TCP + TLS, but permissionless -- i.e., any node is allowed to connect.
+pub fn build_permissionless_tcp_tls_transport(
+    tcp_transport: tcp::TcpTransport,
+    own_identity: Identity,
+    tls_config: TlsConfig,
+    relays: Vec<Multiaddr>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    let tcp_transport = build_tcp_relay_transport(tcp_transport, &own_identity, None, relays);
+    upgrade_permissionless_tls_transport(tcp_transport, own_identity, tls_config, fault_injector)
+}
+
+pub fn build_tcp_transport(
+    tcp_transport: tcp::TcpTransport,
+    own_identity: Identity,
+    relays: Vec<Multiaddr>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    let tcp_transport = build_tcp_relay_transport(tcp_transport, &own_identity, None, relays);
+    upgrade_transport(tcp_transport, own_identity, fault_injector)
+}
+
+// The following are the TCP transports of a network sharing its listener with other networks.
+// They are built on the transport handed out by the shared listener for the network.
+
+pub fn build_shared_tcp_noise_transport(
+    own_identity: Identity,
+    identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    network_transport: NetworkTransport<tcp::TcpTransport>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    upgrade_noise_transport(
+        network_transport,
+        own_identity,
+        identity_keypair,
+        trusted_peers,
+        fault_injector,
+    )
+}
+
+pub fn build_shared_permissionless_tcp_noise_transport(
+    own_identity: Identity,
+    identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
+    network_transport: NetworkTransport<tcp::TcpTransport>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    upgrade_permissionless_noise_transport(
+        network_transport,
+        own_identity,
+        identity_keypair,
+        fault_injector,
+    )
+}
+
+pub fn build_shared_tcp_tls_transport(
+    own_identity: Identity,
+    tls_config: TlsConfig,
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    network_transport: NetworkTransport<tcp::TcpTransport>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    upgrade_tls_transport(
+        network_transport,
+        own_identity,
+        tls_config,
+        trusted_peers,
+        fault_injector,
+    )
+}
+
+pub fn build_shared_permissionless_tcp_tls_transport(
+    own_identity: Identity,
+    tls_config: TlsConfig,
+    network_transport: NetworkTransport<tcp::TcpTransport>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    upgrade_permissionless_tls_transport(
+        network_transport,
+        own_identity,
+        tls_config,
+        fault_injector,
+    )
+}
+
+pub fn build_shared_tcp_transport(
+    own_identity: Identity,
+    network_transport: NetworkTransport<tcp::TcpTransport>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
+    upgrade_transport(network_transport, own_identity, fault_injector)
+}
+
+// Upgrades the connections of `transport` with Noise, only accepting trusted peers, then with
+// multiplexing and the identity exchange. Faults are injected below the upgrades, into the raw
+// connections of `transport`.
+fn upgrade_noise_transport<TTransport>(
+    transport: TTransport,
+    own_identity: Identity,
+    identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error>
+where
+    TTransport: Transport<Error = io::Error> + Send + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
     let noise_config = Arc::new(NoiseConfig::new(identity_keypair));
 
-    tcp_transport
+    transport
+        .with_faults(fault_injector)
         .and_then(move |socket, origin| {
             async move {
                 let (remote_static_key, socket) =
@@ -222,14 +442,24 @@ pub fn build_tcp_noise_transport(
         .boxed()
 }
 
-// Transport based on TCP + Noise, but permissionless -- i.e., any node is allowed to connect.
-pub fn build_permissionless_tcp_noise_transport(
+// Upgrades the connections of `transport` with Noise, accepting any peer, then with multiplexing
+// and the identity exchange.
+fn upgrade_permissionless_noise_transport<TTransport>(
+    transport: TTransport,
     own_identity: Identity,
     identity_keypair: (X25519StaticPrivateKey, X25519StaticPublicKey),
-) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
-    let tcp_transport = tcp::TcpTransport::default();
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error>
+where
+    TTransport: Transport<Error = io::Error> + Send + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
     let noise_config = Arc::new(NoiseConfig::new(identity_keypair));
-    tcp_transport
+    transport
+        .with_faults(fault_injector)
         .and_then(move |socket, origin| {
             async move {
                 let (remote_static_key, socket) =
@@ -263,12 +493,122 @@ pub fn build_permissionless_tcp_noise_transport(
         .boxed()
 }
 
-pub fn build_tcp_transport(
+// Upgrades the connections of `transport` with TLS, only accepting trusted peers, then with
+// multiplexing and the identity exchange. Peers are identified by the network signing key their
+// certificate is for.
+fn upgrade_tls_transport<TTransport>(
+    transport: TTransport,
     own_identity: Identity,
-) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error> {
-    let tcp_transport = tcp::TcpTransport::default();
+    tls_config: TlsConfig,
+    trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error>
+where
+    TTransport: Transport<Error = io::Error> + Send + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    let tls_config = Arc::new(tls_config);
 
-    tcp_transport
+    transport
+        .with_faults(fault_injector)
+        .and_then(move |socket, origin| {
+            async move {
+                let (remote_signing_key, socket) =
+                    tls_config.upgrade_connection(socket, origin).await?;
+                if let Some(peer_id) = signing_key_to_peer_id(&trusted_peers, &remote_signing_key) {
+                    Ok((peer_id, socket))
+                } else {
+                    security_log(SecurityEvent::InvalidNetworkPeer)
+                        .error("UntrustedPeer")
+                        .data(&trusted_peers)
+                        .data(&remote_signing_key)
+                        .log();
+                    Err(io::Error::new(io::ErrorKind::Other, "Not a trusted peer"))
+                }
+            }
+        })
+        .and_then(|(peer_id, socket), origin| {
+            async move {
+                let muxer = Yamux::upgrade_connection(socket, origin).await?;
+                Ok((peer_id, muxer))
+            }
+        })
+        .and_then(move |(peer_id, muxer), origin| {
+            async move {
+                let (identity, muxer) = exchange_identity(&own_identity, muxer, origin).await?;
+                match_peer_id(identity, peer_id)
+                    .and_then(|identity| check_role(&own_identity, identity))
+                    .and_then(|identity| Ok((identity, muxer)))
+            }
+        })
+        .with_timeout(TRANSPORT_TIMEOUT)
+        .boxed()
+}
+
+// Upgrades the connections of `transport` with TLS, accepting any peer, then with multiplexing
+// and the identity exchange.
+fn upgrade_permissionless_tls_transport<TTransport>(
+    transport: TTransport,
+    own_identity: Identity,
+    tls_config: TlsConfig,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error>
+where
+    TTransport: Transport<Error = io::Error> + Send + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    let tls_config = Arc::new(tls_config);
+    transport
+        .with_faults(fault_injector)
+        .and_then(move |socket, origin| {
+            async move {
+                let (remote_signing_key, socket) =
+                    tls_config.upgrade_connection(socket, origin).await?;
+                // As with Noise, the PeerId is derived from the key the remote authenticated with,
+                // its network signing key here, which is 32 bytes in size as well.
+                let peer_id = PeerId::try_from(remote_signing_key).unwrap();
+                Ok((peer_id, socket))
+            }
+        })
+        .and_then(|(peer_id, socket), origin| {
+            async move {
+                let muxer = Yamux::upgrade_connection(socket, origin).await?;
+                Ok((peer_id, muxer))
+            }
+        })
+        .and_then(move |(peer_id, muxer), origin| {
+            async move {
+                let (identity, muxer) = exchange_identity(&own_identity, muxer, origin).await?;
+                match_peer_id(identity, peer_id)
+                    .and_then(|identity| check_role(&own_identity, identity))
+                    .and_then(|identity| Ok((identity, muxer)))
+            }
+        })
+        .with_timeout(TRANSPORT_TIMEOUT)
+        .boxed()
+}
+
+// Upgrades the connections of `transport` with multiplexing and the identity exchange.
+fn upgrade_transport<TTransport>(
+    transport: TTransport,
+    own_identity: Identity,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+) -> boxed::BoxedTransport<(Identity, impl StreamMultiplexer), impl ::std::error::Error>
+where
+    TTransport: Transport<Error = io::Error> + Send + 'static,
+    TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    TTransport::Listener: Send + 'static,
+    TTransport::Inbound: Send + 'static,
+    TTransport::Outbound: Send + 'static,
+{
+    transport
+        .with_faults(fault_injector)
         .and_then(|socket, origin| {
             async move {
                 let muxer = Yamux::upgrade_connection(socket, origin).await?;
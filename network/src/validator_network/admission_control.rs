@@ -4,9 +4,10 @@
 //! Interface between Admission Control and Network layers.
 
 use crate::{
+    counters,
     error::NetworkError,
     interface::{NetworkNotification, NetworkRequest},
-    protocols::rpc::{self, error::RpcError},
+    protocols::rpc::{self, error::RpcError, utils::RpcPolicy},
     validator_network::Event,
     ProtocolId,
 };
@@ -15,18 +16,31 @@ use admission_control_proto::proto::admission_control::{
     SubmitTransactionRequest, SubmitTransactionResponse,
 };
 use channel;
+use config::config::{UpstreamConfig, UpstreamSelection};
 use futures::{
     stream::Map,
     task::{Context, Poll},
     Stream, StreamExt,
 };
+use logger::prelude::*;
 use pin_utils::unsafe_pinned;
 use prost::Message as _;
-use std::{pin::Pin, time::Duration};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use types::PeerId;
 
 /// Protocol id for admission control RPC calls
-pub const ADMISSION_CONTROL_RPC_PROTOCOL: &[u8] = b"/libra/admission_control/rpc/0.1.0";
+pub const ADMISSION_CONTROL_RPC_PROTOCOL: &[u8] = b"/libra/admission_control/rpc/0.2.0";
+/// Protocol id for admission control RPC calls with the nodes which predate the propagation of
+/// rpc deadlines
+pub const LEGACY_ADMISSION_CONTROL_RPC_PROTOCOL: &[u8] = b"/libra/admission_control/rpc/0.1.0";
+
+/// Weight of the latest latency of an upstream peer in its moving average latency
+const UPSTREAM_LATENCY_WEIGHT: f64 = 0.2;
 
 /// The interface from Network to Admission Control layer.
 ///
@@ -59,11 +73,18 @@ impl AdmissionControlNetworkEvents {
             NetworkNotification::NewPeer(peer_id) => Ok(Event::NewPeer(peer_id)),
             NetworkNotification::LostPeer(peer_id) => Ok(Event::LostPeer(peer_id)),
             NetworkNotification::RecvRpc(peer_id, rpc_req) => {
-                let req_msg = AdmissionControlMsg::decode(rpc_req.data.as_ref())?;
-                Ok(Event::RpcRequest((peer_id, req_msg, rpc_req.res_tx)))
+                let req_msg = AdmissionControlMsg::decode(rpc_req.data.as_ref())
+                    .map_err(|err| NetworkError::from(err).with_peer(peer_id))?;
+                Ok(Event::RpcRequest((
+                    peer_id,
+                    req_msg,
+                    rpc_req.res_tx,
+                    rpc_req.deadline,
+                )))
             }
             NetworkNotification::RecvMessage(peer_id, msg) => {
-                let msg = AdmissionControlMsg::decode(msg.mdata.as_ref())?;
+                let msg = AdmissionControlMsg::decode(msg.mdata.as_ref())
+                    .map_err(|err| NetworkError::from(err).with_peer(peer_id))?;
                 Ok(Event::Message((peer_id, msg)))
             }
         });
@@ -132,6 +153,235 @@ impl AdmissionControlNetworkSender {
             Err(RpcError::InvalidRpcResponse)
         }
     }
+
+    /// Same as [`send_transaction_upstream`](Self::send_transaction_upstream), except that the
+    /// request may be sent to several of the `upstream_peers`, as specified by `policy`. Returns
+    /// the peer which responded along with its response.
+    pub async fn send_transaction_upstream_with_policy(
+        &mut self,
+        upstream_peers: Vec<PeerId>,
+        req_msg: SubmitTransactionRequest,
+        deadline: Instant,
+        policy: RpcPolicy,
+    ) -> Result<(PeerId, SubmitTransactionResponse), RpcError> {
+        let protocol = ProtocolId::from_static(ADMISSION_CONTROL_RPC_PROTOCOL);
+        let send_txn_req_msg_enum = AdmissionControlMsg {
+            message: Some(AdmissionControlMsg_oneof::SubmitTransactionRequest(req_msg)),
+        };
+
+        let (peer_id, res_msg_enum) = rpc::utils::unary_rpc_with_policy(
+            self.inner.clone(),
+            upstream_peers,
+            protocol,
+            send_txn_req_msg_enum,
+            deadline,
+            policy,
+        )
+        .await?;
+
+        if let Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(response)) =
+            res_msg_enum.message
+        {
+            Ok((peer_id, response))
+        } else {
+            Err(RpcError::InvalidRpcResponse)
+        }
+    }
+
+    /// Sends a SubmitTransactionRequest RPC request to one of the `upstreams`, failing over to
+    /// the next upstream peer each time a request fails. Each request is given at most `timeout`,
+    /// and is hedged to the next upstream peer as specified by the policy of the `upstreams`.
+    /// Returns the peer which responded along with its response, or the error of the last
+    /// request sent.
+    pub async fn send_transaction_to_upstreams(
+        &mut self,
+        upstreams: &AdmissionControlUpstreams,
+        req_msg: SubmitTransactionRequest,
+        timeout: Duration,
+    ) -> Result<(PeerId, SubmitTransactionResponse), RpcError> {
+        let peers = upstreams.select(Instant::now());
+        if peers.is_empty() {
+            return Err(RpcError::NoPeers);
+        }
+        let mut last_err = RpcError::TimedOut;
+        for attempt in 0..peers.len() {
+            if attempt > 0 {
+                counters::RPC_REQUESTS_RETRIED.inc();
+            }
+            let peer_id = peers[attempt];
+            let start = Instant::now();
+            match self
+                .send_transaction_upstream_with_policy(
+                    peers[attempt..].to_vec(),
+                    req_msg.clone(),
+                    start + timeout,
+                    upstreams.policy.clone(),
+                )
+                .await
+            {
+                // the response may come from the peer the request was hedged to
+                Ok((peer_id, response)) => {
+                    upstreams.on_success(peer_id, start.elapsed());
+                    return Ok((peer_id, response));
+                }
+                Err(err) => {
+                    debug!(
+                        "Failed to send transaction to upstream peer {}: {}",
+                        peer_id, err
+                    );
+                    upstreams.on_failure(peer_id, Instant::now());
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+}
+
+/// Upstream peers of a full node, which the transactions submitted to it are forwarded to by
+/// [`AdmissionControlNetworkSender::send_transaction_to_upstreams`].
+///
+/// Each transaction is sent to one of the usable preferred peers, or of the usable fallback peers
+/// while none of the preferred ones is usable, as chosen by the `submission_selection` of the
+/// config, then to the other upstream peers in turn while the requests fail. A peer is unusable
+/// once its requests failed `failover_threshold` times in a row, until
+/// `failover_retry_interval_ms` later, when it is tried again. Clones share the same health of
+/// the peers.
+#[derive(Clone)]
+pub struct AdmissionControlUpstreams {
+    preferred: Vec<PeerId>,
+    fallback: Vec<PeerId>,
+    selection: UpstreamSelection,
+    failover_threshold: u64,
+    retry_interval: Duration,
+    // how each request is hedged to the next upstream peer
+    policy: RpcPolicy,
+    state: Arc<Mutex<UpstreamsState>>,
+}
+
+#[derive(Default)]
+struct UpstreamsState {
+    // number of round robin selections so far
+    selections: usize,
+    health: HashMap<PeerId, UpstreamHealth>,
+}
+
+/// Health of the requests to an upstream peer
+#[derive(Default)]
+struct UpstreamHealth {
+    consecutive_failures: u64,
+    // when the peer was last failed over, if it was
+    failed_over_at: Option<Instant>,
+    // moving average latency of the successful requests
+    latency: Option<Duration>,
+}
+
+impl UpstreamHealth {
+    fn is_usable(&self, retry_interval: Duration, now: Instant) -> bool {
+        match self.failed_over_at {
+            Some(failed_over_at) => now.duration_since(failed_over_at) >= retry_interval,
+            None => true,
+        }
+    }
+}
+
+impl AdmissionControlUpstreams {
+    pub fn new(config: &UpstreamConfig) -> Self {
+        let (preferred, fallback) = config.get_submission_peers();
+        Self {
+            preferred,
+            fallback,
+            selection: config.submission_selection,
+            failover_threshold: config.failover_threshold,
+            retry_interval: Duration::from_millis(config.failover_retry_interval_ms),
+            policy: match config.submission_hedge_delay_ms {
+                0 => RpcPolicy::NoRetry,
+                delay_ms => RpcPolicy::Hedge {
+                    delay: Duration::from_millis(delay_ms),
+                },
+            },
+            state: Arc::new(Mutex::new(UpstreamsState::default())),
+        }
+    }
+
+    /// Upstream peers to send a transaction to as of `now`, in the order they are to be tried:
+    /// the usable peers of the selected tier, ordered by the selection policy, then all the other
+    /// upstream peers as a last resort.
+    fn select(&self, now: Instant) -> Vec<PeerId> {
+        let mut guard = self.state.lock().expect("[upstreams] acquire lock");
+        let state = &mut *guard;
+        let health = &state.health;
+        let usable = |tier: &[PeerId]| -> Vec<PeerId> {
+            tier.iter()
+                .filter(|peer_id| {
+                    health
+                        .get(peer_id)
+                        .map_or(true, |health| health.is_usable(self.retry_interval, now))
+                })
+                .cloned()
+                .collect()
+        };
+        let mut selected = usable(&self.preferred);
+        if selected.is_empty() {
+            selected = usable(&self.fallback);
+        }
+        match self.selection {
+            UpstreamSelection::RoundRobin => {
+                if !selected.is_empty() {
+                    selected.rotate_left(state.selections % selected.len());
+                }
+                state.selections = state.selections.wrapping_add(1);
+            }
+            // the peers never heard from yet come first, to learn their latency
+            UpstreamSelection::LowestLatency => selected.sort_by_key(|peer_id| {
+                health
+                    .get(peer_id)
+                    .and_then(|health| health.latency)
+                    .unwrap_or_default()
+            }),
+        }
+        let last_resort: Vec<PeerId> = self
+            .preferred
+            .iter()
+            .chain(self.fallback.iter())
+            .filter(|peer_id| !selected.contains(peer_id))
+            .cloned()
+            .collect();
+        selected.extend(last_resort);
+        selected
+    }
+
+    fn on_success(&self, peer_id: PeerId, latency: Duration) {
+        let mut state = self.state.lock().expect("[upstreams] acquire lock");
+        let health = state.health.entry(peer_id).or_default();
+        health.consecutive_failures = 0;
+        health.failed_over_at = None;
+        health.latency = Some(match health.latency {
+            Some(average) => {
+                average.mul_f64(1.0 - UPSTREAM_LATENCY_WEIGHT)
+                    + latency.mul_f64(UPSTREAM_LATENCY_WEIGHT)
+            }
+            None => latency,
+        });
+    }
+
+    /// Fails the peer over once its requests failed `failover_threshold` times in a row. A peer
+    /// which is tried again after its retry interval is failed over again by its next failure
+    fn on_failure(&self, peer_id: PeerId, now: Instant) {
+        let mut state = self.state.lock().expect("[upstreams] acquire lock");
+        let health = state.health.entry(peer_id).or_default();
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        if health.consecutive_failures >= self.failover_threshold {
+            if health.failed_over_at.is_none() {
+                counters::UPSTREAM_FAILOVERS.inc();
+                warn!(
+                    "Failing over upstream peer {} after {} failed transaction submissions",
+                    peer_id, health.consecutive_failures
+                );
+            }
+            health.failed_over_at = Some(now);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -139,7 +389,29 @@ mod tests {
     use super::*;
     use crate::protocols::rpc::InboundRpcRequest;
     use crate::utils::MessageExt;
-    use futures::{channel::oneshot, executor::block_on, future::try_join, SinkExt};
+    use futures::{
+        channel::oneshot,
+        executor::block_on,
+        future::{join, try_join},
+        FutureExt, SinkExt, TryFutureExt,
+    };
+    use tokio::runtime::Runtime;
+
+    fn upstreams(
+        preferred: &[PeerId],
+        fallback: &[PeerId],
+        selection: UpstreamSelection,
+    ) -> AdmissionControlUpstreams {
+        let to_strings = |peers: &[PeerId]| peers.iter().map(PeerId::to_string).collect();
+        AdmissionControlUpstreams::new(&UpstreamConfig {
+            preferred_peers: to_strings(preferred),
+            fallback_peers: to_strings(fallback),
+            failover_threshold: 1,
+            failover_retry_interval_ms: 1_000,
+            submission_selection: selection,
+            ..UpstreamConfig::default()
+        })
+    }
 
     // `AdmissionControlNetworkEvents` should deserialize inbound RPC requests
     #[test]
@@ -156,10 +428,12 @@ mod tests {
         let req_data = req_msg_enum.clone().to_bytes().unwrap();
 
         let (res_tx, _) = oneshot::channel();
+        let deadline = Instant::now() + Duration::from_secs(5);
         let rpc_req = InboundRpcRequest {
             protocol: ProtocolId::from_static(ADMISSION_CONTROL_RPC_PROTOCOL),
             data: req_data,
             res_tx,
+            deadline,
         };
 
         // mock receiving rpc request
@@ -169,7 +443,7 @@ mod tests {
 
         // request should be properly deserialized
         let (res_tx, _) = oneshot::channel();
-        let expected_event = Event::RpcRequest((peer_id, req_msg_enum.clone(), res_tx));
+        let expected_event = Event::RpcRequest((peer_id, req_msg_enum.clone(), res_tx, deadline));
         let event = block_on(stream.next()).unwrap().unwrap();
         assert_eq!(event, expected_event);
     }
@@ -222,4 +496,180 @@ mod tests {
         let (recv_res_msg, _) = block_on(try_join(f_res_msg, f_recv)).unwrap();
         assert_eq!(recv_res_msg, res_msg);
     }
+
+    // Round robin spreads the transactions across the usable preferred peers, and fails over to
+    // the fallback peers while none of them is usable.
+    #[test]
+    fn test_upstreams_round_robin() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let upstreams = upstreams(&[a, b], &[c], UpstreamSelection::RoundRobin);
+        let now = Instant::now();
+
+        assert_eq!(upstreams.select(now), vec![a, b, c]);
+        assert_eq!(upstreams.select(now), vec![b, a, c]);
+        assert_eq!(upstreams.select(now), vec![a, b, c]);
+
+        // failed over peers are only tried as a last resort
+        upstreams.on_failure(a, now);
+        assert_eq!(upstreams.select(now), vec![b, a, c]);
+        upstreams.on_failure(b, now);
+        assert_eq!(upstreams.select(now), vec![c, a, b]);
+
+        // until their retry interval passed
+        let later = now + Duration::from_secs(1);
+        assert_eq!(upstreams.select(later), vec![b, a, c]);
+        upstreams.on_success(a, Duration::from_millis(10));
+        assert_eq!(upstreams.select(now), vec![a, b, c]);
+    }
+
+    // The fastest peers are tried first, the ones never heard from yet before any other.
+    #[test]
+    fn test_upstreams_lowest_latency() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let upstreams = upstreams(&[a, b, c], &[], UpstreamSelection::LowestLatency);
+        let now = Instant::now();
+
+        upstreams.on_success(a, Duration::from_millis(100));
+        upstreams.on_success(b, Duration::from_millis(10));
+        assert_eq!(upstreams.select(now), vec![c, b, a]);
+        upstreams.on_success(c, Duration::from_millis(50));
+        assert_eq!(upstreams.select(now), vec![b, c, a]);
+
+        // the latencies are averaged over the requests
+        upstreams.on_success(b, Duration::from_millis(500));
+        assert_eq!(upstreams.select(now), vec![c, a, b]);
+        upstreams.on_failure(c, now);
+        assert_eq!(upstreams.select(now), vec![a, b, c]);
+    }
+
+    // A transaction sent upstream should be sent again to the next upstream peer when the request
+    // to the first one fails.
+    #[test]
+    fn test_admission_control_upstream_failover() {
+        let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+        let mut sender = AdmissionControlNetworkSender::new(network_reqs_tx);
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let upstreams = upstreams(&[a, b], &[], UpstreamSelection::RoundRobin);
+
+        let req_msg = SubmitTransactionRequest::default();
+        let f_res_msg = sender.send_transaction_to_upstreams(
+            &upstreams,
+            req_msg.clone(),
+            Duration::from_secs(5),
+        );
+
+        let res_msg = SubmitTransactionResponse::default();
+        let res_msg_enum = AdmissionControlMsg {
+            message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
+                res_msg.clone(),
+            )),
+        };
+        let res_data = res_msg_enum.to_bytes().unwrap();
+
+        let f_recv = async move {
+            // the first upstream peer is unreachable
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(recv_peer_id, req) => {
+                    assert_eq!(recv_peer_id, a);
+                    req.res_tx.send(Err(RpcError::NotConnected(a))).unwrap();
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            }
+            // the second one responds
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(recv_peer_id, req) => {
+                    assert_eq!(recv_peer_id, b);
+                    req.res_tx.send(Ok(res_data)).unwrap();
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            }
+            Ok(())
+        };
+
+        let ((peer_id, recv_res_msg), _) = block_on(try_join(f_res_msg, f_recv)).unwrap();
+        assert_eq!(peer_id, b);
+        assert_eq!(recv_res_msg, res_msg);
+        // the unreachable peer is failed over
+        assert_eq!(upstreams.select(Instant::now()), vec![b, a]);
+    }
+
+    // A transaction is also sent to the next upstream peer once the first one is slow to respond,
+    // which is not failed over for it.
+    #[test]
+    fn test_admission_control_upstream_hedge() {
+        let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+        let mut sender = AdmissionControlNetworkSender::new(network_reqs_tx);
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let upstreams = AdmissionControlUpstreams::new(&UpstreamConfig {
+            preferred_peers: vec![a.to_string(), b.to_string()],
+            failover_threshold: 1,
+            submission_selection: UpstreamSelection::LowestLatency,
+            submission_hedge_delay_ms: 50,
+            ..UpstreamConfig::default()
+        });
+
+        let res_msg = SubmitTransactionResponse::default();
+        let res_msg_enum = AdmissionControlMsg {
+            message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
+                res_msg.clone(),
+            )),
+        };
+        let res_data = res_msg_enum.to_bytes().unwrap();
+
+        let f_recv = async move {
+            // the first upstream peer hangs
+            let slow_req = match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(recv_peer_id, req) => {
+                    assert_eq!(recv_peer_id, a);
+                    req
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            };
+            // the second one responds
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(recv_peer_id, req) => {
+                    assert_eq!(recv_peer_id, b);
+                    req.res_tx.send(Ok(res_data)).unwrap();
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            }
+            slow_req
+        };
+
+        let f = async move {
+            let f_res_msg = sender.send_transaction_to_upstreams(
+                &upstreams,
+                SubmitTransactionRequest::default(),
+                Duration::from_secs(5),
+            );
+            let (res, _slow_req) = join(f_res_msg, f_recv).await;
+            let (peer_id, recv_res_msg) = res.unwrap();
+            assert_eq!(peer_id, b);
+            assert_eq!(recv_res_msg, res_msg);
+            // the slow peer, never heard from, is still tried first
+            assert_eq!(upstreams.select(Instant::now()), vec![a, b]);
+        };
+        Runtime::new()
+            .unwrap()
+            .block_on(f.boxed().unit_error().compat())
+            .unwrap();
+    }
+
+    // A transaction can't be sent upstream without upstream peers.
+    #[test]
+    fn test_admission_control_no_upstream() {
+        let (network_reqs_tx, _network_reqs_rx) = channel::new_test(8);
+        let mut sender = AdmissionControlNetworkSender::new(network_reqs_tx);
+        let upstreams = upstreams(&[], &[], UpstreamSelection::RoundRobin);
+
+        let res = block_on(sender.send_transaction_to_upstreams(
+            &upstreams,
+            SubmitTransactionRequest::default(),
+            Duration::from_secs(5),
+        ));
+        assert!(match res {
+            Err(RpcError::NoPeers) => true,
+            _ => false,
+        });
+    }
 }
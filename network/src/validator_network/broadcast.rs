@@ -0,0 +1,233 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Broadcast of a message to a set of validators, tracking which of them acknowledged it.
+//!
+//! The message is sent to each validator as an rpc request over the given protocol; a successful
+//! response, whatever its content, acknowledges the message. Requests which fail or time out are
+//! retried, up to the number of attempts of the [`BroadcastPolicy`]. Peers which are not connected
+//! yet, e.g. right after startup, are given time to connect without using up their attempts.
+
+use crate::{
+    counters,
+    interface::NetworkRequest,
+    protocols::rpc::{error::RpcError, utils::send_rpc},
+    utils::MessageExt,
+    ProtocolId,
+};
+use bytes::Bytes;
+use futures::{
+    compat::Future01CompatExt,
+    future::{BoxFuture, FutureExt},
+    stream::{FuturesUnordered, StreamExt},
+};
+use logger::prelude::*;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::timer;
+use types::PeerId;
+
+/// How a [`Broadcast`] delivers its message, and how many acknowledgements make a quorum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BroadcastPolicy {
+    /// Fraction of the peers, in `(0, 1]`, whose acknowledgements make a quorum.
+    pub quorum_fraction: f64,
+    /// Time given to each peer to acknowledge the message, for each attempt.
+    pub attempt_timeout: Duration,
+    /// Number of times the message is sent to a peer before giving up on it.
+    pub max_attempts: usize,
+    /// Time to wait after a failed attempt before sending the message to the peer again.
+    pub retry_delay: Duration,
+    /// Time since the start of the broadcast during which a peer not connected yet is waited for,
+    /// instead of failing an attempt.
+    pub connect_timeout: Duration,
+}
+
+impl Default for BroadcastPolicy {
+    fn default() -> Self {
+        Self {
+            quorum_fraction: 2.0 / 3.0,
+            attempt_timeout: Duration::from_secs(1),
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(200),
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Delivery of the message of a [`Broadcast`] to one of its peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// The message has not been acknowledged yet, and is still being sent.
+    Pending,
+    /// The peer acknowledged the message.
+    Acked,
+    /// The peer did not acknowledge any of the attempts to send it the message.
+    Failed,
+}
+
+/// A message being sent to a set of peers.
+///
+/// Deliveries only make progress while [`quorum`](Broadcast::quorum) or
+/// [`complete`](Broadcast::complete) is being awaited; dropping the broadcast cancels the pending
+/// ones.
+pub struct Broadcast {
+    deliveries: FuturesUnordered<BoxFuture<'static, (PeerId, Result<(), RpcError>)>>,
+    statuses: HashMap<PeerId, DeliveryStatus>,
+    quorum_size: usize,
+}
+
+impl Broadcast {
+    /// Starts sending `msg` over `protocol` to each of `peers`.
+    ///
+    /// Panics if `policy.quorum_fraction` is not in `(0, 1]`.
+    pub fn new<T: prost::Message>(
+        inner: channel::Sender<NetworkRequest>,
+        peers: Vec<PeerId>,
+        protocol: ProtocolId,
+        msg: &T,
+        policy: BroadcastPolicy,
+    ) -> Result<Self, RpcError> {
+        assert!(
+            policy.quorum_fraction > 0.0 && policy.quorum_fraction <= 1.0,
+            "Invalid quorum fraction {}",
+            policy.quorum_fraction
+        );
+        let data = msg.to_bytes()?;
+        let statuses: HashMap<_, _> = peers
+            .into_iter()
+            .map(|peer_id| (peer_id, DeliveryStatus::Pending))
+            .collect();
+        let deliveries = statuses
+            .keys()
+            .map(|peer_id| {
+                deliver(
+                    inner.clone(),
+                    *peer_id,
+                    protocol.clone(),
+                    data.clone(),
+                    policy.clone(),
+                )
+                .boxed()
+            })
+            .collect();
+        let quorum_size = (statuses.len() as f64 * policy.quorum_fraction).ceil() as usize;
+        Ok(Self {
+            deliveries,
+            statuses,
+            quorum_size,
+        })
+    }
+
+    /// The delivery status of the message for each peer.
+    pub fn statuses(&self) -> &HashMap<PeerId, DeliveryStatus> {
+        &self.statuses
+    }
+
+    /// Number of peers which acknowledged the message.
+    pub fn num_acked(&self) -> usize {
+        self.count(DeliveryStatus::Acked)
+    }
+
+    /// Number of acknowledgements which make a quorum.
+    pub fn quorum_size(&self) -> usize {
+        self.quorum_size
+    }
+
+    /// Resolves to `true` once a quorum of the peers acknowledged the message, or to `false` once
+    /// too many of them failed to for a quorum to be reached.
+    pub async fn quorum(&mut self) -> bool {
+        loop {
+            if self.num_acked() >= self.quorum_size {
+                return true;
+            }
+            if self.num_acked() + self.count(DeliveryStatus::Pending) < self.quorum_size {
+                return false;
+            }
+            match self.deliveries.next().await {
+                Some((peer_id, res)) => self.record(peer_id, res),
+                None => return false,
+            }
+        }
+    }
+
+    /// Resolves once the delivery to each of the peers either succeeded or failed.
+    pub async fn complete(&mut self) {
+        while let Some((peer_id, res)) = self.deliveries.next().await {
+            self.record(peer_id, res);
+        }
+    }
+
+    fn count(&self, status: DeliveryStatus) -> usize {
+        self.statuses.values().filter(|s| **s == status).count()
+    }
+
+    fn record(&mut self, peer_id: PeerId, res: Result<(), RpcError>) {
+        let status = match res {
+            Ok(()) => DeliveryStatus::Acked,
+            Err(e) => {
+                warn!(
+                    "Peer {} did not acknowledge broadcast message: {}",
+                    peer_id.short_str(),
+                    e
+                );
+                counters::BROADCAST_DELIVERIES_FAILED.inc();
+                DeliveryStatus::Failed
+            }
+        };
+        self.statuses.insert(peer_id, status);
+    }
+}
+
+// Sends the message to the peer until it acknowledges it, or `policy.max_attempts` attempts failed.
+async fn deliver(
+    inner: channel::Sender<NetworkRequest>,
+    peer_id: PeerId,
+    protocol: ProtocolId,
+    data: Bytes,
+    policy: BroadcastPolicy,
+) -> (PeerId, Result<(), RpcError>) {
+    let connect_deadline = Instant::now() + policy.connect_timeout;
+    let mut attempt = 1;
+    loop {
+        match send_rpc(
+            inner.clone(),
+            peer_id,
+            protocol.clone(),
+            data.clone(),
+            policy.attempt_timeout,
+        )
+        .await
+        {
+            Ok(_) => return (peer_id, Ok(())),
+            Err(RpcError::NotConnected(_)) if Instant::now() < connect_deadline => {
+                // The connection may not be established yet, which doesn't count as an attempt.
+                if let Err(e) = timer::Delay::new(Instant::now() + policy.retry_delay)
+                    .compat()
+                    .await
+                {
+                    return (peer_id, Err(RpcError::TimerError(e)));
+                }
+            }
+            Err(e) if attempt >= policy.max_attempts => return (peer_id, Err(e)),
+            Err(e) => {
+                debug!(
+                    "Attempt {} to broadcast message to peer {} failed: {}",
+                    attempt,
+                    peer_id.short_str(),
+                    e
+                );
+                attempt += 1;
+                counters::BROADCAST_DELIVERIES_RETRIED.inc();
+                if let Err(e) = timer::Delay::new(Instant::now() + policy.retry_delay)
+                    .compat()
+                    .await
+                {
+                    return (peer_id, Err(RpcError::TimerError(e)));
+                }
+            }
+        }
+    }
+}
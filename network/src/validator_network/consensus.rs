@@ -12,7 +12,7 @@ use crate::{
         rpc::{self, error::RpcError},
     },
     utils::MessageExt,
-    validator_network::Event,
+    validator_network::{Broadcast, BroadcastPolicy, Event},
     NetworkPublicKeys, ProtocolId,
 };
 use channel;
@@ -23,11 +23,17 @@ use futures::{
 };
 use pin_utils::unsafe_pinned;
 use prost::Message as _;
-use std::{pin::Pin, time::Duration};
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
 use types::{validator_public_keys::ValidatorPublicKeys, PeerId};
 
 /// Protocol id for consensus RPC calls
-pub const CONSENSUS_RPC_PROTOCOL: &[u8] = b"/libra/consensus/rpc/0.1.0";
+pub const CONSENSUS_RPC_PROTOCOL: &[u8] = b"/libra/consensus/rpc/0.2.0";
+/// Protocol id for consensus RPC calls with the nodes which predate the propagation of rpc
+/// deadlines
+pub const LEGACY_CONSENSUS_RPC_PROTOCOL: &[u8] = b"/libra/consensus/rpc/0.1.0";
 /// Protocol id for consensus direct-send calls
 pub const CONSENSUS_DIRECT_SEND_PROTOCOL: &[u8] = b"/libra/consensus/direct-send/0.1.0";
 
@@ -62,11 +68,18 @@ impl ConsensusNetworkEvents {
             NetworkNotification::NewPeer(peer_id) => Ok(Event::NewPeer(peer_id)),
             NetworkNotification::LostPeer(peer_id) => Ok(Event::LostPeer(peer_id)),
             NetworkNotification::RecvRpc(peer_id, rpc_req) => {
-                let req_msg = ConsensusMsg::decode(rpc_req.data.as_ref())?;
-                Ok(Event::RpcRequest((peer_id, req_msg, rpc_req.res_tx)))
+                let req_msg = ConsensusMsg::decode(rpc_req.data.as_ref())
+                    .map_err(|err| NetworkError::from(err).with_peer(peer_id))?;
+                Ok(Event::RpcRequest((
+                    peer_id,
+                    req_msg,
+                    rpc_req.res_tx,
+                    rpc_req.deadline,
+                )))
             }
             NetworkNotification::RecvMessage(peer_id, msg) => {
-                let msg = ConsensusMsg::decode(msg.mdata.as_ref())?;
+                let msg = ConsensusMsg::decode(msg.mdata.as_ref())
+                    .map_err(|err| NetworkError::from(err).with_peer(peer_id))?;
                 Ok(Event::Message((peer_id, msg)))
             }
         });
@@ -110,6 +123,17 @@ impl ConsensusNetworkSender {
         &mut self,
         recipient: PeerId,
         message: ConsensusMsg,
+    ) -> Result<(), NetworkError> {
+        self.send_to_with_ttl(recipient, message, None).await
+    }
+
+    /// Same as [`send_to`](Self::send_to), except that the message is dropped instead of being
+    /// sent if it is still queued once `ttl` has elapsed.
+    pub async fn send_to_with_ttl(
+        &mut self,
+        recipient: PeerId,
+        message: ConsensusMsg,
+        ttl: Option<Duration>,
     ) -> Result<(), NetworkError> {
         self.inner
             .send(NetworkRequest::SendMessage(
@@ -118,8 +142,10 @@ impl ConsensusNetworkSender {
                     protocol: ProtocolId::from_static(CONSENSUS_DIRECT_SEND_PROTOCOL),
                     mdata: message.to_bytes().unwrap(),
                 },
+                ttl.map(|ttl| Instant::now() + ttl),
             ))
-            .await?;
+            .await
+            .map_err(|err| NetworkError::from(err).with_peer(recipient))?;
         Ok(())
     }
 
@@ -155,6 +181,23 @@ impl ConsensusNetworkSender {
         }
     }
 
+    /// Starts broadcasting `message` to `recipients` over the consensus rpc protocol, each of
+    /// them acknowledging it with a response. See [`Broadcast`].
+    pub fn broadcast(
+        &self,
+        recipients: Vec<PeerId>,
+        message: &ConsensusMsg,
+        policy: BroadcastPolicy,
+    ) -> Result<Broadcast, RpcError> {
+        Broadcast::new(
+            self.inner.clone(),
+            recipients,
+            ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL),
+            message,
+            policy,
+        )
+    }
+
     pub async fn update_eligible_nodes(
         &mut self,
         validators: Vec<ValidatorPublicKeys>,
@@ -247,7 +290,7 @@ mod tests {
         // Network layer should receive serialized message to send out
         let event = block_on(network_reqs_rx.next()).unwrap();
         match event {
-            NetworkRequest::SendMessage(recv_peer_id, network_msg) => {
+            NetworkRequest::SendMessage(recv_peer_id, network_msg, _) => {
                 assert_eq!(recv_peer_id, peer_id);
                 assert_eq!(network_msg, expected_network_msg);
             }
@@ -269,10 +312,12 @@ mod tests {
         let req_data = req_msg_enum.clone().to_bytes().unwrap();
 
         let (res_tx, _) = oneshot::channel();
+        let deadline = Instant::now() + Duration::from_secs(5);
         let rpc_req = InboundRpcRequest {
             protocol: ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL),
             data: req_data,
             res_tx,
+            deadline,
         };
 
         // mock receiving rpc request
@@ -282,7 +327,7 @@ mod tests {
 
         // request should be properly deserialized
         let (res_tx, _) = oneshot::channel();
-        let expected_event = Event::RpcRequest((peer_id, req_msg_enum.clone(), res_tx));
+        let expected_event = Event::RpcRequest((peer_id, req_msg_enum.clone(), res_tx, deadline));
         let event = block_on(stream.next()).unwrap().unwrap();
         assert_eq!(event, expected_event);
     }
@@ -20,7 +20,10 @@ use futures::{
 };
 use pin_utils::unsafe_pinned;
 use prost::Message as proto_msg;
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
 use types::PeerId;
 
 /// Protocol id for mempool direct-send calls
@@ -64,7 +67,8 @@ impl MempoolNetworkEvents {
                     unimplemented!("Mempool does not currently use RPC");
                 }
                 NetworkNotification::RecvMessage(peer_id, msg) => {
-                    let msg = MempoolSyncMsg::decode(msg.mdata.as_ref())?;
+                    let msg = MempoolSyncMsg::decode(msg.mdata.as_ref())
+                        .map_err(|err| NetworkError::from(err).with_peer(peer_id))?;
                     Ok(Event::Message((peer_id, msg)))
                 }
             });
@@ -109,6 +113,17 @@ impl MempoolNetworkSender {
         &mut self,
         recipient: PeerId,
         message: MempoolSyncMsg,
+    ) -> Result<(), NetworkError> {
+        self.send_to_with_ttl(recipient, message, None).await
+    }
+
+    /// Same as [`send_to`](Self::send_to), except that the message is dropped instead of being
+    /// sent if it is still queued once `ttl` has elapsed.
+    pub async fn send_to_with_ttl(
+        &mut self,
+        recipient: PeerId,
+        message: MempoolSyncMsg,
+        ttl: Option<Duration>,
     ) -> Result<(), NetworkError> {
         self.inner
             .send(NetworkRequest::SendMessage(
@@ -117,8 +132,10 @@ impl MempoolNetworkSender {
                     protocol: ProtocolId::from_static(MEMPOOL_DIRECT_SEND_PROTOCOL),
                     mdata: message.to_bytes().unwrap(),
                 },
+                ttl.map(|ttl| Instant::now() + ttl),
             ))
-            .await?;
+            .await
+            .map_err(|err| NetworkError::from(err).with_peer(recipient))?;
         Ok(())
     }
 }
@@ -176,7 +193,7 @@ mod tests {
         // Network layer should receive serialized message to send out
         let event = block_on(network_reqs_rx.next()).unwrap();
         match event {
-            NetworkRequest::SendMessage(recv_peer_id, network_msg) => {
+            NetworkRequest::SendMessage(recv_peer_id, network_msg, _) => {
                 assert_eq!(recv_peer_id, peer_id);
                 assert_eq!(network_msg, expected_network_msg);
             }
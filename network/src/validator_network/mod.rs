@@ -3,15 +3,18 @@
 
 //! Network API for [`Consensus`](/consensus/index.html) and [`Mempool`](/mempool/index.html)
 
-pub use crate::protocols::rpc::error::RpcError;
+pub use crate::protocols::rpc::{error::RpcError, utils::RpcPolicy};
 use bytes::Bytes;
 use futures::channel::oneshot;
+use std::time::Instant;
 
 pub mod network_builder;
 
 mod admission_control;
+mod broadcast;
 mod consensus;
 mod mempool;
+mod protocol_handler;
 mod state_synchronizer;
 #[cfg(test)]
 mod test;
@@ -19,18 +22,34 @@ mod test;
 // Public re-exports
 pub use crate::interface::LibraNetworkProvider;
 pub use admission_control::{
-    AdmissionControlNetworkEvents, AdmissionControlNetworkSender, ADMISSION_CONTROL_RPC_PROTOCOL,
+    AdmissionControlNetworkEvents, AdmissionControlNetworkSender, AdmissionControlUpstreams,
+    ADMISSION_CONTROL_RPC_PROTOCOL, LEGACY_ADMISSION_CONTROL_RPC_PROTOCOL,
 };
+pub use broadcast::{Broadcast, BroadcastPolicy, DeliveryStatus};
 pub use consensus::{
     ConsensusNetworkEvents, ConsensusNetworkSender, CONSENSUS_DIRECT_SEND_PROTOCOL,
-    CONSENSUS_RPC_PROTOCOL,
+    CONSENSUS_RPC_PROTOCOL, LEGACY_CONSENSUS_RPC_PROTOCOL,
 };
 pub use mempool::{MempoolNetworkEvents, MempoolNetworkSender, MEMPOOL_DIRECT_SEND_PROTOCOL};
+pub use protocol_handler::{NetworkEvents, NetworkSender};
 pub use state_synchronizer::{
-    StateSynchronizerEvents, StateSynchronizerSender, STATE_SYNCHRONIZER_MSG_PROTOCOL,
+    RawChunkResponse, StateSynchronizerEvents, StateSynchronizerInboundMsg,
+    StateSynchronizerSender, STATE_SYNCHRONIZER_MSG_PROTOCOL, STATE_SYNCHRONIZER_RPC_PROTOCOL,
 };
 use types::PeerId;
 
+/// The versions of the rpc protocols spoken by the nodes which predate the propagation of rpc
+/// deadlines, along with the current version of each. Their requests are not prefixed with the
+/// dialer's timeout. They are still supported, and negotiated with the peers which do not support
+/// the current version.
+pub const LEGACY_RPC_PROTOCOLS: &[(&[u8], &[u8])] = &[
+    (LEGACY_CONSENSUS_RPC_PROTOCOL, CONSENSUS_RPC_PROTOCOL),
+    (
+        LEGACY_ADMISSION_CONTROL_RPC_PROTOCOL,
+        ADMISSION_CONTROL_RPC_PROTOCOL,
+    ),
+];
+
 /// Events received by network clients in a validator
 ///
 /// An enumeration of the various types of messages that the network will be sending
@@ -46,8 +65,17 @@ pub enum Event<TMessage> {
     Message((PeerId, TMessage)),
     /// New inbound rpc request. The request is fulfilled by sending the
     /// serialized response `Bytes` over the `onshot::Sender`, where the network
-    /// layer will handle sending the response over-the-wire.
-    RpcRequest((PeerId, TMessage, oneshot::Sender<Result<Bytes, RpcError>>)),
+    /// layer will handle sending the response over-the-wire. The `Instant` is
+    /// the deadline after which the caller has given up on the request; once it
+    /// passes, the `oneshot::Sender` is canceled and any response is dropped.
+    RpcRequest(
+        (
+            PeerId,
+            TMessage,
+            oneshot::Sender<Result<Bytes, RpcError>>,
+            Instant,
+        ),
+    ),
     /// Peer which we have a newly established connection with.
     NewPeer(PeerId),
     /// Peer with which we've lost our connection.
@@ -60,8 +88,8 @@ impl<TMessage: PartialEq> PartialEq for Event<TMessage> {
         use Event::*;
         match (self, other) {
             (Message((pid1, msg1)), Message((pid2, msg2))) => pid1 == pid2 && msg1 == msg2,
-            // ignore oneshot::Sender in comparison
-            (RpcRequest((pid1, msg1, _)), RpcRequest((pid2, msg2, _))) => {
+            // ignore oneshot::Sender and deadline in comparison
+            (RpcRequest((pid1, msg1, _, _)), RpcRequest((pid2, msg2, _, _))) => {
                 pid1 == pid2 && msg1 == msg2
             }
             (NewPeer(pid1), NewPeer(pid2)) => pid1 == pid2,
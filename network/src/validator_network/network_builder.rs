@@ -11,38 +11,58 @@
 //! set.
 use crate::{
     common::NetworkPublicKeys,
-    connectivity_manager::ConnectivityManager,
+    connectivity_manager::{self, ConnectivityManager, DialStats},
     counters,
     interface::{LibraNetworkProvider, NetworkProvider},
-    peer_manager::{PeerManager, PeerManagerRequestSender},
+    peer_manager::{PeerManager, PeerManagerRequestSender, PeerMetadataStore, GOAWAY_PROTOCOL},
+    peer_store::PeerStore,
     proto::PeerInfo,
     protocols::{
-        direct_send::DirectSend,
+        direct_send::{BatchConfig, DirectSend},
         discovery::{Discovery, DISCOVERY_PROTOCOL_NAME},
         health_checker::{HealthChecker, PING_PROTOCOL_NAME},
         identity::Identity,
         rpc::Rpc,
     },
+    relay::{self, Relay},
+    shared_listener::NetworkTransport,
     transport::*,
+    validator_network::LEGACY_RPC_PROTOCOLS,
     ProtocolId,
 };
 use channel;
-use config::config::RoleType;
+use config::config::{
+    OutboundConnectionsConfig, PeerQueueConfig, RoleType, SocketConfig, UpstreamChannelConfig,
+    DIRECT_SEND_BATCH_WINDOW_MS, DIRECT_SEND_MAX_BATCH_BYTES,
+};
 use crypto::{
     ed25519::*,
     x25519::{X25519StaticPrivateKey, X25519StaticPublicKey},
 };
-use futures::{compat::Compat01As03, FutureExt, StreamExt, TryFutureExt};
+use futures::{
+    compat::Compat01As03,
+    io::{AsyncRead, AsyncWrite},
+    StreamExt,
+};
 use logger::prelude::*;
-use netcore::{multiplexing::StreamMultiplexer, transport::boxed::BoxedTransport};
+use netcore::{
+    multiplexing::StreamMultiplexer,
+    transport::{
+        boxed::BoxedTransport, fault::FaultInjector, memory::MemoryTransport, tcp::TcpTransport,
+        Transport,
+    },
+};
 use parity_multiaddr::Multiaddr;
 use std::{
     collections::HashMap,
+    iter,
     sync::{Arc, RwLock},
     time::Duration,
 };
+use task_manager::TaskManager;
+use tls::TlsConfig;
 use tokio::runtime::TaskExecutor;
-use tokio_retry::strategy::ExponentialBackoff;
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_timer::Interval;
 use types::{validator_signer::ValidatorSigner, PeerId};
 
@@ -51,6 +71,7 @@ pub const DISCOVERY_INTERVAL_MS: u64 = 1000;
 pub const PING_INTERVAL_MS: u64 = 1000;
 pub const PING_TIMEOUT_MS: u64 = 10_000;
 pub const DISOVERY_MSG_TIMEOUT_MS: u64 = 10_000;
+pub const DISCOVERY_NOTE_TTL_MS: u64 = 60 * 60 * 1000 /* 1 hour */;
 pub const CONNECTIVITY_CHECK_INTERNAL_MS: u64 = 5000;
 pub const INBOUND_RPC_TIMEOUT_MS: u64 = 10_000;
 pub const MAX_CONCURRENT_OUTBOUND_RPCS: u32 = 100;
@@ -59,9 +80,11 @@ pub const PING_FAILURES_TOLERATED: u64 = 10;
 pub const MAX_CONCURRENT_NETWORK_REQS: u32 = 100;
 pub const MAX_CONCURRENT_NETWORK_NOTIFS: u32 = 100;
 pub const MAX_CONNECTION_DELAY_MS: u64 = 10 * 60 * 1000 /* 10 minutes */;
+pub const MAX_CONCURRENT_DIALS: usize = 16;
+pub const DIAL_STAGGER_MS: u64 = 250;
 
 /// The type of the transport layer, i.e., running on memory or TCP stream,
-/// with or without Noise encryption
+/// with or without Noise or TLS encryption
 pub enum TransportType {
     Memory,
     MemoryNoise(Option<(X25519StaticPrivateKey, X25519StaticPublicKey)>),
@@ -69,6 +92,8 @@ pub enum TransportType {
     Tcp,
     TcpNoise(Option<(X25519StaticPrivateKey, X25519StaticPublicKey)>),
     PermissionlessTcpNoise(Option<(X25519StaticPrivateKey, X25519StaticPublicKey)>),
+    TcpTls(Option<TlsConfig>),
+    PermissionlessTcpTls(Option<TlsConfig>),
 }
 
 /// Build Network module with custom configuration values.
@@ -77,7 +102,8 @@ pub enum TransportType {
 /// [`NetworkBuilder::build`].  New instances of `NetworkBuilder` are obtained
 /// via [`NetworkBuilder::new`].
 pub struct NetworkBuilder {
-    executor: TaskExecutor,
+    // Spawns the actors of the network and their tasks, under their names.
+    task_manager: TaskManager,
     peer_id: PeerId,
     addr: Multiaddr,
     role: RoleType,
@@ -88,8 +114,15 @@ pub struct NetworkBuilder {
     channel_size: usize,
     direct_send_protocols: Vec<ProtocolId>,
     rpc_protocols: Vec<ProtocolId>,
+    // Protocols of applications which are not built into Libra, registered with
+    // `add_protocol_handler` and `add_rpc_protocol_handler`.
+    handler_direct_send_protocols: Vec<ProtocolId>,
+    handler_rpc_protocols: Vec<ProtocolId>,
+    // Size of the queue of events of each of these protocols.
+    handler_queue_sizes: HashMap<ProtocolId, usize>,
     discovery_interval_ms: u64,
     discovery_msg_timeout_ms: u64,
+    discovery_note_ttl_ms: u64,
     ping_interval_ms: u64,
     ping_timeout_ms: u64,
     ping_failures_tolerated: u64,
@@ -100,8 +133,29 @@ pub struct NetworkBuilder {
     max_concurrent_network_reqs: u32,
     max_concurrent_network_notifs: u32,
     max_connection_delay_ms: u64,
+    max_concurrent_dials: usize,
+    dial_stagger_ms: u64,
+    direct_send_batch_window_ms: u64,
+    direct_send_max_batch_bytes: usize,
+    relay_listen_address: Option<Multiaddr>,
+    relays: Vec<Multiaddr>,
+    outbound_connections: OutboundConnectionsConfig,
+    max_inbound_per_subnet: Option<usize>,
+    peer_queue: PeerQueueConfig,
+    socket: SocketConfig,
+    shared_listener: Option<NetworkTransport<TcpTransport>>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+    peer_store: Option<PeerStore>,
     signing_keys: Option<(Ed25519PrivateKey, Ed25519PublicKey)>,
     is_permissioned: bool,
+    software_version: String,
+    chain_id: String,
+    // Label of the metrics of this network, which tells them apart from the ones of the other
+    // networks of the node.
+    metrics_label: String,
+    mempool_channel: UpstreamChannelConfig,
+    consensus_channel: UpstreamChannelConfig,
+    state_sync_channel: UpstreamChannelConfig,
 }
 
 impl NetworkBuilder {
@@ -113,7 +167,7 @@ impl NetworkBuilder {
         role: RoleType,
     ) -> NetworkBuilder {
         NetworkBuilder {
-            executor,
+            task_manager: TaskManager::new("network", executor),
             peer_id,
             addr,
             role,
@@ -123,9 +177,13 @@ impl NetworkBuilder {
             channel_size: NETWORK_CHANNEL_SIZE,
             direct_send_protocols: vec![],
             rpc_protocols: vec![],
+            handler_direct_send_protocols: vec![],
+            handler_rpc_protocols: vec![],
+            handler_queue_sizes: HashMap::new(),
             transport: TransportType::Memory,
             discovery_interval_ms: DISCOVERY_INTERVAL_MS,
             discovery_msg_timeout_ms: DISOVERY_MSG_TIMEOUT_MS,
+            discovery_note_ttl_ms: DISCOVERY_NOTE_TTL_MS,
             ping_interval_ms: PING_INTERVAL_MS,
             ping_timeout_ms: PING_TIMEOUT_MS,
             ping_failures_tolerated: PING_FAILURES_TOLERATED,
@@ -136,8 +194,30 @@ impl NetworkBuilder {
             max_concurrent_network_reqs: MAX_CONCURRENT_NETWORK_REQS,
             max_concurrent_network_notifs: MAX_CONCURRENT_NETWORK_NOTIFS,
             max_connection_delay_ms: MAX_CONNECTION_DELAY_MS,
+            max_concurrent_dials: MAX_CONCURRENT_DIALS,
+            dial_stagger_ms: DIAL_STAGGER_MS,
+            direct_send_batch_window_ms: DIRECT_SEND_BATCH_WINDOW_MS,
+            direct_send_max_batch_bytes: DIRECT_SEND_MAX_BATCH_BYTES,
+            relay_listen_address: None,
+            relays: vec![],
+            outbound_connections: OutboundConnectionsConfig::default(),
+            max_inbound_per_subnet: None,
+            peer_queue: PeerQueueConfig::default(),
+            socket: SocketConfig::default(),
+            shared_listener: None,
+            fault_injector: None,
+            peer_store: None,
             signing_keys: None,
             is_permissioned: true,
+            software_version: env!("CARGO_PKG_VERSION").to_string(),
+            chain_id: String::new(),
+            metrics_label: match role {
+                RoleType::Validator => "validator".to_string(),
+                RoleType::FullNode => "full_node".to_string(),
+            },
+            mempool_channel: UpstreamChannelConfig::default(),
+            consensus_channel: UpstreamChannelConfig::default(),
+            state_sync_channel: UpstreamChannelConfig::default(),
         }
     }
 
@@ -215,6 +295,13 @@ impl NetworkBuilder {
         self
     }
 
+    /// Set how long the discovery note of this node is valid for. The note is refreshed once half
+    /// of it has elapsed.
+    pub fn discovery_note_ttl_ms(&mut self, discovery_note_ttl_ms: u64) -> &mut Self {
+        self.discovery_note_ttl_ms = discovery_note_ttl_ms;
+        self
+    }
+
     /// Set connectivity check ticker interval
     pub fn connectivity_check_interval_ms(
         &mut self,
@@ -265,37 +352,254 @@ impl NetworkBuilder {
         self
     }
 
+    /// The maximum number of dials which may be outstanding at any time, across all peers.
+    pub fn max_concurrent_dials(&mut self, max_concurrent_dials: usize) -> &mut Self {
+        self.max_concurrent_dials = max_concurrent_dials;
+        self
+    }
+
+    /// The delay (in milliseconds) after which the dial to the next address of a peer starts if
+    /// the dial to the current one has not completed yet.
+    pub fn dial_stagger_ms(&mut self, dial_stagger_ms: u64) -> &mut Self {
+        self.dial_stagger_ms = dial_stagger_ms;
+        self
+    }
+
     /// Set the size of the channels between different network actors.
     pub fn channel_size(&mut self, channel_size: usize) -> &mut Self {
         self.channel_size = channel_size;
         self
     }
 
+    /// Set the capacity and overflow policy of the channel delivering network events to Mempool.
+    pub fn mempool_channel(&mut self, mempool_channel: UpstreamChannelConfig) -> &mut Self {
+        self.mempool_channel = mempool_channel;
+        self
+    }
+
+    /// Set the capacity and overflow policy of the channel delivering network events to Consensus.
+    pub fn consensus_channel(&mut self, consensus_channel: UpstreamChannelConfig) -> &mut Self {
+        self.consensus_channel = consensus_channel;
+        self
+    }
+
+    /// Set the capacity and overflow policy of the channel delivering network events to State
+    /// Synchronizer.
+    pub fn state_sync_channel(&mut self, state_sync_channel: UpstreamChannelConfig) -> &mut Self {
+        self.state_sync_channel = state_sync_channel;
+        self
+    }
+
     /// Set the protocol IDs that DirectSend actor subscribes.
     pub fn direct_send_protocols(&mut self, protocols: Vec<ProtocolId>) -> &mut Self {
         self.direct_send_protocols = protocols;
         self
     }
 
+    /// Set how long (in milliseconds) DirectSend may hold outbound messages to coalesce them with
+    /// later messages to the same peer and protocol. A value of 0 disables batching.
+    pub fn direct_send_batch_window_ms(&mut self, direct_send_batch_window_ms: u64) -> &mut Self {
+        self.direct_send_batch_window_ms = direct_send_batch_window_ms;
+        self
+    }
+
+    /// Set the size (in bytes) at which a DirectSend batch is written out without waiting for the
+    /// end of the batch window.
+    pub fn direct_send_max_batch_bytes(&mut self, direct_send_max_batch_bytes: usize) -> &mut Self {
+        self.direct_send_max_batch_bytes = direct_send_max_batch_bytes;
+        self
+    }
+
+    fn direct_send_batch_config(&self) -> Option<BatchConfig> {
+        if self.direct_send_batch_window_ms == 0 {
+            return None;
+        }
+        Some(BatchConfig {
+            window: Duration::from_millis(self.direct_send_batch_window_ms),
+            max_bytes: self.direct_send_max_batch_bytes,
+        })
+    }
+
+    /// Act as a relay for peers which cannot accept inbound connections, listening for relay
+    /// requests on `relay_listen_address`.
+    pub fn relay_listen_address(&mut self, relay_listen_address: Multiaddr) -> &mut Self {
+        self.relay_listen_address = Some(relay_listen_address);
+        self
+    }
+
+    /// Set the relays through which we accept connections. The circuit addresses at which we are
+    /// reachable through them are advertised along with our own address. Relays are only
+    /// supported by TCP transports, and connections are only accepted through them by Noise
+    /// transports.
+    pub fn relays(&mut self, relays: Vec<Multiaddr>) -> &mut Self {
+        self.relays = relays;
+        self
+    }
+
+    /// Set the limits on the connections we initiate, whether to the eligible peers of a
+    /// permissioned network or to the known peers a network which isn't permissioned warm starts
+    /// with.
+    pub fn outbound_connections(
+        &mut self,
+        outbound_connections: OutboundConnectionsConfig,
+    ) -> &mut Self {
+        self.outbound_connections = outbound_connections;
+        self
+    }
+
+    /// Set the maximum number of connections that peers in the same subnet may initiate to us.
+    pub fn max_inbound_per_subnet(&mut self, max_inbound_per_subnet: Option<usize>) -> &mut Self {
+        self.max_inbound_per_subnet = max_inbound_per_subnet;
+        self
+    }
+
+    /// Set the size of the queue of requests to each connected peer, and how long it may stay full
+    /// before the peer is disconnected.
+    pub fn peer_queue(&mut self, peer_queue: PeerQueueConfig) -> &mut Self {
+        self.peer_queue = peer_queue;
+        self
+    }
+
+    /// Set the options of the TCP sockets of the network. They do not apply to the connections of
+    /// a shared listener, whose sockets are opened by the listener.
+    pub fn socket(&mut self, socket: SocketConfig) -> &mut Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Accept and dial connections through a listener shared with other networks, instead of a
+    /// listener of our own. Shared listeners are only supported by TCP transports, and cannot be
+    /// combined with relays.
+    pub fn shared_listener(
+        &mut self,
+        network_transport: NetworkTransport<TcpTransport>,
+    ) -> &mut Self {
+        self.shared_listener = Some(network_transport);
+        self
+    }
+
+    /// Let `fault_injector` inject faults into the connections of the network, to simulate flaky
+    /// links in tests. Faults are only injected by TCP transports.
+    pub fn fault_injector(&mut self, fault_injector: Arc<dyn FaultInjector>) -> &mut Self {
+        self.fault_injector = Some(fault_injector);
+        self
+    }
+
+    /// Persist what the network learns about its peers in `peer_store`, and warm up the
+    /// connectivity to them with what it learned before a restart.
+    pub fn peer_store(&mut self, peer_store: PeerStore) -> &mut Self {
+        self.peer_store = Some(peer_store);
+        self
+    }
+
     /// Set the protocol IDs that RPC actor subscribes.
     pub fn rpc_protocols(&mut self, protocols: Vec<ProtocolId>) -> &mut Self {
         self.rpc_protocols = protocols;
         self
     }
 
+    /// Register `protocol_id` as a direct-send protocol handled outside of the network crate, whose
+    /// events are delivered over a queue of `queue_size` events. Its `NetworkSender` and
+    /// `NetworkEvents` are taken from the built network with
+    /// [`LibraNetworkProvider::protocol_handler`].
+    pub fn add_protocol_handler(
+        &mut self,
+        protocol_id: ProtocolId,
+        queue_size: usize,
+    ) -> &mut Self {
+        self.handler_queue_sizes
+            .insert(protocol_id.clone(), queue_size);
+        self.handler_direct_send_protocols.push(protocol_id);
+        self
+    }
+
+    /// Same as [`add_protocol_handler`](Self::add_protocol_handler), for an RPC protocol.
+    pub fn add_rpc_protocol_handler(
+        &mut self,
+        protocol_id: ProtocolId,
+        queue_size: usize,
+    ) -> &mut Self {
+        self.handler_queue_sizes
+            .insert(protocol_id.clone(), queue_size);
+        self.handler_rpc_protocols.push(protocol_id);
+        self
+    }
+
+    fn all_direct_send_protocols(&self) -> impl Iterator<Item = &ProtocolId> {
+        self.direct_send_protocols
+            .iter()
+            .chain(&self.handler_direct_send_protocols)
+    }
+
+    // The rpc protocols we speak, along with the legacy versions of those which have one.
+    fn all_rpc_protocols(&self) -> Vec<ProtocolId> {
+        self.rpc_protocols
+            .iter()
+            .chain(&self.handler_rpc_protocols)
+            .cloned()
+            .chain(
+                self.legacy_rpc_protocols()
+                    .into_iter()
+                    .map(|(legacy, _)| legacy),
+            )
+            .collect()
+    }
+
+    // The legacy versions of the rpc protocols we speak, mapped to their current version.
+    fn legacy_rpc_protocols(&self) -> HashMap<ProtocolId, ProtocolId> {
+        LEGACY_RPC_PROTOCOLS
+            .iter()
+            .map(|&(legacy, current)| {
+                (
+                    ProtocolId::from_static(legacy),
+                    ProtocolId::from_static(current),
+                )
+            })
+            .filter(|(_, current)| {
+                self.rpc_protocols
+                    .iter()
+                    .chain(&self.handler_rpc_protocols)
+                    .any(|protocol| protocol == current)
+            })
+            .collect()
+    }
+
     /// Set the is_permissioned flag to make the network permissioned or permission-less.
     pub fn permissioned(&mut self, is_permissioned: bool) -> &mut Self {
         self.is_permissioned = is_permissioned;
         self
     }
 
+    /// Set the software version advertised to peers.
+    pub fn software_version(&mut self, software_version: String) -> &mut Self {
+        self.software_version = software_version;
+        self
+    }
+
+    /// Set the id of the chain this node is on. It is advertised to peers, and peers which
+    /// advertise another chain id are reported as such by the `PeerMetadataStore`.
+    pub fn chain_id(&mut self, chain_id: String) -> &mut Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Set the label of the metrics of this network, the role of the node in the network by
+    /// default, so that the networks of a node with several networks of the same role need
+    /// distinct labels for their metrics to be told apart.
+    pub fn metrics_label(&mut self, metrics_label: String) -> &mut Self {
+        self.metrics_label = metrics_label;
+        self
+    }
+
     fn supported_protocols(&self) -> Vec<ProtocolId> {
         let mut supported_protocols: Vec<ProtocolId> = self
-            .direct_send_protocols
-            .iter()
-            .chain(&self.rpc_protocols)
-            .chain(&vec![ProtocolId::from_static(PING_PROTOCOL_NAME)])
+            .all_direct_send_protocols()
             .cloned()
+            .chain(self.all_rpc_protocols())
+            .chain(vec![
+                ProtocolId::from_static(PING_PROTOCOL_NAME),
+                ProtocolId::from_static(GOAWAY_PROTOCOL),
+            ])
             .collect();
         // TODO: This check is performed at 2 places to modify how protocols are setup. Ideally we
         // should do it at only 1 place.
@@ -308,13 +612,33 @@ impl NetworkBuilder {
     /// Create the configured `NetworkBuilder`
     /// Return the constructed Mempool and Consensus Sender+Events
     pub fn build(&mut self) -> (Multiaddr, Box<dyn LibraNetworkProvider>) {
-        let identity = Identity::new(self.peer_id, self.supported_protocols(), self.role);
+        let identity = Identity::new(self.peer_id, self.supported_protocols(), self.role)
+            .with_node_info(self.software_version.clone(), self.chain_id.clone());
         // Build network based on the transport type
         let trusted_peers = self.trusted_peers.clone();
+        let relays = self.relays.clone();
+        let fault_injector = self.fault_injector.clone();
+        if let Some(network_transport) = self.shared_listener.take() {
+            // The config of a network sharing a listener is rejected if it sets relays.
+            assert!(
+                relays.is_empty(),
+                "Relays are not supported over a shared listener"
+            );
+            return self.build_with_shared_listener(
+                identity,
+                trusted_peers,
+                network_transport,
+                fault_injector,
+            );
+        }
         match self.transport {
-            TransportType::Memory => self.build_with_transport(build_memory_transport(identity)),
+            TransportType::Memory => {
+                self.start_relay(MemoryTransport::default());
+                self.build_with_transport(build_memory_transport(identity))
+            }
             TransportType::MemoryNoise(ref mut keys) => {
                 let keys = keys.take().expect("Identity keys not set");
+                self.start_relay(MemoryTransport::default());
                 self.build_with_transport(build_memory_noise_transport(
                     identity,
                     keys,
@@ -323,19 +647,154 @@ impl NetworkBuilder {
             }
             TransportType::PermissionlessMemoryNoise(ref mut keys) => {
                 let keys = keys.take().expect("Identity keys not set");
+                self.start_relay(MemoryTransport::default());
                 self.build_with_transport(build_permissionless_memory_noise_transport(
                     identity, keys,
                 ))
             }
-            TransportType::Tcp => self.build_with_transport(build_tcp_transport(identity)),
+            TransportType::Tcp => {
+                let tcp_transport = build_tcp_socket_transport(&self.socket);
+                self.start_relay(tcp_transport.clone());
+                self.build_with_transport(build_tcp_transport(
+                    tcp_transport,
+                    identity,
+                    relays,
+                    fault_injector,
+                ))
+            }
+            TransportType::TcpNoise(ref mut keys) => {
+                let keys = keys.take().expect("Identity keys not set");
+                let tcp_transport = build_tcp_socket_transport(&self.socket);
+                self.start_relay(tcp_transport.clone());
+                self.build_with_transport(build_tcp_noise_transport(
+                    tcp_transport,
+                    identity,
+                    keys,
+                    trusted_peers,
+                    relays,
+                    fault_injector,
+                ))
+            }
+            TransportType::PermissionlessTcpNoise(ref mut keys) => {
+                let keys = keys.take().expect("Identity keys not set");
+                let tcp_transport = build_tcp_socket_transport(&self.socket);
+                self.start_relay(tcp_transport.clone());
+                self.build_with_transport(build_permissionless_tcp_noise_transport(
+                    tcp_transport,
+                    identity,
+                    keys,
+                    relays,
+                    fault_injector,
+                ))
+            }
+            TransportType::TcpTls(ref mut tls_config) => {
+                let tls_config = tls_config.take().expect("TLS config not set");
+                let tcp_transport = build_tcp_socket_transport(&self.socket);
+                self.start_relay(tcp_transport.clone());
+                self.build_with_transport(build_tcp_tls_transport(
+                    tcp_transport,
+                    identity,
+                    tls_config,
+                    trusted_peers,
+                    relays,
+                    fault_injector,
+                ))
+            }
+            TransportType::PermissionlessTcpTls(ref mut tls_config) => {
+                let tls_config = tls_config.take().expect("TLS config not set");
+                let tcp_transport = build_tcp_socket_transport(&self.socket);
+                self.start_relay(tcp_transport.clone());
+                self.build_with_transport(build_permissionless_tcp_tls_transport(
+                    tcp_transport,
+                    identity,
+                    tls_config,
+                    relays,
+                    fault_injector,
+                ))
+            }
+        }
+    }
+
+    /// Build the network over a listener shared with other networks.
+    fn build_with_shared_listener(
+        &mut self,
+        identity: Identity,
+        trusted_peers: Arc<RwLock<HashMap<PeerId, NetworkPublicKeys>>>,
+        network_transport: NetworkTransport<TcpTransport>,
+        fault_injector: Option<Arc<dyn FaultInjector>>,
+    ) -> (Multiaddr, Box<dyn LibraNetworkProvider>) {
+        self.start_relay(build_tcp_socket_transport(&self.socket));
+        match self.transport {
+            TransportType::Tcp => self.build_with_transport(build_shared_tcp_transport(
+                identity,
+                network_transport,
+                fault_injector,
+            )),
             TransportType::TcpNoise(ref mut keys) => {
                 let keys = keys.take().expect("Identity keys not set");
-                self.build_with_transport(build_tcp_noise_transport(identity, keys, trusted_peers))
+                self.build_with_transport(build_shared_tcp_noise_transport(
+                    identity,
+                    keys,
+                    trusted_peers,
+                    network_transport,
+                    fault_injector,
+                ))
             }
             TransportType::PermissionlessTcpNoise(ref mut keys) => {
                 let keys = keys.take().expect("Identity keys not set");
-                self.build_with_transport(build_permissionless_tcp_noise_transport(identity, keys))
+                self.build_with_transport(build_shared_permissionless_tcp_noise_transport(
+                    identity,
+                    keys,
+                    network_transport,
+                    fault_injector,
+                ))
             }
+            TransportType::TcpTls(ref mut tls_config) => {
+                let tls_config = tls_config.take().expect("TLS config not set");
+                self.build_with_transport(build_shared_tcp_tls_transport(
+                    identity,
+                    tls_config,
+                    trusted_peers,
+                    network_transport,
+                    fault_injector,
+                ))
+            }
+            TransportType::PermissionlessTcpTls(ref mut tls_config) => {
+                let tls_config = tls_config.take().expect("TLS config not set");
+                self.build_with_transport(build_shared_permissionless_tcp_tls_transport(
+                    identity,
+                    tls_config,
+                    network_transport,
+                    fault_injector,
+                ))
+            }
+            _ => panic!("Shared listeners are only supported by TCP transports"),
+        }
+    }
+
+    /// Start the relay actor on `transport` if this node acts as a relay.
+    fn start_relay<TTransport>(&self, transport: TTransport)
+    where
+        TTransport: Transport<Error = ::std::io::Error>,
+        TTransport::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        TTransport::Listener: Send + Unpin + 'static,
+        TTransport::Inbound: Send + 'static,
+    {
+        if let Some(relay_listen_address) = &self.relay_listen_address {
+            // On a permissioned network, only trusted peers may reserve with the relay.
+            let trusted_peers = if self.is_permissioned {
+                Some(self.trusted_peers.clone())
+            } else {
+                None
+            };
+            let relay = Relay::new(
+                transport,
+                self.task_manager.clone(),
+                relay_listen_address.clone(),
+                trusted_peers,
+            );
+            info!("Acting as a relay on {}", relay.listen_addr());
+            self.task_manager.spawn("relay", relay.start());
         }
     }
 
@@ -361,10 +820,10 @@ impl NetworkBuilder {
             &counters::PENDING_PEER_MANAGER_DIRECT_SEND_NOTIFICATIONS,
         );
         let direct_send_handlers = self
-            .direct_send_protocols
-            .iter()
+            .all_direct_send_protocols()
             .map(|p| (p.clone(), pm_ds_notifs_tx.clone()));
         protocol_handlers.extend(direct_send_handlers);
+        peer_event_handlers.push(pm_ds_notifs_tx);
         let (ds_reqs_tx, ds_reqs_rx) =
             channel::new(self.channel_size, &counters::PENDING_DIRECT_SEND_REQUESTS);
         let (ds_net_notifs_tx, ds_net_notifs_rx) = channel::new(
@@ -372,14 +831,14 @@ impl NetworkBuilder {
             &counters::PENDING_DIRECT_SEND_NOTIFICATIONS,
         );
         let ds = DirectSend::new(
-            self.executor.clone(),
+            self.task_manager.clone(),
             ds_reqs_rx,
             ds_net_notifs_tx,
             pm_ds_notifs_rx,
             PeerManagerRequestSender::new(pm_reqs_tx.clone()),
+            self.direct_send_batch_config(),
         );
-        self.executor
-            .spawn(ds.start().boxed().unit_error().compat());
+        self.task_manager.spawn("direct_send", ds.start());
         debug!("Started direct send actor");
 
         // Initialize and start RPC actor.
@@ -388,16 +847,16 @@ impl NetworkBuilder {
             &counters::PENDING_PEER_MANAGER_RPC_NOTIFICATIONS,
         );
         let rpc_handlers = self
-            .rpc_protocols
-            .iter()
-            .map(|p| (p.clone(), pm_rpc_notifs_tx.clone()));
+            .all_rpc_protocols()
+            .into_iter()
+            .map(|p| (p, pm_rpc_notifs_tx.clone()));
         protocol_handlers.extend(rpc_handlers);
         let (rpc_net_notifs_tx, rpc_net_notifs_rx) =
             channel::new(self.channel_size, &counters::PENDING_RPC_NOTIFICATIONS);
         let (rpc_reqs_tx, rpc_reqs_rx) =
             channel::new(self.channel_size, &counters::PENDING_RPC_REQUESTS);
         let rpc = Rpc::new(
-            self.executor.clone(),
+            self.task_manager.clone(),
             rpc_reqs_rx,
             pm_rpc_notifs_rx,
             PeerManagerRequestSender::new(pm_reqs_tx.clone()),
@@ -405,35 +864,11 @@ impl NetworkBuilder {
             Duration::from_millis(self.inbound_rpc_timeout_ms),
             self.max_concurrent_outbound_rpcs,
             self.max_concurrent_inbound_rpcs,
+            self.legacy_rpc_protocols(),
         );
-        self.executor
-            .spawn(rpc.start().boxed().unit_error().compat());
+        self.task_manager.spawn("rpc", rpc.start());
         debug!("Started RPC actor");
 
-        // Initialize and start HealthChecker.
-        let (pm_ping_notifs_tx, pm_ping_notifs_rx) = channel::new(
-            self.channel_size,
-            &counters::PENDING_PEER_MANAGER_PING_NOTIFICATIONS,
-        );
-        protocol_handlers.insert(
-            ProtocolId::from_static(PING_PROTOCOL_NAME),
-            pm_ping_notifs_tx.clone(),
-        );
-        peer_event_handlers.push(pm_ping_notifs_tx);
-        let health_checker = HealthChecker::new(
-            Compat01As03::new(Interval::new_interval(Duration::from_millis(
-                self.ping_interval_ms,
-            )))
-            .fuse(),
-            PeerManagerRequestSender::new(pm_reqs_tx.clone()),
-            pm_ping_notifs_rx,
-            Duration::from_millis(self.ping_timeout_ms),
-            self.ping_failures_tolerated,
-        );
-        self.executor
-            .spawn(health_checker.start().boxed().unit_error().compat());
-        debug!("Started health checker");
-
         let mut net_conn_mgr_reqs_tx = None;
 
         // We start the discovery and connectivity_manager module only if the network is
@@ -450,6 +885,7 @@ impl NetworkBuilder {
                 &counters::PENDING_PEER_MANAGER_CONNECTIVITY_MANAGER_NOTIFICATIONS,
             );
             peer_event_handlers.push(pm_conn_mgr_notifs_tx);
+            let dial_stats = DialStats::new();
             let conn_mgr = ConnectivityManager::new(
                 self.trusted_peers.clone(),
                 Compat01As03::new(Interval::new_interval(Duration::from_millis(
@@ -459,11 +895,20 @@ impl NetworkBuilder {
                 PeerManagerRequestSender::new(pm_reqs_tx.clone()),
                 pm_conn_mgr_notifs_rx,
                 conn_mgr_reqs_rx,
-                ExponentialBackoff::from_millis(2).factor(1000 /* seconds */),
+                // Jitter spreads out the redials of peers which became unreachable at the same
+                // time, e.g., because of a network partition.
+                ExponentialBackoff::from_millis(2)
+                    .factor(1000 /* seconds */)
+                    .map(jitter),
                 self.max_connection_delay_ms,
+                self.max_concurrent_dials,
+                self.dial_stagger_ms,
+                dial_stats.clone(),
+                self.peer_store.clone(),
+                self.outbound_connections.clone(),
             );
-            self.executor
-                .spawn(conn_mgr.start().boxed().unit_error().compat());
+            self.task_manager
+                .spawn("connectivity_manager", conn_mgr.start());
             debug!("Started connection manager");
 
             // Initialize and start Discovery actor.
@@ -480,12 +925,22 @@ impl NetworkBuilder {
                 self.signing_keys.take().expect("Signing keys not set");
             // Setup signer from keys.
             let signer = ValidatorSigner::new(self.peer_id, signing_private_key);
+            // We advertise our circuit address at each of our relays after our own address, so
+            // that peers which cannot dial us directly can reach us through the relays.
+            let advertised_addrs = iter::once(
+                self.advertised_address
+                    .clone()
+                    .unwrap_or_else(|| self.addr.clone()),
+            )
+            .chain(
+                self.relays
+                    .iter()
+                    .map(|relay_addr| relay::circuit_addr(relay_addr, self.peer_id)),
+            )
+            .collect();
             let discovery = Discovery::new(
                 self.peer_id,
-                vec![self
-                    .advertised_address
-                    .clone()
-                    .unwrap_or_else(|| self.addr.clone())],
+                advertised_addrs,
                 signer,
                 self.seed_peers.clone(),
                 self.trusted_peers.clone(),
@@ -496,30 +951,79 @@ impl NetworkBuilder {
                 PeerManagerRequestSender::new(pm_reqs_tx.clone()),
                 pm_discovery_notifs_rx,
                 conn_mgr_reqs_tx.clone(),
+                dial_stats,
                 Duration::from_millis(self.discovery_msg_timeout_ms),
+                Duration::from_millis(self.discovery_note_ttl_ms),
             );
-            self.executor
-                .spawn(discovery.start().boxed().unit_error().compat());
+            self.task_manager.spawn("discovery", discovery.start());
             debug!("Started discovery protocol actor");
+        } else if let Some(peer_store) = &self.peer_store {
+            // Without a connectivity manager, the peers known before a restart are only dialed
+            // once on startup.
+            self.task_manager.spawn(
+                "warm_start",
+                connectivity_manager::warm_start(
+                    peer_store.clone(),
+                    PeerManagerRequestSender::new(pm_reqs_tx.clone()),
+                    self.max_concurrent_dials,
+                    Duration::from_millis(self.dial_stagger_ms),
+                    self.outbound_connections.clone(),
+                ),
+            );
+            debug!("Started warm start");
         }
 
+        // Initialize and start HealthChecker.
+        let (pm_ping_notifs_tx, pm_ping_notifs_rx) = channel::new(
+            self.channel_size,
+            &counters::PENDING_PEER_MANAGER_PING_NOTIFICATIONS,
+        );
+        protocol_handlers.insert(
+            ProtocolId::from_static(PING_PROTOCOL_NAME),
+            pm_ping_notifs_tx.clone(),
+        );
+        peer_event_handlers.push(pm_ping_notifs_tx);
+        let health_checker = HealthChecker::new(
+            Compat01As03::new(Interval::new_interval(Duration::from_millis(
+                self.ping_interval_ms,
+            )))
+            .fuse(),
+            PeerManagerRequestSender::new(pm_reqs_tx.clone()),
+            pm_ping_notifs_rx,
+            net_conn_mgr_reqs_tx.clone(),
+            Duration::from_millis(self.ping_timeout_ms),
+            self.ping_failures_tolerated,
+            self.peer_store.clone(),
+        );
+        self.task_manager
+            .spawn("health_checker", health_checker.start());
+        debug!("Started health checker");
+
         let (pm_net_notifs_tx, pm_net_notifs_rx) = channel::new(
             self.channel_size,
             &counters::PENDING_PEER_MANAGER_NET_NOTIFICATIONS,
         );
         peer_event_handlers.push(pm_net_notifs_tx);
+        let mut peer_metadata = PeerMetadataStore::new(self.chain_id.clone());
+        if let Some(peer_store) = &self.peer_store {
+            peer_metadata = peer_metadata.with_peer_store(peer_store.clone());
+        }
         let peer_mgr = PeerManager::new(
             transport,
-            self.executor.clone(),
+            self.task_manager.clone(),
             self.peer_id,
             self.addr.clone(),
+            peer_metadata.clone(),
             pm_reqs_rx,
             protocol_handlers,
+            self.all_rpc_protocols(),
             peer_event_handlers,
-        );
+            self.peer_queue.clone(),
+        )
+        .with_max_inbound_per_subnet(self.max_inbound_per_subnet);
         let listen_addr = peer_mgr.listen_addr().clone();
-        self.executor
-            .spawn(peer_mgr.start().boxed().unit_error().compat());
+        let shutdown_handle = peer_mgr.shutdown_handle();
+        self.task_manager.spawn("peer_manager", peer_mgr.start());
         debug!("Started peer manager");
 
         // Setup communication channels.
@@ -532,11 +1036,17 @@ impl NetworkBuilder {
             ds_reqs_tx,
             ds_net_notifs_rx,
             net_conn_mgr_reqs_tx,
+            peer_metadata,
+            shutdown_handle,
             network_reqs_rx,
             network_reqs_tx,
             self.max_concurrent_network_reqs,
             self.max_concurrent_network_notifs,
-            self.channel_size,
+            self.mempool_channel.clone(),
+            self.consensus_channel.clone(),
+            self.state_sync_channel.clone(),
+            counters::OP_COUNTERS.gauge(&format!("connected_peers.{}", self.metrics_label)),
+            self.handler_queue_sizes.clone(),
         );
         (listen_addr, Box::new(validator_network))
     }
@@ -0,0 +1,159 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Network API for application protocols which are not built into Libra.
+//!
+//! A protocol is registered with the [`NetworkBuilder`], using
+//! [`add_protocol_handler`] for direct-send protocols or [`add_rpc_protocol_handler`] for RPC
+//! protocols, and its [`NetworkSender`] and [`NetworkEvents`] are then taken from the network
+//! provider with [`LibraNetworkProvider::protocol_handler`]. Messages are exchanged as raw
+//! `Bytes`: their format is up to the application.
+//!
+//! [`NetworkBuilder`]: crate::validator_network::network_builder::NetworkBuilder
+//! [`add_protocol_handler`]: crate::validator_network::network_builder::NetworkBuilder::add_protocol_handler
+//! [`add_rpc_protocol_handler`]: crate::validator_network::network_builder::NetworkBuilder::add_rpc_protocol_handler
+//! [`LibraNetworkProvider::protocol_handler`]: crate::interface::LibraNetworkProvider::protocol_handler
+
+use crate::{
+    error::NetworkError,
+    interface::{NetworkNotification, NetworkRequest},
+    protocols::{
+        direct_send::Message,
+        rpc::{error::RpcError, OutboundRpcRequest},
+    },
+    validator_network::Event,
+    ProtocolId,
+};
+use bytes::Bytes;
+use channel;
+use futures::{
+    channel::oneshot,
+    stream::Map,
+    task::{Context, Poll},
+    SinkExt, Stream, StreamExt,
+};
+use pin_utils::unsafe_pinned;
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use types::PeerId;
+
+/// The events of an application protocol: the messages and RPC requests received for it, along
+/// with the connections to peers being established and lost.
+pub struct NetworkEvents {
+    inner: Map<
+        channel::Receiver<NetworkNotification>,
+        fn(NetworkNotification) -> Result<Event<Bytes>, NetworkError>,
+    >,
+}
+
+impl NetworkEvents {
+    // This use of `unsafe_pinned` is safe because:
+    //   1. This struct does not implement [`Drop`]
+    //   2. This struct does not implement [`Unpin`]
+    //   3. This struct is not `#[repr(packed)]`
+    unsafe_pinned!(
+        inner:
+            Map<
+                channel::Receiver<NetworkNotification>,
+                fn(NetworkNotification) -> Result<Event<Bytes>, NetworkError>,
+            >
+    );
+
+    pub fn new(receiver: channel::Receiver<NetworkNotification>) -> Self {
+        let inner = receiver.map::<_, fn(_) -> _>(|notification| match notification {
+            NetworkNotification::NewPeer(peer_id) => Ok(Event::NewPeer(peer_id)),
+            NetworkNotification::LostPeer(peer_id) => Ok(Event::LostPeer(peer_id)),
+            NetworkNotification::RecvRpc(peer_id, rpc_req) => Ok(Event::RpcRequest((
+                peer_id,
+                rpc_req.data,
+                rpc_req.res_tx,
+                rpc_req.deadline,
+            ))),
+            NetworkNotification::RecvMessage(peer_id, msg) => {
+                Ok(Event::Message((peer_id, msg.mdata)))
+            }
+        });
+
+        Self { inner }
+    }
+}
+
+impl Stream for NetworkEvents {
+    type Item = Result<Event<Bytes>, NetworkError>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        self.inner().poll_next(context)
+    }
+}
+
+/// Sends the messages and RPC requests of an application protocol. Direct-send messages are only
+/// delivered for protocols registered as direct-send protocols, and RPC requests for protocols
+/// registered as RPC protocols.
+#[derive(Clone)]
+pub struct NetworkSender {
+    protocol: ProtocolId,
+    inner: channel::Sender<NetworkRequest>,
+}
+
+impl NetworkSender {
+    pub fn new(protocol: ProtocolId, inner: channel::Sender<NetworkRequest>) -> Self {
+        Self { protocol, inner }
+    }
+
+    /// The protocol the messages are sent on.
+    pub fn protocol(&self) -> &ProtocolId {
+        &self.protocol
+    }
+
+    /// Send a fire-and-forget "direct-send" message to remote peer `recipient`.
+    pub async fn send_to(&mut self, recipient: PeerId, message: Bytes) -> Result<(), NetworkError> {
+        self.send_to_with_ttl(recipient, message, None).await
+    }
+
+    /// Same as [`send_to`](Self::send_to), except that the message is dropped instead of being
+    /// sent if it is still queued once `ttl` has elapsed.
+    pub async fn send_to_with_ttl(
+        &mut self,
+        recipient: PeerId,
+        message: Bytes,
+        ttl: Option<Duration>,
+    ) -> Result<(), NetworkError> {
+        self.inner
+            .send(NetworkRequest::SendMessage(
+                recipient,
+                Message {
+                    protocol: self.protocol.clone(),
+                    mdata: message,
+                },
+                ttl.map(|ttl| Instant::now() + ttl),
+            ))
+            .await
+            .map_err(|err| NetworkError::from(err).with_peer(recipient))?;
+        Ok(())
+    }
+
+    /// Send an RPC request to remote peer `recipient`, and wait at most `timeout` for its
+    /// response.
+    ///
+    /// The rpc request can be canceled at any point by dropping the returned future.
+    pub async fn send_rpc(
+        &mut self,
+        recipient: PeerId,
+        request: Bytes,
+        timeout: Duration,
+    ) -> Result<Bytes, RpcError> {
+        let (res_tx, res_rx) = oneshot::channel();
+        let req = OutboundRpcRequest {
+            protocol: self.protocol.clone(),
+            data: request,
+            res_tx,
+            timeout,
+        };
+        self.inner
+            .send(NetworkRequest::SendRpc(recipient, req))
+            .await?;
+        Ok(res_rx.await??)
+    }
+}
@@ -4,14 +4,22 @@
 //! Interface between StateSynchronizer and Network layers.
 
 use crate::{
-    error::NetworkError,
+    error::{NetworkError, NetworkErrorKind},
     interface::{NetworkNotification, NetworkRequest},
-    proto::StateSynchronizerMsg,
-    protocols::direct_send::Message,
+    peer_manager::PeerMetadataStore,
+    proto::{GetChunkRequest, StateSynchronizerMsg, StateSynchronizerMsg_oneof},
+    protocols::{
+        direct_send::Message,
+        rpc::{
+            error::RpcError,
+            utils::{send_rpc_with_policy, RpcPolicy},
+        },
+    },
     utils::MessageExt,
     validator_network::Event,
     ProtocolId,
 };
+use bytes::Bytes;
 use channel;
 use futures::{
     stream::Map,
@@ -20,15 +28,41 @@ use futures::{
 };
 use pin_utils::unsafe_pinned;
 use prost::Message as _;
-use std::pin::Pin;
-use types::PeerId;
+use prost_ext::wire::wire_fields;
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use types::{proto::types::LedgerInfoWithSignatures, PeerId};
 
 pub const STATE_SYNCHRONIZER_MSG_PROTOCOL: &[u8] = b"/libra/state_synchronizer/direct-send/0.1.0";
+/// Protocol id for the chunk requests which are answered over rpc, rather than by a direct-send
+/// response.
+pub const STATE_SYNCHRONIZER_RPC_PROTOCOL: &[u8] = b"/libra/state_synchronizer/rpc/0.1.0";
+
+/// Inbound message of the state synchronizer, decoded out of a [`StateSynchronizerMsg`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateSynchronizerInboundMsg {
+    ChunkRequest(GetChunkRequest),
+    ChunkResponse(RawChunkResponse),
+}
+
+/// A [`GetChunkResponse`] whose transactions are left encoded, as a slice of the received message
+/// which shares its memory. Chunks are the largest messages a node receives: decoding them
+/// through the proto types would copy every transaction once more.
+///
+/// [`GetChunkResponse`]: crate::proto::GetChunkResponse
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawChunkResponse {
+    pub ledger_info_with_sigs: Option<LedgerInfoWithSignatures>,
+    /// Encoded `TransactionListWithProof` proto.
+    pub txn_list_with_proof: Option<Bytes>,
+}
 
 pub struct StateSynchronizerEvents {
     inner: Map<
         channel::Receiver<NetworkNotification>,
-        fn(NetworkNotification) -> Result<Event<StateSynchronizerMsg>, NetworkError>,
+        fn(NetworkNotification) -> Result<Event<StateSynchronizerInboundMsg>, NetworkError>,
     >,
 }
 impl StateSynchronizerEvents {
@@ -40,7 +74,7 @@ impl StateSynchronizerEvents {
         inner:
             Map<
                 channel::Receiver<NetworkNotification>,
-                fn(NetworkNotification) -> Result<Event<StateSynchronizerMsg>, NetworkError>,
+                fn(NetworkNotification) -> Result<Event<StateSynchronizerInboundMsg>, NetworkError>,
             >
     );
 
@@ -48,11 +82,17 @@ impl StateSynchronizerEvents {
         let inner = receiver.map::<_, fn(_) -> _>(|notification| match notification {
             NetworkNotification::NewPeer(peer_id) => Ok(Event::NewPeer(peer_id)),
             NetworkNotification::LostPeer(peer_id) => Ok(Event::LostPeer(peer_id)),
-            NetworkNotification::RecvRpc(_, _) => {
-                unimplemented!("StateSynchronizer does not currently use RPC");
+            NetworkNotification::RecvRpc(peer_id, rpc_req) => {
+                let msg = decode_inbound_msg(rpc_req.data).map_err(|err| err.with_peer(peer_id))?;
+                Ok(Event::RpcRequest((
+                    peer_id,
+                    msg,
+                    rpc_req.res_tx,
+                    rpc_req.deadline,
+                )))
             }
             NetworkNotification::RecvMessage(peer_id, msg) => {
-                let msg = StateSynchronizerMsg::decode(msg.mdata.as_ref())?;
+                let msg = decode_inbound_msg(msg.mdata).map_err(|err| err.with_peer(peer_id))?;
                 Ok(Event::Message((peer_id, msg)))
             }
         });
@@ -61,8 +101,53 @@ impl StateSynchronizerEvents {
     }
 }
 
+/// Decodes an encoded [`StateSynchronizerMsg`], slicing the transactions of chunk responses out
+/// of `bytes` instead of decoding them.
+fn decode_inbound_msg(bytes: Bytes) -> Result<StateSynchronizerInboundMsg, NetworkError> {
+    let mut msg = None;
+    for field in wire_fields(bytes) {
+        let field = field?;
+        // The fields of the `message` oneof, of which the last one wins.
+        match field.tag {
+            1 => {
+                let request = GetChunkRequest::decode(field.length_delimited()?)?;
+                msg = Some(StateSynchronizerInboundMsg::ChunkRequest(request));
+            }
+            2 => {
+                let response = decode_chunk_response(field.length_delimited()?)?;
+                msg = Some(StateSynchronizerInboundMsg::ChunkResponse(response));
+            }
+            _ => {}
+        }
+    }
+    msg.ok_or_else(|| NetworkErrorKind::DecodeError.into())
+}
+
+fn decode_chunk_response(bytes: Bytes) -> Result<RawChunkResponse, NetworkError> {
+    let mut response = RawChunkResponse {
+        ledger_info_with_sigs: None,
+        txn_list_with_proof: None,
+    };
+    for field in wire_fields(bytes) {
+        let field = field?;
+        match field.tag {
+            1 => {
+                let ledger_info_with_sigs = response
+                    .ledger_info_with_sigs
+                    .get_or_insert_with(LedgerInfoWithSignatures::default);
+                ledger_info_with_sigs.merge(field.length_delimited()?)?;
+            }
+            // Unlike the ledger info, occurrences of the transaction list are not merged: there
+            // is no reason for a sender to split it.
+            2 => response.txn_list_with_proof = Some(field.length_delimited()?),
+            _ => {}
+        }
+    }
+    Ok(response)
+}
+
 impl Stream for StateSynchronizerEvents {
-    type Item = Result<Event<StateSynchronizerMsg>, NetworkError>;
+    type Item = Result<Event<StateSynchronizerInboundMsg>, NetworkError>;
 
     fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
         self.inner().poll_next(context)
@@ -72,17 +157,46 @@ impl Stream for StateSynchronizerEvents {
 #[derive(Clone)]
 pub struct StateSynchronizerSender {
     inner: channel::Sender<NetworkRequest>,
+    peer_metadata: Option<PeerMetadataStore>,
 }
 
 impl StateSynchronizerSender {
     pub fn new(inner: channel::Sender<NetworkRequest>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            peer_metadata: None,
+        }
+    }
+
+    /// Checks the chain of the peers against the metadata they advertised to the network.
+    pub fn with_peer_metadata(mut self, peer_metadata: PeerMetadataStore) -> Self {
+        self.peer_metadata = Some(peer_metadata);
+        self
+    }
+
+    /// Returns whether `peer_id` is on the same chain as this node. Without peer metadata, every
+    /// peer is assumed to be.
+    pub fn is_same_chain(&self, peer_id: &PeerId) -> bool {
+        self.peer_metadata
+            .as_ref()
+            .map_or(true, |peer_metadata| peer_metadata.is_same_chain(peer_id))
     }
 
     pub async fn send_to(
         &mut self,
         recipient: PeerId,
         msg: StateSynchronizerMsg,
+    ) -> Result<(), NetworkError> {
+        self.send_to_with_ttl(recipient, msg, None).await
+    }
+
+    /// Same as [`send_to`](Self::send_to), except that the message is dropped instead of being
+    /// sent if it is still queued once `ttl` has elapsed.
+    pub async fn send_to_with_ttl(
+        &mut self,
+        recipient: PeerId,
+        msg: StateSynchronizerMsg,
+        ttl: Option<Duration>,
     ) -> Result<(), NetworkError> {
         let protocol = ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL);
         self.inner
@@ -92,18 +206,55 @@ impl StateSynchronizerSender {
                     protocol,
                     mdata: msg.to_bytes().unwrap(),
                 },
+                ttl.map(|ttl| Instant::now() + ttl),
             ))
-            .await?;
+            .await
+            .map_err(|err| NetworkError::from(err).with_peer(recipient))?;
         Ok(())
     }
+
+    /// Requests a chunk over rpc from the first of `peers`, or from the other ones as specified
+    /// by `policy`. Returns the peer which responded along with its chunk, whose transactions are
+    /// left encoded as for the chunks received over direct-send.
+    pub async fn request_chunk(
+        &mut self,
+        peers: Vec<PeerId>,
+        request: GetChunkRequest,
+        deadline: Instant,
+        policy: RpcPolicy,
+    ) -> Result<(PeerId, RawChunkResponse), RpcError> {
+        let protocol = ProtocolId::from_static(STATE_SYNCHRONIZER_RPC_PROTOCOL);
+        let msg = StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::ChunkRequest(request)),
+        };
+        let (peer_id, res_data) = send_rpc_with_policy(
+            self.inner.clone(),
+            peers,
+            protocol,
+            msg.to_bytes()?,
+            deadline,
+            policy,
+        )
+        .await?;
+        match decode_inbound_msg(res_data) {
+            Ok(StateSynchronizerInboundMsg::ChunkResponse(response)) => Ok((peer_id, response)),
+            _ => Err(RpcError::InvalidRpcResponse),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::proto::{GetChunkRequest, GetChunkResponse, StateSynchronizerMsg_oneof};
-    use futures::executor::block_on;
+    use crate::{
+        peer_manager::PeerMetadata,
+        proto::GetChunkResponse,
+        protocols::{identity::Identity, rpc::InboundRpcRequest},
+    };
+    use config::config::RoleType;
+    use futures::{channel::oneshot, executor::block_on, future::join};
+    use types::proto::types::TransactionListWithProof;
 
     // `StateSynchronizerSender` should serialize outbound messages
     #[test]
@@ -124,7 +275,7 @@ mod tests {
         // Wait for msg at network layer.
         let event = block_on(network_reqs_rx.next()).unwrap();
         match event {
-            NetworkRequest::SendMessage(recv_peer_id, msg) => {
+            NetworkRequest::SendMessage(recv_peer_id, msg, _) => {
                 assert_eq!(recv_peer_id, peer_id);
                 assert_eq!(msg.protocol.as_ref(), STATE_SYNCHRONIZER_MSG_PROTOCOL);
                 // check request deserializes
@@ -135,6 +286,27 @@ mod tests {
         }
     }
 
+    // Peers are on the same chain only once they advertised it.
+    #[test]
+    fn test_same_chain() {
+        let (network_reqs_tx, _network_reqs_rx) = channel::new_test(8);
+        let peer_metadata = PeerMetadataStore::new("testnet".to_string());
+        let sender = StateSynchronizerSender::new(network_reqs_tx.clone())
+            .with_peer_metadata(peer_metadata.clone());
+        let peer_id = PeerId::random();
+        assert!(StateSynchronizerSender::new(network_reqs_tx).is_same_chain(&peer_id));
+        assert!(!sender.is_same_chain(&peer_id));
+
+        let identity = Identity::new(peer_id, vec![], RoleType::Validator)
+            .with_node_info("0.1.0".to_string(), "mainnet".to_string());
+        peer_metadata.insert(peer_id, PeerMetadata::from(&identity));
+        assert!(!sender.is_same_chain(&peer_id));
+
+        let identity = identity.with_node_info("0.1.0".to_string(), "testnet".to_string());
+        peer_metadata.insert(peer_id, PeerMetadata::from(&identity));
+        assert!(sender.is_same_chain(&peer_id));
+    }
+
     // Direct send messages should get deserialized through the `StateSynchronizerEvents` stream.
     #[test]
     fn test_inbound_msg() {
@@ -142,10 +314,13 @@ mod tests {
         let mut stream = StateSynchronizerEvents::new(state_sync_rx);
         let peer_id = PeerId::random();
 
-        // Create GetChunkResponse and embed in StateSynchronizerMsg.
-        let chunk_response = GetChunkResponse::default();
+        // Create GetChunkRequest and embed in StateSynchronizerMsg.
+        let mut chunk_request = GetChunkRequest::default();
+        chunk_request.limit = 100;
         let mut state_sync_msg = StateSynchronizerMsg::default();
-        state_sync_msg.message = Some(StateSynchronizerMsg_oneof::ChunkResponse(chunk_response));
+        state_sync_msg.message = Some(StateSynchronizerMsg_oneof::ChunkRequest(
+            chunk_request.clone(),
+        ));
 
         // mock receiving request.
         let event = NetworkNotification::RecvMessage(
@@ -158,8 +333,175 @@ mod tests {
         block_on(state_sync_tx.send(event)).unwrap();
 
         // request should be properly deserialized
-        let expected_event = Event::Message((peer_id, state_sync_msg.clone()));
+        let expected_event = Event::Message((
+            peer_id,
+            StateSynchronizerInboundMsg::ChunkRequest(chunk_request),
+        ));
         let event = block_on(stream.next()).unwrap().unwrap();
         assert_eq!(event, expected_event);
     }
+
+    // The transactions of chunk responses should be handed out still encoded, as a slice of the
+    // received message.
+    #[test]
+    fn test_inbound_chunk_response() {
+        let (mut state_sync_tx, state_sync_rx) = channel::new_test(8);
+        let mut stream = StateSynchronizerEvents::new(state_sync_rx);
+        let peer_id = PeerId::random();
+
+        let mut txn_list_with_proof = TransactionListWithProof::default();
+        txn_list_with_proof.first_transaction_version = Some(42);
+        let mut chunk_response = GetChunkResponse::default();
+        chunk_response.ledger_info_with_sigs = Some(LedgerInfoWithSignatures::default());
+        chunk_response.txn_list_with_proof = Some(txn_list_with_proof.clone());
+        let mut state_sync_msg = StateSynchronizerMsg::default();
+        state_sync_msg.message = Some(StateSynchronizerMsg_oneof::ChunkResponse(chunk_response));
+        let mdata = state_sync_msg.to_bytes().unwrap();
+
+        let event = NetworkNotification::RecvMessage(
+            peer_id,
+            Message {
+                protocol: ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL),
+                mdata: mdata.clone(),
+            },
+        );
+        block_on(state_sync_tx.send(event)).unwrap();
+
+        let response = match block_on(stream.next()).unwrap().unwrap() {
+            Event::Message((
+                recv_peer_id,
+                StateSynchronizerInboundMsg::ChunkResponse(response),
+            )) => {
+                assert_eq!(recv_peer_id, peer_id);
+                response
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        };
+        assert_eq!(
+            response.ledger_info_with_sigs,
+            Some(LedgerInfoWithSignatures::default())
+        );
+        let txn_list_bytes = response.txn_list_with_proof.unwrap();
+        assert_eq!(txn_list_bytes, txn_list_with_proof.to_bytes().unwrap());
+        // The transactions point into the received message.
+        let mdata_range = mdata.as_ptr() as usize..mdata.as_ptr() as usize + mdata.len();
+        assert!(mdata_range.contains(&(txn_list_bytes.as_ptr() as usize)));
+    }
+
+    // Chunk requests received over rpc should get deserialized through the
+    // `StateSynchronizerEvents` stream.
+    #[test]
+    fn test_inbound_rpc() {
+        let (mut state_sync_tx, state_sync_rx) = channel::new_test(8);
+        let mut stream = StateSynchronizerEvents::new(state_sync_rx);
+        let peer_id = PeerId::random();
+
+        let mut chunk_request = GetChunkRequest::default();
+        chunk_request.limit = 100;
+        let state_sync_msg = StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::ChunkRequest(
+                chunk_request.clone(),
+            )),
+        };
+
+        let (res_tx, _) = oneshot::channel();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let event = NetworkNotification::RecvRpc(
+            peer_id,
+            InboundRpcRequest {
+                protocol: ProtocolId::from_static(STATE_SYNCHRONIZER_RPC_PROTOCOL),
+                data: state_sync_msg.to_bytes().unwrap(),
+                res_tx,
+                deadline,
+            },
+        );
+        block_on(state_sync_tx.send(event)).unwrap();
+
+        let (res_tx, _) = oneshot::channel();
+        let expected_event = Event::RpcRequest((
+            peer_id,
+            StateSynchronizerInboundMsg::ChunkRequest(chunk_request),
+            res_tx,
+            deadline,
+        ));
+        let event = block_on(stream.next()).unwrap().unwrap();
+        assert_eq!(event, expected_event);
+    }
+
+    // Chunks requested over rpc should be returned with their transactions still encoded.
+    #[test]
+    fn test_request_chunk() {
+        let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+        let mut sender = StateSynchronizerSender::new(network_reqs_tx);
+        let peer_id = PeerId::random();
+
+        let mut chunk_request = GetChunkRequest::default();
+        chunk_request.limit = 100;
+        let f_res = sender.request_chunk(
+            vec![peer_id],
+            chunk_request.clone(),
+            Instant::now() + Duration::from_secs(5),
+            RpcPolicy::NoRetry,
+        );
+
+        let mut txn_list_with_proof = TransactionListWithProof::default();
+        txn_list_with_proof.first_transaction_version = Some(42);
+        let chunk_response = GetChunkResponse {
+            ledger_info_with_sigs: Some(LedgerInfoWithSignatures::default()),
+            txn_list_with_proof: Some(txn_list_with_proof.clone()),
+        };
+        let state_sync_msg = StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::ChunkResponse(chunk_response)),
+        };
+        let f_network = async move {
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(recv_peer_id, req) => {
+                    assert_eq!(recv_peer_id, peer_id);
+                    assert_eq!(req.protocol.as_ref(), STATE_SYNCHRONIZER_RPC_PROTOCOL);
+                    let recv_msg = StateSynchronizerMsg::decode(req.data.as_ref()).unwrap();
+                    assert_eq!(
+                        recv_msg.message,
+                        Some(StateSynchronizerMsg_oneof::ChunkRequest(chunk_request))
+                    );
+                    req.res_tx
+                        .send(Ok(state_sync_msg.to_bytes().unwrap()))
+                        .unwrap();
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            }
+        };
+
+        let (res, ()) = block_on(join(f_res, f_network));
+        let (recv_peer_id, response) = res.unwrap();
+        assert_eq!(recv_peer_id, peer_id);
+        assert_eq!(
+            response,
+            RawChunkResponse {
+                ledger_info_with_sigs: Some(LedgerInfoWithSignatures::default()),
+                txn_list_with_proof: Some(txn_list_with_proof.to_bytes().unwrap()),
+            }
+        );
+    }
+
+    // Messages without any content are rejected.
+    #[test]
+    fn test_inbound_empty_msg() {
+        let (mut state_sync_tx, state_sync_rx) = channel::new_test(8);
+        let mut stream = StateSynchronizerEvents::new(state_sync_rx);
+
+        let peer_id = PeerId::random();
+        let event = NetworkNotification::RecvMessage(
+            peer_id,
+            Message {
+                protocol: ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL),
+                mdata: StateSynchronizerMsg::default().to_bytes().unwrap(),
+            },
+        );
+        block_on(state_sync_tx.send(event)).unwrap();
+
+        let err = block_on(stream.next()).unwrap().unwrap_err();
+        assert_eq!(err.kind(), NetworkErrorKind::DecodeError);
+        assert!(err.is_peer_fault());
+        assert_eq!(err.peer_id(), Some(peer_id));
+    }
 }
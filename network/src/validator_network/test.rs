@@ -4,14 +4,17 @@
 //! Integration tests for validator_network.
 use crate::{
     common::NetworkPublicKeys,
+    interface::NetworkRequest,
     proto::{ConsensusMsg, ConsensusMsg_oneof, MempoolSyncMsg, RequestBlock, RespondBlock},
     utils::MessageExt,
     validator_network::{
         network_builder::{NetworkBuilder, TransportType},
-        Event, CONSENSUS_RPC_PROTOCOL, MEMPOOL_DIRECT_SEND_PROTOCOL,
+        Broadcast, BroadcastPolicy, DeliveryStatus, Event, RpcError, CONSENSUS_RPC_PROTOCOL,
+        MEMPOOL_DIRECT_SEND_PROTOCOL,
     },
     ProtocolId,
 };
+use bytes::Bytes;
 use config::config::RoleType;
 use crypto::{ed25519::compat, test_utils::TEST_SEED, traits::ValidKey, x25519};
 use futures::{
@@ -20,12 +23,14 @@ use futures::{
     StreamExt,
 };
 use parity_multiaddr::Multiaddr;
+use prost::Message as _;
 use rand::{rngs::StdRng, SeedableRng};
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     time::Duration,
 };
+use tls::TlsConfig;
 use tokio::runtime::Runtime;
 use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
@@ -217,6 +222,126 @@ fn test_mempool_sync() {
     block_on(join(f_dialer, f_listener));
 }
 
+// Test that validators connected over TLS are bound to the PeerId of the network signing key
+// their certificate is for.
+#[test]
+fn test_tls_mempool_sync() {
+    ::logger::try_init_for_testing();
+    let runtime = Runtime::new().unwrap();
+    let mempool_sync_protocol = ProtocolId::from_static(MEMPOOL_DIRECT_SEND_PROTOCOL);
+
+    let listener_peer_id = PeerId::random();
+    let dialer_peer_id = PeerId::random();
+    let mut rng = StdRng::from_seed(TEST_SEED);
+    let (listener_signing_private_key, listener_signing_public_key) =
+        compat::generate_keypair(&mut rng);
+    let (dialer_signing_private_key, dialer_signing_public_key) =
+        compat::generate_keypair(&mut rng);
+    let (_, listener_identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+    let (_, dialer_identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+    let listener_tls_config = TlsConfig::new(&listener_signing_private_key).unwrap();
+    let dialer_tls_config = TlsConfig::new(&dialer_signing_private_key).unwrap();
+
+    let trusted_peers: HashMap<_, _> = vec![
+        (
+            listener_peer_id,
+            NetworkPublicKeys {
+                signing_public_key: listener_signing_public_key.clone(),
+                identity_public_key: listener_identity_public_key,
+            },
+        ),
+        (
+            dialer_peer_id,
+            NetworkPublicKeys {
+                signing_public_key: dialer_signing_public_key.clone(),
+                identity_public_key: dialer_identity_public_key,
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    // Set up the listener network
+    let listener_addr: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    let (listener_addr, mut network_provider) = NetworkBuilder::new(
+        runtime.executor(),
+        listener_peer_id,
+        listener_addr,
+        RoleType::Validator,
+    )
+    .signing_keys((listener_signing_private_key, listener_signing_public_key))
+    .trusted_peers(trusted_peers.clone())
+    .transport(TransportType::TcpTls(Some(listener_tls_config)))
+    .channel_size(8)
+    .direct_send_protocols(vec![mempool_sync_protocol.clone()])
+    .build();
+    let (_, mut listener_mp_net_events) =
+        network_provider.add_mempool(vec![mempool_sync_protocol.clone()]);
+    runtime
+        .executor()
+        .spawn(network_provider.start().unit_error().compat());
+
+    // Set up the dialer network
+    let dialer_addr: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    let (_dialer_addr, mut network_provider) = NetworkBuilder::new(
+        runtime.executor(),
+        dialer_peer_id,
+        dialer_addr,
+        RoleType::Validator,
+    )
+    .signing_keys((dialer_signing_private_key, dialer_signing_public_key))
+    .trusted_peers(trusted_peers)
+    .transport(TransportType::TcpTls(Some(dialer_tls_config)))
+    .seed_peers(
+        [(listener_peer_id, vec![listener_addr])]
+            .iter()
+            .cloned()
+            .collect(),
+    )
+    .channel_size(8)
+    .direct_send_protocols(vec![mempool_sync_protocol.clone()])
+    .build();
+    let (mut dialer_mp_net_sender, mut dialer_mp_net_events) =
+        network_provider.add_mempool(vec![mempool_sync_protocol.clone()]);
+    runtime
+        .executor()
+        .spawn(network_provider.start().unit_error().compat());
+
+    let mut mempool_msg = MempoolSyncMsg::default();
+    mempool_msg.peer_id = dialer_peer_id.into();
+
+    let f_dialer = async move {
+        match dialer_mp_net_events.next().await.unwrap().unwrap() {
+            Event::NewPeer(peer_id) => {
+                assert_eq!(peer_id, listener_peer_id);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        dialer_mp_net_sender
+            .send_to(listener_peer_id, mempool_msg)
+            .await
+            .unwrap();
+    };
+
+    let f_listener = async move {
+        match listener_mp_net_events.next().await.unwrap().unwrap() {
+            Event::NewPeer(peer_id) => {
+                assert_eq!(peer_id, dialer_peer_id);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        match listener_mp_net_events.next().await.unwrap().unwrap() {
+            Event::Message((peer_id, msg)) => {
+                assert_eq!(peer_id, dialer_peer_id);
+                assert_eq!(msg.peer_id, Vec::from(&dialer_peer_id));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    };
+
+    block_on(join(f_dialer, f_listener));
+}
+
 // Test that a permissioned end-point can connect to a permission-less end-point if both are
 // correctly configured.
 #[test]
@@ -493,7 +618,7 @@ fn test_consensus_rpc() {
 
         // The listener then handles the RequestBlock rpc request.
         match listener_con_net_events.next().await.unwrap().unwrap() {
-            Event::RpcRequest((peer_id, req_msg, res_tx)) => {
+            Event::RpcRequest((peer_id, req_msg, res_tx, _)) => {
                 assert_eq!(peer_id, dialer_peer_id);
 
                 // Check the request
@@ -515,3 +640,262 @@ fn test_consensus_rpc() {
 
     block_on(join(f_dialer, f_listener));
 }
+
+// Test that applications can run protocols of their own, both direct-send and RPC, through the
+// handlers registered with the NetworkBuilder.
+#[test]
+fn test_protocol_handler() {
+    ::logger::try_init_for_testing();
+    let runtime = Runtime::new().unwrap();
+    let ds_protocol = ProtocolId::from_static(b"/test/side-channel/direct-send/0.1.0");
+    let rpc_protocol = ProtocolId::from_static(b"/test/side-channel/rpc/0.1.0");
+
+    let listener_peer_id = PeerId::random();
+    let dialer_peer_id = PeerId::random();
+    let mut rng = StdRng::from_seed(TEST_SEED);
+    let (listener_signing_private_key, listener_signing_public_key) =
+        compat::generate_keypair(&mut rng);
+    let (dialer_signing_private_key, dialer_signing_public_key) =
+        compat::generate_keypair(&mut rng);
+    let (_, listener_identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+    let (_, dialer_identity_public_key) = x25519::compat::generate_keypair(&mut rng);
+
+    let trusted_peers: HashMap<_, _> = vec![
+        (
+            listener_peer_id,
+            NetworkPublicKeys {
+                signing_public_key: listener_signing_public_key.clone(),
+                identity_public_key: listener_identity_public_key,
+            },
+        ),
+        (
+            dialer_peer_id,
+            NetworkPublicKeys {
+                signing_public_key: dialer_signing_public_key.clone(),
+                identity_public_key: dialer_identity_public_key,
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    // Set up the listener network
+    let listener_addr: Multiaddr = "/memory/0".parse().unwrap();
+    let (listener_addr, mut network_provider) = NetworkBuilder::new(
+        runtime.executor(),
+        listener_peer_id,
+        listener_addr,
+        RoleType::Validator,
+    )
+    .signing_keys((listener_signing_private_key, listener_signing_public_key))
+    .trusted_peers(trusted_peers.clone())
+    .transport(TransportType::Memory)
+    .channel_size(8)
+    .add_protocol_handler(ds_protocol.clone(), 8)
+    .add_rpc_protocol_handler(rpc_protocol.clone(), 8)
+    .build();
+    let (_, mut listener_ds_events) = network_provider.protocol_handler(ds_protocol.clone());
+    let (_, mut listener_rpc_events) = network_provider.protocol_handler(rpc_protocol.clone());
+    runtime
+        .executor()
+        .spawn(network_provider.start().unit_error().compat());
+
+    // Set up the dialer network
+    let dialer_addr: Multiaddr = "/memory/0".parse().unwrap();
+    let (_dialer_addr, mut network_provider) = NetworkBuilder::new(
+        runtime.executor(),
+        dialer_peer_id,
+        dialer_addr,
+        RoleType::Validator,
+    )
+    .transport(TransportType::Memory)
+    .signing_keys((dialer_signing_private_key, dialer_signing_public_key))
+    .trusted_peers(trusted_peers)
+    .seed_peers(
+        [(listener_peer_id, vec![listener_addr])]
+            .iter()
+            .cloned()
+            .collect(),
+    )
+    .channel_size(8)
+    .add_protocol_handler(ds_protocol.clone(), 8)
+    .add_rpc_protocol_handler(rpc_protocol.clone(), 8)
+    .build();
+    let (mut dialer_ds_sender, mut dialer_ds_events) =
+        network_provider.protocol_handler(ds_protocol);
+    let (mut dialer_rpc_sender, _) = network_provider.protocol_handler(rpc_protocol);
+    runtime
+        .executor()
+        .spawn(network_provider.start().unit_error().compat());
+
+    let f_dialer = async move {
+        // Wait until dialing finished and NewPeer event received
+        match dialer_ds_events.next().await.unwrap().unwrap() {
+            Event::NewPeer(peer_id) => {
+                assert_eq!(peer_id, listener_peer_id);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        dialer_ds_sender
+            .send_to(listener_peer_id, Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        let response = dialer_rpc_sender
+            .send_rpc(
+                listener_peer_id,
+                Bytes::from_static(b"ping"),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, Bytes::from_static(b"pong"));
+    };
+
+    let f_listener = async move {
+        match listener_ds_events.next().await.unwrap().unwrap() {
+            Event::NewPeer(peer_id) => {
+                assert_eq!(peer_id, dialer_peer_id);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        match listener_ds_events.next().await.unwrap().unwrap() {
+            Event::Message((peer_id, msg)) => {
+                assert_eq!(peer_id, dialer_peer_id);
+                assert_eq!(msg, Bytes::from_static(b"hello"));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        // Each handler gets the connection events, as well as its own messages.
+        match listener_rpc_events.next().await.unwrap().unwrap() {
+            Event::NewPeer(peer_id) => {
+                assert_eq!(peer_id, dialer_peer_id);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        match listener_rpc_events.next().await.unwrap().unwrap() {
+            Event::RpcRequest((peer_id, req, res_tx, _)) => {
+                assert_eq!(peer_id, dialer_peer_id);
+                assert_eq!(req, Bytes::from_static(b"ping"));
+                res_tx.send(Ok(Bytes::from_static(b"pong"))).unwrap();
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    };
+
+    block_on(join(f_dialer, f_listener));
+}
+
+#[test]
+fn test_broadcast_quorum() {
+    ::logger::try_init_for_testing();
+    let runtime = Runtime::new().unwrap();
+    let peers = vec![PeerId::random(), PeerId::random(), PeerId::random()];
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let msg = ConsensusMsg {
+        message: Some(ConsensusMsg_oneof::RequestBlock(RequestBlock::default())),
+    };
+    let mut broadcast = Broadcast::new(
+        network_reqs_tx,
+        peers.clone(),
+        ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL),
+        &msg,
+        BroadcastPolicy {
+            quorum_fraction: 2.0 / 3.0,
+            attempt_timeout: Duration::from_secs(1),
+            max_attempts: 2,
+            retry_delay: Duration::from_millis(10),
+            connect_timeout: Duration::from_secs(1),
+        },
+    )
+    .unwrap();
+    assert_eq!(broadcast.quorum_size(), 2);
+
+    let (slow_peer, failing_peer) = (peers[0], peers[1]);
+    let f_network = async move {
+        let mut slow_req = None;
+        // The failing peer fails both attempts and the last peer acknowledges right away.
+        for _ in 0..4 {
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(peer_id, req) => {
+                    assert_eq!(ConsensusMsg::decode(req.data.as_ref()).unwrap(), msg);
+                    if peer_id == slow_peer {
+                        slow_req = Some(req);
+                    } else if peer_id == failing_peer {
+                        req.res_tx.send(Err(RpcError::TimedOut)).unwrap();
+                    } else {
+                        req.res_tx.send(Ok(Bytes::new())).unwrap();
+                    }
+                }
+                req => panic!("Unexpected NetworkRequest: {:?}", req),
+            }
+        }
+        // The slow peer acknowledges last, making a quorum.
+        slow_req.unwrap().res_tx.send(Ok(Bytes::new())).unwrap();
+    };
+
+    let f = async move {
+        let (reached, ()) = join(broadcast.quorum(), f_network).await;
+        assert!(reached);
+        broadcast.complete().await;
+        assert_eq!(broadcast.num_acked(), 2);
+        assert_eq!(broadcast.statuses()[&slow_peer], DeliveryStatus::Acked);
+        assert_eq!(broadcast.statuses()[&failing_peer], DeliveryStatus::Failed);
+    };
+    runtime
+        .block_on_all(f.boxed().unit_error().compat())
+        .unwrap();
+}
+
+#[test]
+fn test_broadcast_waits_for_connection() {
+    ::logger::try_init_for_testing();
+    let runtime = Runtime::new().unwrap();
+    let peer = PeerId::random();
+    let (network_reqs_tx, mut network_reqs_rx) = channel::new_test(8);
+    let msg = ConsensusMsg {
+        message: Some(ConsensusMsg_oneof::RequestBlock(RequestBlock::default())),
+    };
+    let mut broadcast = Broadcast::new(
+        network_reqs_tx,
+        vec![peer],
+        ProtocolId::from_static(CONSENSUS_RPC_PROTOCOL),
+        &msg,
+        BroadcastPolicy {
+            quorum_fraction: 1.0,
+            attempt_timeout: Duration::from_secs(1),
+            max_attempts: 1,
+            retry_delay: Duration::from_millis(10),
+            connect_timeout: Duration::from_secs(5),
+        },
+    )
+    .unwrap();
+
+    let f_network = async move {
+        // The peer isn't connected for the first two requests, which don't use up its only
+        // attempt.
+        for response in vec![
+            Err(RpcError::NotConnected(peer)),
+            Err(RpcError::NotConnected(peer)),
+            Ok(Bytes::new()),
+        ] {
+            match network_reqs_rx.next().await.unwrap() {
+                NetworkRequest::SendRpc(peer_id, req) => {
+                    assert_eq!(peer_id, peer);
+                    req.res_tx.send(response).unwrap();
+                }
+                req => panic!("Unexpected NetworkRequest: {:?}", req),
+            }
+        }
+    };
+
+    let f = async move {
+        let (reached, ()) = join(broadcast.quorum(), f_network).await;
+        assert!(reached);
+        assert_eq!(broadcast.statuses()[&peer], DeliveryStatus::Acked);
+    };
+    runtime
+        .block_on_all(f.boxed().unit_error().compat())
+        .unwrap();
+}
@@ -0,0 +1,283 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! TLS 1.3 support for use in Libra, as an alternative to Noise for deployments which need
+//! standard TLS on the wire, e.g. to go through middleboxes.
+//!
+//! Each node authenticates with a self-signed certificate for its network signing key, an
+//! Ed25519 key. Certificates are not checked against any authority: a connection binds the remote
+//! to the key its certificate holds, and it is up to the caller to map that key to a `PeerId`, as
+//! it does with the static key of a Noise handshake.
+
+use crypto::ed25519::Ed25519PrivateKey;
+use futures::io::{AsyncRead, AsyncWrite};
+use netcore::transport::ConnectionOrigin;
+use rustls::{
+    Certificate, ClientCertVerified, ClientCertVerifier, ClientConfig, ClientSession,
+    DistinguishedNames, PrivateKey, ProtocolVersion, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, ServerConfig, ServerSession, TLSError,
+};
+use std::{io, sync::Arc};
+use webpki::{DNSName, DNSNameRef};
+
+mod socket;
+
+pub use self::socket::TlsSocket;
+
+/// Name all the certificates are issued for. Peers are not told apart by name but by key.
+const SERVER_NAME: &str = "libra-node";
+
+/// PKCS#8 (v1) encoding of an Ed25519 private key, less the 32 bytes of the key itself.
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Contents of the DER encoding of an Ed25519 SubjectPublicKeyInfo, less the 32 bytes of the key
+/// itself.
+const ED25519_SPKI_PREFIX: [u8; 10] = [0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+const DER_SEQUENCE: u8 = 0x30;
+/// Tag of the explicit, optional, version of a certificate.
+const DER_VERSION: u8 = 0xa0;
+
+/// The TLS configuration to be used to perform a protocol upgrade on an underlying socket.
+pub struct TlsConfig {
+    client_config: Arc<ClientConfig>,
+    server_config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Create a new TlsConfig, authenticating with a self-signed certificate for `signing_key`.
+    pub fn new(signing_key: &Ed25519PrivateKey) -> io::Result<Self> {
+        let (cert, key) = self_signed_cert(signing_key)?;
+
+        let mut client_config = ClientConfig::new();
+        client_config.versions = vec![ProtocolVersion::TLSv1_3];
+        client_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AnyEd25519Cert));
+        client_config
+            .set_single_client_cert(vec![cert.clone()], key.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut server_config = ServerConfig::new(Arc::new(AnyEd25519Cert));
+        server_config.versions = vec![ProtocolVersion::TLSv1_3];
+        server_config
+            .set_single_cert(vec![cert], key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        Ok(Self {
+            client_config: Arc::new(client_config),
+            server_config: Arc::new(server_config),
+        })
+    }
+
+    /// Perform a protocol upgrade on an underlying connection: run a TLS 1.3 handshake, in which
+    /// both ends present their certificate. Upon success, returns the Ed25519 public key of the
+    /// remote as well as a TlsSocket.
+    pub async fn upgrade_connection<TSocket>(
+        &self,
+        socket: TSocket,
+        origin: ConnectionOrigin,
+    ) -> io::Result<(Vec<u8>, TlsSocket<TSocket>)>
+    where
+        TSocket: AsyncRead + AsyncWrite + Unpin,
+    {
+        let session: Box<dyn rustls::Session> = match origin {
+            ConnectionOrigin::Inbound => Box::new(ServerSession::new(&self.server_config)),
+            ConnectionOrigin::Outbound => Box::new(ClientSession::new(
+                &self.client_config,
+                DNSNameRef::try_from_ascii_str(SERVER_NAME).expect("Invalid server name"),
+            )),
+        };
+        let socket = TlsSocket::handshake(socket, session).await?;
+
+        // Both verifiers only accept certificates holding an Ed25519 key, and the handshake
+        // proved that the remote holds the matching private key.
+        let remote_public_key = socket
+            .session()
+            .get_peer_certificates()
+            .and_then(|certs| certs.first().and_then(ed25519_public_key))
+            .expect("TLS remote certificate not verified");
+        Ok((remote_public_key.to_vec(), socket))
+    }
+}
+
+/// Builds a self-signed certificate for `signing_key`, and the matching private key for rustls.
+fn self_signed_cert(signing_key: &Ed25519PrivateKey) -> io::Result<(Certificate, PrivateKey)> {
+    let to_io_error = |e: rcgen::RcgenError| io::Error::new(io::ErrorKind::InvalidInput, e);
+
+    let mut pkcs8 = ED25519_PKCS8_PREFIX.to_vec();
+    pkcs8.extend_from_slice(&signing_key.to_bytes());
+    let mut params = rcgen::CertificateParams::new(vec![SERVER_NAME.to_string()]);
+    params.alg = &rcgen::PKCS_ED25519;
+    params.key_pair = Some(rcgen::KeyPair::from_der(&pkcs8).map_err(to_io_error)?);
+    let cert = rcgen::Certificate::from_params(params).map_err(to_io_error)?;
+    let cert_der = cert.serialize_der().map_err(to_io_error)?;
+    Ok((Certificate(cert_der), PrivateKey(pkcs8)))
+}
+
+/// Returns the Ed25519 public key a DER encoded certificate is for, if it is for one.
+///
+/// The certificate is walked down to its SubjectPublicKeyInfo rather than searched for an
+/// Ed25519 key: other fields of the certificate, such as its subject, are under the control of
+/// its holder and may contain anything.
+fn ed25519_public_key(cert: &Certificate) -> Option<[u8; 32]> {
+    let (tag, cert, _) = der_element(&cert.0)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    let (tag, mut tbs_cert, _) = der_element(cert)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    let (tag, _, rest) = der_element(tbs_cert)?;
+    if tag == DER_VERSION {
+        tbs_cert = rest;
+    }
+    // Skip the serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        tbs_cert = der_element(tbs_cert)?.2;
+    }
+    let (tag, spki, _) = der_element(tbs_cert)?;
+    if tag != DER_SEQUENCE
+        || spki.len() != ED25519_SPKI_PREFIX.len() + 32
+        || !spki.starts_with(&ED25519_SPKI_PREFIX)
+    {
+        return None;
+    }
+    let mut public_key = [0; 32];
+    public_key.copy_from_slice(&spki[ED25519_SPKI_PREFIX.len()..]);
+    Some(public_key)
+}
+
+/// Splits the DER element at the start of `der` into its tag, its contents and the bytes which
+/// follow it.
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, der) = der.split_first()?;
+    let (&len, der) = der.split_first()?;
+    let (len, der) = if len < 0x80 {
+        (len as usize, der)
+    } else {
+        // Long form: the low bits tell the number of bytes of the length.
+        let len_bytes = (len & 0x7f) as usize;
+        if len_bytes == 0 || len_bytes > 4 || der.len() < len_bytes {
+            return None;
+        }
+        let len = der[..len_bytes]
+            .iter()
+            .fold(0, |len, &byte| (len << 8) | byte as usize);
+        (len, &der[len_bytes..])
+    };
+    if der.len() < len {
+        return None;
+    }
+    Some((tag, &der[..len], &der[len..]))
+}
+
+fn verify_cert(presented_certs: &[Certificate]) -> Result<(), TLSError> {
+    presented_certs
+        .first()
+        .and_then(ed25519_public_key)
+        .map(|_| ())
+        .ok_or_else(|| TLSError::General("Certificate is not for an Ed25519 key".to_string()))
+}
+
+/// Accepts any certificate for an Ed25519 key, on both ends of a connection. Whether the holder
+/// of the key is trusted is decided once the handshake completes.
+struct AnyEd25519Cert;
+
+impl ServerCertVerifier for AnyEd25519Cert {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        verify_cert(presented_certs).map(|_| ServerCertVerified::assertion())
+    }
+}
+
+impl ClientCertVerifier for AnyEd25519Cert {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self, _sni: Option<&DNSName>) -> Option<bool> {
+        Some(true)
+    }
+
+    fn client_auth_root_subjects(&self, _sni: Option<&DNSName>) -> Option<DistinguishedNames> {
+        Some(DistinguishedNames::new())
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[Certificate],
+        _sni: Option<&DNSName>,
+    ) -> Result<ClientCertVerified, TLSError> {
+        verify_cert(presented_certs).map(|_| ClientCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::ed25519::{compat, Ed25519PublicKey};
+    use futures::{
+        executor::block_on,
+        future::join,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+    use memsocket::MemorySocket;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn generate_keypair(seed: u8) -> (Ed25519PrivateKey, Ed25519PublicKey) {
+        compat::generate_keypair(&mut StdRng::from_seed([seed; 32]))
+    }
+
+    #[test]
+    fn cert_public_key() {
+        let (private_key, public_key) = generate_keypair(0);
+        let (cert, _) = self_signed_cert(&private_key).unwrap();
+        assert_eq!(ed25519_public_key(&cert), Some(public_key.to_bytes()));
+
+        // Truncated certificates are rejected rather than misread.
+        let truncated = Certificate(cert.0[..cert.0.len() / 2].to_vec());
+        assert_eq!(ed25519_public_key(&truncated), None);
+    }
+
+    #[test]
+    fn handshake_and_transfer() {
+        let (dialer_private_key, dialer_public_key) = generate_keypair(0);
+        let (listener_private_key, listener_public_key) = generate_keypair(1);
+        let dialer_config = TlsConfig::new(&dialer_private_key).unwrap();
+        let listener_config = TlsConfig::new(&listener_private_key).unwrap();
+        let (dialer_socket, listener_socket) = MemorySocket::new_pair();
+
+        let (dialer, listener) = block_on(join(
+            dialer_config.upgrade_connection(dialer_socket, ConnectionOrigin::Outbound),
+            listener_config.upgrade_connection(listener_socket, ConnectionOrigin::Inbound),
+        ));
+        let (dialer_remote_key, mut dialer) = dialer.unwrap();
+        let (listener_remote_key, mut listener) = listener.unwrap();
+        assert_eq!(dialer_remote_key, listener_public_key.to_bytes().to_vec());
+        assert_eq!(listener_remote_key, dialer_public_key.to_bytes().to_vec());
+
+        let msg = vec![7; 100_000];
+        block_on(async {
+            let write = async {
+                dialer.write_all(&msg).await.unwrap();
+                dialer.close().await.unwrap();
+            };
+            let read = async {
+                let mut received = vec![];
+                listener.read_to_end(&mut received).await.unwrap();
+                received
+            };
+            let ((), received) = join(write, read).await;
+            assert_eq!(received, msg);
+        });
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! TLS Socket
+
+use futures::{
+    future::poll_fn,
+    io::{AsyncRead, AsyncWrite},
+    ready,
+};
+use rustls::Session;
+use std::{
+    io::{self, Read, Write},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Adapts an async socket to the blocking `Read` and `Write` traits rustls works with. The socket
+/// not being ready is reported as an `io::ErrorKind::WouldBlock` error, after `context` has been
+/// registered to be woken up once it is.
+struct SyncAdapter<'a, 'b, TSocket> {
+    socket: &'a mut TSocket,
+    context: &'a mut Context<'b>,
+}
+
+impl<'a, 'b, TSocket> Read for SyncAdapter<'a, 'b, TSocket>
+where
+    TSocket: AsyncRead + Unpin,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match Pin::new(&mut *self.socket).poll_read(self.context, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<'a, 'b, TSocket> Write for SyncAdapter<'a, 'b, TSocket>
+where
+    TSocket: AsyncWrite + Unpin,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match Pin::new(&mut *self.socket).poll_write(self.context, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match Pin::new(&mut *self.socket).poll_flush(self.context) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+/// A socket whose traffic is encrypted and authenticated by a TLS session.
+pub struct TlsSocket<TSocket> {
+    socket: TSocket,
+    session: Box<dyn Session>,
+    /// Whether the remote closed its side of the session, or the socket reached EOF.
+    read_closed: bool,
+    /// Whether we sent a close_notify alert to the remote.
+    write_closed: bool,
+}
+
+impl<TSocket> TlsSocket<TSocket>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Runs the handshake of `session` over `socket`.
+    pub(crate) async fn handshake(socket: TSocket, session: Box<dyn Session>) -> io::Result<Self> {
+        let mut socket = Self {
+            socket,
+            session,
+            read_closed: false,
+            write_closed: false,
+        };
+        poll_fn(|context| socket.poll_handshake(context)).await?;
+        Ok(socket)
+    }
+
+    /// The TLS session of the socket.
+    pub fn session(&self) -> &dyn Session {
+        self.session.as_ref()
+    }
+
+    fn poll_handshake(&mut self, context: &mut Context) -> Poll<io::Result<()>> {
+        while self.session.is_handshaking() {
+            // Send out whatever the last records we read call for, before waiting for more.
+            ready!(self.poll_write_tls(context))?;
+            if !self.session.is_handshaking() {
+                break;
+            }
+            match self.read_tls(context) {
+                Ok(0) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Connection closed during the TLS handshake",
+                    )));
+                }
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        // The last flight of the handshake may still be buffered.
+        self.poll_write_tls(context)
+    }
+
+    /// Reads TLS records from the socket and processes them. Returns the number of bytes read, 0
+    /// on EOF.
+    fn read_tls(&mut self, context: &mut Context) -> io::Result<usize> {
+        let n = self.session.read_tls(&mut SyncAdapter {
+            socket: &mut self.socket,
+            context,
+        })?;
+        if let Err(e) = self.session.process_new_packets() {
+            // Best effort to let the remote know why the session is over.
+            let _ = self.poll_write_tls(context);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+        Ok(n)
+    }
+
+    /// Writes all the buffered TLS records to the socket.
+    fn poll_write_tls(&mut self, context: &mut Context) -> Poll<io::Result<()>> {
+        while self.session.wants_write() {
+            match self.session.write_tls(&mut SyncAdapter {
+                socket: &mut self.socket,
+                context,
+            }) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<TSocket> AsyncRead for TlsSocket<TSocket>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.read_closed {
+                return Poll::Ready(Ok(0));
+            }
+            match this.session.read(buf) {
+                // No plaintext is buffered, more records need to be read.
+                Ok(0) if !buf.is_empty() => {}
+                // The remote sent a close_notify alert.
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => {
+                    this.read_closed = true;
+                    continue;
+                }
+                result => return Poll::Ready(result),
+            }
+            match this.read_tls(context) {
+                Ok(0) => this.read_closed = true,
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl<TSocket> AsyncWrite for TlsSocket<TSocket>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let n = this.session.write(buf)?;
+            if n > 0 || buf.is_empty() {
+                // The records are written out as far as the socket allows, the rest on the next
+                // write or flush.
+                if let Poll::Ready(Err(e)) = this.poll_write_tls(context) {
+                    return Poll::Ready(Err(e));
+                }
+                return Poll::Ready(Ok(n));
+            }
+            // The session buffers are full, make room.
+            ready!(this.poll_write_tls(context))?;
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_tls(context))?;
+        Pin::new(&mut this.socket).poll_flush(context)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.write_closed {
+            this.session.send_close_notify();
+            this.write_closed = true;
+        }
+        ready!(this.poll_write_tls(context))?;
+        Pin::new(&mut this.socket).poll_close(context)
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Limits on the chunks a node serves to its peers, so that public full nodes serving the whole
+//! history of the ledger are not drained by anonymous peers syncing it over and over.
+//!
+//! Peers are identified by the PeerId the network authenticated during the Noise handshake of
+//! their connection. The authorized peers of the config are served without limit. The other ones
+//! are refused chunks once serving a chunk would exceed their daily byte budget, or the daily byte
+//! budget shared by all of them, which new identities don't renew, if any. They are refused chunks
+//! altogether if the node only serves authorized peers.
+
+use crate::{counters, PeerId};
+use config::config::StateSyncConfig;
+use failure::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const BUDGET_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub(crate) struct ChunkQuota {
+    authorized_peers: HashSet<PeerId>,
+    require_auth: bool,
+    daily_byte_budget: Option<u64>,
+    total_daily_byte_budget: Option<u64>,
+    usage: Mutex<Usage>,
+}
+
+/// Bytes served to the unauthorized peers since the start of their current window
+struct Usage {
+    per_peer: HashMap<PeerId, (Instant, u64)>,
+    total: (Instant, u64),
+}
+
+impl ChunkQuota {
+    pub fn new(config: &StateSyncConfig) -> Self {
+        Self {
+            authorized_peers: config
+                .get_chunk_serving_authorized_peers()
+                .into_iter()
+                .collect(),
+            require_auth: config.chunk_serving_require_auth,
+            daily_byte_budget: config.chunk_serving_daily_byte_budget,
+            total_daily_byte_budget: config.chunk_serving_total_daily_byte_budget,
+            usage: Mutex::new(Usage {
+                per_peer: HashMap::new(),
+                total: (Instant::now(), 0),
+            }),
+        }
+    }
+
+    /// Whether `peer_id` is served chunks without limit.
+    pub fn is_authorized(&self, peer_id: &PeerId) -> bool {
+        self.authorized_peers.contains(peer_id)
+    }
+
+    /// Checks that `peer_id` may be served a chunk at time `now`, before the chunk is read.
+    pub fn check(&self, peer_id: PeerId, now: Instant) -> Result<()> {
+        self.try_charge(peer_id, 0, now)
+    }
+
+    /// Records that a chunk of `bytes` is served to `peer_id` at time `now`, unless that would
+    /// exceed one of its budgets.
+    pub fn try_charge(&self, peer_id: PeerId, bytes: u64, now: Instant) -> Result<()> {
+        if self.is_authorized(&peer_id) {
+            counters::CHUNK_BYTES_SERVED.inc_by(bytes as i64);
+            return Ok(());
+        }
+        if self.require_auth {
+            counters::CHUNK_REQUESTS_UNAUTHENTICATED.inc();
+            bail!("[state sync] peer {} is not authorized", peer_id);
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        // forget the peers whose window is over, so that the map does not grow with every peer
+        // ever served
+        usage
+            .per_peer
+            .retain(|_, (window_start, _)| now.duration_since(*window_start) < BUDGET_WINDOW);
+        if now.duration_since(usage.total.0) >= BUDGET_WINDOW {
+            usage.total = (now, 0);
+        }
+        let used = usage.per_peer.get(&peer_id).map_or(0, |(_, used)| *used);
+        let total_used = usage.total.1;
+        for (used, budget) in &[
+            (used, self.daily_byte_budget),
+            (total_used, self.total_daily_byte_budget),
+        ] {
+            if let Some(budget) = budget {
+                // a request is refused once the budget is exhausted, and a chunk once serving it
+                // would exceed the budget
+                if *used >= *budget || *used + bytes > *budget {
+                    counters::CHUNK_REQUESTS_OVER_QUOTA.inc();
+                    bail!(
+                        "[state sync] peer {} can't be served {} more bytes, {} of the daily budget of {} used",
+                        peer_id,
+                        bytes,
+                        used,
+                        budget
+                    );
+                }
+            }
+        }
+
+        counters::CHUNK_BYTES_SERVED.inc_by(bytes as i64);
+        if self.daily_byte_budget.is_some() && bytes > 0 {
+            usage.per_peer.entry(peer_id).or_insert((now, 0)).1 += bytes;
+        }
+        usage.total.1 += bytes;
+        Ok(())
+    }
+}
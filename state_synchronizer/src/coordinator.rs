@@ -2,31 +2,39 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    chunk_quota::ChunkQuota,
     counters,
     executor_proxy::ExecutorProxyTrait,
     peer_manager::{PeerManager, PeerScoreUpdateType},
     LedgerInfo, PeerId,
 };
-use config::config::StateSyncConfig;
+use bytes::Bytes;
+use config::config::{StateSyncConfig, UpstreamConfig};
+use disk_monitor::DiskMonitor;
 use failure::prelude::*;
 use futures::{
     channel::{mpsc, oneshot},
     compat::Stream01CompatExt,
+    future::{BoxFuture, FutureExt},
     stream::{futures_unordered::FuturesUnordered, select_all},
     StreamExt,
 };
 use logger::prelude::*;
 use network::{
     proto::{GetChunkRequest, GetChunkResponse, StateSynchronizerMsg, StateSynchronizerMsg_oneof},
-    validator_network::{Event, StateSynchronizerEvents, StateSynchronizerSender},
+    validator_network::{
+        Event, RawChunkResponse, RpcError, RpcPolicy, StateSynchronizerEvents,
+        StateSynchronizerInboundMsg, StateSynchronizerSender,
+    },
 };
+use prost::Message;
 use std::{
     collections::HashMap,
     convert::TryInto,
-    str::FromStr,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::timer::Interval;
+use trusted_ledger::TrustedLedger;
 use types::{crypto_proxies::LedgerInfoWithSignatures, transaction::TransactionListWithProof};
 
 /// message used by StateSyncClient for communication with Coordinator
@@ -38,6 +46,16 @@ pub enum CoordinatorMessage {
     GetState(oneshot::Sender<u64>),
 }
 
+/// chunk requested over rpc, resolving to the peer it was requested from first along with the
+/// peer which responded and its chunk
+type ChunkRpcRequest = BoxFuture<
+    'static,
+    (
+        PeerId,
+        std::result::Result<(PeerId, RawChunkResponse), RpcError>,
+    ),
+>;
+
 /// used to coordinate synchronization process
 /// handles external sync requests and drives synchronization with remote peers
 pub(crate) struct SyncCoordinator<T> {
@@ -60,25 +78,28 @@ pub(crate) struct SyncCoordinator<T> {
     // peer will be notified about new chunk of transactions if it's available before expiry time
     // value format is (expiration_time, known_version, limit)
     subscriptions: HashMap<PeerId, (SystemTime, u64, u64)>,
+    // limits on the chunks served to peers
+    chunk_quota: ChunkQuota,
     executor_proxy: T,
+    // latest ledger info known to be committed, shared with the other components of the node
+    trusted_ledger: TrustedLedger,
+    // no chunks are fetched nor stored while it is in protective mode
+    disk_monitor: DiskMonitor,
+    // chunks requested over rpc, along with the peer each was requested from first
+    pending_chunk_requests: FuturesUnordered<ChunkRpcRequest>,
 }
 
 impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
     pub fn new(
         client_events: mpsc::UnboundedReceiver<CoordinatorMessage>,
         config: StateSyncConfig,
+        upstream_config: &UpstreamConfig,
         executor_proxy: T,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
     ) -> Self {
-        let upstream_peers: Vec<_> = config
-            .upstream_peers
-            .upstream_peers
-            .iter()
-            .map(|peer_id_str| {
-                PeerId::from_str(peer_id_str).unwrap_or_else(|_| {
-                    panic!("Failed to parse peer_id from string: {}", peer_id_str)
-                })
-            })
-            .collect();
+        let (preferred_peers, fallback_peers) = upstream_config.get_sync_peers();
+        let chunk_quota = ChunkQuota::new(&config);
         Self {
             client_events,
             known_version: 0,
@@ -86,11 +107,20 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             config,
             // Note: We use upstream peer ids being non-empty as a proxy for a node being a full
             // node.
-            autosync: !upstream_peers.is_empty(),
-            peer_manager: PeerManager::new(upstream_peers),
+            autosync: !preferred_peers.is_empty() || !fallback_peers.is_empty(),
+            peer_manager: PeerManager::new(
+                preferred_peers,
+                fallback_peers,
+                upstream_config.failover_threshold,
+                Duration::from_millis(upstream_config.failover_retry_interval_ms),
+            ),
             subscriptions: HashMap::new(),
+            chunk_quota,
             callback: None,
             executor_proxy,
+            trusted_ledger,
+            disk_monitor,
+            pending_chunk_requests: FuturesUnordered::new(),
         }
     }
 
@@ -101,6 +131,13 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             .get_latest_version()
             .await
             .expect("[start sync] failed to fetch latest version from storage");
+        logger::context::set_version(self.known_version);
+        match self.executor_proxy.get_latest_ledger_info().await {
+            Ok(ledger_info) => {
+                self.trusted_ledger.update(ledger_info);
+            }
+            Err(e) => error!("[start sync] failed to fetch latest ledger info: {:?}", e),
+        }
 
         let mut interval =
             Interval::new_interval(Duration::from_millis(self.config.tick_interval_ms))
@@ -137,36 +174,63 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
                             match event {
                                 Event::NewPeer(peer_id) => {
                                     debug!("[state sync] new peer {}", peer_id);
-                                    self.peer_manager.enable_peer(peer_id, network_senders[idx].clone());
-                                    self.check_progress().await;
+                                    // The chunks of peers on another chain would never verify.
+                                    if network_senders[idx].is_same_chain(&peer_id) {
+                                        self.peer_manager.enable_peer(peer_id, network_senders[idx].clone());
+                                        self.check_progress().await;
+                                    } else {
+                                        warn!("[state sync] ignoring peer {} on another chain", peer_id);
+                                        counters::PEERS_ON_OTHER_CHAIN.inc();
+                                    }
                                 }
                                 Event::LostPeer(peer_id) => {
                                     debug!("[state sync] lost peer {}", peer_id);
                                     self.peer_manager.disable_peer(&peer_id);
                                 }
-                                Event::Message((peer_id, mut message)) => {
-                                    match message.message.unwrap() {
-                                        StateSynchronizerMsg_oneof::ChunkRequest(request) => {
+                                Event::Message((peer_id, message)) => {
+                                    match message {
+                                        StateSynchronizerInboundMsg::ChunkRequest(request) => {
                                             let known_version = request.known_version;
                                             if let Err(err) = self.process_chunk_request(peer_id, request).await {
                                                 error!("[state sync] failed to serve chunk request to {} with known version {}: {:?}", peer_id, known_version, err);
                                             }
                                         }
-                                        StateSynchronizerMsg_oneof::ChunkResponse(response) => {
-                                            if let Err(err) = self.process_chunk_response(&peer_id, response).await {
-                                                error!("[state sync] failed to process chunk response from {}: {:?}", peer_id, err);
-                                                counters::OP_COUNTERS.inc(&format!("{}.{}", counters::APPLY_CHUNK_FAILURE, peer_id));
-                                            } else {
-                                                self.peer_manager.update_score(&peer_id, PeerScoreUpdateType::Success);
-                                                counters::OP_COUNTERS.inc(&format!("{}.{}", counters::APPLY_CHUNK_SUCCESS, peer_id));
+                                        StateSynchronizerInboundMsg::ChunkResponse(response) => {
+                                            self.apply_chunk_response(peer_id, response).await;
+                                        }
+                                    }
+                                }
+                                Event::RpcRequest((peer_id, message, callback, _)) => {
+                                    match message {
+                                        StateSynchronizerInboundMsg::ChunkRequest(request) => {
+                                            let known_version = request.known_version;
+                                            if let Err(err) = self.process_chunk_rpc_request(peer_id, request, callback).await {
+                                                error!("[state sync] failed to serve chunk rpc request to {} with known version {}: {:?}", peer_id, known_version, err);
                                             }
                                         }
+                                        StateSynchronizerInboundMsg::ChunkResponse(_) => {
+                                            warn!("[state sync] unexpected chunk response rpc from {}", peer_id);
+                                        }
                                     }
                                 }
-                                _ => {}
                             }
                         },
-                        Err(err) => { error!("[state sync] network error {:?}", err); },
+                        Err(err) => {
+                            error!("[state sync] network error {:?}", err);
+                            // Peers sending messages which cannot be decoded are deprioritized,
+                            // as if they had sent an invalid chunk.
+                            if let (true, Some(peer_id)) = (err.is_peer_fault(), err.peer_id()) {
+                                self.peer_manager.update_score(&peer_id, PeerScoreUpdateType::InvalidChunk);
+                            }
+                        },
+                    }
+                },
+                (requested_peer_id, res) = self.pending_chunk_requests.select_next_some() => {
+                    match res {
+                        Ok((peer_id, response)) => self.apply_chunk_response(peer_id, response).await,
+                        Err(err) => {
+                            debug!("[state sync] chunk rpc request to {} failed: {:?}", requested_peer_id, err);
+                        }
                     }
                 },
                 _ = interval.select_next_some() => {
@@ -186,6 +250,10 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
     async fn request_sync(&mut self, target: LedgerInfo, callback: oneshot::Sender<bool>) {
         let requested_version = target.ledger_info().version();
         counters::TARGET_VERSION.set(requested_version as i64);
+        // the target is certified by the validators, so that the network is known to be there
+        self.trusted_ledger
+            .update_network_version(requested_version);
+        logger::context::set_epoch(target.ledger_info().epoch_num());
         self.known_version = self
             .executor_proxy
             .get_latest_version()
@@ -247,6 +315,8 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         }
         self.peer_manager.remove_requests(version);
         counters::COMMITTED_VERSION.set(version as i64);
+        logger::context::set_version(self.known_version);
+        counters::OP_COUNTERS.export_log_context();
     }
 
     fn get_state(&self, callback: oneshot::Sender<u64>) {
@@ -261,30 +331,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         peer_id: PeerId,
         mut request: GetChunkRequest,
     ) -> Result<()> {
-        if request.timeout > self.config.max_timeout_ms
-            || request.limit > self.config.max_chunk_limit
-        {
-            return Err(format_err!(
-                "[state sync] timeout: {:?}, chunk limit: {:?}, but timeout must not exceed {:?} ms, and chunk limit must not exceed {:?}",
-                request.timeout,
-                request.limit,
-                self.config.max_timeout_ms,
-                self.config.max_chunk_limit
-            ));
-        }
-
-        let latest_ledger_info = self.executor_proxy.get_latest_ledger_info().await?;
-        let target = match request
-            .ledger_info_with_sigs
-            .take()
-            .map(TryInto::try_into)
-            .transpose()
-        {
-            Ok(Some(x)) => x,
-            _ => latest_ledger_info.clone(),
-        };
-
-        debug!("[state sync] chunk request: peer_id: {:?}, known_version: {}, latest_ledger_info: {}, target: {}", peer_id, request.known_version, latest_ledger_info.ledger_info().version(), target.ledger_info().version());
+        let target = self.check_chunk_request(peer_id, &mut request).await?;
 
         // if upstream synchronizer doesn't have new data and request timeout is set
         // add peer request into subscription queue
@@ -316,6 +363,74 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         }
     }
 
+    /// Serves a chunk request received over rpc. Such requests are for a sync target, so they are
+    /// answered right away rather than subscribed to.
+    async fn process_chunk_rpc_request(
+        &mut self,
+        peer_id: PeerId,
+        mut request: GetChunkRequest,
+        callback: oneshot::Sender<std::result::Result<Bytes, RpcError>>,
+    ) -> Result<()> {
+        ensure!(
+            request.timeout == 0,
+            "[state sync] long polling chunk requests are not served over rpc"
+        );
+        let target = self.check_chunk_request(peer_id, &mut request).await?;
+        let response = self
+            .get_chunk(peer_id, request.known_version, request.limit, target)
+            .await?;
+        let msg = StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::ChunkResponse(response)),
+        };
+        let mut data = vec![];
+        msg.encode(&mut data)?;
+        if callback.send(Ok(Bytes::from(data))).is_err() {
+            debug!(
+                "[state sync] chunk rpc request of {} answered past its deadline",
+                peer_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the chunk request of `peer_id` against the limits on the chunks served, and returns
+    /// the ledger info the chunk is to be proven against.
+    async fn check_chunk_request(
+        &mut self,
+        peer_id: PeerId,
+        request: &mut GetChunkRequest,
+    ) -> Result<LedgerInfo> {
+        if request.timeout > self.config.max_timeout_ms
+            || request.limit > self.config.max_chunk_limit
+        {
+            return Err(format_err!(
+                "[state sync] timeout: {:?}, chunk limit: {:?}, but timeout must not exceed {:?} ms, and chunk limit must not exceed {:?}",
+                request.timeout,
+                request.limit,
+                self.config.max_timeout_ms,
+                self.config.max_chunk_limit
+            ));
+        }
+
+        // the network authenticated `peer_id` during the Noise handshake of the connection
+        self.chunk_quota.check(peer_id, Instant::now())?;
+
+        let latest_ledger_info = self.latest_ledger_info().await?;
+        let target = match request
+            .ledger_info_with_sigs
+            .take()
+            .map(TryInto::try_into)
+            .transpose()
+        {
+            Ok(Some(x)) => x,
+            _ => latest_ledger_info.clone(),
+        };
+
+        debug!("[state sync] chunk request: peer_id: {:?}, known_version: {}, latest_ledger_info: {}, target: {}", peer_id, request.known_version, latest_ledger_info.ledger_info().version(), target.ledger_info().version());
+
+        Ok(target)
+    }
+
     async fn deliver_chunk(
         &self,
         peer_id: PeerId,
@@ -325,8 +440,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         mut network_sender: StateSynchronizerSender,
     ) -> Result<()> {
         let response = self
-            .executor_proxy
-            .get_chunk(known_version, limit, target)
+            .get_chunk(peer_id, known_version, limit, target)
             .await?;
         let msg = StateSynchronizerMsg {
             message: Some(StateSynchronizerMsg_oneof::ChunkResponse(response)),
@@ -337,18 +451,51 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         Ok(())
     }
 
+    /// Fetches the chunk to serve to `peer_id`, unless that would exceed its quota.
+    async fn get_chunk(
+        &self,
+        peer_id: PeerId,
+        known_version: u64,
+        limit: u64,
+        target: LedgerInfo,
+    ) -> Result<GetChunkResponse> {
+        let response = self
+            .executor_proxy
+            .get_chunk(known_version, limit, target)
+            .await?;
+        self.chunk_quota
+            .try_charge(peer_id, response.encoded_len() as u64, Instant::now())?;
+        Ok(response)
+    }
+
+    /// Applies the chunk received from `peer_id`, and scores the peer accordingly.
+    async fn apply_chunk_response(&mut self, peer_id: PeerId, response: RawChunkResponse) {
+        if let Err(err) = self.process_chunk_response(&peer_id, response).await {
+            error!(
+                "[state sync] failed to process chunk response from {}: {:?}",
+                peer_id, err
+            );
+            counters::OP_COUNTERS.inc(&format!("{}.{}", counters::APPLY_CHUNK_FAILURE, peer_id));
+        } else {
+            self.peer_manager
+                .update_score(&peer_id, PeerScoreUpdateType::Success);
+            counters::OP_COUNTERS.inc(&format!("{}.{}", counters::APPLY_CHUNK_SUCCESS, peer_id));
+        }
+    }
+
     /// processes batch of transactions downloaded from peer
     /// executes transactions, updates progress state, calls callback if some sync is finished
     async fn process_chunk_response(
         &mut self,
         peer_id: &PeerId,
-        response: GetChunkResponse,
+        response: RawChunkResponse,
     ) -> Result<()> {
         counters::OP_COUNTERS.inc(&format!("{}.{}", counters::RESPONSES_RECEIVED, peer_id));
-        let txn_list_with_proof: TransactionListWithProof = response
-            .txn_list_with_proof
-            .ok_or_else(|| format_err!("Missing txn_list_with_proof"))?
-            .try_into()?;
+        let txn_list_with_proof = TransactionListWithProof::from_proto_bytes(
+            response
+                .txn_list_with_proof
+                .ok_or_else(|| format_err!("Missing txn_list_with_proof"))?,
+        )?;
 
         if let Some(version) = txn_list_with_proof.first_transaction_version {
             let has_requested = self.peer_manager.has_requested(version, *peer_id);
@@ -370,6 +517,14 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             }
         }
 
+        // Do not write to storage while the disk is nearly full. The chunk is requested again once
+        // space is freed.
+        if self.disk_monitor.is_protective() {
+            return Err(format_err!(
+                "[state sync] node is low on disk space, dropping chunk"
+            ));
+        }
+
         let previous_version = self.known_version;
         let chunk_size = txn_list_with_proof.len();
         let target: LedgerInfo = response
@@ -410,6 +565,10 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         );
 
         self.executor_proxy.validate_ledger_info(&target)?;
+        // the peer is known to have reached the version of its verified ledger info, which tells
+        // how far this node lags behind
+        self.trusted_ledger
+            .update_network_version(target.ledger_info().version());
 
         self.store_transactions(txn_list_with_proof, target).await?;
 
@@ -421,6 +580,11 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
     /// ensures that StateSynchronizer makes progress
     /// if peer is not responding, issues new sync request
     async fn check_progress(&mut self) {
+        // Sync is paused, so the lack of progress is not the fault of the peers.
+        if self.disk_monitor.is_protective() {
+            debug!("[state sync] node is low on disk space, sync is paused");
+            return;
+        }
         if !self.peer_manager.is_empty() && (self.autosync || self.target.is_some()) {
             let last_request_tst = self
                 .peer_manager
@@ -444,6 +608,9 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
     }
 
     async fn request_next_chunk(&mut self, offset: u64) {
+        if self.disk_monitor.is_protective() {
+            return;
+        }
         if self.autosync || self.known_version + offset < self.target_version() {
             if let Some((peer_id, mut sender)) = self.peer_manager.pick_peer() {
                 let mut req = GetChunkRequest::default();
@@ -468,12 +635,36 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
                     timeout
                 );
 
-                let msg = StateSynchronizerMsg {
-                    message: Some(StateSynchronizerMsg_oneof::ChunkRequest(req)),
-                };
+                match (self.config.chunk_request_hedge_delay_ms, &self.target) {
+                    // the chunks of a sync target are requested over rpc, to hedge the requests
+                    (Some(delay_ms), Some(_)) => {
+                        let mut peers = vec![peer_id];
+                        if let Some((other_peer_id, _)) =
+                            self.peer_manager.pick_other_peer(&peer_id)
+                        {
+                            peers.push(other_peer_id);
+                        }
+                        // the request is given up on once `check_progress` issues a new one
+                        let deadline = Instant::now()
+                            + Duration::from_millis(2 * self.config.tick_interval_ms);
+                        let policy = RpcPolicy::Hedge {
+                            delay: Duration::from_millis(delay_ms),
+                        };
+                        let f_response = async move {
+                            let res = sender.request_chunk(peers, req, deadline, policy).await;
+                            (peer_id, res)
+                        };
+                        self.pending_chunk_requests.push(f_response.boxed());
+                    }
+                    _ => {
+                        let msg = StateSynchronizerMsg {
+                            message: Some(StateSynchronizerMsg_oneof::ChunkRequest(req)),
+                        };
 
-                if sender.send_to(peer_id, msg).await.is_err() {
-                    error!("[state sync] failed to send p2p message");
+                        if sender.send_to(peer_id, msg).await.is_err() {
+                            error!("[state sync] failed to send p2p message");
+                        }
+                    }
                 }
                 counters::OP_COUNTERS.inc(&format!("{}.{}", counters::REQUESTS_SENT, peer_id));
             }
@@ -485,13 +676,39 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         txn_list_with_proof: TransactionListWithProof,
         ledger_info: LedgerInfoWithSignatures,
     ) -> Result<()> {
+        // The ledger info is committed along with the chunk only if the chunk reaches its version.
+        let reaches_ledger_info = match txn_list_with_proof.first_transaction_version {
+            Some(first_version) => {
+                first_version + txn_list_with_proof.len() as u64
+                    == ledger_info.ledger_info().version() + 1
+            }
+            None => false,
+        };
+        let committed_ledger_info = if reaches_ledger_info {
+            Some(ledger_info.clone())
+        } else {
+            None
+        };
         self.executor_proxy
             .execute_chunk(txn_list_with_proof, ledger_info)
-            .await
+            .await?;
+        if let Some(ledger_info) = committed_ledger_info {
+            self.trusted_ledger.update(ledger_info);
+        }
+        Ok(())
+    }
+
+    /// Latest committed ledger info, from the trusted ledger if it holds one, from storage
+    /// otherwise.
+    async fn latest_ledger_info(&self) -> Result<LedgerInfo> {
+        match self.trusted_ledger.latest() {
+            Some(ledger_info) => Ok(ledger_info),
+            None => self.executor_proxy.get_latest_ledger_info().await,
+        }
     }
 
     async fn check_subscriptions(&mut self) -> Result<()> {
-        let ledger_info = self.executor_proxy.get_latest_ledger_info().await?;
+        let ledger_info = self.latest_ledger_info().await?;
         let committed_version = self.known_version;
         let mut ready = vec![];
 
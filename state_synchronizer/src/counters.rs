@@ -30,6 +30,9 @@ pub static ref STATE_SYNC_TXN_REPLAYED: IntCounter = OP_COUNTERS.counter("state_
 /// They are the set of nodes a node can make sync requests to
 pub static ref ACTIVE_UPSTREAM_PEERS: IntGauge = OP_COUNTERS.gauge("active_upstream_peers");
 
+/// Number of times an upstream peer has been failed over after too many failed requests
+pub static ref UPSTREAM_FAILOVERS: IntCounter = OP_COUNTERS.counter("upstream_failovers");
+
 /// Most recent version that has been committed
 pub static ref COMMITTED_VERSION: IntGauge = OP_COUNTERS.gauge("committed_version");
 
@@ -42,4 +45,16 @@ pub static ref TARGET_VERSION: IntGauge = OP_COUNTERS.gauge("target_version");
 
 /// Number of timeouts that occur during sync
 pub static ref TIMEOUT: IntCounter = OP_COUNTERS.counter("timeout");
+
+/// Bytes of chunks served to peers
+pub static ref CHUNK_BYTES_SERVED: IntCounter = OP_COUNTERS.counter("chunk_bytes_served");
+
+/// Number of chunk requests refused because the peer exhausted its daily byte budget
+pub static ref CHUNK_REQUESTS_OVER_QUOTA: IntCounter = OP_COUNTERS.counter("chunk_requests_over_quota");
+
+/// Number of chunk requests refused because the peer is not authorized
+pub static ref CHUNK_REQUESTS_UNAUTHENTICATED: IntCounter = OP_COUNTERS.counter("chunk_requests_unauthenticated");
+
+/// Number of connected peers ignored because they advertised another chain
+pub static ref PEERS_ON_OTHER_CHAIN: IntCounter = OP_COUNTERS.counter("peers_on_other_chain");
 }
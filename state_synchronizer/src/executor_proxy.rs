@@ -6,11 +6,15 @@ use futures::{channel::oneshot, Future, FutureExt};
 use grpcio::EnvBuilder;
 use logger::prelude::*;
 use network::proto::GetChunkResponse;
-use std::{pin::Pin, sync::Arc};
+use std::{
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
 use storage_client::{StorageRead, StorageReadServiceClient};
 use types::{
     crypto_proxies::{LedgerInfoWithSignatures, ValidatorVerifier},
     transaction::TransactionListWithProof,
+    validator_set::ValidatorSet,
 };
 use vm_runtime::MoveVM;
 
@@ -43,7 +47,9 @@ pub trait ExecutorProxyTrait: Sync + Send {
 pub(crate) struct ExecutorProxy {
     storage_read_client: Arc<StorageReadServiceClient>,
     executor: Arc<Executor<MoveVM>>,
-    validator_verifier: ValidatorVerifier,
+    /// Verifier of the validators of the latest committed epoch, which moves to the next validator
+    /// set whenever a ledger info ending an epoch is committed.
+    validator_verifier: Arc<RwLock<ValidatorVerifier>>,
 }
 
 impl ExecutorProxy {
@@ -54,13 +60,37 @@ impl ExecutorProxy {
             &config.storage.address,
             config.storage.port,
         ));
-        let validator_verifier = config.consensus.consensus_peers.get_validator_verifier();
+        // The validators of the genesis configuration only verify the ledger infos of the first
+        // epoch: later epochs are verified by the validator set committed at the end of the
+        // previous one.
+        let validator_verifier = match Self::latest_committed_validator_set(&storage_read_client) {
+            Ok(Some(validator_set)) => ValidatorVerifier::from(&validator_set),
+            Ok(None) => config.consensus.consensus_peers.get_validator_verifier(),
+            Err(e) => {
+                error!(
+                    "[state sync] failed to fetch the committed validator set, falling back to the configured one: {:?}",
+                    e
+                );
+                config.consensus.consensus_peers.get_validator_verifier()
+            }
+        };
         Self {
             storage_read_client,
             executor,
-            validator_verifier,
+            validator_verifier: Arc::new(RwLock::new(validator_verifier)),
         }
     }
+
+    /// Returns the validator set the last committed epoch change moved to, if any.
+    fn latest_committed_validator_set(
+        storage_read_client: &StorageReadServiceClient,
+    ) -> Result<Option<ValidatorSet>> {
+        Ok(storage_read_client
+            .get_latest_ledger_infos_per_epoch(0)?
+            .iter()
+            .rev()
+            .find_map(|ledger_info| ledger_info.ledger_info().next_validator_set().cloned()))
+    }
 }
 
 fn convert_to_future<T: Send + 'static>(
@@ -99,10 +129,39 @@ impl ExecutorProxyTrait for ExecutorProxy {
         txn_list_with_proof: TransactionListWithProof,
         ledger_info_with_sigs: LedgerInfoWithSignatures,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
-        convert_to_future(
+        // The ledger info is committed along with the chunk only if the chunk reaches its version.
+        let reaches_ledger_info = match txn_list_with_proof.first_transaction_version {
+            Some(first_version) => {
+                first_version + txn_list_with_proof.len() as u64
+                    == ledger_info_with_sigs.ledger_info().version() + 1
+            }
+            None => false,
+        };
+        let next_validator_set = if reaches_ledger_info {
+            ledger_info_with_sigs
+                .ledger_info()
+                .next_validator_set()
+                .cloned()
+        } else {
+            None
+        };
+        let validator_verifier = Arc::clone(&self.validator_verifier);
+        let committed = convert_to_future(
             self.executor
                 .execute_chunk(txn_list_with_proof, ledger_info_with_sigs),
-        )
+        );
+        async move {
+            committed.await?;
+            if let Some(validator_set) = next_validator_set {
+                info!(
+                    "[state sync] moving to the next epoch with {} validators",
+                    validator_set.payload().len()
+                );
+                *validator_verifier.write().unwrap() = ValidatorVerifier::from(&validator_set);
+            }
+            Ok(())
+        }
+            .boxed()
     }
 
     fn get_chunk(
@@ -136,7 +195,7 @@ impl ExecutorProxyTrait for ExecutorProxy {
     }
 
     fn validate_ledger_info(&self, target: &LedgerInfo) -> Result<()> {
-        target.verify(&self.validator_verifier)?;
+        target.verify(&self.validator_verifier.read().unwrap())?;
         Ok(())
     }
 }
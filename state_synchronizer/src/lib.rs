@@ -7,6 +7,7 @@ use types::{account_address::AccountAddress, crypto_proxies::LedgerInfoWithSigna
 
 pub use synchronizer::{StateSyncClient, StateSynchronizer};
 
+mod chunk_quota;
 mod coordinator;
 mod counters;
 mod executor_proxy;
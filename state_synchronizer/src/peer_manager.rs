@@ -10,25 +10,39 @@ use rand::{
 };
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
 const MAX_SCORE: f64 = 100.0;
 const MIN_SCORE: f64 = 1.0;
 
+/// How much state sync prefers to request chunks from an upstream peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UpstreamTier {
+    Preferred,
+    /// Only used while none of the preferred peers is usable.
+    Fallback,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct PeerInfo {
     is_alive: bool,
-    is_upstream: bool,
+    // None for the peers which are not upstream.
+    tier: Option<UpstreamTier>,
     score: f64,
+    // Failed requests since the last successful one, and when the last of them failed.
+    consecutive_failures: u64,
+    last_failure: Option<Instant>,
 }
 
 impl PeerInfo {
-    pub fn new(is_alive: bool, is_upstream: bool, score: f64) -> Self {
+    pub fn new(is_alive: bool, tier: Option<UpstreamTier>, score: f64) -> Self {
         Self {
             is_alive,
-            is_upstream,
+            tier,
             score,
+            consecutive_failures: 0,
+            last_failure: None,
         }
     }
 }
@@ -45,35 +59,56 @@ pub struct PeerManager {
     network_senders: HashMap<PeerId, StateSynchronizerSender>,
     // Latest requested block versions from a peer
     requests: BTreeMap<u64, (PeerId, SystemTime)>,
-    weighted_index: Option<WeightedIndex<f64>>,
+    // Number of consecutive failed requests after which an upstream peer is failed over
+    failover_threshold: u64,
+    // How long a failed over upstream peer is left alone before it is tried again
+    failover_retry_interval: Duration,
 }
 
 impl PeerManager {
-    pub fn new(peer_ids: Vec<PeerId>) -> Self {
-        let peers = peer_ids
-            .into_iter()
-            .map(|peer_id| (peer_id, PeerInfo::new(false, true, MAX_SCORE)))
-            .collect();
+    pub fn new(
+        preferred_peer_ids: Vec<PeerId>,
+        fallback_peer_ids: Vec<PeerId>,
+        failover_threshold: u64,
+        failover_retry_interval: Duration,
+    ) -> Self {
+        let mut peers = HashMap::new();
+        for (peer_ids, tier) in vec![
+            (fallback_peer_ids, UpstreamTier::Fallback),
+            (preferred_peer_ids, UpstreamTier::Preferred),
+        ] {
+            for peer_id in peer_ids {
+                peers.insert(peer_id, PeerInfo::new(false, Some(tier), MAX_SCORE));
+            }
+        }
         Self {
             peers,
             network_senders: HashMap::new(),
             requests: BTreeMap::new(),
-            weighted_index: None,
+            failover_threshold,
+            failover_retry_interval,
         }
     }
 
+    /// Makes `peer_ids` the only upstream peers, all of them preferred.
     pub fn set_peers(&mut self, peer_ids: Vec<PeerId>) {
         let new_peer_ids: HashSet<_> = peer_ids.iter().collect();
         for (peer_id, info) in self.peers.iter_mut() {
-            info.is_upstream = new_peer_ids.contains(peer_id);
+            info.tier = if new_peer_ids.contains(peer_id) {
+                Some(UpstreamTier::Preferred)
+            } else {
+                None
+            };
         }
         for peer_id in new_peer_ids {
             if !self.peers.contains_key(peer_id) {
-                self.peers
-                    .insert(*peer_id, PeerInfo::new(false, true, MAX_SCORE));
+                self.peers.insert(
+                    *peer_id,
+                    PeerInfo::new(false, Some(UpstreamTier::Preferred), MAX_SCORE),
+                );
             }
         }
-        self.compute_weighted_index();
+        self.update_active_peers_counter();
         debug!("[state sync] (set_peers) state: {:?}", self.peers);
     }
 
@@ -84,9 +119,9 @@ impl PeerManager {
             peer_info.is_alive = true;
         } else {
             self.peers
-                .insert(peer_id, PeerInfo::new(true, false, MAX_SCORE));
+                .insert(peer_id, PeerInfo::new(true, None, MAX_SCORE));
         }
-        self.compute_weighted_index();
+        self.update_active_peers_counter();
         debug!("[state sync] state after: {:?}", self.peers);
     }
 
@@ -95,7 +130,7 @@ impl PeerManager {
         if let Some(peer_info) = self.peers.get_mut(peer_id) {
             peer_info.is_alive = false;
         };
-        self.compute_weighted_index();
+        self.update_active_peers_counter();
     }
 
     pub fn is_empty(&self) -> bool {
@@ -104,11 +139,12 @@ impl PeerManager {
 
     pub fn update_score(&mut self, peer_id: &PeerId, update_type: PeerScoreUpdateType) {
         if let Some(peer_info) = self.peers.get_mut(peer_id) {
-            let old_score = peer_info.score;
             match update_type {
                 PeerScoreUpdateType::Success => {
                     let new_score = peer_info.score + 1.0;
                     peer_info.score = new_score.min(MAX_SCORE);
+                    peer_info.consecutive_failures = 0;
+                    peer_info.last_failure = None;
                 }
                 PeerScoreUpdateType::InvalidChunk => {
                     let new_score = peer_info.score * 0.8;
@@ -119,58 +155,107 @@ impl PeerManager {
                     peer_info.score = new_score.max(MIN_SCORE);
                 }
             }
-            if (old_score - peer_info.score).abs() > std::f64::EPSILON {
-                self.compute_weighted_index();
+            if update_type == PeerScoreUpdateType::Success {
+                return;
+            }
+            peer_info.consecutive_failures += 1;
+            peer_info.last_failure = Some(Instant::now());
+            if peer_info.tier.is_some() && peer_info.consecutive_failures == self.failover_threshold
+            {
+                warn!(
+                    "[state sync] failing over upstream peer {} after {} failed requests",
+                    peer_id, peer_info.consecutive_failures
+                );
+                counters::UPSTREAM_FAILOVERS.inc();
             }
         }
     }
 
-    fn compute_weighted_index(&mut self) {
-        let active_peers = self.get_active_upstream_peers();
-        counters::ACTIVE_UPSTREAM_PEERS.set(active_peers.len() as i64);
-
-        if !active_peers.is_empty() {
-            let weights: Vec<_> = active_peers
-                .iter()
-                .map(|(_, peer_info)| peer_info.score)
-                .collect();
-            match WeightedIndex::new(&weights) {
-                Ok(weighted_index) => {
-                    self.weighted_index = Some(weighted_index);
-                }
-                Err(e) => {
-                    error!(
-                        "[state sync] (pick_peer) failed to compute weighted index, {:?}",
-                        e
-                    );
-                }
-            }
-        }
+    fn update_active_peers_counter(&self) {
+        let num_active_peers = self
+            .peers
+            .values()
+            .filter(|peer_info| peer_info.is_alive && peer_info.tier.is_some())
+            .count();
+        counters::ACTIVE_UPSTREAM_PEERS.set(num_active_peers as i64);
     }
 
     pub fn pick_peer(&self) -> Option<(PeerId, StateSynchronizerSender)> {
-        let active_peers = self.get_active_upstream_peers();
         debug!("[state sync] (pick_peer) state: {:?}", self.peers);
+        self.pick_peer_among(self.get_active_upstream_peers())
+    }
 
-        if let Some(weighted_index) = &self.weighted_index {
-            let mut rng = thread_rng();
-            if let Some(peer) = active_peers.get(weighted_index.sample(&mut rng)) {
-                let peer_id = *peer.0;
-                if let Some(sender) = self.get_network_sender(&peer_id) {
-                    return Some((peer_id, sender));
-                } else {
-                    debug!("[state sync] (pick_peer) no sender for {}", peer_id);
-                }
+    /// Picks an upstream peer other than `peer_id`, for a request sent to `peer_id` to be hedged
+    /// to.
+    pub fn pick_other_peer(&self, peer_id: &PeerId) -> Option<(PeerId, StateSynchronizerSender)> {
+        let mut active_peers = self.get_active_upstream_peers();
+        active_peers.retain(|(other_peer_id, _)| *other_peer_id != peer_id);
+        self.pick_peer_among(active_peers)
+    }
+
+    fn pick_peer_among(
+        &self,
+        active_peers: Vec<(&PeerId, &PeerInfo)>,
+    ) -> Option<(PeerId, StateSynchronizerSender)> {
+        if active_peers.is_empty() {
+            return None;
+        }
+        let weights: Vec<_> = active_peers
+            .iter()
+            .map(|(_, peer_info)| peer_info.score)
+            .collect();
+        let weighted_index = match WeightedIndex::new(&weights) {
+            Ok(weighted_index) => weighted_index,
+            Err(e) => {
+                error!(
+                    "[state sync] (pick_peer) failed to compute weighted index, {:?}",
+                    e
+                );
+                return None;
+            }
+        };
+        let mut rng = thread_rng();
+        if let Some(peer) = active_peers.get(weighted_index.sample(&mut rng)) {
+            let peer_id = *peer.0;
+            if let Some(sender) = self.get_network_sender(&peer_id) {
+                return Some((peer_id, sender));
+            } else {
+                debug!("[state sync] (pick_peer) no sender for {}", peer_id);
             }
         }
         None
     }
 
+    /// Returns the upstream peers to request chunks from: the live preferred peers which have not
+    /// been failed over, or else such fallback peers. If every live upstream peer has been failed
+    /// over, all of them are returned, as they are still better than none.
     fn get_active_upstream_peers(&self) -> Vec<(&PeerId, &PeerInfo)> {
-        self.peers
+        let now = Instant::now();
+        let active_peers: Vec<_> = self
+            .peers
             .iter()
-            .filter(|&(_, peer_info)| peer_info.is_alive && peer_info.is_upstream)
-            .collect()
+            .filter(|&(_, peer_info)| peer_info.is_alive && peer_info.tier.is_some())
+            .collect();
+        for tier in &[UpstreamTier::Preferred, UpstreamTier::Fallback] {
+            let usable_peers: Vec<_> = active_peers
+                .iter()
+                .filter(|&(_, peer_info)| {
+                    peer_info.tier == Some(*tier) && !self.is_failed_over(peer_info, now)
+                })
+                .cloned()
+                .collect();
+            if !usable_peers.is_empty() {
+                return usable_peers;
+            }
+        }
+        active_peers
+    }
+
+    fn is_failed_over(&self, peer_info: &PeerInfo, now: Instant) -> bool {
+        peer_info.consecutive_failures >= self.failover_threshold
+            && peer_info.last_failure.map_or(false, |last_failure| {
+                now.duration_since(last_failure) < self.failover_retry_interval
+            })
     }
 
     pub fn get_network_sender(&self, peer_id: &PeerId) -> Option<StateSynchronizerSender> {
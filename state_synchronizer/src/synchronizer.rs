@@ -5,17 +5,21 @@ use crate::{
     coordinator::{CoordinatorMessage, SyncCoordinator},
     executor_proxy::{ExecutorProxy, ExecutorProxyTrait},
 };
-use config::config::{NodeConfig, StateSyncConfig};
+use config::config::{NodeConfig, StateSyncConfig, UpstreamConfig};
+use disk_monitor::DiskMonitor;
 use executor::Executor;
 use failure::prelude::*;
 use futures::{
     channel::{mpsc, oneshot},
-    future::{Future, FutureExt, TryFutureExt},
+    future::Future,
     SinkExt,
 };
+use logger::context::with_log_context;
 use network::validator_network::{StateSynchronizerEvents, StateSynchronizerSender};
 use std::sync::Arc;
-use tokio::runtime::{Builder, Runtime};
+use task_manager::{build_runtime, TaskManager};
+use tokio::runtime::Runtime;
+use trusted_ledger::TrustedLedger;
 use types::crypto_proxies::LedgerInfoWithSignatures;
 use vm_runtime::MoveVM;
 
@@ -25,35 +29,49 @@ pub struct StateSynchronizer {
 }
 
 impl StateSynchronizer {
-    /// Setup state synchronizer. spawns coordinator and downloader routines on executor
+    /// Setup state synchronizer. spawns coordinator and downloader routines on executor.
+    /// `trusted_ledger` is kept up to date with the ledger infos committed by state sync. Sync is
+    /// paused while `disk_monitor` is in protective mode.
     pub fn bootstrap(
         network: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
         executor: Arc<Executor<MoveVM>>,
         config: &NodeConfig,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
     ) -> Self {
         let executor_proxy = ExecutorProxy::new(executor, config);
-        Self::bootstrap_with_executor_proxy(network, &config.state_sync, executor_proxy)
+        Self::bootstrap_with_executor_proxy(
+            network,
+            &config.state_sync,
+            &config.upstream,
+            executor_proxy,
+            trusted_ledger,
+            disk_monitor,
+        )
     }
 
     pub fn bootstrap_with_executor_proxy<E: ExecutorProxyTrait + 'static>(
         network: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
         state_sync_config: &StateSyncConfig,
+        upstream_config: &UpstreamConfig,
         executor_proxy: E,
+        trusted_ledger: TrustedLedger,
+        disk_monitor: DiskMonitor,
     ) -> Self {
-        let runtime = Builder::new()
-            .name_prefix("state-sync-")
-            .build()
-            .expect("[state synchronizer] failed to create runtime");
-        let executor = runtime.executor();
+        let runtime = build_runtime("state-sync-");
+        let task_manager = TaskManager::new("state_sync", runtime.executor());
 
         let (coordinator_sender, coordinator_receiver) = mpsc::unbounded();
 
         let coordinator = SyncCoordinator::new(
             coordinator_receiver,
             state_sync_config.clone(),
+            upstream_config,
             executor_proxy,
+            trusted_ledger,
+            disk_monitor,
         );
-        executor.spawn(coordinator.start(network).boxed().unit_error().compat());
+        task_manager.spawn("coordinator", with_log_context(coordinator.start(network)));
 
         Self {
             _runtime: runtime,
@@ -7,6 +7,7 @@ use crate::{
 use config::config::RoleType;
 use config_builder::util::get_test_config;
 use crypto::{ed25519::*, test_utils::TEST_SEED, traits::Genesis, x25519, HashValue, SigningKey};
+use disk_monitor::DiskMonitor;
 use failure::{prelude::*, Result};
 use futures::{
     executor::block_on,
@@ -17,7 +18,7 @@ use network::{
     proto::GetChunkResponse,
     validator_network::{
         network_builder::{NetworkBuilder, TransportType},
-        STATE_SYNCHRONIZER_MSG_PROTOCOL,
+        STATE_SYNCHRONIZER_MSG_PROTOCOL, STATE_SYNCHRONIZER_RPC_PROTOCOL,
     },
     NetworkPublicKeys, ProtocolId,
 };
@@ -33,6 +34,7 @@ use std::{
 };
 use tokio::runtime::{Builder, Runtime};
 use transaction_builder::encode_transfer_script;
+use trusted_ledger::TrustedLedger;
 use types::{
     account_address::AccountAddress,
     crypto_proxies::LedgerInfoWithSignatures,
@@ -158,16 +160,33 @@ struct SynchronizerEnv {
     _synchronizers: Vec<StateSynchronizer>,
     peers: Vec<PeerId>,
     clients: Vec<Arc<StateSyncClient>>,
+    trusted_ledgers: Vec<TrustedLedger>,
 }
 
 impl SynchronizerEnv {
     fn new(handler: MockRpcHandler, role: RoleType) -> Self {
+        Self::new_with_hedge_delay(handler, role, None)
+    }
+
+    /// Same as `new`, except that the first node requests the chunks of its sync targets over
+    /// rpc, hedged after `chunk_request_hedge_delay_ms`.
+    fn new_with_hedge_delay(
+        handler: MockRpcHandler,
+        role: RoleType,
+        chunk_request_hedge_delay_ms: Option<u64>,
+    ) -> Self {
         let runtime = Builder::new().build().unwrap();
         let peers = vec![PeerId::random(), PeerId::random()];
 
         // setup network
         let addr: Multiaddr = "/memory/0".parse().unwrap();
         let protocols = vec![ProtocolId::from_static(STATE_SYNCHRONIZER_MSG_PROTOCOL)];
+        let rpc_protocols = vec![ProtocolId::from_static(STATE_SYNCHRONIZER_RPC_PROTOCOL)];
+        let state_sync_protocols: Vec<_> = protocols
+            .iter()
+            .chain(rpc_protocols.iter())
+            .cloned()
+            .collect();
 
         // Setup signing public keys.
         let mut rng = StdRng::from_seed(TEST_SEED);
@@ -208,8 +227,10 @@ impl SynchronizerEnv {
         .trusted_peers(trusted_peers.clone())
         .transport(TransportType::Memory)
         .direct_send_protocols(protocols.clone())
+        .rpc_protocols(rpc_protocols.clone())
         .build();
-        let (sender_b, events_b) = network_provider.add_state_synchronizer(protocols.clone());
+        let (sender_b, events_b) =
+            network_provider.add_state_synchronizer(state_sync_protocols.clone());
         runtime
             .executor()
             .spawn(network_provider.start().unit_error().compat());
@@ -224,9 +245,10 @@ impl SynchronizerEnv {
         .signing_keys((a_signing_private_key, a_signing_public_key))
         .trusted_peers(trusted_peers.clone())
         .seed_peers([(peers[1], vec![listener_addr])].iter().cloned().collect())
-        .direct_send_protocols(protocols.clone())
+        .direct_send_protocols(protocols)
+        .rpc_protocols(rpc_protocols)
         .build();
-        let (sender_a, events_a) = network_provider.add_state_synchronizer(protocols);
+        let (sender_a, events_a) = network_provider.add_state_synchronizer(state_sync_protocols);
         runtime
             .executor()
             .spawn(network_provider.start().unit_error().compat());
@@ -239,21 +261,26 @@ impl SynchronizerEnv {
         } else {
             config.networks.get_mut(0).unwrap().role = "validator".to_string();
         }
-        config
-            .state_sync
-            .upstream_peers
-            .upstream_peers
-            .push(peers[1].to_string());
+        config.upstream.preferred_peers.push(peers[1].to_string());
+        config.state_sync.chunk_request_hedge_delay_ms = chunk_request_hedge_delay_ms;
+        let upstream_node_config = get_test_config().0;
+        let trusted_ledgers = vec![TrustedLedger::new(), TrustedLedger::new()];
         let synchronizers: Vec<StateSynchronizer> = vec![
             StateSynchronizer::bootstrap_with_executor_proxy(
                 vec![(sender_a, events_a)],
                 &config.state_sync,
+                &config.upstream,
                 MockExecutorProxy::new(peers[0], Self::default_handler()),
+                trusted_ledgers[0].clone(),
+                DiskMonitor::default(),
             ),
             StateSynchronizer::bootstrap_with_executor_proxy(
                 vec![(sender_b, events_b)],
-                &get_test_config().0.state_sync,
+                &upstream_node_config.state_sync,
+                &upstream_node_config.upstream,
                 MockExecutorProxy::new(peers[1], handler),
+                trusted_ledgers[1].clone(),
+                DiskMonitor::default(),
             ),
         ];
         let clients = synchronizers.iter().map(|s| s.create_client()).collect();
@@ -261,6 +288,7 @@ impl SynchronizerEnv {
         Self {
             peers,
             clients,
+            trusted_ledgers,
             _synchronizers: synchronizers,
             _runtime: runtime,
         }
@@ -304,6 +332,29 @@ fn test_basic_catch_up() {
     assert!(env.sync_to(0, 10));
 }
 
+#[test]
+fn test_catch_up_over_rpc() {
+    let env = SynchronizerEnv::new_with_hedge_delay(
+        SynchronizerEnv::default_handler(),
+        RoleType::Validator,
+        Some(50),
+    );
+
+    for version in 1..5 {
+        assert!(env.sync_to(0, version));
+    }
+    assert!(env.sync_to(0, 10));
+}
+
+#[test]
+fn test_trusted_ledger_follows_sync() {
+    let env = SynchronizerEnv::new(SynchronizerEnv::default_handler(), RoleType::Validator);
+    assert!(env.sync_to(0, 5));
+    assert_eq!(env.trusted_ledgers[0].version(), Some(5));
+    // the upstream peer did not sync, so it only knows about its initial state
+    assert_eq!(env.trusted_ledgers[1].version(), Some(0));
+}
+
 #[test]
 fn test_flaky_peer_sync() {
     // create handler that causes error, but has successful retries
@@ -2,4 +2,5 @@
 // SPDX-License-Identifier: Apache-2.0
 
 mod integration_tests;
+mod proptest_types;
 mod unit_tests;
@@ -0,0 +1,111 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proptest strategies for the chunk requests and responses exchanged by state synchronizers.
+//!
+//! Requests respect the limits of the `StateSyncConfig` they are generated for, and target ledger
+//! infos are signed by a single validator (see [`validator_verifier`]) and are never behind the
+//! known version of the request. Responses carry the chunk following a known version, but the
+//! accumulator proofs of their transactions are arbitrary.
+
+use config::config::StateSyncConfig;
+use crypto::{hash::CryptoHash, HashValue};
+use network::proto::{GetChunkRequest, GetChunkResponse};
+use proptest::prelude::*;
+use std::collections::HashMap;
+use types::{
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier},
+    ledger_info::LedgerInfo,
+    transaction::{TransactionListWithProof, Version},
+};
+
+/// Returns the signer of the ledger infos generated by this module.
+fn signer() -> ValidatorSigner {
+    ValidatorSigner::random(None)
+}
+
+/// Returns a verifier for the signatures of the ledger infos generated by this module.
+pub fn validator_verifier() -> ValidatorVerifier {
+    let signer = signer();
+    ValidatorVerifier::new_single(signer.author(), signer.public_key())
+}
+
+prop_compose! {
+    /// Generates a signed ledger info at a version in `versions`.
+    pub fn arb_ledger_info_with_sigs(versions: std::ops::Range<Version>)(
+        version in versions,
+        transaction_accumulator_hash in any::<HashValue>(),
+        consensus_data_hash in any::<HashValue>(),
+        consensus_block_id in any::<HashValue>(),
+        epoch_num in any::<u64>(),
+        timestamp_usecs in any::<u64>(),
+    ) -> LedgerInfoWithSignatures {
+        let ledger_info = LedgerInfo::new(
+            version,
+            transaction_accumulator_hash,
+            consensus_data_hash,
+            consensus_block_id,
+            epoch_num,
+            timestamp_usecs,
+            None,
+        );
+        let signer = signer();
+        let signature = signer
+            .sign_message(ledger_info.hash())
+            .expect("Failed to sign LedgerInfo");
+        let mut signatures = HashMap::new();
+        signatures.insert(signer.author(), signature);
+        LedgerInfoWithSignatures::new(ledger_info, signatures)
+    }
+}
+
+/// Generates a chunk request within the limits of `config`. A request either targets a ledger
+/// info, or is a long poll request with a timeout.
+pub fn arb_chunk_request(config: &StateSyncConfig) -> impl Strategy<Value = GetChunkRequest> {
+    let max_chunk_limit = config.max_chunk_limit;
+    let max_timeout_ms = config.max_timeout_ms;
+    proptest::option::of(arb_ledger_info_with_sigs(0..std::u64::MAX / 2))
+        .prop_flat_map(move |target| {
+            let max_known_version = target
+                .as_ref()
+                .map_or(std::u64::MAX / 2, |target| target.ledger_info().version());
+            (
+                Just(target),
+                0..=max_known_version,
+                1..=max_chunk_limit,
+                1..=max_timeout_ms,
+            )
+        })
+        .prop_map(|(target, known_version, limit, timeout)| {
+            let mut request = GetChunkRequest::default();
+            request.known_version = known_version;
+            request.limit = limit;
+            match target {
+                Some(target) => request.ledger_info_with_sigs = Some(target.into()),
+                None => request.timeout = timeout,
+            }
+            request
+        })
+}
+
+/// Generates the response to a chunk request with `known_version`: its transactions start right
+/// after `known_version`, and its ledger info is at or after the last of them.
+pub fn arb_chunk_response(known_version: Version) -> impl Strategy<Value = GetChunkResponse> {
+    any::<TransactionListWithProof>()
+        .prop_flat_map(move |mut txn_list_with_proof| {
+            let last_version = known_version + txn_list_with_proof.len() as Version;
+            if !txn_list_with_proof.is_empty() {
+                txn_list_with_proof.first_transaction_version = Some(known_version + 1);
+            }
+            (
+                Just(txn_list_with_proof),
+                arb_ledger_info_with_sigs(last_version..last_version + 100),
+            )
+        })
+        .prop_map(|(txn_list_with_proof, ledger_info_with_sigs)| {
+            let mut response = GetChunkResponse::default();
+            response.txn_list_with_proof = Some(txn_list_with_proof.into());
+            response.ledger_info_with_sigs = Some(ledger_info_with_sigs.into());
+            response
+        })
+}
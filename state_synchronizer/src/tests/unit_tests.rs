@@ -2,12 +2,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    chunk_quota::ChunkQuota,
     peer_manager::{PeerManager, PeerScoreUpdateType},
+    tests::proptest_types::{arb_chunk_request, arb_chunk_response, validator_verifier},
     PeerId,
 };
 use channel;
+use config::config::StateSyncConfig;
 use network::validator_network::StateSynchronizerSender;
-use std::collections::HashMap;
+use proptest::prelude::*;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+use types::{crypto_proxies::LedgerInfoWithSignatures, transaction::TransactionListWithProof};
 
 #[test]
 fn test_peer_manager() {
@@ -17,7 +26,7 @@ fn test_peer_manager() {
         PeerId::random(),
         PeerId::random(),
     ];
-    let mut peer_manager = PeerManager::new(peers.clone());
+    let mut peer_manager = PeerManager::new(peers.clone(), vec![], 3, Duration::from_secs(30));
     let (network_reqs_tx, _) = channel::new_test(8);
     let sender = StateSynchronizerSender::new(network_reqs_tx);
     for peer_id in peers.clone() {
@@ -42,10 +51,124 @@ fn test_peer_manager() {
     assert!(pick_counts.get(&peers[0]).unwrap_or(&0) < pick_counts.get(&peers[3]).unwrap());
 }
 
+#[test]
+fn test_peer_manager_failover() {
+    let preferred_peer = PeerId::random();
+    let fallback_peer = PeerId::random();
+    let mut peer_manager = PeerManager::new(
+        vec![preferred_peer],
+        vec![fallback_peer],
+        3,
+        Duration::from_secs(3600),
+    );
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    peer_manager.enable_peer(preferred_peer, sender.clone());
+    peer_manager.enable_peer(fallback_peer, sender);
+
+    // The fallback peer is left alone while the preferred peer works.
+    for _ in 0..100 {
+        assert_eq!(peer_manager.pick_peer().unwrap().0, preferred_peer);
+    }
+
+    // A success resets the count of failures.
+    for _ in 0..2 {
+        peer_manager.update_score(&preferred_peer, PeerScoreUpdateType::TimeOut);
+    }
+    peer_manager.update_score(&preferred_peer, PeerScoreUpdateType::Success);
+    for _ in 0..2 {
+        peer_manager.update_score(&preferred_peer, PeerScoreUpdateType::TimeOut);
+    }
+    assert_eq!(peer_manager.pick_peer().unwrap().0, preferred_peer);
+
+    // The preferred peer is failed over once its requests failed 3 times in a row.
+    peer_manager.update_score(&preferred_peer, PeerScoreUpdateType::InvalidChunk);
+    for _ in 0..100 {
+        assert_eq!(peer_manager.pick_peer().unwrap().0, fallback_peer);
+    }
+
+    // It is still used when no other upstream peer is left.
+    peer_manager.disable_peer(&fallback_peer);
+    assert_eq!(peer_manager.pick_peer().unwrap().0, preferred_peer);
+}
+
+#[test]
+fn test_peer_manager_failover_retry() {
+    let preferred_peer = PeerId::random();
+    let fallback_peer = PeerId::random();
+    let mut peer_manager = PeerManager::new(
+        vec![preferred_peer],
+        vec![fallback_peer],
+        1,
+        Duration::from_millis(0),
+    );
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    peer_manager.enable_peer(preferred_peer, sender.clone());
+    peer_manager.enable_peer(fallback_peer, sender);
+
+    // The failed over preferred peer is tried again once the retry interval has passed.
+    peer_manager.update_score(&preferred_peer, PeerScoreUpdateType::TimeOut);
+    assert_eq!(peer_manager.pick_peer().unwrap().0, preferred_peer);
+}
+
+#[test]
+fn test_peer_manager_pick_other_peer() {
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let mut peer_manager = PeerManager::new(peers.clone(), vec![], 3, Duration::from_secs(30));
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    peer_manager.enable_peer(peers[0], sender.clone());
+
+    // A request can't be hedged without another upstream peer.
+    assert!(peer_manager.pick_other_peer(&peers[0]).is_none());
+
+    peer_manager.enable_peer(peers[1], sender);
+    for _ in 0..100 {
+        assert_eq!(peer_manager.pick_other_peer(&peers[0]).unwrap().0, peers[1]);
+    }
+}
+
+#[test]
+fn test_chunk_quota() {
+    let (peer_id, other_peer_id, authorized_peer_id) =
+        (PeerId::random(), PeerId::random(), PeerId::random());
+    let mut config = StateSyncConfig::default();
+    config.chunk_serving_daily_byte_budget = Some(100);
+    config.chunk_serving_total_daily_byte_budget = Some(150);
+    config.chunk_serving_authorized_peers = vec![format!("{:x}", authorized_peer_id)];
+    let quota = ChunkQuota::new(&config);
+    let now = Instant::now();
+
+    assert!(quota.is_authorized(&authorized_peer_id));
+    assert!(!quota.is_authorized(&peer_id));
+
+    // unauthorized peers are refused the chunks which would exceed their budget
+    assert!(quota.try_charge(peer_id, 60, now).is_ok());
+    assert!(quota.check(peer_id, now).is_ok());
+    assert!(quota.try_charge(peer_id, 60, now).is_err());
+    assert!(quota.try_charge(peer_id, 40, now).is_ok());
+    assert!(quota.check(peer_id, now).is_err());
+    // and share a total budget, which new identities don't renew
+    assert!(quota.try_charge(other_peer_id, 60, now).is_err());
+    assert!(quota.try_charge(other_peer_id, 50, now).is_ok());
+    assert!(quota.check(PeerId::random(), now).is_err());
+    // authorized ones are never limited
+    assert!(quota.try_charge(authorized_peer_id, 1000, now).is_ok());
+    // budgets are renewed every day
+    let tomorrow = now + Duration::from_secs(24 * 60 * 60);
+    assert!(quota.check(peer_id, tomorrow).is_ok());
+
+    config.chunk_serving_require_auth = true;
+    let quota = ChunkQuota::new(&config);
+    assert!(quota.check(peer_id, now).is_err());
+    assert!(quota.check(authorized_peer_id, now).is_ok());
+}
+
 #[test]
 fn test_remove_requests() {
     let peers = vec![PeerId::random(), PeerId::random()];
-    let mut peer_manager = PeerManager::new(peers.clone());
+    let mut peer_manager = PeerManager::new(peers.clone(), vec![], 3, Duration::from_secs(30));
 
     peer_manager.process_request(1, peers[0]);
     peer_manager.process_request(3, peers[1]);
@@ -61,3 +184,37 @@ fn test_remove_requests() {
     assert!(peer_manager.has_requested(10, peers[0]));
     assert!(peer_manager.has_requested(12, peers[1]));
 }
+
+proptest! {
+    #[test]
+    fn test_chunk_request_strategy(request in arb_chunk_request(&StateSyncConfig::default())) {
+        let config = StateSyncConfig::default();
+        prop_assert!(request.limit <= config.max_chunk_limit);
+        prop_assert!(request.timeout <= config.max_timeout_ms);
+        if let Some(target) = request.ledger_info_with_sigs {
+            let target: LedgerInfoWithSignatures = target.try_into().unwrap();
+            prop_assert!(target.verify(&validator_verifier()).is_ok());
+            prop_assert!(request.known_version <= target.ledger_info().version());
+        }
+    }
+
+    #[test]
+    fn test_chunk_response_strategy(
+        (known_version, response) in (0..1000u64).prop_flat_map(|known_version| {
+            (Just(known_version), arb_chunk_response(known_version))
+        })
+    ) {
+        let txn_list_with_proof: TransactionListWithProof =
+            response.txn_list_with_proof.unwrap().try_into().unwrap();
+        let ledger_info_with_sigs: LedgerInfoWithSignatures =
+            response.ledger_info_with_sigs.unwrap().try_into().unwrap();
+        prop_assert!(ledger_info_with_sigs.verify(&validator_verifier()).is_ok());
+        if let Some(first_version) = txn_list_with_proof.first_transaction_version {
+            prop_assert_eq!(first_version, known_version + 1);
+        }
+        prop_assert!(
+            ledger_info_with_sigs.ledger_info().version()
+                >= known_version + txn_list_with_proof.len() as u64
+        );
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    export::export,
+    format::{DATA_FILE_NAME, HEADER_LEN, INDEX_FILE_NAME},
+    reader::ArchiveReader,
+    writer::ArchiveWriter,
+};
+use crypto::{hash::CryptoHash, x25519};
+use libradb::{mock_genesis::db_with_mock_genesis, test_helper::arb_blocks_to_commit, LibraDB};
+use proptest::prelude::*;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+use tools::tempdir::TempPath;
+use types::{
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier},
+    ledger_info::LedgerInfo,
+    transaction::TransactionToCommit,
+    validator_public_keys::ValidatorPublicKeys,
+    validator_set::ValidatorSet,
+};
+
+type Block = (Vec<TransactionToCommit>, LedgerInfoWithSignatures);
+
+fn verifier(signer: &ValidatorSigner) -> ValidatorVerifier {
+    ValidatorVerifier::new_single(signer.author(), signer.public_key())
+}
+
+fn validator_set(signer: &ValidatorSigner) -> ValidatorSet {
+    let (_, network_identity_public_key) = x25519::compat::generate_keypair(None);
+    ValidatorSet::new(vec![ValidatorPublicKeys::new(
+        signer.author(),
+        signer.public_key(),
+        1, /* consensus_voting_power */
+        signer.public_key(),
+        network_identity_public_key,
+    )])
+}
+
+/// Moves the ledger info of `block` to `epoch`, and has `signer` sign it.
+fn sign_block(
+    block: Block,
+    epoch: u64,
+    next_validator_set: Option<ValidatorSet>,
+    signer: &ValidatorSigner,
+) -> Block {
+    let (txns_to_commit, ledger_info_with_sigs) = block;
+    let ledger_info = ledger_info_with_sigs.ledger_info();
+    let ledger_info = LedgerInfo::new(
+        ledger_info.version(),
+        ledger_info.transaction_accumulator_hash(),
+        ledger_info.consensus_data_hash(),
+        ledger_info.consensus_block_id(),
+        epoch,
+        ledger_info.timestamp_usecs(),
+        next_validator_set,
+    );
+    let signature = signer.sign_message(ledger_info.hash()).unwrap();
+    let signatures = vec![(signer.author(), signature)].into_iter().collect();
+    (
+        txns_to_commit,
+        LedgerInfoWithSignatures::new(ledger_info, signatures),
+    )
+}
+
+fn sign_blocks(blocks: Vec<Block>, signer: &ValidatorSigner) -> Vec<Block> {
+    blocks
+        .into_iter()
+        .map(|block| sign_block(block, 0 /* epoch */, None, signer))
+        .collect()
+}
+
+fn db_with_blocks(dir: &TempPath, blocks: &[Block]) -> LibraDB {
+    let db = db_with_mock_genesis(&dir).unwrap();
+    let mut version = 1;
+    for (txns_to_commit, ledger_info_with_sigs) in blocks {
+        db.save_transactions(
+            txns_to_commit,
+            version,
+            &Some(ledger_info_with_sigs.clone()),
+        )
+        .unwrap();
+        version += txns_to_commit.len() as u64;
+    }
+    db
+}
+
+fn append_to_file(path: &Path, bytes: &[u8]) {
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10))]
+
+    #[test]
+    fn test_export_and_verify(
+        blocks in arb_blocks_to_commit().no_shrink(),
+        chunk_size in 1..5u64,
+    ) {
+        let signer = ValidatorSigner::random(None);
+        let blocks = sign_blocks(blocks, &signer);
+        let db_dir = TempPath::new();
+        let db = db_with_blocks(&db_dir, &blocks);
+        let num_txns = 1 + blocks.iter().map(|(txns, _)| txns.len() as u64).sum::<u64>();
+
+        let archive_dir = TempPath::new();
+        let mut writer = ArchiveWriter::open(&archive_dir).unwrap();
+        prop_assert_eq!(export(&db, &mut writer, chunk_size).unwrap(), num_txns);
+        // Nothing new to export.
+        prop_assert_eq!(export(&db, &mut writer, chunk_size).unwrap(), 0);
+        drop(writer);
+
+        let mut reader = ArchiveReader::open(&archive_dir).unwrap();
+        if num_txns > 1 {
+            prop_assert!(reader.verify(verifier(&ValidatorSigner::random(None))).is_err());
+        }
+        let summary = reader.verify(verifier(&signer)).unwrap();
+        prop_assert_eq!(summary.num_transactions, num_txns);
+        prop_assert_eq!(
+            summary.num_chunks as u64,
+            (num_txns + chunk_size - 1) / chunk_size
+        );
+
+        let last_version = num_txns - 1;
+        let chunk = reader.read_chunk_of_version(last_version).unwrap().unwrap();
+        let expected = db.get_transactions(last_version, 1, last_version, true).unwrap();
+        prop_assert_eq!(
+            chunk.txn_list_with_proof.transaction_and_infos.last(),
+            expected.transaction_and_infos.last()
+        );
+        prop_assert!(reader.read_chunk_of_version(num_txns).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_verify_across_epochs(
+        blocks in arb_blocks_to_commit().no_shrink(),
+        chunk_size in 1..5u64,
+    ) {
+        prop_assume!(blocks.len() >= 2);
+        let num_epoch_0_blocks = blocks.len() / 2;
+        prop_assume!(blocks[..num_epoch_0_blocks].iter().any(|(txns, _)| !txns.is_empty()));
+        prop_assume!(blocks[num_epoch_0_blocks..].iter().any(|(txns, _)| !txns.is_empty()));
+        let signer_0 = ValidatorSigner::random(None);
+        let signer_1 = ValidatorSigner::random(None);
+        let blocks = blocks
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| {
+                if i + 1 < num_epoch_0_blocks {
+                    sign_block(block, 0, None, &signer_0)
+                } else if i + 1 == num_epoch_0_blocks {
+                    sign_block(block, 0, Some(validator_set(&signer_1)), &signer_0)
+                } else {
+                    sign_block(block, 1, None, &signer_1)
+                }
+            })
+            .collect::<Vec<_>>();
+        let db_dir = TempPath::new();
+        let db = db_with_blocks(&db_dir, &blocks);
+
+        let archive_dir = TempPath::new();
+        export(&db, &mut ArchiveWriter::open(&archive_dir).unwrap(), chunk_size).unwrap();
+
+        // The validators of epoch 1 are only trusted through the ledger info ending epoch 0.
+        let mut reader = ArchiveReader::open(&archive_dir).unwrap();
+        prop_assert!(reader.verify(verifier(&signer_1)).is_err());
+        let summary = reader.verify(verifier(&signer_0)).unwrap();
+        prop_assert_eq!(summary.latest_ledger_info.unwrap().epoch_num(), 1);
+    }
+}
+
+fn export_mock_genesis(archive_dir: &TempPath) {
+    let db_dir = TempPath::new();
+    let db = db_with_mock_genesis(&db_dir).unwrap();
+    export(&db, &mut ArchiveWriter::open(archive_dir).unwrap(), 10).unwrap();
+}
+
+#[test]
+fn test_resume_after_torn_append() {
+    let archive_dir = TempPath::new();
+    export_mock_genesis(&archive_dir);
+
+    // Simulate a crash in the middle of an append.
+    append_to_file(&archive_dir.path().join(DATA_FILE_NAME), &[1, 2, 3]);
+    append_to_file(&archive_dir.path().join(INDEX_FILE_NAME), &[4, 5]);
+    assert!(ArchiveReader::open(&archive_dir).is_err());
+
+    let writer = ArchiveWriter::open(&archive_dir).unwrap();
+    assert_eq!(writer.next_version(), 1);
+    let summary = ArchiveReader::open(&archive_dir)
+        .unwrap()
+        .verify(verifier(&ValidatorSigner::random(None)))
+        .unwrap();
+    assert_eq!(summary.num_transactions, 1);
+}
+
+#[test]
+fn test_detect_corruption() {
+    let archive_dir = TempPath::new();
+    export_mock_genesis(&archive_dir);
+
+    // Flip the last byte of the payload of the only record.
+    let data_path = archive_dir.path().join(DATA_FILE_NAME);
+    let mut data = fs::read(&data_path).unwrap();
+    *data.last_mut().unwrap() ^= 0xff;
+    fs::write(&data_path, data).unwrap();
+
+    let mut reader = ArchiveReader::open(&archive_dir).unwrap();
+    assert!(reader
+        .verify(verifier(&ValidatorSigner::random(None)))
+        .is_err());
+}
+
+#[test]
+fn test_short_index() {
+    let archive_dir = TempPath::new();
+    export_mock_genesis(&archive_dir);
+    let index_path = archive_dir.path().join(INDEX_FILE_NAME);
+    let index = fs::read(&index_path).unwrap();
+    fs::write(&index_path, &index[..HEADER_LEN as usize - 1]).unwrap();
+
+    assert!(ArchiveReader::open(&archive_dir).is_err());
+    assert!(ArchiveWriter::open(&archive_dir).is_err());
+}
+
+#[test]
+fn test_oversized_record_length() {
+    let archive_dir = TempPath::new();
+    export_mock_genesis(&archive_dir);
+
+    // Claim the only record is 4 GB long.
+    let data_path = archive_dir.path().join(DATA_FILE_NAME);
+    let mut data = fs::read(&data_path).unwrap();
+    let payload_len_offset = HEADER_LEN as usize;
+    data[payload_len_offset..payload_len_offset + 4].copy_from_slice(&[0xff; 4]);
+    fs::write(&data_path, data).unwrap();
+
+    let mut reader = ArchiveReader::open(&archive_dir).unwrap();
+    let entry = reader.index()[0];
+    assert_eq!(entry.offset, HEADER_LEN);
+    assert!(reader.read_chunk(&entry).is_err());
+    assert!(ArchiveWriter::open(&archive_dir).is_err());
+}
@@ -0,0 +1,80 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{writer::ArchiveWriter, ArchiveChunk};
+use failure::prelude::*;
+use libradb::LibraDB;
+use logger::prelude::*;
+use std::cmp::min;
+
+/// Largest number of transactions LibraDB returns in a single read, and thus the largest chunk
+/// size.
+pub const MAX_CHUNK_SIZE: u64 = 1000;
+
+/// Appends to the archive of `writer` all the transactions committed in `db` that it does not
+/// hold yet, in chunks of `chunk_size` transactions. Returns the number of transactions appended.
+///
+/// The chunks of each past epoch are proven by the ledger info ending it, so that the archive
+/// carries the validator sets to verify the following epochs with. The chunks of the current epoch
+/// are proven by the latest ledger info of `db` at the time of the call, so transactions committed
+/// during the export are left for the next one.
+pub fn export(db: &LibraDB, writer: &mut ArchiveWriter, chunk_size: u64) -> Result<u64> {
+    ensure!(
+        chunk_size > 0 && chunk_size <= MAX_CHUNK_SIZE,
+        "Chunk size must be between 1 and {}, got {}.",
+        MAX_CHUNK_SIZE,
+        chunk_size,
+    );
+    let (_, latest_ledger_info_with_sigs, _, _) = db.update_to_latest_ledger(0, vec![])?;
+    let ledger_version = latest_ledger_info_with_sigs.ledger_info().version();
+    // Keep the ledger as of `ledger_version` readable until all the chunks are proven against it.
+    let _snapshot = db.pin_snapshot(ledger_version)?;
+    let first_version = writer.next_version();
+    ensure!(
+        first_version <= ledger_version + 1,
+        "Archive holds transactions up to version {}, beyond the latest version {} of the DB.",
+        first_version - 1,
+        ledger_version,
+    );
+
+    let mut epoch_ending_ledger_infos = db
+        .get_latest_ledger_infos_per_epoch(0)?
+        .into_iter()
+        .filter(|li| {
+            li.ledger_info().next_validator_set().is_some()
+                && li.ledger_info().version() < ledger_version
+        })
+        .collect::<Vec<_>>();
+    epoch_ending_ledger_infos.sort_by_key(|li| li.ledger_info().epoch_num());
+    epoch_ending_ledger_infos.push(latest_ledger_info_with_sigs);
+
+    for ledger_info_with_sigs in epoch_ending_ledger_infos {
+        let proven_version = ledger_info_with_sigs.ledger_info().version();
+        while writer.next_version() <= proven_version {
+            let start_version = writer.next_version();
+            let limit = min(chunk_size, proven_version - start_version + 1);
+            let txn_list_with_proof = db.get_transactions(
+                start_version,
+                limit,
+                proven_version,
+                true, /* fetch_events */
+            )?;
+            writer.append(&ArchiveChunk::new(
+                ledger_info_with_sigs.clone(),
+                txn_list_with_proof,
+            ))?;
+            debug!(
+                "Archived transactions {} to {}.",
+                start_version,
+                writer.next_version() - 1
+            );
+        }
+    }
+
+    let num_exported = writer.next_version() - first_version;
+    info!(
+        "Archived {} transactions, up to version {}.",
+        num_exported, ledger_version
+    );
+    Ok(num_exported)
+}
@@ -0,0 +1,180 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-disk layout of the archive files.
+//!
+//! Both files start with an 8-byte magic followed by the format version as a big endian `u32`.
+//!
+//! A record of the data file is laid out as:
+//!
+//! ```text
+//! | payload length (u32) | SHA3-256 of the payload (32 bytes) | payload |
+//! ```
+//!
+//! where the payload is the length-prefixed protobuf encoding of the ledger info with signatures,
+//! followed by the protobuf encoding of the transaction list with proof. An entry of the index file
+//! is three big endian `u64`: the first version of the record, its number of transactions and its
+//! offset in the data file.
+
+use crate::ArchiveChunk;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::HashValue;
+use failure::prelude::*;
+use prost::Message;
+use prost_ext::MessageExt;
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+};
+use types::transaction::Version;
+
+pub(crate) const DATA_FILE_NAME: &str = "ledger.arc";
+pub(crate) const INDEX_FILE_NAME: &str = "ledger.idx";
+
+pub(crate) const DATA_MAGIC: &[u8; 8] = b"LIBRAARC";
+pub(crate) const INDEX_MAGIC: &[u8; 8] = b"LIBRAIDX";
+const FORMAT_VERSION: u32 = 1;
+
+/// Length of the header of both files.
+pub(crate) const HEADER_LEN: u64 = 12;
+/// Length of the part of a record preceding its payload.
+pub(crate) const RECORD_HEADER_LEN: u64 = 4 + HashValue::LENGTH as u64;
+pub(crate) const INDEX_ENTRY_LEN: u64 = 24;
+/// Largest payload of a record, well above the size of a chunk of
+/// [`MAX_CHUNK_SIZE`](crate::export::MAX_CHUNK_SIZE) transactions. Bounds what is allocated when
+/// reading a record whose length may be corrupt.
+pub(crate) const MAX_PAYLOAD_LEN: u64 = 1 << 28;
+
+/// Locates a record of the data file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IndexEntry {
+    pub first_version: Version,
+    pub num_transactions: u64,
+    pub offset: u64,
+}
+
+impl IndexEntry {
+    /// Version right after the last transaction of the record.
+    pub fn next_version(&self) -> Version {
+        self.first_version + self.num_transactions
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(INDEX_ENTRY_LEN as usize);
+        buf.write_u64::<BigEndian>(self.first_version)
+            .expect("Writing to a Vec never fails.");
+        buf.write_u64::<BigEndian>(self.num_transactions)
+            .expect("Writing to a Vec never fails.");
+        buf.write_u64::<BigEndian>(self.offset)
+            .expect("Writing to a Vec never fails.");
+        buf
+    }
+
+    pub(crate) fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            first_version: reader.read_u64::<BigEndian>()?,
+            num_transactions: reader.read_u64::<BigEndian>()?,
+            offset: reader.read_u64::<BigEndian>()?,
+        })
+    }
+}
+
+pub(crate) fn write_header<W: Write>(writer: &mut W, magic: &[u8; 8]) -> Result<()> {
+    writer.write_all(magic)?;
+    writer.write_u32::<BigEndian>(FORMAT_VERSION)?;
+    Ok(())
+}
+
+pub(crate) fn check_header<R: Read>(reader: &mut R, magic: &[u8; 8]) -> Result<()> {
+    let mut actual_magic = [0u8; 8];
+    reader.read_exact(&mut actual_magic)?;
+    ensure!(
+        &actual_magic == magic,
+        "Bad magic, expected {:?}, got {:?}.",
+        magic,
+        actual_magic,
+    );
+    let version = reader.read_u32::<BigEndian>()?;
+    ensure!(
+        version == FORMAT_VERSION,
+        "Unsupported archive format version {}, expected {}.",
+        version,
+        FORMAT_VERSION,
+    );
+    Ok(())
+}
+
+/// Returns the record holding `chunk`, ready to be appended to the data file.
+pub(crate) fn encode_record(chunk: &ArchiveChunk) -> Result<Vec<u8>> {
+    let ledger_info: types::proto::types::LedgerInfoWithSignatures =
+        chunk.ledger_info_with_sigs.clone().into();
+    let txn_list: types::proto::types::TransactionListWithProof =
+        chunk.txn_list_with_proof.clone().into();
+    let ledger_info_bytes = ledger_info.to_vec()?;
+
+    let mut payload = Vec::new();
+    payload.write_u32::<BigEndian>(ledger_info_bytes.len() as u32)?;
+    payload.extend(ledger_info_bytes);
+    payload.extend(txn_list.to_vec()?);
+    ensure!(
+        payload.len() as u64 <= MAX_PAYLOAD_LEN,
+        "Record of {} bytes exceeds the limit of {} bytes.",
+        payload.len(),
+        MAX_PAYLOAD_LEN,
+    );
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+    record.write_u32::<BigEndian>(payload.len() as u32)?;
+    record.extend(HashValue::from_sha3_256(&payload).to_vec());
+    record.extend(payload);
+    Ok(record)
+}
+
+/// Reads the record at the current position of `reader`, checking its checksum. Fails without
+/// allocating it if the payload is longer than `max_payload_len` or [`MAX_PAYLOAD_LEN`].
+pub(crate) fn read_record<R: Read>(reader: &mut R, max_payload_len: u64) -> Result<ArchiveChunk> {
+    let payload_len = read_payload_len(reader)?;
+    ensure!(
+        payload_len <= max_payload_len,
+        "Payload length {} exceeds the {} bytes left in the file.",
+        payload_len,
+        max_payload_len,
+    );
+    let mut checksum = [0u8; HashValue::LENGTH];
+    reader.read_exact(&mut checksum)?;
+    let mut payload = vec![0u8; payload_len as usize];
+    reader.read_exact(&mut payload)?;
+    ensure!(
+        HashValue::from_sha3_256(&payload) == HashValue::new(checksum),
+        "Checksum mismatch.",
+    );
+    decode_payload(&payload)
+}
+
+/// Reads the length of the payload of the record at the current position of `reader`.
+pub(crate) fn read_payload_len<R: Read>(reader: &mut R) -> Result<u64> {
+    let payload_len = u64::from(reader.read_u32::<BigEndian>()?);
+    ensure!(
+        payload_len <= MAX_PAYLOAD_LEN,
+        "Payload length {} exceeds the limit of {} bytes.",
+        payload_len,
+        MAX_PAYLOAD_LEN,
+    );
+    Ok(payload_len)
+}
+
+fn decode_payload(mut payload: &[u8]) -> Result<ArchiveChunk> {
+    let ledger_info_len = payload.read_u32::<BigEndian>()? as usize;
+    ensure!(
+        ledger_info_len <= payload.len(),
+        "Ledger info length {} exceeds payload.",
+        ledger_info_len,
+    );
+    let (ledger_info_bytes, txn_list_bytes) = payload.split_at(ledger_info_len);
+    let ledger_info = types::proto::types::LedgerInfoWithSignatures::decode(ledger_info_bytes)?;
+    let txn_list = types::proto::types::TransactionListWithProof::decode(txn_list_bytes)?;
+    Ok(ArchiveChunk::new(
+        TryFrom::try_from(ledger_info)?,
+        TryFrom::try_from(txn_list)?,
+    ))
+}
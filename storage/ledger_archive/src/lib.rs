@@ -0,0 +1,81 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This crate exports the committed ledger into an offline archive, and reads it back.
+//!
+//! An archive is a directory holding two append-only files:
+//!
+//! * `ledger.arc`, the data file, is a sequence of checksummed records. Each record is a
+//!   [`ArchiveChunk`]: a list of consecutive transactions together with their events, and the
+//!   proofs of these transactions against the ledger info carried by the chunk.
+//! * `ledger.idx`, the index file, has a fixed size entry for each record of the data file, giving
+//!   the versions of its transactions and its offset in the data file.
+//!
+//! A record is only ever referenced by the index once it is fully written to the data file, so
+//! after a crash the writer drops anything past the last indexed record and resumes from there.
+//!
+//! Everything in an archive can be verified without access to the [`LibraDB`](libradb::LibraDB)
+//! it was exported from: see [`ArchiveReader::verify`](reader::ArchiveReader::verify).
+
+pub mod export;
+pub mod reader;
+pub mod writer;
+
+mod format;
+
+#[cfg(test)]
+mod archive_test;
+
+pub use format::IndexEntry;
+
+use failure::prelude::*;
+use types::{
+    crypto_proxies::LedgerInfoWithSignatures,
+    transaction::{TransactionListWithProof, Version},
+};
+
+/// Transactions archived as a single record, with the proofs needed to verify them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArchiveChunk {
+    pub ledger_info_with_sigs: LedgerInfoWithSignatures,
+    pub txn_list_with_proof: TransactionListWithProof,
+}
+
+impl ArchiveChunk {
+    pub fn new(
+        ledger_info_with_sigs: LedgerInfoWithSignatures,
+        txn_list_with_proof: TransactionListWithProof,
+    ) -> Self {
+        Self {
+            ledger_info_with_sigs,
+            txn_list_with_proof,
+        }
+    }
+
+    pub fn first_version(&self) -> Option<Version> {
+        self.txn_list_with_proof.first_transaction_version
+    }
+
+    pub fn num_transactions(&self) -> u64 {
+        self.txn_list_with_proof.len() as u64
+    }
+
+    /// Verifies that the chunk holds the events of its transactions, and that it starts at
+    /// `expected_first_version` and is proven by its ledger info. The signatures of the ledger
+    /// info are not checked.
+    pub fn verify(&self, expected_first_version: Version) -> Result<()> {
+        ensure!(
+            !self.txn_list_with_proof.is_empty(),
+            "Chunk has no transactions."
+        );
+        ensure!(
+            self.txn_list_with_proof.events.is_some(),
+            "Chunk starting at version {} has no events.",
+            expected_first_version,
+        );
+        self.txn_list_with_proof.verify(
+            self.ledger_info_with_sigs.ledger_info(),
+            Some(expected_first_version),
+        )
+    }
+}
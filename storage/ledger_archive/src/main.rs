@@ -0,0 +1,80 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use config::{config::PersistableConfig, trusted_peers::ConsensusPeersConfig};
+use ledger_archive::{export::export, reader::ArchiveReader, writer::ArchiveWriter};
+use libradb::LibraDB;
+use std::{fs, path::PathBuf, process};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Export the committed ledger to an offline archive, and verify archives")]
+enum Command {
+    #[structopt(name = "export")]
+    /// Append the transactions committed since the last export to an archive
+    Export {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, as in its NodeConfig
+        db_dir: PathBuf,
+        #[structopt(long, parse(from_os_str))]
+        /// Directory of the archive, created if needed
+        archive_dir: PathBuf,
+        #[structopt(long, default_value = "1000")]
+        /// Number of transactions per archive record
+        chunk_size: u64,
+    },
+    #[structopt(name = "verify")]
+    /// Verify the checksums, index, proofs and signatures of an archive
+    Verify {
+        #[structopt(long, parse(from_os_str))]
+        /// Directory of the archive
+        archive_dir: PathBuf,
+        #[structopt(long, parse(from_os_str))]
+        /// Consensus peers file holding the validator set of epoch 0
+        consensus_peers: PathBuf,
+    },
+}
+
+fn main() {
+    let _logger =
+        logger::set_default_global_logger(false /* async */, None /* chan_size */);
+    let result = match Command::from_args() {
+        Command::Export {
+            db_dir,
+            archive_dir,
+            chunk_size,
+        } => LibraDB::open_readonly(&db_dir).and_then(|db| {
+            let mut writer = ArchiveWriter::open(&archive_dir)?;
+            let num_exported = export(&db, &mut writer, chunk_size)?;
+            println!(
+                "Exported {} transactions, the archive now holds {} transactions.",
+                num_exported,
+                writer.next_version(),
+            );
+            Ok(())
+        }),
+        Command::Verify {
+            archive_dir,
+            consensus_peers,
+        } => fs::read_to_string(&consensus_peers)
+            .map_err(Into::into)
+            .and_then(|contents| ConsensusPeersConfig::parse(&contents))
+            .and_then(|peers| {
+                let mut reader = ArchiveReader::open(&archive_dir)?;
+                reader.verify(peers.get_validator_verifier())
+            })
+            .map(|summary| {
+                println!(
+                    "Verified {} transactions in {} chunks.",
+                    summary.num_transactions, summary.num_chunks
+                );
+                if let Some(ledger_info) = summary.latest_ledger_info {
+                    println!("Latest ledger info: {}", ledger_info);
+                }
+            }),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
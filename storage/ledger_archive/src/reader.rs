@@ -0,0 +1,187 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    format::{
+        check_header, read_record, IndexEntry, DATA_FILE_NAME, DATA_MAGIC, HEADER_LEN,
+        INDEX_ENTRY_LEN, INDEX_FILE_NAME, INDEX_MAGIC, RECORD_HEADER_LEN,
+    },
+    ArchiveChunk,
+};
+use failure::prelude::*;
+use std::{
+    fs::File,
+    io::{BufReader, Seek, SeekFrom},
+    path::Path,
+};
+use types::{crypto_proxies::ValidatorVerifier, ledger_info::LedgerInfo, transaction::Version};
+
+/// Reads an archive written by [`ArchiveWriter`](crate::writer::ArchiveWriter).
+pub struct ArchiveReader {
+    data_file: BufReader<File>,
+    data_len: u64,
+    index: Vec<IndexEntry>,
+}
+
+/// What [`ArchiveReader::verify`] went through.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifySummary {
+    pub num_chunks: usize,
+    pub num_transactions: u64,
+    /// Ledger info of the last chunk, which proves the last archived transaction.
+    pub latest_ledger_info: Option<LedgerInfo>,
+}
+
+impl ArchiveReader {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut data_file = BufReader::new(File::open(dir.join(DATA_FILE_NAME))?);
+        check_header(&mut data_file, DATA_MAGIC)?;
+        let data_len = data_file.get_ref().metadata()?.len();
+
+        let mut index_file = BufReader::new(File::open(dir.join(INDEX_FILE_NAME))?);
+        check_header(&mut index_file, INDEX_MAGIC)?;
+        let index_len = index_file
+            .get_ref()
+            .metadata()?
+            .len()
+            .checked_sub(HEADER_LEN)
+            .ok_or_else(|| format_err!("Index file is shorter than its header."))?;
+        ensure!(
+            index_len % INDEX_ENTRY_LEN == 0,
+            "Index file ends with a partial entry.",
+        );
+        let index = (0..index_len / INDEX_ENTRY_LEN)
+            .map(|_| IndexEntry::decode(&mut index_file))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            data_file,
+            data_len,
+            index,
+        })
+    }
+
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    /// Version of the transaction following the last archived one.
+    pub fn next_version(&self) -> Version {
+        self.index.last().map_or(0, IndexEntry::next_version)
+    }
+
+    /// Reads the chunk located by `entry`, checking that it matches the entry. The proofs of the
+    /// chunk are not verified.
+    pub fn read_chunk(&mut self, entry: &IndexEntry) -> Result<ArchiveChunk> {
+        let max_payload_len = entry
+            .offset
+            .checked_add(RECORD_HEADER_LEN)
+            .and_then(|payload_offset| self.data_len.checked_sub(payload_offset))
+            .ok_or_else(|| {
+                format_err!(
+                    "Index entry {:?} points past the end of the data file.",
+                    entry
+                )
+            })?;
+        self.data_file.seek(SeekFrom::Start(entry.offset))?;
+        let chunk = read_record(&mut self.data_file, max_payload_len)
+            .map_err(|e| format_err!("Bad record at offset {}: {}", entry.offset, e))?;
+        ensure!(
+            chunk.first_version() == Some(entry.first_version)
+                && chunk.num_transactions() == entry.num_transactions,
+            "Record at offset {} does not match its index entry {:?}.",
+            entry.offset,
+            entry,
+        );
+        Ok(chunk)
+    }
+
+    /// Reads the chunk holding the transaction at `version`, if it is archived.
+    pub fn read_chunk_of_version(&mut self, version: Version) -> Result<Option<ArchiveChunk>> {
+        let position = self
+            .index
+            .binary_search_by(|entry| {
+                if entry.next_version() <= version {
+                    std::cmp::Ordering::Less
+                } else if entry.first_version > version {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .ok();
+        match position {
+            Some(position) => {
+                let entry = self.index[position];
+                self.read_chunk(&entry).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the whole archive and verifies it: every record must be intact and indexed, the
+    /// records must cover a contiguous range of versions starting at 0, and each of them must be
+    /// proven by its ledger info.
+    ///
+    /// The ledger infos must be signed by the validators of their epoch: `validator_verifier` is
+    /// the validator set of epoch 0, and the validator set of each following epoch is the one
+    /// carried by the ledger info ending the previous epoch, which must be archived as the ledger
+    /// info of the last chunk of that epoch.
+    pub fn verify(&mut self, mut validator_verifier: ValidatorVerifier) -> Result<VerifySummary> {
+        let mut expected_offset = HEADER_LEN;
+        let mut expected_version = 0;
+        let mut epoch = 0;
+        let mut latest_ledger_info = None;
+        for entry in self.index.clone() {
+            ensure!(
+                entry.offset == expected_offset && entry.first_version == expected_version,
+                "Index entry {:?} does not follow the previous one.",
+                entry,
+            );
+            let chunk = self.read_chunk(&entry)?;
+            chunk.verify(expected_version)?;
+            let ledger_info = chunk.ledger_info_with_sigs.ledger_info();
+            ensure!(
+                ledger_info.epoch_num() == epoch,
+                "Chunk starting at version {} is proven by a ledger info of epoch {}, but the \
+                 archive lacks the ledger info ending epoch {}.",
+                entry.first_version,
+                ledger_info.epoch_num(),
+                epoch,
+            );
+            chunk
+                .ledger_info_with_sigs
+                .verify(&validator_verifier)
+                .map_err(|e| {
+                    format_err!(
+                        "Bad signatures on the ledger info of version {}: {:?}",
+                        ledger_info.version(),
+                        e,
+                    )
+                })?;
+            if let Some(next_validator_set) = ledger_info.next_validator_set() {
+                // Only the last chunk proven by the ledger info ending an epoch moves to the next.
+                if ledger_info.version() + 1 == entry.next_version() {
+                    epoch += 1;
+                    validator_verifier = ValidatorVerifier::from(next_validator_set);
+                }
+            }
+
+            expected_offset = self.data_file.seek(SeekFrom::Current(0))?;
+            expected_version = entry.next_version();
+            latest_ledger_info = Some(chunk.ledger_info_with_sigs.ledger_info().clone());
+        }
+        ensure!(
+            expected_offset == self.data_len,
+            "Data file has {} bytes past its last indexed record.",
+            self.data_len - expected_offset,
+        );
+
+        Ok(VerifySummary {
+            num_chunks: self.index.len(),
+            num_transactions: expected_version,
+            latest_ledger_info,
+        })
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    format::{
+        check_header, encode_record, read_payload_len, write_header, IndexEntry, DATA_FILE_NAME,
+        DATA_MAGIC, HEADER_LEN, INDEX_ENTRY_LEN, INDEX_FILE_NAME, INDEX_MAGIC, RECORD_HEADER_LEN,
+    },
+    ArchiveChunk,
+};
+use failure::prelude::*;
+use logger::prelude::*;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+use types::transaction::Version;
+
+/// Appends chunks to an archive.
+///
+/// Every chunk is verified before being written, and must start right after the last transaction
+/// of the archive, so the archive always holds a contiguous range of versions starting at 0.
+pub struct ArchiveWriter {
+    data_file: File,
+    index_file: File,
+    /// Length of the data file, up to the end of the last indexed record.
+    data_len: u64,
+    next_version: Version,
+}
+
+impl ArchiveWriter {
+    /// Opens the archive in `dir`, creating it if needed. Anything written to an existing archive
+    /// after its last complete append is discarded.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut data_file = open_file(&dir.join(DATA_FILE_NAME), DATA_MAGIC)?;
+        let mut index_file = open_file(&dir.join(INDEX_FILE_NAME), INDEX_MAGIC)?;
+
+        // An index entry is written after its record, so a partial entry at the end of the index
+        // is the only possible leftover of an interrupted append.
+        let num_entries = index_file
+            .metadata()?
+            .len()
+            .checked_sub(HEADER_LEN)
+            .ok_or_else(|| format_err!("Index file is shorter than its header."))?
+            / INDEX_ENTRY_LEN;
+        let index_len = HEADER_LEN + num_entries * INDEX_ENTRY_LEN;
+        index_file.set_len(index_len)?;
+
+        let (data_len, next_version) = if num_entries == 0 {
+            (HEADER_LEN, 0)
+        } else {
+            index_file.seek(SeekFrom::Start(index_len - INDEX_ENTRY_LEN))?;
+            let last_entry = IndexEntry::decode(&mut index_file)?;
+            data_file.seek(SeekFrom::Start(last_entry.offset))?;
+            let payload_len = read_payload_len(&mut data_file)
+                .map_err(|e| format_err!("Bad record at offset {}: {}", last_entry.offset, e))?;
+            let data_len = last_entry
+                .offset
+                .checked_add(RECORD_HEADER_LEN + payload_len)
+                .ok_or_else(|| format_err!("Bad last index entry {:?}.", last_entry))?;
+            (data_len, last_entry.next_version())
+        };
+        let actual_data_len = data_file.metadata()?.len();
+        ensure!(
+            actual_data_len >= data_len,
+            "Data file is shorter ({} bytes) than its index implies ({} bytes).",
+            actual_data_len,
+            data_len,
+        );
+        if actual_data_len > data_len {
+            warn!(
+                "Dropping {} bytes written after the last indexed record of the archive.",
+                actual_data_len - data_len,
+            );
+            data_file.set_len(data_len)?;
+        }
+        data_file.sync_all()?;
+        index_file.sync_all()?;
+
+        Ok(Self {
+            data_file,
+            index_file,
+            data_len,
+            next_version,
+        })
+    }
+
+    /// Version of the next transaction to archive, i.e. the number of archived transactions.
+    pub fn next_version(&self) -> Version {
+        self.next_version
+    }
+
+    /// Verifies `chunk`, then durably appends it to the archive.
+    pub fn append(&mut self, chunk: &ArchiveChunk) -> Result<()> {
+        chunk.verify(self.next_version)?;
+        let record = encode_record(chunk)?;
+        let entry = IndexEntry {
+            first_version: self.next_version,
+            num_transactions: chunk.num_transactions(),
+            offset: self.data_len,
+        };
+
+        self.data_file.seek(SeekFrom::Start(self.data_len))?;
+        self.data_file.write_all(&record)?;
+        self.data_file.sync_data()?;
+        self.index_file.seek(SeekFrom::End(0))?;
+        self.index_file.write_all(&entry.encode())?;
+        self.index_file.sync_data()?;
+
+        self.data_len += record.len() as u64;
+        self.next_version = entry.next_version();
+        Ok(())
+    }
+}
+
+/// Opens the file at `path` for writing, writing its header if it is new and checking it
+/// otherwise.
+fn open_file(path: &Path, magic: &[u8; 8]) -> Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)?;
+    if file.metadata()?.len() == 0 {
+        write_header(&mut file, magic)?;
+    } else {
+        check_header(&mut file, magic)
+            .map_err(|e| format_err!("Invalid archive file {:?}: {}", path, e))?;
+    }
+    Ok(file)
+}
@@ -21,6 +21,7 @@ mod event_store;
 mod ledger_counters;
 mod ledger_store;
 mod pruner;
+mod read_snapshot;
 mod state_store;
 mod system_store;
 mod transaction_store;
@@ -28,6 +29,8 @@ mod transaction_store;
 #[cfg(test)]
 mod libradb_test;
 
+pub use crate::read_snapshot::ReadSnapshot;
+
 use crate::{
     change_set::{ChangeSet, SealedChangeSet},
     errors::LibraDbError,
@@ -35,6 +38,7 @@ use crate::{
     ledger_counters::LedgerCounters,
     ledger_store::LedgerStore,
     pruner::Pruner,
+    read_snapshot::SnapshotPins,
     schema::*,
     state_store::StateStore,
     system_store::SystemStore,
@@ -47,8 +51,15 @@ use lazy_static::lazy_static;
 use logger::prelude::*;
 use metrics::OpMetrics;
 use schemadb::{ColumnFamilyOptions, ColumnFamilyOptionsMap, DB, DEFAULT_CF_NAME};
-use std::{convert::TryInto, iter::Iterator, path::Path, sync::Arc, time::Instant};
-use storage_proto::StartupInfo;
+use std::{
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+    iter::Iterator,
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+use storage_proto::{AccountStateChange, ResourceChange, StartupInfo};
 use types::{
     access_path::AccessPath,
     account_address::AccountAddress,
@@ -73,6 +84,8 @@ lazy_static! {
 
 const MAX_LIMIT: u64 = 1000;
 const MAX_REQUEST_ITEMS: u64 = 100;
+/// Name of the directory of the DB under its root path.
+const LIBRADB_NAME: &str = "libradb";
 
 fn error_if_too_many_requested(num_requested: u64, max_allowed: u64) -> Result<()> {
     if num_requested > max_allowed {
@@ -91,7 +104,8 @@ pub struct LibraDB {
     state_store: StateStore,
     event_store: EventStore,
     system_store: SystemStore,
-    pruner: Pruner,
+    pruner: Option<Pruner>,
+    snapshot_pins: Arc<SnapshotPins>,
 }
 
 impl LibraDB {
@@ -100,11 +114,74 @@ impl LibraDB {
 
     /// This creates an empty LibraDB instance on disk or opens one if it already exists.
     pub fn new<P: AsRef<Path> + Clone>(db_root_path: P) -> Self {
-        let cf_opts_map: ColumnFamilyOptionsMap = [
+        let path = db_root_path.as_ref().join(LIBRADB_NAME);
+        let instant = Instant::now();
+        let db = Arc::new(
+            DB::open(path.clone(), Self::column_families())
+                .unwrap_or_else(|e| panic!("LibraDB open failed: {:?}", e)),
+        );
+
+        info!(
+            "Opened LibraDB at {:?} in {} ms",
+            path,
+            instant.elapsed().as_millis()
+        );
+
+        let snapshot_pins = SnapshotPins::new();
+        let pruner = Pruner::new(
+            Arc::clone(&db),
+            Self::NUM_HISTORICAL_VERSIONS_TO_KEEP,
+            Arc::clone(&snapshot_pins),
+        );
+        Self::new_with_db(db, Some(pruner), snapshot_pins)
+    }
+
+    /// Opens an existing LibraDB instance on disk in read-only mode, e.g. for inspecting the
+    /// ledger of a node that is not running. No pruner is started and every write fails.
+    pub fn open_readonly<P: AsRef<Path>>(db_root_path: P) -> Result<Self> {
+        let path = db_root_path.as_ref().join(LIBRADB_NAME);
+        let db = Arc::new(DB::open_readonly(path, Self::column_families())?);
+        Ok(Self::new_with_db(db, None, SnapshotPins::new()))
+    }
+
+    /// Returns whether a LibraDB instance was ever created under `db_root_path`, i.e. whether
+    /// [`open_readonly`](LibraDB::open_readonly) can be expected to succeed.
+    pub fn exists<P: AsRef<Path>>(db_root_path: P) -> bool {
+        db_root_path.as_ref().join(LIBRADB_NAME).is_dir()
+    }
+
+    fn new_with_db(db: Arc<DB>, pruner: Option<Pruner>, snapshot_pins: Arc<SnapshotPins>) -> Self {
+        let libra_db = LibraDB {
+            db: Arc::clone(&db),
+            event_store: EventStore::new(Arc::clone(&db)),
+            ledger_store: LedgerStore::new(Arc::clone(&db)),
+            state_store: StateStore::new(Arc::clone(&db)),
+            transaction_store: TransactionStore::new(Arc::clone(&db)),
+            system_store: SystemStore::new(Arc::clone(&db)),
+            pruner,
+            snapshot_pins,
+        };
+        // The pruner of a previous run may have pruned everything before the versions it keeps
+        // readable, which therefore can't be pinned.
+        if let Some((latest_version, _)) = libra_db
+            .ledger_store
+            .get_latest_transaction_info_option()
+            .expect("Reading the latest transaction info should succeed.")
+        {
+            libra_db.snapshot_pins.forbid_pins_before(
+                latest_version.saturating_sub(Self::NUM_HISTORICAL_VERSIONS_TO_KEEP),
+            );
+        }
+        libra_db
+    }
+
+    fn column_families() -> ColumnFamilyOptionsMap {
+        [
             (
                 /* LedgerInfo CF = */ DEFAULT_CF_NAME,
                 ColumnFamilyOptions::default(),
             ),
+            (ACCOUNT_STATE_CHANGE_CF_NAME, ColumnFamilyOptions::default()),
             (EVENT_ACCUMULATOR_CF_NAME, ColumnFamilyOptions::default()),
             (EVENT_BY_KEY_CF_NAME, ColumnFamilyOptions::default()),
             (EVENT_CF_NAME, ColumnFamilyOptions::default()),
@@ -128,30 +205,7 @@ impl LibraDB {
         ]
         .iter()
         .cloned()
-        .collect();
-
-        let path = db_root_path.as_ref().join("libradb");
-        let instant = Instant::now();
-        let db = Arc::new(
-            DB::open(path.clone(), cf_opts_map)
-                .unwrap_or_else(|e| panic!("LibraDB open failed: {:?}", e)),
-        );
-
-        info!(
-            "Opened LibraDB at {:?} in {} ms",
-            path,
-            instant.elapsed().as_millis()
-        );
-
-        LibraDB {
-            db: Arc::clone(&db),
-            event_store: EventStore::new(Arc::clone(&db)),
-            ledger_store: LedgerStore::new(Arc::clone(&db)),
-            state_store: StateStore::new(Arc::clone(&db)),
-            transaction_store: TransactionStore::new(Arc::clone(&db)),
-            system_store: SystemStore::new(Arc::clone(&db)),
-            pruner: Pruner::new(Arc::clone(&db), Self::NUM_HISTORICAL_VERSIONS_TO_KEEP),
-        }
+        .collect()
     }
 
     // ================================== Public API ==================================
@@ -385,7 +439,9 @@ impl LibraDB {
                 .expect("Counters should be bumped with transactions being saved.")
                 .bump_op_counters();
 
-            self.pruner.wake(last_version);
+            if let Some(pruner) = &self.pruner {
+                pruner.wake(last_version);
+            }
         }
 
         Ok(())
@@ -585,6 +641,104 @@ impl LibraDB {
         }))
     }
 
+    /// Pins the ledger state as of `version`, so that the pruner keeps it readable until the
+    /// returned snapshot is dropped. Fails if `version` may already have been pruned.
+    ///
+    /// This is meant for long-running reads, e.g. serving chunks or exporting the ledger, which
+    /// generate proofs against a fixed version over many calls.
+    pub fn pin_snapshot(&self, version: Version) -> Result<ReadSnapshot> {
+        self.snapshot_pins.pin(version)
+    }
+
+    // ================================== Indexer APIs ============================================
+    /// Gets the accounts whose state changed in any version in `(start_version, end_version]`, in
+    /// order of their address, as recorded when the transactions were saved. If `fetch_resources`
+    /// is set, each of them comes with its resources which differ between the two versions, which
+    /// requires the state as of `start_version` not to have been pruned.
+    ///
+    /// At most `MAX_LIMIT` versions are covered by one call. Fails if some of the versions are not
+    /// indexed, because they were saved by a version of the DB which didn't index them or because
+    /// they have been pruned.
+    pub fn get_account_state_changes(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Result<Vec<AccountStateChange>> {
+        ensure!(
+            start_version <= end_version,
+            "The start version {} should be equal to or older than end version {}.",
+            start_version,
+            end_version
+        );
+        error_if_too_many_requested(end_version - start_version, MAX_LIMIT)?;
+        let latest_version = self.get_latest_version()?;
+        ensure!(
+            end_version <= latest_version,
+            "The end version {} is greater than the latest version {}.",
+            end_version,
+            latest_version
+        );
+
+        self.state_store
+            .get_changed_accounts(start_version, end_version)?
+            .into_iter()
+            .map(|address| {
+                let resource_changes = if fetch_resources {
+                    self.get_resource_changes(address, start_version, end_version)?
+                } else {
+                    vec![]
+                };
+                Ok(AccountStateChange {
+                    address,
+                    resource_changes,
+                })
+            })
+            .collect()
+    }
+
+    // Diffs the resources of the account as of the two versions.
+    fn get_resource_changes(
+        &self,
+        address: AccountAddress,
+        start_version: Version,
+        end_version: Version,
+    ) -> Result<Vec<ResourceChange>> {
+        let get_resources = |version| -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+            match self
+                .state_store
+                .get_account_state_with_proof_by_version(address, version)?
+                .0
+            {
+                Some(blob) => BTreeMap::try_from(&blob),
+                None => Ok(BTreeMap::new()),
+            }
+        };
+        let mut old_resources = get_resources(start_version)?;
+        let new_resources = get_resources(end_version)?;
+
+        let mut resource_changes = vec![];
+        for (path, new_value) in new_resources {
+            let old_value = old_resources.remove(&path);
+            if old_value.as_ref() != Some(&new_value) {
+                resource_changes.push(ResourceChange {
+                    path,
+                    old_value,
+                    new_value: Some(new_value),
+                });
+            }
+        }
+        resource_changes.extend(old_resources.into_iter().map(|(path, old_value)| {
+            ResourceChange {
+                path,
+                old_value: Some(old_value),
+                new_value: None,
+            }
+        }));
+        resource_changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(resource_changes)
+    }
+
     // ======================= State Synchronizer Internal APIs ===================================
     /// Gets a batch of transactions for the purpose of synchronizing state to another node.
     ///
@@ -602,6 +756,10 @@ impl LibraDB {
             return Ok(TransactionListWithProof::new_empty());
         }
 
+        // The chunk is proven against `ledger_version`, which is pinned until it is. Transactions
+        // and their accumulator proofs are never pruned, so chunks proven against a version which
+        // can't be pinned anymore, e.g. the end of an old epoch, are still served.
+        let _snapshot = self.pin_snapshot(ledger_version).ok();
         let limit = std::cmp::min(limit, ledger_version - start_version + 1);
         let txn_and_txn_info_list = (start_version..start_version + limit)
             .map(|version| {
@@ -331,6 +331,19 @@ fn verify_committed_transactions(
             assert_eq!(account_state_with_proof.blob, Some(expected_blob.clone()));
             account_state_with_proof.verify(ledger_info, cur_ver, *addr)?;
         }
+
+        // Verify the accounts recorded as changed by the transaction.
+        let mut changed_accounts: Vec<_> = txn_to_commit.account_states().keys().collect();
+        changed_accounts.sort();
+        let account_state_changes =
+            db.get_account_state_changes(cur_ver - 1, cur_ver, false /* fetch_resources */)?;
+        assert_eq!(
+            account_state_changes
+                .iter()
+                .map(|change| &change.address)
+                .collect::<Vec<_>>(),
+            changed_accounts
+        );
     }
 
     // Fetch and verify events.
@@ -463,4 +476,7 @@ fn test_too_many_requested() {
             0
         )
         .is_err());
+    assert!(db
+        .get_account_state_changes(0, 1001 /* end_version */, false)
+        .is_err());
 }
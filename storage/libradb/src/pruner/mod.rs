@@ -3,9 +3,14 @@
 
 //! This module provides `Pruner` which manages a thread pruning old data in the background and is
 //! meant to be triggered by other threads as they commit new data to the DB.
+//!
+//! The pruner never prunes past the oldest version pinned by a `ReadSnapshot`. Pruning held back
+//! by a snapshot resumes on the first wake after the snapshot is released.
 
 use crate::{
+    read_snapshot::SnapshotPins,
     schema::{
+        account_state_change::AccountStateChangeSchema,
         jellyfish_merkle_node::JellyfishMerkleNodeSchema, stale_node_index::StaleNodeIndexSchema,
     },
     OP_COUNTER,
@@ -54,14 +59,20 @@ pub(crate) struct Pruner {
 
 impl Pruner {
     /// Creates a worker thread that waits on a channel for pruning commands.
-    pub fn new(db: Arc<DB>, num_historical_versions_to_keep: u64) -> Self {
+    pub fn new(
+        db: Arc<DB>,
+        num_historical_versions_to_keep: u64,
+        snapshot_pins: Arc<SnapshotPins>,
+    ) -> Self {
         let (command_sender, command_receiver) = channel();
         let worker_progress = Arc::new(AtomicU64::new(0));
         let worker_progress_clone = Arc::clone(&worker_progress);
 
         let worker_thread = std::thread::Builder::new()
             .name("libradb_pruner".into())
-            .spawn(move || Worker::new(db, command_receiver, worker_progress_clone).work_loop())
+            .spawn(move || {
+                Worker::new(db, command_receiver, worker_progress_clone, snapshot_pins).work_loop()
+            })
             .expect("Creating pruner thread should succeed.");
 
         Self {
@@ -138,6 +149,8 @@ struct Worker {
     /// smaller than `V` are no longer readable.
     /// This being an atomic value is to communicate the info with the Pruner thread (for tests).
     least_readable_version: Arc<AtomicU64>,
+    /// Versions pinned by read snapshots, which must not be pruned.
+    snapshot_pins: Arc<SnapshotPins>,
     /// Indicates if there's NOT any pending work to do currently, to hint
     /// `Self::receive_commands()` to `recv()` blocking-ly.
     blocking_recv: bool,
@@ -152,11 +165,13 @@ impl Worker {
         db: Arc<DB>,
         command_receiver: Receiver<Command>,
         least_readable_version: Arc<AtomicU64>,
+        snapshot_pins: Arc<SnapshotPins>,
     ) -> Self {
         Self {
             db,
             command_receiver,
             least_readable_version,
+            snapshot_pins,
             target_least_readable_version: 0,
             blocking_recv: true,
             index_min_nonpurged_version: 0,
@@ -166,18 +181,21 @@ impl Worker {
 
     fn work_loop(mut self) {
         while self.receive_commands() {
+            // Versions pinned by read snapshots are kept, so the target may not be reachable yet.
+            let target_least_readable_version = self
+                .snapshot_pins
+                .start_pruning(self.target_least_readable_version);
             // Process a reasonably small batch of work before trying to receive commands again,
             // in case `Command::Quit` is received (that's when we should quit.)
             match prune_state(
                 Arc::clone(&self.db),
                 self.least_readable_version.load(Ordering::Relaxed),
-                self.target_least_readable_version,
+                target_least_readable_version,
                 Self::MAX_VERSIONS_TO_PRUNE_PER_BATCH,
             ) {
                 Ok(least_readable_version) => {
-                    // Make next recv() blocking if all done.
-                    self.blocking_recv =
-                        least_readable_version == self.target_least_readable_version;
+                    // Make next recv() blocking if all done, or if held back by a snapshot.
+                    self.blocking_recv = least_readable_version == target_least_readable_version;
 
                     // Log the progress.
                     self.least_readable_version
@@ -243,6 +261,9 @@ impl Worker {
     /// Purge the stale node index so that after restart not too much already pruned stuff is dealt
     /// with again (although no harm is done deleting those then non-existent things.)
     ///
+    /// The account state change index is purged along with it, since the changes made up to the
+    /// least readable version can't be queried once the state before them is pruned.
+    ///
     /// We issue (range) deletes on the index only periodically instead of after every pruning batch
     /// to avoid sending too many deletions to the DB, which takes disk space and slows it down.
     fn maybe_purge_index(&mut self) -> Result<()> {
@@ -262,6 +283,10 @@ impl Worker {
                     &self.index_min_nonpurged_version,
                     &new_min_non_purged_version, // end is exclusive
                 )?;
+                self.db.range_delete::<AccountStateChangeSchema, Version>(
+                    &self.index_min_nonpurged_version,
+                    &new_min_non_purged_version,
+                )?;
                 self.index_min_nonpurged_version = new_min_non_purged_version;
                 self.index_purged_at = now;
             }
@@ -55,6 +55,7 @@ fn test_pruner() {
     let pruner = Pruner::new(
         Arc::clone(&db),
         0, /* num_historical_versions_to_keep */
+        SnapshotPins::new(),
     );
 
     let _root0 = put_account_state_set(
@@ -142,6 +143,7 @@ fn test_worker_quit_eagerly() {
             Arc::clone(&db),
             command_receiver,
             Arc::new(AtomicU64::new(0)), /* progress */
+            SnapshotPins::new(),
         );
         command_sender
             .send(Command::Prune {
@@ -161,3 +163,78 @@ fn test_worker_quit_eagerly() {
         verify_state_in_store(state_store, address, Some(&value2), 2);
     }
 }
+
+#[test]
+fn test_pruner_respects_pinned_snapshots() {
+    let address = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let value0 = AccountStateBlob::from(vec![0x01]);
+    let value1 = AccountStateBlob::from(vec![0x02]);
+    let value2 = AccountStateBlob::from(vec![0x03]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir).db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    let snapshot_pins = SnapshotPins::new();
+    let pruner = Pruner::new(
+        Arc::clone(&db),
+        0, /* num_historical_versions_to_keep */
+        Arc::clone(&snapshot_pins),
+    );
+
+    let _root0 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value0.clone())],
+        0, /* version */
+    );
+    let _root1 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value1.clone())],
+        1, /* version */
+    );
+    let _root2 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value2.clone())],
+        2, /* version */
+    );
+
+    // Pinning version 1 holds the pruner back at version 1.
+    let snapshot = snapshot_pins.pin(1).unwrap();
+    assert_eq!(snapshot.version(), 1);
+    pruner.wake(2 /* latest_version */);
+    pruner.wake_and_wait(1 /* latest_version */).unwrap();
+    assert!(state_store
+        .get_account_state_with_proof_by_version(address, 0)
+        .is_err());
+    verify_state_in_store(state_store, address, Some(&value1), 1);
+    verify_state_in_store(state_store, address, Some(&value2), 2);
+
+    // Once the snapshot is released, pruning resumes on the next wake.
+    drop(snapshot);
+    pruner.wake_and_wait(2 /* latest_version */).unwrap();
+    assert!(state_store
+        .get_account_state_with_proof_by_version(address, 1)
+        .is_err());
+    verify_state_in_store(state_store, address, Some(&value2), 2);
+
+    // Pruned versions can't be pinned anymore.
+    assert!(snapshot_pins.pin(1).is_err());
+    assert!(snapshot_pins.pin(2).is_ok());
+}
+
+#[test]
+fn test_pins_forbidden_before_pruned_versions() {
+    let snapshot_pins = SnapshotPins::new();
+    assert!(snapshot_pins.pin(0).is_ok());
+
+    // As on open, after a previous run pruned everything before version 5.
+    snapshot_pins.forbid_pins_before(5);
+    assert!(snapshot_pins.pin(4).is_err());
+    assert!(snapshot_pins.pin(5).is_ok());
+
+    // The least pinnable version never moves back.
+    snapshot_pins.forbid_pins_before(3);
+    assert!(snapshot_pins.pin(4).is_err());
+}
@@ -0,0 +1,125 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module provides `ReadSnapshot`, which keeps the state as of a version readable for as long
+//! as it is held.
+//!
+//! Data written by a commit is never modified afterwards, so a consistent view of the ledger at a
+//! version only requires that the pruner does not delete what is needed to read at that version.
+//! The pruner consults `SnapshotPins` before each batch and never prunes past the oldest pinned
+//! version, so long-running reads, e.g. serving chunks or exporting the ledger, can generate proofs
+//! against their version without racing with the pruner. Compactions only drop data which was
+//! deleted, so they need no coordination.
+
+use failure::prelude::*;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use types::transaction::Version;
+
+/// The versions pinned by live `ReadSnapshot`s, shared between `LibraDB` and its pruner.
+#[derive(Default)]
+pub(crate) struct SnapshotPins {
+    inner: Mutex<PinsInner>,
+}
+
+#[derive(Default)]
+struct PinsInner {
+    /// Number of live snapshots pinning each version.
+    pinned: BTreeMap<Version, usize>,
+    /// Versions before this one may already be pruned and cannot be pinned anymore.
+    least_pinnable_version: Version,
+}
+
+impl SnapshotPins {
+    /// Constructor.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Pins `version`, failing if it may already have been pruned.
+    pub fn pin(self: &Arc<Self>, version: Version) -> Result<ReadSnapshot> {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Locking snapshot pins should not fail.");
+        ensure!(
+            version >= inner.least_pinnable_version,
+            "Version {} may have been pruned, versions before {} can't be pinned.",
+            version,
+            inner.least_pinnable_version,
+        );
+        *inner.pinned.entry(version).or_insert(0) += 1;
+        Ok(ReadSnapshot {
+            pins: Arc::clone(self),
+            version,
+        })
+    }
+
+    /// Makes the versions before `least_readable_version` unpinnable, e.g. on open, as a previous
+    /// run of the pruner may have pruned them.
+    pub fn forbid_pins_before(&self, least_readable_version: Version) {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Locking snapshot pins should not fail.");
+        if least_readable_version > inner.least_pinnable_version {
+            inner.least_pinnable_version = least_readable_version;
+        }
+    }
+
+    /// Called by the pruner before pruning up to `target_least_readable_version`. Returns the
+    /// version it may actually prune up to, which is capped by the oldest pinned version. Versions
+    /// before the returned one can't be pinned from then on.
+    pub fn start_pruning(&self, target_least_readable_version: Version) -> Version {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Locking snapshot pins should not fail.");
+        let least_readable_version = match inner.pinned.keys().next() {
+            Some(oldest_pinned) => std::cmp::min(*oldest_pinned, target_least_readable_version),
+            None => target_least_readable_version,
+        };
+        if least_readable_version > inner.least_pinnable_version {
+            inner.least_pinnable_version = least_readable_version;
+        }
+        inner.least_pinnable_version
+    }
+
+    fn unpin(&self, version: Version) {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Locking snapshot pins should not fail.");
+        let count = inner
+            .pinned
+            .get_mut(&version)
+            .expect("Pinned version should be tracked.");
+        *count -= 1;
+        if *count == 0 {
+            inner.pinned.remove(&version);
+        }
+    }
+}
+
+/// A consistent view of the ledger as of a version: as long as it is held, the pruner keeps
+/// everything needed to read at its version, including proofs. Obtained via
+/// [`LibraDB::pin_snapshot`](crate::LibraDB::pin_snapshot) and released on drop.
+pub struct ReadSnapshot {
+    pins: Arc<SnapshotPins>,
+    version: Version,
+}
+
+impl ReadSnapshot {
+    /// The pinned version.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        self.pins.unpin(self.version);
+    }
+}
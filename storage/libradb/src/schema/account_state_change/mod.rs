@@ -0,0 +1,78 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines physical storage schema for an index of the accounts whose state each
+//! transaction changed, so that the accounts modified over a range of versions can be found
+//! without going through the state Merkle tree.
+//!
+//! ```text
+//! |<-------key------->|
+//! | version | address |
+//! ```
+//!
+//! `version` is serialized in big endian so that records in RocksDB will be in order of its
+//! numeric value.
+
+use crate::schema::{ensure_slice_len_eq, ACCOUNT_STATE_CHANGE_CF_NAME};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use failure::prelude::*;
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, SeekKeyCodec, ValueCodec},
+};
+use std::{convert::TryFrom, mem::size_of};
+use types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    transaction::Version,
+};
+
+define_schema!(
+    AccountStateChangeSchema,
+    Key,
+    (),
+    ACCOUNT_STATE_CHANGE_CF_NAME
+);
+
+type Key = (Version, AccountAddress);
+
+impl KeyCodec<AccountStateChangeSchema> for Key {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        let (version, ref account_address) = *self;
+
+        let mut encoded = vec![];
+        encoded.write_u64::<BigEndian>(version)?;
+        encoded.extend_from_slice(&account_address.to_vec());
+
+        Ok(encoded)
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+
+        let version_size = size_of::<Version>();
+        let version = (&data[..version_size]).read_u64::<BigEndian>()?;
+        let address = AccountAddress::try_from(&data[version_size..version_size + ADDRESS_LENGTH])?;
+
+        Ok((version, address))
+    }
+}
+
+impl ValueCodec<AccountStateChangeSchema> for () {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, 0)?;
+        Ok(())
+    }
+}
+
+impl SeekKeyCodec<AccountStateChangeSchema> for Version {
+    fn encode_seek_key(&self) -> Result<Vec<u8>> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test;
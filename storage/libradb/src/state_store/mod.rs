@@ -10,6 +10,7 @@ use crate::{
     change_set::ChangeSet,
     ledger_counters::LedgerCounter,
     schema::{
+        account_state_change::AccountStateChangeSchema,
         jellyfish_merkle_node::JellyfishMerkleNodeSchema, stale_node_index::StaleNodeIndexSchema,
     },
 };
@@ -19,8 +20,11 @@ use jellyfish_merkle::{
     node_type::{LeafNode, Node, NodeKey},
     JellyfishMerkleTree, TreeReader,
 };
-use schemadb::DB;
-use std::{collections::HashMap, sync::Arc};
+use schemadb::{ReadOptions, DB};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 use types::{
     account_address::AccountAddress, account_state_blob::AccountStateBlob,
     proof::SparseMerkleProof, transaction::Version,
@@ -46,6 +50,41 @@ impl StateStore {
         Ok((blob, proof))
     }
 
+    /// Gets the addresses of the accounts whose state changed in any version in
+    /// `(start_version, end_version]`.
+    pub fn get_changed_accounts(
+        &self,
+        start_version: Version,
+        end_version: Version,
+    ) -> Result<BTreeSet<AccountAddress>> {
+        let mut addresses = BTreeSet::new();
+        let mut iter = self
+            .db
+            .iter::<AccountStateChangeSchema>(ReadOptions::default())?;
+        if start_version < end_version {
+            // Every transaction changes at least the account of its sender, so the index has a
+            // record for every version it covers.
+            iter.seek_to_first();
+            let first_indexed_version = iter.next().transpose()?.map(|((version, _), ())| version);
+            match first_indexed_version {
+                Some(first) if first <= start_version + 1 => (),
+                _ => bail!(
+                    "The changes after version {} are not indexed, they were saved before the \
+                     index existed or have been pruned.",
+                    start_version
+                ),
+            }
+        }
+        iter.seek(&(start_version + 1))?;
+        while let Some(((version, address), ())) = iter.next().transpose()? {
+            if version > end_version {
+                break;
+            }
+            addresses.insert(address);
+        }
+        Ok(addresses)
+    }
+
     /// Put the results generated by `account_state_sets` to `batch` and return the result root
     /// hashes for each write set.
     pub fn put_account_state_sets(
@@ -54,6 +93,17 @@ impl StateStore {
         first_version: Version,
         cs: &mut ChangeSet,
     ) -> Result<Vec<HashValue>> {
+        account_state_sets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, account_states)| {
+                account_states
+                    .keys()
+                    .map(move |address| (first_version + i as u64, *address))
+            })
+            .map(|key| cs.batch.put::<AccountStateChangeSchema>(&key, &()))
+            .collect::<Result<Vec<()>>>()?;
+
         let blob_sets = account_state_sets
             .into_iter()
             .map(|account_states| {
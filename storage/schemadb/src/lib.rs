@@ -209,6 +209,32 @@ impl DB {
         Ok(db)
     }
 
+    /// Opens an existing db at `path` with all the column families provided, in read-only mode.
+    /// Unlike [`DB::open`], this never creates the db or any column family and fails if the db
+    /// doesn't exist yet.
+    pub fn open_readonly<P: AsRef<Path>>(
+        path: P,
+        cf_opts_map: ColumnFamilyOptionsMap,
+    ) -> Result<Self> {
+        ensure!(
+            db_exists(path.as_ref()),
+            "DB at {:?} doesn't exist.",
+            path.as_ref()
+        );
+
+        let inner = rocksdb::DB::open_cf_for_read_only(
+            DBOptions::new(),
+            path.as_ref().to_str().ok_or_else(|| {
+                format_err!("Path {:?} can not be converted to string.", path.as_ref())
+            })?,
+            cf_opts_map.into_iter().collect(),
+            false, /* error_if_log_file_exist */
+        )
+        .map_err(convert_rocksdb_err)?;
+
+        Ok(DB { inner })
+    }
+
     fn open_cf<'a, P, T>(opts: DBOptions, path: P, cfds: Vec<T>) -> Result<DB>
     where
         P: AsRef<Path>,
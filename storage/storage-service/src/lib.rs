@@ -7,11 +7,15 @@
 //! [`storage_client`](../storage_client/index.html) instead of via
 //! [`StorageClient`](../storage_proto/proto/storage_grpc/struct.StorageClient.html) directly.
 
+pub mod local_client;
 pub mod mocks;
 
 use config::config::NodeConfig;
 use failure::prelude::*;
-use grpc_helpers::{provide_grpc_response, spawn_service_thread_with_drop_closure, ServerHandle};
+use grpc_helpers::{
+    provide_grpc_response, provide_grpc_streaming_response, spawn_service_thread_with_drop_closure,
+    ServerHandle,
+};
 use libradb::LibraDB;
 use logger::prelude::*;
 use metrics::counters::SVC_COUNTERS;
@@ -22,14 +26,17 @@ use std::{
     sync::{mpsc, Arc, Mutex},
 };
 use storage_proto::proto::storage::{
-    create_storage, GetAccountStateWithProofByVersionRequest,
-    GetAccountStateWithProofByVersionResponse, GetLatestLedgerInfosPerEpochRequest,
-    GetLatestLedgerInfosPerEpochResponse, GetStartupInfoRequest, GetStartupInfoResponse,
-    GetTransactionsRequest, GetTransactionsResponse, SaveTransactionsRequest,
-    SaveTransactionsResponse, Storage,
+    create_storage, GetAccountStateChangesRequest, GetAccountStateChangesResponse,
+    GetAccountStateWithProofByVersionRequest, GetAccountStateWithProofByVersionResponse,
+    GetLatestLedgerInfosPerEpochRequest, GetLatestLedgerInfosPerEpochResponse,
+    GetStartupInfoRequest, GetStartupInfoResponse, GetTransactionsRequest, GetTransactionsResponse,
+    SaveTransactionsRequest, SaveTransactionsResponse, Storage,
 };
 use types::proto::types::{UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse};
 
+/// Number of accounts in each response streamed by `GetAccountStateChanges`.
+const ACCOUNT_STATE_CHANGES_BATCH_SIZE: usize = 100;
+
 /// Starts storage service according to config.
 pub fn start_storage_service(config: &NodeConfig) -> ServerHandle {
     let (storage_service, shutdown_receiver) = StorageService::new(&config.get_storage_dir());
@@ -227,6 +234,22 @@ impl StorageService {
         let rust_resp = storage_proto::GetLatestLedgerInfosPerEpochResponse::new(ledger_infos);
         Ok(rust_resp.into())
     }
+
+    fn get_account_state_changes_inner(
+        &self,
+        req: GetAccountStateChangesRequest,
+    ) -> Result<Vec<GetAccountStateChangesResponse>> {
+        let rust_req = storage_proto::GetAccountStateChangesRequest::try_from(req)?;
+        let account_state_changes = self.db.get_account_state_changes(
+            rust_req.start_version,
+            rust_req.end_version,
+            rust_req.fetch_resources,
+        )?;
+        Ok(account_state_changes
+            .chunks(ACCOUNT_STATE_CHANGES_BATCH_SIZE)
+            .map(|batch| storage_proto::GetAccountStateChangesResponse::new(batch.to_vec()).into())
+            .collect())
+    }
 }
 
 impl Storage for StorageService {
@@ -301,6 +324,18 @@ impl Storage for StorageService {
         let resp = self.get_latest_ledger_infos_per_epoch_inner(req);
         provide_grpc_response(resp, ctx, sink);
     }
+
+    fn get_account_state_changes(
+        &mut self,
+        ctx: grpcio::RpcContext,
+        req: GetAccountStateChangesRequest,
+        sink: grpcio::ServerStreamingSink<GetAccountStateChangesResponse>,
+    ) {
+        debug!("[GRPC] Storage::get_account_state_changes");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let resp = self.get_account_state_changes_inner(req);
+        provide_grpc_streaming_response(resp, ctx, sink);
+    }
 }
 
 #[cfg(test)]
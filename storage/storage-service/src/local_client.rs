@@ -0,0 +1,183 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module provides a storage client serving [`LibraDB`] in-process, without going through
+//! the storage service.
+
+use failure::prelude::*;
+use futures::prelude::*;
+use libradb::LibraDB;
+use std::{path::Path, pin::Pin, sync::Arc};
+use storage_client::{StorageRead, StorageWrite};
+use storage_proto::{AccountStateChange, StartupInfo};
+use types::{
+    account_address::AccountAddress,
+    account_state_blob::AccountStateBlob,
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof},
+    get_with_proof::{RequestItem, ResponseItem},
+    proof::{AccumulatorConsistencyProof, SparseMerkleProof},
+    transaction::{TransactionListWithProof, TransactionToCommit, Version},
+};
+
+/// A storage client calling into a [`LibraDB`] owned by the process, for nodes which run all their
+/// components in a single process. It implements both [`StorageRead`] and [`StorageWrite`]; the
+/// asynchronous APIs complete immediately.
+#[derive(Clone)]
+pub struct LocalStorageClient {
+    db: Arc<LibraDB>,
+}
+
+impl LocalStorageClient {
+    /// Opens the [`LibraDB`] at `path`, creating it if needed.
+    pub fn new<P: AsRef<Path> + Clone>(path: P) -> Self {
+        Self {
+            db: Arc::new(LibraDB::new(path)),
+        }
+    }
+}
+
+impl StorageRead for LocalStorageClient {
+    fn update_to_latest_ledger(
+        &self,
+        client_known_version: Version,
+        request_items: Vec<RequestItem>,
+    ) -> Result<(
+        Vec<ResponseItem>,
+        LedgerInfoWithSignatures,
+        Vec<ValidatorChangeEventWithProof>,
+        AccumulatorConsistencyProof,
+    )> {
+        self.db
+            .update_to_latest_ledger(client_known_version, request_items)
+    }
+
+    fn update_to_latest_ledger_async(
+        &self,
+        client_known_version: Version,
+        request_items: Vec<RequestItem>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<(
+                        Vec<ResponseItem>,
+                        LedgerInfoWithSignatures,
+                        Vec<ValidatorChangeEventWithProof>,
+                        AccumulatorConsistencyProof,
+                    )>,
+                > + Send,
+        >,
+    > {
+        future::ready(self.update_to_latest_ledger(client_known_version, request_items)).boxed()
+    }
+
+    fn get_transactions(
+        &self,
+        start_version: Version,
+        batch_size: u64,
+        ledger_version: Version,
+        fetch_events: bool,
+    ) -> Result<TransactionListWithProof> {
+        self.db
+            .get_transactions(start_version, batch_size, ledger_version, fetch_events)
+    }
+
+    fn get_transactions_async(
+        &self,
+        start_version: Version,
+        batch_size: u64,
+        ledger_version: Version,
+        fetch_events: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<TransactionListWithProof>> + Send>> {
+        future::ready(self.get_transactions(
+            start_version,
+            batch_size,
+            ledger_version,
+            fetch_events,
+        ))
+        .boxed()
+    }
+
+    fn get_account_state_with_proof_by_version(
+        &self,
+        address: AccountAddress,
+        version: Version,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        self.db
+            .get_account_state_with_proof_by_version(address, version)
+    }
+
+    fn get_account_state_with_proof_by_version_async(
+        &self,
+        address: AccountAddress,
+        version: Version,
+    ) -> Pin<Box<dyn Future<Output = Result<(Option<AccountStateBlob>, SparseMerkleProof)>> + Send>>
+    {
+        future::ready(self.get_account_state_with_proof_by_version(address, version)).boxed()
+    }
+
+    fn get_startup_info(&self) -> Result<Option<StartupInfo>> {
+        self.db.get_startup_info()
+    }
+
+    fn get_startup_info_async(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StartupInfo>>> + Send>> {
+        future::ready(self.get_startup_info()).boxed()
+    }
+
+    fn get_latest_ledger_infos_per_epoch(
+        &self,
+        start_epoch: u64,
+    ) -> Result<Vec<LedgerInfoWithSignatures>> {
+        self.db.get_latest_ledger_infos_per_epoch(start_epoch)
+    }
+
+    fn get_latest_ledger_infos_per_epoch_async(
+        &self,
+        start_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>> {
+        future::ready(self.get_latest_ledger_infos_per_epoch(start_epoch)).boxed()
+    }
+
+    fn get_account_state_changes(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Result<Vec<AccountStateChange>> {
+        self.db
+            .get_account_state_changes(start_version, end_version, fetch_resources)
+    }
+
+    fn get_account_state_changes_async(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<AccountStateChange>>> + Send>> {
+        future::ready(self.get_account_state_changes(start_version, end_version, fetch_resources))
+            .boxed()
+    }
+}
+
+impl StorageWrite for LocalStorageClient {
+    fn save_transactions(
+        &self,
+        txns_to_commit: Vec<TransactionToCommit>,
+        first_version: Version,
+        ledger_info_with_sigs: Option<LedgerInfoWithSignatures>,
+    ) -> Result<()> {
+        self.db
+            .save_transactions(&txns_to_commit, first_version, &ledger_info_with_sigs)
+    }
+
+    fn save_transactions_async(
+        &self,
+        txns_to_commit: Vec<TransactionToCommit>,
+        first_version: Version,
+        ledger_info_with_sigs: Option<LedgerInfoWithSignatures>,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        future::ready(self.save_transactions(txns_to_commit, first_version, ledger_info_with_sigs))
+            .boxed()
+    }
+}
@@ -13,7 +13,7 @@ use rand::{
 };
 use std::{collections::BTreeMap, convert::TryFrom, pin::Pin};
 use storage_client::StorageRead;
-use storage_proto::StartupInfo;
+use storage_proto::{AccountStateChange, StartupInfo};
 use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
     account_state_blob::AccountStateBlob,
@@ -24,10 +24,10 @@ use types::{
     proof::SparseMerkleProof,
     proto::types::{
         request_item::RequestedItems, response_item::ResponseItems, AccountStateWithProof,
-        AccumulatorProof, GetAccountStateResponse, GetTransactionsResponse,
-        LedgerInfoWithSignatures as ProtoLedgerInfoWithSignatures, RequestItem as ProtoRequestItem,
-        ResponseItem as ProtoResponseItem, TransactionInfo, TransactionListWithProof,
-        UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse,
+        AccumulatorProof, GetAccountStateResponse, GetAccountTransactionBySequenceNumberResponse,
+        GetTransactionsResponse, LedgerInfoWithSignatures as ProtoLedgerInfoWithSignatures,
+        RequestItem as ProtoRequestItem, ResponseItem as ProtoResponseItem, TransactionInfo,
+        TransactionListWithProof, UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse,
     },
     test_helpers::transaction_test_helpers::get_test_signed_txn,
     transaction::Version,
@@ -152,6 +152,24 @@ impl StorageRead for MockStorageReadClient {
     ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>> {
         unimplemented!()
     }
+
+    fn get_account_state_changes(
+        &self,
+        _start_version: Version,
+        _end_version: Version,
+        _fetch_resources: bool,
+    ) -> Result<Vec<AccountStateChange>> {
+        unimplemented!()
+    }
+
+    fn get_account_state_changes_async(
+        &self,
+        _start_version: Version,
+        _end_version: Version,
+        _fetch_resources: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<AccountStateChange>>> + Send>> {
+        unimplemented!()
+    }
 }
 
 fn get_mock_update_to_latest_ledger(
@@ -223,7 +241,10 @@ fn get_mock_response_item(request_item: &ProtoRequestItem) -> Result<ProtoRespon
                 response_item.response_items = Some(ResponseItems::GetAccountStateResponse(resp));
             }
             RequestedItems::GetAccountTransactionBySequenceNumberRequest(_request) => {
-                unimplemented!();
+                // no transaction was committed
+                let resp = GetAccountTransactionBySequenceNumberResponse::default();
+                response_item.response_items =
+                    Some(ResponseItems::GetAccountTransactionBySequenceNumberResponse(resp));
             }
             RequestedItems::GetEventsByEventAccessPathRequest(_request) => {
                 unimplemented!();
@@ -59,6 +59,7 @@ proptest! {
 
         let mut version = 0;
         for (txns_to_commit, ledger_info_with_sigs) in &blocks {
+            let start_version = version;
             write_client
                 .save_transactions(txns_to_commit.clone(),
                                    version + 1, /* first_version */
@@ -75,6 +76,20 @@ proptest! {
                                                 .clone())
                 );
 
+            // The accounts changed by the block are streamed back.
+            let mut changed_accounts: Vec<_> = account_states.keys().cloned().collect();
+            changed_accounts.sort();
+            let account_state_changes = read_client
+                .get_account_state_changes(start_version, version, /* fetch_resources = */ false)
+                .unwrap();
+            prop_assert_eq!(
+                account_state_changes
+                    .into_iter()
+                    .map(|change| change.address)
+                    .collect::<Vec<_>>(),
+                changed_accounts
+            );
+
             let account_state_request_items = account_states
                 .keys()
                 .map(|address| RequestItem::GetAccountState{
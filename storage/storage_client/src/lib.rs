@@ -10,14 +10,20 @@
 mod state_view;
 
 use failure::prelude::*;
-use futures::{compat::Future01CompatExt, executor::block_on, prelude::*};
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    executor::block_on,
+    prelude::*,
+};
 use futures_01::future::Future as Future01;
+use grpc_helpers::connect_internal;
 use grpcio::{ChannelBuilder, Environment};
 use rand::Rng;
 use std::convert::TryFrom;
 use std::{pin::Pin, sync::Arc};
 use storage_proto::{
     proto::storage::{GetStartupInfoRequest, StorageClient},
+    AccountStateChange, GetAccountStateChangesRequest, GetAccountStateChangesResponse,
     GetAccountStateWithProofByVersionRequest, GetAccountStateWithProofByVersionResponse,
     GetLatestLedgerInfosPerEpochRequest, GetLatestLedgerInfosPerEpochResponse,
     GetStartupInfoResponse, GetTransactionsRequest, GetTransactionsResponse,
@@ -58,7 +64,11 @@ fn make_clients(
             if let Some(m) = max_receive_len {
                 builder = builder.max_receive_message_len(m);
             }
-            let channel = builder.connect(&format!("{}:{}", host, port));
+            let channel = connect_internal(
+                builder,
+                &format!("{}:{}", host, port),
+                &format!("storage-{}-{}", client_type, i),
+            );
             StorageClient::new(channel)
         })
         .collect::<Vec<StorageClient>>()
@@ -233,6 +243,39 @@ impl StorageRead for StorageReadServiceClient {
         })
         .boxed()
     }
+
+    fn get_account_state_changes(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Result<Vec<AccountStateChange>> {
+        block_on(self.get_account_state_changes_async(start_version, end_version, fetch_resources))
+    }
+
+    fn get_account_state_changes_async(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<AccountStateChange>>> + Send>> {
+        let proto_req =
+            GetAccountStateChangesRequest::new(start_version, end_version, fetch_resources);
+        let receiver = match self.client().get_account_state_changes(&proto_req.into()) {
+            Ok(receiver) => receiver,
+            Err(e) => return future::err(convert_grpc_err(e)).boxed(),
+        };
+        receiver
+            .compat()
+            .map_err(convert_grpc_err)
+            .try_fold(vec![], |mut account_state_changes, resp| {
+                future::ready(GetAccountStateChangesResponse::try_from(resp).map(|resp| {
+                    account_state_changes.extend(resp.account_state_changes);
+                    account_state_changes
+                }))
+            })
+            .boxed()
+    }
 }
 
 /// This provides storage write interfaces backed by real storage service.
@@ -397,6 +440,28 @@ pub trait StorageRead: Send + Sync {
         &self,
         start_epoch: u64,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>>;
+
+    /// See [`LibraDB::get_account_state_changes`].
+    ///
+    /// [`LibraDB::get_account_state_changes`]:
+    /// ../libradb/struct.LibraDB.html#method.get_account_state_changes
+    fn get_account_state_changes(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Result<Vec<AccountStateChange>>;
+
+    /// See [`LibraDB::get_account_state_changes`].
+    ///
+    /// [`LibraDB::get_account_state_changes`]:
+    /// ../libradb/struct.LibraDB.html#method.get_account_state_changes
+    fn get_account_state_changes_async(
+        &self,
+        start_version: Version,
+        end_version: Version,
+        fetch_resources: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<AccountStateChange>>> + Send>>;
 }
 
 /// This trait defines interfaces to be implemented by a storage write client.
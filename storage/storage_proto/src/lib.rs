@@ -462,6 +462,164 @@ impl Into<Vec<LedgerInfoWithSignatures>> for GetLatestLedgerInfosPerEpochRespons
     }
 }
 
+/// Helper to construct and parse [`proto::storage::GetAccountStateChangesRequest`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct GetAccountStateChangesRequest {
+    pub start_version: Version,
+    pub end_version: Version,
+    pub fetch_resources: bool,
+}
+
+impl GetAccountStateChangesRequest {
+    /// Constructor.
+    pub fn new(start_version: Version, end_version: Version, fetch_resources: bool) -> Self {
+        Self {
+            start_version,
+            end_version,
+            fetch_resources,
+        }
+    }
+}
+
+impl TryFrom<crate::proto::storage::GetAccountStateChangesRequest>
+    for GetAccountStateChangesRequest
+{
+    type Error = Error;
+
+    fn try_from(proto: crate::proto::storage::GetAccountStateChangesRequest) -> Result<Self> {
+        Ok(Self {
+            start_version: proto.start_version,
+            end_version: proto.end_version,
+            fetch_resources: proto.fetch_resources,
+        })
+    }
+}
+
+impl From<GetAccountStateChangesRequest> for crate::proto::storage::GetAccountStateChangesRequest {
+    fn from(request: GetAccountStateChangesRequest) -> Self {
+        Self {
+            start_version: request.start_version,
+            end_version: request.end_version,
+            fetch_resources: request.fetch_resources,
+        }
+    }
+}
+
+/// Helper to construct and parse [`proto::storage::ResourceChange`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct ResourceChange {
+    pub path: Vec<u8>,
+    pub old_value: Option<Vec<u8>>,
+    pub new_value: Option<Vec<u8>>,
+}
+
+impl TryFrom<crate::proto::storage::ResourceChange> for ResourceChange {
+    type Error = Error;
+
+    fn try_from(proto: crate::proto::storage::ResourceChange) -> Result<Self> {
+        Ok(Self {
+            path: proto.path,
+            old_value: proto.old_value,
+            new_value: proto.new_value,
+        })
+    }
+}
+
+impl From<ResourceChange> for crate::proto::storage::ResourceChange {
+    fn from(change: ResourceChange) -> Self {
+        Self {
+            path: change.path,
+            old_value: change.old_value,
+            new_value: change.new_value,
+        }
+    }
+}
+
+/// Helper to construct and parse [`proto::storage::AccountStateChange`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct AccountStateChange {
+    pub address: AccountAddress,
+    /// Empty unless the changes of the resources were requested.
+    pub resource_changes: Vec<ResourceChange>,
+}
+
+impl TryFrom<crate::proto::storage::AccountStateChange> for AccountStateChange {
+    type Error = Error;
+
+    fn try_from(proto: crate::proto::storage::AccountStateChange) -> Result<Self> {
+        Ok(Self {
+            address: AccountAddress::try_from(&proto.address[..])?,
+            resource_changes: proto
+                .resource_changes
+                .into_iter()
+                .map(TryFrom::try_from)
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+}
+
+impl From<AccountStateChange> for crate::proto::storage::AccountStateChange {
+    fn from(change: AccountStateChange) -> Self {
+        Self {
+            address: change.address.to_vec(),
+            resource_changes: change
+                .resource_changes
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
+/// Helper to construct and parse [`proto::storage::GetAccountStateChangesResponse`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct GetAccountStateChangesResponse {
+    pub account_state_changes: Vec<AccountStateChange>,
+}
+
+impl GetAccountStateChangesResponse {
+    /// Constructor.
+    pub fn new(account_state_changes: Vec<AccountStateChange>) -> Self {
+        Self {
+            account_state_changes,
+        }
+    }
+}
+
+impl TryFrom<crate::proto::storage::GetAccountStateChangesResponse>
+    for GetAccountStateChangesResponse
+{
+    type Error = Error;
+
+    fn try_from(proto: crate::proto::storage::GetAccountStateChangesResponse) -> Result<Self> {
+        Ok(Self {
+            account_state_changes: proto
+                .account_state_changes
+                .into_iter()
+                .map(TryFrom::try_from)
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+}
+
+impl From<GetAccountStateChangesResponse>
+    for crate::proto::storage::GetAccountStateChangesResponse
+{
+    fn from(response: GetAccountStateChangesResponse) -> Self {
+        Self {
+            account_state_changes: response
+                .account_state_changes
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
 pub mod prelude {
     pub use super::*;
 }
@@ -32,4 +32,14 @@ proptest! {
     fn test_get_startup_info_response(res in any::<GetStartupInfoResponse>()) {
         assert_protobuf_encode_decode::<crate::proto::storage::GetStartupInfoResponse, GetStartupInfoResponse>(&res);
     }
+
+    #[test]
+    fn test_get_account_state_changes_request(req in any::<GetAccountStateChangesRequest>()) {
+        assert_protobuf_encode_decode::<crate::proto::storage::GetAccountStateChangesRequest, GetAccountStateChangesRequest>(&req);
+    }
+
+    #[test]
+    fn test_get_account_state_changes_response(resp in any::<GetAccountStateChangesResponse>()) {
+        assert_protobuf_encode_decode::<crate::proto::storage::GetAccountStateChangesResponse, GetAccountStateChangesResponse>(&resp);
+    }
 }
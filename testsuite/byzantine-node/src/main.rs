@@ -0,0 +1,40 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A validator node deviating from the consensus protocol in a given way, for end-to-end tests
+//! checking that the honest validators keep their safety and liveness guarantees. Never run it
+//! in a real deployment.
+
+use consensus::byzantine::{set_byzantine_behavior, ByzantineBehavior};
+use executable_helpers::helpers::setup_executable;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Byzantine Libra Node, for tests only")]
+struct Args {
+    #[structopt(short = "f", long, parse(from_os_str))]
+    /// Path to NodeConfig
+    config: Option<PathBuf>,
+    #[structopt(short = "d", long)]
+    /// Disable logging
+    no_logging: bool,
+    #[structopt(short = "b", long)]
+    /// Behavior of the node: equivocating-proposer, vote-withholder or garbage-spammer
+    behavior: ByzantineBehavior,
+}
+
+fn main() {
+    let args = Args::from_args();
+
+    let (mut config, _logger) =
+        setup_executable(args.config.as_ref().map(PathBuf::as_path), args.no_logging);
+    set_byzantine_behavior(args.behavior);
+
+    let (_ac_handle, _node_handle) = libra_node::main_node::setup_environment(&mut config);
+
+    // The node is killed by the test driving it.
+    loop {
+        std::thread::park();
+    }
+}
@@ -1,7 +1,9 @@
+mod network_faults;
 mod reboot;
 mod stop_container;
 
 use failure;
+pub use network_faults::NetworkFaults;
 pub use reboot::Reboot;
 use std::fmt::Display;
 pub use stop_container::StopContainer;
@@ -0,0 +1,51 @@
+use crate::{effects::Effect, instance::Instance};
+use debug_interface::{
+    proto::{NetworkFaultProfile, SetNetworkFaultsRequest},
+    NodeDebugClient,
+};
+use failure;
+use std::fmt;
+
+/// Injects faults into all the network connections of a validator, through its debug interface.
+/// The validator must run with network fault injection enabled in its config.
+pub struct NetworkFaults {
+    instance: Instance,
+    profile: NetworkFaultProfile,
+}
+
+impl NetworkFaults {
+    pub fn new(instance: Instance, profile: NetworkFaultProfile) -> Self {
+        Self { instance, profile }
+    }
+
+    fn set_network_faults(&self, request: SetNetworkFaultsRequest) -> failure::Result<()> {
+        NodeDebugClient::new(self.instance.ip(), 6191).set_network_faults(&request)
+    }
+}
+
+impl Effect for NetworkFaults {
+    fn activate(&self) -> failure::Result<()> {
+        let mut request = SetNetworkFaultsRequest::default();
+        request.default_profile = Some(self.profile.clone());
+        self.set_network_faults(request)
+    }
+
+    fn deactivate(&self) -> failure::Result<()> {
+        self.set_network_faults(SetNetworkFaultsRequest::default())
+    }
+}
+
+impl fmt::Display for NetworkFaults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Network faults on {}: drop {}, corrupt {}, duplicate {}, delay {} ({} ms)",
+            self.instance,
+            self.profile.drop_probability,
+            self.profile.corrupt_probability,
+            self.profile.duplicate_probability,
+            self.profile.delay_probability,
+            self.profile.delay_ms,
+        )
+    }
+}
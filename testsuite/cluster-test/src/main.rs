@@ -5,7 +5,7 @@ use cluster_test::{
     aws::Aws,
     cluster::Cluster,
     deployment::{DeploymentManager, SOURCE_TAG, TESTED_TAG},
-    effects::{Action, Effect, Reboot, StopContainer},
+    effects::{Action, Effect, NetworkFaults, Reboot, StopContainer},
     experiments::{Experiment, RebootRandomValidators},
     health::{DebugPortLogThread, HealthCheckRunner, LogTail},
     log_prune::LogPruner,
@@ -13,6 +13,7 @@ use cluster_test::{
     suite::ExperimentSuite,
     tx_emitter::TxEmitter,
 };
+use debug_interface::proto::NetworkFaultProfile;
 use failure::{
     self,
     prelude::{bail, format_err},
@@ -66,6 +67,8 @@ struct Args {
     emit_tx: bool,
     #[structopt(long, group = "action")]
     stop_experiment: bool,
+    #[structopt(long, group = "action")]
+    flaky_network_experiment: bool,
 
     // emit_tx options
     #[structopt(long, default_value = "10")]
@@ -78,6 +81,18 @@ struct Args {
     //stop_experiment options
     #[structopt(long, default_value = "10")]
     max_stopped: usize,
+
+    //flaky_network_experiment options
+    #[structopt(long, default_value = "0.01")]
+    drop_probability: f64,
+    #[structopt(long, default_value = "0")]
+    corrupt_probability: f64,
+    #[structopt(long, default_value = "0")]
+    duplicate_probability: f64,
+    #[structopt(long, default_value = "0.1")]
+    delay_probability: f64,
+    #[structopt(long, default_value = "100")]
+    delay_ms: u64,
 }
 
 pub fn main() {
@@ -101,6 +116,16 @@ pub fn main() {
         let util = ClusterUtil::setup(&args);
         util.stop_experiment(args.max_stopped);
         return;
+    } else if args.flaky_network_experiment {
+        let util = ClusterUtil::setup(&args);
+        let mut profile = NetworkFaultProfile::default();
+        profile.drop_probability = args.drop_probability;
+        profile.corrupt_probability = args.corrupt_probability;
+        profile.duplicate_probability = args.duplicate_probability;
+        profile.delay_probability = args.delay_probability;
+        profile.delay_ms = args.delay_ms;
+        util.flaky_network_experiment(profile);
+        return;
     }
 
     let mut runner = ClusterTestRunner::setup(&args);
@@ -239,6 +264,51 @@ impl ClusterUtil {
         }
     }
 
+    /// Compares the throughput of the cluster without and with faults injected into the network
+    /// connections of all its validators, which must have network fault injection enabled.
+    pub fn flaky_network_experiment(self, profile: NetworkFaultProfile) {
+        let mut emitter = TxEmitter::new(&self.cluster);
+        let window = Duration::from_secs(60);
+        let job = emitter.start_job(EmitJobRequest {
+            instances: self.cluster.instances().to_vec(),
+            accounts_per_client: 10,
+            thread_params: EmitThreadParams::default(),
+        });
+        let mut results = vec![];
+        thread::sleep(Duration::from_secs(30) + window);
+        results.push(("clean", self.print_stat(window)));
+
+        let fault_effects: Vec<_> = self
+            .cluster
+            .instances()
+            .iter()
+            .map(|instance| NetworkFaults::new(instance.clone(), profile.clone()))
+            .collect();
+        for effect in &fault_effects {
+            info!("{}", effect);
+            if let Err(e) = effect.activate() {
+                info!("Failed to activate {}: {:?}", effect, e);
+            }
+        }
+        thread::sleep(Duration::from_secs(30) + window);
+        results.push(("flaky", self.print_stat(window)));
+        for effect in &fault_effects {
+            if let Err(e) = effect.deactivate() {
+                info!("Failed to deactivate {}: {:?}", effect, e);
+            }
+        }
+        emitter.stop_job(job);
+
+        println!("Results in csv format:");
+        println!("NETWORK\tTPS\tLAT");
+        for (network, result) in results {
+            match result {
+                Ok((tps, lat)) => println!("{}\t{:.0}\t{:.0}", network, tps, lat * 1000.),
+                Err(e) => info!("Failed to get stats of the {} network: {:?}", network, e),
+            }
+        }
+    }
+
     fn run_stat_loop(&self) {
         let window = Duration::from_secs(30);
         thread::sleep(Duration::from_secs(30)); // warm up
@@ -9,7 +9,9 @@ use crypto::{ed25519::*, test_utils::KeyPair, SigningKey};
 use libra_swarm::{swarm::LibraSwarm, utils};
 use logger::prelude::*;
 use num_traits::cast::FromPrimitive;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rust_decimal::Decimal;
+use std::collections::BTreeMap;
 use std::fs;
 use std::str::FromStr;
 use tools::tempdir::TempPath;
@@ -149,7 +151,7 @@ fn setup_swarm_and_client_proxy(
     (env, ac_client)
 }
 
-fn test_smoke_script(mut client_proxy: ClientProxy) {
+fn test_smoke_script(client_proxy: &mut ClientProxy) {
     client_proxy.create_next_account(false).unwrap();
     client_proxy
         .mint_coins(&["mintb", "0", "10"], true)
@@ -236,13 +238,13 @@ fn test_execute_custom_module_and_script() {
 #[test]
 fn smoke_test_single_node() {
     let (_swarm, mut client_proxy) = setup_swarm_and_client_proxy(1, 0);
-    test_smoke_script(client_proxy);
+    test_smoke_script(&mut client_proxy);
 }
 
 #[test]
 fn smoke_test_multi_node() {
     let (_swarm, mut client_proxy) = setup_swarm_and_client_proxy(4, 0);
-    test_smoke_script(client_proxy);
+    test_smoke_script(&mut client_proxy);
 }
 
 #[test]
@@ -271,6 +273,66 @@ fn test_concurrent_transfers_single_node() {
     );
 }
 
+#[test]
+fn test_mempool_consistency() {
+    // A randomized workload is submitted to the validators while they can't commit anything: the
+    // shared mempool must bring their mempools to the same transactions, and the same ready ones,
+    // for each account.
+    let num_nodes = 4;
+    let num_live_nodes = 2;
+    let num_accounts = 8;
+    let (mut env, mut client_proxy) = setup_swarm_and_client_proxy(num_nodes, 0);
+    for i in 0..num_accounts {
+        client_proxy.create_next_account(false).unwrap();
+        client_proxy
+            .mint_coins(&["mintb", &i.to_string(), "100"], true)
+            .unwrap();
+    }
+    // Once the mints are committed everywhere, half of the validators are stopped, so that the
+    // others can't form a quorum and keep the submitted transactions in their mempools.
+    assert!(env.validator_swarm.wait_for_all_nodes_to_catchup());
+    for node_index in num_live_nodes..num_nodes {
+        env.validator_swarm.kill_node(node_index);
+    }
+    // Each account submits its transactions through a single validator, which keeps track of its
+    // sequence number.
+    let mut node_clients: Vec<ClientProxy> = (0..num_live_nodes)
+        .map(|node_index| {
+            let mut node_client = env.get_validator_ac_client(node_index);
+            node_client.set_accounts(client_proxy.copy_all_accounts());
+            node_client
+        })
+        .collect();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut expected_digest = BTreeMap::new();
+    for _ in 0..40 {
+        let sender = rng.gen_range(0, num_accounts);
+        let receiver = (sender + rng.gen_range(1, num_accounts)) % num_accounts;
+        let amount = rng.gen_range(1, 4);
+        node_clients[sender % num_live_nodes]
+            .transfer_coins(
+                &[
+                    "t",
+                    &sender.to_string(),
+                    &receiver.to_string(),
+                    &amount.to_string(),
+                ],
+                false,
+            )
+            .unwrap();
+        // nothing is committed, so all the transactions of the account stay in Mempool and are
+        // ready
+        let (num_transactions, num_ready) = expected_digest
+            .entry(client_proxy.accounts[sender].address.to_vec())
+            .or_insert((0, 0));
+        *num_transactions += 1;
+        *num_ready += 1;
+    }
+    let digest = env.validator_swarm.wait_for_mempools_to_converge(60);
+    assert!(!expected_digest.is_empty());
+    assert_eq!(digest, Some(expected_digest));
+}
+
 #[test]
 fn test_basic_fault_tolerance() {
     // A configuration with 4 validators should tolerate single node failure.
@@ -278,7 +340,45 @@ fn test_basic_fault_tolerance() {
     // kill the first validator
     env.validator_swarm.kill_node(0);
     // run the script for the smoke test by submitting requests to the second validator
-    test_smoke_script(client_proxy);
+    test_smoke_script(&mut client_proxy);
+}
+
+fn test_byzantine_fault_tolerance(behavior: &str) {
+    // A configuration with 4 validators should tolerate a single byzantine validator.
+    let (mut env, mut client_proxy) = setup_swarm_and_client_proxy(4, 1);
+    assert!(env
+        .validator_swarm
+        .restart_as_byzantine(0, behavior, false)
+        .is_ok());
+    // Liveness: the honest validators keep committing transactions.
+    test_smoke_script(&mut client_proxy);
+    // Safety: all the honest validators committed the same transactions.
+    assert!(env.validator_swarm.wait_for_all_nodes_to_catchup());
+    for node_index in 2..4 {
+        let mut other_client_proxy = env.get_validator_ac_client(node_index);
+        other_client_proxy.set_accounts(client_proxy.copy_all_accounts());
+        for (account, balance) in &[("0", 7.0), ("1", 4.0), ("2", 15.0)] {
+            assert_eq!(
+                Decimal::from_f64(*balance),
+                Decimal::from_str(&other_client_proxy.get_balance(&["b", account]).unwrap()).ok()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_byzantine_equivocating_proposer() {
+    test_byzantine_fault_tolerance("equivocating-proposer");
+}
+
+#[test]
+fn test_byzantine_vote_withholder() {
+    test_byzantine_fault_tolerance("vote-withholder");
+}
+
+#[test]
+fn test_byzantine_garbage_spammer() {
+    test_byzantine_fault_tolerance("garbage-spammer");
 }
 
 #[test]
@@ -540,7 +640,7 @@ fn test_full_node_basic_flow() {
     env.launch_swarm(RoleType::FullNode);
 
     // execute smoke script
-    test_smoke_script(env.get_validator_ac_client(0));
+    test_smoke_script(&mut env.get_validator_ac_client(0));
 
     // read state from full node client
     let mut validator_ac_client = env.get_validator_ac_client(1);
@@ -34,6 +34,10 @@
 //!
 //! On the other hand, if you want to query only <Alice>/a/*, `address` will be set to Alice and
 //! `path` will be set to "/a" and use the `get_prefix()` method from statedb
+//!
+//! The raw `path` bytes of an [`AccessPath`] should not be assembled by hand. [`Path`] is the
+//! typed form of the paths actually used on chain (module code, resources, and the event streams
+//! of resources); it builds the raw bytes and parses them back.
 
 use crate::{
     account_address::AccountAddress,
@@ -43,7 +47,7 @@ use crate::{
     },
     identifier::{IdentStr, Identifier},
     language_storage::{ModuleId, ResourceKey, StructTag},
-    validator_set::validator_set_path,
+    validator_set::validator_set_tag,
 };
 use canonical_serialization::{
     CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
@@ -200,7 +204,97 @@ impl TrieKey for Accesses {
 lazy_static! {
     /// The access path where the Validator Set resource is stored.
     pub static ref VALIDATOR_SET_ACCESS_PATH: AccessPath =
-        AccessPath::new(association_address(), validator_set_path());
+        AccessPath::new_for_resource(association_address(), &validator_set_tag());
+}
+
+/// Typed form of the `path` of an [`AccessPath`].
+///
+/// Converting a `Path` into bytes and parsing those bytes back always yields the same `Path`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Path {
+    /// The code of a published module, identified by the hash of its [`ModuleId`].
+    Code(HashValue),
+    /// A published resource, identified by the hash of its [`StructTag`].
+    Resource(HashValue),
+    /// An event stream of a published resource, identified by the hash of the resource's
+    /// [`StructTag`] and the name of the field counting the events.
+    Event(HashValue, Identifier),
+}
+
+impl Path {
+    pub fn code(module_id: &ModuleId) -> Self {
+        Path::Code(module_id.hash())
+    }
+
+    pub fn resource(tag: &StructTag) -> Self {
+        Path::Resource(tag.hash())
+    }
+
+    pub fn event(tag: &StructTag, counter_field: &IdentStr) -> Self {
+        Path::Event(tag.hash(), counter_field.to_owned())
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let (tag, hash) = match self {
+            Path::Code(hash) => (AccessPath::CODE_TAG, hash),
+            Path::Resource(hash) | Path::Event(hash, _) => (AccessPath::RESOURCE_TAG, hash),
+        };
+        let mut path = vec![tag];
+        path.extend_from_slice(&hash.to_vec());
+        if let Path::Event(_, field) = self {
+            path.push(SEPARATOR as u8);
+            path.extend_from_slice(field.as_bytes());
+            path.push(SEPARATOR as u8);
+        }
+        path
+    }
+}
+
+impl TryFrom<&[u8]> for Path {
+    type Error = Error;
+
+    fn try_from(path: &[u8]) -> Result<Self> {
+        ensure!(
+            path.len() > HashValue::LENGTH,
+            "Access path too short: {}",
+            hex::encode(path)
+        );
+        let hash = HashValue::from_slice(&path[1..=HashValue::LENGTH])?;
+        let suffix = &path[1 + HashValue::LENGTH..];
+        match path[0] {
+            AccessPath::CODE_TAG if suffix.is_empty() => Ok(Path::Code(hash)),
+            AccessPath::RESOURCE_TAG if suffix.is_empty() => Ok(Path::Resource(hash)),
+            AccessPath::RESOURCE_TAG => {
+                let separator = SEPARATOR as u8;
+                ensure!(
+                    suffix.len() > 2
+                        && suffix[0] == separator
+                        && suffix[suffix.len() - 1] == separator,
+                    "Malformed event path suffix: {:?}",
+                    String::from_utf8_lossy(suffix)
+                );
+                let field = Identifier::from_utf8(suffix[1..suffix.len() - 1].to_vec())?;
+                Ok(Path::Event(hash, field))
+            }
+            tag => bail!("Unrecognized access path tag {} or suffix", tag),
+        }
+    }
+}
+
+impl From<Path> for Vec<u8> {
+    fn from(path: Path) -> Self {
+        path.to_vec()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Path::Code(hash) => write!(f, "code/{:x}", hash),
+            Path::Resource(hash) => write!(f, "resource/{:x}", hash),
+            Path::Event(hash, field) => write!(f, "resource/{:x}/{}", hash, field),
+        }
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Default, Hash, Serialize, Deserialize, Ord, PartialOrd)]
@@ -223,6 +317,21 @@ impl AccessPath {
         Self::new(address, account_resource_path())
     }
 
+    /// Returns the access path of the resource of type `tag` published under `address`.
+    pub fn new_for_resource(address: AccountAddress, tag: &StructTag) -> Self {
+        Self::new(address, Path::resource(tag).into())
+    }
+
+    /// Returns the access path of the event stream counted by `counter_field` of the resource of
+    /// type `tag` published under `address`.
+    pub fn new_for_resource_event(
+        address: AccountAddress,
+        tag: &StructTag,
+        counter_field: &IdentStr,
+    ) -> Self {
+        Self::new(address, Path::event(tag, counter_field).into())
+    }
+
     /// Create an AccessPath for a ContractEvent.
     /// That is an AccessPah that uniquely identifies a given event for a published resource.
     pub fn new_for_event(address: AccountAddress, root: &[u8], key: &[u8]) -> Self {
@@ -275,20 +384,17 @@ impl AccessPath {
         }
     }
 
-    fn code_access_path_vec(key: &ModuleId) -> Vec<u8> {
-        let mut root = vec![];
-        root.push(Self::CODE_TAG);
-        root.append(&mut key.hash().to_vec());
-        root
-    }
-
     pub fn code_access_path(key: &ModuleId) -> AccessPath {
-        let path = AccessPath::code_access_path_vec(key);
         AccessPath {
             address: *key.address(),
-            path,
+            path: Path::code(key).into(),
         }
     }
+
+    /// Parses `path` into its typed form.
+    pub fn parse_path(&self) -> Result<Path> {
+        Path::try_from(self.path.as_slice())
+    }
 }
 
 impl fmt::Debug for AccessPath {
@@ -304,25 +410,13 @@ impl fmt::Debug for AccessPath {
 
 impl fmt::Display for AccessPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.path.len() < 1 + HashValue::LENGTH {
-            write!(f, "{:?}", self)
-        } else {
-            write!(f, "AccessPath {{ address: {:x}, ", self.address)?;
-            match self.path[0] {
-                Self::RESOURCE_TAG => write!(f, "type: Resource, ")?,
-                Self::CODE_TAG => write!(f, "type: Module, ")?,
-                tag => write!(f, "type: {:?}, ", tag)?,
-            };
-            write!(
-                f,
-                "hash: {:?}, ",
-                hex::encode(&self.path[1..=HashValue::LENGTH])
-            )?;
-            write!(
+        match self.parse_path() {
+            Ok(path) => write!(
                 f,
-                "suffix: {:?} }} ",
-                String::from_utf8_lossy(&self.path[1 + HashValue::LENGTH..])
-            )
+                "AccessPath {{ address: {:x}, path: {} }}",
+                self.address, path
+            ),
+            Err(_) => write!(f, "{:?}", self),
         }
     }
 }
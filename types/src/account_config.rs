@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    access_path::{AccessPath, Accesses},
+    access_path::Path,
     account_address::AccountAddress,
     account_state_blob::AccountStateBlob,
     byte_array::ByteArray,
@@ -14,11 +14,15 @@ use canonical_serialization::{
     CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
     SimpleDeserializer,
 };
+use crypto::hash::CryptoHash;
 use failure::prelude::*;
 use lazy_static::lazy_static;
 #[cfg(any(test, feature = "testing"))]
 use proptest_derive::Arbitrary;
-use std::{collections::BTreeMap, convert::TryInto};
+use std::{
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+};
 
 lazy_static! {
     // LibraCoin
@@ -150,12 +154,17 @@ impl AccountResource {
     }
 
     pub fn get_event_handle_by_query_path(&self, query_path: &[u8]) -> Result<&EventHandle> {
-        if *ACCOUNT_RECEIVED_EVENT_PATH == query_path {
-            Ok(&self.received_events)
-        } else if *ACCOUNT_SENT_EVENT_PATH == query_path {
-            Ok(&self.sent_events)
-        } else {
-            bail!("Unrecognized query path: {:?}", query_path);
+        match Path::try_from(query_path)? {
+            Path::Event(tag_hash, ref field) if tag_hash == account_struct_tag().hash() => {
+                if field.as_ident_str() == account_received_event_counter_field() {
+                    Ok(&self.received_events)
+                } else if field.as_ident_str() == account_sent_event_counter_field() {
+                    Ok(&self.sent_events)
+                } else {
+                    bail!("Unrecognized account event counter: {}", field)
+                }
+            }
+            path => bail!("Unrecognized query path: {}", path),
         }
     }
 }
@@ -213,25 +222,32 @@ pub fn get_account_resource_or_default(
 /// Return the path to the Account resource. It can be used to create an AccessPath for an
 /// Account resource.
 pub fn account_resource_path() -> Vec<u8> {
-    AccessPath::resource_access_vec(&account_struct_tag(), &Accesses::empty())
+    Path::resource(&account_struct_tag()).into()
+}
+
+pub fn account_sent_event_counter_field() -> &'static IdentStr {
+    &*ACCOUNT_SENT_EVENT_COUNTER_FIELD
+}
+
+pub fn account_received_event_counter_field() -> &'static IdentStr {
+    &*ACCOUNT_RECEIVED_EVENT_COUNTER_FIELD
 }
 
 lazy_static! {
+    static ref ACCOUNT_SENT_EVENT_COUNTER_FIELD: Identifier =
+        Identifier::new("sent_events_count").unwrap();
+    static ref ACCOUNT_RECEIVED_EVENT_COUNTER_FIELD: Identifier =
+        Identifier::new("received_events_count").unwrap();
+
     /// The path to the sent event counter for an Account resource.
     /// It can be used to query the event DB for the given event.
-    pub static ref ACCOUNT_SENT_EVENT_PATH: Vec<u8> = {
-        let mut path = account_resource_path();
-        path.extend_from_slice(b"/sent_events_count/");
-        path
-    };
+    pub static ref ACCOUNT_SENT_EVENT_PATH: Vec<u8> =
+        Path::event(&account_struct_tag(), account_sent_event_counter_field()).into();
 
     /// Returns the path to the received event counter for an Account resource.
     /// It can be used to query the event DB for the given event.
-    pub static ref ACCOUNT_RECEIVED_EVENT_PATH: Vec<u8> = {
-        let mut path = account_resource_path();
-        path.extend_from_slice(b"/received_events_count/");
-        path
-    };
+    pub static ref ACCOUNT_RECEIVED_EVENT_PATH: Vec<u8> =
+        Path::event(&account_struct_tag(), account_received_event_counter_field()).into();
 }
 
 /// Generic struct that represents an Account event.
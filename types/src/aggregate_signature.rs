@@ -0,0 +1,239 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signatures of a same message by several validators, aggregated into a single signature.
+//!
+//! The signers are identified by a bitmap over the validators of the epoch, ordered by account
+//! address, so that a certificate signed by n validators carries one signature and n bits instead
+//! of n signatures and n account addresses.
+
+use crate::{
+    account_address::AccountAddress,
+    validator_verifier::{ValidatorVerifier, VerifyError},
+};
+use crypto::{traits::AggregatableSignature, HashValue};
+use failure::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AggregateSignature<Sig> {
+    // Bit i (of byte i / 8, least significant first) is set when the i-th validator signed
+    signers: Vec<u8>,
+    signature: Sig,
+}
+
+impl<Sig: AggregatableSignature> AggregateSignature<Sig> {
+    /// Aggregates the `signatures` of a same message by the validators known to `validator`.
+    pub fn aggregate(
+        validator: &ValidatorVerifier<Sig::VerifyingKeyMaterial>,
+        signatures: &HashMap<AccountAddress, Sig>,
+    ) -> Result<Self> {
+        let authors = validator.get_ordered_account_addresses();
+        let mut signers = vec![0u8; (authors.len() + 7) / 8];
+        let mut author_signatures = Vec::with_capacity(signatures.len());
+        for (index, author) in authors.iter().enumerate() {
+            if let Some(signature) = signatures.get(author) {
+                signers[index / 8] |= 1 << (index % 8);
+                author_signatures.push(signature.clone());
+            }
+        }
+        ensure!(
+            author_signatures.len() == signatures.len(),
+            "Signatures of unknown validators cannot be aggregated"
+        );
+        Ok(Self {
+            signers,
+            signature: Sig::aggregate(&author_signatures)?,
+        })
+    }
+
+    /// Returns the validators whose signatures were aggregated, ordered by account address.
+    pub fn signers(
+        &self,
+        validator: &ValidatorVerifier<Sig::VerifyingKeyMaterial>,
+    ) -> std::result::Result<Vec<AccountAddress>, VerifyError> {
+        let authors = validator.get_ordered_account_addresses();
+        if self.signers.len() != (authors.len() + 7) / 8 {
+            return Err(VerifyError::UnknownAuthor);
+        }
+        let is_signer = |index: usize| self.signers[index / 8] & (1 << (index % 8)) != 0;
+        // bits beyond the last validator would stand for unknown authors
+        if (authors.len()..self.signers.len() * 8).any(is_signer) {
+            return Err(VerifyError::UnknownAuthor);
+        }
+        Ok(authors
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| is_signer(*index))
+            .map(|(_, author)| author)
+            .collect())
+    }
+
+    /// Returns the aggregated signature.
+    pub fn signature(&self) -> &Sig {
+        &self.signature
+    }
+
+    /// Verifies that the signers have at least the quorum voting power of `validator` and that
+    /// the aggregated signature of `hash` is theirs.
+    pub fn verify(
+        &self,
+        validator: &ValidatorVerifier<Sig::VerifyingKeyMaterial>,
+        hash: HashValue,
+    ) -> std::result::Result<(), VerifyError> {
+        let signers = self.signers(validator)?;
+        validator.verify_aggregate_signature(hash, &signers, &self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        account_address::AccountAddress,
+        aggregate_signature::AggregateSignature,
+        validator_signer::ValidatorSigner,
+        validator_verifier::{ValidatorInfo, ValidatorVerifier, VerifyError},
+    };
+    use crypto::{bls12381::*, traits::AggregatableSignature, HashValue};
+    use std::collections::HashMap;
+
+    fn random_bls_validators(
+        count: u8,
+    ) -> (
+        Vec<ValidatorSigner<BLS12381PrivateKey>>,
+        ValidatorVerifier<BLS12381PublicKey>,
+    ) {
+        let signers: Vec<ValidatorSigner<BLS12381PrivateKey>> = (0..count)
+            .map(|i| ValidatorSigner::random([i; 32]))
+            .collect();
+        let proofs_of_possession = signers
+            .iter()
+            .map(|signer| (signer.author(), signer.proof_of_possession()))
+            .collect();
+        let validator = ValidatorVerifier::new_with_proofs_of_possession(
+            signers
+                .iter()
+                .map(|signer| (signer.author(), ValidatorInfo::new(signer.public_key(), 1)))
+                .collect(),
+            &proofs_of_possession,
+        )
+        .unwrap();
+        (signers, validator)
+    }
+
+    #[test]
+    fn test_aggregate_signature() {
+        let (signers, validator) = random_bls_validators(10);
+        let hash = HashValue::random();
+        let signatures: HashMap<AccountAddress, BLS12381Signature> = signers
+            .iter()
+            .skip(3)
+            .map(|signer| (signer.author(), signer.sign_message(hash).unwrap()))
+            .collect();
+
+        let aggregate = AggregateSignature::aggregate(&validator, &signatures).unwrap();
+        let mut expected_signers: Vec<AccountAddress> = signatures.keys().cloned().collect();
+        expected_signers.sort();
+        assert_eq!(aggregate.signers(&validator), Ok(expected_signers));
+        assert_eq!(aggregate.verify(&validator, hash), Ok(()));
+        assert_eq!(
+            aggregate.verify(&validator, HashValue::random()),
+            Err(VerifyError::InvalidSignature)
+        );
+
+        // The signers must have the quorum voting power.
+        let few_signatures: HashMap<AccountAddress, BLS12381Signature> =
+            signatures.into_iter().take(3).collect();
+        let aggregate = AggregateSignature::aggregate(&validator, &few_signatures).unwrap();
+        assert_eq!(
+            aggregate.verify(&validator, hash),
+            Err(VerifyError::TooLittleVotingPower {
+                voting_power: 3,
+                quorum_voting_power: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_aggregate_signature_unknown_author() {
+        let (signers, validator) = random_bls_validators(4);
+        let (others, _) = random_bls_validators(5);
+        let hash = HashValue::random();
+        let mut signatures: HashMap<AccountAddress, BLS12381Signature> = signers
+            .iter()
+            .map(|signer| (signer.author(), signer.sign_message(hash).unwrap()))
+            .collect();
+
+        // The signers are identified among the validators of the verifier only.
+        let aggregate = AggregateSignature::aggregate(&validator, &signatures).unwrap();
+        let (_, larger_validator) = random_bls_validators(9);
+        assert_eq!(
+            aggregate.signers(&larger_validator),
+            Err(VerifyError::UnknownAuthor)
+        );
+
+        let other = &others[4];
+        signatures.insert(other.author(), other.sign_message(hash).unwrap());
+        assert!(AggregateSignature::aggregate(&validator, &signatures).is_err());
+    }
+
+    #[test]
+    fn test_proofs_of_possession() {
+        let (signers, _) = random_bls_validators(4);
+        let validator_infos: HashMap<AccountAddress, ValidatorInfo<BLS12381PublicKey>> = signers
+            .iter()
+            .map(|signer| (signer.author(), ValidatorInfo::new(signer.public_key(), 1)))
+            .collect();
+        let mut proofs_of_possession: HashMap<AccountAddress, BLS12381Signature> = signers
+            .iter()
+            .map(|signer| (signer.author(), signer.proof_of_possession()))
+            .collect();
+        assert!(ValidatorVerifier::new_with_proofs_of_possession(
+            validator_infos.clone(),
+            &proofs_of_possession
+        )
+        .is_ok());
+
+        // The proof of a validator does not prove the possession of the key of another one.
+        let proof = proofs_of_possession[&signers[1].author()].clone();
+        proofs_of_possession.insert(signers[0].author(), proof);
+        assert!(ValidatorVerifier::new_with_proofs_of_possession(
+            validator_infos.clone(),
+            &proofs_of_possession
+        )
+        .is_err());
+        proofs_of_possession.remove(&signers[0].author());
+        assert!(ValidatorVerifier::new_with_proofs_of_possession(
+            validator_infos.clone(),
+            &proofs_of_possession
+        )
+        .is_err());
+
+        // Aggregated signatures are not verified without the proofs.
+        let hash = HashValue::random();
+        let signatures: HashMap<AccountAddress, BLS12381Signature> = signers
+            .iter()
+            .map(|signer| (signer.author(), signer.sign_message(hash).unwrap()))
+            .collect();
+        let unproven_validator = ValidatorVerifier::new(validator_infos);
+        let aggregate = AggregateSignature::aggregate(&unproven_validator, &signatures).unwrap();
+        assert_eq!(
+            aggregate.verify(&unproven_validator, hash),
+            Err(VerifyError::MissingProofsOfPossession)
+        );
+    }
+
+    #[test]
+    fn test_verify_aggregate_signature_duplicate_author() {
+        let (signers, validator) = random_bls_validators(4);
+        let hash = HashValue::random();
+        let signature = signers[0].sign_message(hash).unwrap();
+        let doubled = BLS12381Signature::aggregate(&[signature.clone(), signature]).unwrap();
+        let author = signers[0].author();
+        assert_eq!(
+            validator.verify_aggregate_signature(hash, &[author, author], &doubled),
+            Err(VerifyError::DuplicateAuthor)
+        );
+    }
+}
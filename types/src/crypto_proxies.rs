@@ -21,6 +21,7 @@
 
 use crate::{
     account_address::AccountAddress,
+    aggregate_signature::AggregateSignature as RawAggregateSignature,
     ledger_info::LedgerInfoWithSignatures as RawLedgerInfoWithSignatures,
     validator_change::ValidatorChangeEventWithProof as RawValidatorChangeEventWithProof,
     validator_signer::ValidatorSigner as RawValidatorSigner,
@@ -28,7 +29,10 @@ use crate::{
         ValidatorInfo as RawValidatorInfo, ValidatorVerifier as RawValidatorVerifier, VerifyError,
     },
 };
-use crypto::{hash::HashValue, traits::Signature as RawSignature};
+use crypto::{
+    hash::HashValue,
+    traits::{AggregatableSignature, Signature as RawSignature},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -61,6 +65,19 @@ impl<Sig: RawSignature> SignatureWrapper<Sig> {
     }
 }
 
+impl<Sig: AggregatableSignature> SignatureWrapper<Sig> {
+    pub fn aggregate(
+        validator_verifier: &RawValidatorVerifier<Sig::VerifyingKeyMaterial>,
+        signatures: &HashMap<AccountAddress, Self>,
+    ) -> failure::Result<RawAggregateSignature<Sig>> {
+        let signatures = signatures
+            .iter()
+            .map(|(author, signature)| (*author, signature.0.clone()))
+            .collect();
+        RawAggregateSignature::aggregate(validator_verifier, &signatures)
+    }
+}
+
 impl<Sig: RawSignature> From<Sig> for SignatureWrapper<Sig> {
     fn from(s: Sig) -> Self {
         SignatureWrapper(s)
@@ -78,7 +95,8 @@ impl<Sig: RawSignature> From<Sig> for SignatureWrapper<Sig> {
 // types that do not go through the instantiated polymorphic structures
 // below is banned.
 
-use crypto::ed25519::*;
+use crypto::{bls12381::*, ed25519::*, traits::Uniform};
+use rand::{rngs::StdRng, SeedableRng};
 use std::collections::HashMap;
 
 // used in chained_bft::consensus_types::block_test
@@ -92,6 +110,25 @@ pub type ValidatorVerifier = RawValidatorVerifier<Ed25519PublicKey>;
 pub type ValidatorSigner = RawValidatorSigner<Ed25519PrivateKey>;
 pub type ValidatorChangeEventWithProof = RawValidatorChangeEventWithProof<Ed25519Signature>;
 
+// The signatures validators aggregate into quorum and timeout certificates, when consensus is
+// configured to, are made with BLS12-381 keys.
+pub type SignatureShare = SignatureWrapper<BLS12381Signature>;
+pub type AggregateSignature = RawAggregateSignature<BLS12381Signature>;
+pub type AggregateVerifier = RawValidatorVerifier<BLS12381PublicKey>;
+
+/// Derives the key a validator aggregates its consensus signatures with from its consensus
+/// private key, so that no other secret has to be provisioned. The public key and its proof of
+/// possession are distributed along with the consensus public key. The key is sampled from a
+/// generator seeded with a hash of the consensus private key, not with the key generation of the
+/// BLS signature draft.
+pub fn derive_aggregate_key(consensus_private_key: &Ed25519PrivateKey) -> BLS12381PrivateKey {
+    let mut material = b"CONSENSUS_AGGREGATE_KEY".to_vec();
+    material.extend_from_slice(&consensus_private_key.to_bytes());
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&HashValue::from_sha3_256(&material).to_vec());
+    BLS12381PrivateKey::generate_for_testing(&mut StdRng::from_seed(seed))
+}
+
 /// Helper function to get random validator signers and a corresponding validator verifier for
 /// testing.  If custom_voting_power_quorum is not None, set a custom voting power quorum amount.
 /// With pseudo_random_account_address enabled, logs show 0 -> [0000], 1 -> [1000]
@@ -127,3 +164,47 @@ pub fn random_validator_verifier(
         },
     )
 }
+
+/// Helper function to get random validator signers which also sign with aggregate keys, and a
+/// corresponding validator verifier of their signatures and of their aggregated signatures, for
+/// testing.
+pub fn random_aggregate_validator_verifier(
+    count: usize,
+) -> (Vec<ValidatorSigner>, ValidatorVerifier) {
+    let (signers, validator) = random_validator_verifier(count, None, false);
+    let signers: Vec<ValidatorSigner> = signers
+        .into_iter()
+        .enumerate()
+        .map(|(i, signer)| {
+            let mut rng = StdRng::from_seed([i as u8; 32]);
+            signer.with_aggregate_key(BLS12381PrivateKey::generate_for_testing(&mut rng))
+        })
+        .collect();
+    let aggregate_signers: Vec<_> = signers
+        .iter()
+        .filter_map(ValidatorSigner::aggregate_signer)
+        .collect();
+    let proofs_of_possession = aggregate_signers
+        .iter()
+        .map(|signer| (signer.author(), signer.proof_of_possession()))
+        .collect();
+    let aggregate_verifier = AggregateVerifier::new_with_proofs_of_possession(
+        aggregate_signers
+            .iter()
+            .map(|signer| {
+                (
+                    signer.author(),
+                    RawValidatorInfo::new(signer.public_key(), 1),
+                )
+            })
+            .collect(),
+        &proofs_of_possession,
+    )
+    .expect("Unable to create testing aggregate verifier");
+    (
+        signers,
+        validator
+            .with_aggregate_verifier(aggregate_verifier)
+            .expect("Unable to create testing validator verifier"),
+    )
+}
@@ -14,6 +14,7 @@ use crate::{
     vm_error::{StatusCode, StatusType, VMStatus},
     write_set::WriteSet,
 };
+use bytes::Bytes;
 use canonical_serialization::{
     CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
     SimpleDeserializer, SimpleSerializer,
@@ -30,6 +31,8 @@ use crypto::{
 use failure::prelude::*;
 #[cfg(any(test, feature = "testing"))]
 use proptest_derive::Arbitrary;
+use prost::Message;
+use prost_ext::wire::wire_fields;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -476,6 +479,18 @@ impl SignedTransaction {
         self.transaction_length
     }
 
+    /// Length of the serialized signed transaction: the raw transaction along with the public key
+    /// and the signature.
+    pub fn signed_txn_bytes_len(&self) -> usize {
+        let public_key_len = SimpleSerializer::<Vec<u8>>::serialize(&self.public_key)
+            .expect("Unable to serialize public key")
+            .len();
+        let signature_len = SimpleSerializer::<Vec<u8>>::serialize(&self.signature)
+            .expect("Unable to serialize signature")
+            .len();
+        self.transaction_length + public_key_len + signature_len
+    }
+
     /// Checks that the signature of given transaction. Returns `Ok(SignatureCheckedTransaction)` if
     /// the signature is valid.
     pub fn check_signature(self) -> Result<SignatureCheckedTransaction> {
@@ -516,6 +531,22 @@ impl TryFrom<crate::proto::types::SignedTransaction> for SignedTransaction {
     }
 }
 
+impl SignedTransaction {
+    /// Decodes an encoded `SignedTransaction` proto. The transaction is deserialized straight out
+    /// of `bytes`, without copying it into the proto first.
+    pub fn from_proto_bytes(bytes: Bytes) -> Result<Self> {
+        let mut signed_txn = Bytes::new();
+        for field in wire_fields(bytes) {
+            let field = field?;
+            // The `signed_txn` field. As with any proto, the last occurrence wins.
+            if field.tag == 5 {
+                signed_txn = field.length_delimited()?;
+            }
+        }
+        SimpleDeserializer::deserialize(&signed_txn)
+    }
+}
+
 impl From<SignedTransaction> for crate::proto::types::SignedTransaction {
     fn from(txn: SignedTransaction) -> Self {
         let signed_txn = SimpleSerializer::<Vec<u8>>::serialize(&txn)
@@ -663,6 +694,8 @@ impl CanonicalDeserialize for SignedTransaction {
 /// The status of executing a transaction. The VM decides whether or not we should `Keep` the
 /// transaction output or `Discard` it based upon the execution of the transaction. We wrap these
 /// decisions around a `VMStatus` that provides more detail on the final execution state of the VM.
+/// A transaction the VM did not get to execute, e.g. because the execution of its block was
+/// interrupted, is to be retried in a later block.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TransactionStatus {
     /// Discard the transaction output
@@ -670,12 +703,19 @@ pub enum TransactionStatus {
 
     /// Keep the transaction output
     Keep(VMStatus),
+
+    /// The transaction was not executed, and its output is empty
+    Retry,
 }
 
 impl TransactionStatus {
-    pub fn vm_status(&self) -> &VMStatus {
+    /// The status of the execution of the transaction, unless it was not executed.
+    pub fn vm_status(&self) -> Option<&VMStatus> {
         match self {
-            TransactionStatus::Discard(vm_status) | TransactionStatus::Keep(vm_status) => vm_status,
+            TransactionStatus::Discard(vm_status) | TransactionStatus::Keep(vm_status) => {
+                Some(vm_status)
+            }
+            TransactionStatus::Retry => None,
         }
     }
 }
@@ -768,8 +808,7 @@ impl TryFrom<crate::proto::types::TransactionInfo> for TransactionInfo {
         let state_root_hash = HashValue::from_slice(&proto_txn_info.state_root_hash)?;
         let event_root_hash = HashValue::from_slice(&proto_txn_info.event_root_hash)?;
         let gas_used = proto_txn_info.gas_used;
-        let major_status =
-            StatusCode::try_from(proto_txn_info.major_status).unwrap_or(StatusCode::UNKNOWN_STATUS);
+        let major_status = StatusCode::from_u64_or_unknown(proto_txn_info.major_status);
         Ok(TransactionInfo::new(
             signed_txn_hash,
             state_root_hash,
@@ -962,8 +1001,7 @@ impl TryFrom<crate::proto::types::TransactionToCommit> for TransactionToCommit {
             .map(ContractEvent::try_from)
             .collect::<Result<Vec<_>>>()?;
         let gas_used = proto.gas_used;
-        let major_status =
-            StatusCode::try_from(proto.major_status).unwrap_or(StatusCode::UNKNOWN_STATUS);
+        let major_status = StatusCode::from_u64_or_unknown(proto.major_status);
 
         Ok(TransactionToCommit {
             signed_txn,
@@ -1067,13 +1105,34 @@ impl TransactionListWithProof {
             None => String::from("absent"),
         }
     }
-}
 
-impl TryFrom<crate::proto::types::TransactionListWithProof> for TransactionListWithProof {
-    type Error = Error;
+    /// Decodes an encoded `TransactionListWithProof` proto, as [`TryFrom`] does for a decoded
+    /// one. Chunks carry up to thousands of transactions, which are deserialized straight out of
+    /// `bytes` instead of being copied into the proto first.
+    pub fn from_proto_bytes(bytes: Bytes) -> Result<Self> {
+        let mut proto = crate::proto::types::TransactionListWithProof::default();
+        let mut transactions = vec![];
+        for field in wire_fields(bytes) {
+            let field = field?;
+            // The `transactions` field. The other fields are small and decoded as usual.
+            if field.tag == 1 {
+                transactions.push(SignedTransaction::from_proto_bytes(
+                    field.length_delimited()?,
+                )?);
+            } else {
+                proto.merge(field.raw)?;
+            }
+        }
+        Self::from_proto_parts(proto, transactions)
+    }
 
-    fn try_from(mut proto: crate::proto::types::TransactionListWithProof) -> Result<Self> {
-        let num_txns = proto.transactions.len();
+    /// Builds the list out of `proto`, whose transactions have already been converted into
+    /// `transactions`.
+    fn from_proto_parts(
+        mut proto: crate::proto::types::TransactionListWithProof,
+        transactions: Vec<SignedTransaction>,
+    ) -> Result<Self> {
+        let num_txns = transactions.len();
         let num_infos = proto.infos.len();
         ensure!(
             num_txns == num_infos,
@@ -1121,13 +1180,8 @@ impl TryFrom<crate::proto::types::TransactionListWithProof> for TransactionListW
             .transpose()?;
 
         let transaction_and_infos =
-            itertools::zip_eq(proto.transactions.into_iter(), proto.infos.into_iter())
-                .map(|(txn, info)| {
-                    Ok((
-                        SignedTransaction::try_from(txn)?,
-                        TransactionInfo::try_from(info)?,
-                    ))
-                })
+            itertools::zip_eq(transactions.into_iter(), proto.infos.into_iter())
+                .map(|(txn, info)| Ok((txn, TransactionInfo::try_from(info)?)))
                 .collect::<Result<Vec<_>>>()?;
 
         Ok(TransactionListWithProof {
@@ -1148,6 +1202,19 @@ impl TryFrom<crate::proto::types::TransactionListWithProof> for TransactionListW
     }
 }
 
+impl TryFrom<crate::proto::types::TransactionListWithProof> for TransactionListWithProof {
+    type Error = Error;
+
+    fn try_from(mut proto: crate::proto::types::TransactionListWithProof) -> Result<Self> {
+        let transactions = proto
+            .transactions
+            .drain(..)
+            .map(SignedTransaction::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_proto_parts(proto, transactions)
+    }
+}
+
 impl From<TransactionListWithProof> for crate::proto::types::TransactionListWithProof {
     fn from(txn: TransactionListWithProof) -> Self {
         let (transactions, infos) = txn
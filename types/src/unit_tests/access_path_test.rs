@@ -2,9 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    access_path::AccessPath,
+    access_path::{AccessPath, Path},
     account_address::{AccountAddress, ADDRESS_LENGTH},
+    account_config::{
+        account_received_event_counter_field, account_resource_path,
+        account_sent_event_counter_field, account_struct_tag, core_code_address,
+        ACCOUNT_RECEIVED_EVENT_PATH, ACCOUNT_SENT_EVENT_PATH,
+    },
+    identifier::Identifier,
+    language_storage::ModuleId,
 };
+use crypto::hash::CryptoHash;
 use proptest::prelude::*;
 use prost_ext::test_helpers::assert_protobuf_encode_decode;
 use std::convert::TryFrom;
@@ -55,6 +63,80 @@ fn test_access_path_protobuf_conversion_error() {
     assert!(AccessPath::try_from(proto_ap).is_err());
 }
 
+#[test]
+fn test_path_roundtrip() {
+    let module_id = ModuleId::new(
+        core_code_address(),
+        Identifier::new("LibraAccount").unwrap(),
+    );
+    let tag = account_struct_tag();
+    let paths = vec![
+        Path::code(&module_id),
+        Path::resource(&tag),
+        Path::event(&tag, account_sent_event_counter_field()),
+    ];
+    for path in paths {
+        let bytes: Vec<u8> = path.clone().into();
+        assert_eq!(Path::try_from(bytes.as_slice()).unwrap(), path);
+    }
+}
+
+#[test]
+fn test_account_paths_parse() {
+    let tag = account_struct_tag();
+    assert_eq!(
+        Path::try_from(account_resource_path().as_slice()).unwrap(),
+        Path::resource(&tag)
+    );
+    assert_eq!(
+        Path::try_from(ACCOUNT_SENT_EVENT_PATH.as_slice()).unwrap(),
+        Path::event(&tag, account_sent_event_counter_field())
+    );
+    assert_eq!(
+        AccessPath::new_for_received_event(AccountAddress::random())
+            .parse_path()
+            .unwrap(),
+        Path::try_from(ACCOUNT_RECEIVED_EVENT_PATH.as_slice()).unwrap()
+    );
+    assert_eq!(
+        AccessPath::new_for_resource_event(
+            AccountAddress::default(),
+            &tag,
+            account_received_event_counter_field()
+        )
+        .path,
+        *ACCOUNT_RECEIVED_EVENT_PATH
+    );
+}
+
+#[test]
+fn test_malformed_path() {
+    let mut path = account_resource_path();
+    // Too short.
+    assert!(Path::try_from(&path[..10]).is_err());
+    // Unknown tag.
+    path[0] = 7;
+    assert!(Path::try_from(path.as_slice()).is_err());
+    // Code paths have no suffix.
+    path[0] = 0;
+    path.extend_from_slice(b"/sent_events_count/");
+    assert!(Path::try_from(path.as_slice()).is_err());
+    // Event suffix must be delimited.
+    path[0] = 1;
+    path.pop();
+    assert!(Path::try_from(path.as_slice()).is_err());
+}
+
+#[test]
+fn test_path_display() {
+    let tag = account_struct_tag();
+    let path = Path::event(&tag, account_sent_event_counter_field());
+    assert_eq!(
+        path.to_string(),
+        format!("resource/{:x}/sent_events_count", tag.hash())
+    );
+}
+
 proptest! {
     #[test]
     fn test_access_path_to_protobuf_roundtrip(access_path in any::<AccessPath>()) {
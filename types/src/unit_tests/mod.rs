@@ -16,4 +16,5 @@ mod transaction_test;
 mod validator_change_proto_conversion_test;
 mod validator_set_test;
 mod vm_error_proto_conversion_test;
+mod vm_error_registry_test;
 mod write_set_test;
@@ -3,7 +3,7 @@
 
 use crate::transaction::*;
 use proptest::prelude::*;
-use prost_ext::test_helpers::assert_protobuf_encode_decode;
+use prost_ext::{test_helpers::assert_protobuf_encode_decode, MessageExt};
 
 proptest! {
     #[test]
@@ -34,4 +34,11 @@ proptest! {
     fn test_transaction_list_with_proof(txn_list in any::<TransactionListWithProof>()) {
         assert_protobuf_encode_decode::<crate::proto::types::TransactionListWithProof, TransactionListWithProof>(&txn_list);
     }
+
+    #[test]
+    fn test_transaction_list_with_proof_from_bytes(txn_list in any::<TransactionListWithProof>()) {
+        let proto: crate::proto::types::TransactionListWithProof = txn_list.clone().into();
+        let bytes = proto.to_bytes().unwrap();
+        prop_assert_eq!(TransactionListWithProof::from_proto_bytes(bytes).unwrap(), txn_list);
+    }
 }
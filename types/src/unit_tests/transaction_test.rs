@@ -42,6 +42,13 @@ proptest! {
         assert!(signed_txn.check_signature().is_ok());
     }
 
+    #[test]
+    fn test_signed_txn_bytes_len(raw_txn in any::<RawTransaction>(), (sk1, pk1) in compat::keypair_strategy()) {
+        let signed_txn = raw_txn.sign(&sk1, pk1).unwrap().into_inner();
+        let serialized_bytes = SimpleSerializer::<Vec<u8>>::serialize(&signed_txn).unwrap();
+        assert_eq!(signed_txn.signed_txn_bytes_len(), serialized_bytes.len());
+    }
+
     #[test]
     fn transaction_payload_round_trip_canonical_serialization(txn_payload in any::<TransactionPayload>()) {
         let mut serializer = SimpleSerializer::<Vec<u8>>::new();
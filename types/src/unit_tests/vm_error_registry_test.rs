@@ -0,0 +1,237 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The numeric values of status codes are persisted in storage and sent to clients, so they must
+//! never change. New status codes must be added to this registry with a new number.
+
+use crate::vm_error::{AbortCode, StatusCode, StatusType, VMStatus};
+use std::{collections::HashSet, convert::TryFrom};
+
+const STATUS_CODE_REGISTRY: &[(StatusCode, u64)] = &[
+    (StatusCode::UNKNOWN_VALIDATION_STATUS, 0),
+    (StatusCode::INVALID_SIGNATURE, 1),
+    (StatusCode::INVALID_AUTH_KEY, 2),
+    (StatusCode::SEQUENCE_NUMBER_TOO_OLD, 3),
+    (StatusCode::SEQUENCE_NUMBER_TOO_NEW, 4),
+    (StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE, 5),
+    (StatusCode::TRANSACTION_EXPIRED, 6),
+    (StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST, 7),
+    (StatusCode::REJECTED_WRITE_SET, 8),
+    (StatusCode::INVALID_WRITE_SET, 9),
+    (StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE, 10),
+    (StatusCode::UNKNOWN_SCRIPT, 11),
+    (StatusCode::UNKNOWN_MODULE, 12),
+    (StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND, 13),
+    (
+        StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS,
+        14,
+    ),
+    (StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND, 15),
+    (StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND, 16),
+    (StatusCode::UNKNOWN_VERIFICATION_ERROR, 1000),
+    (StatusCode::INDEX_OUT_OF_BOUNDS, 1001),
+    (StatusCode::RANGE_OUT_OF_BOUNDS, 1002),
+    (StatusCode::INVALID_SIGNATURE_TOKEN, 1003),
+    (StatusCode::INVALID_FIELD_DEF, 1004),
+    (StatusCode::RECURSIVE_STRUCT_DEFINITION, 1005),
+    (StatusCode::INVALID_RESOURCE_FIELD, 1006),
+    (StatusCode::INVALID_FALL_THROUGH, 1007),
+    (StatusCode::JOIN_FAILURE, 1008),
+    (StatusCode::NEGATIVE_STACK_SIZE_WITHIN_BLOCK, 1009),
+    (StatusCode::UNBALANCED_STACK, 1010),
+    (StatusCode::INVALID_MAIN_FUNCTION_SIGNATURE, 1011),
+    (StatusCode::DUPLICATE_ELEMENT, 1012),
+    (StatusCode::INVALID_MODULE_HANDLE, 1013),
+    (StatusCode::UNIMPLEMENTED_HANDLE, 1014),
+    (StatusCode::INCONSISTENT_FIELDS, 1015),
+    (StatusCode::UNUSED_FIELD, 1016),
+    (StatusCode::LOOKUP_FAILED, 1017),
+    (StatusCode::VISIBILITY_MISMATCH, 1018),
+    (StatusCode::TYPE_RESOLUTION_FAILURE, 1019),
+    (StatusCode::TYPE_MISMATCH, 1020),
+    (StatusCode::MISSING_DEPENDENCY, 1021),
+    (StatusCode::POP_REFERENCE_ERROR, 1022),
+    (StatusCode::POP_RESOURCE_ERROR, 1023),
+    (StatusCode::RELEASEREF_TYPE_MISMATCH_ERROR, 1024),
+    (StatusCode::BR_TYPE_MISMATCH_ERROR, 1025),
+    (StatusCode::ABORT_TYPE_MISMATCH_ERROR, 1026),
+    (StatusCode::STLOC_TYPE_MISMATCH_ERROR, 1027),
+    (StatusCode::STLOC_UNSAFE_TO_DESTROY_ERROR, 1028),
+    (StatusCode::RET_UNSAFE_TO_DESTROY_ERROR, 1029),
+    (StatusCode::RET_TYPE_MISMATCH_ERROR, 1030),
+    (StatusCode::FREEZEREF_TYPE_MISMATCH_ERROR, 1031),
+    (StatusCode::FREEZEREF_EXISTS_MUTABLE_BORROW_ERROR, 1032),
+    (StatusCode::BORROWFIELD_TYPE_MISMATCH_ERROR, 1033),
+    (StatusCode::BORROWFIELD_BAD_FIELD_ERROR, 1034),
+    (StatusCode::BORROWFIELD_EXISTS_MUTABLE_BORROW_ERROR, 1035),
+    (StatusCode::COPYLOC_UNAVAILABLE_ERROR, 1036),
+    (StatusCode::COPYLOC_RESOURCE_ERROR, 1037),
+    (StatusCode::COPYLOC_EXISTS_BORROW_ERROR, 1038),
+    (StatusCode::MOVELOC_UNAVAILABLE_ERROR, 1039),
+    (StatusCode::MOVELOC_EXISTS_BORROW_ERROR, 1040),
+    (StatusCode::BORROWLOC_REFERENCE_ERROR, 1041),
+    (StatusCode::BORROWLOC_UNAVAILABLE_ERROR, 1042),
+    (StatusCode::BORROWLOC_EXISTS_BORROW_ERROR, 1043),
+    (StatusCode::CALL_TYPE_MISMATCH_ERROR, 1044),
+    (StatusCode::CALL_BORROWED_MUTABLE_REFERENCE_ERROR, 1045),
+    (StatusCode::PACK_TYPE_MISMATCH_ERROR, 1046),
+    (StatusCode::UNPACK_TYPE_MISMATCH_ERROR, 1047),
+    (StatusCode::READREF_TYPE_MISMATCH_ERROR, 1048),
+    (StatusCode::READREF_RESOURCE_ERROR, 1049),
+    (StatusCode::READREF_EXISTS_MUTABLE_BORROW_ERROR, 1050),
+    (StatusCode::WRITEREF_TYPE_MISMATCH_ERROR, 1051),
+    (StatusCode::WRITEREF_RESOURCE_ERROR, 1052),
+    (StatusCode::WRITEREF_EXISTS_BORROW_ERROR, 1053),
+    (StatusCode::WRITEREF_NO_MUTABLE_REFERENCE_ERROR, 1054),
+    (StatusCode::INTEGER_OP_TYPE_MISMATCH_ERROR, 1055),
+    (StatusCode::BOOLEAN_OP_TYPE_MISMATCH_ERROR, 1056),
+    (StatusCode::EQUALITY_OP_TYPE_MISMATCH_ERROR, 1057),
+    (StatusCode::EXISTS_RESOURCE_TYPE_MISMATCH_ERROR, 1058),
+    (StatusCode::BORROWGLOBAL_TYPE_MISMATCH_ERROR, 1059),
+    (StatusCode::BORROWGLOBAL_NO_RESOURCE_ERROR, 1060),
+    (StatusCode::MOVEFROM_TYPE_MISMATCH_ERROR, 1061),
+    (StatusCode::MOVEFROM_NO_RESOURCE_ERROR, 1062),
+    (StatusCode::MOVETOSENDER_TYPE_MISMATCH_ERROR, 1063),
+    (StatusCode::MOVETOSENDER_NO_RESOURCE_ERROR, 1064),
+    (StatusCode::CREATEACCOUNT_TYPE_MISMATCH_ERROR, 1065),
+    (StatusCode::MODULE_ADDRESS_DOES_NOT_MATCH_SENDER, 1066),
+    (StatusCode::NO_MODULE_HANDLES, 1067),
+    (StatusCode::POSITIVE_STACK_SIZE_AT_BLOCK_END, 1068),
+    (StatusCode::MISSING_ACQUIRES_RESOURCE_ANNOTATION_ERROR, 1069),
+    (
+        StatusCode::EXTRANEOUS_ACQUIRES_RESOURCE_ANNOTATION_ERROR,
+        1070,
+    ),
+    (
+        StatusCode::DUPLICATE_ACQUIRES_RESOURCE_ANNOTATION_ERROR,
+        1071,
+    ),
+    (StatusCode::INVALID_ACQUIRES_RESOURCE_ANNOTATION_ERROR, 1072),
+    (StatusCode::GLOBAL_REFERENCE_ERROR, 1073),
+    (StatusCode::CONTRAINT_KIND_MISMATCH, 1074),
+    (StatusCode::NUMBER_OF_TYPE_ACTUALS_MISMATCH, 1075),
+    (StatusCode::LOOP_IN_INSTANTIATION_GRAPH, 1076),
+    (StatusCode::UNUSED_LOCALS_SIGNATURE, 1077),
+    (StatusCode::UNUSED_TYPE_SIGNATURE, 1078),
+    (StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR, 2000),
+    (StatusCode::OUT_OF_BOUNDS_INDEX, 2001),
+    (StatusCode::OUT_OF_BOUNDS_RANGE, 2002),
+    (StatusCode::EMPTY_VALUE_STACK, 2003),
+    (StatusCode::EMPTY_CALL_STACK, 2004),
+    (StatusCode::PC_OVERFLOW, 2005),
+    (StatusCode::LINKER_ERROR, 2006),
+    (StatusCode::LOCAL_REFERENCE_ERROR, 2007),
+    (StatusCode::STORAGE_ERROR, 2008),
+    (StatusCode::INTERNAL_TYPE_ERROR, 2009),
+    (StatusCode::EVENT_KEY_MISMATCH, 2010),
+    (StatusCode::UNKNOWN_BINARY_ERROR, 3000),
+    (StatusCode::MALFORMED, 3001),
+    (StatusCode::BAD_MAGIC, 3002),
+    (StatusCode::UNKNOWN_VERSION, 3003),
+    (StatusCode::UNKNOWN_TABLE_TYPE, 3004),
+    (StatusCode::UNKNOWN_SIGNATURE_TYPE, 3005),
+    (StatusCode::UNKNOWN_SERIALIZED_TYPE, 3006),
+    (StatusCode::UNKNOWN_OPCODE, 3007),
+    (StatusCode::BAD_HEADER_TABLE, 3008),
+    (StatusCode::UNEXPECTED_SIGNATURE_TYPE, 3009),
+    (StatusCode::DUPLICATE_TABLE, 3010),
+    (StatusCode::VERIFIER_INVARIANT_VIOLATION, 3011),
+    (StatusCode::UNKNOWN_RUNTIME_STATUS, 4000),
+    (StatusCode::EXECUTED, 4001),
+    (StatusCode::OUT_OF_GAS, 4002),
+    (StatusCode::RESOURCE_DOES_NOT_EXIST, 4003),
+    (StatusCode::RESOURCE_ALREADY_EXISTS, 4004),
+    (StatusCode::EVICTED_ACCOUNT_ACCESS, 4005),
+    (StatusCode::ACCOUNT_ADDRESS_ALREADY_EXISTS, 4006),
+    (StatusCode::TYPE_ERROR, 4007),
+    (StatusCode::MISSING_DATA, 4008),
+    (StatusCode::DATA_FORMAT_ERROR, 4009),
+    (StatusCode::INVALID_DATA, 4010),
+    (StatusCode::REMOTE_DATA_ERROR, 4011),
+    (StatusCode::CANNOT_WRITE_EXISTING_RESOURCE, 4012),
+    (StatusCode::VALUE_SERIALIZATION_ERROR, 4013),
+    (StatusCode::VALUE_DESERIALIZATION_ERROR, 4014),
+    (StatusCode::DUPLICATE_MODULE_NAME, 4015),
+    (StatusCode::ABORTED, 4016),
+    (StatusCode::ARITHMETIC_ERROR, 4017),
+    (StatusCode::DYNAMIC_REFERENCE_ERROR, 4018),
+    (StatusCode::CODE_DESERIALIZATION_ERROR, 4019),
+    (StatusCode::EXECUTION_STACK_OVERFLOW, 4020),
+    (StatusCode::CALL_STACK_OVERFLOW, 4021),
+    (StatusCode::NATIVE_FUNCTION_ERROR, 4022),
+    (StatusCode::UNKNOWN_STATUS, std::u64::MAX),
+];
+
+#[test]
+fn status_codes_are_stable() {
+    let mut seen = HashSet::new();
+    for (status, number) in STATUS_CODE_REGISTRY {
+        assert_eq!(u64::from(*status), *number, "{:?} changed value", status);
+        assert_eq!(StatusCode::try_from(*number).unwrap(), *status);
+        assert!(seen.insert(*number), "{} registered twice", number);
+    }
+}
+
+#[test]
+fn status_type_by_range() {
+    assert_eq!(
+        StatusCode::SEQUENCE_NUMBER_TOO_OLD.status_type(),
+        StatusType::Validation
+    );
+    assert_eq!(StatusCode::ABORTED.status_type(), StatusType::Execution);
+    assert_eq!(
+        StatusCode::UNKNOWN_STATUS.status_type(),
+        StatusType::Unknown
+    );
+    // Codes unknown to this version still get categorized.
+    assert_eq!(
+        StatusType::from_major_status(1999),
+        StatusType::Verification
+    );
+}
+
+#[test]
+fn unknown_major_status_is_preserved() {
+    let mut proto = crate::proto::types::VmStatus::default();
+    proto.major_status = 4999;
+    let status = VMStatus::try_from(proto).unwrap();
+    assert_eq!(status.major_status, StatusCode::UNKNOWN_STATUS);
+    assert!(status
+        .message
+        .unwrap()
+        .contains("unrecognized major status 4999 of type Execution"));
+}
+
+#[test]
+fn abort_code_split() {
+    let code = AbortCode::new(7, 42);
+    assert_eq!(u64::from(code), 42 * 256 + 7);
+    assert_eq!(AbortCode::from(u64::from(code)), code);
+    assert_eq!(AbortCode::from(std::u64::MAX).reason, AbortCode::MAX_REASON);
+
+    let status = VMStatus::new(StatusCode::ABORTED).with_sub_status(u64::from(code));
+    assert_eq!(status.abort_code(), Some(code));
+    assert_eq!(
+        VMStatus::new(StatusCode::ARITHMETIC_ERROR)
+            .with_sub_status(1)
+            .abort_code(),
+        None
+    );
+}
+
+#[test]
+fn abort_code_in_proto() {
+    let code = AbortCode::new(7, 42);
+    let proto: crate::proto::types::VmStatus = VMStatus::new(StatusCode::ABORTED)
+        .with_sub_status(u64::from(code))
+        .into();
+    assert!(proto.has_abort_code);
+    assert_eq!(proto.abort_category, 7);
+    assert_eq!(proto.abort_reason, 42);
+    assert_eq!(VMStatus::try_from(proto).unwrap().abort_code(), Some(code));
+
+    let proto: crate::proto::types::VmStatus = VMStatus::new(StatusCode::ARITHMETIC_ERROR)
+        .with_sub_status(1)
+        .into();
+    assert!(!proto.has_abort_code);
+}
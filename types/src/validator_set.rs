@@ -2,7 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    access_path::{AccessPath, Accesses},
     account_config,
     event::EventKey,
     identifier::{IdentStr, Identifier},
@@ -45,10 +44,6 @@ pub fn validator_set_tag() -> StructTag {
     }
 }
 
-pub(crate) fn validator_set_path() -> Vec<u8> {
-    AccessPath::resource_access_vec(&validator_set_tag(), &Accesses::empty())
-}
-
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
 pub struct ValidatorSet(Vec<ValidatorPublicKeys>);
@@ -2,10 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::account_address::{AccountAddress, ADDRESS_LENGTH};
-use crypto::{test_utils::TEST_SEED, HashValue, *};
+use crypto::{bls12381::BLS12381PrivateKey, test_utils::TEST_SEED, HashValue, *};
 use failure::Error;
 use rand::{rngs::StdRng, SeedableRng};
-use std::convert::TryFrom;
+use std::{convert::TryFrom, sync::Arc};
 
 /// ValidatorSigner associates an author with public and private keys with helpers for signing and
 /// validating. This struct can be used for all signing operations including block and network
@@ -16,6 +16,9 @@ pub struct ValidatorSigner<PrivateKey: SigningKey> {
     author: AccountAddress,
     public_key: PrivateKey::VerifyingKeyMaterial,
     private_key: PrivateKey,
+    /// The signer of the same author with a BLS12-381 key, whose signatures can be aggregated, if
+    /// any
+    aggregate_signer: Option<Arc<ValidatorSigner<BLS12381PrivateKey>>>,
 }
 
 impl<PrivateKey: SigningKey> ValidatorSigner<PrivateKey> {
@@ -32,14 +35,33 @@ impl<PrivateKey: SigningKey> ValidatorSigner<PrivateKey> {
             author: account_address,
             public_key,
             private_key,
+            aggregate_signer: None,
         }
     }
 
+    /// Attaches a BLS12-381 `private_key` to sign, as the same author, messages whose signatures
+    /// are aggregated.
+    pub fn with_aggregate_key(mut self, private_key: BLS12381PrivateKey) -> Self {
+        self.aggregate_signer = Some(Arc::new(ValidatorSigner::new(self.author, private_key)));
+        self
+    }
+
+    /// Returns the signer of messages whose signatures are aggregated, if any.
+    pub fn aggregate_signer(&self) -> Option<&ValidatorSigner<BLS12381PrivateKey>> {
+        self.aggregate_signer.as_ref().map(Arc::as_ref)
+    }
+
     /// Constructs a signature for `message` using `private_key`.
     pub fn sign_message(&self, message: HashValue) -> Result<PrivateKey::SignatureMaterial, Error> {
         Ok(self.private_key.sign_message(&message))
     }
 
+    /// Constructs the proof of possession of `private_key`, to register `public_key` along with.
+    pub fn proof_of_possession(&self) -> PrivateKey::SignatureMaterial {
+        self.private_key
+            .sign_message(&proof_of_possession_message(&self.public_key.to_bytes()))
+    }
+
     /// Returns the author associated with this signer.
     pub fn author(&self) -> AccountAddress {
         self.author
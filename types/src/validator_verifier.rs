@@ -1,11 +1,14 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::account_address::AccountAddress;
-use crypto::*;
+use crate::{account_address::AccountAddress, validator_set::ValidatorSet};
+use crypto::{bls12381::BLS12381PublicKey, ed25519::Ed25519PublicKey, *};
 use failure::prelude::*;
 use logger::prelude::*;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 /// Errors possible during signature verification.
 #[derive(Debug, Fail, PartialEq)]
@@ -32,6 +35,13 @@ pub enum VerifyError {
     #[fail(display = "Signature is invalid")]
     /// The signature does not match the hash.
     InvalidSignature,
+    #[fail(display = "Author is counted more than once")]
+    /// The same author appears several times among the signers of an aggregated signature.
+    DuplicateAuthor,
+    #[fail(display = "Public keys lack proofs of possession")]
+    /// The public keys were not registered with proofs of possession, which aggregated
+    /// signatures require.
+    MissingProofsOfPossession,
 }
 
 /// Helper struct to manage validator information for validation
@@ -68,6 +78,12 @@ pub struct ValidatorVerifier<PublicKey> {
     quorum_voting_power: u64,
     /// Total voting power of all validators (cached from address_to_validator_info)
     total_voting_power: u64,
+    /// Whether the possession of the private keys was proven when the public keys were
+    /// registered, without which aggregated signatures cannot be trusted
+    proofs_of_possession_checked: bool,
+    /// The verifier of the signatures the same validators aggregate with their BLS12-381 keys, if
+    /// any
+    aggregate_verifier: Option<Arc<ValidatorVerifier<BLS12381PublicKey>>>,
 }
 
 impl<PublicKey: VerifyingKey> ValidatorVerifier<PublicKey> {
@@ -89,9 +105,33 @@ impl<PublicKey: VerifyingKey> ValidatorVerifier<PublicKey> {
             address_to_validator_info,
             quorum_voting_power,
             total_voting_power,
+            proofs_of_possession_checked: false,
+            aggregate_verifier: None,
         }
     }
 
+    /// Initializes like `new`, with a proof of possession of the private key of each validator,
+    /// which enables the verification of aggregated signatures.
+    pub fn new_with_proofs_of_possession(
+        address_to_validator_info: HashMap<AccountAddress, ValidatorInfo<PublicKey>>,
+        proofs_of_possession: &HashMap<AccountAddress, PublicKey::SignatureMaterial>,
+    ) -> Result<Self>
+    where
+        PublicKey::SignatureMaterial: AggregatableSignature,
+    {
+        for (author, validator_info) in &address_to_validator_info {
+            let proof = proofs_of_possession
+                .get(author)
+                .ok_or_else(|| format_err!("No proof of possession for validator {}", author))?;
+            proof
+                .verify_proof_of_possession(&validator_info.public_key)
+                .map_err(|e| format_err!("Validator {}: {}", author, e))?;
+        }
+        let mut validator_verifier = Self::new(address_to_validator_info);
+        validator_verifier.proofs_of_possession_checked = true;
+        Ok(validator_verifier)
+    }
+
     /// Initializes a validator verifier with a specified quorum voting power.
     pub fn new_with_quorum_voting_power(
         address_to_validator_info: HashMap<AccountAddress, ValidatorInfo<PublicKey>>,
@@ -111,9 +151,39 @@ impl<PublicKey: VerifyingKey> ValidatorVerifier<PublicKey> {
             address_to_validator_info,
             quorum_voting_power,
             total_voting_power,
+            proofs_of_possession_checked: false,
+            aggregate_verifier: None,
         })
     }
 
+    /// Attaches the verifier of the signatures the same validators, with the same voting powers,
+    /// aggregate with their BLS12-381 keys. Its public keys must have been registered with proofs
+    /// of possession.
+    pub fn with_aggregate_verifier(
+        mut self,
+        aggregate_verifier: ValidatorVerifier<BLS12381PublicKey>,
+    ) -> Result<Self> {
+        ensure!(
+            aggregate_verifier.proofs_of_possession_checked,
+            "The aggregated public keys lack proofs of possession"
+        );
+        ensure!(
+            aggregate_verifier.len() == self.len()
+                && aggregate_verifier.quorum_voting_power == self.quorum_voting_power
+                && self.address_to_validator_info.iter().all(|(author, info)| {
+                    aggregate_verifier.get_voting_power(author) == Some(info.voting_power)
+                }),
+            "The aggregate verifier does not have the same validators"
+        );
+        self.aggregate_verifier = Some(Arc::new(aggregate_verifier));
+        Ok(self)
+    }
+
+    /// Returns the verifier of the signatures the validators aggregate, if any.
+    pub fn aggregate_verifier(&self) -> Option<&ValidatorVerifier<BLS12381PublicKey>> {
+        self.aggregate_verifier.as_ref().map(Arc::as_ref)
+    }
+
     /// Helper method to initialize with a single author and public key with quorum voting power 1.
     pub fn new_single(author: AccountAddress, public_key: PublicKey) -> Self {
         let mut author_to_validator_info = HashMap::new();
@@ -237,6 +307,41 @@ impl<PublicKey: VerifyingKey> ValidatorVerifier<PublicKey> {
         Ok(())
     }
 
+    /// Verify a single signature of a hash aggregating the signatures of the `signers`, which
+    /// must all be known authors with at least quorum_voting_power together. The verifier must
+    /// have been initialized with the proofs of possession of the public keys.
+    pub fn verify_aggregate_signature(
+        &self,
+        hash: HashValue,
+        signers: &[AccountAddress],
+        aggregate_signature: &PublicKey::SignatureMaterial,
+    ) -> std::result::Result<(), VerifyError>
+    where
+        PublicKey::SignatureMaterial: AggregatableSignature,
+    {
+        if !self.proofs_of_possession_checked {
+            return Err(VerifyError::MissingProofsOfPossession);
+        }
+        // the signature of an author aggregated twice would count its voting power twice
+        let mut unique_signers = HashSet::new();
+        if !signers.iter().all(|author| unique_signers.insert(author)) {
+            return Err(VerifyError::DuplicateAuthor);
+        }
+        self.check_voting_power(signers.iter())?;
+        let public_keys = signers
+            .iter()
+            .map(|author| {
+                self.address_to_validator_info
+                    .get(author)
+                    .map(|validator_info| &validator_info.public_key)
+                    .ok_or(VerifyError::UnknownAuthor)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        aggregate_signature
+            .verify_aggregate(&hash, &public_keys)
+            .map_err(|_| VerifyError::InvalidSignature)
+    }
+
     /// Returns the public key for this address.
     pub fn get_public_key(&self, author: &AccountAddress) -> Option<PublicKey> {
         self.address_to_validator_info
@@ -275,18 +380,79 @@ impl<PublicKey: VerifyingKey> ValidatorVerifier<PublicKey> {
     }
 }
 
+/// Builds the verifier of the signatures of the validators of a validator set, e.g. the one a
+/// reconfiguration moves to.
+impl From<&ValidatorSet> for ValidatorVerifier<Ed25519PublicKey> {
+    fn from(validator_set: &ValidatorSet) -> Self {
+        Self::new(
+            validator_set
+                .payload()
+                .iter()
+                .map(|keys| {
+                    (
+                        *keys.account_address(),
+                        ValidatorInfo::new(
+                            keys.consensus_public_key().clone(),
+                            keys.consensus_voting_power(),
+                        ),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::crypto_proxies::random_validator_verifier;
     use crate::validator_verifier::VerifyError::TooLittleVotingPower;
     use crate::{
         account_address::AccountAddress,
+        validator_public_keys::ValidatorPublicKeys,
+        validator_set::ValidatorSet,
         validator_signer::ValidatorSigner,
         validator_verifier::{ValidatorInfo, ValidatorVerifier, VerifyError},
     };
     use crypto::{ed25519::*, test_utils::TEST_SEED, HashValue};
+    use proptest::{collection::vec, prelude::*};
     use std::collections::HashMap;
 
+    proptest! {
+        #[test]
+        fn test_from_validator_set(
+            keys in vec(any::<ValidatorPublicKeys>(), 0..10),
+            voting_powers in vec(1..100u64, 10),
+        ) {
+            // arbitrary voting powers would overflow the total voting power
+            let validator_set = ValidatorSet::new(
+                keys.iter()
+                    .zip(voting_powers)
+                    .map(|(keys, voting_power)| {
+                        ValidatorPublicKeys::new(
+                            *keys.account_address(),
+                            keys.consensus_public_key().clone(),
+                            voting_power,
+                            keys.network_signing_public_key().clone(),
+                            keys.network_identity_public_key().clone(),
+                        )
+                    })
+                    .collect(),
+            );
+            let validator_verifier = ValidatorVerifier::from(&validator_set);
+            prop_assert_eq!(validator_verifier.len(), validator_set.payload().len());
+            for keys in validator_set.payload() {
+                prop_assert_eq!(
+                    validator_verifier.get_public_key(keys.account_address()),
+                    Some(keys.consensus_public_key().clone())
+                );
+                prop_assert_eq!(
+                    validator_verifier.get_voting_power(keys.account_address()),
+                    Some(keys.consensus_voting_power())
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_check_voting_power() {
         let (validator_signers, validator_verifier) = random_validator_verifier(2, None, false);
@@ -62,6 +62,9 @@ pub struct VMStatus {
 
 /// A status type is one of 5 different variants, along with a fallback variant in the case that we
 /// don't recognize the status code.
+///
+/// The status type of a status code is determined by the range its numeric value falls in, so it
+/// can be computed even for codes that this version of the software doesn't know about.
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub enum StatusType {
     Validation,
@@ -72,6 +75,34 @@ pub enum StatusType {
     Unknown,
 }
 
+impl StatusType {
+    /// Return the status type of the numeric major status `major_status`.
+    pub fn from_major_status(major_status: u64) -> Self {
+        if major_status >= VALIDATION_STATUS_MIN_CODE && major_status <= VALIDATION_STATUS_MAX_CODE
+        {
+            StatusType::Validation
+        } else if major_status >= VERIFICATION_STATUS_MIN_CODE
+            && major_status <= VERIFICATION_STATUS_MAX_CODE
+        {
+            StatusType::Verification
+        } else if major_status >= INVARIANT_VIOLATION_STATUS_MIN_CODE
+            && major_status <= INVARIANT_VIOLATION_STATUS_MAX_CODE
+        {
+            StatusType::InvariantViolation
+        } else if major_status >= DESERIALIZATION_STATUS_MIN_CODE
+            && major_status <= DESERIALIZATION_STATUS_MAX_CODE
+        {
+            StatusType::Deserialization
+        } else if major_status >= EXECUTION_STATUS_MIN_CODE
+            && major_status <= EXECUTION_STATUS_MAX_CODE
+        {
+            StatusType::Execution
+        } else {
+            StatusType::Unknown
+        }
+    }
+}
+
 impl fmt::Display for StatusType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let string = match self {
@@ -89,9 +120,16 @@ impl fmt::Display for StatusType {
 impl fmt::Display for VMStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let status_type = self.status_type();
-        let mut status = format!("status {:#?} of type {}", self.major_status, status_type);
+        let mut status = format!(
+            "status {:#?} ({}) of type {}",
+            self.major_status,
+            u64::from(self.major_status),
+            status_type
+        );
 
-        if let Some(sub_status) = self.sub_status {
+        if let Some(abort_code) = self.abort_code() {
+            status = format!("{} with {}", status, abort_code);
+        } else if let Some(sub_status) = self.sub_status {
             status = format!("{} with sub status {}", status, sub_status);
         }
 
@@ -153,38 +191,15 @@ impl VMStatus {
     /// Return the status type for this VMStatus. This is solely determined by the `major_status`
     /// field.
     pub fn status_type(&self) -> StatusType {
-        let major_status_number: u64 = self.major_status.into();
-        if major_status_number >= VALIDATION_STATUS_MIN_CODE
-            && major_status_number <= VALIDATION_STATUS_MAX_CODE
-        {
-            return StatusType::Validation;
-        }
-
-        if major_status_number >= VERIFICATION_STATUS_MIN_CODE
-            && major_status_number <= VERIFICATION_STATUS_MAX_CODE
-        {
-            return StatusType::Verification;
-        }
-
-        if major_status_number >= INVARIANT_VIOLATION_STATUS_MIN_CODE
-            && major_status_number <= INVARIANT_VIOLATION_STATUS_MAX_CODE
-        {
-            return StatusType::InvariantViolation;
-        }
-
-        if major_status_number >= DESERIALIZATION_STATUS_MIN_CODE
-            && major_status_number <= DESERIALIZATION_STATUS_MAX_CODE
-        {
-            return StatusType::Deserialization;
-        }
+        self.major_status.status_type()
+    }
 
-        if major_status_number >= EXECUTION_STATUS_MIN_CODE
-            && major_status_number <= EXECUTION_STATUS_MAX_CODE
-        {
-            return StatusType::Execution;
+    /// Return the Move abort code carried by this VMStatus, if it is an `ABORTED` status.
+    pub fn abort_code(&self) -> Option<AbortCode> {
+        match (self.major_status, self.sub_status) {
+            (StatusCode::ABORTED, Some(code)) => Some(AbortCode::from(code)),
+            _ => None,
         }
-
-        StatusType::Unknown
     }
 
     /// Determine if the VMStatus has status type `status_type`.
@@ -206,9 +221,8 @@ impl TryFrom<crate::proto::types::VmStatus> for VMStatus {
     type Error = Error;
 
     fn try_from(proto: crate::proto::types::VmStatus) -> Result<Self> {
-        let mut status = VMStatus::new(
-            StatusCode::try_from(proto.major_status).unwrap_or(StatusCode::UNKNOWN_STATUS),
-        );
+        let major_status = StatusCode::from_u64_or_unknown(proto.major_status);
+        let mut status = VMStatus::new(major_status);
 
         if proto.has_sub_status {
             status.set_sub_status(proto.sub_status);
@@ -218,6 +232,20 @@ impl TryFrom<crate::proto::types::VmStatus> for VMStatus {
             status.set_message(proto.message);
         }
 
+        // Keep track of the numeric status we couldn't decode, so that its meaning isn't lost.
+        if major_status == StatusCode::UNKNOWN_STATUS
+            && proto.major_status != u64::from(StatusCode::UNKNOWN_STATUS)
+        {
+            status = status.append_message_with_separator(
+                '\n',
+                format!(
+                    "unrecognized major status {} of type {}",
+                    proto.major_status,
+                    StatusType::from_major_status(proto.major_status)
+                ),
+            );
+        }
+
         Ok(status)
     }
 }
@@ -238,6 +266,13 @@ impl From<VMStatus> for crate::proto::types::VmStatus {
             proto_status.sub_status = sub_status;
         }
 
+        // Set the split of the abort code if there is one
+        if let Some(abort_code) = status.abort_code() {
+            proto_status.has_abort_code = true;
+            proto_status.abort_category = abort_code.category.into();
+            proto_status.abort_reason = abort_code.reason;
+        }
+
         // Set info string
         if let Some(string) = status.message {
             proto_status.has_message = true;
@@ -463,6 +498,71 @@ pub enum StatusCode {
     UNKNOWN_STATUS = std::u64::MAX,
 }
 
+impl StatusCode {
+    /// Decode a numeric major status, mapping the codes this version of the software doesn't know
+    /// about to `UNKNOWN_STATUS`.
+    pub fn from_u64_or_unknown(major_status: u64) -> Self {
+        StatusCode::try_from(major_status).unwrap_or(StatusCode::UNKNOWN_STATUS)
+    }
+
+    /// Return the status type of this status code.
+    pub fn status_type(self) -> StatusType {
+        StatusType::from_major_status(self.into())
+    }
+}
+
+/// A Move abort code, as carried in the sub status of an `ABORTED` VM status.
+///
+/// The lowest byte of the code is the category of the abort, which tells what kind of condition
+/// failed (e.g. an invalid argument or a missing resource) independently of the module that
+/// aborted, and the remaining bytes are the reason, which is specific to the aborting module.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AbortCode {
+    pub category: u8,
+    pub reason: u64,
+}
+
+impl AbortCode {
+    /// The largest reason that fits in an abort code alongside its category.
+    pub const MAX_REASON: u64 = std::u64::MAX >> 8;
+
+    pub fn new(category: u8, reason: u64) -> Self {
+        assert!(
+            reason <= Self::MAX_REASON,
+            "Abort reason {} too large",
+            reason
+        );
+        Self { category, reason }
+    }
+}
+
+impl From<u64> for AbortCode {
+    fn from(code: u64) -> Self {
+        Self {
+            category: (code & 0xff) as u8,
+            reason: code >> 8,
+        }
+    }
+}
+
+impl From<AbortCode> for u64 {
+    fn from(code: AbortCode) -> Self {
+        (code.reason << 8) | u64::from(code.category)
+    }
+}
+
+impl fmt::Display for AbortCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "abort code {} (category {}, reason {})",
+            u64::from(*self),
+            self.category,
+            self.reason
+        )
+    }
+}
+
 pub mod sub_status {
     // Arithmetic sub status sub-codes
     pub const AEU_UNKNOWN_ARITHMETIC_ERROR: u64 = 0;
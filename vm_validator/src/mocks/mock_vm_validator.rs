@@ -2,12 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::vm_validator::TransactionValidation;
-use futures::future::{ok, Future};
+use failure::prelude::*;
+use futures::future::{err, ok, Future};
 use state_view::StateView;
 use std::convert::TryFrom;
 use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
-    transaction::SignedTransaction,
+    transaction::{SignatureCheckedTransaction, SignedTransaction},
     vm_error::{StatusCode, VMStatus},
 };
 use vm_runtime::VMVerifier;
@@ -23,6 +24,14 @@ impl VMVerifier for MockVMValidator {
     ) -> Option<VMStatus> {
         None
     }
+
+    fn validate_signature_checked_transaction(
+        &self,
+        _transaction: SignatureCheckedTransaction,
+        _state_view: &dyn StateView,
+    ) -> Option<VMStatus> {
+        None
+    }
 }
 
 impl TransactionValidation for MockVMValidator {
@@ -31,11 +40,16 @@ impl TransactionValidation for MockVMValidator {
         &self,
         txn: SignedTransaction,
     ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send> {
-        let txn = match txn.check_signature() {
-            Ok(txn) => txn,
-            Err(_) => return Box::new(ok(Some(VMStatus::new(StatusCode::INVALID_SIGNATURE)))),
-        };
+        match txn.check_signature() {
+            Ok(txn) => self.validate_signature_checked_transaction(txn),
+            Err(_) => Box::new(ok(Some(VMStatus::new(StatusCode::INVALID_SIGNATURE)))),
+        }
+    }
 
+    fn validate_signature_checked_transaction(
+        &self,
+        txn: SignatureCheckedTransaction,
+    ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send> {
         let sender = txn.sender();
         let account_dne_test_add = AccountAddress::try_from(&[0 as u8; ADDRESS_LENGTH]).unwrap();
         let invalid_sig_test_add = AccountAddress::try_from(&[1 as u8; ADDRESS_LENGTH]).unwrap();
@@ -49,6 +63,11 @@ impl TransactionValidation for MockVMValidator {
             AccountAddress::try_from(&[5 as u8; ADDRESS_LENGTH]).unwrap();
         let invalid_auth_key_test_add =
             AccountAddress::try_from(&[6 as u8; ADDRESS_LENGTH]).unwrap();
+        let validation_error_test_add =
+            AccountAddress::try_from(&[7 as u8; ADDRESS_LENGTH]).unwrap();
+        if sender == validation_error_test_add {
+            return Box::new(err(format_err!("Failed to read the account state")));
+        }
         let ret = if sender == account_dne_test_add {
             Some(VMStatus::new(StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST))
         } else if sender == invalid_sig_test_add {
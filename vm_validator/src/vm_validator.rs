@@ -5,13 +5,14 @@ use config::config::NodeConfig;
 use failure::prelude::*;
 use futures::future::{err, ok, Future};
 use scratchpad::SparseMerkleTree;
+use state_view::StateView;
 use std::sync::Arc;
 use storage_client::{StorageRead, VerifiedStateView};
 use types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
     account_config::get_account_resource_or_default,
     get_with_proof::{RequestItem, ResponseItem},
-    transaction::SignedTransaction,
+    transaction::{SignatureCheckedTransaction, SignedTransaction},
     vm_error::VMStatus,
 };
 use vm_runtime::{MoveVM, VMVerifier};
@@ -27,6 +28,13 @@ pub trait TransactionValidation: Send + Sync {
         &self,
         _txn: SignedTransaction,
     ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send>;
+
+    /// Same as `validate_transaction`, for a txn whose signature was already verified, which is
+    /// not verified again
+    fn validate_signature_checked_transaction(
+        &self,
+        _txn: SignatureCheckedTransaction,
+    ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send>;
 }
 
 #[derive(Clone)]
@@ -42,14 +50,11 @@ impl VMValidator {
             vm: MoveVM::new(&config.vm_config),
         }
     }
-}
-
-impl TransactionValidation for VMValidator {
-    type ValidationInstance = MoveVM;
 
-    fn validate_transaction(
+    /// Runs `validate` against a view of the latest state
+    fn validate_with_latest_state(
         &self,
-        txn: SignedTransaction,
+        validate: impl FnOnce(&MoveVM, &dyn StateView) -> Option<VMStatus>,
     ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send> {
         // TODO: For transaction validation, there are two options to go:
         // 1. Trust storage: there is no need to get root hash from storage here. We will
@@ -94,7 +99,7 @@ impl TransactionValidation for VMValidator {
                             ),
                             &smt,
                         );
-                        Box::new(ok(self.vm.validate_transaction(txn, &state_view)))
+                        Box::new(ok(validate(&self.vm, &state_view)))
                     }
                     _ => panic!("Unexpected item in response."),
                 }
@@ -104,6 +109,26 @@ impl TransactionValidation for VMValidator {
     }
 }
 
+impl TransactionValidation for VMValidator {
+    type ValidationInstance = MoveVM;
+
+    fn validate_transaction(
+        &self,
+        txn: SignedTransaction,
+    ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send> {
+        self.validate_with_latest_state(|vm, state_view| vm.validate_transaction(txn, state_view))
+    }
+
+    fn validate_signature_checked_transaction(
+        &self,
+        txn: SignatureCheckedTransaction,
+    ) -> Box<dyn Future<Item = Option<VMStatus>, Error = failure::Error> + Send> {
+        self.validate_with_latest_state(|vm, state_view| {
+            vm.validate_signature_checked_transaction(txn, state_view)
+        })
+    }
+}
+
 /// read account state
 /// returns account's current sequence number and balance
 pub async fn get_account_state(